@@ -0,0 +1,1005 @@
+use chrono::NaiveDate;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::middleware::security_headers::SecurityHeaders;
+use crate::models::activity::DEFAULT_MIN_ACTIVITY_MINUTES;
+use crate::models::search::{DayFloor, DayFloors};
+use crate::services::account_service::{EmailSettings, MAX_VERIFICATION_ATTEMPTS};
+use crate::services::content_flag_service::ReportLimits;
+use crate::services::cost_recompute_service::DEFAULT_RECOMPUTE_BATCH_SIZE;
+use crate::services::credential_check::CredentialCheck;
+use crate::services::distance_service::DistancePrefilter;
+use crate::services::generation_budget::BudgetCaps;
+use crate::services::geocoding_service::GeocodingPace;
+use crate::services::image_fallback::DEFAULT_PLACEHOLDER_IMAGE;
+use crate::services::moderation::Moderator;
+use crate::services::notification_service::TwilioSettings;
+use crate::services::payment_teardown::CustomerDisposition;
+use crate::services::impersonation_service::DEFAULT_TOKEN_MINUTES;
+use crate::services::reservation_service::DEFAULT_HOLD_MINUTES;
+use crate::services::retention_service::RetentionPolicy;
+use crate::services::score_preview_service::{default_score_presets, parse_score_presets, ScorePresets};
+use crate::services::search_scoring::SearchWeights;
+use crate::services::storage::StorageConfig;
+use crate::services::trip_limits::TripLimits;
+use crate::services::vertex_activity::ActivityDefaults;
+use crate::services::vertex_search_service::VertexSearchSettings;
+use crate::services::webhook_replay::DEFAULT_MAX_EVENT_AGE_HOURS;
+
+/// Variables the server cannot run without
+pub const REQUIRED_VARS: &[&str] = &[
+    "MONGODB_URI",
+    "JWT_SECRET",
+    "STRIPE_SECRET_KEY",
+    "STRIPE_WEBHOOK_SECRET",
+];
+
+/// Variables whose absence disables a feature at first use rather than at startup
+pub const RECOMMENDED_VARS: &[&str] = &[
+    "GOOGLE_MAPS_API_KEY",
+    "SENDGRID_API_KEY",
+    "SENDGRID_WEBHOOK_PUBLIC_KEY",
+    "FROM_EMAIL",
+    "FRONTEND_URL",
+    "CLOUD_STORAGE_URL",
+    "ITINERARY_BUCKET",
+    "PROFILE_PIC_BUCKET",
+    "DOCUMENTS_BUCKET",
+    "ACTIVITY_BUCKET",
+    "GOOGLE_CLIENT_ID",
+    "GOOGLE_CLIENT_SECRET",
+    "GOOGLE_REDIRECT_URI",
+    "FACEBOOK_CLIENT_ID",
+    "FACEBOOK_CLIENT_SECRET",
+    "FACEBOOK_REDIRECT_URI",
+    "TWILIO_ACCOUNT_SID",
+    "TWILIO_AUTH_TOKEN",
+    "TWILIO_FROM_NUMBER",
+    "IMAGE_RESIZE_URL",
+    "GOOGLE_CLOUD_PROJECT_ID",
+    "VERTEX_SEARCH_DATA_STORE_ID",
+];
+
+/// Numeric tunables that fall back to built-in defaults when unset
+pub const TUNABLE_VARS: &[&str] = &[
+    "PORT",
+    "MIN_SEARCH_RESULTS",
+    "SEARCH_MIN_SCORE",
+    "SEARCH_LOCATION_WEIGHT",
+    "SEARCH_ACTIVITY_WEIGHT",
+    "SEARCH_GROUP_SIZE_WEIGHT",
+    "SEARCH_LODGING_WEIGHT",
+    "SEARCH_TRANSPORT_WEIGHT",
+    "SEARCH_TRIP_PACE_WEIGHT",
+    "AVAILABILITY_LIMITED_THRESHOLD",
+    "FX_REFRESH_HOURS",
+    "API_TOKEN_RATE_LIMIT_PER_MINUTE",
+    "PRICE_ALERT_MIN_DROP_PERCENT",
+    "PRICE_ALERT_INTERVAL_HOURS",
+    "RETENTION_SEARCH_SUBMISSIONS_DAYS",
+    "RETENTION_GENERATED_ITINERARIES_DAYS",
+    "RETENTION_INTERVAL_HOURS",
+    "EMAIL_CHANGE_SENDS_VERIFICATION",
+    "CONTENT_REPORTS_PER_HOUR",
+    "CONTENT_FLAG_THRESHOLD",
+    "RESCHEDULE_CUTOFF_HOURS",
+    "STRIPE_WEBHOOK_MAX_AGE_HOURS",
+    "FAVORITE_DIGEST_INTERVAL_HOURS",
+    "LOCATION_INDEX_REFRESH_MINUTES",
+    "HTTP_WORKERS",
+    "HTTP_KEEP_ALIVE_SECS",
+    "HTTP_CLIENT_REQUEST_TIMEOUT_SECS",
+    "HTTP_BACKLOG",
+    "IMPERSONATION_TOKEN_MINUTES",
+    "RESERVATION_HOLD_MINUTES",
+    "INTEGRITY_CHECK_INTERVAL_HOURS",
+    "INTEGRITY_IMAGE_SAMPLE_SIZE",
+    "MIN_ACTIVITY_DURATION_MINUTES",
+    "MAX_TRIP_DAYS",
+    "MAX_PARTY_SIZE",
+    "MAX_ACTIVITIES_FETCH",
+    "MAX_VERTEX_ACTIVITIES",
+    "MAX_VERTEX_ACTIVITIES_PER_TYPE",
+    "VERTEX_DEFAULT_PRICE",
+    "VERTEX_DEFAULT_DURATION_MINUTES",
+    "VERTEX_DEFAULT_CAPACITY_MIN",
+    "VERTEX_DEFAULT_CAPACITY_MAX",
+    "REVIEW_REQUEST_DELAY_DAYS",
+    "REVIEW_REQUEST_INTERVAL_HOURS",
+    "REFUND_CUTOFF_HOURS",
+    "TRIP_STATUS_INTERVAL_MINUTES",
+    "MONGODB_TRANSACTIONS",
+    "EMAIL_VERIFICATION_MAX_ATTEMPTS",
+    "GENERATION_MAX_VERTEX_QUERIES",
+    "GENERATION_MAX_MAPS_LOOKUPS",
+    "MODERATION_DENYLIST",
+    "GEOCODING_BATCH_SIZE",
+    "GEOCODING_BATCH_DELAY_MS",
+    "RECOMPUTE_COSTS_BATCH_SIZE",
+    "SCORE_PREVIEW_PRESETS",
+    "CREDENTIAL_CHECK",
+    "STORAGE_REQUIRED_BUCKETS",
+    "STRIPE_CUSTOMER_ON_DELETE",
+    "API_V1_SUNSET",
+    "PLACEHOLDER_IMAGE_URL",
+    "SECURITY_CONTENT_TYPE_OPTIONS",
+    "SECURITY_FRAME_OPTIONS",
+    "SECURITY_REFERRER_POLICY",
+    "SECURITY_HSTS",
+    "SERVER_HEADER",
+    "CORS_ALLOWED_ORIGINS",
+    "CORS_MAX_AGE_SECS",
+    "RUST_ENV",
+    "VERTEX_SEARCH_LOCATION",
+    "VERTEX_SEARCH_SERVING_CONFIG",
+    "DISTANCE_SHORT_CIRCUIT_MILES",
+    "DISTANCE_MAX_PAIR_MILES",
+    "TRIP_PACE_RELAXED_MIN_ACTIVITIES",
+    "TRIP_PACE_RELAXED_MIN_HOURS",
+    "TRIP_PACE_MODERATE_MIN_ACTIVITIES",
+    "TRIP_PACE_MODERATE_MIN_HOURS",
+    "TRIP_PACE_ADVENTURE_MIN_ACTIVITIES",
+    "TRIP_PACE_ADVENTURE_MIN_HOURS",
+];
+
+/// HTTP server tuning. How these relate to Cloud Run's request concurrency is
+/// covered under "Server tuning" in the README.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSettings {
+    /// Worker threads, each running its own event loop. Defaults to the CPUs
+    /// available to the process, which honours the container's CPU limit.
+    pub workers: usize,
+    /// Idle keep-alive connections are closed after this long; 0 disables keep-alive
+    pub keep_alive_secs: u64,
+    /// Time allowed for a client to send request headers; 0 disables the timeout
+    pub client_request_timeout_secs: u64,
+    /// Pending connections queued before new ones are refused
+    pub backlog: u32,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            workers: std::thread::available_parallelism().map_or(1, |cpus| cpus.get()),
+            keep_alive_secs: 75,
+            client_request_timeout_secs: 60,
+            backlog: 1024,
+        }
+    }
+}
+
+/// One OAuth provider's client. Signing in through the provider is unavailable
+/// until all three are set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OAuthClientSettings {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub redirect_uri: Option<String>,
+}
+
+/// Logins created by the demo seed (`demo-tools` builds)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoAccounts {
+    pub admin_email: String,
+    pub user_email: String,
+    pub password: String,
+}
+
+impl Default for DemoAccounts {
+    fn default() -> Self {
+        DemoAccounts {
+            admin_email: "demo-admin@actota.com".to_string(),
+            user_email: "demo@actota.com".to_string(),
+            password: "actota-demo".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigError {
+    pub missing: Vec<&'static str>,
+    /// Variables that are set but can't be parsed, with the offending value
+    pub invalid: Vec<(&'static str, String)>,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut problems = Vec::new();
+        if !self.missing.is_empty() {
+            problems.push(format!("missing required variables: {}", self.missing.join(", ")));
+        }
+        if !self.invalid.is_empty() {
+            let invalid: Vec<String> = self
+                .invalid
+                .iter()
+                .map(|(name, value)| format!("{}={:?}", name, value))
+                .collect();
+            problems.push(format!("invalid values: {}", invalid.join(", ")));
+        }
+        write!(f, "Invalid configuration: {}", problems.join("; "))
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Application configuration, read from the environment once at startup and shared
+/// with handlers through `web::Data`. Handlers should read tunables from here rather
+/// than calling `env::var` per request.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub port: u16,
+    pub mongodb_uri: String,
+    pub jwt_secret: String,
+    pub stripe_secret_key: String,
+    pub stripe_webhook_secret: String,
+    pub frontend_url: String,
+    /// Cloud Storage buckets by what they hold
+    pub storage: Arc<StorageConfig>,
+    /// Fewer search results than this triggers itinerary generation.
+    /// Unset means each search endpoint uses its own default.
+    pub min_search_results: Option<usize>,
+    /// Parsed once here; scorers share it rather than building their own
+    pub search_weights: Arc<SearchWeights>,
+    /// Start dates with this many seats or fewer left are shown as limited
+    pub availability_limited_threshold: u32,
+    /// Exchange rate provider returning USD-based rates (Frankfurter-compatible)
+    pub fx_rates_url: String,
+    pub fx_refresh_hours: u64,
+    /// Region assumed for phone numbers entered without a country code
+    pub default_phone_region: phonenumber::country::Id,
+    /// Requests each personal access token may make per minute
+    pub api_token_rate_limit_per_minute: u32,
+    /// Smallest fall in a favorite's per-person price, as a percentage, that emails an alert
+    pub price_alert_min_drop_percent: u32,
+    pub price_alert_interval_hours: u64,
+    /// How long search submissions and unbooked generated itineraries are kept
+    pub retention: RetentionPolicy,
+    pub retention_interval_hours: u64,
+    /// Image CDN URL with `{path}` and `{width}` placeholders for resized images
+    pub image_resize_url: Option<String>,
+    /// Verifies SendGrid's event webhook; the webhook is refused without it
+    pub sendgrid_webhook_public_key: Option<String>,
+    /// Email a verification code to the new address when a user changes their email
+    pub email_change_sends_verification: bool,
+    /// Report rate limit and when admins are alerted about reported content
+    pub content_reports: ReportLimits,
+    /// How close to arrival a booking can still be moved to new dates
+    pub reschedule_cutoff_hours: u64,
+    /// How close to arrival a booking can still be cancelled for a refund. Trips
+    /// that have started are never refunded.
+    pub refund_cutoff_hours: u64,
+    /// Oldest Stripe event the webhook will act on
+    pub stripe_webhook_max_age_hours: u64,
+    /// How often queued favorite changes are checked for digests to send. Each
+    /// user still gets at most one digest a week.
+    pub favorite_digest_interval_hours: u64,
+    /// How often the in-memory index behind location autocomplete is rebuilt
+    pub location_index_refresh_minutes: u64,
+    pub server: ServerSettings,
+    /// How long a support impersonation token lasts
+    pub impersonation_token_minutes: u64,
+    /// How long `POST /payment/reserve` holds seats while the traveler pays
+    pub reservation_hold_minutes: u64,
+    /// How often listed itineraries are checked for activities or accommodations that no longer exist
+    pub integrity_check_interval_hours: u64,
+    /// Itineraries whose images each integrity check looks for in the bucket; 0 skips it
+    pub integrity_image_sample_size: u64,
+    /// Generated schedules give every activity at least this long, whatever its stored duration
+    pub min_activity_minutes: u16,
+    /// Longest trip and largest party accepted, and how many activities generation reads
+    pub trip_limits: TripLimits,
+    /// What Vertex AI documents missing a price, duration or capacity are given
+    pub activity_defaults: ActivityDefaults,
+    /// How long after a trip ends the traveler is asked for a review
+    pub review_request_delay_days: u64,
+    /// How often finished trips are checked for review requests to send
+    pub review_request_interval_hours: u64,
+    /// How often bookings are moved to in_progress and completed as their trips start and end
+    pub trip_status_interval_minutes: u64,
+    /// Confirm bookings in multi-document transactions. Needs a replica set; leave
+    /// off on a standalone server.
+    pub mongodb_transactions: bool,
+    /// Wrong codes accepted for one email verification before a new code is needed
+    pub email_verification_max_attempts: u32,
+    /// External calls one search request may make while generating
+    pub generation_budget: BudgetCaps,
+    /// Checks user-influenced text others can see, with terms from `MODERATION_DENYLIST`
+    pub moderator: Moderator,
+    /// How fast the coordinate backfill calls the Geocoding API
+    pub geocoding_pace: GeocodingPace,
+    /// Itineraries re-priced per activity lookup by the cost recompute
+    pub recompute_costs_batch_size: usize,
+    /// Named searches admins preview itinerary edits against
+    pub score_presets: ScorePresets,
+    /// What happens at startup when the Google credentials can't reach the itinerary bucket
+    pub credential_check: CredentialCheck,
+    /// Whether a deleted account's Stripe customer is deleted or only marked
+    pub stripe_customer_on_delete: CustomerDisposition,
+    /// Sent as the `Sunset` header on v1 and unprefixed responses once set (`YYYY-MM-DD`)
+    pub api_v1_sunset: Option<NaiveDate>,
+    /// Shown on search results with no image of their own, their activities' or their city's
+    pub placeholder_image_url: String,
+    /// Hardening headers on every response, and which origins CORS allows
+    pub security_headers: SecurityHeaders,
+    /// Reported by `/health` (`RUST_ENV`)
+    pub environment: String,
+    /// SendGrid key, sender address and the site email links point at
+    pub email: EmailSettings,
+    /// Booking texts are only sent when all three Twilio variables are set
+    pub twilio: Option<TwilioSettings>,
+    pub google_oauth: OAuthClientSettings,
+    pub facebook_oauth: OAuthClientSettings,
+    /// Generation searches MongoDB only without it
+    pub vertex_search: Option<VertexSearchSettings>,
+    /// Distance Matrix and Geocoding; straight-line estimates are used without it
+    pub google_maps_api_key: Option<String>,
+    /// Straight-line distances decided without calling the Distance Matrix API
+    pub distance_prefilter: DistancePrefilter,
+    /// Least a generated day must hold at each trip pace
+    pub day_floors: DayFloors,
+    /// Bucket activity images are listed from; activities show no images of their own without it
+    pub activity_bucket: Option<String>,
+    pub demo_accounts: DemoAccounts,
+}
+
+impl AppConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    /// Build the config from any variable source, reporting every problem at once
+    pub fn from_lookup<F>(lookup: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let get = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+
+        let mut error = ConfigError {
+            missing: REQUIRED_VARS
+                .iter()
+                .copied()
+                .filter(|name| get(name).is_none())
+                .collect(),
+            invalid: Vec::new(),
+        };
+
+        let port = parse_tunable(&get, "PORT", 8080u16, &mut error);
+        let min_search_results = get("MIN_SEARCH_RESULTS").map(|_| {
+            parse_tunable(&get, "MIN_SEARCH_RESULTS", 0usize, &mut error)
+        });
+        let defaults = SearchWeights::default();
+        let search_weights = Arc::new(SearchWeights {
+            location_weight: parse_tunable(&get, "SEARCH_LOCATION_WEIGHT", defaults.location_weight, &mut error),
+            activity_weight: parse_tunable(&get, "SEARCH_ACTIVITY_WEIGHT", defaults.activity_weight, &mut error),
+            group_size_weight: parse_tunable(&get, "SEARCH_GROUP_SIZE_WEIGHT", defaults.group_size_weight, &mut error),
+            lodging_weight: parse_tunable(&get, "SEARCH_LODGING_WEIGHT", defaults.lodging_weight, &mut error),
+            transportation_weight: parse_tunable(&get, "SEARCH_TRANSPORT_WEIGHT", defaults.transportation_weight, &mut error),
+            trip_pace_weight: parse_tunable(&get, "SEARCH_TRIP_PACE_WEIGHT", defaults.trip_pace_weight, &mut error),
+            minimum_score: parse_tunable(&get, "SEARCH_MIN_SCORE", defaults.minimum_score, &mut error),
+        });
+
+        let availability_limited_threshold =
+            parse_tunable(&get, "AVAILABILITY_LIMITED_THRESHOLD", 4u32, &mut error);
+        let fx_refresh_hours = parse_tunable(&get, "FX_REFRESH_HOURS", 24u64, &mut error);
+        let default_phone_region = parse_tunable(
+            &get,
+            "DEFAULT_PHONE_REGION",
+            phonenumber::country::Id::US,
+            &mut error,
+        );
+
+        let api_token_rate_limit_per_minute =
+            parse_tunable(&get, "API_TOKEN_RATE_LIMIT_PER_MINUTE", 60u32, &mut error);
+
+        let price_alert_min_drop_percent =
+            parse_tunable(&get, "PRICE_ALERT_MIN_DROP_PERCENT", 5u32, &mut error);
+        let price_alert_interval_hours =
+            parse_tunable(&get, "PRICE_ALERT_INTERVAL_HOURS", 6u64, &mut error);
+
+        let retention_defaults = RetentionPolicy::default();
+        let retention = RetentionPolicy {
+            search_submissions_days: parse_tunable(&get, "RETENTION_SEARCH_SUBMISSIONS_DAYS", retention_defaults.search_submissions_days, &mut error),
+            generated_itineraries_days: parse_tunable(&get, "RETENTION_GENERATED_ITINERARIES_DAYS", retention_defaults.generated_itineraries_days, &mut error),
+        };
+        let retention_interval_hours = parse_tunable(&get, "RETENTION_INTERVAL_HOURS", 24u64, &mut error);
+
+        let email_change_sends_verification =
+            parse_tunable(&get, "EMAIL_CHANGE_SENDS_VERIFICATION", true, &mut error);
+
+        let report_defaults = ReportLimits::default();
+        let content_reports = ReportLimits {
+            per_user_per_hour: parse_tunable(&get, "CONTENT_REPORTS_PER_HOUR", report_defaults.per_user_per_hour, &mut error),
+            notify_threshold: parse_tunable(&get, "CONTENT_FLAG_THRESHOLD", report_defaults.notify_threshold, &mut error),
+        };
+
+        let reschedule_cutoff_hours = parse_tunable(&get, "RESCHEDULE_CUTOFF_HOURS", 72u64, &mut error);
+        let refund_cutoff_hours = parse_tunable(&get, "REFUND_CUTOFF_HOURS", 0u64, &mut error);
+        let stripe_webhook_max_age_hours =
+            parse_tunable(&get, "STRIPE_WEBHOOK_MAX_AGE_HOURS", DEFAULT_MAX_EVENT_AGE_HOURS, &mut error);
+        let favorite_digest_interval_hours =
+            parse_tunable(&get, "FAVORITE_DIGEST_INTERVAL_HOURS", 6u64, &mut error);
+        let location_index_refresh_minutes =
+            parse_tunable(&get, "LOCATION_INDEX_REFRESH_MINUTES", 15u64, &mut error);
+
+        let server_defaults = ServerSettings::default();
+        let server = ServerSettings {
+            workers: parse_tunable(&get, "HTTP_WORKERS", server_defaults.workers, &mut error),
+            keep_alive_secs: parse_tunable(&get, "HTTP_KEEP_ALIVE_SECS", server_defaults.keep_alive_secs, &mut error),
+            client_request_timeout_secs: parse_tunable(
+                &get,
+                "HTTP_CLIENT_REQUEST_TIMEOUT_SECS",
+                server_defaults.client_request_timeout_secs,
+                &mut error,
+            ),
+            backlog: parse_tunable(&get, "HTTP_BACKLOG", server_defaults.backlog, &mut error),
+        };
+        // Zero workers would start a server that never answers
+        for (name, value) in [("HTTP_WORKERS", server.workers as u64), ("HTTP_BACKLOG", server.backlog as u64)] {
+            if value == 0 {
+                error.invalid.push((name, value.to_string()));
+            }
+        }
+
+        let impersonation_token_minutes =
+            parse_tunable(&get, "IMPERSONATION_TOKEN_MINUTES", DEFAULT_TOKEN_MINUTES, &mut error);
+        if impersonation_token_minutes == 0 {
+            error.invalid.push(("IMPERSONATION_TOKEN_MINUTES", "0".to_string()));
+        }
+        let reservation_hold_minutes =
+            parse_tunable(&get, "RESERVATION_HOLD_MINUTES", DEFAULT_HOLD_MINUTES, &mut error);
+        if reservation_hold_minutes == 0 {
+            error.invalid.push(("RESERVATION_HOLD_MINUTES", "0".to_string()));
+        }
+        let integrity_check_interval_hours =
+            parse_tunable(&get, "INTEGRITY_CHECK_INTERVAL_HOURS", 24u64, &mut error);
+        let integrity_image_sample_size =
+            parse_tunable(&get, "INTEGRITY_IMAGE_SAMPLE_SIZE", 25u64, &mut error);
+        let min_activity_minutes =
+            parse_tunable(&get, "MIN_ACTIVITY_DURATION_MINUTES", DEFAULT_MIN_ACTIVITY_MINUTES, &mut error);
+        if min_activity_minutes == 0 {
+            error.invalid.push(("MIN_ACTIVITY_DURATION_MINUTES", "0".to_string()));
+        }
+        let limit_defaults = TripLimits::default();
+        let trip_limits = TripLimits {
+            max_trip_days: parse_tunable(&get, "MAX_TRIP_DAYS", limit_defaults.max_trip_days, &mut error),
+            max_party_size: parse_tunable(&get, "MAX_PARTY_SIZE", limit_defaults.max_party_size, &mut error),
+            max_activities_fetch: parse_tunable(&get, "MAX_ACTIVITIES_FETCH", limit_defaults.max_activities_fetch, &mut error),
+            max_vertex_activities: parse_tunable(&get, "MAX_VERTEX_ACTIVITIES", limit_defaults.max_vertex_activities, &mut error),
+            max_vertex_activities_per_type: parse_tunable(
+                &get,
+                "MAX_VERTEX_ACTIVITIES_PER_TYPE",
+                limit_defaults.max_vertex_activities_per_type,
+                &mut error,
+            ),
+        };
+        for (name, value) in [
+            ("MAX_TRIP_DAYS", trip_limits.max_trip_days),
+            ("MAX_PARTY_SIZE", trip_limits.max_party_size),
+            ("MAX_ACTIVITIES_FETCH", trip_limits.max_activities_fetch),
+            ("MAX_VERTEX_ACTIVITIES", trip_limits.max_vertex_activities),
+            ("MAX_VERTEX_ACTIVITIES_PER_TYPE", trip_limits.max_vertex_activities_per_type),
+        ] {
+            if value == 0 {
+                error.invalid.push((name, "0".to_string()));
+            }
+        }
+
+        let fallback = ActivityDefaults::default();
+        let activity_defaults = ActivityDefaults {
+            price_per_person: parse_tunable(&get, "VERTEX_DEFAULT_PRICE", fallback.price_per_person, &mut error),
+            duration_minutes: parse_tunable(&get, "VERTEX_DEFAULT_DURATION_MINUTES", fallback.duration_minutes, &mut error),
+            capacity_minimum: parse_tunable(&get, "VERTEX_DEFAULT_CAPACITY_MIN", fallback.capacity_minimum, &mut error),
+            capacity_maximum: parse_tunable(&get, "VERTEX_DEFAULT_CAPACITY_MAX", fallback.capacity_maximum, &mut error),
+        };
+        if !(activity_defaults.price_per_person >= 0.0 && activity_defaults.price_per_person.is_finite()) {
+            error.invalid.push(("VERTEX_DEFAULT_PRICE", activity_defaults.price_per_person.to_string()));
+        }
+        if activity_defaults.duration_minutes == 0 {
+            error.invalid.push(("VERTEX_DEFAULT_DURATION_MINUTES", "0".to_string()));
+        }
+        if activity_defaults.capacity_minimum == 0 {
+            error.invalid.push(("VERTEX_DEFAULT_CAPACITY_MIN", "0".to_string()));
+        }
+        if activity_defaults.capacity_maximum < activity_defaults.capacity_minimum {
+            error.invalid.push(("VERTEX_DEFAULT_CAPACITY_MAX", activity_defaults.capacity_maximum.to_string()));
+        }
+
+        let review_request_delay_days = parse_tunable(&get, "REVIEW_REQUEST_DELAY_DAYS", 2u64, &mut error);
+        let review_request_interval_hours =
+            parse_tunable(&get, "REVIEW_REQUEST_INTERVAL_HOURS", 6u64, &mut error);
+        let trip_status_interval_minutes =
+            parse_tunable(&get, "TRIP_STATUS_INTERVAL_MINUTES", 60u64, &mut error);
+        let mongodb_transactions = parse_tunable(&get, "MONGODB_TRANSACTIONS", false, &mut error);
+        let email_verification_max_attempts = parse_tunable(
+            &get,
+            "EMAIL_VERIFICATION_MAX_ATTEMPTS",
+            MAX_VERIFICATION_ATTEMPTS,
+            &mut error,
+        );
+        if email_verification_max_attempts == 0 {
+            error.invalid.push(("EMAIL_VERIFICATION_MAX_ATTEMPTS", "0".to_string()));
+        }
+        let budget_defaults = BudgetCaps::default();
+        let generation_budget = BudgetCaps {
+            vertex_queries: parse_tunable(&get, "GENERATION_MAX_VERTEX_QUERIES", budget_defaults.vertex_queries, &mut error),
+            maps_lookups: parse_tunable(&get, "GENERATION_MAX_MAPS_LOOKUPS", budget_defaults.maps_lookups, &mut error),
+        };
+
+        let pace_defaults = GeocodingPace::default();
+        let geocoding_pace = GeocodingPace {
+            batch_size: parse_tunable(&get, "GEOCODING_BATCH_SIZE", pace_defaults.batch_size, &mut error),
+            batch_delay_ms: parse_tunable(&get, "GEOCODING_BATCH_DELAY_MS", pace_defaults.batch_delay_ms, &mut error),
+        };
+        if geocoding_pace.batch_size == 0 {
+            error.invalid.push(("GEOCODING_BATCH_SIZE", "0".to_string()));
+        }
+        let recompute_costs_batch_size =
+            parse_tunable(&get, "RECOMPUTE_COSTS_BATCH_SIZE", DEFAULT_RECOMPUTE_BATCH_SIZE, &mut error);
+        if recompute_costs_batch_size == 0 {
+            error.invalid.push(("RECOMPUTE_COSTS_BATCH_SIZE", "0".to_string()));
+        }
+
+        let moderator = get("MODERATION_DENYLIST")
+            .map(|list| Moderator::from_list(&list))
+            .unwrap_or_default();
+
+        let score_presets = match get("SCORE_PREVIEW_PRESETS") {
+            Some(json) => parse_score_presets(&json).unwrap_or_else(|_| {
+                error.invalid.push(("SCORE_PREVIEW_PRESETS", json));
+                default_score_presets()
+            }),
+            None => default_score_presets(),
+        };
+
+        let security_headers = SecurityHeaders::from_lookup(&get, &mut error.invalid);
+
+        let storage = StorageConfig::from_lookup(get, &mut error.missing, &mut error.invalid);
+
+        let credential_check = parse_tunable(&get, "CREDENTIAL_CHECK", CredentialCheck::default(), &mut error);
+        let stripe_customer_on_delete = parse_tunable(
+            &get,
+            "STRIPE_CUSTOMER_ON_DELETE",
+            CustomerDisposition::default(),
+            &mut error,
+        );
+
+        let api_v1_sunset = get("API_V1_SUNSET").map(|_| {
+            parse_tunable(&get, "API_V1_SUNSET", NaiveDate::default(), &mut error)
+        });
+
+        let twilio_vars = ["TWILIO_ACCOUNT_SID", "TWILIO_AUTH_TOKEN", "TWILIO_FROM_NUMBER"];
+        let twilio = match twilio_vars.map(get) {
+            [Some(account_sid), Some(auth_token), Some(from_number)] => Some(TwilioSettings {
+                account_sid,
+                auth_token,
+                from_number,
+            }),
+            [None, None, None] => None,
+            // Some but not all set is a mistake, not SMS turned off
+            values => {
+                for (name, value) in twilio_vars.into_iter().zip(values) {
+                    if value.is_none() {
+                        error.missing.push(name);
+                    }
+                }
+                None
+            }
+        };
+
+        let oauth_client = |prefix: &str| OAuthClientSettings {
+            client_id: get(&format!("{}_CLIENT_ID", prefix)),
+            client_secret: get(&format!("{}_CLIENT_SECRET", prefix)),
+            redirect_uri: get(&format!("{}_REDIRECT_URI", prefix)),
+        };
+
+        let prefilter_defaults = DistancePrefilter::default();
+        let distance_prefilter = DistancePrefilter {
+            short_circuit_miles: parse_tunable(&get, "DISTANCE_SHORT_CIRCUIT_MILES", prefilter_defaults.short_circuit_miles, &mut error),
+            max_pair_miles: parse_tunable(&get, "DISTANCE_MAX_PAIR_MILES", prefilter_defaults.max_pair_miles, &mut error),
+        };
+
+        let floor_defaults = DayFloors::default();
+        let mut day_floor = |activities_var, hours_var, defaults: DayFloor| DayFloor {
+            min_activities: parse_tunable(&get, activities_var, defaults.min_activities, &mut error),
+            min_hours: parse_tunable(&get, hours_var, defaults.min_hours, &mut error),
+        };
+        let day_floors = DayFloors {
+            relaxed: day_floor("TRIP_PACE_RELAXED_MIN_ACTIVITIES", "TRIP_PACE_RELAXED_MIN_HOURS", floor_defaults.relaxed),
+            moderate: day_floor("TRIP_PACE_MODERATE_MIN_ACTIVITIES", "TRIP_PACE_MODERATE_MIN_HOURS", floor_defaults.moderate),
+            adventure: day_floor("TRIP_PACE_ADVENTURE_MIN_ACTIVITIES", "TRIP_PACE_ADVENTURE_MIN_HOURS", floor_defaults.adventure),
+        };
+
+        let image_resize_url = get("IMAGE_RESIZE_URL");
+        if let Some(template) = image_resize_url.as_ref().filter(|template| !template.contains("{path}")) {
+            error.invalid.push(("IMAGE_RESIZE_URL", template.clone()));
+        }
+
+        if !error.missing.is_empty() || !error.invalid.is_empty() {
+            return Err(error);
+        }
+
+        let frontend_url = get("FRONTEND_URL").unwrap_or_else(|| "http://localhost:3000".to_string());
+        let demo_defaults = DemoAccounts::default();
+
+        Ok(AppConfig {
+            port,
+            mongodb_uri: get("MONGODB_URI").unwrap_or_default(),
+            jwt_secret: get("JWT_SECRET").unwrap_or_default(),
+            stripe_secret_key: get("STRIPE_SECRET_KEY").unwrap_or_default(),
+            stripe_webhook_secret: get("STRIPE_WEBHOOK_SECRET").unwrap_or_default(),
+            email: EmailSettings {
+                sendgrid_api_key: get("SENDGRID_API_KEY"),
+                from_email: get("FROM_EMAIL").unwrap_or_else(|| EmailSettings::default().from_email),
+                frontend_url: frontend_url.clone(),
+            },
+            frontend_url,
+            storage: Arc::new(storage),
+            min_search_results,
+            search_weights,
+            availability_limited_threshold,
+            fx_rates_url: get("FX_RATES_URL")
+                .unwrap_or_else(|| "https://api.frankfurter.app/latest?from=USD".to_string()),
+            fx_refresh_hours,
+            default_phone_region,
+            api_token_rate_limit_per_minute,
+            price_alert_min_drop_percent,
+            price_alert_interval_hours,
+            retention,
+            retention_interval_hours,
+            image_resize_url,
+            sendgrid_webhook_public_key: get("SENDGRID_WEBHOOK_PUBLIC_KEY"),
+            email_change_sends_verification,
+            content_reports,
+            reschedule_cutoff_hours,
+            refund_cutoff_hours,
+            stripe_webhook_max_age_hours,
+            favorite_digest_interval_hours,
+            location_index_refresh_minutes,
+            server,
+            impersonation_token_minutes,
+            reservation_hold_minutes,
+            integrity_check_interval_hours,
+            integrity_image_sample_size,
+            min_activity_minutes,
+            trip_limits,
+            activity_defaults,
+            review_request_delay_days,
+            review_request_interval_hours,
+            trip_status_interval_minutes,
+            mongodb_transactions,
+            email_verification_max_attempts,
+            generation_budget,
+            moderator,
+            geocoding_pace,
+            recompute_costs_batch_size,
+            score_presets,
+            credential_check,
+            stripe_customer_on_delete,
+            api_v1_sunset,
+            placeholder_image_url: get("PLACEHOLDER_IMAGE_URL")
+                .unwrap_or_else(|| DEFAULT_PLACEHOLDER_IMAGE.to_string()),
+            security_headers,
+            environment: get("RUST_ENV").unwrap_or_else(|| "development".to_string()),
+            twilio,
+            google_oauth: oauth_client("GOOGLE"),
+            facebook_oauth: oauth_client("FACEBOOK"),
+            vertex_search: VertexSearchSettings::from_lookup(get),
+            google_maps_api_key: get("GOOGLE_MAPS_API_KEY"),
+            distance_prefilter,
+            day_floors,
+            activity_bucket: get("ACTIVITY_BUCKET"),
+            demo_accounts: DemoAccounts {
+                admin_email: get("DEMO_ADMIN_EMAIL").unwrap_or(demo_defaults.admin_email),
+                user_email: get("DEMO_USER_EMAIL").unwrap_or(demo_defaults.user_email),
+                password: get("DEMO_USER_PASSWORD").unwrap_or(demo_defaults.password),
+            },
+        })
+    }
+}
+
+/// Parse an optional numeric variable, recording unparseable values instead of
+/// silently falling back to the default
+fn parse_tunable<T: FromStr>(
+    get: &impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    default: T,
+    error: &mut ConfigError,
+) -> T {
+    match get(name) {
+        Some(value) => value.trim().parse().unwrap_or_else(|_| {
+            error.invalid.push((name, value));
+            default
+        }),
+        None => default,
+    }
+}
+
+/// Print which variables are set, missing, or falling back to defaults
+pub fn log_env_summary() {
+    log_env_summary_from(|name| env::var(name).ok());
+}
+
+fn log_env_summary_from<F>(lookup: F)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let is_set = |name: &str| lookup(name).is_some_and(|value| !value.trim().is_empty());
+
+    println!("Configuration check:");
+    for name in REQUIRED_VARS {
+        if is_set(name) {
+            println!("   ✅ {} (required)", name);
+        } else {
+            println!("   ❌ {} (required) is missing", name);
+        }
+    }
+    for name in RECOMMENDED_VARS {
+        if is_set(name) {
+            println!("   ✅ {}", name);
+        } else {
+            println!("   ⚠️  {} is not set, dependent features will be unavailable", name);
+        }
+    }
+    for name in TUNABLE_VARS {
+        if !is_set(name) {
+            println!("   ➖ {} not set, using default", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::BucketKind;
+    use std::collections::HashMap;
+
+    fn lookup_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_reports_all_missing_required_vars_at_once() {
+        let err = AppConfig::from_lookup(lookup_from(&[("MONGODB_URI", "mongodb://localhost")]))
+            .unwrap_err();
+        assert_eq!(
+            err.missing,
+            vec!["JWT_SECRET", "STRIPE_SECRET_KEY", "STRIPE_WEBHOOK_SECRET"]
+        );
+        assert!(err.to_string().contains("JWT_SECRET, STRIPE_SECRET_KEY, STRIPE_WEBHOOK_SECRET"));
+    }
+
+    #[test]
+    fn test_required_buckets_must_be_configured() {
+        let required = [
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+            ("STORAGE_REQUIRED_BUCKETS", "itinerary_images,profile_pictures"),
+        ];
+        let err = AppConfig::from_lookup(lookup_from(&required)).unwrap_err();
+        assert_eq!(err.missing, vec!["PROFILE_PIC_BUCKET"]);
+
+        let mut vars = required.to_vec();
+        vars.push(("PROFILE_PIC_BUCKET", "actota-profile-pictures"));
+        let config = AppConfig::from_lookup(lookup_from(&vars)).unwrap();
+        assert_eq!(
+            config.storage.bucket(BucketKind::ProfilePictures).unwrap().bucket,
+            "actota-profile-pictures"
+        );
+    }
+
+    #[test]
+    fn test_loads_with_required_vars_and_default_port() {
+        let config = AppConfig::from_lookup(lookup_from(&[
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+        ]))
+        .unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.stripe_webhook_secret, "whsec");
+        assert_eq!(config.min_search_results, None);
+        assert_eq!(config.search_weights.location_weight, SearchWeights::default().location_weight);
+        assert_eq!(config.server, ServerSettings::default());
+        assert!(config.server.workers >= 1);
+        assert_eq!(config.trip_limits, TripLimits::default());
+        assert_eq!(config.activity_defaults, ActivityDefaults::default());
+        assert!(!config.mongodb_transactions);
+        assert_eq!(config.email_verification_max_attempts, MAX_VERIFICATION_ATTEMPTS);
+        assert_eq!(config.generation_budget, BudgetCaps::default());
+        assert_eq!(config.recompute_costs_batch_size, DEFAULT_RECOMPUTE_BATCH_SIZE);
+        assert_eq!(config.environment, "development");
+        assert_eq!(config.day_floors, DayFloors::default());
+        assert_eq!(config.distance_prefilter, DistancePrefilter::default());
+        assert_eq!(config.twilio, None);
+        assert_eq!(config.vertex_search, None);
+        assert_eq!(config.email.frontend_url, config.frontend_url);
+    }
+
+    #[test]
+    fn test_service_settings_are_read_once_and_validated() {
+        let required = [
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+        ];
+
+        let config = AppConfig::from_lookup(lookup_from(
+            &[
+                &required[..],
+                &[
+                    ("TWILIO_ACCOUNT_SID", "AC1"),
+                    ("TWILIO_AUTH_TOKEN", "token"),
+                    ("TWILIO_FROM_NUMBER", "+15550100"),
+                    ("TRIP_PACE_RELAXED_MIN_ACTIVITIES", "2"),
+                    ("DISTANCE_MAX_PAIR_MILES", "40"),
+                ],
+            ]
+            .concat(),
+        ))
+        .unwrap();
+        assert_eq!(config.twilio.map(|twilio| twilio.from_number), Some("+15550100".to_string()));
+        assert_eq!(config.day_floors.relaxed.min_activities, 2);
+        assert_eq!(config.day_floors.moderate, DayFloors::default().moderate);
+        assert_eq!(config.distance_prefilter.max_pair_miles, 40.0);
+
+        // Half-configured Twilio is missing the rest, not SMS turned off
+        let err = AppConfig::from_lookup(lookup_from(
+            &[
+                &required[..],
+                &[
+                    ("TWILIO_ACCOUNT_SID", "AC1"),
+                    ("DISTANCE_SHORT_CIRCUIT_MILES", "close"),
+                    ("TRIP_PACE_ADVENTURE_MIN_HOURS", "lots"),
+                ],
+            ]
+            .concat(),
+        ))
+        .unwrap_err();
+        assert_eq!(err.missing, vec!["TWILIO_AUTH_TOKEN", "TWILIO_FROM_NUMBER"]);
+        assert_eq!(
+            err.invalid,
+            vec![
+                ("DISTANCE_SHORT_CIRCUIT_MILES", "close".to_string()),
+                ("TRIP_PACE_ADVENTURE_MIN_HOURS", "lots".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_weights_reflect_env_at_startup() {
+        let config = AppConfig::from_lookup(lookup_from(&[
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+            ("SEARCH_ACTIVITY_WEIGHT", "40"),
+            ("SEARCH_MIN_SCORE", "20.5"),
+        ]))
+        .unwrap();
+        assert_eq!(config.search_weights.activity_weight, 40.0);
+        assert_eq!(config.search_weights.minimum_score, 20.5);
+        assert_eq!(config.search_weights.location_weight, SearchWeights::default().location_weight);
+
+        // Handlers get clones of the config; they share the one set of weights
+        let handler_config = config.clone();
+        assert!(Arc::ptr_eq(&handler_config.search_weights, &config.search_weights));
+    }
+
+    #[test]
+    fn test_server_settings_are_tunable_and_validated() {
+        let required = [
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+        ];
+
+        let config = AppConfig::from_lookup(lookup_from(
+            &[&required[..], &[("HTTP_WORKERS", "4"), ("HTTP_KEEP_ALIVE_SECS", "0")]].concat(),
+        ))
+        .unwrap();
+        assert_eq!(config.server.workers, 4);
+        assert_eq!(config.server.keep_alive_secs, 0);
+        assert_eq!(config.server.backlog, 1024);
+
+        let err = AppConfig::from_lookup(lookup_from(
+            &[
+                &required[..],
+                &[("HTTP_WORKERS", "0"), ("HTTP_BACKLOG", "-5"), ("HTTP_CLIENT_REQUEST_TIMEOUT_SECS", "1m")],
+            ]
+            .concat(),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.invalid,
+            vec![
+                ("HTTP_CLIENT_REQUEST_TIMEOUT_SECS", "1m".to_string()),
+                ("HTTP_BACKLOG", "-5".to_string()),
+                ("HTTP_WORKERS", "0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_activity_defaults_are_tunable_and_validated() {
+        let required = [
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+        ];
+
+        let config = AppConfig::from_lookup(lookup_from(
+            &[&required[..], &[("VERTEX_DEFAULT_PRICE", "80"), ("VERTEX_DEFAULT_CAPACITY_MAX", "8")]].concat(),
+        ))
+        .unwrap();
+        assert_eq!(config.activity_defaults.price_per_person, 80.0);
+        assert_eq!(config.activity_defaults.capacity_maximum, 8);
+        assert_eq!(config.activity_defaults.duration_minutes, ActivityDefaults::default().duration_minutes);
+
+        let err = AppConfig::from_lookup(lookup_from(
+            &[
+                &required[..],
+                &[
+                    ("VERTEX_DEFAULT_PRICE", "-1"),
+                    ("VERTEX_DEFAULT_DURATION_MINUTES", "0"),
+                    ("VERTEX_DEFAULT_CAPACITY_MIN", "10"),
+                    ("VERTEX_DEFAULT_CAPACITY_MAX", "4"),
+                ],
+            ]
+            .concat(),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.invalid,
+            vec![
+                ("VERTEX_DEFAULT_PRICE", "-1".to_string()),
+                ("VERTEX_DEFAULT_DURATION_MINUTES", "0".to_string()),
+                ("VERTEX_DEFAULT_CAPACITY_MAX", "4".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blank_and_unparseable_values_are_rejected() {
+        let err = AppConfig::from_lookup(lookup_from(&[
+            ("MONGODB_URI", "mongodb://localhost"),
+            ("JWT_SECRET", "  "),
+            ("STRIPE_SECRET_KEY", "sk_test"),
+            ("STRIPE_WEBHOOK_SECRET", "whsec"),
+            ("PORT", "eighty"),
+            ("MIN_SEARCH_RESULTS", "2.5"),
+            ("SEARCH_MIN_SCORE", "high"),
+            ("RECOMPUTE_COSTS_BATCH_SIZE", "0"),
+            ("SCORE_PREVIEW_PRESETS", "{\"family\": 4}"),
+            ("STRIPE_CUSTOMER_ON_DELETE", "archive"),
+            ("API_V1_SUNSET", "next spring"),
+            ("IMAGE_RESIZE_URL", "https://images.example.com/resize?w={width}"),
+        ]))
+        .unwrap_err();
+        assert_eq!(err.missing, vec!["JWT_SECRET"]);
+        assert_eq!(
+            err.invalid,
+            vec![
+                ("PORT", "eighty".to_string()),
+                ("MIN_SEARCH_RESULTS", "2.5".to_string()),
+                ("SEARCH_MIN_SCORE", "high".to_string()),
+                ("RECOMPUTE_COSTS_BATCH_SIZE", "0".to_string()),
+                ("SCORE_PREVIEW_PRESETS", "{\"family\": 4}".to_string()),
+                ("STRIPE_CUSTOMER_ON_DELETE", "archive".to_string()),
+                ("API_V1_SUNSET", "next spring".to_string()),
+                ("IMAGE_RESIZE_URL", "https://images.example.com/resize?w={width}".to_string()),
+            ]
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use mongodb::{
-    options::{ClientOptions, ServerApi, ServerApiVersion},
-    Client,
+    options::{
+        Acknowledgment, ClientOptions, CollectionOptions, ReadPreference, SelectionCriteria,
+        ServerApi, ServerApiVersion, WriteConcern,
+    },
+    Client, Collection,
 };
 use std::sync::Arc;
 use std::time::Duration;
@@ -69,6 +72,28 @@ pub async fn create_mongo_client(uri: &String) -> Arc<Client> {
     Arc::new(client)
 }
 
+/// Handle for read-only traffic that can tolerate a little replication lag (public
+/// content, itinerary listings). Reads prefer a secondary, so they keep working while
+/// the primary steps down for maintenance.
+pub fn read_only_collection<T: Send + Sync>(client: &Client, database: &str, collection: &str) -> Collection<T> {
+    let options = CollectionOptions::builder()
+        .selection_criteria(SelectionCriteria::ReadPreference(ReadPreference::SecondaryPreferred {
+            options: None,
+        }))
+        .build();
+    client.database(database).collection_with_options(collection, options)
+}
+
+/// Handle for bookings and payments: reads from the primary and writes acknowledged by
+/// a majority, so a confirmed booking can't be lost to a failover
+pub fn primary_collection<T: Send + Sync>(client: &Client, database: &str, collection: &str) -> Collection<T> {
+    let options = CollectionOptions::builder()
+        .selection_criteria(SelectionCriteria::ReadPreference(ReadPreference::Primary))
+        .write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build())
+        .build();
+    client.database(database).collection_with_options(collection, options)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
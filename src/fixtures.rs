@@ -0,0 +1,190 @@
+//! Test-only constructors for the records unit tests build over and over. Each
+//! fills in a plausible minimum; tests override what they care about with struct
+//! update syntax.
+
+use actix_web::web;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::options::{ClientOptions, ServerAddress};
+use mongodb::Client;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::models::account::User;
+use crate::models::activity::{Activity, Address, Capacity};
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::services::distance_service::{DistanceMatrix, DistanceResult, DistanceSource, TravelMode};
+
+/// Never connects. For handlers that answer before running a query, such as
+/// ownership checks.
+pub const UNREACHABLE_MONGODB_URI: &str = "mongodb://localhost:1";
+
+impl Activity {
+    /// A one-hour, $50 activity in Denver for up to ten people, open every day
+    pub fn test(title: &str) -> Self {
+        Activity {
+            id: Some(ObjectId::new()),
+            company: "Test Co".to_string(),
+            company_id: "test".to_string(),
+            booking_link: String::new(),
+            online_booking_status: "available".to_string(),
+            guide: None,
+            title: title.to_string(),
+            description: title.to_string(),
+            activity_types: Vec::new(),
+            tags: Vec::new(),
+            price_per_person: 50.0,
+            duration_minutes: 60,
+            daily_time_slots: Vec::new(),
+            address: Address {
+                street: String::new(),
+                unit: String::new(),
+                city: "Denver".to_string(),
+                state: "CO".to_string(),
+                zip: String::new(),
+                country: "USA".to_string(),
+            },
+            whats_included: Vec::new(),
+            weight_limit_lbs: None,
+            age_requirement: None,
+            height_requiremnt: None,
+            blackout_date_ranges: None,
+            operating_days: None,
+            closed_dates: Vec::new(),
+            closed_on_holidays: false,
+            capacity: Capacity {
+                minimum: 1,
+                maximum: 10,
+            },
+            location: None,
+            images: Vec::new(),
+            defaulted_fields: Vec::new(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl BookingDetails {
+    /// A booking of a fresh itinerary by a fresh user, without payment details
+    pub fn test(status: PaymentStatus, arrival: DateTime, departure: DateTime) -> Self {
+        BookingDetails {
+            id: Some(ObjectId::new()),
+            user_id: ObjectId::new(),
+            itinerary_id: ObjectId::new(),
+            customer_id: None,
+            transaction_id: None,
+            arrival_datetime: arrival,
+            departure_datetime: departure,
+            status,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: None,
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl User {
+    /// A user as stored with only the required fields, everything else defaulted
+    pub fn test(email: &str) -> Self {
+        serde_json::from_value(serde_json::json!({
+            "email": email,
+            "password": "hashed",
+        }))
+        .unwrap()
+    }
+}
+
+impl Claims {
+    /// A plain user's unexpired session token
+    pub fn test(user_id: ObjectId) -> Self {
+        Claims {
+            sub: "traveler@example.com".to_string(),
+            exp: usize::MAX,
+            iat: 0,
+            user_id: user_id.to_hex(),
+            role: Some("user".to_string()),
+            email: None,
+            jti: None,
+            token_scopes: None,
+            impersonation: None,
+        }
+    }
+}
+
+/// A client for `UNREACHABLE_MONGODB_URI`, shared the way handlers receive it
+pub async fn unreachable_client() -> web::Data<Arc<Client>> {
+    web::Data::new(Arc::new(Client::with_uri_str(UNREACHABLE_MONGODB_URI).await.unwrap()))
+}
+
+/// A client whose distance cache reads and writes fail within 50ms and are skipped
+pub fn cacheless_client() -> Arc<Client> {
+    let options = ClientOptions::builder()
+        .hosts(vec![ServerAddress::Tcp {
+            host: "localhost".to_string(),
+            port: Some(1),
+        }])
+        .server_selection_timeout(Duration::from_millis(50))
+        .build();
+    Arc::new(Client::with_options(options).unwrap())
+}
+
+/// Answers every distance lookup with a 30-minute drive, counting the pairs asked
+/// for. Clones share the count.
+#[derive(Clone, Default)]
+pub struct FixedMatrix(pub Arc<AtomicUsize>);
+
+impl DistanceMatrix for FixedMatrix {
+    async fn fetch(
+        &self,
+        _origin: (f64, f64),
+        _destination: (f64, f64),
+        _travel_mode: &TravelMode,
+        _with_traffic: bool,
+    ) -> Result<DistanceResult, Box<dyn std::error::Error>> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(DistanceResult {
+            distance_meters: 40_000,
+            duration_minutes: 30,
+            duration_in_traffic_minutes: None,
+            from_cache: false,
+            source: DistanceSource::GoogleMaps,
+            too_far: false,
+        })
+    }
+
+    async fn fetch_batch(
+        &self,
+        origins: Vec<(f64, f64)>,
+        destinations: Vec<(f64, f64)>,
+        travel_mode: &TravelMode,
+        with_traffic: bool,
+    ) -> Result<Vec<DistanceResult>, Box<dyn std::error::Error>> {
+        let mut results = Vec::new();
+        for (origin, destination) in origins.into_iter().zip(destinations) {
+            results.push(self.fetch(origin, destination, travel_mode, with_traffic).await?);
+        }
+        Ok(results)
+    }
+}
+
+/// The smallest valid configuration, pointing at `UNREACHABLE_MONGODB_URI`
+pub fn app_config(jwt_secret: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(UNREACHABLE_MONGODB_URI.to_string()),
+        "JWT_SECRET" => Some(jwt_secret.to_string()),
+        "STRIPE_SECRET_KEY" | "STRIPE_WEBHOOK_SECRET" => Some("sk_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
@@ -1,5 +1,331 @@
+pub mod config;
 pub mod db;
 pub mod middleware;
 pub mod models;
 pub mod routes;
-pub mod services;
\ No newline at end of file
+pub mod services;
+
+#[cfg(test)]
+mod fixtures;
+
+use actix_web::{
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    App, Error,
+};
+
+/// The API's routes on a bare `App`, registered exactly as `main` registers them.
+/// Callers add shared state with `app_data`; `main` also adds logging, CORS and
+/// compression around the same routes.
+pub fn build_app() -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(routes::json_config())
+        .configure(routes::configure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::Method, http::StatusCode, test as http_test, HttpResponse};
+    use mongodb::bson::oid::ObjectId;
+
+    use crate::fixtures::app_config;
+    use crate::models::account::UserRole;
+    use crate::routes::account::auth::generate_token;
+    use crate::routes::versioning::V2_EXEMPT_PATHS;
+
+    /// Every path and method the API serves. Add new routes here.
+    const ROUTES: &[(&str, &str)] = &[
+        ("GET", "/health"),
+        ("GET", "/request-info"),
+        ("GET", "/"),
+        ("POST", "/stripe/webhook"),
+        ("POST", "/payment/payment-intent"),
+        ("POST", "/payment/capture-payment"),
+        ("POST", "/payment/reserve"),
+        ("DELETE", "/payment/reserve/r1"),
+        ("POST", "/payment/apply-gift-card"),
+        ("POST", "/auth/signup"),
+        ("POST", "/auth/signin"),
+        ("GET", "/auth/google"),
+        ("GET", "/auth/google/callback"),
+        ("GET", "/auth/facebook"),
+        ("GET", "/auth/facebook/callback"),
+        ("POST", "/auth/link-oauth"),
+        ("GET", "/auth/session"),
+        ("POST", "/auth/impersonation/end"),
+        ("POST", "/email-verifications"),
+        ("PUT", "/email-verifications/v1"),
+        ("GET", "/email-verifications/v1/status"),
+        ("GET", "/account/u1"),
+        ("PUT", "/account/u1"),
+        ("DELETE", "/account/u1"),
+        ("GET", "/account/u1/export"),
+        ("GET", "/account/u1/favorites"),
+        ("POST", "/account/u1/favorites/bulk"),
+        ("POST", "/account/u1/favorites/i1"),
+        ("DELETE", "/account/u1/favorites/i1"),
+        ("GET", "/account/u1/bookings"),
+        ("GET", "/account/u1/bookings/b1"),
+        ("GET", "/account/u1/bookings/itinerary/i1"),
+        ("POST", "/account/u1/bookings/itinerary/i1"),
+        ("DELETE", "/account/u1/bookings/itinerary/i1"),
+        ("PUT", "/account/u1/bookings/itinerary/i1/payment"),
+        ("POST", "/account/u1/bookings/itinerary/i1/with-payment"),
+        ("POST", "/account/u1/bookings/b1/cancel"),
+        ("PUT", "/account/u1/bookings/b1/special-requests"),
+        ("GET", "/account/u1/bookings/b1/notes"),
+        ("PUT", "/account/u1/bookings/b1/notes"),
+        ("PUT", "/account/u1/bookings/b1/reschedule"),
+        ("GET", "/account/u1/payment-methods"),
+        ("POST", "/account/u1/payment-methods"),
+        ("GET", "/account/u1/transactions"),
+        ("GET", "/account/u1/security-events"),
+        ("GET", "/account/u1/recent-searches"),
+        ("POST", "/account/u1/recent-searches/f1/rerun"),
+        ("GET", "/account/u1/recently-viewed"),
+        ("GET", "/account/u1/notifications"),
+        ("PUT", "/account/u1/notifications"),
+        ("POST", "/account/u1/api-tokens"),
+        ("GET", "/account/u1/api-tokens"),
+        ("DELETE", "/account/u1/api-tokens/t1"),
+        ("POST", "/account/u1/customer"),
+        ("DELETE", "/account/u1/payment-methods/pm1"),
+        ("POST", "/account/u1/payment-methods/attach"),
+        ("POST", "/account/u1/payment-methods/detach"),
+        ("POST", "/account/u1/update-customer-id"),
+        ("POST", "/account/u1/profile-picture"),
+        ("POST", "/account/u1/email-verifications"),
+        ("GET", "/account/u1/email-verifications"),
+        ("PUT", "/account/u1/email-verifications/v1"),
+        ("GET", "/admin/users"),
+        ("POST", "/admin/activities/backfill-coordinates"),
+        ("GET", "/admin/activities/duplicates"),
+        ("POST", "/admin/activities/merge"),
+        ("PUT", "/admin/activities/{id}/images"),
+        ("POST", "/admin/bookings"),
+        ("POST", "/admin/bookings/status"),
+        ("POST", "/admin/bookings/b1/send-review-request"),
+        ("GET", "/admin/content-flags"),
+        ("POST", "/admin/content-flags/resolve"),
+        ("GET", "/admin/email/suppressions"),
+        ("DELETE", "/admin/email/suppressions/a@example.com"),
+        ("GET", "/admin/export/bookings"),
+        ("GET", "/admin/export/newsletter"),
+        ("PUT", "/admin/users/u1/role"),
+        ("POST", "/admin/users/u1/impersonate"),
+        ("POST", "/admin/itineraries/bulk"),
+        ("POST", "/admin/itineraries/featured/add"),
+        ("POST", "/admin/itineraries/recompute-costs"),
+        ("PUT", "/admin/itineraries/i1/images"),
+        ("POST", "/admin/itineraries/i1/images/discover"),
+        ("PUT", "/admin/itineraries/i1/days"),
+        ("GET", "/admin/itineraries/i1/provenance"),
+        ("POST", "/admin/itineraries/i1/score-preview"),
+        ("GET", "/admin/payments/reconciliation"),
+        ("POST", "/admin/payments/reconciliation/r1/resolve"),
+        ("GET", "/admin/retention/runs"),
+        ("POST", "/admin/retention/run-now"),
+        ("GET", "/admin/integrity/dangling-references"),
+        ("POST", "/admin/stripe/events/evt_1/reprocess"),
+        ("GET", "/admin/search-experiments"),
+        ("POST", "/admin/search-experiments"),
+        ("PUT", "/admin/search-experiments/e1"),
+        ("POST", "/admin/search-experiments/e1/activate"),
+        ("POST", "/admin/search-experiments/e1/deactivate"),
+        ("GET", "/admin/stock-images"),
+        ("PUT", "/admin/stock-images"),
+        ("GET", "/admin/feature-flags"),
+        ("PUT", "/admin/feature-flags"),
+        #[cfg(feature = "demo-tools")]
+        ("POST", "/admin/seed-demo-data"),
+        ("GET", "/admin/gift-cards"),
+        ("POST", "/admin/gift-cards"),
+        ("GET", "/admin/gift-cards/g1"),
+        ("PUT", "/admin/gift-cards/g1"),
+        ("DELETE", "/admin/gift-cards/g1"),
+        ("GET", "/operator/bookings"),
+        ("GET", "/operator/activities"),
+        ("POST", "/newsletter/subscribe"),
+        ("PUT", "/newsletter/unsubscribe"),
+        ("POST", "/webhooks/sendgrid/events"),
+        ("POST", "/review-requests/unsubscribe"),
+        ("GET", "/review-requests/t1"),
+        ("POST", "/review-requests/t1/reviews"),
+        ("GET", "/locations"),
+        ("GET", "/locations/autocomplete"),
+        ("GET", "/lodging"),
+        ("GET", "/activities"),
+        ("GET", "/activities/a1/availability"),
+        ("GET", "/itineraries"),
+        ("POST", "/itineraries/search"),
+        ("POST", "/itineraries/search-or-generate"),
+        ("GET", "/itineraries/i1"),
+        ("GET", "/itineraries/i1/availability"),
+        ("GET", "/itineraries/i1/distance-matrix"),
+        ("GET", "/itineraries/i1/map-geojson"),
+        ("POST", "/itineraries/find"),
+        ("POST", "/itineraries/validate"),
+        ("POST", "/itineraries/i1/report"),
+    ];
+
+    #[actix_rt::test]
+    async fn test_route_inventory() {
+        let secret = "route-inventory-secret";
+        let config = app_config(secret);
+        let token =
+            generate_token(secret, "admin@example.com", ObjectId::new(), Some(&UserRole::Admin))
+                .unwrap();
+
+        // Unrouted requests get a status no handler returns, so a matched route can't
+        // be mistaken for a missing one. Only the config is registered, so matched
+        // handlers stop at their extractors without touching a database.
+        let app = http_test::init_service(
+            build_app()
+                .app_data(actix_web::web::Data::new(config))
+                .default_service(actix_web::web::to(HttpResponse::ImATeapot)),
+        )
+        .await;
+
+        // Every route is served unprefixed and under /v1, and under /v2 unless exempt
+        let mut missing = Vec::new();
+        for (method, path) in ROUTES {
+            for prefix in ["", "/v1", "/v2"] {
+                if prefix == "/v2" && V2_EXEMPT_PATHS.contains(path) {
+                    continue;
+                }
+                let path = format!("{}{}", prefix, path);
+                let request = http_test::TestRequest::default()
+                    .method(Method::from_bytes(method.as_bytes()).unwrap())
+                    .uri(&path)
+                    .insert_header(("Authorization", format!("Bearer {}", token)))
+                    .to_request();
+                // Route middleware that needs the database (e.g. `RequireRole::verified`)
+                // fails before the handler runs, which still means the route matched
+                let status = match http_test::try_call_service(&app, request).await {
+                    Ok(response) => response.status(),
+                    Err(error) => error.as_response_error().status_code(),
+                };
+                if status == StatusCode::IM_A_TEAPOT {
+                    missing.push(format!("{} {}", method, path));
+                }
+            }
+        }
+        assert!(missing.is_empty(), "routes not registered: {:?}", missing);
+        for path in V2_EXEMPT_PATHS {
+            assert!(
+                ROUTES.iter().any(|(_, route)| route == path),
+                "{} is exempt from v2 but isn't a route",
+                path
+            );
+        }
+
+        // And the teapot really does mean "no route"
+        let request = http_test::TestRequest::delete()
+            .uri("/itineraries/search")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let response = http_test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    }
+
+    #[actix_rt::test]
+    async fn test_v1_responses_are_marked_deprecated() {
+        let app = http_test::init_service(build_app()).await;
+        for (path, deprecated) in [("/locations", true), ("/v1/locations", true), ("/v2/locations", false)] {
+            let response = http_test::call_service(&app, http_test::TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(response.headers().contains_key("deprecation"), deprecated, "{}", path);
+            // No sunset date without AppConfig
+            assert!(!response.headers().contains_key("sunset"), "{}", path);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_api_token_routes_match_registered_patterns() {
+        let routes = crate::middleware::auth::API_TOKEN_ROUTES
+            .iter()
+            .map(|(method, pattern, _)| (*method, *pattern));
+        assert_patterns_registered(routes).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_impersonation_routes_match_registered_patterns() {
+        let routes = crate::middleware::auth::IMPERSONATION_ALLOWED_ROUTES
+            .iter()
+            .chain(crate::middleware::auth::IMPERSONATION_PRIVATE_ROUTES)
+            .copied();
+        assert_patterns_registered(routes).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_money_routes_are_blocked_while_impersonating() {
+        // Answer with the pattern the auth middleware would see
+        let app = http_test::init_service(build_app().wrap_fn(|req, _| {
+            let pattern = req.match_pattern().unwrap_or_default();
+            std::future::ready(Ok(req.into_response(HttpResponse::Ok().body(pattern))))
+        }))
+        .await;
+
+        for (method, path) in [
+            ("PUT", "/account/u1/bookings/b1/reschedule"),
+            ("POST", "/account/u1/bookings/b1/cancel"),
+            ("POST", "/account/u1/bookings/itinerary/i1/with-payment"),
+            ("DELETE", "/account/u1"),
+            ("PUT", "/account/u1"),
+            ("POST", "/payment/reserve"),
+            ("POST", "/payment/payment-intent"),
+            ("POST", "/payment/capture-payment"),
+            ("POST", "/account/u1/payment-methods/attach"),
+            ("POST", "/account/u1/payment-methods/detach"),
+            ("POST", "/account/u1/api-tokens"),
+            ("POST", "/v2/account/u1/customer"),
+        ] {
+            let request = http_test::TestRequest::default()
+                .method(Method::from_bytes(method.as_bytes()).unwrap())
+                .uri(path)
+                .to_request();
+            let pattern = http_test::call_and_read_body(&app, request).await;
+            let pattern = std::str::from_utf8(&pattern).unwrap();
+            assert!(
+                crate::middleware::auth::blocked_while_impersonating(method, Some(pattern)),
+                "{} {} ({})",
+                method,
+                path,
+                pattern
+            );
+        }
+    }
+
+    /// Route tables keyed on `match_pattern` silently stop applying if a pattern
+    /// drifts from the one registered
+    async fn assert_patterns_registered(routes: impl Iterator<Item = (&'static str, &'static str)>) {
+        // Answer every request with the route pattern it matched, before any
+        // handler or middleware runs
+        let app = http_test::init_service(build_app().wrap_fn(|req, _| {
+            let pattern = req.match_pattern().unwrap_or_default();
+            std::future::ready(Ok(req.into_response(HttpResponse::Ok().body(pattern))))
+        }))
+        .await;
+
+        for (method, pattern) in routes {
+            let path = pattern
+                .split('/')
+                .map(|segment| if segment.starts_with('{') { "x1" } else { segment })
+                .collect::<Vec<_>>()
+                .join("/");
+            let request = http_test::TestRequest::default()
+                .method(Method::from_bytes(method.as_bytes()).unwrap())
+                .uri(&path)
+                .to_request();
+            let matched = http_test::call_and_read_body(&app, request).await;
+            assert_eq!(matched, pattern.as_bytes(), "{} {}", method, pattern);
+        }
+    }
+}
@@ -1,55 +1,74 @@
 use std::{env, path::PathBuf, sync::Arc};
 
-use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::{middleware::Logger, web, App, HttpServer};
 use env_logger::Env;
-use routes::payment::{handle_stripe_webhook, StripeConfig};
+use routes::payment::StripeConfig;
+use services::api_token_service::{ApiTokenRateLimiter, ApiTokenService};
+use services::availability_service::AvailabilityCache;
+use services::credential_check::{self, GcsProbe};
+use services::email_suppression_service::EmailSuppressionService;
+use services::favorite_digest_service::FavoriteDigestService;
+use services::feature_flags::{self, Flags};
+use services::fx_service::FxRates;
+use services::image_fallback::{self, StockImages};
+use services::location_autocomplete::LocationAutocomplete;
+use services::write_behind::WriteBehindQueue;
+use services::price_alert_service::PriceAlertJob;
+use services::reservation_service::ReservationService;
+use services::retention_service::RetentionService;
+use services::review_request_service::ReviewRequestService;
+use services::search_experiments::SearchExperimentService;
+use services::trip_notes_service::TripNotesService;
+use services::trip_status_service::{ReviewRequestHook, TripStatusService};
+use services::integrity_service::IntegrityService;
+use services::security_event_service::SecurityEventQueue;
+use services::self_check::{self, LiveProbes, SelfCheckOutput};
+use services::oauth_link_service::OAuthLinkService;
+use services::recently_viewed_service::RecentlyViewedService;
+use services::itinerary_bulk_service::ItineraryBulkService;
+use services::payment_idempotency::PaymentIdempotencyService;
+use services::admin_booking_service::AdminBookingService;
+use services::payment_teardown::{self, PaymentTeardownService};
+use services::storage::{BucketKind, Storage};
+use services::webhook_replay::ProcessedWebhookService;
 
+mod config;
 mod db;
 mod middleware;
 mod models;
 mod routes;
 mod services;
 
-// General request diagnostic endpoint
-async fn request_info(req: HttpRequest) -> impl Responder {
-    let protocol = req.connection_info().scheme().to_string();
-    let version = format!("{:?}", req.version());
-
-    let headers = req
-        .headers()
-        .iter()
-        .map(|(name, value)| format!("{}: {:?}", name, value))
-        .collect::<Vec<String>>()
-        .join("\n");
-
-    HttpResponse::Ok().content_type("text/plain").body(format!(
-        "Protocol: {}\nHTTP Version: {}\n\nHeaders:\n{}",
-        protocol, version, headers
-    ))
-}
+#[cfg(test)]
+mod fixtures;
 
-// Setup credentials for local development
+// Setup credentials for local development. `quiet` keeps stdout clean for
+// `--self-check=json`.
 #[cfg(debug_assertions)]
-fn setup_credentials() {
-    println!("Setting up Google Cloud credentials for development");
+fn setup_credentials(quiet: bool) {
+    let say = |line: String| {
+        if !quiet {
+            println!("{}", line);
+        }
+    };
+    say("Setting up Google Cloud credentials for development".to_string());
 
     // Check if credentials are already set in the environment
     if let Ok(existing_creds) = env::var("GOOGLE_APPLICATION_CREDENTIALS") {
-        println!(
+        say(format!(
             "Using Google credentials from environment variable: {}",
             existing_creds
-        );
+        ));
         return;
     }
 
     // Fall back to file-based credentials for local development only
     let credentials_path = PathBuf::from("credentials/service-account.json");
     if credentials_path.exists() {
-        println!(
+        say(format!(
             "Using Google credentials from file: {}",
             credentials_path.display()
-        );
+        ));
 
         // Set path-based credential variable
         env::set_var(
@@ -57,361 +76,278 @@ fn setup_credentials() {
             credentials_path.to_str().unwrap_or_default(),
         );
     } else {
-        println!("No explicit Google credentials found. Using Application Default Credentials.");
+        say("No explicit Google credentials found. Using Application Default Credentials.".to_string());
     }
 }
 
 #[cfg(not(debug_assertions))]
-fn setup_credentials() {
-    println!("Setting up Google Cloud credentials for production");
+fn setup_credentials(quiet: bool) {
+    let say = |line: &str| {
+        if !quiet {
+            println!("{}", line);
+        }
+    };
+    say("Setting up Google Cloud credentials for production");
 
     // Check if we're running in Cloud Run
     let is_cloud_run = env::var("K_SERVICE").is_ok();
 
     if is_cloud_run {
-        println!("Detected Cloud Run environment - using Application Default Credentials");
+        say("Detected Cloud Run environment - using Application Default Credentials");
         // When running in Cloud Run, the google-cloud-storage crate
         // will automatically use the service account attached to the service
     } else {
-        println!(
-            "Not running in Cloud Run - will try to use local Application Default Credentials"
-        );
+        say("Not running in Cloud Run - will try to use local Application Default Credentials");
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("Application starting...");
+    // `--self-check[=json]` checks every dependency and exits instead of serving
+    let self_check = match self_check::output_mode(env::args().skip(1)) {
+        Ok(self_check) => self_check,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        }
+    };
+    let quiet = self_check == Some(SelfCheckOutput::Json);
+    if !quiet {
+        println!("Application starting...");
+    }
 
     // Setup credentials for both development and production
-    setup_credentials();
+    setup_credentials(quiet);
 
     // Initialize logging
     env_logger::init_from_env(
         Env::default().default_filter_or("info,actix_web=debug,actix_http=debug"),
     );
-    println!("Logger initialized");
+    if !quiet {
+        println!("Logger initialized");
+    }
 
     if cfg!(debug_assertions) {
         dotenv::dotenv().ok();
-        println!("Loaded environment from .env file");
-    } else {
+        if !quiet {
+            println!("Loaded environment from .env file");
+        }
+    } else if !quiet {
         println!("Running in release mode, using environment variables from the system");
     }
 
-    // Get port from environment or use default
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .expect("PORT must be a valid number");
+    if let Some(output) = self_check {
+        let report = self_check::run(|name| env::var(name).ok(), &LiveProbes).await;
+        match output {
+            SelfCheckOutput::Text => print!("{}", report.render_text()),
+            SelfCheckOutput::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default()),
+        }
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
+    // Validate the whole configuration up front so every problem is reported at once
+    config::log_env_summary();
+    let app_config = match config::AppConfig::from_env() {
+        Ok(app_config) => app_config,
+        Err(e) => {
+            eprintln!("❌ Refusing to start. {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        }
+    };
+    let port = app_config.port;
+
+    // Find out now, not on the first image upload, when the credentials can't reach storage
+    let checked_bucket = credential_check::storage_configured(|name| env::var(name).ok())
+        .then(|| app_config.storage.bucket(BucketKind::ItineraryImages))
+        .flatten()
+        .map(|bucket| bucket.bucket.as_str());
+    if let Err(e) = credential_check::verify_credentials(&GcsProbe, checked_bucket, app_config.credential_check).await {
+        eprintln!("❌ Refusing to start. {}", e);
+        return Err(std::io::Error::other(e));
+    }
 
     println!("Attempting to bind to port {}", port);
 
     // Connect to MongoDB
-    let mongo_uri = std::env::var("MONGODB_URI").expect("MONGODB_URI must be set");
     println!("Connecting to MongoDB...");
-    let client = db::mongo::create_mongo_client(&mongo_uri).await;
+    let client = db::mongo::create_mongo_client(&app_config.mongodb_uri).await;
     println!("MongoDB connection established successfully");
 
     // Initialize the Stripe client
     println!("Initializing Stripe client...");
-    let stripe_secret_key =
-        std::env::var("STRIPE_SECRET_KEY").expect("STRIPE_SECRET_KEY must be set");
-    let stripe_client = Arc::new(stripe::Client::new(stripe_secret_key));
-    let stripe_data = web::Data::new(stripe_client);
+    let stripe_client = Arc::new(stripe::Client::new(app_config.stripe_secret_key.clone()));
+    let stripe_data = web::Data::new(stripe_client.clone());
     println!("Stripe client initialized successfully");
 
     // Initialize the Stripe configuration for webhook
     let stripe_config = StripeConfig {
-        webhook_secret: std::env::var("STRIPE_WEBHOOK_SECRET")
-            .expect("STRIPE_WEBHOOK_SECRET must be set"),
+        webhook_secret: app_config.stripe_webhook_secret.clone(),
+        max_event_age_hours: app_config.stripe_webhook_max_age_hours,
     };
+    if let Err(e) = ProcessedWebhookService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create processed webhook indexes: {}", e);
+    }
+    if let Err(e) = ReservationService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create reservation indexes: {}", e);
+    }
+    if let Err(e) = AdminBookingService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create booking payment index: {}", e);
+    }
+    if let Err(e) = PaymentIdempotencyService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create payment intent key indexes: {}", e);
+    }
+    if let Err(e) = OAuthLinkService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create pending OAuth link indexes: {}", e);
+    }
+    if let Err(e) = RecentlyViewedService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create recently viewed indexes: {}", e);
+    }
+    if let Err(e) = ItineraryBulkService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create bulk itinerary dry run indexes: {}", e);
+    }
+    if let Err(e) = TripStatusService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create trip status transition indexes: {}", e);
+    }
+    if let Err(e) = TripNotesService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create trip notes indexes: {}", e);
+    }
+    if let Err(e) = EmailSuppressionService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create email event indexes: {}", e);
+    }
+    if let Err(e) = SearchExperimentService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create search experiment indexes: {}", e);
+    }
+    if let Err(e) = ApiTokenService::new(client.clone()).ensure_indexes().await {
+        eprintln!("⚠️  Failed to create API token indexes: {}", e);
+    }
+
+    // Security events are written behind the request by a background worker
+    let security_events = web::Data::new(SecurityEventQueue::start(client.clone(), &app_config.email));
+
+    // Best-effort bookkeeping (search submissions) is written behind the request, so
+    // searches keep working while the database refuses writes
+    let writes = web::Data::new(WriteBehindQueue::start(client.clone()));
+
+    // Availability months are cached across workers until a booking touches them
+    let availability_cache = web::Data::new(AvailabilityCache::default());
+
+    // Display-currency rates, refreshed in the background and cached in MongoDB
+    let fx_rates = web::Data::from(FxRates::start(
+        client.clone(),
+        app_config.fx_rates_url.clone(),
+        std::time::Duration::from_secs(app_config.fx_refresh_hours.max(1) * 60 * 60),
+    ));
+
+    // Feature flags: defaults from code, overridden per environment in MongoDB
+    let feature_flags = web::Data::from(Flags::start(client.clone(), feature_flags::REFRESH_INTERVAL));
+
+    // Stand-in images for search results without any, by city, refreshed from MongoDB
+    let stock_images = web::Data::from(StockImages::start(
+        client.clone(),
+        &app_config.placeholder_image_url,
+        image_fallback::REFRESH_INTERVAL,
+    ));
+
+    // Destination autocomplete searches an in-memory index of cities we have inventory in
+    let location_index = web::Data::from(LocationAutocomplete::start(
+        client.clone(),
+        std::time::Duration::from_secs(app_config.location_index_refresh_minutes.max(1) * 60),
+    ));
+
+    // Personal access token requests are limited per token, shared across workers
+    let api_token_limiter = web::Data::new(ApiTokenRateLimiter::new(
+        app_config.api_token_rate_limit_per_minute,
+    ));
+
+    // Favorited itineraries are checked for price drops in the background
+    PriceAlertJob::new(client.clone(), app_config.price_alert_min_drop_percent)
+        .with_email(&app_config.email)
+        .start(std::time::Duration::from_secs(
+            app_config.price_alert_interval_hours.max(1) * 60 * 60,
+        ));
+
+    // Changes to favorited itineraries go out as at most one digest per user a week
+    FavoriteDigestService::new(client.clone())
+        .with_email(&app_config.email)
+        .start(std::time::Duration::from_secs(
+            app_config.favorite_digest_interval_hours.max(1) * 60 * 60,
+        ));
+
+    // Confirmed bookings move to in_progress and completed as their trips start and end
+    TripStatusService::new(client.clone()).start(
+        std::time::Duration::from_secs(app_config.trip_status_interval_minutes.max(1) * 60),
+        ReviewRequestHook {
+            service: ReviewRequestService::new(client.clone()).with_email(&app_config.email),
+            delay_days: app_config.review_request_delay_days,
+        },
+    );
+
+    // Travelers are asked to review a trip a couple of days after it ends
+    ReviewRequestService::new(client.clone())
+        .with_email(&app_config.email)
+        .start(
+            std::time::Duration::from_secs(app_config.review_request_interval_hours.max(1) * 60 * 60),
+            app_config.review_request_delay_days,
+        );
+
+    // Expired search submissions and unbooked generated itineraries are purged (daily by default)
+    RetentionService::new(client.clone(), app_config.retention.clone()).start(
+        std::time::Duration::from_secs(app_config.retention_interval_hours.max(1) * 60 * 60),
+    );
+
+    // Listed itineraries are checked for references to deleted activities and
+    // lodging, and a sample of them for images missing from the bucket
+    IntegrityService::new(client.clone())
+        .with_image_sample(
+            Storage::new(app_config.storage.clone()),
+            app_config.integrity_image_sample_size,
+        )
+        .start(std::time::Duration::from_secs(
+            app_config.integrity_check_interval_hours.max(1) * 60 * 60,
+        ));
+
+    // Stripe cleanup for deleted accounts that failed is retried until it goes through
+    PaymentTeardownService::new(client.clone()).start(stripe_client, payment_teardown::RETRY_INTERVAL);
+
+    let server = app_config.server.clone();
+    println!(
+        "Starting {} workers (keep-alive {}s, request timeout {}s, backlog {})",
+        server.workers, server.keep_alive_secs, server.client_request_timeout_secs, server.backlog
+    );
 
     // Create and configure the HTTP server (HTTP/1.1 only)
     HttpServer::new(move || {
         App::new()
             // Add middleware
             .wrap(Logger::default())
-            .wrap(actix_web::middleware::DefaultHeaders::new().add(("Server", "actota-api")))
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header()
-                    .max_age(240),
-            )
+            .wrap(actix_web::middleware::Compress::default())
+            .wrap(app_config.security_headers.default_headers())
+            .wrap(app_config.security_headers.cors())
             // Add JSON error handling
-            .app_data(web::JsonConfig::default().error_handler(|err, _req| {
-                let error_message = format!("JSON error: {}", err);
-                eprintln!("{}", error_message);
-                actix_web::error::InternalError::from_response(
-                    err,
-                    HttpResponse::BadRequest()
-                        .content_type("application/json")
-                        .body(format!(r#"{{"error":"{}"}}"#, error_message)),
-                )
-                .into()
-            }))
-            // Add diagnostic endpoints
-            .route("/health", web::get().to(routes::health::health_check))
-            .route("/request-info", web::get().to(request_info))
-            .route(
-                "/",
-                web::get().to(|| async {
-                    HttpResponse::Ok()
-                        .content_type("text/plain")
-                        .body("ACTOTA API is running")
-                }),
-            )
+            .app_data(routes::json_config())
             // Share MongoDB client with all routes
             .app_data(stripe_data.clone())
             .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(app_config.clone()))
             .app_data(web::Data::new(stripe_config.clone()))
-            .route("/stripe/webhook", web::post().to(handle_stripe_webhook))
+            .app_data(availability_cache.clone())
+            .app_data(security_events.clone())
+            .app_data(writes.clone())
+            .app_data(fx_rates.clone())
+            .app_data(feature_flags.clone())
+            .app_data(location_index.clone())
+            .app_data(stock_images.clone())
+            .app_data(api_token_limiter.clone())
             // API Routes - organized by domain
-            
-            // Authentication routes
-            .service(
-                web::scope("/auth")
-                            // Public auth routes (no authentication required)
-                            .route("/signup", web::post().to(routes::account::auth::signup))
-                            .route("/signin", web::post().to(routes::account::auth::signin))
-                            .route(
-                                "/google",
-                                web::get().to(routes::account::google_auth::google_auth_init),
-                            )
-                            .route(
-                                "/google/callback",
-                                web::get().to(routes::account::google_auth::google_auth_callback),
-                            )
-                            .route(
-                                "/facebook",
-                                web::get().to(routes::account::facebook_auth::facebook_auth_init),
-                            )
-                            .route(
-                                "/facebook/callback",
-                                web::get()
-                                    .to(routes::account::facebook_auth::facebook_auth_callback),
-                            )
-                            // Protected auth routes (require authentication)
-                            .route(
-                                "/session",
-                                web::get()
-                                    .to(routes::account::auth::user_session)
-                                    .wrap(middleware::auth::AuthMiddleware),
-                            ),
-            )
-            
-            // Payment routes (protected)
-            .service(
-                web::scope("/payment")
-                            .wrap(middleware::auth::AuthMiddleware)
-                            .route(
-                                "/payment-intent",
-                                web::post().to(routes::payment::create_payment_intent),
-                            )
-                            .route(
-                                "/capture-payment",
-                                web::post().to(routes::payment::capture_payment),
-                            ),
-            )
-            
-            // Account routes (protected)
-            .service(
-                web::scope("/account")
-                            .wrap(middleware::auth::AuthMiddleware)
-                            .route(
-                                "/{id}",
-                                web::get()
-                                    .to(routes::account::account_info::get_personal_information),
-                            )
-                            .route(
-                                "/{id}",
-                                web::put()
-                                    .to(routes::account::account_info::update_personal_information),
-                            )
-                            .route(
-                                "/{id}/favorites",
-                                web::get().to(routes::account::favorites::get_favorites),
-                            )
-                            .route(
-                                "/{id}/favorites/{itinerary_id}",
-                                web::post().to(routes::account::favorites::add_favorite),
-                            )
-                            .route(
-                                "/{id}/favorites/{itinerary_id}",
-                                web::delete().to(routes::account::favorites::remove_favorite),
-                            )
-                            .route(
-                                "/{id}/bookings",
-                                web::get().to(routes::account::bookings::get_all_bookings),
-                            )
-                            .route(
-                                "/{id}/bookings/{booking_id}",
-                                web::get().to(routes::account::bookings::get_booking_by_id),
-                            )
-                            .route(
-                                "/{id}/bookings/itinerary/{itinerary_id}",
-                                web::get().to(routes::account::bookings::get_booking),
-                            )
-                            .route(
-                                "/{id}/bookings/itinerary/{itinerary_id}",
-                                web::post().to(routes::account::bookings::add_booking),
-                            )
-                            .route(
-                                "/{id}/bookings/itinerary/{itinerary_id}",
-                                web::delete().to(routes::account::bookings::remove_booking),
-                            )
-                            .route(
-                                "/{id}/bookings/itinerary/{itinerary_id}/payment",
-                                web::put().to(routes::account::bookings::update_booking_payment),
-                            )
-                            .route(
-                                "/{id}/bookings/itinerary/{itinerary_id}/with-payment",
-                                web::post().to(routes::account::bookings::add_booking_with_payment),
-                            )
-                            .route(
-                                "/{id}/bookings/{booking_id}/cancel",
-                                web::post().to(routes::account::bookings::cancel_booking_with_refund),
-                            )
-                            .route(
-                                "/{id}/payment-methods",
-                                web::get()
-                                    .to(routes::account::payment_methods::get_payment_methods),
-                            )
-                            .route(
-                                "/{id}/payment-methods",
-                                web::post()
-                                    .to(routes::account::payment_methods::add_payment_method),
-                            )
-                            .route(
-                                "/{id}/transactions",
-                                web::get().to(routes::account::transactions::get_transactions),
-                            )
-                            .route(
-                                "/{id}/customer",
-                                web::post()
-                                    .to(routes::account::payment_methods::get_or_create_customer),
-                            )
-                            .route(
-                                "/{id}/payment-methods/{pm_id}",
-                                web::delete()
-                                    .to(routes::account::payment_methods::remove_payment_method),
-                            )
-                            .route(
-                                "/{id}/payment-methods/attach",
-                                web::post()
-                                    .to(routes::account::payment_methods::attach_payment_method),
-                            )
-                            .route(
-                                "/{id}/payment-methods/detach",
-                                web::post() // Using post to send data in body
-                                    .to(routes::account::payment_methods::detach_payment_method),
-                            )
-                            .route(
-                                "/{id}/update-customer-id",
-                                web::post()
-                                    .to(routes::account::payment_methods_update::update_customer_id),
-                            )
-                            .route(
-                                "/{id}/profile-picture",
-                                web::post()
-                                    .to(routes::account::account_info::upload_profile_pic),
-                            )
-                            .service(
-                                web::scope("/{id}/email-verifications")
-                                    .route("", web::post().to(routes::account::email_verification::create_user_email_verification))
-                                    .route("", web::get().to(routes::account::email_verification::get_user_email_verifications))
-                                    .route("/{verification_id}", web::put().to(routes::account::email_verification::verify_user_email_code))
-                            ),
-            )
-            
-            // Admin routes (protected with role check)
-            .service(
-                web::scope("/admin")
-                            .wrap(middleware::role_auth::RequireRole::new(models::account::UserRole::Admin))
-                            .wrap(middleware::auth::AuthMiddleware)
-                            .service(
-                                web::scope("/users")
-                                    .route("", web::get().to(routes::account::role_management::list_users_with_roles))
-                                    .route("/{id}/role", web::put().to(routes::account::role_management::update_user_role))
-                            )
-                            .service(
-                                web::scope("/itineraries")
-                                    .route(
-                                        "/featured/add",
-                                        web::post().to(routes::featured_vacation::add),
-                                    )
-                                    .service(
-                                        web::scope("/{id}")
-                                            .route("/images",
-                                                web::put().to(routes::featured_vacation::update_itinerary_images)
-                                            )
-                                    )
-                            )
-            )
-            
-            // Newsletter routes
-            .service(
-                web::scope("/newsletter")
-                                    .route(
-                                        "/subscribe",
-                                        web::post().to(routes::account::auth::newsletter_subscribe),
-                                    )
-                                    .route(
-                                        "/unsubscribe",
-                                        web::put()
-                                            .to(routes::account::auth::newsletter_unsubscribe),
-                                    ),
-            )
-            
-            // Email verification routes (public for signup)
-            .service(
-                web::scope("/email-verifications")
-                                    .route("", web::post().to(routes::account::email_verification::create_signup_email_verification))
-                                    .route("/{id}", web::put().to(routes::account::email_verification::verify_signup_email_code))
-            )
-            
-            // Public content routes
-            .route("/locations", web::get().to(routes::location::get_locations))
-            .route("/lodging", web::get().to(routes::lodging::get_lodging))
-            .route("/activities", web::get().to(routes::activity::get_activities))
-            
-            // Itinerary routes
-            .service(
-                web::scope("/itineraries")
-                                    // Public routes
-                                    // This route is to be removed
-                                    // .route(
-                                    //     "/featured",
-                                    //     web::get().to(routes::featured_vacation::get_all),
-                                    // )
-                                    // Get all itineraries
-                                    .route("", web::get().to(routes::itinerary::get_all))
-                                    // Search itineraries with filters
-                                    .route("/search", web::post().to(routes::itinerary::search_itineraries_endpoint))
-                                    // Search with generation fallback
-                                    .route("/search-or-generate", web::post().to(routes::itinerary::search_or_generate))
-                                    // Public route for getting itinerary by ID
-                                    .route("/{id}", web::get().to(routes::itinerary::get_by_id))
-                                    // Protected routes
-                                    .service(
-                                        web::scope("")
-                                            .wrap(middleware::auth::AuthMiddleware)
-                                    
-                                            .route(
-                                                "/find",
-                                                web::post().to(routes::dream_vacation::find),
-                                            ),
-                                    ),
-            )
+            .configure(routes::configure)
     })
     // HTTP/1.1 configuration
     .bind(("0.0.0.0", port))?
     .server_hostname("actota-api") // Set a server hostname
-    .keep_alive(std::time::Duration::from_secs(75)) // Set an appropriate keep-alive timeout
-    .client_request_timeout(std::time::Duration::from_secs(60)) // Set request timeout
-    .backlog(1024) // Increase the connection backlog for better performance under load
+    .workers(server.workers)
+    .keep_alive(std::time::Duration::from_secs(server.keep_alive_secs))
+    .client_request_timeout(std::time::Duration::from_secs(server.client_request_timeout_secs))
+    .backlog(server.backlog)
     .run()
     .await
 }
@@ -1,12 +1,23 @@
 use actix_http::Payload;
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    Error, FromRequest, HttpMessage, HttpRequest,
+    error::{ErrorInternalServerError, ErrorUnauthorized, InternalError},
+    web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse,
 };
 use futures::future::{ready, LocalBoxFuture, Ready};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use mongodb::Client;
 use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::AppConfig;
+use crate::models::account::UserRole;
+use crate::models::api_token::{ApiToken, TokenScope, API_TOKEN_PREFIX};
+use crate::routes::versioning::unversioned;
+use crate::services::api_token_service::{ApiTokenRateLimiter, ApiTokenService};
+use crate::services::impersonation_service::ImpersonationService;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -15,7 +26,45 @@ pub struct Claims {
     pub iat: usize,  // issued at
     pub user_id: String,
     pub role: Option<String>, // User role (admin, user, etc.)
+    /// The user's email when the token was issued. Tokens issued before this
+    /// was added, and API tokens, don't carry it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Unique id of this token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Set when the request was made with a personal access token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_scopes: Option<Vec<TokenScope>>,
+    /// Set when an admin is acting as this user through support impersonation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonation: Option<Impersonation>,
+}
+
+/// Who is really behind an impersonation token, and the session it belongs to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Impersonation {
+    pub admin_id: String,
+    pub session_id: String,
+}
+
+impl Claims {
+    /// The role the token was issued with. Unknown roles count as plain users;
+    /// `None` when the token has no role at all.
+    pub fn user_role(&self) -> Option<UserRole> {
+        self.role.as_deref().map(|role| match role {
+            "admin" => UserRole::Admin,
+            "operator" => UserRole::Operator,
+            _ => UserRole::User,
+        })
+    }
+
+    /// The admin acting as this user, if the request was made while impersonating
+    pub fn impersonator(&self) -> Option<&str> {
+        self.impersonation.as_ref().map(|impersonation| impersonation.admin_id.as_str())
+    }
 }
+
 impl FromRequest for Claims {
     type Error = Error;
     type Future = Ready<Result<Self, Self::Error>>;
@@ -27,6 +76,10 @@ impl FromRequest for Claims {
             iat: 0,
             user_id: "0".to_string(),
             role: None,
+            email: None,
+            jti: None,
+            token_scopes: None,
+            impersonation: None,
         };
 
         match req.extensions().get::<Claims>() {
@@ -36,11 +89,196 @@ impl FromRequest for Claims {
     }
 }
 
+/// Validate a bearer token against the configured secret
+fn decode_token(req: &HttpRequest, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    // No token is valid on an app built without AppConfig; there is no fallback secret
+    let Some(config) = req.app_data::<web::Data<AppConfig>>() else {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat.into());
+    };
+    let key = &config.jwt_secret;
+
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.validate_exp = true;
+    validation.set_required_spec_claims(&["exp", "iat", "sub", "user_id", "role"]);
+
+    decode::<Claims>(token, &DecodingKey::from_secret(key.as_bytes()), &validation)
+        .map(|token_data| token_data.claims)
+}
+
+/// Claims for public routes that personalise their response when a valid token is
+/// sent. A missing or invalid token is treated as anonymous rather than rejected.
+pub fn optional_claims(req: &HttpRequest) -> Option<Claims> {
+    let auth_str = req.headers().get("Authorization")?.to_str().ok()?;
+    let token = auth_str.strip_prefix("Bearer ")?;
+    decode_token(req, token).ok()
+}
+
+/// Routes a personal access token can be used on, by method and route pattern,
+/// with the scope each needs. Everything else rejects tokens, so new routes are
+/// closed to them until added here.
+pub const API_TOKEN_ROUTES: &[(&str, &str, TokenScope)] = &[
+    ("GET", "/account/{id}/bookings", TokenScope::BookingsRead),
+    ("GET", "/account/{id}/bookings/{booking_id}", TokenScope::BookingsRead),
+    (
+        "GET",
+        "/account/{id}/bookings/itinerary/{itinerary_id}",
+        TokenScope::BookingsRead,
+    ),
+    ("POST", "/itineraries/search", TokenScope::SearchRead),
+    ("POST", "/itineraries/search-or-generate", TokenScope::SearchRead),
+    ("GET", "/itineraries", TokenScope::ItinerariesRead),
+    ("GET", "/itineraries/{id}", TokenScope::ItinerariesRead),
+    ("GET", "/itineraries/{id}/availability", TokenScope::ItinerariesRead),
+    ("GET", "/itineraries/{id}/distance-matrix", TokenScope::ItinerariesRead),
+    ("GET", "/itineraries/{id}/map-geojson", TokenScope::ItinerariesRead),
+];
+
+/// Scope a token needs for `method` on the route `pattern`, or `None` if tokens
+/// can't be used there at all. Versioned patterns match their unprefixed entry.
+pub fn required_token_scope(method: &str, pattern: Option<&str>) -> Option<TokenScope> {
+    let pattern = unversioned(pattern?);
+    API_TOKEN_ROUTES
+        .iter()
+        .find(|(route_method, route_pattern, _)| *route_method == method && *route_pattern == pattern)
+        .map(|(_, _, scope)| *scope)
+}
+
+fn insufficient_scope() -> Error {
+    InternalError::from_response(
+        "insufficient_scope",
+        HttpResponse::Forbidden().json(serde_json::json!({ "error": "insufficient_scope" })),
+    )
+    .into()
+}
+
+fn rate_limited(retry_after: u64) -> Error {
+    InternalError::from_response(
+        "rate_limited",
+        HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .json(serde_json::json!({ "error": "rate_limited", "retry_after": retry_after })),
+    )
+    .into()
+}
+
+/// Claims for a request made with `api_token`. The token never carries the
+/// owner's role, so role-gated routes stay closed to it.
+pub fn api_token_claims(api_token: &ApiToken) -> Claims {
+    Claims {
+        sub: format!("api_token:{}", api_token.id.to_hex()),
+        exp: api_token
+            .expires_at
+            .map(|expires_at| (expires_at.timestamp_millis() / 1000) as usize)
+            .unwrap_or(usize::MAX),
+        iat: (api_token.created_at.timestamp_millis() / 1000) as usize,
+        user_id: api_token.user_id.to_hex(),
+        role: Some("user".to_string()),
+        email: None,
+        jti: Some(api_token.id.to_hex()),
+        token_scopes: Some(api_token.scopes.clone()),
+        impersonation: None,
+    }
+}
+
+/// Look up a personal access token, apply its rate limit and check it covers the
+/// route being called
+async fn authenticate_api_token(req: &ServiceRequest, token: &str) -> Result<Claims, Error> {
+    let Some(client) = req.app_data::<web::Data<Arc<Client>>>() else {
+        return Err(ErrorUnauthorized("Invalid token"));
+    };
+    let api_token = match ApiTokenService::new(client.get_ref().clone()).authenticate(token).await {
+        Ok(Some(api_token)) => api_token,
+        Ok(None) => return Err(ErrorUnauthorized("Invalid token")),
+        Err(e) => {
+            eprintln!("Failed to look up API token: {:?}", e);
+            return Err(ErrorInternalServerError("Failed to check token"));
+        }
+    };
+
+    if let Some(limiter) = req.app_data::<web::Data<ApiTokenRateLimiter>>() {
+        limiter.check(api_token.id, Instant::now()).map_err(rate_limited)?;
+    }
+
+    authorize_api_token(&api_token, req.method().as_str(), req.match_pattern().as_deref())
+}
+
+/// Claims for `api_token` if it has the scope the route needs
+fn authorize_api_token(api_token: &ApiToken, method: &str, pattern: Option<&str>) -> Result<Claims, Error> {
+    match required_token_scope(method, pattern) {
+        Some(scope) if api_token.scopes.contains(&scope) => Ok(api_token_claims(api_token)),
+        _ => Err(insufficient_scope()),
+    }
+}
+
+/// Writes an admin may make while impersonating someone. Reads are allowed and
+/// every other write is refused, so a new route that moves money, changes how the
+/// user pays or signs in, or issues credentials is blocked until it's listed here.
+pub const IMPERSONATION_ALLOWED_ROUTES: &[(&str, &str)] = &[
+    ("POST", "/auth/impersonation/end"),
+    ("POST", "/account/{id}/favorites/bulk"),
+    // Adding and removing: middleware runs before the method guards pick a
+    // route, so both report the pattern registered first for their path
+    ("POST", "/account/{id}/favorites/{itinerary_id}"),
+    ("DELETE", "/account/{id}/favorites/{itinerary_id}"),
+    ("PUT", "/account/{id}/bookings/{booking_id}/special-requests"),
+    ("POST", "/account/{id}/recent-searches/{fingerprint}/rerun"),
+    ("PUT", "/account/{id}/notifications"),
+];
+
+/// Reads refused while impersonating: the traveler's private trip notes
+pub const IMPERSONATION_PRIVATE_ROUTES: &[(&str, &str)] = &[("GET", "/account/{id}/bookings/{booking_id}/notes")];
+
+pub fn blocked_while_impersonating(method: &str, pattern: Option<&str>) -> bool {
+    let Some(pattern) = pattern.map(unversioned) else {
+        return false;
+    };
+    let listed = |routes: &[(&str, &str)]| {
+        routes
+            .iter()
+            .any(|(route_method, route_pattern)| *route_method == method && *route_pattern == pattern)
+    };
+    match method {
+        "GET" | "HEAD" => listed(IMPERSONATION_PRIVATE_ROUTES),
+        _ => !listed(IMPERSONATION_ALLOWED_ROUTES),
+    }
+}
+
+fn not_allowed_while_impersonating() -> Error {
+    InternalError::from_response(
+        "not_allowed_while_impersonating",
+        HttpResponse::Forbidden()
+            .json(serde_json::json!({ "error": "not_allowed_while_impersonating" })),
+    )
+    .into()
+}
+
+/// Refuse blocked routes, and tokens whose session was ended or has expired
+async fn authorize_impersonation(req: &ServiceRequest, impersonation: &Impersonation) -> Result<(), Error> {
+    if blocked_while_impersonating(req.method().as_str(), req.match_pattern().as_deref()) {
+        return Err(not_allowed_while_impersonating());
+    }
+
+    let Some(client) = req.app_data::<web::Data<Arc<Client>>>() else {
+        return Err(ErrorUnauthorized("Invalid token"));
+    };
+    match ImpersonationService::new(client.get_ref().clone())
+        .is_active(&impersonation.session_id)
+        .await
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ErrorUnauthorized("Impersonation session has ended")),
+        Err(e) => {
+            eprintln!("Failed to look up impersonation session: {:?}", e);
+            Err(ErrorInternalServerError("Failed to check token"))
+        }
+    }
+}
+
 pub struct AuthMiddleware;
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -51,17 +289,19 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthMiddlewareService { service }))
+        ready(Ok(AuthMiddlewareService {
+            service: Rc::new(service),
+        }))
     }
 }
 
 pub struct AuthMiddlewareService<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareService<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -78,22 +318,27 @@ where
             if let Ok(auth_str) = auth_header.to_str() {
                 if auth_str.starts_with("Bearer ") {
                     let token = &auth_str[7..];
-                    let key = std::env::var("JWT_SECRET")
-                        .unwrap_or_else(|_| "default_secret".to_string());
-
-                    println!("Key: {}", key);
-                    let mut validation = Validation::new(Algorithm::HS256);
-                    validation.validate_exp = true;
-                    validation.set_required_spec_claims(&["exp", "iat", "sub", "user_id", "role"]);
-
-                    match decode::<Claims>(
-                        token,
-                        &DecodingKey::from_secret(key.as_bytes()),
-                        &validation,
-                    ) {
-                        Ok(token_data) => {
-                            println!("Token decoded successfully. Claims: {:?}", token_data.claims);
-                            req.extensions_mut().insert(token_data.claims);
+                    if token.starts_with(API_TOKEN_PREFIX) {
+                        let token = token.to_string();
+                        let service = Rc::clone(&self.service);
+                        return Box::pin(async move {
+                            let claims = authenticate_api_token(&req, &token).await?;
+                            req.extensions_mut().insert(claims);
+                            service.call(req).await
+                        });
+                    }
+                    match decode_token(req.request(), token) {
+                        Ok(claims) => {
+                            println!("Token decoded successfully. Claims: {:?}", claims);
+                            if let Some(impersonation) = claims.impersonation.clone() {
+                                let service = Rc::clone(&self.service);
+                                return Box::pin(async move {
+                                    authorize_impersonation(&req, &impersonation).await?;
+                                    req.extensions_mut().insert(claims);
+                                    service.call(req).await
+                                });
+                            }
+                            req.extensions_mut().insert(claims);
                             return Box::pin(self.service.call(req));
                         }
                         Err(err) => {
@@ -107,3 +352,175 @@ where
         Box::pin(ready(Err(ErrorUnauthorized("No authorization header"))))
     }
 }
+
+/// For public routes: a request carrying a personal access token is
+/// authenticated, rate limited and scope-checked exactly as under
+/// `AuthMiddleware`. Anything else passes through untouched.
+pub struct ApiTokenMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiTokenMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ApiTokenMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiTokenMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ApiTokenMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiTokenMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .filter(|token| token.starts_with(API_TOKEN_PREFIX))
+            .map(str::to_string);
+        let Some(token) = token else {
+            return Box::pin(self.service.call(req));
+        };
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let claims = authenticate_api_token(&req, &token).await?;
+            req.extensions_mut().insert(claims);
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::api_token_service::new_api_token;
+    use actix_web::http::StatusCode;
+    use mongodb::bson::{oid::ObjectId, DateTime};
+
+    #[test]
+    fn test_api_tokens_only_reach_routes_their_scopes_cover() {
+        let (_, api_token) = new_api_token(
+            ObjectId::new(),
+            "script",
+            vec![TokenScope::BookingsRead, TokenScope::SearchRead],
+            None,
+            DateTime::now(),
+        );
+
+        let claims =
+            authorize_api_token(&api_token, "GET", Some("/account/{id}/bookings")).unwrap();
+        assert_eq!(claims.user_id, api_token.user_id.to_hex());
+        assert_eq!(claims.token_scopes, Some(api_token.scopes.clone()));
+        assert_eq!(claims.role.as_deref(), Some("user"));
+        assert!(authorize_api_token(&api_token, "GET", Some("/v2/account/{id}/bookings")).is_ok());
+        assert!(authorize_api_token(&api_token, "POST", Some("/itineraries/search")).is_ok());
+        assert!(authorize_api_token(&api_token, "POST", Some("/v2/itineraries/search")).is_ok());
+
+        // Creating a booking is a write: no token scope covers it
+        for (method, pattern) in [
+            ("POST", Some("/account/{id}/bookings/itinerary/{itinerary_id}")),
+            ("DELETE", Some("/account/{id}/bookings/itinerary/{itinerary_id}")),
+            ("GET", Some("/account/{id}/payment-methods")),
+            ("POST", Some("/account/{id}/api-tokens")),
+            ("POST", Some("/itineraries/{id}/report")),
+            ("GET", None),
+        ] {
+            let error = authorize_api_token(&api_token, method, pattern).unwrap_err();
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        }
+
+        // A token without the bookings scope can't read bookings either
+        let (_, search_only) =
+            new_api_token(ObjectId::new(), "search", vec![TokenScope::SearchRead], None, DateTime::now());
+        assert!(authorize_api_token(&search_only, "GET", Some("/account/{id}/bookings")).is_err());
+        assert!(authorize_api_token(&search_only, "GET", Some("/itineraries/{id}")).is_err());
+    }
+
+    #[test]
+    fn test_session_tokens_have_no_scopes() {
+        let claims: Claims = serde_json::from_value(serde_json::json!({
+            "sub": "traveler@example.com",
+            "exp": 1,
+            "iat": 0,
+            "user_id": "65f000000000000000000001",
+            "role": "user",
+        }))
+        .unwrap();
+        assert_eq!(claims.token_scopes, None);
+        assert_eq!(claims.impersonator(), None);
+        let serialized = serde_json::to_string(&claims).unwrap();
+        assert!(!serialized.contains("token_scopes"));
+        assert!(!serialized.contains("impersonation"));
+    }
+
+    #[test]
+    fn test_money_and_credential_routes_are_blocked_while_impersonating() {
+        for (method, pattern) in [
+            ("POST", "/payment/payment-intent"),
+            ("POST", "/payment/capture-payment"),
+            ("POST", "/payment/reserve"),
+            ("DELETE", "/payment/reserve/{id}"),
+            ("POST", "/payment/apply-gift-card"),
+            ("PUT", "/account/{id}"),
+            ("DELETE", "/account/{id}"),
+            ("POST", "/account/{id}/bookings/itinerary/{itinerary_id}"),
+            ("POST", "/account/{id}/bookings/itinerary/{itinerary_id}/with-payment"),
+            ("PUT", "/account/{id}/bookings/itinerary/{itinerary_id}/payment"),
+            ("DELETE", "/account/{id}/bookings/itinerary/{itinerary_id}"),
+            ("POST", "/account/{id}/bookings/{booking_id}/cancel"),
+            ("PUT", "/account/{id}/bookings/{booking_id}/reschedule"),
+            ("POST", "/account/{id}/payment-methods"),
+            ("DELETE", "/account/{id}/payment-methods/{pm_id}"),
+            ("POST", "/account/{id}/payment-methods/{pm_id}"),
+            ("POST", "/account/{id}/customer"),
+            ("POST", "/account/{id}/update-customer-id"),
+            ("POST", "/account/{id}/api-tokens"),
+            ("DELETE", "/account/{id}/api-tokens/{token_id}"),
+            ("GET", "/account/{id}/bookings/{booking_id}/notes"),
+            ("PUT", "/account/{id}/bookings/{booking_id}/notes"),
+            ("PUT", "/v1/account/{id}"),
+            ("POST", "/v2/payment/payment-intent"),
+            // Routes nobody has reviewed are refused until they're allowed
+            ("POST", "/account/{id}/wallet/top-up"),
+        ] {
+            assert!(blocked_while_impersonating(method, Some(pattern)), "{} {}", method, pattern);
+        }
+
+        // Support can still see what the user sees and fix up their trip
+        for (method, pattern) in [
+            ("GET", "/account/{id}"),
+            ("GET", "/account/{id}/bookings"),
+            ("GET", "/account/{id}/payment-methods"),
+            ("PUT", "/account/{id}/bookings/{booking_id}/special-requests"),
+            ("POST", "/auth/impersonation/end"),
+            ("POST", "/v2/auth/impersonation/end"),
+        ] {
+            assert!(!blocked_while_impersonating(method, Some(pattern)), "{} {}", method, pattern);
+        }
+        assert!(!blocked_while_impersonating("POST", None));
+    }
+}
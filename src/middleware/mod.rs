@@ -1,3 +1,5 @@
 pub mod auth;
 pub mod auth_context;
 pub mod role_auth;
+pub mod security_headers;
+pub mod typed_json;
@@ -1,26 +1,89 @@
-use actix_http::Payload;
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::{ErrorForbidden, ErrorUnauthorized},
-    Error, FromRequest, HttpMessage, HttpRequest,
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+    web, Error, HttpMessage,
 };
 use futures::future::{ready, LocalBoxFuture, Ready};
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client,
+};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
 use crate::middleware::auth::Claims;
-use crate::models::account::UserRole;
+use crate::models::account::{User, UserRole};
+
+/// Whether `role` may use routes that require `required`. Admins may use everything.
+pub fn role_allows(role: &UserRole, required: &UserRole) -> bool {
+    role == required || *role == UserRole::Admin
+}
+
+/// The role decision, made from the token alone
+pub fn authorize(claims: Option<&Claims>, required: &UserRole) -> Result<(), Error> {
+    let Some(claims) = claims else {
+        return Err(ErrorUnauthorized("No authorization"));
+    };
+    match claims.user_role() {
+        Some(role) if role_allows(&role, required) => Ok(()),
+        _ => Err(ErrorForbidden("Insufficient permissions")),
+    }
+}
+
+/// Re-reads the user's role, for actions where a role removed since the token was
+/// issued must take effect at once
+async fn verify_current_role(
+    client: &Arc<Client>,
+    user_id: &str,
+    required: &UserRole,
+) -> Result<(), Error> {
+    let user_id = ObjectId::parse_str(user_id).map_err(|_| ErrorUnauthorized("No authorization"))?;
+    let user = primary_collection::<User>(client, "Account", "Users")
+        .find_one(doc! { "_id": user_id })
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to verify role for user {}: {}", user_id, e);
+            ErrorInternalServerError("Failed to verify permissions")
+        })?
+        .ok_or_else(|| ErrorUnauthorized("No authorization"))?;
 
+    let role = user.role.unwrap_or(UserRole::User);
+    if role_allows(&role, required) {
+        Ok(())
+    } else {
+        println!("🔒 Role for user {} changed since their token was issued", user_id);
+        Err(ErrorForbidden("Insufficient permissions"))
+    }
+}
+
+/// Checks the `role` claim set by `AuthMiddleware`, so it must be wrapped inside it
 pub struct RequireRole {
     required_role: UserRole,
+    verify_with_database: bool,
 }
 
 impl RequireRole {
     pub fn new(role: UserRole) -> Self {
-        RequireRole { required_role: role }
+        RequireRole {
+            required_role: role,
+            verify_with_database: false,
+        }
+    }
+
+    /// Also confirms the role against the user's account, for sensitive actions
+    /// that shouldn't trust a token issued before a demotion
+    pub fn verified(role: UserRole) -> Self {
+        RequireRole {
+            required_role: role,
+            verify_with_database: true,
+        }
     }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for RequireRole
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -32,20 +95,22 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(RequireRoleService {
-            service,
+            service: Rc::new(service),
             required_role: self.required_role.clone(),
+            verify_with_database: self.verify_with_database,
         }))
     }
 }
 
 pub struct RequireRoleService<S> {
-    service: S,
+    service: Rc<S>,
     required_role: UserRole,
+    verify_with_database: bool,
 }
 
 impl<S, B> Service<ServiceRequest> for RequireRoleService<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -57,39 +122,99 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let required_role = self.required_role.clone();
-
-        // Clone the claims before checking
         let claims = req.extensions().get::<Claims>().cloned();
-        
-        println!("Role auth check - required role: {:?}", required_role);
-        println!("Role auth check - claims: {:?}", claims);
-        
-        if let Some(claims) = claims {
-            if let Some(role_str) = &claims.role {
-                println!("Role string from token: {}", role_str);
-                let user_role = match role_str.as_str() {
-                    "admin" => UserRole::Admin,
-                    "user" => UserRole::User,
-                    _ => {
-                        println!("Unknown role: {}", role_str);
-                        UserRole::User
-                    }
-                };
-                
-                println!("Parsed user role: {:?}, Required role: {:?}", user_role, required_role);
-
-                if user_role == required_role || user_role == UserRole::Admin {
-                    println!("Access granted");
-                    return Box::pin(self.service.call(req));
-                }
-                println!("Access denied - insufficient permissions");
-            } else {
-                println!("No role in claims");
-            }
-            Box::pin(ready(Err(ErrorForbidden("Insufficient permissions"))))
-        } else {
-            println!("No claims found in request extensions");
-            Box::pin(ready(Err(ErrorUnauthorized("No authorization"))))
+
+        if let Err(e) = authorize(claims.as_ref(), &required_role) {
+            println!(
+                "Access denied - required role {:?}, token role {:?}",
+                required_role,
+                claims.as_ref().and_then(|claims| claims.role.as_deref())
+            );
+            return Box::pin(ready(Err(e)));
+        }
+        if !self.verify_with_database {
+            return Box::pin(self.service.call(req));
         }
+
+        let service = self.service.clone();
+        let client = req
+            .app_data::<web::Data<Arc<Client>>>()
+            .map(|data| data.get_ref().clone());
+        Box::pin(async move {
+            let Some(client) = client else {
+                return Err(ErrorUnauthorized("No authorization"));
+            };
+            let user_id = claims.map(|claims| claims.user_id).unwrap_or_default();
+            verify_current_role(&client, &user_id, &required_role).await?;
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::account::auth::generate_token;
+    use actix_web::http::StatusCode;
+    use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+
+    /// Claims exactly as a client would present them
+    fn token_claims(role: Option<&UserRole>) -> Claims {
+        let token = generate_token("secret", "traveler@example.com", ObjectId::new(), role).unwrap();
+        decode::<Claims>(&token, &DecodingKey::from_secret(b"secret"), &Validation::new(Algorithm::HS256))
+            .unwrap()
+            .claims
+    }
+
+    fn status(result: Result<(), Error>) -> Option<StatusCode> {
+        result.err().map(|e| e.as_response_error().status_code())
+    }
+
+    #[test]
+    fn test_token_carries_what_authorization_needs() {
+        let claims = token_claims(Some(&UserRole::Operator));
+        assert_eq!(claims.user_role(), Some(UserRole::Operator));
+        assert_eq!(claims.email.as_deref(), Some("traveler@example.com"));
+        assert!(claims.jti.is_some());
+        assert_ne!(claims.jti, token_claims(None).jti);
+        assert_eq!(token_claims(None).user_role(), Some(UserRole::User));
+    }
+
+    #[test]
+    fn test_role_decisions_from_the_token_alone() {
+        let admin = token_claims(Some(&UserRole::Admin));
+        let operator = token_claims(Some(&UserRole::Operator));
+        let user = token_claims(None);
+
+        assert_eq!(status(authorize(Some(&admin), &UserRole::Admin)), None);
+        assert_eq!(status(authorize(Some(&admin), &UserRole::Operator)), None);
+        assert_eq!(status(authorize(Some(&operator), &UserRole::Operator)), None);
+        assert_eq!(
+            status(authorize(Some(&operator), &UserRole::Admin)),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            status(authorize(Some(&user), &UserRole::Operator)),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            status(authorize(None, &UserRole::User)),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+
+        let unknown = Claims {
+            role: Some("superuser".to_string()),
+            ..user.clone()
+        };
+        assert_eq!(status(authorize(Some(&unknown), &UserRole::User)), None);
+        assert_eq!(
+            status(authorize(Some(&unknown), &UserRole::Admin)),
+            Some(StatusCode::FORBIDDEN)
+        );
+        let missing = Claims { role: None, ..user };
+        assert_eq!(
+            status(authorize(Some(&missing), &UserRole::User)),
+            Some(StatusCode::FORBIDDEN)
+        );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,277 @@
+//! Hardening headers on every response, and the CORS policy
+//!
+//! By default responses carry `X-Content-Type-Options: nosniff`,
+//! `X-Frame-Options: DENY` and `Referrer-Policy: no-referrer`. Release builds
+//! also send `Strict-Transport-Security` and leave out `Server: actota-api`,
+//! which debug builds keep so local responses are easy to tell apart.
+//!
+//! Each header can be changed or turned off from the environment:
+//!
+//! | Variable | Default |
+//! |----------|---------|
+//! | `SECURITY_CONTENT_TYPE_OPTIONS` | `true` |
+//! | `SECURITY_FRAME_OPTIONS` | `DENY` |
+//! | `SECURITY_REFERRER_POLICY` | `no-referrer` |
+//! | `SECURITY_HSTS` | `max-age=31536000; includeSubDomains` in release builds, off in debug |
+//! | `SERVER_HEADER` | `false` in release builds, `true` in debug |
+//! | `CORS_ALLOWED_ORIGINS` | any origin |
+//! | `CORS_MAX_AGE_SECS` | `240` |
+//!
+//! `off` turns a header off. `CORS_ALLOWED_ORIGINS` is a comma-separated list of
+//! origins such as `https://actota.com`; unset or `*` allows any.
+
+use actix_cors::Cors;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::DefaultHeaders;
+
+pub const DEFAULT_HSTS: &str = "max-age=31536000; includeSubDomains";
+pub const DEFAULT_CORS_MAX_AGE_SECS: usize = 240;
+const SERVER_NAME: &str = "actota-api";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityHeaders {
+    /// `X-Content-Type-Options: nosniff`
+    pub content_type_options: bool,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    /// `Strict-Transport-Security`. Only sent by browsers over HTTPS, which Cloud
+    /// Run terminates in front of us.
+    pub hsts: Option<String>,
+    /// `Server: actota-api`
+    pub server_header: bool,
+    /// Origins allowed to call the API from a browser; empty allows any
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_max_age_secs: usize,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        let release = !cfg!(debug_assertions);
+        SecurityHeaders {
+            content_type_options: true,
+            frame_options: Some("DENY".to_string()),
+            referrer_policy: Some("no-referrer".to_string()),
+            hsts: release.then(|| DEFAULT_HSTS.to_string()),
+            server_header: !release,
+            cors_allowed_origins: Vec::new(),
+            cors_max_age_secs: DEFAULT_CORS_MAX_AGE_SECS,
+        }
+    }
+}
+
+/// A header's value from `name`: the default when unset, none when `off`.
+/// Values that can't be sent as a header are recorded as invalid.
+fn header_setting(
+    get: &impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    default: Option<String>,
+    invalid: &mut Vec<(&'static str, String)>,
+) -> Option<String> {
+    let Some(value) = get(name) else {
+        return default;
+    };
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("off") {
+        return None;
+    }
+    if HeaderValue::from_str(value).is_err() {
+        invalid.push((name, value.to_string()));
+        return default;
+    }
+    Some(value.to_string())
+}
+
+fn bool_setting(
+    get: &impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    default: bool,
+    invalid: &mut Vec<(&'static str, String)>,
+) -> bool {
+    match get(name) {
+        Some(value) => value.trim().parse().unwrap_or_else(|_| {
+            invalid.push((name, value));
+            default
+        }),
+        None => default,
+    }
+}
+
+impl SecurityHeaders {
+    pub fn from_lookup(
+        get: &impl Fn(&str) -> Option<String>,
+        invalid: &mut Vec<(&'static str, String)>,
+    ) -> Self {
+        let defaults = SecurityHeaders::default();
+
+        let cors_allowed_origins = match get("CORS_ALLOWED_ORIGINS") {
+            Some(origins) if origins.trim() != "*" => origins
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        };
+        for origin in &cors_allowed_origins {
+            if !(origin.starts_with("https://") || origin.starts_with("http://")) {
+                invalid.push(("CORS_ALLOWED_ORIGINS", origin.clone()));
+            }
+        }
+
+        let cors_max_age_secs = match get("CORS_MAX_AGE_SECS") {
+            Some(value) => value.trim().parse().unwrap_or_else(|_| {
+                invalid.push(("CORS_MAX_AGE_SECS", value));
+                defaults.cors_max_age_secs
+            }),
+            None => defaults.cors_max_age_secs,
+        };
+
+        SecurityHeaders {
+            content_type_options: bool_setting(
+                get,
+                "SECURITY_CONTENT_TYPE_OPTIONS",
+                defaults.content_type_options,
+                invalid,
+            ),
+            frame_options: header_setting(get, "SECURITY_FRAME_OPTIONS", defaults.frame_options, invalid),
+            referrer_policy: header_setting(get, "SECURITY_REFERRER_POLICY", defaults.referrer_policy, invalid),
+            hsts: header_setting(get, "SECURITY_HSTS", defaults.hsts, invalid),
+            server_header: bool_setting(get, "SERVER_HEADER", defaults.server_header, invalid),
+            cors_allowed_origins,
+            cors_max_age_secs,
+        }
+    }
+
+    /// The headers added to every response
+    pub fn headers(&self) -> Vec<(HeaderName, String)> {
+        use actix_web::http::header;
+
+        let mut headers = Vec::new();
+        if self.content_type_options {
+            headers.push((header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()));
+        }
+        if let Some(value) = &self.frame_options {
+            headers.push((header::X_FRAME_OPTIONS, value.clone()));
+        }
+        if let Some(value) = &self.referrer_policy {
+            headers.push((header::REFERRER_POLICY, value.clone()));
+        }
+        if let Some(value) = &self.hsts {
+            headers.push((header::STRICT_TRANSPORT_SECURITY, value.clone()));
+        }
+        if self.server_header {
+            headers.push((header::SERVER, SERVER_NAME.to_string()));
+        }
+        headers
+    }
+
+    /// Middleware adding `headers`. A handler that sets one of them itself keeps its own.
+    pub fn default_headers(&self) -> DefaultHeaders {
+        self.headers()
+            .into_iter()
+            .fold(DefaultHeaders::new(), |middleware, header| middleware.add(header))
+    }
+
+    pub fn cors(&self) -> Cors {
+        let cors = Cors::default()
+            .allow_any_method()
+            .allow_any_header()
+            .max_age(self.cors_max_age_secs);
+        if self.cors_allowed_origins.is_empty() {
+            return cors.allow_any_origin();
+        }
+        self.cors_allowed_origins
+            .iter()
+            .fold(cors, |cors, origin| cors.allowed_origin(origin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use std::collections::HashMap;
+
+    fn lookup_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[actix_rt::test]
+    async fn test_responses_carry_the_hardening_headers() {
+        let settings = SecurityHeaders {
+            hsts: Some(DEFAULT_HSTS.to_string()),
+            server_header: false,
+            ..SecurityHeaders::default()
+        };
+        let app = init_service(
+            App::new()
+                .wrap(settings.default_headers())
+                .route("/ping", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let response = call_service(&app, TestRequest::get().uri("/ping").to_request()).await;
+        let headers = response.headers();
+
+        assert_eq!(headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer");
+        assert_eq!(headers.get(header::STRICT_TRANSPORT_SECURITY).unwrap(), DEFAULT_HSTS);
+        assert!(headers.get(header::SERVER).is_none());
+    }
+
+    #[test]
+    fn test_headers_can_be_changed_or_turned_off() {
+        let mut invalid = Vec::new();
+        let settings = SecurityHeaders::from_lookup(
+            &lookup_from(&[
+                ("SECURITY_FRAME_OPTIONS", "SAMEORIGIN"),
+                ("SECURITY_REFERRER_POLICY", "off"),
+                ("SECURITY_HSTS", "max-age=600"),
+                ("SERVER_HEADER", "false"),
+                ("CORS_ALLOWED_ORIGINS", "https://actota.com, https://admin.actota.com/"),
+            ]),
+            &mut invalid,
+        );
+
+        assert!(invalid.is_empty());
+        assert_eq!(
+            settings.headers(),
+            vec![
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+                (header::X_FRAME_OPTIONS, "SAMEORIGIN".to_string()),
+                (header::STRICT_TRANSPORT_SECURITY, "max-age=600".to_string()),
+            ]
+        );
+        assert_eq!(
+            settings.cors_allowed_origins,
+            vec!["https://actota.com", "https://admin.actota.com"]
+        );
+    }
+
+    #[test]
+    fn test_unusable_values_are_rejected() {
+        let mut invalid = Vec::new();
+        SecurityHeaders::from_lookup(
+            &lookup_from(&[
+                ("SECURITY_CONTENT_TYPE_OPTIONS", "sometimes"),
+                ("SECURITY_FRAME_OPTIONS", "DE\u{1}NY"),
+                ("CORS_ALLOWED_ORIGINS", "actota.com"),
+            ]),
+            &mut invalid,
+        );
+        assert_eq!(
+            invalid,
+            vec![
+                ("CORS_ALLOWED_ORIGINS", "actota.com".to_string()),
+                ("SECURITY_CONTENT_TYPE_OPTIONS", "sometimes".to_string()),
+                ("SECURITY_FRAME_OPTIONS", "DE\u{1}NY".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use actix_http::Payload;
+use actix_web::{error::InternalError, web, Error, FromRequest, HttpRequest, HttpResponse};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use std::ops::Deref;
+
+use crate::models::api_error::ApiError;
+
+/// JSON body extractor that reports which field failed to deserialize.
+///
+/// `web::Json` only surfaces serde's message (e.g. "invalid type: string, expected
+/// u32 at line 1 column 14"), which doesn't name the field. This tracks the path
+/// while deserializing and answers with an `ApiError` carrying `field` and `expected`.
+#[derive(Debug)]
+pub struct TypedJson<T>(pub T);
+
+impl<T> TypedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for TypedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for TypedJson<T> {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            parse_body(&body).map(TypedJson).map_err(|api_error| {
+                eprintln!("JSON error: {:?}", api_error);
+                InternalError::from_response(
+                    api_error.error.clone(),
+                    HttpResponse::BadRequest().json(api_error),
+                )
+                .into()
+            })
+        })
+    }
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        let inner = err.into_inner();
+        if inner.is_syntax() || inner.is_eof() || path == "." {
+            return ApiError::new(format!("JSON error: {}", inner));
+        }
+
+        let message = inner.to_string();
+        // serde messages end with " at line L column C"; drop it, the field says where
+        let message = message
+            .rsplit_once(" at line ")
+            .map(|(message, _)| message.to_string())
+            .unwrap_or(message);
+        let expected = message
+            .split_once("expected ")
+            .map(|(_, expected)| expected.to_string());
+
+        ApiError {
+            error: format!("Invalid value for field `{}`: {}", path, message),
+            field: Some(path),
+            expected,
+            limit: None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::search::SearchItinerary;
+    use actix_web::{http::StatusCode, test as http_test, App, HttpResponse};
+
+    async fn echo_adults(search: TypedJson<SearchItinerary>) -> HttpResponse {
+        HttpResponse::Ok().json(search.adults)
+    }
+
+    #[actix_rt::test]
+    async fn test_wrong_typed_search_field_is_named_in_error() {
+        let app = http_test::init_service(
+            App::new().route("/search", web::post().to(echo_adults)),
+        )
+        .await;
+
+        let req = http_test::TestRequest::post()
+            .uri("/search")
+            .set_json(serde_json::json!({ "locations": ["Denver"], "adults": "two" }))
+            .to_request();
+        let resp = http_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body: ApiError = http_test::read_body_json(resp).await;
+        assert_eq!(body.field.as_deref(), Some("adults"));
+        assert_eq!(body.expected.as_deref(), Some("u32"));
+        assert!(body.error.contains("adults"));
+
+        let req = http_test::TestRequest::post()
+            .uri("/search")
+            .set_json(serde_json::json!({ "adults": 2 }))
+            .to_request();
+        let resp = http_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_nested_paths_and_syntax_errors() {
+        let err = parse_body::<SearchItinerary>(br#"{"locations": ["Denver", 3]}"#).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("locations[1]"));
+        assert_eq!(err.expected.as_deref(), Some("a string"));
+
+        let err = parse_body::<SearchItinerary>(br#"{"adults": "#).unwrap_err();
+        assert_eq!(err.field, None);
+        assert!(err.error.starts_with("JSON error"));
+    }
+}
@@ -2,12 +2,19 @@ use chrono::{DateTime, NaiveDate, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
+use crate::models::money::Money;
+use crate::services::units::UnitSystem;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Favorite {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub user_id: ObjectId,
     pub itinerary_id: ObjectId,
+    /// Per-person price the user last saw or was alerted about. Price-drop alerts
+    /// compare against this, so it only moves when an alert goes out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_notified_price: Option<Money>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -23,6 +30,12 @@ pub struct PersonalInformation {
     pub last_name: Option<String>,
     pub phone_number: Option<String>,
     pub birth_date: Option<NaiveDate>,
+    /// ISO 4217 code prices should be shown in. Charges are always in USD.
+    #[serde(default)]
+    pub preferred_currency: Option<String>,
+    /// `imperial` or `metric`, for distances and temperatures
+    #[serde(default)]
+    pub preferred_units: Option<UnitSystem>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -32,6 +45,83 @@ pub struct Notification {
     pub travel_tips: bool,
     pub special_offers: bool,
     pub newsletter: bool,
+    /// Booking updates by text to the verified phone number
+    #[serde(default)]
+    pub sms: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Email preferences. Transactional messages are on unless turned off; marketing is opt-in.
+/// Security notices (new-device sign-ins, verification codes) are always sent.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EmailPreferences {
+    #[serde(default = "enabled")]
+    pub booking_updates: bool,
+    #[serde(default = "enabled")]
+    pub reminders: bool,
+    #[serde(default)]
+    pub marketing: bool,
+    /// Price drops on favorited itineraries
+    #[serde(default = "enabled")]
+    pub price_alerts: bool,
+    /// Asking for a review after a trip. Also needs `booking_updates` or `marketing`.
+    #[serde(default = "enabled")]
+    pub review_requests: bool,
+}
+
+impl Default for EmailPreferences {
+    fn default() -> Self {
+        EmailPreferences {
+            booking_updates: true,
+            reminders: true,
+            marketing: false,
+            price_alerts: true,
+            review_requests: true,
+        }
+    }
+}
+
+/// SMS preferences. Every kind is opt-in, since texts need explicit consent.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SmsPreferences {
+    #[serde(default)]
+    pub booking_updates: bool,
+    #[serde(default)]
+    pub reminders: bool,
+    #[serde(default)]
+    pub marketing: bool,
+    #[serde(default)]
+    pub price_alerts: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct NotificationPreferences {
+    #[serde(default)]
+    pub email: EmailPreferences,
+    #[serde(default)]
+    pub sms: SmsPreferences,
+}
+
+impl From<&Notification> for NotificationPreferences {
+    /// Carry over the older `notification` switches
+    fn from(legacy: &Notification) -> Self {
+        let marketing = legacy.special_offers || legacy.travel_tips || legacy.newsletter;
+        NotificationPreferences {
+            email: EmailPreferences {
+                booking_updates: legacy.account_activities,
+                reminders: legacy.reminders,
+                marketing,
+                ..EmailPreferences::default()
+            },
+            sms: SmsPreferences {
+                booking_updates: legacy.sms,
+                ..SmsPreferences::default()
+            },
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -41,6 +131,19 @@ pub enum UserRole {
     User,
     #[serde(rename = "admin")]
     Admin,
+    /// Tour operator with read access to bookings involving their company's activities
+    #[serde(rename = "operator")]
+    Operator,
+}
+
+/// A sign-in provider attached to an account
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LinkedAccount {
+    /// `google` or `facebook`
+    pub provider: String,
+    /// The account's id with the provider
+    pub provider_id: String,
+    pub linked_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -52,7 +155,10 @@ pub struct User {
     pub customer_id: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
-    pub phone_number: Option<String>,
+    pub phone_number: Option<String>, // As entered, for display
+    /// `phone_number` normalized to E.164, for SMS and lookups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phone_number_e164: Option<String>,
     pub birth_date: Option<NaiveDate>,
     pub profile_picture: Option<String>, // URL to the profile picture in Google Cloud Storage
     // Security related fields
@@ -61,8 +167,71 @@ pub struct User {
     pub failed_signins: Option<i32>,
     // Permission field
     pub role: Option<UserRole>,
+    /// Company an operator account belongs to (matches `Activity.company_id`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub company_id: Option<String>,
     // We always want these fields, but have them optional so we can set them in the code
     pub notification: Option<Notification>,
+    /// What the user wants to hear about, per channel. Read through
+    /// `effective_notification_preferences`, which covers accounts that predate it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_preferences: Option<NotificationPreferences>,
+    /// ISO 4217 code prices are displayed in; unset means USD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_currency: Option<String>,
+    /// Units distances and temperatures are shown in; unset falls back to the
+    /// request's `Accept-Language`, then imperial
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_units: Option<UnitSystem>,
+    /// Whether the current `email` has been verified. Changing the email clears it.
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(default)]
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Set when the address hard-bounced or reported spam; nothing is emailed to
+    /// it until an admin clears it or the email changes
+    #[serde(default)]
+    pub email_suppressed: bool,
+    /// OAuth providers the user has signed in with, or proven they own the account from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked_accounts: Vec<LinkedAccount>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
+
+impl User {
+    /// Change the email, dropping the old address's verification and suppression.
+    /// Returns whether it changed.
+    pub fn set_email(&mut self, email: String) -> bool {
+        if email == self.email {
+            return false;
+        }
+        self.email = email;
+        self.email_verified = false;
+        self.email_verified_at = None;
+        self.email_suppressed = false;
+        true
+    }
+
+    /// Whether the user can sign in with a password. Accounts made through OAuth
+    /// store the hash of an empty password.
+    pub fn has_password(&self) -> bool {
+        !self.password.is_empty() && !bcrypt::verify("", &self.password).unwrap_or(false)
+    }
+
+    /// Whether `provider_id` with `provider` has been linked to this account
+    pub fn is_linked(&self, provider: &str, provider_id: &str) -> bool {
+        self.linked_accounts
+            .iter()
+            .any(|linked| linked.provider == provider && linked.provider_id == provider_id)
+    }
+
+    /// Stored preferences, else ones carried over from `notification`, else the defaults
+    pub fn effective_notification_preferences(&self) -> NotificationPreferences {
+        match (&self.notification_preferences, &self.notification) {
+            (Some(preferences), _) => preferences.clone(),
+            (None, Some(legacy)) => legacy.into(),
+            (None, None) => NotificationPreferences::default(),
+        }
+    }
+}
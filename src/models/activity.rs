@@ -1,6 +1,10 @@
+use chrono::{NaiveDate, Weekday};
 use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::models::money::Money;
+use crate::services::vertex_activity::DefaultedField;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TimeSlot {
     pub start: String,
@@ -17,6 +21,28 @@ pub struct Address {
     pub country: String,
 }
 
+/// GeoJSON point, stored as `[lng, lat]` for MongoDB geo queries
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct GeoPoint {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub coordinates: [f64; 2],
+}
+
+impl GeoPoint {
+    pub fn new(lat: f64, lng: f64) -> Self {
+        GeoPoint {
+            kind: "Point".to_string(),
+            coordinates: [lng, lat],
+        }
+    }
+
+    /// `(lat, lng)`, the order the rest of the code uses
+    pub fn lat_lng(&self) -> (f64, f64) {
+        (self.coordinates[1], self.coordinates[0])
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Capacity {
     #[serde(deserialize_with = "deserialize_rounded_u16")]
@@ -31,6 +57,15 @@ pub struct BlackoutDateRange {
     end: i64,
 }
 
+impl BlackoutDateRange {
+    /// Whether any part of the given (UTC) day falls inside this blackout range
+    pub fn covers(&self, date: NaiveDate) -> bool {
+        let day_start = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+        let day_end = day_start + 86_400_000;
+        day_start <= self.end && day_end > self.start
+    }
+}
+
 // Custom deserializer to handle floating point to u16 conversion
 fn deserialize_rounded_u16<'de, D>(deserializer: D) -> Result<u16, D::Error>
 where
@@ -109,6 +144,8 @@ pub struct Activity {
     pub activity_types: Vec<String>,
     pub tags: Vec<String>,
     pub price_per_person: f32,
+    /// Zero when missing; scheduling raises it to a minimum (see `clamp_duration`)
+    #[serde(default)]
     pub duration_minutes: u16,
     pub daily_time_slots: Vec<TimeSlot>,
     pub address: Address,
@@ -120,9 +157,61 @@ pub struct Activity {
     #[serde(deserialize_with = "deserialize_optional_rounded_u8", default)]
     pub height_requiremnt: Option<u8>,
     pub blackout_date_ranges: Option<Vec<BlackoutDateRange>>,
+    /// Days of the week the operator runs this activity. Absent means open daily.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operating_days: Option<Vec<Weekday>>,
+    /// Specific dates the operator is closed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub closed_dates: Vec<NaiveDate>,
+    /// Closed on US federal holidays (see `services::calendar`). Opt-in, because
+    /// holiday weekends are among the busiest days for most outdoor operators;
+    /// listing every holiday in `closed_dates` instead would need yearly upkeep.
+    #[serde(default)]
+    pub closed_on_holidays: bool,
     pub capacity: Capacity,
+    /// Geocoded from the address by `POST /admin/activities/backfill-coordinates`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<GeoPoint>,
+    /// Image URLs, best first. Itineraries without images of their own borrow one
+    /// (see `services::image_fallback`). Set with `PUT /admin/activities/{id}/images`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Fields filled from defaults when the activity came from a sparse Vertex AI
+    /// document (see `services::vertex_activity`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaulted_fields: Vec<DefaultedField>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<DateTime>,
 }
+
+/// Shortest time an activity is scheduled for by default. Zero or missing durations
+/// are bad data, and taken at face value any number of them fit in one day.
+pub const DEFAULT_MIN_ACTIVITY_MINUTES: u16 = 30;
+
+impl Activity {
+    /// Per-person price rounded to the cent, for cost arithmetic
+    pub fn price(&self) -> Money {
+        Money::from_dollars(self.price_per_person as f64)
+    }
+
+    /// Whether a guessed price or capacity keeps itineraries with this activity
+    /// from being paid for
+    pub fn needs_pricing_review(&self) -> bool {
+        self.defaulted_fields.iter().any(|field| field.blocks_booking())
+    }
+
+    /// Raise a duration below `min_minutes` to it. Returns whether it was raised.
+    pub fn clamp_duration(&mut self, min_minutes: u16) -> bool {
+        if self.duration_minutes >= min_minutes {
+            return false;
+        }
+        println!(
+            "⚠️ Activity '{}' ({:?}) has a {} minute duration, scheduling it for {}",
+            self.title, self.id, self.duration_minutes, min_minutes
+        );
+        self.duration_minutes = min_minutes;
+        true
+    }
+}
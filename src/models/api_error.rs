@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// Structured error body. `field` and `expected` are filled in when a request
+/// body failed to deserialize, so clients can tell which input to fix. `limit`
+/// is the configured maximum a request went over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiError {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+impl ApiError {
+    pub fn new(error: impl Into<String>) -> Self {
+        ApiError {
+            error: error.into(),
+            field: None,
+            expected: None,
+            limit: None,
+        }
+    }
+}
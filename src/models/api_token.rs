@@ -0,0 +1,76 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+/// Every personal access token starts with this, so the auth middleware can tell
+/// them apart from session JWTs
+pub const API_TOKEN_PREFIX: &str = "actota_pat_";
+
+/// What a personal access token may be used for. Only read scopes exist for now.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    #[serde(rename = "search:read")]
+    SearchRead,
+    #[serde(rename = "bookings:read")]
+    BookingsRead,
+    #[serde(rename = "itineraries:read")]
+    ItinerariesRead,
+}
+
+/// A personal access token. Only the SHA-256 hash of the secret is stored; the
+/// token itself is shown once, when it is created.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ApiToken {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub name: String,
+    /// Start of the token, enough for the owner to recognise it in a list
+    pub prefix: String,
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires_at: Option<DateTime>,
+    pub last_used_at: Option<DateTime>,
+    pub revoked_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+impl ApiToken {
+    /// Not revoked and not past its expiry
+    pub fn is_usable(&self, now: DateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiTokenInput {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A token as listed to its owner, without the hash
+#[derive(Debug, Serialize)]
+pub struct ApiTokenSummary {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: Option<String>,
+}
+
+impl From<&ApiToken> for ApiTokenSummary {
+    fn from(token: &ApiToken) -> Self {
+        let rfc3339 = |date: DateTime| date.try_to_rfc3339_string().ok();
+        ApiTokenSummary {
+            id: token.id.to_hex(),
+            name: token.name.clone(),
+            prefix: token.prefix.clone(),
+            scopes: token.scopes.clone(),
+            expires_at: token.expires_at.and_then(rfc3339),
+            last_used_at: token.last_used_at.and_then(rfc3339),
+            created_at: rfc3339(token.created_at),
+        }
+    }
+}
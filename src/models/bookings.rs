@@ -26,6 +26,16 @@ pub enum PaymentStatus {
     /// The user has been charged and the booking is guaranteed
     #[serde(rename = "confirmed")]
     Confirmed,
+
+    /// Confirmed booking whose trip has started but not yet ended
+    /// Set by the trip status job once the arrival date passes
+    #[serde(rename = "in_progress")]
+    InProgress,
+
+    /// Confirmed booking whose trip has ended
+    /// Set by the trip status job once the departure date passes
+    #[serde(rename = "completed")]
+    Completed,
     
     /// Booking was cancelled but no payment was ever processed
     /// No refund needed as user was never charged
@@ -43,6 +53,23 @@ pub enum PaymentStatus {
     PaymentFailed,
 }
 
+impl PaymentStatus {
+    /// Whether a booking in this status may move to `next`. Cancelled, refunded
+    /// and completed bookings are final.
+    pub fn can_transition_to(&self, next: &PaymentStatus) -> bool {
+        use PaymentStatus::*;
+        matches!(
+            (self, next),
+            (Pending, PendingPayment | Confirmed | PaymentFailed | Cancelled)
+                | (PendingPayment, Confirmed | PaymentFailed | Cancelled)
+                | (PaymentFailed, Pending | PendingPayment | Confirmed | Cancelled)
+                | (Ongoing, Confirmed | InProgress | Completed | Cancelled)
+                | (Confirmed, InProgress | Completed | Cancelled | Refunded)
+                | (InProgress, Completed)
+        )
+    }
+}
+
 // A flexible date parser that attempts to parse various date formats
 fn flexible_date_parser<'de, D>(deserializer: D) -> Result<DateTime, D::Error>
 where
@@ -101,6 +128,9 @@ pub struct BookingInput {
     
     pub customer_id: Option<String>,
     pub transaction_id: Option<String>,
+    /// Dietary needs, accessibility, occasions; shown to operators
+    #[serde(default)]
+    pub special_requests: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -114,9 +144,29 @@ pub struct BookingWithPaymentInput {
 
     pub customer_id: String,
     // Payment fields
-    pub payment_intent_id: String,
+    /// Required unless a gift card covers the full amount
+    #[serde(default)]
+    pub payment_intent_id: Option<String>,
+    /// Total amount due in cents, before any gift card is applied. The amount
+    /// charged comes from the itinerary's price; one that differs is refused.
     pub amount: Option<i64>,
     pub description: Option<String>,
+    #[serde(default)]
+    pub gift_card_code: Option<String>,
+    #[serde(default)]
+    pub special_requests: Option<String>,
+    /// From `POST /payment/reserve`; the held seats become the booking's
+    #[serde(default)]
+    pub reservation_id: Option<String>,
+}
+
+/// Body of `POST /payment/reserve`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReservationInput {
+    pub itinerary_id: String,
+    #[serde(deserialize_with = "flexible_date_parser")]
+    pub arrival_datetime: DateTime,
+    pub party_size: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -131,10 +181,115 @@ pub struct BookingDetails {
     pub departure_datetime: DateTime,
     pub status: PaymentStatus,
     pub bookings: Option<Vec<SingleBooking>>,
+    /// Gift card redemption applied to this booking, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gift_card_redemption_id: Option<ObjectId>,
+    /// Amount paid by gift card, in cents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gift_card_amount: Option<i64>,
+    /// Traveler's notes for the trip, editable until arrival. Never payment details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub special_requests: Option<String>,
+    /// Who is travelling, when it was recorded at booking time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub party: Option<Party>,
+    /// Created by support on the customer's behalf (`POST /admin/bookings`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub created_by_admin: bool,
+    /// Reservation whose held seats this booking takes over on confirmation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reservation_id: Option<ObjectId>,
+    /// Date changes, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifications: Vec<BookingModification>,
+    /// When the traveler was last asked to review the trip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_request_sent_at: Option<DateTime>,
     pub created_at: Option<DateTime>,
     pub updated_at: Option<DateTime>,
 }
 
+/// A move to new dates and how the price difference was settled
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BookingModification {
+    pub previous_arrival_datetime: DateTime,
+    pub previous_departure_datetime: DateTime,
+    pub arrival_datetime: DateTime,
+    pub departure_datetime: DateTime,
+    /// Cents charged (positive) or given back (negative) for the new dates
+    pub price_difference: i64,
+    /// Intent that paid a positive difference
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_intent_id: Option<String>,
+    /// Stripe refund for a negative difference
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_id: Option<String>,
+    /// Part of a negative difference returned to the gift card
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gift_card_restored: Option<i64>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RescheduleInput {
+    #[serde(deserialize_with = "flexible_date_parser")]
+    pub arrival_datetime: DateTime,
+    #[serde(deserialize_with = "flexible_date_parser")]
+    pub departure_datetime: DateTime,
+    /// Authorized, uncaptured intent for the difference when the new dates cost more
+    #[serde(default)]
+    pub payment_intent_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct Party {
+    pub adults: u32,
+    #[serde(default)]
+    pub children: u32,
+    #[serde(default)]
+    pub infants: u32,
+}
+
+impl Party {
+    pub fn size(&self) -> u32 {
+        self.adults + self.children + self.infants
+    }
+}
+
+/// A booking support creates for a customer, usually after taking payment over
+/// the phone through a Stripe payment link
+#[derive(Debug, Deserialize)]
+pub struct AdminBookingInput {
+    /// Existing customer. Give either this or `email`.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// Customer email. A shell account is created, and invited, when no account uses it.
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub first_name: Option<String>,
+    #[serde(default)]
+    pub last_name: Option<String>,
+    pub itinerary_id: String,
+    #[serde(deserialize_with = "flexible_date_parser")]
+    pub arrival_datetime: DateTime,
+    #[serde(deserialize_with = "flexible_date_parser")]
+    pub departure_datetime: DateTime,
+    pub party: Party,
+    /// An already captured PaymentIntent
+    #[serde(default)]
+    pub transaction_id: Option<String>,
+    /// Accept a payment that doesn't match the itinerary's price
+    #[serde(default)]
+    pub override_amount_check: bool,
+    /// For support only; kept in the audit log, never shown to the customer
+    #[serde(default)]
+    pub internal_note: Option<String>,
+    /// The traveler's own requests, as on bookings they make themselves
+    #[serde(default)]
+    pub special_requests: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct SingleBooking {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -0,0 +1,73 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+/// What kind of content a flag is about. Reviews will join itineraries here
+/// once they exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Itinerary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagReason {
+    Offensive,
+    Inaccurate,
+    Unsafe,
+    Spam,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagStatus {
+    Open,
+    Dismissed,
+    TakenDown,
+}
+
+/// One user's report, stored in `Account.ContentFlags`. The reporter is kept for
+/// rate limiting and abuse checks and never leaves the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentFlag {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub content_type: ContentType,
+    pub content_id: ObjectId,
+    pub reporter_id: ObjectId,
+    pub reason: FlagReason,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    pub status: FlagStatus,
+    pub created_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<ObjectId>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportInput {
+    pub reason: FlagReason,
+    #[serde(default)]
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagResolution {
+    /// The content is fine; close the flags
+    Dismiss,
+    /// Hide the content and close the flags
+    TakeDown,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveFlagsInput {
+    pub content_type: ContentType,
+    pub content_id: String,
+    pub action: FlagResolution,
+    #[serde(default)]
+    pub note: Option<String>,
+}
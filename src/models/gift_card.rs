@@ -0,0 +1,64 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+/// A redeemable gift card. All balances are in the smallest currency unit (cents).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GiftCard {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub code: String,
+    pub initial_balance: i64,
+    pub remaining_balance: i64,
+    pub currency: String,
+    pub expires_at: Option<DateTime>,
+    pub active: bool,
+    pub created_at: Option<DateTime>,
+    pub updated_at: Option<DateTime>,
+}
+
+impl GiftCard {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at < DateTime::now())
+            .unwrap_or(false)
+    }
+}
+
+/// Ledger entry for an amount taken from (and possibly restored to) a gift card
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GiftCardRedemption {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub gift_card_id: ObjectId,
+    pub code: String,
+    pub user_id: ObjectId,
+    pub booking_id: Option<ObjectId>,
+    pub amount: i64,
+    #[serde(default)]
+    pub restored_amount: i64,
+    pub created_at: Option<DateTime>,
+    pub updated_at: Option<DateTime>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GiftCardInput {
+    pub code: String,
+    pub initial_balance: i64,
+    pub currency: Option<String>,
+    pub expires_at: Option<DateTime>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GiftCardUpdate {
+    pub remaining_balance: Option<i64>,
+    pub expires_at: Option<DateTime>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApplyGiftCardInput {
+    pub code: String,
+    /// Amount due in cents, used to preview how much the card covers
+    pub amount: Option<i64>,
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::DateTime;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 fn default_datetime() -> DateTime {
     DateTime::now()
@@ -45,12 +45,23 @@ pub struct ItinerarySubmission {
     pub transportation: String,
     pub budget_per_person: Option<f32>,
     pub interests: Option<Vec<String>>,
+    /// Search weight experiment the search was scored under, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<DateTime>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Moderate,
+    Challenging,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct FeaturedVacation {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -68,6 +79,9 @@ pub struct FeaturedVacation {
     #[serde(flatten)]
     pub days: Days,
     pub images: Option<Vec<String>>,
+    /// Hero image, one of `images`. Listings fall back to the first image when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary_image: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub arrival_datetime: Option<DateTime>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -93,9 +107,39 @@ pub struct FeaturedVacation {
     #[serde(default, skip_serializing)]
     pub activities: Option<Vec<Activity>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub person_cost: Option<crate::models::money::Money>, // Sum of activity prices per person
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub needs_review: bool, // Set when referenced activities no longer exist
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_activity_ids: Vec<ObjectId>,
+    /// Generated from Vertex AI documents without a price or capacity, which
+    /// were filled in from defaults. Checkout refuses the itinerary until a cost
+    /// recompute re-prices it from `Options.Activity`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaulted_activity_ids: Vec<ObjectId>,
+    /// Set when moderation takes the itinerary down. It's then left out of
+    /// listings and search and `GET /itineraries/{id}` returns 404.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taken_down_at: Option<DateTime>,
+    /// Set when an admin archives the itinerary. It's left out of listings and
+    /// search but can still be opened by id, so travelers who booked it keep
+    /// their trip page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<DateTime>,
+    /// Curation tags set by admins; `tag` is where the itinerary came from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub match_score: Option<u8>, // Score from 0-100
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub score_breakdown: Option<crate::services::search_scoring::ScoreBreakdown>, // Detailed score breakdown
+    #[serde(skip)]
+    pub generation_trace: Option<crate::services::generation_trace::GenerationTrace>, // Only set for traced requests
+    /// Where a generated itinerary came from. Curated itineraries don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation_metadata: Option<crate::services::generation_trace::GenerationMetadata>,
 }
 
 impl Default for FeaturedVacation {
@@ -114,6 +158,7 @@ impl Default for FeaturedVacation {
             description: String::new(),
             days: Days::default(),
             images: None,
+            primary_image: None,
             arrival_datetime: None,
             departure_datetime: None,
             adults: None,
@@ -126,8 +171,42 @@ impl Default for FeaturedVacation {
             updated_at: None,
             tag: None,
             activities: None,
+            person_cost: None,
+            needs_review: false,
+            taken_down_at: None,
+            archived_at: None,
+            tags: Vec::new(),
+            difficulty: None,
+            missing_activity_ids: Vec::new(),
+            defaulted_activity_ids: Vec::new(),
             match_score: None,
             score_breakdown: None,
+            generation_trace: None,
+            generation_metadata: None,
+        }
+    }
+}
+
+impl FeaturedVacation {
+    /// Travelers in the party, when the itinerary records who is travelling
+    pub fn party_size(&self) -> Option<u32> {
+        match (self.adults, self.children, self.infants) {
+            (None, None, None) => None,
+            (adults, children, infants) => {
+                Some(adults.unwrap_or(0) + children.unwrap_or(0) + infants.unwrap_or(0))
+            }
+        }
+    }
+
+    /// Move the primary image to the front of `images`, so whatever shows the first
+    /// image as the thumbnail shows the hero. A primary that isn't among the images
+    /// (removed from the bucket since it was set) is ignored.
+    pub fn order_images(&mut self) {
+        let (Some(images), Some(primary)) = (self.images.as_mut(), self.primary_image.as_deref()) else {
+            return;
+        };
+        if let Some(index) = images.iter().position(|image| image == primary) {
+            images[..=index].rotate_right(1);
         }
     }
 }
@@ -162,6 +241,136 @@ pub struct Days {
     pub days: HashMap<String, Vec<DayItem>>,
 }
 
+/// How much a trip holds, counted from its day items as stored
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DayItemCounts {
+    pub days: usize,
+    pub activities: usize,
+    pub accommodations: usize,
+    pub transportation: usize,
+}
+
+impl Days {
+    pub fn item_counts(&self) -> DayItemCounts {
+        let mut counts = DayItemCounts {
+            days: self.days.len(),
+            ..Default::default()
+        };
+        for item in self.days.values().flatten() {
+            match item {
+                DayItem::Activity { .. } => counts.activities += 1,
+                DayItem::Accommodation { .. } => counts.accommodations += 1,
+                DayItem::Transportation { .. } => counts.transportation += 1,
+            }
+        }
+        counts
+    }
+
+    /// Each scheduled activity once, in trip order: by day number, then as listed
+    pub fn activity_ids(&self) -> Vec<ObjectId> {
+        let mut days: Vec<(u32, &Vec<DayItem>)> = self
+            .days
+            .iter()
+            .filter_map(|(day, items)| Some((day.trim().parse().ok()?, items)))
+            .collect();
+        days.sort_by_key(|(day, _)| *day);
+
+        let mut ids: Vec<ObjectId> = Vec::new();
+        for item in days.into_iter().flat_map(|(_, items)| items) {
+            if let DayItem::Activity { activity_id, .. } = item {
+                if !ids.contains(activity_id) {
+                    ids.push(*activity_id);
+                }
+            }
+        }
+        ids
+    }
+
+    /// Point activity items at `replacements[activity_id]` where there is one.
+    /// An activity that then appears twice in a day keeps only its first item.
+    /// Returns how many items were changed or dropped.
+    pub fn replace_activities(&mut self, replacements: &HashMap<ObjectId, ObjectId>) -> usize {
+        let mut changed = 0;
+        for items in self.days.values_mut() {
+            let mut seen = Vec::new();
+            items.retain_mut(|item| {
+                let DayItem::Activity { activity_id, .. } = item else {
+                    return true;
+                };
+                if let Some(replacement) = replacements.get(activity_id) {
+                    *activity_id = *replacement;
+                    changed += 1;
+                }
+                if seen.contains(activity_id) {
+                    changed += 1;
+                    return false;
+                }
+                seen.push(*activity_id);
+                true
+            });
+        }
+        changed
+    }
+}
+
+/// Days keyed by day number, as stored, written out in responses as a list sorted
+/// by day number: `[{"day": 1, "items": [...]}, {"day": 2, ...}]`. Map order is
+/// arbitrary and clients sorting the keys as strings put "10" before "2". Keys that
+/// aren't day numbers are left out.
+pub struct OrderedDays<'a, T>(pub &'a HashMap<String, Vec<T>>);
+
+#[derive(Serialize)]
+struct DayRef<'a, T> {
+    day: u32,
+    items: &'a [T],
+}
+
+#[derive(Deserialize)]
+struct OwnedDay<T> {
+    day: u32,
+    items: Vec<T>,
+}
+
+impl<T: Serialize> Serialize for OrderedDays<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut days: Vec<DayRef<T>> = self
+            .0
+            .iter()
+            .filter_map(|(day, items)| {
+                Some(DayRef {
+                    day: day.trim().parse().ok()?,
+                    items,
+                })
+            })
+            .collect();
+        days.sort_by_key(|day| day.day);
+        serializer.collect_seq(days)
+    }
+}
+
+/// `#[serde(with = ...)]` for an optional day map written as `OrderedDays`
+pub mod ordered_days {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Serialize>(
+        days: &Option<HashMap<String, Vec<T>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        days.as_ref().map(OrderedDays).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Option<HashMap<String, Vec<T>>>, D::Error> {
+        let days: Option<Vec<OwnedDay<T>>> = Option::deserialize(deserializer)?;
+        Ok(days.map(|days| {
+            days.into_iter()
+                .map(|day| (day.day.to_string(), day.items))
+                .collect()
+        }))
+    }
+}
+
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "type")] // Use the "type" field to determine which variant to use
@@ -201,3 +410,68 @@ pub struct ItemLocation {
     pub name: String,
     pub coordinates: Vec<f64>,  // MongoDB stores as array of doubles
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_images(images: &[&str], primary: Option<&str>) -> FeaturedVacation {
+        FeaturedVacation {
+            images: Some(images.iter().map(|image| image.to_string()).collect()),
+            primary_image: primary.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_primary_image_comes_first() {
+        let mut itinerary = with_images(&["a.jpg", "b.jpg", "c.jpg"], Some("c.jpg"));
+        itinerary.order_images();
+        assert_eq!(itinerary.images.unwrap(), vec!["c.jpg", "a.jpg", "b.jpg"]);
+
+        // Unset or stale primaries leave the order alone
+        for primary in [None, Some("gone.jpg")] {
+            let mut itinerary = with_images(&["a.jpg", "b.jpg"], primary);
+            itinerary.order_images();
+            assert_eq!(itinerary.images.unwrap(), vec!["a.jpg", "b.jpg"]);
+        }
+    }
+
+    #[test]
+    fn test_activity_ids_follow_the_trip() {
+        let (first, second, third) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let activity = |activity_id| DayItem::Activity { time: "09:00:00".to_string(), activity_id };
+        let days = Days {
+            days: HashMap::from([
+                ("10".to_string(), vec![activity(third)]),
+                (
+                    "2".to_string(),
+                    vec![
+                        activity(second),
+                        DayItem::Accommodation { time: "18:00:00".to_string(), accommodation_id: ObjectId::new() },
+                        activity(first),
+                    ],
+                ),
+                ("1".to_string(), vec![activity(first)]),
+            ]),
+        };
+        assert_eq!(days.activity_ids(), vec![first, second, third]);
+    }
+
+    #[test]
+    fn test_replacing_activities_drops_repeats_within_a_day() {
+        let (primary, duplicate, other) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let activity = |activity_id| DayItem::Activity { time: "09:00:00".to_string(), activity_id };
+        let mut days = Days {
+            days: HashMap::from([
+                ("1".to_string(), vec![activity(primary), activity(other), activity(duplicate)]),
+                ("2".to_string(), vec![activity(duplicate)]),
+            ]),
+        };
+        let changed = days.replace_activities(&HashMap::from([(duplicate, primary)]));
+        assert_eq!(changed, 3);
+        assert_eq!(days.activity_ids(), vec![primary, other]);
+        assert_eq!(days.days["1"].len(), 2);
+        assert_eq!(days.days["2"].len(), 1);
+    }
+}
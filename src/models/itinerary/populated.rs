@@ -4,7 +4,9 @@ use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 
-use super::base::{FeaturedVacation, ItemLocation};
+use super::base::{FeaturedVacation, ItemLocation, OrderedDays};
+use crate::models::money::Money;
+use crate::services::fx_service::DisplayPrice;
 use crate::services::search_scoring::ScoreBreakdown;
 
 // Custom deserializer to handle floating point to u16 conversion
@@ -156,20 +158,32 @@ pub enum PopulatedDayItem {
     },
 }
 
+/// A day item left out of a populated itinerary because what it points at is gone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PopulationWarning {
+    pub day: String,
+    /// `activity` or `accommodation`
+    pub item_type: String,
+    pub missing_id: ObjectId,
+}
+
 // Populated version using composition for minimal maintenance
 #[derive(Debug, Clone)]
 pub struct PopulatedFeaturedVacation {
     // Reuse the original struct rather than duplicating all fields
     pub base: FeaturedVacation,
-    pub person_cost: f32,
+    pub person_cost: Money,
     pub populated_days: HashMap<String, Vec<PopulatedDayItem>>,
     pub activities: Vec<ActivitySummary>,
     pub match_score: Option<u8>, // Score from 0-100
     pub score_breakdown: Option<ScoreBreakdown>, // Detailed score breakdown
-    pub activity_cost: Option<f32>, // Total activity costs
-    pub lodging_cost: Option<f32>, // Total lodging costs
-    pub transport_cost: Option<f32>, // Total transport costs
-    pub service_fee: Option<f32>, // Service fee
+    pub activity_cost: Option<Money>, // Total activity costs
+    pub lodging_cost: Option<Money>, // Total lodging costs
+    pub transport_cost: Option<Money>, // Total transport costs
+    pub service_fee: Option<Money>, // Service fee
+    pub display_price: Option<DisplayPrice>, // person_cost in the viewer's currency, display only
+    pub population_warnings: Vec<PopulationWarning>, // Day items omitted for missing references
+    pub show_population_warnings: bool, // Only serialized in verbose mode
 }
 
 // Custom serialization to handle the composition
@@ -186,6 +200,8 @@ impl Serialize for PopulatedFeaturedVacation {
         if self.lodging_cost.is_some() { field_count += 1; }
         if self.transport_cost.is_some() { field_count += 1; }
         if self.service_fee.is_some() { field_count += 1; }
+        if self.display_price.is_some() { field_count += 1; }
+        if self.show_population_warnings { field_count += 2; }
         let mut state = serializer.serialize_struct("PopulatedFeaturedVacation", field_count)?;
 
         // Serialize all base fields
@@ -204,11 +220,13 @@ impl Serialize for PopulatedFeaturedVacation {
         state.serialize_field("created_at", &self.base.created_at)?;
         state.serialize_field("updated_at", &self.base.updated_at)?;
 
-        // Serialize the person_cost field
-        state.serialize_field("person_cost", &self.person_cost)?;
+        // Serialize the person_cost field. Zero means nothing could be priced,
+        // which must not read as a free trip.
+        let person_cost = (self.person_cost > Money::ZERO).then_some(self.person_cost);
+        state.serialize_field("person_cost", &person_cost)?;
 
-        // Serialize the populated days
-        state.serialize_field("days", &self.populated_days)?;
+        // Serialize the populated days, in day order
+        state.serialize_field("days", &OrderedDays(&self.populated_days))?;
 
         // Serialize the activities summary
         state.serialize_field("activities", &self.activities)?;
@@ -236,6 +254,13 @@ impl Serialize for PopulatedFeaturedVacation {
         if let Some(service_fee) = self.service_fee {
             state.serialize_field("service_fee", &service_fee)?;
         }
+        if let Some(display_price) = &self.display_price {
+            state.serialize_field("display_price", display_price)?;
+        }
+        if self.show_population_warnings {
+            state.serialize_field("population_warning_count", &self.population_warnings.len())?;
+            state.serialize_field("population_warnings", &self.population_warnings)?;
+        }
 
         state.end()
     }
@@ -245,7 +270,7 @@ impl Serialize for PopulatedFeaturedVacation {
 impl PopulatedFeaturedVacation {
     pub fn from_base(
         base: FeaturedVacation,
-        person_cost: f32,
+        person_cost: Money,
         populated_days: HashMap<String, Vec<PopulatedDayItem>>,
         activities: Vec<ActivitySummary>,
     ) -> Self {
@@ -260,6 +285,9 @@ impl PopulatedFeaturedVacation {
             lodging_cost: None,
             transport_cost: None,
             service_fee: None,
+            display_price: None,
+            population_warnings: Vec::new(),
+            show_population_warnings: false,
         }
     }
 
@@ -267,11 +295,31 @@ impl PopulatedFeaturedVacation {
         self.base.id
     }
 
+    /// The accommodations the trip stays at, once each, in day order
+    pub fn lodging(&self) -> Vec<AccommodationModel> {
+        let mut days: Vec<(u32, &Vec<PopulatedDayItem>)> = self
+            .populated_days
+            .iter()
+            .filter_map(|(day, items)| Some((day.trim().parse().ok()?, items)))
+            .collect();
+        days.sort_by_key(|(day, _)| *day);
+
+        let mut lodging: Vec<AccommodationModel> = Vec::new();
+        for item in days.into_iter().flat_map(|(_, items)| items) {
+            if let PopulatedDayItem::Accommodation { accommodation, .. } = item {
+                if !lodging.iter().any(|stay| stay.id == accommodation.id) {
+                    lodging.push(accommodation.clone());
+                }
+            }
+        }
+        lodging
+    }
+
     pub fn trip_name(&self) -> &str {
         &self.base.trip_name
     }
 
-    pub fn person_cost(&self) -> f32 {
+    pub fn person_cost(&self) -> Money {
         self.person_cost
     }
     
@@ -283,21 +331,30 @@ impl PopulatedFeaturedVacation {
         self.score_breakdown = Some(breakdown);
     }
     
-    pub fn set_activity_cost(&mut self, cost: f32) {
+    pub fn set_activity_cost(&mut self, cost: Money) {
         self.activity_cost = Some(cost);
     }
     
-    pub fn set_lodging_cost(&mut self, cost: f32) {
+    pub fn set_lodging_cost(&mut self, cost: Money) {
         self.lodging_cost = Some(cost);
     }
     
-    pub fn set_transport_cost(&mut self, cost: f32) {
+    pub fn set_transport_cost(&mut self, cost: Money) {
         self.transport_cost = Some(cost);
     }
     
-    pub fn set_service_fee(&mut self, fee: f32) {
+    pub fn set_service_fee(&mut self, fee: Money) {
         self.service_fee = Some(fee);
     }
+
+    pub fn set_display_price(&mut self, display_price: Option<DisplayPrice>) {
+        self.display_price = display_price;
+    }
+
+    /// Include the population warnings in the response, for `?verbose=true`
+    pub fn show_population_warnings(&mut self, show: bool) {
+        self.show_population_warnings = show;
+    }
     
     pub fn populate_images_from_activities(&mut self) {
         // Check if itinerary already has images - only use activity images as fallback
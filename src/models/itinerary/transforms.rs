@@ -1,23 +1,23 @@
-use crate::models::itinerary::populated::{ActivitySummary, Address, Capacity};
+use crate::models::itinerary::populated::{ActivitySummary, PopulationWarning};
 
 use super::{
-    base::{DayItem, FeaturedVacation},
+    base::{DayItem, Days, FeaturedVacation},
     populated::{AccommodationModel, ActivityModel, PopulatedDayItem, PopulatedFeaturedVacation},
 };
-use bson::{doc, oid::ObjectId};
+use bson::{doc, oid::ObjectId, Document};
+use serde::de::DeserializeOwned;
 use futures::stream::TryStreamExt;
 use google_cloud_storage::client::{Client as GcsClient, ClientConfig};
 use google_cloud_storage::http::objects::list::ListObjectsRequest;
 use mongodb::{error::Error, Client, Collection};
 use std::collections::{HashMap, HashSet};
-use std::env;
+use std::future::Future;
 
 // Helper function to fetch activity images from GCS bucket
 async fn fetch_activity_images(
+    bucket_name: &str,
     activity_id: &str,
 ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    // Get bucket name from environment variable
-    let bucket_name = env::var("ACTIVITY_BUCKET").map_err(|_| "ACTIVITY_BUCKET not set")?;
     let base_url = "https://storage.googleapis.com";
 
     // Initialize GCS client
@@ -26,7 +26,7 @@ async fn fetch_activity_images(
 
     // Create a list request for the activity's folder
     let list_request = ListObjectsRequest {
-        bucket: bucket_name.clone(),
+        bucket: bucket_name.to_string(),
         prefix: Some(activity_id.to_string()),
         ..Default::default()
     };
@@ -57,13 +57,146 @@ async fn fetch_activity_images(
     Ok(images)
 }
 
+/// Fetch the documents with the given ids, keyed by id. A document that no longer
+/// decodes is left out, the same as one that's been deleted.
+async fn fetch_by_id<T: DeserializeOwned>(
+    client: &Client,
+    collection: &str,
+    ids: Vec<ObjectId>,
+) -> Result<HashMap<ObjectId, T>, Error> {
+    let mut found = HashMap::new();
+    if ids.is_empty() {
+        return Ok(found);
+    }
+    let collection: Collection<Document> = client.database("Options").collection(collection);
+    let docs: Vec<Document> = collection
+        .find(doc! { "_id": { "$in": ids } })
+        .await?
+        .try_collect()
+        .await?;
+    for document in docs {
+        let Ok(id) = document.get_object_id("_id") else {
+            continue;
+        };
+        match bson::from_document(document) {
+            Ok(decoded) => {
+                found.insert(id, decoded);
+            }
+            Err(e) => eprintln!("⚠️ Skipping unreadable {} {}: {}", collection.name(), id, e),
+        }
+    }
+    Ok(found)
+}
+
+/// Where `populate` looks up what day items refer to
+pub trait ReferenceLookup {
+    fn activities(
+        &self,
+        ids: Vec<ObjectId>,
+    ) -> impl Future<Output = Result<HashMap<ObjectId, ActivityModel>, Error>> + Send;
+
+    fn accommodations(
+        &self,
+        ids: Vec<ObjectId>,
+    ) -> impl Future<Output = Result<HashMap<ObjectId, AccommodationModel>, Error>> + Send;
+}
+
+impl ReferenceLookup for Client {
+    fn activities(
+        &self,
+        ids: Vec<ObjectId>,
+    ) -> impl Future<Output = Result<HashMap<ObjectId, ActivityModel>, Error>> + Send {
+        fetch_by_id(self, "Activity", ids)
+    }
+
+    fn accommodations(
+        &self,
+        ids: Vec<ObjectId>,
+    ) -> impl Future<Output = Result<HashMap<ObjectId, AccommodationModel>, Error>> + Send {
+        fetch_by_id(self, "Lodging", ids)
+    }
+}
+
+/// Which references `populate_with` looks up. Day items of a kind that isn't
+/// looked up are left out of the populated days without a warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lookups {
+    pub activities: bool,
+    pub accommodations: bool,
+}
+
+impl Lookups {
+    pub const ALL: Lookups = Lookups {
+        activities: true,
+        accommodations: true,
+    };
+}
+
+impl Days {
+    /// Day items whose activity or accommodation isn't among the ones that exist,
+    /// in day order
+    pub fn missing_references(
+        &self,
+        activities: &HashSet<ObjectId>,
+        accommodations: &HashSet<ObjectId>,
+    ) -> Vec<PopulationWarning> {
+        let mut days: Vec<_> = self.days.iter().collect();
+        days.sort_by_key(|(day, _)| (day.parse::<u32>().unwrap_or(u32::MAX), day.as_str()));
+
+        let mut warnings = Vec::new();
+        for (day, items) in days {
+            for item in items {
+                let (item_type, id, exists) = match item {
+                    DayItem::Activity { activity_id, .. } => {
+                        ("activity", activity_id, activities.contains(activity_id))
+                    }
+                    DayItem::Accommodation {
+                        accommodation_id, ..
+                    } => (
+                        "accommodation",
+                        accommodation_id,
+                        accommodations.contains(accommodation_id),
+                    ),
+                    DayItem::Transportation { .. } => continue,
+                };
+                if !exists {
+                    warnings.push(PopulationWarning {
+                        day: day.clone(),
+                        item_type: item_type.to_string(),
+                        missing_id: *id,
+                    });
+                }
+            }
+        }
+        warnings
+    }
+}
+
 impl FeaturedVacation {
-    pub async fn populate(self, client: &Client) -> Result<PopulatedFeaturedVacation, Error> {
+    /// Look up the itinerary's activities and accommodations. Items whose activity
+    /// or accommodation is missing are left out and listed in `population_warnings`;
+    /// only a failed lookup is an error. Activity images are listed from
+    /// `activity_bucket` when it's configured.
+    pub async fn populate(
+        self,
+        client: &Client,
+        activity_bucket: Option<&str>,
+    ) -> Result<PopulatedFeaturedVacation, Error> {
+        self.populate_with(client, Lookups::ALL, activity_bucket).await
+    }
+
+    /// `populate`, looking up only what `lookups` asks for
+    pub async fn populate_with<L: ReferenceLookup>(
+        self,
+        lookup: &L,
+        lookups: Lookups,
+        activity_bucket: Option<&str>,
+    ) -> Result<PopulatedFeaturedVacation, Error> {
         // 1. Extract all activity and accommodation IDs
         let mut activity_ids = HashSet::new();
         let mut accommodation_ids = HashSet::new();
         // person_cost will be calculated after population, use placeholder for now
-        let person_cost: f32 = 0.0;
+        let person_cost = crate::models::money::Money::ZERO;
 
         println!("Days.days: {:?}", &self.days.days);
 
@@ -86,68 +219,54 @@ impl FeaturedVacation {
         println!("\n\nActivities: {:?}", activity_ids);
 
         // 2. Fetch activities
-        let activities_collection: Collection<ActivityModel> =
-            client.database("Options").collection("Activity");
-
-        let activities_vec: Vec<ObjectId> = activity_ids.into_iter().collect();
-        let mut activities_map = HashMap::new();
-
-        if !activities_vec.is_empty() {
-            let cursor = activities_collection
-                .find(doc! { "_id": { "$in": activities_vec } })
-                .await?;
-
-            let activities: Vec<ActivityModel> = cursor.try_collect().await?;
-
-            for activity in activities {
-                // Note: person_cost is already set in the database, so we don't add activity costs here
-                // person_cost += activity.price_per_person as f32;
-
-                if let Some(id) = activity.id {
-                    activities_map.insert(id, activity);
-                }
-            }
-        }
+        let activities_map: HashMap<ObjectId, ActivityModel> = if lookups.activities && !activity_ids.is_empty() {
+            lookup.activities(activity_ids.into_iter().collect()).await?
+        } else {
+            HashMap::new()
+        };
 
         // 3. Fetch accommodations
-        let accommodations_collection: Collection<AccommodationModel> =
-            client.database("Options").collection("Lodging");
-
-        let accommodations_vec: Vec<ObjectId> = accommodation_ids.into_iter().collect();
-        let mut accommodations_map = HashMap::new();
-
-        if !accommodations_vec.is_empty() {
-            let cursor = accommodations_collection
-                .find(doc! { "_id": { "$in": accommodations_vec } })
-                .await?;
-
-            let accommodations: Vec<AccommodationModel> = cursor.try_collect().await?;
-
-            for accommodation in accommodations {
-                // Note: person_cost is already set in the database, so we don't add accommodation costs here
-                // if let Some(price) = accommodation.price_per_night {
-                //     person_cost += price as f32;
-                // }
-
-                if let Some(id) = accommodation.id {
-                    accommodations_map.insert(id, accommodation);
-                }
-            }
+        let accommodations_map: HashMap<ObjectId, AccommodationModel> =
+            if lookups.accommodations && !accommodation_ids.is_empty() {
+                lookup.accommodations(accommodation_ids.into_iter().collect()).await?
+            } else {
+                HashMap::new()
+            };
+
+        // Items that weren't looked up aren't missing
+        let population_warnings = if lookups == Lookups::ALL {
+            self.days.missing_references(
+                &activities_map.keys().copied().collect(),
+                &accommodations_map.keys().copied().collect(),
+            )
+        } else {
+            Vec::new()
+        };
+        for warning in &population_warnings {
+            println!(
+                "⚠️ Itinerary '{}' day {}: {} {} not found, leaving it out",
+                self.trip_name,
+                warning.day,
+                warning.item_type,
+                warning.missing_id
+            );
         }
 
-        // 4. Collect all activity IDs that need image fetching
+        // 4. Collect all activity IDs that need image fetching; none without a bucket to list
         let mut activity_image_requests = Vec::new();
-        for activity in activities_map.values() {
-            if let Some(id) = activity.id {
-                activity_image_requests.push(id.to_string());
+        if let Some(bucket) = activity_bucket {
+            for activity in activities_map.values() {
+                if let Some(id) = activity.id {
+                    activity_image_requests.push((bucket, id.to_string()));
+                }
             }
         }
 
         // 5. Fetch all activity images concurrently
         let image_futures: Vec<_> = activity_image_requests
             .into_iter()
-            .map(|activity_id_str| async move {
-                let images = fetch_activity_images(&activity_id_str).await.unwrap_or_default();
+            .map(|(bucket, activity_id_str)| async move {
+                let images = fetch_activity_images(bucket, &activity_id_str).await.unwrap_or_default();
                 (activity_id_str, images)
             })
             .collect();
@@ -180,7 +299,6 @@ impl FeaturedVacation {
                     },
 
                     DayItem::Activity { time, activity_id } => {
-                        // Get activity or create a placeholder if not found
                         if let Some(activity) = activities_map.get(&activity_id) {
                             let mut activity_with_images = activity.clone();
 
@@ -207,49 +325,7 @@ impl FeaturedVacation {
                                 activity: activity_with_images,
                             }
                         } else {
-                            // Create a placeholder activity instead of failing
-                            println!(
-                                "Warning: Activity not found: {}, creating placeholder",
-                                activity_id
-                            );
-                            PopulatedDayItem::Activity {
-                                time,
-                                activity_id: Some(activity_id), // Include the activity_id for backward compatibility
-                                activity: ActivityModel {
-                                    id: Some(activity_id),
-                                    company: "Unknown Company".to_string(),
-                                    company_id: "unknown".to_string(),
-                                    booking_link: "#".to_string(),
-                                    online_booking_status: "unavailable".to_string(),
-                                    title: format!("Unknown Activity (ID: {})", activity_id),
-                                    description: "This activity information could not be found"
-                                        .to_string(),
-                                    activity_types: vec!["unknown".to_string()],
-                                    tags: vec![],
-                                    price_per_person: 0.0,
-                                    duration_minutes: 60,
-                                    daily_time_slots: vec![],
-                                    address: Address {
-                                        street: "Unknown".to_string(),
-                                        unit: None,
-                                        city: "Unknown".to_string(),
-                                        state: "Unknown".to_string(),
-                                        zip: "00000".to_string(),
-                                        country: "Unknown".to_string(),
-                                    },
-                                    whats_included: vec![],
-                                    weight_limit_lbs: None,
-                                    age_requirement: None,
-                                    height_requirement: None,
-                                    capacity: Capacity {
-                                        minimum: 1,
-                                        maximum: 10,
-                                    },
-                                    activities: None,
-                                    primary_image: None,
-                                    images: None,
-                                },
-                            }
+                            continue;
                         }
                     }
 
@@ -257,36 +333,13 @@ impl FeaturedVacation {
                         time,
                         accommodation_id,
                     } => {
-                        // Get accommodation or create a placeholder if not found
                         if let Some(accommodation) = accommodations_map.get(&accommodation_id) {
                             PopulatedDayItem::Accommodation {
                                 time,
                                 accommodation: accommodation.clone(),
                             }
                         } else {
-                            // Create a placeholder accommodation instead of failing
-                            println!(
-                                "Warning: Accommodation not found: {}, creating placeholder",
-                                accommodation_id
-                            );
-                            PopulatedDayItem::Accommodation {
-                                time,
-                                accommodation: AccommodationModel {
-                                    id: Some(accommodation_id),
-                                    name: format!(
-                                        "Unknown Accommodation (ID: {})",
-                                        accommodation_id
-                                    ),
-                                    address: Some("Address information unavailable".to_string()),
-                                    location: None,
-                                    price_per_night: None,
-                                    amenities: Some(vec!["Information unavailable".to_string()]),
-                                    primary_image: None,
-                                    images: None,
-                                    created_at: Some(mongodb::bson::DateTime::now()),
-                                    updated_at: Some(mongodb::bson::DateTime::now()),
-                                },
-                            }
+                            continue;
                         }
                     }
                 };
@@ -309,6 +362,102 @@ impl FeaturedVacation {
             lodging_cost: None,
             transport_cost: None,
             service_fee: None,
+            display_price: None,
+            population_warnings,
+            show_population_warnings: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::search_response::DetailSections;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Finds nothing, and counts how often it was asked
+    #[derive(Default)]
+    struct CountingLookup {
+        activity_lookups: AtomicUsize,
+        accommodation_lookups: AtomicUsize,
+    }
+
+    impl ReferenceLookup for CountingLookup {
+        fn activities(
+            &self,
+            _ids: Vec<ObjectId>,
+        ) -> impl Future<Output = Result<HashMap<ObjectId, ActivityModel>, Error>> + Send {
+            self.activity_lookups.fetch_add(1, Ordering::SeqCst);
+            async { Ok(HashMap::new()) }
+        }
+
+        fn accommodations(
+            &self,
+            _ids: Vec<ObjectId>,
+        ) -> impl Future<Output = Result<HashMap<ObjectId, AccommodationModel>, Error>> + Send {
+            self.accommodation_lookups.fetch_add(1, Ordering::SeqCst);
+            async { Ok(HashMap::new()) }
+        }
+    }
+
+    fn itinerary() -> FeaturedVacation {
+        let mut itinerary = FeaturedVacation {
+            images: Some(vec!["hero.jpg".to_string()]),
+            ..Default::default()
+        };
+        itinerary.days.days.insert(
+            "1".to_string(),
+            vec![
+                DayItem::Activity { time: "09:00".to_string(), activity_id: ObjectId::new() },
+                DayItem::Accommodation { time: "18:00".to_string(), accommodation_id: ObjectId::new() },
+            ],
+        );
+        itinerary
+    }
+
+    fn lookups_for(include: &str, itinerary: &FeaturedVacation) -> Lookups {
+        let sections = DetailSections::parse(include).unwrap();
+        let has_own_images = itinerary.images.as_ref().is_some_and(|images| !images.is_empty());
+        Lookups {
+            activities: sections.needs_activities(has_own_images),
+            accommodations: sections.needs_accommodations(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_images_alone_looks_nothing_up() {
+        let lookup = CountingLookup::default();
+        let itinerary = itinerary();
+        let lookups = lookups_for("images", &itinerary);
+
+        let populated = itinerary.populate_with(&lookup, lookups, None).await.unwrap();
+
+        assert_eq!(lookup.activity_lookups.load(Ordering::SeqCst), 0);
+        assert_eq!(lookup.accommodation_lookups.load(Ordering::SeqCst), 0);
+        // Nothing was looked up, so nothing is reported missing either
+        assert!(populated.population_warnings.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_lodging_looks_up_accommodations_only() {
+        let lookup = CountingLookup::default();
+        let itinerary = itinerary();
+        let lookups = lookups_for("lodging", &itinerary);
+
+        itinerary.populate_with(&lookup, lookups, None).await.unwrap();
+
+        assert_eq!(lookup.activity_lookups.load(Ordering::SeqCst), 0);
+        assert_eq!(lookup.accommodation_lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_everything_looks_up_both_and_reports_missing() {
+        let lookup = CountingLookup::default();
+
+        let populated = itinerary().populate_with(&lookup, Lookups::ALL, None).await.unwrap();
+
+        assert_eq!(lookup.activity_lookups.load(Ordering::SeqCst), 1);
+        assert_eq!(lookup.accommodation_lookups.load(Ordering::SeqCst), 1);
+        assert_eq!(populated.population_warnings.len(), 2);
+    }
+}
@@ -1,11 +1,17 @@
 pub mod account;
+pub mod api_error;
+pub mod api_token;
 pub mod activity;
+pub mod content_flag;
 pub mod facebook_auth;
+pub mod gift_card;
 pub mod google_auth;
 pub mod interests;
 pub mod itinerary;
 pub mod location;
+pub mod money;
 pub mod search;
 pub mod search_response;
+pub mod security_event;
 pub mod user;
 pub mod bookings;
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+
+/// An amount of money in minor units (cents).
+///
+/// All cost arithmetic happens on integer cents so sums never drift. Amounts are
+/// converted to decimal dollars only at the serialization boundary, so stored
+/// documents and API responses keep their existing `12.34` shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    /// Round a decimal dollar amount to the nearest cent
+    pub fn from_dollars(dollars: f64) -> Self {
+        Money((dollars * 100.0).round() as i64)
+    }
+
+    pub fn cents(self) -> i64 {
+        self.0
+    }
+
+    pub fn to_dollars(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    /// `percent`% of this amount, truncated to the cent (never rounds a refund up)
+    pub fn percent(self, percent: i64) -> Self {
+        Money(self.0 * percent / 100)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        write!(f, "{}{}.{:02}", sign, self.0.abs() / 100, self.0.abs() % 100)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_dollars())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        f64::deserialize(deserializer).map(Money::from_dollars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summation_does_not_drift() {
+        // 19.99 as f32 is 19.9899997..., which drifts when summed as floats
+        let price = Money::from_dollars(19.99_f32 as f64);
+        let total: Money = std::iter::repeat(price).take(1_000).sum();
+        assert_eq!(total, Money::from_cents(1_999_000));
+        assert_eq!(total.to_string(), "19990.00");
+    }
+
+    #[test]
+    fn test_cents_conversion_precision() {
+        assert_eq!(Money::from_dollars(0.1 + 0.2).cents(), 30);
+        assert_eq!(Money::from_dollars(72.5).cents(), 7_250);
+        assert_eq!(Money::from_cents(1_005).to_string(), "10.05");
+        assert_eq!(Money::from_cents(9_999).percent(95).cents(), 9_499);
+    }
+
+    #[test]
+    fn test_serializes_as_dollars() {
+        let json = serde_json::to_value(Money::from_cents(12_345)).unwrap();
+        assert_eq!(json, serde_json::json!(123.45));
+        let back: Money = serde_json::from_value(json).unwrap();
+        assert_eq!(back.cents(), 12_345);
+    }
+}
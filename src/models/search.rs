@@ -15,6 +15,23 @@ pub struct SearchItinerary {
     pub lodging: Option<Vec<String>>,
     pub transportation: Option<String>,
     pub trip_pace: Option<TripPace>,
+    /// Search response shape. `2` lists each activity once in `referenced_activities`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_version: Option<u8>,
+}
+
+/// Minimum a generated day must reach before it is accepted.
+/// A day meets the floor with either enough activities or enough filled hours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayFloor {
+    pub min_activities: usize,
+    pub min_hours: f32,
+}
+
+impl DayFloor {
+    pub fn is_met(&self, activities: usize, hours: f32) -> bool {
+        activities >= self.min_activities || hours >= self.min_hours
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -43,4 +60,43 @@ impl TripPace {
             TripPace::Adventure => 5,  // 4-5 activities per day
         }
     }
+
+    /// Get the built-in minimum floor for a generated day at this pace.
+    /// `DayFloors` holds the floors generation actually uses.
+    pub fn day_floor(&self) -> DayFloor {
+        match self {
+            TripPace::Relaxed => DayFloor { min_activities: 1, min_hours: 2.0 },
+            TripPace::Moderate => DayFloor { min_activities: 2, min_hours: 3.0 },
+            TripPace::Adventure => DayFloor { min_activities: 3, min_hours: 5.0 },
+        }
+    }
+}
+
+/// The floor for each pace. Overridable per pace with
+/// TRIP_PACE_<PACE>_MIN_ACTIVITIES and TRIP_PACE_<PACE>_MIN_HOURS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayFloors {
+    pub relaxed: DayFloor,
+    pub moderate: DayFloor,
+    pub adventure: DayFloor,
+}
+
+impl Default for DayFloors {
+    fn default() -> Self {
+        DayFloors {
+            relaxed: TripPace::Relaxed.day_floor(),
+            moderate: TripPace::Moderate.day_floor(),
+            adventure: TripPace::Adventure.day_floor(),
+        }
+    }
+}
+
+impl DayFloors {
+    pub fn for_pace(&self, pace: &TripPace) -> DayFloor {
+        match pace {
+            TripPace::Relaxed => self.relaxed,
+            TripPace::Moderate => self.moderate,
+            TripPace::Adventure => self.adventure,
+        }
+    }
 }
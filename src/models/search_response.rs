@@ -1,14 +1,172 @@
 use crate::models::activity::Activity;
 use crate::models::itinerary::base::Location;
+use crate::models::money::Money;
+use crate::services::fx_service::DisplayPrice;
+use crate::services::generation_trace::GenerationTrace;
+use crate::services::image_fallback::ImageSource;
 use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// How much of an itinerary a response carries, chosen with `?view=`.
+/// `summary` leaves out days and activities, so no activity lookups are needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItineraryView {
+    #[default]
+    Full,
+    Summary,
+}
+
+/// A part of `GET /itineraries/{id}` that can be asked for with `?include=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DetailSection {
+    Days,
+    Activities,
+    Images,
+    Lodging,
+    Pricing,
+    Map,
+    Stats,
+}
+
+impl DetailSection {
+    pub const ALL: [DetailSection; 7] = [
+        DetailSection::Days,
+        DetailSection::Activities,
+        DetailSection::Images,
+        DetailSection::Lodging,
+        DetailSection::Pricing,
+        DetailSection::Map,
+        DetailSection::Stats,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DetailSection::Days => "days",
+            DetailSection::Activities => "activities",
+            DetailSection::Images => "images",
+            DetailSection::Lodging => "lodging",
+            DetailSection::Pricing => "pricing",
+            DetailSection::Map => "map",
+            DetailSection::Stats => "stats",
+        }
+    }
+
+    /// The response fields the section is made of
+    pub fn fields(self) -> &'static [&'static str] {
+        match self {
+            DetailSection::Days => &["days", "population_warning_count", "population_warnings"],
+            DetailSection::Activities => &["activities"],
+            DetailSection::Images => &["images"],
+            DetailSection::Lodging => &["lodging"],
+            DetailSection::Pricing => &[
+                "person_cost",
+                "activity_cost",
+                "lodging_cost",
+                "transport_cost",
+                "service_fee",
+                "display_price",
+            ],
+            DetailSection::Map => &["start_location", "end_location"],
+            DetailSection::Stats => &["stats"],
+        }
+    }
+}
+
+/// The sections a detail response carries. Fields outside every section (the
+/// id, name, group sizes, length and description) are always there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetailSections(Vec<DetailSection>);
+
+impl DetailSections {
+    pub fn all() -> Self {
+        DetailSections(DetailSection::ALL.to_vec())
+    }
+
+    /// A comma-separated list such as `days,pricing`. Errs with the names that
+    /// aren't sections.
+    pub fn parse(include: &str) -> Result<Self, Vec<String>> {
+        let mut sections = Vec::new();
+        let mut unknown = Vec::new();
+        for name in include.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match DetailSection::ALL.iter().find(|section| section.name().eq_ignore_ascii_case(name)) {
+                Some(section) if !sections.contains(section) => sections.push(*section),
+                Some(_) => {}
+                None => unknown.push(name.to_string()),
+            }
+        }
+        if unknown.is_empty() {
+            Ok(DetailSections(sections))
+        } else {
+            Err(unknown)
+        }
+    }
+
+    pub fn contains(&self, section: DetailSection) -> bool {
+        self.0.contains(&section)
+    }
+
+    /// Whether the itinerary's activities have to be looked up. Images only need
+    /// them when the itinerary has none of its own and falls back to its activities'.
+    pub fn needs_activities(&self, has_own_images: bool) -> bool {
+        self.contains(DetailSection::Days)
+            || self.contains(DetailSection::Activities)
+            || self.contains(DetailSection::Pricing)
+            || (self.contains(DetailSection::Images) && !has_own_images)
+    }
+
+    pub fn needs_accommodations(&self) -> bool {
+        self.contains(DetailSection::Days)
+            || self.contains(DetailSection::Lodging)
+            || self.contains(DetailSection::Pricing)
+    }
+
+    /// Drop the fields of sections that weren't asked for
+    pub fn project(&self, response: &mut serde_json::Map<String, serde_json::Value>) {
+        for section in DetailSection::ALL {
+            if !self.contains(section) {
+                for field in section.fields() {
+                    response.remove(*field);
+                }
+            }
+        }
+    }
+}
+
+/// Order of search results, chosen with `?sort=`. The price orders leave out
+/// itineraries whose price is unavailable rather than ranking them as free.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultOrder {
+    #[default]
+    Relevance,
+    PriceAsc,
+    PriceDesc,
+}
+
+impl ResultOrder {
+    pub fn apply(self, items: &mut Vec<SearchResponseItem>) {
+        if self == ResultOrder::Relevance {
+            return;
+        }
+        items.retain(|item| item.person_cost.is_some());
+        items.sort_by_key(|item| item.person_cost);
+        if self == ResultOrder::PriceDesc {
+            items.reverse();
+        }
+    }
+}
+
 /// Custom response format for search results with populated activities
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResponseItem {
-    #[serde(rename = "_id")]
-    pub id: ObjectId,
+    /// Persisted itinerary id. Absent only for ephemeral (unsaved) itineraries.
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// True when the itinerary could not be persisted and cannot be favorited or booked
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
     pub fareharbor_id: Option<String>,
     pub trip_name: String,
     pub min_age: Option<u32>,
@@ -20,16 +178,43 @@ pub struct SearchResponseItem {
     pub end_location: Location,
     pub description: String,
     pub images: Vec<String>,
+    /// Set when the itinerary has no images and `images` holds a stand-in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_source: Option<ImageSource>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<DateTime>,
-    pub days: HashMap<String, Vec<PopulatedDayItem>>,
-    pub activities: Vec<ActivitySummary>,
+    /// Populated days, listed in day order. Omitted in the summary view.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::models::itinerary::base::ordered_days"
+    )]
+    pub days: Option<HashMap<String, Vec<PopulatedDayItem>>>,
+    /// Per-occurrence activity summaries. Omitted in the v2 shape, where day items carry
+    /// titles, and in the summary view.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activities: Option<Vec<ActivitySummary>>,
+    /// Canonical per-person price in USD. Absent when it's unavailable, never zero.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub person_cost: Option<Money>,
+    /// True when `person_cost` was estimated from the activities because none was stored
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub price_estimated: bool,
+    /// `person_cost` in the viewer's currency, display only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_price: Option<DisplayPrice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub match_score: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub score_breakdown: Option<serde_json::Value>,
+    /// Generation decisions, only present when requested via `X-Generation-Trace`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation_trace: Option<GenerationTrace>,
+    /// Day items left out for missing activities or accommodations, only with `?verbose=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub population_warning_count: Option<usize>,
 }
 
 /// Day item with simplified activity data
@@ -40,6 +225,9 @@ pub enum PopulatedDayItem {
     Activity {
         time: String,
         activity_id: ObjectId,
+        /// Only set in the v2 shape
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
     },
     #[serde(rename = "transportation")]
     Transportation {
@@ -67,6 +255,368 @@ impl PopulatedDayItem {
         PopulatedDayItem::Activity {
             time,
             activity_id,
+            title: None,
+        }
+    }
+}
+
+/// What a search result card shows of an activity, listed once per response in
+/// `referenced_activities`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferencedActivity {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub price_per_person: f32,
+    pub duration_minutes: u16,
+    /// The activity's first image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+impl From<&Activity> for ReferencedActivity {
+    fn from(activity: &Activity) -> Self {
+        ReferencedActivity {
+            title: activity.title.clone(),
+            tags: activity.tags.clone(),
+            price_per_person: activity.price_per_person,
+            duration_minutes: activity.duration_minutes,
+            image: activity.images.first().cloned(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Compact search response, returned when the request sets `response_version: 2`.
+///
+/// Every activity used by any itinerary appears once in `referenced_activities`
+/// (keyed by hex id); day items carry only the id, time and title.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResponseV2 {
+    pub response_version: u8,
+    pub itineraries: Vec<SearchResponseItem>,
+    pub referenced_activities: HashMap<String, ReferencedActivity>,
+}
+
+impl SearchResponseV2 {
+    pub fn from_items(
+        mut items: Vec<SearchResponseItem>,
+        activities: &HashMap<ObjectId, Activity>,
+    ) -> Self {
+        let mut referenced_activities = HashMap::new();
+
+        for item in &mut items {
+            item.activities = None;
+            for day_items in item.days.iter_mut().flat_map(|days| days.values_mut()) {
+                for day_item in day_items.iter_mut() {
+                    if let PopulatedDayItem::Activity {
+                        activity_id, title, ..
+                    } = day_item
+                    {
+                        if let Some(activity) = activities.get(activity_id) {
+                            *title = Some(activity.title.clone());
+                            referenced_activities
+                                .entry(activity_id.to_hex())
+                                .or_insert_with(|| ReferencedActivity::from(activity));
+                        }
+                    }
+                }
+            }
+        }
+
+        SearchResponseV2 {
+            response_version: 2,
+            itineraries: items,
+            referenced_activities,
+        }
+    }
+}
+
+/// Search results under `/v2`: the compact results in `data`, every activity they
+/// schedule once in `referenced_activities`, and counts in `meta`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchEnvelope {
+    pub data: Vec<SearchResponseItem>,
+    pub referenced_activities: HashMap<String, ReferencedActivity>,
+    pub meta: SearchMeta,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMeta {
+    pub count: usize,
+    /// Search weight experiment the results were scored under; null for the default weights
+    #[serde(default)]
+    pub experiment: Option<String>,
+}
+
+impl SearchEnvelope {
+    pub fn from_items(
+        items: Vec<SearchResponseItem>,
+        activities: &HashMap<ObjectId, Activity>,
+        experiment: Option<&str>,
+    ) -> Self {
+        let compact = SearchResponseV2::from_items(items, activities);
+        SearchEnvelope {
+            meta: SearchMeta {
+                count: compact.itineraries.len(),
+                experiment: experiment.map(str::to_string),
+            },
+            data: compact.itineraries,
+            referenced_activities: compact.referenced_activities,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity::{Address, Capacity};
+
+    fn activity(title: &str) -> Activity {
+        Activity {
+            company: "Rocky Mountain Adventures".to_string(),
+            company_id: "rma".to_string(),
+            booking_link: "https://example.com/book/rocky-mountain-adventures".to_string(),
+            description: "A half-day guided trip through the canyon with lunch, safety briefing \
+                and all equipment provided. Suitable for first-timers and families."
+                .to_string(),
+            activity_types: vec!["Outdoor".to_string(), "Water".to_string()],
+            tags: vec!["family".to_string(), "guided".to_string()],
+            price_per_person: 89.0,
+            duration_minutes: 240,
+            address: Address {
+                street: "1000 Whitewater Way".to_string(),
+                unit: "".to_string(),
+                city: "Buena Vista".to_string(),
+                state: "CO".to_string(),
+                zip: "81211".to_string(),
+                country: "USA".to_string(),
+            },
+            whats_included: vec![
+                "Wetsuit".to_string(),
+                "Helmet".to_string(),
+                "Lunch".to_string(),
+                "Transportation from the outpost".to_string(),
+            ],
+            age_requirement: Some(8),
+            capacity: Capacity {
+                minimum: 1,
+                maximum: 12,
+            },
+            ..Activity::test(title)
+        }
+    }
+
+    fn item_with(days: HashMap<String, Vec<PopulatedDayItem>>) -> SearchResponseItem {
+        SearchResponseItem {
+            id: Some(ObjectId::new()),
+            ephemeral: false,
+            fareharbor_id: None,
+            trip_name: "Arkansas River Weekend".to_string(),
+            min_age: None,
+            min_group: 1,
+            max_group: 8,
+            length_days: 3,
+            length_hours: 72,
+            start_location: Location::default(),
+            end_location: Location::default(),
+            description: "".to_string(),
+            images: vec![],
+            image_source: None,
+            created_at: None,
+            updated_at: None,
+            days: Some(days),
+            activities: Some(vec![]),
+            person_cost: None,
+            price_estimated: false,
+            display_price: None,
+            match_score: Some(80),
+            score_breakdown: None,
+            generation_trace: None,
+            population_warning_count: None,
+        }
+    }
+
+    /// Five itineraries that each schedule the same three activities on every day,
+    /// with the per-occurrence summaries `transform_to_search_response` adds for v1
+    fn fixture() -> (Vec<SearchResponseItem>, HashMap<ObjectId, Activity>) {
+        let activities: Vec<Activity> = ["Rafting", "Zipline", "Hot Springs"]
+            .iter()
+            .map(|title| activity(title))
+            .collect();
+        let items = (0..5)
+            .map(|_| {
+                let days = (1..=3)
+                    .map(|day| {
+                        let day_items = activities
+                            .iter()
+                            .map(|a| {
+                                PopulatedDayItem::from_activity(
+                                    "09:00:00".to_string(),
+                                    a.id.unwrap(),
+                                    a.clone(),
+                                )
+                            })
+                            .collect();
+                        (day.to_string(), day_items)
+                    })
+                    .collect();
+                let mut item = item_with(days);
+                item.activities = Some(
+                    (1..=3)
+                        .flat_map(|_| &activities)
+                        .map(|a| ActivitySummary {
+                            time: "09:00:00".to_string(),
+                            label: a.title.clone(),
+                            tags: a.tags.clone(),
+                        })
+                        .collect(),
+                );
+                item
+            })
+            .collect();
+        let map = activities.into_iter().map(|a| (a.id.unwrap(), a)).collect();
+        (items, map)
+    }
+
+    /// The whole v2 body, referenced activities included, against what the v1
+    /// handler sends for the same results
+    #[test]
+    fn test_v2_responses_are_smaller_than_v1() {
+        let (items, activities) = fixture();
+        let v1 = serde_json::to_string(&items).unwrap().len();
+        let v2 = serde_json::to_string(&SearchResponseV2::from_items(items, &activities))
+            .unwrap()
+            .len();
+        let (items, activities) = fixture();
+        let envelope = serde_json::to_string(&SearchEnvelope::from_items(items, &activities, None))
+            .unwrap()
+            .len();
+
+        for (shape, bytes) in [("v2", v2), ("/v2 envelope", envelope)] {
+            assert!(bytes * 100 < v1 * 85, "{} is {} bytes, v1 is {} bytes", shape, bytes, v1);
+        }
+    }
+
+    #[test]
+    fn test_v2_day_items_reference_known_activities() {
+        let (items, activities) = fixture();
+        let v2 = SearchResponseV2::from_items(items, &activities);
+
+        assert_eq!(v2.referenced_activities.len(), 3);
+        for item in &v2.itineraries {
+            assert!(item.activities.is_none());
+            for day_item in item.days.iter().flat_map(|days| days.values()).flatten() {
+                if let PopulatedDayItem::Activity { activity_id, title, .. } = day_item {
+                    let referenced = &v2.referenced_activities[&activity_id.to_hex()];
+                    assert_eq!(title.as_deref(), Some(referenced.title.as_str()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_days_are_listed_in_numeric_order() {
+        let (mut items, _) = fixture();
+        let activity_id = ObjectId::new();
+        let days: HashMap<String, Vec<PopulatedDayItem>> = (1..=12)
+            .map(|day| {
+                let item = PopulatedDayItem::Activity {
+                    time: "09:00:00".to_string(),
+                    activity_id,
+                    title: None,
+                };
+                (day.to_string(), vec![item])
+            })
+            .collect();
+        items[0].days = Some(days);
+
+        let value = serde_json::to_value(&items[0]).unwrap();
+        let order: Vec<u64> = value["days"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|day| day["day"].as_u64().unwrap())
+            .collect();
+        assert_eq!(order, (1..=12).collect::<Vec<u64>>());
+
+        // And it reads back into the same map
+        let read: SearchResponseItem = serde_json::from_value(value).unwrap();
+        assert_eq!(read.days.unwrap().len(), 12);
+    }
+
+    #[test]
+    fn test_price_order_leaves_out_unpriced_itineraries() {
+        let (mut items, _) = fixture();
+        let prices = [Some(900.0), None, Some(450.0), None, Some(600.0)];
+        for (item, price) in items.iter_mut().zip(prices) {
+            item.person_cost = price.map(Money::from_dollars);
+            item.trip_name = price.map_or("unpriced".to_string(), |price| price.to_string());
+        }
+        // Estimated prices sort like stored ones
+        items[4].price_estimated = true;
+        let names = |items: &[SearchResponseItem]| -> Vec<String> {
+            items.iter().map(|item| item.trip_name.clone()).collect()
+        };
+
+        ResultOrder::Relevance.apply(&mut items);
+        assert_eq!(names(&items), vec!["900", "unpriced", "450", "unpriced", "600"]);
+
+        ResultOrder::PriceAsc.apply(&mut items);
+        assert_eq!(names(&items), vec!["450", "600", "900"]);
+        ResultOrder::PriceDesc.apply(&mut items);
+        assert_eq!(names(&items), vec!["900", "600", "450"]);
+    }
+
+    #[test]
+    fn test_include_lists_sections_and_rejects_unknown_ones() {
+        let sections = DetailSections::parse("images, Pricing,images").unwrap();
+        assert_eq!(
+            sections,
+            DetailSections(vec![DetailSection::Images, DetailSection::Pricing])
+        );
+        assert_eq!(
+            DetailSections::parse("days,reviews,hotels"),
+            Err(vec!["reviews".to_string(), "hotels".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_only_sections_needing_them_look_up_references() {
+        let images = DetailSections::parse("images").unwrap();
+        assert!(!images.needs_activities(true));
+        assert!(images.needs_activities(false));
+        assert!(!images.needs_accommodations());
+
+        let lodging = DetailSections::parse("lodging,map,stats").unwrap();
+        assert!(!lodging.needs_activities(false));
+        assert!(lodging.needs_accommodations());
+        assert!(DetailSections::all().needs_activities(true));
+    }
+
+    #[test]
+    fn test_projection_keeps_core_fields_and_requested_sections() {
+        let mut response = serde_json::json!({
+            "_id": "abc",
+            "trip_name": "Trip",
+            "length_days": 3,
+            "images": ["a.jpg"],
+            "days": [],
+            "activities": [],
+            "person_cost": 120.0,
+            "service_fee": 12.0,
+            "start_location": {},
+        });
+        DetailSections::parse("images,pricing")
+            .unwrap()
+            .project(response.as_object_mut().unwrap());
+        assert_eq!(
+            response,
+            serde_json::json!({
+                "_id": "abc",
+                "trip_name": "Trip",
+                "length_days": 3,
+                "images": ["a.jpg"],
+                "person_cost": 120.0,
+                "service_fee": 12.0,
+            })
+        );
+    }
+}
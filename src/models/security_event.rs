@@ -0,0 +1,30 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityEventType {
+    SigninSuccess,
+    SigninFailed,
+    PasswordChanged,
+    EmailChanged,
+    PaymentMethodAdded,
+    TokenRefreshedFromNewDevice,
+    /// An OAuth provider was linked after the user proved they own the account
+    OauthLinked,
+    /// A wrong password or code while linking an OAuth provider
+    OauthLinkFailed,
+}
+
+/// Security-relevant account activity, kept for 90 days (TTL index on `timestamp`).
+/// The user agent is stored only as a hash, which is enough to recognise a device.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SecurityEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub event_type: SecurityEventType,
+    pub timestamp: DateTime,
+    pub ip: Option<String>,
+    pub user_agent_hash: Option<String>,
+}
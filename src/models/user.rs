@@ -13,6 +13,9 @@ pub struct UserSession {
     pub customer_id: Option<String>,
     pub role: Option<UserRole>,
     pub created_at: DateTime<Utc>,
+    /// The admin id, while support is signed in as this user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -21,6 +24,9 @@ pub struct Newsletter {
     pub id: Option<ObjectId>,
     pub email: String,
     pub subscribed: Option<bool>,
+    /// Set when the address hard-bounced or reported spam
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub email_suppressed: bool,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
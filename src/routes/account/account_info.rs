@@ -1,31 +1,74 @@
 use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use bson::{doc, oid::ObjectId};
 use futures::{StreamExt, TryStreamExt};
-use google_cloud_storage::client::{Client as GcsClient, ClientConfig};
-use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest, UploadType};
 use mongodb::Client;
-use std::{env, str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc};
 
 use crate::{
+    config::AppConfig,
     middleware::auth::Claims,
+    routes::account::owner_only,
     models::account::{PersonalInformation, User},
+    models::security_event::SecurityEventType,
+    services::fx_service::is_supported_currency,
+    services::phone::normalize_phone,
+    services::account_service::EmailService,
+    services::email_verification_service::EmailVerificationService,
+    services::security_event_service::{ClientFingerprint, SecurityEventQueue},
+    services::payment_teardown::{CustomerDisposition, CustomerVault, PaymentTeardownService, TeardownOutcome},
+    services::storage::{BucketKind, Storage, StorageError},
+    services::trip_notes_service::TripNotesService,
+    db::mongo::primary_collection,
+    models::bookings::BookingDetails,
 };
 
+/// Security events an update will produce, worked out before it is applied
+fn credential_changes(user: &User, info: &PersonalInformation) -> Vec<SecurityEventType> {
+    let mut events = Vec::new();
+    if info.email.as_ref().is_some_and(|email| *email != user.email) {
+        events.push(SecurityEventType::EmailChanged);
+    }
+    if info.password.is_some() {
+        events.push(SecurityEventType::PasswordChanged);
+    }
+    events
+}
+
 pub async fn update_personal_information(
+    req: HttpRequest,
     data: web::Data<Arc<Client>>,
+    security_events: web::Data<SecurityEventQueue>,
+    config: web::Data<AppConfig>,
     claims: Claims,
     path: web::Path<(String,)>,
     input: web::Json<PersonalInformation>,
 ) -> impl Responder {
     let user_id = path.into_inner().0;
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = data.into_inner();
 
     let personal_info = input.into_inner();
+    // Validate the phone number before touching the user; an empty string clears it
+    let phone = match personal_info.phone_number.as_deref().map(str::trim) {
+        None => None,
+        Some("") => Some(None),
+        Some(number) => match normalize_phone(number, config.default_phone_region) {
+            Ok(phone) => Some(Some(phone)),
+            Err(e) => {
+                return HttpResponse::BadRequest()
+                    .json(serde_json::json!({ "error": e.to_string(), "field": "phone_number" }))
+            }
+        },
+    };
+    if let Some(currency) = &personal_info.preferred_currency {
+        if !is_supported_currency(currency) {
+            return HttpResponse::BadRequest().body("Unsupported preferred_currency");
+        }
+    }
 
     let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
 
@@ -41,10 +84,13 @@ pub async fn update_personal_information(
         Err(_) => return HttpResponse::InternalServerError().body("Failed to find user"),
     };
 
+    let credential_events = credential_changes(&user, &personal_info);
+
     // Directly update top-level fields if provided in input
-    if let Some(email) = personal_info.email {
-        user.email = email;
-    }
+    let email_changed = match personal_info.email {
+        Some(email) => user.set_email(email),
+        None => false,
+    };
     if let Some(password) = personal_info.password {
         user.password =
             bcrypt::hash(&password, bcrypt::DEFAULT_COST).unwrap_or(user.password.clone());
@@ -55,12 +101,19 @@ pub async fn update_personal_information(
     if let Some(last_name) = personal_info.last_name {
         user.last_name = Some(last_name);
     }
-    if let Some(phone_number) = personal_info.phone_number {
-        user.phone_number = Some(phone_number);
+    if let Some(phone) = phone {
+        user.phone_number_e164 = phone.as_ref().map(|phone| phone.e164.clone());
+        user.phone_number = phone.map(|phone| phone.display);
     }
     if let Some(birth_date) = personal_info.birth_date {
         user.birth_date = Some(birth_date);
     }
+    if let Some(preferred_currency) = personal_info.preferred_currency {
+        user.preferred_currency = Some(preferred_currency.to_uppercase());
+    }
+    if let Some(preferred_units) = personal_info.preferred_units {
+        user.preferred_units = Some(preferred_units);
+    }
 
     user.updated_at = Some(chrono::Utc::now());
     // let mut info = input.into_inner();
@@ -71,6 +124,17 @@ pub async fn update_personal_information(
 
     match collection.update_one(filter, update_doc).await {
         Ok(result) if result.modified_count > 0 => {
+            if let Some(user_id) = user.id {
+                let fingerprint = ClientFingerprint::from_request(&req);
+                for event_type in credential_events {
+                    security_events.record(user_id, event_type, &fingerprint);
+                }
+            }
+            if email_changed && config.email_change_sends_verification {
+                EmailVerificationService::new(client.as_ref().clone())
+                    .email_changed(&user, &EmailService::new(client.as_ref().clone(), &config.email).ok())
+                    .await;
+            }
             return HttpResponse::Ok().body("User information updated");
         }
         Ok(_) => HttpResponse::NotModified().body("No changes applied"),
@@ -80,14 +144,161 @@ pub async fn update_personal_information(
     }
 }
 
+/// Delete an account, tearing down its Stripe customer first. A Stripe failure is
+/// queued for retry rather than stopping the deletion. `None` when there's no
+/// such user.
+pub async fn delete_account_with(
+    client: Arc<Client>,
+    vault: &impl CustomerVault,
+    user_id: ObjectId,
+    disposition: CustomerDisposition,
+) -> Result<Option<TeardownOutcome>, mongodb::error::Error> {
+    let users: mongodb::Collection<User> = client.database("Account").collection("Users");
+    let Some(user) = users.find_one(doc! { "_id": user_id }).await? else {
+        return Ok(None);
+    };
+
+    let payments = PaymentTeardownService::new(client.clone())
+        .close(vault, user_id, user.customer_id.as_deref(), disposition)
+        .await?;
+
+    client
+        .database("Account")
+        .collection::<bson::Document>("ApiTokens")
+        .delete_many(doc! { "user_id": user_id })
+        .await?;
+    TripNotesService::new(client.clone()).delete_for_user(user_id).await?;
+    users.delete_one(doc! { "_id": user_id }).await?;
+    Ok(Some(payments))
+}
+
+/*
+    /api/account/{id}
+
+    Deletes the account. Saved cards are detached from the Stripe customer, which
+    is then deleted (or marked, per `STRIPE_CUSTOMER_ON_DELETE`). Bookings stay
+    for the operators' records; the traveler's trip notes on them are deleted.
+*/
+pub async fn delete_account(
+    data: web::Data<Arc<Client>>,
+    stripe_data: web::Data<Arc<stripe::Client>>,
+    config: web::Data<AppConfig>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+) -> impl Responder {
+    let user_id = path.into_inner().0;
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let Ok(object_id) = ObjectId::parse_str(&user_id) else {
+        return HttpResponse::BadRequest().body("Invalid user ID format");
+    };
+
+    match delete_account_with(
+        data.into_inner().as_ref().clone(),
+        stripe_data.as_ref().as_ref(),
+        object_id,
+        config.stripe_customer_on_delete,
+    )
+    .await
+    {
+        Ok(Some(payments)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Account deleted",
+            "payments": payments
+        })),
+        Ok(None) => HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Failed to delete account {}: {}", user_id, e);
+            HttpResponse::InternalServerError().body("Failed to delete account")
+        }
+    }
+}
+
+/*
+    /api/account/{id}/export
+
+    Everything the account holds, for the traveler to keep:
+    { "account": {...}, "bookings": [...], "trip_notes": [...] }. The password
+    hash isn't included. Trip notes are private, so they're left out when an
+    admin is impersonating the traveler.
+*/
+pub async fn export_account_data(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+) -> impl Responder {
+    let user_id = path.into_inner().0;
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let Ok(object_id) = ObjectId::parse_str(&user_id) else {
+        return HttpResponse::BadRequest().body("Invalid user ID format");
+    };
+    let client = data.into_inner();
+
+    let user = match client
+        .database("Account")
+        .collection::<User>("Users")
+        .find_one(doc! { "_id": object_id })
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            eprintln!("Failed to export account {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().body("Failed to export account");
+        }
+    };
+    let mut account = serde_json::to_value(&user).unwrap_or_default();
+    if let Some(account) = account.as_object_mut() {
+        account.remove("password");
+    }
+
+    let bookings: Vec<BookingDetails> = match primary_collection::<BookingDetails>(&client, "Account", "Bookings")
+        .find(doc! { "user_id": object_id })
+        .sort(doc! { "_id": 1 })
+        .await
+    {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(bookings) => bookings,
+            Err(e) => {
+                eprintln!("Failed to export bookings for {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().body("Failed to export account");
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to export bookings for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().body("Failed to export account");
+        }
+    };
+
+    let mut export = serde_json::json!({
+        "account": account,
+        "bookings": bookings,
+    });
+    if claims.impersonator().is_none() {
+        match TripNotesService::new(client.as_ref().clone()).for_user(object_id).await {
+            Ok(notes) => {
+                export["trip_notes"] = notes.iter().map(|notes| notes.to_json()).collect();
+            }
+            Err(e) => {
+                eprintln!("Failed to export trip notes for {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().body("Failed to export account");
+            }
+        }
+    }
+    HttpResponse::Ok().json(export)
+}
+
 pub async fn get_personal_information(
     data: web::Data<Arc<Client>>,
     claims: Claims,
     path: web::Path<(String,)>,
 ) -> impl Responder {
     let user_id = path.into_inner().0;
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
     let client = data.into_inner();
     let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
@@ -103,6 +314,7 @@ pub async fn get_personal_information(
 
 pub async fn upload_profile_pic(
     data: web::Data<Arc<Client>>,
+    app_config: web::Data<AppConfig>,
     claims: Claims,
     path: web::Path<(String,)>,
     mut payload: Multipart,
@@ -110,30 +322,20 @@ pub async fn upload_profile_pic(
     let user_id = path.into_inner().0;
 
     // Check authorization - user can only update their own profile
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = data.into_inner();
-    let bucket_name = env::var("PROFILE_PIC_BUCKET").expect("PROFILE_PIC_BUCKET must be set");
-    let cloud_storage_url = env::var("CLOUD_STORAGE_URL")
-        .unwrap_or_else(|_| "https://storage.googleapis.com".to_string());
-
-    // Initialize Google Cloud Storage client
-    let client_config = match ClientConfig::default().with_auth().await {
-        Ok(config) => config,
-        Err(e) => {
-            eprintln!("Failed to initialize GCS client config: {}", e);
-            return HttpResponse::InternalServerError()
-                .body("Failed to initialize cloud storage client");
-        }
-    };
-
-    let gcs_client = GcsClient::new(client_config);
+    let storage = Storage::new(app_config.storage.clone());
+    if let Err(e) = storage.bucket(BucketKind::ProfilePictures) {
+        eprintln!("{}, rejecting profile picture upload", e);
+        return HttpResponse::InternalServerError().body("Profile picture uploads are not configured");
+    }
 
     // Process the multipart form data
     let mut file_bytes: Option<Vec<u8>> = None;
-    let mut file_name: Option<String> = None;
+    let mut extension: Option<String> = None;
     let mut content_type: Option<String> = None;
 
     while let Ok(Some(mut field)) = payload.try_next().await {
@@ -156,18 +358,15 @@ pub async fn upload_profile_pic(
             // Get the filename from the Content-Disposition header
             if let Some(filename) = content_disp.get_filename() {
                 // Determine file extension from the filename
-                let extension = std::path::Path::new(filename)
+                let file_extension = std::path::Path::new(filename)
                     .extension()
                     .and_then(|ext| ext.to_str())
                     .unwrap_or("jpg")
                     .to_lowercase();
 
-                // Set the filename based on the extension
-                file_name = Some(format!("profile-pic.{}", extension));
-
                 // Set content type based on extension
                 content_type = Some(
-                    match extension.as_str() {
+                    match file_extension.as_str() {
                         "jpg" | "jpeg" => "image/jpeg",
                         "png" => "image/png",
                         "gif" => "image/gif",
@@ -175,6 +374,7 @@ pub async fn upload_profile_pic(
                     }
                     .to_string(),
                 );
+                extension = Some(file_extension);
             }
 
             // Read the file data
@@ -199,42 +399,22 @@ pub async fn upload_profile_pic(
     }
 
     // Check if we have a file to upload
-    if file_bytes.is_none() || file_name.is_none() {
+    let (Some(file_data), Some(extension)) = (file_bytes, extension) else {
         return HttpResponse::BadRequest().body("No file uploaded or invalid file");
-    }
-
-    let file_data = file_bytes.unwrap();
-    let file_name = file_name.unwrap();
-    let content_type = content_type.unwrap_or_else(|| "application/octet-stream".to_string());
-
-    // Create the folder path in the bucket (using user_id as folder)
-    let storage_path = format!("{}/{}", user_id, file_name);
-
-    // Construct the URL to the file
-    let file_url = format!("{}/{}/{}", cloud_storage_url, bucket_name, storage_path);
-
-    // Upload the file to Google Cloud Storage
-    let upload_request = UploadObjectRequest {
-        bucket: bucket_name.clone(),
-        // name: storage_path.clone(),
-        // content_type: Some(content_type.clone()),
-        ..Default::default()
     };
+    let content_type = content_type.unwrap_or_else(|| "application/octet-stream".to_string());
 
-    let media = Media::new(file_name);
-
-    let upload_type = UploadType::Simple(media);
-
-    match gcs_client
-        .upload_object(&upload_request, file_data, &upload_type)
+    // Upload into the user's folder of the profile picture bucket
+    match storage
+        .upload_profile_picture(&user_id, &extension, &content_type, file_data)
         .await
     {
-        Ok(_) => {
+        Ok(stored) => {
             // Update the user record with the profile picture URL
             let collection: mongodb::Collection<User> =
                 client.database("Account").collection("Users");
             let filter = doc! { "_id": ObjectId::from_str(&user_id).unwrap() };
-            let update = doc! { "$set": { "profile_picture": file_url.clone() } };
+            let update = doc! { "$set": { "profile_picture": stored.object_url } };
 
             match collection.update_one(filter, update).await {
                 Ok(_) => {
@@ -242,7 +422,7 @@ pub async fn upload_profile_pic(
                     return HttpResponse::Ok().json(serde_json::json!({
                         "success": true,
                         "message": "Profile picture updated successfully",
-                        "profile_picture_url": file_url
+                        "profile_picture_url": stored.url
                     }));
                 }
                 Err(e) => {
@@ -252,6 +432,7 @@ pub async fn upload_profile_pic(
                 }
             }
         }
+        Err(e @ StorageError::TooLarge { .. }) => HttpResponse::PayloadTooLarge().body(e.to_string()),
         Err(e) => {
             eprintln!("Failed to upload file to cloud storage: {}", e);
             return HttpResponse::InternalServerError()
@@ -259,3 +440,47 @@ pub async fn upload_profile_pic(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User::test("traveler@example.com")
+    }
+
+    fn info(email: Option<&str>, password: Option<&str>) -> PersonalInformation {
+        serde_json::from_value(serde_json::json!({
+            "email": email,
+            "password": password,
+            "first_name": "Sam",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_credential_changes_record_email_and_password_events() {
+        assert_eq!(
+            credential_changes(&user(), &info(Some("new@example.com"), Some("hunter2"))),
+            vec![SecurityEventType::EmailChanged, SecurityEventType::PasswordChanged]
+        );
+        // Re-submitting the current email or only editing a name isn't a credential change
+        assert!(credential_changes(&user(), &info(Some("traveler@example.com"), None)).is_empty());
+        assert!(credential_changes(&user(), &info(None, None)).is_empty());
+    }
+
+    #[test]
+    fn test_changing_email_clears_verification() {
+        let mut verified = user();
+        verified.email_verified = true;
+        verified.email_verified_at = Some(chrono::Utc::now());
+
+        assert!(!verified.set_email("traveler@example.com".to_string()));
+        assert!(verified.email_verified);
+
+        assert!(verified.set_email("new@example.com".to_string()));
+        assert_eq!(verified.email, "new@example.com");
+        assert!(!verified.email_verified);
+        assert!(verified.email_verified_at.is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::Client;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::routes::account::owner_only;
+use crate::models::api_token::{ApiTokenInput, ApiTokenSummary, TokenScope};
+use crate::services::api_token_service::{new_api_token, ApiTokenService};
+
+const MAX_API_TOKENS_PER_USER: u64 = 20;
+const MAX_TOKEN_NAME_CHARS: usize = 100;
+
+/// Checked name, de-duplicated scopes and expiry for a new token
+fn validate_token_input(
+    input: &ApiTokenInput,
+    now: DateTime,
+) -> Result<(String, Vec<TokenScope>, Option<DateTime>), String> {
+    let name = input.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_CHARS {
+        return Err(format!("name must be 1-{} characters", MAX_TOKEN_NAME_CHARS));
+    }
+
+    let mut scopes: Vec<TokenScope> = Vec::new();
+    for scope in &input.scopes {
+        if !scopes.contains(scope) {
+            scopes.push(*scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err("at least one scope is required".to_string());
+    }
+
+    let expires_at = input
+        .expires_at
+        .map(|expires_at| DateTime::from_millis(expires_at.timestamp_millis()));
+    if expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err("expires_at must be in the future".to_string());
+    }
+    Ok((name.to_string(), scopes, expires_at))
+}
+
+fn owner_id(claims: &Claims, user_id: &str) -> Result<ObjectId, Box<HttpResponse>> {
+    owner_only(claims, user_id)?;
+    ObjectId::parse_str(user_id)
+        .map_err(|_| Box::new(HttpResponse::BadRequest().json(json!({"error": "Invalid user ID"}))))
+}
+
+/*
+    /api/account/{id}/api-tokens
+    The token is only ever returned here; store it now.
+*/
+pub async fn create_api_token(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+    input: web::Json<ApiTokenInput>,
+) -> impl Responder {
+    let user_id = match owner_id(&claims, &path.into_inner().0) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+    let now = DateTime::now();
+    let (name, scopes, expires_at) = match validate_token_input(&input, now) {
+        Ok(valid) => valid,
+        Err(e) => return HttpResponse::BadRequest().json(json!({"error": e})),
+    };
+
+    let service = ApiTokenService::new(data.into_inner().as_ref().clone());
+    match service.count_active(user_id).await {
+        Ok(count) if count >= MAX_API_TOKENS_PER_USER => {
+            return HttpResponse::Conflict().json(json!({
+                "error": format!("You can have at most {} API tokens. Revoke one first.", MAX_API_TOKENS_PER_USER)
+            }));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Failed to count API tokens: {:?}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to create API token"}));
+        }
+    }
+
+    let (token, api_token) = new_api_token(user_id, &name, scopes, expires_at, now);
+    if let Err(e) = service.insert(&api_token).await {
+        eprintln!("Failed to store API token: {:?}", e);
+        return HttpResponse::InternalServerError().json(json!({"error": "Failed to create API token"}));
+    }
+
+    println!("🔑 API token {} created for user {}", api_token.prefix, user_id);
+    HttpResponse::Created().json(json!({
+        "token": token,
+        "api_token": ApiTokenSummary::from(&api_token),
+    }))
+}
+
+/*
+    /api/account/{id}/api-tokens
+*/
+pub async fn list_api_tokens(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+) -> impl Responder {
+    let user_id = match owner_id(&claims, &path.into_inner().0) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+
+    let service = ApiTokenService::new(data.into_inner().as_ref().clone());
+    match service.list(user_id).await {
+        Ok(tokens) => HttpResponse::Ok().json(json!({
+            "api_tokens": tokens.iter().map(ApiTokenSummary::from).collect::<Vec<_>>(),
+        })),
+        Err(e) => {
+            eprintln!("Failed to list API tokens: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to list API tokens"}))
+        }
+    }
+}
+
+/*
+    /api/account/{id}/api-tokens/{token_id}
+*/
+pub async fn revoke_api_token(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (user_id, token_id) = path.into_inner();
+    let user_id = match owner_id(&claims, &user_id) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+    let Ok(token_id) = ObjectId::parse_str(&token_id) else {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid token ID"}));
+    };
+
+    let service = ApiTokenService::new(data.into_inner().as_ref().clone());
+    match service.revoke(user_id, token_id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({"status": "success", "message": "API token revoked"})),
+        Ok(false) => HttpResponse::NotFound().json(json!({"error": "API token not found"})),
+        Err(e) => {
+            eprintln!("Failed to revoke API token: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to revoke API token"}))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(value: serde_json::Value) -> ApiTokenInput {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_token_input_validation() {
+        let now = DateTime::from_millis(1_760_000_000_000);
+        let (name, scopes, expires_at) = validate_token_input(
+            &input(json!({
+                "name": "  nightly export ",
+                "scopes": ["bookings:read", "search:read", "bookings:read"],
+                "expires_at": "2026-01-01T00:00:00Z",
+            })),
+            now,
+        )
+        .unwrap();
+        assert_eq!(name, "nightly export");
+        assert_eq!(scopes, vec![TokenScope::BookingsRead, TokenScope::SearchRead]);
+        assert!(expires_at.is_some());
+
+        assert!(validate_token_input(&input(json!({"name": " ", "scopes": ["search:read"]})), now).is_err());
+        assert!(validate_token_input(&input(json!({"name": "x", "scopes": []})), now).is_err());
+        assert!(validate_token_input(
+            &input(json!({"name": "x", "scopes": ["search:read"], "expires_at": "2020-01-01T00:00:00Z"})),
+            now
+        )
+        .is_err());
+
+        // Write scopes don't exist, so they can't be requested
+        assert!(serde_json::from_value::<ApiTokenInput>(json!({"name": "x", "scopes": ["bookings:write"]})).is_err());
+    }
+}
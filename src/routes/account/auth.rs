@@ -1,4 +1,4 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use chrono::{Duration, Utc};
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use mongodb::bson::doc;
@@ -8,16 +8,26 @@ use mongodb::Client;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::middleware::auth::Claims;
+use crate::config::AppConfig;
+use crate::middleware::auth::{AuthMiddleware, Claims};
 use crate::models::account::{User, UserRole};
+use crate::models::security_event::SecurityEventType;
 use crate::models::user::{Newsletter, UserSession};
+use crate::services::security_event_service::{ClientFingerprint, SecurityEventQueue};
+
+use super::{email_verification, facebook_auth, google_auth, oauth_link};
+use crate::routes::admin::impersonation;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenResponse {
-    auth_token: String,
+    pub auth_token: String,
 }
 
-pub async fn signup(data: web::Data<Arc<Client>>, input: web::Json<User>) -> impl Responder {
+pub async fn signup(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    input: web::Json<User>,
+) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
 
@@ -33,10 +43,13 @@ pub async fn signup(data: web::Data<Arc<Client>>, input: web::Json<User>) -> imp
     doc.updated_at = Some(curr_time);
     // Default role is User for new signups
     doc.role = Some(UserRole::User);
+    // Only a verification code can mark the email verified
+    doc.email_verified = false;
+    doc.email_verified_at = None;
 
     match collection.insert_one(&doc).await {
         Ok(result) => {
-            match generate_token(&doc.email, result.inserted_id.as_object_id().unwrap(), doc.role.as_ref()) {
+            match generate_token(&config.jwt_secret, &doc.email, result.inserted_id.as_object_id().unwrap(), doc.role.as_ref()) {
                 Ok(token) => HttpResponse::Ok().json(TokenResponse { auth_token: token }),
                 Err(_) => HttpResponse::InternalServerError().body("Token generation failed"),
             }
@@ -58,7 +71,13 @@ pub async fn signup(data: web::Data<Arc<Client>>, input: web::Json<User>) -> imp
     }
 }
 
-pub async fn signin(data: web::Data<Arc<Client>>, input: web::Json<User>) -> impl Responder {
+pub async fn signin(
+    req: HttpRequest,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    security_events: web::Data<SecurityEventQueue>,
+    input: web::Json<User>,
+) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
 
@@ -83,8 +102,16 @@ pub async fn signin(data: web::Data<Arc<Client>>, input: web::Json<User>) -> imp
                     .await
                 {
                     Ok(_) => {
+                        let user_id = user.id.expect("Unable to read user_id.");
+                        // New-device detection happens on the queue worker, after we respond
+                        security_events.record(
+                            user_id,
+                            SecurityEventType::SigninSuccess,
+                            &ClientFingerprint::from_request(&req),
+                        );
+
                         let token =
-                            generate_token(&email, user.id.expect("Unable to read user_id."), user.role.as_ref())
+                            generate_token(&config.jwt_secret, &email, user_id, user.role.as_ref())
                                 .map_err(|_| {
                                     HttpResponse::InternalServerError()
                                         .body("Token generation failed")
@@ -100,6 +127,14 @@ pub async fn signin(data: web::Data<Arc<Client>>, input: web::Json<User>) -> imp
                     }
                 }
             } else {
+                if let Some(user_id) = user.id {
+                    security_events.record(
+                        user_id,
+                        SecurityEventType::SigninFailed,
+                        &ClientFingerprint::from_request(&req),
+                    );
+                }
+
                 let failed_signins = user.failed_signins.unwrap_or(0) + 1;
                 let update = doc! {
                     "$set": { "failed_signins": failed_signins }
@@ -126,8 +161,10 @@ pub async fn signin(data: web::Data<Arc<Client>>, input: web::Json<User>) -> imp
 }
 
 pub async fn user_session(
+    req: HttpRequest,
     claims: web::ReqData<Claims>,
     data: web::Data<Arc<Client>>,
+    security_events: web::Data<SecurityEventQueue>,
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
@@ -137,6 +174,16 @@ pub async fn user_session(
     match user_id {
         Ok(user_id) => match collection.find_one(doc! { "_id": user_id }).await {
             Ok(Some(user)) => {
+                // Kept by the queue worker only if this device hasn't been seen before.
+                // Support's device isn't the user's, so impersonated sessions are skipped.
+                if claims.impersonator().is_none() {
+                    security_events.record(
+                        user_id,
+                        SecurityEventType::TokenRefreshedFromNewDevice,
+                        &ClientFingerprint::from_request(&req),
+                    );
+                }
+
                 let user_session = UserSession {
                     id: user.id.unwrap_or_default(),
                     email: user.email,
@@ -145,6 +192,7 @@ pub async fn user_session(
                     customer_id: user.customer_id,
                     role: user.role,
                     created_at: user.created_at.unwrap_or_default(),
+                    impersonated_by: claims.impersonator().map(str::to_string),
                 };
                 HttpResponse::Ok().json(user_session)
             }
@@ -158,33 +206,40 @@ pub async fn user_session(
     }
 }
 
-fn is_valid_email(email: &str) -> bool {
+pub(crate) fn is_valid_email(email: &str) -> bool {
     let re = regex::Regex::new(
         r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]*[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]*[a-zA-Z0-9])?)*$",
     );
     return re.unwrap().is_match(email);
 }
 
+/// The `role` claim for a user's role; users without one are plain users
+pub fn role_claim(role: Option<&UserRole>) -> &'static str {
+    match role {
+        Some(UserRole::Admin) => "admin",
+        Some(UserRole::Operator) => "operator",
+        Some(UserRole::User) | None => "user",
+    }
+}
+
 pub fn generate_token(
+    secret: &str,
     email: &str,
     user_id: ObjectId,
     role: Option<&UserRole>,
 ) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     let now = Utc::now();
 
-    let role_string = match role {
-        Some(UserRole::Admin) => Some("admin".to_string()),
-        Some(UserRole::User) => Some("user".to_string()),
-        None => Some("user".to_string()),
-    };
-
     let claims = Claims {
         sub: email.to_string(),
         iat: now.timestamp() as usize,
         exp: (now + Duration::days(14)).timestamp() as usize,
         user_id: user_id.to_string(),
-        role: role_string,
+        role: Some(role_claim(role).to_string()),
+        email: Some(email.to_string()),
+        jti: Some(ObjectId::new().to_hex()),
+        token_scopes: None,
+        impersonation: None,
     };
 
     let header = Header::new(Algorithm::HS256);
@@ -233,3 +288,47 @@ pub async fn newsletter_unsubscribe(
         }
     }
 }
+
+/// Sign-up, sign-in and OAuth routes, plus the public email verifications used
+/// during sign-up. Only `/auth/session` needs a token.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/auth")
+            .route("/signup", web::post().to(signup))
+            .route("/signin", web::post().to(signin))
+            .route("/google", web::get().to(google_auth::google_auth_init))
+            .route(
+                "/google/callback",
+                web::get().to(google_auth::google_auth_callback),
+            )
+            .route("/facebook", web::get().to(facebook_auth::facebook_auth_init))
+            .route(
+                "/facebook/callback",
+                web::get().to(facebook_auth::facebook_auth_callback),
+            )
+            .route("/link-oauth", web::post().to(oauth_link::link_oauth))
+            .route(
+                "/session",
+                web::get().to(user_session).wrap(AuthMiddleware),
+            )
+            .route(
+                "/impersonation/end",
+                web::post().to(impersonation::end_impersonation).wrap(AuthMiddleware),
+            ),
+    )
+    .service(
+        web::scope("/email-verifications")
+            .route(
+                "",
+                web::post().to(email_verification::create_signup_email_verification),
+            )
+            .route(
+                "/{id}",
+                web::put().to(email_verification::verify_signup_email_code),
+            )
+            .route(
+                "/{id}/status",
+                web::get().to(email_verification::get_signup_verification_status),
+            ),
+    );
+}
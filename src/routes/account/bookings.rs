@@ -1,47 +1,151 @@
 use crate::{
+    config::AppConfig,
+    db::mongo::primary_collection,
     middleware::auth::Claims,
+    routes::{
+        account::owner_only, email_settings, limit_exceeded, moderator, pagination::{ListQuery, DEFAULT_PAGE_SIZE}, trip_limits,
+        versioning::ResponseVersion,
+    },
     models::{
-        bookings::{BookingDetails, BookingInput, BookingWithPaymentInput, PaymentStatus},
+        bookings::{
+            BookingDetails, BookingInput, BookingWithPaymentInput, PaymentStatus, RescheduleInput,
+        },
         itinerary::base::FeaturedVacation,
         account::User,
+        money::Money,
+    },
+    services::{
+        account_service::EmailService,
+        availability_service::{AvailabilityCache, AvailabilityService},
+        booking_confirmation::{BookingConfirmationService, CapturedPayment},
+        booking_reschedule::{BookingRescheduleService, RescheduleError, ReschedulePolicy},
+        calendar,
+        gift_card_service::{refund_plan, split_payment, GiftCardService, RefundStep},
+        moderation::Moderator,
+        reservation_service::{ReservationError, ReservationService},
+        special_requests::{
+            sanitize_special_requests, special_requests_editable, SpecialRequestsError,
+        },
+        trip_status_service::check_refundable,
     },
-    services::account_service::EmailService,
 };
 use actix_web::{web, HttpResponse, Responder};
 use bson::{doc, oid::ObjectId, DateTime};
 use futures::TryStreamExt;
 use mongodb::Client;
+use serde::Deserialize;
 use std::{str::FromStr, sync::Arc};
 use stripe::{CancelPaymentIntent, CapturePaymentIntent};
 
+/// Refuse a booking made without a reservation when held or booked seats leave no
+/// room for it
+async fn check_room(
+    client: &Arc<Client>,
+    itinerary_id: &str,
+    arrival_datetime: DateTime,
+) -> Result<(), HttpResponse> {
+    let (Ok(itinerary_id), Some(start)) =
+        (ObjectId::parse_str(itinerary_id), calendar::utc_date(arrival_datetime))
+    else {
+        return Ok(());
+    };
+    let itinerary = match client
+        .database("Itineraries")
+        .collection::<FeaturedVacation>("Featured")
+        .find_one(doc! { "_id": itinerary_id })
+        .await
+    {
+        Ok(Some(itinerary)) => itinerary,
+        Ok(None) => return Err(HttpResponse::NotFound().body("Itinerary not found")),
+        Err(e) => {
+            eprintln!("Failed to load itinerary {} for booking: {}", itinerary_id, e);
+            return Err(HttpResponse::InternalServerError().body("Failed to check availability"));
+        }
+    };
+
+    let seats = itinerary.party_size().unwrap_or(itinerary.min_group).max(1);
+    match ReservationService::new(client.clone())
+        .has_room_for(&itinerary, start, seats)
+        .await
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(crate::routes::payment::reservation_error_response(ReservationError::SoldOut)),
+        Err(e) => {
+            eprintln!("Failed to check availability for itinerary {}: {}", itinerary_id, e);
+            Err(HttpResponse::InternalServerError().body("Failed to check availability"))
+        }
+    }
+}
+
+/// Clean special requests from a request body. Payment details and text failing
+/// moderation are a 422 so the client can tell the traveler what to remove;
+/// anything else invalid is a 400.
+fn checked_special_requests(
+    input: Option<&str>,
+    moderator: &Moderator,
+) -> Result<Option<String>, Box<HttpResponse>> {
+    match input.map(sanitize_special_requests).transpose() {
+        Ok(Some(Some(special_requests))) => match moderator.moderate(&special_requests) {
+            Ok(special_requests) => Ok(Some(special_requests)),
+            Err(hit) => {
+                hit.log("special requests");
+                Err(Box::new(HttpResponse::UnprocessableEntity().json(
+                    serde_json::json!({ "error": hit.to_string(), "field": "special_requests" }),
+                )))
+            }
+        },
+        Ok(special_requests) => Ok(special_requests.flatten()),
+        Err(e @ SpecialRequestsError::PaymentDetails) => Err(Box::new(HttpResponse::UnprocessableEntity()
+            .json(serde_json::json!({ "error": e.to_string(), "field": "special_requests" })))),
+        Err(e) => Err(Box::new(HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": e.to_string(), "field": "special_requests" })))),
+    }
+}
+
 pub async fn add_booking(
     data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
     input: web::Json<BookingInput>,
     path: web::Path<(String, String)>,
     claims: Claims,
 ) -> impl Responder {
     // Get the itinerary_id from the path
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = data.into_inner();
     let input = input.into_inner();
+    let special_requests = match checked_special_requests(input.special_requests.as_deref(), &moderator(config.as_ref())) {
+        Ok(special_requests) => special_requests,
+        Err(response) => return *response,
+    };
 
     println!("\n\n");
     println!("input: {:?}", input);
 
+    let email = email_settings(config.as_ref());
+    let limits = trip_limits(config);
+    if let Err(err) = limits.check_booking_dates(input.arrival_datetime, input.departure_datetime) {
+        return limit_exceeded(&err);
+    }
+
     // Verify itinerary exists in the database
     let itinerary: mongodb::Collection<FeaturedVacation> =
         client.database("Itineraries").collection("Featured");
 
-    if itinerary
+    match itinerary
         .find_one(doc! { "_id": ObjectId::parse_str(&itinerary_id).unwrap() })
         .await
-        .is_err()
     {
-        return HttpResponse::NotFound().body("Itinerary not found");
+        Ok(Some(itinerary)) => {
+            if let Err(err) = limits.check_itinerary(&itinerary) {
+                return limit_exceeded(&err);
+            }
+        }
+        Ok(None) => {}
+        Err(_) => return HttpResponse::NotFound().body("Itinerary not found"),
     }
 
     let arrival_datetime = input.arrival_datetime;
@@ -50,7 +154,7 @@ pub async fn add_booking(
     let transaction_id = input.transaction_id.clone();
 
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     // Create the booking directly without checking for duplicates
     let time = DateTime::now();
@@ -65,6 +169,14 @@ pub async fn add_booking(
         arrival_datetime,
         departure_datetime,
         bookings: None,
+        gift_card_redemption_id: None,
+        gift_card_amount: None,
+        special_requests,
+        party: None,
+        created_by_admin: false,
+        reservation_id: None,
+        modifications: Vec::new(),
+        review_request_sent_at: None,
         created_at: Some(time),
         updated_at: Some(time),
     };
@@ -86,12 +198,15 @@ pub async fn add_booking(
             if let Ok(Some(user)) = users_collection.find_one(doc! {
                 "_id": ObjectId::parse_str(&claims.user_id).unwrap()
             }).await {
+                if !user.effective_notification_preferences().email.booking_updates {
+                    println!("Skipping booking confirmation email, user opted out of booking updates");
+                }
                 // Get itinerary details
-                if let Ok(Some(itinerary)) = itinerary.find_one(doc! {
+                else if let Ok(Some(itinerary)) = itinerary.find_one(doc! {
                     "_id": ObjectId::parse_str(&itinerary_id).unwrap()
                 }).await {
                     // Initialize email service and send confirmation
-                    if let Ok(email_service) = EmailService::new() {
+                    if let Ok(email_service) = EmailService::new(client.as_ref().clone(), &email) {
                         // Create updated booking with ID for email
                         let mut booking_for_email = booking.clone();
                         booking_for_email.id = Some(booking_object_id);
@@ -150,11 +265,11 @@ pub async fn remove_booking(
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let filter = doc! {
@@ -179,11 +294,11 @@ pub async fn get_booking(
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let filter = doc! {
@@ -212,11 +327,11 @@ pub async fn update_booking_payment(
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     // Parse the input to get customer_id and transaction_id
@@ -266,17 +381,22 @@ pub async fn update_booking_payment(
     }
 }
 
+/*
+    /api/account/{id}/bookings
+    All of them unless ?page= or ?limit= is given; ?envelope=true adds the paging
+*/
 pub async fn get_all_bookings(
     data: web::Data<Arc<Client>>,
     path: web::Path<(String,)>,
     claims: Claims,
+    query: web::Query<ListQuery>,
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
-    if path.into_inner().0 != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &path.into_inner().0) {
+        return *response;
     }
 
     let filter = doc! {
@@ -285,11 +405,32 @@ pub async fn get_all_bookings(
 
     println!("Getting bookings!");
 
-    match collection.find(filter).await {
+    let page = query.page(DEFAULT_PAGE_SIZE);
+    let total = if query.envelope {
+        match collection.count_documents(filter.clone()).await {
+            Ok(total) => total,
+            Err(err) => {
+                eprintln!("Error counting bookings: {:?}", err);
+                return HttpResponse::InternalServerError().body("Failed to fetch bookings");
+            }
+        }
+    } else {
+        0
+    };
+
+    // Oldest first, as they were stored, so pages don't shift under the client
+    let find = collection.find(filter).sort(doc! { "_id": 1 }).skip(page.skip());
+    let find = match page.limit {
+        Some(limit) => find.limit(limit),
+        None => find,
+    };
+
+    match find.await {
         Ok(cursor) => {
             let results = cursor.try_collect::<Vec<BookingDetails>>().await;
             match results {
-                Ok(bookings) => return HttpResponse::Ok().json(bookings),
+                // Served the same under every API version
+                Ok(bookings) => page.respond(ResponseVersion::V1, query.envelope, &bookings, total),
                 Err(err) => {
                     eprintln!("Error retrieving booking: {:?}", err);
                     HttpResponse::InternalServerError().body("Failed to retrieve booking")
@@ -310,11 +451,11 @@ pub async fn get_booking_by_id(
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     let (user_id, booking_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     // Parse booking ObjectId
@@ -351,14 +492,16 @@ pub async fn get_booking_by_id(
 pub async fn add_booking_with_payment(
     mongodb_data: web::Data<Arc<Client>>,
     stripe_data: web::Data<Arc<stripe::Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    config: Option<web::Data<AppConfig>>,
     input: web::Json<BookingWithPaymentInput>,
     path: web::Path<(String, String)>,
     claims: Claims,
 ) -> impl Responder {
     // Get the user_id and itinerary_id from the path
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = mongodb_data.into_inner();
@@ -368,8 +511,92 @@ pub async fn add_booking_with_payment(
         "Parsed dates - arrival: {:?}, departure: {:?}",
         input.arrival_datetime, input.departure_datetime
     );
+    let special_requests = match checked_special_requests(input.special_requests.as_deref(), &moderator(config.as_ref())) {
+        Ok(special_requests) => special_requests,
+        Err(response) => return *response,
+    };
+
+    let transactions = config.as_ref().is_some_and(|config| config.mongodb_transactions);
+    let email = email_settings(config.as_ref());
+    let sms = config.as_ref().and_then(|config| config.twilio.clone());
+    let limits = trip_limits(config);
+    if let Err(err) = limits.check_booking_dates(input.arrival_datetime, input.departure_datetime) {
+        return limit_exceeded(&err);
+    }
+    // Charged from the itinerary's price; an amount from the client only has to agree
+    let price = match crate::routes::payment::checkout_price(&client, &itinerary_id, &limits).await {
+        Ok(price) => price,
+        Err(response) => return response,
+    };
+    let amount_due = match price.check_claimed(input.amount) {
+        Ok(amount_due) => amount_due,
+        Err(mismatch) => return mismatch.response(),
+    };
 
-    let payment_intent_id = input.payment_intent_id.clone();
+    // Held seats are already counted; anything else has to fit around them
+    let reservation_id = match &input.reservation_id {
+        Some(reservation_id) => match crate::routes::payment::checkout_reservation(
+            &client,
+            reservation_id,
+            &claims.user_id,
+            &itinerary_id,
+            Some(input.arrival_datetime),
+        )
+        .await
+        {
+            Ok(reservation_id) => Some(reservation_id),
+            Err(response) => return response,
+        },
+        None => {
+            if let Err(response) = check_room(&client, &itinerary_id, input.arrival_datetime).await {
+                return response;
+            }
+            None
+        }
+    };
+
+    let gift_card_service = GiftCardService::new(client.as_ref().clone()).with_transactions(transactions);
+
+    // 0. Work out how much of the amount due a gift card covers
+    let gift_card_split = match &input.gift_card_code {
+        Some(code) => {
+            match gift_card_service.find_redeemable(code).await {
+                Ok(gift_card) => Some(split_payment(amount_due, gift_card.remaining_balance)),
+                Err(e) => {
+                    return HttpResponse::BadRequest()
+                        .json(serde_json::json!({ "success": false, "error": e.to_string() }));
+                }
+            }
+        }
+        None => None,
+    };
+
+    if let (Some(split), Some(code)) = (gift_card_split, &input.gift_card_code) {
+        if split.paid_in_full() {
+            return add_booking_paid_by_gift_card(
+                BookingConfirmationService::new(client.as_ref().clone())
+                    .with_transactions(transactions)
+                    .with_notifications(&email, sms.as_ref()),
+                &gift_card_service,
+                &availability_cache,
+                &claims,
+                &itinerary_id,
+                input.customer_id,
+                input.arrival_datetime,
+                input.departure_datetime,
+                code,
+                split.gift_card_amount,
+                special_requests,
+                reservation_id,
+            )
+            .await;
+        }
+    }
+
+    let payment_intent_id = match input.payment_intent_id.clone() {
+        Some(id) => id,
+        None => return HttpResponse::BadRequest().body("payment_intent_id is required"),
+    };
 
     // 1. First verify the payment intent exists and is in a capturable state
     println!("Verifying payment intent: {}", payment_intent_id);
@@ -389,6 +616,15 @@ pub async fn add_booking_with_payment(
                     intent.status
                 ));
             }
+
+            // The card is charged the price, less what the gift card covers
+            let card_due = gift_card_split.map_or(amount_due, |split| split.card_amount);
+            if intent.amount != card_due {
+                return HttpResponse::BadRequest().body(format!(
+                    "Payment intent amount {} does not match amount due ({})",
+                    intent.amount, card_due
+                ));
+            }
         }
         Err(e) => {
             println!("Error retrieving payment intent: {:?}", e);
@@ -409,9 +645,31 @@ pub async fn add_booking_with_payment(
         return HttpResponse::NotFound().body("Itinerary not found");
     }
 
-    // 3. Create the booking
+    // 3. Redeem the gift card portion before anything is charged
+    let redemption = match (gift_card_split, &input.gift_card_code) {
+        (Some(split), Some(code)) if split.gift_card_amount > 0 => {
+            match gift_card_service
+                .redeem(
+                    code,
+                    split.gift_card_amount,
+                    ObjectId::parse_str(&claims.user_id).unwrap(),
+                    None,
+                )
+                .await
+            {
+                Ok(redemption) => Some(redemption),
+                Err(e) => {
+                    return HttpResponse::Conflict()
+                        .json(serde_json::json!({ "success": false, "error": e.to_string() }));
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // 4. Create the booking
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     // Create the booking directly without checking for duplicates
     let time = DateTime::now();
@@ -426,6 +684,14 @@ pub async fn add_booking_with_payment(
         arrival_datetime: input.arrival_datetime,
         departure_datetime: input.departure_datetime,
         bookings: None,
+        gift_card_redemption_id: redemption.as_ref().and_then(|r| r.id),
+        gift_card_amount: redemption.as_ref().map(|r| r.amount),
+        special_requests,
+        party: None,
+        created_by_admin: false,
+        reservation_id,
+        modifications: Vec::new(),
+        review_request_sent_at: None,
         created_at: Some(time),
         updated_at: Some(time),
     };
@@ -438,7 +704,16 @@ pub async fn add_booking_with_payment(
                         .unwrap()
                         .to_string();
 
-                    // 4. Capture the payment
+                    if let Some(redemption_id) = redemption.as_ref().and_then(|r| r.id) {
+                        if let Err(e) = gift_card_service
+                            .attach_booking(redemption_id, insert_result.inserted_id.as_object_id().unwrap())
+                            .await
+                        {
+                            eprintln!("Failed to link gift card redemption to booking: {}", e);
+                        }
+                    }
+
+                    // 5. Capture the payment
                     println!("Capturing payment intent: {}", payment_intent_id);
                     match stripe::PaymentIntent::capture(
                         stripe_data.as_ref(),
@@ -448,76 +723,48 @@ pub async fn add_booking_with_payment(
                     .await
                     {
                         Ok(captured_intent) => {
-                            // 5. Update booking status based on payment result
-                            let payment_status = captured_intent.status.to_string();
-                            let update_status = if payment_status == "succeeded" {
-                                PaymentStatus::Confirmed
-                            } else {
-                                PaymentStatus::PendingPayment
-                            };
-
+                            // 6. Update booking status based on payment result
                             let booking_object_id = insert_result.inserted_id.as_object_id().unwrap();
-                            let update_filter = doc! {
-                                "_id": booking_object_id
-                            };
-
-                            let update = doc! {
-                                "$set": {
-                                    "status": bson::to_bson(&update_status).unwrap(),
-                                    "updated_at": DateTime::now()
-                                }
+                            let mut stored_booking = booking.clone();
+                            stored_booking.id = Some(booking_object_id);
+
+                            let status_result = if captured_intent.status
+                                == stripe::PaymentIntentStatus::Succeeded
+                            {
+                                // Shared with the Stripe webhook; whichever confirms first
+                                // reserves inventory and sends the confirmation email
+                                let payment = CapturedPayment {
+                                    payment_intent_id: payment_intent_id.clone(),
+                                    amount: captured_intent.amount,
+                                    currency: captured_intent.currency.to_string(),
+                                };
+                                BookingConfirmationService::new(client.as_ref().clone())
+                                    .with_transactions(transactions)
+                                    .with_notifications(&email, sms.as_ref())
+                                    .confirm(&availability_cache, &stored_booking, &payment)
+                                    .await
+                                    .map(|_| PaymentStatus::Confirmed)
+                            } else {
+                                let update = doc! {
+                                    "$set": {
+                                        "status": bson::to_bson(&PaymentStatus::PendingPayment).unwrap(),
+                                        "updated_at": DateTime::now()
+                                    }
+                                };
+                                // Leave it alone if a webhook has already confirmed it
+                                let filter = doc! {
+                                    "_id": booking_object_id,
+                                    "status": bson::to_bson(&PaymentStatus::Pending).unwrap()
+                                };
+                                collection
+                                    .update_one(filter, update)
+                                    .await
+                                    .map(|_| PaymentStatus::PendingPayment)
                             };
 
                             // Update booking with payment status
-                            match collection.update_one(update_filter, update).await {
-                                Ok(_) => {
-                                    // If payment succeeded, send confirmation email
-                                    if update_status == PaymentStatus::Confirmed {
-                                        // Get user details for email
-                                        let users_collection: mongodb::Collection<User> = 
-                                            client.database("Account").collection("Users");
-                                        
-                                        if let Ok(Some(user)) = users_collection.find_one(doc! {
-                                            "_id": ObjectId::parse_str(&claims.user_id).unwrap()
-                                        }).await {
-                                            // Get itinerary details
-                                            if let Ok(Some(itinerary)) = itinerary.find_one(doc! {
-                                                "_id": ObjectId::parse_str(&itinerary_id).unwrap()
-                                            }).await {
-                                                // Initialize email service and send confirmation
-                                                if let Ok(email_service) = EmailService::new() {
-                                                    let amount = captured_intent.amount as f64 / 100.0; // Convert cents to dollars
-                                                    let currency = captured_intent.currency.to_string();
-                                                    
-                                                    // Create updated booking with ID for email
-                                                    let mut booking_for_email = booking.clone();
-                                                    booking_for_email.id = Some(booking_object_id);
-                                                    
-                                                    let user_name = user.first_name
-                                                        .map(|first| {
-                                                            user.last_name
-                                                                .map(|last| format!("{} {}", first, last))
-                                                                .unwrap_or(first)
-                                                        })
-                                                        .unwrap_or_else(|| "Valued Customer".to_string());
-                                                    
-                                                    if let Err(e) = email_service.send_booking_confirmation_email(
-                                                        &user.email,
-                                                        &user_name,
-                                                        &booking_for_email,
-                                                        &itinerary.trip_name,
-                                                        amount,
-                                                        &currency,
-                                                        &payment_intent_id
-                                                    ).await {
-                                                        eprintln!("Failed to send booking confirmation email: {:?}", e);
-                                                        // Don't fail the booking if email fails
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    
+                            match status_result {
+                                Ok(update_status) => {
                                     // Return success with all the details
                                     return HttpResponse::Ok().json(serde_json::json!({
                                         "success": true,
@@ -555,6 +802,13 @@ pub async fn add_booking_with_payment(
                             // Try to update the booking status to failed
                             let _ = collection.update_one(update_filter, update).await;
 
+                            // Give back the gift card portion since nothing was charged
+                            if let Some(redemption) = &redemption {
+                                if let Some(redemption_id) = redemption.id {
+                                    let _ = gift_card_service.restore(redemption_id, redemption.amount).await;
+                                }
+                            }
+
                             return HttpResponse::InternalServerError()
                                 .json(serde_json::json!({
                                     "success": false,
@@ -566,26 +820,188 @@ pub async fn add_booking_with_payment(
                 }
                 Err(err) => {
                     println!("Error creating booking: {:?}", err);
+                    if let Some(redemption) = &redemption {
+                        if let Some(redemption_id) = redemption.id {
+                            let _ = gift_card_service.restore(redemption_id, redemption.amount).await;
+                        }
+                    }
                     return HttpResponse::InternalServerError()
                         .body(format!("Failed to create booking: {}", err));
                 }
             }
 }
 
+/// Confirm a booking paid in full by gift card. Stripe is skipped entirely.
+#[allow(clippy::too_many_arguments)]
+async fn add_booking_paid_by_gift_card(
+    confirmation: BookingConfirmationService,
+    gift_card_service: &GiftCardService,
+    availability_cache: &AvailabilityCache,
+    claims: &Claims,
+    itinerary_id: &str,
+    customer_id: String,
+    arrival_datetime: DateTime,
+    departure_datetime: DateTime,
+    gift_card_code: &str,
+    gift_card_amount: i64,
+    special_requests: Option<String>,
+    reservation_id: Option<ObjectId>,
+) -> HttpResponse {
+    let user_id = ObjectId::parse_str(&claims.user_id).unwrap();
+    let itinerary_object_id = match ObjectId::parse_str(itinerary_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid itinerary ID"),
+    };
+
+    let redemption = match gift_card_service
+        .redeem(gift_card_code, gift_card_amount, user_id, None)
+        .await
+    {
+        Ok(redemption) => redemption,
+        Err(e) => {
+            return HttpResponse::Conflict()
+                .json(serde_json::json!({ "success": false, "error": e.to_string() }));
+        }
+    };
+
+    let time = DateTime::now();
+    let booking = BookingDetails {
+        id: None,
+        user_id,
+        itinerary_id: itinerary_object_id,
+        customer_id: Some(customer_id),
+        transaction_id: None,
+        status: PaymentStatus::Confirmed,
+        arrival_datetime,
+        departure_datetime,
+        bookings: None,
+        gift_card_redemption_id: redemption.id,
+        gift_card_amount: Some(redemption.amount),
+        special_requests,
+        party: None,
+        created_by_admin: false,
+        reservation_id,
+        modifications: Vec::new(),
+        review_request_sent_at: None,
+        created_at: Some(time),
+        updated_at: Some(time),
+    };
+
+    match confirmation.record_prepaid(availability_cache, &booking).await {
+        Ok(stored) => {
+            let booking_object_id = stored.id.unwrap();
+            println!("🎁 Booking {} paid in full by gift card", booking_object_id);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "booking_id": booking_object_id.to_string(),
+                "status": PaymentStatus::Confirmed,
+                "paid_in_full_by_gift_card": true,
+                "gift_card_amount": redemption.amount
+            }))
+        }
+        Err(err) => {
+            println!("Error creating gift card booking: {:?}", err);
+            if let Some(redemption_id) = redemption.id {
+                let _ = gift_card_service.restore(redemption_id, redemption.amount).await;
+            }
+            HttpResponse::InternalServerError()
+                .body(format!("Failed to create booking: {}", err))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SpecialRequestsInput {
+    /// `null` or blank clears the requests
+    pub special_requests: Option<String>,
+}
+
+/*
+    /api/account/{id}/bookings/{booking_id}/special-requests
+*/
+pub async fn update_special_requests(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    path: web::Path<(String, String)>,
+    claims: Claims,
+    input: web::Json<SpecialRequestsInput>,
+) -> impl Responder {
+    let (user_id, booking_id) = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let (user_object_id, booking_object_id) =
+        match (ObjectId::parse_str(&user_id), ObjectId::parse_str(&booking_id)) {
+            (Ok(user_id), Ok(booking_id)) => (user_id, booking_id),
+            _ => return HttpResponse::BadRequest().body("Invalid booking ID format"),
+        };
+
+    let special_requests = match checked_special_requests(input.special_requests.as_deref(), &moderator(config.as_ref())) {
+        Ok(special_requests) => special_requests,
+        Err(response) => return *response,
+    };
+
+    let client = data.into_inner();
+    let collection: mongodb::Collection<BookingDetails> =
+        primary_collection(&client, "Account", "Bookings");
+    let filter = doc! { "_id": booking_object_id, "user_id": user_object_id };
+
+    let booking = match collection.find_one(filter.clone()).await {
+        Ok(Some(booking)) => booking,
+        Ok(None) => return HttpResponse::NotFound().body("Booking not found"),
+        Err(e) => {
+            eprintln!("Error fetching booking: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch booking");
+        }
+    };
+
+    let now = DateTime::now();
+    if !special_requests_editable(&booking, now) {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Special requests can't be changed once the trip has started or the booking is cancelled"
+        }));
+    }
+
+    let update = match &special_requests {
+        Some(text) => doc! { "$set": { "special_requests": text, "updated_at": now } },
+        None => doc! { "$unset": { "special_requests": "" }, "$set": { "updated_at": now } },
+    };
+    match collection.update_one(filter, update).await {
+        Ok(_) => {
+            let mut updated = booking;
+            updated.special_requests = special_requests;
+            updated.updated_at = Some(now);
+            HttpResponse::Ok().json(updated)
+        }
+        Err(e) => {
+            eprintln!("Error updating special requests: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update special requests")
+        }
+    }
+}
+
+/*
+    /api/account/{id}/bookings/{booking_id}/cancel
+
+    Cancels a booking, refunding 95% of what was paid, and gives its seats back.
+    Trips that have started, or that arrive within `REFUND_CUTOFF_HOURS`, are a 409.
+*/
 pub async fn cancel_booking_with_refund(
     mongodb_data: web::Data<Arc<Client>>,
     stripe_data: web::Data<Arc<stripe::Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    config: Option<web::Data<AppConfig>>,
     path: web::Path<(String, String)>,
     claims: Claims,
 ) -> impl Responder {
     let (user_id, booking_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = mongodb_data.into_inner();
     let collection: mongodb::Collection<BookingDetails> =
-        client.database("Account").collection("Bookings");
+        primary_collection(&client, "Account", "Bookings");
 
     // Find the booking by ID
     let booking_object_id = match ObjectId::parse_str(&booking_id) {
@@ -616,9 +1032,57 @@ pub async fn cancel_booking_with_refund(
         }));
     }
 
+    let email = email_settings(config.as_ref());
+    let transactions = config.as_ref().is_some_and(|config| config.mongodb_transactions);
+    let cutoff_hours = config.map(|config| config.refund_cutoff_hours).unwrap_or(0);
+    if let Err(blocked) = check_refundable(&booking, DateTime::now(), cutoff_hours) {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "error": blocked.to_string()
+        }));
+    }
+
+    let gift_card_service = GiftCardService::new(client.as_ref().clone()).with_transactions(transactions);
+    let availability = AvailabilityService::new(client.as_ref().clone());
+    let gift_card_paid = booking.gift_card_amount.unwrap_or(0);
+
     // Check if there's a transaction ID for refund
-    let transaction_id = match booking.transaction_id {
+    let transaction_id = match booking.transaction_id.clone() {
         Some(id) => id,
+        None if gift_card_paid > 0 => {
+            // Paid entirely by gift card - restore the refundable share to the card
+            let refund_total = Money::from_cents(gift_card_paid).percent(95).cents();
+            let restored = restore_gift_card_share(
+                &gift_card_service,
+                booking.gift_card_redemption_id,
+                &refund_plan(gift_card_paid, 0, refund_total),
+            )
+            .await;
+
+            let update = doc! {
+                "$set": {
+                    "status": bson::to_bson(&PaymentStatus::Refunded).unwrap(),
+                    "refund_amount": restored,
+                    "updated_at": DateTime::now()
+                }
+            };
+
+            return match collection.update_one(filter, update).await {
+                Ok(_) => {
+                    availability.release_cancelled_booking(&availability_cache, &booking).await;
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "success": true,
+                        "message": "Booking cancelled and gift card balance restored",
+                        "booking_id": booking_id,
+                        "gift_card_restored": restored
+                    }))
+                }
+                Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false,
+                    "error": format!("Failed to cancel booking: {}", e)
+                })),
+            };
+        }
         None => {
             // If no transaction, just cancel the booking
             let update = doc! {
@@ -630,6 +1094,7 @@ pub async fn cancel_booking_with_refund(
 
             match collection.update_one(filter, update).await {
                 Ok(_) => {
+                    availability.release_cancelled_booking(&availability_cache, &booking).await;
                     return HttpResponse::Ok().json(serde_json::json!({
                         "success": true,
                         "message": "Booking cancelled successfully (no payment to refund)",
@@ -681,6 +1146,13 @@ pub async fn cancel_booking_with_refund(
             .await
             {
                 Ok(cancelled_intent) => {
+                    // Nothing was charged, so the whole gift card portion goes back
+                    if let Some(redemption_id) = booking.gift_card_redemption_id {
+                        if let Err(e) = gift_card_service.restore(redemption_id, gift_card_paid).await {
+                            eprintln!("Failed to restore gift card balance: {}", e);
+                        }
+                    }
+
                     // Update booking status to cancelled
                     let update = doc! {
                         "$set": {
@@ -691,6 +1163,7 @@ pub async fn cancel_booking_with_refund(
 
                     match collection.update_one(filter, update).await {
                         Ok(_) => {
+                            availability.release_cancelled_booking(&availability_cache, &booking).await;
                             return HttpResponse::Ok().json(serde_json::json!({
                                 "success": true,
                                 "message": "Booking cancelled successfully (payment authorization reversed)",
@@ -725,8 +1198,23 @@ pub async fn cancel_booking_with_refund(
             // Payment was captured - proceed with refund
             println!("Payment intent was captured, processing refund");
             
-            // Calculate 95% refund (5% cancellation fee)
-            let refund_amount = (payment_intent.amount as f64 * 0.95) as i64;
+            // Calculate 95% refund (5% cancellation fee) over everything paid,
+            // split proportionally between the gift card and the card
+            let refund_total = Money::from_cents(payment_intent.amount + gift_card_paid)
+                .percent(95)
+                .cents();
+            let plan = refund_plan(gift_card_paid, payment_intent.amount, refund_total);
+
+            // Gift card balances are restored before the card is refunded
+            let gift_card_restored =
+                restore_gift_card_share(&gift_card_service, booking.gift_card_redemption_id, &plan).await;
+            let refund_amount = plan
+                .iter()
+                .find_map(|step| match step {
+                    RefundStep::RefundCard(amount) => Some(*amount),
+                    _ => None,
+                })
+                .unwrap_or(0);
 
             // Create the refund
             let refund_params = stripe::CreateRefund {
@@ -749,6 +1237,8 @@ pub async fn cancel_booking_with_refund(
 
             match collection.update_one(filter, update).await {
                 Ok(_) => {
+                    availability.release_cancelled_booking(&availability_cache, &booking).await;
+
                     // Send cancellation email notification
                     let users_collection: mongodb::Collection<User> = 
                         client.database("Account").collection("Users");
@@ -756,7 +1246,9 @@ pub async fn cancel_booking_with_refund(
                     if let Ok(Some(user)) = users_collection.find_one(doc! {
                         "_id": ObjectId::parse_str(&claims.user_id).unwrap()
                     }).await {
-                        if let Ok(email_service) = EmailService::new() {
+                        if !user.effective_notification_preferences().email.booking_updates {
+                            println!("Skipping cancellation email, user opted out of booking updates");
+                        } else if let Ok(email_service) = EmailService::new(client.as_ref().clone(), &email) {
                             // You might want to implement send_cancellation_email method
                             // For now, we'll just log it
                             println!("Booking cancelled and refunded for user: {}", user.email);
@@ -767,6 +1259,7 @@ pub async fn cancel_booking_with_refund(
                         "success": true,
                         "message": "Booking cancelled and refunded successfully",
                         "booking_id": booking_id,
+                        "gift_card_restored": gift_card_restored,
                         "refund": {
                             "id": refund.id.to_string(),
                             "amount": refund_amount,
@@ -813,3 +1306,120 @@ pub async fn cancel_booking_with_refund(
         }
     }
 }
+
+/*
+    /api/account/{id}/bookings/{booking_id}/reschedule
+
+    Moves a booking to new dates. When the new dates cost more the response is a
+    402 with `amount_due`; authorize a payment intent for that amount and retry
+    with its `payment_intent_id`. A lower price is refunded straight away.
+*/
+pub async fn reschedule_booking(
+    mongodb_data: web::Data<Arc<Client>>,
+    stripe_data: web::Data<Arc<stripe::Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    config: web::Data<AppConfig>,
+    path: web::Path<(String, String)>,
+    claims: Claims,
+    input: web::Json<RescheduleInput>,
+) -> impl Responder {
+    let (user_id, booking_id) = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let (user_object_id, booking_object_id) =
+        match (ObjectId::parse_str(&user_id), ObjectId::parse_str(&booking_id)) {
+            (Ok(user_id), Ok(booking_id)) => (user_id, booking_id),
+            _ => return HttpResponse::BadRequest().body("Invalid booking ID format"),
+        };
+
+    let policy = ReschedulePolicy {
+        cutoff_hours: config.reschedule_cutoff_hours,
+        limited_threshold: config.availability_limited_threshold,
+    };
+    let service = BookingRescheduleService::new(mongodb_data.into_inner().as_ref().clone())
+        .with_email(&config.email)
+        .with_transactions(config.mongodb_transactions);
+    match service
+        .reschedule(
+            user_object_id,
+            booking_object_id,
+            input.into_inner(),
+            &policy,
+            &availability_cache,
+            stripe_data.as_ref().as_ref(),
+        )
+        .await
+    {
+        Ok(outcome) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "booking": outcome.booking,
+            "price_difference": outcome.price_difference
+        })),
+        Err(e @ (RescheduleError::BookingNotFound | RescheduleError::ItineraryNotFound)) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "error": e.to_string()
+            }))
+        }
+        Err(
+            e @ (RescheduleError::InvalidDates(_)
+            | RescheduleError::PaymentRejected(_)
+            | RescheduleError::PaymentAlreadyUsed),
+        ) => HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "error": e.to_string()
+        })),
+        Err(e @ RescheduleError::PriceUnavailable) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "error": "price_unavailable",
+            "message": e.to_string()
+        })),
+        Err(e @ RescheduleError::PaymentRequired(amount_due)) => {
+            HttpResponse::PaymentRequired().json(serde_json::json!({
+                "success": false,
+                "error": e.to_string(),
+                "amount_due": amount_due
+            }))
+        }
+        Err(
+            e @ (RescheduleError::NotReschedulable(_)
+            | RescheduleError::PaymentPending
+            | RescheduleError::InsideCutoff(_)
+            | RescheduleError::Unavailable(_)),
+        ) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "error": e.to_string()
+        })),
+        Err(e) => {
+            eprintln!("Failed to reschedule booking {}: {}", booking_id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Apply the gift card steps of a refund plan, returning the amount restored
+async fn restore_gift_card_share(
+    gift_card_service: &GiftCardService,
+    redemption_id: Option<ObjectId>,
+    plan: &[RefundStep],
+) -> i64 {
+    let redemption_id = match redemption_id {
+        Some(id) => id,
+        None => return 0,
+    };
+
+    let mut restored = 0;
+    for step in plan {
+        if let RefundStep::RestoreGiftCard(amount) = step {
+            match gift_card_service.restore(redemption_id, *amount).await {
+                Ok(amount) => restored += amount,
+                Err(e) => eprintln!("Failed to restore gift card balance: {}", e),
+            }
+        }
+    }
+    restored
+}
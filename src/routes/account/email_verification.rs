@@ -4,15 +4,28 @@ use mongodb::{Client, bson::doc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
-use futures::TryStreamExt;
 
-use crate::services::account_service::{EmailService, EmailError, EmailVerification};
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::models::account::User;
+use crate::routes::{account::owner_only, email_settings};
+use crate::services::account_service::{
+    EmailService, EmailError, EmailVerification, MAX_VERIFICATION_ATTEMPTS,
+};
+use crate::services::email_verification_service::{verification_status, EmailVerificationService};
 
 #[derive(Debug, Deserialize)]
 pub struct CreateVerificationRequest {
     pub email: String,
 }
 
+/// Codes for an account always go to its current email; `email`, if given, must match it
+#[derive(Debug, Deserialize)]
+pub struct CreateUserVerificationRequest {
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct VerifyCodeRequest {
     pub code: String,
@@ -32,13 +45,61 @@ pub struct ErrorResponse {
     pub message: String,
 }
 
+/// The account, or the response to give when it can't be loaded
+async fn find_user(service: &EmailVerificationService, user_id: ObjectId) -> Result<User, HttpResponse> {
+    match service.find_user(user_id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().json(ErrorResponse {
+            error: "user_not_found".to_string(),
+            message: "User not found".to_string(),
+        })),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "database_error".to_string(),
+                message: "Database error occurred".to_string(),
+            }))
+        }
+    }
+}
+
+/// A 429 telling the client when to ask for a new code
+fn retry_later(error: &str, err: &EmailError, retry_after_seconds: i64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after_seconds.to_string()))
+        .json(json!({
+            "error": error,
+            "message": err.to_string(),
+            "retry_after_seconds": retry_after_seconds
+        }))
+}
+
+/// Wrong codes allowed per verification, from `EMAIL_VERIFICATION_MAX_ATTEMPTS`
+fn max_attempts(config: Option<web::Data<AppConfig>>) -> u32 {
+    config
+        .map(|config| config.email_verification_max_attempts)
+        .unwrap_or(MAX_VERIFICATION_ATTEMPTS)
+}
+
+fn email_changed_response() -> HttpResponse {
+    HttpResponse::Conflict().json(ErrorResponse {
+        error: "email_changed".to_string(),
+        message: "This code was sent to an email that is no longer on the account".to_string(),
+    })
+}
+
 // POST /api/users/{user_id}/email-verifications
 pub async fn create_user_email_verification(
     data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
     path: web::Path<String>,
-    req_body: web::Json<CreateVerificationRequest>,
+    req_body: web::Json<CreateUserVerificationRequest>,
+    claims: Claims,
 ) -> impl Responder {
     let user_id_str = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id_str) {
+        return *response;
+    }
     let user_id = match ObjectId::parse_str(&user_id_str) {
         Ok(id) => id,
         Err(_) => {
@@ -50,8 +111,21 @@ pub async fn create_user_email_verification(
     };
 
     let client = data.into_inner();
+    let verifications = EmailVerificationService::new(client.as_ref().clone());
+    let user = match find_user(&verifications, user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if let Some(email) = &req_body.email {
+        if !email.trim().eq_ignore_ascii_case(&user.email) {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "email_mismatch".to_string(),
+                message: "Codes can only be sent to the account's current email".to_string(),
+            });
+        }
+    }
     
-    let email_service = match EmailService::new() {
+    let email_service = match EmailService::new(client.as_ref().clone(), &email_settings(config.as_ref())) {
         Ok(service) => service,
         Err(err) => {
             eprintln!("Failed to initialize email service: {:?}", err);
@@ -63,14 +137,14 @@ pub async fn create_user_email_verification(
     };
 
     match email_service
-        .send_verification_html_email(&req_body.email, Some(user_id), &client)
+        .send_verification_html_email(&user.email, Some(user_id), &client)
         .await
     {
-        Ok(verification_code) => {
+        Ok(_) => {
             // Get the created verification record to return its details
             let collection = client.database("actota").collection::<EmailVerification>("email_verifications");
             match collection.find_one(mongodb::bson::doc! {
-                "email": &req_body.email,
+                "email": &user.email,
                 "user_id": user_id,
                 "verified": false
             }).await {
@@ -87,6 +161,9 @@ pub async fn create_user_email_verification(
                 }))
             }
         }
+        Err(err @ EmailError::ResendTooSoon { retry_after_seconds }) => {
+            retry_later("resend_too_soon", &err, retry_after_seconds)
+        }
         Err(err) => {
             eprintln!("Error sending verification: {:?}", err);
             HttpResponse::InternalServerError().json(ErrorResponse {
@@ -100,11 +177,12 @@ pub async fn create_user_email_verification(
 // POST /api/email-verifications (for signup flow)
 pub async fn create_signup_email_verification(
     data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
     req_body: web::Json<CreateVerificationRequest>,
 ) -> impl Responder {
     let client = data.into_inner();
     
-    let email_service = match EmailService::new() {
+    let email_service = match EmailService::new(client.as_ref().clone(), &email_settings(config.as_ref())) {
         Ok(service) => service,
         Err(err) => {
             eprintln!("Failed to initialize email service: {:?}", err);
@@ -140,6 +218,9 @@ pub async fn create_signup_email_verification(
                 }))
             }
         }
+        Err(err @ EmailError::ResendTooSoon { retry_after_seconds }) => {
+            retry_later("resend_too_soon", &err, retry_after_seconds)
+        }
         Err(err) => {
             eprintln!("Error sending verification: {:?}", err);
             HttpResponse::InternalServerError().json(ErrorResponse {
@@ -155,8 +236,13 @@ pub async fn verify_user_email_code(
     data: web::Data<Arc<Client>>,
     path: web::Path<(String, String)>,
     req_body: web::Json<VerifyCodeRequest>,
+    claims: Claims,
+    config: Option<web::Data<AppConfig>>,
 ) -> impl Responder {
     let (user_id_str, verification_id_str) = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id_str) {
+        return *response;
+    }
     
     let user_id = match ObjectId::parse_str(&user_id_str) {
         Ok(id) => id,
@@ -203,12 +289,30 @@ pub async fn verify_user_email_code(
         }
     };
 
-    match EmailService::verify_email_code(&verification.email, &req_body.code, &client).await {
-        Ok(true) => HttpResponse::Ok().json(json!({
-            "verified": true,
-            "email": verification.email,
-            "verified_at": mongodb::bson::DateTime::now().try_to_rfc3339_string().unwrap_or_default()
-        })),
+    // A code for an address the account has since moved away from verifies nothing
+    let verifications = EmailVerificationService::new(client.as_ref().clone());
+    match find_user(&verifications, user_id).await {
+        Ok(user) if user.email == verification.email => {}
+        Ok(_) => return email_changed_response(),
+        Err(response) => return response,
+    }
+
+    match EmailService::verify_email_code(verification_id, &req_body.code, max_attempts(config), &client).await {
+        Ok(true) => match verifications.mark_verified(user_id, &verification.email).await {
+            Ok(Some(verified_at)) => HttpResponse::Ok().json(json!({
+                "verified": true,
+                "email": verification.email,
+                "verified_at": verified_at.to_rfc3339()
+            })),
+            Ok(None) => email_changed_response(),
+            Err(err) => {
+                eprintln!("Failed to mark user {} verified: {}", user_id, err);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "database_error".to_string(),
+                    message: "Database error occurred".to_string(),
+                })
+            }
+        },
         Ok(false) => HttpResponse::BadRequest().json(ErrorResponse {
             error: "verification_failed".to_string(),
             message: "Verification failed".to_string(),
@@ -221,6 +325,9 @@ pub async fn verify_user_email_code(
             error: "code_expired".to_string(),
             message: "Verification code has expired".to_string(),
         }),
+        Err(err @ EmailError::TooManyAttempts { retry_after_seconds }) => {
+            retry_later("too_many_attempts", &err, retry_after_seconds)
+        }
         Err(err) => {
             eprintln!("Verification error: {}", err);
             HttpResponse::InternalServerError().json(ErrorResponse {
@@ -236,6 +343,7 @@ pub async fn verify_signup_email_code(
     data: web::Data<Arc<Client>>,
     path: web::Path<String>,
     req_body: web::Json<VerifyCodeRequest>,
+    config: Option<web::Data<AppConfig>>,
 ) -> impl Responder {
     let verification_id_str = path.into_inner();
     
@@ -274,7 +382,7 @@ pub async fn verify_signup_email_code(
         }
     };
 
-    match EmailService::verify_email_code(&verification.email, &req_body.code, &client).await {
+    match EmailService::verify_email_code(verification_id, &req_body.code, max_attempts(config), &client).await {
         Ok(true) => HttpResponse::Ok().json(json!({
             "verified": true,
             "email": verification.email,
@@ -292,6 +400,9 @@ pub async fn verify_signup_email_code(
             error: "code_expired".to_string(),
             message: "Verification code has expired".to_string(),
         }),
+        Err(err @ EmailError::TooManyAttempts { retry_after_seconds }) => {
+            retry_later("too_many_attempts", &err, retry_after_seconds)
+        }
         Err(err) => {
             eprintln!("Verification error: {}", err);
             HttpResponse::InternalServerError().json(ErrorResponse {
@@ -303,11 +414,16 @@ pub async fn verify_signup_email_code(
 }
 
 // GET /api/users/{user_id}/email-verifications
+// The account's verification state; pending codes are listed without the code
 pub async fn get_user_email_verifications(
     data: web::Data<Arc<Client>>,
     path: web::Path<String>,
+    claims: Claims,
 ) -> impl Responder {
     let user_id_str = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id_str) {
+        return *response;
+    }
     let user_id = match ObjectId::parse_str(&user_id_str) {
         Ok(id) => id,
         Err(_) => {
@@ -318,26 +434,14 @@ pub async fn get_user_email_verifications(
         }
     };
 
-    let client = data.into_inner();
-    let collection = client.database("actota").collection::<EmailVerification>("email_verifications");
-    
-    match collection.find(mongodb::bson::doc! {
-        "user_id": user_id,
-        "verified": false,
-        "expires_at": { "$gt": mongodb::bson::DateTime::now() }
-    }).await {
-        Ok(mut cursor) => {
-            let mut verifications = Vec::new();
-            while let Ok(Some(verification)) = cursor.try_next().await {
-                verifications.push(VerificationResponse {
-                    id: verification.id.unwrap().to_hex(),
-                    email: verification.email,
-                    expires_at: verification.expires_at.try_to_rfc3339_string().unwrap_or_default(),
-                    created_at: verification.created_at.try_to_rfc3339_string().unwrap_or_default(),
-                });
-            }
-            HttpResponse::Ok().json(verifications)
-        }
+    let verifications = EmailVerificationService::new(data.into_inner().as_ref().clone());
+    let user = match find_user(&verifications, user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match verifications.state(&user).await {
+        Ok(state) => HttpResponse::Ok().json(state),
         Err(err) => {
             eprintln!("Database error: {}", err);
             HttpResponse::InternalServerError().json(ErrorResponse {
@@ -346,4 +450,37 @@ pub async fn get_user_email_verifications(
             })
         }
     }
-}
\ No newline at end of file
+}
+
+// GET /api/email-verifications/{verification_id}/status
+// For the signup screen's countdown and attempt counter; never includes the code
+pub async fn get_signup_verification_status(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<String>,
+    config: Option<web::Data<AppConfig>>,
+) -> impl Responder {
+    let Ok(verification_id) = ObjectId::parse_str(path.into_inner()) else {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_verification_id".to_string(),
+            message: "Invalid verification ID format".to_string(),
+        });
+    };
+
+    let verifications = EmailVerificationService::new(data.into_inner().as_ref().clone());
+    match verifications.find_verification(verification_id).await {
+        Ok(Some(verification)) => {
+            HttpResponse::Ok().json(verification_status(&verification, mongodb::bson::DateTime::now(), max_attempts(config)))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "verification_not_found".to_string(),
+            message: "Verification not found".to_string(),
+        }),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "database_error".to_string(),
+                message: "Database error occurred".to_string(),
+            })
+        }
+    }
+}
@@ -1,15 +1,113 @@
 use crate::{
+    config::AppConfig,
     middleware::auth::Claims,
-    models::{account::Favorite, itinerary::base::FeaturedVacation},
-    services::itinerary_service::get_images,
+    routes::{
+        account::owner_only,
+        itinerary::unit_system,
+        pagination::{ListQuery, DEFAULT_PAGE_SIZE},
+        versioning::ResponseVersion,
+    },
+    models::{account::Favorite, itinerary::base::FeaturedVacation, money::Money},
+    services::{itinerary_service::get_images, pricing_service::PersonPrice, storage::Storage, units},
 };
-use actix_web::{web, HttpResponse, Responder};
-use bson::{doc, oid::ObjectId};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use bson::{doc, oid::ObjectId, Document};
 use futures::TryStreamExt;
+use mongodb::error::{ErrorKind, InsertManyError};
 use mongodb::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+/// Most itinerary ids accepted by one bulk request
+pub const MAX_BULK_FAVORITES: usize = 100;
+
+#[derive(Deserialize)]
+pub struct BulkFavoritesInput {
+    pub itinerary_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkFavoriteStatus {
+    Added,
+    AlreadyFavorited,
+    NotFound,
+    InvalidId,
+    /// Listed earlier in the same request
+    Duplicate,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkFavoriteResult {
+    pub itinerary_id: String,
+    pub status: BulkFavoriteStatus,
+}
+
+/// Decide what happens to each requested id. Ids marked `Added` still have to be inserted.
+fn plan_bulk_favorites(
+    requested: &[String],
+    existing_itineraries: &HashSet<ObjectId>,
+    already_favorited: &HashSet<ObjectId>,
+) -> Vec<BulkFavoriteResult> {
+    let mut seen = HashSet::new();
+    requested
+        .iter()
+        .map(|raw_id| {
+            let status = match ObjectId::parse_str(raw_id) {
+                Err(_) => BulkFavoriteStatus::InvalidId,
+                Ok(id) if !seen.insert(id) => BulkFavoriteStatus::Duplicate,
+                Ok(id) if !existing_itineraries.contains(&id) => BulkFavoriteStatus::NotFound,
+                Ok(id) if already_favorited.contains(&id) => BulkFavoriteStatus::AlreadyFavorited,
+                Ok(_) => BulkFavoriteStatus::Added,
+            };
+            BulkFavoriteResult {
+                itinerary_id: raw_id.clone(),
+                status,
+            }
+        })
+        .collect()
+}
+
+/// What became of each favorite in an unordered `insert_many` that returned `err`,
+/// by position. The ones without a write error of their own were inserted.
+fn insert_outcomes(err: &mongodb::error::Error, attempted: usize) -> Vec<BulkFavoriteStatus> {
+    let ErrorKind::InsertMany(InsertManyError { write_errors, .. }) = &*err.kind else {
+        return vec![BulkFavoriteStatus::Failed; attempted];
+    };
+    let mut outcomes = vec![BulkFavoriteStatus::Added; attempted];
+    for write_error in write_errors.iter().flatten() {
+        if let Some(outcome) = outcomes.get_mut(write_error.index) {
+            *outcome = if write_error.code == 11000 {
+                BulkFavoriteStatus::AlreadyFavorited
+            } else {
+                BulkFavoriteStatus::Failed
+            };
+        }
+    }
+    outcomes
+}
+
+/// Keep the denormalized `favorite_count` on itineraries in step with the Favorites collection
+async fn adjust_favorite_counts(client: &Client, itinerary_ids: &[ObjectId], delta: i32) {
+    if itinerary_ids.is_empty() {
+        return;
+    }
+    let itineraries: mongodb::Collection<Document> =
+        client.database("Itineraries").collection("Featured");
+    if let Err(err) = itineraries
+        .update_many(
+            doc! { "_id": { "$in": itinerary_ids } },
+            doc! { "$inc": { "favorite_count": delta } },
+        )
+        .await
+    {
+        eprintln!("Failed to update favorite counts: {:?}", err);
+    }
+}
+
 pub async fn add_favorite(
     data: web::Data<Arc<Client>>,
     path: web::Path<(String, String)>,
@@ -17,8 +115,8 @@ pub async fn add_favorite(
 ) -> impl Responder {
     // Get the itinerary_id from the path
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().json(json!({"error": "Forbidden"}));
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = data.into_inner();
@@ -26,13 +124,13 @@ pub async fn add_favorite(
     // Verify itinerary exists in the database
     let itinerary: mongodb::Collection<FeaturedVacation> =
         client.database("Itineraries").collection("Featured");
-    if itinerary
+    let current_price = match itinerary
         .find_one(doc! { "_id": ObjectId::parse_str(&itinerary_id).unwrap() })
         .await
-        .is_err()
     {
-        return HttpResponse::NotFound().json(json!({"error": "Itinerary not found"}));
-    }
+        Ok(Some(itinerary)) => PersonPrice::stored(itinerary.person_cost).amount(),
+        _ => return HttpResponse::NotFound().json(json!({"error": "Itinerary not found"})),
+    };
 
     let collection: mongodb::Collection<Favorite> =
         client.database("Account").collection("Favorites");
@@ -56,12 +154,14 @@ pub async fn add_favorite(
                 id: None,
                 user_id: ObjectId::parse_str(&claims.user_id).unwrap(),
                 itinerary_id: ObjectId::parse_str(&itinerary_id).unwrap(),
+                last_notified_price: current_price,
                 created_at: Some(time),
                 updated_at: Some(time),
             };
 
             match collection.insert_one(&favorite).await {
                 Ok(_) => {
+                    adjust_favorite_counts(&client, &[favorite.itinerary_id], 1).await;
                     return HttpResponse::Ok().json(json!({"status": "success", "message": "Itinerary added to favorites"}));
                 }
                 Err(_) => {
@@ -85,17 +185,21 @@ pub async fn remove_favorite(
         client.database("Account").collection("Favorites");
 
     let (user_id, itinerary_id) = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().json(json!({"error": "Forbidden"}));
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
+    let itinerary_object_id = ObjectId::parse_str(itinerary_id).unwrap();
     let filter = doc! {
         "user_id": ObjectId::parse_str(&claims.user_id).unwrap(),
-        "itinerary_id": ObjectId::parse_str(itinerary_id).unwrap(),
+        "itinerary_id": itinerary_object_id,
     };
 
     match collection.delete_one(filter).await {
-        Ok(_) => {
+        Ok(result) => {
+            if result.deleted_count > 0 {
+                adjust_favorite_counts(&client, &[itinerary_object_id], -1).await;
+            }
             return HttpResponse::Ok().json(json!({"status": "success", "message": "Removed Favorite"}));
         }
         Err(_) => {
@@ -104,13 +208,159 @@ pub async fn remove_favorite(
     }
 }
 
+/*
+    /api/account/{id}/favorites/bulk
+*/
+pub async fn add_favorites_bulk(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<(String,)>,
+    claims: Claims,
+    input: web::Json<BulkFavoritesInput>,
+) -> impl Responder {
+    let user_id = path.into_inner().0;
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let user_id = match ObjectId::parse_str(&user_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid user ID"})),
+    };
+
+    let requested = input.into_inner().itinerary_ids;
+    if requested.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "itinerary_ids must not be empty"}));
+    }
+    if requested.len() > MAX_BULK_FAVORITES {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("At most {} itineraries can be added at once", MAX_BULK_FAVORITES)
+        }));
+    }
+
+    let client = data.into_inner();
+    let candidate_ids: Vec<ObjectId> = requested
+        .iter()
+        .filter_map(|id| ObjectId::parse_str(id).ok())
+        .collect();
+
+    // Which of the requested itineraries exist, at what price, and which are already favorited
+    let itineraries: mongodb::Collection<Document> =
+        client.database("Itineraries").collection("Featured");
+    let current_prices: HashMap<ObjectId, Option<Money>> = match itineraries
+        .find(doc! { "_id": { "$in": &candidate_ids } })
+        .projection(doc! { "_id": 1, "person_cost": 1 })
+        .await
+    {
+        Ok(cursor) => match cursor.try_collect::<Vec<Document>>().await {
+            Ok(docs) => docs
+                .iter()
+                .filter_map(|d| {
+                    let price = d
+                        .get("person_cost")
+                        .and_then(|cost| mongodb::bson::from_bson::<Money>(cost.clone()).ok());
+                    d.get_object_id("_id").ok().map(|id| (id, price))
+                })
+                .collect(),
+            Err(err) => {
+                eprintln!("Error reading itineraries: {:?}", err);
+                return HttpResponse::InternalServerError().json(json!({"error": "Failed to check itineraries"}));
+            }
+        },
+        Err(err) => {
+            eprintln!("Error fetching itineraries: {:?}", err);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to check itineraries"}));
+        }
+    };
+
+    let collection: mongodb::Collection<Favorite> =
+        client.database("Account").collection("Favorites");
+    let already_favorited: HashSet<ObjectId> = match collection
+        .find(doc! { "user_id": user_id, "itinerary_id": { "$in": &candidate_ids } })
+        .await
+    {
+        Ok(cursor) => match cursor.try_collect::<Vec<Favorite>>().await {
+            Ok(favorites) => favorites.iter().map(|f| f.itinerary_id).collect(),
+            Err(err) => {
+                eprintln!("Error reading favorites: {:?}", err);
+                return HttpResponse::InternalServerError().json(json!({"error": "Failed to check for favorites"}));
+            }
+        },
+        Err(err) => {
+            eprintln!("Error fetching favorites: {:?}", err);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to check for favorites"}));
+        }
+    };
+
+    let existing_itineraries: HashSet<ObjectId> = current_prices.keys().copied().collect();
+    let mut results = plan_bulk_favorites(&requested, &existing_itineraries, &already_favorited);
+
+    let time = chrono::Utc::now();
+    let new_favorites: Vec<Favorite> = results
+        .iter()
+        .filter(|result| result.status == BulkFavoriteStatus::Added)
+        .filter_map(|result| ObjectId::parse_str(&result.itinerary_id).ok())
+        .map(|itinerary_id| Favorite {
+            id: None,
+            user_id,
+            itinerary_id,
+            last_notified_price: PersonPrice::stored(current_prices.get(&itinerary_id).copied().flatten())
+                .amount(),
+            created_at: Some(time),
+            updated_at: Some(time),
+        })
+        .collect();
+
+    if !new_favorites.is_empty() {
+        // Unordered, so one bad document doesn't stop the ones after it
+        let outcomes = match collection.insert_many(&new_favorites).ordered(false).await {
+            Ok(_) => vec![BulkFavoriteStatus::Added; new_favorites.len()],
+            Err(err) => {
+                eprintln!("Failed to insert favorites: {:?}", err);
+                insert_outcomes(&err, new_favorites.len())
+            }
+        };
+        let added: Vec<ObjectId> = new_favorites
+            .iter()
+            .zip(&outcomes)
+            .filter(|(_, outcome)| **outcome == BulkFavoriteStatus::Added)
+            .map(|(favorite, _)| favorite.itinerary_id)
+            .collect();
+        adjust_favorite_counts(&client, &added, 1).await;
+
+        // `new_favorites` holds the `Added` results, in order
+        let planned = results
+            .iter_mut()
+            .filter(|result| result.status == BulkFavoriteStatus::Added);
+        for (result, outcome) in planned.zip(outcomes) {
+            result.status = outcome;
+        }
+    }
+
+    let count = |status: BulkFavoriteStatus| results.iter().filter(|r| r.status == status).count();
+    let added = count(BulkFavoriteStatus::Added);
+    let failed = count(BulkFavoriteStatus::Failed);
+    HttpResponse::Ok().json(json!({
+        "status": if added == results.len() { "success" } else if added > 0 { "partial" } else { "none_added" },
+        "added": added,
+        "skipped": results.len() - added - failed,
+        "failed": failed,
+        "results": results,
+    }))
+}
+
+/*
+    /api/account/{id}/favorites
+    All of them unless ?page= or ?limit= is given; ?envelope=true adds the paging
+*/
 pub async fn get_favorites(
+    req: HttpRequest,
     data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
     claims: Claims,
     path: web::Path<(String,)>,
+    query: web::Query<ListQuery>,
 ) -> impl Responder {
-    if path.into_inner().0 != claims.user_id {
-        return HttpResponse::Forbidden().json(json!({"error": "Forbidden"}));
+    if let Err(response) = owner_only(&claims, &path.into_inner().0) {
+        return *response;
     }
 
     let client = data.into_inner();
@@ -121,7 +371,27 @@ pub async fn get_favorites(
         "user_id": ObjectId::parse_str(&claims.user_id).unwrap(),
     };
 
-    match collection.find(filter).await {
+    let page = query.page(DEFAULT_PAGE_SIZE);
+    let total = if query.envelope {
+        match collection.count_documents(filter.clone()).await {
+            Ok(total) => total,
+            Err(err) => {
+                eprintln!("Error counting favorites: {:?}", err);
+                return HttpResponse::InternalServerError().json(json!({"error": "Failed to fetch favorites"}));
+            }
+        }
+    } else {
+        0
+    };
+
+    // Paged in the order they were favorited
+    let find = collection.find(filter).sort(doc! { "_id": 1 }).skip(page.skip());
+    let find = match page.limit {
+        Some(limit) => find.limit(limit),
+        None => find,
+    };
+
+    match find.await {
         Ok(cursor) => {
             let results = cursor.try_collect::<Vec<Favorite>>().await;
             match results {
@@ -150,13 +420,17 @@ pub async fn get_favorites(
                             {
                                 Ok(mut featured_itineraries) => {
                                     // Fetch images for each itinerary
-                                    featured_itineraries = get_images(featured_itineraries).await;
+                                    featured_itineraries = get_images(featured_itineraries, &Storage::new(config.storage.clone())).await;
+                                    let units = unit_system(&req, &client, None).await;
+                                    for itinerary in &mut featured_itineraries {
+                                        itinerary.description = units::render(&itinerary.description, units);
+                                    }
                                     
                                     // Populate each itinerary to include person_cost
                                     let mut populated_itineraries = Vec::new();
                                     
                                     for itinerary in featured_itineraries.clone() {
-                                        match itinerary.populate(&client).await {
+                                        match itinerary.populate(&client, config.activity_bucket.as_deref()).await {
                                             Ok(mut populated) => {
                                                 // Populate images from activities if no itinerary images exist
                                                 populated.populate_images_from_activities();
@@ -169,11 +443,13 @@ pub async fn get_favorites(
                                         }
                                     }
                                     
+                                    // Served the same under every API version
+                                    let version = ResponseVersion::V1;
                                     if !populated_itineraries.is_empty() {
-                                        HttpResponse::Ok().json(populated_itineraries)
+                                        page.respond(version, query.envelope, &populated_itineraries, total)
                                     } else {
                                         // Fallback to original itineraries if population failed
-                                        HttpResponse::Ok().json(featured_itineraries)
+                                        page.respond(version, query.envelope, &featured_itineraries, total)
                                     }
                                 }
                                 Err(err) => {
@@ -202,3 +478,63 @@ pub async fn get_favorites(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_plan_reports_each_id() {
+        let existing = ObjectId::new();
+        let favorited = ObjectId::new();
+        let missing = ObjectId::new();
+        let requested = vec![
+            existing.to_hex(),
+            favorited.to_hex(),
+            missing.to_hex(),
+            "not-an-id".to_string(),
+            existing.to_hex(),
+        ];
+
+        let results = plan_bulk_favorites(
+            &requested,
+            &HashSet::from([existing, favorited]),
+            &HashSet::from([favorited]),
+        );
+        let statuses: Vec<BulkFavoriteStatus> = results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                BulkFavoriteStatus::Added,
+                BulkFavoriteStatus::AlreadyFavorited,
+                BulkFavoriteStatus::NotFound,
+                BulkFavoriteStatus::InvalidId,
+                BulkFavoriteStatus::Duplicate,
+            ]
+        );
+        assert_eq!(results[3].itinerary_id, "not-an-id");
+    }
+
+    #[test]
+    fn test_failed_bulk_insert_reports_each_favorite() {
+        let partial: InsertManyError = bson::from_document(doc! {
+            "writeErrors": [
+                { "index": 1, "code": 11000, "errmsg": "E11000 duplicate key error" },
+                { "index": 2, "code": 121, "errmsg": "Document failed validation" },
+            ]
+        })
+        .unwrap();
+        assert_eq!(
+            insert_outcomes(&ErrorKind::InsertMany(partial).into(), 4),
+            vec![
+                BulkFavoriteStatus::Added,
+                BulkFavoriteStatus::AlreadyFavorited,
+                BulkFavoriteStatus::Failed,
+                BulkFavoriteStatus::Added,
+            ]
+        );
+
+        let unreachable = std::io::ErrorKind::ConnectionReset.into();
+        assert_eq!(insert_outcomes(&unreachable, 2), vec![BulkFavoriteStatus::Failed; 2]);
+    }
+}
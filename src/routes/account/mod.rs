@@ -1,11 +1,236 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+use crate::middleware::auth::{AuthMiddleware, Claims};
+
 pub mod account_info;
+pub mod api_tokens;
 pub mod auth;
 pub mod bookings;
 pub mod email_verification;
 pub mod facebook_auth;
 pub mod favorites;
 pub mod google_auth;
+pub mod notifications;
+pub mod oauth_link;
 pub mod payment_methods;
 pub mod payment_methods_update;
+pub mod recent_searches;
+pub mod recently_viewed;
 pub mod role_management;
+pub mod security_events;
 pub mod transactions;
+pub mod trip_notes;
+
+/// Allow only the owner of the account in an `/account/{id}` path. Anyone else's
+/// account gets a 404, the same as a missing resource, so callers can't learn
+/// which ids belong to other users' bookings, favorites or payment methods.
+pub(crate) fn owner_only(claims: &Claims, user_id: &str) -> Result<(), Box<HttpResponse>> {
+    if user_id == claims.user_id {
+        Ok(())
+    } else {
+        Err(Box::new(HttpResponse::NotFound().json(json!({ "error": "Not found" }))))
+    }
+}
+
+/// Protected account routes, all scoped to `/account/{id}`
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/account")
+            .wrap(AuthMiddleware)
+            .route("/{id}", web::get().to(account_info::get_personal_information))
+            .route("/{id}", web::put().to(account_info::update_personal_information))
+            .route("/{id}", web::delete().to(account_info::delete_account))
+            .route("/{id}/export", web::get().to(account_info::export_account_data))
+            .route("/{id}/favorites", web::get().to(favorites::get_favorites))
+            // Before `/{id}/favorites/{itinerary_id}` so "bulk" isn't taken as an id
+            .route(
+                "/{id}/favorites/bulk",
+                web::post().to(favorites::add_favorites_bulk),
+            )
+            .route(
+                "/{id}/favorites/{itinerary_id}",
+                web::post().to(favorites::add_favorite),
+            )
+            .route(
+                "/{id}/favorites/{itinerary_id}",
+                web::delete().to(favorites::remove_favorite),
+            )
+            .route("/{id}/bookings", web::get().to(bookings::get_all_bookings))
+            .route(
+                "/{id}/bookings/{booking_id}",
+                web::get().to(bookings::get_booking_by_id),
+            )
+            .route(
+                "/{id}/bookings/itinerary/{itinerary_id}",
+                web::get().to(bookings::get_booking),
+            )
+            .route(
+                "/{id}/bookings/itinerary/{itinerary_id}",
+                web::post().to(bookings::add_booking),
+            )
+            .route(
+                "/{id}/bookings/itinerary/{itinerary_id}",
+                web::delete().to(bookings::remove_booking),
+            )
+            .route(
+                "/{id}/bookings/itinerary/{itinerary_id}/payment",
+                web::put().to(bookings::update_booking_payment),
+            )
+            .route(
+                "/{id}/bookings/itinerary/{itinerary_id}/with-payment",
+                web::post().to(bookings::add_booking_with_payment),
+            )
+            .route(
+                "/{id}/bookings/{booking_id}/cancel",
+                web::post().to(bookings::cancel_booking_with_refund),
+            )
+            .route(
+                "/{id}/bookings/{booking_id}/reschedule",
+                web::put().to(bookings::reschedule_booking),
+            )
+            .route(
+                "/{id}/bookings/{booking_id}/special-requests",
+                web::put().to(bookings::update_special_requests),
+            )
+            .route(
+                "/{id}/bookings/{booking_id}/notes",
+                web::get().to(trip_notes::get_trip_notes),
+            )
+            .route(
+                "/{id}/bookings/{booking_id}/notes",
+                web::put().to(trip_notes::put_trip_notes),
+            )
+            .route(
+                "/{id}/payment-methods",
+                web::get().to(payment_methods::get_payment_methods),
+            )
+            .route(
+                "/{id}/payment-methods",
+                web::post().to(payment_methods::add_payment_method),
+            )
+            .route("/{id}/transactions", web::get().to(transactions::get_transactions))
+            .route(
+                "/{id}/security-events",
+                web::get().to(security_events::get_security_events),
+            )
+            .route(
+                "/{id}/recent-searches",
+                web::get().to(recent_searches::get_recent_searches),
+            )
+            .route(
+                "/{id}/recent-searches/{fingerprint}/rerun",
+                web::post().to(recent_searches::rerun_recent_search),
+            )
+            .route(
+                "/{id}/recently-viewed",
+                web::get().to(recently_viewed::get_recently_viewed),
+            )
+            .route(
+                "/{id}/notifications",
+                web::get().to(notifications::get_notification_preferences),
+            )
+            .route(
+                "/{id}/notifications",
+                web::put().to(notifications::update_notification_preferences),
+            )
+            .route("/{id}/api-tokens", web::post().to(api_tokens::create_api_token))
+            .route("/{id}/api-tokens", web::get().to(api_tokens::list_api_tokens))
+            .route(
+                "/{id}/api-tokens/{token_id}",
+                web::delete().to(api_tokens::revoke_api_token),
+            )
+            .route(
+                "/{id}/customer",
+                web::post().to(payment_methods::get_or_create_customer),
+            )
+            .route(
+                "/{id}/payment-methods/{pm_id}",
+                web::delete().to(payment_methods::remove_payment_method),
+            )
+            .route(
+                "/{id}/payment-methods/attach",
+                web::post().to(payment_methods::attach_payment_method),
+            )
+            .route(
+                "/{id}/payment-methods/detach",
+                web::post() // Using post to send data in body
+                    .to(payment_methods::detach_payment_method),
+            )
+            .route(
+                "/{id}/update-customer-id",
+                web::post().to(payment_methods_update::update_customer_id),
+            )
+            .route(
+                "/{id}/profile-picture",
+                web::post().to(account_info::upload_profile_pic),
+            )
+            .service(
+                web::scope("/{id}/email-verifications")
+                    .route(
+                        "",
+                        web::post().to(email_verification::create_user_email_verification),
+                    )
+                    .route(
+                        "",
+                        web::get().to(email_verification::get_user_email_verifications),
+                    )
+                    .route(
+                        "/{verification_id}",
+                        web::put().to(email_verification::verify_user_email_code),
+                    ),
+            ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{app_config, unreachable_client};
+    use actix_web::{body::to_bytes, http::StatusCode, Responder};
+    use mongodb::bson::oid::ObjectId;
+
+    #[actix_rt::test]
+    async fn test_other_accounts_look_missing() {
+        // Ownership is checked before any query runs
+        let client = unreachable_client().await;
+        let config = web::Data::new(app_config("test"));
+        let claims = Claims::test(ObjectId::new());
+        let other = ObjectId::new().to_hex();
+        let resource = ObjectId::new().to_hex();
+        let req = actix_web::test::TestRequest::default().to_http_request();
+
+        let responses = vec![
+            bookings::get_booking_by_id(client.clone(), web::Path::from((other.clone(), resource.clone())), claims.clone())
+                .await
+                .respond_to(&req)
+                .map_into_boxed_body(),
+            bookings::get_booking(client.clone(), web::Path::from((other.clone(), resource.clone())), claims.clone())
+                .await
+                .respond_to(&req)
+                .map_into_boxed_body(),
+            favorites::get_favorites(req.clone(), client.clone(), config.clone(), claims.clone(), web::Path::from((other.clone(),)), web::Query(Default::default()))
+                .await
+                .respond_to(&req)
+                .map_into_boxed_body(),
+            favorites::add_favorite(client.clone(), web::Path::from((other.clone(), resource.clone())), claims.clone())
+                .await
+                .respond_to(&req)
+                .map_into_boxed_body(),
+            payment_methods::remove_payment_method(client.clone(), config, web::Path::from((other.clone(), resource)), claims.clone())
+                .await
+                .respond_to(&req)
+                .map_into_boxed_body(),
+            email_verification::get_user_email_verifications(client, web::Path::from(other), claims)
+                .await
+                .respond_to(&req)
+                .map_into_boxed_body(),
+        ];
+
+        for response in responses {
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let body = to_bytes(response.into_body()).await.unwrap();
+            assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap(), json!({ "error": "Not found" }));
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::Client;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::routes::account::owner_only;
+use crate::models::account::{NotificationPreferences, User};
+
+/// Parse the account id from the path, allowing only the account owner
+fn owned_user_id(claims: &Claims, user_id: &str) -> Result<ObjectId, Box<HttpResponse>> {
+    owner_only(claims, user_id)?;
+    ObjectId::parse_str(user_id).map_err(|_| Box::new(HttpResponse::BadRequest().body("Invalid user ID")))
+}
+
+async fn find_user(client: &Client, user_id: ObjectId) -> Result<User, HttpResponse> {
+    let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
+    match collection.find_one(doc! { "_id": user_id }).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().body("User not found")),
+        Err(e) => {
+            eprintln!("Failed to find user: {:?}", e);
+            Err(HttpResponse::InternalServerError().body("Failed to find user"))
+        }
+    }
+}
+
+/*
+    /api/account/{id}/notifications
+*/
+pub async fn get_notification_preferences(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+) -> impl Responder {
+    let user_id = match owned_user_id(&claims, &path.into_inner().0) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+
+    match find_user(&data, user_id).await {
+        Ok(user) => HttpResponse::Ok().json(user.effective_notification_preferences()),
+        Err(response) => response,
+    }
+}
+
+/*
+    /api/account/{id}/notifications
+    Replaces the preferences; omitted switches fall back to their defaults.
+*/
+pub async fn update_notification_preferences(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+    input: web::Json<NotificationPreferences>,
+) -> impl Responder {
+    let user_id = match owned_user_id(&claims, &path.into_inner().0) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+    let preferences = input.into_inner();
+
+    let user = match find_user(&data, user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let users: mongodb::Collection<User> = data.database("Account").collection("Users");
+    let update = doc! {
+        "$set": {
+            "notification_preferences": mongodb::bson::to_bson(&preferences).unwrap(),
+            "updated_at": mongodb::bson::to_bson(&Utc::now()).unwrap(),
+        }
+    };
+    if let Err(e) = users.update_one(doc! { "_id": user_id }, update).await {
+        eprintln!("Failed to update notification preferences: {:?}", e);
+        return HttpResponse::InternalServerError().body("Failed to update notification preferences");
+    }
+
+    // The newsletter is marketing email, so keep an existing subscription in step
+    let newsletter: mongodb::Collection<Document> =
+        data.database("Travelers").collection("Newsletter");
+    if let Err(e) = newsletter
+        .update_many(
+            doc! { "email": &user.email },
+            doc! { "$set": { "subscribed": preferences.email.marketing } },
+        )
+        .await
+    {
+        eprintln!("Failed to sync newsletter subscription: {:?}", e);
+    }
+
+    println!("🔔 Notification preferences updated for user {}", user_id);
+    HttpResponse::Ok().json(preferences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::unreachable_client;
+    use crate::models::account::Notification;
+    use actix_web::http::StatusCode;
+
+    fn user(legacy: Option<Notification>) -> User {
+        User {
+            notification: legacy,
+            ..User::test("traveler@example.com")
+        }
+    }
+
+    #[test]
+    fn test_defaults_are_transactional_on_marketing_off() {
+        let preferences = user(None).effective_notification_preferences();
+        assert!(preferences.email.booking_updates);
+        assert!(preferences.email.reminders);
+        assert!(preferences.email.price_alerts);
+        assert!(!preferences.email.marketing);
+        assert_eq!(preferences.sms, Default::default());
+
+        // Switches left out of an update take their defaults
+        let preferences: NotificationPreferences =
+            serde_json::from_value(serde_json::json!({ "email": { "marketing": true } })).unwrap();
+        assert!(preferences.email.marketing);
+        assert!(preferences.email.booking_updates);
+        assert!(!preferences.sms.booking_updates);
+    }
+
+    #[test]
+    fn test_stored_preferences_win_over_legacy_switches() {
+        let legacy = Notification {
+            account_activities: false,
+            reminders: true,
+            travel_tips: false,
+            special_offers: true,
+            newsletter: false,
+            sms: true,
+        };
+        let mut user = user(Some(legacy));
+        let carried_over = user.effective_notification_preferences();
+        assert!(!carried_over.email.booking_updates);
+        assert!(carried_over.email.marketing);
+        assert!(carried_over.sms.booking_updates);
+
+        user.notification_preferences = Some(NotificationPreferences::default());
+        assert_eq!(user.effective_notification_preferences(), NotificationPreferences::default());
+    }
+
+    #[actix_rt::test]
+    async fn test_preferences_are_limited_to_the_account_owner() {
+        // Ownership is checked before any query runs
+        let response = get_notification_preferences(
+            unreachable_client().await,
+            Claims::test(ObjectId::new()),
+            web::Path::from((ObjectId::new().to_hex(),)),
+        )
+        .await
+        .respond_to(&actix_web::test::TestRequest::default().to_http_request());
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
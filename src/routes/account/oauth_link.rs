@@ -0,0 +1,263 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::models::account::{LinkedAccount, User, UserRole};
+use crate::models::security_event::SecurityEventType;
+use crate::routes::account::auth::{generate_token, TokenResponse};
+use crate::services::oauth_link_service::{
+    normalize_email, oauth_signin, LinkProof, OAuthLinkError, OAuthLinkService, OAuthSignin,
+};
+use crate::services::security_event_service::{ClientFingerprint, SecurityEventQueue};
+
+/// Who the provider says signed in
+pub struct OAuthProfile {
+    pub provider: &'static str,
+    pub provider_id: String,
+    pub email: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+fn redirect(url: String) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish()
+}
+
+fn token_redirect(config: &AppConfig, user: &User, user_id: ObjectId) -> HttpResponse {
+    match generate_token(&config.jwt_secret, &user.email, user_id, user.role.as_ref()) {
+        Ok(token) => redirect(format!("{}/?token={}", config.frontend_url, token)),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to generate token"),
+    }
+}
+
+/// Finish an OAuth callback: sign in, create the account, or, for a password
+/// account the provider isn't linked to, send the user off to prove they own it
+pub async fn oauth_signin_redirect(
+    client: Arc<Client>,
+    config: &AppConfig,
+    profile: OAuthProfile,
+) -> HttpResponse {
+    let service = OAuthLinkService::new(client.clone());
+    let existing = match service.find_user_by_email(&profile.email).await {
+        Ok(existing) => existing,
+        Err(err) => {
+            eprintln!("Database error: {:?}", err);
+            return HttpResponse::InternalServerError().body("Database error");
+        }
+    };
+    let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
+    let now = Utc::now();
+
+    match (oauth_signin(existing.as_ref(), profile.provider, &profile.provider_id), existing) {
+        (OAuthSignin::SignIn { link }, Some(existing_user)) => {
+            let Some(user_id) = existing_user.id else {
+                return HttpResponse::InternalServerError().body("Failed to update user");
+            };
+            if link {
+                if let Err(err) = service
+                    .link_provider(user_id, profile.provider, &profile.provider_id)
+                    .await
+                {
+                    eprintln!("Failed to link {} account: {:?}", profile.provider, err);
+                    return HttpResponse::InternalServerError().body("Failed to update user");
+                }
+            }
+
+            let update = doc! {
+                "$set": {
+                    "last_signin": now.to_string(),
+                    "failed_signins": 0
+                }
+            };
+            if let Err(err) = collection.update_one(doc! { "_id": user_id }, update).await {
+                eprintln!("Failed to update user sign-in info: {:?}", err);
+                return HttpResponse::InternalServerError().body("Failed to update user");
+            }
+
+            token_redirect(config, &existing_user, user_id)
+        }
+        (OAuthSignin::RequireLink, Some(existing_user)) => {
+            match service
+                .start(&existing_user, profile.provider, &profile.provider_id, DateTime::now())
+                .await
+            {
+                Ok(token) => redirect(format!(
+                    "{}/?status=account_exists_requires_link&provider={}&link_token={}",
+                    config.frontend_url, profile.provider, token
+                )),
+                Err(err) => {
+                    eprintln!("Failed to start {} account link: {}", profile.provider, err);
+                    HttpResponse::InternalServerError().body("Failed to sign in")
+                }
+            }
+        }
+        _ => {
+            // No account has the email, so create one
+            let new_user = User {
+                id: None,
+                email: normalize_email(&profile.email),
+                // We don't set a password for users who sign in with a provider
+                password: bcrypt::hash("", bcrypt::DEFAULT_COST).unwrap_or("".to_string()),
+                customer_id: None,
+                first_name: profile.first_name,
+                last_name: profile.last_name,
+                phone_number: None,
+                phone_number_e164: None,
+                birth_date: None,
+                last_signin: Some(now),
+                last_signin_ip: None,
+                failed_signins: Some(0),
+                role: Some(UserRole::User),
+                company_id: None,
+                preferred_currency: None,
+                preferred_units: None,
+                email_verified: false,
+                email_verified_at: None,
+                email_suppressed: false,
+                linked_accounts: vec![LinkedAccount {
+                    provider: profile.provider.to_string(),
+                    provider_id: profile.provider_id,
+                    linked_at: now,
+                }],
+                notification: None,
+                notification_preferences: None,
+                profile_picture: None,
+                created_at: Some(now),
+                updated_at: Some(now),
+            };
+
+            match collection.insert_one(&new_user).await {
+                Ok(result) => match result.inserted_id.as_object_id() {
+                    Some(user_id) => token_redirect(config, &new_user, user_id),
+                    None => HttpResponse::InternalServerError().body("Failed to create user"),
+                },
+                Err(err) => {
+                    eprintln!("Failed to create user: {:?}", err);
+                    HttpResponse::InternalServerError().body("Failed to create user")
+                }
+            }
+        }
+    }
+}
+
+/// Proof of owning the account: its `password`, or a `code` sent to its email by
+/// `POST /email-verifications` along with that verification's id
+#[derive(Debug, Deserialize)]
+pub struct LinkOAuthRequest {
+    pub link_token: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub verification_id: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl LinkOAuthRequest {
+    fn proof(&self) -> Option<LinkProof> {
+        if let Some(password) = &self.password {
+            return Some(LinkProof::Password(password.clone()));
+        }
+        let verification_id = ObjectId::parse_str(self.verification_id.as_deref()?).ok()?;
+        Some(LinkProof::EmailCode {
+            verification_id,
+            code: self.code.clone()?,
+        })
+    }
+}
+
+fn link_error(err: &OAuthLinkError) -> HttpResponse {
+    let body = |error: &str| json!({ "error": error, "message": err.to_string() });
+    match err {
+        OAuthLinkError::LinkNotFound => HttpResponse::NotFound().json(body("link_not_found")),
+        OAuthLinkError::InvalidProof { attempts_remaining } => {
+            let mut body = body("invalid_proof");
+            body["attempts_remaining"] = json!(attempts_remaining);
+            HttpResponse::Unauthorized().json(body)
+        }
+        OAuthLinkError::TooManyAttempts => {
+            HttpResponse::TooManyRequests().json(body("too_many_attempts"))
+        }
+        OAuthLinkError::UserNotFound => HttpResponse::NotFound().json(body("user_not_found")),
+        OAuthLinkError::DatabaseError(e) => {
+            eprintln!("Failed to link OAuth account: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "database_error",
+                "message": "Failed to link account"
+            }))
+        }
+    }
+}
+
+/* /api/auth/link-oauth
+    Finish an OAuth sign-in that was redirected with
+    `status=account_exists_requires_link`, proving ownership of the existing
+    account. Links the provider and returns a token for the account.
+
+    Request:
+    {
+        "link_token": "the link_token from the redirect",
+        "password": "the account's password"
+    }
+    or, with a code from POST /email-verifications for the account's email:
+    {
+        "link_token": "...",
+        "verification_id": "...",
+        "code": "123456"
+    }
+
+    Response: { "auth_token": "..." }
+    401 with `attempts_remaining` on a wrong password or code, 429 once the link
+    has taken too many, 404 when it has expired.
+*/
+pub async fn link_oauth(
+    req: HttpRequest,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    security_events: web::Data<SecurityEventQueue>,
+    input: web::Json<LinkOAuthRequest>,
+) -> impl Responder {
+    let Some(proof) = input.proof() else {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "proof_required",
+            "message": "Send the account's password, or a verification_id and code"
+        }));
+    };
+
+    let service = OAuthLinkService::new(data.into_inner().as_ref().clone());
+    let link = match service.pending(&input.link_token, DateTime::now()).await {
+        Ok(link) => link,
+        Err(err) => return link_error(&err),
+    };
+
+    let fingerprint = ClientFingerprint::from_request(&req);
+    match service
+        .complete(&link, &proof, config.email_verification_max_attempts)
+        .await
+    {
+        Ok(user) => {
+            security_events.record(link.user_id, SecurityEventType::OauthLinked, &fingerprint);
+            match generate_token(&config.jwt_secret, &user.email, link.user_id, user.role.as_ref()) {
+                Ok(token) => HttpResponse::Ok().json(TokenResponse { auth_token: token }),
+                Err(_) => HttpResponse::InternalServerError().body("Token generation failed"),
+            }
+        }
+        Err(err) => {
+            if matches!(
+                err,
+                OAuthLinkError::InvalidProof { .. } | OAuthLinkError::TooManyAttempts
+            ) {
+                security_events.record(link.user_id, SecurityEventType::OauthLinkFailed, &fingerprint);
+            }
+            link_error(&err)
+        }
+    }
+}
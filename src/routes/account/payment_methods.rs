@@ -1,13 +1,16 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use bson::{doc, oid::ObjectId};
 use mongodb::Client;
 use serde::{Deserialize, Serialize};
 use std::{str::FromStr, sync::Arc};
 
 use crate::{
+    config::AppConfig,
     middleware::auth::Claims,
-    models::account::User,
+    routes::account::owner_only,
+    models::{account::User, security_event::SecurityEventType},
     services::{
+        security_event_service::{ClientFingerprint, SecurityEventQueue},
         payment::interface::{CustomerError, PaymentOperations},
         stripe::{models::customer::CustomerData, provider::StripeProvider},
     },
@@ -82,6 +85,7 @@ async fn update_user_customer_id(
 
 pub async fn add_payment_method(
     data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
     input: web::Json<CustomerData>,
     claims: Claims,
     path: web::Path<(String,)>,
@@ -89,14 +93,14 @@ pub async fn add_payment_method(
     let customer = input.into_inner();
 
     let user_id = path.into_inner().0;
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = data.into_inner();
     let collection: mongodb::Collection<User> = client.database("Account").collection("Users");
 
-    let stripe_op = StripeProvider::new(std::env::var("STRIPE_SECRET_KEY").unwrap());
+    let stripe_op = StripeProvider::new(config.stripe_secret_key.as_str());
 
     let filter = doc! { "_id": ObjectId::from_str(&user_id).unwrap() };
 
@@ -150,8 +154,12 @@ pub async fn add_payment_method(
     }
 }
 
-pub async fn get_payment_methods(data: web::Data<Arc<Client>>, claims: Claims) -> impl Responder {
-    let stripe_op = StripeProvider::new(std::env::var("STRIPE_SECRET_KEY").unwrap());
+pub async fn get_payment_methods(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    claims: Claims,
+) -> impl Responder {
+    let stripe_op = StripeProvider::new(config.stripe_secret_key.as_str());
     let client = data.into_inner();
 
     let customer_id = match get_customer_id(&client, claims.user_id.clone()).await {
@@ -173,6 +181,7 @@ pub async fn get_payment_methods(data: web::Data<Arc<Client>>, claims: Claims) -
 
 pub async fn get_or_create_customer(
     data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
     path: web::Path<String>,
     claims: Claims,
 ) -> impl Responder {
@@ -183,12 +192,12 @@ pub async fn get_or_create_customer(
     println!("Claim: {:?}", claims.user_id);
 
     // Verify user has permission
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let client = data.into_inner();
-    let stripe_op = StripeProvider::new(std::env::var("STRIPE_SECRET_KEY").unwrap());
+    let stripe_op = StripeProvider::new(config.stripe_secret_key.as_str());
 
     // First check if customer already exists in our database
     let existing_customer_id = get_customer_id(&client, user_id.clone()).await;
@@ -234,7 +243,7 @@ pub async fn get_or_create_customer(
             f
         }
     });
-    customer_data.phone = user.phone_number.clone();
+    customer_data.phone = user.phone_number_e164.clone().or_else(|| user.phone_number.clone());
 
     // Create customer in Stripe
     let new_customer = match stripe_op.create_customer(customer_data).await {
@@ -271,14 +280,15 @@ pub async fn get_or_create_customer(
 
 pub async fn remove_payment_method(
     data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
     path: web::Path<(String, String)>,
     claims: Claims,
 ) -> impl Responder {
     let (user_id, payment_id) = path.into_inner();
 
     // Verify user has permission
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     // Get customer_id from the database
@@ -290,7 +300,7 @@ pub async fn remove_payment_method(
         }
     };
 
-    let stripe_op = StripeProvider::new(std::env::var("STRIPE_SECRET_KEY").unwrap());
+    let stripe_op = StripeProvider::new(config.stripe_secret_key.as_str());
 
     match stripe_op
         .detach_payment_method(customer_id, payment_id)
@@ -305,16 +315,19 @@ pub async fn remove_payment_method(
 }
 
 pub async fn attach_payment_method(
+    req: HttpRequest,
+    security_events: web::Data<SecurityEventQueue>,
+    config: web::Data<AppConfig>,
     input: web::Json<AttachPaymentMethod>,
     claims: Claims,
     path: web::Path<String>,
 ) -> impl Responder {
     let user_id = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
-    let stripe_op = StripeProvider::new(std::env::var("STRIPE_SECRET_KEY").unwrap());
+    let stripe_op = StripeProvider::new(config.stripe_secret_key.as_str());
     let customer_id = &input.customer_id;
     let payment_id = &input.payment_id;
     let _default = input.default;
@@ -323,7 +336,18 @@ pub async fn attach_payment_method(
         .attach_payment_method(customer_id.to_string(), payment_id.to_string())
         .await
     {
-        Ok(res) => return res,
+        Ok(res) => {
+            if let (true, Ok(user_object_id)) =
+                (res.status().is_success(), ObjectId::from_str(&user_id))
+            {
+                security_events.record(
+                    user_object_id,
+                    SecurityEventType::PaymentMethodAdded,
+                    &ClientFingerprint::from_request(&req),
+                );
+            }
+            res
+        }
         Err(_) => {
             return HttpResponse::InternalServerError().body("Failed to attach payment method")
         }
@@ -331,16 +355,17 @@ pub async fn attach_payment_method(
 }
 
 pub async fn detach_payment_method(
+    config: web::Data<AppConfig>,
     input: web::Json<DetachPaymentMethod>,
     claims: Claims,
     path: web::Path<String>,
 ) -> impl Responder {
     let user_id = path.into_inner();
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
-    let stripe_op = StripeProvider::new(std::env::var("STRIPE_SECRET_KEY").unwrap());
+    let stripe_op = StripeProvider::new(config.stripe_secret_key.as_str());
     let customer_id = &input.customer_id;
     let payment_method_id = &input.payment_method_id;
 
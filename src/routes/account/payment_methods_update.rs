@@ -6,6 +6,7 @@ use std::{str::FromStr, sync::Arc};
 
 use crate::{
     middleware::auth::Claims,
+    routes::account::owner_only,
     models::account::User,
 };
 
@@ -25,8 +26,8 @@ pub async fn update_customer_id(
     let user_id = path.into_inner();
     
     // Check authorization - user can only update their own record
-    if user_id != claims.user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
 
     let customer_id = input.into_inner().customer_id;
@@ -0,0 +1,132 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use mongodb::Client;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::middleware::typed_json::TypedJson;
+use crate::routes::account::owner_only;
+use crate::routes::itinerary::{search_itineraries_endpoint, ViewQuery};
+use crate::routes::versioning::ResponseVersion;
+use crate::services::feature_flags::Flags;
+use crate::services::fx_service::FxRates;
+use crate::services::image_fallback::StockImages;
+use crate::services::recent_search_service::{
+    RecentSearchService, DEFAULT_RECENT_SEARCHES, MAX_RECENT_SEARCHES,
+};
+use crate::services::write_behind::WriteBehindQueue;
+
+#[derive(Deserialize)]
+pub struct RecentSearchesQuery {
+    pub limit: Option<i64>,
+}
+
+/// Parse the account id from the path, allowing only the account owner
+fn owned_user_id(claims: &Claims, user_id: &str) -> Result<ObjectId, Box<HttpResponse>> {
+    owner_only(claims, user_id)?;
+    ObjectId::parse_str(user_id).map_err(|_| Box::new(HttpResponse::BadRequest().body("Invalid user ID")))
+}
+
+/*
+    /api/account/{id}/recent-searches?limit=5
+    The traveler's most recent distinct searches, newest first. Repeating a search
+    moves it to the top rather than listing it twice.
+*/
+pub async fn get_recent_searches(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+    query: web::Query<RecentSearchesQuery>,
+) -> impl Responder {
+    let user_id = match owned_user_id(&claims, &path.into_inner().0) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RECENT_SEARCHES)
+        .clamp(1, MAX_RECENT_SEARCHES);
+
+    match RecentSearchService::new(data.into_inner().as_ref().clone())
+        .recent(user_id, limit)
+        .await
+    {
+        Ok(searches) => {
+            let searches: Vec<_> = searches
+                .into_iter()
+                .map(|recent| {
+                    serde_json::json!({
+                        "fingerprint": recent.fingerprint,
+                        "summary": recent.summary,
+                        "search": recent.search,
+                        "search_count": recent.search_count,
+                        "last_searched_at": recent.last_searched_at.try_to_rfc3339_string().ok(),
+                    })
+                })
+                .collect();
+            HttpResponse::Ok().json(serde_json::json!({ "searches": searches }))
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch recent searches: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch recent searches")
+        }
+    }
+}
+
+/*
+    /api/account/{id}/recent-searches/{fingerprint}/rerun
+    Runs a recent search again through /api/itineraries/search, taking the same
+    query parameters, and moves it to the top of the list.
+*/
+#[allow(clippy::too_many_arguments)]
+pub async fn rerun_recent_search(
+    req: HttpRequest,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    view: web::Query<ViewQuery>,
+    fx: web::Data<FxRates>,
+    stock: Option<web::Data<StockImages>>,
+    writes: web::Data<WriteBehindQueue>,
+    flags: web::Data<Flags>,
+    version: ResponseVersion,
+    claims: Claims,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (user_id, fingerprint) = path.into_inner();
+    let user_id = match owned_user_id(&claims, &user_id) {
+        Ok(id) => id,
+        Err(response) => return *response,
+    };
+
+    let recent = match RecentSearchService::new(data.get_ref().clone())
+        .find(user_id, &fingerprint)
+        .await
+    {
+        Ok(Some(recent)) => recent,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({ "error": "Recent search not found" }))
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch recent search: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch recent search");
+        }
+    };
+
+    search_itineraries_endpoint(
+        req.clone(),
+        data,
+        config,
+        view,
+        fx,
+        stock,
+        writes,
+        flags,
+        version,
+        TypedJson(recent.search),
+    )
+    .await
+    .respond_to(&req)
+    .map_into_boxed_body()
+}
@@ -0,0 +1,87 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use mongodb::Client;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::routes::account::owner_only;
+use crate::routes::itinerary::{itinerary_summaries, unit_system};
+use crate::services::recently_viewed_service::{
+    RecentlyViewedService, DEFAULT_RECENTLY_VIEWED, MAX_RECENTLY_VIEWED,
+};
+
+#[derive(Deserialize)]
+pub struct RecentlyViewedQuery {
+    pub limit: Option<i64>,
+}
+
+/*
+    /api/account/{id}/recently-viewed?limit=10
+    Itineraries the traveler opened while signed in, newest first, each once.
+    Each entry is the itinerary's summary view with when it was last viewed:
+    { "itineraries": [{ "viewed_at": "...", "view_count": 3, "itinerary": {...} }] }
+    Itineraries taken down or deleted since are left out.
+*/
+pub async fn get_recently_viewed(
+    req: HttpRequest,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+    query: web::Query<RecentlyViewedQuery>,
+) -> impl Responder {
+    let user_id = path.into_inner().0;
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let Ok(user_id) = ObjectId::parse_str(&user_id) else {
+        return HttpResponse::BadRequest().body("Invalid user ID");
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RECENTLY_VIEWED)
+        .clamp(1, MAX_RECENTLY_VIEWED);
+
+    let client = data.into_inner();
+    let views = match RecentlyViewedService::new(client.as_ref().clone())
+        .recent(user_id, limit)
+        .await
+    {
+        Ok(views) => views,
+        Err(e) => {
+            eprintln!("Failed to fetch recently viewed itineraries: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch recently viewed itineraries");
+        }
+    };
+
+    let ids: Vec<ObjectId> = views.iter().map(|view| view.itinerary_id).collect();
+    let units = unit_system(&req, &client, None).await;
+    let summaries = match itinerary_summaries(&client, &config, &ids, units).await {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            eprintln!("Failed to fetch recently viewed itineraries: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch recently viewed itineraries");
+        }
+    };
+
+    // Summaries come back in view order, less any that are gone
+    let mut summaries = summaries.into_iter().peekable();
+    let mut itineraries = Vec::new();
+    for view in views {
+        if summaries.peek().and_then(|item| item.id) != Some(view.itinerary_id) {
+            continue;
+        }
+        let Some(item) = summaries.next() else {
+            break;
+        };
+        itineraries.push(serde_json::json!({
+            "viewed_at": view.viewed_at.try_to_rfc3339_string().ok(),
+            "view_count": view.view_count,
+            "itinerary": item,
+        }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "itineraries": itineraries }))
+}
@@ -1,5 +1,5 @@
 use actix_web::{web, HttpResponse, Responder};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Bson, Document};
 use mongodb::bson::oid::ObjectId;
 use mongodb::Client;
 use serde::{Deserialize, Serialize};
@@ -11,6 +11,9 @@ use crate::models::account::UserRole;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateRoleRequest {
     pub role: UserRole,
+    /// Required when assigning the operator role
+    #[serde(default)]
+    pub company_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,6 +22,8 @@ pub struct UpdateRoleResponse {
     pub message: String,
     pub user_id: String,
     pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub company_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +55,7 @@ pub async fn update_user_role(
                 message: "Invalid user ID format".to_string(),
                 user_id: user_id_str,
                 role: format!("{:?}", input.role),
+                company_id: None,
             });
         }
     };
@@ -57,7 +63,14 @@ pub async fn update_user_role(
     let role_string = match input.role {
         UserRole::Admin => "admin",
         UserRole::User => "user",
+        UserRole::Operator => "operator",
     };
+    let company_id = input
+        .company_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string);
     
     // First check if the user exists and what format they have
     match collection.find_one(doc! { "_id": user_id }).await {
@@ -65,24 +78,26 @@ pub async fn update_user_role(
             println!("Found user: {:#?}", doc);
             
             // Figure out what format the role is stored in
-            let update = if doc.get_document("role").is_ok() {
+            let role_value = if doc.get_document("role").is_ok() {
                 // Role is stored as a document
                 println!("Role is stored as a BSON document");
-                doc! {
-                    "$set": {
-                        "role": match input.role {
-                            UserRole::Admin => doc! { "$serde_name": "admin" },
-                            UserRole::User => doc! { "$serde_name": "user" },
-                        }
-                    }
-                }
+                Bson::Document(doc! { "$serde_name": role_string })
             } else {
                 // Role is stored as a string or doesn't exist
                 println!("Role is stored as a string or doesn't exist");
-                doc! {
-                    "$set": {
-                        "role": role_string
-                    }
+                Bson::String(role_string.to_string())
+            };
+
+            let update = match role_update(role_value, &input.role, company_id.as_deref()) {
+                Ok(update) => update,
+                Err(message) => {
+                    return HttpResponse::BadRequest().json(UpdateRoleResponse {
+                        success: false,
+                        message: message.to_string(),
+                        user_id: user_id.to_hex(),
+                        role: role_string.to_string(),
+                        company_id: None,
+                    });
                 }
             };
             
@@ -97,6 +112,7 @@ pub async fn update_user_role(
                         message: format!("User role updated to {}", role_string),
                         user_id: user_id.to_hex(),
                         role: role_string.to_string(),
+                        company_id: company_id.clone(),
                     })
                 },
                 Err(err) => {
@@ -106,6 +122,7 @@ pub async fn update_user_role(
                         message: format!("Failed to update user role: {}", err),
                         user_id: user_id.to_hex(),
                         role: role_string.to_string(),
+                        company_id: None,
                     })
                 }
             }
@@ -116,6 +133,7 @@ pub async fn update_user_role(
                 message: "User not found".to_string(),
                 user_id: user_id.to_hex(),
                 role: role_string.to_string(),
+                company_id: None,
             })
         },
         Err(err) => {
@@ -124,11 +142,31 @@ pub async fn update_user_role(
                 message: format!("Database error: {}", err),
                 user_id: user_id.to_hex(),
                 role: role_string.to_string(),
+                company_id: None,
             })
         }
     }
 }
 
+/// Build the user update for a role change. Operators must belong to a company;
+/// any other role drops a previously assigned company.
+fn role_update(
+    role_value: Bson,
+    role: &UserRole,
+    company_id: Option<&str>,
+) -> Result<Document, &'static str> {
+    match (role, company_id) {
+        (UserRole::Operator, Some(company_id)) => Ok(doc! {
+            "$set": { "role": role_value, "company_id": company_id }
+        }),
+        (UserRole::Operator, None) => Err("company_id is required for the operator role"),
+        _ => Ok(doc! {
+            "$set": { "role": role_value },
+            "$unset": { "company_id": "" }
+        }),
+    }
+}
+
 // Helper function to dump a single user for debugging
 async fn dump_user_schema(client: &Client, email: &str) -> Result<(), mongodb::error::Error> {
     let collection = client.database("Account").collection::<mongodb::bson::Document>("Users");
@@ -223,4 +261,28 @@ pub async fn list_users_with_roles(data: web::Data<Arc<Client>>) -> impl Respond
             HttpResponse::InternalServerError().body("Failed to fetch users")
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operator_role_assigns_company() {
+        let update = role_update(Bson::from("operator"), &UserRole::Operator, Some("acme-tours")).unwrap();
+        let set = update.get_document("$set").unwrap();
+        assert_eq!(set.get_str("role").unwrap(), "operator");
+        assert_eq!(set.get_str("company_id").unwrap(), "acme-tours");
+    }
+
+    #[test]
+    fn test_operator_role_requires_company() {
+        assert!(role_update(Bson::from("operator"), &UserRole::Operator, None).is_err());
+    }
+
+    #[test]
+    fn test_other_roles_clear_company() {
+        let update = role_update(Bson::from("user"), &UserRole::User, Some("acme-tours")).unwrap();
+        assert!(update.get_document("$set").unwrap().get("company_id").is_none());
+        assert!(update.get_document("$unset").unwrap().contains_key("company_id"));
+    }
+}
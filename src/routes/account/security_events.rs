@@ -0,0 +1,75 @@
+use actix_web::{web, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use mongodb::Client;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{
+    middleware::auth::Claims, routes::account::owner_only,
+    services::security_event_service::SecurityEventService,
+};
+
+#[derive(Deserialize)]
+pub struct SecurityEventsQuery {
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+/*
+    /api/account/{id}/security-events
+*/
+pub async fn get_security_events(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String,)>,
+    query: web::Query<SecurityEventsQuery>,
+) -> impl Responder {
+    let user_id = path.into_inner().0;
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+
+    let user_object_id = match ObjectId::parse_str(&user_id) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid user ID"),
+    };
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let service = SecurityEventService::new(data.into_inner().as_ref().clone());
+    match service.list(user_object_id, page, limit).await {
+        Ok(result) => HttpResponse::Ok().json(serde_json::json!({
+            "events": result.events,
+            "page": page,
+            "limit": limit,
+            "total": result.total,
+        })),
+        Err(e) => {
+            eprintln!("Failed to fetch security events: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch security events")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::unreachable_client;
+    use actix_web::http::StatusCode;
+
+    #[actix_rt::test]
+    async fn test_listing_is_limited_to_the_account_owner() {
+        // Ownership is checked before any query runs
+        let response = get_security_events(
+            unreachable_client().await,
+            Claims::test(ObjectId::new()),
+            web::Path::from((ObjectId::new().to_hex(),)),
+            web::Query(SecurityEventsQuery { page: None, limit: None }),
+        )
+        .await
+        .respond_to(&actix_web::test::TestRequest::default().to_http_request());
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
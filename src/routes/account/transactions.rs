@@ -7,7 +7,9 @@ use serde::{Deserialize, Serialize};
 use stripe::{Charge, CustomerId, ListCharges, ListRefunds, Refund};
 
 use crate::{
+    db::mongo::primary_collection,
     middleware::auth::Claims,
+    routes::account::owner_only,
     models::bookings::PaymentStatus,
     models::{account::User, bookings::BookingDetails},
 };
@@ -54,8 +56,8 @@ pub async fn get_transactions(
     let user_id = path.into_inner();
     println!("\n\nUserId: {:?}", user_id);
 
-    if claims.user_id != user_id {
-        return HttpResponse::Forbidden().body("Forbidden");
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
     }
     // Get customer_id
     let mongodb_client = mongodb_data.into_inner();
@@ -93,7 +95,7 @@ pub async fn get_transactions(
                     (Ok(charges), Ok(refunds)) => {
                         // Get the user's bookings to filter transactions
                         let bookings_collection: mongodb::Collection<BookingDetails> =
-                            mongodb_client.database("Account").collection("Bookings");
+                            primary_collection(&mongodb_client, "Account", "Bookings");
 
                         let booking_filter = doc! { "user_id": object_id };
 
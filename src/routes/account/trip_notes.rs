@@ -0,0 +1,98 @@
+use actix_web::{web, HttpResponse, Responder};
+use bson::{oid::ObjectId, DateTime};
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::routes::account::owner_only;
+use crate::services::trip_notes_service::{TripNotesError, TripNotesService};
+
+#[derive(Debug, Deserialize)]
+pub struct TripNotesInput {
+    #[serde(default)]
+    pub text: String,
+    /// Day number to note; leaving a day out (or blank) removes its note
+    #[serde(default)]
+    pub days: BTreeMap<u32, String>,
+    /// The version being edited, 0 for notes never saved
+    pub version: u64,
+}
+
+fn notes_error(err: TripNotesError) -> HttpResponse {
+    match err {
+        TripNotesError::BookingNotFound => HttpResponse::NotFound().body("Booking not found"),
+        TripNotesError::VersionConflict(ref current) => HttpResponse::Conflict().json(json!({
+            "error": err.to_string(),
+            "notes": current.to_json()
+        })),
+        TripNotesError::TooLarge => HttpResponse::PayloadTooLarge().json(json!({ "error": err.to_string() })),
+        TripNotesError::DatabaseError(e) => {
+            eprintln!("Trip notes failed: {}", e);
+            HttpResponse::InternalServerError().body("Failed to save trip notes")
+        }
+        _ => HttpResponse::BadRequest().json(json!({ "error": err.to_string() })),
+    }
+}
+
+/*
+    /api/account/{id}/bookings/{booking_id}/notes
+
+    The traveler's own notes on a booking:
+    { "text": "...", "days": { "1": "..." }, "version": 3, "updated_at": "..." }.
+    Bookings without notes return empty ones at version 0. Only the traveler
+    can read them; admins impersonating them can't.
+*/
+pub async fn get_trip_notes(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (user_id, booking_id) = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let (user_id, booking_id) = match (ObjectId::parse_str(&user_id), ObjectId::parse_str(&booking_id)) {
+        (Ok(user_id), Ok(booking_id)) => (user_id, booking_id),
+        _ => return HttpResponse::BadRequest().body("Invalid booking ID format"),
+    };
+
+    match TripNotesService::new(data.get_ref().clone()).get(user_id, booking_id).await {
+        Ok(notes) => HttpResponse::Ok().json(notes.to_json()),
+        Err(err) => notes_error(err),
+    }
+}
+
+/*
+    /api/account/{id}/bookings/{booking_id}/notes
+
+    Replaces the notes, at any time, even after the trip. Send the `version`
+    that was read; if the notes were saved from somewhere else since, it's a
+    409 carrying the saved copy as `notes`. Text and day notes together can be
+    at most 20KB; control characters are dropped and null bytes refused.
+*/
+pub async fn put_trip_notes(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<(String, String)>,
+    input: web::Json<TripNotesInput>,
+) -> impl Responder {
+    let (user_id, booking_id) = path.into_inner();
+    if let Err(response) = owner_only(&claims, &user_id) {
+        return *response;
+    }
+    let (user_id, booking_id) = match (ObjectId::parse_str(&user_id), ObjectId::parse_str(&booking_id)) {
+        (Ok(user_id), Ok(booking_id)) => (user_id, booking_id),
+        _ => return HttpResponse::BadRequest().body("Invalid booking ID format"),
+    };
+
+    match TripNotesService::new(data.get_ref().clone())
+        .save(user_id, booking_id, input.version, &input.text, &input.days, DateTime::now())
+        .await
+    {
+        Ok(notes) => HttpResponse::Ok().json(notes.to_json()),
+        Err(err) => notes_error(err),
+    }
+}
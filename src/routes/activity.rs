@@ -1,23 +1,27 @@
 use actix_web::{web, HttpResponse, Responder};
+use chrono::{Datelike, NaiveDate, Utc};
 use futures::TryStreamExt;
 use mongodb::{
     bson::{doc, oid::ObjectId, Document},
     Client,
 };
+use serde::Deserialize;
 use std::sync::Arc;
 
+use crate::db::mongo::read_only_collection;
 use crate::models::activity::Activity;
+use crate::services::calendar;
 
 pub async fn get_activities(data: web::Data<Arc<Client>>) -> impl Responder {
     println!("GETTING ACTIVITIES");
 
     let client = data.into_inner();
-    let db = client.database("Options");
 
     // First get raw documents to prevent deserialization errors from blocking everything
-    let raw_collection = db.collection::<Document>("Activity");
+    let raw_collection = read_only_collection::<Document>(&client, "Options", "Activity");
 
-    match raw_collection.find(doc! {}).await {
+    // Duplicates merged into another activity are soft-deleted
+    match raw_collection.find(doc! { "merged_into": null }).await {
         Ok(mut cursor) => {
             let mut successful_activities = Vec::new();
             let mut error_count = 0;
@@ -100,3 +104,66 @@ pub async fn get_activities(data: web::Data<Arc<Client>>) -> impl Responder {
         }
     }
 }
+
+#[derive(Deserialize)]
+pub struct AvailabilityQuery {
+    /// First date to report, YYYY-MM-DD. Defaults to today.
+    pub start: Option<NaiveDate>,
+    /// Number of days to report. Defaults to 30, capped at a year.
+    pub days: Option<u32>,
+}
+
+/*
+    /api/activities/{id}/availability
+*/
+pub async fn get_availability(
+    path: web::Path<String>,
+    query: web::Query<AvailabilityQuery>,
+    data: web::Data<Arc<Client>>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(path.into_inner().as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid activity id"),
+    };
+
+    let client = data.into_inner();
+    let collection: mongodb::Collection<Activity> =
+        client.database("Options").collection("Activity");
+
+    let activity = match collection.find_one(doc! { "_id": id }).await {
+        Ok(Some(activity)) => activity,
+        Ok(None) => return HttpResponse::NotFound().body("Activity not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch activity {}: {:?}", id, err);
+            return HttpResponse::InternalServerError().body("Failed to fetch activity");
+        }
+    };
+
+    let start = query.start.unwrap_or_else(|| Utc::now().date_naive());
+    let days = query.days.unwrap_or(30).min(366);
+
+    let mut closed_days = Vec::new();
+    let calendar_days: Vec<_> = calendar::trip_dates(start, days)
+        .into_iter()
+        .map(|date| {
+            let reason = calendar::closure_reason(&activity, date);
+            if reason.is_some() {
+                closed_days.push(date);
+            }
+            serde_json::json!({
+                "date": date,
+                "weekday": date.weekday().to_string(),
+                "open": reason.is_none(),
+                "closed_reason": reason,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "activity_id": id.to_hex(),
+        "operating_days": activity.operating_days,
+        "closed_on_holidays": activity.closed_on_holidays,
+        "closed_days": closed_days,
+        "days": calendar_days,
+    }))
+}
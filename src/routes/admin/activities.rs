@@ -0,0 +1,318 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::services::activity_dedup_service::{
+    ActivityDedupService, ActivityMergeError, DEFAULT_DUPLICATE_THRESHOLD,
+};
+use crate::services::geocoding_service::{GeocodingService, DEFAULT_BACKFILL_LIMIT};
+use crate::services::image_fallback::{StockImageService, StockImages};
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillQuery {
+    pub limit: Option<u32>,
+}
+
+/*
+    /api/admin/activities/backfill-coordinates?limit=500
+
+    Geocodes the address of each activity that has no stored coordinates and
+    stores a GeoJSON `location` point on it, so route optimization stops guessing
+    from the city. Reports how many resolved and which failed. Safe to run again;
+    activities with coordinates are skipped.
+
+    Lookups go through the geocoding cache and are paced by GEOCODING_BATCH_SIZE
+    and GEOCODING_BATCH_DELAY_MS (defaults: 10 per batch, 1000ms apart). Needs
+    GOOGLE_MAPS_API_KEY with the Geocoding API enabled.
+*/
+pub async fn backfill_coordinates(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    query: web::Query<BackfillQuery>,
+) -> impl Responder {
+    let maps_api_key = config.as_ref().and_then(|config| config.google_maps_api_key.clone());
+    let service = match GeocodingService::new(data.into_inner().as_ref().clone(), maps_api_key.as_deref()) {
+        Ok(service) => service,
+        Err(e) => {
+            eprintln!("Geocoding unavailable: {}", e);
+            return HttpResponse::ServiceUnavailable().json(json!({
+                "success": false,
+                "message": "Geocoding is not configured"
+            }));
+        }
+    };
+    let pace = config.map(|config| config.geocoding_pace).unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_BACKFILL_LIMIT).max(1);
+
+    match service.backfill_activity_coordinates(limit, pace).await {
+        Ok(report) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": report
+        })),
+        Err(err) => {
+            eprintln!("Failed to backfill activity coordinates: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to backfill activity coordinates"
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityImagesInput {
+    pub images: Vec<String>,
+}
+
+/*
+    /api/admin/activities/{id}/images
+
+    Body: {"images": ["https://...", ...]}. Replaces the activity's images, best
+    first; an empty list removes them. Search results for itineraries without
+    images of their own show the first image of their priciest activity that has
+    one, and newly generated itineraries are saved with them.
+*/
+pub async fn update_activity_images(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<String>,
+    input: web::Json<ActivityImagesInput>,
+) -> impl Responder {
+    let Ok(activity_id) = ObjectId::parse_str(path.into_inner()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid activity ID format"
+        }));
+    };
+    let images: Vec<String> = input.into_inner().images.into_iter().map(|image| image.trim().to_string()).collect();
+    if let Some(index) = images.iter().position(|image| image.is_empty()) {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": format!("Image at index {} cannot be empty", index)
+        }));
+    }
+
+    let activities: mongodb::Collection<Document> = data.database("Options").collection("Activity");
+    let update = doc! { "$set": { "images": &images, "updated_at": DateTime::now() } };
+    match activities.update_one(doc! { "_id": activity_id }, update).await {
+        Ok(result) if result.matched_count == 0 => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": "Activity not found"
+        })),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "images": images
+        })),
+        Err(err) => {
+            eprintln!("Failed to update activity images: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to update activity images"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/stock-images
+
+    The stock image shown for each city on search results that have neither
+    images of their own nor an activity image, and the placeholder shown when the
+    city has none.
+*/
+pub async fn get_stock_images(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
+    match StockImageService::new(data.into_inner().as_ref().clone()).load().await {
+        Ok(stored) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": {
+                "cities": stored.cities,
+                "placeholder": config.placeholder_image_url,
+                "updated_at": stored.updated_at,
+            }
+        })),
+        Err(err) => {
+            eprintln!("Failed to load stock images: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to load stock images"
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StockImagesInput {
+    /// Image URL by `"City, ST"` or `"City"`
+    pub cities: HashMap<String, String>,
+}
+
+/*
+    /api/admin/stock-images
+
+    Body: {"cities": {"Salida, CO": "https://...", "Moab": "https://..."}}.
+    Replaces the whole mapping. Takes effect on this instance at once and on the
+    others at their next refresh.
+*/
+pub async fn update_stock_images(
+    data: web::Data<Arc<Client>>,
+    stock: Option<web::Data<StockImages>>,
+    input: web::Json<StockImagesInput>,
+) -> impl Responder {
+    let cities = input.into_inner().cities;
+    if let Some(city) = cities.keys().find(|city| city.trim().is_empty()) {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": format!("City name cannot be empty (image {})", cities[city])
+        }));
+    }
+    if let Some((city, _)) = cities.iter().find(|(_, image)| image.trim().is_empty()) {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": format!("Image for {} cannot be empty", city)
+        }));
+    }
+
+    match StockImageService::new(data.into_inner().as_ref().clone()).save(cities).await {
+        Ok(stored) => {
+            if let Some(stock) = stock {
+                stock.replace(&stored.cities);
+            }
+            HttpResponse::Ok().json(json!({
+                "success": true,
+                "data": stored
+            }))
+        }
+        Err(err) => {
+            eprintln!("Failed to save stock images: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to save stock images"
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicatesQuery {
+    pub threshold: Option<f64>,
+}
+
+/*
+    /api/admin/activities/duplicates?threshold=75
+
+    Likely duplicate activities: same company, same city, and titles with a
+    token sort ratio (0-100, after lowercasing and dropping punctuation and
+    words like "tour" or "trip") of at least `threshold`, 75 by default.
+    Returns clusters, largest first, each with its activities oldest first and
+    the score of every similar pair. Merged activities are left out.
+*/
+pub async fn find_duplicates(
+    data: web::Data<Arc<Client>>,
+    query: web::Query<DuplicatesQuery>,
+) -> impl Responder {
+    let threshold = query.threshold.unwrap_or(DEFAULT_DUPLICATE_THRESHOLD);
+    if !(0.0..=100.0).contains(&threshold) {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "threshold must be between 0 and 100"
+        }));
+    }
+
+    match ActivityDedupService::new(data.get_ref().clone()).duplicates(threshold).await {
+        Ok(clusters) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "threshold": threshold,
+            "clusters": clusters
+        })),
+        Err(err) => {
+            eprintln!("Failed to find duplicate activities: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to find duplicate activities"
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeActivitiesInput {
+    pub primary_id: String,
+    pub duplicate_ids: Vec<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/*
+    /api/admin/activities/merge
+
+    Body: {"primary_id": "...", "duplicate_ids": ["..."], "dry_run": true}.
+    Points every itinerary day, itinerary activity list and review request
+    naming a duplicate at the primary (a day that then has the primary twice
+    keeps the first), copies fields the primary is missing from the duplicates,
+    and soft-deletes the duplicates with `merged_into` set, in one transaction
+    when `MONGODB_TRANSACTIONS` is on. Reports the fields copied, the
+    itineraries changed and how many references were re-pointed. A dry run
+    reports the same without changing anything. Merges are audited.
+*/
+pub async fn merge_activities(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    claims: Claims,
+    input: web::Json<MergeActivitiesInput>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let input = input.into_inner();
+    let mut ids = Vec::with_capacity(input.duplicate_ids.len() + 1);
+    for id in std::iter::once(&input.primary_id).chain(&input.duplicate_ids) {
+        let Ok(id) = ObjectId::parse_str(id.trim()) else {
+            return HttpResponse::BadRequest().json(json!({
+                "success": false,
+                "message": format!("Invalid activity ID: {}", id)
+            }));
+        };
+        ids.push(id);
+    }
+
+    let transactions = config.is_some_and(|config| config.mongodb_transactions);
+    match ActivityDedupService::new(data.get_ref().clone())
+        .with_transactions(transactions)
+        .merge(admin_id, ids[0], &ids[1..], input.dry_run)
+        .await
+    {
+        Ok(report) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": report
+        })),
+        Err(ActivityMergeError::DatabaseError(e)) => {
+            eprintln!("Failed to merge activities: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to merge activities"
+            }))
+        }
+        Err(err @ ActivityMergeError::NotFound(_)) => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err @ ActivityMergeError::AlreadyMerged(_)) => HttpResponse::Conflict().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err) => HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+    }
+}
@@ -0,0 +1,310 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashSet, str::FromStr, sync::Arc};
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::models::bookings::{AdminBookingInput, PaymentStatus};
+use crate::routes::{account::auth::is_valid_email, email_settings};
+use crate::services::account_service::EmailService;
+use crate::services::admin_booking_service::{
+    AdminBookingError, AdminBookingRequest, AdminBookingService, Customer,
+};
+use crate::services::availability_service::AvailabilityCache;
+use crate::services::booking_confirmation::CapturedPayment;
+use crate::services::booking_status_service::{
+    BookingStatusOutcome, BookingStatusService, MAX_BULK_BOOKINGS,
+};
+use crate::services::review_request_service::{ReviewRequestError, ReviewRequestService};
+use crate::services::special_requests::sanitize_special_requests;
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+/// Who the booking is for, or why the input doesn't say
+fn customer(input: &AdminBookingInput) -> Result<Customer, &'static str> {
+    match (&input.user_id, &input.email) {
+        (Some(user_id), None) => ObjectId::parse_str(user_id)
+            .map(Customer::Existing)
+            .map_err(|_| "Invalid user ID"),
+        (None, Some(email)) => {
+            let email = email.trim().to_lowercase();
+            if !is_valid_email(&email) {
+                return Err("Invalid email address");
+            }
+            Ok(Customer::Email {
+                email,
+                first_name: input.first_name.clone(),
+                last_name: input.last_name.clone(),
+            })
+        }
+        _ => Err("Give either user_id or email"),
+    }
+}
+
+/// The payment behind `transaction_id`, which must already be captured
+async fn captured_payment(
+    stripe: &stripe::Client,
+    transaction_id: &str,
+) -> Result<CapturedPayment, HttpResponse> {
+    let Ok(intent_id) = stripe::PaymentIntentId::from_str(transaction_id) else {
+        return Err(bad_request("Invalid transaction ID"));
+    };
+    let intent = match stripe::PaymentIntent::retrieve(stripe, &intent_id, &[]).await {
+        Ok(intent) => intent,
+        Err(e) => {
+            eprintln!("Failed to retrieve payment intent {}: {:?}", transaction_id, e);
+            return Err(bad_request("Payment intent not found"));
+        }
+    };
+    if intent.status != stripe::PaymentIntentStatus::Succeeded {
+        return Err(bad_request(&format!(
+            "Payment intent has not been captured (status: {:?})",
+            intent.status
+        )));
+    }
+    Ok(CapturedPayment {
+        payment_intent_id: transaction_id.to_string(),
+        amount: intent.amount,
+        currency: intent.currency.to_string(),
+    })
+}
+
+/*
+    /api/admin/bookings
+
+    Books an itinerary for a customer, typically one who paid over the phone
+    through a payment link. An email without an account gets a shell account and
+    an invitation. A captured payment must match the itinerary's price for the
+    party unless `override_amount_check` is set; either way it's audited.
+*/
+pub async fn create_booking(
+    data: web::Data<Arc<Client>>,
+    stripe_data: web::Data<Arc<stripe::Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    config: Option<web::Data<AppConfig>>,
+    claims: Claims,
+    input: web::Json<AdminBookingInput>,
+) -> impl Responder {
+    let input = input.into_inner();
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let customer = match customer(&input) {
+        Ok(customer) => customer,
+        Err(message) => return bad_request(message),
+    };
+    let Ok(itinerary_id) = ObjectId::parse_str(&input.itinerary_id) else {
+        return bad_request("Invalid itinerary ID");
+    };
+    if input.departure_datetime <= input.arrival_datetime {
+        return bad_request("departure_datetime must be after arrival_datetime");
+    }
+    if input.party.adults == 0 {
+        return bad_request("At least one adult is required");
+    }
+    let special_requests = match input.special_requests.as_deref().map(sanitize_special_requests).transpose() {
+        Ok(special_requests) => special_requests.flatten(),
+        Err(e) => {
+            return HttpResponse::UnprocessableEntity().json(json!({
+                "success": false,
+                "message": e.to_string(),
+                "field": "special_requests"
+            }))
+        }
+    };
+
+    let payment = match input.transaction_id.as_deref().map(str::trim) {
+        Some(transaction_id) if !transaction_id.is_empty() => {
+            match captured_payment(&stripe_data, transaction_id).await {
+                Ok(payment) => Some(payment),
+                Err(response) => return response,
+            }
+        }
+        _ => None,
+    };
+
+    let request = AdminBookingRequest {
+        customer,
+        itinerary_id,
+        arrival_datetime: input.arrival_datetime,
+        departure_datetime: input.departure_datetime,
+        party: input.party,
+        payment,
+        override_amount_check: input.override_amount_check,
+        internal_note: input
+            .internal_note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty()),
+        special_requests,
+    };
+
+    let email = email_settings(config.as_ref());
+    let sms = config.as_ref().and_then(|config| config.twilio.clone());
+    let invitations = EmailService::new(data.get_ref().clone(), &email).ok();
+    let service = AdminBookingService::new(data.into_inner().as_ref().clone())
+        .with_transactions(config.is_some_and(|config| config.mongodb_transactions))
+        .with_notifications(&email, sms.as_ref());
+    match service
+        .create(admin_id, request, &availability_cache, &invitations)
+        .await
+    {
+        Ok(outcome) => HttpResponse::Created().json(json!({
+            "success": true,
+            "data": outcome
+        })),
+        Err(err @ (AdminBookingError::UserNotFound | AdminBookingError::ItineraryNotFound)) => {
+            HttpResponse::NotFound().json(json!({
+                "success": false,
+                "message": err.to_string()
+            }))
+        }
+        Err(ref err @ AdminBookingError::AmountMismatch(ref check)) => {
+            HttpResponse::Conflict().json(json!({
+                "success": false,
+                "message": err.to_string(),
+                "amount_check": check
+            }))
+        }
+        Err(err @ AdminBookingError::PaymentAlreadyUsed) => HttpResponse::Conflict().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err) => {
+            eprintln!("Failed to create admin booking: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to create booking"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/bookings/{id}/send-review-request
+
+    Emails the traveler the review request for a confirmed booking now, even if
+    one already went out. Travelers who turned review requests off aren't sent one.
+*/
+pub async fn send_review_request(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    claims: Claims,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let Ok(booking_id) = ObjectId::parse_str(path.into_inner()) else {
+        return bad_request("Invalid booking ID");
+    };
+
+    let sender = EmailService::new(data.get_ref().clone(), &email_settings(config.as_ref())).ok();
+    let service = ReviewRequestService::new(data.get_ref().clone());
+    match service.send_for_booking(booking_id, &sender, admin_id).await {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "message": "Review request sent"
+        })),
+        Err(err @ ReviewRequestError::BookingNotFound) => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err @ (ReviewRequestError::NotConfirmed | ReviewRequestError::OptedOut)) => {
+            HttpResponse::Conflict().json(json!({
+                "success": false,
+                "message": err.to_string()
+            }))
+        }
+        Err(err) => {
+            eprintln!("Failed to send review request for booking {}: {}", booking_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to send review request"
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkStatusInput {
+    pub booking_ids: Vec<String>,
+    pub status: PaymentStatus,
+    /// Why, for the audit log
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/*
+    /api/admin/bookings/status
+
+    Sets the status of up to 500 bookings, e.g. marking trips that have ended
+    `completed`: { "booking_ids": [...], "status": "completed", "note": "..." }.
+    A booking only moves where its current status allows it; the rest are
+    reported as `rejected` with a `reason`, and unknown ids as `not_found`. Each
+    change is audited. Cancelled or refunded bookings give their seats back;
+    nothing is charged, refunded or emailed.
+*/
+pub async fn bulk_update_status(
+    data: web::Data<Arc<Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    claims: Claims,
+    input: web::Json<BulkStatusInput>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let input = input.into_inner();
+    if input.booking_ids.is_empty() {
+        return bad_request("booking_ids is required");
+    }
+    if input.booking_ids.len() > MAX_BULK_BOOKINGS {
+        return bad_request(&format!("At most {} bookings can be updated at once", MAX_BULK_BOOKINGS));
+    }
+    let mut seen = HashSet::new();
+    let mut booking_ids = Vec::with_capacity(input.booking_ids.len());
+    for id in &input.booking_ids {
+        let Ok(booking_id) = ObjectId::parse_str(id.trim()) else {
+            return bad_request(&format!("Invalid booking ID: {}", id));
+        };
+        if seen.insert(booking_id) {
+            booking_ids.push(booking_id);
+        }
+    }
+    let note = input
+        .note
+        .map(|note| note.trim().to_string())
+        .filter(|note| !note.is_empty());
+
+    let service = BookingStatusService::new(data.get_ref().clone());
+    match service
+        .bulk_update(&availability_cache, admin_id, &booking_ids, input.status, note)
+        .await
+    {
+        Ok(results) => {
+            let count = |matches: fn(&BookingStatusOutcome) -> bool| {
+                results.iter().filter(|result| matches(&result.outcome)).count()
+            };
+            HttpResponse::Ok().json(json!({
+                "success": true,
+                "updated": count(|outcome| matches!(outcome, BookingStatusOutcome::Updated { .. })),
+                "rejected": count(|outcome| matches!(outcome, BookingStatusOutcome::Rejected { .. })),
+                "not_found": count(|outcome| *outcome == BookingStatusOutcome::NotFound),
+                "results": results
+            }))
+        }
+        Err(e) => {
+            eprintln!("Failed to update booking statuses: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to update booking statuses"
+            }))
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::models::content_flag::ResolveFlagsInput;
+use crate::services::content_flag_service::{ContentFlagError, ContentFlagService};
+
+/*
+    /api/admin/content-flags
+
+    Content with open reports, most reported first, with reason counts and any
+    details travelers gave. Reporters aren't included.
+*/
+pub async fn list_flags(data: web::Data<Arc<Client>>) -> impl Responder {
+    let service = ContentFlagService::new(data.into_inner().as_ref().clone());
+    match service.open_queue().await {
+        Ok(queue) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": queue
+        })),
+        Err(err) => {
+            eprintln!("Failed to list content flags: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list content flags"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/content-flags/resolve
+
+    Closes every open report on a piece of content, either dismissing them or
+    taking the content down. The decision goes to the admin audit log.
+*/
+pub async fn resolve_flags(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    input: web::Json<ResolveFlagsInput>,
+) -> impl Responder {
+    let input = input.into_inner();
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let Ok(content_id) = ObjectId::parse_str(&input.content_id) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid content ID"
+        }));
+    };
+    let note = input
+        .note
+        .map(|note| note.trim().to_string())
+        .filter(|note| !note.is_empty());
+
+    let service = ContentFlagService::new(data.into_inner().as_ref().clone());
+    match service
+        .resolve(admin_id, input.content_type, content_id, input.action, note)
+        .await
+    {
+        Ok(outcome) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": outcome
+        })),
+        Err(err @ (ContentFlagError::NothingToResolve | ContentFlagError::ContentNotFound)) => {
+            HttpResponse::NotFound().json(json!({
+                "success": false,
+                "message": err.to_string()
+            }))
+        }
+        Err(err) => {
+            eprintln!("Failed to resolve content flags for {}: {}", content_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to resolve content flags"
+            }))
+        }
+    }
+}
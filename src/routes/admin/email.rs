@@ -0,0 +1,67 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::services::email_suppression_service::EmailSuppressionService;
+
+/*
+    /api/admin/email/suppressions
+
+    Addresses we've stopped emailing after a hard bounce or spam report, with
+    the account or newsletter signup they belong to and the event that caused it.
+*/
+pub async fn list_suppressions(data: web::Data<Arc<Client>>) -> impl Responder {
+    match EmailSuppressionService::new(data.get_ref().clone()).list().await {
+        Ok(suppressions) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": suppressions
+        })),
+        Err(e) => {
+            eprintln!("Failed to list email suppressions: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list email suppressions"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/email/suppressions/{email}
+
+    Starts emailing the address again, once the mailbox is fixed or the traveler
+    asks. Audited.
+*/
+pub async fn clear_suppression(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid user ID format"
+        }));
+    };
+    let email = path.into_inner();
+
+    match EmailSuppressionService::new(data.get_ref().clone()).clear(admin_id, &email).await {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "message": format!("Email to {} resumed", email)
+        })),
+        Ok(false) => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": "Address is not suppressed"
+        })),
+        Err(e) => {
+            eprintln!("Failed to clear email suppression for {}: {:?}", email, e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to clear email suppression"
+            }))
+        }
+    }
+}
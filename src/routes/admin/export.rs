@@ -0,0 +1,78 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::NaiveDate;
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::services::export_service::ExportService;
+use crate::services::streaming::{export_response, ExportFormat};
+
+#[derive(Debug, Deserialize)]
+pub struct FormatQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BookingExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// First day of bookings to include, `YYYY-MM-DD`
+    pub from: NaiveDate,
+    /// Last day of bookings to include, `YYYY-MM-DD`
+    pub to: NaiveDate,
+}
+
+fn export_failed(what: &str, err: mongodb::error::Error) -> HttpResponse {
+    eprintln!("Failed to start {} export: {:?}", what, err);
+    HttpResponse::InternalServerError().json(json!({
+        "success": false,
+        "message": format!("Failed to start {} export", what)
+    }))
+}
+
+/*
+    /api/admin/export/bookings?format=ndjson&from=2025-01-01&to=2025-03-31
+
+    Bookings created between `from` and `to`, inclusive, streamed as CSV
+    (default) or NDJSON. Amounts are in cents.
+*/
+pub async fn export_bookings(
+    data: web::Data<Arc<Client>>,
+    query: web::Query<BookingExportQuery>,
+) -> impl Responder {
+    if query.to < query.from {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "`to` must not be before `from`"
+        }));
+    }
+
+    let service = ExportService::new(data.into_inner().as_ref().clone());
+    match service.bookings(query.from, query.to).await {
+        Ok(rows) => {
+            println!("📤 Exporting bookings created {} to {}", query.from, query.to);
+            export_response(
+                rows,
+                query.format,
+                &format!("bookings-{}-{}", query.from, query.to),
+            )
+        }
+        Err(err) => export_failed("booking", err),
+    }
+}
+
+/*
+    /api/admin/export/newsletter?format=csv
+*/
+pub async fn export_newsletter(
+    data: web::Data<Arc<Client>>,
+    query: web::Query<FormatQuery>,
+) -> impl Responder {
+    let service = ExportService::new(data.into_inner().as_ref().clone());
+    match service.newsletter().await {
+        Ok(rows) => export_response(rows, query.format, "newsletter-subscribers"),
+        Err(err) => export_failed("newsletter", err),
+    }
+}
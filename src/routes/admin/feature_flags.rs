@@ -0,0 +1,73 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::services::feature_flags::{
+    flag_states, FeatureFlagError, FeatureFlagService, FlagValues, Flags,
+};
+
+/*
+    /api/admin/feature-flags
+
+    Every flag with its default, its value here and whether it's overridden.
+*/
+pub async fn list_flags(data: web::Data<Arc<Client>>) -> impl Responder {
+    let service = FeatureFlagService::new(data.into_inner().as_ref().clone());
+    match service.overrides().await {
+        Ok(overrides) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": flag_states(&overrides)
+        })),
+        Err(err) => {
+            eprintln!("Failed to list feature flags: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list feature flags"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/feature-flags
+
+    Body: {"search_debug": false, "itinerary_generation": null}. True or false
+    overrides a flag, null puts it back to its default. Takes effect on this
+    instance at once and on the others at their next refresh. Changes go to the
+    admin audit log.
+*/
+pub async fn update_flags(
+    data: web::Data<Arc<Client>>,
+    flags: web::Data<Flags>,
+    claims: Claims,
+    input: web::Json<BTreeMap<String, Option<bool>>>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+
+    let service = FeatureFlagService::new(data.into_inner().as_ref().clone());
+    match service.update(admin_id, input.into_inner()).await {
+        Ok(overrides) => {
+            flags.replace(FlagValues::with_overrides(&overrides).0);
+            HttpResponse::Ok().json(json!({
+                "success": true,
+                "data": flag_states(&overrides)
+            }))
+        }
+        Err(err @ FeatureFlagError::UnknownFlag(_)) => HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err) => {
+            eprintln!("Failed to update feature flags: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to update feature flags"
+            }))
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::services::impersonation_service::{ImpersonationError, ImpersonationService};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImpersonationInput {
+    /// Why support needs to act as the user, e.g. a ticket number
+    pub reason: Option<String>,
+}
+
+fn error_response(error: ImpersonationError) -> HttpResponse {
+    let body = json!({
+        "success": false,
+        "message": error.to_string()
+    });
+    match error {
+        ImpersonationError::UserNotFound | ImpersonationError::SessionNotFound => {
+            HttpResponse::NotFound().json(body)
+        }
+        ImpersonationError::CannotImpersonateAdmin => HttpResponse::Forbidden().json(body),
+        ImpersonationError::CannotImpersonateSelf => HttpResponse::BadRequest().json(body),
+        ImpersonationError::TokenError(_) | ImpersonationError::DatabaseError(_) => {
+            eprintln!("Impersonation failed: {}", error);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to impersonate user"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/users/{id}/impersonate
+
+    Issues a short-lived token that acts as the user, so support can see exactly
+    what they see. The token carries the admin's id, payments and other sensitive
+    changes are refused while it's used, and the session is audited.
+*/
+pub async fn start_impersonation(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    claims: Claims,
+    path: web::Path<String>,
+    input: Option<web::Json<ImpersonationInput>>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let Ok(user_id) = ObjectId::parse_str(path.into_inner()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid user ID"
+        }));
+    };
+    let reason = input
+        .and_then(|input| input.into_inner().reason)
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+
+    let service = ImpersonationService::new(data.into_inner().as_ref().clone());
+    match service
+        .start(&config.jwt_secret, admin_id, user_id, reason, config.impersonation_token_minutes)
+        .await
+    {
+        Ok(started) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": {
+                "token": started.token,
+                "session_id": started.session.id.to_hex(),
+                "expires_at": started.session.expires_at.try_to_rfc3339_string().ok(),
+                "user_id": user_id.to_hex(),
+                "email": started.user_email,
+                "impersonation": true
+            }
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/*
+    /api/auth/impersonation/end
+
+    Called with the impersonation token to give it up. The token stops working
+    straight away; the admin goes back to their own token.
+*/
+pub async fn end_impersonation(data: web::Data<Arc<Client>>, claims: Claims) -> impl Responder {
+    let Some(impersonation) = claims.impersonation else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Not an impersonation token"
+        }));
+    };
+
+    let service = ImpersonationService::new(data.into_inner().as_ref().clone());
+    match service.end(&impersonation.session_id).await {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "message": "Impersonation ended"
+        })),
+        Err(e) => error_response(e),
+    }
+}
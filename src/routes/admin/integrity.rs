@@ -0,0 +1,52 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::services::integrity_service::IntegrityService;
+use crate::services::storage::Storage;
+
+#[derive(Debug, Deserialize)]
+pub struct DanglingReferencesQuery {
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/*
+    /api/admin/integrity/dangling-references?refresh=true
+
+    The latest scheduled report of itineraries pointing at activities or
+    accommodations that no longer exist, and of sampled itineraries showing
+    images missing from the bucket. Runs the check now when asked to, or when it
+    hasn't run yet.
+*/
+pub async fn dangling_references(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    query: web::Query<DanglingReferencesQuery>,
+) -> impl Responder {
+    let service = IntegrityService::new(data.into_inner().as_ref().clone()).with_image_sample(
+        Storage::new(config.storage.clone()),
+        config.integrity_image_sample_size,
+    );
+    let report = match service.latest().await {
+        Ok(Some(report)) if !query.refresh => Ok(report),
+        Ok(_) => service.run().await,
+        Err(err) => Err(err),
+    };
+    match report {
+        Ok(report) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": report
+        })),
+        Err(err) => {
+            eprintln!("Failed to check itinerary references: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to check itinerary references"
+            }))
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use actix_web::{web, HttpResponse, Responder};
+use bson::{oid::ObjectId, DateTime};
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::services::itinerary_bulk_service::{
+    BulkFilter, BulkItemResult, BulkOperation, BulkOutcome, BulkParams, ItineraryBulkError,
+    ItineraryBulkService, MAX_BULK_ITINERARIES,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct BulkItinerariesInput {
+    #[serde(default)]
+    pub ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub filter: Option<BulkFilter>,
+    pub operation: BulkOperation,
+    #[serde(default)]
+    pub params: BulkParams,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub dry_run_token: Option<String>,
+}
+
+fn bulk_error(err: ItineraryBulkError) -> HttpResponse {
+    let body = json!({
+        "success": false,
+        "message": err.to_string()
+    });
+    match err {
+        ItineraryBulkError::DryRunNotFound => HttpResponse::NotFound().json(body),
+        ItineraryBulkError::DryRunMismatch => HttpResponse::Conflict().json(body),
+        ItineraryBulkError::DatabaseError(e) => {
+            eprintln!("Bulk itinerary operation failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to update itineraries"
+            }))
+        }
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+fn count(results: &[BulkItemResult], outcome: fn(&BulkOutcome) -> bool) -> usize {
+    results.iter().filter(|result| outcome(&result.outcome)).count()
+}
+
+fn bulk_response(operation: BulkOperation, dry_run: bool, results: &[BulkItemResult]) -> serde_json::Value {
+    json!({
+        "success": true,
+        "operation": operation,
+        "dry_run": dry_run,
+        "applied": count(results, |outcome| *outcome == BulkOutcome::Applied),
+        "skipped": count(results, |outcome| matches!(outcome, BulkOutcome::Skipped { .. })),
+        "not_found": count(results, |outcome| *outcome == BulkOutcome::NotFound),
+        "results": results
+    })
+}
+
+/*
+    /api/admin/itineraries/bulk
+
+    Archives, publishes, tags, untags or sets the difficulty of up to 500
+    itineraries: { "ids": [...], "operation": "add_tags", "params": { "tags": ["ski"] } }.
+    Each itinerary is checked as a single edit would be: publishing has to pass
+    validation, and one with confirmed bookings that haven't started isn't
+    archived. Those are skipped, and every id is reported as `applied`,
+    `skipped` with a `reason`, or `not_found`. The batch is audited once.
+
+    Instead of ids a `filter` ({ "tag": "generated", "created_before": "..." })
+    can pick them. A filter has to be sent with `"dry_run": true` first, which
+    reports what would happen and returns a `dry_run_token`; sending the same
+    request with the token executes it on the itineraries previewed. `dry_run`
+    works with ids too, without a token.
+*/
+pub async fn bulk_itineraries(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    claims: Claims,
+    input: web::Json<BulkItinerariesInput>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let input = input.into_inner();
+    let params = input.params.normalized();
+    if let Err(err) = params.check(input.operation) {
+        return bulk_error(err);
+    }
+    let service = ItineraryBulkService::new(data.get_ref().clone());
+    let limits = &config.trip_limits;
+    let min_activity_minutes = config.min_activity_minutes;
+
+    match (input.ids, input.filter) {
+        (Some(ids), None) => {
+            if ids.len() > MAX_BULK_ITINERARIES {
+                return bulk_error(ItineraryBulkError::TooManyItineraries);
+            }
+            let mut seen = HashSet::new();
+            let mut parsed = Vec::with_capacity(ids.len());
+            for id in &ids {
+                let Ok(id) = ObjectId::parse_str(id.trim()) else {
+                    return HttpResponse::BadRequest().json(json!({
+                        "success": false,
+                        "message": format!("Invalid itinerary ID: {}", id)
+                    }));
+                };
+                if seen.insert(id) {
+                    parsed.push(id);
+                }
+            }
+            if parsed.is_empty() {
+                return bulk_error(ItineraryBulkError::NothingSelected);
+            }
+
+            let results = if input.dry_run {
+                service
+                    .plan(input.operation, &params, &parsed, limits, min_activity_minutes)
+                    .await
+            } else {
+                service
+                    .apply(admin_id, input.operation, &params, None, &parsed, limits, min_activity_minutes)
+                    .await
+            };
+            match results {
+                Ok(results) => HttpResponse::Ok().json(bulk_response(input.operation, input.dry_run, &results)),
+                Err(err) => bulk_error(err),
+            }
+        }
+        (None, Some(filter)) if input.dry_run => {
+            let ids = match service.resolve(&filter).await {
+                Ok(ids) => ids,
+                Err(err) => return bulk_error(err),
+            };
+            let results = match service
+                .plan(input.operation, &params, &ids, limits, min_activity_minutes)
+                .await
+            {
+                Ok(results) => results,
+                Err(err) => return bulk_error(err),
+            };
+            let token = match service
+                .save_dry_run(admin_id, input.operation, &params, &filter, ids, DateTime::now())
+                .await
+            {
+                Ok(token) => token,
+                Err(err) => return bulk_error(err),
+            };
+            let mut body = bulk_response(input.operation, true, &results);
+            body["dry_run_token"] = json!(token);
+            HttpResponse::Ok().json(body)
+        }
+        (None, Some(filter)) => {
+            let Some(token) = input.dry_run_token else {
+                return bulk_error(ItineraryBulkError::DryRunRequired);
+            };
+            let ids = match service
+                .take_dry_run(&token, admin_id, input.operation, &params, &filter, DateTime::now())
+                .await
+            {
+                Ok(ids) => ids,
+                Err(err) => return bulk_error(err),
+            };
+            match service
+                .apply(admin_id, input.operation, &params, Some(&filter), &ids, limits, min_activity_minutes)
+                .await
+            {
+                Ok(results) => HttpResponse::Ok().json(bulk_response(input.operation, false, &results)),
+                Err(err) => bulk_error(err),
+            }
+        }
+        _ => bulk_error(ItineraryBulkError::NothingSelected),
+    }
+}
@@ -1,15 +1,151 @@
 use actix_web::web;
-use crate::routes::account::role_management::{update_user_role, list_users_with_roles};
+
+pub mod activities;
+pub mod bookings;
+pub mod content_flags;
+pub mod email;
+pub mod export;
+pub mod feature_flags;
+pub mod impersonation;
+pub mod integrity;
+pub mod itinerary_bulk;
+pub mod provenance;
+pub mod reconciliation;
+pub mod retention;
+pub mod search_experiments;
+pub mod stripe_events;
+
 use crate::middleware::auth::AuthMiddleware;
 use crate::middleware::role_auth::RequireRole;
 use crate::models::account::UserRole;
+use crate::routes::account::role_management::{list_users_with_roles, update_user_role};
+use crate::routes::{featured_vacation, gift_card};
 
-pub fn config(cfg: &mut web::ServiceConfig) {
+/// Admin routes. The role check runs after `AuthMiddleware` has decoded the token.
+pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/admin")
-            .wrap(AuthMiddleware)
             .wrap(RequireRole::new(UserRole::Admin))
-            .route("/users", web::get().to(list_users_with_roles))
-            .route("/update-role", web::post().to(update_user_role))
+            .wrap(AuthMiddleware)
+            .service(
+                web::scope("/users")
+                    .route("", web::get().to(list_users_with_roles))
+                    // Granting roles and acting as users re-check the admin's own
+                    // role, so a demoted admin can't keep using an older token
+                    .route(
+                        "/{id}/role",
+                        web::put()
+                            .to(update_user_role)
+                            .wrap(RequireRole::verified(UserRole::Admin)),
+                    )
+                    .route(
+                        "/{id}/impersonate",
+                        web::post()
+                            .to(impersonation::start_impersonation)
+                            .wrap(RequireRole::verified(UserRole::Admin)),
+                    ),
+            )
+            .route(
+                "/activities/backfill-coordinates",
+                web::post().to(activities::backfill_coordinates),
+            )
+            .route("/activities/duplicates", web::get().to(activities::find_duplicates))
+            .route("/activities/merge", web::post().to(activities::merge_activities))
+            .route(
+                "/activities/{id}/images",
+                web::put().to(activities::update_activity_images),
+            )
+            .route("/bookings", web::post().to(bookings::create_booking))
+            .route("/bookings/status", web::post().to(bookings::bulk_update_status))
+            .route(
+                "/bookings/{id}/send-review-request",
+                web::post().to(bookings::send_review_request),
+            )
+            .service(
+                web::scope("/content-flags")
+                    .route("", web::get().to(content_flags::list_flags))
+                    .route("/resolve", web::post().to(content_flags::resolve_flags)),
+            )
+            .service(
+                web::scope("/email/suppressions")
+                    .route("", web::get().to(email::list_suppressions))
+                    .route("/{email}", web::delete().to(email::clear_suppression)),
+            )
+            .service(
+                web::scope("/export")
+                    .route("/bookings", web::get().to(export::export_bookings))
+                    .route("/newsletter", web::get().to(export::export_newsletter)),
+            )
+            .service(
+                web::scope("/itineraries")
+                    .route("/bulk", web::post().to(itinerary_bulk::bulk_itineraries))
+                    .route("/featured/add", web::post().to(featured_vacation::add))
+                    .route(
+                        "/recompute-costs",
+                        web::post().to(featured_vacation::recompute_costs),
+                    )
+                    .service(
+                        web::scope("/{id}")
+                            .route(
+                                "/images",
+                                web::put().to(featured_vacation::update_itinerary_images),
+                            )
+                            .route(
+                                "/images/discover",
+                                web::post().to(featured_vacation::discover_itinerary_images),
+                            )
+                            .route("/days", web::put().to(featured_vacation::update_itinerary_days))
+                            .route("/provenance", web::get().to(provenance::itinerary_provenance))
+                            .route("/score-preview", web::post().to(featured_vacation::score_preview)),
+                    ),
+            )
+            .service(
+                web::scope("/payments/reconciliation")
+                    .route("", web::get().to(reconciliation::list_reconciliation))
+                    .route("/{id}/resolve", web::post().to(reconciliation::resolve_reconciliation)),
+            )
+            .service(
+                web::scope("/retention")
+                    .route("/runs", web::get().to(retention::list_runs))
+                    .route("/run-now", web::post().to(retention::run_now)),
+            )
+            .service(
+                web::scope("/search-experiments")
+                    .route("", web::get().to(search_experiments::list_experiments))
+                    .route("", web::post().to(search_experiments::create_experiment))
+                    .route("/{id}", web::put().to(search_experiments::update_experiment))
+                    .route("/{id}/activate", web::post().to(search_experiments::activate_experiment))
+                    .route(
+                        "/{id}/deactivate",
+                        web::post().to(search_experiments::deactivate_experiment),
+                    ),
+            )
+            .service(
+                web::scope("/stock-images")
+                    .route("", web::get().to(activities::get_stock_images))
+                    .route("", web::put().to(activities::update_stock_images)),
+            )
+            .service(
+                web::scope("/feature-flags")
+                    .route("", web::get().to(feature_flags::list_flags))
+                    .route("", web::put().to(feature_flags::update_flags)),
+            )
+            .route(
+                "/integrity/dangling-references",
+                web::get().to(integrity::dangling_references),
+            )
+            .route(
+                "/stripe/events/{event_id}/reprocess",
+                web::post().to(stripe_events::reprocess_stripe_event),
+            )
+            .configure(super::configure_demo_tools)
+            .service(
+                web::scope("/gift-cards")
+                    .route("", web::get().to(gift_card::list_gift_cards))
+                    .route("", web::post().to(gift_card::create_gift_card))
+                    .route("/{id}", web::get().to(gift_card::get_gift_card))
+                    .route("/{id}", web::put().to(gift_card::update_gift_card))
+                    .route("/{id}", web::delete().to(gift_card::delete_gift_card)),
+            ),
     );
-}
\ No newline at end of file
+}
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::Client;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::db::mongo::read_only_collection;
+use crate::models::itinerary::base::FeaturedVacation;
+
+fn not_found(message: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+/*
+    /api/admin/itineraries/{id}/provenance
+
+    Where a generated itinerary came from: the search that produced it, which of
+    its variations it is, the seed behind its random choices, when it was generated
+    and why each activity was or wasn't chosen. Curated itineraries have none and
+    are a 404.
+*/
+pub async fn itinerary_provenance(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Ok(itinerary_id) = ObjectId::parse_str(path.into_inner()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid itinerary ID"
+        }));
+    };
+
+    let itineraries = read_only_collection::<FeaturedVacation>(&data, "Itineraries", "Featured");
+    match itineraries.find_one(doc! { "_id": itinerary_id }).await {
+        Ok(Some(FeaturedVacation {
+            trip_name,
+            created_at,
+            generation_metadata: Some(metadata),
+            ..
+        })) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": {
+                "itinerary_id": itinerary_id.to_hex(),
+                "trip_name": trip_name,
+                "created_at": created_at,
+                "generation_metadata": metadata
+            }
+        })),
+        Ok(Some(_)) => not_found("Itinerary was not generated"),
+        Ok(None) => not_found("Itinerary not found"),
+        Err(err) => {
+            eprintln!("Failed to load provenance of itinerary {}: {:?}", itinerary_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to load itinerary provenance"
+            }))
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::services::booking_confirmation::BookingConfirmationService;
+
+#[derive(Debug, Deserialize)]
+pub struct ReconciliationQuery {
+    #[serde(default)]
+    pub include_resolved: bool,
+}
+
+/*
+    /api/admin/payments/reconciliation?include_resolved=true
+
+    Payments captured whose booking couldn't be confirmed: a wrong amount or
+    currency, or a write that failed after the card was charged. Each one needs
+    the booking finished or the payment refunded by hand. Oldest first.
+*/
+pub async fn list_reconciliation(
+    data: web::Data<Arc<Client>>,
+    query: web::Query<ReconciliationQuery>,
+) -> impl Responder {
+    match BookingConfirmationService::new(data.into_inner().as_ref().clone())
+        .list_reconciliation(query.include_resolved)
+        .await
+    {
+        Ok(records) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": records
+        })),
+        Err(e) => {
+            eprintln!("Failed to list payments for reconciliation: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list payments for reconciliation"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/payments/reconciliation/{id}/resolve
+
+    Marks a payment as settled once it has been dealt with in Stripe, recording
+    which admin did it.
+*/
+pub async fn resolve_reconciliation(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<String>,
+) -> impl Responder {
+    let (Ok(admin_id), Ok(id)) = (ObjectId::parse_str(&claims.user_id), ObjectId::parse_str(path.as_str())) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid ID format"
+        }));
+    };
+
+    match BookingConfirmationService::new(data.into_inner().as_ref().clone())
+        .resolve_reconciliation(id, admin_id)
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().json(json!({ "success": true })),
+        Ok(false) => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": "No unresolved payment with that ID"
+        })),
+        Err(e) => {
+            eprintln!("Failed to resolve payment {}: {:?}", id, e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to resolve payment"
+            }))
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::services::retention_service::{RetentionService, RetentionTrigger, LISTED_RUNS};
+
+#[derive(Debug, Deserialize)]
+pub struct RunNowQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/*
+    /api/admin/retention/runs
+*/
+pub async fn list_runs(data: web::Data<Arc<Client>>, config: web::Data<AppConfig>) -> impl Responder {
+    let service = RetentionService::new(data.into_inner().as_ref().clone(), config.retention.clone());
+    match service.list_runs(LISTED_RUNS).await {
+        Ok(runs) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": runs
+        })),
+        Err(err) => {
+            eprintln!("Failed to list retention runs: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list retention runs"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/retention/run-now?dry_run=true
+
+    Runs retention immediately. A dry run counts what would be deleted without
+    deleting anything.
+*/
+pub async fn run_now(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    query: web::Query<RunNowQuery>,
+) -> impl Responder {
+    let service = RetentionService::new(data.into_inner().as_ref().clone(), config.retention.clone());
+    match service.run(RetentionTrigger::Manual, query.dry_run).await {
+        Ok(run) if run.error.is_none() => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": run
+        })),
+        Ok(run) => HttpResponse::InternalServerError().json(json!({
+            "success": false,
+            "message": "Retention run stopped early",
+            "data": run
+        })),
+        Err(err) => {
+            eprintln!("Failed to record retention run: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to run retention"
+            }))
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::middleware::auth::Claims;
+use crate::services::search_experiments::{ExperimentInput, SearchExperimentError, SearchExperimentService};
+
+fn error_response(action: &str, err: SearchExperimentError) -> HttpResponse {
+    let body = json!({
+        "success": false,
+        "message": err.to_string()
+    });
+    match err {
+        SearchExperimentError::Invalid(_) | SearchExperimentError::Ended => HttpResponse::BadRequest().json(body),
+        SearchExperimentError::NameTaken(_) | SearchExperimentError::AnotherActive(_) => {
+            HttpResponse::Conflict().json(body)
+        }
+        SearchExperimentError::NotFound => HttpResponse::NotFound().json(body),
+        SearchExperimentError::DatabaseError(_) => {
+            eprintln!("Failed to {} search experiment: {}", action, err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": format!("Failed to {} search experiment", action)
+            }))
+        }
+    }
+}
+
+/// The admin's id and the experiment's, or what's wrong with them
+fn parse_ids(claims: &Claims, id: &str) -> Result<(ObjectId, ObjectId), &'static str> {
+    let admin_id = ObjectId::parse_str(&claims.user_id).map_err(|_| "Invalid user ID format")?;
+    let id = ObjectId::parse_str(id).map_err(|_| "Invalid experiment ID format")?;
+    Ok((admin_id, id))
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+/*
+    /api/admin/search-experiments
+
+    Every search weight experiment, newest first, with its weight overrides,
+    traffic share, dates and whether it's active.
+*/
+pub async fn list_experiments(data: web::Data<Arc<Client>>) -> impl Responder {
+    match SearchExperimentService::new(data.get_ref().clone()).list().await {
+        Ok(experiments) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": experiments
+        })),
+        Err(e) => {
+            eprintln!("Failed to list search experiments: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list search experiments"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/search-experiments
+
+    Body: {"name": "heavier_activities", "weights": {"activity_weight": 45},
+    "traffic_percent": 20, "starts_at": "2026-11-01T00:00:00Z", "ends_at": null}.
+    Weights left out keep their configured value; starts_at defaults to now.
+    The experiment starts inactive. Audited.
+*/
+pub async fn create_experiment(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    input: web::Json<ExperimentInput>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return bad_request("Invalid user ID format");
+    };
+    match SearchExperimentService::new(data.get_ref().clone())
+        .create(admin_id, input.into_inner())
+        .await
+    {
+        Ok(experiment) => HttpResponse::Created().json(json!({
+            "success": true,
+            "data": experiment
+        })),
+        Err(err) => error_response("create", err),
+    }
+}
+
+/*
+    /api/admin/search-experiments/{id}
+
+    Replaces the name, weights, traffic share and dates, with the same body as
+    creating one. An active experiment stays active. Audited.
+*/
+pub async fn update_experiment(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<String>,
+    input: web::Json<ExperimentInput>,
+) -> impl Responder {
+    let (admin_id, id) = match parse_ids(&claims, &path) {
+        Ok(ids) => ids,
+        Err(message) => return bad_request(message),
+    };
+    match SearchExperimentService::new(data.get_ref().clone())
+        .update(admin_id, id, input.into_inner())
+        .await
+    {
+        Ok(experiment) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": experiment
+        })),
+        Err(err) => error_response("update", err),
+    }
+}
+
+/*
+    /api/admin/search-experiments/{id}/activate
+
+    Starts scoring the experiment's share of searches with its weights. 409 while
+    another experiment is active; 400 once its end date has passed. Audited.
+*/
+pub async fn activate_experiment(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<String>,
+) -> impl Responder {
+    let (admin_id, id) = match parse_ids(&claims, &path) {
+        Ok(ids) => ids,
+        Err(message) => return bad_request(message),
+    };
+    match SearchExperimentService::new(data.get_ref().clone()).activate(admin_id, id).await {
+        Ok(experiment) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": experiment
+        })),
+        Err(err) => error_response("activate", err),
+    }
+}
+
+/*
+    /api/admin/search-experiments/{id}/deactivate
+
+    Puts every search back on the configured weights. Audited.
+*/
+pub async fn deactivate_experiment(
+    data: web::Data<Arc<Client>>,
+    claims: Claims,
+    path: web::Path<String>,
+) -> impl Responder {
+    let (admin_id, id) = match parse_ids(&claims, &path) {
+        Ok(ids) => ids,
+        Err(message) => return bad_request(message),
+    };
+    match SearchExperimentService::new(data.get_ref().clone()).deactivate(admin_id, id).await {
+        Ok(experiment) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": experiment
+        })),
+        Err(err) => error_response("deactivate", err),
+    }
+}
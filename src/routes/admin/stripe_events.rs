@@ -0,0 +1,86 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::oid::ObjectId, Client};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::middleware::auth::Claims;
+use crate::routes::payment::reprocess_event;
+use crate::services::account_service::EmailService;
+use crate::services::availability_service::AvailabilityCache;
+use crate::services::webhook_replay::{ProcessedWebhookService, ReprocessError};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReprocessInput {
+    /// Send confirmation emails and texts again for bookings the event already confirmed
+    #[serde(default)]
+    pub force_notifications: bool,
+}
+
+/*
+    /api/admin/stripe/events/{event_id}/reprocess
+
+    Runs a Stripe event we received through the webhook handling again. Booking
+    status changes only move forward, so repeating them is safe; emails already
+    sent for the event go out again only with `force_notifications`. Every attempt
+    is audited.
+*/
+pub async fn reprocess_stripe_event(
+    data: web::Data<Arc<Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    config: Option<web::Data<AppConfig>>,
+    claims: Claims,
+    path: web::Path<String>,
+    input: Option<web::Json<ReprocessInput>>,
+) -> impl Responder {
+    let Ok(admin_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid user ID format"
+        }));
+    };
+    let client = data.into_inner().as_ref().clone();
+    let event_id = path.into_inner();
+    let force_notifications = input.is_some_and(|input| input.force_notifications);
+    let config = config.map(|config| config.into_inner());
+    let email = config.as_ref().map(|config| config.email.clone()).unwrap_or_default();
+    let sms = config.as_ref().and_then(|config| config.twilio.as_ref());
+    let transactions = config.as_ref().is_some_and(|config| config.mongodb_transactions);
+
+    let result = reprocess_event(
+        client.clone(),
+        &availability_cache,
+        transactions,
+        &event_id,
+        force_notifications,
+        &EmailService::new(client.clone(), &email).ok(),
+        sms,
+    )
+    .await;
+    ProcessedWebhookService::new(client)
+        .audit_reprocess(admin_id, &event_id, force_notifications, result.as_ref().ok())
+        .await;
+
+    match result {
+        Ok(outcome) => HttpResponse::Ok().json(json!({
+            "success": outcome.succeeded(),
+            "data": outcome
+        })),
+        Err(err @ (ReprocessError::NotFound | ReprocessError::NoPayload)) => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err @ ReprocessError::InvalidPayload(_)) => HttpResponse::UnprocessableEntity().json(json!({
+            "success": false,
+            "message": err.to_string()
+        })),
+        Err(err) => {
+            eprintln!("Failed to reprocess webhook event {}: {}", event_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to reprocess event"
+            }))
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::Client;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::services::demo_seed_service;
+
+/*
+    /api/admin/seed-demo-data (demo-tools builds only)
+*/
+pub async fn seed_demo_data(data: web::Data<Arc<Client>>, config: web::Data<AppConfig>) -> impl Responder {
+    match demo_seed_service::seed_demo_data(
+        data.into_inner().as_ref().clone(),
+        &config.demo_accounts,
+        &config.storage,
+    )
+    .await
+    {
+        Ok(summary) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": summary
+        })),
+        Err(err) => {
+            eprintln!("Failed to seed demo data: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to seed demo data"
+            }))
+        }
+    }
+}
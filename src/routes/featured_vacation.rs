@@ -1,8 +1,18 @@
 use crate::{
-    models::itinerary::base::FeaturedVacation, 
+    config::AppConfig,
+    middleware::auth::Claims,
+    routes::email_settings,
+    models::itinerary::base::{ordered_days, DayItem, FeaturedVacation},
     services::{
+        account_service::EmailService,
+        booking_impact_service::{BookingImpactError, BookingImpactService},
+        cost_recompute_service::recompute_person_costs,
+        favorite_digest_service::{material_changes, FavoriteDigestService},
         itinerary_service::get_images,
-        image_service::{ImageService, ImageData}
+        image_service::{discover_images, itinerary_prefix, ImageData, ImageService},
+        price_alert_service::PriceAlertJob,
+        score_preview_service::{ItineraryEdits, ScorePreviewError, ScorePreviewService, ScoreScenario},
+        storage::{BucketKind, ObjectStore, Storage},
     }
 };
 use actix_multipart::form::json;
@@ -10,13 +20,18 @@ use actix_web::{web, HttpResponse, Responder};
 use bson::{doc, oid::ObjectId, DateTime};
 use futures::TryStreamExt;
 use mongodb::Client;
+use serde::Deserialize;
 use serde_json::json;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 /*
     /api/itineraries/featured/
 */
-pub async fn get_all(data: web::Data<Arc<Client>>) -> impl Responder {
+pub async fn get_all(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<FeaturedVacation> =
         client.database("Itineraries").collection("Featured");
@@ -48,7 +63,7 @@ pub async fn get_all(data: web::Data<Arc<Client>>) -> impl Responder {
             // Store the original count to compare later
             let original_count = valid_vacations.len();
 
-            let processed_vacations = get_images(valid_vacations.clone()).await;
+            let processed_vacations = get_images(valid_vacations.clone(), &Storage::new(config.storage.clone())).await;
 
             // Check if we lost any vacations during processing
             if processed_vacations.len() < original_count {
@@ -74,7 +89,7 @@ pub async fn get_all(data: web::Data<Arc<Client>>) -> impl Responder {
             let mut populated_vacations = Vec::new();
 
             for vacation in processed_vacations.iter() {
-                match vacation.clone().populate(&client).await {
+                match vacation.clone().populate(&client, config.activity_bucket.as_deref()).await {
                     Ok(mut populated) => {
                         // Log original image count
                         let original_image_count = populated.base.images.as_ref().map(|imgs| imgs.len()).unwrap_or(0);
@@ -113,6 +128,7 @@ pub async fn get_all(data: web::Data<Arc<Client>>) -> impl Responder {
 
 pub async fn add(
     data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
     req_body: web::Json<serde_json::Value>,
 ) -> impl Responder {
     let client = data.into_inner();
@@ -173,7 +189,7 @@ pub async fn add(
                     }
                 };
 
-                match ImageService::new().await {
+                match ImageService::new(config.storage.clone()) {
                     Ok(image_service) => {
                         let upload_results = image_service.upload_images(images, &itinerary_id).await;
                         
@@ -223,8 +239,20 @@ pub async fn add(
     }))
 }
 
+/*
+    /api/admin/itineraries/{id}/images
+
+    Replaces the image set. An optional `primary_image`, which must be one of
+    `images`, is listed first wherever the itinerary's images are shown. A new
+    set of images is included in the favorites digest of users who saved the trip.
+
+    Images in the itinerary bucket, by object name or URL, must exist there: a
+    set naming missing objects is a 422 listing them, unless `skip_validation`
+    is true. Images hosted elsewhere aren't checked.
+*/
 pub async fn update_itinerary_images(
     data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
     path: web::Path<String>,
     req_body: web::Json<serde_json::Value>,
 ) -> impl Responder {
@@ -288,13 +316,63 @@ pub async fn update_itinerary_images(
         }
     };
 
+    // The hero image must be one of the new images. Leaving it out clears any
+    // earlier choice, so listings fall back to the first image.
+    let primary_image = match req_body.get("primary_image") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(primary) => match primary.as_str() {
+            Some(primary) if images.iter().any(|img| img.as_str() == Some(primary)) => {
+                Some(primary.to_string())
+            }
+            _ => {
+                return HttpResponse::BadRequest().json(json!({
+                    "success": false,
+                    "message": "primary_image must be one of the images"
+                }));
+            }
+        },
+    };
+
+    let skip_validation = req_body
+        .get("skip_validation")
+        .and_then(|skip| skip.as_bool())
+        .unwrap_or(false);
+    if !skip_validation {
+        let names: Vec<String> = images
+            .iter()
+            .filter_map(|img| img.as_str().map(str::to_string))
+            .collect();
+        if let Some(rejection) = reject_missing_images(&Storage::new(config.storage.clone()), &names).await {
+            return rejection;
+        }
+    }
+
+    // Kept to tell favoriting users what changed
+    let before = collection
+        .find_one(doc! { "_id": object_id })
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to load itinerary before updating images: {:?}", err);
+            None
+        });
+
     // Convert images to BSON before using in doc! macro
     let images_bson = bson::to_bson(&images).unwrap_or(bson::Bson::Array(vec![]));
-    let update_doc = doc! {
-        "$set": {
-            "images": images_bson,
-            "updated_at": DateTime::now()
-        }
+    let update_doc = match &primary_image {
+        Some(primary_image) => doc! {
+            "$set": {
+                "images": images_bson,
+                "primary_image": primary_image,
+                "updated_at": DateTime::now()
+            }
+        },
+        None => doc! {
+            "$set": {
+                "images": images_bson,
+                "updated_at": DateTime::now()
+            },
+            "$unset": { "primary_image": "" }
+        },
     };
 
     match collection
@@ -308,9 +386,22 @@ pub async fn update_itinerary_images(
                     "message": "Itinerary not found"
                 }))
             } else {
+                if let Some(before) = before.filter(|_| update_result.modified_count > 0) {
+                    let mut after = before.clone();
+                    after.images = Some(
+                        images
+                            .iter()
+                            .filter_map(|img| img.as_str().map(str::to_string))
+                            .collect(),
+                    );
+                    FavoriteDigestService::new(client.as_ref().clone())
+                        .notify(object_id, &after.trip_name, material_changes(&before, &after))
+                        .await;
+                }
                 HttpResponse::Ok().json(json!({
                     "success": true,
                     "message": "Images updated successfully",
+                    "primary_image": primary_image,
                     "modified_count": update_result.modified_count
                 }))
             }
@@ -324,3 +415,338 @@ pub async fn update_itinerary_images(
         }
     }
 }
+
+/// A 422 naming the images whose objects aren't in the itinerary bucket, or a 500
+/// when the bucket can't be checked
+async fn reject_missing_images<S: ObjectStore>(storage: &Storage<S>, images: &[String]) -> Option<HttpResponse> {
+    match storage.missing_objects(BucketKind::ItineraryImages, images).await {
+        Ok(missing) if missing.is_empty() => None,
+        Ok(missing) => Some(HttpResponse::UnprocessableEntity().json(json!({
+            "success": false,
+            "message": "Some images aren't in the itinerary bucket",
+            "missing_objects": missing
+        }))),
+        Err(err) => {
+            eprintln!("Failed to check itinerary images in storage: {}", err);
+            Some(HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to check the images in storage. Resend with skip_validation to save them anyway."
+            })))
+        }
+    }
+}
+
+/*
+    /api/admin/itineraries/{id}/images/discover
+
+    The objects stored under the itinerary's prefix in the itinerary bucket, each
+    marked with whether the itinerary currently shows it, and the itinerary's
+    images whose objects are missing. For repairing an image set that drifted
+    from what's in the bucket.
+*/
+pub async fn discover_itinerary_images(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let itinerary_id = path.into_inner();
+    let Ok(object_id) = ObjectId::parse_str(&itinerary_id) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid itinerary ID format"
+        }));
+    };
+    let collection: mongodb::Collection<FeaturedVacation> =
+        data.database("Itineraries").collection("Featured");
+    let itinerary = match collection.find_one(doc! { "_id": object_id }).await {
+        Ok(Some(itinerary)) => itinerary,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "success": false,
+                "message": "Itinerary not found"
+            }));
+        }
+        Err(err) => {
+            eprintln!("Failed to load itinerary {}: {:?}", itinerary_id, err);
+            return HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to load itinerary"
+            }));
+        }
+    };
+
+    let images = itinerary.images.unwrap_or_default();
+    let storage = Storage::new(config.storage.clone());
+    let found = match discover_images(&storage, &itinerary_id, &images).await {
+        Ok(found) => found,
+        Err(err) => {
+            eprintln!("Failed to list images for itinerary {}: {}", itinerary_id, err);
+            return HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to list the itinerary's images"
+            }));
+        }
+    };
+    let missing = storage
+        .missing_objects(BucketKind::ItineraryImages, &images)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to check images for itinerary {}: {}", itinerary_id, err);
+            Vec::new()
+        });
+
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "data": {
+            "prefix": itinerary_prefix(&itinerary_id),
+            "objects": found,
+            "missing_objects": missing
+        }
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDaysInput {
+    /// `[{"day": 1, "items": [...]}, ...]`, as itineraries are returned
+    #[serde(with = "ordered_days")]
+    pub days: Option<HashMap<String, Vec<DayItem>>>,
+    #[serde(default)]
+    pub acknowledge_booking_impact: bool,
+}
+
+/*
+    /api/admin/itineraries/{id}/days
+
+    Replaces an itinerary's days. The edit is checked against confirmed bookings
+    that haven't started: removing an activity they booked is a 409 with the
+    `booking_impact` unless `acknowledge_booking_impact` is true. Every edit is
+    audited, and travelers whose booked activities moved or were removed are
+    emailed what changed.
+*/
+pub async fn update_itinerary_days(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    claims: Claims,
+    path: web::Path<String>,
+    input: web::Json<UpdateDaysInput>,
+) -> impl Responder {
+    let client = data.into_inner();
+    let (Ok(itinerary_id), Ok(admin_id)) =
+        (ObjectId::parse_str(path.into_inner()), ObjectId::parse_str(&claims.user_id))
+    else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid itinerary ID format"
+        }));
+    };
+    let input = input.into_inner();
+    let Some(days) = input.days.filter(|days| !days.is_empty()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Days are required"
+        }));
+    };
+
+    let impact_service = BookingImpactService::new(client.as_ref().clone());
+    let impact = match impact_service.check_booking_impact(itinerary_id, &days).await {
+        Ok(impact) => impact,
+        Err(BookingImpactError::ItineraryNotFound) => {
+            return HttpResponse::NotFound().json(json!({
+                "success": false,
+                "message": "Itinerary not found"
+            }));
+        }
+        Err(err) => {
+            eprintln!("Failed to check booking impact for itinerary {}: {}", itinerary_id, err);
+            return HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to update itinerary days"
+            }));
+        }
+    };
+    if impact.requires_acknowledgement() && !input.acknowledge_booking_impact {
+        return HttpResponse::Conflict().json(json!({
+            "success": false,
+            "message": "This removes activities travelers have booked. Resend with acknowledge_booking_impact to go ahead.",
+            "booking_impact": impact
+        }));
+    }
+
+    let collection: mongodb::Collection<FeaturedVacation> =
+        client.database("Itineraries").collection("Featured");
+    let before = match collection
+        .find_one_and_update(
+            doc! { "_id": itinerary_id },
+            doc! { "$set": { "days.days": bson::to_bson(&days).unwrap_or_default(), "updated_at": DateTime::now() } },
+        )
+        .await
+    {
+        Ok(Some(before)) => before,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({
+                "success": false,
+                "message": "Itinerary not found"
+            }));
+        }
+        Err(err) => {
+            eprintln!("Failed to update itinerary days: {:?}", err);
+            return HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to update itinerary days"
+            }));
+        }
+    };
+
+    impact_service.record(admin_id, &impact, input.acknowledge_booking_impact).await;
+    let notified = match impact_service
+        .notify_travelers(&impact, &before.trip_name, &EmailService::new(client.as_ref().clone(), &email_settings(config.as_ref())).ok())
+        .await
+    {
+        Ok(summary) => summary,
+        Err(err) => {
+            eprintln!("Failed to notify travelers about itinerary {}: {}", itinerary_id, err);
+            Default::default()
+        }
+    };
+
+    let mut after = before.clone();
+    after.days.days = days;
+    FavoriteDigestService::new(client.as_ref().clone())
+        .notify(itinerary_id, &after.trip_name, material_changes(&before, &after))
+        .await;
+
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Days updated successfully",
+        "booking_impact": impact,
+        "travelers_notified": notified
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScorePreviewInput {
+    #[serde(default)]
+    pub overrides: ItineraryEdits,
+    /// Preset names or searches; every preset when empty
+    #[serde(default)]
+    pub scenarios: Vec<ScoreScenario>,
+}
+
+/*
+    /api/admin/itineraries/{id}/score-preview
+
+    Scores the itinerary as stored and with `overrides` applied against each
+    scenario, side by side. Overridden activities are scored as given, so
+    activities that aren't saved yet can be tried out. Nothing is written.
+*/
+pub async fn score_preview(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    path: web::Path<String>,
+    input: web::Json<ScorePreviewInput>,
+) -> impl Responder {
+    let Ok(itinerary_id) = ObjectId::parse_str(path.into_inner()) else {
+        return HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": "Invalid itinerary ID format"
+        }));
+    };
+    let input = input.into_inner();
+
+    let service = ScorePreviewService::new(data.into_inner().as_ref().clone(), config.search_weights.clone());
+    match service
+        .preview(itinerary_id, &input.overrides, input.scenarios, &config.score_presets)
+        .await
+    {
+        Ok(comparisons) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": comparisons
+        })),
+        Err(ScorePreviewError::NotFound) => HttpResponse::NotFound().json(json!({
+            "success": false,
+            "message": "Itinerary not found"
+        })),
+        Err(err @ ScorePreviewError::UnknownPreset(_)) => HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "message": err.to_string(),
+            "presets": config.score_presets.keys().collect::<Vec<_>>()
+        })),
+        Err(err) => {
+            eprintln!("Failed to preview scores for itinerary {}: {}", itinerary_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to preview itinerary scores"
+            }))
+        }
+    }
+}
+
+/*
+    /api/admin/itineraries/recompute-costs
+
+    Re-prices every itinerary from current activity prices. Itineraries that reference
+    deleted activities are flagged with `needs_review` instead of being priced at zero.
+    When any price changed, favorites are checked for price drops straight away.
+*/
+pub async fn recompute_costs(
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
+    let client = data.into_inner();
+    println!("💲 Recomputing person_cost for all itineraries");
+
+    match recompute_person_costs(client.as_ref().clone(), config.recompute_costs_batch_size).await {
+        Ok(summary) => {
+            println!("✅ Cost recompute finished: {:?}", summary);
+            if summary.changed > 0 {
+                let job = PriceAlertJob::new(client.as_ref().clone(), config.price_alert_min_drop_percent)
+                    .with_email(&config.email);
+                tokio::spawn(async move {
+                    match job.run().await {
+                        Ok(alerts) => println!("🔔 Price alert check finished: {:?}", alerts),
+                        Err(e) => eprintln!("Price alert check failed: {}", e),
+                    }
+                });
+            }
+            HttpResponse::Ok().json(json!({
+                "success": true,
+                "data": summary
+            }))
+        }
+        Err(err) => {
+            eprintln!("Failed to recompute itinerary costs: {:?}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "message": "Failed to recompute itinerary costs"
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::memory;
+    use actix_web::{body::to_bytes, http::StatusCode};
+
+    #[actix_rt::test]
+    async fn test_images_missing_from_the_bucket_are_rejected() {
+        let storage = memory::storage();
+        storage
+            .upload(BucketKind::ItineraryImages, "i1/cover.jpg", "image/jpeg", vec![0; 10])
+            .await
+            .unwrap();
+        let stored = "https://storage.googleapis.com/actota-itineraries/i1/cover.jpg".to_string();
+        let external = "https://cdn.fareharbor.com/images/tour.jpg".to_string();
+
+        assert!(reject_missing_images(&storage, &[stored.clone(), external]).await.is_none());
+
+        let rejection = reject_missing_images(&storage, &[stored, "i1/typo.jpg".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(rejection.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value =
+            serde_json::from_slice(&to_bytes(rejection.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["missing_objects"], json!(["i1/typo.jpg"]));
+    }
+}
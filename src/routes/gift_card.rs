@@ -0,0 +1,229 @@
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use std::sync::Arc;
+
+use crate::models::gift_card::{ApplyGiftCardInput, GiftCard, GiftCardInput, GiftCardUpdate};
+use crate::services::gift_card_service::{
+    normalize_code, split_payment, GiftCardError, GiftCardService,
+};
+
+fn gift_cards(client: &Client) -> Collection<GiftCard> {
+    client.database("Account").collection("GiftCards")
+}
+
+fn gift_card_error_response(err: GiftCardError) -> HttpResponse {
+    match err {
+        GiftCardError::NotFound => HttpResponse::NotFound().json(serde_json::json!({
+            "error": err.to_string()
+        })),
+        GiftCardError::DatabaseError(_) => {
+            eprintln!("Gift card error: {}", err);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to process gift card"
+            }))
+        }
+        _ => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": err.to_string()
+        })),
+    }
+}
+
+/*
+    /payment/apply-gift-card
+*/
+pub async fn apply_gift_card(
+    data: web::Data<Arc<Client>>,
+    input: web::Json<ApplyGiftCardInput>,
+) -> impl Responder {
+    let service = GiftCardService::new(data.into_inner().as_ref().clone());
+    let input = input.into_inner();
+
+    match service.find_redeemable(&input.code).await {
+        Ok(gift_card) => {
+            let split = input
+                .amount
+                .map(|amount| split_payment(amount, gift_card.remaining_balance));
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "code": gift_card.code,
+                "remaining_balance": gift_card.remaining_balance,
+                "currency": gift_card.currency,
+                "expires_at": gift_card.expires_at,
+                "gift_card_amount": split.map(|s| s.gift_card_amount),
+                "amount_due": split.map(|s| s.card_amount),
+                "paid_in_full": split.map(|s| s.paid_in_full()),
+            }))
+        }
+        Err(err) => gift_card_error_response(err),
+    }
+}
+
+/*
+    /admin/gift-cards
+*/
+pub async fn create_gift_card(
+    data: web::Data<Arc<Client>>,
+    input: web::Json<GiftCardInput>,
+) -> impl Responder {
+    let input = input.into_inner();
+    if input.initial_balance <= 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "initial_balance must be positive"
+        }));
+    }
+
+    let collection = gift_cards(data.as_ref());
+    let code = normalize_code(&input.code);
+
+    match collection.find_one(doc! { "code": &code }).await {
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A gift card with this code already exists"
+            }));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Failed to check gift card code: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to create gift card");
+        }
+    }
+
+    let now = DateTime::now();
+    let mut gift_card = GiftCard {
+        id: None,
+        code,
+        initial_balance: input.initial_balance,
+        remaining_balance: input.initial_balance,
+        currency: input.currency.unwrap_or_else(|| "usd".to_string()).to_lowercase(),
+        expires_at: input.expires_at,
+        active: input.active.unwrap_or(true),
+        created_at: Some(now),
+        updated_at: Some(now),
+    };
+
+    match collection.insert_one(&gift_card).await {
+        Ok(result) => {
+            gift_card.id = result.inserted_id.as_object_id();
+            HttpResponse::Created().json(gift_card)
+        }
+        Err(e) => {
+            eprintln!("Failed to create gift card: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to create gift card")
+        }
+    }
+}
+
+pub async fn list_gift_cards(data: web::Data<Arc<Client>>) -> impl Responder {
+    match gift_cards(data.as_ref()).find(doc! {}).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<GiftCard>>().await {
+            Ok(cards) => HttpResponse::Ok().json(cards),
+            Err(e) => {
+                eprintln!("Failed to read gift cards: {:?}", e);
+                HttpResponse::InternalServerError().body("Failed to fetch gift cards")
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to fetch gift cards: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch gift cards")
+        }
+    }
+}
+
+/*
+    /admin/gift-cards/{id}
+*/
+pub async fn get_gift_card(data: web::Data<Arc<Client>>, path: web::Path<String>) -> impl Responder {
+    let id = match ObjectId::parse_str(path.into_inner()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid gift card ID"),
+    };
+
+    match gift_cards(data.as_ref()).find_one(doc! { "_id": id }).await {
+        Ok(Some(gift_card)) => HttpResponse::Ok().json(gift_card),
+        Ok(None) => HttpResponse::NotFound().body("Gift card not found"),
+        Err(e) => {
+            eprintln!("Failed to fetch gift card: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch gift card")
+        }
+    }
+}
+
+pub async fn update_gift_card(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<String>,
+    input: web::Json<GiftCardUpdate>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(path.into_inner()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid gift card ID"),
+    };
+    let input = input.into_inner();
+
+    let mut set = Document::new();
+    if let Some(remaining_balance) = input.remaining_balance {
+        if remaining_balance < 0 {
+            return HttpResponse::BadRequest().body("remaining_balance cannot be negative");
+        }
+        set.insert("remaining_balance", remaining_balance);
+    }
+    if let Some(expires_at) = input.expires_at {
+        set.insert("expires_at", expires_at);
+    }
+    if let Some(active) = input.active {
+        set.insert("active", active);
+    }
+    set.insert("updated_at", DateTime::now());
+
+    let collection = gift_cards(data.as_ref());
+    match collection
+        .update_one(doc! { "_id": id }, doc! { "$set": set })
+        .await
+    {
+        Ok(result) if result.matched_count == 0 => {
+            HttpResponse::NotFound().body("Gift card not found")
+        }
+        Ok(_) => match collection.find_one(doc! { "_id": id }).await {
+            Ok(Some(gift_card)) => HttpResponse::Ok().json(gift_card),
+            _ => HttpResponse::Ok().json(serde_json::json!({ "success": true })),
+        },
+        Err(e) => {
+            eprintln!("Failed to update gift card: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update gift card")
+        }
+    }
+}
+
+/// Gift cards are deactivated rather than deleted so redemptions keep their history
+pub async fn delete_gift_card(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(path.into_inner()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid gift card ID"),
+    };
+
+    match gift_cards(data.as_ref())
+        .update_one(
+            doc! { "_id": id },
+            doc! { "$set": { "active": false, "updated_at": DateTime::now() } },
+        )
+        .await
+    {
+        Ok(result) if result.matched_count == 0 => {
+            HttpResponse::NotFound().body("Gift card not found")
+        }
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Gift card deactivated"
+        })),
+        Err(e) => {
+            eprintln!("Failed to deactivate gift card: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to deactivate gift card")
+        }
+    }
+}
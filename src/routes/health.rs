@@ -1,12 +1,15 @@
-use actix_web::{web, HttpResponse, Responder};
-use google_cloud_storage::client::{Client as GcsClient, ClientConfig};
-use google_cloud_storage::http::objects::list::ListObjectsRequest;
-use mongodb::{bson::doc, Client};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::Client;
 use serde::Serialize;
 use std::collections::HashMap;
-use std::env;
 use std::sync::Arc;
 
+use crate::config::{AppConfig, OAuthClientSettings};
+use crate::services::credential_check::GcsProbe;
+use crate::services::self_check::{self, CheckResult, CheckStatus};
+use crate::services::storage::BucketKind;
+use crate::services::write_behind::WriteBehindQueue;
+
 #[derive(Serialize)]
 struct HealthStatus {
     status: String,
@@ -21,46 +24,84 @@ struct ServiceStatus {
     details: Option<String>,
 }
 
-pub async fn health_check(client: web::Data<Arc<Client>>) -> impl Responder {
+/// Shared checks report "ok" unless they failed or only warned
+impl From<CheckResult> for ServiceStatus {
+    fn from(result: CheckResult) -> Self {
+        let status = match result.status {
+            CheckStatus::Ok | CheckStatus::Skipped => "ok",
+            CheckStatus::Warn | CheckStatus::Fail => "error",
+        };
+        ServiceStatus {
+            status: status.to_string(),
+            details: Some(result.details),
+        }
+    }
+}
+
+pub async fn health_check(
+    client: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    writes: Option<web::Data<WriteBehindQueue>>,
+) -> impl Responder {
     let mut health = HealthStatus {
         status: "ok".to_string(),
         services: HashMap::new(),
-        environment: env::var("RUST_ENV").unwrap_or("development".to_string()),
+        environment: config.environment.clone(),
         version: env!("CARGO_PKG_VERSION").to_string(),
     };
 
-    // Check MongoDB connection
-    let mongo_result = check_mongodb(&client).await;
+    // Check MongoDB connection and the collections requests can't do without
+    let mongo_result = ServiceStatus::from(self_check::check_mongodb(&client).await);
+    if mongo_result.status != "ok" {
+        // Log error for internal visibility
+        eprintln!("MongoDB health check failed: {}", mongo_result.details.clone().unwrap_or_default());
+    }
     health
         .services
         .insert("mongodb".to_string(), mongo_result.clone());
 
-    // Check Stripe API (just validate key existence for now)
-    let stripe_result = check_stripe_api().await;
+    // Reads can succeed while writes fail (a primary stepping down)
+    let writes_result = check_mongodb_writes(writes.as_ref().map(|writes| writes.get_ref()));
+    health
+        .services
+        .insert("mongodb_writes".to_string(), writes_result.clone());
+
+    // Check the Stripe key is set and well-formed; `--self-check` also asks Stripe
+    let stripe_result =
+        ServiceStatus::from(self_check::check_stripe_key(Some(config.stripe_secret_key.as_str())));
     health
         .services
         .insert("stripe".to_string(), stripe_result.clone());
 
     // Check Google Auth API connection
-    let google_auth_result = check_google_auth().await;
+    let google_auth_result = check_google_auth(&config.google_oauth).await;
     health
         .services
         .insert("google_auth".to_string(), google_auth_result.clone());
 
     // Check Facebook Auth API connection
-    let facebook_auth_result = check_facebook_auth().await;
+    let facebook_auth_result = check_facebook_auth(&config.facebook_oauth).await;
     health
         .services
         .insert("facebook_auth".to_string(), facebook_auth_result.clone());
 
     // Check Cloud Storage connection
-    let cloud_storage_result = check_cloud_storage().await;
+    let bucket_named = |name: &str| {
+        BucketKind::ALL
+            .into_iter()
+            .find(|kind| kind.env_var() == name)
+            .and_then(|kind| config.storage.bucket(kind))
+            .map(|bucket| bucket.bucket.clone())
+    };
+    let cloud_storage_result =
+        ServiceStatus::from(self_check::check_storage(&GcsProbe, bucket_named).await);
     health
         .services
         .insert("cloud_storage".to_string(), cloud_storage_result.clone());
 
     // Determine overall status (if any service is not ok, the overall status is degraded)
     if mongo_result.status != "ok"
+        || writes_result.status != "ok"
         || stripe_result.status != "ok"
         || google_auth_result.status != "ok"
         || facebook_auth_result.status != "ok"
@@ -72,56 +113,38 @@ pub async fn health_check(client: web::Data<Arc<Client>>) -> impl Responder {
     HttpResponse::Ok().json(health)
 }
 
-async fn check_mongodb(client: &web::Data<Arc<Client>>) -> ServiceStatus {
-    match client
-        .database("Account")
-        .run_command(doc! {"ping": 1})
-        .await
-    {
-        Ok(_) => ServiceStatus {
+fn check_mongodb_writes(writes: Option<&WriteBehindQueue>) -> ServiceStatus {
+    let Some(writes) = writes else {
+        return ServiceStatus {
             status: "ok".to_string(),
-            details: Some("Connected successfully to MongoDB".to_string()),
-        },
-        Err(e) => {
-            // Log error for internal visibility
-            eprintln!("MongoDB health check failed: {}", e);
-
-            ServiceStatus {
-                status: "error".to_string(),
-                details: Some(format!("Failed to connect: {}", e)),
-            }
+            details: Some("Write-behind queue not running".to_string()),
+        };
+    };
+    let health = writes.health();
+    if health.writes_failing() {
+        ServiceStatus {
+            status: "error".to_string(),
+            details: Some(format!(
+                "Reads OK, writes failing: {}",
+                health.last_error.unwrap_or_default()
+            )),
         }
-    }
-}
-
-async fn check_stripe_api() -> ServiceStatus {
-    // Just validate key existence for basic check
-    // In a more comprehensive check, you could make a test API call
-    match env::var("STRIPE_SECRET_KEY") {
-        Ok(key) => {
-            let masked_key = if key.len() > 8 {
-                format!("{}***{}", &key[0..4], &key[key.len() - 4..])
-            } else {
-                "***".to_string()
-            };
-
-            ServiceStatus {
-                status: "ok".to_string(),
-                details: Some(format!("Stripe API key configured ({})", masked_key)),
-            }
+    } else {
+        ServiceStatus {
+            status: "ok".to_string(),
+            details: Some(format!(
+                "{} background writes stored, {} failed, {} dropped",
+                health.succeeded, health.failed, health.dropped
+            )),
         }
-        Err(_) => ServiceStatus {
-            status: "error".to_string(),
-            details: Some("STRIPE_SECRET_KEY not configured".to_string()),
-        },
     }
 }
 
-async fn check_google_auth() -> ServiceStatus {
-    // Check if required environment variables are set
-    let client_id = env::var("GOOGLE_CLIENT_ID").ok();
-    let client_secret = env::var("GOOGLE_CLIENT_SECRET").ok();
-    let redirect_uri = env::var("GOOGLE_REDIRECT_URI").ok();
+async fn check_google_auth(settings: &OAuthClientSettings) -> ServiceStatus {
+    // Check the client is fully configured
+    let client_id = settings.client_id.clone();
+    let client_secret = settings.client_secret.clone();
+    let redirect_uri = settings.redirect_uri.clone();
 
     if client_id.is_some() && client_secret.is_some() && redirect_uri.is_some() {
         let id = client_id.unwrap();
@@ -159,11 +182,11 @@ async fn check_google_auth() -> ServiceStatus {
     }
 }
 
-async fn check_facebook_auth() -> ServiceStatus {
-    // Check if required environment variables are set
-    let client_id = env::var("FACEBOOK_CLIENT_ID").ok();
-    let client_secret = env::var("FACEBOOK_CLIENT_SECRET").ok();
-    let redirect_uri = env::var("FACEBOOK_REDIRECT_URI").ok();
+async fn check_facebook_auth(settings: &OAuthClientSettings) -> ServiceStatus {
+    // Check the client is fully configured
+    let client_id = settings.client_id.clone();
+    let client_secret = settings.client_secret.clone();
+    let redirect_uri = settings.redirect_uri.clone();
 
     if client_id.is_some() && client_secret.is_some() && redirect_uri.is_some() {
         let id = client_id.unwrap();
@@ -201,69 +224,34 @@ async fn check_facebook_auth() -> ServiceStatus {
     }
 }
 
-async fn check_cloud_storage() -> ServiceStatus {
-    // Check if required environment variables are set
-    let itinerary_bucket = env::var("ITINERARY_BUCKET").ok();
-    let profile_pic_bucket = env::var("PROFILE_PIC_BUCKET").ok();
-    let activity_bucket = env::var("ACTIVITY_BUCKET").ok();
-
-    if itinerary_bucket.is_none() || profile_pic_bucket.is_none() || activity_bucket.is_none() {
-        let mut missing = Vec::new();
-
-        if itinerary_bucket.is_none() {
-            missing.push("ITINERARY_BUCKET");
-        }
-        if profile_pic_bucket.is_none() {
-            missing.push("PROFILE_PIC_BUCKET");
-        }
-        if activity_bucket.is_none() {
-            missing.push("ACTIVITY_BUCKET");
-        }
-
-        return ServiceStatus {
-            status: "error".to_string(),
-            details: Some(format!(
-                "Missing bucket configuration: {}",
-                missing.join(", ")
-            )),
-        };
-    }
-
-    // Capture the bucket name before using it in the async operation
-    let bucket_name = itinerary_bucket.as_ref().unwrap().clone();
-
-    // Create Google Cloud Storage client
-    let client_config = match ClientConfig::default().with_auth().await {
-        Ok(config) => config,
-        Err(e) => {
-            return ServiceStatus {
-                status: "error".to_string(),
-                details: Some(format!("Failed to initialize GCS client config: {}", e)),
-            };
-        }
-    };
-
-    let gcs_client = GcsClient::new(client_config);
-
-    // Create a list request for the bucket with a limit of 1 object
-    let list_request = ListObjectsRequest {
-        bucket: bucket_name.clone(),
-        max_results: Some(1),
-        ..Default::default()
-    };
+// General request diagnostic endpoint
+pub async fn request_info(req: HttpRequest) -> impl Responder {
+    let protocol = req.connection_info().scheme().to_string();
+    let version = format!("{:?}", req.version());
+
+    let headers = req
+        .headers()
+        .iter()
+        .map(|(name, value)| format!("{}: {:?}", name, value))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    HttpResponse::Ok().content_type("text/plain").body(format!(
+        "Protocol: {}\nHTTP Version: {}\n\nHeaders:\n{}",
+        protocol, version, headers
+    ))
+}
 
-    // Test the connection by trying to list objects
-    match gcs_client.list_objects(&list_request).await {
-        Ok(_) => ServiceStatus {
-            status: "ok".to_string(),
-            details: Some(format!(
-                "Connected to Cloud Storage: itinerary bucket '{}' accessible",
-                bucket_name
-            )),
-        },
-        Err(e) => ServiceStatus {
-            status: "error".to_string(),
-            details: Some(format!("Failed to access Cloud Storage bucket: {}", e)),
-        },
-    }
+/// Diagnostic endpoints
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/health", web::get().to(health_check))
+        .route("/request-info", web::get().to(request_info))
+        .route(
+            "/",
+            web::get().to(|| async {
+                HttpResponse::Ok()
+                    .content_type("text/plain")
+                    .body("ACTOTA API is running")
+            }),
+        );
 }
@@ -1,11 +1,49 @@
+use crate::config::AppConfig;
+use crate::db::mongo::read_only_collection;
+use crate::middleware::auth::{optional_claims, ApiTokenMiddleware, AuthMiddleware, Claims};
+use crate::middleware::typed_json::TypedJson;
+use crate::routes::{limit_exceeded, trip_limits};
+use crate::routes::pagination::Page;
+use crate::routes::versioning::ResponseVersion;
+use crate::models::activity::DEFAULT_MIN_ACTIVITY_MINUTES;
+use crate::models::api_error::ApiError;
+use crate::models::content_flag::{ContentType, ReportInput};
 use crate::models::itinerary::base::{Activity, ItinerarySubmission};
 use crate::models::itinerary::populated::PopulatedFeaturedVacation;
-use crate::models::search_response::{ActivitySummary, PopulatedDayItem, SearchResponseItem};
+use crate::models::itinerary::transforms::Lookups;
+use crate::models::search_response::{
+    ActivitySummary, DetailSection, DetailSections, ItineraryView, PopulatedDayItem, ResultOrder,
+    SearchEnvelope, SearchResponseItem, SearchResponseV2,
+};
+use crate::models::money::Money;
 use crate::models::{itinerary::base::FeaturedVacation, search::SearchItinerary};
-use crate::services::itinerary_search_service::search_or_generate_itineraries;
+use crate::services::account_service::EmailService;
+use crate::services::availability_service::{
+    parse_month, AvailabilityCache, AvailabilityError, AvailabilityService,
+};
+use crate::services::content_flag_service::{ContentFlagError, ContentFlagService};
+use crate::services::destination_constraints;
+use crate::services::fx_service::{DisplayPrice, ExchangeRates, FxRates, BASE_CURRENCY};
+use crate::services::feature_flags::Flags;
+use crate::services::generation_budget::GenerationBudget;
+use crate::services::geocoding_service::GeocodingService;
+use crate::services::generation_trace::trace_requested;
+use crate::services::itinerary_search_service::{search_or_generate_itineraries, GenerationPolicy};
+use crate::services::pricing_service::PersonPrice;
+use crate::services::recent_search_service::RecentSearchService;
+use crate::services::recently_viewed_service::RecentlyViewedService;
+use crate::services::route_map_service::{RouteMapError, RouteMapService};
+use crate::services::image_fallback::{activity_images, StockImages};
+use crate::services::itinerary_generation_service::GenerationError;
+use crate::services::image_service::{ImageSize, ImageUrlBuilder};
 use crate::services::itinerary_service::get_images;
-use crate::services::search_scoring::AsyncSearchScorer;
-use actix_web::{web, HttpResponse, Responder};
+use crate::services::itinerary_validation_service::ItineraryValidationService;
+use crate::services::search_experiments::{assignment_subject, SearchExperimentService, SESSION_ID_HEADER};
+use crate::services::search_scoring::{AsyncSearchScorer, ScoredItinerary, SearchWeights};
+use crate::services::storage::{BucketKind, Storage, DEFAULT_STORAGE_URL};
+use crate::services::units::{self, UnitSystem};
+use crate::services::write_behind::WriteBehindQueue;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
 use bson::{doc, DateTime};
 use futures::TryStreamExt;
 use mongodb::{bson::oid::ObjectId, Client};
@@ -17,13 +55,258 @@ use std::sync::Arc;
 pub struct PaginationQuery {
     pub limit: Option<i64>,
     pub page: Option<i64>,
+    #[serde(default)]
+    pub image_size: ImageSize,
+    /// Include the day items left out for missing activities or accommodations
+    #[serde(default)]
+    pub verbose: bool,
+    /// Wrap the results in a `Paginated` envelope
+    #[serde(default)]
+    pub envelope: bool,
+    /// `imperial` or `metric`, overriding the viewer's preference
+    pub units: Option<UnitSystem>,
+}
+
+#[derive(Deserialize)]
+pub struct ViewQuery {
+    #[serde(default)]
+    pub view: ItineraryView,
+    /// Show prices in this currency as well as USD, overriding the viewer's preference
+    pub display_currency: Option<String>,
+    /// `imperial` or `metric`, overriding the viewer's preference
+    pub units: Option<UnitSystem>,
+    /// `thumb` or `medium` for resized images; originals by default
+    #[serde(default)]
+    pub image_size: ImageSize,
+    /// `price_asc` or `price_desc`; by relevance otherwise
+    #[serde(default)]
+    pub sort: ResultOrder,
+    /// Include the day items left out for missing activities or accommodations
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+/// Generation as the feature flags allow it, traced when the request asks and
+/// search debugging is on
+fn generation_policy(flags: &Flags, config: &AppConfig, req: &HttpRequest) -> GenerationPolicy {
+    GenerationPolicy {
+        generate: flags.itinerary_generation(),
+        persist: flags.persist_generated_itineraries(),
+        trace: flags.search_debug() && trace_requested(req),
+        min_activity_minutes: config.min_activity_minutes,
+        limits: config.trip_limits,
+        activity_defaults: config.activity_defaults,
+        budget: GenerationBudget::new(config.generation_budget),
+        moderator: config.moderator.clone(),
+        day_floors: config.day_floors,
+        vertex_search: config.vertex_search.clone(),
+    }
+}
+
+fn image_urls(config: &AppConfig, size: ImageSize) -> ImageUrlBuilder {
+    let storage_url = config
+        .storage
+        .bucket(BucketKind::ItineraryImages)
+        .map_or(DEFAULT_STORAGE_URL, |bucket| bucket.base_url.as_str());
+    ImageUrlBuilder::new(storage_url, config.image_resize_url.as_deref(), size)
+}
+
+/// Rates and currency for the `display_price` fields of a response
+struct PriceDisplay {
+    rates: ExchangeRates,
+    currency: String,
+}
+
+impl PriceDisplay {
+    fn price(&self, usd: Money) -> Option<DisplayPrice> {
+        self.rates.display_price(usd, &self.currency)
+    }
+}
+
+/// Currency to show prices in: `?display_currency=` wins over the signed-in viewer's
+/// `preferred_currency`, and USD viewers get no display price. Answers 400 when the
+/// requested currency has no rate.
+async fn price_display(
+    req: &HttpRequest,
+    client: &Client,
+    requested: Option<&str>,
+    fx: &FxRates,
+) -> Result<Option<PriceDisplay>, HttpResponse> {
+    let rates = fx.current();
+    if let Some(currency) = requested {
+        if rates.rate(currency).is_none() {
+            return Err(HttpResponse::BadRequest()
+                .json(serde_json::json!({ "error": format!("Unsupported display currency: {}", currency) })));
+        }
+        return Ok(Some(PriceDisplay {
+            rates,
+            currency: currency.to_uppercase(),
+        }));
+    }
+
+    let Some(user_id) = optional_claims(req).and_then(|claims| ObjectId::parse_str(&claims.user_id).ok())
+    else {
+        return Ok(None);
+    };
+    let users: mongodb::Collection<bson::Document> = client.database("Account").collection("Users");
+    let preferred = match users
+        .find_one(doc! { "_id": user_id })
+        .projection(doc! { "preferred_currency": 1 })
+        .await
+    {
+        Ok(Some(user)) => user.get_str("preferred_currency").ok().map(str::to_uppercase),
+        Ok(None) => None,
+        Err(e) => {
+            // Prices still show in USD; the preference is a nicety
+            eprintln!("Failed to load display currency preference: {:?}", e);
+            None
+        }
+    };
+    Ok(preferred
+        .filter(|currency| currency != BASE_CURRENCY && rates.rate(currency).is_some())
+        .map(|currency| PriceDisplay { rates, currency }))
+}
+
+#[derive(Deserialize)]
+pub struct UnitsQuery {
+    /// `imperial` or `metric`, overriding the viewer's preference
+    pub units: Option<UnitSystem>,
+}
+
+/// Units for the distances and temperatures of a response: `?units=` wins over
+/// the signed-in viewer's `preferred_units`, which wins over the
+/// `Accept-Language` region. Imperial when none of them says.
+pub(crate) async fn unit_system(req: &HttpRequest, client: &Client, requested: Option<UnitSystem>) -> UnitSystem {
+    let preferred = match requested {
+        Some(_) => None,
+        None => match optional_claims(req).and_then(|claims| ObjectId::parse_str(&claims.user_id).ok()) {
+            None => None,
+            Some(user_id) => {
+                let users: mongodb::Collection<bson::Document> = client.database("Account").collection("Users");
+                match users
+                    .find_one(doc! { "_id": user_id })
+                    .projection(doc! { "preferred_units": 1 })
+                    .await
+                {
+                    Ok(user) => user
+                        .and_then(|user| user.get_str("preferred_units").ok().map(str::to_string))
+                        .and_then(|units| serde_json::from_value(serde_json::Value::String(units)).ok()),
+                    Err(e) => {
+                        // Falls back to the request's language; the preference is a nicety
+                        eprintln!("Failed to load units preference: {:?}", e);
+                        None
+                    }
+                }
+            }
+        },
+    };
+    let accept_language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    UnitSystem::resolve(requested, preferred, accept_language)
+}
+
+/// Weights to score a search with: the running experiment's when the signed-in
+/// user, or the `X-Session-Id` session, falls in its traffic, else the configured
+/// ones. Also the experiment's name, for the submission log and `meta.experiment`.
+async fn search_weights(req: &HttpRequest, client: &Arc<Client>, config: &AppConfig) -> (Arc<SearchWeights>, Option<String>) {
+    let user_id = optional_claims(req).and_then(|claims| ObjectId::parse_str(&claims.user_id).ok());
+    let session_id = req
+        .headers()
+        .get(SESSION_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let subject = assignment_subject(user_id, session_id);
+    match SearchExperimentService::new(client.clone())
+        .assign(subject.as_deref(), DateTime::now())
+        .await
+    {
+        Some(experiment) => (
+            Arc::new(experiment.weights.apply(&config.search_weights)),
+            Some(experiment.name),
+        ),
+        None => (config.search_weights.clone(), None),
+    }
+}
+
+/// 422 naming the rule when the search breaks a destination's season or minimum
+/// nights. The rules failing to load doesn't block the search.
+async fn destination_constraint_check(client: &Client, search: &SearchItinerary) -> Result<(), HttpResponse> {
+    match destination_constraints::check_search(client, search).await {
+        Ok(Some(violation)) => Err(HttpResponse::UnprocessableEntity().json(violation.to_api_error())),
+        Ok(None) => Ok(()),
+        Err(e) => {
+            eprintln!("Failed to load destination constraints: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Why a search came back with nothing: 422 when there's nothing to build an
+/// itinerary from, 400 for input the traveler can fix, 503 while activity search
+/// is down, 500 otherwise
+fn generation_error_response(err: GenerationError) -> HttpResponse {
+    match err {
+        GenerationError::NoActivities => HttpResponse::UnprocessableEntity().json(ApiError::new(err.to_string())),
+        GenerationError::MissingDates | GenerationError::InvalidInput(_) => {
+            HttpResponse::BadRequest().json(ApiError::new(err.to_string()))
+        }
+        GenerationError::ExternalServiceUnavailable(_) => {
+            eprintln!("Failed to search/generate itineraries: {}", err);
+            HttpResponse::ServiceUnavailable().json(ApiError::new("Activity search is unavailable, try again shortly"))
+        }
+        GenerationError::Persistence(_) => {
+            eprintln!("Failed to search/generate itineraries: {}", err);
+            HttpResponse::InternalServerError().body("Failed to search or generate itineraries")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IncludeQuery {
+    /// Comma-separated sections of the full view to return; all of them when unset
+    pub include: Option<String>,
 }
 
 /*
-    /api/itineraries/{id}
+    /api/itineraries/{id}?include=days,pricing
+
+    The full view carries every section: `days`, `activities`, `images`,
+    `lodging` (the accommodations stayed at), `pricing` (person_cost, the cost
+    breakdown and display_price), `map` (start and end locations) and `stats`
+    (counts of days and day items). `include` returns only the sections listed,
+    plus the id, name, group sizes, length and description, and skips the
+    lookups the others need: `include=images` doesn't look up activities unless
+    the itinerary has no images of its own. Unknown sections are a 400.
 */
-pub async fn get_by_id(path: web::Path<String>, data: web::Data<Arc<Client>>) -> impl Responder {
+#[allow(clippy::too_many_arguments)]
+pub async fn get_by_id(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ViewQuery>,
+    include: web::Query<IncludeQuery>,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    fx: web::Data<FxRates>,
+    flags: web::Data<Flags>,
+    version: ResponseVersion,
+) -> impl Responder {
+    let sections = match include.include.as_deref().map(DetailSections::parse) {
+        None => DetailSections::all(),
+        Some(Ok(sections)) => sections,
+        Some(Err(unknown)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Unknown sections: {}", unknown.join(", ")),
+                "valid_sections": DetailSection::ALL.map(DetailSection::name),
+            }));
+        }
+    };
     let client = data.into_inner();
+    let display = match price_display(&req, &client, query.display_currency.as_deref(), &fx).await {
+        Ok(display) => display,
+        Err(response) => return response,
+    };
+    let units = unit_system(&req, &client, query.units).await;
     let collection: mongodb::Collection<FeaturedVacation> =
         client.database("Itineraries").collection("Featured");
     let id: ObjectId = match ObjectId::parse_str(path.into_inner().as_str()) {
@@ -31,13 +314,33 @@ pub async fn get_by_id(path: web::Path<String>, data: web::Data<Arc<Client>>) ->
         Err(_) => return HttpResponse::BadRequest().body("Invalid ID"),
     };
 
-    let filter = doc! { "_id": id };
+    let filter = doc! { "_id": id, "taken_down_at": null };
 
     match collection.find_one(filter).await {
         Ok(Some(doc)) => {
-            let processed_doc = get_images(vec![doc.clone()]).await;
+            // Signed-in travelers get it back under "recently viewed"
+            if let Some(user_id) = optional_claims(&req).and_then(|claims| ObjectId::parse_str(&claims.user_id).ok()) {
+                RecentlyViewedService::new(client.as_ref().clone()).record_in_background(user_id, id);
+            }
 
-            match processed_doc[0].clone().populate(&client).await {
+            let processed_doc = get_images(vec![doc.clone()], &Storage::new(config.storage.clone())).await;
+
+            if query.view == ItineraryView::Summary {
+                let mut item = summary_item(processed_doc[0].clone());
+                item.display_price = display
+                    .as_ref()
+                    .and_then(|display| display.price(item.person_cost?));
+                item.description = units::render(&item.description, units);
+                image_urls(&config, query.image_size).apply(&mut item.images);
+                return version.ok(&item);
+            }
+
+            let has_own_images = processed_doc[0].images.as_ref().is_some_and(|images| !images.is_empty());
+            let lookups = Lookups {
+                activities: sections.needs_activities(has_own_images),
+                accommodations: sections.needs_accommodations(),
+            };
+            match processed_doc[0].clone().populate_with(client.as_ref().as_ref(), lookups, config.activity_bucket.as_deref()).await {
                 Ok(mut populated) => {
                     // Calculate costs using the pricing service
                     let activity_cost =
@@ -71,11 +374,30 @@ pub async fn get_by_id(path: web::Path<String>, data: web::Data<Arc<Client>>) ->
                     populated.set_lodging_cost(lodging_cost);
                     populated.set_transport_cost(transport_cost);
                     populated.set_service_fee(service_fee);
+                    populated.set_display_price(display.as_ref().and_then(|display| {
+                        PersonPrice::stored(Some(person_cost)).amount().and_then(|usd| display.price(usd))
+                    }));
 
                     // Populate images from activities if no itinerary images exist
                     populated.populate_images_from_activities();
+                    if let Some(images) = populated.base.images.as_mut() {
+                        image_urls(&config, query.image_size).apply(images);
+                    }
+                    populated.base.description = units::render(&populated.base.description, units);
+                    populated.show_population_warnings(query.verbose && flags.search_debug());
+
+                    let mut response = match serde_json::to_value(&populated) {
+                        Ok(serde_json::Value::Object(response)) => response,
+                        _ => {
+                            eprintln!("Failed to serialize itinerary {}", id);
+                            return HttpResponse::InternalServerError().body("Failed to populate itinerary data");
+                        }
+                    };
+                    response.insert("lodging".to_string(), serde_json::json!(populated.lodging()));
+                    response.insert("stats".to_string(), serde_json::json!(populated.base.days.item_counts()));
+                    sections.project(&mut response);
 
-                    HttpResponse::Ok().json(populated)
+                    version.ok(&response)
                 }
                 Err(err) => {
                     eprintln!("Failed to populate data: {:?}", err);
@@ -91,57 +413,201 @@ pub async fn get_by_id(path: web::Path<String>, data: web::Data<Arc<Client>>) ->
     }
 }
 
+#[derive(Deserialize)]
+pub struct AvailabilityMonthQuery {
+    /// Month to report, formatted as `YYYY-MM`
+    pub month: String,
+}
+
 /*
-    /api/itineraries (Get all itineraries - public endpoint)
+    /api/itineraries/{id}/availability?month=YYYY-MM
 */
-pub async fn get_all(
+pub async fn get_availability(
+    path: web::Path<String>,
+    query: web::Query<AvailabilityMonthQuery>,
     data: web::Data<Arc<Client>>,
-    query: web::Query<PaginationQuery>,
+    config: web::Data<AppConfig>,
+    cache: web::Data<AvailabilityCache>,
 ) -> impl Responder {
-    println!("Handling request for /api/itineraries");
+    let id = match ObjectId::parse_str(path.into_inner().as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid ID"),
+    };
 
-    // Log cloud storage environment variables (without values)
-    if let Ok(_) = std::env::var("CLOUD_STORAGE_URL") {
-        println!("CLOUD_STORAGE_URL is set");
-    } else {
-        println!("CLOUD_STORAGE_URL is NOT set");
+    let today = chrono::Utc::now().date_naive();
+    let month_start = match parse_month(&query.month, today) {
+        Ok(month_start) => month_start,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }));
+        }
+    };
+
+    let service = AvailabilityService::new(data.into_inner().as_ref().clone());
+    match service
+        .month_availability(
+            &cache,
+            id,
+            month_start,
+            today,
+            config.availability_limited_threshold,
+        )
+        .await
+    {
+        Ok(days) => HttpResponse::Ok().json(serde_json::json!({
+            "itinerary_id": id.to_hex(),
+            "month": month_start.format("%Y-%m").to_string(),
+            "days": days,
+        })),
+        Err(AvailabilityError::NotFound) => HttpResponse::NotFound().body("Itinerary not found"),
+        Err(e) => {
+            eprintln!("Failed to compute availability for {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to compute availability")
+        }
     }
+}
 
-    if let Ok(_) = std::env::var("ITINERARY_BUCKET") {
-        println!("ITINERARY_BUCKET is set");
-    } else {
-        println!("ITINERARY_BUCKET is NOT set");
+/*
+    /api/itineraries/{id}/distance-matrix?units=metric
+
+    Each day's `distance` is formatted in the viewer's units; `distance_meters`
+    and the matrix stay in metres.
+
+    - GENERATION_MAX_MAPS_LOOKUPS: Maps lookups one request may make (default: 80).
+      Past it the remaining legs are straight-line estimates.
+*/
+pub async fn get_distance_matrix(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<UnitsQuery>,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(path.into_inner().as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid ID"),
+    };
+
+    let client = data.into_inner();
+    let units = unit_system(&req, &client, query.units).await;
+    let service = RouteMapService::new(
+        client.as_ref().clone(),
+        config.google_maps_api_key.as_deref(),
+        config.distance_prefilter,
+        GenerationBudget::new(config.generation_budget),
+    );
+    match service.distance_matrix(id).await {
+        // Stops don't move, so a complete matrix keeps for a day; a partial one
+        // is rechecked sooner in case the coordinate backfill has caught up
+        Ok(mut matrix) => {
+            let max_age = if matrix.is_complete() { 86_400 } else { 300 };
+            matrix.show_distances(units);
+            HttpResponse::Ok()
+                .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", max_age)))
+                .insert_header((header::VARY, "Accept-Language, Authorization"))
+                .json(matrix)
+        }
+        Err(RouteMapError::NotFound) => HttpResponse::NotFound().body("Itinerary not found"),
+        Err(e @ RouteMapError::NoCoordinates) => {
+            HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            eprintln!("Failed to build distance matrix for {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to build distance matrix")
+        }
     }
+}
 
-    if let Ok(_) = std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
-        println!("GOOGLE_APPLICATION_CREDENTIALS is set");
-    } else {
-        println!("GOOGLE_APPLICATION_CREDENTIALS is NOT set");
+/*
+    /api/itineraries/{id}/map-geojson (Public endpoint)
+    The itinerary's stops and each day's route as a GeoJSON FeatureCollection
+*/
+pub async fn get_map_geojson(
+    path: web::Path<String>,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(path.into_inner().as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid ID"),
+    };
+
+    let client = data.into_inner().as_ref().clone();
+    // Without a Google Maps key, only stored coordinates are used
+    let maps_api_key = config.google_maps_api_key.as_deref();
+    let geocoding = GeocodingService::new(client.clone(), maps_api_key).ok();
+    let service = RouteMapService::new(
+        client,
+        maps_api_key,
+        config.distance_prefilter,
+        GenerationBudget::new(config.generation_budget),
+    );
+    match service.map_geojson(id, geocoding.as_ref()).await {
+        Ok(map) => {
+            let max_age = if map.is_complete() { 86_400 } else { 300 };
+            HttpResponse::Ok()
+                .content_type("application/geo+json")
+                .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", max_age)))
+                .json(map)
+        }
+        Err(RouteMapError::NotFound) => HttpResponse::NotFound().body("Itinerary not found"),
+        Err(e) => {
+            eprintln!("Failed to build map layer for {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to build map layer")
+        }
     }
+}
+
+/*
+    /api/itineraries (Get all itineraries - public endpoint)
+    ?page=&limit= page through them, 50 at a time by default; ?envelope=true adds the paging
+*/
+pub async fn get_all(
+    req: HttpRequest,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    flags: web::Data<Flags>,
+    query: web::Query<PaginationQuery>,
+    version: ResponseVersion,
+) -> impl Responder {
+    println!("Handling request for /api/itineraries");
 
     let client = data.into_inner();
+    let units = unit_system(&req, &client, query.units).await;
 
     // Return all itineraries
-    let collection = client
-        .database("Itineraries")
-        .collection::<FeaturedVacation>("Featured");
+    let collection =
+        read_only_collection::<FeaturedVacation>(&client, "Itineraries", "Featured");
 
-    // Extract pagination parameters with defaults
-    let limit = query.limit.unwrap_or(50); // Default to 50 items per page if not specified
-    let page = query.page.unwrap_or(1); // Default to page 1
-    let skip = (page - 1) * limit;
+    // Extract pagination parameters with defaults (50 items per page, page 1)
+    let page = Page::new(query.page, Some(query.limit.unwrap_or(50)));
+    let limit = page.limit.unwrap_or(50);
+    let skip = page.skip();
 
     println!(
         "Pagination - page: {}, limit: {}, skip: {}",
-        page, limit, skip
+        page.page, limit, skip
     );
 
+    // The envelope reports how many there are in all
+    let listed = doc! { "taken_down_at": null, "archived_at": null };
+    let total = if query.envelope {
+        match collection.count_documents(listed.clone()).await {
+            Ok(total) => total,
+            Err(err) => {
+                eprintln!("Failed to count itineraries: {:?}", err);
+                return HttpResponse::InternalServerError().body("Failed to retrieve itineraries");
+            }
+        }
+    } else {
+        0
+    };
+
     // Get itineraries with pagination
     let sort_options = doc! { "created_at": -1 };
     let cursor = collection
-        .find(doc! {})
+        .find(listed)
         .sort(sort_options)
-        .skip(skip as u64)
+        .skip(skip)
         .limit(limit)
         .await;
 
@@ -149,13 +615,13 @@ pub async fn get_all(
         Ok(cursor) => match cursor.try_collect::<Vec<FeaturedVacation>>().await {
             Ok(itineraries) => {
                 if itineraries.is_empty() {
-                    return HttpResponse::Ok().json(Vec::<FeaturedVacation>::new());
+                    return page.respond::<FeaturedVacation>(version, query.envelope, &[], total);
                 }
 
                 println!("Found {} itineraries in database", itineraries.len());
 
                 // Process images for all itineraries
-                let processed_itineraries = get_images(itineraries).await;
+                let processed_itineraries = get_images(itineraries, &Storage::new(config.storage.clone())).await;
                 println!(
                     "Processed {} itineraries with images",
                     processed_itineraries.len()
@@ -167,7 +633,8 @@ pub async fn get_all(
                     .map(|itinerary| {
                         let client_clone = client.clone();
                         let itinerary_clone = itinerary.clone();
-                        async move { itinerary_clone.populate(&client_clone).await }
+                        let activity_bucket = config.activity_bucket.clone();
+                        async move { itinerary_clone.populate(&client_clone, activity_bucket.as_deref()).await }
                     })
                     .collect();
 
@@ -230,7 +697,10 @@ pub async fn get_all(
                         if let Some(original_itinerary) = processed_itineraries.get(failed_index) {
                             let populated = PopulatedFeaturedVacation {
                                 base: original_itinerary.clone(),
-                                person_cost: 0.0, // Default to 0.0 for f32
+                                // The stored price if there is one; zero is sent as unavailable
+                                person_cost: PersonPrice::stored(original_itinerary.person_cost)
+                                    .amount()
+                                    .unwrap_or(Money::ZERO),
                                 populated_days: std::collections::HashMap::new(), // Empty HashMap
                                 activities: Vec::new(), // Empty Vec
                                 match_score: None,
@@ -239,17 +709,34 @@ pub async fn get_all(
                                 lodging_cost: None,
                                 transport_cost: None,
                                 service_fee: None,
+                                display_price: None,
+                                population_warnings: Vec::new(),
+                                show_population_warnings: false,
                             };
                             populated_itineraries.push(populated);
                         }
                     }
                 }
 
+                let image_urls = image_urls(&config, query.image_size);
                 if !populated_itineraries.is_empty() {
-                    HttpResponse::Ok().json(populated_itineraries)
+                    for populated in &mut populated_itineraries {
+                        if let Some(images) = populated.base.images.as_mut() {
+                            image_urls.apply(images);
+                        }
+                        populated.show_population_warnings(query.verbose && flags.search_debug());
+                        populated.base.description = units::render(&populated.base.description, units);
+                    }
+                    page.respond(version, query.envelope, &populated_itineraries, total)
                 } else {
                     // Fallback to original itineraries if population failed
-                    HttpResponse::Ok().json(processed_itineraries)
+                    let mut processed_itineraries = processed_itineraries;
+                    for itinerary in &mut processed_itineraries {
+                        if let Some(images) = itinerary.images.as_mut() {
+                            image_urls.apply(images);
+                        }
+                    }
+                    page.respond(version, query.envelope, &processed_itineraries, total)
                 }
             }
             Err(err) => {
@@ -276,21 +763,69 @@ pub async fn get_all(
     Environment variables:
     - MIN_SEARCH_RESULTS: Minimum results before triggering generation (default: 3)
     - GOOGLE_MAPS_API_KEY: For real driving distances and traffic-aware routing
+    - MAX_TRIP_DAYS / MAX_PARTY_SIZE: Longer trips and larger parties get a 422
+      naming the limit (defaults: 14, 16)
+    - Searches outside a destination's season or under its minimum nights get a
+      422 naming the rule (Itineraries.DestinationConstraints)
+    - MAX_ACTIVITIES_FETCH: Activities read for each generated itinerary (default: 50)
+    - MAX_VERTEX_ACTIVITIES / MAX_VERTEX_ACTIVITIES_PER_TYPE: Activities one search
+      keeps from Vertex AI, in total and for each activity type (defaults: 60, 15)
+    - GENERATION_MAX_VERTEX_QUERIES: Vertex AI queries one request may make
+      (default: 6). Past it generation uses MongoDB activities, and the trace's
+      `budget` says so. Generation itself makes no Maps lookups.
+
+    Feature flags (GET/PUT /api/admin/feature-flags):
+    - itinerary_generation: off returns every match instead of generating
+    - persist_generated_itineraries: off returns generated itineraries as ephemeral
+    - search_debug: off ignores X-Generation-Trace and ?verbose=true
+
+    Search experiments (/api/admin/search-experiments):
+    - The active experiment's weights score the search when the signed-in user, or
+      the X-Session-Id of an anonymous client, falls in its traffic. Its name is in
+      `meta.experiment` (null otherwise) and in the search submission log.
+
+    Headers:
+    - X-Generation-Trace: true adds a `generation_trace` (considered activities, skip
+      reasons, schedule decisions) to each generated itinerary. Off by default.
+    - X-Session-Id: an anonymous client's stable id, so it keeps the same
+      experiment assignment between searches.
+
+    When nothing matches and generation fails, the error says why: 422 when no
+    activities match, 400 for dates that are missing or don't parse, 503 while
+    Vertex AI is down and MongoDB has nothing to fall back on, 500 otherwise.
 */
 pub async fn search_itineraries_endpoint(
+    req: HttpRequest,
     data: web::Data<Arc<Client>>,
-    search_params: web::Json<SearchItinerary>,
+    config: web::Data<AppConfig>,
+    view: web::Query<ViewQuery>,
+    fx: web::Data<FxRates>,
+    stock: Option<web::Data<StockImages>>,
+    writes: web::Data<WriteBehindQueue>,
+    flags: web::Data<Flags>,
+    version: ResponseVersion,
+    search_params: TypedJson<SearchItinerary>,
 ) -> impl Responder {
     println!("Handling search request for /api/itineraries/search");
     println!("Search params: {:?}", search_params);
+    let stock = stock_images(stock, &config);
 
     let client = data.into_inner();
     let search_query = search_params.into_inner();
+    if let Err(err) = config.trip_limits.check_search(&search_query) {
+        return limit_exceeded(&err);
+    }
+    if let Err(response) = destination_constraint_check(&client, &search_query).await {
+        return response;
+    }
+    let display = match price_display(&req, &client, view.display_currency.as_deref(), &fx).await {
+        Ok(display) => display,
+        Err(response) => return response,
+    };
+    let units = unit_system(&req, &client, view.units).await;
+    let (weights, experiment) = search_weights(&req, &client, &config).await;
 
     // Log the search query to the Travelers.Submission collection
-    let submission_collection: mongodb::Collection<ItinerarySubmission> =
-        client.database("Travelers").collection("Submission");
-
     // Convert SearchItinerary to ItinerarySubmission for logging
     // Only attempt this if we have enough data to make a meaningful log
     if search_query
@@ -300,12 +835,13 @@ pub async fn search_itineraries_endpoint(
     {
         // Create a minimal submission record from the search parameters
         let now = DateTime::now();
+        let user_id = optional_claims(&req).and_then(|claims| ObjectId::parse_str(&claims.user_id).ok());
         let chrono_date = chrono::Utc::now() + chrono::Duration::days(7);
         let week_out = bson::DateTime::from_millis(chrono_date.timestamp_millis());
 
         let search_log = ItinerarySubmission {
             id: None,
-            user_id: None, // Anonymous search
+            user_id,
             location_start: search_query
                 .locations
                 .as_ref()
@@ -341,26 +877,28 @@ pub async fn search_itineraries_endpoint(
                 .clone(),
             budget_per_person: None,
             interests: None,
+            experiment: experiment.clone(),
             created_at: Some(now),
             updated_at: Some(now),
         };
 
-        // Log the search asynchronously (don't wait for result)
-        let log_client = Arc::clone(&client);
-        tokio::spawn(async move {
-            match submission_collection.insert_one(&search_log).await {
-                Ok(_) => println!("Search query logged successfully"),
-                Err(e) => eprintln!("Failed to log search query: {:?}", e),
+        // Best effort: a read-only database must not fail the search
+        writes.insert("search submission", "Travelers", "Submission", &search_log);
+
+        // Signed-in travelers get it back as a "search again" action
+        if let Some(user_id) = user_id {
+            if let Err(e) = RecentSearchService::new(client.as_ref().clone())
+                .record(user_id, &search_query, now)
+                .await
+            {
+                eprintln!("Failed to record recent search: {:?}", e);
             }
-        });
+        }
     }
 
     // Use search-or-generate functionality for better user experience
-    // Define minimum results threshold (configurable via env var)
-    let min_results_threshold = std::env::var("MIN_SEARCH_RESULTS")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(5); // Default to 5 minimum results to ensure generation
+    // Minimum results threshold (MIN_SEARCH_RESULTS, read once at startup)
+    let min_results_threshold = config.min_search_results.unwrap_or(5); // Default to 5 minimum results to ensure generation
 
     println!(
         "Using search-or-generate with threshold: {}",
@@ -372,11 +910,27 @@ pub async fn search_itineraries_endpoint(
         client.as_ref().clone(),
         search_query.clone(),
         min_results_threshold,
+        weights.clone(),
+        generation_policy(&flags, &config, &req),
     )
     .await
     {
         Ok(itineraries) => {
             if itineraries.is_empty() {
+                if version == ResponseVersion::V2 || search_query.response_version == Some(2) {
+                    return search_response(
+                        version,
+                        Some(2),
+                        Vec::new(),
+                        &HashMap::new(),
+                        None,
+                        units,
+                        experiment.as_deref(),
+                        view.sort,
+                        &image_urls(&config, view.image_size),
+                        &stock,
+                    );
+                }
                 return HttpResponse::Ok().json(Vec::<PopulatedFeaturedVacation>::new());
             }
 
@@ -397,121 +951,54 @@ pub async fn search_itineraries_endpoint(
             }
 
             // Process images for all itineraries
-            let processed_itineraries = get_images(itineraries).await;
+            let processed_itineraries = get_images(itineraries, &Storage::new(config.storage.clone())).await;
 
             // Initialize the async search scorer for better activity matching
-            let scorer = AsyncSearchScorer::new(client.as_ref().clone());
+            let scorer = AsyncSearchScorer::with_weights(client.as_ref().clone(), weights.clone());
 
             // Score all itineraries (existing and generated) with database lookup
             let scored_results = scorer
                 .score_and_rank_itineraries(processed_itineraries.clone(), &search_query)
                 .await;
 
-            // Calculate max possible score once
-            let max_possible_score = scorer.weights.location_weight
-                + scorer.weights.activity_weight
-                + scorer.weights.group_size_weight
-                + scorer.weights.lodging_weight
-                + scorer.weights.transportation_weight
-                + scorer.weights.trip_pace_weight;
+            if view.view == ItineraryView::Summary {
+                let items =
+                    summary_search_items(processed_itineraries, &scored_results, &scorer.weights);
+                return search_response(
+                    version,
+                    search_query.response_version,
+                    items,
+                    &HashMap::new(),
+                    display.as_ref(),
+                    units,
+                    experiment.as_deref(),
+                    view.sort,
+                    &image_urls(&config, view.image_size),
+                    &stock,
+                );
+            }
 
             // Populate all itineraries concurrently with scores
+            let weights = &scorer.weights;
             let populate_futures: Vec<_> = processed_itineraries
                 .iter()
                 .map(|itinerary| {
                     let client_clone = client.clone();
                     let itinerary_clone = itinerary.clone();
+                    let activity_bucket = config.activity_bucket.clone();
                     let scored_result = scored_results
                         .iter()
                         .find(|s| s.itinerary.id == itinerary.id)
                         .cloned();
 
                     async move {
-                        match itinerary_clone.populate(&client_clone).await {
+                        match itinerary_clone.populate(&client_clone, activity_bucket.as_deref()).await {
                             Ok(mut populated) => {
                                 // Apply scores if found
                                 if let Some(scored) = scored_result {
-                                    // Normalize total score to 0-100 scale
-                                    let normalized_score = if max_possible_score > 0.0 {
-                                        ((scored.total_score / max_possible_score) * 100.0)
-                                            .min(100.0)
-                                            .max(0.0) as u8
-                                    } else {
-                                        0
-                                    };
-                                    populated.set_match_score(normalized_score);
-
-                                    // Normalize individual score components to 0-100 range
-                                    let mut normalized_breakdown = scored.score_breakdown.clone();
-
-                                    // Normalize each component based on its maximum possible weight
-                                    normalized_breakdown.location_score =
-                                        if scorer.weights.location_weight > 0.0 {
-                                            ((normalized_breakdown.location_score
-                                                / scorer.weights.location_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.activity_score =
-                                        if scorer.weights.activity_weight > 0.0 {
-                                            ((normalized_breakdown.activity_score
-                                                / scorer.weights.activity_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.group_size_score =
-                                        if scorer.weights.group_size_weight > 0.0 {
-                                            ((normalized_breakdown.group_size_score
-                                                / scorer.weights.group_size_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.lodging_score =
-                                        if scorer.weights.lodging_weight > 0.0 {
-                                            ((normalized_breakdown.lodging_score
-                                                / scorer.weights.lodging_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.transportation_score =
-                                        if scorer.weights.transportation_weight > 0.0 {
-                                            ((normalized_breakdown.transportation_score
-                                                / scorer.weights.transportation_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.trip_pace_score =
-                                        if scorer.weights.trip_pace_weight > 0.0 {
-                                            ((normalized_breakdown.trip_pace_score
-                                                / scorer.weights.trip_pace_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    populated.set_score_breakdown(normalized_breakdown);
+                                    let (match_score, breakdown) = scored.normalized(weights);
+                                    populated.set_match_score(match_score);
+                                    populated.set_score_breakdown(breakdown);
                                 }
 
                                 // Log generated itineraries for frontend visibility
@@ -522,9 +1009,6 @@ pub async fn search_itineraries_endpoint(
                                     );
                                 }
 
-                                // Populate images from activities if no itinerary images exist
-                                populated.populate_images_from_activities();
-
                                 Ok(populated)
                             }
                             Err(err) => {
@@ -554,14 +1038,11 @@ pub async fn search_itineraries_endpoint(
                 {
                     processed.match_score = populated.match_score;
                     processed.score_breakdown = populated.score_breakdown.clone();
-                    // Copy images from the populated base
-                    processed.images = populated.base.images.clone();
                     println!(
-                        "   📊 Copied scores and images to {}: match_score={:?}, breakdown={:?}, images={:?}",
+                        "   📊 Copied scores to {}: match_score={:?}, breakdown={:?}",
                         processed.trip_name,
                         processed.match_score,
-                        processed.score_breakdown.is_some(),
-                        processed.images.as_ref().map(|imgs| imgs.len())
+                        processed.score_breakdown.is_some()
                     );
                 }
             }
@@ -579,18 +1060,27 @@ pub async fn search_itineraries_endpoint(
             }
 
             // Transform to the custom response format with populated activities
-            let response_items = transform_to_search_response(&client, processed_itineraries).await;
+            let (mut response_items, activities) =
+                transform_to_search_response(&client, processed_itineraries, version).await;
+            if view.verbose && flags.search_debug() {
+                attach_warning_counts(&mut response_items, &populated_itineraries);
+            }
 
             println!("Transformed to {} response items", response_items.len());
-            HttpResponse::Ok().json(response_items)
-        }
-        Err(err) => {
-            eprintln!(
-                "Failed to search/generate itineraries for frontend: {:?}",
-                err
-            );
-            HttpResponse::InternalServerError().body("Failed to search or generate itineraries")
+            search_response(
+                version,
+                search_query.response_version,
+                response_items,
+                &activities,
+                display.as_ref(),
+                units,
+                experiment.as_deref(),
+                view.sort,
+                &image_urls(&config, view.image_size),
+                &stock,
+            )
         }
+        Err(err) => generation_error_response(err),
     }
 }
 
@@ -598,156 +1088,124 @@ pub async fn search_itineraries_endpoint(
     /api/itineraries/search-or-generate (Explicit search with generation fallback)
 
     This endpoint provides the same functionality as /search but with explicit naming.
-    Both endpoints now use the same intelligent search-or-generate logic.
-    This endpoint is kept for API compatibility and explicit use cases.
+    Both endpoints now use the same intelligent search-or-generate logic, including
+    search experiments and the error statuses. This endpoint is kept for API
+    compatibility and explicit use cases.
 */
+#[allow(clippy::too_many_arguments)]
 pub async fn search_or_generate(
+    req: HttpRequest,
     data: web::Data<Arc<Client>>,
-    search_params: web::Json<SearchItinerary>,
+    config: web::Data<AppConfig>,
+    view: web::Query<ViewQuery>,
+    fx: web::Data<FxRates>,
+    stock: Option<web::Data<StockImages>>,
+    flags: web::Data<Flags>,
+    version: ResponseVersion,
+    search_params: TypedJson<SearchItinerary>,
 ) -> impl Responder {
     println!("Handling search-or-generate request");
     println!("Search params: {:?}", search_params);
+    let stock = stock_images(stock, &config);
 
     let client = data.into_inner();
     let search_query = search_params.into_inner();
+    if let Err(err) = config.trip_limits.check_search(&search_query) {
+        return limit_exceeded(&err);
+    }
+    if let Err(response) = destination_constraint_check(&client, &search_query).await {
+        return response;
+    }
+    let display = match price_display(&req, &client, view.display_currency.as_deref(), &fx).await {
+        Ok(display) => display,
+        Err(response) => return response,
+    };
+    let units = unit_system(&req, &client, view.units).await;
+    let (weights, experiment) = search_weights(&req, &client, &config).await;
 
-    // Define minimum results threshold (configurable via env var)
-    let min_results_threshold = std::env::var("MIN_SEARCH_RESULTS")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(3); // Default to 3 minimum results
+    // Minimum results threshold (MIN_SEARCH_RESULTS, read once at startup)
+    let min_results_threshold = config.min_search_results.unwrap_or(3); // Default to 3 minimum results
 
     // Use search_or_generate_itineraries
     match search_or_generate_itineraries(
         client.as_ref().clone(),
         search_query.clone(),
         min_results_threshold,
+        weights.clone(),
+        generation_policy(&flags, &config, &req),
     )
     .await
     {
         Ok(itineraries) => {
             if itineraries.is_empty() {
+                if version == ResponseVersion::V2 || search_query.response_version == Some(2) {
+                    return search_response(
+                        version,
+                        Some(2),
+                        Vec::new(),
+                        &HashMap::new(),
+                        None,
+                        units,
+                        experiment.as_deref(),
+                        view.sort,
+                        &image_urls(&config, view.image_size),
+                        &stock,
+                    );
+                }
                 return HttpResponse::Ok().json(Vec::<PopulatedFeaturedVacation>::new());
             }
 
             println!("Found/generated {} itineraries", itineraries.len());
 
             // Process images for all itineraries
-            let processed_itineraries = get_images(itineraries).await;
+            let processed_itineraries = get_images(itineraries, &Storage::new(config.storage.clone())).await;
 
             // Initialize the async search scorer for better activity matching
-            let scorer = AsyncSearchScorer::new(client.as_ref().clone());
+            let scorer = AsyncSearchScorer::with_weights(client.as_ref().clone(), weights.clone());
 
             // Score all itineraries (including generated ones) with database lookup
             let scored_results = scorer
                 .score_and_rank_itineraries(processed_itineraries.clone(), &search_query)
                 .await;
 
-            // Calculate max possible score once
-            let max_possible_score = scorer.weights.location_weight
-                + scorer.weights.activity_weight
-                + scorer.weights.group_size_weight
-                + scorer.weights.lodging_weight
-                + scorer.weights.transportation_weight
-                + scorer.weights.trip_pace_weight;
+            if view.view == ItineraryView::Summary {
+                let items =
+                    summary_search_items(processed_itineraries, &scored_results, &scorer.weights);
+                return search_response(
+                    version,
+                    search_query.response_version,
+                    items,
+                    &HashMap::new(),
+                    display.as_ref(),
+                    units,
+                    experiment.as_deref(),
+                    view.sort,
+                    &image_urls(&config, view.image_size),
+                    &stock,
+                );
+            }
 
             // Populate all itineraries concurrently with scores
+            let weights = &scorer.weights;
             let populate_futures: Vec<_> = processed_itineraries
                 .iter()
                 .map(|itinerary| {
                     let client_clone = client.clone();
                     let itinerary_clone = itinerary.clone();
+                    let activity_bucket = config.activity_bucket.clone();
                     let scored_result = scored_results
                         .iter()
                         .find(|s| s.itinerary.id == itinerary.id)
                         .cloned();
 
                     async move {
-                        match itinerary_clone.populate(&client_clone).await {
+                        match itinerary_clone.populate(&client_clone, activity_bucket.as_deref()).await {
                             Ok(mut populated) => {
                                 // Apply scores if found
                                 if let Some(scored) = scored_result {
-                                    // Normalize total score to 0-100 scale
-                                    let normalized_score = if max_possible_score > 0.0 {
-                                        ((scored.total_score / max_possible_score) * 100.0)
-                                            .min(100.0)
-                                            .max(0.0) as u8
-                                    } else {
-                                        0
-                                    };
-                                    populated.set_match_score(normalized_score);
-
-                                    // Normalize individual score components to 0-100 range
-                                    let mut normalized_breakdown = scored.score_breakdown.clone();
-
-                                    // Normalize each component based on its maximum possible weight
-                                    normalized_breakdown.location_score =
-                                        if scorer.weights.location_weight > 0.0 {
-                                            ((normalized_breakdown.location_score
-                                                / scorer.weights.location_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.activity_score =
-                                        if scorer.weights.activity_weight > 0.0 {
-                                            ((normalized_breakdown.activity_score
-                                                / scorer.weights.activity_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.group_size_score =
-                                        if scorer.weights.group_size_weight > 0.0 {
-                                            ((normalized_breakdown.group_size_score
-                                                / scorer.weights.group_size_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.lodging_score =
-                                        if scorer.weights.lodging_weight > 0.0 {
-                                            ((normalized_breakdown.lodging_score
-                                                / scorer.weights.lodging_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.transportation_score =
-                                        if scorer.weights.transportation_weight > 0.0 {
-                                            ((normalized_breakdown.transportation_score
-                                                / scorer.weights.transportation_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    normalized_breakdown.trip_pace_score =
-                                        if scorer.weights.trip_pace_weight > 0.0 {
-                                            ((normalized_breakdown.trip_pace_score
-                                                / scorer.weights.trip_pace_weight)
-                                                * 100.0)
-                                                .min(100.0)
-                                                .max(0.0)
-                                        } else {
-                                            0.0
-                                        };
-
-                                    populated.set_score_breakdown(normalized_breakdown);
+                                    let (match_score, breakdown) = scored.normalized(weights);
+                                    populated.set_match_score(match_score);
+                                    populated.set_score_breakdown(breakdown);
                                 }
 
                                 // Mark generated itineraries
@@ -758,9 +1216,6 @@ pub async fn search_or_generate(
                                     );
                                 }
 
-                                // Populate images from activities if no itinerary images exist
-                                populated.populate_images_from_activities();
-
                                 Ok(populated)
                             }
                             Err(err) => {
@@ -790,14 +1245,11 @@ pub async fn search_or_generate(
                 {
                     processed.match_score = populated.match_score;
                     processed.score_breakdown = populated.score_breakdown.clone();
-                    // Copy images from the populated base
-                    processed.images = populated.base.images.clone();
                     println!(
-                        "   📊 Copied scores and images to {}: match_score={:?}, breakdown={:?}, images={:?}",
+                        "   📊 Copied scores to {}: match_score={:?}, breakdown={:?}",
                         processed.trip_name,
                         processed.match_score,
-                        processed.score_breakdown.is_some(),
-                        processed.images.as_ref().map(|imgs| imgs.len())
+                        processed.score_breakdown.is_some()
                     );
                 }
             }
@@ -815,24 +1267,122 @@ pub async fn search_or_generate(
             }
 
             // Transform to the custom response format with populated activities
-            let response_items = transform_to_search_response(&client, processed_itineraries).await;
+            let (mut response_items, activities) =
+                transform_to_search_response(&client, processed_itineraries, version).await;
+            if view.verbose && flags.search_debug() {
+                attach_warning_counts(&mut response_items, &populated_itineraries);
+            }
 
             println!("Transformed to {} response items", response_items.len());
-            HttpResponse::Ok().json(response_items)
+            search_response(
+                version,
+                search_query.response_version,
+                response_items,
+                &activities,
+                display.as_ref(),
+                units,
+                experiment.as_deref(),
+                view.sort,
+                &image_urls(&config, view.image_size),
+                &stock,
+            )
         }
-        Err(err) => {
-            eprintln!("Failed to search/generate itineraries: {:?}", err);
-            HttpResponse::InternalServerError().body("Failed to search or generate itineraries")
+        Err(err) => generation_error_response(err),
+    }
+}
+
+/// The shared stock images, or only the placeholder when none were registered
+fn stock_images(stock: Option<web::Data<StockImages>>, config: &AppConfig) -> Arc<StockImages> {
+    stock
+        .map(|stock| stock.into_inner())
+        .unwrap_or_else(|| Arc::new(StockImages::new(&config.placeholder_image_url)))
+}
+
+/// Resolve the response id for an itinerary, flagging it as ephemeral when it was never persisted
+fn response_id(itinerary: &FeaturedVacation) -> (Option<ObjectId>, bool) {
+    (itinerary.id, itinerary.id.is_none())
+}
+
+/// Number of day items each result lost to missing activities or accommodations,
+/// for `?verbose=true`
+fn attach_warning_counts(items: &mut [SearchResponseItem], populated: &[PopulatedFeaturedVacation]) {
+    for item in items.iter_mut().filter(|item| item.id.is_some()) {
+        if let Some(populated) = populated.iter().find(|p| p.id() == item.id) {
+            item.population_warning_count = Some(populated.population_warnings.len());
+        }
+    }
+}
+
+/// Serialize search results in the shape the client asked for: the v2 envelope
+/// under `/v2`, otherwise v1 unless `response_version` is 2. Results without
+/// images get a stand-in from `stock`.
+#[allow(clippy::too_many_arguments)]
+fn search_response(
+    version: ResponseVersion,
+    response_version: Option<u8>,
+    mut items: Vec<SearchResponseItem>,
+    activities: &HashMap<ObjectId, crate::models::activity::Activity>,
+    display: Option<&PriceDisplay>,
+    units: UnitSystem,
+    experiment: Option<&str>,
+    order: ResultOrder,
+    image_urls: &ImageUrlBuilder,
+    stock: &StockImages,
+) -> HttpResponse {
+    order.apply(&mut items);
+    for item in &mut items {
+        if let Some(display) = display {
+            item.display_price = item.person_cost.and_then(|usd| display.price(usd));
+        }
+        item.description = units::render(&item.description, units);
+        if item.images.is_empty() {
+            let (image, source) = stock.fallback(
+                &activity_images(&scheduled_activity_ids(item), activities),
+                item.start_location.city(),
+                item.start_location.state(),
+            );
+            item.images = vec![image];
+            item.image_source = Some(source);
         }
+        image_urls.apply(&mut item.images);
     }
+    match (version, response_version) {
+        (ResponseVersion::V2, _) => version.ok(&SearchEnvelope::from_items(items, activities, experiment)),
+        (_, Some(2)) => HttpResponse::Ok().json(SearchResponseV2::from_items(items, activities)),
+        _ => HttpResponse::Ok().json(items),
+    }
+}
+
+/// The activities a result schedules, in day order. None for the summary view.
+fn scheduled_activity_ids(item: &SearchResponseItem) -> Vec<ObjectId> {
+    let Some(days) = &item.days else {
+        return Vec::new();
+    };
+    let mut ordered: Vec<_> = days.iter().collect();
+    ordered.sort_by_key(|(day, _)| (day.parse::<u32>().unwrap_or(u32::MAX), day.as_str()));
+    ordered
+        .into_iter()
+        .flat_map(|(_, items)| items)
+        .filter_map(|item| match item {
+            PopulatedDayItem::Activity { activity_id, .. } => Some(*activity_id),
+            _ => None,
+        })
+        .collect()
 }
 
-/// Transform itineraries to the custom search response format with populated activities
+/// Transform itineraries to the custom search response format with populated activities.
+/// In v2, day items carry activity titles instead of a separate activity list.
+/// Also returns every activity that was looked up, keyed by id.
 async fn transform_to_search_response(
     client: &Arc<Client>,
     itineraries: Vec<FeaturedVacation>,
-) -> Vec<SearchResponseItem> {
+    version: ResponseVersion,
+) -> (
+    Vec<SearchResponseItem>,
+    HashMap<ObjectId, crate::models::activity::Activity>,
+) {
     let mut response_items = Vec::new();
+    let mut all_activities = HashMap::new();
     let mut seen_ids = std::collections::HashSet::new();
 
     // Get activities collection
@@ -942,11 +1492,16 @@ async fn transform_to_search_response(
                     crate::models::itinerary::base::DayItem::Activity { time, activity_id } => {
                         if let Some(activity) = activities_map.get(activity_id) {
                             // Create populated activity item
-                            let populated_item = PopulatedDayItem::from_activity(
+                            let mut populated_item = PopulatedDayItem::from_activity(
                                 time.clone(),
                                 *activity_id,
                                 activity.clone(),
                             );
+                            if version == ResponseVersion::V2 {
+                                if let PopulatedDayItem::Activity { title, .. } = &mut populated_item {
+                                    *title = Some(activity.title.clone());
+                                }
+                            }
                             populated_items.push(populated_item);
 
                             // Add to activity summaries
@@ -985,33 +1540,452 @@ async fn transform_to_search_response(
 
             populated_days.insert(day_num.clone(), populated_items);
         }
+        // Missing prices are estimated from the activities just looked up
+        let price = PersonPrice::resolve(itinerary.person_cost, &itinerary.days.days, &activities_map);
+        all_activities.extend(activities_map);
+
+        if itinerary.id.is_none() {
+            println!(
+                "   ⚠️  Itinerary '{}' has no persisted id, returning as ephemeral",
+                itinerary.trip_name
+            );
+        }
 
         // Create response item
-        let response_item = SearchResponseItem {
-            id: itinerary.id.unwrap_or_else(|| ObjectId::new()),
-            fareharbor_id: itinerary.fareharbor_id,
-            trip_name: itinerary.trip_name,
-            min_age: itinerary.min_age,
-            min_group: itinerary.min_group,
-            max_group: itinerary.max_group,
-            length_days: itinerary.length_days,
-            length_hours: itinerary.length_hours,
-            start_location: itinerary.start_location,
-            end_location: itinerary.end_location,
-            description: itinerary.description,
-            images: itinerary.images.unwrap_or_default(),
-            created_at: itinerary.created_at,
-            updated_at: itinerary.updated_at,
-            days: populated_days,
-            activities: activity_summaries,
-            match_score: itinerary.match_score,
-            score_breakdown: itinerary
-                .score_breakdown
-                .map(|s| serde_json::to_value(s).unwrap_or(serde_json::Value::Null)),
-        };
+        let mut response_item = summary_item(itinerary);
+        response_item.days = Some(populated_days);
+        response_item.activities = (version == ResponseVersion::V1).then_some(activity_summaries);
+        response_item.person_cost = price.amount();
+        response_item.price_estimated = price.is_estimated();
 
         response_items.push(response_item);
     }
 
-    response_items
+    (response_items, all_activities)
+}
+
+/// Response item without days or activities, as returned by the summary view
+fn summary_item(itinerary: FeaturedVacation) -> SearchResponseItem {
+    // Never mint a throwaway id here: a random id changes on every request and
+    // can't be favorited or booked. Unsaved itineraries are flagged instead.
+    let (id, ephemeral) = response_id(&itinerary);
+
+    SearchResponseItem {
+        id,
+        ephemeral,
+        fareharbor_id: itinerary.fareharbor_id,
+        trip_name: itinerary.trip_name,
+        min_age: itinerary.min_age,
+        min_group: itinerary.min_group,
+        max_group: itinerary.max_group,
+        length_days: itinerary.length_days,
+        length_hours: itinerary.length_hours,
+        start_location: itinerary.start_location,
+        end_location: itinerary.end_location,
+        description: itinerary.description,
+        images: itinerary.images.unwrap_or_default(),
+        image_source: None,
+        created_at: itinerary.created_at,
+        updated_at: itinerary.updated_at,
+        days: None,
+        activities: None,
+        // No activities are looked up here, so a missing price stays unavailable
+        person_cost: PersonPrice::stored(itinerary.person_cost).amount(),
+        price_estimated: false,
+        display_price: None,
+        match_score: itinerary.match_score,
+        score_breakdown: itinerary
+            .score_breakdown
+            .map(|s| serde_json::to_value(s).unwrap_or(serde_json::Value::Null)),
+        generation_trace: itinerary.generation_trace,
+        population_warning_count: None,
+    }
+}
+
+/// Summary items for the listed itineraries that still exist, in the order of `ids`
+pub(crate) async fn itinerary_summaries(
+    client: &Client,
+    config: &AppConfig,
+    ids: &[ObjectId],
+    units: UnitSystem,
+) -> Result<Vec<SearchResponseItem>, mongodb::error::Error> {
+    let collection: mongodb::Collection<FeaturedVacation> =
+        client.database("Itineraries").collection("Featured");
+    let found: Vec<FeaturedVacation> = collection
+        .find(doc! { "_id": { "$in": ids }, "taken_down_at": null })
+        .await?
+        .try_collect()
+        .await?;
+    let found = get_images(found, &Storage::new(config.storage.clone())).await;
+    let mut by_id: HashMap<ObjectId, FeaturedVacation> = found
+        .into_iter()
+        .filter_map(|itinerary| Some((itinerary.id?, itinerary)))
+        .collect();
+
+    let urls = image_urls(config, ImageSize::default());
+    Ok(ids
+        .iter()
+        .filter_map(|id| by_id.remove(id))
+        .map(|itinerary| {
+            let mut item = summary_item(itinerary);
+            urls.apply(&mut item.images);
+            item.description = units::render(&item.description, units);
+            item
+        })
+        .collect())
+}
+
+/// Summary view of search results: scores are applied but nothing is populated,
+/// so no activity or accommodation lookups happen
+fn summary_search_items(
+    itineraries: Vec<FeaturedVacation>,
+    scored: &[ScoredItinerary],
+    weights: &SearchWeights,
+) -> Vec<SearchResponseItem> {
+    let mut seen_ids = std::collections::HashSet::new();
+    itineraries
+        .into_iter()
+        .filter(|itinerary| itinerary.id.is_none_or(|id| seen_ids.insert(id)))
+        .map(|mut itinerary| {
+            if let Some(scored) = scored.iter().find(|s| s.itinerary.id == itinerary.id) {
+                let (match_score, breakdown) = scored.normalized(weights);
+                itinerary.match_score = Some(match_score);
+                itinerary.score_breakdown = Some(breakdown);
+            }
+            summary_item(itinerary)
+        })
+        .collect()
+}
+
+/*
+    /api/itineraries/{id}/report
+
+    Reports an itinerary as offensive, inaccurate, unsafe or spam. Admins are
+    emailed once enough travelers have reported it. Who reported it is never
+    shown, to admins or anyone else.
+*/
+pub async fn report_itinerary(
+    path: web::Path<String>,
+    data: web::Data<Arc<Client>>,
+    config: web::Data<AppConfig>,
+    claims: Claims,
+    input: web::Json<ReportInput>,
+) -> impl Responder {
+    let Ok(itinerary_id) = ObjectId::parse_str(path.into_inner()) else {
+        return HttpResponse::BadRequest().body("Invalid ID");
+    };
+    let Ok(reporter_id) = ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let client = data.into_inner().as_ref().clone();
+    let service = ContentFlagService::new(client.clone());
+    match service
+        .report(
+            reporter_id,
+            ContentType::Itinerary,
+            itinerary_id,
+            input.into_inner(),
+            &config.content_reports,
+            &EmailService::new(client, &config.email).ok(),
+        )
+        .await
+    {
+        Ok(_) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "message": "Thanks for letting us know. We'll take a look."
+        })),
+        Err(ContentFlagError::ContentNotFound) => HttpResponse::NotFound().body("Itinerary not found"),
+        Err(err @ ContentFlagError::AlreadyReported) => HttpResponse::Conflict().body(err.to_string()),
+        Err(err @ ContentFlagError::RateLimited) => HttpResponse::TooManyRequests().body(err.to_string()),
+        Err(err) => {
+            eprintln!("Failed to report itinerary {}: {}", itinerary_id, err);
+            HttpResponse::InternalServerError().body("Failed to save report")
+        }
+    }
+}
+
+/*
+    /api/itineraries/validate (Protected endpoint)
+
+    Checks a hand-built itinerary before it's saved: unknown activities or
+    lodging, overlapping or unreachable activities, closures and sold-out seats
+    on the trip's dates, and parties that don't fit. Returns every issue found
+    with its severity, the per-person cost and each day's route efficiency.
+    Nothing is saved.
+*/
+pub async fn validate_itinerary(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    input: TypedJson<FeaturedVacation>,
+) -> impl Responder {
+    let min_activity_minutes = config
+        .as_ref()
+        .map_or(DEFAULT_MIN_ACTIVITY_MINUTES, |config| config.min_activity_minutes);
+    let limits = trip_limits(config);
+    let service = ItineraryValidationService::new(data.into_inner().as_ref().clone());
+    match service
+        .validate(&input.into_inner(), &limits, min_activity_minutes)
+        .await
+    {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => {
+            eprintln!("Failed to validate itinerary: {}", err);
+            HttpResponse::InternalServerError().body("Failed to validate itinerary")
+        }
+    }
+}
+
+/// Public itinerary browsing and search, plus the authenticated `/find`, `/validate` and `/report`
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/itineraries")
+            // Personal access tokens are checked here; session tokens stay optional
+            .wrap(ApiTokenMiddleware)
+            // Get all itineraries
+            .route("", web::get().to(get_all))
+            // Search itineraries with filters
+            .route("/search", web::post().to(search_itineraries_endpoint))
+            // Search with generation fallback
+            .route("/search-or-generate", web::post().to(search_or_generate))
+            // Public route for getting itinerary by ID
+            .route("/{id}", web::get().to(get_by_id))
+            // Month calendar of bookable start dates
+            .route("/{id}/availability", web::get().to(get_availability))
+            // Travel between the itinerary's stops for the map view
+            .route("/{id}/distance-matrix", web::get().to(get_distance_matrix))
+            .route("/{id}/map-geojson", web::get().to(get_map_geojson))
+            // Protected routes
+            .service(
+                web::scope("")
+                    .wrap(AuthMiddleware)
+                    .route("/find", web::post().to(super::dream_vacation::find))
+                    .route("/validate", web::post().to(validate_itinerary))
+                    .route("/{id}/report", web::post().to(report_itinerary)),
+            ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_id_never_fabricated_for_unsaved_itinerary() {
+        let itinerary = FeaturedVacation::default();
+        assert_eq!(response_id(&itinerary), (None, true));
+    }
+
+    #[test]
+    fn test_summary_view_scores_without_days_or_activities() {
+        let itinerary = FeaturedVacation {
+            id: Some(ObjectId::new()),
+            trip_name: "Arkansas River Weekend".to_string(),
+            ..Default::default()
+        };
+        let weights = SearchWeights::default();
+        let scored = ScoredItinerary {
+            itinerary: itinerary.clone(),
+            total_score: weights.max_score() / 2.0,
+            score_breakdown: Default::default(),
+            max_score: weights.max_score(),
+        };
+
+        // The same itinerary found twice is only listed once
+        let items = summary_search_items(vec![itinerary.clone(), itinerary], &[scored], &weights);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].match_score, Some(50));
+
+        let json = serde_json::to_value(&items[0]).unwrap();
+        assert_eq!(json["trip_name"], "Arkansas River Weekend");
+        assert!(json.get("days").is_none());
+        assert!(json.get("activities").is_none());
+    }
+
+    /// A full search result with each kind of day item and fixed ids
+    fn search_fixture() -> Vec<SearchResponseItem> {
+        let mut item = summary_item(FeaturedVacation {
+            id: Some(ObjectId::parse_str("65f000000000000000000001").unwrap()),
+            trip_name: "Arkansas River Weekend".to_string(),
+            images: Some(vec!["https://storage.googleapis.com/actota-itineraries/rafting.jpg".to_string()]),
+            ..Default::default()
+        });
+        let days = vec![
+            PopulatedDayItem::Activity {
+                time: "09:00:00".to_string(),
+                activity_id: ObjectId::parse_str("65f000000000000000000002").unwrap(),
+                title: None,
+            },
+            PopulatedDayItem::Accommodation {
+                time: "20:00:00".to_string(),
+                accommodation_id: ObjectId::parse_str("65f000000000000000000003").unwrap(),
+            },
+        ];
+        item.days = Some(HashMap::from([("1".to_string(), days)]));
+        item.activities = Some(vec![ActivitySummary {
+            time: "09:00:00".to_string(),
+            label: "Rafting".to_string(),
+            tags: vec!["water".to_string()],
+        }]);
+        item.person_cost = Some(Money::from_dollars(450.0));
+        item.match_score = Some(80);
+        vec![item]
+    }
+
+    async fn search_body(version: ResponseVersion, response_version: Option<u8>) -> String {
+        let images = ImageUrlBuilder::new(DEFAULT_STORAGE_URL, None, ImageSize::Full);
+        let response = search_response(
+            version,
+            response_version,
+            search_fixture(),
+            &HashMap::new(),
+            None,
+            UnitSystem::Imperial,
+            None,
+            ResultOrder::Relevance,
+            &images,
+            &StockImages::default(),
+        );
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_v1_search_response_is_byte_for_byte_unchanged() {
+        // Captured before responses were versioned; the mobile app parses exactly this
+        let expected = concat!(
+            r#"[{"_id":{"$oid":"65f000000000000000000001"},"fareharbor_id":null,"trip_name":"Arkansas River Weekend","min_age":null,"min_group":1,"max_group":1,"length_days":1,"length_hours":24,"start_location":{"city":"","state":"","coordinates":[]},"end_location":{"city":"","state":"","coordinates":[]}"#,
+            r#","description":"","images":["https://storage.googleapis.com/actota-itineraries/rafting.jpg"]"#,
+            r#","days":[{"day":1,"items":[{"type":"activity","time":"09:00:00","activity_id":{"$oid":"65f000000000000000000002"}},{"type":"accommodation","time":"20:00:00","accommodation_id":{"$oid":"65f000000000000000000003"}}]}],"activities":[{"time":"09:00:00","label":"Rafting","tags":["water"]}],"person_cost":450.0,"match_score":80}]"#,
+        );
+        assert_eq!(search_body(ResponseVersion::V1, None).await, expected);
+    }
+
+    #[actix_rt::test]
+    async fn test_v2_search_response_is_an_envelope_with_hex_ids() {
+        let body: serde_json::Value =
+            serde_json::from_str(&search_body(ResponseVersion::V2, None).await).unwrap();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["meta"]["experiment"], serde_json::Value::Null);
+        let item = &body["data"][0];
+        assert_eq!(item["_id"], "65f000000000000000000001");
+        assert!(item.get("activities").is_none());
+        assert_eq!(item["days"][0]["items"][1]["accommodation_id"], "65f000000000000000000003");
+        assert_eq!(
+            item["images"][0],
+            serde_json::json!({
+                "url": "https://storage.googleapis.com/actota-itineraries/rafting.jpg",
+                "primary": true,
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_results_without_images_get_a_tagged_stand_in() {
+        let mut items = search_fixture();
+        items[0].images.clear();
+        let activity_id = ObjectId::parse_str("65f000000000000000000002").unwrap();
+        let mut rafting: crate::models::activity::Activity = serde_json::from_value(serde_json::json!({
+            "_id": activity_id,
+            "company": "Rocky Mountain Adventures",
+            "company_id": "rma",
+            "booking_link": "",
+            "online_booking_status": "available",
+            "title": "Rafting",
+            "description": "",
+            "activity_types": [],
+            "tags": [],
+            "price_per_person": 89.0,
+            "daily_time_slots": [],
+            "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+            "whats_included": [],
+            "capacity": { "minimum": 1, "maximum": 10 },
+        }))
+        .unwrap();
+        rafting.images = vec!["https://example.com/rafting.jpg".to_string()];
+        let images = ImageUrlBuilder::new(DEFAULT_STORAGE_URL, None, ImageSize::Full);
+
+        let respond = |items, activities: &HashMap<ObjectId, crate::models::activity::Activity>, version| {
+            search_response(
+                version,
+                None,
+                items,
+                activities,
+                None,
+                UnitSystem::Imperial,
+                None,
+                ResultOrder::Relevance,
+                &images,
+                &StockImages::new("https://example.com/placeholder.jpg"),
+            )
+        };
+        let body = |response: HttpResponse| async move {
+            let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let v1 = body(respond(items, &HashMap::from([(activity_id, rafting)]), ResponseVersion::V1)).await;
+        assert_eq!(v1[0]["images"], serde_json::json!(["https://example.com/rafting.jpg"]));
+        assert_eq!(v1[0]["image_source"], "activity");
+
+        // Without the activity's image it's the placeholder, tagged on the v2 image entry
+        let v2 = body(respond(
+            {
+                let mut items = search_fixture();
+                items[0].images.clear();
+                items
+            },
+            &HashMap::new(),
+            ResponseVersion::V2,
+        ))
+        .await;
+        assert_eq!(
+            v2["data"][0]["images"][0],
+            serde_json::json!({
+                "url": "https://example.com/placeholder.jpg",
+                "primary": true,
+                "source": "placeholder",
+            })
+        );
+        assert!(v2["data"][0].get("image_source").is_none());
+    }
+
+    #[test]
+    fn test_view_defaults_to_full() {
+        let query: ViewQuery = serde_json::from_str("{}").unwrap();
+        assert_eq!(query.view, ItineraryView::Full);
+        let query: ViewQuery = serde_json::from_str(r#"{"view": "summary"}"#).unwrap();
+        assert_eq!(query.view, ItineraryView::Summary);
+    }
+
+    #[actix_rt::test]
+    async fn test_generation_errors_map_to_statuses() {
+        let cases = [
+            (GenerationError::NoActivities, 422),
+            (GenerationError::MissingDates, 400),
+            (GenerationError::InvalidInput("Unable to parse datetime 'soon'".to_string()), 400),
+            (GenerationError::ExternalServiceUnavailable("HTTP error: timed out".to_string()), 503),
+            (GenerationError::Persistence("connection refused".to_string()), 500),
+        ];
+        for (err, status) in cases {
+            assert_eq!(generation_error_response(err.clone()).status().as_u16(), status, "{:?}", err);
+        }
+
+        // Travelers see what to change, not the outage's details
+        let body = actix_web::body::to_bytes(
+            generation_error_response(GenerationError::InvalidInput("Unable to parse datetime 'soon'".to_string()))
+                .into_body(),
+        )
+        .await
+        .unwrap();
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.error, "Unable to parse datetime 'soon'");
+        let body = actix_web::body::to_bytes(
+            generation_error_response(GenerationError::ExternalServiceUnavailable("HTTP error: timed out".to_string()))
+                .into_body(),
+        )
+        .await
+        .unwrap();
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+        assert!(!error.error.contains("timed out"));
+    }
 }
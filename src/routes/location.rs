@@ -3,7 +3,9 @@ use futures::TryStreamExt;
 use mongodb::{bson::doc, options::FindOptions, Client};
 use std::sync::Arc;
 
+use crate::db::mongo::read_only_collection;
 use crate::models::location::Location;
+use crate::services::location_autocomplete::{LocationAutocomplete, DEFAULT_LIMIT, MAX_LIMIT};
 
 #[derive(serde::Deserialize)]
 pub struct QueryParams {
@@ -11,13 +13,35 @@ pub struct QueryParams {
     search: Option<String>,
 }
 
+#[derive(serde::Deserialize)]
+pub struct AutocompleteParams {
+    q: Option<String>,
+    limit: Option<usize>,
+}
+
+/*
+    /api/locations/autocomplete?q=bre&limit=8
+
+    Destination suggestions from cities we have inventory in, served from an
+    in-memory index. Prefix matches come before substring matches, then cities
+    with more itineraries and activities.
+*/
+pub async fn autocomplete(
+    index: web::Data<LocationAutocomplete>,
+    params: web::Query<AutocompleteParams>,
+) -> impl Responder {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let query = params.q.as_deref().unwrap_or_default();
+    HttpResponse::Ok().json(index.search(query, limit))
+}
+
 pub async fn get_locations(
     data: web::Data<Arc<Client>>,
     params: web::Query<QueryParams>,
 ) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<Location> =
-        client.database("Options").collection("Location");
+        read_only_collection(&client, "Options", "Location");
 
     let mut options = FindOptions::default();
     if let Some(limit) = params.limit {
@@ -3,12 +3,13 @@ use futures::TryStreamExt;
 use mongodb::{bson::doc, Client};
 use std::sync::Arc;
 
+use crate::db::mongo::read_only_collection;
 use crate::models::activity::Activity;
 
 pub async fn get_lodging(data: web::Data<Arc<Client>>) -> impl Responder {
     let client = data.into_inner();
     let collection: mongodb::Collection<Activity> =
-        client.database("Options").collection("Lodging");
+        read_only_collection(&client, "Options", "Lodging");
 
     match collection.find(doc! {}).await {
         Ok(cursor) => match cursor.try_collect::<Vec<Activity>>().await {
@@ -1,9 +1,128 @@
 pub mod account;
 pub mod activity;
+pub mod admin;
+#[cfg(feature = "demo-tools")]
+pub mod demo;
 pub mod dream_vacation;
 pub mod featured_vacation;
+pub mod gift_card;
 pub mod health;
 pub mod itinerary;
 pub mod location;
 pub mod lodging;
+pub mod newsletter;
+pub mod operator;
+pub mod pagination;
 pub mod payment;
+pub mod versioning;
+
+use actix_web::{middleware::from_fn, web, HttpResponse};
+
+use crate::config::AppConfig;
+use crate::services::account_service::EmailSettings;
+use crate::services::moderation::Moderator;
+use crate::services::trip_limits::{LimitExceeded, TripLimits};
+use versioning::{deprecation_headers, ResponseVersion};
+
+/// Every route the API serves, under `/v1`, `/v2` and unprefixed as an alias
+/// for v1. `main` and `build_app` both go through here so they can't drift apart.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    // The versioned scopes come first; the unprefixed scope matches every path
+    cfg.service(
+        web::scope(ResponseVersion::V1.prefix())
+            .app_data(ResponseVersion::V1)
+            .wrap(from_fn(deprecation_headers))
+            .configure(configure_v1),
+    )
+    .service(
+        web::scope(ResponseVersion::V2.prefix())
+            .app_data(ResponseVersion::V2)
+            .configure(configure_v2),
+    )
+    .service(
+        web::scope("")
+            .wrap(from_fn(deprecation_headers))
+            .configure(configure_v1),
+    );
+}
+
+/// The v1 routes. Domains register in this order.
+pub fn configure_v1(cfg: &mut web::ServiceConfig) {
+    health::configure(cfg);
+    configure_api(cfg);
+}
+
+/// The v2 routes: the v1 routes without `V2_EXEMPT_PATHS`
+pub fn configure_v2(cfg: &mut web::ServiceConfig) {
+    configure_api(cfg);
+}
+
+fn configure_api(cfg: &mut web::ServiceConfig) {
+    payment::configure(cfg);
+    account::auth::configure(cfg);
+    account::configure(cfg);
+    admin::configure(cfg);
+    operator::configure(cfg);
+    newsletter::configure(cfg);
+    configure_public_content(cfg);
+    itinerary::configure(cfg);
+}
+
+/// Malformed JSON bodies get a JSON 400 instead of actix's plain-text error
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req| {
+        let error_message = format!("JSON error: {}", err);
+        eprintln!("{}", error_message);
+        actix_web::error::InternalError::from_response(
+            err,
+            HttpResponse::BadRequest()
+                .content_type("application/json")
+                .body(format!(r#"{{"error":"{}"}}"#, error_message)),
+        )
+        .into()
+    })
+}
+
+/// Public content routes
+fn configure_public_content(cfg: &mut web::ServiceConfig) {
+    cfg.route("/locations", web::get().to(location::get_locations))
+        .route("/locations/autocomplete", web::get().to(location::autocomplete))
+        .route("/lodging", web::get().to(lodging::get_lodging))
+        .route("/activities", web::get().to(activity::get_activities))
+        .route(
+            "/activities/{id}/availability",
+            web::get().to(activity::get_availability),
+        );
+}
+
+/// Demo-only admin routes. Registers nothing unless built with the `demo-tools` feature.
+pub fn configure_demo_tools(_cfg: &mut actix_web::web::ServiceConfig) {
+    #[cfg(feature = "demo-tools")]
+    _cfg.route(
+        "/seed-demo-data",
+        actix_web::web::post().to(demo::seed_demo_data),
+    );
+}
+
+/// 422 for a request over a configured trip limit. The limit is in the body so
+/// the frontend can show it.
+pub(crate) fn limit_exceeded(err: &LimitExceeded) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(err.to_api_error())
+}
+
+/// The configured trip limits. Apps built without `AppConfig` get the defaults.
+pub(crate) fn trip_limits(config: Option<web::Data<AppConfig>>) -> TripLimits {
+    config.map(|config| config.trip_limits).unwrap_or_default()
+}
+
+/// Where emails are sent from. Apps built without `AppConfig` have no SendGrid key
+/// and send none.
+pub(crate) fn email_settings(config: Option<&web::Data<AppConfig>>) -> EmailSettings {
+    config.map(|config| config.email.clone()).unwrap_or_default()
+}
+
+/// The configured content moderator. Apps built without `AppConfig` deny no terms
+/// but still refuse links and email addresses.
+pub(crate) fn moderator(config: Option<&web::Data<AppConfig>>) -> Moderator {
+    config.map(|config| config.moderator.clone()).unwrap_or_default()
+}
@@ -0,0 +1,197 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::{
+    bson::{oid::ObjectId, DateTime},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::routes::account::auth::{newsletter_subscribe, newsletter_unsubscribe};
+use crate::services::email_suppression_service::{
+    verify_signature, EmailSuppressionService, SendGridEvent, SignatureError, SIGNATURE_HEADER,
+    TIMESTAMP_HEADER,
+};
+use crate::routes::moderator;
+use crate::services::review_request_service::{ReviewRequestService, ReviewSubmissionError};
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequestUnsubscribe {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewSubmission {
+    pub activity_id: String,
+    pub rating: u8,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/*
+    /api/review-requests/unsubscribe
+
+    Linked from review request emails, so no sign-in is needed: the token from
+    the link identifies the traveler, whose review requests are turned off.
+*/
+pub async fn review_requests_unsubscribe(
+    data: web::Data<Arc<Client>>,
+    input: web::Json<ReviewRequestUnsubscribe>,
+) -> impl Responder {
+    let service = ReviewRequestService::new(data.get_ref().clone());
+    match service.unsubscribe(input.token.trim()).await {
+        Ok(true) => HttpResponse::Ok().json(json!({ "message": "Unsubscribed from review requests" })),
+        Ok(false) => HttpResponse::NotFound().json(json!({ "error": "Unknown unsubscribe link" })),
+        Err(e) => {
+            eprintln!("Failed to unsubscribe from review requests: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "Failed to unsubscribe" }))
+        }
+    }
+}
+
+/*
+    /api/review-requests/{review_token}
+
+    Opened from the review links in a review request email, without signing in:
+    the trip and the activities it asked about,
+    { "booking_id": "...", "trip_name": "...", "activities": [{ "id": "...", "title": "..." }],
+    "sent_at": "..." }.
+    Links stop working 60 days after the email.
+*/
+pub async fn get_review_request(
+    data: web::Data<Arc<Client>>,
+    path: web::Path<(String,)>,
+) -> impl Responder {
+    let review_token = path.into_inner().0;
+    let service = ReviewRequestService::new(data.get_ref().clone());
+    match service.find_by_review_token(review_token.trim(), DateTime::now()).await {
+        Ok(Some(request)) => HttpResponse::Ok().json(json!({
+            "booking_id": request.booking_id.to_hex(),
+            "trip_name": request.trip_name,
+            "activities": request
+                .activities
+                .iter()
+                .map(|activity| json!({ "id": activity.id.to_hex(), "title": activity.title }))
+                .collect::<Vec<_>>(),
+            "sent_at": request.sent_at.try_to_rfc3339_string().ok(),
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({ "error": "Unknown or expired review link" })),
+        Err(e) => {
+            eprintln!("Failed to look up review request: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "Failed to look up review request" }))
+        }
+    }
+}
+
+/*
+    /api/review-requests/{review_token}/reviews
+
+    Reviews one of the request's activities from the email's link, without signing
+    in: { "activity_id": "...", "rating": 1-5, "comment": "..." }. The comment is
+    optional and moderated like other text shown to travelers. Reviewing the same
+    activity again replaces the earlier review.
+*/
+pub async fn submit_review(
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+    path: web::Path<(String,)>,
+    input: web::Json<ReviewSubmission>,
+) -> impl Responder {
+    let review_token = path.into_inner().0;
+    let Ok(activity_id) = ObjectId::parse_str(&input.activity_id) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "Invalid activity ID" }));
+    };
+    let comment = match input.comment.as_deref().map(|comment| moderator(config.as_ref()).moderate(comment)) {
+        Some(Ok(comment)) => Some(comment),
+        Some(Err(hit)) => {
+            hit.log("review comment");
+            return HttpResponse::UnprocessableEntity().json(json!({ "error": hit.to_string(), "field": "comment" }));
+        }
+        None => None,
+    };
+
+    let service = ReviewRequestService::new(data.get_ref().clone());
+    match service
+        .submit_review(review_token.trim(), activity_id, input.rating, comment.as_deref(), DateTime::now())
+        .await
+    {
+        Ok(review) => HttpResponse::Created().json(json!({
+            "booking_id": review.booking_id.to_hex(),
+            "activity_id": review.activity_id.to_hex(),
+            "rating": review.rating,
+            "comment": review.comment,
+            "submitted_at": review.submitted_at.try_to_rfc3339_string().ok(),
+        })),
+        Err(e @ ReviewSubmissionError::UnknownLink) => HttpResponse::NotFound().json(json!({ "error": e.to_string() })),
+        Err(ReviewSubmissionError::DatabaseError(e)) => {
+            eprintln!("Failed to save review: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "Failed to save review" }))
+        }
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    }
+}
+
+/*
+    /api/webhooks/sendgrid/events
+
+    SendGrid's event webhook (public, verified by signature). Delivered, bounce,
+    dropped, spam report and unsubscribe events are recorded; hard bounces and
+    spam reports stop further email to the address.
+*/
+pub async fn handle_sendgrid_events(
+    req: HttpRequest,
+    payload: web::Bytes,
+    data: web::Data<Arc<Client>>,
+    config: Option<web::Data<AppConfig>>,
+) -> impl Responder {
+    let Some(public_key) = config.and_then(|config| config.sendgrid_webhook_public_key.clone()) else {
+        return HttpResponse::ServiceUnavailable().json(json!({ "error": "SendGrid event webhook is not configured" }));
+    };
+    let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER)) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "Missing SendGrid signature headers" }));
+    };
+
+    match verify_signature(&public_key, signature, timestamp, &payload) {
+        Ok(()) => {}
+        Err(e @ SignatureError::InvalidKey) => {
+            eprintln!("Can't verify SendGrid events: {}", e);
+            return HttpResponse::ServiceUnavailable().json(json!({ "error": "SendGrid event webhook is not configured" }));
+        }
+        Err(e) => {
+            println!("SendGrid webhook error: {}", e);
+            return HttpResponse::Unauthorized().json(json!({ "error": "Invalid signature" }));
+        }
+    }
+
+    let events: Vec<SendGridEvent> = match serde_json::from_slice(&payload) {
+        Ok(events) => events,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": format!("Invalid event payload: {}", e) })),
+    };
+    match EmailSuppressionService::new(data.get_ref().clone()).ingest(events).await {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(e) => {
+            // A non-2xx response makes SendGrid retry the batch
+            eprintln!("Failed to record SendGrid events: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "Failed to record events" }))
+        }
+    }
+}
+
+/// Public email routes: the newsletter, SendGrid's event webhook, and the review
+/// links, reviews and opting out of review requests from a review request email
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/newsletter")
+            .route("/subscribe", web::post().to(newsletter_subscribe))
+            .route("/unsubscribe", web::put().to(newsletter_unsubscribe)),
+    )
+    .route("/webhooks/sendgrid/events", web::post().to(handle_sendgrid_events))
+    .route(
+        "/review-requests/unsubscribe",
+        web::post().to(review_requests_unsubscribe),
+    )
+    .route("/review-requests/{review_token}", web::get().to(get_review_request))
+    .route("/review-requests/{review_token}/reviews", web::post().to(submit_review));
+}
@@ -0,0 +1,92 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    Client, Collection,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::middleware::auth::{AuthMiddleware, Claims};
+use crate::middleware::role_auth::RequireRole;
+use crate::models::account::UserRole;
+use crate::services::operator_service::OperatorService;
+
+/// Resolve the company the signed-in operator belongs to.
+/// Accounts without a company (including admins) get a 403.
+async fn operator_company(client: &Client, claims: &Claims) -> Result<String, HttpResponse> {
+    let user_id = ObjectId::parse_str(&claims.user_id)
+        .map_err(|_| HttpResponse::Unauthorized().body("Invalid user ID"))?;
+
+    let users: Collection<Document> = client.database("Account").collection("Users");
+    match users.find_one(doc! { "_id": user_id }).await {
+        Ok(Some(user)) => user
+            .get_str("company_id")
+            .map(str::to_string)
+            .map_err(|_| {
+                HttpResponse::Forbidden().json(json!({
+                    "success": false,
+                    "message": "Account is not linked to an operator company"
+                }))
+            }),
+        Ok(None) => Err(HttpResponse::NotFound().body("User not found")),
+        Err(e) => {
+            eprintln!("Failed to look up operator account: {:?}", e);
+            Err(HttpResponse::InternalServerError().body("Failed to look up account"))
+        }
+    }
+}
+
+/*
+    /api/operator/bookings
+*/
+pub async fn list_bookings(data: web::Data<Arc<Client>>, claims: Claims) -> impl Responder {
+    let client = data.into_inner().as_ref().clone();
+    let company_id = match operator_company(&client, &claims).await {
+        Ok(company_id) => company_id,
+        Err(response) => return response,
+    };
+
+    match OperatorService::new(client).list_bookings(&company_id).await {
+        Ok(bookings) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": bookings
+        })),
+        Err(e) => {
+            eprintln!("Failed to list operator bookings for {}: {:?}", company_id, e);
+            HttpResponse::InternalServerError().body("Failed to fetch bookings")
+        }
+    }
+}
+
+/*
+    /api/operator/activities
+*/
+pub async fn list_activities(data: web::Data<Arc<Client>>, claims: Claims) -> impl Responder {
+    let client = data.into_inner().as_ref().clone();
+    let company_id = match operator_company(&client, &claims).await {
+        Ok(company_id) => company_id,
+        Err(response) => return response,
+    };
+
+    match OperatorService::new(client).list_activities(&company_id).await {
+        Ok(activities) => HttpResponse::Ok().json(json!({
+            "success": true,
+            "data": activities
+        })),
+        Err(e) => {
+            eprintln!("Failed to list operator activities for {}: {:?}", company_id, e);
+            HttpResponse::InternalServerError().body("Failed to fetch activities")
+        }
+    }
+}
+
+/// Operator portal routes (read-only, scoped to the operator's company)
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/operator")
+            .wrap(RequireRole::new(UserRole::Operator))
+            .wrap(AuthMiddleware)
+            .route("/bookings", web::get().to(list_bookings))
+            .route("/activities", web::get().to(list_activities)),
+    );
+}
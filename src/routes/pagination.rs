@@ -0,0 +1,148 @@
+//! Paging for list endpoints
+//!
+//! List endpoints answer with a bare array. With `?envelope=true` the same items
+//! come wrapped with what a client needs to fetch the rest:
+//!
+//! ```json
+//! { "data": [...], "page": 2, "limit": 50, "total": 173, "next_cursor": "3" }
+//! ```
+//!
+//! `total` counts every item the list has, not just this page. `next_cursor` is the
+//! `page` to ask for next, and null on the last page. Endpoints that return
+//! everything unless a `limit` is given send a null `limit`.
+
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::routes::versioning::ResponseVersion;
+
+/// Page size for a `ListQuery` with a `page` but no `limit`
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// `?page=&limit=&envelope=` for lists that aren't paged unless asked
+#[derive(Debug, Deserialize, Default)]
+pub struct ListQuery {
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+    /// Wrap the results in a `Paginated` envelope
+    #[serde(default)]
+    pub envelope: bool,
+}
+
+impl ListQuery {
+    /// Everything when neither `page` nor `limit` is given, otherwise pages of
+    /// `default_limit`
+    pub fn page(&self, default_limit: i64) -> Page {
+        if self.page.is_none() && self.limit.is_none() {
+            return Page::all();
+        }
+        Page::new(self.page, Some(self.limit.unwrap_or(default_limit)))
+    }
+}
+
+/// The slice of a list a request asked for. Pages count from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub page: i64,
+    /// None for the whole list
+    pub limit: Option<i64>,
+}
+
+impl Page {
+    pub fn new(page: Option<i64>, limit: Option<i64>) -> Self {
+        Page {
+            page: page.unwrap_or(1).max(1),
+            limit: limit.map(|limit| limit.max(1)),
+        }
+    }
+
+    pub fn all() -> Self {
+        Page { page: 1, limit: None }
+    }
+
+    /// Items before this page
+    pub fn skip(&self) -> u64 {
+        self.limit.map_or(0, |limit| ((self.page - 1) * limit) as u64)
+    }
+
+    /// `data` as this page of a list `total` long
+    pub fn envelope<'a, T>(&self, data: &'a [T], total: u64) -> Paginated<'a, T> {
+        let more = match self.limit {
+            Some(limit) => ((self.page * limit) as u64) < total,
+            None => false,
+        };
+        Paginated {
+            data,
+            page: self.page,
+            limit: self.limit,
+            total,
+            next_cursor: more.then(|| (self.page + 1).to_string()),
+        }
+    }
+
+    /// A 200 with `items`, in an envelope if `envelope` is set and as a bare array otherwise
+    pub fn respond<T: Serialize>(
+        &self,
+        version: ResponseVersion,
+        envelope: bool,
+        items: &[T],
+        total: u64,
+    ) -> HttpResponse {
+        if envelope {
+            version.ok(&self.envelope(items, total))
+        } else {
+            version.ok(&items)
+        }
+    }
+}
+
+/// The `?envelope=true` response
+#[derive(Debug, Serialize)]
+pub struct Paginated<'a, T> {
+    pub data: &'a [T],
+    pub page: i64,
+    pub limit: Option<i64>,
+    pub total: u64,
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::{json, Value};
+
+    async fn body(response: HttpResponse) -> Value {
+        serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_bare_array_and_envelope_carry_the_same_items() {
+        let items = vec!["a", "b"];
+        let page = Page::new(Some(2), Some(2));
+
+        let bare = body(page.respond(ResponseVersion::V1, false, &items, 5)).await;
+        let enveloped = body(page.respond(ResponseVersion::V1, true, &items, 5)).await;
+
+        assert_eq!(bare, json!(["a", "b"]));
+        assert_eq!(
+            enveloped,
+            json!({ "data": ["a", "b"], "page": 2, "limit": 2, "total": 5, "next_cursor": "3" })
+        );
+    }
+
+    #[test]
+    fn test_last_page_has_no_next_cursor() {
+        let page = Page::new(Some(3), Some(2));
+        assert_eq!(page.skip(), 4);
+        assert_eq!(page.envelope(&["e"], 5).next_cursor, None);
+        assert_eq!(Page::all().envelope(&["a", "b"], 2).next_cursor, None);
+    }
+
+    #[test]
+    fn test_lists_are_whole_unless_paging_is_asked_for() {
+        assert_eq!(ListQuery::default().page(20), Page::all());
+        let query = ListQuery { page: Some(0), limit: None, envelope: true };
+        assert_eq!(query.page(20), Page { page: 1, limit: Some(20) });
+    }
+}
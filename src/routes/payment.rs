@@ -3,15 +3,51 @@ use serde::{Deserialize, Serialize};
 use std::{str::FromStr, sync::Arc};
 use stripe::{CapturePaymentIntent, EventObject, EventType, Webhook};
 
-use crate::middleware::auth::Claims;
+use crate::config::AppConfig;
+use crate::middleware::auth::{AuthMiddleware, Claims};
+use crate::routes::{limit_exceeded, trip_limits};
+use crate::models::bookings::{PaymentStatus, ReservationInput};
+use crate::services::availability_service::AvailabilityCache;
+use crate::services::account_service::{EmailService, EmailSettings};
+use crate::services::notification_service::TwilioSettings;
+use crate::services::booking_confirmation::{
+    BookingConfirmationService, CapturedPayment, ConfirmationOutcome, ConfirmationSender,
+};
+use crate::models::itinerary::base::FeaturedVacation;
+use crate::models::money::Money;
+use crate::services::gift_card_service::{split_payment, GiftCardService};
+use crate::services::calendar;
+use crate::services::payment_idempotency::{IdempotencyError, PaymentIdempotencyService};
+use crate::services::pricing_service::{PersonPrice, PricingService};
+use crate::services::reservation_service::{ReservationError, ReservationService};
+use crate::services::trip_limits::TripLimits;
+use crate::services::webhook_replay::{
+    is_stale, Claim, ProcessedWebhookService, ReprocessError, ReprocessOutcome,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct PaymentIntentInput {
     user_id: String,
-    amount: i64,
+    /// What the client expects to pay, in cents. Optional; the charge is the
+    /// itinerary's price, and a different amount is refused.
+    #[serde(default)]
+    amount: Option<i64>,
     customer_id: String,
     payment_method_id: String,
     description: String,
+    /// Optional gift card to deduct from the amount before charging the card
+    #[serde(default)]
+    gift_card_code: Option<String>,
+    /// Itinerary being booked, which sets the amount. Stored on the intent so a
+    /// webhook can find the booking before its transaction id is saved.
+    itinerary_id: String,
+    /// Seats held with `POST /payment/reserve`. Checked before the card is
+    /// authorized, so an expired hold is caught before the traveler pays.
+    #[serde(default)]
+    reservation_id: Option<String>,
+    /// Same as the `Idempotency-Key` header, which wins when both are sent
+    #[serde(default)]
+    idempotency_key: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -20,14 +56,276 @@ pub struct CapturePayment {
     payment_intent_id: String,
 }
 
+/// What checkout charges for an itinerary: its per-person price for every traveler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CheckoutPrice {
+    pub person_cost: Money,
+    pub travelers: u32,
+}
+
+impl CheckoutPrice {
+    /// Total due in cents, before any gift card
+    pub fn amount_due(&self) -> i64 {
+        self.person_cost.cents() * self.travelers as i64
+    }
+
+    /// Refuse an amount from the client that isn't what's due. Leaving it out is fine.
+    pub fn check_claimed(&self, claimed: Option<i64>) -> Result<i64, AmountMismatch> {
+        let due = self.amount_due();
+        match claimed {
+            Some(claimed) if claimed != due => Err(AmountMismatch { claimed, due }),
+            _ => Ok(due),
+        }
+    }
+}
+
+/// A client amount that disagrees with the checkout price
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct AmountMismatch {
+    pub claimed: i64,
+    pub due: i64,
+}
+
+impl AmountMismatch {
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::Conflict().json(serde_json::json!({
+            "error": "amount_mismatch",
+            "message": format!("Amount {} does not match the trip's price ({})", self.claimed, self.due)
+        }))
+    }
+}
+
+/// The price `itinerary_id` is sold at, or the response refusing checkout. An
+/// itinerary whose price is unavailable is never charged as if it were free, one
+/// scheduling activities with defaulted prices waits for a cost recompute, and
+/// one whose party is over the limit isn't sold at all.
+pub(crate) async fn checkout_price(
+    client: &mongodb::Client,
+    itinerary_id: &str,
+    limits: &TripLimits,
+) -> Result<CheckoutPrice, HttpResponse> {
+    let Ok(itinerary_id) = mongodb::bson::oid::ObjectId::parse_str(itinerary_id) else {
+        return Err(HttpResponse::BadRequest().body("Invalid itinerary ID"));
+    };
+    let itinerary = match client
+        .database("Itineraries")
+        .collection::<FeaturedVacation>("Featured")
+        .find_one(mongodb::bson::doc! { "_id": itinerary_id })
+        .await
+    {
+        Ok(Some(itinerary)) => itinerary,
+        Ok(None) => return Err(HttpResponse::NotFound().body("Itinerary not found")),
+        Err(e) => {
+            eprintln!("Failed to load itinerary {} for checkout: {:?}", itinerary_id, e);
+            return Err(HttpResponse::InternalServerError().body("Failed to price itinerary"));
+        }
+    };
+    limits.check_itinerary(&itinerary).map_err(|err| limit_exceeded(&err))?;
+    if !itinerary.defaulted_activity_ids.is_empty() {
+        println!(
+            "⚠️  Refusing checkout for '{}': {} activities have guessed prices",
+            itinerary.trip_name,
+            itinerary.defaulted_activity_ids.len()
+        );
+        return Err(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "price_unreviewed",
+            "message": "This trip can't be booked online until its activity prices are confirmed"
+        })));
+    }
+
+    match PricingService::person_price(client, &itinerary).await.map(PersonPrice::amount) {
+        Ok(None) => {
+            println!("⚠️  Refusing checkout for '{}': no price", itinerary.trip_name);
+            Err(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "price_unavailable",
+                "message": "This trip can't be booked online until it has a price"
+            })))
+        }
+        Ok(Some(person_cost)) => Ok(CheckoutPrice {
+            person_cost,
+            travelers: PricingService::travelers(&itinerary),
+        }),
+        Err(e) => {
+            eprintln!("Failed to price itinerary {}: {:?}", itinerary_id, e);
+            Err(HttpResponse::InternalServerError().body("Failed to price itinerary"))
+        }
+    }
+}
+
+pub(crate) fn reservation_error_response(error: ReservationError) -> HttpResponse {
+    let body = |code: &str| {
+        serde_json::json!({
+            "error": code,
+            "message": error.to_string()
+        })
+    };
+    match error {
+        ReservationError::ItineraryNotFound | ReservationError::NotFound => {
+            HttpResponse::NotFound().json(body("not_found"))
+        }
+        ReservationError::InvalidPartySize => HttpResponse::BadRequest().json(body("invalid_party_size")),
+        ReservationError::NotOperating => HttpResponse::Conflict().json(body("not_operating")),
+        ReservationError::SoldOut => HttpResponse::Conflict().json(body("sold_out")),
+        ReservationError::Expired => HttpResponse::Conflict().json(body("reservation_expired")),
+        ReservationError::DoesNotMatch => HttpResponse::Conflict().json(body("reservation_mismatch")),
+        ReservationError::DatabaseError(_) => {
+            eprintln!("Reservation failed: {}", error);
+            HttpResponse::InternalServerError().body("Failed to reserve seats")
+        }
+    }
+}
+
+/// The user's reservation if checkout can use it, for the itinerary being
+/// booked and, when known, the trip's start date
+pub(crate) async fn checkout_reservation(
+    client: &Arc<mongodb::Client>,
+    reservation_id: &str,
+    user_id: &str,
+    itinerary_id: &str,
+    arrival: Option<mongodb::bson::DateTime>,
+) -> Result<mongodb::bson::oid::ObjectId, HttpResponse> {
+    let parse = mongodb::bson::oid::ObjectId::parse_str;
+    let (Ok(reservation_id), Ok(user_id), Ok(itinerary_id)) =
+        (parse(reservation_id), parse(user_id), parse(itinerary_id))
+    else {
+        return Err(reservation_error_response(ReservationError::NotFound));
+    };
+    ReservationService::new(client.clone())
+        .find_for_checkout(reservation_id, user_id, itinerary_id, arrival.and_then(calendar::utc_date))
+        .await
+        .map(|reservation| reservation.id)
+        .map_err(reservation_error_response)
+}
+
+/*
+    /api/payment/reserve
+
+    Holds the seats a trip needs while the traveler pays, for
+    RESERVATION_HOLD_MINUTES. Pass the returned reservation_id to
+    create_payment_intent and add_booking_with_payment. Reserving the same trip
+    and date again extends the hold.
+*/
+pub async fn reserve(
+    claims: Claims,
+    mongodb_data: web::Data<Arc<mongodb::Client>>,
+    config: web::Data<AppConfig>,
+    availability_cache: web::Data<AvailabilityCache>,
+    input: web::Json<ReservationInput>,
+) -> impl Responder {
+    let Ok(user_id) = mongodb::bson::oid::ObjectId::parse_str(&claims.user_id) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let input = input.into_inner();
+    let Ok(itinerary_id) = mongodb::bson::oid::ObjectId::parse_str(&input.itinerary_id) else {
+        return HttpResponse::BadRequest().body("Invalid itinerary ID");
+    };
+    let Some(start) = calendar::utc_date(input.arrival_datetime) else {
+        return HttpResponse::BadRequest().body("Invalid arrival date");
+    };
+
+    match ReservationService::new(mongodb_data.into_inner().as_ref().clone())
+        .reserve(
+            &availability_cache,
+            user_id,
+            itinerary_id,
+            start,
+            input.party_size,
+            config.reservation_hold_minutes,
+        )
+        .await
+    {
+        Ok(reservation) => HttpResponse::Ok().json(serde_json::json!({
+            "reservation_id": reservation.id.to_hex(),
+            "itinerary_id": reservation.itinerary_id.to_hex(),
+            "start_date": reservation.start_date,
+            "party_size": reservation.seats,
+            "expires_at": reservation.expires_at.try_to_rfc3339_string().ok(),
+        })),
+        Err(e) => reservation_error_response(e),
+    }
+}
+
+/*
+    /api/payment/reserve/{id}
+
+    Gives the held seats back, e.g. when the traveler leaves checkout
+*/
+pub async fn release_reservation(
+    claims: Claims,
+    mongodb_data: web::Data<Arc<mongodb::Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let parse = mongodb::bson::oid::ObjectId::parse_str;
+    let (Ok(user_id), Ok(reservation_id)) = (parse(&claims.user_id), parse(&path.into_inner())) else {
+        return reservation_error_response(ReservationError::NotFound);
+    };
+    match ReservationService::new(mongodb_data.into_inner().as_ref().clone())
+        .release(&availability_cache, reservation_id, user_id)
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({ "released": true })),
+        Err(e) => reservation_error_response(e),
+    }
+}
+
+/// Card intent for `amount` cents. Always charged in USD: display currencies
+/// (`preferred_currency`, `?display_currency=`) never reach the payment path.
+fn card_intent<'a>(amount: i64) -> stripe::CreatePaymentIntent<'a> {
+    let mut create_intent = stripe::CreatePaymentIntent::new(amount, stripe::Currency::USD);
+    // Manual, as we capture on the frontend
+    create_intent.capture_method = Some(stripe::PaymentIntentCaptureMethod::Manual);
+    create_intent
+}
+
 #[derive(Clone)]
 pub struct StripeConfig {
     pub webhook_secret: String,
+    /// Events created longer ago than this are refused, even when correctly signed
+    pub max_event_age_hours: u64,
+}
+
+fn idempotency_error_response(error: IdempotencyError) -> HttpResponse {
+    let body = |code: &str| {
+        serde_json::json!({
+            "error": code,
+            "message": error.to_string()
+        })
+    };
+    match error {
+        IdempotencyError::InvalidKey => HttpResponse::BadRequest().json(body("invalid_idempotency_key")),
+        IdempotencyError::KeyInUse => HttpResponse::Conflict().json(body("idempotency_key_in_use")),
+        IdempotencyError::AmountMismatch => {
+            HttpResponse::UnprocessableEntity().json(body("idempotency_key_mismatch"))
+        }
+        IdempotencyError::StripeError(ref e) => {
+            println!("Error creating payment intent: {}", e);
+            HttpResponse::InternalServerError().body(error.to_string())
+        }
+        IdempotencyError::DatabaseError(_) => {
+            eprintln!("Payment intent idempotency failed: {}", error);
+            HttpResponse::InternalServerError().body("Failed to create payment intent")
+        }
+    }
 }
 
+/*
+    /api/payment/payment-intent
+
+    Send an `Idempotency-Key` header (or `idempotency_key`) to make a double
+    submit safe: for 24 hours the same key from the same user answers with the
+    intent it first created, without creating another. The key is refused (409)
+    for any other user.
+
+    itinerary_id is required: the amount is the itinerary's price for its party,
+    and a different `amount` is refused (409, amount_mismatch).
+*/
 pub async fn create_payment_intent(
+    req: HttpRequest,
     claims: Claims,
     data: web::Data<Arc<stripe::Client>>,
+    mongodb_data: web::Data<Arc<mongodb::Client>>,
+    config: Option<web::Data<AppConfig>>,
     input: web::Json<PaymentIntentInput>,
 ) -> impl Responder {
     println!("Creating payment intent...");
@@ -37,13 +335,77 @@ pub async fn create_payment_intent(
     }
 
     let input = input.into_inner();
+    let client = mongodb_data.into_inner();
+
+    // The amount is the itinerary's price, whatever the client claims
+    let price = match checkout_price(&client, &input.itinerary_id, &trip_limits(config)).await {
+        Ok(price) => price,
+        Err(response) => return response,
+    };
+    let amount = match price.check_claimed(input.amount) {
+        Ok(amount) => amount,
+        Err(mismatch) => return mismatch.response(),
+    };
+
+    let idempotency_key = req
+        .headers()
+        .get("Idempotency-Key")
+        .map(|key| key.to_str().unwrap_or_default().to_string())
+        .or_else(|| input.idempotency_key.clone());
+    let idempotency = PaymentIdempotencyService::new(client.as_ref().clone());
+    if let Some(key) = &idempotency_key {
+        match idempotency
+            .replay(&input.user_id, key, amount, mongodb::bson::DateTime::now())
+            .await
+        {
+            Ok(Some(intent)) => {
+                return HttpResponse::Ok()
+                    .insert_header(("Idempotent-Replayed", "true"))
+                    .json(intent)
+            }
+            Ok(None) => {}
+            Err(e) => return idempotency_error_response(e),
+        }
+    }
+
+    if let Some(reservation_id) = &input.reservation_id {
+        if let Err(response) =
+            checkout_reservation(&client, reservation_id, &input.user_id, &input.itinerary_id, None).await
+        {
+            return response;
+        }
+    }
+
+    let requested_amount = amount;
+    let mut amount = amount;
+
+    // Deduct the gift card balance first; only the remainder is charged to the card
+    if let Some(code) = &input.gift_card_code {
+        let service = GiftCardService::new(client.as_ref().clone());
+        let gift_card = match service.find_redeemable(code).await {
+            Ok(gift_card) => gift_card,
+            Err(e) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }));
+            }
+        };
+
+        let split = split_payment(amount, gift_card.remaining_balance);
+        if split.paid_in_full() {
+            // Nothing to charge - the booking is confirmed with the gift card alone
+            return HttpResponse::Ok().json(serde_json::json!({
+                "paid_in_full_by_gift_card": true,
+                "gift_card_amount": split.gift_card_amount,
+                "amount": 0,
+            }));
+        }
+        amount = split.card_amount;
+    }
 
-    let amount = input.amount;
     let customer_id = input.customer_id;
     let payment_method_id = input.payment_method_id;
     let description = input.description;
 
-    let mut create_intent = stripe::CreatePaymentIntent::new(amount, stripe::Currency::USD);
+    let mut create_intent = card_intent(amount);
 
     // Add customer and payment method
     create_intent.customer =
@@ -51,18 +413,32 @@ pub async fn create_payment_intent(
     create_intent.payment_method = Some(
         stripe::PaymentMethodId::from_str(&payment_method_id).expect("Invalid payment method ID"),
     );
-    // Manual, as we capture on the frontend
-    create_intent.capture_method = Some(stripe::PaymentIntentCaptureMethod::Manual);
     create_intent.description = Some(&description);
 
+    // The webhook finds the booking through these, so they come from the token
+    // and the priced itinerary rather than the request body
+    let user_id = claims.user_id.clone();
+    let mut metadata = std::collections::HashMap::from([("user_id".to_string(), user_id.clone())]);
+    metadata.insert("itinerary_id".to_string(), input.itinerary_id);
+    if let Some(reservation_id) = input.reservation_id {
+        metadata.insert("reservation_id".to_string(), reservation_id);
+    }
+    create_intent.metadata = Some(metadata);
+
     // Create the payment intent using the injected client
-    match stripe::PaymentIntent::create(data.as_ref(), create_intent).await {
+    match idempotency
+        .create(
+            data.get_ref().as_ref(),
+            &user_id,
+            idempotency_key.as_deref(),
+            requested_amount,
+            create_intent,
+            mongodb::bson::DateTime::now(),
+        )
+        .await
+    {
         Ok(intent) => HttpResponse::Ok().json(intent),
-        Err(e) => {
-            println!("Error creating payment intent: {:?}", e);
-            HttpResponse::InternalServerError()
-                .body(format!("Failed to create payment intent: {}", e))
-        }
+        Err(e) => idempotency_error_response(e),
     }
 }
 
@@ -120,10 +496,114 @@ pub async fn capture_payment(
     }
 }
 
+/// Confirm the booking behind a payment intent that may have been captured outside
+/// `add_booking_with_payment` (Stripe dashboard, retry tooling). An authorization
+/// alone only links the intent to its booking; confirmation waits for the capture.
+/// A booking that's already confirmed is left alone, and its confirmation is only
+/// sent again with `force_notifications`. A payment that isn't what the booking
+/// costs leaves it pending and goes to reconciliation.
+async fn process_payment_intent_event(
+    service: BookingConfirmationService,
+    availability_cache: &AvailabilityCache,
+    intent: &stripe::PaymentIntent,
+    sender: &impl ConfirmationSender,
+    force_notifications: bool,
+) -> HttpResponse {
+    let intent_id = intent.id.to_string();
+
+    let booking = match service
+        .find_for_payment_intent(&intent_id, &intent.metadata)
+        .await
+    {
+        Ok(Some(booking)) => booking,
+        Ok(None) => {
+            println!("No booking found for payment intent {}", intent_id);
+            return HttpResponse::Ok().json(serde_json::json!({ "received": true }));
+        }
+        Err(e) => {
+            // A 500 makes Stripe redeliver the event later
+            eprintln!("Failed to look up booking for payment intent {}: {:?}", intent_id, e);
+            return HttpResponse::InternalServerError().body("Failed to look up booking");
+        }
+    };
+
+    if intent.status != stripe::PaymentIntentStatus::Succeeded {
+        if let (Some(booking_id), None) = (booking.id, &booking.transaction_id) {
+            if let Err(e) = service.link_payment_intent(booking_id, &intent_id).await {
+                eprintln!("Failed to link payment intent {}: {:?}", intent_id, e);
+                return HttpResponse::InternalServerError().body("Failed to update booking");
+            }
+        }
+        return HttpResponse::Ok().json(serde_json::json!({ "received": true }));
+    }
+
+    let payment = CapturedPayment {
+        payment_intent_id: intent_id.clone(),
+        amount: if intent.amount_received > 0 {
+            intent.amount_received
+        } else {
+            intent.amount
+        },
+        currency: intent.currency.to_string(),
+    };
+
+    // A pending booking is only confirmed by a payment for what it costs
+    if matches!(booking.status, PaymentStatus::Pending | PaymentStatus::PendingPayment) {
+        match service.payment_mismatch(&booking, &payment).await {
+            Ok(None) => {}
+            Ok(Some(reason)) => {
+                if let Some(booking_id) = booking.id {
+                    service.hold_for_reconciliation(booking_id, &payment, &reason).await;
+                }
+                return HttpResponse::Ok().json(serde_json::json!({
+                    "received": true,
+                    "confirmed": false,
+                    "reason": reason
+                }));
+            }
+            Err(e) => {
+                eprintln!("Failed to price booking for payment intent {}: {:?}", intent_id, e);
+                return HttpResponse::InternalServerError().body("Failed to price booking");
+            }
+        }
+    }
+
+    match service
+        .confirm_notifying(availability_cache, &booking, &payment, sender)
+        .await
+    {
+        Ok(ConfirmationOutcome::Confirmed(booking)) => {
+            println!(
+                "Webhook confirmed booking {:?} for payment intent {}",
+                booking.id, intent_id
+            );
+            HttpResponse::Ok().json(serde_json::json!({ "received": true }))
+        }
+        Ok(ConfirmationOutcome::AlreadyProcessed) => {
+            let resend = force_notifications && booking.status == PaymentStatus::Confirmed;
+            if resend {
+                service.resend_confirmation(&booking, &payment, sender).await;
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "received": true,
+                "already_processed": true,
+                "notifications_resent": resend
+            }))
+        }
+        Err(e) => {
+            eprintln!("Failed to confirm booking for payment intent {}: {:?}", intent_id, e);
+            HttpResponse::InternalServerError().body("Failed to confirm booking")
+        }
+    }
+}
+
 pub async fn handle_stripe_webhook(
     req: HttpRequest,
     payload: web::Bytes,
     stripe_config: web::Data<StripeConfig>,
+    mongodb_data: web::Data<Arc<mongodb::Client>>,
+    availability_cache: web::Data<AvailabilityCache>,
+    config: Option<web::Data<AppConfig>>,
 ) -> impl Responder {
     // Get the Stripe-Signature header
     let signature = match req.headers().get("stripe-signature") {
@@ -141,6 +621,7 @@ pub async fn handle_stripe_webhook(
         }
     };
 
+    // Also rejects signatures more than five minutes old
     let event =
         match Webhook::construct_event(&payload_str, signature, &stripe_config.webhook_secret) {
             Ok(event) => event,
@@ -150,11 +631,77 @@ pub async fn handle_stripe_webhook(
             }
         };
 
-    // Check the event type and handle accordingly
+    // A freshly signed payload can still carry an old event
+    let now = chrono::Utc::now().timestamp();
+    if is_stale(event.created, now, stripe_config.max_event_age_hours) {
+        println!("⚠️  Rejecting stale webhook event {} created at {}", event.id, event.created);
+        return HttpResponse::BadRequest().body("Webhook error: event is too old");
+    }
+
+    let client = mongodb_data.into_inner().as_ref().clone();
+    let processed = ProcessedWebhookService::new(client.clone());
+    let event_id = event.id.to_string();
+    match processed
+        .claim(
+            &event_id,
+            // `EventType` displays as a JSON string, quotes included
+            event.type_.to_string().trim_matches('"'),
+            event.created,
+            stripe_config.max_event_age_hours,
+            &payload_str,
+        )
+        .await
+    {
+        Ok(Claim::New) => {}
+        Ok(Claim::AlreadyProcessed) => {
+            println!("🔁 Webhook event {} already processed, skipping", event_id);
+            return HttpResponse::Ok().json(serde_json::json!({ "received": true, "duplicate": true }));
+        }
+        Err(e) => {
+            eprintln!("Failed to record webhook event {}: {:?}", event_id, e);
+            return HttpResponse::InternalServerError().body("Failed to record webhook event");
+        }
+    }
+
+    let mut service = BookingConfirmationService::new(client.clone());
+    let mut email_service = None;
+    if let Some(config) = config {
+        service = service
+            .with_transactions(config.mongodb_transactions)
+            .with_notifications(&config.email, config.twilio.as_ref());
+        email_service = EmailService::new(client, &config.email).ok();
+    }
+    let response = dispatch_event(event, service, &availability_cache, &email_service, false).await;
+
+    // Let Stripe's retry through if handling failed
+    if response.status().is_server_error() {
+        if let Err(e) = processed.release(&event_id).await {
+            eprintln!("Failed to release webhook event {}: {:?}", event_id, e);
+        }
+    }
+    response
+}
+
+/// Handle a verified event. Emails already sent for it are only sent again with
+/// `force_notifications`; everything else is safe to repeat.
+async fn dispatch_event(
+    event: stripe::Event,
+    service: BookingConfirmationService,
+    availability_cache: &AvailabilityCache,
+    sender: &impl ConfirmationSender,
+    force_notifications: bool,
+) -> HttpResponse {
     match event.type_ {
-        EventType::PaymentIntentSucceeded => {
-            if let EventObject::PaymentIntent(_payment_intent) = event.data.object {
-                HttpResponse::Ok().json(serde_json::json!({ "received": true }))
+        EventType::PaymentIntentSucceeded | EventType::PaymentIntentAmountCapturableUpdated => {
+            if let EventObject::PaymentIntent(payment_intent) = event.data.object {
+                process_payment_intent_event(
+                    service,
+                    availability_cache,
+                    &payment_intent,
+                    sender,
+                    force_notifications,
+                )
+                .await
             } else {
                 HttpResponse::BadRequest().body("Invalid payment intent object")
             }
@@ -189,3 +736,119 @@ pub async fn handle_stripe_webhook(
         }
     }
 }
+
+/// Run a claimed event through the webhook handling again, for
+/// `POST /admin/stripe/events/{id}/reprocess`. Signature and age checks are skipped:
+/// the payload was verified when it arrived. Confirmation texts go out through `sms`.
+pub async fn reprocess_event(
+    client: Arc<mongodb::Client>,
+    availability_cache: &AvailabilityCache,
+    transactions: bool,
+    event_id: &str,
+    force_notifications: bool,
+    sender: &impl ConfirmationSender,
+    sms: Option<&TwilioSettings>,
+) -> Result<ReprocessOutcome, ReprocessError> {
+    let record = ProcessedWebhookService::new(client.clone())
+        .stored_event(event_id)
+        .await?;
+    let event: stripe::Event = serde_json::from_str(record.payload.as_deref().unwrap_or_default())
+        .map_err(|e| ReprocessError::InvalidPayload(e.to_string()))?;
+
+    println!("🔁 Reprocessing webhook event {} ({})", event_id, record.event_type);
+    let service = BookingConfirmationService::new(client)
+        .with_transactions(transactions)
+        .with_notifications(&EmailSettings::default(), sms);
+    let response = dispatch_event(event, service, availability_cache, sender, force_notifications).await;
+    let status = response.status().as_u16();
+    let body = actix_web::body::to_bytes(response.into_body())
+        .await
+        .unwrap_or_default();
+    Ok(ReprocessOutcome {
+        event_id: record.event_id,
+        event_type: record.event_type,
+        status,
+        response: serde_json::from_slice(&body)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&body).into_owned())),
+        force_notifications,
+    })
+}
+
+/// Stripe webhook (public, verified by signature) and the protected payment routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/stripe/webhook", web::post().to(handle_stripe_webhook))
+        .service(
+            web::scope("/payment")
+                .wrap(AuthMiddleware)
+                .route("/payment-intent", web::post().to(create_payment_intent))
+                .route("/capture-payment", web::post().to(capture_payment))
+                .route("/reserve", web::post().to(reserve))
+                .route("/reserve/{id}", web::delete().to(release_reservation))
+                .route(
+                    "/apply-gift-card",
+                    web::post().to(super::gift_card::apply_gift_card),
+                ),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_currency_does_not_change_the_charge() {
+        // A client that sends its display currency along still gets a USD charge
+        let input: PaymentIntentInput = serde_json::from_value(serde_json::json!({
+            "user_id": "65f000000000000000000002",
+            "amount": 125_000,
+            "customer_id": "cus_123",
+            "payment_method_id": "pm_123",
+            "description": "Arkansas River Weekend",
+            "itinerary_id": "65f000000000000000000009",
+            "display_currency": "EUR",
+            "preferred_currency": "GBP",
+        }))
+        .unwrap();
+        let price = CheckoutPrice {
+            person_cost: Money::from_cents(62_500),
+            travelers: 2,
+        };
+
+        let intent = card_intent(price.check_claimed(input.amount).unwrap());
+        assert_eq!(intent.amount, 125_000);
+        assert_eq!(intent.currency, stripe::Currency::USD);
+        assert_eq!(
+            intent.capture_method,
+            Some(stripe::PaymentIntentCaptureMethod::Manual)
+        );
+    }
+
+    #[test]
+    fn test_payment_intent_needs_an_itinerary_to_price() {
+        let input = serde_json::from_value::<PaymentIntentInput>(serde_json::json!({
+            "user_id": "65f000000000000000000002",
+            "amount": 100,
+            "customer_id": "cus_123",
+            "payment_method_id": "pm_123",
+            "description": "No itinerary",
+        }));
+        assert!(input.is_err());
+    }
+
+    #[test]
+    fn test_amount_due_is_the_price_for_the_whole_party() {
+        let price = CheckoutPrice {
+            person_cost: Money::from_cents(42_050),
+            travelers: 3,
+        };
+        assert_eq!(price.amount_due(), 126_150);
+        assert_eq!(price.check_claimed(None).unwrap(), 126_150);
+        assert_eq!(price.check_claimed(Some(126_150)).unwrap(), 126_150);
+
+        // Claiming less can't buy the trip, with or without a gift card
+        let refused = price.check_claimed(Some(1_000)).unwrap_err();
+        assert_eq!(refused, AmountMismatch { claimed: 1_000, due: 126_150 });
+        assert_eq!(refused.response().status(), 409);
+        assert!(split_payment(price.amount_due(), 5_000).card_amount > 0);
+    }
+}
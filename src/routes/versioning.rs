@@ -0,0 +1,223 @@
+//! API versions
+//!
+//! Every route is served under `/v1` and, with the exceptions in
+//! `V2_EXEMPT_PATHS`, under `/v2`. The unprefixed paths are aliases for v1 and,
+//! like v1, carry `Deprecation` and (with `API_V1_SUNSET` set) `Sunset` headers.
+//!
+//! Handlers are shared between versions. Those whose response shape changed take a
+//! `ResponseVersion`, which comes from the scope the request was routed through,
+//! and serialize with it. In v2, ObjectIds are hex strings rather than
+//! `{"$oid": ...}` and image lists are objects rather than bare URLs.
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web, Error, FromRequest, HttpRequest, HttpResponse,
+};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::future::{ready, Ready};
+
+use crate::config::AppConfig;
+
+/// Paths served unprefixed and under `/v1` but not under `/v2`. Health checks
+/// aren't part of the versioned API.
+pub const V2_EXEMPT_PATHS: &[&str] = &["/health", "/request-info", "/"];
+
+/// The response shape a request was routed to. Requests outside `/v2` get v1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl ResponseVersion {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ResponseVersion::V1 => "/v1",
+            ResponseVersion::V2 => "/v2",
+        }
+    }
+
+    /// `value` in this version's shape
+    pub fn to_value<T: Serialize>(self, value: &T) -> Result<Value, serde_json::Error> {
+        let mut value = serde_json::to_value(value)?;
+        if self == ResponseVersion::V2 {
+            reshape_v2(&mut value);
+        }
+        Ok(value)
+    }
+
+    /// A 200 with `value` in this version's shape. v1 is serialized directly, so its
+    /// bytes are exactly what the handlers sent before versioning.
+    pub fn ok<T: Serialize>(self, value: &T) -> HttpResponse {
+        match self {
+            ResponseVersion::V1 => HttpResponse::Ok().json(value),
+            ResponseVersion::V2 => match self.to_value(value) {
+                Ok(value) => HttpResponse::Ok().json(value),
+                Err(e) => {
+                    eprintln!("Failed to serialize v2 response: {}", e);
+                    HttpResponse::InternalServerError().body("Failed to serialize response")
+                }
+            },
+        }
+    }
+}
+
+impl FromRequest for ResponseVersion {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(req.app_data::<ResponseVersion>().copied().unwrap_or_default()))
+    }
+}
+
+/// Hex ObjectIds and structured images, everywhere in `value`
+fn reshape_v2(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if let Some(hex) = object_id_hex(map) {
+                *value = Value::String(hex);
+                return;
+            }
+            // A stand-in image says where it came from on the image itself
+            let source = map.remove("image_source");
+            for (key, field) in map.iter_mut() {
+                if key == "images" {
+                    structure_images(field, source.as_ref());
+                } else {
+                    reshape_v2(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(reshape_v2),
+        _ => {}
+    }
+}
+
+/// The hex string of an extended-JSON ObjectId, `{"$oid": "..."}`
+fn object_id_hex(map: &Map<String, Value>) -> Option<String> {
+    match (map.len(), map.get("$oid")) {
+        (1, Some(Value::String(hex))) => Some(hex.clone()),
+        _ => None,
+    }
+}
+
+/// `["a.jpg", "b.jpg"]` becomes `[{"url": "a.jpg", "primary": true}, {"url": "b.jpg", "primary": false}]`,
+/// with `"source"` on each when the images are stand-ins
+fn structure_images(images: &mut Value, source: Option<&Value>) {
+    let Value::Array(urls) = images else {
+        return;
+    };
+    for (index, url) in urls.iter_mut().enumerate() {
+        if url.is_string() {
+            let mut image = serde_json::json!({ "url": url.take(), "primary": index == 0 });
+            if let Some(source) = source {
+                image["source"] = source.clone();
+            }
+            *url = image;
+        }
+    }
+}
+
+/// `pattern` without its `/v1` or `/v2` prefix, for route tables written against
+/// the unprefixed paths
+pub fn unversioned(pattern: &str) -> &str {
+    for version in [ResponseVersion::V1, ResponseVersion::V2] {
+        if let Some(rest) = pattern.strip_prefix(version.prefix()) {
+            if rest.is_empty() {
+                return "/";
+            }
+            if rest.starts_with('/') {
+                return rest;
+            }
+        }
+    }
+    pattern
+}
+
+/// An HTTP-date for the `Sunset` header, at midnight UTC
+fn http_date(date: NaiveDate) -> String {
+    date.and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Marks v1 responses as deprecated, with the sunset date once one is configured
+pub async fn deprecation_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let sunset = req
+        .app_data::<web::Data<AppConfig>>()
+        .and_then(|config| config.api_v1_sunset);
+    let mut response = next.call(req).await?;
+    let headers = response.headers_mut();
+    headers.insert(HeaderName::from_static("deprecation"), HeaderValue::from_static("true"));
+    if let Some(value) = sunset.and_then(|date| HeaderValue::from_str(&http_date(date)).ok()) {
+        headers.insert(HeaderName::from_static("sunset"), value);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct Itinerary {
+        #[serde(rename = "_id")]
+        id: ObjectId,
+        activity_ids: Vec<ObjectId>,
+        images: Vec<String>,
+    }
+
+    #[test]
+    fn test_v2_uses_hex_ids_and_structured_images() {
+        let (id, activity_id) = (ObjectId::new(), ObjectId::new());
+        let itinerary = Itinerary {
+            id,
+            activity_ids: vec![activity_id],
+            images: vec!["a.jpg".to_string(), "b.jpg".to_string()],
+        };
+
+        let v1 = ResponseVersion::V1.to_value(&itinerary).unwrap();
+        assert_eq!(v1["_id"], json!({ "$oid": id.to_hex() }));
+        assert_eq!(v1["images"], json!(["a.jpg", "b.jpg"]));
+
+        let v2 = ResponseVersion::V2.to_value(&itinerary).unwrap();
+        assert_eq!(
+            v2,
+            json!({
+                "_id": id.to_hex(),
+                "activity_ids": [activity_id.to_hex()],
+                "images": [
+                    { "url": "a.jpg", "primary": true },
+                    { "url": "b.jpg", "primary": false },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_patterns_lose_their_version_prefix() {
+        assert_eq!(unversioned("/v1/account/{id}"), "/account/{id}");
+        assert_eq!(unversioned("/v2/payment/reserve"), "/payment/reserve");
+        assert_eq!(unversioned("/v1"), "/");
+        assert_eq!(unversioned("/account/{id}"), "/account/{id}");
+        assert_eq!(unversioned("/v10/account"), "/v10/account");
+        assert_eq!(
+            http_date(NaiveDate::from_ymd_opt(2027, 3, 1).unwrap()),
+            "Mon, 01 Mar 2027 00:00:00 GMT"
+        );
+    }
+}
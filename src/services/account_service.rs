@@ -1,10 +1,14 @@
 use reqwest;
 use serde::{Deserialize, Serialize};
-use std::env;
-use mongodb::{Client, Collection, bson::{doc, oid::ObjectId, DateTime}};
-use rand::{distributions::Alphanumeric, Rng};
+use std::sync::{Arc, Mutex};
+use mongodb::{Client, Collection, bson::{doc, oid::ObjectId, DateTime}, options::ReturnDocument};
+use rand::Rng;
 use chrono::{TimeZone, Utc};
 use crate::models::bookings::BookingDetails;
+use crate::models::money::Money;
+use crate::services::email_suppression_service::EmailSuppressionService;
+use crate::services::favorite_digest_service::DigestItem;
+use crate::services::review_request_service::{ReviewRequestEmail, REVIEW_LINK_DAYS};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SendGridEmail {
@@ -29,8 +33,18 @@ pub struct SendGridRequest {
     pub from: SendGridEmail,
     pub subject: String,
     pub content: Vec<SendGridContent>,
+    /// Returned on delivery events, so bounces can be told apart by message kind
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
 }
 
+/// Wrong codes accepted for one verification before a new code is needed, unless
+/// `EMAIL_VERIFICATION_MAX_ATTEMPTS` says otherwise
+pub const MAX_VERIFICATION_ATTEMPTS: u32 = 5;
+
+/// How long after sending a code another can be requested for the same address
+pub const VERIFICATION_RESEND_COOLDOWN_SECS: i64 = 60;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmailVerification {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -41,6 +55,17 @@ pub struct EmailVerification {
     pub expires_at: DateTime,
     pub verified: bool,
     pub created_at: DateTime,
+    /// Codes entered so far
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl EmailVerification {
+    /// Seconds until another code can be sent to this address
+    pub fn resend_wait_seconds(&self, now: DateTime) -> i64 {
+        let age_secs = (now.timestamp_millis() - self.created_at.timestamp_millis()) / 1000;
+        (VERIFICATION_RESEND_COOLDOWN_SECS - age_secs).max(0)
+    }
 }
 
 #[derive(Debug)]
@@ -51,6 +76,12 @@ pub enum EmailError {
     DatabaseError(String),
     CodeExpired,
     InvalidCode,
+    /// Every attempt on the code was used; a new one can be requested after the wait
+    TooManyAttempts { retry_after_seconds: i64 },
+    /// A code was sent to this address moments ago
+    ResendTooSoon { retry_after_seconds: i64 },
+    /// The address bounced or reported spam, so nothing is sent to it
+    Suppressed(String),
 }
 
 impl std::fmt::Display for EmailError {
@@ -62,84 +93,198 @@ impl std::fmt::Display for EmailError {
             EmailError::DatabaseError(err) => write!(f, "Database error: {}", err),
             EmailError::CodeExpired => write!(f, "Verification code has expired"),
             EmailError::InvalidCode => write!(f, "Invalid verification code"),
+            EmailError::TooManyAttempts { retry_after_seconds } => write!(
+                f,
+                "Too many incorrect codes; request a new code in {} seconds",
+                retry_after_seconds
+            ),
+            EmailError::ResendTooSoon { retry_after_seconds } => write!(
+                f,
+                "A code was just sent; request another in {} seconds",
+                retry_after_seconds
+            ),
+            EmailError::Suppressed(email) => {
+                write!(f, "Email to {} is suppressed after a bounce or spam report", email)
+            }
         }
     }
 }
 
 impl std::error::Error for EmailError {}
 
+/// A random 6-digit code, leading zeros included
+pub fn generate_verification_code() -> String {
+    format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+}
+
+/// Compare codes in time that depends only on their length, so response timing
+/// doesn't reveal how many leading characters of a guess were right
+pub fn codes_match(expected: &str, submitted: &str) -> bool {
+    let (expected, submitted) = (expected.as_bytes(), submitted.as_bytes());
+    if expected.len() != submitted.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(submitted)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Refuse to send another code while the last one for `email` is still fresh
+async fn check_resend_cooldown(
+    collection: &Collection<EmailVerification>,
+    email: &str,
+    now: DateTime,
+) -> Result<(), EmailError> {
+    let latest = collection
+        .find_one(doc! { "email": email, "verified": false })
+        .sort(doc! { "created_at": -1 })
+        .await
+        .map_err(|e| EmailError::DatabaseError(e.to_string()))?;
+    match latest.map(|verification| verification.resend_wait_seconds(now)) {
+        Some(wait) if wait > 0 => Err(EmailError::ResendTooSoon { retry_after_seconds: wait }),
+        _ => Ok(()),
+    }
+}
+
+/// Who emails come from and the site their links point at
+#[derive(Debug, Clone)]
+pub struct EmailSettings {
+    /// Nothing is sent through SendGrid without it
+    pub sendgrid_api_key: Option<String>,
+    pub from_email: String,
+    pub frontend_url: String,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        EmailSettings {
+            sendgrid_api_key: None,
+            from_email: "noreply@actota.com".to_string(),
+            frontend_url: "https://actota.com".to_string(),
+        }
+    }
+}
+
 pub struct EmailService {
-    api_key: String,
-    client: reqwest::Client,
+    transport: Transport,
+    suppressions: EmailSuppressionService,
+    settings: EmailSettings,
+}
+
+enum Transport {
+    SendGrid { api_key: String, client: reqwest::Client },
+    Outbox(Outbox),
+}
+
+/// A message `EmailService::with_outbox` kept instead of sending
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    pub to: String,
+    pub subject: String,
+    pub category: String,
+}
+
+/// Messages an outbox `EmailService` would have sent, in order
+#[derive(Debug, Clone, Default)]
+pub struct Outbox(Arc<Mutex<Vec<OutboxMessage>>>);
+
+impl Outbox {
+    pub fn messages(&self) -> Vec<OutboxMessage> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn sent_to(&self, email: &str) -> usize {
+        self.messages().iter().filter(|message| message.to == email).count()
+    }
 }
 
 impl EmailService {
-    pub fn new() -> Result<Self, EmailError> {
-        let api_key = env::var("SENDGRID_API_KEY")
-            .map_err(|_| EmailError::EnvironmentError("SENDGRID_API_KEY not set".to_string()))?;
+    /// Sends through SendGrid, skipping addresses that bounced or reported spam
+    pub fn new(db_client: Arc<Client>, settings: &EmailSettings) -> Result<Self, EmailError> {
+        let api_key = settings
+            .sendgrid_api_key
+            .clone()
+            .ok_or_else(|| EmailError::EnvironmentError("SENDGRID_API_KEY not set".to_string()))?;
 
         let client = reqwest::Client::new();
 
-        Ok(Self { api_key, client })
+        Ok(Self {
+            transport: Transport::SendGrid { api_key, client },
+            suppressions: EmailSuppressionService::new(db_client),
+            settings: settings.clone(),
+        })
+    }
+
+    /// Keeps messages in the returned outbox instead of sending them. Suppressed
+    /// addresses are skipped just the same.
+    pub fn with_outbox(db_client: Arc<Client>) -> (Self, Outbox) {
+        let outbox = Outbox::default();
+        let service = Self {
+            transport: Transport::Outbox(outbox.clone()),
+            suppressions: EmailSuppressionService::new(db_client),
+            settings: EmailSettings::default(),
+        };
+        (service, outbox)
     }
 
     pub async fn send_email(
         &self,
+        category: &str,
         to_email: &str,
         from_email: &str,
         subject: &str,
         content: &str,
     ) -> Result<(), EmailError> {
-        let url = "https://api.sendgrid.com/v3/mail/send";
-
-        let request = SendGridRequest {
-            personalizations: vec![SendGridPersonalization {
-                to: vec![SendGridEmail {
-                    email: to_email.to_string(),
-                }],
-            }],
-            from: SendGridEmail {
-                email: from_email.to_string(),
-            },
-            subject: subject.to_string(),
-            content: vec![SendGridContent {
-                content_type: "text/plain".to_string(),
-                value: content.to_string(),
-            }],
-        };
-
-        let response = self
-            .client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
+        self.deliver(category, to_email, from_email, subject, "text/plain", content)
             .await
-            .map_err(|e| EmailError::RequestError(e.to_string()))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            let status = response.status();
-            let body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Err(EmailError::ApiError(format!(
-                "Status: {}, Body: {}",
-                status, body
-            )))
-        }
     }
 
     pub async fn send_html_email(
         &self,
+        category: &str,
         to_email: &str,
         from_email: &str,
         subject: &str,
         html_content: &str,
     ) -> Result<(), EmailError> {
+        self.deliver(category, to_email, from_email, subject, "text/html", html_content)
+            .await
+    }
+
+    /// Every email goes through here. `category` comes back on SendGrid's
+    /// delivery events (see `email_suppression_service`).
+    async fn deliver(
+        &self,
+        category: &str,
+        to_email: &str,
+        from_email: &str,
+        subject: &str,
+        content_type: &str,
+        content: &str,
+    ) -> Result<(), EmailError> {
+        match self.suppressions.is_suppressed(to_email).await {
+            Ok(true) => {
+                println!("✉️  Not sending {} email to suppressed address {}", category, to_email);
+                return Err(EmailError::Suppressed(to_email.to_string()));
+            }
+            Ok(false) => {}
+            // Better a message to a bad address than none to a good one
+            Err(e) => eprintln!("Failed to check email suppression for {}: {:?}", to_email, e),
+        }
+
+        let (api_key, client) = match &self.transport {
+            Transport::SendGrid { api_key, client } => (api_key, client),
+            Transport::Outbox(outbox) => {
+                outbox.0.lock().unwrap().push(OutboxMessage {
+                    to: to_email.to_string(),
+                    subject: subject.to_string(),
+                    category: category.to_string(),
+                });
+                return Ok(());
+            }
+        };
         let url = "https://api.sendgrid.com/v3/mail/send";
 
         let request = SendGridRequest {
@@ -153,15 +298,15 @@ impl EmailService {
             },
             subject: subject.to_string(),
             content: vec![SendGridContent {
-                content_type: "text/html".to_string(),
-                value: html_content.to_string(),
+                content_type: content_type.to_string(),
+                value: content.to_string(),
             }],
+            categories: vec![category.to_string()],
         };
 
-        let response = self
-            .client
+        let response = client
             .post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Authorization", format!("Bearer {}", api_key))
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
@@ -189,13 +334,7 @@ impl EmailService {
         user_id: Option<ObjectId>,
         db_client: &Client,
     ) -> Result<String, EmailError> {
-        // Generate 6-digit verification code
-        let verification_code: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(6)
-            .map(char::from)
-            .collect::<String>()
-            .to_uppercase();
+        let verification_code = generate_verification_code();
 
         let now = DateTime::now();
         let expires_at = DateTime::from_millis(now.timestamp_millis() + (15 * 60 * 1000)); // 15 minutes
@@ -209,11 +348,13 @@ impl EmailService {
             expires_at,
             verified: false,
             created_at: now,
+            attempts: 0,
         };
 
         let collection: Collection<EmailVerification> = db_client
             .database("actota")
             .collection("email_verifications");
+        check_resend_cooldown(&collection, email, now).await?;
 
         // Remove any existing unverified codes for this email
         let _ = collection
@@ -229,8 +370,7 @@ impl EmailService {
             .map_err(|e| EmailError::DatabaseError(e.to_string()))?;
 
         // Send verification email
-        let from_email = env::var("FROM_EMAIL")
-            .unwrap_or_else(|_| "noreply@actota.com".to_string());
+        let from_email = &self.settings.from_email;
 
         let subject = "Verify Your Email Address";
         let content = format!(
@@ -238,7 +378,7 @@ impl EmailService {
             verification_code
         );
 
-        self.send_email(email, &from_email, subject, &content)
+        self.send_email("verification", email, from_email, subject, &content)
             .await?;
 
         Ok(verification_code)
@@ -250,13 +390,7 @@ impl EmailService {
         user_id: Option<ObjectId>,
         db_client: &Client,
     ) -> Result<String, EmailError> {
-        // Generate 6-digit verification code
-        let verification_code: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(6)
-            .map(char::from)
-            .collect::<String>()
-            .to_uppercase();
+        let verification_code = generate_verification_code();
 
         let now = DateTime::now();
         let expires_at = DateTime::from_millis(now.timestamp_millis() + (15 * 60 * 1000)); // 15 minutes
@@ -270,11 +404,13 @@ impl EmailService {
             expires_at,
             verified: false,
             created_at: now,
+            attempts: 0,
         };
 
         let collection: Collection<EmailVerification> = db_client
             .database("actota")
             .collection("email_verifications");
+        check_resend_cooldown(&collection, email, now).await?;
 
         // Remove any existing unverified codes for this email
         let _ = collection
@@ -290,8 +426,7 @@ impl EmailService {
             .map_err(|e| EmailError::DatabaseError(e.to_string()))?;
 
         // Send HTML verification email
-        let from_email = env::var("FROM_EMAIL")
-            .unwrap_or_else(|_| "noreply@actota.com".to_string());
+        let from_email = &self.settings.from_email;
 
         let subject = "Verify Your Email Address";
         let html_content = format!(
@@ -329,15 +464,18 @@ impl EmailService {
             verification_code
         );
 
-        self.send_html_email(email, &from_email, subject, &html_content)
+        self.send_html_email("verification", email, from_email, subject, &html_content)
             .await?;
 
         Ok(verification_code)
     }
 
+    /// Check `code` against a pending verification. Each code entered uses up one
+    /// of `max_attempts`; after the last the code is dead and only a new one will do.
     pub async fn verify_email_code(
-        email: &str,
+        verification_id: ObjectId,
         code: &str,
+        max_attempts: u32,
         db_client: &Client,
     ) -> Result<bool, EmailError> {
         let collection: Collection<EmailVerification> = db_client
@@ -346,40 +484,61 @@ impl EmailService {
 
         let now = DateTime::now();
 
-        // Find the verification record
-        let verification = collection
-            .find_one(doc! {
-                "email": email,
-                "verification_code": code,
-                "verified": false
-            })
+        // Claim an attempt before comparing, so concurrent guesses can't all get
+        // in under the cap before any of them is counted
+        let claimed = collection
+            .find_one_and_update(
+                doc! {
+                    "_id": verification_id,
+                    "verified": false,
+                    "attempts": { "$lt": max_attempts }
+                },
+                doc! { "$inc": { "attempts": 1 } },
+            )
+            .return_document(ReturnDocument::After)
             .await
             .map_err(|e| EmailError::DatabaseError(e.to_string()))?;
 
-        match verification {
-            Some(v) => {
-                // Check if code has expired
-                if v.expires_at.timestamp_millis() < now.timestamp_millis() {
-                    // Clean up expired code
-                    let _ = collection
-                        .delete_one(doc! { "_id": v.id })
-                        .await;
-                    return Err(EmailError::CodeExpired);
-                }
-
-                // Mark as verified
-                collection
-                    .update_one(
-                        doc! { "_id": v.id },
-                        doc! { "$set": { "verified": true } }
-                    )
+        let v = match claimed {
+            Some(v) => v,
+            None => {
+                // Either out of attempts, or already verified or gone
+                let unverified = collection
+                    .find_one(doc! { "_id": verification_id, "verified": false })
                     .await
                     .map_err(|e| EmailError::DatabaseError(e.to_string()))?;
-
-                Ok(true)
+                return match unverified {
+                    Some(v) => Err(EmailError::TooManyAttempts {
+                        retry_after_seconds: v.resend_wait_seconds(now),
+                    }),
+                    None => Err(EmailError::InvalidCode),
+                };
             }
-            None => Err(EmailError::InvalidCode),
+        };
+
+        // Check if code has expired
+        if v.expires_at.timestamp_millis() < now.timestamp_millis() {
+            // Clean up expired code
+            let _ = collection
+                .delete_one(doc! { "_id": v.id })
+                .await;
+            return Err(EmailError::CodeExpired);
         }
+
+        if !codes_match(&v.verification_code, code) {
+            return Err(EmailError::InvalidCode);
+        }
+
+        // Mark as verified
+        collection
+            .update_one(
+                doc! { "_id": v.id },
+                doc! { "$set": { "verified": true } }
+            )
+            .await
+            .map_err(|e| EmailError::DatabaseError(e.to_string()))?;
+
+        Ok(true)
     }
 
     pub async fn cleanup_expired_codes(db_client: &Client) -> Result<u64, EmailError> {
@@ -410,11 +569,9 @@ impl EmailService {
         currency: &str,
         transaction_id: &str,
     ) -> Result<(), EmailError> {
-        let from_email = env::var("FROM_EMAIL")
-            .unwrap_or_else(|_| "noreply@actota.com".to_string());
+        let from_email = &self.settings.from_email;
 
-        let frontend_url = env::var("FRONTEND_URL")
-            .unwrap_or_else(|_| "https://actota.com".to_string());
+        let frontend_url = &self.settings.frontend_url;
 
         let booking_url = format!(
             "{}/account/bookings/{}",
@@ -485,6 +642,23 @@ impl EmailService {
             "#.to_string()
         };
 
+        let special_requests_section = booking
+            .special_requests
+            .as_deref()
+            .map(|requests| {
+                format!(
+                    r#"
+                    <div class="booking-details">
+                        <h3>Your Special Requests</h3>
+                        <p style="white-space: pre-line;">{}</p>
+                        <p style="font-size: 12px; color: #666;">We've passed these on to your activity operators.</p>
+                    </div>
+                    "#,
+                    html_escape(requests)
+                )
+            })
+            .unwrap_or_default();
+
         let html_content = format!(
             r#"
             <!DOCTYPE html>
@@ -554,6 +728,8 @@ impl EmailService {
                     
                     {}
                     
+                    {}
+                    
                     <div style="text-align: center;">
                         <a href="{}" class="cta-button">View Full Booking Details</a>
                     </div>
@@ -585,11 +761,342 @@ impl EmailService {
             departure_date,
             booking.id.unwrap().to_hex(),
             serde_json::to_value(&booking.status).unwrap().as_str().unwrap(),
+            special_requests_section,
             payment_section,
             booking_url
         );
 
-        self.send_html_email(user_email, &from_email, &subject, &html_content)
+        self.send_html_email("booking_confirmation", user_email, from_email, &subject, &html_content)
+            .await
+    }
+
+    pub async fn send_new_device_signin_email(
+        &self,
+        user_email: &str,
+        ip: Option<&str>,
+        signed_in_at: DateTime,
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let when = match Utc.timestamp_millis_opt(signed_in_at.timestamp_millis()) {
+            chrono::LocalResult::Single(dt) => dt.format("%B %d, %Y at %I:%M %p UTC").to_string(),
+            _ => "recently".to_string(),
+        };
+
+        let content = format!(
+            "We noticed a new sign-in to your ACTOTA account on {} from IP address {}.\n\n\
+             If this was you, no action is needed.\n\n\
+             If you don't recognise this activity, change your password right away and review \
+             your recent security activity at {}/account/security.\n\n\
+             - The ACTOTA Team",
+            when,
+            ip.unwrap_or("unknown"),
+            frontend_url
+        );
+
+        self.send_email("security", user_email, from_email, "New sign-in to your ACTOTA account", &content)
+            .await
+    }
+
+    pub async fn send_price_drop_email(
+        &self,
+        user_email: &str,
+        itinerary_id: ObjectId,
+        trip_name: &str,
+        old_price: Money,
+        new_price: Money,
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let content = format!(
+            "Good news! {}, one of your favorites, dropped from ${} to ${} per person.\n\n\
+             Take another look at {}/itineraries/{}.\n\n\
+             You're receiving this because price alerts are on. You can turn them off at \
+             {}/account/notifications.\n\n\
+             - The ACTOTA Team",
+            trip_name,
+            old_price,
+            new_price,
+            frontend_url,
+            itinerary_id.to_hex(),
+            frontend_url
+        );
+
+        let subject = format!("Price drop: {}", trip_name);
+        self.send_email("price_alert", user_email, from_email, &subject, &content)
+            .await
+    }
+
+    /// Weekly roundup of favorited itineraries that got cheaper or were updated
+    pub async fn send_favorites_digest_email(
+        &self,
+        user_email: &str,
+        first_name: Option<&str>,
+        items: &[DigestItem],
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let favorites: Vec<String> = items
+            .iter()
+            .map(|item| {
+                let mut news = Vec::new();
+                if let Some((from, to)) = item.price {
+                    news.push(format!("now ${} per person, down from ${}", to, from));
+                }
+                if item.days_updated {
+                    news.push("a refreshed day-by-day plan".to_string());
+                }
+                if item.images_updated {
+                    news.push("new photos".to_string());
+                }
+                format!(
+                    "- {}: {}\n  {}/itineraries/{}",
+                    item.trip_name,
+                    news.join(", "),
+                    frontend_url,
+                    item.itinerary_id.to_hex()
+                )
+            })
+            .collect();
+
+        let content = format!(
+            "Hi {},\n\n\
+             Some trips you saved have changed since you last looked:\n\n\
+             {}\n\n\
+             You're receiving this because marketing emails are on. You can turn them off at \
+             {}/account/notifications.\n\n\
+             - The ACTOTA Team",
+            first_name.unwrap_or("there"),
+            favorites.join("\n"),
+            frontend_url
+        );
+
+        let subject = if items.len() == 1 {
+            format!("Your favorite {} has changed", items[0].trip_name)
+        } else {
+            format!("{} of your favorite trips have changed", items.len())
+        };
+        self.send_email("favorites_digest", user_email, from_email, &subject, &content)
+            .await
+    }
+
+    /// For accounts support created while booking on the customer's behalf. The
+    /// account has a random password, so the customer is sent to choose their own.
+    pub async fn send_account_invitation_email(
+        &self,
+        user_email: &str,
+        first_name: Option<&str>,
+        trip_name: &str,
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let content = format!(
+            "Hi {},\n\n\
+             Thanks for booking {} with us over the phone. We've set up an ACTOTA account \
+             for {} so you can see your booking and manage your trip online.\n\n\
+             Choose a password at {}/auth/forgot-password, or sign in with Google or \
+             Facebook using this email address.\n\n\
+             - The ACTOTA Team",
+            first_name.unwrap_or("there"),
+            trip_name,
+            user_email,
+            frontend_url
+        );
+
+        self.send_email("account_invitation", user_email, from_email, "Your ACTOTA account is ready", &content)
+            .await
+    }
+
+    /// Asks how a finished trip went, linking to the review form for the booking
+    pub async fn send_review_request_email(&self, email: &ReviewRequestEmail) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let links: String = email
+            .review_links(frontend_url)
+            .iter()
+            .map(|(title, link)| format!("{}\n{}\n\n", title, link))
+            .collect();
+        let content = format!(
+            "Hi {},\n\n\
+             Welcome back from {}! We'd love to hear how it went. Your reviews help other \
+             travelers choose their trips and help us make ours better. Each link below \
+             works for {} days without signing in:\n\n\
+             {}\
+             Don't want to be asked about your trips? Stop review requests at \
+             {}/review-requests/unsubscribe?token={}\n\n\
+             - The ACTOTA Team",
+            email.first_name.as_deref().unwrap_or("there"),
+            email.trip_name,
+            REVIEW_LINK_DAYS,
+            links,
+            frontend_url,
+            email.unsubscribe_token
+        );
+
+        let subject = format!("How was {}?", email.trip_name);
+        self.send_email("review_request", &email.user_email, from_email, &subject, &content)
             .await
     }
+
+    /// The new dates after a reschedule, and how any price difference was settled
+    pub async fn send_booking_rescheduled_email(
+        &self,
+        user_email: &str,
+        first_name: Option<&str>,
+        trip_name: &str,
+        booking: &BookingDetails,
+        price_difference: i64,
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let format_date = |datetime: DateTime| match Utc.timestamp_millis_opt(datetime.timestamp_millis()) {
+            chrono::LocalResult::Single(dt) => dt.format("%B %d, %Y").to_string(),
+            _ => "Date unavailable".to_string(),
+        };
+        let payment_note = match price_difference {
+            0 => "The price didn't change.".to_string(),
+            charged if charged > 0 => format!(
+                "The new dates cost ${} more, which has been charged to your card.",
+                Money::from_cents(charged)
+            ),
+            refunded => format!(
+                "The new dates cost ${} less, which is on its way back to you.",
+                Money::from_cents(-refunded)
+            ),
+        };
+
+        let content = format!(
+            "Hi {},\n\n\
+             Your {} trip has been moved. You now arrive on {} and depart on {}.\n\n\
+             {}\n\n\
+             See your booking at {}/account/bookings/{}.\n\n\
+             - The ACTOTA Team",
+            first_name.unwrap_or("there"),
+            trip_name,
+            format_date(booking.arrival_datetime),
+            format_date(booking.departure_datetime),
+            payment_note,
+            frontend_url,
+            booking.id.map(|id| id.to_hex()).unwrap_or_default()
+        );
+
+        self.send_email(
+            "booking_change",
+            user_email,
+            from_email,
+            &format!("Booking rescheduled: {}", trip_name),
+            &content,
+        )
+        .await
+    }
+
+    /// What changed in a booked trip after an admin edited its days
+    pub async fn send_booking_itinerary_changed_email(
+        &self,
+        user_email: &str,
+        first_name: Option<&str>,
+        trip_name: &str,
+        booking: &BookingDetails,
+        changes: &[String],
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let frontend_url = &self.settings.frontend_url;
+
+        let changes: String = changes.iter().map(|change| format!("- {}\n", change)).collect();
+        let content = format!(
+            "Hi {},\n\n\
+             We've made changes to your {} trip:\n\n\
+             {}\n\
+             See your updated itinerary at {}/account/bookings/{}. If the changes don't work \
+             for you, reply to this email and we'll help.\n\n\
+             - The ACTOTA Team",
+            first_name.unwrap_or("there"),
+            trip_name,
+            changes,
+            frontend_url,
+            booking.id.map(|id| id.to_hex()).unwrap_or_default()
+        );
+
+        self.send_email(
+            "booking_change",
+            user_email,
+            from_email,
+            &format!("Changes to your trip: {}", trip_name),
+            &content,
+        )
+        .await
+    }
+
+    /// Tell an admin that reports about a piece of content have piled up
+    pub async fn send_content_flag_alert_email(
+        &self,
+        admin_email: &str,
+        content_title: &str,
+        content_id: &str,
+        open_flags: u64,
+    ) -> Result<(), EmailError> {
+        let from_email = &self.settings.from_email;
+
+        let content = format!(
+            "\"{}\" ({}) has {} open reports from travelers.\n\n\
+             Review it in the moderation queue (GET /api/admin/content-flags) and either \
+             dismiss the reports or take the content down.\n\n\
+             - ACTOTA",
+            content_title, content_id, open_flags
+        );
+
+        self.send_email(
+            "moderation",
+            admin_email,
+            from_email,
+            &format!("Content reported: {}", content_title),
+            &content,
+        )
+        .await
+    }
+}
+
+/// Escape user-provided text for inclusion in an HTML email
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_six_digits() {
+        for _ in 0..100 {
+            let code = generate_verification_code();
+            assert_eq!(code.len(), 6);
+            assert!(code.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn test_codes_match_only_exactly() {
+        assert!(codes_match("042917", "042917"));
+        assert!(!codes_match("042917", "042918"));
+        assert!(!codes_match("042917", "42917"));
+        assert!(!codes_match("042917", "0429170"));
+        assert!(!codes_match("042917", ""));
+    }
 }
@@ -0,0 +1,651 @@
+//! Finding and merging duplicate activities
+//!
+//! The same tour often gets into `Options.Activity` twice, once from the Vertex
+//! sync and once from a CSV import, under slightly different titles. Candidates
+//! are activities of the same company in the same city whose normalized titles
+//! have a token sort ratio of at least the threshold; pairs chain into clusters.
+//!
+//! Merging keeps one activity, the primary. Itinerary days, the activity lists
+//! kept on itineraries (missing and defaulted activities) and review requests
+//! that name a duplicate are pointed at the primary, fields the primary lacks
+//! are copied from the duplicates, and the duplicates are soft-deleted: they get
+//! `merged_into` and `deleted_at` and stop being offered for new itineraries.
+//! With `MONGODB_TRANSACTIONS` on, all of it commits together. Favorites hold
+//! itineraries rather than activities, so a merge leaves them alone.
+//! Anything still holding a duplicate's id, such as the Vertex index until its
+//! next sync, resolves it through `merged_into` (see `resolve_merged`).
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::Days;
+use crate::services::unit_of_work::{self, UnitOfWork};
+
+/// Token sort ratio, out of 100, two titles need to be candidates by default
+pub const DEFAULT_DUPLICATE_THRESHOLD: f64 = 75.0;
+
+/// Most duplicates one merge can fold into the primary
+pub const MAX_MERGE_DUPLICATES: usize = 20;
+
+/// Words that say nothing about which activity it is
+const FILLER_WORDS: &[&str] = &[
+    "a", "an", "and", "at", "experience", "excursion", "for", "in", "of", "on", "the", "tour", "tours", "trip",
+    "with",
+];
+
+/// Fields a merge never copies from a duplicate
+const UNMERGED_FIELDS: &[&str] = &["_id", "created_at", "updated_at", "merged_into", "deleted_at"];
+
+/// Lowercased words of `title` without punctuation or filler, sorted
+pub fn normalize_title(title: &str) -> String {
+    let cleaned: String = title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut words: Vec<&str> = cleaned
+        .split_whitespace()
+        .filter(|word| !FILLER_WORDS.contains(word))
+        .collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+/// Similarity of two titles, 0 to 100: twice the longest common subsequence of
+/// their normalized forms over their combined length
+pub fn token_sort_ratio(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize_title(a).chars().collect();
+    let b: Vec<char> = normalize_title(b).chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 100.0;
+    }
+    let mut previous = vec![0usize; b.len() + 1];
+    for ca in &a {
+        let mut current = vec![0usize; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = if ca == cb {
+                previous[j] + 1
+            } else {
+                current[j].max(previous[j + 1])
+            };
+        }
+        previous = current;
+    }
+    let common = previous[b.len()];
+    (200.0 * common as f64 / (a.len() + b.len()) as f64 * 10.0).round() / 10.0
+}
+
+/// What clustering needs of an activity
+#[derive(Debug, Clone, Deserialize)]
+pub struct DuplicateCandidate {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub company: String,
+    #[serde(default)]
+    pub address: CandidateAddress,
+    #[serde(default)]
+    pub created_at: Option<DateTime>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CandidateAddress {
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub state: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClusterActivity {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SimilarPair {
+    pub a: String,
+    pub b: String,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateCluster {
+    pub company: String,
+    pub city: String,
+    /// Oldest first, the usual choice of primary
+    pub activities: Vec<ClusterActivity>,
+    /// Every pair in the cluster at or above the threshold
+    pub pairs: Vec<SimilarPair>,
+}
+
+fn group_key(candidate: &DuplicateCandidate) -> (String, String) {
+    let normalize = |value: &str| value.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (
+        normalize(&candidate.company),
+        format!("{}, {}", normalize(&candidate.address.city), normalize(&candidate.address.state)),
+    )
+}
+
+/// Candidates of the same company and city whose titles score at least
+/// `threshold`, chained into clusters. Largest clusters first.
+pub fn cluster(candidates: &[DuplicateCandidate], threshold: f64) -> Vec<DuplicateCluster> {
+    let mut groups: BTreeMap<(String, String), Vec<&DuplicateCandidate>> = BTreeMap::new();
+    for candidate in candidates {
+        if !candidate.company.trim().is_empty() {
+            groups.entry(group_key(candidate)).or_default().push(candidate);
+        }
+    }
+
+    let mut clusters = Vec::new();
+    for group in groups.values() {
+        // Union-find over the group, joined by every similar pair
+        let mut parent: Vec<usize> = (0..group.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let mut pairs = Vec::new();
+        for i in 0..group.len() {
+            for j in i + 1..group.len() {
+                let score = token_sort_ratio(&group[i].title, &group[j].title);
+                if score >= threshold {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[a] = b;
+                    pairs.push((i, j, score));
+                }
+            }
+        }
+
+        let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for i in 0..group.len() {
+            let r = root(&mut parent, i);
+            members.entry(r).or_default().push(i);
+        }
+        for (r, mut indexes) in members.into_iter().filter(|(_, indexes)| indexes.len() > 1) {
+            indexes.sort_by_key(|i| (group[*i].created_at.map(|at| at.timestamp_millis()), group[*i].id));
+            clusters.push(DuplicateCluster {
+                company: group[indexes[0]].company.trim().to_string(),
+                city: format!("{}, {}", group[indexes[0]].address.city.trim(), group[indexes[0]].address.state.trim()),
+                activities: indexes
+                    .iter()
+                    .map(|i| ClusterActivity {
+                        id: group[*i].id.to_hex(),
+                        title: group[*i].title.clone(),
+                    })
+                    .collect(),
+                pairs: pairs
+                    .iter()
+                    .filter(|(i, _, _)| root(&mut parent, *i) == r)
+                    .map(|(i, j, score)| SimilarPair {
+                        a: group[*i].id.to_hex(),
+                        b: group[*j].id.to_hex(),
+                        score: *score,
+                    })
+                    .collect(),
+            });
+        }
+    }
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.activities.len()));
+    clusters
+}
+
+fn is_blank(value: &Bson) -> bool {
+    match value {
+        Bson::Null => true,
+        Bson::String(s) => s.trim().is_empty(),
+        Bson::Array(items) => items.is_empty(),
+        Bson::Document(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// Fields missing or blank on `primary` that a duplicate has, from the first
+/// duplicate that has each
+pub fn missing_fields(primary: &Document, duplicates: &[Document]) -> Document {
+    let mut fields = Document::new();
+    for duplicate in duplicates {
+        for (key, value) in duplicate {
+            if UNMERGED_FIELDS.contains(&key.as_str()) || is_blank(value) || fields.contains_key(key) {
+                continue;
+            }
+            if primary.get(key).is_none_or(is_blank) {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    fields
+}
+
+#[derive(Debug, Deserialize)]
+struct ItineraryDays {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(flatten)]
+    days: Days,
+    #[serde(default)]
+    missing_activity_ids: Vec<ObjectId>,
+    #[serde(default)]
+    defaulted_activity_ids: Vec<ObjectId>,
+}
+
+/// Point the ids in `ids` at `replacements[id]`, dropping any that repeat.
+/// Returns how many were replaced.
+fn replace_ids(ids: &mut Vec<ObjectId>, replacements: &HashMap<ObjectId, ObjectId>) -> usize {
+    let mut replaced = 0;
+    let mut seen = HashSet::new();
+    ids.retain_mut(|id| {
+        if let Some(replacement) = replacements.get(id) {
+            *id = *replacement;
+            replaced += 1;
+        }
+        seen.insert(*id)
+    });
+    replaced
+}
+
+impl ItineraryDays {
+    /// Point this itinerary's references at `replacements`. Returns the day items
+    /// and the listed ids that changed.
+    fn replace_activities(&mut self, replacements: &HashMap<ObjectId, ObjectId>) -> (usize, usize) {
+        let day_items = self.days.replace_activities(replacements);
+        let listed = replace_ids(&mut self.missing_activity_ids, replacements)
+            + replace_ids(&mut self.defaulted_activity_ids, replacements);
+        (day_items, listed)
+    }
+}
+
+/// What a merge changed, or would change on a dry run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeReport {
+    pub primary_id: ObjectId,
+    pub duplicate_ids: Vec<ObjectId>,
+    pub dry_run: bool,
+    /// Copied onto the primary from the duplicates
+    pub fields_merged: Vec<String>,
+    /// Itineraries whose days named a duplicate
+    pub itinerary_ids: Vec<ObjectId>,
+    /// Day items pointed at the primary, or dropped as a repeat of it
+    pub day_items_rewritten: u64,
+    /// Entries in itineraries' missing and defaulted activity lists
+    #[serde(default)]
+    pub listed_ids_rewritten: u64,
+    pub review_requests: u64,
+    /// Every reference pointed at the primary: day items, listed ids and review requests
+    #[serde(default)]
+    pub references_updated: u64,
+}
+
+/// A merge, kept in the admin audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityMergeAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    #[serde(flatten)]
+    pub report: MergeReport,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ActivityMergeError {
+    NothingToMerge,
+    TooManyDuplicates,
+    PrimaryIsDuplicate,
+    NotFound(ObjectId),
+    /// Already merged into another activity
+    AlreadyMerged(ObjectId),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ActivityMergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ActivityMergeError::NothingToMerge => write!(f, "Name at least one duplicate to merge"),
+            ActivityMergeError::TooManyDuplicates => {
+                write!(f, "At most {} duplicates can be merged at once", MAX_MERGE_DUPLICATES)
+            }
+            ActivityMergeError::PrimaryIsDuplicate => write!(f, "The primary can't also be a duplicate"),
+            ActivityMergeError::NotFound(id) => write!(f, "Activity {} not found", id),
+            ActivityMergeError::AlreadyMerged(id) => {
+                write!(f, "Activity {} was already merged into another", id)
+            }
+            ActivityMergeError::DatabaseError(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ActivityMergeError {}
+
+impl From<mongodb::error::Error> for ActivityMergeError {
+    fn from(err: mongodb::error::Error) -> Self {
+        ActivityMergeError::DatabaseError(err.to_string())
+    }
+}
+
+pub struct ActivityDedupService {
+    client: Arc<Client>,
+    transactional: bool,
+}
+
+impl ActivityDedupService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ActivityDedupService {
+            client,
+            transactional: false,
+        }
+    }
+
+    /// Merge in a multi-document transaction (`MONGODB_TRANSACTIONS`), so a merge
+    /// that fails part way leaves every reference as it was
+    pub fn with_transactions(mut self, enabled: bool) -> Self {
+        self.transactional = enabled;
+        self
+    }
+
+    fn activities(&self) -> Collection<Document> {
+        primary_collection(&self.client, "Options", "Activity")
+    }
+
+    /// Clusters of likely duplicates among activities that haven't been merged
+    pub async fn duplicates(&self, threshold: f64) -> Result<Vec<DuplicateCluster>, mongodb::error::Error> {
+        let candidates: Vec<DuplicateCandidate> = self
+            .activities()
+            .clone_with_type::<DuplicateCandidate>()
+            .find(doc! { "merged_into": null })
+            .projection(doc! { "title": 1, "company": 1, "address.city": 1, "address.state": 1, "created_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        Ok(cluster(&candidates, threshold))
+    }
+
+    /// Fold `duplicate_ids` into `primary_id`. With `dry_run` nothing is written
+    /// and the report says what would be.
+    pub async fn merge(
+        &self,
+        admin_id: ObjectId,
+        primary_id: ObjectId,
+        duplicate_ids: &[ObjectId],
+        dry_run: bool,
+    ) -> Result<MergeReport, ActivityMergeError> {
+        let mut seen = HashSet::new();
+        let duplicate_ids: Vec<ObjectId> = duplicate_ids.iter().copied().filter(|id| seen.insert(*id)).collect();
+        if duplicate_ids.is_empty() {
+            return Err(ActivityMergeError::NothingToMerge);
+        }
+        if duplicate_ids.len() > MAX_MERGE_DUPLICATES {
+            return Err(ActivityMergeError::TooManyDuplicates);
+        }
+        if duplicate_ids.contains(&primary_id) {
+            return Err(ActivityMergeError::PrimaryIsDuplicate);
+        }
+
+        let mut ids = duplicate_ids.clone();
+        ids.push(primary_id);
+        let found: HashMap<ObjectId, Document> = self
+            .activities()
+            .find(doc! { "_id": { "$in": &ids } })
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| Some((activity.get_object_id("_id").ok()?, activity)))
+            .collect();
+        let mut duplicates = Vec::with_capacity(duplicate_ids.len());
+        for id in &ids {
+            let activity = found.get(id).ok_or(ActivityMergeError::NotFound(*id))?;
+            if !matches!(activity.get("merged_into"), None | Some(Bson::Null)) {
+                return Err(ActivityMergeError::AlreadyMerged(*id));
+            }
+            if *id != primary_id {
+                duplicates.push(activity.clone());
+            }
+        }
+        let fields = missing_fields(&found[&primary_id], &duplicates);
+
+        let replacements: HashMap<ObjectId, ObjectId> = duplicate_ids.iter().map(|id| (*id, primary_id)).collect();
+        let mut rewritten = Vec::new();
+        let (mut day_items_rewritten, mut listed_ids_rewritten) = (0, 0);
+        let mut cursor = self
+            .client
+            .database("Itineraries")
+            .collection::<ItineraryDays>("Featured")
+            .find(doc! {})
+            .projection(doc! { "_id": 1, "days": 1, "missing_activity_ids": 1, "defaulted_activity_ids": 1 })
+            .await?;
+        while let Some(mut itinerary) = cursor.try_next().await? {
+            let (day_items, listed) = itinerary.replace_activities(&replacements);
+            if day_items + listed > 0 {
+                day_items_rewritten += day_items as u64;
+                listed_ids_rewritten += listed as u64;
+                rewritten.push(itinerary);
+            }
+        }
+
+        let review_requests = primary_collection::<Document>(&self.client, "Account", "ReviewRequests");
+        let review_request_count = review_requests
+            .count_documents(doc! { "activities.id": { "$in": &duplicate_ids } })
+            .await?;
+        let mut report = MergeReport {
+            primary_id,
+            duplicate_ids: duplicate_ids.clone(),
+            dry_run,
+            fields_merged: fields.keys().cloned().collect(),
+            itinerary_ids: rewritten.iter().map(|itinerary| itinerary.id).collect(),
+            day_items_rewritten,
+            listed_ids_rewritten,
+            review_requests: review_request_count,
+            references_updated: day_items_rewritten + listed_ids_rewritten + review_request_count,
+        };
+        if dry_run {
+            return Ok(report);
+        }
+
+        let itineraries = self.client.database("Itineraries").collection::<Document>("Featured");
+        let activities = self.activities();
+        let now = DateTime::now();
+        let mut primary_update = fields;
+        primary_update.insert("updated_at", now);
+        // References first and the soft delete last, so without a transaction a
+        // merge that fails part way can simply be sent again
+        let review_requests_updated = unit_of_work::run(&self.client, self.transactional, async |uow: &mut UnitOfWork| {
+            for itinerary in &rewritten {
+                uow.update_one(
+                    &itineraries,
+                    doc! { "_id": itinerary.id },
+                    doc! { "$set": {
+                        "days": mongodb::bson::to_bson(&itinerary.days.days)?,
+                        "missing_activity_ids": &itinerary.missing_activity_ids,
+                        "defaulted_activity_ids": &itinerary.defaulted_activity_ids,
+                        "updated_at": now,
+                    } },
+                )
+                .await?;
+            }
+            let review_requests_updated = uow
+                .update_many_filtered(
+                    &review_requests,
+                    doc! { "activities.id": { "$in": &duplicate_ids } },
+                    doc! { "$set": { "activities.$[merged].id": primary_id } },
+                    vec![doc! { "merged.id": { "$in": &duplicate_ids } }],
+                )
+                .await?
+                .modified_count;
+            uow.update_one(&activities, doc! { "_id": primary_id }, doc! { "$set": primary_update.clone() })
+                .await?;
+            // Earlier merges into a duplicate now resolve straight to the primary
+            uow.update_many(
+                &activities,
+                doc! { "merged_into": { "$in": &duplicate_ids } },
+                doc! { "$set": { "merged_into": primary_id, "updated_at": now } },
+            )
+            .await?;
+            uow.update_many(
+                &activities,
+                doc! { "_id": { "$in": &duplicate_ids } },
+                doc! { "$set": { "merged_into": primary_id, "deleted_at": now, "updated_at": now } },
+            )
+            .await?;
+            Ok(review_requests_updated)
+        })
+        .await?;
+        // A review request can pick up a duplicate between the count and the update
+        report.review_requests = review_requests_updated;
+        report.references_updated = day_items_rewritten + listed_ids_rewritten + review_requests_updated;
+
+        self.record(ActivityMergeAudit {
+            id: None,
+            action: "activities_merged".to_string(),
+            admin_id,
+            report: report.clone(),
+            created_at: now,
+        })
+        .await;
+        Ok(report)
+    }
+
+    async fn record(&self, audit: ActivityMergeAudit) {
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<ActivityMergeAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for activity merge into {}: {}", audit.report.primary_id, e);
+        }
+    }
+
+    /// Replace merged activities with what they were merged into, keeping the
+    /// first of any that end up the same. For activity lists that may hold stale
+    /// ids, like Vertex results.
+    pub async fn resolve_merged(&self, activities: Vec<Activity>) -> Result<Vec<Activity>, mongodb::error::Error> {
+        let ids: Vec<ObjectId> = activities.iter().filter_map(|activity| activity.id).collect();
+        let merged: HashMap<ObjectId, ObjectId> = self
+            .activities()
+            .find(doc! { "_id": { "$in": &ids }, "merged_into": { "$ne": null } })
+            .projection(doc! { "merged_into": 1 })
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| Some((activity.get_object_id("_id").ok()?, activity.get_object_id("merged_into").ok()?)))
+            .collect();
+        if merged.is_empty() {
+            return Ok(activities);
+        }
+
+        let missing: Vec<ObjectId> = merged.values().filter(|id| !ids.contains(id)).copied().collect();
+        let mut primaries: HashMap<ObjectId, Activity> = self
+            .activities()
+            .clone_with_type::<Activity>()
+            .find(doc! { "_id": { "$in": missing }, "merged_into": null })
+            .await?
+            .try_collect::<Vec<Activity>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| Some((activity.id?, activity)))
+            .collect();
+
+        let mut seen = HashSet::new();
+        let mut resolved = Vec::with_capacity(activities.len());
+        for activity in activities {
+            let activity = match activity.id.and_then(|id| merged.get(&id)) {
+                None => activity,
+                Some(primary_id) => match primaries.remove(primary_id) {
+                    Some(primary) => primary,
+                    // The primary is in the list itself, or gone
+                    None => continue,
+                },
+            };
+            if activity.id.is_none_or(|id| seen.insert(id)) {
+                resolved.push(activity);
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(title: &str, company: &str, city: &str) -> DuplicateCandidate {
+        DuplicateCandidate {
+            id: ObjectId::new(),
+            title: title.to_string(),
+            company: company.to_string(),
+            address: CandidateAddress {
+                city: city.to_string(),
+                state: "CO".to_string(),
+            },
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_titles_are_compared_as_sorted_words() {
+        assert_eq!(normalize_title("The Royal-Gorge Rafting Trip!"), "gorge rafting royal");
+        assert_eq!(token_sort_ratio("Rafting, Royal Gorge", "Royal Gorge Rafting"), 100.0);
+        let score = token_sort_ratio("Royal Gorge Rafting", "Royal Gorge White Water Rafting Trip");
+        assert!(score >= DEFAULT_DUPLICATE_THRESHOLD, "{}", score);
+        let score = token_sort_ratio("Royal Gorge Rafting", "Royal Gorge Bridge Zipline");
+        assert!(score < DEFAULT_DUPLICATE_THRESHOLD, "{}", score);
+    }
+
+    #[test]
+    fn test_clusters_need_the_same_company_and_city() {
+        let original = candidate("Royal Gorge Rafting", "Echo Canyon", "Cañon City");
+        let import = candidate("Royal Gorge White Water Rafting Trip", " echo canyon", "cañon city");
+        let elsewhere = candidate("Royal Gorge Rafting", "Echo Canyon", "Salida");
+        let competitor = candidate("Royal Gorge Rafting", "River Runners", "Cañon City");
+        let different = candidate("Sunset Horseback Ride", "Echo Canyon", "Cañon City");
+        let clusters = cluster(&[original.clone(), import.clone(), elsewhere, competitor, different], 75.0);
+
+        assert_eq!(clusters.len(), 1);
+        let ids: Vec<&str> = clusters[0].activities.iter().map(|activity| activity.id.as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&original.id.to_hex().as_str()) && ids.contains(&import.id.to_hex().as_str()));
+        assert_eq!(clusters[0].pairs.len(), 1);
+        assert!(clusters[0].pairs[0].score >= 75.0);
+    }
+
+    #[test]
+    fn test_only_missing_fields_are_merged() {
+        let primary = doc! { "_id": ObjectId::new(), "title": "Rafting", "description": "", "images": [] };
+        let duplicates = vec![
+            doc! { "_id": ObjectId::new(), "title": "Rafting trip", "description": "Class III", "images": [], "guide": "Sam" },
+            doc! { "_id": ObjectId::new(), "description": "Other", "images": ["a.jpg"], "created_at": DateTime::now() },
+        ];
+        let fields = missing_fields(&primary, &duplicates);
+        assert_eq!(fields, doc! { "description": "Class III", "guide": "Sam", "images": ["a.jpg"] });
+    }
+    #[test]
+    fn test_listed_ids_point_at_the_primary_once() {
+        let (primary, duplicate, other) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let replacements = HashMap::from([(duplicate, primary)]);
+
+        let mut ids = vec![duplicate, other, primary];
+        assert_eq!(replace_ids(&mut ids, &replacements), 1);
+        assert_eq!(ids, vec![primary, other]);
+
+        let mut ids = vec![other];
+        assert_eq!(replace_ids(&mut ids, &replacements), 0);
+        assert_eq!(ids, vec![other]);
+    }
+}
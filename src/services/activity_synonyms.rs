@@ -0,0 +1,149 @@
+//! Activity synonyms shared by search scoring and query expansion.
+//!
+//! Each group is an activity, the terms travelers search it by and the words
+//! that suggest it in activity and itinerary text. The dictionary is built once
+//! per process; [`ActivitySynonyms::canonical`] maps any of a group's terms or
+//! synonyms back to the activity.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+pub struct SynonymGroup {
+    /// The activity, as the frontend names it
+    pub canonical: &'static str,
+    /// Requested terms that search for this activity, `canonical` included
+    pub terms: &'static [&'static str],
+    /// Text that counts as this activity when the term itself isn't there
+    pub synonyms: &'static [&'static str],
+}
+
+const GROUPS: &[SynonymGroup] = &[
+    SynonymGroup {
+        canonical: "atving",
+        terms: &["atving", "atv", "atvs"],
+        synonyms: &["quad", "four wheeler", "off road", "off-road", "4x4", "all terrain vehicle", "dirt bike", "trail riding"],
+    },
+    SynonymGroup {
+        canonical: "hotsprings",
+        terms: &["hotsprings", "hot springs", "hot spring"],
+        synonyms: &["thermal", "spa", "mineral springs", "geothermal", "springs", "natural springs", "thermal baths"],
+    },
+    SynonymGroup {
+        canonical: "goldminetours",
+        terms: &["goldminetours", "gold mine tours", "gold mine", "goldmine"],
+        synonyms: &["mining", "mine tour", "mining tour", "historical mine", "gold rush", "underground tour", "mine exploration", "mining history"],
+    },
+    SynonymGroup {
+        canonical: "hiking",
+        terms: &["hiking", "hike", "hikes"],
+        synonyms: &["trail", "trek", "walking", "nature walk", "mountain", "wilderness"],
+    },
+    SynonymGroup {
+        canonical: "skiing",
+        terms: &["skiing", "ski"],
+        synonyms: &["slope", "mountain resort", "powder", "alpine"],
+    },
+    SynonymGroup {
+        canonical: "rafting",
+        terms: &["rafting", "raft"],
+        synonyms: &["river", "whitewater", "rapids", "float"],
+    },
+    SynonymGroup {
+        canonical: "climbing",
+        terms: &["climbing", "climb"],
+        synonyms: &["rock climbing", "bouldering", "mountaineering"],
+    },
+    SynonymGroup {
+        canonical: "fishing",
+        terms: &["fishing", "fish"],
+        synonyms: &["angling", "fly fishing", "catch"],
+    },
+    SynonymGroup {
+        canonical: "biking",
+        terms: &["biking", "bike", "cycling"],
+        synonyms: &["bicycle", "mountain bike", "trail ride"],
+    },
+    SynonymGroup {
+        canonical: "kayaking",
+        terms: &["kayaking", "kayak"],
+        synonyms: &["paddle", "paddling", "water sports"],
+    },
+    SynonymGroup {
+        canonical: "camping",
+        terms: &["camping", "camp"],
+        synonyms: &["campground", "outdoor", "tent", "rv"],
+    },
+    SynonymGroup {
+        canonical: "wildlife",
+        terms: &["wildlife"],
+        synonyms: &["animals", "safari", "nature viewing", "bird watching"],
+    },
+];
+
+static ACTIVITY_SYNONYMS: LazyLock<ActivitySynonyms> = LazyLock::new(|| ActivitySynonyms::new(GROUPS));
+
+pub struct ActivitySynonyms {
+    groups: &'static [SynonymGroup],
+    /// Requested term → its group
+    by_term: HashMap<&'static str, usize>,
+    /// Term or synonym → its group; a word in two groups belongs to the first
+    reverse: HashMap<&'static str, usize>,
+}
+
+impl ActivitySynonyms {
+    fn new(groups: &'static [SynonymGroup]) -> Self {
+        let mut by_term = HashMap::new();
+        let mut reverse = HashMap::new();
+        for (index, group) in groups.iter().enumerate() {
+            for term in group.terms {
+                by_term.entry(*term).or_insert(index);
+                reverse.entry(*term).or_insert(index);
+            }
+            for synonym in group.synonyms {
+                reverse.entry(*synonym).or_insert(index);
+            }
+        }
+        ActivitySynonyms { groups, by_term, reverse }
+    }
+
+    /// The process-wide dictionary
+    pub fn shared() -> &'static ActivitySynonyms {
+        &ACTIVITY_SYNONYMS
+    }
+
+    /// Synonyms to look for when the lowercase `term` is requested, empty when it
+    /// isn't a term any group is searched by
+    pub fn synonyms_for(&self, term: &str) -> &'static [&'static str] {
+        self.by_term
+            .get(term)
+            .map(|index| self.groups[*index].synonyms)
+            .unwrap_or_default()
+    }
+
+    /// The activity a term or synonym stands for: "whitewater" → "rafting"
+    pub fn canonical(&self, word: &str) -> Option<&'static str> {
+        self.reverse
+            .get(word.trim().to_lowercase().as_str())
+            .map(|index| self.groups[*index].canonical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse_lookup_returns_the_canonical_activity() {
+        let synonyms = ActivitySynonyms::shared();
+        assert_eq!(synonyms.canonical("whitewater"), Some("rafting"));
+        assert_eq!(synonyms.canonical(" ATVs "), Some("atving"));
+        assert_eq!(synonyms.canonical("hot springs"), Some("hotsprings"));
+        assert_eq!(synonyms.canonical("mountain bike"), Some("biking"));
+        assert_eq!(synonyms.canonical("museum"), None);
+
+        // Only requested terms have synonyms to look for, not the synonyms themselves
+        assert!(synonyms.synonyms_for("hike").contains(&"trail"));
+        assert!(synonyms.synonyms_for("trail").is_empty());
+        assert!(std::ptr::eq(synonyms, ActivitySynonyms::shared()));
+    }
+}
@@ -0,0 +1,489 @@
+//! Bookings support creates on a customer's behalf
+//!
+//! Phone bookings are paid through a Stripe payment link, so by the time support
+//! records the booking the payment is already captured. The booking then goes
+//! through the same confirmation as a self-serve one (inventory, confirmation
+//! email and text). Every manual booking is written to the admin audit log, and
+//! one that can't be audited is removed again.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::{
+    account::{User, UserRole},
+    bookings::{BookingDetails, Party, PaymentStatus},
+    itinerary::base::FeaturedVacation,
+    money::Money,
+};
+use crate::services::account_service::{EmailService, EmailSettings};
+use crate::services::availability_service::AvailabilityCache;
+use crate::services::booking_confirmation::{BookingConfirmationService, CapturedPayment};
+use crate::services::notification_service::TwilioSettings;
+use crate::services::pricing_service::PricingService;
+use crate::services::webhook_replay::is_duplicate_key;
+
+/// Who the booking is for
+#[derive(Debug, Clone)]
+pub enum Customer {
+    Existing(ObjectId),
+    /// Matched to an existing account by email, or given a new shell account
+    Email {
+        email: String,
+        first_name: Option<String>,
+        last_name: Option<String>,
+    },
+}
+
+/// The price the itinerary works out to for the party, against what was captured
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AmountCheck {
+    /// `None` when the itinerary has no price to compare with
+    pub expected: Option<Money>,
+    pub captured: Money,
+    /// Currency of the captured payment. Itineraries are priced in USD.
+    pub currency: String,
+}
+
+impl AmountCheck {
+    pub fn new(person_cost: Option<Money>, party: &Party, payment: &CapturedPayment) -> Self {
+        AmountCheck {
+            expected: person_cost.map(|cost| Money::from_cents(cost.cents() * party.size() as i64)),
+            captured: Money::from_cents(payment.amount),
+            currency: payment.currency.to_lowercase(),
+        }
+    }
+
+    fn is_usd(&self) -> bool {
+        self.currency == "usd"
+    }
+
+    pub fn matches(&self) -> bool {
+        self.is_usd() && self.expected == Some(self.captured)
+    }
+}
+
+pub struct AdminBookingRequest {
+    pub customer: Customer,
+    pub itinerary_id: ObjectId,
+    pub arrival_datetime: DateTime,
+    pub departure_datetime: DateTime,
+    pub party: Party,
+    /// Captured payment the booking is for; without one the booking is pay-later
+    pub payment: Option<CapturedPayment>,
+    pub override_amount_check: bool,
+    pub internal_note: Option<String>,
+    /// Already sanitized with `sanitize_special_requests`
+    pub special_requests: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminBookingOutcome {
+    pub booking: BookingDetails,
+    pub created_account: bool,
+    pub invitation_sent: bool,
+    /// Set when the booking was paid; `matches` is false only for an override
+    pub amount_check: Option<AmountCheck>,
+}
+
+#[derive(Debug)]
+pub enum AdminBookingError {
+    UserNotFound,
+    ItineraryNotFound,
+    /// The payment doesn't match the itinerary's price and no override was given
+    AmountMismatch(AmountCheck),
+    /// The payment is already attached to another booking
+    PaymentAlreadyUsed,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for AdminBookingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AdminBookingError::UserNotFound => write!(f, "User not found"),
+            AdminBookingError::ItineraryNotFound => write!(f, "Itinerary not found"),
+            AdminBookingError::AmountMismatch(check) if !check.is_usd() => write!(
+                f,
+                "Captured {} {} but itineraries are priced in usd; set override_amount_check to accept it",
+                check.captured, check.currency
+            ),
+            AdminBookingError::AmountMismatch(check) => match check.expected {
+                Some(expected) => write!(
+                    f,
+                    "Captured ${} but the itinerary costs ${} for this party; set override_amount_check to accept it",
+                    check.captured, expected
+                ),
+                None => write!(
+                    f,
+                    "The itinerary has no price to check the ${} payment against; set override_amount_check to accept it",
+                    check.captured
+                ),
+            },
+            AdminBookingError::PaymentAlreadyUsed => {
+                write!(f, "This payment is already attached to a booking")
+            }
+            AdminBookingError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AdminBookingError {}
+
+impl From<mongodb::error::Error> for AdminBookingError {
+    fn from(e: mongodb::error::Error) -> Self {
+        AdminBookingError::DatabaseError(e.to_string())
+    }
+}
+
+/// Tells a customer about an account support created for them
+pub trait InvitationSender {
+    fn send_invitation(
+        &self,
+        user: &User,
+        trip_name: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// `None` when email isn't configured, so no invitation goes out
+impl InvitationSender for Option<EmailService> {
+    fn send_invitation(
+        &self,
+        user: &User,
+        trip_name: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        let email = user.email.clone();
+        let first_name = user.first_name.clone();
+        let trip_name = trip_name.to_string();
+        async move {
+            let Some(service) = self else {
+                return Err("Email is not configured".to_string());
+            };
+            service
+                .send_account_invitation_email(&email, first_name.as_deref(), &trip_name)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// What happened, kept for support and finance
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManualBookingAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub booking_id: ObjectId,
+    pub user_id: ObjectId,
+    pub created_account: bool,
+    pub transaction_id: Option<String>,
+    pub expected_amount: Option<Money>,
+    pub captured_amount: Option<Money>,
+    pub captured_currency: Option<String>,
+    pub override_amount_check: bool,
+    pub internal_note: Option<String>,
+    pub created_at: DateTime,
+}
+
+pub struct AdminBookingService {
+    client: Arc<Client>,
+    transactional: bool,
+    email: EmailSettings,
+    sms: Option<TwilioSettings>,
+}
+
+impl AdminBookingService {
+    pub fn new(client: Arc<Client>) -> Self {
+        AdminBookingService {
+            client,
+            transactional: false,
+            email: EmailSettings::default(),
+            sms: None,
+        }
+    }
+
+    /// Where paid bookings' confirmations are sent from; see
+    /// `BookingConfirmationService::with_notifications`
+    pub fn with_notifications(mut self, email: &EmailSettings, sms: Option<&TwilioSettings>) -> Self {
+        self.email = email.clone();
+        self.sms = sms.cloned();
+        self
+    }
+
+    /// Confirm paid bookings in a transaction; see `BookingConfirmationService::with_transactions`
+    pub fn with_transactions(mut self, enabled: bool) -> Self {
+        self.transactional = enabled;
+        self
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    fn users(&self) -> Collection<User> {
+        self.client.database("Account").collection("Users")
+    }
+
+    fn audit_log(&self) -> Collection<ManualBookingAudit> {
+        self.client.database("Account").collection("AdminAuditLog")
+    }
+
+    /// One booking per payment. Pay-later bookings have no transaction_id and
+    /// aren't constrained.
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let one_per_payment = IndexModel::builder()
+            .keys(doc! { "transaction_id": 1 })
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { "transaction_id": { "$type": "string" } })
+                    .build(),
+            )
+            .build();
+        self.bookings().create_index(one_per_payment).await?;
+        Ok(())
+    }
+
+    pub async fn create(
+        &self,
+        admin_id: ObjectId,
+        request: AdminBookingRequest,
+        cache: &AvailabilityCache,
+        invitations: &impl InvitationSender,
+    ) -> Result<AdminBookingOutcome, AdminBookingError> {
+        let itinerary = self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": request.itinerary_id })
+            .await?
+            .ok_or(AdminBookingError::ItineraryNotFound)?;
+
+        let amount_check = match &request.payment {
+            Some(payment) => {
+                if self
+                    .bookings()
+                    .find_one(doc! { "transaction_id": &payment.payment_intent_id })
+                    .await?
+                    .is_some()
+                {
+                    return Err(AdminBookingError::PaymentAlreadyUsed);
+                }
+                let price = PricingService::person_price(&self.client, &itinerary).await?;
+                let check = AmountCheck::new(price.amount(), &request.party, payment);
+                if !check.matches() && !request.override_amount_check {
+                    return Err(AdminBookingError::AmountMismatch(check));
+                }
+                Some(check)
+            }
+            None => None,
+        };
+
+        let (user, created_account) = self.find_or_create_customer(&request.customer).await?;
+        let user_id = user.id.ok_or(AdminBookingError::UserNotFound)?;
+
+        let now = DateTime::now();
+        let mut booking = BookingDetails {
+            id: None,
+            user_id,
+            itinerary_id: request.itinerary_id,
+            customer_id: user.customer_id.clone(),
+            transaction_id: request.payment.as_ref().map(|p| p.payment_intent_id.clone()),
+            status: if request.payment.is_some() {
+                PaymentStatus::Pending
+            } else {
+                PaymentStatus::Ongoing
+            },
+            arrival_datetime: request.arrival_datetime,
+            departure_datetime: request.departure_datetime,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: request.special_requests,
+            party: Some(request.party),
+            created_by_admin: true,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+        // The unique index on transaction_id settles two admins racing on one payment
+        let inserted = match self.bookings().insert_one(&booking).await {
+            Ok(inserted) => inserted,
+            Err(e) if is_duplicate_key(&e) => return Err(AdminBookingError::PaymentAlreadyUsed),
+            Err(e) => return Err(e.into()),
+        };
+        booking.id = inserted.inserted_id.as_object_id();
+        let booking_id = inserted.inserted_id.as_object_id().unwrap_or_default();
+
+        // No manual booking goes ahead unaudited
+        let audit = ManualBookingAudit {
+            id: None,
+            action: "manual_booking".to_string(),
+            admin_id,
+            booking_id,
+            user_id,
+            created_account,
+            transaction_id: booking.transaction_id.clone(),
+            expected_amount: amount_check.as_ref().and_then(|check| check.expected),
+            captured_amount: amount_check.as_ref().map(|check| check.captured),
+            captured_currency: amount_check.as_ref().map(|check| check.currency.clone()),
+            override_amount_check: request.override_amount_check,
+            internal_note: request.internal_note,
+            created_at: now,
+        };
+        if let Err(e) = self.audit_log().insert_one(&audit).await {
+            eprintln!("Failed to write audit log for booking {}, removing it: {}", booking_id, e);
+            if let Err(e) = self.bookings().delete_one(doc! { "_id": booking_id }).await {
+                eprintln!("Failed to remove unaudited booking {}: {}", booking_id, e);
+            }
+            return Err(e.into());
+        }
+        println!("📞 Booking {} created by admin {} for user {}", booking_id, admin_id, user_id);
+
+        // Before the confirmation email, so the customer hears about the account first
+        let invitation_sent = if created_account {
+            match invitations.send_invitation(&user, &itinerary.trip_name).await {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Failed to send account invitation to user {}: {}", user_id, e);
+                    false
+                }
+            }
+        } else {
+            false
+        };
+
+        let confirmation =
+            BookingConfirmationService::new(self.client.clone())
+                .with_transactions(self.transactional)
+                .with_notifications(&self.email, self.sms.as_ref());
+        match &request.payment {
+            Some(payment) => {
+                confirmation.confirm(cache, &booking, payment).await?;
+                booking.status = PaymentStatus::Confirmed;
+            }
+            None => confirmation.reserve_inventory(cache, &booking).await,
+        }
+
+        Ok(AdminBookingOutcome {
+            booking,
+            created_account,
+            invitation_sent,
+            amount_check,
+        })
+    }
+
+    /// The customer's account and whether it was created just now
+    async fn find_or_create_customer(
+        &self,
+        customer: &Customer,
+    ) -> Result<(User, bool), AdminBookingError> {
+        let (email, first_name, last_name) = match customer {
+            Customer::Existing(user_id) => {
+                return match self.users().find_one(doc! { "_id": user_id }).await? {
+                    Some(user) => Ok((user, false)),
+                    None => Err(AdminBookingError::UserNotFound),
+                };
+            }
+            Customer::Email {
+                email,
+                first_name,
+                last_name,
+            } => (email, first_name, last_name),
+        };
+
+        if let Some(user) = self.users().find_one(doc! { "email": email }).await? {
+            return Ok((user, false));
+        }
+
+        // Nobody knows this password; the invitation asks the customer to set one
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let password = bcrypt::hash(URL_SAFE_NO_PAD.encode(secret), bcrypt::DEFAULT_COST)
+            .map_err(|e| AdminBookingError::DatabaseError(e.to_string()))?;
+
+        let now = Utc::now();
+        let mut user = User {
+            id: None,
+            email: email.clone(),
+            password,
+            customer_id: None,
+            first_name: first_name.clone(),
+            last_name: last_name.clone(),
+            phone_number: None,
+            phone_number_e164: None,
+            birth_date: None,
+            profile_picture: None,
+            last_signin: None,
+            last_signin_ip: None,
+            failed_signins: None,
+            role: Some(UserRole::User),
+            company_id: None,
+            notification: None,
+            notification_preferences: None,
+            preferred_currency: None,
+            preferred_units: None,
+            email_verified: false,
+            email_verified_at: None,
+            email_suppressed: false,
+            linked_accounts: Vec::new(),
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+        let inserted = self.users().insert_one(&user).await?;
+        user.id = inserted.inserted_id.as_object_id();
+        println!("👤 Shell account created for {}", email);
+        Ok((user, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn party(adults: u32, children: u32) -> Party {
+        Party {
+            adults,
+            children,
+            infants: 0,
+        }
+    }
+
+    fn payment(amount: i64, currency: &str) -> CapturedPayment {
+        CapturedPayment {
+            payment_intent_id: "pi_123".to_string(),
+            amount,
+            currency: currency.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_amount_check() {
+        let per_person = Some(Money::from_dollars(450.0));
+        assert!(AmountCheck::new(per_person, &party(2, 1), &payment(135_000, "usd")).matches());
+        assert!(AmountCheck::new(per_person, &party(2, 1), &payment(135_000, "USD")).matches());
+
+        let short = AmountCheck::new(per_person, &party(2, 1), &payment(90_000, "usd"));
+        assert!(!short.matches());
+        assert_eq!(short.expected, Some(Money::from_cents(135_000)));
+
+        // The right number in another currency isn't the right amount
+        let euros = AmountCheck::new(per_person, &party(2, 1), &payment(135_000, "eur"));
+        assert!(!euros.matches());
+        assert!(AdminBookingError::AmountMismatch(euros).to_string().contains("eur"));
+
+        // Nothing to compare with is a mismatch too
+        assert!(!AmountCheck::new(None, &party(2, 0), &payment(90_000, "usd")).matches());
+    }
+}
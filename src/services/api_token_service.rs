@@ -0,0 +1,236 @@
+//! Personal access tokens for scripted, read-only access to a user's data
+//!
+//! Tokens look like `actota_pat_<43 url-safe characters>` (32 random bytes). The
+//! secret is shown once at creation and stored only as a SHA-256 hash, so a
+//! lookup hashes the presented token and matches on that.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::models::api_token::{ApiToken, TokenScope, API_TOKEN_PREFIX};
+
+/// Characters of the secret kept (after the `actota_pat_` prefix) for display
+const DISPLAY_PREFIX_CHARS: usize = 6;
+
+/// Hex-encoded SHA-256 of a presented token
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// A new token for `user_id`, returned with the secret the owner sees once.
+/// Only the returned `ApiToken` is stored.
+pub fn new_api_token(
+    user_id: ObjectId,
+    name: &str,
+    scopes: Vec<TokenScope>,
+    expires_at: Option<DateTime>,
+    now: DateTime,
+) -> (String, ApiToken) {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let token = format!("{}{}", API_TOKEN_PREFIX, URL_SAFE_NO_PAD.encode(secret));
+
+    let api_token = ApiToken {
+        id: ObjectId::new(),
+        user_id,
+        name: name.to_string(),
+        prefix: token[..API_TOKEN_PREFIX.len() + DISPLAY_PREFIX_CHARS].to_string(),
+        token_hash: hash_token(&token),
+        scopes,
+        expires_at,
+        last_used_at: None,
+        revoked_at: None,
+        created_at: now,
+    };
+    (token, api_token)
+}
+
+pub struct ApiTokenService {
+    client: Arc<Client>,
+}
+
+impl ApiTokenService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ApiTokenService { client }
+    }
+
+    fn collection(&self) -> Collection<ApiToken> {
+        self.client.database("Account").collection("ApiTokens")
+    }
+
+    /// Every authenticated request looks its token up by hash, and listing is per
+    /// user, newest first
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let by_hash = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let per_user = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "created_at": -1 })
+            .build();
+        self.collection().create_indexes([by_hash, per_user]).await?;
+        Ok(())
+    }
+
+    pub async fn insert(&self, token: &ApiToken) -> Result<(), mongodb::error::Error> {
+        self.collection().insert_one(token).await?;
+        Ok(())
+    }
+
+    /// Tokens the user hasn't revoked, newest first
+    pub async fn list(&self, user_id: ObjectId) -> Result<Vec<ApiToken>, mongodb::error::Error> {
+        self.collection()
+            .find(doc! { "user_id": user_id, "revoked_at": null })
+            .sort(doc! { "created_at": -1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    pub async fn count_active(&self, user_id: ObjectId) -> Result<u64, mongodb::error::Error> {
+        self.collection()
+            .count_documents(doc! { "user_id": user_id, "revoked_at": null })
+            .await
+    }
+
+    /// Revoke one of the user's tokens. Returns false if there was no such active token.
+    pub async fn revoke(&self, user_id: ObjectId, token_id: ObjectId) -> Result<bool, mongodb::error::Error> {
+        let result = self
+            .collection()
+            .update_one(
+                doc! { "_id": token_id, "user_id": user_id, "revoked_at": null },
+                doc! { "$set": { "revoked_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// The usable token matching `token`, if any. Looked up on every request so a
+    /// revocation takes effect immediately.
+    pub async fn authenticate(&self, token: &str) -> Result<Option<ApiToken>, mongodb::error::Error> {
+        let now = DateTime::now();
+        let Some(api_token) = self
+            .collection()
+            .find_one(doc! { "token_hash": hash_token(token), "revoked_at": null })
+            .await?
+        else {
+            return Ok(None);
+        };
+        if !api_token.is_usable(now) {
+            return Ok(None);
+        }
+
+        if let Err(e) = self
+            .collection()
+            .update_one(doc! { "_id": api_token.id }, doc! { "$set": { "last_used_at": now } })
+            .await
+        {
+            eprintln!("Failed to record API token use: {}", e);
+        }
+        Ok(Some(api_token))
+    }
+}
+
+/// Fixed one-minute request windows per token, kept apart from any limits on
+/// browser sessions
+pub struct ApiTokenRateLimiter {
+    per_minute: u32,
+    windows: Mutex<HashMap<ObjectId, (Instant, u32)>>,
+}
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+impl ApiTokenRateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        ApiTokenRateLimiter {
+            per_minute,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a request made with `token_id`. Over the limit, returns the seconds
+    /// until the window resets.
+    pub fn check(&self, token_id: ObjectId, now: Instant) -> Result<(), u64> {
+        let Ok(mut windows) = self.windows.lock() else {
+            return Ok(());
+        };
+        // Forget windows that have ended so idle tokens don't accumulate
+        windows.retain(|_, (started, _)| now.duration_since(*started) < RATE_WINDOW);
+
+        let (started, count) = windows.entry(token_id).or_insert((now, 0));
+        if *count >= self.per_minute {
+            let reset = RATE_WINDOW.saturating_sub(now.duration_since(*started));
+            return Err(reset.as_secs().max(1));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_hash_is_stored() {
+        let now = DateTime::now();
+        let (token, api_token) =
+            new_api_token(ObjectId::new(), "nightly export", vec![TokenScope::BookingsRead], None, now);
+
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+        assert!(token.len() >= API_TOKEN_PREFIX.len() + 43);
+        assert!(token.starts_with(&api_token.prefix));
+        assert_eq!(api_token.token_hash, hash_token(&token));
+        assert_ne!(hash_token(&token), hash_token(&format!("{}x", token)));
+
+        let stored = mongodb::bson::to_document(&api_token).unwrap();
+        let secret = &token[API_TOKEN_PREFIX.len()..];
+        for (_, value) in stored.iter() {
+            assert!(!value.to_string().contains(secret));
+        }
+
+        let (other, _) = new_api_token(ObjectId::new(), "other", vec![], None, now);
+        assert_ne!(token, other);
+    }
+
+    #[test]
+    fn test_revoked_and_expired_tokens_stop_working() {
+        let now = DateTime::from_millis(1_760_000_000_000);
+        let (_, mut api_token) =
+            new_api_token(ObjectId::new(), "script", vec![TokenScope::SearchRead], None, now);
+        assert!(api_token.is_usable(now));
+
+        api_token.expires_at = Some(DateTime::from_millis(now.timestamp_millis() + 1));
+        assert!(api_token.is_usable(now));
+        api_token.expires_at = Some(now);
+        assert!(!api_token.is_usable(now));
+
+        api_token.expires_at = None;
+        api_token.revoked_at = Some(now);
+        assert!(!api_token.is_usable(now));
+    }
+
+    #[test]
+    fn test_rate_limit_is_per_token_and_resets() {
+        let limiter = ApiTokenRateLimiter::new(2);
+        let (first, second) = (ObjectId::new(), ObjectId::new());
+        let start = Instant::now();
+
+        assert!(limiter.check(first, start).is_ok());
+        assert!(limiter.check(first, start).is_ok());
+        assert_eq!(limiter.check(first, start + Duration::from_secs(20)), Err(40));
+        assert!(limiter.check(second, start).is_ok());
+
+        assert!(limiter.check(first, start + RATE_WINDOW).is_ok());
+    }
+}
@@ -0,0 +1,566 @@
+//! Month view of bookable start dates for an itinerary
+//!
+//! A start date is bookable when every scheduled activity runs on the date it falls
+//! on (see `services::calendar`) and has seats left in the `Options.ActivityBookings`
+//! inventory. Activities without inventory records are assumed to have room.
+//! Seats held for a checkout in progress (see `reservation_service`) count as taken
+//! until the hold expires.
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::models::activity::Activity;
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::models::itinerary::base::{DayItem, FeaturedVacation};
+use crate::services::calendar;
+use crate::services::unit_of_work::UnitOfWork;
+
+/// How far ahead availability can be requested
+pub const MAX_MONTHS_AHEAD: u32 = 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DayStatus {
+    Available,
+    Limited,
+    SoldOut,
+    NotOperating,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayAvailability {
+    pub date: NaiveDate,
+    pub status: DayStatus,
+    /// Fewest seats left across the trip's activities, when inventory is tracked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<u32>,
+}
+
+#[derive(Debug)]
+pub enum AvailabilityError {
+    InvalidMonth,
+    TooFarAhead,
+    NotFound,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for AvailabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AvailabilityError::InvalidMonth => write!(f, "month must be formatted as YYYY-MM"),
+            AvailabilityError::TooFarAhead => write!(
+                f,
+                "Availability is only published {} months ahead",
+                MAX_MONTHS_AHEAD
+            ),
+            AvailabilityError::NotFound => write!(f, "Itinerary not found"),
+            AvailabilityError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AvailabilityError {}
+
+/// Seats already booked for an activity on a date, stored in `Options.ActivityBookings`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityBookingCount {
+    pub activity_id: ObjectId,
+    pub date: NaiveDate,
+    pub booked: u32,
+    /// Seats held by reservations, including lapsed ones not yet cleared out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holds: Vec<SeatHold>,
+}
+
+/// Seats a checkout in progress is holding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeatHold {
+    pub reservation_id: ObjectId,
+    pub seats: u32,
+    pub expires_at: DateTime,
+}
+
+/// Seats held by holds that haven't expired at `now`
+pub fn held_seats(holds: &[SeatHold], now: DateTime) -> u32 {
+    holds
+        .iter()
+        .filter(|hold| hold.expires_at > now)
+        .map(|hold| hold.seats)
+        .sum()
+}
+
+pub type Inventory = HashMap<(ObjectId, NaiveDate), u32>;
+
+/// Parse `YYYY-MM` into the first day of that month, rejecting months too far ahead
+pub fn parse_month(month: &str, today: NaiveDate) -> Result<NaiveDate, AvailabilityError> {
+    let first = NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d")
+        .map_err(|_| AvailabilityError::InvalidMonth)?;
+
+    let this_month = today.with_day(1).unwrap_or(today);
+    match this_month.checked_add_months(Months::new(MAX_MONTHS_AHEAD)) {
+        Some(limit) if first <= limit => Ok(first),
+        _ => Err(AvailabilityError::TooFarAhead),
+    }
+}
+
+fn month_dates(month_start: NaiveDate) -> Vec<NaiveDate> {
+    month_start
+        .iter_days()
+        .take_while(|date| date.month() == month_start.month())
+        .collect()
+}
+
+/// Each scheduled activity with its offset in days from the trip start
+fn scheduled_activities(itinerary: &FeaturedVacation) -> Vec<(i64, ObjectId)> {
+    itinerary
+        .days
+        .days
+        .iter()
+        .filter_map(|(day, items)| day.parse::<i64>().ok().map(|day| (day - 1, items)))
+        .flat_map(|(offset, items)| {
+            items.iter().filter_map(move |item| match item {
+                DayItem::Activity { activity_id, .. } => Some((offset, *activity_id)),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+pub fn scheduled_activity_ids(itinerary: &FeaturedVacation) -> Vec<ObjectId> {
+    let ids: HashSet<ObjectId> = scheduled_activities(itinerary)
+        .into_iter()
+        .map(|(_, id)| id)
+        .collect();
+    ids.into_iter().collect()
+}
+
+/// The (activity, date) pairs a trip starting on `start` occupies
+pub fn activity_dates(itinerary: &FeaturedVacation, start: NaiveDate) -> Vec<(ObjectId, NaiveDate)> {
+    scheduled_activities(itinerary)
+        .into_iter()
+        .map(|(offset, id)| (id, start + Duration::days(offset)))
+        .collect()
+}
+
+fn day_status(
+    itinerary: &FeaturedVacation,
+    start: NaiveDate,
+    activities: &HashMap<ObjectId, Activity>,
+    inventory: &Inventory,
+    limited_threshold: u32,
+) -> DayAvailability {
+    let not_operating = DayAvailability {
+        date: start,
+        status: DayStatus::NotOperating,
+        remaining: None,
+    };
+
+    let mut remaining: Option<u32> = None;
+    for (activity_id, date) in activity_dates(itinerary, start) {
+        let Some(activity) = activities.get(&activity_id) else {
+            continue;
+        };
+        if calendar::closure_reason(activity, date).is_some() {
+            return not_operating;
+        }
+        if let Some(booked) = inventory.get(&(activity_id, date)) {
+            let left = (activity.capacity.maximum as u32).saturating_sub(*booked);
+            remaining = Some(remaining.map_or(left, |r| r.min(left)));
+        }
+    }
+
+    let party = itinerary.party_size().unwrap_or(itinerary.min_group).max(1);
+    let status = match remaining {
+        Some(left) if left < party => DayStatus::SoldOut,
+        Some(left) if left <= limited_threshold => DayStatus::Limited,
+        _ => DayStatus::Available,
+    };
+
+    DayAvailability {
+        date: start,
+        status,
+        remaining,
+    }
+}
+
+/// Availability for every start date in the month beginning at `month_start`.
+///
+/// Past dates, and dates other than the fixed start of a fixed-date itinerary, are
+/// reported as not operating.
+pub fn compute_month(
+    itinerary: &FeaturedVacation,
+    activities: &HashMap<ObjectId, Activity>,
+    inventory: &Inventory,
+    month_start: NaiveDate,
+    today: NaiveDate,
+    limited_threshold: u32,
+) -> Vec<DayAvailability> {
+    let fixed_start = itinerary.arrival_datetime.and_then(calendar::utc_date);
+
+    month_dates(month_start)
+        .into_iter()
+        .map(|date| {
+            if date < today || fixed_start.is_some_and(|fixed| fixed != date) {
+                DayAvailability {
+                    date,
+                    status: DayStatus::NotOperating,
+                    remaining: None,
+                }
+            } else {
+                day_status(itinerary, date, activities, inventory, limited_threshold)
+            }
+        })
+        .collect()
+}
+
+struct CachedMonth {
+    activity_ids: Vec<ObjectId>,
+    days: Vec<DayAvailability>,
+}
+
+/// Computed months per itinerary. Entries are dropped when a confirmed booking
+/// changes the inventory of any activity they depend on.
+#[derive(Default)]
+pub struct AvailabilityCache {
+    months: Mutex<HashMap<(ObjectId, NaiveDate), CachedMonth>>,
+}
+
+impl AvailabilityCache {
+    pub fn get(&self, itinerary_id: ObjectId, month_start: NaiveDate) -> Option<Vec<DayAvailability>> {
+        let months = self.months.lock().ok()?;
+        months
+            .get(&(itinerary_id, month_start))
+            .map(|cached| cached.days.clone())
+    }
+
+    pub fn insert(
+        &self,
+        itinerary_id: ObjectId,
+        month_start: NaiveDate,
+        activity_ids: Vec<ObjectId>,
+        days: Vec<DayAvailability>,
+    ) {
+        if let Ok(mut months) = self.months.lock() {
+            months.insert((itinerary_id, month_start), CachedMonth { activity_ids, days });
+        }
+    }
+
+    /// Drop every cached month for itineraries that schedule any of `activity_ids`
+    pub fn invalidate_activities(&self, activity_ids: &[ObjectId]) {
+        if let Ok(mut months) = self.months.lock() {
+            months.retain(|_, cached| !cached.activity_ids.iter().any(|id| activity_ids.contains(id)));
+        }
+    }
+}
+
+pub struct AvailabilityService {
+    client: Arc<Client>,
+}
+
+impl AvailabilityService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    fn inventory(&self) -> Collection<ActivityBookingCount> {
+        self.client.database("Options").collection("ActivityBookings")
+    }
+
+    /// Seats taken per activity and date, and whether any of them are only held
+    pub async fn load_inventory(
+        &self,
+        activity_ids: &[ObjectId],
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<(Inventory, bool), mongodb::error::Error> {
+        let filter = doc! {
+            "activity_id": { "$in": activity_ids },
+            "date": { "$gte": from.to_string(), "$lte": to.to_string() },
+        };
+        let now = DateTime::now();
+        let counts: Vec<ActivityBookingCount> = self.inventory().find(filter).await?.try_collect().await?;
+        let held = counts.iter().any(|count| held_seats(&count.holds, now) > 0);
+        let inventory = counts
+            .into_iter()
+            .map(|count| {
+                let taken = count.booked + held_seats(&count.holds, now);
+                ((count.activity_id, count.date), taken)
+            })
+            .collect();
+        Ok((inventory, held))
+    }
+
+    pub async fn month_availability(
+        &self,
+        cache: &AvailabilityCache,
+        itinerary_id: ObjectId,
+        month_start: NaiveDate,
+        today: NaiveDate,
+        limited_threshold: u32,
+    ) -> Result<Vec<DayAvailability>, AvailabilityError> {
+        if let Some(days) = cache.get(itinerary_id, month_start) {
+            return Ok(days);
+        }
+
+        let db_error = |e: mongodb::error::Error| AvailabilityError::DatabaseError(e.to_string());
+        let itinerary = self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": itinerary_id })
+            .await
+            .map_err(db_error)?
+            .ok_or(AvailabilityError::NotFound)?;
+
+        let activity_ids = scheduled_activity_ids(&itinerary);
+        let activities: HashMap<ObjectId, Activity> = self
+            .client
+            .database("Options")
+            .collection::<Activity>("Activity")
+            .find(doc! { "_id": { "$in": &activity_ids } })
+            .await
+            .map_err(db_error)?
+            .try_collect::<Vec<Activity>>()
+            .await
+            .map_err(db_error)?
+            .into_iter()
+            .filter_map(|activity| activity.id.map(|id| (id, activity)))
+            .collect();
+
+        // Trips starting late in the month spill into the next one
+        let trip_days = itinerary.days.days.len().max(itinerary.length_days as usize) as i64;
+        let last_date = month_dates(month_start).last().copied().unwrap_or(month_start)
+            + Duration::days(trip_days);
+        let (inventory, held) = self
+            .load_inventory(&activity_ids, month_start, last_date)
+            .await
+            .map_err(db_error)?;
+
+        let days = compute_month(
+            &itinerary,
+            &activities,
+            &inventory,
+            month_start,
+            today,
+            limited_threshold,
+        );
+        // Holds lapse on their own, without a booking to invalidate the cache
+        if !held {
+            cache.insert(itinerary_id, month_start, activity_ids, days.clone());
+        }
+        Ok(days)
+    }
+
+    /// Take seats for a confirmed booking and drop cached months that depended on them
+    pub async fn record_confirmed_booking(
+        &self,
+        cache: &AvailabilityCache,
+        itinerary: &FeaturedVacation,
+        start: NaiveDate,
+    ) -> Result<(), mongodb::error::Error> {
+        let activity_ids = self
+            .record_confirmed_booking_in(&mut UnitOfWork::sequential(), itinerary, start)
+            .await?;
+        cache.invalidate_activities(&activity_ids);
+        Ok(())
+    }
+
+    /// `record_confirmed_booking` as part of a larger unit of work. Returns the
+    /// activities whose cached months the caller drops once the work commits.
+    pub async fn record_confirmed_booking_in(
+        &self,
+        uow: &mut UnitOfWork,
+        itinerary: &FeaturedVacation,
+        start: NaiveDate,
+    ) -> Result<Vec<ObjectId>, mongodb::error::Error> {
+        let seats = itinerary.party_size().unwrap_or(itinerary.min_group).max(1);
+        let occupied = activity_dates(itinerary, start);
+
+        let inventory = self.inventory();
+        for (activity_id, date) in &occupied {
+            uow.upsert_one(
+                &inventory,
+                doc! { "activity_id": activity_id, "date": date.to_string() },
+                doc! { "$inc": { "booked": seats } },
+            )
+            .await?;
+        }
+
+        Ok(occupied.into_iter().map(|(id, _)| id).collect())
+    }
+
+    /// Give back the seats `record_confirmed_booking` took for a trip starting on `start`
+    pub async fn release_booking(
+        &self,
+        cache: &AvailabilityCache,
+        itinerary: &FeaturedVacation,
+        start: NaiveDate,
+    ) -> Result<(), mongodb::error::Error> {
+        let seats = itinerary.party_size().unwrap_or(itinerary.min_group).max(1);
+        let occupied = activity_dates(itinerary, start);
+
+        for (activity_id, date) in &occupied {
+            self.inventory()
+                .update_one(
+                    doc! { "activity_id": activity_id, "date": date.to_string(), "booked": { "$gte": seats } },
+                    doc! { "$inc": { "booked": -(seats as i64) } },
+                )
+                .await?;
+        }
+
+        let activity_ids: Vec<ObjectId> = occupied.into_iter().map(|(id, _)| id).collect();
+        cache.invalidate_activities(&activity_ids);
+        Ok(())
+    }
+
+    /// Give back the seats of a booking that is being cancelled or refunded, as
+    /// it was before. Only confirmed bookings hold seats. Failures are logged,
+    /// since the booking is cancelled either way.
+    pub async fn release_cancelled_booking(&self, cache: &AvailabilityCache, booking: &BookingDetails) {
+        if booking.status != PaymentStatus::Confirmed {
+            return;
+        }
+        let Some(start) = calendar::utc_date(booking.arrival_datetime) else {
+            return;
+        };
+        let itinerary = match self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": booking.itinerary_id })
+            .await
+        {
+            Ok(Some(itinerary)) => itinerary,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Failed to load itinerary to release booking {:?}: {}", booking.id, e);
+                return;
+            }
+        };
+        if let Err(e) = self.release_booking(cache, &itinerary, start).await {
+            eprintln!("Failed to release seats for booking {:?}: {}", booking.id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::itinerary::base::Days;
+
+    fn activity(maximum: u16) -> Activity {
+        let mut activity = Activity {
+            description: "".to_string(),
+            price_per_person: 80.0,
+            duration_minutes: 120,
+            ..Activity::test("Rafting")
+        };
+        activity.address.city = "Buena Vista".to_string();
+        activity.capacity.maximum = maximum;
+        activity
+    }
+
+    fn itinerary_with(activity_ids: &[ObjectId], adults: u32) -> FeaturedVacation {
+        let days = activity_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                (
+                    (i + 1).to_string(),
+                    vec![DayItem::Activity {
+                        time: "09:00:00".to_string(),
+                        activity_id: *id,
+                    }],
+                )
+            })
+            .collect();
+        FeaturedVacation {
+            id: Some(ObjectId::new()),
+            days: Days { days },
+            adults: Some(adults),
+            ..Default::default()
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 7, day).unwrap()
+    }
+
+    fn status_on(days: &[DayAvailability], day: u32) -> DayStatus {
+        days.iter().find(|d| d.date == date(day)).unwrap().status
+    }
+
+    #[test]
+    fn test_blackout_week_is_not_operating() {
+        let mut rafting = activity(12);
+        let start = date(14).and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+        let end = date(20).and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp_millis();
+        rafting.blackout_date_ranges =
+            Some(serde_json::from_value(serde_json::json!([{ "start": start, "end": end }])).unwrap());
+        let itinerary = itinerary_with(&[rafting.id.unwrap()], 2);
+        let activities = HashMap::from([(rafting.id.unwrap(), rafting)]);
+
+        let days = compute_month(&itinerary, &activities, &Inventory::new(), date(1), date(1), 4);
+
+        assert_eq!(days.len(), 31);
+        for day in 14..=20 {
+            assert_eq!(status_on(&days, day), DayStatus::NotOperating);
+        }
+        assert_eq!(status_on(&days, 13), DayStatus::Available);
+        assert_eq!(status_on(&days, 21), DayStatus::Available);
+    }
+
+    #[test]
+    fn test_nearly_full_date_is_limited_at_threshold() {
+        let rafting = activity(12);
+        let id = rafting.id.unwrap();
+        let itinerary = itinerary_with(&[id], 2);
+        let activities = HashMap::from([(id, rafting)]);
+        let inventory = Inventory::from([((id, date(10)), 8), ((id, date(11)), 7)]);
+
+        let days = compute_month(&itinerary, &activities, &inventory, date(1), date(1), 4);
+
+        // 4 seats left is at the threshold, 5 is above it
+        assert_eq!(status_on(&days, 10), DayStatus::Limited);
+        assert_eq!(status_on(&days, 11), DayStatus::Available);
+        assert_eq!(days.iter().find(|d| d.date == date(10)).unwrap().remaining, Some(4));
+    }
+
+    #[test]
+    fn test_confirmed_booking_invalidates_cache_and_sells_out() {
+        let rafting = activity(12);
+        let id = rafting.id.unwrap();
+        let itinerary = itinerary_with(&[id], 2);
+        let itinerary_id = itinerary.id.unwrap();
+        let activities = HashMap::from([(id, rafting)]);
+        let mut inventory = Inventory::from([((id, date(10)), 9)]);
+        let cache = AvailabilityCache::default();
+
+        let before = compute_month(&itinerary, &activities, &inventory, date(1), date(1), 4);
+        assert_eq!(status_on(&before, 10), DayStatus::Limited);
+        cache.insert(itinerary_id, date(1), scheduled_activity_ids(&itinerary), before);
+
+        // Another party of two confirms on the 10th, leaving a single seat
+        *inventory.get_mut(&(id, date(10))).unwrap() += 2;
+        cache.invalidate_activities(&[id]);
+        assert!(cache.get(itinerary_id, date(1)).is_none());
+
+        let after = compute_month(&itinerary, &activities, &inventory, date(1), date(1), 4);
+        assert_eq!(status_on(&after, 10), DayStatus::SoldOut);
+    }
+
+    #[test]
+    fn test_months_beyond_limit_rejected() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 15).unwrap();
+        assert_eq!(parse_month("2026-09", today).unwrap(), NaiveDate::from_ymd_opt(2026, 9, 1).unwrap());
+        assert!(matches!(parse_month("2026-10", today), Err(AvailabilityError::TooFarAhead)));
+        assert!(matches!(parse_month("2025-13", today), Err(AvailabilityError::InvalidMonth)));
+    }
+}
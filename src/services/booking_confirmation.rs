@@ -0,0 +1,677 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::{
+    account::User,
+    bookings::{BookingDetails, PaymentStatus},
+    itinerary::base::FeaturedVacation,
+    money::Money,
+};
+use crate::services::{
+    account_service::{EmailService, EmailSettings},
+    availability_service::{AvailabilityCache, AvailabilityService},
+    calendar,
+    gift_card_service::GiftCardService,
+    notification_service::{NotificationService, TwilioSettings},
+    pricing_service::PricingService,
+    reservation_service::ReservationService,
+    unit_of_work::{self, UnitOfWork},
+};
+
+/// A captured card payment, reported either by the inline capture in
+/// `add_booking_with_payment` or by a Stripe webhook
+#[derive(Debug, Clone)]
+pub struct CapturedPayment {
+    pub payment_intent_id: String,
+    /// Amount captured, in cents
+    pub amount: i64,
+    pub currency: String,
+}
+
+/// Sends booking confirmation emails. Implemented for the real email service; tests
+/// substitute their own to see what would have been sent.
+pub trait ConfirmationSender {
+    fn send_confirmation(
+        &self,
+        user_email: &str,
+        user_name: &str,
+        booking: &BookingDetails,
+        trip_name: &str,
+        payment: &CapturedPayment,
+    ) -> impl Future<Output = Result<(), String>>;
+}
+
+/// `None` when email isn't configured
+impl ConfirmationSender for Option<EmailService> {
+    async fn send_confirmation(
+        &self,
+        user_email: &str,
+        user_name: &str,
+        booking: &BookingDetails,
+        trip_name: &str,
+        payment: &CapturedPayment,
+    ) -> Result<(), String> {
+        let Some(service) = self else {
+            return Err("Email is not configured".to_string());
+        };
+        service
+            .send_booking_confirmation_email(
+                user_email,
+                user_name,
+                booking,
+                trip_name,
+                Money::from_cents(payment.amount).to_dollars(),
+                &payment.currency,
+                &payment.payment_intent_id,
+            )
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfirmationOutcome {
+    Confirmed(Box<BookingDetails>),
+    /// The booking was already confirmed (or cancelled); nothing was done
+    AlreadyProcessed,
+}
+
+/// Statuses a booking can still be confirmed from
+fn confirmable_statuses() -> Vec<mongodb::bson::Bson> {
+    [PaymentStatus::Pending, PaymentStatus::PendingPayment]
+        .iter()
+        .map(|status| mongodb::bson::to_bson(status).unwrap())
+        .collect()
+}
+
+/// The booking as it looks once `payment` confirms it, or `None` if it is no longer
+/// pending. Both confirmation paths go through this so they store the same document.
+pub fn confirmed_booking(
+    booking: &BookingDetails,
+    payment: &CapturedPayment,
+    now: DateTime,
+) -> Option<BookingDetails> {
+    if !matches!(
+        booking.status,
+        PaymentStatus::Pending | PaymentStatus::PendingPayment
+    ) {
+        return None;
+    }
+
+    let mut confirmed = booking.clone();
+    confirmed.status = PaymentStatus::Confirmed;
+    confirmed.transaction_id = Some(payment.payment_intent_id.clone());
+    confirmed.updated_at = Some(now);
+    Some(confirmed)
+}
+
+/// A payment that was captured but whose booking couldn't be confirmed. Someone
+/// has to finish the booking or refund the payment by hand; capturing again would
+/// charge the traveler twice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentReconciliation {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub booking_id: ObjectId,
+    pub payment_intent_id: String,
+    /// Cents captured
+    pub amount: i64,
+    pub currency: String,
+    pub error: String,
+    pub created_at: DateTime,
+    /// Set by the admin who settled the payment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<ObjectId>,
+}
+
+pub struct BookingConfirmationService {
+    client: Arc<Client>,
+    transactional: bool,
+    email: EmailSettings,
+    sms: Option<TwilioSettings>,
+}
+
+impl BookingConfirmationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        BookingConfirmationService {
+            client,
+            transactional: false,
+            email: EmailSettings::default(),
+            sms: None,
+        }
+    }
+
+    /// Where confirmations are emailed and texted from. Without it no email or
+    /// text is sent.
+    pub fn with_notifications(mut self, email: &EmailSettings, sms: Option<&TwilioSettings>) -> Self {
+        self.email = email.clone();
+        self.sms = sms.cloned();
+        self
+    }
+
+    /// Confirm bookings in a multi-document transaction (`MONGODB_TRANSACTIONS`),
+    /// so the booking and the seats it takes are updated together or not at all
+    pub fn with_transactions(mut self, enabled: bool) -> Self {
+        self.transactional = enabled;
+        self
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    fn reconciliation(&self) -> Collection<PaymentReconciliation> {
+        primary_collection(&self.client, "Account", "PaymentReconciliation")
+    }
+
+    /// Find the booking a payment intent paid for. Falls back to the `user_id` and
+    /// `itinerary_id` metadata set when the intent was created, for bookings whose
+    /// transaction id hasn't been stored yet.
+    pub async fn find_for_payment_intent(
+        &self,
+        payment_intent_id: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Option<BookingDetails>, mongodb::error::Error> {
+        if let Some(booking) = self
+            .bookings()
+            .find_one(doc! { "transaction_id": payment_intent_id })
+            .await?
+        {
+            return Ok(Some(booking));
+        }
+
+        let (Some(user_id), Some(itinerary_id)) = (
+            metadata.get("user_id").and_then(|id| ObjectId::parse_str(id).ok()),
+            metadata.get("itinerary_id").and_then(|id| ObjectId::parse_str(id).ok()),
+        ) else {
+            return Ok(None);
+        };
+
+        let mut candidates: Vec<BookingDetails> = self
+            .bookings()
+            .find(doc! {
+                "user_id": user_id,
+                "itinerary_id": itinerary_id,
+                "status": { "$in": confirmable_statuses() },
+                "transaction_id": null,
+            })
+            .sort(doc! { "created_at": -1 })
+            .limit(1)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(candidates.pop())
+    }
+
+    /// Why `payment` can't confirm `booking`, if it can't: a currency other than
+    /// USD, or an amount other than the itinerary's price for the party less any
+    /// gift card. A booking whose itinerary has no price can't be confirmed either.
+    pub async fn payment_mismatch(
+        &self,
+        booking: &BookingDetails,
+        payment: &CapturedPayment,
+    ) -> Result<Option<String>, mongodb::error::Error> {
+        if !payment.currency.eq_ignore_ascii_case("usd") {
+            return Ok(Some(format!("Payment is in {}, bookings are charged in usd", payment.currency)));
+        }
+        let Some(itinerary) = self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": booking.itinerary_id })
+            .await?
+        else {
+            return Ok(Some("Booked itinerary no longer exists".to_string()));
+        };
+        let Some(person_cost) = PricingService::person_price(&self.client, &itinerary).await?.amount() else {
+            return Ok(Some("Booked itinerary has no price".to_string()));
+        };
+        let expected = person_cost.cents() * PricingService::travelers(&itinerary) as i64
+            - booking.gift_card_amount.unwrap_or(0);
+        Ok((payment.amount != expected).then(|| {
+            format!("Payment of {} does not match the {} due", payment.amount, expected)
+        }))
+    }
+
+    /// Leave a paid booking unconfirmed and queue the payment to be settled by hand
+    pub async fn hold_for_reconciliation(&self, booking_id: ObjectId, payment: &CapturedPayment, reason: &str) {
+        self.queue_reconciliation(booking_id, payment, reason).await;
+    }
+
+    /// Store the payment intent on a booking that doesn't have one yet
+    pub async fn link_payment_intent(
+        &self,
+        booking_id: ObjectId,
+        payment_intent_id: &str,
+    ) -> Result<(), mongodb::error::Error> {
+        self.bookings()
+            .update_one(
+                doc! { "_id": booking_id, "transaction_id": null },
+                doc! { "$set": { "transaction_id": payment_intent_id, "updated_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Move a pending booking to confirmed and run the follow-up work: inventory
+    /// reservation and the confirmation email. The status update only matches
+    /// pending bookings, so whichever path confirms first does the follow-up work
+    /// and any later delivery is a no-op.
+    ///
+    /// With transactions on, the status and the seats are written together and a
+    /// failure leaves both as they were; the captured payment is then queued for
+    /// reconciliation. Without them an inventory failure is only logged, since the
+    /// booking is already confirmed.
+    pub async fn confirm(
+        &self,
+        cache: &AvailabilityCache,
+        booking: &BookingDetails,
+        payment: &CapturedPayment,
+    ) -> Result<ConfirmationOutcome, mongodb::error::Error> {
+        self.confirm_notifying(cache, booking, payment, &EmailService::new(self.client.clone(), &self.email).ok()).await
+    }
+
+    /// `confirm`, sending the confirmation email through `sender`
+    pub async fn confirm_notifying(
+        &self,
+        cache: &AvailabilityCache,
+        booking: &BookingDetails,
+        payment: &CapturedPayment,
+        sender: &impl ConfirmationSender,
+    ) -> Result<ConfirmationOutcome, mongodb::error::Error> {
+        let (Some(booking_id), Some(confirmed)) =
+            (booking.id, confirmed_booking(booking, payment, DateTime::now()))
+        else {
+            return Ok(ConfirmationOutcome::AlreadyProcessed);
+        };
+        let itinerary = self.find_itinerary(booking.itinerary_id).await;
+
+        let update: Document = doc! {
+            "$set": {
+                "status": mongodb::bson::to_bson(&confirmed.status).unwrap(),
+                "transaction_id": &payment.payment_intent_id,
+                "updated_at": confirmed.updated_at,
+            }
+        };
+        let bookings = self.bookings();
+        let result = unit_of_work::run(&self.client, self.transactional, async |uow: &mut UnitOfWork| {
+            let result = uow
+                .update_one(
+                    &bookings,
+                    doc! { "_id": booking_id, "status": { "$in": confirmable_statuses() } },
+                    update.clone(),
+                )
+                .await?;
+            if result.modified_count == 0 {
+                return Ok(None);
+            }
+            let Some(itinerary) = &itinerary else {
+                return Ok(Some(Vec::new()));
+            };
+            match self.reserve_inventory_in(uow, &confirmed, itinerary).await {
+                Ok(activity_ids) => Ok(Some(activity_ids)),
+                Err(e) if !uow.is_transactional() => {
+                    eprintln!("Failed to record booking {} against availability: {}", booking_id, e);
+                    Ok(Some(Vec::new()))
+                }
+                Err(e) => Err(e),
+            }
+        })
+        .await;
+
+        let activity_ids = match result {
+            Ok(Some(activity_ids)) => activity_ids,
+            Ok(None) => return Ok(ConfirmationOutcome::AlreadyProcessed),
+            Err(e) => {
+                self.queue_reconciliation(booking_id, payment, &e.to_string()).await;
+                return Err(e);
+            }
+        };
+
+        println!("✅ Booking {} confirmed by payment {}", booking_id, payment.payment_intent_id);
+        cache.invalidate_activities(&activity_ids);
+        self.notify_confirmed(&confirmed, payment, sender).await;
+        Ok(ConfirmationOutcome::Confirmed(Box::new(confirmed)))
+    }
+
+    /// Store a booking that was paid for without a card, in full by gift card. The
+    /// booking, the link from its gift card redemption and the seats it takes are
+    /// one unit of work. Returns the booking as stored.
+    pub async fn record_prepaid(
+        &self,
+        cache: &AvailabilityCache,
+        booking: &BookingDetails,
+    ) -> Result<BookingDetails, mongodb::error::Error> {
+        let itinerary = self.find_itinerary(booking.itinerary_id).await;
+        let bookings = self.bookings();
+        let gift_cards = GiftCardService::new(self.client.clone());
+        let (stored, activity_ids) = unit_of_work::run(&self.client, self.transactional, async |uow: &mut UnitOfWork| {
+            let mut stored = booking.clone();
+            stored.id = uow.insert_one(&bookings, booking).await?.inserted_id.as_object_id();
+            let Some(booking_id) = stored.id else {
+                return Ok((stored, Vec::new()));
+            };
+
+            // Without a transaction the booking is already written, so later
+            // failures are only logged, as they were before there was one
+            if let Some(redemption_id) = booking.gift_card_redemption_id {
+                match gift_cards.attach_booking_in(uow, redemption_id, booking_id).await {
+                    Ok(()) => {}
+                    Err(e) if !uow.is_transactional() => {
+                        eprintln!("Failed to link gift card redemption to booking {}: {}", booking_id, e)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            let Some(itinerary) = &itinerary else {
+                return Ok((stored, Vec::new()));
+            };
+            match self.reserve_inventory_in(uow, &stored, itinerary).await {
+                Ok(activity_ids) => Ok((stored, activity_ids)),
+                Err(e) if !uow.is_transactional() => {
+                    eprintln!("Failed to record booking {} against availability: {}", booking_id, e);
+                    Ok((stored, Vec::new()))
+                }
+                Err(e) => Err(e),
+            }
+        })
+        .await?;
+
+        cache.invalidate_activities(&activity_ids);
+        Ok(stored)
+    }
+
+    /// Send the confirmation email and text again for a booking that's already
+    /// confirmed. Nothing else about the booking is touched.
+    pub async fn resend_confirmation(
+        &self,
+        booking: &BookingDetails,
+        payment: &CapturedPayment,
+        sender: &impl ConfirmationSender,
+    ) {
+        println!("📧 Resending confirmation for booking {:?}", booking.id);
+        self.notify_confirmed(booking, payment, sender).await;
+    }
+
+    /// Count a confirmed booking against activity inventory, taking over the seats
+    /// its reservation held if it had one. Failures are logged rather than failing
+    /// the booking, since payment has already been taken.
+    pub async fn reserve_inventory(&self, cache: &AvailabilityCache, booking: &BookingDetails) {
+        let Some(itinerary) = self.find_itinerary(booking.itinerary_id).await else {
+            return;
+        };
+
+        match self
+            .reserve_inventory_in(&mut UnitOfWork::sequential(), booking, &itinerary)
+            .await
+        {
+            Ok(activity_ids) => cache.invalidate_activities(&activity_ids),
+            Err(e) => eprintln!("Failed to record booking against availability: {}", e),
+        }
+    }
+
+    /// The inventory writes of `reserve_inventory` as part of a larger unit of
+    /// work, returning the activities whose cached availability is now stale
+    async fn reserve_inventory_in(
+        &self,
+        uow: &mut UnitOfWork,
+        booking: &BookingDetails,
+        itinerary: &FeaturedVacation,
+    ) -> Result<Vec<ObjectId>, mongodb::error::Error> {
+        let Some(start) = calendar::utc_date(booking.arrival_datetime) else {
+            return Ok(Vec::new());
+        };
+
+        if let Some(reservation_id) = booking.reservation_id {
+            // A reservation that is long gone books the seats as if there never was one
+            if let Some(activity_ids) = ReservationService::new(self.client.clone())
+                .convert_in(uow, reservation_id, booking.id, itinerary)
+                .await?
+            {
+                return Ok(activity_ids);
+            }
+        }
+
+        AvailabilityService::new(self.client.clone())
+            .record_confirmed_booking_in(uow, itinerary, start)
+            .await
+    }
+
+    /// Record a captured payment whose booking couldn't be confirmed, so it is
+    /// settled by hand rather than captured again. A redelivered payment is
+    /// queued once.
+    async fn queue_reconciliation(&self, booking_id: ObjectId, payment: &CapturedPayment, error: &str) {
+        eprintln!(
+            "Payment {} captured but booking {} not confirmed, queued for reconciliation: {}",
+            payment.payment_intent_id, booking_id, error
+        );
+        let record = PaymentReconciliation {
+            id: None,
+            booking_id,
+            payment_intent_id: payment.payment_intent_id.clone(),
+            amount: payment.amount,
+            currency: payment.currency.clone(),
+            error: error.to_string(),
+            created_at: DateTime::now(),
+            resolved_at: None,
+            resolved_by: None,
+        };
+        let record = match mongodb::bson::to_document(&record) {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("Failed to queue payment {} for reconciliation: {}", payment.payment_intent_id, e);
+                return;
+            }
+        };
+        if let Err(e) = self
+            .reconciliation()
+            .update_one(
+                doc! { "booking_id": booking_id, "payment_intent_id": &payment.payment_intent_id },
+                doc! { "$setOnInsert": record },
+            )
+            .upsert(true)
+            .await
+        {
+            eprintln!("Failed to queue payment {} for reconciliation: {}", payment.payment_intent_id, e);
+        }
+    }
+
+    /// Payments queued for reconciliation, oldest first. Resolved ones are left out
+    /// unless asked for.
+    pub async fn list_reconciliation(
+        &self,
+        include_resolved: bool,
+    ) -> Result<Vec<PaymentReconciliation>, mongodb::error::Error> {
+        let filter = if include_resolved {
+            doc! {}
+        } else {
+            doc! { "resolved_at": null }
+        };
+        self.reconciliation()
+            .find(filter)
+            .sort(doc! { "created_at": 1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// Mark a queued payment as settled by `admin_id`. False when there is no
+    /// unresolved record with that id.
+    pub async fn resolve_reconciliation(
+        &self,
+        id: ObjectId,
+        admin_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let result = self
+            .reconciliation()
+            .update_one(
+                doc! { "_id": id, "resolved_at": null },
+                doc! { "$set": { "resolved_at": DateTime::now(), "resolved_by": admin_id } },
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    async fn find_itinerary(&self, itinerary_id: ObjectId) -> Option<FeaturedVacation> {
+        match self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": itinerary_id })
+            .await
+        {
+            Ok(itinerary) => itinerary,
+            Err(e) => {
+                eprintln!("Failed to load itinerary {}: {}", itinerary_id, e);
+                None
+            }
+        }
+    }
+
+    /// Confirmation email and text, each sent only if the user's preferences allow it
+    async fn notify_confirmed(
+        &self,
+        booking: &BookingDetails,
+        payment: &CapturedPayment,
+        sender: &impl ConfirmationSender,
+    ) {
+        let users: Collection<User> = self.client.database("Account").collection("Users");
+        let user = match users.find_one(doc! { "_id": booking.user_id }).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Failed to load user for booking confirmation email: {}", e);
+                return;
+            }
+        };
+        let Some(itinerary) = self.find_itinerary(booking.itinerary_id).await else {
+            return;
+        };
+
+        if !user.effective_notification_preferences().email.booking_updates {
+            println!("Skipping booking confirmation email, user opted out of booking updates");
+        } else {
+            let user_name = user
+                .first_name
+                .clone()
+                .map(|first| {
+                    user.last_name
+                        .clone()
+                        .map(|last| format!("{} {}", first, last))
+                        .unwrap_or(first)
+                })
+                .unwrap_or_else(|| "Valued Customer".to_string());
+
+            if let Err(e) = sender
+                .send_confirmation(&user.email, &user_name, booking, &itinerary.trip_name, payment)
+                .await
+            {
+                // Don't fail the booking if email fails
+                eprintln!("Failed to send booking confirmation email: {:?}", e);
+            }
+        }
+
+        NotificationService::new(self.sms.as_ref())
+            .send_booking_confirmation_sms(&user, &itinerary.trip_name, booking)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending_booking(transaction_id: Option<&str>) -> BookingDetails {
+        let created = DateTime::from_millis(1_750_000_000_000);
+        BookingDetails {
+            id: Some(ObjectId::parse_str("65f000000000000000000001").unwrap()),
+            user_id: ObjectId::parse_str("65f000000000000000000002").unwrap(),
+            itinerary_id: ObjectId::parse_str("65f000000000000000000003").unwrap(),
+            customer_id: Some("cus_123".to_string()),
+            transaction_id: transaction_id.map(str::to_string),
+            created_at: Some(created),
+            updated_at: Some(created),
+            ..BookingDetails::test(
+                PaymentStatus::Pending,
+                DateTime::from_millis(1_760_000_000_000),
+                DateTime::from_millis(1_760_300_000_000),
+            )
+        }
+    }
+
+    fn payment() -> CapturedPayment {
+        CapturedPayment {
+            payment_intent_id: "pi_123".to_string(),
+            amount: 125_000,
+            currency: "usd".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_webhook_first_confirmation_matches_inline_confirmation() {
+        let now = DateTime::from_millis(1_755_000_000_000);
+        // Inline: the booking was stored with the intent id before capture
+        let inline = confirmed_booking(&pending_booking(Some("pi_123")), &payment(), now).unwrap();
+        // Webhook first: found through intent metadata, no transaction id stored yet
+        let webhook = confirmed_booking(&pending_booking(None), &payment(), now).unwrap();
+
+        assert_eq!(
+            mongodb::bson::to_document(&inline).unwrap(),
+            mongodb::bson::to_document(&webhook).unwrap()
+        );
+        assert_eq!(webhook.status, PaymentStatus::Confirmed);
+        assert_eq!(webhook.transaction_id.as_deref(), Some("pi_123"));
+    }
+
+    #[test]
+    fn test_repeat_delivery_is_a_no_op() {
+        let now = DateTime::now();
+        let confirmed = confirmed_booking(&pending_booking(None), &payment(), now).unwrap();
+        assert!(confirmed_booking(&confirmed, &payment(), now).is_none());
+
+        let mut cancelled = pending_booking(Some("pi_123"));
+        cancelled.status = PaymentStatus::Cancelled;
+        assert!(confirmed_booking(&cancelled, &payment(), now).is_none());
+
+        let mut processing = pending_booking(Some("pi_123"));
+        processing.status = PaymentStatus::PendingPayment;
+        assert!(confirmed_booking(&processing, &payment(), now).is_some());
+    }
+
+    #[test]
+    fn test_confirmable_statuses_match_stored_values() {
+        assert_eq!(
+            confirmable_statuses(),
+            vec![
+                mongodb::bson::Bson::String("pending".to_string()),
+                mongodb::bson::Bson::String("pending_payment".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reconciliation_queued_before_resolving_existed_reads_as_unresolved() {
+        let stored = doc! {
+            "_id": ObjectId::new(),
+            "booking_id": ObjectId::new(),
+            "payment_intent_id": "pi_123",
+            "amount": 125_000_i64,
+            "currency": "usd",
+            "error": "write conflict",
+            "created_at": DateTime::now(),
+        };
+        let record: PaymentReconciliation = mongodb::bson::from_document(stored).unwrap();
+        assert!(record.resolved_at.is_none());
+        assert!(!mongodb::bson::to_document(&record).unwrap().contains_key("resolved_at"));
+    }
+}
@@ -0,0 +1,478 @@
+//! What an admin edit to an itinerary's days does to people who already booked it
+//!
+//! [`BookingImpactService::check_booking_impact`] compares the proposed days with
+//! the stored ones and finds the confirmed bookings still to come. Removing an
+//! activity someone paid for needs the admin to acknowledge it; every days edit is
+//! written to the admin audit log, and each affected traveler gets a notice in
+//! `Account.BookingChangeNotices` and an email describing the change.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::account::User;
+use crate::models::activity::Activity;
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::models::itinerary::base::{DayItem, FeaturedVacation};
+use crate::services::account_service::EmailService;
+
+/// How much a days edit matters to travelers who booked, least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImpactKind {
+    None,
+    /// Transportation, accommodation or added activities; booked activities are untouched
+    Cosmetic,
+    /// A booked activity moved to another day or time
+    TimeShift,
+    /// A booked activity is gone
+    RemovedActivity,
+}
+
+/// A booked activity's first slot before and after the edit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShiftedActivity {
+    pub activity_id: ObjectId,
+    pub from_day: String,
+    pub from_time: String,
+    pub to_day: String,
+    pub to_time: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayChanges {
+    pub kind: ImpactKind,
+    pub removed_activity_ids: Vec<ObjectId>,
+    pub shifted_activities: Vec<ShiftedActivity>,
+}
+
+/// Every `(day, time)` each activity is scheduled at, in day order
+fn activity_slots(days: &HashMap<String, Vec<DayItem>>) -> BTreeMap<ObjectId, BTreeSet<(u32, String, String)>> {
+    let mut slots: BTreeMap<ObjectId, BTreeSet<(u32, String, String)>> = BTreeMap::new();
+    for (day, items) in days {
+        for item in items {
+            if let DayItem::Activity { time, activity_id } = item {
+                let order = day.trim().parse().unwrap_or(u32::MAX);
+                slots.entry(*activity_id).or_default().insert((order, day.clone(), time.clone()));
+            }
+        }
+    }
+    slots
+}
+
+/// Classify the difference between the stored days and an edit of them
+pub fn classify_day_changes(
+    current: &HashMap<String, Vec<DayItem>>,
+    proposed: &HashMap<String, Vec<DayItem>>,
+) -> DayChanges {
+    let before = activity_slots(current);
+    let after = activity_slots(proposed);
+
+    let removed_activity_ids: Vec<ObjectId> =
+        before.keys().filter(|id| !after.contains_key(id)).copied().collect();
+    let shifted_activities: Vec<ShiftedActivity> = before
+        .iter()
+        .filter_map(|(id, from)| {
+            let to = after.get(id)?;
+            if from == to {
+                return None;
+            }
+            let (_, from_day, from_time) = from.first()?.clone();
+            let (_, to_day, to_time) = to.first()?.clone();
+            Some(ShiftedActivity {
+                activity_id: *id,
+                from_day,
+                from_time,
+                to_day,
+                to_time,
+            })
+        })
+        .collect();
+
+    let kind = if !removed_activity_ids.is_empty() {
+        ImpactKind::RemovedActivity
+    } else if !shifted_activities.is_empty() {
+        ImpactKind::TimeShift
+    } else if serde_json::to_value(current).ok() != serde_json::to_value(proposed).ok() {
+        ImpactKind::Cosmetic
+    } else {
+        ImpactKind::None
+    };
+    DayChanges {
+        kind,
+        removed_activity_ids,
+        shifted_activities,
+    }
+}
+
+/// A days edit and the bookings it reaches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookingImpact {
+    pub itinerary_id: ObjectId,
+    #[serde(flatten)]
+    pub changes: DayChanges,
+    /// Confirmed bookings that haven't started. Empty unless a booked activity moved or went.
+    pub affected_booking_ids: Vec<ObjectId>,
+}
+
+impl BookingImpact {
+    /// Removing an activity travelers have paid for needs `acknowledge_booking_impact`
+    pub fn requires_acknowledgement(&self) -> bool {
+        self.changes.kind == ImpactKind::RemovedActivity && !self.affected_booking_ids.is_empty()
+    }
+}
+
+/// A days edit, kept in the admin audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ItineraryDaysAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub itinerary_id: ObjectId,
+    pub booking_impact: BookingImpact,
+    pub acknowledged: bool,
+    pub created_at: DateTime,
+}
+
+/// A traveler told that their booked trip changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookingChangeNotice {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub booking_id: ObjectId,
+    pub user_id: ObjectId,
+    pub itinerary_id: ObjectId,
+    pub kind: ImpactKind,
+    /// The changes as written in the email
+    pub changes: Vec<String>,
+    pub email_sent: bool,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct NotifySummary {
+    pub notified: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug)]
+pub enum BookingImpactError {
+    ItineraryNotFound,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for BookingImpactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BookingImpactError::ItineraryNotFound => write!(f, "Itinerary not found"),
+            BookingImpactError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BookingImpactError {}
+
+impl From<mongodb::error::Error> for BookingImpactError {
+    fn from(e: mongodb::error::Error) -> Self {
+        BookingImpactError::DatabaseError(e.to_string())
+    }
+}
+
+/// Tells a traveler that a trip they booked changed
+pub trait BookingChangeSender {
+    fn send_booking_change(
+        &self,
+        user: &User,
+        trip_name: &str,
+        booking: &BookingDetails,
+        changes: &[String],
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// `None` when email isn't configured; the notice is still recorded
+impl BookingChangeSender for Option<EmailService> {
+    fn send_booking_change(
+        &self,
+        user: &User,
+        trip_name: &str,
+        booking: &BookingDetails,
+        changes: &[String],
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        let email = user.email.clone();
+        let first_name = user.first_name.clone();
+        let trip_name = trip_name.to_string();
+        let booking = booking.clone();
+        let changes = changes.to_vec();
+        async move {
+            let Some(service) = self else {
+                return Err("Email is not configured".to_string());
+            };
+            service
+                .send_booking_itinerary_changed_email(&email, first_name.as_deref(), &trip_name, &booking, &changes)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+pub struct BookingImpactService {
+    client: Arc<Client>,
+}
+
+impl BookingImpactService {
+    pub fn new(client: Arc<Client>) -> Self {
+        BookingImpactService { client }
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    fn notices(&self) -> Collection<BookingChangeNotice> {
+        self.client.database("Account").collection("BookingChangeNotices")
+    }
+
+    /// Classify replacing the itinerary's days with `proposed_days`, and find the
+    /// confirmed future bookings that would notice
+    pub async fn check_booking_impact(
+        &self,
+        itinerary_id: ObjectId,
+        proposed_days: &HashMap<String, Vec<DayItem>>,
+    ) -> Result<BookingImpact, BookingImpactError> {
+        let itinerary = self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": itinerary_id })
+            .await?
+            .ok_or(BookingImpactError::ItineraryNotFound)?;
+
+        let changes = classify_day_changes(&itinerary.days.days, proposed_days);
+        let affected_booking_ids = if changes.kind >= ImpactKind::TimeShift {
+            self.bookings()
+                .distinct(
+                    "_id",
+                    doc! {
+                        "itinerary_id": itinerary_id,
+                        "status": "confirmed",
+                        "arrival_datetime": { "$gt": DateTime::now() },
+                    },
+                )
+                .await?
+                .into_iter()
+                .filter_map(|id| id.as_object_id())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(BookingImpact {
+            itinerary_id,
+            changes,
+            affected_booking_ids,
+        })
+    }
+
+    /// Write the edit and its impact to the admin audit log
+    pub async fn record(&self, admin_id: ObjectId, impact: &BookingImpact, acknowledged: bool) {
+        let audit = ItineraryDaysAudit {
+            id: None,
+            action: "itinerary_days_updated".to_string(),
+            admin_id,
+            itinerary_id: impact.itinerary_id,
+            booking_impact: impact.clone(),
+            acknowledged,
+            created_at: DateTime::now(),
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<ItineraryDaysAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for itinerary {}: {}", impact.itinerary_id, e);
+        }
+    }
+
+    /// The changes in words, naming activities by title
+    async fn describe(&self, changes: &DayChanges) -> Result<Vec<String>, mongodb::error::Error> {
+        let ids: Vec<ObjectId> = changes
+            .removed_activity_ids
+            .iter()
+            .chain(changes.shifted_activities.iter().map(|shift| &shift.activity_id))
+            .copied()
+            .collect();
+        let titles: HashMap<ObjectId, String> = self
+            .client
+            .database("Options")
+            .collection::<Activity>("Activity")
+            .find(doc! { "_id": { "$in": ids } })
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| Some((activity.id?, activity.title)))
+            .collect();
+        let title = |id: &ObjectId| titles.get(id).cloned().unwrap_or_else(|| "An activity".to_string());
+
+        let removed = changes
+            .removed_activity_ids
+            .iter()
+            .map(|id| format!("{} is no longer part of the trip", title(id)));
+        let shifted = changes.shifted_activities.iter().map(|shift| {
+            format!(
+                "{} moved from day {} at {} to day {} at {}",
+                title(&shift.activity_id),
+                shift.from_day,
+                shift.from_time,
+                shift.to_day,
+                shift.to_time
+            )
+        });
+        Ok(removed.chain(shifted).collect())
+    }
+
+    /// Record a notice for, and email, each affected traveler. Failed emails are
+    /// logged and leave the notice with `email_sent: false`.
+    pub async fn notify_travelers(
+        &self,
+        impact: &BookingImpact,
+        trip_name: &str,
+        sender: &impl BookingChangeSender,
+    ) -> Result<NotifySummary, mongodb::error::Error> {
+        let mut summary = NotifySummary::default();
+        if impact.affected_booking_ids.is_empty() {
+            return Ok(summary);
+        }
+
+        let changes = self.describe(&impact.changes).await?;
+        let bookings: Vec<BookingDetails> = self
+            .bookings()
+            .find(doc! { "_id": { "$in": impact.affected_booking_ids.clone() } })
+            .await?
+            .try_collect()
+            .await?;
+        let users: Collection<User> = self.client.database("Account").collection("Users");
+
+        for booking in bookings {
+            let Some(booking_id) = booking.id else { continue };
+            // Bookings confirmed since the check are told too; cancelled ones aren't
+            if booking.status != PaymentStatus::Confirmed {
+                continue;
+            }
+            let email_sent = match users.find_one(doc! { "_id": booking.user_id }).await? {
+                Some(user) => match sender.send_booking_change(&user, trip_name, &booking, &changes).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        eprintln!("Failed to email booking change for booking {}: {}", booking_id, e);
+                        false
+                    }
+                },
+                None => false,
+            };
+            if email_sent {
+                summary.notified += 1;
+            } else {
+                summary.failed += 1;
+            }
+
+            let notice = BookingChangeNotice {
+                id: None,
+                booking_id,
+                user_id: booking.user_id,
+                itinerary_id: impact.itinerary_id,
+                kind: impact.changes.kind,
+                changes: changes.clone(),
+                email_sent,
+                created_at: DateTime::now(),
+            };
+            if let Err(e) = self.notices().insert_one(&notice).await {
+                eprintln!("Failed to record booking change notice for booking {}: {}", booking_id, e);
+            }
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::itinerary::base::ItemLocation;
+
+    fn activity(time: &str, activity_id: ObjectId) -> DayItem {
+        DayItem::Activity {
+            time: time.to_string(),
+            activity_id,
+        }
+    }
+
+    fn transport(name: &str) -> DayItem {
+        DayItem::Transportation {
+            time: "08:00".to_string(),
+            location: ItemLocation::default(),
+            name: name.to_string(),
+        }
+    }
+
+    fn days(entries: Vec<(&str, Vec<DayItem>)>) -> HashMap<String, Vec<DayItem>> {
+        entries.into_iter().map(|(day, items)| (day.to_string(), items)).collect()
+    }
+
+    #[test]
+    fn test_unchanged_and_cosmetic_edits() {
+        let (rafting, hiking) = (ObjectId::new(), ObjectId::new());
+        let current = days(vec![
+            ("1", vec![transport("Shuttle"), activity("09:00", rafting)]),
+            ("2", vec![activity("10:00", hiking)]),
+        ]);
+        assert_eq!(classify_day_changes(&current, &current.clone()).kind, ImpactKind::None);
+
+        let renamed_and_added = days(vec![
+            ("1", vec![transport("Private van"), activity("09:00", rafting)]),
+            ("2", vec![activity("10:00", hiking), activity("15:00", ObjectId::new())]),
+        ]);
+        let changes = classify_day_changes(&current, &renamed_and_added);
+        assert_eq!(changes.kind, ImpactKind::Cosmetic);
+        assert!(changes.removed_activity_ids.is_empty());
+        assert!(changes.shifted_activities.is_empty());
+    }
+
+    #[test]
+    fn test_moved_and_removed_activities() {
+        let (rafting, hiking) = (ObjectId::new(), ObjectId::new());
+        let current = days(vec![
+            ("1", vec![activity("09:00", rafting)]),
+            ("2", vec![activity("10:00", hiking)]),
+        ]);
+
+        let moved = days(vec![("1", vec![activity("09:00", rafting), activity("14:00", hiking)])]);
+        let changes = classify_day_changes(&current, &moved);
+        assert_eq!(changes.kind, ImpactKind::TimeShift);
+        assert_eq!(
+            changes.shifted_activities,
+            vec![ShiftedActivity {
+                activity_id: hiking,
+                from_day: "2".to_string(),
+                from_time: "10:00".to_string(),
+                to_day: "1".to_string(),
+                to_time: "14:00".to_string(),
+            }]
+        );
+
+        // A removal outranks a move in the same edit
+        let removed = days(vec![("1", vec![activity("11:00", hiking)])]);
+        let changes = classify_day_changes(&current, &removed);
+        assert_eq!(changes.kind, ImpactKind::RemovedActivity);
+        assert_eq!(changes.removed_activity_ids, vec![rafting]);
+        assert_eq!(changes.shifted_activities.len(), 1);
+    }
+}
@@ -0,0 +1,693 @@
+//! Moving a booking to new dates
+//!
+//! The new dates have to fit the itinerary's length and be bookable (see
+//! `availability_service`). A paid booking is re-priced at the itinerary's
+//! current per-person cost: a higher price is paid with a second, already
+//! authorized payment intent, and a lower one is refunded the way a cancellation
+//! is, gift card first. Each move is kept in `BookingDetails.modifications`.
+
+use chrono::{Duration, NaiveDate};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::{
+    account::User,
+    bookings::{BookingDetails, BookingModification, PaymentStatus, RescheduleInput},
+    itinerary::base::FeaturedVacation,
+    money::Money,
+};
+use crate::services::account_service::{EmailService, EmailSettings};
+use crate::services::availability_service::{
+    parse_month, AvailabilityCache, AvailabilityError, AvailabilityService, DayStatus,
+};
+use crate::services::calendar;
+use crate::services::gift_card_service::{refund_plan, GiftCardService, RefundStep};
+use crate::services::pricing_service::{PersonPrice, PricingService};
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReschedulePolicy {
+    /// Neither the current nor the new arrival may be closer than this
+    pub cutoff_hours: u64,
+    /// `AVAILABILITY_LIMITED_THRESHOLD`, so cached months stay consistent
+    pub limited_threshold: u32,
+}
+
+#[derive(Debug)]
+pub enum RescheduleError {
+    BookingNotFound,
+    ItineraryNotFound,
+    /// Cancelled, refunded and failed bookings can't be moved
+    NotReschedulable(PaymentStatus),
+    /// The payment hasn't been captured yet, so there's no price to compare with
+    PaymentPending,
+    InsideCutoff(u64),
+    InvalidDates(String),
+    Unavailable(DayStatus),
+    /// The new dates cost more and no payment intent was given for the difference
+    PaymentRequired(i64),
+    /// The itinerary has no price, so the difference can't be worked out
+    PriceUnavailable,
+    PaymentAlreadyUsed,
+    PaymentRejected(String),
+    RefundFailed(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for RescheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RescheduleError::BookingNotFound => write!(f, "Booking not found"),
+            RescheduleError::ItineraryNotFound => write!(f, "Itinerary not found"),
+            RescheduleError::NotReschedulable(status) => {
+                write!(f, "A booking with status {:?} can't be rescheduled", status)
+            }
+            RescheduleError::PaymentPending => write!(
+                f,
+                "The payment for this booking is still processing; try again once it's confirmed"
+            ),
+            RescheduleError::InsideCutoff(hours) => write!(
+                f,
+                "Bookings can't be rescheduled within {} hours of arrival",
+                hours
+            ),
+            RescheduleError::InvalidDates(msg) => write!(f, "{}", msg),
+            RescheduleError::Unavailable(DayStatus::SoldOut) => {
+                write!(f, "The trip is sold out on the new dates")
+            }
+            RescheduleError::Unavailable(_) => write!(f, "The trip doesn't run on the new dates"),
+            RescheduleError::PaymentRequired(amount) => write!(
+                f,
+                "The new dates cost ${} more; authorize a payment intent for the difference",
+                Money::from_cents(*amount)
+            ),
+            RescheduleError::PriceUnavailable => write!(
+                f,
+                "This trip has no price to reschedule against; contact support to move it"
+            ),
+            RescheduleError::PaymentAlreadyUsed => {
+                write!(f, "This payment is already attached to a booking")
+            }
+            RescheduleError::PaymentRejected(msg) => write!(f, "Payment rejected: {}", msg),
+            RescheduleError::RefundFailed(msg) => write!(f, "Refund failed: {}", msg),
+            RescheduleError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for RescheduleError {}
+
+impl From<mongodb::error::Error> for RescheduleError {
+    fn from(e: mongodb::error::Error) -> Self {
+        RescheduleError::DatabaseError(e.to_string())
+    }
+}
+
+/// The card side of a price difference
+pub trait PaymentAdjuster {
+    /// Cents captured on `payment_intent_id`
+    fn amount_received(&self, payment_intent_id: &str) -> impl Future<Output = Result<i64, String>> + Send;
+
+    /// Capture an authorized intent, which must be for exactly `amount`
+    fn capture(&self, payment_intent_id: &str, amount: i64) -> impl Future<Output = Result<(), String>> + Send;
+
+    /// Refund part of a captured intent, returning the refund id
+    fn refund(&self, payment_intent_id: &str, amount: i64) -> impl Future<Output = Result<String, String>> + Send;
+}
+
+impl PaymentAdjuster for stripe::Client {
+    fn amount_received(&self, payment_intent_id: &str) -> impl Future<Output = Result<i64, String>> + Send {
+        let intent_id = stripe::PaymentIntentId::from_str(payment_intent_id);
+        async move {
+            let intent_id = intent_id.map_err(|e| e.to_string())?;
+            stripe::PaymentIntent::retrieve(self, &intent_id, &[])
+                .await
+                .map(|intent| intent.amount_received)
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    fn capture(&self, payment_intent_id: &str, amount: i64) -> impl Future<Output = Result<(), String>> + Send {
+        let intent_id = stripe::PaymentIntentId::from_str(payment_intent_id);
+        let payment_intent_id = payment_intent_id.to_string();
+        async move {
+            let intent_id = intent_id.map_err(|e| e.to_string())?;
+            let intent = stripe::PaymentIntent::retrieve(self, &intent_id, &[])
+                .await
+                .map_err(|e| e.to_string())?;
+            if intent.status != stripe::PaymentIntentStatus::RequiresCapture {
+                return Err(format!("payment intent is {:?}, not awaiting capture", intent.status));
+            }
+            if intent.amount != amount {
+                return Err(format!(
+                    "payment intent is for {} cents but the difference is {}",
+                    intent.amount, amount
+                ));
+            }
+            let captured = stripe::PaymentIntent::capture(
+                self,
+                &payment_intent_id,
+                stripe::CapturePaymentIntent::default(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+            if captured.status != stripe::PaymentIntentStatus::Succeeded {
+                return Err(format!("capture left the intent {:?}", captured.status));
+            }
+            Ok(())
+        }
+    }
+
+    fn refund(&self, payment_intent_id: &str, amount: i64) -> impl Future<Output = Result<String, String>> + Send {
+        let intent_id = stripe::PaymentIntentId::from_str(payment_intent_id);
+        async move {
+            let params = stripe::CreateRefund {
+                payment_intent: Some(intent_id.map_err(|e| e.to_string())?),
+                amount: Some(amount),
+                ..Default::default()
+            };
+            stripe::Refund::create(self, params)
+                .await
+                .map(|refund| refund.id.to_string())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RescheduleOutcome {
+    pub booking: BookingDetails,
+    /// Cents charged (positive) or given back (negative)
+    pub price_difference: i64,
+}
+
+/// Whether `booking` may be moved at `now`, going by its status and current arrival
+pub fn check_reschedulable(
+    booking: &BookingDetails,
+    now: DateTime,
+    cutoff_hours: u64,
+) -> Result<(), RescheduleError> {
+    match booking.status {
+        PaymentStatus::Confirmed | PaymentStatus::Ongoing => {}
+        PaymentStatus::Pending | PaymentStatus::PendingPayment => {
+            return Err(RescheduleError::PaymentPending)
+        }
+        ref status => return Err(RescheduleError::NotReschedulable(status.clone())),
+    }
+    if !outside_cutoff(booking.arrival_datetime, now, cutoff_hours) {
+        return Err(RescheduleError::InsideCutoff(cutoff_hours));
+    }
+    Ok(())
+}
+
+fn outside_cutoff(arrival: DateTime, now: DateTime, cutoff_hours: u64) -> bool {
+    arrival.timestamp_millis() - now.timestamp_millis() >= cutoff_hours as i64 * HOUR_MILLIS
+}
+
+/// Days the itinerary runs, as availability counts them
+fn trip_days(itinerary: &FeaturedVacation) -> i64 {
+    itinerary.days.days.len().max(itinerary.length_days as usize).max(1) as i64
+}
+
+/// Check the new dates against the itinerary's length and the cutoff, returning
+/// the new start date
+pub fn validate_dates(
+    itinerary: &FeaturedVacation,
+    booking: &BookingDetails,
+    arrival: DateTime,
+    departure: DateTime,
+    now: DateTime,
+    cutoff_hours: u64,
+) -> Result<NaiveDate, RescheduleError> {
+    if departure <= arrival {
+        return Err(RescheduleError::InvalidDates(
+            "departure_datetime must be after arrival_datetime".to_string(),
+        ));
+    }
+    if arrival == booking.arrival_datetime && departure == booking.departure_datetime {
+        return Err(RescheduleError::InvalidDates(
+            "The booking is already for these dates".to_string(),
+        ));
+    }
+    let (Some(start), Some(end)) = (calendar::utc_date(arrival), calendar::utc_date(departure)) else {
+        return Err(RescheduleError::InvalidDates("Dates are out of range".to_string()));
+    };
+
+    let days = trip_days(itinerary);
+    if end - start != Duration::days(days - 1) {
+        return Err(RescheduleError::InvalidDates(format!(
+            "This itinerary is {} days long, so departure must be {} days after arrival",
+            days,
+            days - 1
+        )));
+    }
+    if !outside_cutoff(arrival, now, cutoff_hours) {
+        return Err(RescheduleError::InvalidDates(format!(
+            "The new arrival must be at least {} hours away",
+            cutoff_hours
+        )));
+    }
+    Ok(start)
+}
+
+/// Cents still owed (positive) or to give back (negative) at `price` for
+/// `travelers`, after `paid`. `None` when the price is unavailable: guessing zero
+/// would refund everything that was paid.
+pub fn price_difference(price: PersonPrice, travelers: u32, paid: i64) -> Option<i64> {
+    price
+        .amount()
+        .map(|cost| cost.cents() * travelers as i64 - paid)
+}
+
+/// Who the booking was priced for: the recorded party, or the itinerary's own
+fn travelers(booking: &BookingDetails, itinerary: &FeaturedVacation) -> u32 {
+    booking
+        .party
+        .map(|party| party.size())
+        .or_else(|| itinerary.party_size())
+        .unwrap_or(itinerary.min_group)
+        .max(1)
+}
+
+pub struct BookingRescheduleService {
+    client: Arc<Client>,
+    email: EmailSettings,
+    transactional: bool,
+}
+
+impl BookingRescheduleService {
+    pub fn new(client: Arc<Client>) -> Self {
+        BookingRescheduleService {
+            client,
+            email: EmailSettings::default(),
+            transactional: false,
+        }
+    }
+
+    /// Where reschedule notices are emailed from. Without it none are sent.
+    pub fn with_email(mut self, settings: &EmailSettings) -> Self {
+        self.email = settings.clone();
+        self
+    }
+
+    /// Restore gift card shares of a refund in a transaction (`MONGODB_TRANSACTIONS`)
+    pub fn with_transactions(mut self, enabled: bool) -> Self {
+        self.transactional = enabled;
+        self
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    pub async fn reschedule(
+        &self,
+        user_id: ObjectId,
+        booking_id: ObjectId,
+        input: RescheduleInput,
+        policy: &ReschedulePolicy,
+        cache: &AvailabilityCache,
+        payments: &impl PaymentAdjuster,
+    ) -> Result<RescheduleOutcome, RescheduleError> {
+        let now = DateTime::now();
+        let booking = self
+            .bookings()
+            .find_one(doc! { "_id": booking_id, "user_id": user_id })
+            .await?
+            .ok_or(RescheduleError::BookingNotFound)?;
+        check_reschedulable(&booking, now, policy.cutoff_hours)?;
+
+        let itinerary = self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": booking.itinerary_id })
+            .await?
+            .ok_or(RescheduleError::ItineraryNotFound)?;
+        let start = validate_dates(
+            &itinerary,
+            &booking,
+            input.arrival_datetime,
+            input.departure_datetime,
+            now,
+            policy.cutoff_hours,
+        )?;
+        self.check_available(&itinerary, booking.itinerary_id, start, policy, cache)
+            .await?;
+
+        let difference = match booking.status {
+            PaymentStatus::Confirmed => {
+                let card_paid = match &booking.transaction_id {
+                    Some(intent_id) => payments
+                        .amount_received(intent_id)
+                        .await
+                        .map_err(RescheduleError::PaymentRejected)?,
+                    None => 0,
+                };
+                let adjusted: i64 = booking
+                    .modifications
+                    .iter()
+                    .map(|modification| modification.price_difference)
+                    .sum();
+                let paid = card_paid + booking.gift_card_amount.unwrap_or(0) + adjusted;
+                let price = PricingService::person_price(&self.client, &itinerary).await?;
+                price_difference(price, travelers(&booking, &itinerary), paid)
+                    .ok_or(RescheduleError::PriceUnavailable)?
+            }
+            // Pay-later bookings settle the price outside the app
+            _ => 0,
+        };
+
+        let mut modification = BookingModification {
+            previous_arrival_datetime: booking.arrival_datetime,
+            previous_departure_datetime: booking.departure_datetime,
+            arrival_datetime: input.arrival_datetime,
+            departure_datetime: input.departure_datetime,
+            price_difference: difference,
+            payment_intent_id: None,
+            refund_id: None,
+            gift_card_restored: None,
+            created_at: now,
+        };
+        if difference > 0 {
+            let intent_id = input
+                .payment_intent_id
+                .ok_or(RescheduleError::PaymentRequired(difference))?;
+            self.settle_charge(&intent_id, difference, payments).await?;
+            modification.payment_intent_id = Some(intent_id);
+        } else if difference < 0 {
+            self.settle_refund(&booking, -difference, payments, &mut modification)
+                .await?;
+        }
+        let difference = modification.price_difference;
+
+        let update = doc! {
+            "$set": {
+                "arrival_datetime": input.arrival_datetime,
+                "departure_datetime": input.departure_datetime,
+                "updated_at": now,
+            },
+            "$push": { "modifications": mongodb::bson::to_bson(&modification).unwrap_or_default() },
+        };
+        self.bookings()
+            .update_one(doc! { "_id": booking_id }, update)
+            .await?;
+        println!(
+            "📅 Booking {} moved from {} to {} (difference {} cents)",
+            booking_id,
+            booking.arrival_datetime,
+            input.arrival_datetime,
+            difference
+        );
+
+        let mut updated = booking;
+        updated.arrival_datetime = input.arrival_datetime;
+        updated.departure_datetime = input.departure_datetime;
+        updated.updated_at = Some(now);
+        updated.modifications.push(modification);
+
+        self.move_inventory(cache, &itinerary, &updated).await;
+        self.notify_rescheduled(&updated, &itinerary.trip_name, difference)
+            .await;
+
+        Ok(RescheduleOutcome {
+            booking: updated,
+            price_difference: difference,
+        })
+    }
+
+    async fn check_available(
+        &self,
+        itinerary: &FeaturedVacation,
+        itinerary_id: ObjectId,
+        start: NaiveDate,
+        policy: &ReschedulePolicy,
+        cache: &AvailabilityCache,
+    ) -> Result<(), RescheduleError> {
+        let today = chrono::Utc::now().date_naive();
+        let month_start = parse_month(&start.format("%Y-%m").to_string(), today).map_err(|e| {
+            RescheduleError::InvalidDates(e.to_string())
+        })?;
+        let days = AvailabilityService::new(self.client.clone())
+            .month_availability(cache, itinerary_id, month_start, today, policy.limited_threshold)
+            .await
+            .map_err(|e| match e {
+                AvailabilityError::NotFound => RescheduleError::ItineraryNotFound,
+                e => RescheduleError::DatabaseError(e.to_string()),
+            })?;
+        let status = days
+            .iter()
+            .find(|day| day.date == start)
+            .map(|day| day.status)
+            .unwrap_or(DayStatus::NotOperating);
+        match status {
+            DayStatus::Available | DayStatus::Limited => Ok(()),
+            status => {
+                println!(
+                    "📅 {} ({}) not bookable on {}: {:?}",
+                    itinerary.trip_name, itinerary_id, start, status
+                );
+                Err(RescheduleError::Unavailable(status))
+            }
+        }
+    }
+
+    async fn settle_charge(
+        &self,
+        intent_id: &str,
+        amount: i64,
+        payments: &impl PaymentAdjuster,
+    ) -> Result<(), RescheduleError> {
+        let used = doc! {
+            "$or": [
+                { "transaction_id": intent_id },
+                { "modifications.payment_intent_id": intent_id },
+            ]
+        };
+        if self.bookings().find_one(used).await?.is_some() {
+            return Err(RescheduleError::PaymentAlreadyUsed);
+        }
+        payments
+            .capture(intent_id, amount)
+            .await
+            .map_err(RescheduleError::PaymentRejected)
+    }
+
+    /// Give back `amount`, split between the gift card and the card as they paid.
+    /// The modification records what actually went back, so a later difference
+    /// starts from the right total.
+    async fn settle_refund(
+        &self,
+        booking: &BookingDetails,
+        amount: i64,
+        payments: &impl PaymentAdjuster,
+        modification: &mut BookingModification,
+    ) -> Result<(), RescheduleError> {
+        let card_paid = match &booking.transaction_id {
+            Some(intent_id) => payments
+                .amount_received(intent_id)
+                .await
+                .map_err(RescheduleError::RefundFailed)?,
+            None => 0,
+        };
+
+        let mut returned = 0;
+        for step in refund_plan(booking.gift_card_amount.unwrap_or(0), card_paid, amount) {
+            match (step, booking.gift_card_redemption_id, &booking.transaction_id) {
+                (RefundStep::RestoreGiftCard(share), Some(redemption_id), _) => {
+                    let restored = GiftCardService::new(self.client.clone())
+                        .with_transactions(self.transactional)
+                        .restore(redemption_id, share)
+                        .await
+                        .map_err(|e| RescheduleError::RefundFailed(e.to_string()))?;
+                    modification.gift_card_restored = Some(restored);
+                    returned += restored;
+                }
+                (RefundStep::RefundCard(share), _, Some(intent_id)) => {
+                    let refund_id = payments
+                        .refund(intent_id, share)
+                        .await
+                        .map_err(RescheduleError::RefundFailed)?;
+                    modification.refund_id = Some(refund_id);
+                    returned += share;
+                }
+                (step, _, _) => eprintln!("Nothing to apply {:?} to on booking {:?}", step, booking.id),
+            }
+        }
+        modification.price_difference = -returned;
+        Ok(())
+    }
+
+    /// Seats follow the booking to its new dates. Failures are logged, as with
+    /// confirmation, since the booking has already moved.
+    async fn move_inventory(
+        &self,
+        cache: &AvailabilityCache,
+        itinerary: &FeaturedVacation,
+        booking: &BookingDetails,
+    ) {
+        let Some(modification) = booking.modifications.last() else {
+            return;
+        };
+        let (Some(previous), Some(start)) = (
+            calendar::utc_date(modification.previous_arrival_datetime),
+            calendar::utc_date(modification.arrival_datetime),
+        ) else {
+            return;
+        };
+        let availability = AvailabilityService::new(self.client.clone());
+        if let Err(e) = availability.release_booking(cache, itinerary, previous).await {
+            eprintln!("Failed to release seats for booking {:?}: {}", booking.id, e);
+        }
+        if let Err(e) = availability
+            .record_confirmed_booking(cache, itinerary, start)
+            .await
+        {
+            eprintln!("Failed to reserve seats for booking {:?}: {}", booking.id, e);
+        }
+    }
+
+    async fn notify_rescheduled(&self, booking: &BookingDetails, trip_name: &str, difference: i64) {
+        let users: Collection<User> = self.client.database("Account").collection("Users");
+        let user = match users.find_one(doc! { "_id": booking.user_id }).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Failed to load user for reschedule email: {}", e);
+                return;
+            }
+        };
+        if !user.effective_notification_preferences().email.booking_updates {
+            println!("Skipping reschedule email, user opted out of booking updates");
+            return;
+        }
+        let Ok(email_service) = EmailService::new(self.client.clone(), &self.email) else {
+            return;
+        };
+        if let Err(e) = email_service
+            .send_booking_rescheduled_email(&user.email, user.first_name.as_deref(), trip_name, booking, difference)
+            .await
+        {
+            eprintln!("Failed to send reschedule email: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::itinerary::base::{DayItem, Days};
+
+    const DAY_MILLIS: i64 = 24 * HOUR_MILLIS;
+
+    fn now() -> DateTime {
+        DateTime::from_millis(1_750_000_000_000)
+    }
+
+    fn booking(status: PaymentStatus, arrival_in_hours: i64) -> BookingDetails {
+        let arrival = now().timestamp_millis() + arrival_in_hours * HOUR_MILLIS;
+        BookingDetails {
+            transaction_id: Some("pi_123".to_string()),
+            created_at: Some(now()),
+            updated_at: Some(now()),
+            ..BookingDetails::test(
+                status,
+                DateTime::from_millis(arrival),
+                DateTime::from_millis(arrival + 2 * DAY_MILLIS),
+            )
+        }
+    }
+
+    fn three_day_itinerary() -> FeaturedVacation {
+        let days = (1..=3)
+            .map(|day| {
+                (
+                    day.to_string(),
+                    vec![DayItem::Activity {
+                        time: "09:00:00".to_string(),
+                        activity_id: ObjectId::new(),
+                    }],
+                )
+            })
+            .collect();
+        FeaturedVacation {
+            days: Days { days },
+            length_days: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cancelled_refunded_and_late_bookings_stay_put() {
+        assert!(check_reschedulable(&booking(PaymentStatus::Confirmed, 96), now(), 72).is_ok());
+        assert!(check_reschedulable(&booking(PaymentStatus::Ongoing, 72), now(), 72).is_ok());
+        assert!(matches!(
+            check_reschedulable(&booking(PaymentStatus::Cancelled, 500), now(), 72),
+            Err(RescheduleError::NotReschedulable(PaymentStatus::Cancelled))
+        ));
+        assert!(matches!(
+            check_reschedulable(&booking(PaymentStatus::Refunded, 500), now(), 72),
+            Err(RescheduleError::NotReschedulable(PaymentStatus::Refunded))
+        ));
+        assert!(matches!(
+            check_reschedulable(&booking(PaymentStatus::Pending, 500), now(), 72),
+            Err(RescheduleError::PaymentPending)
+        ));
+        assert!(matches!(
+            check_reschedulable(&booking(PaymentStatus::Confirmed, 71), now(), 72),
+            Err(RescheduleError::InsideCutoff(72))
+        ));
+    }
+
+    #[test]
+    fn test_new_dates_must_match_itinerary_length() {
+        let itinerary = three_day_itinerary();
+        let current = booking(PaymentStatus::Confirmed, 500);
+        let arrival = now().timestamp_millis() + 30 * DAY_MILLIS;
+        let at = DateTime::from_millis;
+
+        let start = validate_dates(&itinerary, &current, at(arrival), at(arrival + 2 * DAY_MILLIS), now(), 72);
+        assert_eq!(start.unwrap(), calendar::utc_date(at(arrival)).unwrap());
+
+        let too_long = validate_dates(&itinerary, &current, at(arrival), at(arrival + 3 * DAY_MILLIS), now(), 72);
+        assert!(matches!(too_long, Err(RescheduleError::InvalidDates(_))));
+
+        let backwards = validate_dates(&itinerary, &current, at(arrival), at(arrival - DAY_MILLIS), now(), 72);
+        assert!(matches!(backwards, Err(RescheduleError::InvalidDates(_))));
+
+        // Inside the cutoff from today
+        let soon = now().timestamp_millis() + DAY_MILLIS;
+        let late = validate_dates(&itinerary, &current, at(soon), at(soon + 2 * DAY_MILLIS), now(), 72);
+        assert!(matches!(late, Err(RescheduleError::InvalidDates(_))));
+
+        let unchanged = validate_dates(
+            &itinerary,
+            &current,
+            current.arrival_datetime,
+            current.departure_datetime,
+            now(),
+            72,
+        );
+        assert!(matches!(unchanged, Err(RescheduleError::InvalidDates(_))));
+    }
+
+    #[test]
+    fn test_price_difference_charges_or_refunds_the_delta() {
+        let cost = PersonPrice::Stored(Money::from_dollars(450.0));
+        assert_eq!(price_difference(cost, 2, 90_000), Some(0));
+        assert_eq!(price_difference(cost, 2, 80_000), Some(10_000));
+        assert_eq!(price_difference(cost, 2, 95_000), Some(-5_000));
+        // Estimates are what the trip is sold at when nothing is stored
+        let estimate = PersonPrice::Estimated(Money::from_dollars(400.0));
+        assert_eq!(price_difference(estimate, 2, 90_000), Some(-10_000));
+        assert_eq!(price_difference(PersonPrice::Unavailable, 2, 95_000), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! Correcting booking statuses in bulk, for ops
+//!
+//! Each booking moves only if `PaymentStatus::can_transition_to` allows it, and
+//! only from the status it was read in, so a booking the payment webhooks or the
+//! trip status job move at the same time is reported rather than overwritten.
+//! Every change is written to the admin audit log. A confirmed booking that is
+//! cancelled or refunded gives its seats back; nothing is charged, refunded or
+//! emailed.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::services::availability_service::{AvailabilityCache, AvailabilityService};
+
+/// Most bookings one request can change
+pub const MAX_BULK_BOOKINGS: usize = 500;
+
+/// What happened to one booking
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum BookingStatusOutcome {
+    Updated { from: PaymentStatus },
+    Rejected { from: PaymentStatus, reason: String },
+    NotFound,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BookingStatusResult {
+    /// The booking, as a hex id
+    pub id: String,
+    #[serde(flatten)]
+    pub outcome: BookingStatusOutcome,
+}
+
+/// One status change, kept in the admin audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookingStatusAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub booking_id: ObjectId,
+    pub from: PaymentStatus,
+    pub to: PaymentStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub created_at: DateTime,
+}
+
+/// Why a booking in `from` can't be set to `to`, if it can't
+pub fn rejection(from: &PaymentStatus, to: &PaymentStatus) -> Option<String> {
+    if from == to {
+        return Some("Booking already has this status".to_string());
+    }
+    if !from.can_transition_to(to) {
+        return Some(format!(
+            "Can't move a booking from {} to {}",
+            status_name(from),
+            status_name(to)
+        ));
+    }
+    None
+}
+
+fn status_name(status: &PaymentStatus) -> String {
+    mongodb::bson::to_bson(status)
+        .ok()
+        .and_then(|status| status.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", status))
+}
+
+pub struct BookingStatusService {
+    client: Arc<Client>,
+}
+
+impl BookingStatusService {
+    pub fn new(client: Arc<Client>) -> Self {
+        BookingStatusService { client }
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    /// Move each of `booking_ids` to `to` where that's allowed, reporting each
+    pub async fn bulk_update(
+        &self,
+        cache: &AvailabilityCache,
+        admin_id: ObjectId,
+        booking_ids: &[ObjectId],
+        to: PaymentStatus,
+        note: Option<String>,
+    ) -> Result<Vec<BookingStatusResult>, mongodb::error::Error> {
+        let found: HashMap<ObjectId, BookingDetails> = self
+            .bookings()
+            .find(doc! { "_id": { "$in": booking_ids } })
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .filter_map(|booking| booking.id.map(|id| (id, booking)))
+            .collect();
+        let target = mongodb::bson::to_bson(&to)?;
+
+        let mut results = Vec::with_capacity(booking_ids.len());
+        for booking_id in booking_ids {
+            let Some(booking) = found.get(booking_id) else {
+                results.push(BookingStatusResult {
+                    id: booking_id.to_hex(),
+                    outcome: BookingStatusOutcome::NotFound,
+                });
+                continue;
+            };
+            let from = booking.status.clone();
+            if let Some(reason) = rejection(&from, &to) {
+                results.push(BookingStatusResult {
+                    id: booking_id.to_hex(),
+                    outcome: BookingStatusOutcome::Rejected { from, reason },
+                });
+                continue;
+            }
+
+            let now = DateTime::now();
+            let updated = self
+                .bookings()
+                .update_one(
+                    doc! { "_id": booking_id, "status": mongodb::bson::to_bson(&from)? },
+                    doc! { "$set": { "status": target.clone(), "updated_at": now } },
+                )
+                .await?;
+            if updated.modified_count == 0 {
+                results.push(BookingStatusResult {
+                    id: booking_id.to_hex(),
+                    outcome: BookingStatusOutcome::Rejected {
+                        from,
+                        reason: "Booking status changed while updating; try again".to_string(),
+                    },
+                });
+                continue;
+            }
+            if matches!(to, PaymentStatus::Cancelled | PaymentStatus::Refunded) {
+                AvailabilityService::new(self.client.clone())
+                    .release_cancelled_booking(cache, booking)
+                    .await;
+            }
+
+            self.record(BookingStatusAudit {
+                id: None,
+                action: "booking_status_changed".to_string(),
+                admin_id,
+                booking_id: *booking_id,
+                from: from.clone(),
+                to: to.clone(),
+                note: note.clone(),
+                created_at: now,
+            })
+            .await;
+            results.push(BookingStatusResult {
+                id: booking_id.to_hex(),
+                outcome: BookingStatusOutcome::Updated { from },
+            });
+        }
+        Ok(results)
+    }
+
+    async fn record(&self, audit: BookingStatusAudit) {
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<BookingStatusAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for booking {}: {}", audit.booking_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finished_bookings_stay_finished() {
+        for from in [PaymentStatus::Cancelled, PaymentStatus::Refunded, PaymentStatus::Completed] {
+            assert!(rejection(&from, &PaymentStatus::Confirmed).is_some());
+        }
+        assert_eq!(
+            rejection(&PaymentStatus::Refunded, &PaymentStatus::Confirmed),
+            Some("Can't move a booking from refunded to confirmed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_trips_move_forward_only() {
+        assert_eq!(rejection(&PaymentStatus::Confirmed, &PaymentStatus::Completed), None);
+        assert_eq!(rejection(&PaymentStatus::InProgress, &PaymentStatus::Completed), None);
+        assert_eq!(rejection(&PaymentStatus::Ongoing, &PaymentStatus::Completed), None);
+        assert!(rejection(&PaymentStatus::InProgress, &PaymentStatus::Confirmed).is_some());
+        assert!(rejection(&PaymentStatus::Pending, &PaymentStatus::Completed).is_some());
+    }
+
+    #[test]
+    fn test_setting_the_same_status_is_rejected() {
+        assert_eq!(
+            rejection(&PaymentStatus::Completed, &PaymentStatus::Completed),
+            Some("Booking already has this status".to_string())
+        );
+    }
+
+    #[test]
+    fn test_outcomes_serialize_flat() {
+        let result = BookingStatusResult {
+            id: "abc".to_string(),
+            outcome: BookingStatusOutcome::Updated { from: PaymentStatus::InProgress },
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({ "id": "abc", "outcome": "updated", "from": "in_progress" })
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Calendar helpers for activity scheduling
+//!
+//! Operators commonly close on specific weekdays, on one-off dates, or on major
+//! holidays. This module answers "is this activity running on this date?" so that
+//! generation and the availability endpoint agree on the same rules.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::Serialize;
+
+use crate::models::activity::Activity;
+
+/// Why an activity is not running on a given date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClosureReason {
+    ClosedWeekday,
+    ClosedDate,
+    Holiday,
+    Blackout,
+}
+
+/// US federal holidays for a year, on their actual (not observed) dates
+pub fn us_federal_holidays(year: i32) -> Vec<NaiveDate> {
+    let fixed = |month, day| NaiveDate::from_ymd_opt(year, month, day);
+    let nth = |month, weekday, n| NaiveDate::from_weekday_of_month_opt(year, month, weekday, n);
+
+    // Memorial Day is the last Monday of May
+    let memorial_day = NaiveDate::from_ymd_opt(year, 5, 31).map(|end_of_may| {
+        let days_back = end_of_may.weekday().num_days_from_monday() as i64;
+        end_of_may - Duration::days(days_back)
+    });
+
+    [
+        fixed(1, 1),                  // New Year's Day
+        nth(1, Weekday::Mon, 3),      // Martin Luther King Jr. Day
+        nth(2, Weekday::Mon, 3),      // Washington's Birthday
+        memorial_day,                 // Memorial Day
+        fixed(6, 19),                 // Juneteenth
+        fixed(7, 4),                  // Independence Day
+        nth(9, Weekday::Mon, 1),      // Labor Day
+        nth(10, Weekday::Mon, 2),     // Columbus Day
+        fixed(11, 11),                // Veterans Day
+        nth(11, Weekday::Thu, 4),     // Thanksgiving
+        fixed(12, 25),                // Christmas Day
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+pub fn is_us_federal_holiday(date: NaiveDate) -> bool {
+    us_federal_holidays(date.year()).contains(&date)
+}
+
+/// Returns the reason an activity is closed on `date`, or `None` if it is running
+pub fn closure_reason(activity: &Activity, date: NaiveDate) -> Option<ClosureReason> {
+    if let Some(days) = &activity.operating_days {
+        if !days.contains(&date.weekday()) {
+            return Some(ClosureReason::ClosedWeekday);
+        }
+    }
+
+    if activity.closed_dates.contains(&date) {
+        return Some(ClosureReason::ClosedDate);
+    }
+
+    if activity.closed_on_holidays && is_us_federal_holiday(date) {
+        return Some(ClosureReason::Holiday);
+    }
+
+    if let Some(ranges) = &activity.blackout_date_ranges {
+        if ranges.iter().any(|range| range.covers(date)) {
+            return Some(ClosureReason::Blackout);
+        }
+    }
+
+    None
+}
+
+pub fn is_operating_on(activity: &Activity, date: NaiveDate) -> bool {
+    closure_reason(activity, date).is_none()
+}
+
+/// The UTC calendar date of a stored timestamp
+pub fn utc_date(datetime: mongodb::bson::DateTime) -> Option<NaiveDate> {
+    chrono::DateTime::from_timestamp_millis(datetime.timestamp_millis()).map(|dt| dt.date_naive())
+}
+
+/// Dates for each day of a trip, starting on `start`
+pub fn trip_dates(start: NaiveDate, days: u32) -> Vec<NaiveDate> {
+    (0..days as i64).map(|offset| start + Duration::days(offset)).collect()
+}
+
+/// Number of the given dates the activity is running on
+pub fn operating_day_count(activity: &Activity, dates: &[NaiveDate]) -> usize {
+    dates.iter().filter(|date| is_operating_on(activity, **date)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_us_federal_holidays_2025() {
+        let holidays = us_federal_holidays(2025);
+        assert_eq!(holidays.len(), 11);
+        assert!(holidays.contains(&NaiveDate::from_ymd_opt(2025, 1, 20).unwrap())); // MLK
+        assert!(holidays.contains(&NaiveDate::from_ymd_opt(2025, 5, 26).unwrap())); // Memorial
+        assert!(holidays.contains(&NaiveDate::from_ymd_opt(2025, 11, 27).unwrap())); // Thanksgiving
+        assert!(!is_us_federal_holiday(NaiveDate::from_ymd_opt(2025, 11, 28).unwrap()));
+    }
+
+    #[test]
+    fn test_trip_dates() {
+        let start = NaiveDate::from_ymd_opt(2025, 12, 30).unwrap();
+        let dates = trip_dates(start, 3);
+        assert_eq!(dates.last(), Some(&NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()));
+    }
+}
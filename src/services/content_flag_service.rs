@@ -0,0 +1,489 @@
+//! Traveler reports about content, and the admin queue that resolves them
+//!
+//! Reports land in `Account.ContentFlags`. When a piece of content reaches the
+//! configured number of open reports, every admin gets an email. Admins then
+//! dismiss the reports or take the content down, and the decision is written
+//! to the admin audit log. Who reported what never leaves the server.
+
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+
+use crate::models::{
+    account::User,
+    content_flag::{ContentFlag, ContentType, FlagReason, FlagResolution, FlagStatus, ReportInput},
+    itinerary::base::FeaturedVacation,
+};
+use crate::services::account_service::EmailService;
+
+/// Longest `details` kept with a report
+const MAX_DETAILS_CHARS: usize = 1_000;
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+
+/// How many reports a user may file and when admins hear about them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReportLimits {
+    pub per_user_per_hour: u32,
+    /// Open reports on one piece of content that email the admins; 0 never does
+    pub notify_threshold: u32,
+}
+
+impl Default for ReportLimits {
+    fn default() -> Self {
+        ReportLimits {
+            per_user_per_hour: 10,
+            notify_threshold: 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ContentFlagError {
+    ContentNotFound,
+    /// The user already has an open report on this content
+    AlreadyReported,
+    RateLimited,
+    /// No open reports to resolve
+    NothingToResolve,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ContentFlagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ContentFlagError::ContentNotFound => write!(f, "Content not found"),
+            ContentFlagError::AlreadyReported => write!(f, "You have already reported this"),
+            ContentFlagError::RateLimited => {
+                write!(f, "Too many reports, please try again later")
+            }
+            ContentFlagError::NothingToResolve => write!(f, "No open reports for this content"),
+            ContentFlagError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ContentFlagError {}
+
+impl From<mongodb::error::Error> for ContentFlagError {
+    fn from(e: mongodb::error::Error) -> Self {
+        ContentFlagError::DatabaseError(e.to_string())
+    }
+}
+
+/// Lets admins know content needs a look
+pub trait ModerationNotifier {
+    fn send_alert(
+        &self,
+        admin_email: &str,
+        content_title: &str,
+        content_id: ObjectId,
+        open_flags: u64,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// `None` when email isn't configured, so no alert goes out
+impl ModerationNotifier for Option<EmailService> {
+    fn send_alert(
+        &self,
+        admin_email: &str,
+        content_title: &str,
+        content_id: ObjectId,
+        open_flags: u64,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        let admin_email = admin_email.to_string();
+        let content_title = content_title.to_string();
+        async move {
+            let Some(service) = self else {
+                return Err("Email is not configured".to_string());
+            };
+            service
+                .send_content_flag_alert_email(&admin_email, &content_title, &content_id.to_hex(), open_flags)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportOutcome {
+    pub open_flags: u64,
+    /// Admins emailed because this report reached the threshold
+    pub admins_notified: usize,
+}
+
+/// Open reports about one piece of content, as the admin queue shows them
+#[derive(Debug, Serialize)]
+pub struct FlaggedContent {
+    pub content_type: ContentType,
+    pub content_id: String,
+    pub title: Option<String>,
+    pub open_flags: usize,
+    pub reasons: BTreeMap<FlagReason, usize>,
+    pub details: Vec<String>,
+    pub first_reported_at: String,
+    pub last_reported_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolveOutcome {
+    pub status: FlagStatus,
+    pub flags_resolved: u64,
+}
+
+/// A moderation decision, kept in the admin audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModerationAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub content_type: ContentType,
+    pub content_id: ObjectId,
+    pub resolution: FlagStatus,
+    pub flags_resolved: u64,
+    pub note: Option<String>,
+    pub created_at: DateTime,
+}
+
+/// Group open flags by content, most reported first. Reporters are left out.
+pub fn summarize(flags: &[ContentFlag]) -> Vec<FlaggedContent> {
+    let mut grouped: HashMap<(ContentType, ObjectId), Vec<&ContentFlag>> = HashMap::new();
+    for flag in flags {
+        grouped
+            .entry((flag.content_type, flag.content_id))
+            .or_default()
+            .push(flag);
+    }
+
+    let mut queue: Vec<(DateTime, FlaggedContent)> = grouped
+        .into_iter()
+        .map(|((content_type, content_id), flags)| {
+            let mut reasons = BTreeMap::new();
+            for flag in &flags {
+                *reasons.entry(flag.reason).or_insert(0) += 1;
+            }
+            let first = flags.iter().map(|flag| flag.created_at).min().unwrap_or_else(DateTime::now);
+            let last = flags.iter().map(|flag| flag.created_at).max().unwrap_or(first);
+            let summary = FlaggedContent {
+                content_type,
+                content_id: content_id.to_hex(),
+                title: None,
+                open_flags: flags.len(),
+                reasons,
+                details: flags.iter().filter_map(|flag| flag.details.clone()).collect(),
+                first_reported_at: first.try_to_rfc3339_string().unwrap_or_default(),
+                last_reported_at: last.try_to_rfc3339_string().unwrap_or_default(),
+            };
+            (first, summary)
+        })
+        .collect();
+    queue.sort_by(|(a_first, a), (b_first, b)| {
+        b.open_flags.cmp(&a.open_flags).then(a_first.cmp(b_first))
+    });
+    queue.into_iter().map(|(_, summary)| summary).collect()
+}
+
+pub struct ContentFlagService {
+    client: Arc<Client>,
+}
+
+impl ContentFlagService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ContentFlagService { client }
+    }
+
+    fn flags(&self) -> Collection<ContentFlag> {
+        self.client.database("Account").collection("ContentFlags")
+    }
+
+    fn itineraries(&self) -> Collection<FeaturedVacation> {
+        self.client.database("Itineraries").collection("Featured")
+    }
+
+    /// Title of content that is still up
+    async fn live_title(
+        &self,
+        content_type: ContentType,
+        content_id: ObjectId,
+    ) -> Result<String, ContentFlagError> {
+        match content_type {
+            ContentType::Itinerary => self
+                .itineraries()
+                .find_one(doc! { "_id": content_id, "taken_down_at": null })
+                .await?
+                .map(|itinerary| itinerary.trip_name)
+                .ok_or(ContentFlagError::ContentNotFound),
+        }
+    }
+
+    pub async fn report(
+        &self,
+        reporter_id: ObjectId,
+        content_type: ContentType,
+        content_id: ObjectId,
+        input: ReportInput,
+        limits: &ReportLimits,
+        notifier: &impl ModerationNotifier,
+    ) -> Result<ReportOutcome, ContentFlagError> {
+        let title = self.live_title(content_type, content_id).await?;
+        let content = doc! {
+            "content_type": mongodb::bson::to_bson(&content_type).unwrap_or_default(),
+            "content_id": content_id,
+        };
+
+        let mut already_reported = content.clone();
+        already_reported.insert("reporter_id", reporter_id);
+        already_reported.insert("status", "open");
+        if self.flags().find_one(already_reported).await?.is_some() {
+            return Err(ContentFlagError::AlreadyReported);
+        }
+
+        let now = DateTime::now();
+        let recent = self
+            .flags()
+            .count_documents(doc! {
+                "reporter_id": reporter_id,
+                "created_at": { "$gt": DateTime::from_millis(now.timestamp_millis() - HOUR_MILLIS) }
+            })
+            .await?;
+        if recent >= limits.per_user_per_hour as u64 {
+            return Err(ContentFlagError::RateLimited);
+        }
+
+        let reason = input.reason;
+        let details = input
+            .details
+            .map(|details| details.trim().chars().take(MAX_DETAILS_CHARS).collect::<String>())
+            .filter(|details| !details.is_empty());
+        self.flags()
+            .insert_one(ContentFlag {
+                id: None,
+                content_type,
+                content_id,
+                reporter_id,
+                reason,
+                details,
+                status: FlagStatus::Open,
+                created_at: now,
+                resolved_at: None,
+                resolved_by: None,
+            })
+            .await?;
+        println!("🚩 {:?} {} reported ({:?})", content_type, content_id, reason);
+
+        let mut open = content;
+        open.insert("status", "open");
+        let open_flags = self.flags().count_documents(open).await?;
+
+        // Only the report that reaches the threshold alerts, so admins hear once
+        let admins_notified = if limits.notify_threshold > 0 && open_flags == limits.notify_threshold as u64 {
+            self.notify_admins(&title, content_id, open_flags, notifier).await?
+        } else {
+            0
+        };
+
+        Ok(ReportOutcome {
+            open_flags,
+            admins_notified,
+        })
+    }
+
+    async fn notify_admins(
+        &self,
+        title: &str,
+        content_id: ObjectId,
+        open_flags: u64,
+        notifier: &impl ModerationNotifier,
+    ) -> Result<usize, ContentFlagError> {
+        let admins: Vec<User> = self
+            .client
+            .database("Account")
+            .collection::<User>("Users")
+            .find(doc! { "role": "admin" })
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut notified = 0;
+        for admin in admins {
+            match notifier.send_alert(&admin.email, title, content_id, open_flags).await {
+                Ok(()) => notified += 1,
+                Err(e) => eprintln!("Failed to alert admin {} about {}: {}", admin.email, content_id, e),
+            }
+        }
+        Ok(notified)
+    }
+
+    /// Open reports grouped by content, most reported first
+    pub async fn open_queue(&self) -> Result<Vec<FlaggedContent>, ContentFlagError> {
+        let flags: Vec<ContentFlag> = self
+            .flags()
+            .find(doc! { "status": "open" })
+            .await?
+            .try_collect()
+            .await?;
+        let mut queue = summarize(&flags);
+
+        let itinerary_ids: Vec<ObjectId> = flags
+            .iter()
+            .filter(|flag| flag.content_type == ContentType::Itinerary)
+            .map(|flag| flag.content_id)
+            .collect();
+        let titles: HashMap<String, String> = self
+            .client
+            .database("Itineraries")
+            .collection::<Document>("Featured")
+            .find(doc! { "_id": { "$in": itinerary_ids } })
+            .projection(doc! { "trip_name": 1 })
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .into_iter()
+            .filter_map(|itinerary| {
+                Some((
+                    itinerary.get_object_id("_id").ok()?.to_hex(),
+                    itinerary.get_str("trip_name").ok()?.to_string(),
+                ))
+            })
+            .collect();
+        for item in &mut queue {
+            item.title = titles.get(&item.content_id).cloned();
+        }
+        Ok(queue)
+    }
+
+    pub async fn resolve(
+        &self,
+        admin_id: ObjectId,
+        content_type: ContentType,
+        content_id: ObjectId,
+        action: FlagResolution,
+        note: Option<String>,
+    ) -> Result<ResolveOutcome, ContentFlagError> {
+        let open = doc! {
+            "content_type": mongodb::bson::to_bson(&content_type).unwrap_or_default(),
+            "content_id": content_id,
+            "status": "open",
+        };
+        if self.flags().count_documents(open.clone()).await? == 0 {
+            return Err(ContentFlagError::NothingToResolve);
+        }
+
+        let now = DateTime::now();
+        let status = match action {
+            FlagResolution::Dismiss => FlagStatus::Dismissed,
+            FlagResolution::TakeDown => {
+                match content_type {
+                    ContentType::Itinerary => {
+                        let result = self
+                            .itineraries()
+                            .update_one(
+                                doc! { "_id": content_id },
+                                doc! { "$set": { "taken_down_at": now } },
+                            )
+                            .await?;
+                        if result.matched_count == 0 {
+                            return Err(ContentFlagError::ContentNotFound);
+                        }
+                    }
+                }
+                FlagStatus::TakenDown
+            }
+        };
+
+        let result = self
+            .flags()
+            .update_many(
+                open,
+                doc! { "$set": {
+                    "status": mongodb::bson::to_bson(&status).unwrap_or_default(),
+                    "resolved_at": now,
+                    "resolved_by": admin_id,
+                } },
+            )
+            .await?;
+        println!(
+            "🛡️ {:?} {} resolved as {:?} by admin {} ({} reports)",
+            content_type, content_id, status, admin_id, result.modified_count
+        );
+
+        let audit = ModerationAudit {
+            id: None,
+            action: "content_flags_resolved".to_string(),
+            admin_id,
+            content_type,
+            content_id,
+            resolution: status,
+            flags_resolved: result.modified_count,
+            note,
+            created_at: now,
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<ModerationAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for {}: {}", content_id, e);
+        }
+
+        Ok(ResolveOutcome {
+            status,
+            flags_resolved: result.modified_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(content_id: ObjectId, reason: FlagReason, at: i64, details: Option<&str>) -> ContentFlag {
+        ContentFlag {
+            id: Some(ObjectId::new()),
+            content_type: ContentType::Itinerary,
+            content_id,
+            reporter_id: ObjectId::new(),
+            reason,
+            details: details.map(str::to_string),
+            status: FlagStatus::Open,
+            created_at: DateTime::from_millis(at),
+            resolved_at: None,
+            resolved_by: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_groups_by_content_without_reporters() {
+        let quiet = ObjectId::new();
+        let busy = ObjectId::new();
+        let flags = vec![
+            flag(quiet, FlagReason::Spam, 1_000, None),
+            flag(busy, FlagReason::Unsafe, 2_000, Some("Trail closed since spring")),
+            flag(busy, FlagReason::Unsafe, 3_000, None),
+            flag(busy, FlagReason::Inaccurate, 4_000, None),
+        ];
+
+        let queue = summarize(&flags);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0].content_id, busy.to_hex());
+        assert_eq!(queue[0].open_flags, 3);
+        assert_eq!(queue[0].reasons.get(&FlagReason::Unsafe), Some(&2));
+        assert_eq!(queue[0].details, vec!["Trail closed since spring".to_string()]);
+        assert_eq!(queue[1].content_id, quiet.to_hex());
+
+        let json = serde_json::to_string(&queue).unwrap();
+        assert!(!json.contains("reporter"));
+        for flag in &flags {
+            assert!(!json.contains(&flag.reporter_id.to_hex()));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::{DayItem, FeaturedVacation};
+use crate::models::money::Money;
+use crate::services::favorite_digest_service::{price_drop, FavoriteDigestService};
+use crate::services::pricing_service::PricingService;
+
+/// Itineraries re-priced per activity lookup unless `RECOMPUTE_COSTS_BATCH_SIZE` says otherwise
+pub const DEFAULT_RECOMPUTE_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Default, Serialize)]
+pub struct RecomputeSummary {
+    pub processed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+    pub flagged_for_review: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CostUpdate {
+    Unchanged,
+    Changed { old: Option<Money>, new: Money },
+    /// Some scheduled activities no longer exist, so the itinerary can't be priced
+    NeedsReview { missing: Vec<ObjectId> },
+}
+
+fn scheduled_activity_ids(itinerary: &FeaturedVacation) -> Vec<ObjectId> {
+    itinerary
+        .days
+        .days
+        .values()
+        .flatten()
+        .filter_map(|item| match item {
+            DayItem::Activity { activity_id, .. } => Some(*activity_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Decide how an itinerary's stored cost should change given current activity prices
+pub fn plan_cost_update(
+    itinerary: &FeaturedVacation,
+    activities: &HashMap<ObjectId, Activity>,
+) -> CostUpdate {
+    let mut missing: Vec<ObjectId> = Vec::new();
+    let mut priced = Vec::new();
+    for id in scheduled_activity_ids(itinerary) {
+        match activities.get(&id) {
+            Some(activity) => priced.push(activity.clone()),
+            None if !missing.contains(&id) => missing.push(id),
+            None => {}
+        }
+    }
+
+    if !missing.is_empty() {
+        return CostUpdate::NeedsReview { missing };
+    }
+
+    let new_cost = PricingService::calculate_cost(&itinerary.days.days, &priced);
+    match itinerary.person_cost {
+        Some(old)
+            if old == new_cost && !itinerary.needs_review && itinerary.defaulted_activity_ids.is_empty() =>
+        {
+            CostUpdate::Unchanged
+        }
+        old => CostUpdate::Changed { old, new: new_cost },
+    }
+}
+
+/// Re-price every itinerary from the current `Options.Activity` prices.
+///
+/// Itineraries are processed in batches of `batch_size`, with one activity lookup
+/// per batch. An itinerary that can't be read is counted as failed and skipped; a
+/// cursor error ends the run with that error.
+pub async fn recompute_person_costs(
+    client: Arc<Client>,
+    batch_size: usize,
+) -> Result<RecomputeSummary, mongodb::error::Error> {
+    let batch_size = batch_size.max(1);
+
+    let itineraries: Collection<FeaturedVacation> =
+        client.database("Itineraries").collection("Featured");
+    let activities: Collection<Activity> = client.database("Options").collection("Activity");
+    let digests = FavoriteDigestService::new(client.clone());
+
+    let mut summary = RecomputeSummary::default();
+    // Read raw so one malformed itinerary is skipped rather than ending the cursor
+    let mut cursor = itineraries.clone_with_type::<Document>().find(doc! {}).await?;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut batch_number = 0;
+
+    loop {
+        let next = cursor.try_next().await?;
+        let done = next.is_none();
+        if let Some(document) = next {
+            match mongodb::bson::from_document::<FeaturedVacation>(document) {
+                Ok(itinerary) => batch.push(itinerary),
+                Err(e) => {
+                    eprintln!("Skipping unreadable itinerary during cost recompute: {:?}", e);
+                    summary.failed += 1;
+                }
+            }
+        }
+
+        if batch.len() >= batch_size || (done && !batch.is_empty()) {
+            batch_number += 1;
+            process_batch(&itineraries, &activities, &digests, &batch, &mut summary).await?;
+            println!(
+                "💲 Cost recompute batch {}: {} processed so far ({} changed, {} flagged)",
+                batch_number, summary.processed, summary.changed, summary.flagged_for_review
+            );
+            batch.clear();
+        }
+
+        if done {
+            break;
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn process_batch(
+    itineraries: &Collection<FeaturedVacation>,
+    activities: &Collection<Activity>,
+    digests: &FavoriteDigestService,
+    batch: &[FeaturedVacation],
+    summary: &mut RecomputeSummary,
+) -> Result<(), mongodb::error::Error> {
+    let ids: HashSet<ObjectId> = batch.iter().flat_map(scheduled_activity_ids).collect();
+    let ids: Vec<ObjectId> = ids.into_iter().collect();
+
+    let current: HashMap<ObjectId, Activity> = activities
+        .find(doc! { "_id": { "$in": ids } })
+        .await?
+        .try_collect::<Vec<Activity>>()
+        .await?
+        .into_iter()
+        .filter_map(|activity| activity.id.map(|id| (id, activity)))
+        .collect();
+
+    for itinerary in batch {
+        summary.processed += 1;
+        let Some(itinerary_id) = itinerary.id else {
+            summary.failed += 1;
+            continue;
+        };
+
+        let mut cheaper = None;
+        let update = match plan_cost_update(itinerary, &current) {
+            CostUpdate::Unchanged => {
+                summary.unchanged += 1;
+                continue;
+            }
+            CostUpdate::Changed { old, new } => {
+                println!(
+                    "   💲 '{}' person_cost {} -> {}",
+                    itinerary.trip_name,
+                    old.map(|old| old.to_string()).unwrap_or_else(|| "unset".to_string()),
+                    new
+                );
+                summary.changed += 1;
+                cheaper = price_drop(old, Some(new));
+                doc! {
+                    "$set": { "person_cost": new.to_dollars(), "updated_at": DateTime::now() },
+                    "$unset": { "needs_review": "", "missing_activity_ids": "", "defaulted_activity_ids": "" },
+                }
+            }
+            CostUpdate::NeedsReview { missing } => {
+                println!(
+                    "   ⚠️  '{}' references {} missing activities, flagged for review",
+                    itinerary.trip_name,
+                    missing.len()
+                );
+                summary.flagged_for_review += 1;
+                doc! {
+                    "$set": {
+                        "needs_review": true,
+                        "missing_activity_ids": missing,
+                        "updated_at": DateTime::now(),
+                    },
+                }
+            }
+        };
+
+        match itineraries
+            .update_one(doc! { "_id": itinerary_id }, update)
+            .await
+        {
+            Ok(_) => {
+                if let Some(drop) = cheaper {
+                    digests.notify(itinerary_id, &itinerary.trip_name, vec![drop]).await;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to update costs for itinerary {}: {:?}", itinerary_id, e);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::itinerary::base::Days;
+
+    fn priced_activity(price_per_person: f32) -> Activity {
+        Activity {
+            description: "".to_string(),
+            price_per_person,
+            ..Activity::test("Tour")
+        }
+    }
+
+    fn itinerary_with(activity_ids: &[ObjectId], person_cost: Option<Money>) -> FeaturedVacation {
+        let items = activity_ids
+            .iter()
+            .map(|id| DayItem::Activity {
+                time: "09:00:00".to_string(),
+                activity_id: *id,
+            })
+            .collect();
+        FeaturedVacation {
+            days: Days {
+                days: HashMap::from([("1".to_string(), items)]),
+            },
+            person_cost,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_price_change_detected() {
+        let kayak = priced_activity(80.0);
+        let hike = priced_activity(40.0);
+        let itinerary = itinerary_with(
+            &[kayak.id.unwrap(), hike.id.unwrap()],
+            Some(Money::from_cents(10_000)),
+        );
+        let current = HashMap::from([(kayak.id.unwrap(), kayak), (hike.id.unwrap(), hike)]);
+
+        assert_eq!(
+            plan_cost_update(&itinerary, &current),
+            CostUpdate::Changed {
+                old: Some(Money::from_cents(10_000)),
+                new: Money::from_cents(12_000),
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_activity_flags_for_review() {
+        let kayak = priced_activity(80.0);
+        let deleted = ObjectId::new();
+        let itinerary = itinerary_with(&[kayak.id.unwrap(), deleted], Some(Money::from_cents(8_000)));
+        let current = HashMap::from([(kayak.id.unwrap(), kayak)]);
+
+        assert_eq!(
+            plan_cost_update(&itinerary, &current),
+            CostUpdate::NeedsReview { missing: vec![deleted] }
+        );
+    }
+
+    #[test]
+    fn test_defaulted_prices_are_cleared_once_repriced() {
+        let kayak = priced_activity(80.0);
+        let mut itinerary = itinerary_with(&[kayak.id.unwrap()], Some(Money::from_cents(8_000)));
+        itinerary.defaulted_activity_ids = vec![kayak.id.unwrap()];
+        let current = HashMap::from([(kayak.id.unwrap(), kayak)]);
+
+        // Same price, but the stored activity confirms it, so the flag is cleared
+        assert_eq!(
+            plan_cost_update(&itinerary, &current),
+            CostUpdate::Changed {
+                old: Some(Money::from_cents(8_000)),
+                new: Money::from_cents(8_000),
+            }
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Startup check that the Google credentials can actually reach Cloud Storage.
+//!
+//! `setup_credentials` only decides where credentials come from. A key file for the
+//! wrong project, or a service account without access to the bucket, otherwise goes
+//! unnoticed until the first request that uploads or lists images. The check lists
+//! one object from `ITINERARY_BUCKET`. `CREDENTIAL_CHECK` decides what a failure
+//! does: `off`, `warn` (the default in debug builds) or `require` (the default in
+//! release builds, where the server refuses to start).
+
+use google_cloud_storage::client::{Client, ClientConfig};
+use google_cloud_storage::http::{objects::list::ListObjectsRequest, Error as GcsError};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Longest the startup check waits for Cloud Storage
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialCheck {
+    Off,
+    /// Log a failure and start anyway
+    Warn,
+    /// Refuse to start when the check fails
+    Require,
+}
+
+impl Default for CredentialCheck {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            CredentialCheck::Warn
+        } else {
+            CredentialCheck::Require
+        }
+    }
+}
+
+impl FromStr for CredentialCheck {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(CredentialCheck::Off),
+            "warn" => Ok(CredentialCheck::Warn),
+            "require" => Ok(CredentialCheck::Require),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CredentialError {
+    /// No credentials could be loaded
+    NoCredentials(String),
+    /// Credentials loaded, but Google didn't accept them
+    Unauthorized(String),
+    /// The credentials work but can't list the bucket
+    Forbidden { bucket: String, detail: String },
+    BucketNotFound(String),
+    Unreachable(String),
+}
+
+impl std::fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CredentialError::NoCredentials(e) => write!(
+                f,
+                "No Google credentials found ({}). Set GOOGLE_APPLICATION_CREDENTIALS to a service account key file or attach a service account to the service.",
+                e
+            ),
+            CredentialError::Unauthorized(e) => write!(
+                f,
+                "Google rejected the service account credentials ({}). Check the key hasn't been revoked or rotated.",
+                e
+            ),
+            CredentialError::Forbidden { bucket, detail } => write!(
+                f,
+                "The service account can't list bucket '{}' ({}). Grant it Storage Object Admin on the bucket.",
+                bucket, detail
+            ),
+            CredentialError::BucketNotFound(bucket) => write!(
+                f,
+                "Bucket '{}' doesn't exist. Check ITINERARY_BUCKET and the project the credentials belong to.",
+                bucket
+            ),
+            CredentialError::Unreachable(e) => write!(f, "Couldn't reach Cloud Storage: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+fn classify(bucket: &str, error: GcsError) -> CredentialError {
+    match error {
+        GcsError::Response(response) => match response.code {
+            401 => CredentialError::Unauthorized(response.message),
+            403 => CredentialError::Forbidden {
+                bucket: bucket.to_string(),
+                detail: response.message,
+            },
+            404 => CredentialError::BucketNotFound(bucket.to_string()),
+            code => CredentialError::Unreachable(format!("{} {}", code, response.message)),
+        },
+        GcsError::TokenSource(e) => CredentialError::Unauthorized(e.to_string()),
+        GcsError::HttpClient(e) => CredentialError::Unreachable(e.to_string()),
+    }
+}
+
+/// An authenticated call that changes nothing. Cloud Storage in production; tests
+/// use fixed answers.
+pub trait BucketProbe {
+    fn list_one(&self, bucket: &str) -> impl Future<Output = Result<(), CredentialError>>;
+}
+
+/// Lists one object with the Application Default Credentials
+pub struct GcsProbe;
+
+impl BucketProbe for GcsProbe {
+    async fn list_one(&self, bucket: &str) -> Result<(), CredentialError> {
+        let config = ClientConfig::default()
+            .with_auth()
+            .await
+            .map_err(|e| CredentialError::NoCredentials(e.to_string()))?;
+        let request = ListObjectsRequest {
+            bucket: bucket.to_string(),
+            max_results: Some(1),
+            ..Default::default()
+        };
+        match tokio::time::timeout(CHECK_TIMEOUT, Client::new(config).list_objects(&request)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(classify(bucket, e)),
+            Err(_) => Err(CredentialError::Unreachable(format!(
+                "no answer after {}s",
+                CHECK_TIMEOUT.as_secs()
+            ))),
+        }
+    }
+}
+
+/// Whether there's anything to check: a bucket or a key file was configured
+pub fn storage_configured(get: impl Fn(&str) -> Option<String>) -> bool {
+    ["ITINERARY_BUCKET", "GOOGLE_APPLICATION_CREDENTIALS"]
+        .iter()
+        .any(|name| get(name).is_some_and(|value| !value.trim().is_empty()))
+}
+
+/// Check the credentials against `bucket` and log the outcome. Only a failure
+/// under `CredentialCheck::Require` is returned; a `None` bucket skips the check.
+pub async fn verify_credentials(
+    probe: &impl BucketProbe,
+    bucket: Option<&str>,
+    mode: CredentialCheck,
+) -> Result<(), CredentialError> {
+    let Some(bucket) = bucket else {
+        println!("   ➖ Cloud Storage isn't configured, skipping the credential check");
+        return Ok(());
+    };
+    if mode == CredentialCheck::Off {
+        println!("   ➖ Credential check is off");
+        return Ok(());
+    }
+
+    match probe.list_one(bucket).await {
+        Ok(()) => {
+            println!("   ✅ Google credentials can read bucket '{}'", bucket);
+            Ok(())
+        }
+        Err(e) if mode == CredentialCheck::Require => Err(e),
+        Err(e) => {
+            eprintln!("   ⚠️  {} Starting anyway; image uploads and listings will fail.", e);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    struct FixedProbe {
+        forbidden: bool,
+        calls: Cell<u32>,
+    }
+
+    impl BucketProbe for FixedProbe {
+        async fn list_one(&self, bucket: &str) -> Result<(), CredentialError> {
+            self.calls.set(self.calls.get() + 1);
+            if self.forbidden {
+                Err(CredentialError::Forbidden {
+                    bucket: bucket.to_string(),
+                    detail: "storage.objects.list denied".to_string(),
+                })
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn probe(forbidden: bool) -> FixedProbe {
+        FixedProbe { forbidden, calls: Cell::new(0) }
+    }
+
+    #[actix_rt::test]
+    async fn test_only_required_checks_stop_startup() {
+        let denied = probe(true);
+        let err = verify_credentials(&denied, Some("actota-itineraries"), CredentialCheck::Require)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("can't list bucket 'actota-itineraries'"));
+        assert!(verify_credentials(&denied, Some("actota-itineraries"), CredentialCheck::Warn).await.is_ok());
+        assert_eq!(denied.calls.get(), 2);
+
+        // Nothing to check, or checking turned off, never calls out
+        assert!(verify_credentials(&denied, None, CredentialCheck::Require).await.is_ok());
+        assert!(verify_credentials(&denied, Some("actota-itineraries"), CredentialCheck::Off).await.is_ok());
+        assert_eq!(denied.calls.get(), 2);
+
+        assert!(verify_credentials(&probe(false), Some("actota-itineraries"), CredentialCheck::Require).await.is_ok());
+    }
+
+    #[test]
+    fn test_modes_and_configuration() {
+        assert_eq!(" Require ".parse(), Ok(CredentialCheck::Require));
+        assert_eq!("off".parse(), Ok(CredentialCheck::Off));
+        assert!("strict".parse::<CredentialCheck>().is_err());
+        assert_eq!(CredentialCheck::default() == CredentialCheck::Warn, cfg!(debug_assertions));
+
+        assert!(storage_configured(|name| (name == "GOOGLE_APPLICATION_CREDENTIALS").then(|| "key.json".to_string())));
+        assert!(!storage_configured(|name| (name == "ITINERARY_BUCKET").then(|| " ".to_string())));
+        assert!(!storage_configured(|_| None));
+    }
+}
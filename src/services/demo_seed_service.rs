@@ -0,0 +1,417 @@
+//! Demo dataset for staging environments (only built with the `demo-tools` feature).
+//!
+//! Every document gets an `_id` derived from a fixed seed string, so re-running the
+//! seed replaces the same documents instead of creating duplicates.
+
+use chrono::Utc;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_document, DateTime, Document},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::config::DemoAccounts;
+use crate::models::account::{Favorite, User, UserRole};
+use crate::models::activity::{Activity, Address, Capacity, TimeSlot};
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::models::money::Money;
+use crate::services::storage::{BucketKind, StorageConfig, DEFAULT_STORAGE_URL};
+
+/// Deterministic ObjectId for a seed string (FNV-1a, prefixed with "demo")
+pub fn demo_id(seed: &str) -> ObjectId {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in seed.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    let mut bytes = [0u8; 12];
+    bytes[..4].copy_from_slice(b"demo");
+    bytes[4..].copy_from_slice(&hash.to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct SeedCounts {
+    pub created: u32,
+    pub updated: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SeedSummary {
+    pub activities: SeedCounts,
+    pub itineraries: SeedCounts,
+    pub users: SeedCounts,
+    pub bookings: SeedCounts,
+    pub favorites: SeedCounts,
+}
+
+struct DemoCity {
+    city: &'static str,
+    coordinates: [f64; 2],
+}
+
+const CITIES: [DemoCity; 4] = [
+    DemoCity { city: "Denver", coordinates: [-104.9903, 39.7392] },
+    DemoCity { city: "Boulder", coordinates: [-105.2705, 40.0150] },
+    DemoCity { city: "Colorado Springs", coordinates: [-104.8214, 38.8339] },
+    DemoCity { city: "Breckenridge", coordinates: [-106.0384, 39.4817] },
+];
+
+/// (seed, title, city index, types, price, minutes, first slot start)
+const ACTIVITIES: [(&str, &str, usize, &[&str], f32, u16, u32); 10] = [
+    ("activity-red-rocks", "Red Rocks Sunrise Hike", 0, &["hiking"], 45.0, 180, 6),
+    ("activity-denver-brewery", "RiNo Brewery Walk", 0, &["food", "tour"], 65.0, 150, 14),
+    ("activity-flatirons", "Flatirons Guided Climb", 1, &["climbing"], 129.0, 240, 8),
+    ("activity-boulder-creek", "Boulder Creek Tubing", 1, &["water", "tubing"], 35.0, 120, 11),
+    ("activity-garden-gods", "Garden of the Gods Jeep Tour", 2, &["atv", "tour"], 89.0, 120, 9),
+    ("activity-pikes-peak", "Pikes Peak Cog Railway", 2, &["sightseeing"], 72.5, 210, 10),
+    ("activity-royal-gorge", "Royal Gorge Whitewater Rafting", 2, &["rafting"], 110.0, 240, 9),
+    ("activity-breck-ski", "Breckenridge Ski Lesson", 3, &["skiing"], 199.0, 180, 9),
+    ("activity-breck-mine", "Country Boy Gold Mine Tour", 3, &["gold mine tours"], 32.0, 90, 10),
+    ("activity-breck-snowshoe", "Snowshoe & Hot Cocoa", 3, &["hiking", "winter"], 55.0, 120, 13),
+];
+
+/// (seed, trip name, city index, activity seeds by day)
+const ITINERARIES: [(&str, &str, usize, &[&[&str]]); 5] = [
+    (
+        "itinerary-denver-weekend",
+        "Denver Weekend Escape",
+        0,
+        &[&["activity-red-rocks", "activity-denver-brewery"], &["activity-flatirons"]],
+    ),
+    (
+        "itinerary-boulder-outdoors",
+        "Boulder Outdoors",
+        1,
+        &[&["activity-flatirons"], &["activity-boulder-creek"]],
+    ),
+    (
+        "itinerary-springs-adventure",
+        "Colorado Springs Adventure",
+        2,
+        &[
+            &["activity-garden-gods", "activity-pikes-peak"],
+            &["activity-royal-gorge"],
+        ],
+    ),
+    (
+        "itinerary-breck-winter",
+        "Breckenridge Winter Getaway",
+        3,
+        &[
+            &["activity-breck-ski"],
+            &["activity-breck-snowshoe", "activity-breck-mine"],
+        ],
+    ),
+    (
+        "itinerary-front-range",
+        "Front Range Highlights",
+        0,
+        &[
+            &["activity-red-rocks"],
+            &["activity-garden-gods"],
+            &["activity-breck-mine"],
+        ],
+    ),
+];
+
+fn demo_activities() -> Vec<Activity> {
+    ACTIVITIES
+        .iter()
+        .map(|(seed, title, city, types, price, minutes, start)| {
+            let end_minutes = start * 60 + *minutes as u32;
+            Activity {
+                id: Some(demo_id(seed)),
+                company: "Actota Demo Outfitters".to_string(),
+                company_id: "demo".to_string(),
+                booking_link: "https://actota.com/demo".to_string(),
+                online_booking_status: "available".to_string(),
+                guide: None,
+                title: title.to_string(),
+                description: format!("{} in {}, Colorado.", title, CITIES[*city].city),
+                activity_types: types.iter().map(|t| t.to_string()).collect(),
+                tags: vec!["demo".to_string()],
+                price_per_person: *price,
+                duration_minutes: *minutes,
+                daily_time_slots: vec![TimeSlot {
+                    start: format!("{:02}:00", start),
+                    end: format!("{:02}:{:02}", end_minutes / 60, end_minutes % 60),
+                }],
+                address: Address {
+                    street: "".to_string(),
+                    unit: "".to_string(),
+                    city: CITIES[*city].city.to_string(),
+                    state: "CO".to_string(),
+                    zip: "".to_string(),
+                    country: "USA".to_string(),
+                },
+                whats_included: vec!["Guide".to_string()],
+                weight_limit_lbs: None,
+                age_requirement: None,
+                height_requiremnt: None,
+                blackout_date_ranges: None,
+                operating_days: None,
+                closed_dates: Vec::new(),
+                closed_on_holidays: false,
+                location: None,
+                images: Vec::new(),
+                defaulted_fields: Vec::new(),
+                capacity: Capacity {
+                    minimum: 1,
+                    maximum: 12,
+                },
+                created_at: None,
+                updated_at: None,
+            }
+        })
+        .collect()
+}
+
+fn placeholder_image(storage: &StorageConfig, index: usize) -> String {
+    let object = format!("demo/placeholder-{}.jpg", index + 1);
+    match storage.bucket(BucketKind::ItineraryImages) {
+        Some(bucket) => bucket.object_url(&object),
+        None => format!("{}/actota-itineraries/{}", DEFAULT_STORAGE_URL, object),
+    }
+}
+
+fn demo_itineraries(activities: &[Activity], storage: &StorageConfig) -> Vec<Document> {
+    let now = DateTime::now();
+
+    ITINERARIES
+        .iter()
+        .enumerate()
+        .map(|(index, (seed, trip_name, city, days))| {
+            let mut day_docs = Document::new();
+            let mut person_cost = Money::ZERO;
+            for (day_index, activity_seeds) in days.iter().enumerate() {
+                let items: Vec<Document> = activity_seeds
+                    .iter()
+                    .enumerate()
+                    .map(|(slot, activity_seed)| {
+                        let activity_id = demo_id(activity_seed);
+                        person_cost += activities
+                            .iter()
+                            .find(|a| a.id == Some(activity_id))
+                            .map(Activity::price)
+                            .unwrap_or_default();
+                        doc! {
+                            "type": "activity",
+                            "time": format!("{:02}:00:00", 9 + slot * 4),
+                            "activity_id": activity_id,
+                        }
+                    })
+                    .collect();
+                day_docs.insert((day_index + 1).to_string(), items);
+            }
+
+            let location = doc! {
+                "city": CITIES[*city].city,
+                "state": "CO",
+                "coordinates": CITIES[*city].coordinates.to_vec(),
+            };
+
+            doc! {
+                "_id": demo_id(seed),
+                "trip_name": *trip_name,
+                "min_group": 1,
+                "max_group": 8,
+                "length_days": days.len() as i32,
+                "length_hours": (days.len() * 24) as i32,
+                "start_location": location.clone(),
+                "end_location": location,
+                "description": format!("A demo itinerary exploring {}.", CITIES[*city].city),
+                "days": day_docs,
+                "images": [placeholder_image(storage, index)],
+                "person_cost": person_cost.to_dollars(),
+                "tag": "demo",
+                "created_at": now,
+                "updated_at": now,
+            }
+        })
+        .collect()
+}
+
+fn demo_user(seed: &str, email: &str, password: &str, role: UserRole) -> User {
+    let now = Utc::now();
+
+    User {
+        id: Some(demo_id(seed)),
+        email: email.to_string(),
+        password: bcrypt::hash(password, bcrypt::DEFAULT_COST).unwrap_or_default(),
+        customer_id: None,
+        first_name: Some("Demo".to_string()),
+        last_name: Some(match role {
+            UserRole::Admin => "Admin".to_string(),
+            UserRole::User | UserRole::Operator => "Traveler".to_string(),
+        }),
+        phone_number: None,
+        phone_number_e164: None,
+        birth_date: None,
+        profile_picture: None,
+        last_signin: None,
+        last_signin_ip: None,
+        failed_signins: Some(0),
+        role: Some(role),
+        company_id: None,
+        preferred_currency: None,
+        preferred_units: None,
+        email_verified: false,
+        email_verified_at: None,
+        email_suppressed: false,
+        linked_accounts: Vec::new(),
+        notification: None,
+        notification_preferences: None,
+        created_at: Some(now),
+        updated_at: Some(now),
+    }
+}
+
+fn demo_bookings(user_id: ObjectId) -> Vec<BookingDetails> {
+    let day = 86_400_000;
+    let start = DateTime::now().timestamp_millis() / day * day + 30 * day;
+
+    [
+        ("booking-confirmed", "itinerary-denver-weekend", PaymentStatus::Confirmed, 0),
+        ("booking-pending", "itinerary-springs-adventure", PaymentStatus::Pending, 14),
+        ("booking-cancelled", "itinerary-breck-winter", PaymentStatus::Cancelled, 45),
+    ]
+    .into_iter()
+    .map(|(seed, itinerary_seed, status, offset_days)| {
+        let arrival = start + offset_days * day;
+        BookingDetails {
+            id: Some(demo_id(seed)),
+            user_id,
+            itinerary_id: demo_id(itinerary_seed),
+            customer_id: None,
+            transaction_id: None,
+            arrival_datetime: DateTime::from_millis(arrival),
+            departure_datetime: DateTime::from_millis(arrival + 2 * day),
+            status,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: None,
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: Some(DateTime::now()),
+            updated_at: Some(DateTime::now()),
+        }
+    })
+    .collect()
+}
+
+fn demo_favorites(user_id: ObjectId) -> Vec<Favorite> {
+    ["itinerary-boulder-outdoors", "itinerary-front-range"]
+        .into_iter()
+        .map(|itinerary_seed| Favorite {
+            id: Some(demo_id(&format!("favorite-{}", itinerary_seed))),
+            user_id,
+            itinerary_id: demo_id(itinerary_seed),
+            last_notified_price: None,
+            created_at: Some(Utc::now()),
+            updated_at: Some(Utc::now()),
+        })
+        .collect()
+}
+
+async fn upsert_all(
+    collection: Collection<Document>,
+    documents: Vec<Document>,
+) -> Result<SeedCounts, mongodb::error::Error> {
+    let mut counts = SeedCounts::default();
+    for document in documents {
+        let id = document.get_object_id("_id").ok();
+        let result = collection
+            .replace_one(doc! { "_id": id }, document)
+            .upsert(true)
+            .await?;
+        if result.upserted_id.is_some() {
+            counts.created += 1;
+        } else {
+            counts.updated += 1;
+        }
+    }
+    Ok(counts)
+}
+
+fn to_documents<T: Serialize>(items: &[T]) -> Result<Vec<Document>, mongodb::error::Error> {
+    items
+        .iter()
+        .map(|item| to_document(item).map_err(mongodb::error::Error::from))
+        .collect()
+}
+
+/// Create or refresh the demo dataset, with placeholder images under the itinerary bucket
+pub async fn seed_demo_data(
+    client: Arc<Client>,
+    accounts: &DemoAccounts,
+    storage: &StorageConfig,
+) -> Result<SeedSummary, mongodb::error::Error> {
+    let activities = demo_activities();
+    let itineraries = demo_itineraries(&activities, storage);
+    let admin = demo_user("user-admin", &accounts.admin_email, &accounts.password, UserRole::Admin);
+    let traveler = demo_user("user-traveler", &accounts.user_email, &accounts.password, UserRole::User);
+    let traveler_id = demo_id("user-traveler");
+
+    let summary = SeedSummary {
+        activities: upsert_all(
+            client.database("Options").collection("Activity"),
+            to_documents(&activities)?,
+        )
+        .await?,
+        itineraries: upsert_all(
+            client.database("Itineraries").collection("Featured"),
+            itineraries,
+        )
+        .await?,
+        users: upsert_all(
+            client.database("Account").collection("Users"),
+            to_documents(&[admin, traveler])?,
+        )
+        .await?,
+        bookings: upsert_all(
+            client.database("Account").collection("Bookings"),
+            to_documents(&demo_bookings(traveler_id))?,
+        )
+        .await?,
+        favorites: upsert_all(
+            client.database("Account").collection("Favorites"),
+            to_documents(&demo_favorites(traveler_id))?,
+        )
+        .await?,
+    };
+
+    println!("🌱 Demo data seeded: {:?}", summary);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demo_ids_are_deterministic() {
+        assert_eq!(demo_id("activity-red-rocks"), demo_id("activity-red-rocks"));
+        assert_ne!(demo_id("activity-red-rocks"), demo_id("activity-flatirons"));
+    }
+
+    #[test]
+    fn test_itinerary_days_reference_seeded_activities() {
+        let activities = demo_activities();
+        for itinerary in demo_itineraries(&activities) {
+            let days = itinerary.get_document("days").unwrap();
+            for (_, items) in days {
+                for item in items.as_array().unwrap() {
+                    let activity_id = item.as_document().unwrap().get_object_id("activity_id").unwrap();
+                    assert!(activities.iter().any(|a| a.id == Some(activity_id)));
+                }
+            }
+        }
+    }
+}
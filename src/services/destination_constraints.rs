@@ -0,0 +1,292 @@
+//! Trip length and season rules for destinations, such as ski trips that only run
+//! in winter or a remote park that needs at least three nights.
+//!
+//! Rules are stored in `Itineraries.DestinationConstraints`, one document per city
+//! or state, optionally limited to some activities:
+//!
+//! ```json
+//! { "city": "Aspen", "state": "CO", "activities": ["Skiing"],
+//!   "season_months": [12, 1, 2, 3, 4], "min_nights": 3 }
+//! ```
+//!
+//! Searches that break a rule are rejected with the reason, and generation never
+//! runs for them.
+
+use chrono::{Datelike, NaiveDate};
+use futures::TryStreamExt;
+use mongodb::{bson::oid::ObjectId, bson::doc, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::db::mongo::read_only_collection;
+use crate::models::api_error::ApiError;
+use crate::models::search::SearchItinerary;
+use crate::services::itinerary_generation_service::ItineraryGenerator;
+use crate::services::location_terms::{self, resolve_state, LocationTerm};
+
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October",
+    "November", "December",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DestinationConstraint {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// Absent for a rule covering the whole state
+    #[serde(default)]
+    pub city: Option<String>,
+    /// Code or name
+    pub state: String,
+    /// Only searches for one of these activities are held to the rule. Empty means every search.
+    #[serde(default)]
+    pub activities: Vec<String>,
+    /// Months, 1 to 12, the trip has to fall in. Empty means all year.
+    #[serde(default)]
+    pub season_months: Vec<u32>,
+    #[serde(default)]
+    pub min_nights: Option<u32>,
+}
+
+impl DestinationConstraint {
+    /// "Aspen, CO", or the state alone
+    fn destination(&self) -> String {
+        match &self.city {
+            Some(city) => format!("{}, {}", city, self.state),
+            None => self.state.clone(),
+        }
+    }
+
+    /// Whether a search for `terms` and `activities` is held to this rule
+    fn applies_to(&self, terms: &[LocationTerm], activities: &[String]) -> bool {
+        let Some(state) = resolve_state(&self.state) else {
+            return false;
+        };
+        let place_matches = terms.iter().any(|term| match (term, &self.city) {
+            (LocationTerm::State(searched), None) => searched.code == state.code,
+            (LocationTerm::City { city, .. }, Some(rule_city)) => {
+                city.eq_ignore_ascii_case(rule_city.trim()) && term.state().is_none_or(|searched| searched.code == state.code)
+            }
+            (LocationTerm::City { .. }, None) => term.state().is_some_and(|searched| searched.code == state.code),
+            (LocationTerm::State(_), Some(_)) => false,
+        });
+        place_matches
+            && (self.activities.is_empty()
+                || self
+                    .activities
+                    .iter()
+                    .any(|rule| activities.iter().any(|searched| searched.trim().eq_ignore_ascii_case(rule.trim()))))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstraintViolation {
+    OutOfSeason { destination: String, season: String },
+    TooShort { destination: String, min_nights: u32, nights: i64 },
+}
+
+impl std::fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConstraintViolation::OutOfSeason { destination, season } => write!(
+                f,
+                "Trips to {} are only offered {}; pick dates in season",
+                destination, season
+            ),
+            ConstraintViolation::TooShort { destination, min_nights, nights } => write!(
+                f,
+                "Trips to {} need at least {} nights; this one is {}",
+                destination, min_nights, nights
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConstraintViolation {}
+
+impl ConstraintViolation {
+    pub fn to_api_error(&self) -> ApiError {
+        match self {
+            ConstraintViolation::OutOfSeason { .. } => ApiError {
+                field: Some("arrival_datetime".to_string()),
+                ..ApiError::new(self.to_string())
+            },
+            ConstraintViolation::TooShort { min_nights, .. } => ApiError {
+                field: Some("departure_datetime".to_string()),
+                limit: Some(*min_nights),
+                ..ApiError::new(self.to_string())
+            },
+        }
+    }
+}
+
+/// "December through April" for a run of months, wrapping over the new year;
+/// otherwise the months listed in calendar order
+pub fn season_description(months: &[u32]) -> String {
+    let mut months: Vec<u32> = months.iter().copied().filter(|month| (1..=12).contains(month)).collect();
+    months.sort();
+    months.dedup();
+    let name = |month: u32| MONTH_NAMES[month as usize - 1];
+
+    // A single run starts at the one month whose predecessor isn't in season
+    let starts: Vec<u32> = months
+        .iter()
+        .copied()
+        .filter(|month| !months.contains(&if *month == 1 { 12 } else { month - 1 }))
+        .collect();
+    match starts.as_slice() {
+        [start] if months.len() > 1 => {
+            let end = (start + months.len() as u32 - 2) % 12 + 1;
+            format!("{} through {}", name(*start), name(end))
+        }
+        _ => months.iter().map(|month| name(*month)).collect::<Vec<_>>().join(", "),
+    }
+}
+
+/// Every rule a trip from `arrival` to `departure` breaks. Both ends of the trip
+/// have to be in season.
+pub fn check_trip(
+    constraints: &[DestinationConstraint],
+    terms: &[LocationTerm],
+    activities: &[String],
+    arrival: NaiveDate,
+    departure: NaiveDate,
+) -> Vec<ConstraintViolation> {
+    let nights = (departure - arrival).num_days();
+    let mut violations = Vec::new();
+    for constraint in constraints.iter().filter(|constraint| constraint.applies_to(terms, activities)) {
+        let in_season = |date: NaiveDate| {
+            constraint.season_months.is_empty() || constraint.season_months.contains(&date.month())
+        };
+        if !in_season(arrival) || !in_season(departure) {
+            violations.push(ConstraintViolation::OutOfSeason {
+                destination: constraint.destination(),
+                season: season_description(&constraint.season_months),
+            });
+        }
+        if let Some(min_nights) = constraint.min_nights.filter(|min_nights| nights < *min_nights as i64) {
+            violations.push(ConstraintViolation::TooShort {
+                destination: constraint.destination(),
+                min_nights,
+                nights,
+            });
+        }
+    }
+    violations
+}
+
+/// The first rule `search` breaks, if any. Searches without both dates aren't checked.
+pub async fn check_search(
+    client: &Client,
+    search: &SearchItinerary,
+) -> Result<Option<ConstraintViolation>, mongodb::error::Error> {
+    let date = |datetime: &Option<String>| {
+        datetime
+            .as_deref()
+            .and_then(|datetime| ItineraryGenerator::parse_datetime(datetime).ok())
+            .map(|datetime| datetime.date())
+    };
+    let (Some(arrival), Some(departure)) = (date(&search.arrival_datetime), date(&search.departure_datetime)) else {
+        return Ok(None);
+    };
+    let terms = search.locations.as_deref().map(location_terms::parse_terms).unwrap_or_default();
+    if terms.is_empty() {
+        return Ok(None);
+    }
+
+    let constraints: Vec<DestinationConstraint> =
+        read_only_collection::<DestinationConstraint>(client, "Itineraries", "DestinationConstraints")
+            .find(doc! {})
+            .await?
+            .try_collect()
+            .await?;
+    let activities = search.activities.clone().unwrap_or_default();
+    Ok(check_trip(&constraints, &terms, &activities, arrival, departure).into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aspen_skiing() -> DestinationConstraint {
+        DestinationConstraint {
+            id: None,
+            city: Some("Aspen".to_string()),
+            state: "CO".to_string(),
+            activities: vec!["Skiing".to_string()],
+            season_months: vec![12, 1, 2, 3, 4],
+            min_nights: Some(3),
+        }
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn check(locations: &[&str], activities: &[&str], arrival: &str, departure: &str) -> Vec<ConstraintViolation> {
+        let locations: Vec<String> = locations.iter().map(|location| location.to_string()).collect();
+        let activities: Vec<String> = activities.iter().map(|activity| activity.to_string()).collect();
+        check_trip(
+            &[aspen_skiing()],
+            &location_terms::parse_terms(&locations),
+            &activities,
+            date(arrival),
+            date(departure),
+        )
+    }
+
+    #[test]
+    fn test_off_season_ski_search_is_refused() {
+        let violations = check(&["Aspen, CO"], &["skiing"], "2031-07-10", "2031-07-15");
+        assert_eq!(
+            violations,
+            vec![ConstraintViolation::OutOfSeason {
+                destination: "Aspen, CO".to_string(),
+                season: "December through April".to_string(),
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "Trips to Aspen, CO are only offered December through April; pick dates in season"
+        );
+
+        // A trip running past the end of the season is out too
+        assert_eq!(check(&["Aspen"], &["Skiing"], "2031-04-28", "2031-05-02").len(), 1);
+    }
+
+    #[test]
+    fn test_in_season_ski_search_needs_the_minimum_nights() {
+        assert!(check(&["Aspen, Colorado"], &["Skiing"], "2031-12-28", "2032-01-02").is_empty());
+        assert_eq!(
+            check(&["Aspen, CO"], &["Skiing"], "2031-01-10", "2031-01-12"),
+            vec![ConstraintViolation::TooShort {
+                destination: "Aspen, CO".to_string(),
+                min_nights: 3,
+                nights: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rules_only_cover_their_destination_and_activities() {
+        // Summer hiking in Aspen isn't a ski trip
+        assert!(check(&["Aspen, CO"], &["Hiking"], "2031-07-10", "2031-07-15").is_empty());
+        assert!(check(&["Denver, CO"], &["Skiing"], "2031-07-10", "2031-07-15").is_empty());
+        assert!(check(&["Aspen, WY"], &["Skiing"], "2031-07-10", "2031-07-15").is_empty());
+
+        let statewide = DestinationConstraint {
+            city: None,
+            activities: Vec::new(),
+            ..aspen_skiing()
+        };
+        let terms = location_terms::parse_terms(&["Colorado".to_string()]);
+        assert_eq!(check_trip(&[statewide], &terms, &[], date("2031-07-10"), date("2031-07-11")).len(), 2);
+    }
+
+    #[test]
+    fn test_season_descriptions() {
+        assert_eq!(season_description(&[12, 1, 2, 3, 4]), "December through April");
+        assert_eq!(season_description(&[6, 7, 8]), "June through August");
+        assert_eq!(season_description(&[1, 7]), "January, July");
+        assert_eq!(season_description(&[5]), "May");
+    }
+}
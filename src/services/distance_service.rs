@@ -14,6 +14,10 @@
 //! - Configurable cache durations (24h for static routes, 1h for traffic-aware)
 //! - Batch distance calculations for efficiency
 //! - Automatic fallback if API is unavailable
+//! - Haversine prefilter: pairs a short walk apart or too far apart to share a
+//!   day never reach the API (`DISTANCE_SHORT_CIRCUIT_MILES`, `DISTANCE_MAX_PAIR_MILES`)
+//! - Per-request budget: once a search's Maps lookups are spent, the rest of its
+//!   distances are straight-line estimates (`GENERATION_MAX_MAPS_LOOKUPS`)
 //!
 //! ## Cost Optimization
 //! - Results are cached in database to avoid repeated API calls
@@ -24,12 +28,115 @@
 use mongodb::{bson::oid::ObjectId, Client, Collection};
 use reqwest;
 use serde::{Deserialize, Serialize};
-use std::{env, sync::Arc, time::Duration};
+use std::{future::Future, sync::Arc, time::Duration};
+
+use crate::services::generation_budget::GenerationBudget;
 
 // Cache duration in seconds (24 hours for non-traffic, 1 hour for traffic-aware)
 const CACHE_DURATION_STATIC: i64 = 86400; // 24 hours
 const CACHE_DURATION_TRAFFIC: i64 = 3600; // 1 hour
 
+/// Great-circle distance between two `(lat, lng)` points
+pub fn haversine_miles(from: (f64, f64), to: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_MILES: f64 = 3959.0;
+
+    let lat1_rad = from.0.to_radians();
+    let lat2_rad = to.0.to_radians();
+    let delta_lat = (to.0 - from.0).to_radians();
+    let delta_lon = (to.1 - from.1).to_radians();
+
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + lat1_rad.cos() * lat2_rad.cos() * (delta_lon / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+    EARTH_RADIUS_MILES * c
+}
+
+/// Rough driving time for a straight-line distance
+pub fn straight_line_minutes(miles: f64) -> i64 {
+    const MINUTES_PER_MILE: f64 = 2.0;
+    (miles * MINUTES_PER_MILE) as i64
+}
+
+/// Where a distance came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceSource {
+    #[default]
+    GoogleMaps,
+    /// Decided from the straight-line distance without calling the API
+    HaversineShortcircuit,
+    /// Estimated from the straight-line distance because the request's lookups were spent
+    HaversineFallback,
+}
+
+/// Straight-line thresholds checked before paying for a Distance Matrix call.
+/// Configured with `DISTANCE_SHORT_CIRCUIT_MILES` and `DISTANCE_MAX_PAIR_MILES`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistancePrefilter {
+    /// Closer than this is treated as a short walk
+    pub short_circuit_miles: f64,
+    /// Farther than this is never put in the same day
+    pub max_pair_miles: f64,
+}
+
+impl Default for DistancePrefilter {
+    fn default() -> Self {
+        DistancePrefilter {
+            short_circuit_miles: 0.3,
+            max_pair_miles: 400.0,
+        }
+    }
+}
+
+impl DistancePrefilter {
+    /// The result for a pair that doesn't need the API, if any
+    pub fn check(&self, origin: (f64, f64), destination: (f64, f64)) -> Option<DistanceResult> {
+        const METERS_PER_MILE: f64 = 1609.344;
+        // A pair under the short-circuit threshold is treated as a five-minute walk
+        const SHORT_WALK_MINUTES: u32 = 5;
+
+        let miles = haversine_miles(origin, destination);
+        let distance_meters = (miles * METERS_PER_MILE) as u32;
+        if miles < self.short_circuit_miles {
+            Some(DistanceResult {
+                distance_meters,
+                duration_minutes: SHORT_WALK_MINUTES,
+                duration_in_traffic_minutes: None,
+                from_cache: false,
+                source: DistanceSource::HaversineShortcircuit,
+                too_far: false,
+            })
+        } else if miles > self.max_pair_miles {
+            Some(DistanceResult {
+                distance_meters,
+                duration_minutes: straight_line_minutes(miles) as u32,
+                duration_in_traffic_minutes: None,
+                from_cache: false,
+                source: DistanceSource::HaversineShortcircuit,
+                too_far: true,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Straight-line estimate for a pair the API wasn't asked about. Not cached, so a
+/// later request with budget to spare still gets the real figure.
+fn haversine_fallback(origin: (f64, f64), destination: (f64, f64)) -> DistanceResult {
+    const METERS_PER_MILE: f64 = 1609.344;
+
+    let miles = haversine_miles(origin, destination);
+    DistanceResult {
+        distance_meters: (miles * METERS_PER_MILE) as u32,
+        duration_minutes: straight_line_minutes(miles) as u32,
+        duration_in_traffic_minutes: None,
+        from_cache: false,
+        source: DistanceSource::HaversineFallback,
+        too_far: false,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedDistance {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -43,6 +150,10 @@ pub struct CachedDistance {
     pub duration_in_traffic_seconds: Option<u32>,
     pub travel_mode: String, // "driving", "walking", "transit", "bicycling"
     pub with_traffic: bool,
+    #[serde(default)]
+    pub source: DistanceSource,
+    #[serde(default)]
+    pub too_far: bool,
     pub cached_at: mongodb::bson::DateTime,
     pub expires_at: mongodb::bson::DateTime,
 }
@@ -101,29 +212,100 @@ pub struct DistanceResult {
     pub duration_minutes: u32,
     pub duration_in_traffic_minutes: Option<u32>,
     pub from_cache: bool,
+    pub source: DistanceSource,
+    /// Too far apart to visit on the same day; route optimization never pairs these
+    pub too_far: bool,
 }
 
-pub struct DistanceService {
-    client: Arc<Client>,
+impl From<CachedDistance> for DistanceResult {
+    fn from(cached: CachedDistance) -> Self {
+        DistanceResult {
+            distance_meters: cached.distance_meters,
+            duration_minutes: cached.duration_seconds / 60,
+            duration_in_traffic_minutes: cached.duration_in_traffic_seconds.map(|d| d / 60),
+            from_cache: true,
+            source: cached.source,
+            too_far: cached.too_far,
+        }
+    }
+}
+
+/// Where uncached distances come from. Google Maps in production; tests count calls.
+pub trait DistanceMatrix {
+    fn fetch(
+        &self,
+        origin: (f64, f64),
+        destination: (f64, f64),
+        travel_mode: &TravelMode,
+        with_traffic: bool,
+    ) -> impl Future<Output = Result<DistanceResult, Box<dyn std::error::Error>>>;
+
+    fn fetch_batch(
+        &self,
+        origins: Vec<(f64, f64)>,
+        destinations: Vec<(f64, f64)>,
+        travel_mode: &TravelMode,
+        with_traffic: bool,
+    ) -> impl Future<Output = Result<Vec<DistanceResult>, Box<dyn std::error::Error>>>;
+}
+
+/// The Google Maps Distance Matrix API
+pub struct GoogleMaps {
     http_client: reqwest::Client,
     api_key: String,
 }
 
-impl DistanceService {
-    pub fn new(client: Arc<Client>) -> Result<Self, Box<dyn std::error::Error>> {
-        let api_key = env::var("GOOGLE_MAPS_API_KEY")
-            .map_err(|_| "GOOGLE_MAPS_API_KEY environment variable not set")?;
+impl GoogleMaps {
+    pub fn new(api_key: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let api_key = api_key.ok_or("GOOGLE_MAPS_API_KEY not set")?.to_string();
 
         let http_client = reqwest::Client::builder()
             .timeout(Duration::from_secs(10))
             .build()?;
 
         Ok(Self {
-            client,
             http_client,
             api_key,
         })
     }
+}
+
+pub struct DistanceService<M = GoogleMaps> {
+    client: Arc<Client>,
+    matrix: M,
+    prefilter: DistancePrefilter,
+    budget: GenerationBudget,
+}
+
+impl DistanceService {
+    pub fn new(
+        client: Arc<Client>,
+        api_key: Option<&str>,
+        prefilter: DistancePrefilter,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::with_matrix(client, GoogleMaps::new(api_key)?, prefilter))
+    }
+}
+
+impl<M: DistanceMatrix> DistanceService<M> {
+    pub fn with_matrix(client: Arc<Client>, matrix: M, prefilter: DistancePrefilter) -> Self {
+        Self {
+            client,
+            matrix,
+            prefilter,
+            budget: GenerationBudget::unlimited(),
+        }
+    }
+
+    /// Draw API lookups from a request's budget; once it's spent, distances are estimated
+    pub fn with_budget(mut self, budget: GenerationBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn prefilter(&self) -> &DistancePrefilter {
+        &self.prefilter
+    }
 
     /// Get distance between two coordinates with smart caching
     pub async fn get_distance(
@@ -138,19 +320,21 @@ impl DistanceService {
             println!("Using cached distance for ({:.4}, {:.4}) to ({:.4}, {:.4})", 
                 origin.0, origin.1, destination.0, destination.1);
             
-            return Ok(DistanceResult {
-                distance_meters: cached.distance_meters,
-                duration_minutes: cached.duration_seconds / 60,
-                duration_in_traffic_minutes: cached.duration_in_traffic_seconds.map(|d| d / 60),
-                from_cache: true,
-            });
+            return Ok(cached.into());
         }
 
-        // Not in cache or expired, call Google Maps API
-        println!("Fetching distance from Google Maps API for ({:.4}, {:.4}) to ({:.4}, {:.4})", 
-            origin.0, origin.1, destination.0, destination.1);
-        
-        let result = self.fetch_from_google_maps(origin, destination, &travel_mode, with_traffic).await?;
+        // Not in cache or expired; a short walk or an impossible pair needs no API call
+        let result = match self.prefilter.check(origin, destination) {
+            Some(result) => result,
+            None if !self.budget.try_maps_lookups(1) => {
+                return Ok(haversine_fallback(origin, destination));
+            }
+            None => {
+                println!("Fetching distance from Google Maps API for ({:.4}, {:.4}) to ({:.4}, {:.4})", 
+                    origin.0, origin.1, destination.0, destination.1);
+                self.matrix.fetch(origin, destination, &travel_mode, with_traffic).await?
+            }
+        };
         
         // Cache the result
         if let Err(e) = self.cache_distance(origin, destination, &travel_mode, with_traffic, &result).await {
@@ -175,18 +359,25 @@ impl DistanceService {
         for (i, origin) in origins.iter().enumerate() {
             for (j, destination) in destinations.iter().enumerate() {
                 if let Ok(Some(cached)) = self.get_cached_distance(*origin, *destination, &travel_mode, with_traffic).await {
-                    results[i][j] = Some(DistanceResult {
-                        distance_meters: cached.distance_meters,
-                        duration_minutes: cached.duration_seconds / 60,
-                        duration_in_traffic_minutes: cached.duration_in_traffic_seconds.map(|d| d / 60),
-                        from_cache: true,
-                    });
+                    results[i][j] = Some(cached.into());
+                } else if let Some(result) = self.prefilter.check(*origin, *destination) {
+                    if let Err(e) = self.cache_distance(*origin, *destination, &travel_mode, with_traffic, &result).await {
+                        eprintln!("Failed to cache batch distance result: {}", e);
+                    }
+                    results[i][j] = Some(result);
                 } else {
                     missing_pairs.push((i, j, *origin, *destination));
                 }
             }
         }
 
+        // Over budget, the whole batch is estimated instead
+        if !missing_pairs.is_empty() && !self.budget.try_maps_lookups(missing_pairs.len() as u32) {
+            for (i, j, origin, destination) in missing_pairs.drain(..) {
+                results[i][j] = Some(haversine_fallback(origin, destination));
+            }
+        }
+
         // If we have missing pairs, make API calls
         if !missing_pairs.is_empty() {
             println!("Making batch API call for {} missing distance pairs", missing_pairs.len());
@@ -195,10 +386,10 @@ impl DistanceService {
             let batch_origins: Vec<(f64, f64)> = missing_pairs.iter().map(|(_, _, origin, _)| *origin).collect();
             let batch_destinations: Vec<(f64, f64)> = missing_pairs.iter().map(|(_, _, _, dest)| *dest).collect();
             
-            let api_results = self.fetch_batch_from_google_maps(batch_origins, batch_destinations, &travel_mode, with_traffic).await?;
+            let api_results = self.matrix.fetch_batch(batch_origins, batch_destinations, &travel_mode, with_traffic).await?;
             
             // Fill in the missing results and cache them
-            for ((i, j, origin, destination), api_result) in missing_pairs.into_iter().zip(api_results.into_iter()) {
+            for ((i, j, origin, destination), api_result) in missing_pairs.into_iter().zip(api_results) {
                 results[i][j] = Some(api_result.clone());
                 
                 // Cache the result
@@ -287,6 +478,8 @@ impl DistanceService {
             duration_in_traffic_seconds: result.duration_in_traffic_minutes.map(|d| d * 60),
             travel_mode: travel_mode.as_str().to_string(),
             with_traffic,
+            source: result.source,
+            too_far: result.too_far,
             cached_at: now,
             expires_at,
         };
@@ -295,8 +488,27 @@ impl DistanceService {
         Ok(())
     }
 
+    /// Clean up expired cache entries
+    pub async fn cleanup_expired_cache(&self) -> mongodb::error::Result<u64> {
+        let collection: Collection<CachedDistance> = self
+            .client
+            .database("Itineraries")
+            .collection("DistanceCache");
+
+        let filter = mongodb::bson::doc! {
+            "expires_at": { "$lt": mongodb::bson::DateTime::now() }
+        };
+
+        let result = collection.delete_many(filter).await?;
+        println!("Cleaned up {} expired distance cache entries", result.deleted_count);
+        
+        Ok(result.deleted_count)
+    }
+}
+
+impl DistanceMatrix for GoogleMaps {
     /// Fetch distance from Google Maps Distance Matrix API
-    async fn fetch_from_google_maps(
+    async fn fetch(
         &self,
         origin: (f64, f64),
         destination: (f64, f64),
@@ -346,11 +558,13 @@ impl DistanceService {
             duration_minutes: duration.value / 60,
             duration_in_traffic_minutes: element.duration_in_traffic.as_ref().map(|d| d.value / 60),
             from_cache: false,
+            source: DistanceSource::GoogleMaps,
+            too_far: false,
         })
     }
 
     /// Fetch multiple distances in a single API call (more efficient)
-    async fn fetch_batch_from_google_maps(
+    async fn fetch_batch(
         &self,
         origins: Vec<(f64, f64)>,
         destinations: Vec<(f64, f64)>,
@@ -409,6 +623,8 @@ impl DistanceService {
                         duration_minutes: duration.value / 60,
                         duration_in_traffic_minutes: element.duration_in_traffic.as_ref().map(|d| d.value / 60),
                         from_cache: false,
+                        source: DistanceSource::GoogleMaps,
+                        too_far: false,
                     });
                 } else {
                     return Err(format!("Google Maps batch element error: {}", element.status).into());
@@ -418,21 +634,143 @@ impl DistanceService {
 
         Ok(results)
     }
+}
 
-    /// Clean up expired cache entries
-    pub async fn cleanup_expired_cache(&self) -> mongodb::error::Result<u64> {
-        let collection: Collection<CachedDistance> = self
-            .client
-            .database("Itineraries")
-            .collection("DistanceCache");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cacheless_client, FixedMatrix};
+    use crate::services::generation_budget::BudgetCaps;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const DENVER: (f64, f64) = (39.7392, -104.9903);
+    const BOULDER: (f64, f64) = (40.0150, -105.2705);
+    const KANSAS_CITY: (f64, f64) = (39.0997, -94.5786);
+    /// About a tenth of a mile north of downtown Denver
+    const NEAR_DENVER: (f64, f64) = (39.7407, -104.9903);
+
+    /// Counts calls instead of reaching Google
+    #[derive(Default)]
+    struct CountingMatrix(AtomicUsize);
+
+    impl DistanceMatrix for CountingMatrix {
+        async fn fetch(
+            &self,
+            _origin: (f64, f64),
+            _destination: (f64, f64),
+            _travel_mode: &TravelMode,
+            _with_traffic: bool,
+        ) -> Result<DistanceResult, Box<dyn std::error::Error>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Err("no API in tests".into())
+        }
 
-        let filter = mongodb::bson::doc! {
-            "expires_at": { "$lt": mongodb::bson::DateTime::now() }
-        };
+        async fn fetch_batch(
+            &self,
+            _origins: Vec<(f64, f64)>,
+            _destinations: Vec<(f64, f64)>,
+            _travel_mode: &TravelMode,
+            _with_traffic: bool,
+        ) -> Result<Vec<DistanceResult>, Box<dyn std::error::Error>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Err("no API in tests".into())
+        }
+    }
 
-        let result = collection.delete_many(filter).await?;
-        println!("Cleaned up {} expired distance cache entries", result.deleted_count);
-        
-        Ok(result.deleted_count)
+    fn test_service() -> DistanceService<CountingMatrix> {
+        DistanceService::with_matrix(cacheless_client(), CountingMatrix::default(), DistancePrefilter::default())
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn test_spent_budget_switches_to_straight_line_estimates() {
+        let budget = GenerationBudget::new(BudgetCaps {
+            vertex_queries: 6,
+            maps_lookups: 2,
+        });
+        let service =
+            DistanceService::with_matrix(cacheless_client(), FixedMatrix::default(), DistancePrefilter::default())
+                .with_budget(budget.clone());
+
+        let mut sources = Vec::new();
+        for _ in 0..4 {
+            let result = service
+                .get_distance(DENVER, BOULDER, TravelMode::Driving, false)
+                .await
+                .unwrap();
+            sources.push(result.source);
+        }
+        assert_eq!(
+            sources,
+            [
+                DistanceSource::GoogleMaps,
+                DistanceSource::GoogleMaps,
+                DistanceSource::HaversineFallback,
+                DistanceSource::HaversineFallback,
+            ]
+        );
+        assert_eq!(service.matrix.0.load(Ordering::SeqCst), 2);
+
+        // A batch that no longer fits is estimated without calling the API
+        let batch = service
+            .get_distances_batch(vec![DENVER], vec![BOULDER], TravelMode::Driving, false)
+            .await
+            .unwrap();
+        assert_eq!(batch[0][0].source, DistanceSource::HaversineFallback);
+        assert_eq!(service.matrix.0.load(Ordering::SeqCst), 2);
+
+        let report = budget.report();
+        assert_eq!(report.maps_lookups, 2);
+        assert!(report.maps_exhausted);
+        assert!(!report.vertex_exhausted);
+    }
+
+    #[actix_rt::test]
+    async fn test_short_pair_skips_the_api() {
+        let service = test_service();
+        let result = service
+            .get_distance(DENVER, NEAR_DENVER, TravelMode::Driving, false)
+            .await
+            .unwrap();
+        assert_eq!(service.matrix.0.load(Ordering::SeqCst), 0);
+        assert_eq!(result.source, DistanceSource::HaversineShortcircuit);
+        assert_eq!(result.duration_minutes, 5);
+        assert!(!result.too_far);
+    }
+
+    #[actix_rt::test]
+    async fn test_far_pair_is_marked_without_the_api() {
+        let service = test_service();
+        let result = service
+            .get_distance(DENVER, KANSAS_CITY, TravelMode::Driving, true)
+            .await
+            .unwrap();
+        assert_eq!(service.matrix.0.load(Ordering::SeqCst), 0);
+        assert_eq!(result.source, DistanceSource::HaversineShortcircuit);
+        assert!(result.too_far);
+
+        // Anything in between still goes to the API
+        assert!(service
+            .get_distance(DENVER, BOULDER, TravelMode::Driving, false)
+            .await
+            .is_err());
+        assert_eq!(service.matrix.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_prefilter_thresholds() {
+        let miles = haversine_miles(DENVER, BOULDER);
+        assert!((miles - 24.0).abs() < 1.0, "Denver to Boulder is {} miles", miles);
+
+        assert!(DistancePrefilter::default().check(DENVER, BOULDER).is_none());
+        let wide = DistancePrefilter {
+            short_circuit_miles: 30.0,
+            max_pair_miles: 400.0,
+        };
+        assert!(wide.check(DENVER, BOULDER).is_some_and(|result| !result.too_far));
+        let tight = DistancePrefilter {
+            short_circuit_miles: 0.3,
+            max_pair_miles: 20.0,
+        };
+        assert!(tight.check(DENVER, BOULDER).is_some_and(|result| result.too_far));
+    }
+}
@@ -0,0 +1,479 @@
+//! Delivery events from SendGrid's signed event webhook, and the addresses we
+//! stop emailing because of them.
+//!
+//! Every event is kept in `Account.EmailEvents`, keyed by recipient and message
+//! category. Hard bounces and spam reports set `email_suppressed` on the matching
+//! user and newsletter records, and `EmailService` checks the flag before each
+//! send. Admins clear it once the address is fixed; changing the account email
+//! clears it too.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::services::oauth_link_service::normalize_email;
+
+pub const SIGNATURE_HEADER: &str = "X-Twilio-Email-Event-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Twilio-Email-Event-Webhook-Timestamp";
+
+/// Category recorded for messages sent without one
+pub const UNCATEGORIZED: &str = "uncategorized";
+
+/// DER header of a P-256 `SubjectPublicKeyInfo`, which SendGrid gives out; the
+/// uncompressed point follows it
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86,
+    0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    /// The configured key isn't a base64 P-256 public key
+    InvalidKey,
+    /// The signature header isn't base64
+    Malformed,
+    Mismatch,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SignatureError::InvalidKey => write!(f, "SENDGRID_WEBHOOK_PUBLIC_KEY is not a P-256 public key"),
+            SignatureError::Malformed => write!(f, "Signature is not base64"),
+            SignatureError::Mismatch => write!(f, "Signature does not match the payload"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// Check SendGrid's ECDSA signature over `timestamp` followed by the raw body.
+/// `public_key` is the verification key from the SendGrid settings, base64 or PEM.
+pub fn verify_signature(
+    public_key: &str,
+    signature: &str,
+    timestamp: &str,
+    payload: &[u8],
+) -> Result<(), SignatureError> {
+    let key: String = public_key
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+    let der = BASE64.decode(key).map_err(|_| SignatureError::InvalidKey)?;
+    let point = der
+        .strip_prefix(&P256_SPKI_PREFIX[..])
+        .filter(|point| point.len() == 65)
+        .ok_or(SignatureError::InvalidKey)?;
+    let signature = BASE64.decode(signature.trim()).map_err(|_| SignatureError::Malformed)?;
+
+    let mut signed = timestamp.as_bytes().to_vec();
+    signed.extend_from_slice(payload);
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, point)
+        .verify(&signed, &signature)
+        .map_err(|_| SignatureError::Mismatch)
+}
+
+/// One event as SendGrid posts it. Fields we don't use are ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SendGridEvent {
+    pub email: String,
+    pub event: String,
+    /// Unix seconds
+    pub timestamp: i64,
+    /// A string, or a list when the message had several
+    #[serde(default)]
+    pub category: Option<serde_json::Value>,
+    #[serde(default)]
+    pub sg_event_id: Option<String>,
+    #[serde(default)]
+    pub sg_message_id: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// `bounce` or `blocked` on bounce events
+    #[serde(default, rename = "type")]
+    pub bounce_type: Option<String>,
+}
+
+impl SendGridEvent {
+    fn category(&self) -> String {
+        let category = match &self.category {
+            Some(serde_json::Value::String(category)) => Some(category.as_str()),
+            Some(serde_json::Value::Array(categories)) => categories.first().and_then(|category| category.as_str()),
+            _ => None,
+        };
+        category.unwrap_or(UNCATEGORIZED).to_string()
+    }
+
+    /// Whether we should stop emailing the address: a hard bounce, which
+    /// SendGrid tells apart from a temporary block, or a spam report
+    pub fn suppresses(&self) -> bool {
+        match self.event.as_str() {
+            "bounce" => self.bounce_type.as_deref() != Some("blocked"),
+            "spamreport" => true,
+            _ => false,
+        }
+    }
+}
+
+/// Events that are kept. Opens, clicks and the like are dropped.
+const RECORDED_EVENTS: &[&str] = &["delivered", "bounce", "dropped", "spamreport", "unsubscribe"];
+
+/// A stored delivery event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// Normalized recipient
+    pub email: String,
+    pub category: String,
+    pub event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounce_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sg_event_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sg_message_id: Option<String>,
+    pub occurred_at: DateTime,
+    pub received_at: DateTime,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct IngestSummary {
+    pub recorded: usize,
+    /// Event types we don't keep, and SendGrid's retries of ones we already have
+    pub ignored: usize,
+    /// Addresses newly suppressed
+    pub suppressed: Vec<String>,
+}
+
+/// An address we no longer email
+#[derive(Debug, Clone, Serialize)]
+pub struct Suppression {
+    pub email: String,
+    /// The account with this email, if there is one
+    pub user_id: Option<ObjectId>,
+    /// Whether a newsletter signup is suppressed
+    pub newsletter: bool,
+    /// The most recent bounce or spam report for the address
+    pub last_event: Option<EmailEvent>,
+}
+
+/// A suppression an admin cleared, kept in the admin audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SuppressionClearedAudit {
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub email: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Clone)]
+pub struct EmailSuppressionService {
+    client: Arc<Client>,
+}
+
+impl EmailSuppressionService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    fn events(&self) -> Collection<EmailEvent> {
+        self.client.database("Account").collection("EmailEvents")
+    }
+
+    fn users(&self) -> Collection<Document> {
+        primary_collection(&self.client, "Account", "Users")
+    }
+
+    fn newsletter(&self) -> Collection<Document> {
+        primary_collection(&self.client, "Travelers", "Newsletter")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let by_recipient = IndexModel::builder().keys(doc! { "email": 1, "category": 1 }).build();
+        // SendGrid retries deliveries it isn't sure of; its event id keeps them from doubling up
+        let per_event = IndexModel::builder()
+            .keys(doc! { "sg_event_id": 1 })
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { "sg_event_id": { "$type": "string" } })
+                    .build(),
+            )
+            .build();
+        self.events().create_indexes([by_recipient, per_event]).await?;
+        Ok(())
+    }
+
+    /// Records for `email` whatever case it was stored in
+    fn email_filter(email: &str) -> Document {
+        let pattern = format!("^{}$", regex::escape(&normalize_email(email)));
+        doc! { "email": { "$regex": pattern, "$options": "i" } }
+    }
+
+    /// Store a batch of webhook events and act on them
+    pub async fn ingest(&self, events: Vec<SendGridEvent>) -> Result<IngestSummary, mongodb::error::Error> {
+        let mut summary = IngestSummary::default();
+        let received_at = DateTime::now();
+        for event in events {
+            if !RECORDED_EVENTS.contains(&event.event.as_str()) {
+                summary.ignored += 1;
+                continue;
+            }
+            let stored = EmailEvent {
+                id: None,
+                email: normalize_email(&event.email),
+                category: event.category(),
+                event: event.event.clone(),
+                reason: event.reason.clone(),
+                bounce_type: event.bounce_type.clone(),
+                sg_event_id: event.sg_event_id.clone(),
+                sg_message_id: event.sg_message_id.clone(),
+                occurred_at: DateTime::from_millis(event.timestamp.saturating_mul(1000)),
+                received_at,
+            };
+            match &event.sg_event_id {
+                Some(sg_event_id) => {
+                    let result = self
+                        .events()
+                        .update_one(
+                            doc! { "sg_event_id": sg_event_id },
+                            doc! { "$setOnInsert": mongodb::bson::to_document(&stored)? },
+                        )
+                        .upsert(true)
+                        .await?;
+                    if result.upserted_id.is_none() {
+                        summary.ignored += 1;
+                        continue;
+                    }
+                }
+                None => {
+                    self.events().insert_one(&stored).await?;
+                }
+            }
+            summary.recorded += 1;
+
+            if event.suppresses() {
+                if self.suppress(&event.email).await? && !summary.suppressed.contains(&stored.email) {
+                    summary.suppressed.push(stored.email.clone());
+                }
+            } else if event.event == "unsubscribe" {
+                self.newsletter()
+                    .update_many(Self::email_filter(&event.email), doc! { "$set": { "subscribed": false } })
+                    .await?;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Stop emailing `email`. Returns whether any record wasn't suppressed already.
+    pub async fn suppress(&self, email: &str) -> Result<bool, mongodb::error::Error> {
+        let mut filter = Self::email_filter(email);
+        filter.insert("email_suppressed", doc! { "$ne": true });
+        let update = doc! { "$set": { "email_suppressed": true } };
+        let users = self.users().update_many(filter.clone(), update.clone()).await?;
+        let newsletter = self.newsletter().update_many(filter, update).await?;
+        if users.modified_count + newsletter.modified_count > 0 {
+            println!("✉️  Suppressed {} after a bounce or spam report", normalize_email(email));
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Whether email to `email` should not be sent
+    pub async fn is_suppressed(&self, email: &str) -> Result<bool, mongodb::error::Error> {
+        let mut filter = Self::email_filter(email);
+        filter.insert("email_suppressed", true);
+        if self.users().count_documents(filter.clone()).limit(1).await? > 0 {
+            return Ok(true);
+        }
+        Ok(self.newsletter().count_documents(filter).limit(1).await? > 0)
+    }
+
+    /// Every suppressed address, with what suppressed it
+    pub async fn list(&self) -> Result<Vec<Suppression>, mongodb::error::Error> {
+        let mut suppressions: BTreeMap<String, Suppression> = BTreeMap::new();
+        let entry = |suppressions: &mut BTreeMap<String, Suppression>, email: &str| {
+            let email = normalize_email(email);
+            suppressions.entry(email.clone()).or_insert_with(|| Suppression {
+                email,
+                user_id: None,
+                newsletter: false,
+                last_event: None,
+            });
+        };
+
+        let users: Vec<Document> = self
+            .users()
+            .find(doc! { "email_suppressed": true })
+            .projection(doc! { "email": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        for user in users {
+            let Ok(email) = user.get_str("email") else { continue };
+            entry(&mut suppressions, email);
+            if let Some(suppression) = suppressions.get_mut(&normalize_email(email)) {
+                suppression.user_id = user.get_object_id("_id").ok();
+            }
+        }
+        let signups: Vec<Document> = self
+            .newsletter()
+            .find(doc! { "email_suppressed": true })
+            .projection(doc! { "email": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        for signup in signups {
+            let Ok(email) = signup.get_str("email") else { continue };
+            entry(&mut suppressions, email);
+            if let Some(suppression) = suppressions.get_mut(&normalize_email(email)) {
+                suppression.newsletter = true;
+            }
+        }
+
+        let emails: Vec<&String> = suppressions.keys().collect();
+        let events: Vec<EmailEvent> = self
+            .events()
+            .find(doc! {
+                "email": { "$in": emails },
+                "$or": [{ "event": "spamreport" }, { "event": "bounce", "bounce_type": { "$ne": "blocked" } }],
+            })
+            .sort(doc! { "occurred_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        for event in events {
+            if let Some(suppression) = suppressions.get_mut(&event.email) {
+                suppression.last_event = Some(event);
+            }
+        }
+        Ok(suppressions.into_values().collect())
+    }
+
+    /// Email `email` again. Returns whether it was suppressed.
+    pub async fn clear(&self, admin_id: ObjectId, email: &str) -> Result<bool, mongodb::error::Error> {
+        let mut filter = Self::email_filter(email);
+        filter.insert("email_suppressed", true);
+        let update = doc! { "$unset": { "email_suppressed": "" } };
+        let users = self.users().update_many(filter.clone(), update.clone()).await?;
+        let newsletter = self.newsletter().update_many(filter, update).await?;
+        if users.modified_count + newsletter.modified_count == 0 {
+            return Ok(false);
+        }
+
+        let audit = SuppressionClearedAudit {
+            action: "email_suppression_cleared".to_string(),
+            admin_id,
+            email: normalize_email(email),
+            created_at: DateTime::now(),
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<SuppressionClearedAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for clearing suppression of {}: {}", audit.email, e);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    fn key_pair() -> (EcdsaKeyPair, String) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let public_key = BASE64.encode([&P256_SPKI_PREFIX[..], pair.public_key().as_ref()].concat());
+        (pair, public_key)
+    }
+
+    fn sign(pair: &EcdsaKeyPair, timestamp: &str, payload: &[u8]) -> String {
+        let signed = [timestamp.as_bytes(), payload].concat();
+        BASE64.encode(pair.sign(&SystemRandom::new(), &signed).unwrap().as_ref())
+    }
+
+    #[test]
+    fn test_signature_over_timestamp_and_body() {
+        let (pair, public_key) = key_pair();
+        let payload = br#"[{"email":"a@example.com","event":"bounce","timestamp":1700000000}]"#;
+        let signature = sign(&pair, "1700000000", payload);
+
+        assert_eq!(verify_signature(&public_key, &signature, "1700000000", payload), Ok(()));
+        let pem = format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", public_key);
+        assert_eq!(verify_signature(&pem, &signature, "1700000000", payload), Ok(()));
+
+        // Another timestamp, or another key, doesn't verify
+        assert_eq!(
+            verify_signature(&public_key, &signature, "1700000001", payload),
+            Err(SignatureError::Mismatch)
+        );
+        let (_, other_key) = key_pair();
+        assert_eq!(
+            verify_signature(&other_key, &signature, "1700000000", payload),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(
+            verify_signature(&public_key, "not base64!", "1700000000", payload),
+            Err(SignatureError::Malformed)
+        );
+        assert_eq!(
+            verify_signature("c2VuZGdyaWQ=", &signature, "1700000000", payload),
+            Err(SignatureError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn test_tampered_payload_is_rejected() {
+        let (pair, public_key) = key_pair();
+        let payload = br#"[{"email":"a@example.com","event":"delivered","timestamp":1700000000}]"#;
+        let signature = sign(&pair, "1700000000", payload);
+        let tampered = br#"[{"email":"b@example.com","event":"delivered","timestamp":1700000000}]"#;
+
+        assert_eq!(
+            verify_signature(&public_key, &signature, "1700000000", tampered),
+            Err(SignatureError::Mismatch)
+        );
+    }
+
+    #[test]
+    fn test_only_hard_bounces_and_spam_reports_suppress() {
+        let event = |event: &str, bounce_type: Option<&str>| SendGridEvent {
+            email: "a@example.com".to_string(),
+            event: event.to_string(),
+            timestamp: 1_700_000_000,
+            category: Some(serde_json::json!(["booking_confirmation", "transactional"])),
+            sg_event_id: None,
+            sg_message_id: None,
+            reason: None,
+            bounce_type: bounce_type.map(str::to_string),
+        };
+
+        assert!(event("bounce", Some("bounce")).suppresses());
+        assert!(event("bounce", None).suppresses());
+        assert!(event("spamreport", None).suppresses());
+        assert!(!event("bounce", Some("blocked")).suppresses());
+        assert!(!event("dropped", None).suppresses());
+        assert!(!event("unsubscribe", None).suppresses());
+        assert_eq!(event("delivered", None).category(), "booking_confirmation");
+    }
+}
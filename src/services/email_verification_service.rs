@@ -0,0 +1,227 @@
+//! Ties email verification codes to the account they were issued for
+//!
+//! Codes live in `actota.email_verifications`, keyed by address. Verifying one
+//! through the account-scoped routes marks the user's *current* email verified;
+//! changing the email clears that again (see `User::set_email`) and, unless
+//! `EMAIL_CHANGE_SENDS_VERIFICATION` is off, sends a code to the new address.
+//! Codes are never part of a response.
+
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::models::account::User;
+use crate::services::account_service::{EmailService, EmailVerification};
+
+/// Sends a verification code to an account's address
+pub trait VerificationSender {
+    fn send_code(
+        &self,
+        client: &Client,
+        user_id: ObjectId,
+        email: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// `None` when email isn't configured, so no code goes out
+impl VerificationSender for Option<EmailService> {
+    fn send_code(
+        &self,
+        client: &Client,
+        user_id: ObjectId,
+        email: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        let email = email.to_string();
+        async move {
+            let Some(service) = self else {
+                return Err("Email is not configured".to_string());
+            };
+            service
+                .send_verification_html_email(&email, Some(user_id), client)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// A code waiting to be entered, without the code itself
+#[derive(Debug, Serialize)]
+pub struct PendingVerification {
+    pub id: String,
+    pub expires_at: String,
+    pub created_at: String,
+}
+
+/// What `GET /account/{id}/email-verifications` reports
+#[derive(Debug, Serialize)]
+pub struct EmailVerificationState {
+    pub email: String,
+    pub email_verified: bool,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// The latest unexpired code sent to the current email, if any
+    pub pending: Option<PendingVerification>,
+}
+
+/// What `GET /email-verifications/{id}/status` reports
+#[derive(Debug, Serialize, PartialEq)]
+pub struct VerificationStatus {
+    pub verified: bool,
+    pub expired: bool,
+    pub attempts_remaining: u32,
+    /// Zero once expired
+    pub seconds_until_expiry: i64,
+}
+
+/// Status of `verification` at `now`, out of `max_attempts` wrong codes allowed
+pub fn verification_status(
+    verification: &EmailVerification,
+    now: mongodb::bson::DateTime,
+    max_attempts: u32,
+) -> VerificationStatus {
+    let millis_left = verification.expires_at.timestamp_millis() - now.timestamp_millis();
+    VerificationStatus {
+        verified: verification.verified,
+        expired: !verification.verified && millis_left <= 0,
+        attempts_remaining: max_attempts.saturating_sub(verification.attempts),
+        seconds_until_expiry: (millis_left / 1000).max(0),
+    }
+}
+
+pub struct EmailVerificationService {
+    client: Arc<Client>,
+}
+
+impl EmailVerificationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        EmailVerificationService { client }
+    }
+
+    fn users(&self) -> Collection<User> {
+        self.client.database("Account").collection("Users")
+    }
+
+    fn verifications(&self) -> Collection<EmailVerification> {
+        self.client.database("actota").collection("email_verifications")
+    }
+
+    pub async fn find_user(&self, user_id: ObjectId) -> mongodb::error::Result<Option<User>> {
+        self.users().find_one(doc! { "_id": user_id }).await
+    }
+
+    pub async fn find_verification(
+        &self,
+        verification_id: ObjectId,
+    ) -> mongodb::error::Result<Option<EmailVerification>> {
+        self.verifications().find_one(doc! { "_id": verification_id }).await
+    }
+
+    /// Mark `email` verified if it is still the account's email. Returns when, or
+    /// `None` when the account has moved on to another address.
+    pub async fn mark_verified(
+        &self,
+        user_id: ObjectId,
+        email: &str,
+    ) -> mongodb::error::Result<Option<DateTime<Utc>>> {
+        let now = Utc::now();
+        let verified_at = mongodb::bson::to_bson(&now)?;
+        let result = self
+            .users()
+            .update_one(
+                doc! { "_id": user_id, "email": email },
+                doc! { "$set": { "email_verified": true, "email_verified_at": verified_at } },
+            )
+            .await?;
+        Ok((result.matched_count > 0).then_some(now))
+    }
+
+    /// Send a code to the user's new address after an email change
+    pub async fn email_changed(&self, user: &User, sender: &impl VerificationSender) -> bool {
+        let Some(user_id) = user.id else {
+            return false;
+        };
+        match sender.send_code(&self.client, user_id, &user.email).await {
+            Ok(()) => {
+                println!("📧 Verification code sent to new email for user {}", user_id);
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to send verification code to user {}: {}", user_id, e);
+                false
+            }
+        }
+    }
+
+    pub async fn state(&self, user: &User) -> mongodb::error::Result<EmailVerificationState> {
+        let pending = match user.id {
+            Some(user_id) => self
+                .verifications()
+                .find_one(doc! {
+                    "user_id": user_id,
+                    "email": &user.email,
+                    "verified": false,
+                    "expires_at": { "$gt": mongodb::bson::DateTime::now() }
+                })
+                .sort(doc! { "created_at": -1 })
+                .await?
+                .map(|verification| PendingVerification {
+                    id: verification.id.map(|id| id.to_hex()).unwrap_or_default(),
+                    expires_at: verification.expires_at.try_to_rfc3339_string().unwrap_or_default(),
+                    created_at: verification.created_at.try_to_rfc3339_string().unwrap_or_default(),
+                }),
+            None => None,
+        };
+        Ok(EmailVerificationState {
+            email: user.email.clone(),
+            email_verified: user.email_verified,
+            email_verified_at: user.email_verified_at,
+            pending,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::account_service::MAX_VERIFICATION_ATTEMPTS;
+    use mongodb::bson::DateTime;
+
+    fn verification(expires_in_secs: i64, attempts: u32, verified: bool) -> EmailVerification {
+        let now = DateTime::now().timestamp_millis();
+        EmailVerification {
+            id: Some(ObjectId::new()),
+            email: "traveler@example.com".to_string(),
+            user_id: None,
+            verification_code: "ABC123".to_string(),
+            expires_at: DateTime::from_millis(now + expires_in_secs * 1000),
+            verified,
+            created_at: DateTime::from_millis(now - 60_000),
+            attempts,
+        }
+    }
+
+    #[test]
+    fn test_status_counts_down_expiry_and_attempts() {
+        let pending = verification_status(&verification(600, 2, false), DateTime::now(), MAX_VERIFICATION_ATTEMPTS);
+        assert!(!pending.verified && !pending.expired);
+        assert_eq!(pending.attempts_remaining, MAX_VERIFICATION_ATTEMPTS - 2);
+        assert!((599..=600).contains(&pending.seconds_until_expiry));
+
+        let expired = verification_status(&verification(-30, 0, false), DateTime::now(), MAX_VERIFICATION_ATTEMPTS);
+        assert!(expired.expired);
+        assert_eq!(expired.seconds_until_expiry, 0);
+
+        let exhausted = verification_status(&verification(600, MAX_VERIFICATION_ATTEMPTS + 1, false), DateTime::now(), MAX_VERIFICATION_ATTEMPTS);
+        assert_eq!(exhausted.attempts_remaining, 0);
+        // The configured limit applies rather than the default
+        assert_eq!(verification_status(&verification(600, 2, false), DateTime::now(), 3).attempts_remaining, 1);
+
+        // A verified code doesn't count as expired once its time runs out
+        assert!(!verification_status(&verification(-30, 0, true), DateTime::now(), MAX_VERIFICATION_ATTEMPTS).expired);
+    }
+}
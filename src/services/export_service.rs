@@ -0,0 +1,250 @@
+//! Rows for the admin exports, read straight off MongoDB cursors for
+//! `services::streaming`
+
+use chrono::{Duration, NaiveDate};
+use futures::{Stream, StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::bookings::{BookingModification, Party, PaymentStatus};
+use crate::models::user::Newsletter;
+use crate::services::streaming::ExportRow;
+
+/// The booking fields an export needs, including `refund_amount`, which the
+/// cancellation flow writes but `BookingDetails` doesn't carry
+#[derive(Debug, Deserialize)]
+struct BookingRecord {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    user_id: ObjectId,
+    itinerary_id: ObjectId,
+    status: PaymentStatus,
+    arrival_datetime: DateTime,
+    departure_datetime: DateTime,
+    #[serde(default)]
+    transaction_id: Option<String>,
+    #[serde(default)]
+    gift_card_amount: Option<i64>,
+    #[serde(default)]
+    refund_amount: Option<i64>,
+    #[serde(default)]
+    modifications: Vec<BookingModification>,
+    #[serde(default)]
+    party: Option<Party>,
+    #[serde(default)]
+    created_by_admin: bool,
+    #[serde(default)]
+    special_requests: Option<String>,
+    created_at: Option<DateTime>,
+}
+
+/// One booking in `GET /admin/export/bookings`. Amounts are in cents.
+#[derive(Debug, Serialize)]
+pub struct BookingExportRow {
+    pub booking_id: String,
+    pub user_id: String,
+    pub itinerary_id: String,
+    /// The itinerary's name when the export ran
+    pub trip_name: Option<String>,
+    pub status: PaymentStatus,
+    pub arrival_datetime: String,
+    pub departure_datetime: String,
+    pub created_at: Option<String>,
+    pub party_size: Option<u32>,
+    pub transaction_id: Option<String>,
+    pub gift_card_amount: Option<i64>,
+    /// Net of every reschedule's charge or refund
+    pub reschedule_adjustments: i64,
+    pub refund_amount: Option<i64>,
+    pub created_by_admin: bool,
+    pub special_requests: Option<String>,
+}
+
+fn rfc3339(datetime: DateTime) -> String {
+    datetime.try_to_rfc3339_string().unwrap_or_default()
+}
+
+fn optional_cell<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+impl BookingExportRow {
+    fn new(record: BookingRecord, trip_names: &HashMap<ObjectId, String>) -> Self {
+        BookingExportRow {
+            booking_id: record.id.to_hex(),
+            user_id: record.user_id.to_hex(),
+            itinerary_id: record.itinerary_id.to_hex(),
+            trip_name: trip_names.get(&record.itinerary_id).cloned(),
+            status: record.status,
+            arrival_datetime: rfc3339(record.arrival_datetime),
+            departure_datetime: rfc3339(record.departure_datetime),
+            created_at: record.created_at.map(rfc3339),
+            party_size: record.party.map(|party| party.size()),
+            transaction_id: record.transaction_id,
+            gift_card_amount: record.gift_card_amount,
+            reschedule_adjustments: record
+                .modifications
+                .iter()
+                .map(|modification| modification.price_difference)
+                .sum(),
+            refund_amount: record.refund_amount,
+            created_by_admin: record.created_by_admin,
+            special_requests: record.special_requests,
+        }
+    }
+}
+
+impl ExportRow for BookingExportRow {
+    fn csv_header() -> &'static [&'static str] {
+        &[
+            "booking_id",
+            "user_id",
+            "itinerary_id",
+            "trip_name",
+            "status",
+            "arrival_datetime",
+            "departure_datetime",
+            "created_at",
+            "party_size",
+            "transaction_id",
+            "gift_card_amount",
+            "reschedule_adjustments",
+            "refund_amount",
+            "created_by_admin",
+            "special_requests",
+        ]
+    }
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.booking_id.clone(),
+            self.user_id.clone(),
+            self.itinerary_id.clone(),
+            self.trip_name.clone().unwrap_or_default(),
+            serde_json::to_value(&self.status)
+                .ok()
+                .and_then(|status| status.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            self.arrival_datetime.clone(),
+            self.departure_datetime.clone(),
+            self.created_at.clone().unwrap_or_default(),
+            optional_cell(&self.party_size),
+            self.transaction_id.clone().unwrap_or_default(),
+            optional_cell(&self.gift_card_amount),
+            self.reschedule_adjustments.to_string(),
+            optional_cell(&self.refund_amount),
+            self.created_by_admin.to_string(),
+            self.special_requests.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewsletterExportRow {
+    pub email: String,
+    pub subscribed: bool,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+impl From<Newsletter> for NewsletterExportRow {
+    fn from(subscriber: Newsletter) -> Self {
+        NewsletterExportRow {
+            email: subscriber.email,
+            subscribed: subscriber.subscribed.unwrap_or(false),
+            created_at: subscriber.created_at.map(|at| at.to_rfc3339()),
+            updated_at: subscriber.updated_at.map(|at| at.to_rfc3339()),
+        }
+    }
+}
+
+impl ExportRow for NewsletterExportRow {
+    fn csv_header() -> &'static [&'static str] {
+        &["email", "subscribed", "created_at", "updated_at"]
+    }
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.email.clone(),
+            self.subscribed.to_string(),
+            self.created_at.clone().unwrap_or_default(),
+            self.updated_at.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// Bookings created from the start of `from` through the end of `to`
+pub fn created_between(from: NaiveDate, to: NaiveDate) -> Document {
+    let start = from.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    let end = (to + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    doc! {
+        "created_at": {
+            "$gte": DateTime::from_millis(start.timestamp_millis()),
+            "$lt": DateTime::from_millis(end.timestamp_millis()),
+        }
+    }
+}
+
+pub struct ExportService {
+    client: Arc<Client>,
+}
+
+impl ExportService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ExportService { client }
+    }
+
+    /// Bookings created in the range, oldest first. Trip names are looked up once
+    /// for the itineraries in range, which are far fewer than the bookings.
+    pub async fn bookings(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> mongodb::error::Result<impl Stream<Item = mongodb::error::Result<BookingExportRow>>> {
+        let bookings = self
+            .client
+            .database("Account")
+            .collection::<BookingRecord>("Bookings");
+        let filter = created_between(from, to);
+
+        let itinerary_ids = bookings.distinct("itinerary_id", filter.clone()).await?;
+        let trip_names: HashMap<ObjectId, String> = self
+            .client
+            .database("Itineraries")
+            .collection::<Document>("Featured")
+            .find(doc! { "_id": { "$in": itinerary_ids } })
+            .projection(doc! { "trip_name": 1 })
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .into_iter()
+            .filter_map(|itinerary| {
+                Some((
+                    itinerary.get_object_id("_id").ok()?,
+                    itinerary.get_str("trip_name").ok()?.to_string(),
+                ))
+            })
+            .collect();
+
+        let cursor = bookings.find(filter).sort(doc! { "created_at": 1 }).await?;
+        Ok(cursor.map(move |record| record.map(|record| BookingExportRow::new(record, &trip_names))))
+    }
+
+    pub async fn newsletter(
+        &self,
+    ) -> mongodb::error::Result<impl Stream<Item = mongodb::error::Result<NewsletterExportRow>>> {
+        let cursor = self
+            .client
+            .database("Travelers")
+            .collection::<Newsletter>("Newsletter")
+            .find(doc! {})
+            .sort(doc! { "created_at": 1 })
+            .await?;
+        Ok(cursor.map(|subscriber| subscriber.map(NewsletterExportRow::from)))
+    }
+}
@@ -3,21 +3,24 @@ use oauth2::{
     ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
 use reqwest::Client as ReqwestClient;
-use std::env;
 use url::Url;
 
+use crate::config::OAuthClientSettings;
 use crate::models::facebook_auth::FacebookUserInfo;
 
-// Create a new OAuth client for Facebook
-pub fn create_facebook_oauth_client() -> BasicClient {
-    let facebook_client_id =
-        env::var("FACEBOOK_CLIENT_ID").expect("Missing FACEBOOK_CLIENT_ID environment variable");
-    let facebook_client_secret = env::var("FACEBOOK_CLIENT_SECRET")
-        .expect("Missing FACEBOOK_CLIENT_SECRET environment variable");
-    let facebook_redirect_url = env::var("FACEBOOK_REDIRECT_URI")
-        .expect("Missing FACEBOOK_REDIRECT_URI environment variable");
+// Create a new OAuth client for Facebook, or an error if it isn't fully configured
+pub fn create_facebook_oauth_client(settings: &OAuthClientSettings) -> Result<BasicClient, String> {
+    let (Some(facebook_client_id), Some(facebook_client_secret), Some(facebook_redirect_url)) = (
+        settings.client_id.clone(),
+        settings.client_secret.clone(),
+        settings.redirect_uri.clone(),
+    ) else {
+        return Err("Facebook sign-in is not configured".to_string());
+    };
+    let redirect_url = RedirectUrl::new(facebook_redirect_url)
+        .map_err(|e| format!("Invalid redirect URL: {}", e))?;
 
-    BasicClient::new(
+    Ok(BasicClient::new(
         ClientId::new(facebook_client_id),
         Some(ClientSecret::new(facebook_client_secret)),
         AuthUrl::new("https://www.facebook.com/v18.0/dialog/oauth".to_string())
@@ -27,7 +30,7 @@ pub fn create_facebook_oauth_client() -> BasicClient {
                 .expect("Invalid token endpoint URL"),
         ),
     )
-    .set_redirect_uri(RedirectUrl::new(facebook_redirect_url).expect("Invalid redirect URL"))
+    .set_redirect_uri(redirect_url))
 }
 
 // Generate an authorization URL for Facebook OAuth
@@ -0,0 +1,451 @@
+//! Weekly digest of changes to favorited itineraries
+//!
+//! When an admin change or the cost recompute makes an itinerary cheaper, or
+//! replaces its images or days, a pending entry is queued in
+//! `Account.FavoriteNotifications` for every user who favorited it and has
+//! marketing email on. A background job drains the queue per user into one
+//! digest email, sending each user at most one digest a week
+//! (`Account.FavoriteDigests`). Entries for a user who isn't due yet stay
+//! queued and go out in their next digest.
+//!
+//! This is separate from price alerts (`price_alert_service`), which are
+//! transactional and sent per favorite as soon as a drop passes the threshold.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::models::account::{Favorite, User};
+use crate::models::itinerary::base::FeaturedVacation;
+use crate::models::money::Money;
+use crate::services::account_service::{EmailService, EmailSettings};
+use crate::services::pricing_service::PersonPrice;
+
+const WEEK_MILLIS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// A change to a favorited itinerary worth telling the user about
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FavoriteChange {
+    PriceDrop { from: Money, to: Money },
+    ImagesUpdated,
+    DaysUpdated,
+}
+
+/// A price drop, if `new` is cheaper than `old`. Itineraries that weren't priced
+/// before have nothing to compare against.
+pub fn price_drop(old: Option<Money>, new: Option<Money>) -> Option<FavoriteChange> {
+    match (PersonPrice::stored(old).amount(), PersonPrice::stored(new).amount()) {
+        (Some(from), Some(to)) if to < from => Some(FavoriteChange::PriceDrop { from, to }),
+        _ => None,
+    }
+}
+
+/// What changed between two versions of an itinerary. Reordering images or
+/// picking a different hero image isn't a material change.
+pub fn material_changes(before: &FeaturedVacation, after: &FeaturedVacation) -> Vec<FavoriteChange> {
+    let mut changes: Vec<FavoriteChange> = price_drop(before.person_cost, after.person_cost)
+        .into_iter()
+        .collect();
+
+    let image_set = |itinerary: &FeaturedVacation| {
+        let mut images = itinerary.images.clone().unwrap_or_default();
+        images.sort();
+        images.dedup();
+        images
+    };
+    if image_set(before) != image_set(after) {
+        changes.push(FavoriteChange::ImagesUpdated);
+    }
+
+    if serde_json::to_value(&before.days).ok() != serde_json::to_value(&after.days).ok() {
+        changes.push(FavoriteChange::DaysUpdated);
+    }
+    changes
+}
+
+/// Whether a user whose last digest went out at `last_sent` can be sent another
+pub fn digest_due(last_sent: Option<DateTime>, now: DateTime) -> bool {
+    match last_sent {
+        Some(last_sent) => now.timestamp_millis() - last_sent.timestamp_millis() >= WEEK_MILLIS,
+        None => true,
+    }
+}
+
+/// A queued change for one user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FavoriteNotification {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub itinerary_id: ObjectId,
+    pub trip_name: String,
+    pub changes: Vec<FavoriteChange>,
+    pub created_at: DateTime,
+    /// Set once the change has gone out in a digest
+    #[serde(default)]
+    pub digest_id: Option<ObjectId>,
+}
+
+/// A digest that was sent, for the weekly cap
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FavoriteDigest {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub itinerary_ids: Vec<ObjectId>,
+    pub sent_at: DateTime,
+}
+
+/// One favorite in a digest, with everything that changed since the last one
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestItem {
+    pub itinerary_id: ObjectId,
+    pub trip_name: String,
+    /// Price before the first queued drop and after the last
+    pub price: Option<(Money, Money)>,
+    pub images_updated: bool,
+    pub days_updated: bool,
+}
+
+/// Fold a user's queued changes, oldest first, into one item per itinerary
+pub fn build_digest(notifications: &[FavoriteNotification]) -> Vec<DigestItem> {
+    let mut items: BTreeMap<ObjectId, DigestItem> = BTreeMap::new();
+    for notification in notifications {
+        let item = items.entry(notification.itinerary_id).or_insert_with(|| DigestItem {
+            itinerary_id: notification.itinerary_id,
+            trip_name: notification.trip_name.clone(),
+            price: None,
+            images_updated: false,
+            days_updated: false,
+        });
+        item.trip_name = notification.trip_name.clone();
+        for change in &notification.changes {
+            match change {
+                FavoriteChange::PriceDrop { from, to } => {
+                    let from = item.price.map(|(first, _)| first).unwrap_or(*from);
+                    item.price = Some((from, *to));
+                }
+                FavoriteChange::ImagesUpdated => item.images_updated = true,
+                FavoriteChange::DaysUpdated => item.days_updated = true,
+            }
+        }
+    }
+    items.into_values().collect()
+}
+
+/// Sends digest emails. Implemented for the real email service; tests substitute
+/// their own to see what would have been sent.
+pub trait DigestSender {
+    fn send_digest(
+        &self,
+        user_email: &str,
+        first_name: Option<&str>,
+        items: &[DigestItem],
+    ) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// `None` when email isn't configured, so digests stay queued
+impl DigestSender for Option<EmailService> {
+    fn send_digest(
+        &self,
+        user_email: &str,
+        first_name: Option<&str>,
+        items: &[DigestItem],
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        let user_email = user_email.to_string();
+        let first_name = first_name.map(str::to_string);
+        let items = items.to_vec();
+        async move {
+            let Some(service) = self else {
+                return Err("Email is not configured".to_string());
+            };
+            service
+                .send_favorites_digest_email(&user_email, first_name.as_deref(), &items)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DigestSummary {
+    pub sent: usize,
+    /// Users who had a digest in the last week; their changes wait for the next one
+    pub deferred: usize,
+    /// Users who turned marketing email off (or were deleted) since the changes were queued
+    pub opted_out: usize,
+    pub failed: usize,
+}
+
+pub struct FavoriteDigestService {
+    client: Arc<Client>,
+    email: EmailSettings,
+}
+
+impl FavoriteDigestService {
+    pub fn new(client: Arc<Client>) -> Self {
+        FavoriteDigestService {
+            client,
+            email: EmailSettings::default(),
+        }
+    }
+
+    /// Where digests are emailed from. Without it none are sent.
+    pub fn with_email(mut self, settings: &EmailSettings) -> Self {
+        self.email = settings.clone();
+        self
+    }
+
+    fn notifications(&self) -> Collection<FavoriteNotification> {
+        self.client.database("Account").collection("FavoriteNotifications")
+    }
+
+    fn digests(&self) -> Collection<FavoriteDigest> {
+        self.client.database("Account").collection("FavoriteDigests")
+    }
+
+    fn users(&self) -> Collection<User> {
+        self.client.database("Account").collection("Users")
+    }
+
+    /// Queue `changes` for everyone who favorited the itinerary and wants marketing
+    /// email. Returns how many users were queued.
+    pub async fn enqueue(
+        &self,
+        itinerary_id: ObjectId,
+        trip_name: &str,
+        changes: Vec<FavoriteChange>,
+    ) -> Result<usize, mongodb::error::Error> {
+        if changes.is_empty() {
+            return Ok(0);
+        }
+
+        let favorites: Collection<Favorite> = self.client.database("Account").collection("Favorites");
+        let user_ids = favorites
+            .distinct("user_id", doc! { "itinerary_id": itinerary_id })
+            .await?;
+        if user_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let users: Vec<User> = self
+            .users()
+            .find(doc! { "_id": { "$in": user_ids } })
+            .await?
+            .try_collect()
+            .await?;
+        let now = DateTime::now();
+        let queued: Vec<FavoriteNotification> = users
+            .iter()
+            .filter(|user| user.effective_notification_preferences().email.marketing)
+            .filter_map(|user| user.id)
+            .map(|user_id| FavoriteNotification {
+                id: None,
+                user_id,
+                itinerary_id,
+                trip_name: trip_name.to_string(),
+                changes: changes.clone(),
+                created_at: now,
+                digest_id: None,
+            })
+            .collect();
+
+        if !queued.is_empty() {
+            self.notifications().insert_many(&queued).await?;
+        }
+        Ok(queued.len())
+    }
+
+    /// `enqueue`, logging failures. Used from admin mutations and the cost
+    /// recompute, which shouldn't fail because a digest couldn't be queued.
+    pub async fn notify(&self, itinerary_id: ObjectId, trip_name: &str, changes: Vec<FavoriteChange>) {
+        match self.enqueue(itinerary_id, trip_name, changes).await {
+            Ok(0) => {}
+            Ok(queued) => println!("💌 '{}' changed, queued for {} favoriting users", trip_name, queued),
+            Err(e) => eprintln!("Failed to queue favorite notifications for {}: {}", itinerary_id, e),
+        }
+    }
+
+    /// Send one digest to every user with queued changes who hasn't had one this week
+    pub async fn send_digests(&self, sender: &impl DigestSender) -> Result<DigestSummary, mongodb::error::Error> {
+        let pending: Vec<FavoriteNotification> = self
+            .notifications()
+            .find(doc! { "digest_id": null })
+            .sort(doc! { "created_at": 1 })
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut by_user: HashMap<ObjectId, Vec<FavoriteNotification>> = HashMap::new();
+        for notification in pending {
+            by_user.entry(notification.user_id).or_default().push(notification);
+        }
+
+        let mut summary = DigestSummary::default();
+        let now = DateTime::now();
+        for (user_id, notifications) in by_user {
+            let ids: Vec<ObjectId> = notifications.iter().filter_map(|n| n.id).collect();
+
+            let last_sent = self
+                .digests()
+                .find_one(doc! { "user_id": user_id })
+                .sort(doc! { "sent_at": -1 })
+                .await?
+                .map(|digest| digest.sent_at);
+            if !digest_due(last_sent, now) {
+                summary.deferred += 1;
+                continue;
+            }
+
+            let user = self.users().find_one(doc! { "_id": user_id }).await?;
+            let Some(user) = user.filter(|user| user.effective_notification_preferences().email.marketing) else {
+                summary.opted_out += 1;
+                self.notifications().delete_many(doc! { "_id": { "$in": &ids } }).await?;
+                continue;
+            };
+
+            let items = build_digest(&notifications);
+            if let Err(e) = sender.send_digest(&user.email, user.first_name.as_deref(), &items).await {
+                // Left queued for the next run
+                eprintln!("Failed to send favorites digest to user {}: {}", user_id, e);
+                summary.failed += 1;
+                continue;
+            }
+
+            let digest = FavoriteDigest {
+                id: None,
+                user_id,
+                itinerary_ids: items.iter().map(|item| item.itinerary_id).collect(),
+                sent_at: now,
+            };
+            let digest_id = self.digests().insert_one(&digest).await?.inserted_id.as_object_id();
+            self.notifications()
+                .update_many(doc! { "_id": { "$in": &ids } }, doc! { "$set": { "digest_id": digest_id } })
+                .await?;
+            summary.sent += 1;
+        }
+
+        Ok(summary)
+    }
+
+    /// Drain the queue every `interval`, starting one interval from now
+    pub fn start(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                let email_service = match EmailService::new(self.client.clone(), &self.email) {
+                    Ok(service) => Some(service),
+                    Err(e) => {
+                        println!("Favorites digests won't be emailed this run: {}", e);
+                        None
+                    }
+                };
+                match self.send_digests(&email_service).await {
+                    Ok(summary) => println!("💌 Favorites digest run finished: {:?}", summary),
+                    Err(e) => eprintln!("Favorites digest run failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dollars(amount: f64) -> Money {
+        Money::from_dollars(amount)
+    }
+
+    fn queued(itinerary_id: ObjectId, trip_name: &str, changes: Vec<FavoriteChange>) -> FavoriteNotification {
+        FavoriteNotification {
+            id: Some(ObjectId::new()),
+            user_id: ObjectId::new(),
+            itinerary_id,
+            trip_name: trip_name.to_string(),
+            changes,
+            created_at: DateTime::now(),
+            digest_id: None,
+        }
+    }
+
+    #[test]
+    fn test_only_cheaper_prices_are_drops() {
+        assert_eq!(
+            price_drop(Some(dollars(500.0)), Some(dollars(450.0))),
+            Some(FavoriteChange::PriceDrop { from: dollars(500.0), to: dollars(450.0) })
+        );
+        assert_eq!(price_drop(Some(dollars(450.0)), Some(dollars(500.0))), None);
+        assert_eq!(price_drop(Some(dollars(450.0)), Some(dollars(450.0))), None);
+        assert_eq!(price_drop(None, Some(dollars(450.0))), None);
+        assert_eq!(price_drop(Some(dollars(450.0)), Some(dollars(0.0))), None);
+    }
+
+    #[test]
+    fn test_reordered_images_are_not_material() {
+        let before = FeaturedVacation {
+            images: Some(vec!["a.jpg".to_string(), "b.jpg".to_string()]),
+            person_cost: Some(dollars(300.0)),
+            ..Default::default()
+        };
+        let mut after = before.clone();
+        after.images = Some(vec!["b.jpg".to_string(), "a.jpg".to_string()]);
+        after.primary_image = Some("b.jpg".to_string());
+        assert!(material_changes(&before, &after).is_empty());
+
+        after.images = Some(vec!["c.jpg".to_string()]);
+        after.person_cost = Some(dollars(250.0));
+        assert_eq!(
+            material_changes(&before, &after),
+            vec![
+                FavoriteChange::PriceDrop { from: dollars(300.0), to: dollars(250.0) },
+                FavoriteChange::ImagesUpdated,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weekly_cap() {
+        let now = DateTime::now();
+        let six_days_ago = DateTime::from_millis(now.timestamp_millis() - 6 * 24 * 60 * 60 * 1000);
+        let eight_days_ago = DateTime::from_millis(now.timestamp_millis() - 8 * 24 * 60 * 60 * 1000);
+        assert!(digest_due(None, now));
+        assert!(!digest_due(Some(now), now));
+        assert!(!digest_due(Some(six_days_ago), now));
+        assert!(digest_due(Some(eight_days_ago), now));
+    }
+
+    #[test]
+    fn test_digest_groups_changes_per_favorite() {
+        let canyon = ObjectId::new();
+        let coast = ObjectId::new();
+        let items = build_digest(&[
+            queued(canyon, "Canyon Loop", vec![FavoriteChange::PriceDrop { from: dollars(900.0), to: dollars(850.0) }]),
+            queued(coast, "Coast Drive", vec![FavoriteChange::ImagesUpdated]),
+            queued(
+                canyon,
+                "Canyon Loop Deluxe",
+                vec![FavoriteChange::PriceDrop { from: dollars(850.0), to: dollars(800.0) }, FavoriteChange::DaysUpdated],
+            ),
+        ]);
+
+        assert_eq!(items.len(), 2);
+        let canyon_item = items.iter().find(|item| item.itinerary_id == canyon).unwrap();
+        assert_eq!(canyon_item.trip_name, "Canyon Loop Deluxe");
+        assert_eq!(canyon_item.price, Some((dollars(900.0), dollars(800.0))));
+        assert!(canyon_item.days_updated && !canyon_item.images_updated);
+
+        let coast_item = items.iter().find(|item| item.itinerary_id == coast).unwrap();
+        assert_eq!(coast_item.price, None);
+        assert!(coast_item.images_updated);
+    }
+}
@@ -0,0 +1,360 @@
+//! Flags for dark-launching behavior per environment without a redeploy.
+//!
+//! Every flag is declared in `Flag` with its default. The `current` document in
+//! `Options.FeatureFlags` can override any of them; each instance keeps the
+//! values in memory and re-reads the document every `REFRESH_INTERVAL`. Call
+//! sites read flags through the typed accessors on `Flags`.
+
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+const FLAGS_DOCUMENT_ID: &str = "current";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    /// `X-Generation-Trace` and `?verbose=true` on itinerary responses
+    SearchDebug,
+    /// Generate itineraries when a search finds too few good matches
+    ItineraryGeneration,
+    /// Save generated itineraries so they can be favorited and booked
+    PersistGeneratedItineraries,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 3] = [
+        Flag::SearchDebug,
+        Flag::ItineraryGeneration,
+        Flag::PersistGeneratedItineraries,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::SearchDebug => "search_debug",
+            Flag::ItineraryGeneration => "itinerary_generation",
+            Flag::PersistGeneratedItineraries => "persist_generated_itineraries",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Flag> {
+        Flag::ALL.into_iter().find(|flag| flag.name() == name)
+    }
+
+    pub fn default_value(self) -> bool {
+        match self {
+            Flag::SearchDebug => true,
+            Flag::ItineraryGeneration => true,
+            Flag::PersistGeneratedItineraries => true,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Flag::SearchDebug => "Honor X-Generation-Trace and ?verbose=true on itinerary responses",
+            Flag::ItineraryGeneration => "Generate itineraries when a search finds too few good matches",
+            Flag::PersistGeneratedItineraries => {
+                "Save generated itineraries; otherwise they're returned as ephemeral"
+            }
+        }
+    }
+}
+
+/// A value for every flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlagValues([bool; Flag::ALL.len()]);
+
+impl Default for FlagValues {
+    fn default() -> Self {
+        FlagValues(Flag::ALL.map(Flag::default_value))
+    }
+}
+
+impl FlagValues {
+    pub fn get(&self, flag: Flag) -> bool {
+        self.0[flag as usize]
+    }
+
+    pub fn set(&mut self, flag: Flag, enabled: bool) {
+        self.0[flag as usize] = enabled;
+    }
+
+    /// Defaults with the stored overrides applied. Names that aren't flags and
+    /// values that aren't booleans are left out with a warning, and returned.
+    pub fn with_overrides(overrides: &Document) -> (FlagValues, Vec<String>) {
+        let mut values = FlagValues::default();
+        let mut ignored = Vec::new();
+        for (name, value) in overrides {
+            match (Flag::from_name(name), value) {
+                (Some(flag), Bson::Boolean(enabled)) => values.set(flag, *enabled),
+                (Some(_), _) => {
+                    eprintln!("⚠️ Ignoring feature flag {}: {} isn't true or false", name, value);
+                    ignored.push(name.clone());
+                }
+                (None, _) => {
+                    eprintln!("⚠️ Ignoring unknown feature flag {}", name);
+                    ignored.push(name.clone());
+                }
+            }
+        }
+        (values, ignored)
+    }
+}
+
+/// A flag as listed by `GET /admin/feature-flags`
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagState {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: bool,
+    pub enabled: bool,
+    /// Whether the stored document sets this flag
+    pub overridden: bool,
+}
+
+pub fn flag_states(overrides: &Document) -> Vec<FlagState> {
+    let (values, _) = FlagValues::with_overrides(overrides);
+    Flag::ALL
+        .into_iter()
+        .map(|flag| FlagState {
+            name: flag.name(),
+            description: flag.description(),
+            default: flag.default_value(),
+            enabled: values.get(flag),
+            overridden: overrides.get_bool(flag.name()).is_ok(),
+        })
+        .collect()
+}
+
+/// The in-memory flag values, shared by every worker through `web::Data`
+pub struct Flags {
+    current: RwLock<FlagValues>,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::with_values(FlagValues::default())
+    }
+}
+
+impl Flags {
+    pub fn with_values(values: FlagValues) -> Self {
+        Flags {
+            current: RwLock::new(values),
+        }
+    }
+
+    fn enabled(&self, flag: Flag) -> bool {
+        self.current
+            .read()
+            .map(|values| values.get(flag))
+            .unwrap_or_else(|_| flag.default_value())
+    }
+
+    pub fn search_debug(&self) -> bool {
+        self.enabled(Flag::SearchDebug)
+    }
+
+    pub fn itinerary_generation(&self) -> bool {
+        self.enabled(Flag::ItineraryGeneration)
+    }
+
+    pub fn persist_generated_itineraries(&self) -> bool {
+        self.enabled(Flag::PersistGeneratedItineraries)
+    }
+
+    pub fn replace(&self, values: FlagValues) {
+        if let Ok(mut current) = self.current.write() {
+            *current = values;
+        }
+    }
+
+    /// Re-read the stored overrides. On failure the current values are kept.
+    pub async fn refresh(&self, client: &Arc<Client>) -> Result<(), mongodb::error::Error> {
+        let overrides = FeatureFlagService::new(client.clone()).overrides().await?;
+        self.replace(FlagValues::with_overrides(&overrides).0);
+        Ok(())
+    }
+
+    /// Load the stored overrides now and then every `refresh_interval`
+    pub fn start(client: Arc<Client>, refresh_interval: Duration) -> Arc<Self> {
+        let flags = Arc::new(Flags::default());
+        let shared = flags.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = shared.refresh(&client).await {
+                    eprintln!("Failed to refresh feature flags: {}", e);
+                }
+            }
+        });
+        flags
+    }
+}
+
+#[derive(Debug)]
+pub enum FeatureFlagError {
+    UnknownFlag(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for FeatureFlagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FeatureFlagError::UnknownFlag(name) => write!(f, "Unknown feature flag: {}", name),
+            FeatureFlagError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FeatureFlagError {}
+
+impl From<mongodb::error::Error> for FeatureFlagError {
+    fn from(err: mongodb::error::Error) -> Self {
+        FeatureFlagError::DatabaseError(err.to_string())
+    }
+}
+
+/// A change to the flags, kept in the admin audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeatureFlagAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    /// New value per flag; null puts the flag back to its default
+    pub changes: BTreeMap<String, Option<bool>>,
+    pub created_at: DateTime,
+}
+
+pub struct FeatureFlagService {
+    client: Arc<Client>,
+}
+
+impl FeatureFlagService {
+    pub fn new(client: Arc<Client>) -> Self {
+        FeatureFlagService { client }
+    }
+
+    fn flags(&self) -> Collection<Document> {
+        self.client.database("Options").collection("FeatureFlags")
+    }
+
+    /// The stored overrides, empty when nothing has been set
+    pub async fn overrides(&self) -> Result<Document, mongodb::error::Error> {
+        let stored = self.flags().find_one(doc! { "_id": FLAGS_DOCUMENT_ID }).await?;
+        Ok(stored
+            .and_then(|stored| stored.get_document("flags").ok().cloned())
+            .unwrap_or_default())
+    }
+
+    /// Set or, for `None`, clear overrides, and return the resulting overrides
+    pub async fn update(
+        &self,
+        admin_id: ObjectId,
+        changes: BTreeMap<String, Option<bool>>,
+    ) -> Result<Document, FeatureFlagError> {
+        if let Some(unknown) = changes.keys().find(|name| Flag::from_name(name).is_none()) {
+            return Err(FeatureFlagError::UnknownFlag(unknown.clone()));
+        }
+        if changes.is_empty() {
+            return Ok(self.overrides().await?);
+        }
+
+        let now = DateTime::now();
+        let mut set = doc! { "updated_at": now, "updated_by": admin_id };
+        let mut unset = Document::new();
+        for (name, value) in &changes {
+            match value {
+                Some(enabled) => set.insert(format!("flags.{}", name), *enabled),
+                None => unset.insert(format!("flags.{}", name), ""),
+            };
+        }
+        let mut update = doc! { "$set": set };
+        if !unset.is_empty() {
+            update.insert("$unset", unset);
+        }
+        self.flags()
+            .update_one(doc! { "_id": FLAGS_DOCUMENT_ID }, update)
+            .upsert(true)
+            .await?;
+
+        println!("🚩 Feature flags changed by admin {}: {:?}", admin_id, changes);
+        let audit = FeatureFlagAudit {
+            id: None,
+            action: "feature_flags_updated".to_string(),
+            admin_id,
+            changes,
+            created_at: now,
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<FeatureFlagAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for feature flag change: {}", e);
+        }
+
+        Ok(self.overrides().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_apply_without_stored_overrides() {
+        let (values, ignored) = FlagValues::with_overrides(&Document::new());
+        assert_eq!(values, FlagValues::default());
+        assert!(ignored.is_empty());
+        for flag in Flag::ALL {
+            assert_eq!(values.get(flag), flag.default_value());
+            assert_eq!(Flag::from_name(flag.name()), Some(flag));
+        }
+
+        let flags = Flags::default();
+        assert!(flags.search_debug());
+        assert!(flags.itinerary_generation());
+        assert!(flags.persist_generated_itineraries());
+    }
+
+    #[test]
+    fn test_unknown_and_malformed_overrides_are_ignored() {
+        let overrides = doc! {
+            "search_debug": false,
+            "personalization_boost": true,
+            "itinerary_generation": "off",
+        };
+        let (values, ignored) = FlagValues::with_overrides(&overrides);
+        assert!(!values.get(Flag::SearchDebug));
+        assert!(values.get(Flag::ItineraryGeneration));
+        assert_eq!(ignored, vec!["personalization_boost", "itinerary_generation"]);
+
+        let states = flag_states(&overrides);
+        assert_eq!(states.len(), Flag::ALL.len());
+        let search_debug = states.iter().find(|s| s.name == "search_debug").unwrap();
+        assert!(search_debug.overridden && !search_debug.enabled && search_debug.default);
+        let generation = states.iter().find(|s| s.name == "itinerary_generation").unwrap();
+        assert!(!generation.overridden && generation.enabled);
+    }
+
+    #[test]
+    fn test_replaced_values_are_read_by_accessors() {
+        let flags = Flags::default();
+        let mut values = FlagValues::default();
+        values.set(Flag::PersistGeneratedItineraries, false);
+        flags.replace(values);
+        assert!(!flags.persist_generated_itineraries());
+        assert!(flags.itinerary_generation());
+    }
+}
@@ -0,0 +1,317 @@
+//! Exchange rates for showing prices in a traveler's own currency
+//!
+//! Prices are stored and charged in USD. Converted amounts are display-only and
+//! always marked approximate; nothing in the payment path reads from here.
+//! Rates come from a configurable provider once a day, are cached in
+//! `Options.ExchangeRates`, and fall back to a built-in table when neither is available.
+
+use chrono::NaiveDate;
+use mongodb::{
+    bson::{doc, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::models::money::Money;
+
+/// Currency every price is stored and charged in
+pub const BASE_CURRENCY: &str = "USD";
+
+/// Units of each currency per US dollar, used until the provider has answered once
+const FALLBACK_RATES: &[(&str, f64)] = &[
+    ("EUR", 0.92),
+    ("GBP", 0.79),
+    ("CAD", 1.36),
+    ("AUD", 1.52),
+    ("MXN", 17.1),
+    ("JPY", 151.0),
+];
+const FALLBACK_RATE_DATE: &str = "2025-01-02";
+
+/// Id of the single cached rate document
+const CACHED_RATES_ID: &str = "latest";
+
+#[derive(Debug)]
+pub enum FxError {
+    RequestError(String),
+    InvalidResponse(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for FxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FxError::RequestError(msg) => write!(f, "Rate provider request failed: {}", msg),
+            FxError::InvalidResponse(msg) => write!(f, "Unexpected rate provider response: {}", msg),
+            FxError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FxError {}
+
+/// A USD price converted for display. Never charged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayPrice {
+    pub currency: String,
+    pub amount: f64,
+    pub rate_date: NaiveDate,
+    pub approximate: bool,
+}
+
+/// Units of each currency per US dollar, as of `rate_date`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRates {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub rate_date: NaiveDate,
+    pub rates: HashMap<String, f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<DateTime>,
+}
+
+impl ExchangeRates {
+    /// The built-in table, used when the provider and the cache are both unavailable
+    pub fn fallback() -> Self {
+        ExchangeRates {
+            id: CACHED_RATES_ID.to_string(),
+            rate_date: NaiveDate::parse_from_str(FALLBACK_RATE_DATE, "%Y-%m-%d").unwrap(),
+            rates: FALLBACK_RATES
+                .iter()
+                .map(|(currency, rate)| (currency.to_string(), *rate))
+                .collect(),
+            fetched_at: None,
+        }
+    }
+
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        let currency = currency.to_uppercase();
+        if currency == BASE_CURRENCY {
+            return Some(1.0);
+        }
+        self.rates.get(&currency).copied()
+    }
+
+    /// `usd` in `currency`, rounded to the cent. `None` for currencies without a rate.
+    pub fn display_price(&self, usd: Money, currency: &str) -> Option<DisplayPrice> {
+        let rate = self.rate(currency)?;
+        Some(DisplayPrice {
+            currency: currency.to_uppercase(),
+            amount: (usd.cents() as f64 * rate).round() / 100.0,
+            rate_date: self.rate_date,
+            approximate: true,
+        })
+    }
+}
+
+/// Whether prices can be shown in `currency`. Checked against the built-in table so
+/// a stored preference stays valid when the provider drops a currency for a day.
+pub fn is_supported_currency(currency: &str) -> bool {
+    ExchangeRates::fallback().rate(currency).is_some()
+}
+
+/// Body returned by the rate provider (Frankfurter-compatible)
+#[derive(Debug, Deserialize)]
+struct ProviderResponse {
+    base: String,
+    date: NaiveDate,
+    rates: HashMap<String, f64>,
+}
+
+fn parse_provider_response(body: &str) -> Result<ExchangeRates, FxError> {
+    let response: ProviderResponse =
+        serde_json::from_str(body).map_err(|e| FxError::InvalidResponse(e.to_string()))?;
+    if !response.base.eq_ignore_ascii_case(BASE_CURRENCY) {
+        return Err(FxError::InvalidResponse(format!(
+            "rates are based on {}, expected {}",
+            response.base, BASE_CURRENCY
+        )));
+    }
+    Ok(ExchangeRates {
+        id: CACHED_RATES_ID.to_string(),
+        rate_date: response.date,
+        rates: response
+            .rates
+            .into_iter()
+            .map(|(currency, rate)| (currency.to_uppercase(), rate))
+            .collect(),
+        fetched_at: None,
+    })
+}
+
+/// Rates to serve after a refresh attempt: fresh ones if the provider answered,
+/// otherwise whatever was cached, otherwise the built-in table
+fn choose_rates(fetched: Result<ExchangeRates, FxError>, cached: Option<ExchangeRates>) -> ExchangeRates {
+    match fetched {
+        Ok(rates) => rates,
+        Err(e) => {
+            eprintln!("⚠️  {}, using {} rates", e, if cached.is_some() { "cached" } else { "fallback" });
+            cached.unwrap_or_else(ExchangeRates::fallback)
+        }
+    }
+}
+
+/// Rates handlers convert with, shared through `web::Data` and kept current by
+/// the refresh task started in `FxRates::start`
+pub struct FxRates {
+    current: RwLock<ExchangeRates>,
+}
+
+impl Default for FxRates {
+    fn default() -> Self {
+        FxRates {
+            current: RwLock::new(ExchangeRates::fallback()),
+        }
+    }
+}
+
+impl FxRates {
+    pub fn current(&self) -> ExchangeRates {
+        self.current
+            .read()
+            .map(|rates| rates.clone())
+            .unwrap_or_else(|_| ExchangeRates::fallback())
+    }
+
+    fn replace(&self, rates: ExchangeRates) {
+        if let Ok(mut current) = self.current.write() {
+            *current = rates;
+        }
+    }
+
+    /// Refresh from the provider now and then every `refresh_interval`
+    pub fn start(client: Arc<Client>, provider_url: String, refresh_interval: Duration) -> Arc<Self> {
+        let rates = Arc::new(FxRates::default());
+        let shared = rates.clone();
+        tokio::spawn(async move {
+            let service = FxService::new(client, provider_url);
+            let mut interval = tokio::time::interval(refresh_interval);
+            loop {
+                interval.tick().await;
+                shared.replace(service.refresh().await);
+            }
+        });
+        rates
+    }
+}
+
+pub struct FxService {
+    client: Arc<Client>,
+    http_client: reqwest::Client,
+    provider_url: String,
+}
+
+impl FxService {
+    pub fn new(client: Arc<Client>, provider_url: String) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        FxService {
+            client,
+            http_client,
+            provider_url,
+        }
+    }
+
+    fn collection(&self) -> Collection<ExchangeRates> {
+        self.client.database("Options").collection("ExchangeRates")
+    }
+
+    async fn fetch_latest(&self) -> Result<ExchangeRates, FxError> {
+        let response = self
+            .http_client
+            .get(&self.provider_url)
+            .send()
+            .await
+            .map_err(|e| FxError::RequestError(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(FxError::RequestError(format!("status {}", response.status())));
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|e| FxError::RequestError(e.to_string()))?;
+        let mut rates = parse_provider_response(&body)?;
+        rates.fetched_at = Some(DateTime::now());
+        Ok(rates)
+    }
+
+    async fn cached(&self) -> Option<ExchangeRates> {
+        match self.collection().find_one(doc! { "_id": CACHED_RATES_ID }).await {
+            Ok(rates) => rates,
+            Err(e) => {
+                eprintln!("Failed to load cached exchange rates: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn store(&self, rates: &ExchangeRates) -> Result<(), FxError> {
+        self.collection()
+            .replace_one(doc! { "_id": CACHED_RATES_ID }, rates)
+            .upsert(true)
+            .await
+            .map_err(|e| FxError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Fetch today's rates and cache them. Never fails: a provider outage falls back
+    /// to the cached document, then to the built-in table.
+    pub async fn refresh(&self) -> ExchangeRates {
+        let fetched = self.fetch_latest().await;
+        if let Ok(rates) = &fetched {
+            println!("💱 Exchange rates refreshed for {}", rates.rate_date);
+            if let Err(e) = self.store(rates).await {
+                eprintln!("Failed to cache exchange rates: {}", e);
+            }
+            return choose_rates(fetched, None);
+        }
+        choose_rates(fetched, self.cached().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> ExchangeRates {
+        parse_provider_response(
+            r#"{"amount":1.0,"base":"USD","date":"2026-10-15","rates":{"EUR":0.9,"GBP":0.8,"JPY":150.25}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_conversion_with_fixed_rates() {
+        let rates = fixture();
+        let price = rates.display_price(Money::from_dollars(1234.56), "eur").unwrap();
+        assert_eq!(price.currency, "EUR");
+        assert_eq!(price.amount, 1111.10);
+        assert_eq!(price.rate_date, NaiveDate::from_ymd_opt(2026, 10, 15).unwrap());
+        assert!(price.approximate);
+
+        assert_eq!(rates.display_price(Money::from_dollars(10.0), "JPY").unwrap().amount, 1502.5);
+        assert_eq!(rates.display_price(Money::from_dollars(10.0), "USD").unwrap().amount, 10.0);
+        assert!(rates.display_price(Money::from_dollars(10.0), "CHF").is_none());
+    }
+
+    #[test]
+    fn test_provider_failure_falls_back() {
+        let failed = || Err(FxError::RequestError("connection refused".to_string()));
+        assert_eq!(choose_rates(failed(), None), ExchangeRates::fallback());
+        assert_eq!(choose_rates(failed(), Some(fixture())), fixture());
+        assert_eq!(choose_rates(Ok(fixture()), None), fixture());
+
+        assert!(parse_provider_response(r#"{"base":"EUR","date":"2026-10-15","rates":{}}"#).is_err());
+        assert!(parse_provider_response("<html>rate limited</html>").is_err());
+
+        let fallback = ExchangeRates::fallback();
+        assert!(fallback.display_price(Money::from_dollars(100.0), "GBP").is_some());
+        assert!(is_supported_currency("gbp"));
+        assert!(!is_supported_currency("XYZ"));
+    }
+}
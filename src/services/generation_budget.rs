@@ -0,0 +1,186 @@
+//! Per-request ceiling on paid external calls made while generating itineraries.
+//!
+//! One search can fan out into many Vertex AI queries, and one route map into many
+//! Distance Matrix lookups. Each of those requests gets its own [`GenerationBudget`];
+//! clones share its counters, so the parallel generation tasks of one request draw
+//! from the same budget while other requests are unaffected. Once a cap is reached
+//! the callers use their fallbacks instead: MongoDB for activities, straight-line
+//! estimates for travel times.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Default for `GENERATION_MAX_VERTEX_QUERIES`
+pub const DEFAULT_MAX_VERTEX_QUERIES: u32 = 6;
+/// Default for `GENERATION_MAX_MAPS_LOOKUPS`
+pub const DEFAULT_MAX_MAPS_LOOKUPS: u32 = 80;
+
+/// Most external calls of each kind one request may make
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetCaps {
+    pub vertex_queries: u32,
+    pub maps_lookups: u32,
+}
+
+impl Default for BudgetCaps {
+    fn default() -> Self {
+        BudgetCaps {
+            vertex_queries: DEFAULT_MAX_VERTEX_QUERIES,
+            maps_lookups: DEFAULT_MAX_MAPS_LOOKUPS,
+        }
+    }
+}
+
+/// Calls spent against a budget, as recorded in the generation trace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetReport {
+    pub vertex_queries: u32,
+    pub vertex_query_cap: u32,
+    pub maps_lookups: u32,
+    pub maps_lookup_cap: u32,
+    /// A Vertex query was refused and MongoDB used instead
+    pub vertex_exhausted: bool,
+    /// A Maps lookup was refused and a straight-line estimate used instead
+    pub maps_exhausted: bool,
+}
+
+#[derive(Debug)]
+struct Counter {
+    cap: u32,
+    used: AtomicU32,
+    exhausted: AtomicBool,
+}
+
+impl Counter {
+    fn new(cap: u32) -> Self {
+        Counter {
+            cap,
+            used: AtomicU32::new(0),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Take `n` calls if they all fit under the cap
+    fn try_spend(&self, n: u32) -> bool {
+        let spent = self
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(n).filter(|total| *total <= self.cap)
+            })
+            .is_ok();
+        if !spent {
+            self.exhausted.store(true, Ordering::Release);
+        }
+        spent
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GenerationBudget {
+    vertex: Arc<Counter>,
+    maps: Arc<Counter>,
+}
+
+impl GenerationBudget {
+    pub fn new(caps: BudgetCaps) -> Self {
+        GenerationBudget {
+            vertex: Arc::new(Counter::new(caps.vertex_queries)),
+            maps: Arc::new(Counter::new(caps.maps_lookups)),
+        }
+    }
+
+    /// No ceiling, for callers outside a search request
+    pub fn unlimited() -> Self {
+        Self::new(BudgetCaps {
+            vertex_queries: u32::MAX,
+            maps_lookups: u32::MAX,
+        })
+    }
+
+    /// Spend one Vertex AI query, or `false` if the request has used them all
+    pub fn try_vertex_query(&self) -> bool {
+        self.vertex.try_spend(1)
+    }
+
+    /// Spend `n` Distance Matrix lookups, or `false` if they don't all fit
+    pub fn try_maps_lookups(&self, n: u32) -> bool {
+        self.maps.try_spend(n)
+    }
+
+    pub fn report(&self) -> BudgetReport {
+        BudgetReport {
+            vertex_queries: self.vertex.used.load(Ordering::Acquire),
+            vertex_query_cap: self.vertex.cap,
+            maps_lookups: self.maps.used.load(Ordering::Acquire),
+            maps_lookup_cap: self.maps.cap,
+            vertex_exhausted: self.vertex.exhausted.load(Ordering::Acquire),
+            maps_exhausted: self.maps.exhausted.load(Ordering::Acquire),
+        }
+    }
+}
+
+impl Default for GenerationBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caps_stop_spending_and_mark_exhaustion() {
+        let budget = GenerationBudget::new(BudgetCaps {
+            vertex_queries: 2,
+            maps_lookups: 10,
+        });
+        assert!(budget.try_vertex_query());
+        assert!(budget.try_vertex_query());
+        assert!(!budget.try_vertex_query());
+
+        assert!(budget.try_maps_lookups(8));
+        // A batch that doesn't fit takes nothing
+        assert!(!budget.try_maps_lookups(3));
+        assert!(budget.try_maps_lookups(2));
+
+        assert_eq!(
+            budget.report(),
+            BudgetReport {
+                vertex_queries: 2,
+                vertex_query_cap: 2,
+                maps_lookups: 10,
+                maps_lookup_cap: 10,
+                vertex_exhausted: true,
+                maps_exhausted: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_clones_share_a_budget_and_requests_do_not() {
+        let caps = BudgetCaps {
+            vertex_queries: 6,
+            maps_lookups: 80,
+        };
+        let request = GenerationBudget::new(caps);
+        let other_request = GenerationBudget::new(caps);
+
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = request.clone();
+                std::thread::spawn(move || (0..20).filter(|_| budget.try_maps_lookups(1)).count())
+            })
+            .collect();
+        let spent: usize = workers.into_iter().map(|worker| worker.join().unwrap()).sum();
+
+        assert_eq!(spent, 80);
+        assert!(request.report().maps_exhausted);
+        assert_eq!(other_request.report(), BudgetReport {
+            vertex_query_cap: 6,
+            maps_lookup_cap: 80,
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,253 @@
+//! Optional, per-request trace of itinerary generation decisions.
+//!
+//! Enabled with the `X-Generation-Trace: true` header on the search endpoints. When
+//! disabled every `record_*` call is a no-op, so the scheduler can call them freely.
+//! Generated itineraries also keep a trace in their `GenerationMetadata`, whatever
+//! the request asked for.
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::models::activity::Activity;
+use crate::models::search::SearchItinerary;
+use crate::services::calendar::ClosureReason;
+use crate::services::generation_budget::BudgetReport;
+
+pub const GENERATION_TRACE_HEADER: &str = "X-Generation-Trace";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    AlreadyUsed,
+    ExceedsDailyHours,
+    ClosedWeekday,
+    ClosedDate,
+    Holiday,
+    Blackout,
+    NotOperatingDuringTrip,
+}
+
+impl From<ClosureReason> for SkipReason {
+    fn from(reason: ClosureReason) -> Self {
+        match reason {
+            ClosureReason::ClosedWeekday => SkipReason::ClosedWeekday,
+            ClosureReason::ClosedDate => SkipReason::ClosedDate,
+            ClosureReason::Holiday => SkipReason::Holiday,
+            ClosureReason::Blackout => SkipReason::Blackout,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsideredActivity {
+    pub activity_id: Option<ObjectId>,
+    pub title: String,
+    pub duration_minutes: u16,
+    pub price_per_person: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedActivity {
+    pub day: u32,
+    pub activity_id: Option<ObjectId>,
+    pub title: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleDecision {
+    pub day: u32,
+    pub date: Option<String>,
+    pub activity_id: ObjectId,
+    pub title: String,
+    pub time: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayOutcome {
+    pub day: u32,
+    pub activities: usize,
+    pub hours: f32,
+    pub window_extended: bool,
+    pub below_floor: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationTrace {
+    #[serde(skip)]
+    enabled: bool,
+    pub considered: Vec<ConsideredActivity>,
+    /// Activities whose duration was below the minimum, as they were stored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub short_durations: Vec<ConsideredActivity>,
+    pub skipped: Vec<SkippedActivity>,
+    pub scheduled: Vec<ScheduleDecision>,
+    pub days: Vec<DayOutcome>,
+    /// External calls the request had spent when this itinerary was generated, and
+    /// whether any were refused for being over budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetReport>,
+}
+
+impl GenerationTrace {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn record_considered(&mut self, activities: &[Activity]) {
+        if !self.enabled {
+            return;
+        }
+        self.considered.extend(activities.iter().map(|activity| ConsideredActivity {
+            activity_id: activity.id,
+            title: activity.title.clone(),
+            duration_minutes: activity.duration_minutes,
+            price_per_person: activity.price_per_person,
+        }));
+    }
+
+    pub fn record_short_duration(&mut self, activity: &Activity) {
+        if !self.enabled || self.short_durations.iter().any(|short| short.activity_id == activity.id) {
+            return;
+        }
+        self.short_durations.push(ConsideredActivity {
+            activity_id: activity.id,
+            title: activity.title.clone(),
+            duration_minutes: activity.duration_minutes,
+            price_per_person: activity.price_per_person,
+        });
+    }
+
+    pub fn record_skip(&mut self, day: u32, activity: &Activity, reason: SkipReason) {
+        if !self.enabled {
+            return;
+        }
+        // One entry per activity, day and reason is enough to explain a decision
+        let already_recorded = self.skipped.iter().any(|skip| {
+            skip.day == day && skip.activity_id == activity.id && skip.reason == reason
+        });
+        if !already_recorded {
+            self.skipped.push(SkippedActivity {
+                day,
+                activity_id: activity.id,
+                title: activity.title.clone(),
+                reason,
+            });
+        }
+    }
+
+    pub fn record_scheduled(
+        &mut self,
+        day: u32,
+        date: Option<String>,
+        activity: &Activity,
+        activity_id: ObjectId,
+        time: &str,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.scheduled.push(ScheduleDecision {
+            day,
+            date,
+            activity_id,
+            title: activity.title.clone(),
+            time: time.to_string(),
+        });
+    }
+
+    pub fn record_day(&mut self, outcome: DayOutcome) {
+        if self.enabled {
+            self.days.push(outcome);
+        }
+    }
+
+    pub fn record_budget(&mut self, report: BudgetReport) {
+        if self.enabled {
+            self.budget = Some(report);
+        }
+    }
+}
+
+/// How a generated itinerary came to be. Stored on it as `generation_metadata` and
+/// read by admins with `GET /admin/itineraries/{id}/provenance`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationMetadata {
+    /// The search that produced it
+    pub search: SearchItinerary,
+    /// Which of the search's generated variations it is. Absent for a single generation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variation_index: Option<u32>,
+    /// Seeds the random choices, like which city a state-level search generates in
+    pub seed: u32,
+    pub generated_at: DateTime,
+    /// Why each activity was or wasn't chosen
+    pub selection: GenerationTrace,
+}
+
+/// Whether the request asked for a generation trace
+pub fn trace_requested(req: &actix_web::HttpRequest) -> bool {
+    req.headers()
+        .get(GENERATION_TRACE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_trace_records_nothing() {
+        let mut trace = GenerationTrace::default();
+        trace.record_day(DayOutcome {
+            day: 1,
+            activities: 0,
+            hours: 0.0,
+            window_extended: false,
+            below_floor: true,
+        });
+        assert!(trace.days.is_empty());
+    }
+
+    #[test]
+    fn test_generation_metadata_round_trips_through_bson() {
+        let mut selection = GenerationTrace::new(true);
+        selection.record_day(DayOutcome {
+            day: 1,
+            activities: 2,
+            hours: 4.5,
+            window_extended: false,
+            below_floor: false,
+        });
+        selection.record_budget(BudgetReport {
+            maps_lookups: 80,
+            maps_lookup_cap: 80,
+            maps_exhausted: true,
+            ..Default::default()
+        });
+        let metadata = GenerationMetadata {
+            search: serde_json::from_value(serde_json::json!({ "locations": ["Colorado"] })).unwrap(),
+            variation_index: Some(2),
+            seed: u32::MAX,
+            generated_at: DateTime::now(),
+            selection,
+        };
+
+        let stored = mongodb::bson::to_document(&metadata).unwrap();
+        let read: GenerationMetadata = mongodb::bson::from_document(stored).unwrap();
+        assert_eq!(read.seed, u32::MAX);
+        assert_eq!(read.variation_index, Some(2));
+        assert_eq!(read.search.locations, Some(vec!["Colorado".to_string()]));
+        assert_eq!(read.selection.days.len(), 1);
+        assert!(read.selection.budget.is_some_and(|budget| budget.maps_exhausted));
+    }
+}
@@ -0,0 +1,322 @@
+//! Geocoding with the Google Maps Geocoding API, and the backfill that stores
+//! activity coordinates so route optimization doesn't have to guess them.
+//!
+//! Uses the same `GOOGLE_MAPS_API_KEY` as the Distance Matrix integration (the
+//! Geocoding API must be enabled for the key). Resolved addresses are cached in
+//! `Itineraries.GeocodeCache`; addresses don't move, so entries don't expire.
+//! Addresses that didn't resolve aren't cached and are retried on the next run.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, sync::Arc, time::Duration};
+
+use crate::models::activity::{Activity, Address, GeoPoint};
+
+/// Default for `GEOCODING_BATCH_SIZE`
+pub const DEFAULT_GEOCODING_BATCH_SIZE: u32 = 10;
+/// Default for `GEOCODING_BATCH_DELAY_MS`
+pub const DEFAULT_GEOCODING_BATCH_DELAY_MS: u64 = 1_000;
+/// Activities one backfill run looks at when the request doesn't say
+pub const DEFAULT_BACKFILL_LIMIT: u32 = 500;
+
+/// How fast the backfill calls the Geocoding API: `batch_size` lookups at a time,
+/// with a pause between batches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeocodingPace {
+    pub batch_size: u32,
+    pub batch_delay_ms: u64,
+}
+
+impl Default for GeocodingPace {
+    fn default() -> Self {
+        GeocodingPace {
+            batch_size: DEFAULT_GEOCODING_BATCH_SIZE,
+            batch_delay_ms: DEFAULT_GEOCODING_BATCH_DELAY_MS,
+        }
+    }
+}
+
+/// "1 Main St, Unit 2, Denver, CO 80202, US", or `None` without a street or city to look up
+pub fn address_line(address: &Address) -> Option<String> {
+    if address.street.trim().is_empty() && address.city.trim().is_empty() {
+        return None;
+    }
+    let state_zip = format!("{} {}", address.state.trim(), address.zip.trim());
+    let parts: Vec<&str> = [
+        address.street.trim(),
+        address.unit.trim(),
+        address.city.trim(),
+        state_zip.trim(),
+        address.country.trim(),
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .collect();
+    Some(parts.join(", "))
+}
+
+/// Cache key: case and spacing don't matter
+fn cache_key(address: &str) -> String {
+    address.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedGeocode {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub address: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub cached_at: DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeResponse {
+    status: String,
+    #[serde(default)]
+    results: Vec<GeocodeResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeResult {
+    geometry: GeocodeGeometry,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeGeometry {
+    location: GeocodeLocation,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeLocation {
+    lat: f64,
+    lng: f64,
+}
+
+/// `(lat, lng)` of the first result, `None` when the address matched nothing
+fn parse_geocode_response(response: GeocodeResponse) -> Result<Option<(f64, f64)>, Box<dyn std::error::Error>> {
+    match response.status.as_str() {
+        "OK" => Ok(response
+            .results
+            .first()
+            .map(|result| (result.geometry.location.lat, result.geometry.location.lng))),
+        "ZERO_RESULTS" => Ok(None),
+        status => Err(format!("Geocoding API error: {}", status).into()),
+    }
+}
+
+/// Where uncached coordinates come from. Google Maps in production; tests use fixed answers.
+pub trait Geocoder {
+    fn geocode(&self, address: &str) -> impl Future<Output = Result<Option<(f64, f64)>, Box<dyn std::error::Error>>>;
+}
+
+/// The Google Maps Geocoding API
+pub struct GoogleGeocoder {
+    http_client: reqwest::Client,
+    api_key: String,
+}
+
+impl GoogleGeocoder {
+    pub fn new(api_key: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let api_key = api_key.ok_or("GOOGLE_MAPS_API_KEY not set")?.to_string();
+
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        Ok(Self { http_client, api_key })
+    }
+}
+
+impl Geocoder for GoogleGeocoder {
+    async fn geocode(&self, address: &str) -> Result<Option<(f64, f64)>, Box<dyn std::error::Error>> {
+        let response: GeocodeResponse = self
+            .http_client
+            .get("https://maps.googleapis.com/maps/api/geocode/json")
+            .query(&[("address", address), ("key", self.api_key.as_str())])
+            .send()
+            .await?
+            .json()
+            .await?;
+        parse_geocode_response(response)
+    }
+}
+
+/// Outcome of one backfill run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BackfillReport {
+    /// Activities without coordinates looked at this run
+    pub considered: u32,
+    pub resolved: u32,
+    /// Of those resolved, how many came from the cache without an API call
+    pub from_cache: u32,
+    pub failed: u32,
+    /// Activities that failed, to fix their addresses by hand
+    pub failed_activity_ids: Vec<String>,
+}
+
+pub struct GeocodingService<G = GoogleGeocoder> {
+    client: Arc<Client>,
+    geocoder: G,
+}
+
+impl GeocodingService {
+    pub fn new(client: Arc<Client>, api_key: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::with_geocoder(client, GoogleGeocoder::new(api_key)?))
+    }
+}
+
+impl<G: Geocoder> GeocodingService<G> {
+    pub fn with_geocoder(client: Arc<Client>, geocoder: G) -> Self {
+        Self { client, geocoder }
+    }
+
+    fn cache(&self) -> Collection<CachedGeocode> {
+        self.client.database("Itineraries").collection("GeocodeCache")
+    }
+
+    fn activities(&self) -> Collection<Activity> {
+        self.client.database("Options").collection("Activity")
+    }
+
+    /// Coordinates for `address` and whether they came from the cache
+    pub async fn geocode(&self, address: &str) -> Result<Option<((f64, f64), bool)>, Box<dyn std::error::Error>> {
+        let key = cache_key(address);
+        if let Some(cached) = self.cache().find_one(doc! { "address": &key }).await? {
+            return Ok(Some(((cached.lat, cached.lng), true)));
+        }
+
+        let Some((lat, lng)) = self.geocoder.geocode(address).await? else {
+            return Ok(None);
+        };
+        let cached = CachedGeocode {
+            id: None,
+            address: key,
+            lat,
+            lng,
+            cached_at: DateTime::now(),
+        };
+        if let Err(e) = self.cache().insert_one(cached).await {
+            eprintln!("Failed to cache geocoding result: {}", e);
+        }
+        Ok(Some(((lat, lng), false)))
+    }
+
+    /// Geocode up to `limit` activities that have no stored coordinates and store a
+    /// GeoJSON point on each. Lookups run `pace.batch_size` at a time with
+    /// `pace.batch_delay_ms` between batches; cache hits don't wait.
+    pub async fn backfill_activity_coordinates(
+        &self,
+        limit: u32,
+        pace: GeocodingPace,
+    ) -> Result<BackfillReport, mongodb::error::Error> {
+        let activities: Vec<Activity> = self
+            .activities()
+            .find(doc! { "location": { "$exists": false } })
+            .limit(limit as i64)
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut report = BackfillReport {
+            considered: activities.len() as u32,
+            ..Default::default()
+        };
+        let mut lookups_in_batch = 0;
+        for activity in activities {
+            let Some(id) = activity.id else { continue };
+
+            let geocoded = match address_line(&activity.address) {
+                Some(address) => self.geocode(&address).await.unwrap_or_else(|e| {
+                    eprintln!("Failed to geocode activity {}: {}", id, e);
+                    None
+                }),
+                None => None,
+            };
+            let Some((coordinates, from_cache)) = geocoded else {
+                report.failed += 1;
+                report.failed_activity_ids.push(id.to_hex());
+                continue;
+            };
+
+            let point = mongodb::bson::to_bson(&GeoPoint::new(coordinates.0, coordinates.1))?;
+            self.activities()
+                .update_one(doc! { "_id": id }, doc! { "$set": { "location": point } })
+                .await?;
+            report.resolved += 1;
+            if from_cache {
+                report.from_cache += 1;
+                continue;
+            }
+
+            lookups_in_batch += 1;
+            if lookups_in_batch >= pace.batch_size {
+                lookups_in_batch = 0;
+                tokio::time::sleep(Duration::from_millis(pace.batch_delay_ms)).await;
+            }
+        }
+
+        println!(
+            "Coordinate backfill: {} resolved ({} cached), {} failed",
+            report.resolved, report.from_cache, report.failed
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(street: &str, unit: &str, city: &str, state: &str, zip: &str) -> Address {
+        Address {
+            street: street.to_string(),
+            unit: unit.to_string(),
+            city: city.to_string(),
+            state: state.to_string(),
+            zip: zip.to_string(),
+            country: "US".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_address_line_skips_blank_parts() {
+        assert_eq!(
+            address_line(&address("1 Main St", "", "Denver", "CO", "80202")).as_deref(),
+            Some("1 Main St, Denver, CO 80202, US")
+        );
+        assert_eq!(
+            address_line(&address("", "", "Aspen", "CO", "")).as_deref(),
+            Some("Aspen, CO, US")
+        );
+        assert_eq!(address_line(&address(" ", "", "", "CO", "")), None);
+        assert_eq!(cache_key("1  Main St,\tDENVER"), cache_key("1 main st, Denver"));
+    }
+
+    #[test]
+    fn test_geocode_responses() {
+        let response = |value: serde_json::Value| -> GeocodeResponse { serde_json::from_value(value).unwrap() };
+
+        let found = response(serde_json::json!({
+            "status": "OK",
+            "results": [{ "geometry": { "location": { "lat": 39.7392, "lng": -104.9903 } } }],
+        }));
+        assert_eq!(parse_geocode_response(found).unwrap(), Some((39.7392, -104.9903)));
+        assert_eq!(parse_geocode_response(response(serde_json::json!({ "status": "ZERO_RESULTS" }))).unwrap(), None);
+        assert!(parse_geocode_response(response(serde_json::json!({ "status": "OVER_QUERY_LIMIT" }))).is_err());
+    }
+
+    #[test]
+    fn test_points_are_stored_lng_first() {
+        let point = GeoPoint::new(39.7392, -104.9903);
+        assert_eq!(
+            serde_json::to_value(&point).unwrap(),
+            serde_json::json!({ "type": "Point", "coordinates": [-104.9903, 39.7392] })
+        );
+        assert_eq!(point.lat_lng(), (39.7392, -104.9903));
+    }
+}
@@ -0,0 +1,345 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::ReturnDocument,
+    Client, Collection,
+};
+use std::sync::Arc;
+
+use crate::models::gift_card::{GiftCard, GiftCardRedemption};
+use crate::services::unit_of_work::{self, UnitOfWork};
+
+#[derive(Debug)]
+pub enum GiftCardError {
+    NotFound,
+    Inactive,
+    Expired,
+    InsufficientBalance,
+    InvalidAmount,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for GiftCardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GiftCardError::NotFound => write!(f, "Gift card not found"),
+            GiftCardError::Inactive => write!(f, "Gift card is not active"),
+            GiftCardError::Expired => write!(f, "Gift card has expired"),
+            GiftCardError::InsufficientBalance => write!(f, "Gift card balance is insufficient"),
+            GiftCardError::InvalidAmount => write!(f, "Redemption amount must be positive"),
+            GiftCardError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for GiftCardError {}
+
+/// How an amount due is split between a gift card and the customer's card (cents)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GiftCardSplit {
+    pub gift_card_amount: i64,
+    pub card_amount: i64,
+}
+
+impl GiftCardSplit {
+    /// Nothing left to charge, so Stripe can be skipped entirely
+    pub fn paid_in_full(&self) -> bool {
+        self.card_amount == 0
+    }
+}
+
+/// Deduct up to the available gift card balance from the amount due
+pub fn split_payment(amount_due: i64, available_balance: i64) -> GiftCardSplit {
+    let amount_due = amount_due.max(0);
+    let gift_card_amount = available_balance.max(0).min(amount_due);
+    GiftCardSplit {
+        gift_card_amount,
+        card_amount: amount_due - gift_card_amount,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefundStep {
+    RestoreGiftCard(i64),
+    RefundCard(i64),
+}
+
+/// Split a refund proportionally between the gift card and card portions of a payment.
+/// The gift card is always restored before the card is refunded.
+pub fn refund_plan(gift_card_paid: i64, card_paid: i64, refund_total: i64) -> Vec<RefundStep> {
+    let total_paid = gift_card_paid + card_paid;
+    if total_paid <= 0 || refund_total <= 0 {
+        return Vec::new();
+    }
+
+    let refund_total = refund_total.min(total_paid);
+    let gift_card_restore = refund_total * gift_card_paid / total_paid;
+    let card_refund = refund_total - gift_card_restore;
+
+    let mut steps = Vec::new();
+    if gift_card_restore > 0 {
+        steps.push(RefundStep::RestoreGiftCard(gift_card_restore));
+    }
+    if card_refund > 0 {
+        steps.push(RefundStep::RefundCard(card_refund));
+    }
+    steps
+}
+
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+#[derive(Clone)]
+pub struct GiftCardService {
+    client: Arc<Client>,
+    transactional: bool,
+}
+
+impl GiftCardService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self {
+            client,
+            transactional: false,
+        }
+    }
+
+    /// Restore balances in a multi-document transaction (`MONGODB_TRANSACTIONS`),
+    /// so a redemption and its gift card are credited together or not at all
+    pub fn with_transactions(mut self, enabled: bool) -> Self {
+        self.transactional = enabled;
+        self
+    }
+
+    fn gift_cards(&self) -> Collection<GiftCard> {
+        self.client.database("Account").collection("GiftCards")
+    }
+
+    fn redemptions(&self) -> Collection<GiftCardRedemption> {
+        self.client.database("Account").collection("GiftCardRedemptions")
+    }
+
+    /// Look up a gift card that can currently be redeemed
+    pub async fn find_redeemable(&self, code: &str) -> Result<GiftCard, GiftCardError> {
+        let gift_card = self
+            .gift_cards()
+            .find_one(doc! { "code": normalize_code(code) })
+            .await
+            .map_err(|e| GiftCardError::DatabaseError(e.to_string()))?
+            .ok_or(GiftCardError::NotFound)?;
+
+        if !gift_card.active {
+            return Err(GiftCardError::Inactive);
+        }
+        if gift_card.is_expired() {
+            return Err(GiftCardError::Expired);
+        }
+        Ok(gift_card)
+    }
+
+    /// Take `amount` from a gift card and record the redemption.
+    ///
+    /// The balance is decremented with a conditional update, so concurrent redemptions
+    /// can never take the balance below zero.
+    pub async fn redeem(
+        &self,
+        code: &str,
+        amount: i64,
+        user_id: ObjectId,
+        booking_id: Option<ObjectId>,
+    ) -> Result<GiftCardRedemption, GiftCardError> {
+        if amount <= 0 {
+            return Err(GiftCardError::InvalidAmount);
+        }
+
+        let code = normalize_code(code);
+        let now = DateTime::now();
+        let filter = doc! {
+            "code": &code,
+            "active": true,
+            "remaining_balance": { "$gte": amount },
+            "$or": [
+                { "expires_at": null },
+                { "expires_at": { "$gt": now } },
+            ],
+        };
+        let update = doc! {
+            "$inc": { "remaining_balance": -amount },
+            "$set": { "updated_at": now },
+        };
+
+        let gift_card = match self
+            .gift_cards()
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(|e| GiftCardError::DatabaseError(e.to_string()))?
+        {
+            Some(gift_card) => gift_card,
+            None => {
+                // Work out why the conditional update didn't match
+                self.find_redeemable(&code).await?;
+                return Err(GiftCardError::InsufficientBalance);
+            }
+        };
+
+        let gift_card_id = gift_card.id.ok_or(GiftCardError::NotFound)?;
+        let mut redemption = GiftCardRedemption {
+            id: None,
+            gift_card_id,
+            code,
+            user_id,
+            booking_id,
+            amount,
+            restored_amount: 0,
+            created_at: Some(now),
+            updated_at: Some(now),
+        };
+
+        match self.redemptions().insert_one(&redemption).await {
+            Ok(result) => {
+                redemption.id = result.inserted_id.as_object_id();
+                println!(
+                    "🎁 Redeemed {} from gift card {} (remaining {})",
+                    amount, gift_card_id, gift_card.remaining_balance
+                );
+                Ok(redemption)
+            }
+            Err(e) => {
+                // Put the balance back so a failed ledger write doesn't lose money
+                eprintln!("Failed to record gift card redemption, restoring balance: {:?}", e);
+                let _ = self
+                    .gift_cards()
+                    .update_one(
+                        doc! { "_id": gift_card_id },
+                        doc! { "$inc": { "remaining_balance": amount } },
+                    )
+                    .await;
+                Err(GiftCardError::DatabaseError(e.to_string()))
+            }
+        }
+    }
+
+    pub async fn attach_booking(
+        &self,
+        redemption_id: ObjectId,
+        booking_id: ObjectId,
+    ) -> Result<(), GiftCardError> {
+        self.attach_booking_in(&mut UnitOfWork::sequential(), redemption_id, booking_id)
+            .await
+            .map_err(|e| GiftCardError::DatabaseError(e.to_string()))
+    }
+
+    /// `attach_booking` as part of a larger unit of work
+    pub async fn attach_booking_in(
+        &self,
+        uow: &mut UnitOfWork,
+        redemption_id: ObjectId,
+        booking_id: ObjectId,
+    ) -> Result<(), mongodb::error::Error> {
+        uow.update_one(
+            &self.redemptions(),
+            doc! { "_id": redemption_id },
+            doc! { "$set": { "booking_id": booking_id, "updated_at": DateTime::now() } },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn find_redemption_for_booking(
+        &self,
+        booking_id: ObjectId,
+    ) -> Result<Option<GiftCardRedemption>, GiftCardError> {
+        self.redemptions()
+            .find_one(doc! { "booking_id": booking_id })
+            .await
+            .map_err(|e| GiftCardError::DatabaseError(e.to_string()))
+    }
+
+    /// Return up to `amount` of a redemption to its gift card. Returns the amount restored.
+    ///
+    /// The redemption's `restored_amount` only moves if no other restore got there
+    /// first, and the balance is credited in the same unit of work, so restoring the
+    /// same redemption twice credits it once.
+    pub async fn restore(&self, redemption_id: ObjectId, amount: i64) -> Result<i64, GiftCardError> {
+        let redemption = self
+            .redemptions()
+            .find_one(doc! { "_id": redemption_id })
+            .await
+            .map_err(|e| GiftCardError::DatabaseError(e.to_string()))?
+            .ok_or(GiftCardError::NotFound)?;
+
+        let restorable = (redemption.amount - redemption.restored_amount).max(0);
+        let amount = amount.min(restorable);
+        if amount <= 0 {
+            return Ok(0);
+        }
+
+        let (redemptions, gift_cards) = (self.redemptions(), self.gift_cards());
+        unit_of_work::run(&self.client, self.transactional, async |uow: &mut UnitOfWork| {
+            // Only bump restored_amount if nobody else restored in the meantime
+            let ledger_update = uow
+                .update_one(
+                    &redemptions,
+                    doc! { "_id": redemption_id, "restored_amount": redemption.restored_amount },
+                    doc! {
+                        "$inc": { "restored_amount": amount },
+                        "$set": { "updated_at": DateTime::now() },
+                    },
+                )
+                .await?;
+            if ledger_update.modified_count == 0 {
+                return Ok(0);
+            }
+
+            uow.update_one(
+                &gift_cards,
+                doc! { "_id": redemption.gift_card_id },
+                doc! {
+                    "$inc": { "remaining_balance": amount },
+                    "$set": { "updated_at": DateTime::now() },
+                },
+            )
+            .await?;
+            Ok(amount)
+        })
+        .await
+        .map_err(|e| GiftCardError::DatabaseError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_redemption() {
+        let split = split_payment(15_000, 5_000);
+        assert_eq!(split.gift_card_amount, 5_000);
+        assert_eq!(split.card_amount, 10_000);
+        assert!(!split.paid_in_full());
+    }
+
+    #[test]
+    fn test_full_coverage_skips_stripe() {
+        let split = split_payment(8_000, 10_000);
+        assert_eq!(split.gift_card_amount, 8_000);
+        assert_eq!(split.card_amount, 0);
+        assert!(split.paid_in_full());
+    }
+
+    #[test]
+    fn test_refund_restores_gift_card_first() {
+        // 25% paid by gift card, 95% refund
+        let steps = refund_plan(2_500, 7_500, 9_500);
+        assert_eq!(
+            steps,
+            vec![RefundStep::RestoreGiftCard(2_375), RefundStep::RefundCard(7_125)]
+        );
+    }
+
+    #[test]
+    fn test_refund_fully_gift_card_paid() {
+        let steps = refund_plan(10_000, 0, 9_500);
+        assert_eq!(steps, vec![RefundStep::RestoreGiftCard(9_500)]);
+    }
+}
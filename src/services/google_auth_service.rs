@@ -4,25 +4,28 @@ use oauth2::{
     RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
 use reqwest::Client as ReqwestClient;
-use std::env;
 use url::Url;
 
+use crate::config::OAuthClientSettings;
 use crate::models::google_auth::GoogleUserInfo;
 
-// Create a new OAuth client for Google
-pub fn create_google_oauth_client() -> BasicClient {
-    let google_client_id =
-        env::var("GOOGLE_CLIENT_ID").expect("Missing GOOGLE_CLIENT_ID environment variable");
-    let google_client_secret = env::var("GOOGLE_CLIENT_SECRET")
-        .expect("Missing GOOGLE_CLIENT_SECRET environment variable");
-    let google_redirect_url =
-        env::var("GOOGLE_REDIRECT_URI").expect("Missing GOOGLE_REDIRECT_URI environment variable");
-    
+// Create a new OAuth client for Google, or an error if it isn't fully configured
+pub fn create_google_oauth_client(settings: &OAuthClientSettings) -> Result<BasicClient, String> {
+    let (Some(google_client_id), Some(google_client_secret), Some(google_redirect_url)) = (
+        settings.client_id.clone(),
+        settings.client_secret.clone(),
+        settings.redirect_uri.clone(),
+    ) else {
+        return Err("Google sign-in is not configured".to_string());
+    };
+    let redirect_url = RedirectUrl::new(google_redirect_url.clone())
+        .map_err(|e| format!("Invalid redirect URL: {}", e))?;
+
     println!("Google OAuth client config:");
     println!("  Client ID: {}", if google_client_id.len() > 10 { &google_client_id[..10] } else { &google_client_id });
     println!("  Redirect URI: {}", google_redirect_url);
 
-    BasicClient::new(
+    Ok(BasicClient::new(
         ClientId::new(google_client_id),
         Some(ClientSecret::new(google_client_secret)),
         AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())
@@ -32,7 +35,7 @@ pub fn create_google_oauth_client() -> BasicClient {
                 .expect("Invalid token endpoint URL"),
         ),
     )
-    .set_redirect_uri(RedirectUrl::new(google_redirect_url).expect("Invalid redirect URL"))
+    .set_redirect_uri(redirect_url))
 }
 
 // Generate an authorization URL for Google OAuth
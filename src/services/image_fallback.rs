@@ -0,0 +1,300 @@
+//! Images for search results that have none of their own
+//!
+//! Generated itineraries are saved without images. Rather than a blank card, a
+//! result without images gets, in order:
+//!
+//! 1. the first image of its highest-priced scheduled activity that has one,
+//! 2. the stock image for its start city, from the `current` document in
+//!    `Options.StockImages`, kept in memory and re-read every `REFRESH_INTERVAL`,
+//! 3. the global placeholder, `PLACEHOLDER_IMAGE_URL`.
+//!
+//! The result says which with `image_source`, so the client can style stand-ins
+//! differently from the itinerary's own images.
+
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::DayItem;
+
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Used when `PLACEHOLDER_IMAGE_URL` isn't set
+pub const DEFAULT_PLACEHOLDER_IMAGE: &str =
+    "https://storage.googleapis.com/actota-itineraries/placeholder.jpg";
+
+const STOCK_IMAGES_DOCUMENT_ID: &str = "current";
+
+/// Where a stand-in image came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    Activity,
+    CityStock,
+    Placeholder,
+}
+
+/// The first image of each scheduled activity that has one, highest price first.
+/// Activities scheduled more than once count once.
+pub fn activity_images<'a>(
+    scheduled: impl IntoIterator<Item = &'a ObjectId>,
+    activities: &HashMap<ObjectId, Activity>,
+) -> Vec<String> {
+    let mut found: Vec<&Activity> = Vec::new();
+    for activity in scheduled.into_iter().filter_map(|id| activities.get(id)) {
+        if !found.iter().any(|seen| seen.id == activity.id) {
+            found.push(activity);
+        }
+    }
+    // Stable, so equally priced activities keep their schedule order
+    found.sort_by(|a, b| b.price_per_person.total_cmp(&a.price_per_person));
+
+    let mut images: Vec<String> = Vec::new();
+    for image in found.iter().filter_map(|activity| activity.images.first()) {
+        if !images.contains(image) {
+            images.push(image.clone());
+        }
+    }
+    images
+}
+
+/// The activities scheduled in `days`, in day order
+pub fn scheduled_activities(days: &HashMap<String, Vec<DayItem>>) -> Vec<ObjectId> {
+    let mut ordered: Vec<_> = days.iter().collect();
+    ordered.sort_by_key(|(day, _)| (day.parse::<u32>().unwrap_or(u32::MAX), day.as_str()));
+    ordered
+        .into_iter()
+        .flat_map(|(_, items)| items)
+        .filter_map(|item| match item {
+            DayItem::Activity { activity_id, .. } => Some(*activity_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Stock images by city, as stored
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StockImageDocument {
+    /// Image URL by `"City, ST"` or just `"City"`, matched without regard to case
+    #[serde(default)]
+    pub cities: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime>,
+}
+
+fn city_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// The in-memory stock images and the placeholder, shared by every worker
+/// through `web::Data`
+pub struct StockImages {
+    by_city: RwLock<HashMap<String, String>>,
+    placeholder: String,
+}
+
+impl Default for StockImages {
+    fn default() -> Self {
+        StockImages::new(DEFAULT_PLACEHOLDER_IMAGE)
+    }
+}
+
+impl StockImages {
+    pub fn new(placeholder: &str) -> Self {
+        StockImages {
+            by_city: RwLock::new(HashMap::new()),
+            placeholder: placeholder.to_string(),
+        }
+    }
+
+    pub fn with_cities(placeholder: &str, cities: &HashMap<String, String>) -> Self {
+        let stock = StockImages::new(placeholder);
+        stock.replace(cities);
+        stock
+    }
+
+    pub fn replace(&self, cities: &HashMap<String, String>) {
+        if let Ok(mut by_city) = self.by_city.write() {
+            *by_city = cities
+                .iter()
+                .filter(|(_, url)| !url.trim().is_empty())
+                .map(|(city, url)| (city_key(city), url.clone()))
+                .collect();
+        }
+    }
+
+    /// The image for `city` in `state`, or for `city` in any state
+    pub fn city_image(&self, city: &str, state: &str) -> Option<String> {
+        let by_city = self.by_city.read().ok()?;
+        by_city
+            .get(&city_key(&format!("{}, {}", city, state)))
+            .or_else(|| by_city.get(&city_key(city)))
+            .cloned()
+    }
+
+    /// The image a result without images shows, and where it came from.
+    /// `activity_images` is what `activity_images` found for the result.
+    pub fn fallback(&self, activity_images: &[String], city: &str, state: &str) -> (String, ImageSource) {
+        if let Some(image) = activity_images.first() {
+            return (image.clone(), ImageSource::Activity);
+        }
+        if let Some(image) = self.city_image(city, state) {
+            return (image, ImageSource::CityStock);
+        }
+        (self.placeholder.clone(), ImageSource::Placeholder)
+    }
+
+    /// Re-read the stored stock images. On failure the current ones are kept.
+    pub async fn refresh(&self, client: &Arc<Client>) -> Result<usize, mongodb::error::Error> {
+        let stored = StockImageService::new(client.clone()).load().await?;
+        self.replace(&stored.cities);
+        Ok(stored.cities.len())
+    }
+
+    /// Load the stored stock images now and then every `refresh_interval`
+    pub fn start(client: Arc<Client>, placeholder: &str, refresh_interval: Duration) -> Arc<Self> {
+        let stock = Arc::new(StockImages::new(placeholder));
+        let shared = stock.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = shared.refresh(&client).await {
+                    eprintln!("Failed to refresh stock images: {}", e);
+                }
+            }
+        });
+        stock
+    }
+}
+
+pub struct StockImageService {
+    client: Arc<Client>,
+}
+
+impl StockImageService {
+    pub fn new(client: Arc<Client>) -> Self {
+        StockImageService { client }
+    }
+
+    fn collection(&self) -> Collection<StockImageDocument> {
+        self.client.database("Options").collection("StockImages")
+    }
+
+    /// The stored stock images, empty when none have been set
+    pub async fn load(&self) -> Result<StockImageDocument, mongodb::error::Error> {
+        Ok(self
+            .collection()
+            .find_one(doc! { "_id": STOCK_IMAGES_DOCUMENT_ID })
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Replace the stock images
+    pub async fn save(&self, cities: HashMap<String, String>) -> Result<StockImageDocument, mongodb::error::Error> {
+        let stored = StockImageDocument {
+            cities,
+            updated_at: Some(DateTime::now()),
+        };
+        self.collection()
+            .replace_one(doc! { "_id": STOCK_IMAGES_DOCUMENT_ID }, &stored)
+            .upsert(true)
+            .await?;
+        Ok(stored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(price_per_person: f32, images: &[&str]) -> Activity {
+        let mut activity: Activity = serde_json::from_value(serde_json::json!({
+            "_id": ObjectId::new(),
+            "company": "Rocky Mountain Adventures",
+            "company_id": "rma",
+            "booking_link": "",
+            "online_booking_status": "available",
+            "title": "Rafting",
+            "description": "",
+            "activity_types": [],
+            "tags": [],
+            "price_per_person": price_per_person,
+            "daily_time_slots": [],
+            "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+            "whats_included": [],
+            "capacity": { "minimum": 1, "maximum": 10 },
+        }))
+        .unwrap();
+        activity.images = images.iter().map(|image| image.to_string()).collect();
+        activity
+    }
+
+    fn day(activities: &[&Activity]) -> Vec<DayItem> {
+        activities
+            .iter()
+            .map(|activity| DayItem::Activity {
+                time: "09:00:00".to_string(),
+                activity_id: activity.id.unwrap(),
+            })
+            .collect()
+    }
+
+    fn by_id(activities: &[&Activity]) -> HashMap<ObjectId, Activity> {
+        activities.iter().map(|a| (a.id.unwrap(), (*a).clone())).collect()
+    }
+
+    #[test]
+    fn test_highest_priced_activity_image_comes_first() {
+        let cheap = activity(40.0, &["cheap.jpg"]);
+        let pricey = activity(180.0, &["pricey.jpg", "pricey-2.jpg"]);
+        let imageless = activity(300.0, &[]);
+        let days = HashMap::from([
+            ("1".to_string(), day(&[&cheap, &imageless])),
+            ("2".to_string(), day(&[&pricey, &cheap])),
+        ]);
+
+        let scheduled = scheduled_activities(&days);
+        assert_eq!(scheduled.len(), 4);
+        let images = activity_images(&scheduled, &by_id(&[&cheap, &pricey, &imageless]));
+
+        assert_eq!(images, vec!["pricey.jpg", "cheap.jpg"]);
+    }
+
+    #[test]
+    fn test_each_fallback_tier_applies_in_order() {
+        let stock = StockImages::with_cities("placeholder.jpg", &HashMap::from([
+            ("Salida, CO".to_string(), "salida.jpg".to_string()),
+            ("Moab".to_string(), "moab.jpg".to_string()),
+        ]));
+        let from_activity = vec!["rafting.jpg".to_string()];
+
+        assert_eq!(
+            stock.fallback(&from_activity, "Salida", "CO"),
+            ("rafting.jpg".to_string(), ImageSource::Activity)
+        );
+        assert_eq!(
+            stock.fallback(&[], "salida", "co"),
+            ("salida.jpg".to_string(), ImageSource::CityStock)
+        );
+        assert_eq!(
+            stock.fallback(&[], "Moab", "UT"),
+            ("moab.jpg".to_string(), ImageSource::CityStock)
+        );
+        assert_eq!(
+            stock.fallback(&[], "Salida", "NM"),
+            ("placeholder.jpg".to_string(), ImageSource::Placeholder)
+        );
+    }
+
+    #[test]
+    fn test_image_source_is_snake_case() {
+        assert_eq!(serde_json::to_value(ImageSource::CityStock).unwrap(), "city_stock");
+    }
+}
@@ -1,10 +1,10 @@
 use base64::{engine::general_purpose, Engine as _};
-use google_cloud_storage::client::{Client, ClientConfig};
-use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest, UploadType};
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::services::storage::{BucketKind, ListedObject, ObjectStore, Storage, StorageConfig, StorageError};
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ImageData {
     pub data: String,
@@ -21,6 +21,7 @@ pub enum ImageUploadError {
     Base64DecodeError(String),
     GcsError(String),
     InvalidImageFormat(String),
+    TooLarge(String),
     EnvironmentError(String),
 }
 
@@ -30,6 +31,7 @@ impl std::fmt::Display for ImageUploadError {
             ImageUploadError::Base64DecodeError(err) => write!(f, "Base64 decode error: {}", err),
             ImageUploadError::GcsError(err) => write!(f, "GCS upload error: {}", err),
             ImageUploadError::InvalidImageFormat(err) => write!(f, "Invalid image format: {}", err),
+            ImageUploadError::TooLarge(err) => write!(f, "Image too large: {}", err),
             ImageUploadError::EnvironmentError(err) => write!(f, "Environment error: {}", err),
         }
     }
@@ -37,25 +39,59 @@ impl std::fmt::Display for ImageUploadError {
 
 impl std::error::Error for ImageUploadError {}
 
-pub struct ImageService {
-    client: Client,
-    bucket_name: String,
+impl From<StorageError> for ImageUploadError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::NotConfigured(_) => ImageUploadError::EnvironmentError(err.to_string()),
+            StorageError::TooLarge { .. } => ImageUploadError::TooLarge(err.to_string()),
+            StorageError::Gcs(e) => ImageUploadError::GcsError(format!("Failed to upload to GCS: {}", e)),
+        }
+    }
 }
 
-impl ImageService {
-    pub async fn new() -> Result<Self, ImageUploadError> {
-        let bucket_name = env::var("ITINERARY_BUCKET")
-            .map_err(|_| ImageUploadError::EnvironmentError("ITINERARY_BUCKET not set".to_string()))?;
+/// Where an itinerary's uploaded images are kept in the itinerary bucket
+pub fn itinerary_prefix(itinerary_id: &str) -> String {
+    format!("{}/", itinerary_id)
+}
 
-        let config = ClientConfig::default().with_auth().await
-            .map_err(|e| ImageUploadError::GcsError(format!("Failed to create GCS client: {}", e)))?;
-        
-        let client = Client::new(config);
+/// An object stored for an itinerary, and whether the itinerary shows it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiscoveredImage {
+    #[serde(flatten)]
+    pub object: ListedObject,
+    pub referenced: bool,
+}
 
-        Ok(Self {
-            client,
-            bucket_name,
+/// Everything under the itinerary's prefix, marked with whether `images` (the
+/// itinerary's stored references) points at it
+pub async fn discover_images<S: ObjectStore>(
+    storage: &Storage<S>,
+    itinerary_id: &str,
+    images: &[String],
+) -> Result<Vec<DiscoveredImage>, StorageError> {
+    let bucket = storage.bucket(BucketKind::ItineraryImages)?;
+    let referenced: Vec<String> = images.iter().filter_map(|image| bucket.object_name(image)).collect();
+    let objects = storage
+        .objects(BucketKind::ItineraryImages, &itinerary_prefix(itinerary_id))
+        .await?;
+    Ok(objects
+        .into_iter()
+        .map(|object| DiscoveredImage {
+            referenced: referenced.contains(&object.name),
+            object,
         })
+        .collect())
+}
+
+pub struct ImageService {
+    storage: Storage,
+}
+
+impl ImageService {
+    pub fn new(config: Arc<StorageConfig>) -> Result<Self, ImageUploadError> {
+        let storage = Storage::new(config);
+        storage.bucket(BucketKind::ItineraryImages)?;
+        Ok(Self { storage })
     }
 
     pub async fn upload_images(&self, images: Vec<ImageData>, itinerary_id: &str) -> Vec<Result<String, ImageUploadError>> {
@@ -88,24 +124,14 @@ impl ImageService {
         let file_extension = self.get_file_extension(&image.file_type)?;
         let timestamp = chrono::Utc::now().timestamp();
         let random_id = Uuid::new_v4();
-        let object_name = format!("{}/{}-{}.{}", itinerary_id, timestamp, random_id, file_extension);
+        let object_name = format!("{}{}-{}.{}", itinerary_prefix(itinerary_id), timestamp, random_id, file_extension);
 
-        let upload_type = UploadType::Simple(Media::new(object_name.clone()));
-        let upload_request = UploadObjectRequest {
-            bucket: self.bucket_name.clone(),
-            ..Default::default()
-        };
-
-        self.client.upload_object(&upload_request, image_bytes, &upload_type).await
-            .map_err(|e| ImageUploadError::GcsError(format!("Failed to upload to GCS: {}", e)))?;
-
-        let public_url = format!(
-            "https://storage.googleapis.com/{}/{}",
-            self.bucket_name,
-            object_name
-        );
+        let stored = self
+            .storage
+            .upload(BucketKind::ItineraryImages, &object_name, &image.file_type, image_bytes)
+            .await?;
 
-        Ok(public_url)
+        Ok(stored.url)
     }
 
     fn get_file_extension(&self, file_type: &str) -> Result<String, ImageUploadError> {
@@ -118,4 +144,123 @@ impl ImageService {
             _ => Err(ImageUploadError::InvalidImageFormat(format!("Unsupported file type: {}", file_type))),
         }
     }
-}
\ No newline at end of file
+}
+/// Image size a response asks for with `?image_size=`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    /// For result lists and cards
+    Thumb,
+    Medium,
+    /// The stored original
+    #[default]
+    Full,
+}
+
+impl ImageSize {
+    pub fn width(self) -> Option<u32> {
+        match self {
+            ImageSize::Thumb => Some(320),
+            ImageSize::Medium => Some(800),
+            ImageSize::Full => None,
+        }
+    }
+}
+
+/// Rewrites stored image URLs to resized versions through an image CDN.
+///
+/// `resize_template` (`IMAGE_RESIZE_URL`) is a URL with `{path}` and `{width}`
+/// placeholders, e.g. `https://images.example.com/cdn-cgi/image/width={width}/{path}`.
+/// `{path}` is the part of the stored URL after the storage host, bucket included.
+/// Without a template, for full size, or for images stored elsewhere, the original
+/// URL is returned.
+#[derive(Debug, Clone)]
+pub struct ImageUrlBuilder {
+    storage_url: String,
+    resize_template: Option<String>,
+    size: ImageSize,
+}
+
+impl ImageUrlBuilder {
+    pub fn new(storage_url: &str, resize_template: Option<&str>, size: ImageSize) -> Self {
+        ImageUrlBuilder {
+            storage_url: storage_url.trim_end_matches('/').to_string(),
+            resize_template: resize_template.map(str::to_string),
+            size,
+        }
+    }
+
+    pub fn url(&self, stored_url: &str) -> String {
+        let (Some(template), Some(width)) = (&self.resize_template, self.size.width()) else {
+            return stored_url.to_string();
+        };
+        match stored_url
+            .strip_prefix(&self.storage_url)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(path) if !path.is_empty() => template
+                .replace("{width}", &width.to_string())
+                .replace("{path}", path),
+            _ => stored_url.to_string(),
+        }
+    }
+
+    pub fn apply(&self, images: &mut [String]) {
+        for image in images {
+            *image = self.url(image);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::memory;
+
+    const TEMPLATE: &str = "https://images.example.com/cdn-cgi/image/width={width},fit=cover/{path}";
+
+    #[test]
+    fn test_resized_urls() {
+        let stored = "https://storage.googleapis.com/actota-itineraries/abc/cover.jpg";
+        let thumb = ImageUrlBuilder::new("https://storage.googleapis.com/", Some(TEMPLATE), ImageSize::Thumb);
+        assert_eq!(
+            thumb.url(stored),
+            "https://images.example.com/cdn-cgi/image/width=320,fit=cover/actota-itineraries/abc/cover.jpg"
+        );
+
+        let mut images = vec![stored.to_string()];
+        ImageUrlBuilder::new("https://storage.googleapis.com", Some(TEMPLATE), ImageSize::Medium).apply(&mut images);
+        assert!(images[0].contains("width=800"));
+    }
+
+    #[test]
+    fn test_falls_back_to_the_original_url() {
+        let stored = "https://storage.googleapis.com/actota-itineraries/abc/cover.jpg";
+        let storage = "https://storage.googleapis.com";
+
+        assert_eq!(ImageUrlBuilder::new(storage, None, ImageSize::Thumb).url(stored), stored);
+        assert_eq!(ImageUrlBuilder::new(storage, Some(TEMPLATE), ImageSize::Full).url(stored), stored);
+
+        let external = "https://cdn.fareharbor.com/images/tour.jpg";
+        assert_eq!(ImageUrlBuilder::new(storage, Some(TEMPLATE), ImageSize::Thumb).url(external), external);
+    }
+
+    #[actix_rt::test]
+    async fn test_discovery_lists_the_itinerary_prefix() {
+        let storage = memory::storage();
+        for object in ["i1/cover.jpg", "i1/extra.png", "i10/other.jpg", "i2/cover.jpg"] {
+            storage
+                .upload(BucketKind::ItineraryImages, object, "image/jpeg", vec![0; 10])
+                .await
+                .unwrap();
+        }
+        let images = ["https://storage.googleapis.com/actota-itineraries/i1/cover.jpg".to_string()];
+
+        let found = discover_images(&storage, "i1", &images).await.unwrap();
+        let found: Vec<(&str, bool)> = found
+            .iter()
+            .map(|image| (image.object.name.as_str(), image.referenced))
+            .collect();
+        assert_eq!(found, [("i1/cover.jpg", true), ("i1/extra.png", false)]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Support signing in as a customer
+//!
+//! An admin starts a session for a user and gets a short-lived token that acts
+//! as that user. The token names the admin and the session, and `AuthMiddleware`
+//! checks the session on every request, so ending it (or letting it expire)
+//! cuts the token off at once. Only reads, less `IMPERSONATION_PRIVATE_ROUTES`,
+//! and the writes in `IMPERSONATION_ALLOWED_ROUTES` are served while
+//! impersonating. Starting and ending a session both go to the admin audit log.
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::middleware::auth::{Claims, Impersonation};
+use crate::models::account::{User, UserRole};
+use crate::routes::account::auth::role_claim;
+
+pub const DEFAULT_TOKEN_MINUTES: u64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpersonationSession {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub admin_id: ObjectId,
+    pub user_id: ObjectId,
+    pub reason: Option<String>,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub ended_at: Option<DateTime>,
+}
+
+impl ImpersonationSession {
+    pub fn is_active(&self, now: DateTime) -> bool {
+        self.ended_at.is_none() && now < self.expires_at
+    }
+}
+
+/// Written to the admin audit log when a session starts or ends
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImpersonationAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// "impersonation_started" or "impersonation_ended"
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub user_id: ObjectId,
+    pub session_id: ObjectId,
+    pub reason: Option<String>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug)]
+pub struct StartedImpersonation {
+    pub token: String,
+    pub session: ImpersonationSession,
+    pub user_email: String,
+}
+
+#[derive(Debug)]
+pub enum ImpersonationError {
+    UserNotFound,
+    /// Admin accounts can't be impersonated, so support can't borrow another admin's access
+    CannotImpersonateAdmin,
+    CannotImpersonateSelf,
+    SessionNotFound,
+    TokenError(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ImpersonationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImpersonationError::UserNotFound => write!(f, "User not found"),
+            ImpersonationError::CannotImpersonateAdmin => {
+                write!(f, "Admin accounts can't be impersonated")
+            }
+            ImpersonationError::CannotImpersonateSelf => write!(f, "You can't impersonate yourself"),
+            ImpersonationError::SessionNotFound => write!(f, "Impersonation session not found"),
+            ImpersonationError::TokenError(msg) => write!(f, "Failed to issue token: {}", msg),
+            ImpersonationError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ImpersonationError {}
+
+impl From<mongodb::error::Error> for ImpersonationError {
+    fn from(err: mongodb::error::Error) -> Self {
+        ImpersonationError::DatabaseError(err.to_string())
+    }
+}
+
+/// Claims acting as `user` for the length of `session`. The role is the user's
+/// own, so the token opens nothing the user couldn't.
+pub fn impersonation_claims(user: &User, session: &ImpersonationSession) -> Claims {
+    Claims {
+        sub: user.email.clone(),
+        iat: (session.created_at.timestamp_millis() / 1000) as usize,
+        exp: (session.expires_at.timestamp_millis() / 1000) as usize,
+        user_id: session.user_id.to_hex(),
+        role: Some(role_claim(user.role.as_ref()).to_string()),
+        email: Some(user.email.clone()),
+        // One token per session
+        jti: Some(session.id.to_hex()),
+        token_scopes: None,
+        impersonation: Some(Impersonation {
+            admin_id: session.admin_id.to_hex(),
+            session_id: session.id.to_hex(),
+        }),
+    }
+}
+
+fn minutes_after(time: DateTime, minutes: u64) -> DateTime {
+    DateTime::from_millis(time.timestamp_millis() + minutes as i64 * 60 * 1000)
+}
+
+pub struct ImpersonationService {
+    client: Arc<Client>,
+}
+
+impl ImpersonationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ImpersonationService { client }
+    }
+
+    fn sessions(&self) -> Collection<ImpersonationSession> {
+        // Read on every impersonated request, right after the session may have ended
+        primary_collection(&self.client, "Account", "ImpersonationSessions")
+    }
+
+    /// Open a session for `user_id` and issue its token
+    pub async fn start(
+        &self,
+        jwt_secret: &str,
+        admin_id: ObjectId,
+        user_id: ObjectId,
+        reason: Option<String>,
+        token_minutes: u64,
+    ) -> Result<StartedImpersonation, ImpersonationError> {
+        if admin_id == user_id {
+            return Err(ImpersonationError::CannotImpersonateSelf);
+        }
+        let user = self
+            .client
+            .database("Account")
+            .collection::<User>("Users")
+            .find_one(doc! { "_id": user_id })
+            .await?
+            .ok_or(ImpersonationError::UserNotFound)?;
+        if user.role == Some(UserRole::Admin) {
+            return Err(ImpersonationError::CannotImpersonateAdmin);
+        }
+
+        let now = DateTime::now();
+        let session = ImpersonationSession {
+            id: ObjectId::new(),
+            admin_id,
+            user_id,
+            reason,
+            created_at: now,
+            expires_at: minutes_after(now, token_minutes),
+            ended_at: None,
+        };
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &impersonation_claims(&user, &session),
+            &EncodingKey::from_secret(jwt_secret.as_bytes()),
+        )
+        .map_err(|e| ImpersonationError::TokenError(e.to_string()))?;
+
+        self.sessions().insert_one(&session).await?;
+        self.audit("impersonation_started", &session).await;
+        println!(
+            "🎭 Admin {} started impersonating user {} (session {})",
+            admin_id, user_id, session.id
+        );
+
+        Ok(StartedImpersonation {
+            token,
+            session,
+            user_email: user.email,
+        })
+    }
+
+    /// Whether the session behind a token can still be used
+    pub async fn is_active(&self, session_id: &str) -> Result<bool, mongodb::error::Error> {
+        let Ok(session_id) = ObjectId::parse_str(session_id) else {
+            return Ok(false);
+        };
+        let session = self.sessions().find_one(doc! { "_id": session_id }).await?;
+        Ok(session.is_some_and(|session| session.is_active(DateTime::now())))
+    }
+
+    /// End a session early. Ending one that already ended is a no-op.
+    pub async fn end(&self, session_id: &str) -> Result<ImpersonationSession, ImpersonationError> {
+        let session_id =
+            ObjectId::parse_str(session_id).map_err(|_| ImpersonationError::SessionNotFound)?;
+        let session = self
+            .sessions()
+            .find_one(doc! { "_id": session_id })
+            .await?
+            .ok_or(ImpersonationError::SessionNotFound)?;
+        if session.ended_at.is_some() {
+            return Ok(session);
+        }
+
+        let ended_at = DateTime::now();
+        self.sessions()
+            .update_one(
+                doc! { "_id": session_id, "ended_at": null },
+                doc! { "$set": { "ended_at": ended_at } },
+            )
+            .await?;
+        let session = ImpersonationSession {
+            ended_at: Some(ended_at),
+            ..session
+        };
+        self.audit("impersonation_ended", &session).await;
+        println!(
+            "🎭 Admin {} stopped impersonating user {} (session {})",
+            session.admin_id, session.user_id, session.id
+        );
+        Ok(session)
+    }
+
+    async fn audit(&self, action: &str, session: &ImpersonationSession) {
+        let audit = ImpersonationAudit {
+            id: None,
+            action: action.to_string(),
+            admin_id: session.admin_id,
+            user_id: session.user_id,
+            session_id: session.id,
+            reason: session.reason.clone(),
+            created_at: DateTime::now(),
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<ImpersonationAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for impersonation session {}: {}", session.id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{decode, DecodingKey, Validation};
+
+    #[test]
+    fn test_token_acts_as_the_user_and_names_the_admin() {
+        let user = User {
+            role: Some(UserRole::Operator),
+            ..User::test("traveler@example.com")
+        };
+        let now = DateTime::now();
+        let session = ImpersonationSession {
+            id: ObjectId::new(),
+            admin_id: ObjectId::new(),
+            user_id: ObjectId::new(),
+            reason: Some("Ticket 4411".to_string()),
+            created_at: now,
+            expires_at: minutes_after(now, 30),
+            ended_at: None,
+        };
+
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &impersonation_claims(&user, &session),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        let claims = decode::<Claims>(&token, &DecodingKey::from_secret(b"secret"), &Validation::new(Algorithm::HS256))
+            .unwrap()
+            .claims;
+
+        assert_eq!(claims.user_id, session.user_id.to_hex());
+        assert_eq!(claims.role.as_deref(), Some("operator"));
+        assert_eq!(claims.impersonator(), Some(session.admin_id.to_hex().as_str()));
+        assert_eq!(claims.impersonation.unwrap().session_id, session.id.to_hex());
+        assert_eq!(claims.exp - claims.iat, 30 * 60);
+
+        assert!(session.is_active(minutes_after(now, 29)));
+        assert!(!session.is_active(minutes_after(now, 30)));
+        let ended = ImpersonationSession {
+            ended_at: Some(now),
+            ..session
+        };
+        assert!(!ended.is_active(minutes_after(now, 1)));
+    }
+}
@@ -0,0 +1,370 @@
+//! Finds listed itineraries whose days point at activities or accommodations
+//! that no longer exist. Population leaves those items out, so without this
+//! report a deleted activity quietly shortens every trip that used it.
+//!
+//! Each run also samples `INTEGRITY_IMAGE_SAMPLE_SIZE` itineraries and checks
+//! that the images they show from the itinerary bucket are still there.
+//!
+//! Each run is recorded in `Options.IntegrityReports`; the latest backs
+//! `GET /admin/integrity/dangling-references`.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::models::itinerary::base::{DayItem, Days};
+use crate::models::itinerary::populated::PopulationWarning;
+use crate::services::storage::{BucketKind, ObjectStore, Storage};
+
+const SCAN_BATCH_SIZE: usize = 500;
+
+/// Just what the scan needs from an itinerary
+#[derive(Debug, Deserialize)]
+struct ItineraryReferences {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(default)]
+    trip_name: String,
+    #[serde(default)]
+    days: Days,
+}
+
+/// Just what the image sample needs from an itinerary
+#[derive(Debug, Deserialize)]
+struct ItineraryImages {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(default)]
+    trip_name: String,
+    #[serde(default)]
+    images: Vec<String>,
+}
+
+/// A sampled itinerary showing images whose objects aren't in the bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MissingImages {
+    pub itinerary_id: ObjectId,
+    pub trip_name: String,
+    pub missing_objects: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DanglingItinerary {
+    pub itinerary_id: ObjectId,
+    pub trip_name: String,
+    pub warnings: Vec<PopulationWarning>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub generated_at: DateTime,
+    pub duration_ms: u64,
+    pub itineraries_checked: u64,
+    /// Sum of the warnings across `itineraries`
+    pub dangling_references: u64,
+    pub itineraries: Vec<DanglingItinerary>,
+    /// Itineraries whose images were checked against the bucket
+    #[serde(default)]
+    pub images_sampled: u64,
+    #[serde(default)]
+    pub missing_images: Vec<MissingImages>,
+}
+
+/// Activity and accommodation ids referenced anywhere in `batch`
+fn referenced_ids(batch: &[ItineraryReferences]) -> (HashSet<ObjectId>, HashSet<ObjectId>) {
+    let mut activities = HashSet::new();
+    let mut accommodations = HashSet::new();
+    for item in batch.iter().flat_map(|itinerary| itinerary.days.days.values().flatten()) {
+        match item {
+            DayItem::Activity { activity_id, .. } => {
+                activities.insert(*activity_id);
+            }
+            DayItem::Accommodation {
+                accommodation_id, ..
+            } => {
+                accommodations.insert(*accommodation_id);
+            }
+            DayItem::Transportation { .. } => {}
+        }
+    }
+    (activities, accommodations)
+}
+
+/// The sampled itineraries showing missing images. An itinerary whose images
+/// can't be checked is left out rather than reported.
+async fn missing_images<S: ObjectStore>(storage: &Storage<S>, sample: &[ItineraryImages]) -> Vec<MissingImages> {
+    let mut found = Vec::new();
+    for itinerary in sample {
+        match storage.missing_objects(BucketKind::ItineraryImages, &itinerary.images).await {
+            Ok(missing) if missing.is_empty() => {}
+            Ok(missing) => found.push(MissingImages {
+                itinerary_id: itinerary.id,
+                trip_name: itinerary.trip_name.clone(),
+                missing_objects: missing,
+            }),
+            Err(e) => eprintln!("Failed to check images of itinerary {}: {}", itinerary.id, e),
+        }
+    }
+    found
+}
+
+pub struct IntegrityService {
+    client: Arc<Client>,
+    /// Where images are checked, and how many itineraries to sample per run
+    images: Option<(Storage, u64)>,
+}
+
+impl IntegrityService {
+    pub fn new(client: Arc<Client>) -> Self {
+        IntegrityService { client, images: None }
+    }
+
+    /// Also check the images of `sample_size` random listed itineraries each run
+    pub fn with_image_sample(mut self, storage: Storage, sample_size: u64) -> Self {
+        self.images = (sample_size > 0).then_some((storage, sample_size));
+        self
+    }
+
+    fn reports(&self) -> Collection<IntegrityReport> {
+        self.client.database("Options").collection("IntegrityReports")
+    }
+
+    /// Check every `interval`, starting one interval from now
+    pub fn start(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run().await {
+                    eprintln!("Failed to check itinerary references: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Scan the listed itineraries and record the report
+    pub async fn run(&self) -> Result<IntegrityReport, mongodb::error::Error> {
+        let generated_at = DateTime::now();
+        let timer = Instant::now();
+        println!("🔎 Checking itinerary references");
+
+        let itineraries: Collection<ItineraryReferences> =
+            self.client.database("Itineraries").collection("Featured");
+        let mut cursor = itineraries
+            .find(doc! { "taken_down_at": null })
+            .projection(doc! { "_id": 1, "trip_name": 1, "days": 1 })
+            .await?;
+
+        let mut report = IntegrityReport {
+            id: None,
+            generated_at,
+            duration_ms: 0,
+            itineraries_checked: 0,
+            dangling_references: 0,
+            itineraries: Vec::new(),
+            images_sampled: 0,
+            missing_images: Vec::new(),
+        };
+        let mut batch = Vec::with_capacity(SCAN_BATCH_SIZE);
+        while let Some(itinerary) = cursor.try_next().await? {
+            batch.push(itinerary);
+            if batch.len() == SCAN_BATCH_SIZE {
+                self.check_batch(&batch, &mut report).await?;
+                batch.clear();
+            }
+        }
+        self.check_batch(&batch, &mut report).await?;
+        if let Some((storage, sample_size)) = &self.images {
+            let sample = self.image_sample(*sample_size).await?;
+            report.images_sampled = sample.len() as u64;
+            report.missing_images = missing_images(storage, &sample).await;
+        }
+
+        report.duration_ms = timer.elapsed().as_millis() as u64;
+        report.id = self.reports().insert_one(&report).await?.inserted_id.as_object_id();
+        println!(
+            "🔎 {} dangling references in {} of {} itineraries, missing images in {} of {} sampled",
+            report.dangling_references,
+            report.itineraries.len(),
+            report.itineraries_checked,
+            report.missing_images.len(),
+            report.images_sampled
+        );
+        Ok(report)
+    }
+
+    /// Random listed itineraries that have images
+    async fn image_sample(&self, size: u64) -> Result<Vec<ItineraryImages>, mongodb::error::Error> {
+        let itineraries: Collection<Document> = self.client.database("Itineraries").collection("Featured");
+        let documents: Vec<Document> = itineraries
+            .aggregate(vec![
+                doc! { "$match": { "taken_down_at": null, "images.0": { "$exists": true } } },
+                doc! { "$sample": { "size": size as i64 } },
+                doc! { "$project": { "_id": 1, "trip_name": 1, "images": 1 } },
+            ])
+            .await?
+            .try_collect()
+            .await?;
+        Ok(documents
+            .into_iter()
+            .filter_map(|document| mongodb::bson::from_document(document).ok())
+            .collect())
+    }
+
+    async fn check_batch(
+        &self,
+        batch: &[ItineraryReferences],
+        report: &mut IntegrityReport,
+    ) -> Result<(), mongodb::error::Error> {
+        let (activities, accommodations) = referenced_ids(batch);
+        let activities = self.existing("Activity", activities).await?;
+        let accommodations = self.existing("Lodging", accommodations).await?;
+
+        for itinerary in batch {
+            report.itineraries_checked += 1;
+            let warnings = itinerary.days.missing_references(&activities, &accommodations);
+            if warnings.is_empty() {
+                continue;
+            }
+            report.dangling_references += warnings.len() as u64;
+            report.itineraries.push(DanglingItinerary {
+                itinerary_id: itinerary.id,
+                trip_name: itinerary.trip_name.clone(),
+                warnings,
+            });
+        }
+        Ok(())
+    }
+
+    /// Which of `ids` are still in `Options.{collection}`
+    async fn existing(
+        &self,
+        collection: &str,
+        ids: HashSet<ObjectId>,
+    ) -> Result<HashSet<ObjectId>, mongodb::error::Error> {
+        if ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let ids: Vec<ObjectId> = ids.into_iter().collect();
+        let found: Vec<Bson> = self
+            .client
+            .database("Options")
+            .collection::<Document>(collection)
+            .distinct("_id", doc! { "_id": { "$in": ids } })
+            .await?;
+        Ok(found.iter().filter_map(|id| id.as_object_id()).collect())
+    }
+
+    /// The most recent report, if any check has run
+    pub async fn latest(&self) -> Result<Option<IntegrityReport>, mongodb::error::Error> {
+        self.reports()
+            .find_one(doc! {})
+            .sort(doc! { "generated_at": -1 })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn activity(id: ObjectId) -> DayItem {
+        DayItem::Activity {
+            time: "09:00:00".to_string(),
+            activity_id: id,
+        }
+    }
+
+    #[test]
+    fn test_missing_references_are_reported_in_day_order() {
+        let kept = ObjectId::new();
+        let deleted = ObjectId::new();
+        let lodging = ObjectId::new();
+        let days = Days {
+            days: HashMap::from([
+                ("10".to_string(), vec![activity(deleted)]),
+                (
+                    "2".to_string(),
+                    vec![
+                        activity(kept),
+                        DayItem::Accommodation {
+                            time: "18:00:00".to_string(),
+                            accommodation_id: lodging,
+                        },
+                        activity(deleted),
+                    ],
+                ),
+            ]),
+        };
+        let itinerary = ItineraryReferences {
+            id: ObjectId::new(),
+            trip_name: "Dangling".to_string(),
+            days,
+        };
+
+        let (activities, accommodations) = referenced_ids(std::slice::from_ref(&itinerary));
+        assert_eq!(activities, HashSet::from([kept, deleted]));
+        assert_eq!(accommodations, HashSet::from([lodging]));
+
+        let warnings = itinerary
+            .days
+            .missing_references(&HashSet::from([kept]), &HashSet::new());
+        let found: Vec<(&str, &str, ObjectId)> = warnings
+            .iter()
+            .map(|w| (w.day.as_str(), w.item_type.as_str(), w.missing_id))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("2", "accommodation", lodging),
+                ("2", "activity", deleted),
+                ("10", "activity", deleted),
+            ]
+        );
+        assert!(itinerary
+            .days
+            .missing_references(&activities, &accommodations)
+            .is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_sampled_itineraries_with_missing_images_are_reported() {
+        let storage = crate::services::storage::memory::storage();
+        storage
+            .upload(BucketKind::ItineraryImages, "i1/cover.jpg", "image/jpeg", vec![0; 10])
+            .await
+            .unwrap();
+        let itinerary = |trip_name: &str, images: &[&str]| ItineraryImages {
+            id: ObjectId::new(),
+            trip_name: trip_name.to_string(),
+            images: images.iter().map(|image| image.to_string()).collect(),
+        };
+        let sample = [
+            itinerary("Intact", &["https://storage.googleapis.com/actota-itineraries/i1/cover.jpg"]),
+            itinerary(
+                "Broken",
+                &["i1/cover.jpg", "https://storage.googleapis.com/actota-itineraries/i1/deleted.jpg"],
+            ),
+            itinerary("Elsewhere", &["https://cdn.fareharbor.com/images/tour.jpg"]),
+        ];
+
+        let found = missing_images(&storage, &sample).await;
+        assert_eq!(
+            found,
+            [MissingImages {
+                itinerary_id: sample[1].id,
+                trip_name: "Broken".to_string(),
+                missing_objects: vec!["i1/deleted.jpg".to_string()],
+            }]
+        );
+    }
+}
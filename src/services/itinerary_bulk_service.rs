@@ -0,0 +1,612 @@
+//! Archiving, publishing, tagging and grading many itineraries at once
+//!
+//! An admin picks itineraries by id, or by a filter resolved here. Each one gets
+//! the checks a single edit would: publishing needs the itinerary to pass
+//! validation, and archiving is refused while it has confirmed bookings that
+//! haven't started. Itineraries that fail are skipped and reported, the rest are
+//! written in one `bulk_write`, and the batch is audited once.
+//!
+//! A filter can match much more than intended, so it first has to be run with
+//! `dry_run`. That stores the matched ids as a `BulkDryRun` for
+//! `DRY_RUN_TTL_MINUTES` and returns its token; executing means sending the same
+//! filter and operation back with the token, and acts on exactly the previewed ids.
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::{IndexOptions, UpdateOneModel},
+    Client, Collection, IndexModel,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::mongo::primary_collection;
+use crate::models::itinerary::base::{Difficulty, FeaturedVacation};
+use crate::services::itinerary_validation_service::{
+    ItineraryValidationService, Severity, ValidationReport,
+};
+use crate::services::retention_service::created_before;
+use crate::services::trip_limits::TripLimits;
+
+/// Most itineraries one request can change
+pub const MAX_BULK_ITINERARIES: usize = 500;
+
+/// How long a dry run's token can be used to execute it
+pub const DRY_RUN_TTL_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkOperation {
+    Archive,
+    Publish,
+    AddTags,
+    RemoveTags,
+    SetDifficulty,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkParams {
+    /// For `add_tags` and `remove_tags`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// For `set_difficulty`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+}
+
+impl BulkParams {
+    /// Tags trimmed, without blanks or repeats
+    pub fn normalized(mut self) -> Self {
+        let mut seen = HashSet::new();
+        self.tags = self
+            .tags
+            .iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty() && seen.insert(tag.clone()))
+            .collect();
+        self
+    }
+
+    /// The params `operation` needs are there
+    pub fn check(&self, operation: BulkOperation) -> Result<(), ItineraryBulkError> {
+        match operation {
+            BulkOperation::AddTags | BulkOperation::RemoveTags if self.tags.is_empty() => Err(
+                ItineraryBulkError::MissingParams("params.tags must list at least one tag".to_string()),
+            ),
+            BulkOperation::SetDifficulty if self.difficulty.is_none() => Err(
+                ItineraryBulkError::MissingParams("params.difficulty is required".to_string()),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Itineraries picked by what they are rather than by id
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkFilter {
+    /// Matches the itinerary's origin `tag` or one of its curation `tags`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<chrono::DateTime<Utc>>,
+}
+
+impl BulkFilter {
+    /// The query for the filter; `None` when it names nothing to match on
+    pub fn to_document(&self) -> Option<Document> {
+        let mut clauses = Vec::new();
+        if let Some(tag) = self.tag.as_deref().map(str::trim).filter(|tag| !tag.is_empty()) {
+            clauses.push(doc! { "$or": [{ "tag": tag }, { "tags": tag }] });
+        }
+        if let Some(cutoff) = self.created_before {
+            clauses.push(created_before(DateTime::from_millis(cutoff.timestamp_millis())));
+        }
+        (!clauses.is_empty()).then(|| doc! { "$and": clauses })
+    }
+}
+
+/// What happened to one itinerary in the batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum BulkOutcome {
+    Applied,
+    Skipped { reason: String },
+    NotFound,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BulkItemResult {
+    /// The itinerary, as a hex id
+    pub id: String,
+    #[serde(flatten)]
+    pub outcome: BulkOutcome,
+}
+
+/// A filter's matches, held until the admin executes them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkDryRun {
+    #[serde(rename = "_id")]
+    pub token: String,
+    pub admin_id: ObjectId,
+    pub operation: BulkOperation,
+    pub params: BulkParams,
+    pub filter: BulkFilter,
+    pub itinerary_ids: Vec<ObjectId>,
+    pub created_at: DateTime,
+    /// When the token stops working (TTL)
+    pub expires_at: DateTime,
+}
+
+/// A bulk change, kept in the admin audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItineraryBulkAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub operation: BulkOperation,
+    pub params: BulkParams,
+    /// Set when the itineraries were picked by filter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<BulkFilter>,
+    /// Every itinerary asked for
+    pub itinerary_ids: Vec<ObjectId>,
+    /// The ones actually changed
+    pub applied_ids: Vec<ObjectId>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ItineraryBulkError {
+    NothingSelected,
+    TooManyItineraries,
+    MissingParams(String),
+    /// A filter was executed without the token from its dry run
+    DryRunRequired,
+    /// Unknown, expired or already used token
+    DryRunNotFound,
+    /// The token is for another admin, filter or operation
+    DryRunMismatch,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ItineraryBulkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ItineraryBulkError::NothingSelected => {
+                write!(f, "Send either ids or a filter with tag or created_before")
+            }
+            ItineraryBulkError::TooManyItineraries => write!(
+                f,
+                "A bulk operation can change at most {} itineraries",
+                MAX_BULK_ITINERARIES
+            ),
+            ItineraryBulkError::MissingParams(message) => write!(f, "{}", message),
+            ItineraryBulkError::DryRunRequired => write!(
+                f,
+                "Run the filter with dry_run first and send back its dry_run_token"
+            ),
+            ItineraryBulkError::DryRunNotFound => {
+                write!(f, "This dry run has expired or was already executed; run it again")
+            }
+            ItineraryBulkError::DryRunMismatch => {
+                write!(f, "The dry_run_token is for a different filter or operation")
+            }
+            ItineraryBulkError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ItineraryBulkError {}
+
+impl From<mongodb::error::Error> for ItineraryBulkError {
+    fn from(e: mongodb::error::Error) -> Self {
+        ItineraryBulkError::DatabaseError(e.to_string())
+    }
+}
+
+/// Why `operation` leaves `itinerary` alone, if it does. `upcoming_bookings` is
+/// whether confirmed bookings of it haven't started. Publishing also has to pass
+/// validation, see `validation_skip_reason`.
+pub fn skip_reason(
+    operation: BulkOperation,
+    params: &BulkParams,
+    itinerary: &FeaturedVacation,
+    upcoming_bookings: bool,
+) -> Option<String> {
+    match operation {
+        BulkOperation::Archive if itinerary.archived_at.is_some() => Some("Already archived".to_string()),
+        BulkOperation::Archive if upcoming_bookings => {
+            Some("Has confirmed bookings that haven't started".to_string())
+        }
+        BulkOperation::Publish if itinerary.taken_down_at.is_some() => {
+            Some("Taken down by moderation".to_string())
+        }
+        BulkOperation::Publish if itinerary.archived_at.is_none() => Some("Already published".to_string()),
+        BulkOperation::AddTags if params.tags.iter().all(|tag| itinerary.tags.contains(tag)) => {
+            Some("Already has these tags".to_string())
+        }
+        BulkOperation::RemoveTags if !params.tags.iter().any(|tag| itinerary.tags.contains(tag)) => {
+            Some("Has none of these tags".to_string())
+        }
+        BulkOperation::SetDifficulty if itinerary.difficulty == params.difficulty => {
+            Some("Already has this difficulty".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Why an itinerary with `report` can't be published, if it can't
+pub fn validation_skip_reason(report: &ValidationReport) -> Option<String> {
+    if report.valid {
+        return None;
+    }
+    let first_error = report
+        .issues
+        .iter()
+        .find(|issue| issue.severity == Severity::Error)
+        .map_or("it has errors", |issue| issue.message.as_str());
+    Some(format!("Fails validation: {}", first_error))
+}
+
+/// The write `operation` makes to each itinerary it applies to
+pub fn update_for(operation: BulkOperation, params: &BulkParams, now: DateTime) -> Document {
+    match operation {
+        BulkOperation::Archive => doc! { "$set": { "archived_at": now, "updated_at": now } },
+        BulkOperation::Publish => doc! { "$unset": { "archived_at": "" }, "$set": { "updated_at": now } },
+        BulkOperation::AddTags => doc! {
+            "$addToSet": { "tags": { "$each": &params.tags } },
+            "$set": { "updated_at": now },
+        },
+        BulkOperation::RemoveTags => doc! {
+            "$pull": { "tags": { "$in": &params.tags } },
+            "$set": { "updated_at": now },
+        },
+        BulkOperation::SetDifficulty => doc! {
+            "$set": {
+                "difficulty": mongodb::bson::to_bson(&params.difficulty).unwrap_or_default(),
+                "updated_at": now,
+            },
+        },
+    }
+}
+
+fn minutes_after(time: DateTime, minutes: i64) -> DateTime {
+    DateTime::from_millis(time.timestamp_millis() + minutes * 60 * 1000)
+}
+
+pub struct ItineraryBulkService {
+    client: Arc<Client>,
+}
+
+impl ItineraryBulkService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ItineraryBulkService { client }
+    }
+
+    fn itineraries(&self) -> Collection<FeaturedVacation> {
+        self.client.database("Itineraries").collection("Featured")
+    }
+
+    fn dry_runs(&self) -> Collection<BulkDryRun> {
+        self.client.database("Account").collection("BulkItineraryDryRuns")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let ttl = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        self.dry_runs().create_index(ttl).await?;
+        Ok(())
+    }
+
+    /// The ids `filter` matches, oldest first
+    pub async fn resolve(&self, filter: &BulkFilter) -> Result<Vec<ObjectId>, ItineraryBulkError> {
+        let query = filter.to_document().ok_or(ItineraryBulkError::NothingSelected)?;
+        let matched: Vec<Document> = self
+            .client
+            .database("Itineraries")
+            .collection::<Document>("Featured")
+            .find(query)
+            .projection(doc! { "_id": 1 })
+            .sort(doc! { "_id": 1 })
+            .limit(MAX_BULK_ITINERARIES as i64 + 1)
+            .await?
+            .try_collect()
+            .await?;
+        if matched.len() > MAX_BULK_ITINERARIES {
+            return Err(ItineraryBulkError::TooManyItineraries);
+        }
+        Ok(matched.iter().filter_map(|doc| doc.get_object_id("_id").ok()).collect())
+    }
+
+    /// What `operation` would do to each of `ids`, without writing anything
+    pub async fn plan(
+        &self,
+        operation: BulkOperation,
+        params: &BulkParams,
+        ids: &[ObjectId],
+        limits: &TripLimits,
+        min_activity_minutes: u16,
+    ) -> Result<Vec<BulkItemResult>, ItineraryBulkError> {
+        if ids.len() > MAX_BULK_ITINERARIES {
+            return Err(ItineraryBulkError::TooManyItineraries);
+        }
+        let found: HashMap<ObjectId, FeaturedVacation> = self
+            .itineraries()
+            .find(doc! { "_id": { "$in": ids } })
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .filter_map(|itinerary| itinerary.id.map(|id| (id, itinerary)))
+            .collect();
+
+        let booked: HashSet<ObjectId> = if operation == BulkOperation::Archive {
+            primary_collection::<Document>(&self.client, "Account", "Bookings")
+                .distinct(
+                    "itinerary_id",
+                    doc! {
+                        "itinerary_id": { "$in": ids },
+                        "status": "confirmed",
+                        "arrival_datetime": { "$gt": DateTime::now() },
+                    },
+                )
+                .await?
+                .into_iter()
+                .filter_map(|id| id.as_object_id())
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        let validation = ItineraryValidationService::new(self.client.clone());
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let outcome = match found.get(id) {
+                None => BulkOutcome::NotFound,
+                Some(itinerary) => {
+                    let mut reason = skip_reason(operation, params, itinerary, booked.contains(id));
+                    if reason.is_none() && operation == BulkOperation::Publish {
+                        let report = validation.validate(itinerary, limits, min_activity_minutes).await?;
+                        reason = validation_skip_reason(&report);
+                    }
+                    match reason {
+                        Some(reason) => BulkOutcome::Skipped { reason },
+                        None => BulkOutcome::Applied,
+                    }
+                }
+            };
+            results.push(BulkItemResult { id: id.to_hex(), outcome });
+        }
+        Ok(results)
+    }
+
+    /// Apply `operation` to whichever of `ids` pass their checks, and audit the
+    /// batch. `filter` is recorded when the ids came from one.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn apply(
+        &self,
+        admin_id: ObjectId,
+        operation: BulkOperation,
+        params: &BulkParams,
+        filter: Option<&BulkFilter>,
+        ids: &[ObjectId],
+        limits: &TripLimits,
+        min_activity_minutes: u16,
+    ) -> Result<Vec<BulkItemResult>, ItineraryBulkError> {
+        let results = self.plan(operation, params, ids, limits, min_activity_minutes).await?;
+        let applied_ids: Vec<ObjectId> = ids
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.outcome == BulkOutcome::Applied)
+            .map(|(id, _)| *id)
+            .collect();
+
+        if !applied_ids.is_empty() {
+            let namespace = self.itineraries().namespace();
+            let update = update_for(operation, params, DateTime::now());
+            let models = applied_ids.iter().map(|id| {
+                UpdateOneModel::builder()
+                    .namespace(namespace.clone())
+                    .filter(doc! { "_id": id })
+                    .update(update.clone())
+                    .build()
+            });
+            self.client.bulk_write(models).await.map_err(|e| {
+                ItineraryBulkError::DatabaseError(e.to_string())
+            })?;
+        }
+
+        self.record(ItineraryBulkAudit {
+            id: None,
+            action: "itineraries_bulk_updated".to_string(),
+            admin_id,
+            operation,
+            params: params.clone(),
+            filter: filter.cloned(),
+            itinerary_ids: ids.to_vec(),
+            applied_ids,
+            created_at: DateTime::now(),
+        })
+        .await;
+        Ok(results)
+    }
+
+    async fn record(&self, audit: ItineraryBulkAudit) {
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<ItineraryBulkAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for bulk itinerary {:?}: {}", audit.operation, e);
+        }
+    }
+
+    /// Hold `ids`, matched by `filter`, until the admin executes them. Returns the token.
+    pub async fn save_dry_run(
+        &self,
+        admin_id: ObjectId,
+        operation: BulkOperation,
+        params: &BulkParams,
+        filter: &BulkFilter,
+        ids: Vec<ObjectId>,
+        now: DateTime,
+    ) -> Result<String, ItineraryBulkError> {
+        let dry_run = BulkDryRun {
+            token: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+            admin_id,
+            operation,
+            params: params.clone(),
+            filter: filter.clone(),
+            itinerary_ids: ids,
+            created_at: now,
+            expires_at: minutes_after(now, DRY_RUN_TTL_MINUTES),
+        };
+        self.dry_runs().insert_one(&dry_run).await?;
+        Ok(dry_run.token)
+    }
+
+    /// The ids previewed under `token`, if it was this admin's dry run of the same
+    /// filter and operation. The token is used up.
+    pub async fn take_dry_run(
+        &self,
+        token: &str,
+        admin_id: ObjectId,
+        operation: BulkOperation,
+        params: &BulkParams,
+        filter: &BulkFilter,
+        now: DateTime,
+    ) -> Result<Vec<ObjectId>, ItineraryBulkError> {
+        let dry_run = self
+            .dry_runs()
+            .find_one(doc! { "_id": token, "expires_at": { "$gt": now } })
+            .await?
+            .ok_or(ItineraryBulkError::DryRunNotFound)?;
+        if dry_run.admin_id != admin_id
+            || dry_run.operation != operation
+            || &dry_run.params != params
+            || &dry_run.filter != filter
+        {
+            return Err(ItineraryBulkError::DryRunMismatch);
+        }
+        // Only one request gets to execute it
+        let taken = self.dry_runs().delete_one(doc! { "_id": token }).await?;
+        if taken.deleted_count == 0 {
+            return Err(ItineraryBulkError::DryRunNotFound);
+        }
+        Ok(dry_run.itinerary_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn itinerary() -> FeaturedVacation {
+        FeaturedVacation {
+            tags: vec!["winter".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn tags(tags: &[&str]) -> BulkParams {
+        BulkParams {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            difficulty: None,
+        }
+    }
+
+    #[test]
+    fn test_archive_is_refused_while_bookings_are_upcoming() {
+        let params = BulkParams::default();
+        assert_eq!(skip_reason(BulkOperation::Archive, &params, &itinerary(), false), None);
+        assert_eq!(
+            skip_reason(BulkOperation::Archive, &params, &itinerary(), true),
+            Some("Has confirmed bookings that haven't started".to_string())
+        );
+
+        let archived = FeaturedVacation {
+            archived_at: Some(DateTime::now()),
+            ..itinerary()
+        };
+        assert_eq!(
+            skip_reason(BulkOperation::Archive, &params, &archived, false),
+            Some("Already archived".to_string())
+        );
+        assert_eq!(skip_reason(BulkOperation::Publish, &params, &archived, false), None);
+
+        let taken_down = FeaturedVacation {
+            taken_down_at: Some(DateTime::now()),
+            ..archived
+        };
+        assert_eq!(
+            skip_reason(BulkOperation::Publish, &params, &taken_down, false),
+            Some("Taken down by moderation".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tag_changes_that_would_do_nothing_are_skipped() {
+        assert!(skip_reason(BulkOperation::AddTags, &tags(&["winter"]), &itinerary(), false).is_some());
+        assert!(skip_reason(BulkOperation::AddTags, &tags(&["winter", "ski"]), &itinerary(), false).is_none());
+        assert!(skip_reason(BulkOperation::RemoveTags, &tags(&["summer"]), &itinerary(), false).is_some());
+        assert!(skip_reason(BulkOperation::RemoveTags, &tags(&["summer", "winter"]), &itinerary(), false).is_none());
+    }
+
+    #[test]
+    fn test_params_are_required_by_operation() {
+        let params = tags(&[" ski ", "", "ski", "family"]).normalized();
+        assert_eq!(params.tags, vec!["ski", "family"]);
+        assert!(params.check(BulkOperation::AddTags).is_ok());
+        assert!(params.check(BulkOperation::SetDifficulty).is_err());
+        assert!(tags(&["  "]).normalized().check(BulkOperation::RemoveTags).is_err());
+        assert!(BulkParams::default().check(BulkOperation::Archive).is_ok());
+    }
+
+    #[test]
+    fn test_filter_needs_something_to_match() {
+        assert_eq!(BulkFilter::default().to_document(), None);
+        assert_eq!(
+            BulkFilter { tag: Some(" ".to_string()), created_before: None }.to_document(),
+            None
+        );
+
+        let cutoff = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let filter = BulkFilter {
+            tag: Some("generated".to_string()),
+            created_before: Some(cutoff),
+        };
+        let query = filter.to_document().unwrap();
+        let clauses = query.get_array("$and").unwrap();
+        assert_eq!(clauses.len(), 2);
+        assert_eq!(
+            clauses[0].as_document().unwrap(),
+            &doc! { "$or": [{ "tag": "generated" }, { "tags": "generated" }] }
+        );
+    }
+
+    #[test]
+    fn test_outcomes_serialize_flat() {
+        let result = BulkItemResult {
+            id: "abc".to_string(),
+            outcome: BulkOutcome::Skipped { reason: "Already archived".to_string() },
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({ "id": "abc", "outcome": "skipped", "reason": "Already archived" })
+        );
+    }
+}
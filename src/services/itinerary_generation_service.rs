@@ -1,49 +1,234 @@
 use crate::models::{
-    activity::Activity,
+    activity::{Activity, DEFAULT_MIN_ACTIVITY_MINUTES},
     itinerary::base::{DayItem, FeaturedVacation},
-    search::{SearchItinerary, TripPace},
+    search::{DayFloors, SearchItinerary, TripPace},
 };
-use crate::services::vertex_search_service::VertexSearchService;
+use crate::models::money::Money;
+use crate::services::activity_dedup_service::ActivityDedupService;
+use crate::services::calendar;
+use crate::services::distance_service::haversine_miles;
+use crate::services::generation_budget::GenerationBudget;
+use crate::services::pricing_service::PricingService;
+use crate::services::generation_trace::{DayOutcome, GenerationMetadata, GenerationTrace, SkipReason};
+use crate::services::image_fallback;
+use crate::services::location_autocomplete::{load_sources_in_state, LocationIndex};
+use crate::services::location_terms::{self, LocationTerm, UsState};
+use crate::services::moderation::Moderator;
+use crate::services::trip_limits::{LimitExceeded, TripLimits};
+use crate::services::units::{distance_placeholder, miles_to_meters};
+use crate::services::vertex_activity::{self, ActivityDefaults};
+use crate::services::vertex_search_service::{VertexSearchError, VertexSearchService, VertexSearchSettings};
 use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use rand::{rngs::StdRng, SeedableRng};
 use mongodb::{bson::oid::ObjectId, Client, Collection};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+
+/// How far a day's activity window may stretch past the pace maximum to reach the floor
+const DAY_FLOOR_WINDOW_EXTENSION: f32 = 1.5;
+
+/// Why an itinerary couldn't be generated
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerationError {
+    /// Nothing matched the search to build a schedule from
+    NoActivities,
+    /// Generation needs both arrival and departure dates
+    MissingDates,
+    /// Vertex AI failed and MongoDB had nothing to fall back on
+    ExternalServiceUnavailable(String),
+    /// Dates that don't parse, or a trip over the configured limits
+    InvalidInput(String),
+    Persistence(String),
+}
+
+impl std::fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GenerationError::NoActivities => write!(f, "No matching activities found"),
+            GenerationError::MissingDates => write!(f, "Arrival and departure datetimes are required"),
+            GenerationError::ExternalServiceUnavailable(e) => write!(f, "Activity search unavailable: {}", e),
+            GenerationError::InvalidInput(e) => write!(f, "{}", e),
+            GenerationError::Persistence(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+impl From<mongodb::error::Error> for GenerationError {
+    fn from(e: mongodb::error::Error) -> Self {
+        GenerationError::Persistence(e.to_string())
+    }
+}
+
+impl From<VertexSearchError> for GenerationError {
+    fn from(e: VertexSearchError) -> Self {
+        GenerationError::ExternalServiceUnavailable(e.to_string())
+    }
+}
+
+impl From<LimitExceeded> for GenerationError {
+    fn from(e: LimitExceeded) -> Self {
+        GenerationError::InvalidInput(e.to_string())
+    }
+}
+
+/// Images for a generated itinerary: the first of each scheduled activity's,
+/// highest price first. Saved with the itinerary so it has images of its own.
+fn scheduled_activity_images(days: &HashMap<String, Vec<DayItem>>, activities: &[Activity]) -> Vec<String> {
+    let by_id: HashMap<ObjectId, Activity> = activities
+        .iter()
+        .filter_map(|activity| activity.id.map(|id| (id, activity.clone())))
+        .collect();
+    image_fallback::activity_images(&image_fallback::scheduled_activities(days), &by_id)
+}
+
+/// Append how far apart the start and end are to a generated description. The
+/// distance is stored as a units placeholder, rendered per request. Round trips,
+/// and locations without coordinates, get no note.
+fn with_route_note(
+    description: String,
+    start: &crate::models::itinerary::base::Location,
+    end: &crate::models::itinerary::base::Location,
+) -> String {
+    // Stored as `[lng, lat]`
+    let (start_lng, start_lat) = start.coordinates();
+    let (end_lng, end_lat) = end.coordinates();
+    let unplaced = |lng: f32, lat: f32| lng == 0.0 && lat == 0.0;
+    if start.city().eq_ignore_ascii_case(end.city()) || unplaced(start_lng, start_lat) || unplaced(end_lng, end_lat) {
+        return description;
+    }
+    let miles = haversine_miles((start_lat as f64, start_lng as f64), (end_lat as f64, end_lng as f64));
+    if miles < 1.0 {
+        return description;
+    }
+    format!(
+        "{} {} to {} is about {} as the crow flies.",
+        description,
+        start.city(),
+        end.city(),
+        distance_placeholder(miles_to_meters(miles))
+    )
+}
+
+/// Scheduled activities whose price or capacity was guessed, which keep the
+/// itinerary from being paid for until it's re-priced
+fn defaulted_scheduled_activities(days: &HashMap<String, Vec<DayItem>>, activities: &[Activity]) -> Vec<ObjectId> {
+    let defaulted: HashSet<ObjectId> = activities
+        .iter()
+        .filter(|activity| activity.needs_pricing_review())
+        .filter_map(|activity| activity.id)
+        .collect();
+    let mut ids = image_fallback::scheduled_activities(days);
+    ids.retain(|id| defaulted.contains(id));
+    ids.dedup();
+    ids
+}
+
+/// Copies of `activities` with every duration at least `min_minutes`, flagging the
+/// ones that were shorter in the trace
+fn with_min_durations(
+    activities: &[Activity],
+    min_minutes: u16,
+    trace: &mut GenerationTrace,
+) -> Vec<Activity> {
+    activities
+        .iter()
+        .map(|activity| {
+            let mut scheduled = activity.clone();
+            if scheduled.clamp_duration(min_minutes) {
+                trace.record_short_duration(activity);
+            }
+            scheduled
+        })
+        .collect()
+}
 
 #[derive(Clone)]
 pub struct ItineraryGenerator {
     client: Arc<Client>,
     vertex_search_service: Option<VertexSearchService>,
+    trace_enabled: bool,
+    min_activity_minutes: u16,
+    limits: TripLimits,
+    activity_defaults: ActivityDefaults,
+    budget: GenerationBudget,
+    moderator: Moderator,
+    day_floors: DayFloors,
 }
 
 impl ItineraryGenerator {
     pub fn new(client: Arc<Client>) -> Self {
-        let vertex_search_service = match VertexSearchService::new() {
-            Ok(service) => {
-                println!("VertexSearchService initialized successfully");
-                Some(service)
-            }
-            Err(e) => {
-                println!(
-                    "VertexSearchService not available: {}. Using MongoDB only.",
-                    e
-                );
-                None
-            }
-        };
-
         Self {
             client,
-            vertex_search_service,
+            vertex_search_service: None,
+            trace_enabled: false,
+            min_activity_minutes: DEFAULT_MIN_ACTIVITY_MINUTES,
+            limits: TripLimits::default(),
+            activity_defaults: ActivityDefaults::default(),
+            budget: GenerationBudget::unlimited(),
+            moderator: Moderator::default(),
+            day_floors: DayFloors::default(),
         }
     }
 
+    /// Find activities through Vertex AI Search when it's configured; MongoDB is used either way
+    pub fn with_vertex_search(mut self, settings: Option<&VertexSearchSettings>) -> Self {
+        self.vertex_search_service = settings.map(VertexSearchService::new);
+        self
+    }
+
+    /// Least each generated day must hold at each pace
+    pub fn with_day_floors(mut self, day_floors: DayFloors) -> Self {
+        self.day_floors = day_floors;
+        self
+    }
+
+    /// Check generated names and descriptions, which carry raw search terms, before they're stored
+    pub fn with_moderator(mut self, moderator: Moderator) -> Self {
+        self.moderator = moderator;
+        self
+    }
+
+    /// Draw Vertex AI queries from the request's budget, falling back to MongoDB once it runs out
+    pub fn with_budget(mut self, budget: GenerationBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Attach a generation trace to every itinerary this generator produces
+    pub fn with_trace(mut self, enabled: bool) -> Self {
+        self.trace_enabled = enabled;
+        self
+    }
+
+    /// Schedule every activity for at least this long, whatever its stored duration
+    pub fn with_min_activity_minutes(mut self, minutes: u16) -> Self {
+        self.min_activity_minutes = minutes;
+        self
+    }
+
+    /// Refuse trips longer than the limit and read at most `max_activities_fetch`
+    /// activities for each itinerary
+    pub fn with_limits(mut self, limits: TripLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fill fields missing from Vertex AI documents with these
+    pub fn with_activity_defaults(mut self, defaults: ActivityDefaults) -> Self {
+        self.activity_defaults = defaults;
+        self
+    }
+
     /// Generate a new itinerary based on search parameters
     pub async fn generate_itinerary(
         &self,
         search_params: &SearchItinerary,
-    ) -> Result<FeaturedVacation, Box<dyn std::error::Error>> {
+    ) -> Result<FeaturedVacation, GenerationError> {
         // Get activities and locations
+        let seed: u32 = rand::random();
         let activities = self.fetch_activities(search_params).await?;
-        let locations = self.get_locations(search_params);
+        let locations = self.get_locations(search_params, seed).await;
 
         println!("🔍 Found {} activities total for itinerary generation", activities.len());
         for (i, activity) in activities.iter().enumerate() {
@@ -53,41 +238,54 @@ impl ItineraryGenerator {
 
         if activities.is_empty() {
             println!("❌ No activities found - cannot generate itinerary");
-            return Err("No matching activities found".into());
+            return Err(GenerationError::NoActivities);
         }
 
         // Calculate trip duration
         let arrival_str = search_params
             .arrival_datetime
             .as_ref()
-            .ok_or("Arrival datetime required")?;
+            .ok_or(GenerationError::MissingDates)?;
         let departure_str = search_params
             .departure_datetime
             .as_ref()
-            .ok_or("Departure datetime required")?;
+            .ok_or(GenerationError::MissingDates)?;
 
-        let arrival_date = self.parse_datetime(arrival_str)?;
-        let departure_date = self.parse_datetime(departure_str)?;
-        let trip_duration_days = (departure_date - arrival_date).num_days() as u32;
+        let arrival_date = Self::parse_datetime(arrival_str)?;
+        let departure_date = Self::parse_datetime(departure_str)?;
+        let trip_days = (departure_date - arrival_date).num_days();
+        self.limits.check_trip_days(trip_days)?;
+        let trip_duration_days = trip_days as u32;
 
         // Generate daily schedules based on trip pace
         let trip_pace = search_params.trip_pace.as_ref().unwrap_or(&TripPace::Moderate);
-        let days = self.generate_daily_schedules_with_pace(&activities, trip_duration_days, trip_pace)?;
+        // Always traced for the stored metadata; only sent back when asked for
+        let mut trace = GenerationTrace::new(true);
+        let days = self.generate_daily_schedules_with_pace(
+            &activities,
+            arrival_date.date(),
+            trip_duration_days,
+            trip_pace,
+            &mut trace,
+        )?;
         
         println!("🔄 Generated {} days with total items: {}", 
             days.len(), 
             days.values().map(|v| v.len()).sum::<usize>());
 
         // Calculate cost
-        let person_cost = self.calculate_cost(&days, &activities);
+        let person_cost = PricingService::calculate_cost(&days, &activities);
 
         // Create itinerary
         let trip_name = format!("{} Adventure", locations.0.city());
-        let description = format!(
-            "Discover {} with exciting activities and experiences.",
-            locations.0.city()
+        let description = with_route_note(
+            format!("Discover {} with exciting activities and experiences.", locations.0.city()),
+            &locations.0,
+            &locations.1,
         );
+        trace.record_budget(self.budget.report());
 
+        let defaulted_activity_ids = defaulted_scheduled_activities(&days, &activities);
         let generated_itinerary = FeaturedVacation {
             id: None,
             fareharbor_id: None,
@@ -100,8 +298,9 @@ impl ItineraryGenerator {
             start_location: locations.0.clone(),
             end_location: locations.1.clone(),
             description,
+            images: Some(scheduled_activity_images(&days, &activities)),
             days: crate::models::itinerary::base::Days { days },
-            images: Some(vec![]), // Initialize as empty array instead of None
+            primary_image: None,
             arrival_datetime: Some(mongodb::bson::DateTime::from_millis(
                 arrival_date.and_utc().timestamp_millis(),
             )),
@@ -127,8 +326,24 @@ impl ItineraryGenerator {
                     })
                     .collect(),
             ),
+            person_cost: Some(person_cost),
+            needs_review: false,
+            taken_down_at: None,
+            archived_at: None,
+            tags: Vec::new(),
+            difficulty: None,
+            missing_activity_ids: Vec::new(),
+            defaulted_activity_ids,
             match_score: None, // Will be set during search scoring
             score_breakdown: None, // Will be set during search scoring
+            generation_trace: self.trace_enabled.then(|| trace.clone()),
+            generation_metadata: Some(GenerationMetadata {
+                search: search_params.clone(),
+                variation_index: None,
+                seed,
+                generated_at: mongodb::bson::DateTime::now(),
+                selection: trace,
+            }),
         };
 
         Ok(generated_itinerary)
@@ -140,49 +355,58 @@ impl ItineraryGenerator {
         search_params: &SearchItinerary,
         variation_index: usize,
         existing_names: &std::collections::HashSet<String>,
-    ) -> Result<FeaturedVacation, String> {
+    ) -> Result<FeaturedVacation, GenerationError> {
         // Get activities and locations
-        let activities = self.fetch_activities(search_params).await.map_err(|e| e.to_string())?;
-        let locations = self.get_locations(search_params);
+        let seed: u32 = rand::random();
+        let activities = self.fetch_activities(search_params).await?;
+        let locations = self.get_locations(search_params, seed).await;
 
         if activities.is_empty() {
-            return Err("No matching activities found".to_string());
+            return Err(GenerationError::NoActivities);
         }
 
         // Calculate trip duration
         let arrival_str = search_params
             .arrival_datetime
             .as_ref()
-            .ok_or("Arrival datetime required".to_string())?;
+            .ok_or(GenerationError::MissingDates)?;
         let departure_str = search_params
             .departure_datetime
             .as_ref()
-            .ok_or("Departure datetime required".to_string())?;
+            .ok_or(GenerationError::MissingDates)?;
 
-        let arrival_date = self.parse_datetime(arrival_str).map_err(|e| e.to_string())?;
-        let departure_date = self.parse_datetime(departure_str).map_err(|e| e.to_string())?;
+        let arrival_date = Self::parse_datetime(arrival_str)?;
+        let departure_date = Self::parse_datetime(departure_str)?;
 
-        let trip_duration_days = (departure_date - arrival_date).num_days() as u32;
+        let trip_days = (departure_date - arrival_date).num_days();
+        self.limits.check_trip_days(trip_days)?;
+        let trip_duration_days = trip_days as u32;
 
         // Create unique trip name based on variation
-        let trip_name = self.generate_unique_trip_name(&locations.0, search_params, variation_index, existing_names);
+        let (trip_name, description) =
+            self.moderated_name_and_description(&locations.0, search_params, variation_index, existing_names);
+        let description = with_route_note(description, &locations.0, &locations.1);
 
         // Generate varied daily schedules
+        // Always traced for the stored metadata; only sent back when asked for
+        let mut trace = GenerationTrace::new(true);
         let days = self.generate_varied_daily_schedules_with_pace(
             &activities,
+            arrival_date.date(),
             trip_duration_days,
             search_params.trip_pace.as_ref(),
             variation_index,
-        ).map_err(|e| e.to_string())?;
+            &mut trace,
+        )?;
 
         // Calculate cost with some variation
-        let base_cost = self.calculate_cost(&days, &activities);
-        let cost_variation = (variation_index % 3) as f32 * 10.0; // Small cost variations
+        let base_cost = PricingService::calculate_cost(&days, &activities);
+        let cost_variation = Money::from_cents((variation_index % 3) as i64 * 1_000); // Small cost variations
         let person_cost = base_cost + cost_variation;
 
-        // Create description with variation
-        let description = self.generate_varied_description(&locations.0, search_params, variation_index);
+        trace.record_budget(self.budget.report());
 
+        let defaulted_activity_ids = defaulted_scheduled_activities(&days, &activities);
         let generated_itinerary = FeaturedVacation {
             id: None,
             fareharbor_id: None,
@@ -195,8 +419,9 @@ impl ItineraryGenerator {
             start_location: locations.0.clone(),
             end_location: locations.1.clone(),
             description,
+            images: Some(scheduled_activity_images(&days, &activities)),
             days: crate::models::itinerary::base::Days { days },
-            images: Some(vec![]), // Initialize as empty array instead of None
+            primary_image: None,
             arrival_datetime: Some(mongodb::bson::DateTime::from_millis(
                 arrival_date.and_utc().timestamp_millis(),
             )),
@@ -222,13 +447,66 @@ impl ItineraryGenerator {
                     })
                     .collect(),
             ),
+            person_cost: Some(person_cost),
+            needs_review: false,
+            taken_down_at: None,
+            archived_at: None,
+            tags: Vec::new(),
+            difficulty: None,
+            missing_activity_ids: Vec::new(),
+            defaulted_activity_ids,
             match_score: None,
             score_breakdown: None,
+            generation_trace: self.trace_enabled.then(|| trace.clone()),
+            generation_metadata: Some(GenerationMetadata {
+                search: search_params.clone(),
+                variation_index: Some(variation_index as u32),
+                seed,
+                generated_at: mongodb::bson::DateTime::now(),
+                selection: trace,
+            }),
         };
 
         Ok(generated_itinerary)
     }
 
+    /// Trip name and description, both built from the search's own terms, once
+    /// moderated. Text that fails moderation is replaced with a neutral template
+    /// ("Colorado Adventure") so a bad search term never fails the whole search.
+    fn moderated_name_and_description(
+        &self,
+        location: &crate::models::itinerary::base::Location,
+        search_params: &SearchItinerary,
+        variation_index: usize,
+        existing_names: &std::collections::HashSet<String>,
+    ) -> (String, String) {
+        // The state's name, or the city when the state isn't recognised
+        let region = || {
+            location_terms::resolve_state(location.state())
+                .map(|state| state.name.to_string())
+                .unwrap_or_else(|| location.city().to_string())
+        };
+        let moderated = |text: String, context: &str, fallback: String| match self.moderator.moderate(&text) {
+            Ok(text) => text,
+            Err(hit) => {
+                hit.log(context);
+                fallback
+            }
+        };
+
+        let trip_name = moderated(
+            self.generate_unique_trip_name(location, search_params, variation_index, existing_names),
+            "generated trip name",
+            format!("{} Adventure", region()),
+        );
+        let description = moderated(
+            self.generate_varied_description(location, search_params, variation_index),
+            "generated description",
+            format!("Discover {} with a trip built around its best activities.", region()),
+        );
+        (trip_name, description)
+    }
+
     /// Generate unique trip names with different themes
     fn generate_unique_trip_name(
         &self,
@@ -301,19 +579,22 @@ impl ItineraryGenerator {
     fn generate_varied_daily_schedules_with_pace(
         &self,
         activities: &[Activity],
+        start_date: NaiveDate,
         trip_duration_days: u32,
         trip_pace: Option<&TripPace>,
         variation_index: usize,
-    ) -> Result<HashMap<String, Vec<DayItem>>, String> {
+        trace: &mut GenerationTrace,
+    ) -> Result<HashMap<String, Vec<DayItem>>, GenerationError> {
         let pace = trip_pace.unwrap_or(&TripPace::Moderate);
         let max_hours_per_day = pace.max_activity_hours_per_day();
-        let activities_per_day = pace.typical_activities_per_day();
+        let day_floor = self.day_floors.for_pace(pace);
+        let activities_per_day = pace.typical_activities_per_day().max(day_floor.min_activities);
 
         let mut daily_schedules = HashMap::new();
         let mut used_activity_ids = std::collections::HashSet::new(); // Track used activities
 
         // Create shuffled activity list for variation
-        let mut available_activities = activities.to_vec();
+        let mut available_activities = with_min_durations(activities, self.min_activity_minutes, trace);
         
         // Shuffle based on variation_index for different orderings
         for i in 0..available_activities.len() {
@@ -322,8 +603,13 @@ impl ItineraryGenerator {
         }
         
         let mut global_activity_index = 0; // Track position in shuffled list
+        let dates = calendar::trip_dates(start_date, trip_duration_days);
+        trace.record_considered(activities);
 
         for day in 1..=trip_duration_days {
+            let today = dates[(day - 1) as usize];
+            let remaining_dates = &dates[(day - 1) as usize..];
+            record_closures(trace, day, &available_activities, today);
             let mut day_schedule = Vec::new();
             let mut day_hours = 0.0;
             let mut activities_added = 0;
@@ -337,27 +623,31 @@ impl ItineraryGenerator {
             };
 
             let mut current_hour = base_start_hour;
+            let mut window_hours = max_hours_per_day;
+            let mut window_extended = false;
             
-            while activities_added < activities_per_day && day_hours < max_hours_per_day {
-                // Find next unused activity
+            while activities_added < activities_per_day && day_hours < window_hours {
+                // Find next unused activity that is running today. Activities with fewer
+                // operating days left in the trip go first so they aren't crowded out.
                 let mut found_activity = false;
-                let start_search_index = global_activity_index;
-                
-                loop {
-                    if global_activity_index >= available_activities.len() {
-                        // If we've gone through all activities, we're done
-                        break;
-                    }
-                    
-                    let activity = &available_activities[global_activity_index];
+                let candidates = operating_candidates(
+                    &available_activities,
+                    global_activity_index,
+                    today,
+                    remaining_dates,
+                );
+
+                for idx in candidates {
+                    let activity = &available_activities[idx];
                     
                     // Check if this activity is already used
                     if let Some(activity_id) = activity.id {
                         if !used_activity_ids.contains(&activity_id) {
                             let activity_duration_hours = activity.duration_minutes as f32 / 60.0;
                             
-                            if day_hours + activity_duration_hours <= max_hours_per_day {
+                            if day_hours + activity_duration_hours <= window_hours {
                                 let time = format!("{:02}:00:00", current_hour);
+                                trace.record_scheduled(day, Some(today.to_string()), activity, activity_id, &time);
                                 
                                 day_schedule.push(DayItem::Activity {
                                     activity_id,
@@ -378,42 +668,62 @@ impl ItineraryGenerator {
                                 };
                                 current_hour += buffer_hours;
                                 
-                                global_activity_index += 1;
+                                global_activity_index = (idx + 1) % available_activities.len();
                                 found_activity = true;
                                 break;
+                            } else {
+                                trace.record_skip(day, activity, SkipReason::ExceedsDailyHours);
                             }
+                        } else {
+                            trace.record_skip(day, activity, SkipReason::AlreadyUsed);
                         }
                     }
-                    
-                    global_activity_index += 1;
-                    
-                    // If we've cycled back to where we started, stop looking
-                    if global_activity_index >= available_activities.len() {
-                        break;
-                    }
                 }
                 
                 if !found_activity {
+                    // Below the pace floor, extend the window once to fit a longer activity
+                    if !day_floor.is_met(activities_added, day_hours) && !window_extended {
+                        window_hours = max_hours_per_day * DAY_FLOOR_WINDOW_EXTENSION;
+                        window_extended = true;
+                        continue;
+                    }
                     // No more suitable unused activities available
                     break;
                 }
             }
 
+            trace.record_day(DayOutcome {
+                day,
+                activities: activities_added,
+                hours: day_hours,
+                window_extended,
+                below_floor: !day_floor.is_met(activities_added, day_hours),
+            });
+
             if !day_schedule.is_empty() {
                 daily_schedules.insert(day.to_string(), day_schedule);
             }
         }
 
+        record_dropped(trace, trip_duration_days, &available_activities, &dates);
+
         Ok(daily_schedules)
     }
 
-    /// Fetch activities using Vertex AI first, MongoDB as fallback
+    /// Fetch activities using Vertex AI first, MongoDB as fallback. Nothing from
+    /// MongoDB after Vertex AI failed is reported as the outage, not as no matches.
     async fn fetch_activities(
         &self,
         search_params: &SearchItinerary,
-    ) -> Result<Vec<Activity>, mongodb::error::Error> {
-        // Always try Vertex AI first - even with minimal search criteria
-        if let Some(ref vertex_service) = self.vertex_search_service {
+    ) -> Result<Vec<Activity>, GenerationError> {
+        let mut vertex_failure = None;
+        // Always try Vertex AI first - even with minimal search criteria - while the
+        // request's budget allows
+        if let Some(vertex_service) = self
+            .vertex_search_service
+            .as_ref()
+            .filter(|_| self.budget.try_vertex_query())
+        {
             // Build query from available search parameters
             let activities_query = search_params
                 .activities
@@ -429,132 +739,55 @@ impl ItineraryGenerator {
 
             println!("Trying Vertex AI search with activities: {:?}, location: {}", activities_query, location_query);
 
-            if let Ok(vertex_response) =
-                vertex_service.search_activities(&activities_query, &location_query).await
-            {
-                if !vertex_response.results.is_empty() {
+            match vertex_service.search_activities(&activities_query, &location_query).await {
+                Ok(vertex_response) if !vertex_response.results.is_empty() => {
                     println!("Vertex AI returned {} activity results", vertex_response.results.len());
                     let mut vertex_activities = Vec::new();
-                    let _collection: Collection<Activity> =
-                        self.client.database("Options").collection("Activity");
-
                     for result in vertex_response.results.iter() {
-                            // Transform Vertex AI document to match Activity struct format
-                            let mut vertex_data = result.document.struct_data.clone();
-                            
-                            // Transform daily_time_slots from strings to TimeSlot objects
-                            if let Some(time_slots) = vertex_data.get("daily_time_slots").and_then(|v| v.as_array()) {
-                                let transformed_slots: Vec<serde_json::Value> = time_slots.iter()
-                                    .filter_map(|slot| {
-                                        if let Some(slot_str) = slot.as_str() {
-                                            let parts: Vec<&str> = slot_str.split('-').collect();
-                                            if parts.len() == 2 {
-                                                Some(serde_json::json!({
-                                                    "start": parts[0],
-                                                    "end": parts[1]
-                                                }))
-                                            } else {
-                                                None
-                                            }
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .collect();
-                                
-                                if let Some(data_obj) = vertex_data.as_object_mut() {
-                                    data_obj.insert(
-                                        "daily_time_slots".to_string(),
-                                        serde_json::Value::Array(transformed_slots)
-                                    );
-                                }
+                        match vertex_activity::activity_from_vertex(&result.document.struct_data, &self.activity_defaults) {
+                            Ok(activity) if activity.id.is_none() => {
+                                println!("Warning: Vertex AI document {} has no ObjectId, skipping", result.document.id);
                             }
-
-                            // Add missing required fields with defaults
-                            if let Some(data_obj) = vertex_data.as_object_mut() {
-                                if !data_obj.contains_key("description") {
-                                    if let Some(title) = data_obj.get("title").and_then(|v| v.as_str()) {
-                                        data_obj.insert("description".to_string(), serde_json::Value::String(title.to_string()));
-                                    } else {
-                                        data_obj.insert("description".to_string(), serde_json::Value::String("Activity description".to_string()));
-                                    }
-                                }
-
-                                // Ensure address field exists with proper structure
-                                if !data_obj.contains_key("address") {
-                                    let address = serde_json::json!({
-                                        "street": data_obj.get("full_address").and_then(|v| v.as_str()).unwrap_or(""),
-                                        "unit": "",
-                                        "city": data_obj.get("city").and_then(|v| v.as_str()).unwrap_or(""),
-                                        "state": data_obj.get("state").and_then(|v| v.as_str()).unwrap_or(""),
-                                        "zip": data_obj.get("zip").and_then(|v| v.as_str()).unwrap_or(""),
-                                        "country": data_obj.get("country").and_then(|v| v.as_str()).unwrap_or("USA")
-                                    });
-                                    data_obj.insert("address".to_string(), address);
-                                }
-
-                                // Ensure capacity field exists
-                                if !data_obj.contains_key("capacity") {
-                                    let capacity = serde_json::json!({
-                                        "minimum": data_obj.get("min_capacity").and_then(|v| v.as_u64()).unwrap_or(1),
-                                        "maximum": data_obj.get("max_capacity").and_then(|v| v.as_u64()).unwrap_or(100)
-                                    });
-                                    data_obj.insert("capacity".to_string(), capacity);
-                                }
-
-                                // Handle ID field - Vertex AI returns 'id' as string, convert to ObjectId
-                                if let Some(id_val) = data_obj.get("id") {
-                                    if let Some(id_str) = id_val.as_str() {
-                                        // Try to parse the string as an ObjectId
-                                        if let Ok(object_id) = ObjectId::parse_str(id_str) {
-                                            // Insert as proper ObjectId for _id field
-                                            data_obj.insert("_id".to_string(), serde_json::json!({
-                                                "$oid": id_str
-                                            }));
-                                        } else {
-                                            println!("Warning: Invalid ObjectId format from Vertex AI: {}", id_str);
-                                        }
-                                    }
+                            Ok(activity) => {
+                                if !activity.defaulted_fields.is_empty() {
+                                    println!(
+                                        "⚠️ Vertex activity '{}' ({:?}) defaulted {:?}",
+                                        activity.title, activity.id, activity.defaulted_fields
+                                    );
                                 }
+                                vertex_activities.push(activity);
                             }
-
-                            // Try to parse the transformed document as an Activity
-                            match serde_json::from_value::<Activity>(vertex_data) {
-                                Ok(mut activity) => {
-                                    // If the ID wasn't properly parsed, try to extract it manually
-                                    if activity.id.is_none() {
-                                        if let Some(id_val) = result.document.struct_data.get("id") {
-                                            if let Some(id_str) = id_val.as_str() {
-                                                if let Ok(object_id) = ObjectId::parse_str(id_str) {
-                                                    activity.id = Some(object_id);
-                                                    println!("Manually set VERTEX ID: {:?}", object_id);
-                                                }
-                                            }
-                                        }
-                                    }
-                                    println!("FINAL VERTEX ID: {:?}", activity.id);
-                                    vertex_activities.push(activity);
-                                }
-                                Err(e) => {
-                                    println!("Failed to parse Vertex AI document as Activity: {}", e);
-                                    println!("Document data: {:?}", result.document.struct_data);
-                                }
+                            Err(e) => {
+                                println!("Failed to parse Vertex AI document {}: {}", result.document.id, e);
                             }
                         }
+                    }
 
                     if !vertex_activities.is_empty() {
                         println!(
                             "Found {} activities using Vertex AI",
                             vertex_activities.len()
                         );
-                        return Ok(vertex_activities);
+                        // The index can still hold activities merged since its last sync
+                        return Ok(ActivityDedupService::new(self.client.clone())
+                            .resolve_merged(vertex_activities)
+                            .await?);
                     }
                 }
+                Ok(_) => {}
+                Err(e) => {
+                    println!("Vertex AI search failed: {}", e);
+                    vertex_failure = Some(e);
+                }
             }
         }
 
         // Fallback to MongoDB
-        self.fetch_activities_from_mongodb(search_params).await
+        let activities = self.fetch_activities_from_mongodb(search_params).await?;
+        match vertex_failure {
+            Some(e) if activities.is_empty() => Err(e.into()),
+            _ => Ok(activities),
+        }
     }
 
     /// Fallback MongoDB search
@@ -564,7 +797,8 @@ impl ItineraryGenerator {
     ) -> Result<Vec<Activity>, mongodb::error::Error> {
         let collection: Collection<Activity> =
             self.client.database("Options").collection("Activity");
-        let mut filter = mongodb::bson::doc! {};
+        // Duplicates merged into another activity are left out
+        let mut filter = mongodb::bson::doc! { "merged_into": null };
 
         // Add activity filter if provided
         if let Some(activities) = &search_params.activities {
@@ -587,38 +821,52 @@ impl ItineraryGenerator {
             }
         }
 
-        let cursor = collection.find(filter).limit(10).await?;
+        let cursor = collection
+            .find(filter)
+            .limit(self.limits.max_activities_fetch as i64)
+            .await?;
         let activities: Vec<Activity> = cursor.try_collect().await?;
 
         println!("Found {} activities from MongoDB", activities.len());
         Ok(activities)
     }
 
-    /// Get locations from search params or use default
-    fn get_locations(
+    /// Get locations from search params or use default. A named city wins over a
+    /// state; a state alone gets a city in it that has inventory, picked with `seed`.
+    async fn get_locations(
         &self,
         search_params: &SearchItinerary,
+        seed: u32,
     ) -> (
         crate::models::itinerary::base::Location,
         crate::models::itinerary::base::Location,
     ) {
-        if let Some(locations) = &search_params.locations {
-            if let Some(location_str) = locations.first() {
-                let parts: Vec<&str> = location_str.split(',').map(|s| s.trim()).collect();
-                if parts.len() >= 2 {
-                    let city = parts[0];
-                    let state = parts[1];
-                    let coords = self.get_coordinates(city, state);
-
-                    let location: crate::models::itinerary::base::Location =
-                        serde_json::from_value(serde_json::json!({
-                            "city": city,
-                            "state": state,
-                            "coordinates": [coords.1, coords.0] // [longitude, latitude]
-                        }))
-                        .unwrap();
-                    return (location.clone(), location);
-                }
+        let terms = search_params
+            .locations
+            .as_deref()
+            .map(location_terms::parse_terms)
+            .unwrap_or_default();
+
+        let named_city = terms.iter().find_map(|term| match term {
+            LocationTerm::City {
+                city,
+                state: Some(state),
+            } => Some((city.as_str(), state.as_str())),
+            _ => None,
+        });
+        if let Some((city, state)) = named_city {
+            let coords = self.get_coordinates(city, state);
+            let location = Self::location(city, state, [coords.1, coords.0]); // [longitude, latitude]
+            return (location.clone(), location);
+        }
+
+        let searched_state = terms.iter().find_map(|term| match term {
+            LocationTerm::State(state) => Some(*state),
+            LocationTerm::City { .. } => None,
+        });
+        if let Some(state) = searched_state {
+            if let Some(location) = self.destination_in_state(state, seed).await {
+                return (location.clone(), location);
             }
         }
 
@@ -633,6 +881,44 @@ impl ItineraryGenerator {
         (default_location.clone(), default_location)
     }
 
+    fn location(city: &str, state: &str, coordinates: [f64; 2]) -> crate::models::itinerary::base::Location {
+        serde_json::from_value(serde_json::json!({
+            "city": city,
+            "state": state,
+            "coordinates": coordinates
+        }))
+        .unwrap()
+    }
+
+    /// A city in `state` to generate in, favoring the ones with the most inventory
+    async fn destination_in_state(
+        &self,
+        state: &UsState,
+        seed: u32,
+    ) -> Option<crate::models::itinerary::base::Location> {
+        let sources = match load_sources_in_state(&self.client, state).await {
+            Ok(sources) => sources,
+            Err(e) => {
+                eprintln!("Failed to load destinations in {}: {}", state.name, e);
+                return None;
+            }
+        };
+        let Some(city) = LocationIndex::build(sources).pick_in_state(state, &mut StdRng::seed_from_u64(seed as u64)) else {
+            println!("📍 No destinations with inventory in {}", state.name);
+            return None;
+        };
+        println!("📍 Generating in {} for a search of {}", city.display, state.name);
+
+        let coordinates = match city.coordinates {
+            Some((x, y)) => [x, y],
+            None => {
+                let (lat, lng) = self.get_coordinates(&city.city, &city.state);
+                [lng, lat]
+            }
+        };
+        Some(Self::location(&city.city, &city.state, coordinates))
+    }
+
     /// Simple coordinate lookup
     fn get_coordinates(&self, city: &str, state: &str) -> (f64, f64) {
         match (city.to_lowercase().as_str(), state.to_lowercase().as_str()) {
@@ -648,9 +934,11 @@ impl ItineraryGenerator {
     fn generate_daily_schedules_with_pace(
         &self,
         activities: &[Activity],
+        start_date: NaiveDate,
         trip_duration_days: u32,
         trip_pace: &TripPace,
-    ) -> Result<HashMap<String, Vec<DayItem>>, Box<dyn std::error::Error>> {
+        trace: &mut GenerationTrace,
+    ) -> Result<HashMap<String, Vec<DayItem>>, GenerationError> {
         println!("📅 Generating schedules for {} activities:", activities.len());
         for (i, activity) in activities.iter().enumerate() {
             println!("   Activity {}: ID={:?}, Title={}", i+1, activity.id, activity.title);
@@ -660,18 +948,24 @@ impl ItineraryGenerator {
         let mut used_activity_ids = std::collections::HashSet::new(); // Track used activities
         
         // Determine activities per day based on trip pace
-        let activities_per_day = trip_pace.typical_activities_per_day();
+        let day_floor = self.day_floors.for_pace(trip_pace);
+        let activities_per_day = trip_pace.typical_activities_per_day().max(day_floor.min_activities);
         let max_hours_per_day = trip_pace.max_activity_hours_per_day();
         
-        println!("Trip pace: {:?}, activities per day: {}, max hours: {}", 
-            trip_pace, activities_per_day, max_hours_per_day);
+        println!("Trip pace: {:?}, activities per day: {}, max hours: {}, floor: {:?}", 
+            trip_pace, activities_per_day, max_hours_per_day, day_floor);
 
         // Create a shuffled copy of activities for variety
-        let mut available_activities = activities.to_vec();
+        let available_activities = with_min_durations(activities, self.min_activity_minutes, trace);
         let mut global_activity_index = 0;
+        let dates = calendar::trip_dates(start_date, trip_duration_days);
+        trace.record_considered(activities);
 
         for day_num in 1..=trip_duration_days {
             let day_key = day_num.to_string();
+            let today = dates[(day_num - 1) as usize];
+            let remaining_dates = &dates[(day_num - 1) as usize..];
+            record_closures(trace, day_num, &available_activities, today);
             let mut day_items = Vec::new();
             let mut day_hours = 0.0;
             
@@ -683,16 +977,22 @@ impl ItineraryGenerator {
             };
 
             let mut activities_added = 0;
+            let mut window_hours = max_hours_per_day;
+            let mut window_extended = false;
             
             // Add activities until we reach the pace limit or run out of hours
-            while activities_added < activities_per_day && day_hours < max_hours_per_day {
-                // Find next unused activity
+            while activities_added < activities_per_day && day_hours < window_hours {
+                // Find next unused activity that is running today
                 let mut found_activity = false;
-                let start_search_index = global_activity_index;
                 
-                // Search for unused activity starting from current index
-                for search_offset in 0..available_activities.len() {
-                    let idx = (global_activity_index + search_offset) % available_activities.len();
+                // Search from the current index, most date-constrained activities first
+                let candidates = operating_candidates(
+                    &available_activities,
+                    global_activity_index,
+                    today,
+                    remaining_dates,
+                );
+                for idx in candidates {
                     let activity = &available_activities[idx];
                     
                     if let Some(activity_id) = activity.id {
@@ -701,9 +1001,16 @@ impl ItineraryGenerator {
                             let activity_duration_hours = activity.duration_minutes as f32 / 60.0;
                             
                             // Check if adding this activity would exceed daily hour limit
-                            if day_hours + activity_duration_hours <= max_hours_per_day {
+                            if day_hours + activity_duration_hours <= window_hours {
                                 println!("   📍 Day {}: Adding activity '{}' (ID: {:?}) at {}", 
                                     day_num, activity.title, activity_id, current_time.format("%H:%M:%S"));
+                                trace.record_scheduled(
+                                    day_num,
+                                    Some(today.to_string()),
+                                    activity,
+                                    activity_id,
+                                    &current_time.format("%H:%M:%S").to_string(),
+                                );
                                 
                                 day_items.push(DayItem::Activity {
                                     time: current_time.format("%H:%M:%S").to_string(),
@@ -727,52 +1034,59 @@ impl ItineraryGenerator {
                                 break;
                             } else {
                                 println!("   ⚠️  Day {}: Skipping activity '{}' - would exceed daily hour limit ({} + {} > {})", 
-                                    day_num, activity.title, day_hours, activity_duration_hours, max_hours_per_day);
+                                    day_num, activity.title, day_hours, activity_duration_hours, window_hours);
+                                trace.record_skip(day_num, activity, SkipReason::ExceedsDailyHours);
                             }
+                        } else {
+                            trace.record_skip(day_num, activity, SkipReason::AlreadyUsed);
                         }
                     }
                 }
                 
                 if !found_activity {
+                    // Below the pace floor, extend the window once to fit a longer activity
+                    if !day_floor.is_met(activities_added, day_hours) && !window_extended {
+                        window_hours = max_hours_per_day * DAY_FLOOR_WINDOW_EXTENSION;
+                        window_extended = true;
+                        println!("   ↔️  Day {}: Below pace floor, extending window to {:.1} hours", 
+                            day_num, window_hours);
+                        continue;
+                    }
                     println!("   ⚠️  Day {}: No more suitable unused activities available", day_num);
                     break;
                 }
             }
             
-            println!("   ✅ Day {}: Added {} activities, total hours: {:.1}", 
-                day_num, activities_added, day_hours);
+            println!("   ✅ Day {}: Added {} activities, total hours: {:.1} ({})", 
+                day_num, activities_added, day_hours, today.format("%a %Y-%m-%d"));
+            if !day_floor.is_met(activities_added, day_hours) {
+                println!("   ⚠️  Day {}: Below pace floor {:?} - activity pool exhausted", day_num, day_floor);
+            }
+            trace.record_day(DayOutcome {
+                day: day_num,
+                activities: activities_added,
+                hours: day_hours,
+                window_extended,
+                below_floor: !day_floor.is_met(activities_added, day_hours),
+            });
 
             days.insert(day_key, day_items);
         }
 
-        Ok(days)
-    }
-
-    /// Calculate total cost
-    fn calculate_cost(&self, days: &HashMap<String, Vec<DayItem>>, activities: &[Activity]) -> f32 {
-        let activity_costs: HashMap<ObjectId, f32> = activities
-            .iter()
-            .filter_map(|a| a.id.map(|id| (id, a.price_per_person)))
-            .collect();
-
-        let mut total_cost = 0.0;
-        for day_items in days.values() {
-            for item in day_items {
-                if let DayItem::Activity { activity_id, .. } = item {
-                    if let Some(cost) = activity_costs.get(activity_id) {
-                        total_cost += cost;
-                    }
-                }
+        for activity in &available_activities {
+            if calendar::operating_day_count(activity, &dates) == 0 {
+                println!("   🚫 Dropped activity '{}' - not operating on any day of the trip", activity.title);
             }
         }
-        total_cost
+        record_dropped(trace, trip_duration_days, &available_activities, &dates);
+
+        Ok(days)
     }
 
     /// Enhanced datetime parsing that handles various formats
-    fn parse_datetime(
-        &self,
+    pub fn parse_datetime(
         datetime_str: &str,
-    ) -> Result<chrono::NaiveDateTime, Box<dyn std::error::Error>> {
+    ) -> Result<chrono::NaiveDateTime, GenerationError> {
         let trimmed = datetime_str.trim();
 
         println!("Attempting to parse datetime: '{}'", trimmed);
@@ -871,81 +1185,7 @@ impl ItineraryGenerator {
             return Ok(date.and_hms_opt(0, 0, 0).unwrap());
         }
 
-        Err(format!("Unable to parse datetime '{}'. Supported formats include: YYYY-MM-DD, MM/DD/YYYY, Jul 22T09:00:00, etc.", trimmed).into())
-    }
-
-    /// Simple Vertex AI activity parsing
-    fn parse_vertex_activity(
-        &self,
-        result: &crate::services::vertex_search_service::SearchResult,
-    ) -> Result<Activity, Box<dyn std::error::Error>> {
-        let struct_data = result
-            .document
-            .struct_data
-            .as_object()
-            .ok_or("struct_data is not an object")?;
-
-        // Extract basic fields with defaults
-        let title = struct_data
-            .get("title")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Activity")
-            .to_string();
-        let description = struct_data
-            .get("description")
-            .and_then(|v| v.as_str())
-            .unwrap_or(&title)
-            .to_string();
-        let price = struct_data
-            .get("price_per_person")
-            .and_then(|v| v.as_f64())
-            .unwrap_or(50.0) as f32;
-        let duration = struct_data
-            .get("duration_minutes")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(120) as u16;
-
-        // Create simple activity
-        let activity = Activity {
-            id: None, // Will be assigned when needed
-            company: "Generated".to_string(),
-            company_id: "generated".to_string(),
-            booking_link: "".to_string(),
-            online_booking_status: "available".to_string(),
-            guide: None,
-            title,
-            description,
-            activity_types: vec![],
-            tags: vec![],
-            price_per_person: price,
-            duration_minutes: duration,
-            daily_time_slots: vec![],
-            address: crate::models::activity::Address {
-                street: "".to_string(),
-                unit: "".to_string(),
-                city: struct_data
-                    .get("city")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Denver")
-                    .to_string(),
-                state: "CO".to_string(),
-                zip: "".to_string(),
-                country: "USA".to_string(),
-            },
-            whats_included: vec![],
-            weight_limit_lbs: None,
-            age_requirement: None,
-            height_requiremnt: None,
-            blackout_date_ranges: None,
-            capacity: crate::models::activity::Capacity {
-                minimum: 1,
-                maximum: 100,
-            },
-            created_at: None,
-            updated_at: None,
-        };
-
-        Ok(activity)
+        Err(GenerationError::InvalidInput(format!("Unable to parse datetime '{}'. Supported formats include: YYYY-MM-DD, MM/DD/YYYY, Jul 22T09:00:00, etc.", trimmed)))
     }
 
     /// Simple title case conversion
@@ -968,3 +1208,384 @@ impl ItineraryGenerator {
 }
 
 use futures::TryStreamExt;
+
+/// Indices of activities running on `today`, in rotation order starting at `start_index`.
+/// Activities with the fewest operating days left in the trip come first, so an activity
+/// that only runs on one of the remaining days is scheduled there instead of being dropped.
+fn operating_candidates(
+    activities: &[Activity],
+    start_index: usize,
+    today: NaiveDate,
+    remaining_dates: &[NaiveDate],
+) -> Vec<usize> {
+    if activities.is_empty() {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<(usize, usize)> = (0..activities.len())
+        .map(|offset| (start_index + offset) % activities.len())
+        .filter(|&idx| calendar::is_operating_on(&activities[idx], today))
+        .map(|idx| (idx, calendar::operating_day_count(&activities[idx], remaining_dates)))
+        .collect();
+
+    // Stable sort keeps the rotation order among equally constrained activities
+    candidates.sort_by_key(|&(_, remaining)| remaining);
+    candidates.into_iter().map(|(idx, _)| idx).collect()
+}
+
+/// Record every activity closed on `today`, with the calendar reason
+fn record_closures(trace: &mut GenerationTrace, day: u32, activities: &[Activity], today: NaiveDate) {
+    if !trace.is_enabled() {
+        return;
+    }
+    for activity in activities {
+        if let Some(reason) = calendar::closure_reason(activity, today) {
+            trace.record_skip(day, activity, reason.into());
+        }
+    }
+}
+
+/// Record activities that could not run on any day of the trip
+fn record_dropped(trace: &mut GenerationTrace, last_day: u32, activities: &[Activity], dates: &[NaiveDate]) {
+    if !trace.is_enabled() {
+        return;
+    }
+    for activity in activities {
+        if calendar::operating_day_count(activity, dates) == 0 {
+            trace.record_skip(last_day, activity, SkipReason::NotOperatingDuringTrip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::units::{self, UnitSystem};
+    use chrono::Weekday;
+    use mongodb::options::{ClientOptions, ServerAddress};
+
+    fn test_generator() -> ItineraryGenerator {
+        // The client connects lazily, so no server is needed for schedule generation
+        let options = ClientOptions::builder()
+            .hosts(vec![ServerAddress::Tcp {
+                host: "localhost".to_string(),
+                port: Some(27017),
+            }])
+            .build();
+        ItineraryGenerator {
+            client: Arc::new(Client::with_options(options).unwrap()),
+            vertex_search_service: None,
+            trace_enabled: false,
+            min_activity_minutes: DEFAULT_MIN_ACTIVITY_MINUTES,
+            limits: TripLimits::default(),
+            activity_defaults: ActivityDefaults::default(),
+            budget: GenerationBudget::unlimited(),
+            moderator: Moderator::default(),
+            day_floors: DayFloors::default(),
+        }
+    }
+
+    fn test_activity(title: &str, operating_days: Option<Vec<Weekday>>) -> Activity {
+        Activity {
+            operating_days,
+            ..Activity::test(title)
+        }
+    }
+
+    #[test]
+    fn test_unparseable_dates_and_long_trips_are_invalid_input() {
+        assert!(matches!(
+            ItineraryGenerator::parse_datetime("sometime next week"),
+            Err(GenerationError::InvalidInput(_))
+        ));
+        let err: GenerationError = TripLimits::default().check_trip_days(400).unwrap_err().into();
+        assert!(matches!(err, GenerationError::InvalidInput(ref message) if message.contains("400 days")));
+    }
+
+    #[actix_rt::test]
+    async fn test_generated_itinerary_keeps_scheduled_activity_images() {
+        let generator = test_generator();
+        let mut hike = test_activity("Hike", None);
+        hike.images = vec!["hike.jpg".to_string()];
+        let mut balloon = test_activity("Balloon Ride", None);
+        balloon.price_per_person = 250.0;
+        balloon.images = vec!["balloon.jpg".to_string(), "balloon-2.jpg".to_string()];
+        let activities = vec![hike, balloon, test_activity("Museum", None)];
+
+        let days = generator
+            .generate_daily_schedules_with_pace(
+                &activities,
+                NaiveDate::from_ymd_opt(2025, 6, 5).unwrap(),
+                2,
+                &TripPace::Relaxed,
+                &mut GenerationTrace::default(),
+            )
+            .unwrap();
+
+        assert_eq!(scheduled_activity_images(&days, &activities), vec!["balloon.jpg", "hike.jpg"]);
+        // Nothing to copy when no scheduled activity has images
+        let imageless: Vec<Activity> = activities
+            .iter()
+            .cloned()
+            .map(|mut activity| {
+                activity.images.clear();
+                activity
+            })
+            .collect();
+        assert!(scheduled_activity_images(&days, &imageless).is_empty());
+    }
+
+    fn scheduled_on(days: &HashMap<String, Vec<DayItem>>, day: &str, activity: &Activity) -> bool {
+        days.get(day).map_or(false, |items| {
+            items.iter().any(|item| {
+                matches!(item, DayItem::Activity { activity_id, .. } if Some(*activity_id) == activity.id)
+            })
+        })
+    }
+
+    #[actix_rt::test]
+    async fn test_weekend_only_activity_lands_on_saturday() {
+        let generator = test_generator();
+        let weekend_only = test_activity("Weekend Tour", Some(vec![Weekday::Sat, Weekday::Sun]));
+        let activities = vec![
+            weekend_only.clone(),
+            test_activity("Hike", None),
+            test_activity("Museum", None),
+            test_activity("Brewery", None),
+        ];
+
+        // Thursday through Sunday
+        let thursday = NaiveDate::from_ymd_opt(2025, 6, 5).unwrap();
+        let days = generator
+            .generate_daily_schedules_with_pace(
+                &activities,
+                thursday,
+                4,
+                &TripPace::Relaxed,
+                &mut GenerationTrace::default(),
+            )
+            .unwrap();
+
+        assert!(scheduled_on(&days, "3", &weekend_only));
+        for day in ["1", "2", "4"] {
+            assert!(!scheduled_on(&days, day, &weekend_only));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_sunday_closed_activity_never_on_sunday() {
+        let generator = test_generator();
+        let closed_sunday = test_activity(
+            "Rafting",
+            Some(vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Sat,
+            ]),
+        );
+        let activities = vec![closed_sunday.clone(), test_activity("Hike", None)];
+
+        let sunday = NaiveDate::from_ymd_opt(2025, 6, 8).unwrap();
+        for variation_index in 0..3 {
+            let days = generator
+                .generate_varied_daily_schedules_with_pace(
+                    &activities,
+                    sunday,
+                    3,
+                    Some(&TripPace::Relaxed),
+                    variation_index,
+                    &mut GenerationTrace::default(),
+                )
+                .unwrap();
+            assert!(!scheduled_on(&days, "1", &closed_sunday));
+            assert!(scheduled_on(&days, "2", &closed_sunday));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_holiday_closed_activity_excluded() {
+        let generator = test_generator();
+        let mut tour = test_activity("City Tour", None);
+        tour.closed_on_holidays = true;
+
+        // July 4th, then the 5th
+        let independence_day = NaiveDate::from_ymd_opt(2025, 7, 4).unwrap();
+        let days = generator
+            .generate_daily_schedules_with_pace(
+                &[tour.clone()],
+                independence_day,
+                2,
+                &TripPace::Moderate,
+                &mut GenerationTrace::default(),
+            )
+            .unwrap();
+
+        assert!(!scheduled_on(&days, "1", &tour));
+        assert!(scheduled_on(&days, "2", &tour));
+    }
+
+    #[actix_rt::test]
+    async fn test_trace_records_holiday_skip_and_schedule() {
+        let generator = test_generator();
+        let mut tour = test_activity("City Tour", None);
+        tour.closed_on_holidays = true;
+
+        let independence_day = NaiveDate::from_ymd_opt(2025, 7, 4).unwrap();
+        let mut trace = GenerationTrace::new(true);
+        generator
+            .generate_daily_schedules_with_pace(
+                &[tour.clone()],
+                independence_day,
+                2,
+                &TripPace::Moderate,
+                &mut trace,
+            )
+            .unwrap();
+
+        assert_eq!(trace.considered.len(), 1);
+        assert!(trace
+            .skipped
+            .iter()
+            .any(|skip| skip.day == 1 && skip.reason == SkipReason::Holiday));
+        assert_eq!(trace.scheduled.len(), 1);
+        assert_eq!(trace.scheduled[0].day, 2);
+        assert_eq!(trace.days.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_zero_duration_activity_is_scheduled_for_the_minimum() {
+        let generator = test_generator();
+        let mut tasting = test_activity("Wine Tasting", None);
+        tasting.duration_minutes = 0;
+        let walk = test_activity("Park Walk", None);
+
+        let start = NaiveDate::from_ymd_opt(2025, 6, 4).unwrap();
+        let mut trace = GenerationTrace::new(true);
+        let days = generator
+            .generate_daily_schedules_with_pace(
+                &[tasting.clone(), walk],
+                start,
+                1,
+                &TripPace::Moderate,
+                &mut trace,
+            )
+            .unwrap();
+
+        assert!(scheduled_on(&days, "1", &tasting));
+        // 30 minutes for the tasting plus the hour-long walk
+        assert_eq!(trace.days[0].hours, 1.5);
+        assert_eq!(trace.short_durations.len(), 1);
+        assert_eq!(trace.short_durations[0].activity_id, tasting.id);
+        assert_eq!(trace.short_durations[0].duration_minutes, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_short_day_extends_window_to_reach_floor() {
+        let generator = test_generator();
+        let mut short = test_activity("Coffee Tasting", None);
+        short.duration_minutes = 90;
+        let mut long = test_activity("Canyon Hike", None);
+        long.duration_minutes = 300;
+
+        let start = NaiveDate::from_ymd_opt(2025, 6, 4).unwrap();
+        let days = generator
+            .generate_daily_schedules_with_pace(
+                &[short, long],
+                start,
+                1,
+                &TripPace::Moderate,
+                &mut GenerationTrace::default(),
+            )
+            .unwrap();
+
+        // 1.5h + 5h exceeds the 6h moderate window, but a lone 1.5h day is below the floor
+        assert_eq!(days["1"].len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_no_day_below_floor_when_pool_allows() {
+        let generator = test_generator();
+        let activities: Vec<Activity> = (0..15)
+            .map(|i| {
+                let mut activity = test_activity(&format!("Activity {}", i), None);
+                activity.duration_minutes = 120;
+                activity
+            })
+            .collect();
+        let durations: HashMap<ObjectId, f32> = activities
+            .iter()
+            .map(|a| (a.id.unwrap(), a.duration_minutes as f32 / 60.0))
+            .collect();
+
+        let start = NaiveDate::from_ymd_opt(2025, 6, 4).unwrap();
+        for pace in [TripPace::Relaxed, TripPace::Moderate, TripPace::Adventure] {
+            let floor = generator.day_floors.for_pace(&pace);
+            let days = generator
+                .generate_daily_schedules_with_pace(
+                    &activities,
+                    start,
+                    3,
+                    &pace,
+                    &mut GenerationTrace::default(),
+                )
+                .unwrap();
+
+            for (day, items) in &days {
+                let hours: f32 = items
+                    .iter()
+                    .filter_map(|item| match item {
+                        DayItem::Activity { activity_id, .. } => durations.get(activity_id),
+                        _ => None,
+                    })
+                    .sum();
+                assert!(
+                    floor.is_met(items.len(), hours),
+                    "{:?} day {} below floor: {} activities, {} hours",
+                    pace, day, items.len(), hours
+                );
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_moderation_failures_fall_back_to_a_neutral_template() {
+        let generator = ItineraryGenerator {
+            moderator: Moderator::from_list("scam"),
+            ..test_generator()
+        };
+        let denver = ItineraryGenerator::location("Denver", "CO", [-104.9903, 39.7392]);
+        let names = std::collections::HashSet::new();
+        let search = |activity: &str| -> SearchItinerary {
+            serde_json::from_value(serde_json::json!({ "activities": [activity] })).unwrap()
+        };
+
+        for activity in ["Scam", "https://spam.example/deal", "write deals@spam.net"] {
+            let (name, description) = generator.moderated_name_and_description(&denver, &search(activity), 0, &names);
+            assert_eq!(name, "Colorado Adventure", "{}", activity);
+            assert_eq!(description, "Discover Colorado with a trip built around its best activities.");
+        }
+
+        let (name, description) = generator.moderated_name_and_description(&denver, &search("hiking"), 0, &names);
+        assert_eq!(name, "Denver Hiking Adventure");
+        assert_eq!(description, "Discover Denver with exciting hiking activities and unforgettable experiences.");
+    }
+
+    #[test]
+    fn test_route_note_stores_a_distance_placeholder() {
+        let denver = ItineraryGenerator::location("Denver", "CO", [-104.9903, 39.7392]);
+        let aspen = ItineraryGenerator::location("Aspen", "CO", [-106.8175, 39.1911]);
+
+        let description = with_route_note("Discover Denver.".to_string(), &denver, &aspen);
+        assert!(description.starts_with("Discover Denver. Denver to Aspen is about {{distance:"), "{}", description);
+        // Only the canonical metres are stored; units are chosen per request
+        assert!(!description.contains("mile") && !description.contains("km"), "{}", description);
+        assert_eq!(units::render(&description, UnitSystem::Imperial), "Discover Denver. Denver to Aspen is about 105 miles as the crow flies.");
+        assert_eq!(units::render(&description, UnitSystem::Metric), "Discover Denver. Denver to Aspen is about 168 km as the crow flies.");
+
+        // A round trip has no note
+        assert_eq!(with_route_note("Discover Denver.".to_string(), &denver, &denver), "Discover Denver.");
+    }
+}
@@ -1,25 +1,43 @@
-use crate::models::{itinerary::base::FeaturedVacation, search::SearchItinerary};
-use crate::services::itinerary_generation_service::ItineraryGenerator;
-use crate::services::vertex_search_service::VertexSearchService;
-use crate::services::search_scoring::AsyncSearchScorer;
-use bson::{doc, Document};
+use crate::db::mongo::read_only_collection;
+use crate::models::{itinerary::base::FeaturedVacation, search::{DayFloors, SearchItinerary}};
+use crate::services::destination_constraints;
+use crate::services::generation_budget::GenerationBudget;
+use crate::services::itinerary_generation_service::{GenerationError, ItineraryGenerator};
+use crate::services::vertex_search_service::{VertexSearchError, VertexSearchService, VertexSearchSettings};
+use crate::services::location_terms::{self, LocationTerm};
+use crate::services::moderation::Moderator;
+use crate::services::search_scoring::{AsyncSearchScorer, SearchWeights};
+use crate::services::trip_limits::TripLimits;
+use crate::services::vertex_activity::{self, ActivityDefaults};
+use bson::{doc, Bson, Document};
 use futures::TryStreamExt;
 use mongodb::{Client, Collection};
 use std::{collections::HashSet, sync::Arc};
 use futures::future;
 
+/// Leave out itineraries moderation has taken down or an admin archived
+fn listed(mut filter: Document) -> Document {
+    filter.insert("taken_down_at", Bson::Null);
+    filter.insert("archived_at", Bson::Null);
+    filter
+}
+
 pub async fn search_itineraries(
     client: Arc<Client>,
     search_params: SearchItinerary,
+    budget: &GenerationBudget,
+    limits: &TripLimits,
+    activity_defaults: &ActivityDefaults,
+    vertex_search: Option<&VertexSearchSettings>,
 ) -> Result<Vec<FeaturedVacation>, mongodb::error::Error> {
     let collection: Collection<FeaturedVacation> =
-        client.database("Itineraries").collection("Featured");
+        read_only_collection(&client, "Itineraries", "Featured");
 
     // First, get activities from Vertex AI Search if activity types are provided
     if let Some(activity_types) = &search_params.activities {
         if !activity_types.is_empty() {
             println!("Fetching activities from Vertex AI Search for types: {:?}", activity_types);
-            match fetch_activities_from_vertex(&search_params).await {
+            match fetch_activities_from_vertex(&search_params, budget, limits, activity_defaults, vertex_search).await {
                 Ok(activities) => {
                     println!("Found {} activities from Vertex AI Search", activities.len());
                     // Store activities for later use in generation if needed
@@ -70,28 +88,9 @@ async fn try_exact_search(
 
     // Add search criteria to filter if they exist
     if let Some(locations) = &search_params.locations {
-        if !locations.is_empty() {
-            // Search for itineraries where start or end location city matches any of the requested locations
-            let cities = locations
-                .iter()
-                .map(|l| {
-                    let parts = l.split(',').collect::<Vec<&str>>();
-
-                    if parts.len() > 1 {
-                        parts[0].to_string()
-                    } else {
-                        l.to_string()
-                    }
-                })
-                .collect::<Vec<String>>();
-
-            filter.insert(
-                "$or",
-                vec![
-                    doc! { "start_location.city": { "$in": cities.clone() } },
-                    doc! { "end_location.city": { "$in": cities } },
-                ],
-            );
+        // Itineraries starting or ending in any requested city, or anywhere in a requested state
+        if let Some(location_filter) = location_terms::itinerary_filter(&location_terms::parse_terms(locations)) {
+            filter.extend(location_filter);
         }
     }
 
@@ -188,9 +187,9 @@ async fn try_exact_search(
 
     // If filter is empty (no search criteria provided), return all itineraries
     let cursor = if filter.is_empty() {
-        collection.find(doc! {}).await?
+        collection.find(listed(doc! {})).await?
     } else {
-        collection.find(filter).await?
+        collection.find(listed(filter)).await?
     };
 
     // Collect results
@@ -199,33 +198,62 @@ async fn try_exact_search(
     Ok(itineraries)
 }
 
+/// What a search may do when it finds too few good matches
+#[derive(Debug, Clone)]
+pub struct GenerationPolicy {
+    /// Generate itineraries to make up the shortfall
+    pub generate: bool,
+    /// Save generated itineraries. Unsaved ones are returned as ephemeral.
+    pub persist: bool,
+    /// Attach a `GenerationTrace` to generated itineraries
+    pub trace: bool,
+    /// Shortest time a generated schedule gives an activity
+    pub min_activity_minutes: u16,
+    /// Longest trip generated and how many activities generation reads
+    pub limits: TripLimits,
+    /// Fills fields missing from Vertex AI documents
+    pub activity_defaults: ActivityDefaults,
+    /// External calls this request may still make. Create one per request.
+    pub budget: GenerationBudget,
+    /// Checks generated names and descriptions before they're stored
+    pub moderator: Moderator,
+    /// Least each generated day must hold at each pace
+    pub day_floors: DayFloors,
+    /// Data store activities are searched in; MongoDB only when unset
+    pub vertex_search: Option<VertexSearchSettings>,
+}
+
 /// Search for itineraries with generation fallback
-/// If no exact matches are found, generates a new itinerary based on search parameters
+/// If no exact matches are found, generates a new itinerary based on search parameters,
+/// unless the policy turns generation off. Generation failures are only returned
+/// when there's nothing else to show.
 pub async fn search_or_generate_itineraries(
     client: Arc<Client>,
     search_params: SearchItinerary,
     min_results_threshold: usize,
-) -> Result<Vec<FeaturedVacation>, Box<dyn std::error::Error>> {
+    weights: Arc<SearchWeights>,
+    policy: GenerationPolicy,
+) -> Result<Vec<FeaturedVacation>, GenerationError> {
     // First, try to find existing itineraries
-    let mut results = search_itineraries(client.clone(), search_params.clone()).await?;
+    let mut results =
+        search_itineraries(
+        client.clone(),
+        search_params.clone(),
+        &policy.budget,
+        &policy.limits,
+        &policy.activity_defaults,
+        policy.vertex_search.as_ref(),
+    )
+    .await?;
     
     // Score the results and filter by match score
-    let scorer = AsyncSearchScorer::new(client.clone());
+    let scorer = AsyncSearchScorer::with_weights(client.clone(), weights);
     let mut scored_results = scorer.score_and_rank_itineraries(results.clone(), &search_params).await;
     
-    // Filter for high-quality matches (90+ score)
+    // Filter for high-quality matches (90+ score), out of the dimensions the search asked for
     let high_quality_matches: Vec<FeaturedVacation> = scored_results
         .iter()
-        .filter(|scored| {
-            // Calculate percentage score (0-100)
-            let max_possible_score = scorer.weights.location_weight
-                + scorer.weights.activity_weight
-                + scorer.weights.group_size_weight
-                + scorer.weights.lodging_weight
-                + scorer.weights.transportation_weight;
-            let percentage_score = (scored.total_score / max_possible_score) * 100.0;
-            percentage_score >= 90.0
-        })
+        .filter(|scored| scored.match_percentage() >= 90.0)
         .map(|scored| scored.itinerary.clone())
         .collect();
     
@@ -236,6 +264,12 @@ pub async fn search_or_generate_itineraries(
     if high_quality_matches.len() >= min_results_threshold {
         return Ok(high_quality_matches);
     }
+
+    // Without generation, every match is better than too few
+    if !policy.generate {
+        println!("Itinerary generation is off, returning all {} ranked matches", scored_results.len());
+        return Ok(scored_results.into_iter().map(|scored| scored.itinerary).collect());
+    }
     
     // Otherwise, we need to generate more itineraries
     results = high_quality_matches;
@@ -247,6 +281,16 @@ pub async fn search_or_generate_itineraries(
         min_results_threshold
     );
 
+    // Never generate a trip the destination doesn't offer
+    match destination_constraints::check_search(&client, &search_params).await {
+        Ok(Some(violation)) => {
+            println!("Not generating: {}", violation);
+            return Ok(results);
+        }
+        Ok(None) => {}
+        Err(e) => println!("Failed to load destination constraints: {:?}", e),
+    }
+
     // Check if we have the required fields for generation
     if search_params.arrival_datetime.is_none() || search_params.departure_datetime.is_none() {
         println!("Cannot generate itinerary without arrival and departure dates, returning existing results (including partial matches)");
@@ -254,7 +298,7 @@ pub async fn search_or_generate_itineraries(
         // If we have no results at all and no dates for generation, try a more flexible search
         if results.is_empty() {
             println!("No results found, attempting flexible search without strict criteria");
-            match try_flexible_search(&client.database("Itineraries").collection("Featured"), &search_params).await {
+            match try_flexible_search(&read_only_collection(&client, "Itineraries", "Featured"), &search_params).await {
                 Ok(flexible_results) => {
                     println!("Flexible search found {} results", flexible_results.len());
                     return Ok(flexible_results);
@@ -266,13 +310,14 @@ pub async fn search_or_generate_itineraries(
         }
         
         println!("Attempting to find activities using Vertex AI without dates");
-        match find_and_generate_itineraries(client, &search_params).await {
+        match find_and_generate_itineraries(client, &search_params, policy).await {
             Ok(generated_itineraries) => {
                 if !generated_itineraries.is_empty() {
                     println!("Generated itineraries from search and AI generated activities");
                     results.extend(generated_itineraries);
                 }
             }
+            Err(e) if results.is_empty() => return Err(e),
             Err(e) => {
                 println!("Failed to generate itineraries from Vertex AI: {:?}", e);
             }
@@ -282,7 +327,15 @@ pub async fn search_or_generate_itineraries(
     }
 
     // Create itinerary generator
-    let generator = ItineraryGenerator::new(client.clone());
+    let generator = ItineraryGenerator::new(client.clone())
+        .with_trace(policy.trace)
+        .with_min_activity_minutes(policy.min_activity_minutes)
+        .with_limits(policy.limits)
+        .with_activity_defaults(policy.activity_defaults)
+        .with_budget(policy.budget.clone())
+        .with_moderator(policy.moderator.clone())
+        .with_day_floors(policy.day_floors)
+        .with_vertex_search(policy.vertex_search.as_ref());
     let collection: Collection<FeaturedVacation> =
         client.database("Itineraries").collection("Featured");
 
@@ -293,6 +346,7 @@ pub async fn search_or_generate_itineraries(
     let mut generated_names = std::collections::HashSet::new();
     
     // Create generation tasks in parallel
+    let persist = policy.persist;
     let generation_tasks: Vec<_> = (1..=needed_count)
         .map(|i| {
             let generator = generator.clone();
@@ -302,6 +356,7 @@ pub async fn search_or_generate_itineraries(
             tokio::spawn(async move {
                 let mut attempt = 0;
                 let max_retries = 3; // Reduced retries for speed
+                let mut last_error = GenerationError::NoActivities;
                 
                 while attempt < max_retries {
                     match generator.generate_unique_itinerary(&search_params, i, &HashSet::new()).await {
@@ -310,6 +365,9 @@ pub async fn search_or_generate_itineraries(
                                 "Successfully generated itinerary {}: {}",
                                 i, generated_itinerary.trip_name
                             );
+                            if !persist {
+                                return Ok(generated_itinerary);
+                            }
 
                             // Save to database with error handling
                             match collection.insert_one(&generated_itinerary).await {
@@ -326,19 +384,22 @@ pub async fn search_or_generate_itineraries(
                                 }
                                 Err(e) => {
                                     eprintln!("❌ Failed to save generated itinerary to database: {}", e);
-                                    // Return the itinerary anyway for this request, even if DB save failed
+                                    // Return the itinerary anyway for this request, even if DB save failed.
+                                    // It has no id, so the response flags it as ephemeral.
                                     return Ok(generated_itinerary);
                                 }
                             }
                         }
                         Err(e) => {
                             eprintln!("Failed to generate itinerary {} (attempt {}): {}", i, attempt + 1, e);
+                            last_error = e;
                             attempt += 1;
                         }
                     }
                 }
                 
-                Err(format!("Failed to generate unique itinerary {} after {} attempts", i, max_retries))
+                eprintln!("Failed to generate unique itinerary {} after {} attempts", i, max_retries);
+                Err(last_error)
             })
         })
         .collect();
@@ -347,6 +408,7 @@ pub async fn search_or_generate_itineraries(
     let generation_results = futures::future::join_all(generation_tasks).await;
     
     // Process results and add successful generations
+    let mut generation_error = None;
     for (i, task_result) in generation_results.into_iter().enumerate() {
         match task_result {
             Ok(Ok(generated_itinerary)) => {
@@ -361,6 +423,7 @@ pub async fn search_or_generate_itineraries(
             }
             Ok(Err(e)) => {
                 eprintln!("Generation task {} failed: {}", i + 1, e);
+                generation_error.get_or_insert(e);
             }
             Err(e) => {
                 eprintln!("Generation task {} panicked: {:?}", i + 1, e);
@@ -371,7 +434,10 @@ pub async fn search_or_generate_itineraries(
     println!("🎯 Parallel generation complete. Generated {} unique itineraries", 
         results.len().saturating_sub(min_results_threshold.saturating_sub(needed_count)));
 
-    Ok(results)
+    match generation_error {
+        Some(e) if results.is_empty() => Err(e),
+        _ => Ok(results),
+    }
 }
 
 /// Try partial matching search (some criteria match)
@@ -383,26 +449,8 @@ async fn try_partial_search(
 
     // Add location filter if provided
     if let Some(locations) = &search_params.locations {
-        if !locations.is_empty() {
-            let cities = locations
-                .iter()
-                .map(|l| {
-                    let parts = l.split(',').collect::<Vec<&str>>();
-                    if parts.len() > 1 {
-                        parts[0].to_string()
-                    } else {
-                        l.to_string()
-                    }
-                })
-                .collect::<Vec<String>>();
-
-            filter.insert(
-                "$or",
-                vec![
-                    doc! { "start_location.city": { "$in": cities.clone() } },
-                    doc! { "end_location.city": { "$in": cities } },
-                ],
-            );
+        if let Some(location_filter) = location_terms::itinerary_filter(&location_terms::parse_terms(locations)) {
+            filter.extend(location_filter);
         }
     }
 
@@ -446,7 +494,7 @@ async fn try_partial_search(
         }
     }
 
-    let cursor = collection.find(filter).limit(10).await?;
+    let cursor = collection.find(listed(filter)).limit(10).await?;
     let itineraries = cursor.try_collect().await?;
     Ok(itineraries)
 }
@@ -460,26 +508,8 @@ async fn try_location_only_search(
 
     // Only filter by location
     if let Some(locations) = &search_params.locations {
-        if !locations.is_empty() {
-            let cities = locations
-                .iter()
-                .map(|l| {
-                    let parts = l.split(',').collect::<Vec<&str>>();
-                    if parts.len() > 1 {
-                        parts[0].to_string()
-                    } else {
-                        l.to_string()
-                    }
-                })
-                .collect::<Vec<String>>();
-
-            filter.insert(
-                "$or",
-                vec![
-                    doc! { "start_location.city": { "$in": cities.clone() } },
-                    doc! { "end_location.city": { "$in": cities } },
-                ],
-            );
+        if let Some(location_filter) = location_terms::itinerary_filter(&location_terms::parse_terms(locations)) {
+            filter.extend(location_filter);
         }
     }
 
@@ -497,7 +527,7 @@ async fn try_location_only_search(
         // In production, you'd want to analyze the actual daily schedule
     }
 
-    let cursor = collection.find(filter).limit(5).await?;
+    let cursor = collection.find(listed(filter)).limit(5).await?;
     let itineraries = cursor.try_collect().await?;
     Ok(itineraries)
 }
@@ -515,17 +545,16 @@ async fn try_flexible_search(
     
     // Add location conditions if available
     if let Some(locations) = &search_params.locations {
-        if !locations.is_empty() {
-            for location in locations {
-                let parts = location.split(',').collect::<Vec<&str>>();
-                let city = if parts.len() > 1 {
-                    parts[0].trim().to_string()
-                } else {
-                    location.trim().to_string()
-                };
-                
-                or_conditions.push(doc! { "start_location.city": { "$regex": city.clone(), "$options": "i" } });
-                or_conditions.push(doc! { "end_location.city": { "$regex": city, "$options": "i" } });
+        for term in location_terms::parse_terms(locations) {
+            match term {
+                LocationTerm::City { city, .. } => {
+                    or_conditions.push(doc! { "start_location.city": { "$regex": city.clone(), "$options": "i" } });
+                    or_conditions.push(doc! { "end_location.city": { "$regex": city, "$options": "i" } });
+                }
+                LocationTerm::State(state) => {
+                    or_conditions.push(doc! { "start_location.state": { "$in": state.stored_forms().to_vec() } });
+                    or_conditions.push(doc! { "end_location.state": { "$in": state.stored_forms().to_vec() } });
+                }
             }
         }
     }
@@ -567,11 +596,11 @@ async fn try_flexible_search(
         println!("No search criteria available, returning recent itineraries");
         // Don't sort by created_at to avoid DateTime deserialization issues
         collection
-            .find(doc! {})
+            .find(listed(doc! {}))
             .limit(10)
             .await?
     } else {
-        collection.find(filter).limit(10).await?
+        collection.find(listed(filter)).limit(10).await?
     };
     
     // Use a more lenient collection approach to handle data inconsistencies
@@ -590,12 +619,55 @@ async fn try_flexible_search(
     Ok(itineraries)
 }
 
-/// Fetch activities from Vertex AI Search
+/// Merge each activity type's results round-robin, so no type is exhausted
+/// before the next gets a turn. Takes at most `per_type` from each type and
+/// `total` overall; a result returned for several types, by document id, is kept
+/// once under the first type that reaches it.
+fn interleave_by_type<T>(results: Vec<Vec<(String, T)>>, per_type: usize, total: usize) -> Vec<T> {
+    let mut seen = HashSet::new();
+    let mut taken = vec![0; results.len()];
+    let mut types: Vec<_> = results.into_iter().map(Vec::into_iter).collect();
+    let mut merged = Vec::new();
+    loop {
+        let mut progressed = false;
+        for (index, results) in types.iter_mut().enumerate() {
+            if merged.len() >= total {
+                return merged;
+            }
+            if taken[index] >= per_type {
+                continue;
+            }
+            if let Some((_, item)) = results.find(|(id, _)| seen.insert(id.clone())) {
+                merged.push(item);
+                taken[index] += 1;
+                progressed = true;
+            }
+        }
+        if !progressed {
+            return merged;
+        }
+    }
+}
+
+/// Fetch activities from Vertex AI Search, at most
+/// `limits.max_vertex_activities_per_type` for each activity type and
+/// `limits.max_vertex_activities` in all. Types stop being queried once enough
+/// distinct activities have come back.
 async fn fetch_activities_from_vertex(
     search_params: &SearchItinerary,
-) -> Result<Vec<crate::models::activity::Activity>, Box<dyn std::error::Error>> {
-    let vertex_service = VertexSearchService::new()?;
-    let mut all_activities = Vec::new();
+    budget: &GenerationBudget,
+    limits: &TripLimits,
+    defaults: &ActivityDefaults,
+    settings: Option<&VertexSearchSettings>,
+) -> Result<Vec<crate::models::activity::Activity>, GenerationError> {
+    let settings = settings
+        .ok_or_else(|| VertexSearchError::EnvironmentError("Vertex AI Search not configured".to_string()))?;
+    let vertex_service = VertexSearchService::new(settings);
+    let per_type = limits.max_vertex_activities_per_type as usize;
+    let total = limits.max_vertex_activities as usize;
+    let mut by_type = Vec::new();
+    // Distinct document ids that would be kept so far, for stopping early
+    let mut collected: HashSet<String> = HashSet::new();
     
     // Build location query
     let location_query = search_params.locations
@@ -607,17 +679,35 @@ async fn fetch_activities_from_vertex(
     // Fetch activities for each activity type
     if let Some(activity_types) = &search_params.activities {
         for activity_type in activity_types {
+            if collected.len() >= total {
+                println!("Collected {} Vertex AI activities, skipping remaining activity types", collected.len());
+                break;
+            }
+            if !budget.try_vertex_query() {
+                println!("Vertex AI query budget spent, skipping remaining activity types");
+                break;
+            }
             match vertex_service.search_activities(&[activity_type.clone()], &location_query).await {
                 Ok(response) => {
                     println!("Vertex AI found {} results for activity type: {}", 
                         response.results.len(), activity_type);
                     
                     // Convert Vertex search results to Activity models
+                    let mut activities = Vec::new();
                     for result in response.results {
-                        if let Ok(activity) = parse_vertex_result_to_activity(&result) {
-                            all_activities.push(activity);
+                        match vertex_activity::activity_from_vertex(&result.document.struct_data, defaults) {
+                            Ok(activity) => activities.push((result.document.id, activity)),
+                            Err(e) => eprintln!("Skipping Vertex AI document {}: {}", result.document.id, e),
                         }
                     }
+                    let new_ids = activities
+                        .iter()
+                        .filter(|(id, _)| !collected.contains(id))
+                        .map(|(id, _)| id.clone())
+                        .take(per_type)
+                        .collect::<Vec<_>>();
+                    collected.extend(new_ids);
+                    by_type.push(activities);
                 }
                 Err(e) => {
                     eprintln!("Failed to search for activity type {}: {:?}", activity_type, e);
@@ -626,142 +716,27 @@ async fn fetch_activities_from_vertex(
         }
     }
     
-    Ok(all_activities)
+    Ok(interleave_by_type(by_type, per_type, total))
 }
 
-/// Convert Vertex AI search result to Activity model
-fn parse_vertex_result_to_activity(result: &crate::services::vertex_search_service::SearchResult) -> Result<crate::models::activity::Activity, Box<dyn std::error::Error>> {
-    use mongodb::bson::oid::ObjectId;
-    
-    // Extract data from the structured data
-    let struct_data = &result.document.struct_data;
-    
-    // Parse address
-    let address = if let Some(addr_str) = struct_data.get("address").and_then(|v| v.as_str()) {
-        // Simple address parsing - in production you'd want more robust parsing
-        let parts: Vec<&str> = addr_str.split(',').collect();
-        crate::models::activity::Address {
-            street: parts.get(0).unwrap_or(&"").trim().to_string(),
-            unit: "".to_string(),
-            city: parts.get(1).unwrap_or(&"").trim().to_string(),
-            state: parts.get(2).unwrap_or(&"").trim().to_string(),
-            zip: parts.get(3).unwrap_or(&"").trim().to_string(),
-            country: "USA".to_string(),
-        }
-    } else {
-        crate::models::activity::Address {
-            street: "".to_string(),
-            unit: "".to_string(),
-            city: "".to_string(),
-            state: "".to_string(),
-            zip: "".to_string(),
-            country: "USA".to_string(),
-        }
-    };
-    
-    // Parse time slots
-    let daily_time_slots = struct_data.get("daily_time_slots")
-        .and_then(|v| v.as_array())
-        .map(|arr| arr.iter()
-            .filter_map(|slot| {
-                if let Some(slot_obj) = slot.as_object() {
-                    Some(crate::models::activity::TimeSlot {
-                        start: slot_obj.get("start").and_then(|v| v.as_str()).unwrap_or("09:00").to_string(),
-                        end: slot_obj.get("end").and_then(|v| v.as_str()).unwrap_or("17:00").to_string(),
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect())
-        .unwrap_or_else(|| vec![crate::models::activity::TimeSlot {
-            start: "09:00".to_string(),
-            end: "17:00".to_string(),
-        }]);
-    
-    let activity = crate::models::activity::Activity {
-        id: Some(ObjectId::new()),
-        company: struct_data.get("company")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Unknown Company")
-            .to_string(),
-        company_id: struct_data.get("company_id")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        booking_link: struct_data.get("booking_link")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        online_booking_status: "available".to_string(),
-        guide: struct_data.get("guide")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
-        title: struct_data.get("title")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Unknown Activity")
-            .to_string(),
-        description: struct_data.get("description")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string(),
-        activity_types: struct_data.get("activity_types")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str())
-                .map(|s| s.to_string())
-                .collect())
-            .unwrap_or_default(),
-        tags: struct_data.get("tags")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str())
-                .map(|s| s.to_string())
-                .collect())
-            .unwrap_or_default(),
-        price_per_person: struct_data.get("price")
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0) as f32,
-        duration_minutes: struct_data.get("duration")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(120) as u16, // Default 2 hours
-        daily_time_slots,
-        address,
-        whats_included: struct_data.get("whats_included")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.iter()
-                .filter_map(|v| v.as_str())
-                .map(|s| s.to_string())
-                .collect())
-            .unwrap_or_default(),
-        weight_limit_lbs: struct_data.get("weight_limit")
-            .and_then(|v| v.as_i64())
-            .map(|w| w as u16),
-        age_requirement: struct_data.get("age_requirement")
-            .and_then(|v| v.as_i64())
-            .map(|a| a as u8),
-        height_requiremnt: struct_data.get("height_requirement")
-            .and_then(|v| v.as_i64())
-            .map(|h| h as u8),
-        blackout_date_ranges: None,
-        capacity: crate::models::activity::Capacity {
-            minimum: struct_data.get("min_capacity").and_then(|v| v.as_i64()).unwrap_or(1) as u16,
-            maximum: struct_data.get("max_capacity").and_then(|v| v.as_i64()).unwrap_or(20) as u16,
-        },
-        created_at: None,
-        updated_at: None,
-    };
-    
-    Ok(activity)
-}
-
-/// Find activities using Vertex AI Search and generate itineraries from them
+/// Find activities using Vertex AI Search and generate itineraries from them. Fails
+/// with the last generation error when none could be generated.
 async fn find_and_generate_itineraries(
     client: Arc<Client>,
     search_params: &SearchItinerary,
-) -> Result<Vec<FeaturedVacation>, Box<dyn std::error::Error>> {
-    let generator = ItineraryGenerator::new(client.clone());
+    policy: GenerationPolicy,
+) -> Result<Vec<FeaturedVacation>, GenerationError> {
+    let generator = ItineraryGenerator::new(client.clone())
+        .with_trace(policy.trace)
+        .with_min_activity_minutes(policy.min_activity_minutes)
+        .with_limits(policy.limits)
+        .with_activity_defaults(policy.activity_defaults)
+        .with_budget(policy.budget.clone())
+        .with_moderator(policy.moderator.clone())
+        .with_day_floors(policy.day_floors)
+        .with_vertex_search(policy.vertex_search.as_ref());
     let mut generated_itineraries = Vec::new();
+    let mut last_error = None;
     
     // Create a modified search params with default dates for generation
     let mut modified_params = search_params.clone();
@@ -791,17 +766,26 @@ async fn find_and_generate_itineraries(
     
     for i in 1..=target_count {
         match generator.generate_itinerary(&modified_params).await {
-            Ok(generated_itinerary) => {
+            Ok(mut generated_itinerary) => {
                 println!(
                     "Successfully generated itinerary {}: {}",
                     i, generated_itinerary.trip_name
                 );
                 
+                if !policy.persist {
+                    generated_itineraries.push(generated_itinerary);
+                    continue;
+                }
+
                 // Save the generated itinerary to the database
                 let collection: Collection<FeaturedVacation> =
                     client.database("Itineraries").collection("Featured");
                 match collection.insert_one(&generated_itinerary).await {
                     Ok(insert_result) => {
+                        // Keep the persisted id so favorites/bookings resolve against it
+                        if let Some(object_id) = insert_result.inserted_id.as_object_id() {
+                            generated_itinerary.id = Some(object_id);
+                        }
                         println!(
                             "Saved generated itinerary {} to database with ID: {:?}",
                             i, insert_result.inserted_id
@@ -809,7 +793,8 @@ async fn find_and_generate_itineraries(
                     }
                     Err(e) => {
                         eprintln!("Failed to save generated itinerary to database: {}", e);
-                        // Continue anyway - the itinerary is still useful for this request
+                        // Continue anyway - the itinerary is still useful for this request,
+                        // it is returned as ephemeral since it has no persisted id
                     }
                 }
                 
@@ -818,11 +803,15 @@ async fn find_and_generate_itineraries(
             Err(e) => {
                 eprintln!("Failed to generate itinerary {}: {}", i, e);
                 // Continue trying to generate more
+                last_error = Some(e);
             }
         }
     }
     
-    Ok(generated_itineraries)
+    match last_error {
+        Some(e) if generated_itineraries.is_empty() => Err(e),
+        _ => Ok(generated_itineraries),
+    }
 }
 
 /// Check if a generated itinerary is too similar to existing ones
@@ -857,3 +846,51 @@ fn is_too_similar_to_existing(new_itinerary: &FeaturedVacation, existing_itinera
     
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` results for `activity_type`, with document ids `{activity_type}-{n}`
+    fn results(activity_type: &str, count: usize) -> Vec<(String, String)> {
+        (0..count)
+            .map(|n| {
+                let id = format!("{}-{}", activity_type, n);
+                (id.clone(), id)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_vertex_results_are_capped_deduplicated_and_interleaved() {
+        let types = ["hiking", "rafting", "museums", "food", "wine", "biking", "fishing", "spa"];
+        let mut by_type: Vec<_> = types.iter().map(|activity_type| results(activity_type, 20)).collect();
+        // Every type also returns the same two popular activities
+        for results in &mut by_type {
+            results.insert(0, ("popular-0".to_string(), "popular-0".to_string()));
+            results.insert(2, ("popular-1".to_string(), "popular-1".to_string()));
+        }
+
+        let merged = interleave_by_type(by_type.clone(), 5, 30);
+        assert_eq!(merged.len(), 30);
+        let distinct: HashSet<&String> = merged.iter().collect();
+        assert_eq!(distinct.len(), merged.len());
+        assert_eq!(merged.iter().filter(|id| id.starts_with("popular")).count(), 2);
+
+        // One from each type before any type gets a second
+        assert_eq!(
+            &merged[..8],
+            ["popular-0", "rafting-0", "museums-0", "food-0", "wine-0", "biking-0", "fishing-0", "spa-0"]
+        );
+        for activity_type in types {
+            let kept = merged.iter().filter(|id| id.starts_with(activity_type)).count();
+            assert!(kept <= 5, "{} kept {}", activity_type, kept);
+        }
+
+        // The per-type cap binds before the total does
+        let merged = interleave_by_type(by_type, 3, 100);
+        assert_eq!(merged.len(), 8 * 3);
+        // Hiking's first turn went to a popular activity
+        assert_eq!(merged.iter().filter(|id| id.starts_with("hiking")).count(), 2);
+    }
+}
@@ -0,0 +1,605 @@
+//! Dry-run validation of hand-built itineraries.
+//!
+//! The custom-itinerary editor sends a trip before saving it and gets back every
+//! problem found: activities or lodging that don't exist, activities scheduled on
+//! top of each other or too far apart to drive between, activities closed on the
+//! date they fall on, and parties that don't fit. The report also prices the trip
+//! and says how much of each day goes to activities rather than driving. Nothing
+//! is written.
+//!
+//! Closures and seats are only checked when the trip has an `arrival_datetime`.
+
+use chrono::{Duration, NaiveDate, NaiveTime};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    Client,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::{DayItem, FeaturedVacation};
+use crate::models::money::Money;
+use crate::services::availability_service::{activity_dates, scheduled_activity_ids, AvailabilityService, Inventory};
+use crate::services::calendar::{self, ClosureReason};
+use crate::services::distance_service::{haversine_miles, straight_line_minutes};
+use crate::services::pricing_service::PricingService;
+use crate::services::reservation_service::seats_needed;
+use crate::services::route_optimization_service::{OptimizedActivity, RouteOptimizationService, RouteStats};
+use crate::services::trip_limits::TripLimits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The trip can't be booked as it stands
+    Error,
+    /// Bookable, but likely not what the traveler wants
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    UnknownActivity,
+    UnknownAccommodation,
+    InvalidTime,
+    TimeConflict,
+    TightTransfer,
+    Closed,
+    OverCapacity,
+    UnderMinimum,
+    SoldOut,
+    TripLimit,
+    DatesNotChecked,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<u32>,
+    /// The activity or accommodation the issue is about, as a hex id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closure: Option<ClosureReason>,
+    pub message: String,
+}
+
+/// How a day's activities and the driving between them add up
+#[derive(Debug, Clone, Serialize)]
+pub struct DayRouteStats {
+    pub day: u32,
+    #[serde(flatten)]
+    pub stats: RouteStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    /// No issue is an error
+    pub valid: bool,
+    /// Errors first, then by day, issues with the whole trip before either
+    pub issues: Vec<ValidationIssue>,
+    /// Per-person price of the scheduled activities. `None` when any of them is
+    /// unknown, so a partial sum is never shown as the price.
+    pub person_cost: Option<Money>,
+    pub days: Vec<DayRouteStats>,
+    /// Activity time over activity and driving time across the whole trip
+    pub efficiency_ratio: f32,
+}
+
+/// What the trip's references resolve to
+#[derive(Debug, Default)]
+pub struct KnownReferences {
+    pub activities: HashMap<ObjectId, Activity>,
+    pub accommodations: HashSet<ObjectId>,
+    /// Seats taken per activity and date over the trip's dates
+    pub inventory: Inventory,
+}
+
+/// A day item's time. Items are stored as `HH:MM:SS`; hand-built ones may leave
+/// off the seconds.
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    let time = time.trim();
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .ok()
+}
+
+fn issue(
+    severity: Severity,
+    kind: IssueKind,
+    day: Option<u32>,
+    item_id: Option<ObjectId>,
+    message: String,
+) -> ValidationIssue {
+    ValidationIssue {
+        severity,
+        kind,
+        day,
+        item_id: item_id.map(|id| id.to_hex()),
+        closure: None,
+        message,
+    }
+}
+
+/// Each numbered day's known activities in time order, with their start times.
+/// Unparseable times are reported and the activity left out.
+fn timed_days(
+    itinerary: &FeaturedVacation,
+    activities: &HashMap<ObjectId, Activity>,
+    min_activity_minutes: u16,
+    issues: &mut Vec<ValidationIssue>,
+) -> Vec<(u32, Vec<(NaiveTime, Activity)>)> {
+    let mut days: Vec<(u32, Vec<(NaiveTime, Activity)>)> = itinerary
+        .days
+        .days
+        .iter()
+        .filter_map(|(day, items)| Some((day.trim().parse::<u32>().ok()?, items)))
+        .map(|(day, items)| {
+            let mut timed = Vec::new();
+            for item in items {
+                let DayItem::Activity { time, activity_id } = item else {
+                    continue;
+                };
+                let Some(activity) = activities.get(activity_id) else {
+                    continue;
+                };
+                let Some(start) = parse_time(time) else {
+                    issues.push(issue(
+                        Severity::Error,
+                        IssueKind::InvalidTime,
+                        Some(day),
+                        Some(*activity_id),
+                        format!("'{}' has a start time that isn't HH:MM: '{}'", activity.title, time),
+                    ));
+                    continue;
+                };
+                let mut activity = activity.clone();
+                activity.duration_minutes = activity.duration_minutes.max(min_activity_minutes);
+                timed.push((start, activity));
+            }
+            timed.sort_by_key(|(start, _)| *start);
+            (day, timed)
+        })
+        .collect();
+    days.sort_by_key(|(day, _)| *day);
+    days
+}
+
+/// Straight-line driving minutes between two activities, when both are located
+fn drive_minutes(from: &Activity, to: &Activity) -> Option<i64> {
+    let from = from.location.as_ref()?.lat_lng();
+    let to = to.location.as_ref()?.lat_lng();
+    Some(straight_line_minutes(haversine_miles(from, to)))
+}
+
+/// Overlapping activities, and activities starting before the drive from the
+/// previous one could get there
+fn time_conflicts(day: u32, timed: &[(NaiveTime, Activity)], issues: &mut Vec<ValidationIssue>) {
+    for pair in timed.windows(2) {
+        let ((start, previous), (next_start, next)) = (&pair[0], &pair[1]);
+        let end = *start + Duration::minutes(previous.duration_minutes as i64);
+        if *next_start < end {
+            issues.push(issue(
+                Severity::Error,
+                IssueKind::TimeConflict,
+                Some(day),
+                next.id,
+                format!(
+                    "'{}' starts at {} but '{}' runs until {}",
+                    next.title,
+                    next_start.format("%H:%M"),
+                    previous.title,
+                    end.format("%H:%M")
+                ),
+            ));
+        } else if let Some(drive) = drive_minutes(previous, next) {
+            let arrival = end + Duration::minutes(drive);
+            if *next_start < arrival {
+                issues.push(issue(
+                    Severity::Warning,
+                    IssueKind::TightTransfer,
+                    Some(day),
+                    next.id,
+                    format!(
+                        "'{}' starts at {}, but driving from '{}' takes until about {}",
+                        next.title,
+                        next_start.format("%H:%M"),
+                        previous.title,
+                        arrival.format("%H:%M")
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// The day's route stats, driving between activities in the order they're scheduled
+fn day_route_stats(timed: &[(NaiveTime, Activity)]) -> RouteStats {
+    let route: Vec<OptimizedActivity> = timed
+        .iter()
+        .enumerate()
+        .map(|(i, (start, activity))| OptimizedActivity {
+            activity: activity.clone(),
+            scheduled_time: *start,
+            travel_time_from_previous: i
+                .checked_sub(1)
+                .and_then(|previous| drive_minutes(&timed[previous].1, activity)),
+            coordinates: activity.location.as_ref().map_or((0.0, 0.0), |point| point.lat_lng()),
+        })
+        .collect();
+    RouteOptimizationService::new(None).get_route_stats(&route)
+}
+
+/// Activities the party is too large or too small for
+fn party_fit(itinerary: &FeaturedVacation, activities: &HashMap<ObjectId, Activity>, issues: &mut Vec<ValidationIssue>) {
+    let Some(party) = itinerary.party_size() else {
+        return;
+    };
+    let mut checked = HashSet::new();
+    for activity_id in scheduled_activity_ids(itinerary) {
+        let Some(activity) = activities.get(&activity_id) else {
+            continue;
+        };
+        if !checked.insert(activity_id) {
+            continue;
+        }
+        let capacity = &activity.capacity;
+        if capacity.maximum > 0 && party > capacity.maximum as u32 {
+            issues.push(issue(
+                Severity::Error,
+                IssueKind::OverCapacity,
+                None,
+                Some(activity_id),
+                format!("'{}' takes at most {} travelers; the party has {}", activity.title, capacity.maximum, party),
+            ));
+        } else if party < capacity.minimum as u32 {
+            issues.push(issue(
+                Severity::Warning,
+                IssueKind::UnderMinimum,
+                None,
+                Some(activity_id),
+                format!("'{}' needs at least {} travelers; the party has {}", activity.title, capacity.minimum, party),
+            ));
+        }
+    }
+}
+
+/// Activities closed or out of seats on the date they fall on
+fn dated_checks(
+    itinerary: &FeaturedVacation,
+    start: NaiveDate,
+    known: &KnownReferences,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    let day_of = |date: NaiveDate| Some((date - start).num_days() as u32 + 1);
+    for (activity_id, date) in activity_dates(itinerary, start) {
+        let Some(activity) = known.activities.get(&activity_id) else {
+            continue;
+        };
+        if let Some(reason) = calendar::closure_reason(activity, date) {
+            issues.push(ValidationIssue {
+                closure: Some(reason),
+                ..issue(
+                    Severity::Error,
+                    IssueKind::Closed,
+                    day_of(date),
+                    Some(activity_id),
+                    format!("'{}' isn't running on {}", activity.title, date),
+                )
+            });
+        }
+    }
+
+    let party = itinerary.party_size().unwrap_or(itinerary.min_group).max(1);
+    for ((date, activity_id), seats) in seats_needed(itinerary, start, party) {
+        let Some(activity) = known.activities.get(&activity_id) else {
+            continue;
+        };
+        let Some(taken) = known.inventory.get(&(activity_id, date)) else {
+            continue;
+        };
+        let left = (activity.capacity.maximum as u32).saturating_sub(*taken);
+        if left < seats {
+            issues.push(issue(
+                Severity::Error,
+                IssueKind::SoldOut,
+                day_of(date),
+                Some(activity_id),
+                format!("'{}' has {} seats left on {}; the trip needs {}", activity.title, left, date, seats),
+            ));
+        }
+    }
+}
+
+/// Everything wrong with `itinerary`, checked against what its references resolve to
+pub fn validate(
+    itinerary: &FeaturedVacation,
+    known: &KnownReferences,
+    limits: &TripLimits,
+    min_activity_minutes: u16,
+) -> ValidationReport {
+    let mut issues = Vec::new();
+
+    let activity_ids: HashSet<ObjectId> = known.activities.keys().copied().collect();
+    for missing in itinerary.days.missing_references(&activity_ids, &known.accommodations) {
+        let kind = match missing.item_type.as_str() {
+            "activity" => IssueKind::UnknownActivity,
+            _ => IssueKind::UnknownAccommodation,
+        };
+        issues.push(issue(
+            Severity::Error,
+            kind,
+            missing.day.trim().parse().ok(),
+            Some(missing.missing_id),
+            format!("No {} with id {}", missing.item_type, missing.missing_id.to_hex()),
+        ));
+    }
+
+    if let Err(exceeded) = limits
+        .check_itinerary(itinerary)
+        .and_then(|_| limits.check_trip_days(itinerary.days.days.len() as i64))
+    {
+        issues.push(issue(Severity::Error, IssueKind::TripLimit, None, None, exceeded.to_string()));
+    }
+
+    let days = timed_days(itinerary, &known.activities, min_activity_minutes, &mut issues);
+    for (day, timed) in &days {
+        time_conflicts(*day, timed, &mut issues);
+    }
+
+    party_fit(itinerary, &known.activities, &mut issues);
+    match itinerary.arrival_datetime.and_then(calendar::utc_date) {
+        Some(start) => dated_checks(itinerary, start, known, &mut issues),
+        None => issues.push(issue(
+            Severity::Warning,
+            IssueKind::DatesNotChecked,
+            None,
+            None,
+            "Set arrival_datetime to check closures and seats".to_string(),
+        )),
+    }
+    issues.sort_by_key(|issue| (issue.severity, issue.day.unwrap_or(0)));
+
+    let days: Vec<DayRouteStats> = days
+        .iter()
+        .map(|(day, timed)| DayRouteStats {
+            day: *day,
+            stats: day_route_stats(timed),
+        })
+        .collect();
+    let activity_minutes: i64 = days.iter().map(|day| day.stats.total_activity_time_minutes).sum();
+    let total_minutes: i64 = days.iter().map(|day| day.stats.total_day_time_minutes).sum();
+
+    ValidationReport {
+        valid: !issues.iter().any(|issue| issue.severity == Severity::Error),
+        issues,
+        person_cost: PricingService::estimate_cost(&itinerary.days.days, &known.activities),
+        days,
+        efficiency_ratio: if total_minutes > 0 {
+            activity_minutes as f32 / total_minutes as f32
+        } else {
+            0.0
+        },
+    }
+}
+
+pub struct ItineraryValidationService {
+    client: Arc<Client>,
+}
+
+impl ItineraryValidationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    /// Look up what `itinerary` refers to and validate it
+    pub async fn validate(
+        &self,
+        itinerary: &FeaturedVacation,
+        limits: &TripLimits,
+        min_activity_minutes: u16,
+    ) -> Result<ValidationReport, mongodb::error::Error> {
+        let known = self.known_references(itinerary).await?;
+        Ok(validate(itinerary, &known, limits, min_activity_minutes))
+    }
+
+    async fn known_references(&self, itinerary: &FeaturedVacation) -> Result<KnownReferences, mongodb::error::Error> {
+        let activity_ids = scheduled_activity_ids(itinerary);
+        let activities: HashMap<ObjectId, Activity> = self
+            .client
+            .database("Options")
+            .collection::<Activity>("Activity")
+            .find(doc! { "_id": { "$in": &activity_ids } })
+            .await?
+            .try_collect::<Vec<Activity>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| activity.id.map(|id| (id, activity)))
+            .collect();
+
+        let accommodation_ids: Vec<ObjectId> = itinerary
+            .days
+            .days
+            .values()
+            .flatten()
+            .filter_map(|item| match item {
+                DayItem::Accommodation { accommodation_id, .. } => Some(*accommodation_id),
+                _ => None,
+            })
+            .collect();
+        let accommodations = if accommodation_ids.is_empty() {
+            HashSet::new()
+        } else {
+            let found: Vec<Bson> = self
+                .client
+                .database("Options")
+                .collection::<Document>("Lodging")
+                .distinct("_id", doc! { "_id": { "$in": accommodation_ids } })
+                .await?;
+            found.iter().filter_map(|id| id.as_object_id()).collect()
+        };
+
+        let inventory = match itinerary.arrival_datetime.and_then(calendar::utc_date) {
+            Some(start) if !activity_ids.is_empty() => {
+                let trip_days = itinerary.days.days.len().max(itinerary.length_days as usize) as i64;
+                AvailabilityService::new(self.client.clone())
+                    .load_inventory(&activity_ids, start, start + Duration::days(trip_days))
+                    .await?
+                    .0
+            }
+            _ => Inventory::new(),
+        };
+
+        Ok(KnownReferences {
+            activities,
+            accommodations,
+            inventory,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity::{Capacity, GeoPoint};
+    use mongodb::bson::DateTime;
+
+    fn activity(title: &str, duration_minutes: u16, location: (f64, f64)) -> Activity {
+        Activity {
+            description: String::new(),
+            duration_minutes,
+            location: Some(GeoPoint::new(location.0, location.1)),
+            capacity: Capacity { minimum: 1, maximum: 8 },
+            ..Activity::test(title)
+        }
+    }
+
+    fn arrival(date: NaiveDate) -> DateTime {
+        DateTime::from_millis(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis())
+    }
+
+    fn at(time: &str, activity: &Activity) -> DayItem {
+        DayItem::Activity {
+            time: time.to_string(),
+            activity_id: activity.id.unwrap(),
+        }
+    }
+
+    fn trip(days: Vec<(&str, Vec<DayItem>)>) -> FeaturedVacation {
+        let mut itinerary = FeaturedVacation {
+            adults: Some(2),
+            ..Default::default()
+        };
+        itinerary.days.days = days.into_iter().map(|(day, items)| (day.to_string(), items)).collect();
+        itinerary
+    }
+
+    fn known(activities: &[&Activity]) -> KnownReferences {
+        KnownReferences {
+            activities: activities
+                .iter()
+                .map(|activity| (activity.id.unwrap(), (*activity).clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(report: &ValidationReport) -> Vec<IssueKind> {
+        report.issues.iter().map(|issue| issue.kind).collect()
+    }
+
+    #[test]
+    fn test_clean_trip_is_priced_with_route_efficiency() {
+        let hike = activity("Hike", 120, (39.74, -104.99));
+        let museum = activity("Museum", 60, (39.74, -104.99));
+        let mut itinerary = trip(vec![("1", vec![at("09:00:00", &hike), at("13:00", &museum)])]);
+        itinerary.arrival_datetime = Some(arrival(NaiveDate::from_ymd_opt(2026, 6, 2).unwrap()));
+
+        let report = validate(&itinerary, &known(&[&hike, &museum]), &TripLimits::default(), 30);
+        assert!(report.valid);
+        assert!(report.issues.is_empty());
+        assert_eq!(report.person_cost, Some(Money::from_dollars(100.0)));
+        assert_eq!(report.days.len(), 1);
+        assert_eq!(report.days[0].stats.total_activity_time_minutes, 180);
+        assert_eq!(report.efficiency_ratio, 1.0);
+    }
+
+    #[test]
+    fn test_overlaps_and_long_drives_are_flagged() {
+        let denver = activity("Denver tour", 120, (39.74, -104.99));
+        let boulder = activity("Boulder hike", 60, (40.01, -105.27));
+        let overlapping = activity("Brewery", 60, (40.01, -105.27));
+        let itinerary = trip(vec![(
+            "1",
+            vec![at("09:00:00", &denver), at("11:10:00", &boulder), at("11:30:00", &overlapping)],
+        )]);
+
+        let report = validate(&itinerary, &known(&[&denver, &boulder, &overlapping]), &TripLimits::default(), 30);
+        assert!(!report.valid);
+        assert_eq!(
+            kinds(&report),
+            [IssueKind::TimeConflict, IssueKind::DatesNotChecked, IssueKind::TightTransfer]
+        );
+        assert_eq!(report.issues[0].item_id, Some(overlapping.id.unwrap().to_hex()));
+        assert!(report.efficiency_ratio < 1.0);
+    }
+
+    #[test]
+    fn test_closures_seats_and_party_size_are_checked() {
+        let closed = Activity {
+            operating_days: Some(vec![chrono::Weekday::Sat]),
+            ..activity("Weekend rafting", 60, (39.74, -104.99))
+        };
+        let small = Activity {
+            capacity: Capacity { minimum: 1, maximum: 1 },
+            ..activity("Private lesson", 60, (39.74, -104.99))
+        };
+        let busy = activity("Busy tour", 60, (39.74, -104.99));
+        let start = NaiveDate::from_ymd_opt(2026, 6, 2).unwrap(); // A Tuesday
+        let mut itinerary = trip(vec![
+            ("1", vec![at("09:00:00", &closed), at("12:00:00", &small)]),
+            ("2", vec![at("09:00:00", &busy)]),
+        ]);
+        itinerary.arrival_datetime = Some(arrival(start));
+        let mut known = known(&[&closed, &small, &busy]);
+        known.inventory.insert((busy.id.unwrap(), start + Duration::days(1)), 7);
+
+        let report = validate(&itinerary, &known, &TripLimits::default(), 30);
+        assert_eq!(kinds(&report), [IssueKind::OverCapacity, IssueKind::Closed, IssueKind::SoldOut]);
+        assert_eq!(report.issues[1].closure, Some(ClosureReason::ClosedWeekday));
+        assert_eq!(report.issues[2].day, Some(2));
+    }
+
+    #[test]
+    fn test_unknown_references_leave_the_trip_unpriced() {
+        let hike = activity("Hike", 60, (39.74, -104.99));
+        let gone = ObjectId::new();
+        let itinerary = trip(vec![(
+            "1",
+            vec![
+                at("09:00:00", &hike),
+                DayItem::Activity {
+                    time: "13:00:00".to_string(),
+                    activity_id: gone,
+                },
+                DayItem::Accommodation {
+                    time: "18:00:00".to_string(),
+                    accommodation_id: ObjectId::new(),
+                },
+            ],
+        )]);
+
+        let report = validate(&itinerary, &known(&[&hike]), &TripLimits::default(), 30);
+        assert_eq!(
+            kinds(&report)[..2],
+            [IssueKind::UnknownActivity, IssueKind::UnknownAccommodation]
+        );
+        assert_eq!(report.issues[0].item_id, Some(gone.to_hex()));
+        assert_eq!(report.person_cost, None);
+    }
+}
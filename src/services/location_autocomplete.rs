@@ -0,0 +1,427 @@
+//! Destination autocomplete from our own inventory
+//!
+//! Cities come from `Options.Location`, activity addresses and the start
+//! locations of listed itineraries, merged on city and state. The index lives in
+//! memory and is rebuilt in the background (every `LOCATION_INDEX_REFRESH_MINUTES`),
+//! so a keystroke never touches the database.
+//!
+//! Matching ignores case and diacritics ("sao" finds "São Paulo"). Cities whose
+//! name starts with the query rank above ones that only contain it; within each
+//! group, cities with more itineraries and activities come first.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Client, Collection,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::db::mongo::read_only_collection;
+use crate::services::location_terms::{resolve_state, UsState};
+
+pub const DEFAULT_LIMIT: usize = 8;
+pub const MAX_LIMIT: usize = 25;
+
+/// Lowercase with accents stripped, for matching
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LocationSuggestion {
+    pub city: String,
+    pub state: String,
+    /// "City, ST"
+    pub display: String,
+    pub itinerary_count: u32,
+    pub activity_count: u32,
+    /// As stored on the curated location, else on an itinerary starting there
+    pub coordinates: Option<(f64, f64)>,
+}
+
+impl LocationSuggestion {
+    fn inventory(&self) -> u32 {
+        self.itinerary_count + self.activity_count
+    }
+}
+
+/// A city seen in one of the sources
+#[derive(Debug, Clone, Default)]
+pub struct CitySighting {
+    pub city: String,
+    pub state: String,
+    pub coordinates: Option<(f64, f64)>,
+}
+
+/// Everything the index is built from
+#[derive(Debug, Default)]
+pub struct LocationSources {
+    /// Curated `Options.Location` entries
+    pub locations: Vec<CitySighting>,
+    /// One per listed itinerary, from its start location
+    pub itineraries: Vec<CitySighting>,
+    /// One per activity, from its address
+    pub activities: Vec<CitySighting>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchQuality {
+    Prefix,
+    Substring,
+}
+
+struct IndexedCity {
+    suggestion: LocationSuggestion,
+    folded_city: String,
+    folded_display: String,
+}
+
+impl IndexedCity {
+    fn matches(&self, query: &str) -> Option<MatchQuality> {
+        if self.folded_city.starts_with(query) || self.folded_display.starts_with(query) {
+            Some(MatchQuality::Prefix)
+        } else if self.folded_display.contains(query) {
+            Some(MatchQuality::Substring)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LocationIndex {
+    cities: Vec<IndexedCity>,
+}
+
+impl LocationIndex {
+    /// Merge the sources into one entry per city and state
+    pub fn build(sources: LocationSources) -> Self {
+        let mut merged: HashMap<(String, String), IndexedCity> = HashMap::new();
+        let mut add = |sighting: CitySighting, itineraries: u32, activities: u32| {
+            let city = sighting.city.trim();
+            let state = sighting.state.trim();
+            if city.is_empty() {
+                return;
+            }
+            let entry = merged
+                .entry((fold(city), fold(state)))
+                .or_insert_with(|| {
+                    let display = if state.is_empty() {
+                        city.to_string()
+                    } else {
+                        format!("{}, {}", city, state)
+                    };
+                    IndexedCity {
+                        folded_city: fold(city),
+                        folded_display: fold(&display),
+                        suggestion: LocationSuggestion {
+                            city: city.to_string(),
+                            state: state.to_string(),
+                            display,
+                            itinerary_count: 0,
+                            activity_count: 0,
+                            coordinates: None,
+                        },
+                    }
+                });
+            entry.suggestion.itinerary_count += itineraries;
+            entry.suggestion.activity_count += activities;
+            if entry.suggestion.coordinates.is_none() {
+                entry.suggestion.coordinates = sighting.coordinates;
+            }
+        };
+
+        // Curated locations first, so their spelling and coordinates win
+        for sighting in sources.locations {
+            add(sighting, 0, 0);
+        }
+        for sighting in sources.itineraries {
+            add(sighting, 1, 0);
+        }
+        for sighting in sources.activities {
+            add(sighting, 0, 1);
+        }
+
+        LocationIndex {
+            cities: merged.into_values().collect(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    /// Best `limit` cities for `query`. An empty query matches nothing.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<LocationSuggestion> {
+        let query = fold(query.trim());
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches: Vec<(MatchQuality, &LocationSuggestion)> = self
+            .cities
+            .iter()
+            .filter_map(|city| city.matches(&query).map(|quality| (quality, &city.suggestion)))
+            .collect();
+        matches.sort_by(|(a_quality, a), (b_quality, b)| {
+            a_quality
+                .cmp(b_quality)
+                .then(b.inventory().cmp(&a.inventory()))
+                .then(b.itinerary_count.cmp(&a.itinerary_count))
+                .then(a.display.cmp(&b.display))
+        });
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, suggestion)| suggestion.clone())
+            .collect()
+    }
+
+    /// A city in `state` with inventory, picked with odds in proportion to how
+    /// much it has. `None` when nothing in the state has any.
+    pub fn pick_in_state<R: Rng>(&self, state: &UsState, rng: &mut R) -> Option<LocationSuggestion> {
+        let candidates: Vec<&LocationSuggestion> = self
+            .cities
+            .iter()
+            .map(|city| &city.suggestion)
+            .filter(|city| city.inventory() > 0 && resolve_state(&city.state) == Some(state))
+            .collect();
+        candidates
+            .choose_weighted(rng, |city| city.inventory())
+            .ok()
+            .map(|city| (*city).clone())
+    }
+}
+
+fn coordinates(value: Option<&Bson>) -> Option<(f64, f64)> {
+    let Bson::Array(values) = value? else {
+        return None;
+    };
+    let number = |value: &Bson| match value {
+        Bson::Double(n) => Some(*n),
+        Bson::Int32(n) => Some(*n as f64),
+        Bson::Int64(n) => Some(*n as f64),
+        _ => None,
+    };
+    match (number(values.first()?)?, number(values.get(1)?)?) {
+        // Unset coordinates are stored as zeros
+        (x, y) if x == 0.0 && y == 0.0 => None,
+        pair => Some(pair),
+    }
+}
+
+fn sighting(place: &Document) -> CitySighting {
+    CitySighting {
+        city: place.get_str("city").unwrap_or_default().to_string(),
+        state: place.get_str("state").unwrap_or_default().to_string(),
+        coordinates: coordinates(place.get("coordinates")),
+    }
+}
+
+/// Read the three sources from MongoDB
+pub async fn load_sources(client: &Arc<Client>) -> Result<LocationSources, mongodb::error::Error> {
+    load_sources_where(client, None).await
+}
+
+/// Read the three sources, keeping only places in `state`
+pub async fn load_sources_in_state(
+    client: &Arc<Client>,
+    state: &UsState,
+) -> Result<LocationSources, mongodb::error::Error> {
+    load_sources_where(client, Some(state)).await
+}
+
+async fn load_sources_where(
+    client: &Arc<Client>,
+    state: Option<&UsState>,
+) -> Result<LocationSources, mongodb::error::Error> {
+    let in_state = |field: &str| match state {
+        Some(state) => doc! { field: { "$in": state.stored_forms().to_vec() } },
+        None => doc! {},
+    };
+    let mut listed = in_state("start_location.state");
+    listed.insert("taken_down_at", Bson::Null);
+    listed.insert("archived_at", Bson::Null);
+
+    let locations: Collection<Document> = read_only_collection(client, "Options", "Location");
+    let itineraries: Collection<Document> = read_only_collection(client, "Itineraries", "Featured");
+    let activities: Collection<Document> = read_only_collection(client, "Options", "Activity");
+
+    let locations: Vec<Document> = locations
+        .find(in_state("state"))
+        .projection(doc! { "city": 1, "state": 1, "coordinates": 1 })
+        .await?
+        .try_collect()
+        .await?;
+    let itineraries: Vec<Document> = itineraries
+        .find(listed)
+        .projection(doc! { "start_location": 1 })
+        .await?
+        .try_collect()
+        .await?;
+    let activities: Vec<Document> = activities
+        .find(in_state("address.state"))
+        .projection(doc! { "address.city": 1, "address.state": 1 })
+        .await?
+        .try_collect()
+        .await?;
+
+    Ok(LocationSources {
+        locations: locations.iter().map(sighting).collect(),
+        itineraries: itineraries
+            .iter()
+            .filter_map(|itinerary| itinerary.get_document("start_location").ok())
+            .map(sighting)
+            .collect(),
+        activities: activities
+            .iter()
+            .filter_map(|activity| activity.get_document("address").ok())
+            .map(sighting)
+            .collect(),
+    })
+}
+
+/// The index handlers search, shared through `web::Data` and kept current by
+/// the refresh task started in `LocationAutocomplete::start`
+#[derive(Default)]
+pub struct LocationAutocomplete {
+    current: RwLock<Arc<LocationIndex>>,
+}
+
+impl LocationAutocomplete {
+    pub fn search(&self, query: &str, limit: usize) -> Vec<LocationSuggestion> {
+        let index = self
+            .current
+            .read()
+            .map(|index| index.clone())
+            .unwrap_or_default();
+        index.search(query, limit)
+    }
+
+    /// Rebuild the index from the database. On failure the previous index is kept.
+    pub async fn refresh(&self, client: &Arc<Client>) -> Result<usize, mongodb::error::Error> {
+        let index = LocationIndex::build(load_sources(client).await?);
+        let cities = index.len();
+        if let Ok(mut current) = self.current.write() {
+            *current = Arc::new(index);
+        }
+        Ok(cities)
+    }
+
+    /// Build the index now and then every `refresh_interval`
+    pub fn start(client: Arc<Client>, refresh_interval: Duration) -> Arc<Self> {
+        let autocomplete = Arc::new(LocationAutocomplete::default());
+        let shared = autocomplete.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh_interval);
+            loop {
+                interval.tick().await;
+                match shared.refresh(&client).await {
+                    Ok(cities) => println!("📍 Location index refreshed: {} cities", cities),
+                    Err(e) => eprintln!("Failed to refresh location index: {}", e),
+                }
+            }
+        });
+        autocomplete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(city: &str, state: &str) -> CitySighting {
+        CitySighting {
+            city: city.to_string(),
+            state: state.to_string(),
+            coordinates: None,
+        }
+    }
+
+    fn cities(suggestions: &[LocationSuggestion]) -> Vec<&str> {
+        suggestions.iter().map(|s| s.city.as_str()).collect()
+    }
+
+    #[test]
+    fn test_prefix_matches_rank_above_substring_matches() {
+        let index = LocationIndex::build(LocationSources {
+            // Plenty of inventory, but "bre" is only inside the name
+            itineraries: vec![at("Lake Brenton", "WA"); 5],
+            activities: vec![at("Bremerton", "WA"), at("Lake Brenton", "WA")],
+            ..Default::default()
+        });
+
+        assert_eq!(cities(&index.search("bre", 8)), vec!["Bremerton", "Lake Brenton"]);
+        assert!(index.search("  ", 8).is_empty());
+        assert!(index.search("xyz", 8).is_empty());
+    }
+
+    #[test]
+    fn test_busier_cities_rank_first_and_sources_merge() {
+        let index = LocationIndex::build(LocationSources {
+            locations: vec![CitySighting {
+                coordinates: Some((-106.04, 39.48)),
+                ..at("Breckenridge", "CO")
+            }],
+            itineraries: vec![at("Breckenridge", "CO"), at("breckenridge ", "co"), at("Brevard", "NC")],
+            activities: vec![at("Brevard", "NC"), at("Bremen", "GA")],
+        });
+
+        let results = index.search("BRE", 8);
+        assert_eq!(cities(&results), vec!["Breckenridge", "Brevard", "Bremen"]);
+        assert_eq!(index.len(), 3);
+
+        let breckenridge = &results[0];
+        assert_eq!(breckenridge.display, "Breckenridge, CO");
+        assert_eq!(breckenridge.itinerary_count, 2);
+        assert_eq!(breckenridge.activity_count, 0);
+        assert_eq!(breckenridge.coordinates, Some((-106.04, 39.48)));
+
+        assert_eq!(index.search("bre", 2).len(), 2);
+    }
+
+    #[test]
+    fn test_matching_ignores_accents() {
+        let index = LocationIndex::build(LocationSources {
+            activities: vec![at("São Paulo", "SP"), at("Cañon City", "CO")],
+            ..Default::default()
+        });
+
+        assert_eq!(cities(&index.search("sao", 8)), vec!["São Paulo"]);
+        assert_eq!(cities(&index.search("CANON", 8)), vec!["Cañon City"]);
+        assert_eq!(cities(&index.search("cañ", 8)), vec!["Cañon City"]);
+        assert_eq!(fold("Zürich"), "zurich");
+    }
+
+    #[test]
+    fn test_state_pick_only_lands_on_cities_with_inventory() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let index = LocationIndex::build(LocationSources {
+            // Curated, but nothing to do there yet
+            locations: vec![at("Telluride", "CO")],
+            itineraries: vec![at("Salida", "CO"); 3],
+            activities: vec![at("Ouray", "Colorado"), at("Moab", "UT")],
+        });
+        let colorado = resolve_state("CO").unwrap();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let picks: Vec<String> = (0..50)
+            .map(|_| index.pick_in_state(colorado, &mut rng).unwrap().city)
+            .collect();
+        assert!(picks.iter().all(|city| city == "Salida" || city == "Ouray"));
+        assert!(picks.iter().any(|city| city == "Ouray"));
+        assert!(picks.iter().filter(|city| *city == "Salida").count() > 25);
+
+        let wyoming = resolve_state("Wyoming").unwrap();
+        assert!(index.pick_in_state(wyoming, &mut rng).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+//! How the `locations` of a search are read.
+//!
+//! A term is either a city ("Denver, CO", "Denver") or, when the whole term
+//! names a US state ("Colorado", "CO"), the state itself. State-level terms
+//! match any itinerary in that state, so a search for "Colorado" doesn't have
+//! to name a city to find anything.
+
+use mongodb::bson::{doc, Document};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UsState {
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+impl UsState {
+    /// The ways a state is stored on locations and addresses
+    pub fn stored_forms(&self) -> [&'static str; 2] {
+        [self.code, self.name]
+    }
+}
+
+const fn state(code: &'static str, name: &'static str) -> UsState {
+    UsState { code, name }
+}
+
+pub const US_STATES: [UsState; 51] = [
+    state("AL", "Alabama"),
+    state("AK", "Alaska"),
+    state("AZ", "Arizona"),
+    state("AR", "Arkansas"),
+    state("CA", "California"),
+    state("CO", "Colorado"),
+    state("CT", "Connecticut"),
+    state("DE", "Delaware"),
+    state("DC", "District of Columbia"),
+    state("FL", "Florida"),
+    state("GA", "Georgia"),
+    state("HI", "Hawaii"),
+    state("ID", "Idaho"),
+    state("IL", "Illinois"),
+    state("IN", "Indiana"),
+    state("IA", "Iowa"),
+    state("KS", "Kansas"),
+    state("KY", "Kentucky"),
+    state("LA", "Louisiana"),
+    state("ME", "Maine"),
+    state("MD", "Maryland"),
+    state("MA", "Massachusetts"),
+    state("MI", "Michigan"),
+    state("MN", "Minnesota"),
+    state("MS", "Mississippi"),
+    state("MO", "Missouri"),
+    state("MT", "Montana"),
+    state("NE", "Nebraska"),
+    state("NV", "Nevada"),
+    state("NH", "New Hampshire"),
+    state("NJ", "New Jersey"),
+    state("NM", "New Mexico"),
+    state("NY", "New York"),
+    state("NC", "North Carolina"),
+    state("ND", "North Dakota"),
+    state("OH", "Ohio"),
+    state("OK", "Oklahoma"),
+    state("OR", "Oregon"),
+    state("PA", "Pennsylvania"),
+    state("RI", "Rhode Island"),
+    state("SC", "South Carolina"),
+    state("SD", "South Dakota"),
+    state("TN", "Tennessee"),
+    state("TX", "Texas"),
+    state("UT", "Utah"),
+    state("VT", "Vermont"),
+    state("VA", "Virginia"),
+    state("WA", "Washington"),
+    state("WV", "West Virginia"),
+    state("WI", "Wisconsin"),
+    state("WY", "Wyoming"),
+];
+
+/// The state named by `text`, by code or full name, ignoring case
+pub fn resolve_state(text: &str) -> Option<&'static UsState> {
+    let text = text.trim();
+    US_STATES
+        .iter()
+        .find(|state| state.code.eq_ignore_ascii_case(text) || state.name.eq_ignore_ascii_case(text))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocationTerm {
+    City {
+        city: String,
+        /// As typed after the comma, if anything was
+        state: Option<String>,
+    },
+    State(&'static UsState),
+}
+
+impl LocationTerm {
+    /// Read one search term. Blank terms are `None`.
+    pub fn parse(term: &str) -> Option<LocationTerm> {
+        let term = term.trim();
+        if term.is_empty() {
+            return None;
+        }
+        if let Some(state) = resolve_state(term) {
+            return Some(LocationTerm::State(state));
+        }
+        let mut parts = term.splitn(2, ',').map(str::trim);
+        let city = parts.next().unwrap_or_default().to_string();
+        let state = parts.next().filter(|state| !state.is_empty()).map(str::to_string);
+        Some(LocationTerm::City { city, state })
+    }
+
+    /// The state this term is in, when it names one we know
+    pub fn state(&self) -> Option<&'static UsState> {
+        match self {
+            LocationTerm::City { state, .. } => state.as_deref().and_then(resolve_state),
+            LocationTerm::State(state) => Some(state),
+        }
+    }
+}
+
+/// Every term of a search, without repeats. "Colorado" and "CO" are the same
+/// term, as are "Denver, CO" and "denver, Colorado".
+pub fn parse_terms(locations: &[String]) -> Vec<LocationTerm> {
+    let mut terms: Vec<LocationTerm> = Vec::new();
+    for term in locations.iter().filter_map(|location| LocationTerm::parse(location)) {
+        let repeated = terms.iter().any(|seen| match (seen, &term) {
+            (LocationTerm::State(a), LocationTerm::State(b)) => a.code == b.code,
+            (LocationTerm::City { city: a, .. }, LocationTerm::City { city: b, .. }) => {
+                a.eq_ignore_ascii_case(b) && seen.state() == term.state()
+            }
+            _ => false,
+        });
+        if !repeated {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// Filter matching itineraries that start or end at any of `terms`. A city whose
+/// state is also searched for is left to the state clause, which already covers it.
+pub fn itinerary_filter(terms: &[LocationTerm]) -> Option<Document> {
+    let states: Vec<&'static UsState> = terms
+        .iter()
+        .filter_map(|term| match term {
+            LocationTerm::State(state) => Some(*state),
+            LocationTerm::City { .. } => None,
+        })
+        .collect();
+    let cities: Vec<&str> = terms
+        .iter()
+        .filter_map(|term| match term {
+            LocationTerm::City { city, .. } if !term.state().is_some_and(|s| states.contains(&s)) => {
+                Some(city.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    let state_names: Vec<&str> = states.iter().flat_map(|state| state.stored_forms()).collect();
+
+    let mut or_conditions = Vec::new();
+    if !cities.is_empty() {
+        or_conditions.push(doc! { "start_location.city": { "$in": cities.clone() } });
+        or_conditions.push(doc! { "end_location.city": { "$in": cities } });
+    }
+    if !state_names.is_empty() {
+        or_conditions.push(doc! { "start_location.state": { "$in": state_names.clone() } });
+        or_conditions.push(doc! { "end_location.state": { "$in": state_names } });
+    }
+    if or_conditions.is_empty() {
+        None
+    } else {
+        Some(doc! { "$or": or_conditions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(locations: &[&str]) -> Vec<LocationTerm> {
+        parse_terms(&locations.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_state_names_and_codes_are_state_terms() {
+        let colorado = resolve_state("colorado").unwrap();
+        assert_eq!(LocationTerm::parse(" Colorado "), Some(LocationTerm::State(colorado)));
+        assert_eq!(LocationTerm::parse("co"), Some(LocationTerm::State(colorado)));
+        assert_eq!(
+            LocationTerm::parse("Denver, CO"),
+            Some(LocationTerm::City {
+                city: "Denver".to_string(),
+                state: Some("CO".to_string()),
+            })
+        );
+        assert_eq!(LocationTerm::parse("Denver, CO").unwrap().state(), Some(colorado));
+        assert!(LocationTerm::parse("  ").is_none());
+    }
+
+    #[test]
+    fn test_repeated_terms_are_dropped() {
+        let parsed = terms(&["Colorado", "Denver, CO", "CO", "denver, Colorado", "Boulder"]);
+        assert_eq!(parsed.len(), 3);
+        assert!(matches!(parsed[0], LocationTerm::State(state) if state.code == "CO"));
+    }
+
+    #[test]
+    fn test_state_clause_covers_cities_in_that_state() {
+        let filter = itinerary_filter(&terms(&["Denver, CO", "Colorado", "Moab, UT"])).unwrap();
+        let clauses = filter.get_array("$or").unwrap();
+        assert_eq!(clauses.len(), 4);
+        let start_cities = clauses[0].as_document().unwrap().get_document("start_location.city").unwrap();
+        assert_eq!(start_cities.get_array("$in").unwrap().len(), 1);
+        assert_eq!(start_cities.get_array("$in").unwrap()[0].as_str(), Some("Moab"));
+        let start_states = clauses[2].as_document().unwrap().get_document("start_location.state").unwrap();
+        assert_eq!(
+            start_states.get_array("$in").unwrap().iter().filter_map(|s| s.as_str()).collect::<Vec<_>>(),
+            vec!["CO", "Colorado"]
+        );
+
+        assert!(itinerary_filter(&[]).is_none());
+    }
+}
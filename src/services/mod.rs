@@ -1,14 +1,76 @@
 pub mod account_service;
+pub mod activity_dedup_service;
+pub mod activity_synonyms;
+pub mod admin_booking_service;
+pub mod api_token_service;
+pub mod availability_service;
+pub mod booking_confirmation;
+pub mod booking_impact_service;
+pub mod booking_reschedule;
+pub mod booking_status_service;
+pub mod calendar;
+pub mod content_flag_service;
+pub mod cost_recompute_service;
+pub mod credential_check;
+#[cfg(feature = "demo-tools")]
+pub mod demo_seed_service;
+pub mod destination_constraints;
 pub mod distance_service;
+pub mod email_suppression_service;
+pub mod email_verification_service;
+pub mod export_service;
 pub mod facebook_auth_service;
+pub mod favorite_digest_service;
+pub mod feature_flags;
+pub mod fx_service;
+pub mod geocoding_service;
+pub mod generation_budget;
+pub mod generation_trace;
+pub mod gift_card_service;
 pub mod google_auth_service;
+pub mod image_fallback;
 pub mod image_service;
+pub mod impersonation_service;
+pub mod integrity_service;
+pub mod itinerary_bulk_service;
 pub mod itinerary_generation_service;
 pub mod itinerary_search_service;
 pub mod itinerary_service;
+pub mod itinerary_validation_service;
+pub mod location_autocomplete;
+pub mod location_terms;
+pub mod moderation;
+pub mod notification_service;
+pub mod oauth_link_service;
+pub mod operator_service;
 pub mod payment;
+pub mod payment_idempotency;
+pub mod payment_teardown;
+pub mod phone;
+pub mod price_alert_service;
 pub mod pricing_service;
+pub mod recent_search_service;
+pub mod recently_viewed_service;
+pub mod reservation_service;
+pub mod retention_service;
+pub mod review_request_service;
+pub mod route_map_service;
 pub mod route_optimization_service;
+pub mod score_preview_service;
+pub mod search_experiments;
 pub mod search_scoring;
+pub mod security_event_service;
+pub mod self_check;
+pub mod special_requests;
+pub mod storage;
+pub mod streaming;
 pub mod stripe;
+pub mod trip_limits;
+pub mod trip_notes_service;
+pub mod trip_status_service;
+pub mod unit_of_work;
+pub mod units;
+pub mod vertex_activity;
 pub mod vertex_search_service;
+pub mod webhook_replay;
+pub mod write_behind;
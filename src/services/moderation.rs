@@ -0,0 +1,248 @@
+//! Moderation for user-influenced text that other users can see.
+//!
+//! Generated trip names and descriptions are built from raw search terms and
+//! generated itineraries are public, so that text and travelers' special requests
+//! pass through [`Moderator::moderate`] before they are stored. Look-alike
+//! characters are folded and long punctuation runs shortened; URLs, email addresses
+//! and denylisted terms (`MODERATION_DENYLIST`) fail it outright.
+
+use regex::Regex;
+use std::sync::LazyLock;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Longest run of one punctuation character kept, so "!!!!!!" becomes "!!!"
+pub const MAX_PUNCTUATION_RUN: usize = 3;
+
+static EMAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)[a-z0-9._%+-]+\s*@\s*[a-z0-9-]+(\.[a-z0-9-]+)*\.[a-z]{2,}").unwrap());
+
+static URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(https?://|www\.)|\b[a-z0-9-]+\.(com|net|org|io|co|us|ru|cn|info|biz|xyz|top|link|click|ly|me|app|site|online|shop)\b",
+    )
+    .unwrap()
+});
+
+/// Why text failed moderation. Only the category is logged, never the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationHit {
+    Url,
+    Email,
+    Denylisted,
+}
+
+impl std::fmt::Display for ModerationHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ModerationHit::Url => write!(f, "Links aren't allowed here"),
+            ModerationHit::Email => write!(f, "Email addresses aren't allowed here"),
+            ModerationHit::Denylisted => write!(f, "This text contains language that isn't allowed"),
+        }
+    }
+}
+
+impl std::error::Error for ModerationHit {}
+
+impl ModerationHit {
+    /// Name for logs and monitoring
+    pub fn category(&self) -> &'static str {
+        match self {
+            ModerationHit::Url => "url",
+            ModerationHit::Email => "email",
+            ModerationHit::Denylisted => "denylist",
+        }
+    }
+
+    /// Record a hit for monitoring, naming where it happened but not the text
+    pub fn log(&self, context: &str) {
+        println!("Moderation hit in {}: {}", context, self.category());
+    }
+}
+
+/// Latin letter a Cyrillic or Greek look-alike stands for
+fn unconfuse(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'с' | 'ϲ' => 'c',
+        'е' | 'ε' => 'e',
+        'һ' => 'h',
+        'і' | 'ι' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'м' => 'm',
+        'н' | 'η' => 'n',
+        'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        'А' | 'Α' => 'A',
+        'В' | 'Β' => 'B',
+        'С' => 'C',
+        'Е' | 'Ε' => 'E',
+        'Н' | 'Η' => 'H',
+        'І' | 'Ι' => 'I',
+        'К' | 'Κ' => 'K',
+        'М' | 'Μ' => 'M',
+        'О' | 'Ο' => 'O',
+        'Р' | 'Ρ' => 'P',
+        'Т' | 'Τ' => 'T',
+        'Х' | 'Χ' => 'X',
+        'У' | 'Υ' => 'Y',
+        _ => c,
+    }
+}
+
+/// Invisible characters used to split words past filters
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{00AD}' | '\u{200B}'..='\u{200F}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}')
+}
+
+/// Compatibility forms (fullwidth, ligatures, styled letters) and look-alikes folded
+/// to plain letters, invisible characters dropped and punctuation runs shortened
+pub fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    let mut run: Option<(char, usize)> = None;
+    for c in text.nfkc().filter(|c| !is_invisible(*c)).map(unconfuse) {
+        if c.is_ascii_punctuation() {
+            let length = match run {
+                Some((previous, length)) if previous == c => length + 1,
+                _ => 1,
+            };
+            run = Some((c, length));
+            if length > MAX_PUNCTUATION_RUN {
+                continue;
+            }
+        } else {
+            run = None;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// Lowercase words with accents stripped and common digit swaps undone, for
+/// matching against the denylist
+fn match_words(text: &str) -> Vec<String> {
+    let folded: String = text
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '0' => 'o',
+            '1' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            _ => c,
+        })
+        .collect();
+    folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Moderator {
+    /// Each term as its match words; a multi-word term matches those words in a row
+    denylist: Vec<Vec<String>>,
+}
+
+impl Moderator {
+    pub fn new<S: AsRef<str>>(terms: &[S]) -> Self {
+        Moderator {
+            denylist: terms
+                .iter()
+                .map(|term| match_words(&normalize(term.as_ref())))
+                .filter(|words| !words.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Terms from a comma-separated list, as in `MODERATION_DENYLIST`
+    pub fn from_list(list: &str) -> Self {
+        Self::new(&list.split(',').collect::<Vec<_>>())
+    }
+
+    fn denylisted(&self, text: &str) -> bool {
+        let words = match_words(text);
+        self.denylist
+            .iter()
+            .any(|term| words.windows(term.len()).any(|window| window == term.as_slice()))
+    }
+
+    /// `text` normalized, or why it can't be shown to other users
+    pub fn moderate(&self, text: &str) -> Result<String, ModerationHit> {
+        let normalized = normalize(text);
+        if EMAIL.is_match(&normalized) {
+            return Err(ModerationHit::Email);
+        }
+        if URL.is_match(&normalized) {
+            return Err(ModerationHit::Url);
+        }
+        if self.denylisted(&normalized) {
+            return Err(ModerationHit::Denylisted);
+        }
+        Ok(normalized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moderator() -> Moderator {
+        Moderator::from_list("scam, cheap pills")
+    }
+
+    #[test]
+    fn test_urls_and_emails_are_refused() {
+        let moderator = moderator();
+        for text in [
+            "Denver https://spam.example/deal Adventure",
+            "Visit www.cheap-trips.biz now",
+            "Denver spamdeals.com Getaway",
+            "Denver ｓｐａｍ．ｃｏｍ Getaway",
+        ] {
+            assert_eq!(moderator.moderate(text), Err(ModerationHit::Url), "{}", text);
+        }
+        assert_eq!(moderator.moderate("Write to deals@spam.net"), Err(ModerationHit::Email));
+        assert_eq!(moderator.moderate("deals @ spam.net"), Err(ModerationHit::Email));
+    }
+
+    #[test]
+    fn test_denylisted_terms_are_refused_through_disguises() {
+        let moderator = moderator();
+        for text in ["Denver Scam Adventure", "Denver ѕсаm Tour", "S\u{200B}cam tour", "Buy Ch3ap P1lls"] {
+            assert_eq!(moderator.moderate(text), Err(ModerationHit::Denylisted), "{}", text);
+        }
+        // Whole words only
+        assert!(moderator.moderate("Scampi tasting").is_ok());
+        assert!(moderator.moderate("Cheap eats and vitamin pills").is_ok());
+    }
+
+    #[test]
+    fn test_legitimate_text_passes_untouched() {
+        let moderator = moderator();
+        for text in [
+            "Discover Denver - Hiking Experience",
+            "Immerse yourself in Santa Fe's culture while enjoying amazing rafting activities.",
+            "St. Louis Café & Brewery Tour",
+            "Vegetarian, afraid of heights\nCelebrating our 10th anniversary!",
+        ] {
+            assert_eq!(moderator.moderate(text), Ok(text.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_normalize_folds_look_alikes_and_shortens_punctuation() {
+        assert_eq!(normalize("Ｄｅｎｖｅｒ Тоur!!!!!!"), "Denver Tour!!!");
+        assert_eq!(normalize("Wow...... ok?!?!"), "Wow... ok?!?!");
+    }
+}
@@ -0,0 +1,246 @@
+//! Notifications outside email. Currently SMS through Twilio.
+//!
+//! SMS is optional: without `TWILIO_ACCOUNT_SID`, `TWILIO_AUTH_TOKEN` and
+//! `TWILIO_FROM_NUMBER` in `AppConfig` the sender isn't created and every send
+//! is a no-op.
+
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::models::account::User;
+use crate::models::bookings::BookingDetails;
+
+/// Longest text sent in one SMS segment
+const SMS_SEGMENT_CHARS: usize = 160;
+
+#[derive(Debug)]
+pub enum SmsError {
+    Environment(String),
+    Request(String),
+    Api(String),
+}
+
+impl std::fmt::Display for SmsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmsError::Environment(err) => write!(f, "Environment error: {}", err),
+            SmsError::Request(err) => write!(f, "Request error: {}", err),
+            SmsError::Api(err) => write!(f, "API error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SmsError {}
+
+#[derive(Debug, Deserialize)]
+struct TwilioMessage {
+    sid: String,
+}
+
+/// Twilio account texts are sent from. Only present when all three variables are set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwilioSettings {
+    pub account_sid: String,
+    pub auth_token: String,
+    pub from_number: String,
+}
+
+pub struct TwilioSmsSender {
+    settings: TwilioSettings,
+    client: reqwest::Client,
+}
+
+impl TwilioSmsSender {
+    pub fn new(settings: &TwilioSettings) -> Result<Self, SmsError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| SmsError::Environment(e.to_string()))?;
+
+        Ok(Self {
+            settings: settings.clone(),
+            client,
+        })
+    }
+
+    /// Send `body` to an E.164 number, returning the Twilio message sid
+    pub async fn send(&self, to: &str, body: &str) -> Result<String, SmsError> {
+        let url = format!(
+            "https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json",
+            self.settings.account_sid
+        );
+        let response = self
+            .client
+            .post(url)
+            .basic_auth(&self.settings.account_sid, Some(&self.settings.auth_token))
+            .form(&[("To", to), ("From", self.settings.from_number.as_str()), ("Body", body)])
+            .send()
+            .await
+            .map_err(|e| SmsError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(SmsError::Api(format!("Status: {}, Body: {}", status, body)));
+        }
+
+        response
+            .json::<TwilioMessage>()
+            .await
+            .map(|message| message.sid)
+            .map_err(|e| SmsError::Api(e.to_string()))
+    }
+}
+
+/// Number to text booking updates to, if `user` opted into them and has a normalized
+/// phone number
+pub fn sms_recipient(user: &User) -> Option<&str> {
+    if !user.effective_notification_preferences().sms.booking_updates {
+        return None;
+    }
+    user.phone_number_e164.as_deref()
+}
+
+/// Short booking confirmation text, kept to a single SMS segment
+pub fn booking_confirmation_sms(trip_name: &str, booking: &BookingDetails) -> String {
+    let arrival = match Utc.timestamp_millis_opt(booking.arrival_datetime.timestamp_millis()) {
+        chrono::LocalResult::Single(dt) => dt.format("%b %-d, %Y").to_string(),
+        _ => "your trip date".to_string(),
+    };
+    let reference = booking
+        .id
+        .map(|id| id.to_hex()[16..].to_uppercase())
+        .unwrap_or_default();
+
+    let render = |trip: &str| {
+        format!(
+            "ACTOTA: Your booking for {} on {} is confirmed (ref {}). Reply STOP to opt out.",
+            trip, arrival, reference
+        )
+    };
+    let message = render(trip_name);
+    let overflow = message.chars().count().saturating_sub(SMS_SEGMENT_CHARS);
+    if overflow == 0 {
+        return message;
+    }
+    let keep = trip_name.chars().count().saturating_sub(overflow + 3);
+    let trip: String = trip_name.chars().take(keep).collect();
+    // A short trip name can't absorb all of the overflow, so the rest is cut from the end
+    render(&format!("{}...", trip.trim_end()))
+        .chars()
+        .take(SMS_SEGMENT_CHARS)
+        .collect()
+}
+
+/// Non-email notifications. Channels that aren't configured are skipped.
+pub struct NotificationService {
+    sms: Option<TwilioSmsSender>,
+}
+
+impl NotificationService {
+    pub fn new(twilio: Option<&TwilioSettings>) -> Self {
+        let sms = twilio.and_then(|settings| match TwilioSmsSender::new(settings) {
+            Ok(sender) => Some(sender),
+            Err(e) => {
+                eprintln!("SMS notifications not available: {}", e);
+                None
+            }
+        });
+        Self { sms }
+    }
+
+    /// Text the booking confirmation to users who opted into SMS. Failures are
+    /// logged; a missed text never affects the booking.
+    pub async fn send_booking_confirmation_sms(
+        &self,
+        user: &User,
+        trip_name: &str,
+        booking: &BookingDetails,
+    ) {
+        let (Some(sender), Some(to)) = (&self.sms, sms_recipient(user)) else {
+            return;
+        };
+        match sender.send(to, &booking_confirmation_sms(trip_name, booking)).await {
+            Ok(sid) => println!("📱 Booking confirmation SMS sent ({})", sid),
+            Err(e) => eprintln!("Failed to send booking confirmation SMS: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::account::Notification;
+    use crate::models::bookings::PaymentStatus;
+    use mongodb::bson::{oid::ObjectId, DateTime};
+
+    fn user(sms: bool, phone: Option<&str>) -> User {
+        User {
+            phone_number_e164: phone.map(str::to_string),
+            notification: Some(Notification {
+                account_activities: true,
+                reminders: true,
+                travel_tips: false,
+                special_offers: false,
+                newsletter: false,
+                sms,
+            }),
+            ..User::test("traveler@example.com")
+        }
+    }
+
+    fn booking() -> BookingDetails {
+        BookingDetails {
+            id: Some(ObjectId::parse_str("65f0000000000000000000ab").unwrap()),
+            // 2026-06-14T16:00:00Z
+            ..BookingDetails::test(
+                PaymentStatus::Confirmed,
+                DateTime::from_millis(1_781_452_800_000),
+                DateTime::from_millis(1_781_712_000_000),
+            )
+        }
+    }
+
+    #[test]
+    fn test_only_opted_in_users_with_a_phone_are_texted() {
+        assert_eq!(sms_recipient(&user(true, Some("+13035550142"))), Some("+13035550142"));
+        assert_eq!(sms_recipient(&user(false, Some("+13035550142"))), None);
+        assert_eq!(sms_recipient(&user(true, None)), None);
+
+        let mut no_preferences = user(true, Some("+13035550142"));
+        no_preferences.notification = None;
+        assert_eq!(sms_recipient(&no_preferences), None);
+    }
+
+    #[test]
+    fn test_confirmation_text_fits_one_segment() {
+        let message = booking_confirmation_sms("Arkansas River Weekend", &booking());
+        assert_eq!(
+            message,
+            "ACTOTA: Your booking for Arkansas River Weekend on Jun 14, 2026 is confirmed (ref 000000AB). Reply STOP to opt out."
+        );
+
+        let long_name = "Grand Tour of Every Hot Spring, Ghost Town and Fourteener in the Colorado Rockies";
+        let message = booking_confirmation_sms(long_name, &booking());
+        assert_eq!(message.chars().count(), SMS_SEGMENT_CHARS);
+        assert!(message.contains("Grand Tour of Every Hot Spring"));
+        assert!(message.contains("... on Jun 14, 2026"));
+
+        let message = booking_confirmation_sms(&"Café ".repeat(80), &booking());
+        assert!(message.chars().count() <= SMS_SEGMENT_CHARS);
+        assert!(message.ends_with("Reply STOP to opt out."));
+    }
+
+    #[actix_rt::test]
+    async fn test_unconfigured_sms_is_a_no_op() {
+        let service = NotificationService { sms: None };
+        // Returns without attempting a request
+        service
+            .send_booking_confirmation_sms(&user(true, Some("+13035550142")), "Trip", &booking())
+            .await;
+    }
+}
@@ -0,0 +1,371 @@
+//! Linking an OAuth sign-in to an existing password account
+//!
+//! Signing in with Google or Facebook used to land silently in whichever account
+//! had the provider's email, so anyone controlling that address at the provider
+//! got the account. Now, when the email belongs to an account with a password
+//! that the provider id isn't linked to yet, the callback neither signs in nor
+//! creates an account. It stores a `PendingOAuthLink` in `Account.PendingOAuthLinks`
+//! for `LINK_TTL_MINUTES` and redirects with `status=account_exists_requires_link`
+//! and its token. The user then proves they own the account at
+//! `POST /auth/link-oauth`, with its password or a code sent to its email, and the
+//! provider is linked to it. A link takes `MAX_LINK_ATTEMPTS` wrong proofs, after
+//! which the user has to start over from the provider.
+
+use chrono::Utc;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::models::account::{LinkedAccount, User};
+use crate::services::account_service::{EmailError, EmailService, EmailVerification};
+
+/// How long the user has to prove they own the account
+pub const LINK_TTL_MINUTES: i64 = 10;
+
+/// Wrong passwords or codes a pending link takes
+pub const MAX_LINK_ATTEMPTS: u32 = 5;
+
+/// Emails compare trimmed and without regard to case
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// What an OAuth callback does with the account holding the provider's email
+#[derive(Debug, PartialEq)]
+pub enum OAuthSignin {
+    /// No account has the email
+    Create,
+    /// Sign in, linking the provider first when `link` is set. Accounts without a
+    /// password were made through OAuth, so the provider vouches for the email.
+    SignIn { link: bool },
+    /// A password account the provider isn't linked to; ownership must be proven
+    RequireLink,
+}
+
+pub fn oauth_signin(existing: Option<&User>, provider: &str, provider_id: &str) -> OAuthSignin {
+    match existing {
+        None => OAuthSignin::Create,
+        Some(user) if user.is_linked(provider, provider_id) => OAuthSignin::SignIn { link: false },
+        Some(user) if user.has_password() => OAuthSignin::RequireLink,
+        Some(_) => OAuthSignin::SignIn { link: true },
+    }
+}
+
+/// An OAuth sign-in waiting for the user to prove they own the account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingOAuthLink {
+    #[serde(rename = "_id")]
+    pub token: String,
+    pub user_id: ObjectId,
+    /// The account's email, normalized
+    pub email: String,
+    pub provider: String,
+    pub provider_id: String,
+    /// Wrong proofs so far
+    #[serde(default)]
+    pub attempts: u32,
+    pub created_at: DateTime,
+    /// When the link can no longer be completed (TTL)
+    pub expires_at: DateTime,
+}
+
+/// How the user proves they own the account
+#[derive(Debug, Clone)]
+pub enum LinkProof {
+    Password(String),
+    /// A code from `POST /email-verifications` for the account's email
+    EmailCode { verification_id: ObjectId, code: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OAuthLinkError {
+    /// Unknown, expired or already used
+    LinkNotFound,
+    InvalidProof { attempts_remaining: u32 },
+    TooManyAttempts,
+    UserNotFound,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for OAuthLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OAuthLinkError::LinkNotFound => {
+                write!(f, "This sign-in link has expired; sign in with the provider again")
+            }
+            OAuthLinkError::InvalidProof { attempts_remaining } => write!(
+                f,
+                "Incorrect password or code; {} attempts remaining",
+                attempts_remaining
+            ),
+            OAuthLinkError::TooManyAttempts => write!(
+                f,
+                "Too many incorrect attempts; sign in with the provider again"
+            ),
+            OAuthLinkError::UserNotFound => write!(f, "User not found"),
+            OAuthLinkError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OAuthLinkError {}
+
+impl From<mongodb::error::Error> for OAuthLinkError {
+    fn from(e: mongodb::error::Error) -> Self {
+        OAuthLinkError::DatabaseError(e.to_string())
+    }
+}
+
+fn minutes_after(time: DateTime, minutes: i64) -> DateTime {
+    DateTime::from_millis(time.timestamp_millis() + minutes * 60 * 1000)
+}
+
+pub struct OAuthLinkService {
+    client: Arc<Client>,
+}
+
+impl OAuthLinkService {
+    pub fn new(client: Arc<Client>) -> Self {
+        OAuthLinkService { client }
+    }
+
+    fn users(&self) -> Collection<User> {
+        self.client.database("Account").collection("Users")
+    }
+
+    fn links(&self) -> Collection<PendingOAuthLink> {
+        self.client.database("Account").collection("PendingOAuthLinks")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let ttl = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        self.links().create_index(ttl).await?;
+        Ok(())
+    }
+
+    /// The account with `email`, compared normalized. Older accounts may have
+    /// been stored with the email as typed.
+    pub async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, mongodb::error::Error> {
+        let pattern = format!("^{}$", regex::escape(&normalize_email(email)));
+        self.users()
+            .find_one(doc! { "email": { "$regex": pattern, "$options": "i" } })
+            .await
+    }
+
+    /// Link the provider to the account, unless it already is
+    pub async fn link_provider(
+        &self,
+        user_id: ObjectId,
+        provider: &str,
+        provider_id: &str,
+    ) -> Result<(), mongodb::error::Error> {
+        let linked = mongodb::bson::to_bson(&LinkedAccount {
+            provider: provider.to_string(),
+            provider_id: provider_id.to_string(),
+            linked_at: Utc::now(),
+        })?;
+        self.users()
+            .update_one(
+                doc! {
+                    "_id": user_id,
+                    "linked_accounts": {
+                        "$not": { "$elemMatch": { "provider": provider, "provider_id": provider_id } }
+                    }
+                },
+                doc! { "$push": { "linked_accounts": linked } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Hold the sign-in until the user proves they own `user`. Returns the token.
+    pub async fn start(
+        &self,
+        user: &User,
+        provider: &str,
+        provider_id: &str,
+        now: DateTime,
+    ) -> Result<String, OAuthLinkError> {
+        let user_id = user.id.ok_or(OAuthLinkError::UserNotFound)?;
+        let link = PendingOAuthLink {
+            token: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+            user_id,
+            email: normalize_email(&user.email),
+            provider: provider.to_string(),
+            provider_id: provider_id.to_string(),
+            attempts: 0,
+            created_at: now,
+            expires_at: minutes_after(now, LINK_TTL_MINUTES),
+        };
+        self.links().insert_one(&link).await?;
+        Ok(link.token)
+    }
+
+    /// The pending link for `token`, if it can still be completed
+    pub async fn pending(&self, token: &str, now: DateTime) -> Result<PendingOAuthLink, OAuthLinkError> {
+        match self.links().find_one(doc! { "_id": token }).await? {
+            // The TTL monitor runs once a minute, so expiry is checked here too
+            Some(link) if link.expires_at > now => {
+                if link.attempts >= MAX_LINK_ATTEMPTS {
+                    return Err(OAuthLinkError::TooManyAttempts);
+                }
+                Ok(link)
+            }
+            _ => Err(OAuthLinkError::LinkNotFound),
+        }
+    }
+
+    /// Link the provider if `proof` shows the user owns the account, and return the
+    /// account to sign in to. A wrong proof uses up one of the link's attempts.
+    pub async fn complete(
+        &self,
+        link: &PendingOAuthLink,
+        proof: &LinkProof,
+        max_code_attempts: u32,
+    ) -> Result<User, OAuthLinkError> {
+        let user = self
+            .users()
+            .find_one(doc! { "_id": link.user_id })
+            .await?
+            .ok_or(OAuthLinkError::UserNotFound)?;
+
+        if !self.proves_ownership(link, &user, proof, max_code_attempts).await? {
+            return Err(self.count_failure(link).await?);
+        }
+
+        // Deleting first means a token can't be completed twice
+        if self.links().find_one_and_delete(doc! { "_id": &link.token }).await?.is_none() {
+            return Err(OAuthLinkError::LinkNotFound);
+        }
+        self.link_provider(link.user_id, &link.provider, &link.provider_id).await?;
+        self.users()
+            .update_one(
+                doc! { "_id": link.user_id },
+                doc! { "$set": { "last_signin": Utc::now().to_string(), "failed_signins": 0 } },
+            )
+            .await?;
+        Ok(user)
+    }
+
+    async fn proves_ownership(
+        &self,
+        link: &PendingOAuthLink,
+        user: &User,
+        proof: &LinkProof,
+        max_code_attempts: u32,
+    ) -> Result<bool, OAuthLinkError> {
+        match proof {
+            LinkProof::Password(password) => Ok(!password.is_empty()
+                && user.has_password()
+                && bcrypt::verify(password, &user.password).unwrap_or(false)),
+            LinkProof::EmailCode { verification_id, code } => {
+                let verification = self
+                    .client
+                    .database("actota")
+                    .collection::<EmailVerification>("email_verifications")
+                    .find_one(doc! { "_id": verification_id })
+                    .await?;
+                // A code for some other address proves nothing about this account
+                let for_account = verification.is_some_and(|verification| {
+                    normalize_email(&verification.email) == link.email
+                        && verification.user_id.is_none_or(|id| id == link.user_id)
+                });
+                if !for_account {
+                    return Ok(false);
+                }
+                match EmailService::verify_email_code(*verification_id, code, max_code_attempts, &self.client)
+                    .await
+                {
+                    Ok(verified) => Ok(verified),
+                    Err(EmailError::DatabaseError(e)) => Err(OAuthLinkError::DatabaseError(e)),
+                    Err(_) => Ok(false),
+                }
+            }
+        }
+    }
+
+    /// Count a wrong proof, conditionally so concurrent guesses can't go past the cap
+    async fn count_failure(&self, link: &PendingOAuthLink) -> Result<OAuthLinkError, OAuthLinkError> {
+        let counted = self
+            .links()
+            .find_one_and_update(
+                doc! { "_id": &link.token, "attempts": { "$lt": MAX_LINK_ATTEMPTS } },
+                doc! { "$inc": { "attempts": 1 } },
+            )
+            .return_document(mongodb::options::ReturnDocument::After)
+            .await?;
+        Ok(match counted {
+            Some(link) => OAuthLinkError::InvalidProof {
+                attempts_remaining: MAX_LINK_ATTEMPTS.saturating_sub(link.attempts),
+            },
+            None => OAuthLinkError::TooManyAttempts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::account::UserRole;
+
+    fn user(password: &str, linked: &[(&str, &str)]) -> User {
+        let mut user = User {
+            id: Some(ObjectId::new()),
+            // Low cost, so the tests don't spend their time hashing
+            password: bcrypt::hash(password, 4).unwrap(),
+            first_name: Some("Sam".to_string()),
+            failed_signins: Some(0),
+            role: Some(UserRole::User),
+            ..User::test("traveler@example.com")
+        };
+        user.linked_accounts = linked
+            .iter()
+            .map(|(provider, provider_id)| LinkedAccount {
+                provider: provider.to_string(),
+                provider_id: provider_id.to_string(),
+                linked_at: Utc::now(),
+            })
+            .collect();
+        user
+    }
+
+    #[test]
+    fn test_password_account_with_the_providers_email_needs_linking() {
+        let existing = user("correct horse", &[]);
+        assert_eq!(oauth_signin(Some(&existing), "google", "g-1"), OAuthSignin::RequireLink);
+
+        // Another provider being linked doesn't vouch for this one
+        let existing = user("correct horse", &[("facebook", "f-1")]);
+        assert_eq!(oauth_signin(Some(&existing), "google", "g-1"), OAuthSignin::RequireLink);
+    }
+
+    #[test]
+    fn test_linked_and_oauth_only_accounts_sign_in() {
+        let linked = user("correct horse", &[("google", "g-1")]);
+        assert_eq!(oauth_signin(Some(&linked), "google", "g-1"), OAuthSignin::SignIn { link: false });
+
+        let oauth_only = user("", &[("facebook", "f-1")]);
+        assert!(!oauth_only.has_password());
+        assert_eq!(oauth_signin(Some(&oauth_only), "google", "g-1"), OAuthSignin::SignIn { link: true });
+
+        assert_eq!(oauth_signin(None, "google", "g-1"), OAuthSignin::Create);
+    }
+
+    #[test]
+    fn test_emails_are_normalized() {
+        assert_eq!(normalize_email("  Traveler@Example.COM "), "traveler@example.com");
+    }
+}
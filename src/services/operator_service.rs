@@ -0,0 +1,341 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::activity::Activity;
+use crate::models::bookings::BookingDetails;
+use crate::models::itinerary::base::{DayItem, FeaturedVacation};
+
+/// One of the operator's activities as it appears in a booked itinerary
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct OperatorBookedActivity {
+    pub activity_id: String,
+    pub title: String,
+    pub day: String,
+    pub time: String,
+}
+
+/// A confirmed booking as seen by an operator. Deliberately carries no payment data.
+#[derive(Debug, Serialize, Clone)]
+pub struct OperatorBooking {
+    pub booking_id: String,
+    pub arrival_datetime: DateTime,
+    pub departure_datetime: DateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub party_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub traveler_first_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub special_requests: Option<String>,
+    pub activities: Vec<OperatorBookedActivity>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OperatorActivitySummary {
+    pub activity_id: String,
+    pub title: String,
+    pub price_per_person: f32,
+    pub upcoming_bookings: usize,
+}
+
+/// The operator's activities scheduled in an itinerary, ordered by day then time
+fn operator_activities_in(
+    itinerary: &FeaturedVacation,
+    activities: &HashMap<ObjectId, Activity>,
+) -> Vec<OperatorBookedActivity> {
+    let mut matched: Vec<OperatorBookedActivity> = itinerary
+        .days
+        .days
+        .iter()
+        .flat_map(|(day, items)| items.iter().map(move |item| (day, item)))
+        .filter_map(|(day, item)| match item {
+            DayItem::Activity { time, activity_id } => {
+                activities.get(activity_id).map(|activity| OperatorBookedActivity {
+                    activity_id: activity_id.to_hex(),
+                    title: activity.title.clone(),
+                    day: day.clone(),
+                    time: time.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    matched.sort_by(|a, b| {
+        let day_a = a.day.parse::<u32>().unwrap_or(u32::MAX);
+        let day_b = b.day.parse::<u32>().unwrap_or(u32::MAX);
+        day_a.cmp(&day_b).then_with(|| a.time.cmp(&b.time))
+    });
+    matched
+}
+
+/// Keep only the bookings whose itinerary schedules at least one of the operator's activities.
+///
+/// `activities` must already be limited to the operator's company.
+pub fn operator_bookings(
+    bookings: &[BookingDetails],
+    itineraries: &HashMap<ObjectId, FeaturedVacation>,
+    activities: &HashMap<ObjectId, Activity>,
+    first_names: &HashMap<ObjectId, String>,
+) -> Vec<OperatorBooking> {
+    bookings
+        .iter()
+        .filter_map(|booking| {
+            let itinerary = itineraries.get(&booking.itinerary_id)?;
+            let booked = operator_activities_in(itinerary, activities);
+            if booked.is_empty() {
+                return None;
+            }
+            Some(OperatorBooking {
+                booking_id: booking.id.map(|id| id.to_hex()).unwrap_or_default(),
+                arrival_datetime: booking.arrival_datetime,
+                departure_datetime: booking.departure_datetime,
+                party_size: itinerary.party_size(),
+                traveler_first_name: first_names.get(&booking.user_id).cloned(),
+                special_requests: booking.special_requests.clone(),
+                activities: booked,
+            })
+        })
+        .collect()
+}
+
+/// Read-only views of bookings for tour operators, scoped to a single company.
+///
+/// Bookings, itineraries and activities live in different databases, so the join
+/// is done here rather than with a `$lookup`.
+pub struct OperatorService {
+    client: Arc<Client>,
+}
+
+impl OperatorService {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    async fn company_activities(
+        &self,
+        company_id: &str,
+    ) -> Result<HashMap<ObjectId, Activity>, mongodb::error::Error> {
+        let activities: Collection<Activity> =
+            self.client.database("Options").collection("Activity");
+        Ok(activities
+            .find(doc! { "company_id": company_id })
+            .await?
+            .try_collect::<Vec<Activity>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| activity.id.map(|id| (id, activity)))
+            .collect())
+    }
+
+    async fn confirmed_bookings(
+        &self,
+        filter: Document,
+    ) -> Result<Vec<BookingDetails>, mongodb::error::Error> {
+        let bookings: Collection<BookingDetails> =
+            primary_collection(&self.client, "Account", "Bookings");
+        let mut query = doc! { "status": { "$in": ["confirmed", "in_progress", "completed"] } };
+        query.extend(filter);
+        bookings
+            .find(query)
+            .sort(doc! { "arrival_datetime": 1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    async fn itineraries_for(
+        &self,
+        bookings: &[BookingDetails],
+    ) -> Result<HashMap<ObjectId, FeaturedVacation>, mongodb::error::Error> {
+        let ids: HashSet<ObjectId> = bookings.iter().map(|b| b.itinerary_id).collect();
+        let ids: Vec<ObjectId> = ids.into_iter().collect();
+        let itineraries: Collection<FeaturedVacation> =
+            self.client.database("Itineraries").collection("Featured");
+        Ok(itineraries
+            .find(doc! { "_id": { "$in": ids } })
+            .await?
+            .try_collect::<Vec<FeaturedVacation>>()
+            .await?
+            .into_iter()
+            .filter_map(|itinerary| itinerary.id.map(|id| (id, itinerary)))
+            .collect())
+    }
+
+    async fn first_names(
+        &self,
+        user_ids: Vec<ObjectId>,
+    ) -> Result<HashMap<ObjectId, String>, mongodb::error::Error> {
+        let users: Collection<Document> = self.client.database("Account").collection("Users");
+        let mut cursor = users
+            .find(doc! { "_id": { "$in": user_ids } })
+            .projection(doc! { "first_name": 1 })
+            .await?;
+
+        let mut names = HashMap::new();
+        while let Some(user) = cursor.try_next().await? {
+            if let (Ok(id), Ok(first_name)) = (user.get_object_id("_id"), user.get_str("first_name")) {
+                names.insert(id, first_name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Confirmed bookings that include at least one of the company's activities
+    pub async fn list_bookings(
+        &self,
+        company_id: &str,
+    ) -> Result<Vec<OperatorBooking>, mongodb::error::Error> {
+        let activities = self.company_activities(company_id).await?;
+        if activities.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let bookings = self.confirmed_bookings(doc! {}).await?;
+        let itineraries = self.itineraries_for(&bookings).await?;
+
+        let relevant: Vec<BookingDetails> = bookings
+            .into_iter()
+            .filter(|booking| {
+                itineraries
+                    .get(&booking.itinerary_id)
+                    .is_some_and(|itinerary| !operator_activities_in(itinerary, &activities).is_empty())
+            })
+            .collect();
+        let user_ids: HashSet<ObjectId> = relevant.iter().map(|b| b.user_id).collect();
+        let first_names = self.first_names(user_ids.into_iter().collect()).await?;
+
+        Ok(operator_bookings(&relevant, &itineraries, &activities, &first_names))
+    }
+
+    /// The company's activities with the number of upcoming confirmed bookings for each
+    pub async fn list_activities(
+        &self,
+        company_id: &str,
+    ) -> Result<Vec<OperatorActivitySummary>, mongodb::error::Error> {
+        let activities = self.company_activities(company_id).await?;
+        let bookings = self
+            .confirmed_bookings(doc! { "arrival_datetime": { "$gte": DateTime::now() } })
+            .await?;
+        let itineraries = self.itineraries_for(&bookings).await?;
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for booking in operator_bookings(&bookings, &itineraries, &activities, &HashMap::new()) {
+            let ids: HashSet<String> = booking.activities.into_iter().map(|a| a.activity_id).collect();
+            for id in ids {
+                *counts.entry(id).or_default() += 1;
+            }
+        }
+
+        let mut summaries: Vec<OperatorActivitySummary> = activities
+            .into_iter()
+            .map(|(id, activity)| OperatorActivitySummary {
+                upcoming_bookings: counts.get(&id.to_hex()).copied().unwrap_or(0),
+                activity_id: id.to_hex(),
+                title: activity.title,
+                price_per_person: activity.price_per_person,
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.title.cmp(&b.title));
+        Ok(summaries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bookings::PaymentStatus;
+    use crate::models::itinerary::base::Days;
+
+    fn activity(company_id: &str, title: &str) -> Activity {
+        Activity {
+            company: company_id.to_string(),
+            company_id: company_id.to_string(),
+            description: "".to_string(),
+            ..Activity::test(title)
+        }
+    }
+
+    fn itinerary_with(activity_ids: &[ObjectId]) -> FeaturedVacation {
+        let items = activity_ids
+            .iter()
+            .map(|id| DayItem::Activity {
+                time: "09:00:00".to_string(),
+                activity_id: *id,
+            })
+            .collect();
+        FeaturedVacation {
+            id: Some(ObjectId::new()),
+            days: Days {
+                days: HashMap::from([("1".to_string(), items)]),
+            },
+            adults: Some(2),
+            children: Some(1),
+            ..Default::default()
+        }
+    }
+
+    fn booking_for(itinerary: &FeaturedVacation, user_id: ObjectId) -> BookingDetails {
+        BookingDetails {
+            user_id,
+            itinerary_id: itinerary.id.unwrap(),
+            customer_id: Some("cus_123".to_string()),
+            transaction_id: Some("pi_123".to_string()),
+            gift_card_amount: Some(2_500),
+            ..BookingDetails::test(PaymentStatus::Confirmed, DateTime::now(), DateTime::now())
+        }
+    }
+
+    #[test]
+    fn test_operator_sees_only_own_company_bookings() {
+        let ours = activity("acme-tours", "Rafting");
+        let theirs = activity("other-co", "Zipline");
+        let our_trip = itinerary_with(&[ours.id.unwrap(), theirs.id.unwrap()]);
+        let their_trip = itinerary_with(&[theirs.id.unwrap()]);
+
+        let traveler = ObjectId::new();
+        let bookings = vec![
+            booking_for(&our_trip, traveler),
+            booking_for(&their_trip, ObjectId::new()),
+        ];
+        let itineraries = HashMap::from([
+            (our_trip.id.unwrap(), our_trip.clone()),
+            (their_trip.id.unwrap(), their_trip.clone()),
+        ]);
+        let company_activities = HashMap::from([(ours.id.unwrap(), ours.clone())]);
+        let first_names = HashMap::from([(traveler, "Jamie".to_string())]);
+
+        let visible = operator_bookings(&bookings, &itineraries, &company_activities, &first_names);
+
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].booking_id, bookings[0].id.unwrap().to_hex());
+        assert_eq!(visible[0].party_size, Some(3));
+        assert_eq!(visible[0].traveler_first_name.as_deref(), Some("Jamie"));
+        assert_eq!(visible[0].activities.len(), 1);
+        assert_eq!(visible[0].activities[0].title, "Rafting");
+    }
+
+    #[test]
+    fn test_operator_booking_omits_payment_fields() {
+        let ours = activity("acme-tours", "Rafting");
+        let trip = itinerary_with(&[ours.id.unwrap()]);
+        let bookings = vec![booking_for(&trip, ObjectId::new())];
+        let itineraries = HashMap::from([(trip.id.unwrap(), trip.clone())]);
+        let company_activities = HashMap::from([(ours.id.unwrap(), ours)]);
+
+        let visible = operator_bookings(&bookings, &itineraries, &company_activities, &HashMap::new());
+        let json = serde_json::to_value(&visible[0]).unwrap();
+        let fields = json.as_object().unwrap();
+
+        for field in ["transaction_id", "customer_id", "gift_card_amount", "gift_card_redemption_id", "status"] {
+            assert!(!fields.contains_key(field), "{} should not be exposed", field);
+        }
+        assert!(fields.contains_key("arrival_datetime"));
+    }
+}
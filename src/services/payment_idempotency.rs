@@ -0,0 +1,240 @@
+//! Idempotency keys for payment intent creation
+//!
+//! A double-submitted checkout form used to create two PaymentIntents, and the one
+//! never captured left a pending authorization on the traveler's card. Checkout now
+//! sends an `Idempotency-Key` header (or `idempotency_key` in the body). The key is
+//! passed on to Stripe, and the intent it created is kept in
+//! `Account.PaymentIntentKeys` for `KEY_TTL_HOURS`, so a repeat is answered from
+//! there without calling Stripe at all.
+//!
+//! A key belongs to the user who sent it first: the same key from anyone else is
+//! refused until it expires.
+
+use mongodb::{
+    bson::{doc, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::services::webhook_replay::is_duplicate_key;
+
+/// How long a key answers with the intent it created. Stripe keeps its own
+/// idempotency keys for as long.
+pub const KEY_TTL_HOURS: i64 = 24;
+const MIN_KEY_LENGTH: usize = 16;
+const MAX_KEY_LENGTH: usize = 64;
+
+/// Keys are UUID-like: letters, digits, `-` and `_`, between 16 and 64 characters
+pub fn is_valid_key(key: &str) -> bool {
+    (MIN_KEY_LENGTH..=MAX_KEY_LENGTH).contains(&key.len())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Creates payment intents on Stripe
+pub trait IntentCreator {
+    /// Create the intent, sending `idempotency_key` with the request when given
+    fn create_intent(
+        &self,
+        params: stripe::CreatePaymentIntent<'_>,
+        idempotency_key: Option<&str>,
+    ) -> impl Future<Output = Result<stripe::PaymentIntent, String>>;
+}
+
+impl IntentCreator for stripe::Client {
+    async fn create_intent(
+        &self,
+        params: stripe::CreatePaymentIntent<'_>,
+        idempotency_key: Option<&str>,
+    ) -> Result<stripe::PaymentIntent, String> {
+        let result = match idempotency_key {
+            Some(key) => {
+                let client = self
+                    .clone()
+                    .with_strategy(stripe::RequestStrategy::Idempotent(key.to_string()));
+                stripe::PaymentIntent::create(&client, params).await
+            }
+            None => stripe::PaymentIntent::create(self, params).await,
+        };
+        result.map_err(|e| e.to_string())
+    }
+}
+
+/// The intent a key created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentIntentKey {
+    #[serde(rename = "_id")]
+    pub key: String,
+    pub user_id: String,
+    pub payment_intent_id: String,
+    /// Amount asked for, before any gift card. A repeat must ask for the same.
+    pub amount: i64,
+    /// The intent as first returned, client secret included
+    pub intent: String,
+    pub created_at: DateTime,
+    /// When the key stops answering with this intent (TTL)
+    pub expires_at: DateTime,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IdempotencyError {
+    InvalidKey,
+    /// Another user sent the key first
+    KeyInUse,
+    /// The key was first sent with a different amount
+    AmountMismatch,
+    StripeError(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for IdempotencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IdempotencyError::InvalidKey => write!(
+                f,
+                "Idempotency keys are {} to {} letters, digits, '-' or '_'",
+                MIN_KEY_LENGTH, MAX_KEY_LENGTH
+            ),
+            IdempotencyError::KeyInUse => write!(f, "This idempotency key is already in use"),
+            IdempotencyError::AmountMismatch => {
+                write!(f, "This idempotency key was used with a different amount")
+            }
+            IdempotencyError::StripeError(e) => write!(f, "Failed to create payment intent: {}", e),
+            IdempotencyError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IdempotencyError {}
+
+impl From<mongodb::error::Error> for IdempotencyError {
+    fn from(e: mongodb::error::Error) -> Self {
+        IdempotencyError::DatabaseError(e.to_string())
+    }
+}
+
+/// The key Stripe sees, so users can't collide on Stripe's side either
+fn stripe_key(user_id: &str, key: &str) -> String {
+    format!("{}:{}", user_id, key)
+}
+
+fn hours_after(time: DateTime, hours: i64) -> DateTime {
+    DateTime::from_millis(time.timestamp_millis() + hours * 60 * 60 * 1000)
+}
+
+pub struct PaymentIdempotencyService {
+    client: Arc<Client>,
+}
+
+impl PaymentIdempotencyService {
+    pub fn new(client: Arc<Client>) -> Self {
+        PaymentIdempotencyService { client }
+    }
+
+    fn collection(&self) -> Collection<PaymentIntentKey> {
+        self.client.database("Account").collection("PaymentIntentKeys")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let ttl = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        self.collection().create_index(ttl).await?;
+        Ok(())
+    }
+
+    /// The intent `key` already created for `user_id`, if it hasn't expired. A key
+    /// past its expiry that the TTL monitor hasn't removed yet is removed here.
+    pub async fn replay(
+        &self,
+        user_id: &str,
+        key: &str,
+        amount: i64,
+        now: DateTime,
+    ) -> Result<Option<serde_json::Value>, IdempotencyError> {
+        if !is_valid_key(key) {
+            return Err(IdempotencyError::InvalidKey);
+        }
+        let Some(stored) = self.collection().find_one(doc! { "_id": key }).await? else {
+            return Ok(None);
+        };
+        if stored.expires_at <= now {
+            self.collection()
+                .delete_one(doc! { "_id": key, "expires_at": { "$lte": now } })
+                .await?;
+            return Ok(None);
+        }
+        if stored.user_id != user_id {
+            return Err(IdempotencyError::KeyInUse);
+        }
+        if stored.amount != amount {
+            return Err(IdempotencyError::AmountMismatch);
+        }
+        serde_json::from_str(&stored.intent)
+            .map(Some)
+            .map_err(|e| IdempotencyError::DatabaseError(e.to_string()))
+    }
+
+    /// Create the intent on Stripe and, with a key, remember it. A request racing
+    /// this one with the same key gets the same intent from Stripe, and whichever
+    /// stores it second answers with the stored one.
+    pub async fn create(
+        &self,
+        creator: &impl IntentCreator,
+        user_id: &str,
+        key: Option<&str>,
+        amount: i64,
+        params: stripe::CreatePaymentIntent<'_>,
+        now: DateTime,
+    ) -> Result<serde_json::Value, IdempotencyError> {
+        let Some(key) = key else {
+            let intent = creator
+                .create_intent(params, None)
+                .await
+                .map_err(IdempotencyError::StripeError)?;
+            return serde_json::to_value(&intent).map_err(|e| IdempotencyError::StripeError(e.to_string()));
+        };
+
+        let intent = creator
+            .create_intent(params, Some(&stripe_key(user_id, key)))
+            .await
+            .map_err(IdempotencyError::StripeError)?;
+        let body = serde_json::to_value(&intent).map_err(|e| IdempotencyError::StripeError(e.to_string()))?;
+        let record = PaymentIntentKey {
+            key: key.to_string(),
+            user_id: user_id.to_string(),
+            payment_intent_id: intent.id.to_string(),
+            amount,
+            intent: body.to_string(),
+            created_at: now,
+            expires_at: hours_after(now, KEY_TTL_HOURS),
+        };
+        match self.collection().insert_one(&record).await {
+            Ok(_) => Ok(body),
+            Err(e) if is_duplicate_key(&e) => self
+                .replay(user_id, key, amount, now)
+                .await?
+                .ok_or(IdempotencyError::KeyInUse),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_must_look_like_uuids() {
+        assert!(is_valid_key("3f1c2b9e-8d4a-4c7e-9f00-1a2b3c4d5e6f"));
+        assert!(is_valid_key("checkout_2026_10_16_a"));
+        assert!(!is_valid_key("short-key"));
+        assert!(!is_valid_key(&"a".repeat(MAX_KEY_LENGTH + 1)));
+        assert!(!is_valid_key("3f1c2b9e 8d4a 4c7e 9f00"));
+        assert!(!is_valid_key("3f1c2b9e-8d4a-4c7e-9f00-1a2b3c4d5e6f\n"));
+    }
+}
@@ -0,0 +1,391 @@
+//! Stripe cleanup when an account is deleted
+//!
+//! The user's saved cards are detached, the customer's default payment method is
+//! cleared, and the Stripe customer is then deleted or, with
+//! `STRIPE_CUSTOMER_ON_DELETE=mark`, kept for bookkeeping and tagged as belonging
+//! to a deleted account. Every step is safe to repeat.
+//!
+//! Stripe being down never blocks a deletion. A teardown that fails is queued in
+//! `Account.PaymentTeardowns` and a background job retries it with backoff until it
+//! goes through or runs out of attempts.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often queued teardowns are retried
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Failed attempts before a teardown is left for someone to look at
+const MAX_ATTEMPTS: u32 = 8;
+/// Wait after the first failure, doubled after each one after that
+const FIRST_BACKOFF_MINUTES: i64 = 15;
+
+/// What happens to the Stripe customer once its cards are detached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomerDisposition {
+    #[default]
+    Delete,
+    /// Keep the customer, and its payment history, tagged with `account_deleted`
+    Mark,
+}
+
+impl FromStr for CustomerDisposition {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "delete" => Ok(CustomerDisposition::Delete),
+            "mark" => Ok(CustomerDisposition::Mark),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The Stripe calls a teardown makes. A customer that no longer exists counts as
+/// done rather than as an error.
+pub trait CustomerVault {
+    /// Ids of the payment methods attached to the customer
+    fn payment_methods(&self, customer_id: &str) -> impl Future<Output = Result<Vec<String>, String>>;
+
+    fn detach(&self, payment_method_id: &str) -> impl Future<Output = Result<(), String>>;
+
+    /// Clear the default payment method and, when `mark`, tag the customer as deleted
+    fn clear_default(&self, customer_id: &str, mark: bool) -> impl Future<Output = Result<(), String>>;
+
+    fn delete_customer(&self, customer_id: &str) -> impl Future<Output = Result<(), String>>;
+}
+
+fn is_missing(e: &stripe::StripeError) -> bool {
+    matches!(e, stripe::StripeError::Stripe(request) if request.http_status == 404)
+}
+
+impl CustomerVault for stripe::Client {
+    async fn payment_methods(&self, customer_id: &str) -> Result<Vec<String>, String> {
+        let customer = stripe::CustomerId::from_str(customer_id).map_err(|e| e.to_string())?;
+        let mut ids = Vec::new();
+        let mut params = stripe::ListPaymentMethods {
+            customer: Some(customer),
+            limit: Some(100),
+            ..Default::default()
+        };
+        loop {
+            let page = match stripe::PaymentMethod::list(self, &params).await {
+                Ok(page) => page,
+                Err(e) if is_missing(&e) => return Ok(ids),
+                Err(e) => return Err(e.to_string()),
+            };
+            ids.extend(page.data.iter().map(|method| method.id.to_string()));
+            match page.data.last() {
+                Some(last) if page.has_more => params.starting_after = Some(last.id.clone()),
+                _ => return Ok(ids),
+            }
+        }
+    }
+
+    async fn detach(&self, payment_method_id: &str) -> Result<(), String> {
+        let id = stripe::PaymentMethodId::from_str(payment_method_id).map_err(|e| e.to_string())?;
+        match stripe::PaymentMethod::detach(self, &id).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_missing(&e) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn clear_default(&self, customer_id: &str, mark: bool) -> Result<(), String> {
+        let id = stripe::CustomerId::from_str(customer_id).map_err(|e| e.to_string())?;
+        let params = stripe::UpdateCustomer {
+            // An empty string unsets it
+            invoice_settings: Some(stripe::CustomerInvoiceSettings {
+                default_payment_method: Some(String::new()),
+                ..Default::default()
+            }),
+            metadata: mark.then(|| {
+                stripe::Metadata::from([
+                    ("account_deleted".to_string(), "true".to_string()),
+                    ("account_deleted_at".to_string(), chrono::Utc::now().to_rfc3339()),
+                ])
+            }),
+            ..Default::default()
+        };
+        match stripe::Customer::update(self, &id, params).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_missing(&e) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn delete_customer(&self, customer_id: &str) -> Result<(), String> {
+        let id = stripe::CustomerId::from_str(customer_id).map_err(|e| e.to_string())?;
+        match stripe::Customer::delete(self, &id).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_missing(&e) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Detach every card, clear the default and dispose of the customer. Returns how
+/// many cards were detached.
+pub async fn tear_down(
+    vault: &impl CustomerVault,
+    customer_id: &str,
+    disposition: CustomerDisposition,
+) -> Result<usize, String> {
+    let methods = vault.payment_methods(customer_id).await?;
+    for method in &methods {
+        vault.detach(method).await?;
+    }
+    vault
+        .clear_default(customer_id, disposition == CustomerDisposition::Mark)
+        .await?;
+    if disposition == CustomerDisposition::Delete {
+        vault.delete_customer(customer_id).await?;
+    }
+    Ok(methods.len())
+}
+
+/// A teardown waiting to be retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTeardown {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub customer_id: String,
+    pub disposition: CustomerDisposition,
+    pub attempts: u32,
+    pub last_error: String,
+    /// Unset once the attempts run out
+    pub next_attempt_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+/// What happened to a deleted account's Stripe customer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TeardownOutcome {
+    NoCustomer,
+    Completed { payment_methods_detached: usize },
+    /// Stripe failed; the teardown is queued for retry
+    Queued { error: String },
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct RetrySummary {
+    pub completed: u32,
+    pub failed: u32,
+    /// Teardowns that used their last attempt
+    pub abandoned: u32,
+}
+
+fn next_attempt(attempts: u32, now: DateTime) -> Option<DateTime> {
+    (attempts < MAX_ATTEMPTS).then(|| {
+        let minutes = FIRST_BACKOFF_MINUTES << (attempts.saturating_sub(1)).min(10);
+        DateTime::from_millis(now.timestamp_millis() + minutes * 60 * 1000)
+    })
+}
+
+pub struct PaymentTeardownService {
+    queue: Collection<PendingTeardown>,
+}
+
+impl PaymentTeardownService {
+    pub fn new(client: Arc<Client>) -> Self {
+        PaymentTeardownService {
+            queue: client.database("Account").collection("PaymentTeardowns"),
+        }
+    }
+
+    /// Tear down the Stripe side of a deleted account, queueing it for retry when
+    /// Stripe fails. Only a failure to queue is returned as an error.
+    pub async fn close(
+        &self,
+        vault: &impl CustomerVault,
+        user_id: ObjectId,
+        customer_id: Option<&str>,
+        disposition: CustomerDisposition,
+    ) -> Result<TeardownOutcome, mongodb::error::Error> {
+        let Some(customer_id) = customer_id.filter(|id| !id.is_empty()) else {
+            return Ok(TeardownOutcome::NoCustomer);
+        };
+        match tear_down(vault, customer_id, disposition).await {
+            Ok(detached) => Ok(TeardownOutcome::Completed {
+                payment_methods_detached: detached,
+            }),
+            Err(error) => {
+                eprintln!(
+                    "Stripe teardown for customer {} failed, queueing a retry: {}",
+                    customer_id, error
+                );
+                let now = DateTime::now();
+                self.queue
+                    .insert_one(PendingTeardown {
+                        id: None,
+                        user_id,
+                        customer_id: customer_id.to_string(),
+                        disposition,
+                        attempts: 1,
+                        last_error: error.clone(),
+                        next_attempt_at: next_attempt(1, now),
+                        created_at: now,
+                    })
+                    .await?;
+                Ok(TeardownOutcome::Queued { error })
+            }
+        }
+    }
+
+    /// Retry the queued teardowns that are due
+    pub async fn retry_due(
+        &self,
+        vault: &impl CustomerVault,
+        now: DateTime,
+    ) -> Result<RetrySummary, mongodb::error::Error> {
+        let due: Vec<PendingTeardown> = self
+            .queue
+            .find(doc! { "next_attempt_at": { "$lte": now } })
+            .await?
+            .try_collect()
+            .await?;
+        let mut summary = RetrySummary::default();
+        for pending in due {
+            let Some(id) = pending.id else { continue };
+            match tear_down(vault, &pending.customer_id, pending.disposition).await {
+                Ok(_) => {
+                    self.queue.delete_one(doc! { "_id": id }).await?;
+                    summary.completed += 1;
+                }
+                Err(error) => {
+                    let attempts = pending.attempts + 1;
+                    let next_attempt_at = next_attempt(attempts, now);
+                    if next_attempt_at.is_none() {
+                        eprintln!(
+                            "Giving up on Stripe teardown for customer {} after {} attempts: {}",
+                            pending.customer_id, attempts, error
+                        );
+                        summary.abandoned += 1;
+                    }
+                    self.queue
+                        .update_one(
+                            doc! { "_id": id },
+                            doc! { "$set": {
+                                "attempts": attempts,
+                                "last_error": error,
+                                "next_attempt_at": next_attempt_at,
+                            } },
+                        )
+                        .await?;
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    pub fn start(self, vault: Arc<stripe::Client>, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                match self.retry_due(vault.as_ref(), DateTime::now()).await {
+                    Ok(summary) if summary == RetrySummary::default() => {}
+                    Ok(summary) => println!("💳 Stripe teardown retries finished: {:?}", summary),
+                    Err(e) => eprintln!("Stripe teardown retries failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A Stripe customer with cards, recording each call
+    struct MockCustomer {
+        methods: Vec<String>,
+        failing_detach: Option<&'static str>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl MockCustomer {
+        fn new(methods: &[&str]) -> Self {
+            MockCustomer {
+                methods: methods.iter().map(|id| id.to_string()).collect(),
+                failing_detach: None,
+                calls: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl CustomerVault for MockCustomer {
+        async fn payment_methods(&self, customer_id: &str) -> Result<Vec<String>, String> {
+            self.calls.lock().unwrap().push(format!("list {}", customer_id));
+            Ok(self.methods.clone())
+        }
+
+        async fn detach(&self, payment_method_id: &str) -> Result<(), String> {
+            if self.failing_detach == Some(payment_method_id) {
+                return Err("Stripe is unavailable".to_string());
+            }
+            self.calls.lock().unwrap().push(format!("detach {}", payment_method_id));
+            Ok(())
+        }
+
+        async fn clear_default(&self, customer_id: &str, mark: bool) -> Result<(), String> {
+            let call = if mark { "clear and mark" } else { "clear" };
+            self.calls.lock().unwrap().push(format!("{} {}", call, customer_id));
+            Ok(())
+        }
+
+        async fn delete_customer(&self, customer_id: &str) -> Result<(), String> {
+            self.calls.lock().unwrap().push(format!("delete {}", customer_id));
+            Ok(())
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_tear_down_detaches_every_card_first() {
+        let customer = MockCustomer::new(&["pm_1", "pm_2"]);
+        assert_eq!(tear_down(&customer, "cus_1", CustomerDisposition::Delete).await, Ok(2));
+        assert_eq!(
+            customer.calls(),
+            ["list cus_1", "detach pm_1", "detach pm_2", "clear cus_1", "delete cus_1"]
+        );
+
+        let customer = MockCustomer::new(&["pm_1"]);
+        tear_down(&customer, "cus_1", CustomerDisposition::Mark).await.unwrap();
+        assert_eq!(customer.calls(), ["list cus_1", "detach pm_1", "clear and mark cus_1"]);
+
+        // A failure stops before the customer is touched, so a retry starts clean
+        let mut customer = MockCustomer::new(&["pm_1", "pm_2"]);
+        customer.failing_detach = Some("pm_2");
+        assert!(tear_down(&customer, "cus_1", CustomerDisposition::Delete).await.is_err());
+        assert_eq!(customer.calls(), ["list cus_1", "detach pm_1"]);
+    }
+
+    #[test]
+    fn test_retries_back_off_then_stop() {
+        let now = DateTime::from_millis(0);
+        let minutes = |attempts| next_attempt(attempts, now).map(|at| at.timestamp_millis() / 60_000);
+        assert_eq!(minutes(1), Some(15));
+        assert_eq!(minutes(2), Some(30));
+        assert_eq!(minutes(3), Some(60));
+        assert_eq!(minutes(MAX_ATTEMPTS), None);
+
+        assert_eq!(" Mark".parse(), Ok(CustomerDisposition::Mark));
+        assert!("archive".parse::<CustomerDisposition>().is_err());
+    }
+}
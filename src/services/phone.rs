@@ -0,0 +1,81 @@
+//! Phone number normalization for profile updates
+//!
+//! Numbers are stored twice: `phone_number` keeps what the user typed, for display,
+//! and `phone_number_e164` holds the canonical form that SMS and lookups use.
+
+use phonenumber::{country, Mode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedPhone {
+    /// Canonical form, e.g. `+13035550142`
+    pub e164: String,
+    /// The number as the user entered it, trimmed
+    pub display: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PhoneError {
+    Unparseable,
+    Invalid,
+}
+
+impl std::fmt::Display for PhoneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PhoneError::Unparseable => write!(f, "Phone number could not be read"),
+            PhoneError::Invalid => write!(f, "Phone number is not a valid number"),
+        }
+    }
+}
+
+impl std::error::Error for PhoneError {}
+
+/// Parse `input` into E.164. Numbers without a `+` country code are read as
+/// belonging to `default_region`.
+pub fn normalize_phone(input: &str, default_region: country::Id) -> Result<NormalizedPhone, PhoneError> {
+    let display = input.trim();
+    let number = phonenumber::parse(Some(default_region), display).map_err(|_| PhoneError::Unparseable)?;
+    if !number.is_valid() {
+        return Err(PhoneError::Invalid);
+    }
+
+    Ok(NormalizedPhone {
+        e164: number.format().mode(Mode::E164).to_string(),
+        display: display.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_us_numbers_in_common_formats() {
+        for input in ["(303) 555-0142", "303.555.0142", " 303-555-0142 ", "+1 303 555 0142"] {
+            let phone = normalize_phone(input, country::Id::US).unwrap();
+            assert_eq!(phone.e164, "+13035550142", "{}", input);
+            assert_eq!(phone.display, input.trim());
+        }
+    }
+
+    #[test]
+    fn test_international_and_default_region() {
+        let uk = normalize_phone("+44 20 7946 0958", country::Id::US).unwrap();
+        assert_eq!(uk.e164, "+442079460958");
+
+        // A national number is read in the configured default region
+        let uk = normalize_phone("020 7946 0958", country::Id::GB).unwrap();
+        assert_eq!(uk.e164, "+442079460958");
+
+        let de = normalize_phone("+49 30 901820", country::Id::US).unwrap();
+        assert_eq!(de.e164, "+4930901820");
+    }
+
+    #[test]
+    fn test_invalid_numbers_are_rejected() {
+        assert_eq!(normalize_phone("not a number", country::Id::US), Err(PhoneError::Unparseable));
+        assert!(normalize_phone("", country::Id::US).is_err());
+        assert_eq!(normalize_phone("12345", country::Id::US), Err(PhoneError::Invalid));
+        assert!(normalize_phone("+1 000 000 0000", country::Id::US).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+//! Price-drop alerts for favorited itineraries
+//!
+//! A background job compares each favorite's `last_notified_price` with the
+//! itinerary's current `person_cost` and emails the user when the price has fallen
+//! by at least `PRICE_ALERT_MIN_DROP_PERCENT`. The baseline only moves when an
+//! alert is handled, so small wobbles never alert but a slow slide does once it
+//! adds up past the threshold. The job runs in every instance, so an alert is
+//! claimed by moving the baseline before the email goes out.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::models::account::{Favorite, User};
+use crate::models::money::Money;
+use crate::services::account_service::{EmailService, EmailSettings};
+use crate::services::pricing_service::PersonPrice;
+
+#[derive(Debug, PartialEq)]
+pub enum PriceCheck {
+    /// The favorite has no baseline yet (it predates alerts, or the itinerary wasn't priced)
+    SetBaseline(Money),
+    Unchanged,
+    Dropped { from: Money, to: Money },
+}
+
+/// Compare a favorite's baseline with the itinerary's current price
+pub fn check_price(baseline: Option<Money>, current: Option<Money>, min_drop_percent: u32) -> PriceCheck {
+    // A zero price is a missing one, not a trip that became free
+    let Some(current) = PersonPrice::stored(current).amount() else {
+        return PriceCheck::Unchanged;
+    };
+    let Some(baseline) = PersonPrice::stored(baseline).amount() else {
+        return PriceCheck::SetBaseline(current);
+    };
+
+    let drop = baseline - current;
+    if drop > Money::ZERO && drop.cents() * 100 >= baseline.cents() * min_drop_percent as i64 {
+        PriceCheck::Dropped { from: baseline, to: current }
+    } else {
+        PriceCheck::Unchanged
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PriceAlertSummary {
+    pub checked: usize,
+    pub baselines_set: usize,
+    pub alerts_sent: usize,
+    /// Drops for users with price alerts turned off
+    pub opted_out: usize,
+    pub failed: usize,
+}
+
+struct ItineraryPrice {
+    trip_name: String,
+    person_cost: Option<Money>,
+}
+
+pub struct PriceAlertJob {
+    client: Arc<Client>,
+    min_drop_percent: u32,
+    email: EmailSettings,
+}
+
+impl PriceAlertJob {
+    pub fn new(client: Arc<Client>, min_drop_percent: u32) -> Self {
+        PriceAlertJob {
+            client,
+            min_drop_percent,
+            email: EmailSettings::default(),
+        }
+    }
+
+    /// Where price alerts are emailed from. Without it none are sent.
+    pub fn with_email(mut self, settings: &EmailSettings) -> Self {
+        self.email = settings.clone();
+        self
+    }
+
+    /// Check for price drops every `interval`, starting one interval from now
+    pub fn start(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                match self.run().await {
+                    Ok(summary) => println!("🔔 Price alert check finished: {:?}", summary),
+                    Err(e) => eprintln!("Price alert check failed: {}", e),
+                }
+            }
+        });
+    }
+
+    fn favorites(&self) -> Collection<Favorite> {
+        self.client.database("Account").collection("Favorites")
+    }
+
+    /// One pass over every favorite
+    pub async fn run(&self) -> Result<PriceAlertSummary, mongodb::error::Error> {
+        let prices = self.current_prices().await?;
+        let email_service = match EmailService::new(self.client.clone(), &self.email) {
+            Ok(service) => Some(service),
+            Err(e) => {
+                println!("Price alerts won't be emailed this run: {}", e);
+                None
+            }
+        };
+
+        let mut summary = PriceAlertSummary::default();
+        let mut users: HashMap<ObjectId, Option<User>> = HashMap::new();
+        let mut cursor = self.favorites().find(doc! {}).await?;
+        while let Some(favorite) = cursor.try_next().await? {
+            summary.checked += 1;
+            let Some(favorite_id) = favorite.id else {
+                continue;
+            };
+            let Some(itinerary) = prices.get(&favorite.itinerary_id) else {
+                continue;
+            };
+
+            let (from, to) = match check_price(favorite.last_notified_price, itinerary.person_cost, self.min_drop_percent) {
+                PriceCheck::Unchanged => continue,
+                PriceCheck::SetBaseline(price) => {
+                    if self.set_baseline(favorite_id, price).await {
+                        summary.baselines_set += 1;
+                    } else {
+                        summary.failed += 1;
+                    }
+                    continue;
+                }
+                PriceCheck::Dropped { from, to } => (from, to),
+            };
+
+            if let Entry::Vacant(entry) = users.entry(favorite.user_id) {
+                entry.insert(self.find_user(favorite.user_id).await);
+            }
+            let Some(user) = users.get(&favorite.user_id).and_then(Option::as_ref) else {
+                continue;
+            };
+
+            if !user.effective_notification_preferences().email.price_alerts {
+                // Move the baseline anyway, so turning alerts back on doesn't
+                // announce an old drop
+                summary.opted_out += 1;
+                self.set_baseline(favorite_id, to).await;
+                continue;
+            }
+            let Some(email_service) = &email_service else {
+                continue;
+            };
+
+            // Every instance runs this job; only the one that moves the baseline sends
+            match self.move_baseline(favorite_id, from, to).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    eprintln!("Failed to claim price alert for favorite {}: {}", favorite_id, e);
+                    summary.failed += 1;
+                    continue;
+                }
+            }
+            match email_service
+                .send_price_drop_email(&user.email, favorite.itinerary_id, &itinerary.trip_name, from, to)
+                .await
+            {
+                Ok(()) => {
+                    println!("   🔔 '{}' {} -> {}, alerted user {}", itinerary.trip_name, from, to, favorite.user_id);
+                    summary.alerts_sent += 1;
+                }
+                Err(e) => {
+                    // Put the baseline back, so the next run retries
+                    eprintln!("Failed to send price drop email: {}", e);
+                    summary.failed += 1;
+                    if let Err(e) = self.move_baseline(favorite_id, to, from).await {
+                        eprintln!("Failed to restore price baseline for favorite {}: {}", favorite_id, e);
+                    }
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Name and current price of every favorited itinerary
+    async fn current_prices(&self) -> Result<HashMap<ObjectId, ItineraryPrice>, mongodb::error::Error> {
+        let itinerary_ids = self.favorites().distinct("itinerary_id", doc! {}).await?;
+        let itineraries: Collection<Document> = self.client.database("Itineraries").collection("Featured");
+        let documents: Vec<Document> = itineraries
+            .find(doc! { "_id": { "$in": itinerary_ids } })
+            .projection(doc! { "trip_name": 1, "person_cost": 1 })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(documents
+            .iter()
+            .filter_map(|document| {
+                let id = document.get_object_id("_id").ok()?;
+                let person_cost = document
+                    .get("person_cost")
+                    .and_then(|cost| mongodb::bson::from_bson::<Money>(cost.clone()).ok());
+                let trip_name = document.get_str("trip_name").unwrap_or("Your favorite trip").to_string();
+                Some((id, ItineraryPrice { trip_name, person_cost }))
+            })
+            .collect())
+    }
+
+    async fn find_user(&self, user_id: ObjectId) -> Option<User> {
+        let users: Collection<User> = self.client.database("Account").collection("Users");
+        match users.find_one(doc! { "_id": user_id }).await {
+            Ok(user) => user,
+            Err(e) => {
+                eprintln!("Failed to load user {} for price alert: {}", user_id, e);
+                None
+            }
+        }
+    }
+
+    /// Move the baseline from `from` to `to`, only if it's still `from`. `false` when
+    /// another run got there first.
+    async fn move_baseline(
+        &self,
+        favorite_id: ObjectId,
+        from: Money,
+        to: Money,
+    ) -> Result<bool, mongodb::error::Error> {
+        let update = doc! {
+            "$set": {
+                "last_notified_price": to.to_dollars(),
+                "updated_at": mongodb::bson::to_bson(&chrono::Utc::now()).unwrap(),
+            }
+        };
+        let result = self
+            .favorites()
+            .update_one(doc! { "_id": favorite_id, "last_notified_price": from.to_dollars() }, update)
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    async fn set_baseline(&self, favorite_id: ObjectId, price: Money) -> bool {
+        let update = doc! {
+            "$set": {
+                "last_notified_price": price.to_dollars(),
+                "updated_at": mongodb::bson::to_bson(&chrono::Utc::now()).unwrap(),
+            }
+        };
+        match self.favorites().update_one(doc! { "_id": favorite_id }, update).await {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("Failed to update price baseline for favorite {}: {}", favorite_id, e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dollars(amount: f64) -> Option<Money> {
+        Some(Money::from_dollars(amount))
+    }
+
+    #[test]
+    fn test_small_drops_and_rises_dont_alert() {
+        assert_eq!(check_price(dollars(200.0), dollars(195.0), 5), PriceCheck::Unchanged);
+        assert_eq!(check_price(dollars(200.0), dollars(250.0), 5), PriceCheck::Unchanged);
+        assert_eq!(check_price(dollars(200.0), dollars(200.0), 0), PriceCheck::Unchanged);
+        assert_eq!(
+            check_price(dollars(200.0), dollars(190.0), 5),
+            PriceCheck::Dropped {
+                from: Money::from_cents(20_000),
+                to: Money::from_cents(19_000),
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_prices() {
+        // Favorites made before alerts existed get a baseline instead of an alert
+        assert_eq!(check_price(None, dollars(150.0), 5), PriceCheck::SetBaseline(Money::from_cents(15_000)));
+        assert_eq!(check_price(dollars(150.0), None, 5), PriceCheck::Unchanged);
+        assert_eq!(check_price(None, None, 5), PriceCheck::Unchanged);
+        assert_eq!(check_price(dollars(150.0), dollars(0.0), 5), PriceCheck::Unchanged);
+        assert_eq!(check_price(dollars(0.0), dollars(150.0), 5), PriceCheck::SetBaseline(Money::from_cents(15_000)));
+    }
+}
@@ -1,37 +1,175 @@
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client,
+};
+
+use crate::db::mongo::read_only_collection;
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::{DayItem, FeaturedVacation};
 use crate::models::itinerary::populated::{PopulatedDayItem, PopulatedFeaturedVacation};
+use crate::models::money::Money;
+
+/// An itinerary's per-person price, as far as it can be known.
+///
+/// A missing or zero stored `person_cost` never reads as free: it's estimated
+/// from the scheduled activities, and when that isn't possible the price is
+/// unavailable. Anything that shows, sorts by or charges a price goes through this.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PersonPrice {
+    Stored(Money),
+    Estimated(Money),
+    Unavailable,
+}
+
+impl PersonPrice {
+    /// The stored price alone, for callers without the itinerary's activities
+    pub fn stored(person_cost: Option<Money>) -> Self {
+        match person_cost {
+            Some(cost) if cost > Money::ZERO => PersonPrice::Stored(cost),
+            _ => PersonPrice::Unavailable,
+        }
+    }
+
+    /// The stored price, else an estimate from `activities` (keyed by id)
+    pub fn resolve(
+        person_cost: Option<Money>,
+        days: &HashMap<String, Vec<DayItem>>,
+        activities: &HashMap<ObjectId, Activity>,
+    ) -> Self {
+        match Self::stored(person_cost) {
+            PersonPrice::Unavailable => PricingService::estimate_cost(days, activities)
+                .map_or(PersonPrice::Unavailable, PersonPrice::Estimated),
+            stored => stored,
+        }
+    }
+
+    pub fn amount(self) -> Option<Money> {
+        match self {
+            PersonPrice::Stored(cost) | PersonPrice::Estimated(cost) => Some(cost),
+            PersonPrice::Unavailable => None,
+        }
+    }
+
+    pub fn is_estimated(self) -> bool {
+        matches!(self, PersonPrice::Estimated(_))
+    }
+}
 
 pub struct PricingService;
 
 impl PricingService {
+    /// Travelers a booking of `itinerary` is charged for: its party, or its
+    /// minimum group when it doesn't record one
+    pub fn travelers(itinerary: &FeaturedVacation) -> u32 {
+        itinerary.party_size().unwrap_or(itinerary.min_group).max(1)
+    }
+
+    /// Per-person cost of the activities scheduled in `days`, priced from `activities`
+    pub fn calculate_cost(days: &HashMap<String, Vec<DayItem>>, activities: &[Activity]) -> Money {
+        let activity_costs: HashMap<ObjectId, Money> = activities
+            .iter()
+            .filter_map(|a| a.id.map(|id| (id, a.price())))
+            .collect();
+
+        let mut total_cost = Money::ZERO;
+        for day_items in days.values() {
+            for item in day_items {
+                if let DayItem::Activity { activity_id, .. } = item {
+                    if let Some(cost) = activity_costs.get(activity_id) {
+                        total_cost += *cost;
+                    }
+                }
+            }
+        }
+        total_cost
+    }
+
+    /// Per-person cost of `days` when every scheduled activity is in `activities`.
+    /// `None` if any is missing or nothing is scheduled, so a partial sum is
+    /// never passed off as the price.
+    pub fn estimate_cost(
+        days: &HashMap<String, Vec<DayItem>>,
+        activities: &HashMap<ObjectId, Activity>,
+    ) -> Option<Money> {
+        let scheduled: Vec<Activity> = days
+            .values()
+            .flatten()
+            .filter_map(|item| match item {
+                DayItem::Activity { activity_id, .. } => Some(activities.get(activity_id).cloned()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        let cost = Self::calculate_cost(days, &scheduled);
+        (cost > Money::ZERO).then_some(cost)
+    }
+
+    /// `itinerary`'s price, looking up its activities only when there's no stored price
+    pub async fn person_price(
+        client: &Client,
+        itinerary: &FeaturedVacation,
+    ) -> Result<PersonPrice, mongodb::error::Error> {
+        if let stored @ PersonPrice::Stored(_) = PersonPrice::stored(itinerary.person_cost) {
+            return Ok(stored);
+        }
+
+        let activity_ids: Vec<ObjectId> = itinerary
+            .days
+            .days
+            .values()
+            .flatten()
+            .filter_map(|item| match item {
+                DayItem::Activity { activity_id, .. } => Some(*activity_id),
+                _ => None,
+            })
+            .collect();
+        let activities: Vec<Activity> =
+            read_only_collection::<Activity>(client, "Options", "Activity")
+                .find(doc! { "_id": { "$in": activity_ids } })
+                .await?
+                .try_collect()
+                .await?;
+        let activities = activities
+            .into_iter()
+            .filter_map(|activity| activity.id.map(|id| (id, activity)))
+            .collect();
+
+        Ok(PersonPrice::resolve(itinerary.person_cost, &itinerary.days.days, &activities))
+    }
+
     /// Calculate service fee (5% of total with minimum $50)
-    pub fn calculate_service_fee(total_cost: f32) -> f32 {
-        let fee = total_cost * 0.05;
-        fee.max(50.0)
+    pub fn calculate_service_fee(total_cost: Money) -> Money {
+        total_cost.percent(5).max(Money::from_cents(5_000))
     }
 
     /// Calculate total activity costs from populated days
-    pub fn calculate_activity_cost(itinerary: &PopulatedFeaturedVacation) -> f32 {
+    pub fn calculate_activity_cost(itinerary: &PopulatedFeaturedVacation) -> Money {
         itinerary
             .populated_days
             .values()
             .flatten()
             .filter_map(|item| match item {
-                PopulatedDayItem::Activity { activity, .. } => Some(activity.price_per_person),
+                PopulatedDayItem::Activity { activity, .. } => {
+                    Some(Money::from_dollars(activity.price_per_person as f64))
+                }
                 _ => None,
             })
             .sum()
     }
 
     /// Calculate total lodging costs from populated days
-    pub fn calculate_lodging_cost(itinerary: &PopulatedFeaturedVacation) -> f32 {
+    pub fn calculate_lodging_cost(itinerary: &PopulatedFeaturedVacation) -> Money {
         itinerary
             .populated_days
             .values()
             .flatten()
             .filter_map(|item| match item {
-                PopulatedDayItem::Accommodation { accommodation, .. } => {
-                    accommodation.price_per_night
-                }
+                PopulatedDayItem::Accommodation { accommodation, .. } => accommodation
+                    .price_per_night
+                    .map(|price| Money::from_dollars(price as f64)),
                 _ => None,
             })
             .sum()
@@ -39,18 +177,16 @@ impl PricingService {
 
     /// Calculate total transportation costs from populated days
     /// Note: Transportation items don't currently have cost fields in the model
-    pub fn calculate_transport_cost(_itinerary: &PopulatedFeaturedVacation) -> f32 {
+    pub fn calculate_transport_cost(_itinerary: &PopulatedFeaturedVacation) -> Money {
         // TODO: Add cost fields to transportation items when the model is updated
-        0.0
+        Money::ZERO
     }
 
     /// Calculate total person cost (activity + lodging + transport, excluding service fee)
-    pub fn calculate_person_cost(itinerary: &PopulatedFeaturedVacation) -> f32 {
-        let activity_cost = Self::calculate_activity_cost(itinerary);
-        let lodging_cost = Self::calculate_lodging_cost(itinerary);
-        let transport_cost = Self::calculate_transport_cost(itinerary);
-        
-        activity_cost + lodging_cost + transport_cost
+    pub fn calculate_person_cost(itinerary: &PopulatedFeaturedVacation) -> Money {
+        Self::calculate_activity_cost(itinerary)
+            + Self::calculate_lodging_cost(itinerary)
+            + Self::calculate_transport_cost(itinerary)
     }
 }
 
@@ -61,12 +197,70 @@ mod tests {
     #[test]
     fn test_service_fee_calculation() {
         // Test 5% calculation
-        assert_eq!(PricingService::calculate_service_fee(1000.0), 50.0);
-        assert_eq!(PricingService::calculate_service_fee(2000.0), 100.0);
-        
+        assert_eq!(PricingService::calculate_service_fee(Money::from_cents(100_000)), Money::from_cents(5_000));
+        assert_eq!(PricingService::calculate_service_fee(Money::from_cents(200_000)), Money::from_cents(10_000));
+        assert_eq!(PricingService::calculate_service_fee(Money::from_cents(123_456)), Money::from_cents(6_172));
+
         // Test minimum fee
-        assert_eq!(PricingService::calculate_service_fee(100.0), 50.0);
-        assert_eq!(PricingService::calculate_service_fee(0.0), 50.0);
+        assert_eq!(PricingService::calculate_service_fee(Money::from_cents(10_000)), Money::from_cents(5_000));
+        assert_eq!(PricingService::calculate_service_fee(Money::ZERO), Money::from_cents(5_000));
+    }
+
+    fn priced(title: &str, price: f32) -> Activity {
+        serde_json::from_value(serde_json::json!({
+            "_id": ObjectId::new(),
+            "company": "Rocky Mountain Adventures",
+            "company_id": "rma",
+            "booking_link": "",
+            "online_booking_status": "available",
+            "title": title,
+            "description": "",
+            "activity_types": [],
+            "tags": [],
+            "price_per_person": price,
+            "duration_minutes": 120,
+            "daily_time_slots": [],
+            "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+            "whats_included": [],
+            "capacity": { "minimum": 1, "maximum": 10 },
+        }))
+        .unwrap()
+    }
+
+    fn scheduled(activity_ids: &[ObjectId]) -> HashMap<String, Vec<DayItem>> {
+        let items = activity_ids
+            .iter()
+            .map(|id| DayItem::Activity {
+                time: "09:00:00".to_string(),
+                activity_id: *id,
+            })
+            .collect();
+        HashMap::from([("1".to_string(), items)])
+    }
+
+    #[test]
+    fn test_missing_or_zero_prices_are_estimated_never_free() {
+        let rafting = priced("Rafting", 89.0);
+        let zipline = priced("Zipline", 60.0);
+        let days = scheduled(&[rafting.id.unwrap(), zipline.id.unwrap()]);
+        let activities: HashMap<ObjectId, Activity> = [rafting, zipline]
+            .into_iter()
+            .map(|a| (a.id.unwrap(), a))
+            .collect();
+
+        let stored = Money::from_dollars(200.0);
+        assert_eq!(PersonPrice::resolve(Some(stored), &days, &activities), PersonPrice::Stored(stored));
+        for person_cost in [None, Some(Money::ZERO)] {
+            let price = PersonPrice::resolve(person_cost, &days, &activities);
+            assert_eq!(price, PersonPrice::Estimated(Money::from_dollars(149.0)));
+            assert!(price.is_estimated());
+        }
+
+        // An activity we can't price would understate the total
+        let with_unknown = scheduled(&[activities.keys().next().copied().unwrap(), ObjectId::new()]);
+        assert_eq!(PersonPrice::resolve(None, &with_unknown, &activities), PersonPrice::Unavailable);
+        assert_eq!(PersonPrice::resolve(None, &HashMap::new(), &activities), PersonPrice::Unavailable);
+        assert_eq!(PersonPrice::stored(Some(Money::ZERO)).amount(), None);
     }
 
     #[test]
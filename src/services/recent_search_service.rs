@@ -0,0 +1,265 @@
+//! A signed-in traveler's recent searches, offered back as "search again" actions.
+//!
+//! Searches are keyed by a fingerprint of what was asked for, so repeating a search
+//! moves its entry to the top instead of adding another one.
+
+use chrono::{Datelike, NaiveDate};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::models::search::SearchItinerary;
+use crate::services::itinerary_generation_service::ItineraryGenerator;
+
+/// Entries returned when the request doesn't ask for a number
+pub const DEFAULT_RECENT_SEARCHES: i64 = 5;
+/// Most entries returned by one request
+pub const MAX_RECENT_SEARCHES: i64 = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentSearch {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub fingerprint: String,
+    pub summary: String,
+    /// The search as last sent, run again as-is
+    pub search: SearchItinerary,
+    pub search_count: u32,
+    pub last_searched_at: DateTime,
+}
+
+fn search_date(datetime: &Option<String>) -> Option<NaiveDate> {
+    datetime
+        .as_deref()
+        .and_then(|datetime| ItineraryGenerator::parse_datetime(datetime).ok())
+        .map(|datetime| datetime.date())
+}
+
+/// Trimmed, lowercased, de-duplicated and sorted, so order and case don't matter
+fn normalized(values: &Option<Vec<String>>) -> Vec<String> {
+    let mut values: Vec<String> = values
+        .iter()
+        .flatten()
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .collect();
+    values.sort();
+    values.dedup();
+    values
+}
+
+/// Stable key for what a search asks for: its locations, activities, party and
+/// travel days. Searches differing only in ordering, case or time of day share a
+/// fingerprint; presentation options such as the response version are ignored.
+pub fn search_fingerprint(search: &SearchItinerary) -> String {
+    let day = |datetime: &Option<String>| search_date(datetime).map(|date| date.to_string()).unwrap_or_default();
+    let key = format!(
+        "{}|{}|{}/{}/{}|{}|{}",
+        normalized(&search.locations).join(","),
+        normalized(&search.activities).join(","),
+        search.adults.unwrap_or(1),
+        search.children.unwrap_or(0),
+        search.infants.unwrap_or(0),
+        day(&search.arrival_datetime),
+        day(&search.departure_datetime),
+    );
+
+    // 64-bit FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn count(n: u32, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
+
+/// One line describing a search, e.g. "Denver · 2 adults · Hiking, Rafting · Jul 12–15".
+/// Parts the search left out are skipped.
+pub fn search_summary(search: &SearchItinerary) -> String {
+    let mut parts = Vec::new();
+
+    let locations: Vec<&str> = search
+        .locations
+        .iter()
+        .flatten()
+        .map(|location| location.trim())
+        .filter(|location| !location.is_empty())
+        .collect();
+    if !locations.is_empty() {
+        parts.push(locations.join(" → "));
+    }
+
+    let mut party = vec![count(search.adults.unwrap_or(1), "adult", "adults")];
+    match search.children.unwrap_or(0) {
+        0 => {}
+        n => party.push(count(n, "child", "children")),
+    }
+    match search.infants.unwrap_or(0) {
+        0 => {}
+        n => party.push(count(n, "infant", "infants")),
+    }
+    parts.push(party.join(", "));
+
+    let activities: Vec<&str> = search
+        .activities
+        .iter()
+        .flatten()
+        .map(|activity| activity.trim())
+        .filter(|activity| !activity.is_empty())
+        .collect();
+    if !activities.is_empty() {
+        parts.push(activities.join(", "));
+    }
+
+    match (search_date(&search.arrival_datetime), search_date(&search.departure_datetime)) {
+        (Some(arrival), Some(departure)) if arrival.month() == departure.month() && arrival.year() == departure.year() => {
+            parts.push(format!("{}–{}", arrival.format("%b %-d"), departure.day()))
+        }
+        (Some(arrival), Some(departure)) => {
+            parts.push(format!("{}–{}", arrival.format("%b %-d"), departure.format("%b %-d")))
+        }
+        (Some(arrival), None) => parts.push(format!("From {}", arrival.format("%b %-d"))),
+        (None, Some(departure)) => parts.push(format!("Until {}", departure.format("%b %-d"))),
+        (None, None) => {}
+    }
+
+    parts.join(" · ")
+}
+
+pub struct RecentSearchService {
+    client: Arc<Client>,
+}
+
+impl RecentSearchService {
+    pub fn new(client: Arc<Client>) -> Self {
+        RecentSearchService { client }
+    }
+
+    fn collection(&self) -> Collection<RecentSearch> {
+        self.client.database("Account").collection("RecentSearches")
+    }
+
+    /// Remember `search` for `user_id`, moving an earlier identical search to the top
+    pub async fn record(
+        &self,
+        user_id: ObjectId,
+        search: &SearchItinerary,
+        now: DateTime,
+    ) -> Result<(), mongodb::error::Error> {
+        let search_doc = mongodb::bson::to_document(search)?;
+        self.collection()
+            .update_one(
+                doc! { "user_id": user_id, "fingerprint": search_fingerprint(search) },
+                doc! {
+                    "$set": {
+                        "summary": search_summary(search),
+                        "search": search_doc,
+                        "last_searched_at": now,
+                    },
+                    "$inc": { "search_count": 1 },
+                },
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    /// The user's `limit` most recent distinct searches, newest first
+    pub async fn recent(
+        &self,
+        user_id: ObjectId,
+        limit: i64,
+    ) -> Result<Vec<RecentSearch>, mongodb::error::Error> {
+        self.collection()
+            .find(doc! { "user_id": user_id })
+            .sort(doc! { "last_searched_at": -1 })
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    pub async fn find(
+        &self,
+        user_id: ObjectId,
+        fingerprint: &str,
+    ) -> Result<Option<RecentSearch>, mongodb::error::Error> {
+        self.collection()
+            .find_one(doc! { "user_id": user_id, "fingerprint": fingerprint })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(value: serde_json::Value) -> SearchItinerary {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_ordering_case_and_time_of_day() {
+        let first = search(serde_json::json!({
+            "locations": ["Denver, CO", "Boulder, CO"],
+            "activities": ["Hiking", "Rafting"],
+            "adults": 2,
+            "arrival_datetime": "2026-07-12T09:00:00",
+            "departure_datetime": "2026-07-15",
+        }));
+        let reordered = search(serde_json::json!({
+            "locations": ["boulder, co", " Denver, CO"],
+            "activities": ["rafting", "Hiking"],
+            "adults": 2,
+            "arrival_datetime": "2026-07-12 18:30:00",
+            "departure_datetime": "2026-07-15T10:00:00",
+            "response_version": 2,
+        }));
+        assert_eq!(search_fingerprint(&first), search_fingerprint(&reordered));
+
+        let more_people = search(serde_json::json!({
+            "locations": ["Denver, CO", "Boulder, CO"],
+            "activities": ["Hiking", "Rafting"],
+            "adults": 3,
+            "arrival_datetime": "2026-07-12T09:00:00",
+            "departure_datetime": "2026-07-15",
+        }));
+        assert_ne!(search_fingerprint(&first), search_fingerprint(&more_people));
+    }
+
+    #[test]
+    fn test_summary_names_the_trip_and_skips_what_is_missing() {
+        let full = search(serde_json::json!({
+            "locations": ["Denver"],
+            "activities": ["Hiking", "Rafting"],
+            "adults": 2,
+            "arrival_datetime": "2026-07-12T09:00:00",
+            "departure_datetime": "2026-07-15T09:00:00",
+        }));
+        assert_eq!(search_summary(&full), "Denver · 2 adults · Hiking, Rafting · Jul 12–15");
+
+        let across_months = search(serde_json::json!({
+            "locations": ["Moab"],
+            "adults": 1,
+            "children": 2,
+            "arrival_datetime": "2026-07-30",
+            "departure_datetime": "2026-08-02",
+        }));
+        assert_eq!(search_summary(&across_months), "Moab · 1 adult, 2 children · Jul 30–Aug 2");
+
+        let no_dates = search(serde_json::json!({
+            "locations": ["Denver"],
+            "activities": ["Hiking"],
+        }));
+        assert_eq!(search_summary(&no_dates), "Denver · 1 adult · Hiking");
+    }
+}
@@ -0,0 +1,115 @@
+//! Itineraries a signed-in traveler has opened, for "pick up where you left off".
+//!
+//! Each itinerary is kept once per user with when it was last viewed, so opening
+//! it again moves it to the top. Only the newest `MAX_STORED_VIEWS` are kept per
+//! user; older ones are dropped as new views come in.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Itineraries returned when the request doesn't ask for a number
+pub const DEFAULT_RECENTLY_VIEWED: i64 = 10;
+/// Most itineraries returned by one request
+pub const MAX_RECENTLY_VIEWED: i64 = 20;
+/// Views kept per user
+pub const MAX_STORED_VIEWS: u64 = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentlyViewed {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub itinerary_id: ObjectId,
+    pub view_count: u32,
+    pub viewed_at: DateTime,
+}
+
+pub struct RecentlyViewedService {
+    client: Arc<Client>,
+}
+
+impl RecentlyViewedService {
+    pub fn new(client: Arc<Client>) -> Self {
+        RecentlyViewedService { client }
+    }
+
+    fn collection(&self) -> Collection<RecentlyViewed> {
+        self.client.database("Account").collection("RecentlyViewed")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let per_itinerary = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "itinerary_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let newest_first = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "viewed_at": -1 })
+            .build();
+        self.collection().create_indexes([per_itinerary, newest_first]).await?;
+        Ok(())
+    }
+
+    /// Remember that `user_id` viewed `itinerary_id`, then drop views past the cap
+    pub async fn record(
+        &self,
+        user_id: ObjectId,
+        itinerary_id: ObjectId,
+        now: DateTime,
+    ) -> Result<(), mongodb::error::Error> {
+        self.collection()
+            .update_one(
+                doc! { "user_id": user_id, "itinerary_id": itinerary_id },
+                doc! { "$set": { "viewed_at": now }, "$inc": { "view_count": 1 } },
+            )
+            .upsert(true)
+            .await?;
+        self.trim(user_id).await
+    }
+
+    /// Record the view without holding up the response. Failures are only logged.
+    pub fn record_in_background(&self, user_id: ObjectId, itinerary_id: ObjectId) {
+        let service = RecentlyViewedService::new(self.client.clone());
+        tokio::spawn(async move {
+            if let Err(e) = service.record(user_id, itinerary_id, DateTime::now()).await {
+                eprintln!("Failed to record itinerary view: {:?}", e);
+            }
+        });
+    }
+
+    /// Keep only the user's newest `MAX_STORED_VIEWS`
+    async fn trim(&self, user_id: ObjectId) -> Result<(), mongodb::error::Error> {
+        let oldest_kept = self
+            .collection()
+            .find_one(doc! { "user_id": user_id })
+            .sort(doc! { "viewed_at": -1 })
+            .skip(MAX_STORED_VIEWS - 1)
+            .await?;
+        if let Some(oldest_kept) = oldest_kept {
+            self.collection()
+                .delete_many(doc! { "user_id": user_id, "viewed_at": { "$lt": oldest_kept.viewed_at } })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The user's `limit` most recently viewed itineraries, newest first
+    pub async fn recent(
+        &self,
+        user_id: ObjectId,
+        limit: i64,
+    ) -> Result<Vec<RecentlyViewed>, mongodb::error::Error> {
+        self.collection()
+            .find(doc! { "user_id": user_id })
+            .sort(doc! { "viewed_at": -1 })
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await
+    }
+}
@@ -0,0 +1,603 @@
+//! Seats held while a traveler checks out
+//!
+//! `POST /payment/reserve` holds the seats a trip needs for
+//! `RESERVATION_HOLD_MINUTES`, so nobody can take them between choosing the trip
+//! and paying. Each hold is pushed onto the `Options.ActivityBookings` record it
+//! draws from with a conditional update that only matches while booked seats plus
+//! unexpired holds leave room, so two travelers racing for the last seats can't
+//! both get them. A hold stops counting the moment it expires; the
+//! `Account.Reservations` record itself is removed by a TTL index.
+//!
+//! Confirming the booking turns the hold into booked seats in one update.
+//! Reserving the same trip and start date again extends the user's reservation
+//! rather than holding the seats twice.
+
+use chrono::NaiveDate;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::mongo::primary_collection;
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::FeaturedVacation;
+use crate::services::availability_service::{
+    activity_dates, held_seats, scheduled_activity_ids, ActivityBookingCount, AvailabilityCache,
+    SeatHold,
+};
+use crate::services::calendar;
+use crate::services::unit_of_work::UnitOfWork;
+use crate::services::webhook_replay::is_duplicate_key;
+
+pub const DEFAULT_HOLD_MINUTES: u64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationStatus {
+    Active,
+    /// Became a confirmed booking
+    Converted,
+    /// Given up with `DELETE /payment/reserve/{id}`, or replaced by one for a different party size
+    Released,
+    /// Lapsed before a new reservation for the same trip and dates was made
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reservation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub itinerary_id: ObjectId,
+    pub start_date: NaiveDate,
+    pub seats: u32,
+    pub status: ReservationStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub booking_id: Option<ObjectId>,
+    pub created_at: DateTime,
+    /// When the seats are released (TTL)
+    pub expires_at: DateTime,
+}
+
+impl Reservation {
+    pub fn is_active(&self, now: DateTime) -> bool {
+        self.status == ReservationStatus::Active && now < self.expires_at
+    }
+}
+
+#[derive(Debug)]
+pub enum ReservationError {
+    ItineraryNotFound,
+    InvalidPartySize,
+    /// An activity on the trip doesn't run on one of its dates
+    NotOperating,
+    SoldOut,
+    NotFound,
+    /// The reservation lapsed, was released, or was already used for a booking
+    Expired,
+    /// The reservation is for a different itinerary or start date
+    DoesNotMatch,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ReservationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReservationError::ItineraryNotFound => write!(f, "Itinerary not found"),
+            ReservationError::InvalidPartySize => write!(f, "party_size must be at least 1"),
+            ReservationError::NotOperating => {
+                write!(f, "This trip doesn't run on the dates requested")
+            }
+            ReservationError::SoldOut => {
+                write!(f, "There aren't enough seats left on the dates requested")
+            }
+            ReservationError::NotFound => write!(f, "Reservation not found"),
+            ReservationError::Expired => write!(f, "The reservation has expired"),
+            ReservationError::DoesNotMatch => {
+                write!(f, "The reservation is for a different trip or dates")
+            }
+            ReservationError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ReservationError {}
+
+impl From<mongodb::error::Error> for ReservationError {
+    fn from(err: mongodb::error::Error) -> Self {
+        ReservationError::DatabaseError(err.to_string())
+    }
+}
+
+/// Seats a party of `seats` takes per activity and date on a trip starting on
+/// `start`. An activity scheduled twice on a day takes the seats twice, as
+/// `AvailabilityService::record_confirmed_booking` counts it.
+pub fn seats_needed(
+    itinerary: &FeaturedVacation,
+    start: NaiveDate,
+    seats: u32,
+) -> BTreeMap<(NaiveDate, ObjectId), u32> {
+    let mut needed = BTreeMap::new();
+    for (activity_id, date) in activity_dates(itinerary, start) {
+        *needed.entry((date, activity_id)).or_insert(0) += seats;
+    }
+    needed
+}
+
+/// Whether `seats` more fit on an activity with `maximum` seats
+pub fn has_room(maximum: u32, booked: u32, holds: &[SeatHold], seats: u32, now: DateTime) -> bool {
+    booked + held_seats(holds, now) + seats <= maximum
+}
+
+/// `$expr` matching inventory records with room for `seats` more, the database's
+/// side of `has_room`
+fn room_for(seats: u32, maximum: u32, now: DateTime) -> Document {
+    let held = doc! {
+        "$sum": {
+            "$map": {
+                "input": {
+                    "$filter": {
+                        "input": { "$ifNull": ["$holds", []] },
+                        "as": "hold",
+                        "cond": { "$gt": ["$$hold.expires_at", now] },
+                    }
+                },
+                "as": "hold",
+                "in": "$$hold.seats",
+            }
+        }
+    };
+    doc! {
+        "$lte": [
+            { "$add": [{ "$ifNull": ["$booked", 0] }, held, seats] },
+            maximum,
+        ]
+    }
+}
+
+fn minutes_after(time: DateTime, minutes: u64) -> DateTime {
+    DateTime::from_millis(time.timestamp_millis() + minutes as i64 * 60 * 1000)
+}
+
+pub struct ReservationService {
+    client: Arc<Client>,
+}
+
+impl ReservationService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ReservationService { client }
+    }
+
+    fn reservations(&self) -> Collection<Reservation> {
+        primary_collection(&self.client, "Account", "Reservations")
+    }
+
+    fn inventory(&self) -> Collection<ActivityBookingCount> {
+        primary_collection(&self.client, "Options", "ActivityBookings")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let ttl = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        // One active reservation per user, trip and start date
+        let one_active = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "itinerary_id": 1, "start_date": 1 })
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { "status": "active" })
+                    .build(),
+            )
+            .build();
+        self.reservations().create_indexes([ttl, one_active]).await?;
+        Ok(())
+    }
+
+    async fn load_itinerary(&self, itinerary_id: ObjectId) -> Result<FeaturedVacation, ReservationError> {
+        self.client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": itinerary_id })
+            .await?
+            .ok_or(ReservationError::ItineraryNotFound)
+    }
+
+    async fn load_activities(
+        &self,
+        itinerary: &FeaturedVacation,
+    ) -> Result<HashMap<ObjectId, Activity>, mongodb::error::Error> {
+        Ok(self
+            .client
+            .database("Options")
+            .collection::<Activity>("Activity")
+            .find(doc! { "_id": { "$in": scheduled_activity_ids(itinerary) } })
+            .await?
+            .try_collect::<Vec<Activity>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| activity.id.map(|id| (id, activity)))
+            .collect())
+    }
+
+    /// Hold seats for `seats` travelers on `itinerary_id` starting on `start`, or
+    /// extend the user's reservation for the same trip and date
+    pub async fn reserve(
+        &self,
+        cache: &AvailabilityCache,
+        user_id: ObjectId,
+        itinerary_id: ObjectId,
+        start: NaiveDate,
+        seats: u32,
+        hold_minutes: u64,
+    ) -> Result<Reservation, ReservationError> {
+        if seats == 0 {
+            return Err(ReservationError::InvalidPartySize);
+        }
+        let itinerary = self.load_itinerary(itinerary_id).await?;
+        let activities = self.load_activities(&itinerary).await?;
+        let needed = seats_needed(&itinerary, start, seats);
+        if needed.keys().any(|(date, activity_id)| {
+            activities
+                .get(activity_id)
+                .is_some_and(|activity| calendar::closure_reason(activity, *date).is_some())
+        }) {
+            return Err(ReservationError::NotOperating);
+        }
+
+        let now = DateTime::now();
+        let expires_at = minutes_after(now, hold_minutes);
+        let same_trip = doc! {
+            "user_id": user_id,
+            "itinerary_id": itinerary_id,
+            "start_date": start.to_string(),
+            "status": "active",
+        };
+        // A lapsed reservation no longer holds anything; make way for a new one
+        let mut lapsed = same_trip.clone();
+        lapsed.insert("expires_at", doc! { "$lte": now });
+        self.reservations()
+            .update_many(lapsed, doc! { "$set": { "status": "expired" } })
+            .await?;
+
+        if let Some(existing) = self.reservations().find_one(same_trip.clone()).await? {
+            if existing.seats == seats {
+                return self.extend(existing, expires_at).await;
+            }
+            // A different party size is held afresh
+            self.release_holds(cache, &existing, ReservationStatus::Released).await?;
+        }
+
+        let reservation = Reservation {
+            id: ObjectId::new(),
+            user_id,
+            itinerary_id,
+            start_date: start,
+            seats,
+            status: ReservationStatus::Active,
+            booking_id: None,
+            created_at: now,
+            expires_at,
+        };
+        if let Err(e) = self.reservations().insert_one(&reservation).await {
+            if !is_duplicate_key(&e) {
+                return Err(e.into());
+            }
+            // Another request for the same trip got there first
+            let existing = self
+                .reservations()
+                .find_one(same_trip)
+                .await?
+                .ok_or(ReservationError::Expired)?;
+            return self.extend(existing, expires_at).await;
+        }
+
+        if let Err(e) = self.take_holds(&reservation, &needed, &activities, now).await {
+            self.release_holds(cache, &reservation, ReservationStatus::Released).await?;
+            return Err(e);
+        }
+        cache.invalidate_activities(&scheduled_activity_ids(&itinerary));
+        println!(
+            "🪑 Reservation {} holds {} seats on {} from {}",
+            reservation.id, seats, itinerary.trip_name, start
+        );
+        Ok(reservation)
+    }
+
+    async fn take_holds(
+        &self,
+        reservation: &Reservation,
+        needed: &BTreeMap<(NaiveDate, ObjectId), u32>,
+        activities: &HashMap<ObjectId, Activity>,
+        now: DateTime,
+    ) -> Result<(), ReservationError> {
+        for ((date, activity_id), seats) in needed {
+            // Activities we don't know about aren't tracked, as in the availability view
+            let Some(activity) = activities.get(activity_id) else {
+                continue;
+            };
+            let record = doc! { "activity_id": activity_id, "date": date.to_string() };
+            self.inventory()
+                .update_one(
+                    record.clone(),
+                    doc! {
+                        "$setOnInsert": { "booked": 0 },
+                        "$pull": { "holds": { "expires_at": { "$lte": now } } },
+                    },
+                )
+                .upsert(true)
+                .await?;
+
+            let hold = SeatHold {
+                reservation_id: reservation.id,
+                seats: *seats,
+                expires_at: reservation.expires_at,
+            };
+            let mut with_room = record;
+            with_room.insert("$expr", room_for(*seats, activity.capacity.maximum as u32, now));
+            let result = self
+                .inventory()
+                .update_one(
+                    with_room,
+                    doc! { "$push": { "holds": mongodb::bson::to_bson(&hold).unwrap() } },
+                )
+                .await?;
+            if result.modified_count == 0 {
+                return Err(ReservationError::SoldOut);
+            }
+        }
+        Ok(())
+    }
+
+    /// Push back the expiry of an active reservation and its holds
+    async fn extend(&self, reservation: Reservation, expires_at: DateTime) -> Result<Reservation, ReservationError> {
+        let now = DateTime::now();
+        let result = self
+            .reservations()
+            .update_one(
+                doc! { "_id": reservation.id, "status": "active", "expires_at": { "$gt": now } },
+                doc! { "$set": { "expires_at": expires_at } },
+            )
+            .await?;
+        if result.matched_count == 0 {
+            return Err(ReservationError::Expired);
+        }
+        self.inventory()
+            .update_many(
+                doc! { "holds.reservation_id": reservation.id },
+                doc! { "$set": { "holds.$[hold].expires_at": expires_at } },
+            )
+            .array_filters(vec![doc! {
+                "hold.reservation_id": reservation.id,
+                "hold.expires_at": { "$gt": now },
+            }])
+            .await?;
+        println!("🪑 Reservation {} extended", reservation.id);
+        Ok(Reservation {
+            expires_at,
+            ..reservation
+        })
+    }
+
+    /// Give up the seats and mark the reservation `status`
+    async fn release_holds(
+        &self,
+        cache: &AvailabilityCache,
+        reservation: &Reservation,
+        status: ReservationStatus,
+    ) -> Result<(), mongodb::error::Error> {
+        self.reservations()
+            .update_one(
+                doc! { "_id": reservation.id, "status": "active" },
+                doc! { "$set": { "status": mongodb::bson::to_bson(&status).unwrap() } },
+            )
+            .await?;
+        let records: Vec<ActivityBookingCount> = self
+            .inventory()
+            .find(doc! { "holds.reservation_id": reservation.id })
+            .await?
+            .try_collect()
+            .await?;
+        self.inventory()
+            .update_many(
+                doc! { "holds.reservation_id": reservation.id },
+                doc! { "$pull": { "holds": { "reservation_id": reservation.id } } },
+            )
+            .await?;
+        let activity_ids: Vec<ObjectId> = records.iter().map(|record| record.activity_id).collect();
+        cache.invalidate_activities(&activity_ids);
+        Ok(())
+    }
+
+    /// `DELETE /payment/reserve/{id}`: give the seats back
+    pub async fn release(
+        &self,
+        cache: &AvailabilityCache,
+        reservation_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ReservationError> {
+        let reservation = self
+            .reservations()
+            .find_one(doc! { "_id": reservation_id, "user_id": user_id })
+            .await?
+            .ok_or(ReservationError::NotFound)?;
+        match reservation.status {
+            ReservationStatus::Active => {
+                self.release_holds(cache, &reservation, ReservationStatus::Released).await?;
+                println!("🪑 Reservation {} released", reservation_id);
+                Ok(())
+            }
+            ReservationStatus::Converted => Err(ReservationError::Expired),
+            ReservationStatus::Released | ReservationStatus::Expired => Ok(()),
+        }
+    }
+
+    /// The user's reservation, if it can still be paid for. `start` is checked
+    /// when the caller knows the trip's dates.
+    pub async fn find_for_checkout(
+        &self,
+        reservation_id: ObjectId,
+        user_id: ObjectId,
+        itinerary_id: ObjectId,
+        start: Option<NaiveDate>,
+    ) -> Result<Reservation, ReservationError> {
+        let reservation = self
+            .reservations()
+            .find_one(doc! { "_id": reservation_id, "user_id": user_id })
+            .await?
+            .ok_or(ReservationError::NotFound)?;
+        if !reservation.is_active(DateTime::now()) {
+            return Err(ReservationError::Expired);
+        }
+        if reservation.itinerary_id != itinerary_id
+            || start.is_some_and(|start| start != reservation.start_date)
+        {
+            return Err(ReservationError::DoesNotMatch);
+        }
+        Ok(reservation)
+    }
+
+    /// Whether a booking without a reservation still fits, counting held seats as taken
+    pub async fn has_room_for(
+        &self,
+        itinerary: &FeaturedVacation,
+        start: NaiveDate,
+        seats: u32,
+    ) -> Result<bool, mongodb::error::Error> {
+        let needed = seats_needed(itinerary, start, seats);
+        if needed.is_empty() {
+            return Ok(true);
+        }
+        let activities = self.load_activities(itinerary).await?;
+        let records: HashMap<(NaiveDate, ObjectId), ActivityBookingCount> = self
+            .inventory()
+            .find(doc! {
+                "activity_id": { "$in": scheduled_activity_ids(itinerary) },
+                "date": { "$in": needed.keys().map(|(date, _)| date.to_string()).collect::<Vec<_>>() },
+            })
+            .await?
+            .try_collect::<Vec<ActivityBookingCount>>()
+            .await?
+            .into_iter()
+            .map(|record| ((record.date, record.activity_id), record))
+            .collect();
+
+        let now = DateTime::now();
+        Ok(needed.iter().all(|(key, seats)| {
+            let Some(activity) = activities.get(&key.1) else {
+                return true;
+            };
+            let (booked, holds) = records
+                .get(key)
+                .map(|record| (record.booked, record.holds.as_slice()))
+                .unwrap_or((0, &[]));
+            has_room(activity.capacity.maximum as u32, booked, holds, *seats, now)
+        }))
+    }
+
+    /// Turn a reservation's holds into booked seats for a confirmed booking, as
+    /// part of the unit of work confirming it. Returns the activities whose cached
+    /// months the caller drops once the work commits, or `None` when the reservation
+    /// is gone, so the caller books the seats the usual way. A hold that lapsed is
+    /// booked anyway: the trip is paid for.
+    pub async fn convert_in(
+        &self,
+        uow: &mut UnitOfWork,
+        reservation_id: ObjectId,
+        booking_id: Option<ObjectId>,
+        itinerary: &FeaturedVacation,
+    ) -> Result<Option<Vec<ObjectId>>, mongodb::error::Error> {
+        let Some(reservation) = uow
+            .find_one_and_update(
+                &self.reservations(),
+                doc! { "_id": reservation_id, "status": { "$in": ["active", "expired"] } },
+                doc! { "$set": { "status": "converted", "booking_id": booking_id } },
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let inventory = self.inventory();
+        for ((date, activity_id), seats) in seats_needed(itinerary, reservation.start_date, reservation.seats) {
+            let record = doc! { "activity_id": activity_id, "date": date.to_string() };
+            let mut held = record.clone();
+            held.insert("holds.reservation_id", reservation_id);
+            let result = uow
+                .update_one(
+                    &inventory,
+                    held,
+                    doc! {
+                        "$pull": { "holds": { "reservation_id": reservation_id } },
+                        "$inc": { "booked": seats },
+                    },
+                )
+                .await?;
+            if result.matched_count == 0 {
+                uow.upsert_one(&inventory, record, doc! { "$inc": { "booked": seats } })
+                    .await?;
+            }
+        }
+        println!("🪑 Reservation {} converted to booking {:?}", reservation_id, booking_id);
+        Ok(Some(scheduled_activity_ids(itinerary)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::itinerary::base::{DayItem, Days};
+
+    fn hold(seats: u32, expires_at: DateTime) -> SeatHold {
+        SeatHold {
+            reservation_id: ObjectId::new(),
+            seats,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_expired_holds_free_their_seats() {
+        let now = DateTime::from_millis(1_760_000_000_000);
+        let holds = vec![hold(4, minutes_after(now, 10)), hold(2, now)];
+
+        // 6 booked + 4 held leaves 2 of 12; the lapsed hold of 2 doesn't count
+        assert_eq!(held_seats(&holds, now), 4);
+        assert!(has_room(12, 6, &holds, 2, now));
+        assert!(!has_room(12, 6, &holds, 3, now));
+        // Ten minutes on, the other hold has lapsed too
+        assert!(has_room(12, 6, &holds, 6, minutes_after(now, 10)));
+    }
+
+    #[test]
+    fn test_repeated_activity_needs_seats_twice() {
+        let rafting = ObjectId::new();
+        let hiking = ObjectId::new();
+        let item = |activity_id| DayItem::Activity {
+            time: "09:00:00".to_string(),
+            activity_id,
+        };
+        let itinerary = FeaturedVacation {
+            days: Days {
+                days: HashMap::from([
+                    ("1".to_string(), vec![item(rafting), item(rafting)]),
+                    ("2".to_string(), vec![item(hiking)]),
+                ]),
+            },
+            ..Default::default()
+        };
+        let start = NaiveDate::from_ymd_opt(2026, 7, 10).unwrap();
+
+        let needed = seats_needed(&itinerary, start, 3);
+        assert_eq!(needed.len(), 2);
+        assert_eq!(needed[&(start, rafting)], 6);
+        assert_eq!(needed[&(start.succ_opt().unwrap(), hiking)], 3);
+    }
+}
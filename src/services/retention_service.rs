@@ -0,0 +1,275 @@
+//! Purges search submissions and generated itineraries once they pass their
+//! retention window.
+//!
+//! Deletions go in `_id`-ordered batches of `DELETE_BATCH_SIZE`, each a bounded
+//! `delete_many`, so a large backlog never holds one long-running delete. Every
+//! run, dry or not, is recorded in `Options.RetentionRuns`.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DELETE_BATCH_SIZE: i64 = 500;
+
+/// Runs listed by `GET /admin/retention/runs`
+pub const LISTED_RUNS: i64 = 50;
+
+/// Days each kind of record is kept. Zero keeps that kind forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub search_submissions_days: u64,
+    pub generated_itineraries_days: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            search_submissions_days: 365,
+            generated_itineraries_days: 90,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionTrigger {
+    Scheduled,
+    Manual,
+}
+
+/// Records deleted per category, or that would be on a dry run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetentionCounts {
+    pub search_submissions: u64,
+    pub generated_itineraries: u64,
+    /// Expired generated itineraries kept because a booking or favorite points at them
+    pub generated_itineraries_kept: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionRun {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub trigger: RetentionTrigger,
+    pub dry_run: bool,
+    pub started_at: DateTime,
+    pub duration_ms: u64,
+    pub counts: RetentionCounts,
+    /// Set when the run stopped early; counts cover what happened before that
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The earliest ObjectId generated at `time`, for records without `created_at`
+fn object_id_at(time: DateTime) -> ObjectId {
+    let seconds = (time.timestamp_millis() / 1000).clamp(0, u32::MAX as i64) as u32;
+    let mut bytes = [0u8; 12];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+/// Records created before `cutoff`, going by `created_at` or, when a record has
+/// none, by its `_id`
+pub fn created_before(cutoff: DateTime) -> Document {
+    doc! {
+        "$or": [
+            { "created_at": { "$lt": cutoff } },
+            { "created_at": null, "_id": { "$lt": object_id_at(cutoff) } },
+        ]
+    }
+}
+
+/// Expired submissions that aren't tied to a user. Submissions aren't referenced
+/// by bookings, so an anonymous one has nothing linked to it.
+pub fn expired_submissions_filter(cutoff: DateTime) -> Document {
+    doc! { "$and": [created_before(cutoff), { "user_id": null }] }
+}
+
+pub fn expired_generated_itineraries_filter(cutoff: DateTime) -> Document {
+    doc! { "$and": [created_before(cutoff), { "tag": "generated" }] }
+}
+
+/// Ids in `batch` that nothing references
+pub fn unreferenced(batch: &[ObjectId], referenced: &HashSet<ObjectId>) -> Vec<ObjectId> {
+    batch.iter().filter(|id| !referenced.contains(id)).copied().collect()
+}
+
+fn cutoff(now: DateTime, days: u64) -> DateTime {
+    DateTime::from_millis(now.timestamp_millis() - (days * 24 * 60 * 60 * 1000) as i64)
+}
+
+pub struct RetentionService {
+    client: Arc<Client>,
+    policy: RetentionPolicy,
+}
+
+impl RetentionService {
+    pub fn new(client: Arc<Client>, policy: RetentionPolicy) -> Self {
+        RetentionService { client, policy }
+    }
+
+    fn runs(&self) -> Collection<RetentionRun> {
+        self.client.database("Options").collection("RetentionRuns")
+    }
+
+    /// Run retention every `interval`, starting one interval from now
+    pub fn start(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run(RetentionTrigger::Scheduled, false).await {
+                    eprintln!("Failed to record retention run: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Purge expired records, or only count them when `dry_run` is set, and record
+    /// the run. Only a failure to record the run is returned as an error; a failed
+    /// purge is recorded in the run itself.
+    pub async fn run(&self, trigger: RetentionTrigger, dry_run: bool) -> Result<RetentionRun, mongodb::error::Error> {
+        let started_at = DateTime::now();
+        let timer = Instant::now();
+        println!("🧹 Retention run started ({:?}{})", trigger, if dry_run { ", dry run" } else { "" });
+
+        let mut counts = RetentionCounts::default();
+        let result = self.purge(started_at, dry_run, &mut counts).await;
+
+        let mut run = RetentionRun {
+            id: None,
+            trigger,
+            dry_run,
+            started_at,
+            duration_ms: timer.elapsed().as_millis() as u64,
+            counts,
+            error: result.err().map(|e| e.to_string()),
+        };
+        match &run.error {
+            None => println!("✅ Retention run finished: {:?}", run.counts),
+            Some(e) => eprintln!("Retention run stopped early: {} ({:?})", e, run.counts),
+        }
+
+        let inserted = self.runs().insert_one(&run).await?;
+        run.id = inserted.inserted_id.as_object_id();
+        Ok(run)
+    }
+
+    async fn purge(&self, now: DateTime, dry_run: bool, counts: &mut RetentionCounts) -> Result<(), mongodb::error::Error> {
+        if self.policy.search_submissions_days > 0 {
+            let submissions: Collection<Document> = self.client.database("Travelers").collection("Submission");
+            let filter = expired_submissions_filter(cutoff(now, self.policy.search_submissions_days));
+            let mut after = None;
+            while let Some(batch) = next_batch(&submissions, &filter, &mut after).await? {
+                delete_ids(&submissions, &batch, dry_run).await?;
+                counts.search_submissions += batch.len() as u64;
+            }
+        }
+
+        if self.policy.generated_itineraries_days > 0 {
+            let itineraries: Collection<Document> = self.client.database("Itineraries").collection("Featured");
+            let filter = expired_generated_itineraries_filter(cutoff(now, self.policy.generated_itineraries_days));
+            let mut after = None;
+            while let Some(batch) = next_batch(&itineraries, &filter, &mut after).await? {
+                let referenced = self.referenced_itineraries(&batch).await?;
+                let expired = unreferenced(&batch, &referenced);
+                delete_ids(&itineraries, &expired, dry_run).await?;
+                counts.generated_itineraries += expired.len() as u64;
+                counts.generated_itineraries_kept += (batch.len() - expired.len()) as u64;
+            }
+        }
+        Ok(())
+    }
+
+    /// Itineraries in `ids` that a booking or favorite points at
+    async fn referenced_itineraries(&self, ids: &[ObjectId]) -> Result<HashSet<ObjectId>, mongodb::error::Error> {
+        let filter = doc! { "itinerary_id": { "$in": ids } };
+        let mut referenced = HashSet::new();
+        for collection in ["Bookings", "Favorites"] {
+            let values = self
+                .client
+                .database("Account")
+                .collection::<Document>(collection)
+                .distinct("itinerary_id", filter.clone())
+                .await?;
+            referenced.extend(values.iter().filter_map(|value| value.as_object_id()));
+        }
+        Ok(referenced)
+    }
+
+    /// Most recent runs first
+    pub async fn list_runs(&self, limit: i64) -> Result<Vec<RetentionRun>, mongodb::error::Error> {
+        self.runs()
+            .find(doc! {})
+            .sort(doc! { "started_at": -1 })
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await
+    }
+}
+
+/// The next batch of matching ids after `after`, advancing it. Paging by `_id`
+/// means records kept back (or left by a dry run) aren't fetched again.
+async fn next_batch(
+    collection: &Collection<Document>,
+    filter: &Document,
+    after: &mut Option<ObjectId>,
+) -> Result<Option<Vec<ObjectId>>, mongodb::error::Error> {
+    let mut filter = filter.clone();
+    if let Some(after) = after {
+        filter = doc! { "$and": [filter, { "_id": { "$gt": *after } }] };
+    }
+    let ids: Vec<ObjectId> = collection
+        .find(filter)
+        .projection(doc! { "_id": 1 })
+        .sort(doc! { "_id": 1 })
+        .limit(DELETE_BATCH_SIZE)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?
+        .iter()
+        .filter_map(|document| document.get_object_id("_id").ok())
+        .collect();
+
+    *after = ids.last().copied();
+    Ok((!ids.is_empty()).then_some(ids))
+}
+
+async fn delete_ids(collection: &Collection<Document>, ids: &[ObjectId], dry_run: bool) -> Result<(), mongodb::error::Error> {
+    if dry_run || ids.is_empty() {
+        return Ok(());
+    }
+    collection.delete_many(doc! { "_id": { "$in": ids } }).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_without_created_at_age_by_id() {
+        let cutoff = DateTime::from_millis(1_700_000_000_000);
+        let id = object_id_at(cutoff);
+        assert_eq!(id.timestamp().timestamp_millis(), 1_700_000_000_000);
+
+        let filter = expired_submissions_filter(cutoff);
+        let clauses = filter.get_array("$and").unwrap();
+        assert_eq!(clauses[1].as_document().unwrap(), &doc! { "user_id": null });
+    }
+
+    #[test]
+    fn test_referenced_itineraries_are_kept() {
+        let (booked, favorited, orphan) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let referenced = HashSet::from([booked, favorited]);
+        assert_eq!(unreferenced(&[booked, orphan, favorited], &referenced), vec![orphan]);
+        assert!(unreferenced(&[booked], &referenced).is_empty());
+    }
+}
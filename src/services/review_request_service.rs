@@ -0,0 +1,647 @@
+//! Review requests after a trip
+//!
+//! A background job emails travelers whose confirmed booking ended a couple of
+//! days ago, with a link to review each activity the trip's itinerary scheduled.
+//! The trip status job asks straight away when a trip it completes is already
+//! past that delay. The booking's `review_request_sent_at` is set with a
+//! conditional update before the email goes out, so overlapping runs can't both
+//! send one; it's cleared again if the email fails, and the next run retries.
+//!
+//! Each request is recorded in `Account.ReviewRequests` with two random tokens.
+//! The review links carry `review_token`, which opens the request for
+//! [`REVIEW_LINK_DAYS`] without signing in, and submits a review of any activity
+//! the request asked about to `Account.ActivityReviews`. The unsubscribe link
+//! carries `token` and turns the traveler's `review_requests` email preference off.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    Client, Collection,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::mongo::primary_collection;
+use crate::models::account::User;
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::models::itinerary::base::FeaturedVacation;
+use crate::services::account_service::{EmailError, EmailService, EmailSettings};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+/// Bookings read per page when looking for trips to ask about
+const BATCH_SIZE: i64 = 100;
+/// Trips that ended longer ago than this (after the delay) aren't asked about,
+/// so turning the preference back on doesn't bring up old trips
+const MAX_REQUEST_AGE_DAYS: i64 = 30;
+/// How long the review links in an email work without signing in
+pub const REVIEW_LINK_DAYS: i64 = 60;
+/// Longest review comment kept, in characters
+pub const MAX_REVIEW_COMMENT_CHARS: usize = 2_000;
+
+/// An activity the traveler is asked to review
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewActivity {
+    pub id: ObjectId,
+    pub title: String,
+}
+
+/// A review request that was emailed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewRequest {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub booking_id: ObjectId,
+    pub user_id: ObjectId,
+    /// In the email's unsubscribe link
+    pub token: String,
+    /// In the email's review links. Requests sent before those links existed
+    /// have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_token: Option<String>,
+    #[serde(default)]
+    pub trip_name: String,
+    /// The itinerary's activities when the request was sent, in trip order
+    #[serde(default)]
+    pub activities: Vec<ReviewActivity>,
+    /// Admin who sent it by hand, if it wasn't the scheduled job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<ObjectId>,
+    pub sent_at: DateTime,
+}
+
+/// A traveler's review of one activity on their trip
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityReview {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub booking_id: ObjectId,
+    pub user_id: ObjectId,
+    pub activity_id: ObjectId,
+    /// 1 to 5 stars
+    pub rating: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub submitted_at: DateTime,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReviewSubmissionError {
+    /// The review link is unknown or has expired
+    UnknownLink,
+    /// The activity isn't one the request asked about
+    NotRequested,
+    InvalidRating,
+    CommentTooLong,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ReviewSubmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReviewSubmissionError::UnknownLink => write!(f, "Unknown or expired review link"),
+            ReviewSubmissionError::NotRequested => write!(f, "That activity wasn't part of this trip"),
+            ReviewSubmissionError::InvalidRating => write!(f, "Rating must be from 1 to 5"),
+            ReviewSubmissionError::CommentTooLong => {
+                write!(f, "Comment must be at most {} characters", MAX_REVIEW_COMMENT_CHARS)
+            }
+            ReviewSubmissionError::DatabaseError(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReviewSubmissionError {}
+
+impl From<mongodb::error::Error> for ReviewSubmissionError {
+    fn from(err: mongodb::error::Error) -> Self {
+        ReviewSubmissionError::DatabaseError(err.to_string())
+    }
+}
+
+/// A valid rating and the trimmed comment, `None` when blank
+pub fn check_review(rating: u8, comment: Option<&str>) -> Result<Option<String>, ReviewSubmissionError> {
+    if !(1..=5).contains(&rating) {
+        return Err(ReviewSubmissionError::InvalidRating);
+    }
+    let comment = comment.map(str::trim).filter(|comment| !comment.is_empty());
+    if comment.is_some_and(|comment| comment.chars().count() > MAX_REVIEW_COMMENT_CHARS) {
+        return Err(ReviewSubmissionError::CommentTooLong);
+    }
+    Ok(comment.map(str::to_string))
+}
+
+#[derive(Debug)]
+pub enum ReviewRequestError {
+    BookingNotFound,
+    /// Only confirmed trips are asked about
+    NotConfirmed,
+    /// The traveler turned review requests, or email about their bookings, off
+    OptedOut,
+    /// Another run claimed the booking first
+    AlreadySent,
+    SendFailed(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ReviewRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReviewRequestError::BookingNotFound => write!(f, "Booking not found"),
+            ReviewRequestError::NotConfirmed => write!(f, "Only confirmed bookings can be asked for a review"),
+            ReviewRequestError::OptedOut => write!(f, "The traveler doesn't want review requests"),
+            ReviewRequestError::AlreadySent => write!(f, "A review request was already sent"),
+            ReviewRequestError::SendFailed(err) => write!(f, "Failed to send review request: {}", err),
+            ReviewRequestError::DatabaseError(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReviewRequestError {}
+
+impl From<mongodb::error::Error> for ReviewRequestError {
+    fn from(err: mongodb::error::Error) -> Self {
+        ReviewRequestError::DatabaseError(err.to_string())
+    }
+}
+
+/// What one review request email says
+#[derive(Debug, Clone)]
+pub struct ReviewRequestEmail {
+    pub user_email: String,
+    pub first_name: Option<String>,
+    pub trip_name: String,
+    pub booking_id: ObjectId,
+    pub activities: Vec<ReviewActivity>,
+    pub review_token: String,
+    pub unsubscribe_token: String,
+}
+
+impl ReviewRequestEmail {
+    /// The link to review each activity without signing in, or the booking's
+    /// review page when the itinerary had no activities left
+    pub fn review_links(&self, frontend_url: &str) -> Vec<(String, String)> {
+        let booking_review = format!("{}/account/bookings/{}/review", frontend_url, self.booking_id.to_hex());
+        if self.activities.is_empty() {
+            return vec![(
+                self.trip_name.clone(),
+                format!("{}?token={}", booking_review, self.review_token),
+            )];
+        }
+        self.activities
+            .iter()
+            .map(|activity| {
+                (
+                    activity.title.clone(),
+                    format!(
+                        "{}?activity={}&token={}",
+                        booking_review,
+                        activity.id.to_hex(),
+                        self.review_token
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Sends review request emails. Implemented for the real email service; tests
+/// substitute their own to see what would have been sent.
+pub trait ReviewRequestSender {
+    fn send_review_request(&self, email: &ReviewRequestEmail) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// `None` when email isn't configured, so bookings are left for a later run
+impl ReviewRequestSender for Option<EmailService> {
+    async fn send_review_request(&self, email: &ReviewRequestEmail) -> Result<(), String> {
+        let Some(service) = self else {
+            return Err("Email is not configured".to_string());
+        };
+        service.send_review_request_email(email).await.map_err(|e| e.to_string())
+    }
+}
+
+fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Whether `user` can be asked for a review: review requests on, and email about
+/// their bookings or marketing email on
+pub fn wants_review_requests(user: &User) -> bool {
+    let email = user.effective_notification_preferences().email;
+    email.review_requests && (email.booking_updates || email.marketing)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReviewRequestSummary {
+    pub sent: usize,
+    pub opted_out: usize,
+    /// Claimed by an overlapping run
+    pub already_sent: usize,
+    pub failed: usize,
+}
+
+pub struct ReviewRequestService {
+    client: Arc<Client>,
+    email: EmailSettings,
+}
+
+impl ReviewRequestService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ReviewRequestService {
+            client,
+            email: EmailSettings::default(),
+        }
+    }
+
+    /// Where review requests are emailed from. Without it none are sent.
+    pub fn with_email(mut self, settings: &EmailSettings) -> Self {
+        self.email = settings.clone();
+        self
+    }
+
+    /// The email service review requests go out through
+    pub fn email_service(&self) -> Result<EmailService, EmailError> {
+        EmailService::new(self.client.clone(), &self.email)
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    fn users(&self) -> Collection<User> {
+        self.client.database("Account").collection("Users")
+    }
+
+    fn requests(&self) -> Collection<ReviewRequest> {
+        self.client.database("Account").collection("ReviewRequests")
+    }
+
+    fn reviews(&self) -> Collection<ActivityReview> {
+        self.client.database("Account").collection("ActivityReviews")
+    }
+
+    /// Ask about every confirmed trip that ended `delay_days` or more before `now`
+    /// and hasn't been asked about, a page at a time
+    pub async fn send_due(
+        &self,
+        sender: &impl ReviewRequestSender,
+        delay_days: u64,
+        now: DateTime,
+    ) -> Result<ReviewRequestSummary, mongodb::error::Error> {
+        let due_before = DateTime::from_millis(now.timestamp_millis() - delay_days as i64 * DAY_MILLIS);
+        let not_before = DateTime::from_millis(due_before.timestamp_millis() - MAX_REQUEST_AGE_DAYS * DAY_MILLIS);
+
+        let mut summary = ReviewRequestSummary::default();
+        let mut last_id: Option<ObjectId> = None;
+        loop {
+            let mut filter = doc! {
+                "status": { "$in": ["confirmed", "in_progress", "completed"] },
+                "departure_datetime": { "$lte": due_before, "$gt": not_before },
+                "review_request_sent_at": null,
+            };
+            if let Some(last_id) = last_id {
+                filter.insert("_id", doc! { "$gt": last_id });
+            }
+            let page: Vec<BookingDetails> = self
+                .bookings()
+                .find(filter)
+                .sort(doc! { "_id": 1 })
+                .limit(BATCH_SIZE)
+                .await?
+                .try_collect()
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            last_id = last.id;
+
+            for booking in &page {
+                match self.deliver(booking, sender, None, now).await {
+                    Ok(()) => summary.sent += 1,
+                    Err(ReviewRequestError::OptedOut) => summary.opted_out += 1,
+                    Err(ReviewRequestError::AlreadySent) => summary.already_sent += 1,
+                    Err(err) => {
+                        eprintln!("Review request for booking {:?} not sent: {}", booking.id, err);
+                        summary.failed += 1;
+                    }
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Ask about a trip the trip status job just completed, unless it was already
+    /// asked about
+    pub async fn send_after_trip(
+        &self,
+        booking: &BookingDetails,
+        sender: &impl ReviewRequestSender,
+        now: DateTime,
+    ) -> Result<(), ReviewRequestError> {
+        self.deliver(booking, sender, None, now).await
+    }
+
+    /// Send a review request for one booking now, whether or not one went out
+    /// before. Used by support; the traveler's preferences still apply.
+    pub async fn send_for_booking(
+        &self,
+        booking_id: ObjectId,
+        sender: &impl ReviewRequestSender,
+        admin_id: ObjectId,
+    ) -> Result<(), ReviewRequestError> {
+        let booking = self
+            .bookings()
+            .find_one(doc! { "_id": booking_id })
+            .await?
+            .ok_or(ReviewRequestError::BookingNotFound)?;
+        if !matches!(
+            booking.status,
+            PaymentStatus::Confirmed | PaymentStatus::InProgress | PaymentStatus::Completed
+        ) {
+            return Err(ReviewRequestError::NotConfirmed);
+        }
+        self.deliver(&booking, sender, Some(admin_id), DateTime::now()).await
+    }
+
+    /// Claim the booking, record the request and email it. The scheduled job only
+    /// claims bookings that haven't been asked about; an admin can ask again.
+    async fn deliver(
+        &self,
+        booking: &BookingDetails,
+        sender: &impl ReviewRequestSender,
+        requested_by: Option<ObjectId>,
+        now: DateTime,
+    ) -> Result<(), ReviewRequestError> {
+        let booking_id = booking.id.ok_or(ReviewRequestError::BookingNotFound)?;
+        let user = self.users().find_one(doc! { "_id": booking.user_id }).await?;
+        let Some(user) = user.filter(wants_review_requests) else {
+            return Err(ReviewRequestError::OptedOut);
+        };
+
+        let mut claim = doc! { "_id": booking_id };
+        if requested_by.is_none() {
+            claim.insert("review_request_sent_at", mongodb::bson::Bson::Null);
+        }
+        let claimed = self
+            .bookings()
+            .update_one(claim, doc! { "$set": { "review_request_sent_at": now } })
+            .await?;
+        if claimed.matched_count == 0 {
+            return Err(ReviewRequestError::AlreadySent);
+        }
+
+        let itinerary = self
+            .client
+            .database("Itineraries")
+            .collection::<FeaturedVacation>("Featured")
+            .find_one(doc! { "_id": booking.itinerary_id })
+            .await?;
+        let trip_name = itinerary
+            .as_ref()
+            .map(|itinerary| itinerary.trip_name.clone())
+            .unwrap_or_else(|| "your trip".to_string());
+        let activities = match &itinerary {
+            Some(itinerary) => self.activities(&itinerary.days.activity_ids()).await?,
+            None => Vec::new(),
+        };
+        let request = ReviewRequest {
+            id: None,
+            booking_id,
+            user_id: booking.user_id,
+            token: random_token(),
+            review_token: Some(random_token()),
+            trip_name: trip_name.clone(),
+            activities: activities.clone(),
+            requested_by,
+            sent_at: now,
+        };
+        let request_id = self.requests().insert_one(&request).await?.inserted_id;
+        let email = ReviewRequestEmail {
+            user_email: user.email.clone(),
+            first_name: user.first_name.clone(),
+            trip_name,
+            booking_id,
+            activities,
+            review_token: request.review_token.clone().unwrap_or_default(),
+            unsubscribe_token: request.token.clone(),
+        };
+
+        if let Err(err) = sender.send_review_request(&email).await {
+            // Put the booking back the way it was so a later run tries again
+            self.requests().delete_one(doc! { "_id": request_id }).await?;
+            self.bookings()
+                .update_one(
+                    doc! { "_id": booking_id, "review_request_sent_at": now },
+                    doc! { "$set": { "review_request_sent_at": booking.review_request_sent_at } },
+                )
+                .await?;
+            return Err(ReviewRequestError::SendFailed(err));
+        }
+
+        println!("⭐ Review request sent for booking {}", booking_id);
+        Ok(())
+    }
+
+    /// Titles of the activities with `ids`, in that order. Activities deleted
+    /// since are left out.
+    async fn activities(&self, ids: &[ObjectId]) -> Result<Vec<ReviewActivity>, mongodb::error::Error> {
+        let titles: HashMap<ObjectId, String> = self
+            .client
+            .database("Options")
+            .collection::<Document>("Activity")
+            .find(doc! { "_id": { "$in": ids } })
+            .projection(doc! { "title": 1 })
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .into_iter()
+            .filter_map(|activity| {
+                Some((activity.get_object_id("_id").ok()?, activity.get_str("title").ok()?.to_string()))
+            })
+            .collect();
+        Ok(ids
+            .iter()
+            .filter_map(|id| titles.get(id).map(|title| ReviewActivity { id: *id, title: title.clone() }))
+            .collect())
+    }
+
+    /// The request a review link was for, while its link still works
+    pub async fn find_by_review_token(
+        &self,
+        review_token: &str,
+        now: DateTime,
+    ) -> Result<Option<ReviewRequest>, mongodb::error::Error> {
+        let oldest = DateTime::from_millis(now.timestamp_millis() - REVIEW_LINK_DAYS * DAY_MILLIS);
+        self.requests()
+            .find_one(doc! { "review_token": review_token, "sent_at": { "$gt": oldest } })
+            .await
+    }
+
+    /// Review `activity_id` through the review link carrying `review_token`.
+    /// Reviewing the same activity on the same booking again replaces the review.
+    pub async fn submit_review(
+        &self,
+        review_token: &str,
+        activity_id: ObjectId,
+        rating: u8,
+        comment: Option<&str>,
+        now: DateTime,
+    ) -> Result<ActivityReview, ReviewSubmissionError> {
+        let comment = check_review(rating, comment)?;
+        let request = self
+            .find_by_review_token(review_token, now)
+            .await?
+            .ok_or(ReviewSubmissionError::UnknownLink)?;
+        if !request.activities.iter().any(|activity| activity.id == activity_id) {
+            return Err(ReviewSubmissionError::NotRequested);
+        }
+
+        let review = ActivityReview {
+            id: None,
+            booking_id: request.booking_id,
+            user_id: request.user_id,
+            activity_id,
+            rating,
+            comment,
+            submitted_at: now,
+        };
+        self.reviews()
+            .replace_one(doc! { "booking_id": request.booking_id, "activity_id": activity_id }, &review)
+            .upsert(true)
+            .await?;
+        Ok(review)
+    }
+
+    /// Turn review requests off for whoever was sent `token`. Returns whether the
+    /// token was recognized.
+    pub async fn unsubscribe(&self, token: &str) -> Result<bool, mongodb::error::Error> {
+        let Some(request) = self.requests().find_one(doc! { "token": token }).await? else {
+            return Ok(false);
+        };
+        let Some(user) = self.users().find_one(doc! { "_id": request.user_id }).await? else {
+            return Ok(false);
+        };
+
+        let mut preferences = user.effective_notification_preferences();
+        preferences.email.review_requests = false;
+        self.users()
+            .update_one(
+                doc! { "_id": request.user_id },
+                doc! {
+                    "$set": {
+                        "notification_preferences": mongodb::bson::to_bson(&preferences)?,
+                        "updated_at": mongodb::bson::to_bson(&chrono::Utc::now())?,
+                    }
+                },
+            )
+            .await?;
+        println!("⭐ User {} unsubscribed from review requests", request.user_id);
+        Ok(true)
+    }
+
+    /// Look for finished trips every `interval`, starting one interval from now
+    pub fn start(self, interval: Duration, delay_days: u64) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                let email_service = match self.email_service() {
+                    Ok(service) => Some(service),
+                    Err(e) => {
+                        println!("Review requests won't be emailed this run: {}", e);
+                        None
+                    }
+                };
+                match self.send_due(&email_service, delay_days, DateTime::now()).await {
+                    Ok(summary) => println!("⭐ Review request run finished: {:?}", summary),
+                    Err(e) => eprintln!("Review request run failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::account::{EmailPreferences, NotificationPreferences};
+
+    fn user(email: EmailPreferences) -> User {
+        User {
+            notification_preferences: Some(NotificationPreferences {
+                email,
+                ..Default::default()
+            }),
+            ..User::test("traveler@example.com")
+        }
+    }
+
+    #[test]
+    fn test_each_activity_gets_a_review_link() {
+        let booking_id = ObjectId::new();
+        let (rafting, hike) = (ObjectId::new(), ObjectId::new());
+        let mut email = ReviewRequestEmail {
+            user_email: "traveler@example.com".to_string(),
+            first_name: None,
+            trip_name: "Colorado Rivers".to_string(),
+            booking_id,
+            activities: vec![
+                ReviewActivity { id: rafting, title: "Rafting".to_string() },
+                ReviewActivity { id: hike, title: "Hike".to_string() },
+            ],
+            review_token: "rt".to_string(),
+            unsubscribe_token: "ut".to_string(),
+        };
+        let base = format!("https://actota.com/account/bookings/{}/review", booking_id.to_hex());
+        assert_eq!(
+            email.review_links("https://actota.com"),
+            vec![
+                ("Rafting".to_string(), format!("{}?activity={}&token=rt", base, rafting.to_hex())),
+                ("Hike".to_string(), format!("{}?activity={}&token=rt", base, hike.to_hex())),
+            ]
+        );
+
+        email.activities.clear();
+        assert_eq!(
+            email.review_links("https://actota.com"),
+            vec![("Colorado Rivers".to_string(), format!("{}?token=rt", base))]
+        );
+    }
+
+    #[test]
+    fn test_review_requests_follow_email_preferences() {
+        assert!(wants_review_requests(&user(EmailPreferences::default())));
+        assert!(!wants_review_requests(&user(EmailPreferences {
+            review_requests: false,
+            ..Default::default()
+        })));
+        // With booking emails off, marketing email is enough
+        assert!(!wants_review_requests(&user(EmailPreferences {
+            booking_updates: false,
+            ..Default::default()
+        })));
+        assert!(wants_review_requests(&user(EmailPreferences {
+            booking_updates: false,
+            marketing: true,
+            ..Default::default()
+        })));
+    }
+
+    #[test]
+    fn test_reviews_need_a_rating_and_a_short_comment() {
+        assert_eq!(check_review(5, Some("  Loved it \n")), Ok(Some("Loved it".to_string())));
+        assert_eq!(check_review(1, Some("   ")), Ok(None));
+        assert_eq!(check_review(3, None), Ok(None));
+        assert_eq!(check_review(0, None), Err(ReviewSubmissionError::InvalidRating));
+        assert_eq!(check_review(6, Some("Great")), Err(ReviewSubmissionError::InvalidRating));
+        let long = "a".repeat(MAX_REVIEW_COMMENT_CHARS + 1);
+        assert_eq!(check_review(4, Some(&long)), Err(ReviewSubmissionError::CommentTooLong));
+    }
+}
@@ -0,0 +1,619 @@
+//! Travel times between an itinerary's stops, and the GeoJSON layer, for the map view.
+//!
+//! Stops are the itinerary's activities and lodging. Activities resolve from the
+//! coordinates the geocoding backfill stores on them and lodging from its stored
+//! location; a stop without coordinates is listed with `resolved: false`, has no
+//! row or column in the matrix and is skipped in its day's drive total. The matrix
+//! is the same one route optimization builds for its TSP search, without traffic,
+//! so pairs come from the long-lived distance cache.
+//!
+//! The GeoJSON layer also falls back to geocoding a stop's address, through the
+//! shared geocode cache, when Google Maps is configured. Stops that still have no
+//! coordinates are left off the map and listed in `unresolved_stops`.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client, Collection,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use crate::models::itinerary::populated::AccommodationModel;
+use crate::services::distance_service::{DistancePrefilter, DistanceService};
+use crate::services::generation_budget::GenerationBudget;
+use crate::services::units::{format_distance, UnitSystem};
+use crate::services::geocoding_service::{address_line, Geocoder, GeocodingService};
+use crate::services::route_optimization_service::{
+    OptimizationConfig, RouteOptimizationService, TravelMatrix,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopKind {
+    Activity,
+    Lodging,
+}
+
+/// A place the itinerary visits. Each place is listed once, however many days visit it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapStop {
+    pub id: String,
+    pub kind: StopKind,
+    pub name: String,
+    /// `[lat, lng]`, `None` when unresolved
+    pub coordinates: Option<[f64; 2]>,
+    pub resolved: bool,
+}
+
+/// One day's stops in visiting order, as indexes into `stops`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayRoute {
+    pub day: u32,
+    pub stops: Vec<usize>,
+    pub drive_minutes: i64,
+    pub distance_meters: u64,
+    /// `distance_meters` in the viewer's units, set by `show_distances`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<String>,
+    /// False when a stop was unresolved or a leg couldn't be looked up, so the
+    /// totals leave part of the day out
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItineraryDistanceMatrix {
+    pub itinerary_id: String,
+    pub stops: Vec<MapStop>,
+    /// `matrix[i][j]` is the leg from stop `i` to stop `j`; `None` when either is
+    /// unresolved or the leg couldn't be looked up
+    pub matrix: TravelMatrix,
+    pub days: Vec<DayRoute>,
+    pub unresolved_stops: usize,
+}
+
+impl ItineraryDistanceMatrix {
+    pub fn is_complete(&self) -> bool {
+        self.unresolved_stops == 0 && self.days.iter().all(|day| day.complete)
+    }
+
+    /// Add each day's distance, formatted in `units`, next to the metres
+    pub fn show_distances(&mut self, units: UnitSystem) {
+        for day in &mut self.days {
+            day.distance = Some(format_distance(day.distance_meters as f64, units));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RouteMapError {
+    NotFound,
+    /// None of the itinerary's stops has coordinates
+    NoCoordinates,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for RouteMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RouteMapError::NotFound => write!(f, "Itinerary not found"),
+            RouteMapError::NoCoordinates => write!(f, "None of this itinerary's stops have coordinates yet"),
+            RouteMapError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RouteMapError {}
+
+impl From<mongodb::error::Error> for RouteMapError {
+    fn from(e: mongodb::error::Error) -> Self {
+        RouteMapError::DatabaseError(e.to_string())
+    }
+}
+
+/// Each day's number and its stops, as indexes into the itinerary's stops
+type DayStops = Vec<(u32, Vec<usize>)>;
+
+/// A GeoJSON geometry. Positions are `[lng, lat]`, as the spec orders them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    Point { coordinates: [f64; 2] },
+    LineString { coordinates: Vec<[f64; 2]> },
+}
+
+/// Feature properties; `layer` tells stops and day routes apart for styling
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "layer", rename_all = "snake_case")]
+pub enum FeatureProperties {
+    Stop {
+        id: String,
+        kind: StopKind,
+        name: String,
+        day: u32,
+        /// Position in the day, from 1
+        order: usize,
+    },
+    Route {
+        day: u32,
+        /// False when an unresolved stop was left out of the line
+        complete: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Feature {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub geometry: Geometry,
+    pub properties: FeatureProperties,
+}
+
+/// A stop left off the map, with the days that visit it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnresolvedStop {
+    pub id: String,
+    pub kind: StopKind,
+    pub name: String,
+    pub days: Vec<u32>,
+}
+
+/// A FeatureCollection with a Point for each visit to a stop and a LineString for
+/// each day's route
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItineraryGeoJson {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub features: Vec<Feature>,
+    pub unresolved_stops: Vec<UnresolvedStop>,
+}
+
+impl ItineraryGeoJson {
+    pub fn is_complete(&self) -> bool {
+        self.unresolved_stops.is_empty()
+    }
+}
+
+/// The map layer over resolved stops. A day with fewer than two resolved stops
+/// has no line.
+fn feature_collection(stops: &[MapStop], day_stops: &DayStops) -> ItineraryGeoJson {
+    let position = |stop: &MapStop| stop.coordinates.map(|[lat, lng]| [lng, lat]);
+    let mut features = Vec::new();
+    let mut unresolved: Vec<UnresolvedStop> = Vec::new();
+    for (day, indexes) in day_stops {
+        let mut line = Vec::new();
+        for (order, &index) in indexes.iter().enumerate() {
+            let stop = &stops[index];
+            let Some(coordinates) = position(stop) else {
+                match unresolved.iter_mut().find(|missing| missing.id == stop.id) {
+                    Some(missing) if missing.days.last() != Some(day) => missing.days.push(*day),
+                    Some(_) => {}
+                    None => unresolved.push(UnresolvedStop {
+                        id: stop.id.clone(),
+                        kind: stop.kind,
+                        name: stop.name.clone(),
+                        days: vec![*day],
+                    }),
+                }
+                continue;
+            };
+            line.push(coordinates);
+            features.push(Feature {
+                kind: "Feature",
+                geometry: Geometry::Point { coordinates },
+                properties: FeatureProperties::Stop {
+                    id: stop.id.clone(),
+                    kind: stop.kind,
+                    name: stop.name.clone(),
+                    day: *day,
+                    order: order + 1,
+                },
+            });
+        }
+        if line.len() >= 2 {
+            features.push(Feature {
+                kind: "Feature",
+                properties: FeatureProperties::Route {
+                    day: *day,
+                    complete: line.len() == indexes.len(),
+                },
+                geometry: Geometry::LineString { coordinates: line },
+            });
+        }
+    }
+    ItineraryGeoJson {
+        kind: "FeatureCollection",
+        features,
+        unresolved_stops: unresolved,
+    }
+}
+
+/// The distinct stops in day order, and each day's stops as indexes into them.
+/// Transportation items aren't stops; days whose key isn't a day number are left out.
+fn collect_stops(days: &Days) -> (Vec<(StopKind, ObjectId)>, DayStops) {
+    let mut numbered: Vec<(u32, &Vec<DayItem>)> = days
+        .days
+        .iter()
+        .filter_map(|(day, items)| Some((day.trim().parse().ok()?, items)))
+        .collect();
+    numbered.sort_by_key(|(day, _)| *day);
+
+    let mut stops: Vec<(StopKind, ObjectId)> = Vec::new();
+    let mut day_stops = Vec::new();
+    for (day, items) in numbered {
+        let mut indexes = Vec::new();
+        for item in items {
+            let stop = match item {
+                DayItem::Activity { activity_id, .. } => (StopKind::Activity, *activity_id),
+                DayItem::Accommodation { accommodation_id, .. } => (StopKind::Lodging, *accommodation_id),
+                DayItem::Transportation { .. } => continue,
+            };
+            let index = match stops.iter().position(|known| *known == stop) {
+                Some(index) => index,
+                None => {
+                    stops.push(stop);
+                    stops.len() - 1
+                }
+            };
+            indexes.push(index);
+        }
+        day_stops.push((day, indexes));
+    }
+    (stops, day_stops)
+}
+
+/// Drive totals for each day from the matrix over the resolved stops.
+/// `resolved[i]` is stop `i`'s row in `matrix`.
+fn day_routes(day_stops: DayStops, resolved: &[Option<usize>], matrix: &TravelMatrix) -> Vec<DayRoute> {
+    day_stops
+        .into_iter()
+        .map(|(day, stops)| {
+            let mut route = DayRoute {
+                day,
+                stops,
+                drive_minutes: 0,
+                distance_meters: 0,
+                distance: None,
+                complete: true,
+            };
+            let mut previous: Option<usize> = None;
+            for &stop in &route.stops {
+                let Some(row) = resolved[stop] else {
+                    route.complete = false;
+                    continue;
+                };
+                if let Some(from) = previous {
+                    match matrix[from][row] {
+                        Some(leg) => {
+                            route.drive_minutes += leg.duration_minutes;
+                            route.distance_meters += leg.distance_meters as u64;
+                        }
+                        None => route.complete = false,
+                    }
+                }
+                previous = Some(row);
+            }
+            route
+        })
+        .collect()
+}
+
+/// Spread the matrix over the resolved stops into one over all stops
+fn expand_matrix(resolved: &[Option<usize>], matrix: &TravelMatrix) -> TravelMatrix {
+    resolved
+        .iter()
+        .map(|from| {
+            resolved
+                .iter()
+                .map(|to| matrix[(*from)?][(*to)?])
+                .collect()
+        })
+        .collect()
+}
+
+/// `(lat, lng)` from a stored `[lng, lat]` pair
+fn lodging_coordinates(lodging: &AccommodationModel) -> Option<(f64, f64)> {
+    match lodging.location.as_ref()?.coordinates.as_slice() {
+        [lng, lat] => Some((*lat, *lng)),
+        _ => None,
+    }
+}
+
+pub struct RouteMapService {
+    client: Arc<Client>,
+    routes: RouteOptimizationService,
+}
+
+impl RouteMapService {
+    /// Uses Google Maps when it's configured, straight-line estimates otherwise.
+    /// Maps lookups are drawn from `budget` and estimated once it's spent.
+    pub fn new(
+        client: Arc<Client>,
+        maps_api_key: Option<&str>,
+        prefilter: DistancePrefilter,
+        budget: GenerationBudget,
+    ) -> Self {
+        let distance_service = DistanceService::new(client.clone(), maps_api_key, prefilter)
+            .map_err(|e| println!("Distance matrix falls back to straight-line estimates: {}", e))
+            .ok()
+            .map(|distance_service| distance_service.with_budget(budget));
+        Self::with_routes(client, distance_service, prefilter)
+    }
+
+    pub fn with_routes(
+        client: Arc<Client>,
+        distance_service: Option<DistanceService>,
+        prefilter: DistancePrefilter,
+    ) -> Self {
+        let config = OptimizationConfig {
+            consider_traffic: false,
+            ..Default::default()
+        };
+        Self {
+            client,
+            routes: RouteOptimizationService::with_config(distance_service, config).with_prefilter(prefilter),
+        }
+    }
+
+    /// Name, stored coordinates and address to geocode for each id
+    async fn stop_details(
+        &self,
+        kind: StopKind,
+        ids: Vec<ObjectId>,
+    ) -> Result<HashMap<ObjectId, (String, Option<(f64, f64)>, Option<String>)>, mongodb::error::Error> {
+        let filter = doc! { "_id": { "$in": ids } };
+        let options = self.client.database("Options");
+        let found = match kind {
+            StopKind::Activity => {
+                let collection: Collection<Activity> = options.collection("Activity");
+                let activities: Vec<Activity> = collection.find(filter).await?.try_collect().await?;
+                activities
+                    .into_iter()
+                    .filter_map(|activity| {
+                        let coordinates = activity.location.as_ref().map(|point| point.lat_lng());
+                        let address = address_line(&activity.address);
+                        Some((activity.id?, (activity.title, coordinates, address)))
+                    })
+                    .collect()
+            }
+            StopKind::Lodging => {
+                let collection: Collection<AccommodationModel> = options.collection("Lodging");
+                let lodging: Vec<AccommodationModel> = collection.find(filter).await?.try_collect().await?;
+                lodging
+                    .into_iter()
+                    .filter_map(|lodging| {
+                        let coordinates = lodging_coordinates(&lodging);
+                        let address = lodging.address.filter(|address| !address.trim().is_empty());
+                        Some((lodging.id?, (lodging.name, coordinates, address)))
+                    })
+                    .collect()
+            }
+        };
+        Ok(found)
+    }
+
+    async fn itinerary(&self, itinerary_id: ObjectId) -> Result<FeaturedVacation, RouteMapError> {
+        let itineraries: Collection<FeaturedVacation> =
+            self.client.database("Itineraries").collection("Featured");
+        itineraries
+            .find_one(doc! { "_id": itinerary_id, "taken_down_at": null })
+            .await?
+            .ok_or(RouteMapError::NotFound)
+    }
+
+    /// The itinerary's stops with their coordinates, and each day's stops. With a
+    /// geocoder, stops without stored coordinates are looked up by address; a failed
+    /// lookup leaves the stop unresolved.
+    async fn resolve_stops<G: Geocoder>(
+        &self,
+        days: &Days,
+        geocoding: Option<&GeocodingService<G>>,
+    ) -> Result<(Vec<MapStop>, DayStops), mongodb::error::Error> {
+        let (stop_ids, day_stops) = collect_stops(days);
+        let ids_of = |kind: StopKind| -> Vec<ObjectId> {
+            stop_ids.iter().filter(|(k, _)| *k == kind).map(|(_, id)| *id).collect()
+        };
+        let mut found = self
+            .stop_details(StopKind::Activity, ids_of(StopKind::Activity))
+            .await?;
+        found.extend(self.stop_details(StopKind::Lodging, ids_of(StopKind::Lodging)).await?);
+
+        let mut stops = Vec::with_capacity(stop_ids.len());
+        for (kind, id) in &stop_ids {
+            let (name, mut coordinates, address) = found.get(id).cloned().unwrap_or_default();
+            if let (None, Some(geocoding), Some(address)) = (coordinates, geocoding, address) {
+                coordinates = match geocoding.geocode(&address).await {
+                    Ok(found) => found.map(|(coordinates, _)| coordinates),
+                    Err(e) => {
+                        eprintln!("Failed to geocode map stop {}: {}", id, e);
+                        None
+                    }
+                };
+            }
+            stops.push(MapStop {
+                id: id.to_hex(),
+                kind: *kind,
+                name,
+                coordinates: coordinates.map(|(lat, lng)| [lat, lng]),
+                resolved: coordinates.is_some(),
+            });
+        }
+        Ok((stops, day_stops))
+    }
+
+    /// Pairwise travel between the itinerary's stops, with each day's drive total
+    pub async fn distance_matrix(&self, itinerary_id: ObjectId) -> Result<ItineraryDistanceMatrix, RouteMapError> {
+        let itinerary = self.itinerary(itinerary_id).await?;
+        let (stops, day_stops) = self
+            .resolve_stops(&itinerary.days, None::<&GeocodingService>)
+            .await?;
+
+        let mut places = Vec::new();
+        let resolved: Vec<Option<usize>> = stops
+            .iter()
+            .map(|stop| {
+                stop.coordinates.map(|[lat, lng]| {
+                    places.push((lat, lng));
+                    places.len() - 1
+                })
+            })
+            .collect();
+        if places.is_empty() {
+            return Err(RouteMapError::NoCoordinates);
+        }
+
+        let matrix = self.routes.travel_matrix(&places).await;
+        Ok(ItineraryDistanceMatrix {
+            itinerary_id: itinerary_id.to_hex(),
+            unresolved_stops: stops.iter().filter(|stop| !stop.resolved).count(),
+            stops,
+            days: day_routes(day_stops, &resolved, &matrix),
+            matrix: expand_matrix(&resolved, &matrix),
+        })
+    }
+
+    /// The itinerary's stops and each day's route as GeoJSON, geocoding stops
+    /// without stored coordinates when `geocoding` is given
+    pub async fn map_geojson<G: Geocoder>(
+        &self,
+        itinerary_id: ObjectId,
+        geocoding: Option<&GeocodingService<G>>,
+    ) -> Result<ItineraryGeoJson, RouteMapError> {
+        let itinerary = self.itinerary(itinerary_id).await?;
+        let (stops, day_stops) = self.resolve_stops(&itinerary.days, geocoding).await?;
+        Ok(feature_collection(&stops, &day_stops))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::distance_service::DistanceSource;
+    use crate::services::route_optimization_service::TravelLeg;
+
+    fn activity(id: ObjectId) -> DayItem {
+        DayItem::Activity {
+            time: "10:00:00".to_string(),
+            activity_id: id,
+        }
+    }
+
+    fn leg(minutes: i64) -> Option<TravelLeg> {
+        Some(TravelLeg {
+            duration_minutes: minutes,
+            distance_meters: minutes as u32 * 1000,
+            source: DistanceSource::GoogleMaps,
+            too_far: false,
+        })
+    }
+
+    #[test]
+    fn test_stops_are_listed_once_in_day_order() {
+        let (hike, museum, hotel) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let lodging = DayItem::Accommodation {
+            time: "18:00:00".to_string(),
+            accommodation_id: hotel,
+        };
+        let days = Days {
+            days: HashMap::from([
+                ("10".to_string(), vec![activity(museum)]),
+                ("2".to_string(), vec![activity(museum), DayItem::default(), lodging.clone()]),
+                ("1".to_string(), vec![activity(hike), lodging]),
+                ("extra".to_string(), vec![activity(ObjectId::new())]),
+            ]),
+        };
+
+        let (stops, day_stops) = collect_stops(&days);
+        assert_eq!(
+            stops,
+            vec![(StopKind::Activity, hike), (StopKind::Lodging, hotel), (StopKind::Activity, museum)]
+        );
+        assert_eq!(day_stops, vec![(1, vec![0, 1]), (2, vec![2, 1]), (10, vec![2])]);
+    }
+
+    #[test]
+    fn test_unresolved_stops_are_skipped_and_flagged() {
+        // Stops 0 and 2 resolved as places 0 and 1; stop 1 has no coordinates
+        let resolved = vec![Some(0), None, Some(1)];
+        let matrix = vec![vec![leg(0), leg(25)], vec![leg(30), leg(0)]];
+
+        let days = day_routes(vec![(1, vec![0, 2]), (2, vec![2, 1, 0])], &resolved, &matrix);
+        assert_eq!(days[0].drive_minutes, 25);
+        assert_eq!(days[0].distance_meters, 25_000);
+        assert!(days[0].complete);
+        assert_eq!(days[1].drive_minutes, 30);
+        assert!(!days[1].complete);
+
+        let expanded = expand_matrix(&resolved, &matrix);
+        assert_eq!(expanded[0][2], leg(25));
+        assert_eq!(expanded[1], vec![None, None, None]);
+        assert_eq!(expanded[2][1], None);
+    }
+
+    #[test]
+    fn test_geojson_lines_follow_each_day_and_skip_unresolved_stops() {
+        let stop = |name: &str, kind, coordinates: Option<[f64; 2]>| MapStop {
+            id: name.to_string(),
+            kind,
+            name: name.to_string(),
+            coordinates,
+            resolved: coordinates.is_some(),
+        };
+        let stops = vec![
+            stop("hike", StopKind::Activity, Some([39.7, -105.2])),
+            stop("hotel", StopKind::Lodging, Some([39.6, -105.0])),
+            stop("museum", StopKind::Activity, None),
+            stop("rafting", StopKind::Activity, Some([39.5, -106.0])),
+        ];
+
+        let map = feature_collection(&stops, &vec![(1, vec![0, 2, 1]), (2, vec![3, 2]), (3, vec![1])]);
+        let lines: Vec<_> = map
+            .features
+            .iter()
+            .filter_map(|feature| match (&feature.geometry, &feature.properties) {
+                (Geometry::LineString { coordinates }, FeatureProperties::Route { day, complete }) => {
+                    Some((*day, coordinates.clone(), *complete))
+                }
+                _ => None,
+            })
+            .collect();
+        // GeoJSON positions are [lng, lat]; day 2 has one resolved stop, so no line
+        assert_eq!(lines, vec![(1, vec![[-105.2, 39.7], [-105.0, 39.6]], false)]);
+
+        let points = map
+            .features
+            .iter()
+            .filter(|feature| matches!(feature.geometry, Geometry::Point { .. }))
+            .count();
+        assert_eq!(points, 4);
+        assert!(map.features.contains(&Feature {
+            kind: "Feature",
+            geometry: Geometry::Point { coordinates: [-105.0, 39.6] },
+            properties: FeatureProperties::Stop {
+                id: "hotel".to_string(),
+                kind: StopKind::Lodging,
+                name: "hotel".to_string(),
+                day: 1,
+                order: 3,
+            },
+        }));
+        assert_eq!(
+            map.unresolved_stops,
+            vec![UnresolvedStop {
+                id: "museum".to_string(),
+                kind: StopKind::Activity,
+                name: "museum".to_string(),
+                days: vec![1, 2],
+            }]
+        );
+        assert!(!map.is_complete());
+
+        let json = serde_json::to_value(&map).unwrap();
+        assert_eq!(json["type"], "FeatureCollection");
+        assert_eq!(json["features"][0]["geometry"]["type"], "Point");
+        assert_eq!(json["features"][0]["properties"]["layer"], "stop");
+    }
+}
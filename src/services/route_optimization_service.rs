@@ -11,11 +11,17 @@
 //! - Uses Google Maps real driving times with traffic
 //! - Respects activity time constraints and availability
 //! - Configurable optimization strategies
+//! - Never puts two activities too far apart to drive between in the same day
 
-use crate::models::activity::Activity;
-use crate::services::distance_service::{DistanceService, TravelMode};
+use crate::models::activity::{Activity, DEFAULT_MIN_ACTIVITY_MINUTES};
+use crate::services::distance_service::{
+    haversine_miles, straight_line_minutes, DistanceMatrix, DistancePrefilter, DistanceResult,
+    DistanceService, DistanceSource, GoogleMaps, TravelMode,
+};
 use chrono::{Duration, NaiveTime};
-use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+
+const METERS_PER_MILE: f64 = 1609.344;
 
 #[derive(Debug, Clone)]
 pub struct OptimizedActivity {
@@ -34,6 +40,8 @@ pub struct OptimizationConfig {
     pub day_end_time: NaiveTime,
     pub consider_traffic: bool,
     pub optimization_strategy: OptimizationStrategy,
+    /// Activities are scheduled for at least this long
+    pub min_activity_minutes: u16,
 }
 
 #[derive(Debug, Clone)]
@@ -56,30 +64,97 @@ impl Default for OptimizationConfig {
             day_end_time: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
             consider_traffic: true,
             optimization_strategy: OptimizationStrategy::MinimizeTotalTime,
+            min_activity_minutes: DEFAULT_MIN_ACTIVITY_MINUTES,
+        }
+    }
+}
+
+/// Travel between two places in a precomputed matrix
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TravelLeg {
+    pub duration_minutes: i64,
+    pub distance_meters: u32,
+    pub source: DistanceSource,
+    /// Too far apart to visit on the same day
+    pub too_far: bool,
+}
+
+impl TravelLeg {
+    fn from_result(result: &DistanceResult, with_traffic: bool) -> Self {
+        let minutes = if with_traffic {
+            result.duration_in_traffic_minutes.unwrap_or(result.duration_minutes)
+        } else {
+            result.duration_minutes
+        };
+        TravelLeg {
+            duration_minutes: minutes as i64,
+            distance_meters: result.distance_meters,
+            source: result.source,
+            too_far: result.too_far,
         }
     }
+
+    /// Estimated from the straight-line distance when Maps isn't available
+    fn straight_line(from: (f64, f64), to: (f64, f64), prefilter: &DistancePrefilter) -> Self {
+        let miles = haversine_miles(from, to);
+        TravelLeg {
+            duration_minutes: straight_line_minutes(miles),
+            distance_meters: (miles * METERS_PER_MILE) as u32,
+            source: DistanceSource::HaversineFallback,
+            too_far: prefilter.check(from, to).is_some_and(|result| result.too_far),
+        }
+    }
+
+    fn stay() -> Self {
+        TravelLeg {
+            duration_minutes: 0,
+            distance_meters: 0,
+            source: DistanceSource::HaversineShortcircuit,
+            too_far: false,
+        }
+    }
+
+    /// Minutes to drive this leg, `None` when it can't be driven within a day
+    pub fn drivable_minutes(&self) -> Option<i64> {
+        (!self.too_far).then_some(self.duration_minutes)
+    }
 }
 
-pub struct RouteOptimizationService {
-    distance_service: Option<DistanceService>,
+/// `matrix[i][j]` is the leg from place `i` to place `j`, `None` when it couldn't be looked up
+pub type TravelMatrix = Vec<Vec<Option<TravelLeg>>>;
+
+pub struct RouteOptimizationService<M = GoogleMaps> {
+    distance_service: Option<DistanceService<M>>,
     config: OptimizationConfig,
+    prefilter: DistancePrefilter,
 }
 
 impl RouteOptimizationService {
     pub fn new(distance_service: Option<DistanceService>) -> Self {
-        Self {
-            distance_service,
-            config: OptimizationConfig::default(),
-        }
+        Self::with_config(distance_service, OptimizationConfig::default())
     }
+}
 
-    pub fn with_config(distance_service: Option<DistanceService>, config: OptimizationConfig) -> Self {
+impl<M: DistanceMatrix> RouteOptimizationService<M> {
+    pub fn with_config(distance_service: Option<DistanceService<M>>, config: OptimizationConfig) -> Self {
+        let prefilter = distance_service
+            .as_ref()
+            .map(|distance_service| *distance_service.prefilter())
+            .unwrap_or_default();
         Self {
             distance_service,
             config,
+            prefilter,
         }
     }
 
+    /// Straight-line thresholds for pairs too far apart for one day, when there's
+    /// no distance service to take them from
+    pub fn with_prefilter(mut self, prefilter: DistancePrefilter) -> Self {
+        self.prefilter = prefilter;
+        self
+    }
+
     /// Optimize the order of activities for a single day
     pub async fn optimize_daily_route(
         &self,
@@ -110,14 +185,16 @@ impl RouteOptimizationService {
         // Get coordinates for all activities
         let mut activity_coords: Vec<(Activity, (f64, f64))> = activities
             .into_iter()
-            .map(|activity| {
+            .map(|mut activity| {
+                activity.clamp_duration(self.config.min_activity_minutes);
                 let coords = self.get_activity_coordinates(&activity);
                 (activity, coords)
             })
             .collect();
 
-        // Limit to max activities per day
+        // Limit to max activities per day, then drop any too far from the rest
         activity_coords.truncate(self.config.max_activities_per_day);
+        let activity_coords = self.without_too_far_pairs(activity_coords);
 
         // Choose optimization strategy
         let optimized_order = match self.config.optimization_strategy {
@@ -171,21 +248,22 @@ impl RouteOptimizationService {
         let mut best_order = activities.clone();
         let mut best_total_time = i64::MAX;
 
+        let matrix = self.travel_matrix(&route_places(&activities, starting_location)).await;
+
         // Generate all permutations and find the one with minimum travel time
         let indices: Vec<usize> = (0..n).collect();
         let permutations = self.generate_permutations(indices);
 
         for perm in permutations.iter().take(120) { // Limit to reasonable number for performance
             let mut total_time = 0i64;
-            let mut current_location = starting_location;
+            let mut current_place = 0;
 
             for &idx in perm {
-                let activity_location = activities[idx].1;
-                
-                if let Some(travel_time) = self.get_travel_time(current_location, activity_location).await {
+                let travel_time = matrix[current_place][idx + 1].and_then(|leg| leg.drivable_minutes());
+                if let Some(travel_time) = travel_time {
                     total_time += travel_time;
                     total_time += activities[idx].0.duration_minutes as i64;
-                    current_location = activity_location;
+                    current_place = idx + 1;
                 } else {
                     // Skip this permutation if we can't get travel time
                     total_time = i64::MAX;
@@ -209,17 +287,20 @@ impl RouteOptimizationService {
         activities: Vec<(Activity, (f64, f64))>,
         starting_location: (f64, f64),
     ) -> Result<Vec<(Activity, (f64, f64))>, Box<dyn std::error::Error>> {
-        let mut unvisited = activities;
+        let matrix = self.travel_matrix(&route_places(&activities, starting_location)).await;
+
+        let mut unvisited: Vec<_> =
+            activities.into_iter().enumerate().map(|(idx, activity)| (idx + 1, activity)).collect();
         let mut route = Vec::new();
-        let mut current_location = starting_location;
+        let mut current_place = 0;
 
         while !unvisited.is_empty() {
             let mut nearest_idx = 0;
             let mut nearest_time = i64::MAX;
 
             // Find nearest unvisited activity
-            for (idx, (_, coords)) in unvisited.iter().enumerate() {
-                if let Some(travel_time) = self.get_travel_time(current_location, *coords).await {
+            for (idx, (place, _)) in unvisited.iter().enumerate() {
+                if let Some(travel_time) = matrix[current_place][*place].and_then(|leg| leg.drivable_minutes()) {
                     if travel_time < nearest_time {
                         nearest_time = travel_time;
                         nearest_idx = idx;
@@ -228,8 +309,8 @@ impl RouteOptimizationService {
             }
 
             // Add nearest activity to route
-            let nearest_activity = unvisited.remove(nearest_idx);
-            current_location = nearest_activity.1;
+            let (place, nearest_activity) = unvisited.remove(nearest_idx);
+            current_place = place;
             route.push(nearest_activity);
         }
 
@@ -320,10 +401,73 @@ impl RouteOptimizationService {
         Ok(scheduled_activities)
     }
 
+    /// Keep activities in order, dropping any that's too far from one already kept
+    fn without_too_far_pairs(
+        &self,
+        activities: Vec<(Activity, (f64, f64))>,
+    ) -> Vec<(Activity, (f64, f64))> {
+        let mut kept: Vec<(Activity, (f64, f64))> = Vec::new();
+        'candidates: for (activity, coords) in activities {
+            for (other, other_coords) in &kept {
+                if self.is_too_far(*other_coords, coords) {
+                    println!("Activity '{}' is too far from '{}' for the same day, skipping",
+                        activity.title, other.title);
+                    continue 'candidates;
+                }
+            }
+            kept.push((activity, coords));
+        }
+        kept
+    }
+
+    /// Whether two places are too far apart to visit on the same day, by straight
+    /// line so the check never costs a Distance Matrix lookup
+    fn is_too_far(&self, from: (f64, f64), to: (f64, f64)) -> bool {
+        self.prefilter.check(from, to).is_some_and(|result| result.too_far)
+    }
+
+    /// Travel between every pair of `places`, looked up once (cached and batched
+    /// through the Distance Service) so route search doesn't repeat lookups
+    pub async fn travel_matrix(&self, places: &[(f64, f64)]) -> TravelMatrix {
+        let mut matrix: TravelMatrix = match self.distance_service {
+            Some(ref distance_service) => match distance_service
+                .get_distances_batch(
+                    places.to_vec(),
+                    places.to_vec(),
+                    TravelMode::Driving,
+                    self.config.consider_traffic,
+                )
+                .await
+            {
+                Ok(rows) => rows
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|result| Some(TravelLeg::from_result(result, self.config.consider_traffic)))
+                            .collect()
+                    })
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Error getting travel matrix: {}", e);
+                    vec![vec![None; places.len()]; places.len()]
+                }
+            },
+            None => places
+                .iter()
+                .map(|from| places.iter().map(|to| Some(TravelLeg::straight_line(*from, *to, &self.prefilter))).collect())
+                .collect(),
+        };
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] = Some(TravelLeg::stay());
+        }
+        matrix
+    }
+
     /// Get travel time between two coordinates
     async fn get_travel_time(&self, from: (f64, f64), to: (f64, f64)) -> Option<i64> {
         if let Some(ref distance_service) = self.distance_service {
             match distance_service.get_distance(from, to, TravelMode::Driving, self.config.consider_traffic).await {
+                Ok(result) if result.too_far => None,
                 Ok(result) => {
                     let time = if self.config.consider_traffic {
                         result.duration_in_traffic_minutes.unwrap_or(result.duration_minutes)
@@ -345,25 +489,17 @@ impl RouteOptimizationService {
 
     /// Fallback travel time calculation using Haversine distance
     fn calculate_fallback_travel_time(&self, from: (f64, f64), to: (f64, f64)) -> i64 {
-        const EARTH_RADIUS_MILES: f64 = 3959.0;
-        const MINUTES_PER_MILE: f64 = 2.0;
-
-        let lat1_rad = from.0.to_radians();
-        let lat2_rad = to.0.to_radians();
-        let delta_lat = (to.0 - from.0).to_radians();
-        let delta_lon = (to.1 - from.1).to_radians();
-
-        let a = (delta_lat / 2.0).sin().powi(2)
-            + lat1_rad.cos() * lat2_rad.cos() * (delta_lon / 2.0).sin().powi(2);
-        let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
-        let distance_miles = EARTH_RADIUS_MILES * c;
-
-        (distance_miles * MINUTES_PER_MILE) as i64
+        straight_line_minutes(haversine_miles(from, to))
     }
 
     /// Get coordinates for an activity based on its address
     fn get_activity_coordinates(&self, activity: &Activity) -> (f64, f64) {
-        // Try to geocode based on the activity's address
+        // Coordinates stored by the backfill are exact
+        if let Some(point) = &activity.location {
+            return point.lat_lng();
+        }
+
+        // Otherwise guess from the activity's address
         let city = activity.address.city.to_lowercase();
         let state = activity.address.state.to_lowercase();
         
@@ -496,7 +632,14 @@ impl RouteOptimizationService {
     }
 }
 
-#[derive(Debug)]
+/// Places for a route's travel matrix: the start is place 0 and activity `i` is place `i + 1`
+fn route_places(activities: &[(Activity, (f64, f64))], starting_location: (f64, f64)) -> Vec<(f64, f64)> {
+    std::iter::once(starting_location)
+        .chain(activities.iter().map(|(_, coords)| *coords))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct RouteStats {
     pub total_activities: usize,
     pub total_travel_time_minutes: i64,
@@ -505,4 +648,125 @@ pub struct RouteStats {
     pub start_time: Option<NaiveTime>,
     pub end_time: Option<NaiveTime>,
     pub efficiency_ratio: f32, // Activity time / Total time
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cacheless_client, FixedMatrix};
+    use crate::services::generation_budget::{BudgetCaps, GenerationBudget};
+    use std::sync::atomic::Ordering;
+
+    fn activity_at(title: &str) -> Activity {
+        let mut activity = Activity::test(title);
+        activity.address.city = "".to_string();
+        activity.address.state = "".to_string();
+        activity
+    }
+
+    #[actix_rt::test]
+    async fn test_far_pair_is_not_scheduled_on_the_same_day() {
+        let service = RouteOptimizationService::new(None);
+        let denver = (39.7392, -104.9903);
+        let activities = vec![
+            (activity_at("Red Rocks"), (39.6654, -105.2057)),
+            (activity_at("Kansas City barbecue"), (39.0997, -94.5786)),
+            (activity_at("Boulder Flatirons"), (40.0150, -105.2705)),
+        ];
+
+        let kept = service.without_too_far_pairs(activities);
+        let titles: Vec<&str> = kept.iter().map(|(activity, _)| activity.title.as_str()).collect();
+        assert_eq!(titles, vec!["Red Rocks", "Boulder Flatirons"]);
+
+        let day_start = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        let day_end = NaiveTime::from_hms_opt(20, 0, 0).unwrap();
+        let ordered = service.optimize_for_minimal_travel_time(kept, denver).await.unwrap();
+        let schedule = service
+            .schedule_optimized_activities(ordered, denver, day_start, day_end)
+            .await
+            .unwrap();
+        assert_eq!(schedule.len(), 2);
+        assert!(schedule
+            .iter()
+            .all(|scheduled| scheduled.activity.title != "Kansas City barbecue"));
+    }
+
+    #[test]
+    fn test_configured_prefilter_decides_what_is_too_far() {
+        let activities = || {
+            vec![
+                (activity_at("Red Rocks"), (39.6654, -105.2057)),
+                (activity_at("Boulder Flatirons"), (40.0150, -105.2705)),
+            ]
+        };
+        let service = RouteOptimizationService::new(None);
+        assert_eq!(service.without_too_far_pairs(activities()).len(), 2);
+
+        // Red Rocks to Boulder is about 25 miles
+        let strict = RouteOptimizationService::new(None).with_prefilter(DistancePrefilter {
+            max_pair_miles: 10.0,
+            ..Default::default()
+        });
+        let kept = strict.without_too_far_pairs(activities());
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].0.title, "Red Rocks");
+    }
+
+    #[actix_rt::test]
+    async fn test_travel_matrix_without_maps_uses_straight_lines() {
+        let service = RouteOptimizationService::new(None);
+        let denver = (39.7392, -104.9903);
+        let boulder = (40.0150, -105.2705);
+        let miami = (25.7617, -80.1918);
+
+        let matrix = service.travel_matrix(&[denver, boulder, miami]).await;
+        assert_eq!(matrix[0][0].unwrap().duration_minutes, 0);
+        assert_eq!(matrix[0][1], matrix[1][0]);
+        let leg = matrix[0][1].unwrap();
+        assert_eq!(leg.source, DistanceSource::HaversineFallback);
+        assert!(leg.distance_meters > 20_000 && leg.distance_meters < 50_000);
+        assert_eq!(leg.drivable_minutes(), Some(leg.duration_minutes));
+        assert_eq!(matrix[0][2].unwrap().drivable_minutes(), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_spent_maps_budget_stops_further_lookups() {
+        let budget = GenerationBudget::new(BudgetCaps {
+            vertex_queries: 6,
+            maps_lookups: 2,
+        });
+        let matrix = FixedMatrix::default();
+        let distance_service =
+            DistanceService::with_matrix(cacheless_client(), matrix.clone(), DistancePrefilter::default())
+                .with_budget(budget.clone());
+        let service = RouteOptimizationService::with_config(Some(distance_service), OptimizationConfig::default());
+        let denver = (39.7392, -104.9903);
+        let boulder = (40.0150, -105.2705);
+
+        // Denver to Boulder and back uses the whole budget
+        let legs = service.travel_matrix(&[denver, boulder]).await;
+        assert_eq!(legs[0][1].unwrap().source, DistanceSource::GoogleMaps);
+        assert_eq!(legs[1][0].unwrap().source, DistanceSource::GoogleMaps);
+
+        // The same request asking again gets estimates without reaching Maps
+        let legs = service.travel_matrix(&[denver, boulder]).await;
+        assert_eq!(legs[0][1].unwrap().source, DistanceSource::HaversineFallback);
+        assert_eq!(legs[1][0].unwrap().source, DistanceSource::HaversineFallback);
+        assert_eq!(matrix.0.load(Ordering::SeqCst), 2);
+        assert!(budget.report().maps_exhausted);
+    }
+
+    #[actix_rt::test]
+    async fn test_zero_duration_activity_is_scheduled_for_the_minimum() {
+        let service = RouteOptimizationService::new(None);
+        let mut tasting = activity_at("Wine Tasting");
+        tasting.duration_minutes = 0;
+
+        let schedule = service
+            .optimize_daily_route(vec![tasting], (39.7392, -104.9903), false, false)
+            .await
+            .unwrap();
+        assert_eq!(schedule.len(), 1);
+        assert_eq!(schedule[0].activity.duration_minutes, DEFAULT_MIN_ACTIVITY_MINUTES);
+        assert_eq!(service.get_route_stats(&schedule).total_activity_time_minutes, 30);
+    }
+}
@@ -0,0 +1,373 @@
+//! Dry-run scoring of itinerary edits.
+//!
+//! An admin proposes changes to an itinerary (trip details, days, activities that
+//! aren't saved yet) and sees how the itinerary scores for sample searches before
+//! and after. Edits are applied to an in-memory copy; nothing is written. Named
+//! searches come from `SCORE_PREVIEW_PRESETS`, merged over the built-in presets.
+
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::models::activity::Activity;
+use crate::models::itinerary::base::{ordered_days, DayItem, FeaturedVacation, Location};
+use crate::models::search::SearchItinerary;
+use crate::services::search_scoring::{AsyncSearchScorer, ScoreBreakdown, ScoredItinerary, SearchWeights};
+
+/// Sample searches by name
+pub type ScorePresets = BTreeMap<String, SearchItinerary>;
+
+/// Presets available without any configuration
+pub fn default_score_presets() -> ScorePresets {
+    let preset = |search: serde_json::Value| -> SearchItinerary {
+        serde_json::from_value(search).expect("built-in score preset")
+    };
+    BTreeMap::from([
+        (
+            "denver_family".to_string(),
+            preset(serde_json::json!({
+                "locations": ["Denver, CO"],
+                "adults": 2,
+                "children": 2,
+                "activities": ["hiking", "wildlife"],
+                "trip_pace": "relaxed",
+            })),
+        ),
+        (
+            "adventure_couple".to_string(),
+            preset(serde_json::json!({
+                "locations": ["Colorado"],
+                "adults": 2,
+                "activities": ["rafting", "climbing", "hiking"],
+                "trip_pace": "adventure",
+            })),
+        ),
+    ])
+}
+
+/// `SCORE_PREVIEW_PRESETS`: a JSON object of searches by name. A name that's also
+/// built in replaces the built-in search.
+pub fn parse_score_presets(json: &str) -> Result<ScorePresets, serde_json::Error> {
+    let mut presets = default_score_presets();
+    presets.extend(serde_json::from_str::<ScorePresets>(json)?);
+    Ok(presets)
+}
+
+/// Proposed changes to an itinerary. Fields left out keep their current value.
+#[derive(Debug, Default, Deserialize)]
+pub struct ItineraryEdits {
+    pub trip_name: Option<String>,
+    pub description: Option<String>,
+    pub min_group: Option<u32>,
+    pub max_group: Option<u32>,
+    pub start_location: Option<Location>,
+    pub end_location: Option<Location>,
+    pub lodging: Option<Vec<String>>,
+    pub transportation: Option<String>,
+    /// Replaces every day, `[{"day": 1, "items": [...]}, ...]` as itineraries are returned
+    #[serde(default, with = "ordered_days")]
+    pub days: Option<HashMap<String, Vec<DayItem>>>,
+    /// Activities scored as given rather than as stored, each with its `_id`. An
+    /// activity that isn't saved yet still needs an ID for `days` to refer to.
+    #[serde(default)]
+    pub activities: Vec<Activity>,
+}
+
+impl ItineraryEdits {
+    /// A copy of `itinerary` with the edits made
+    pub fn apply(&self, itinerary: &FeaturedVacation) -> FeaturedVacation {
+        let mut edited = itinerary.clone();
+        if let Some(trip_name) = &self.trip_name {
+            edited.trip_name = trip_name.clone();
+        }
+        if let Some(description) = &self.description {
+            edited.description = description.clone();
+        }
+        if let Some(min_group) = self.min_group {
+            edited.min_group = min_group;
+        }
+        if let Some(max_group) = self.max_group {
+            edited.max_group = max_group;
+        }
+        if let Some(start_location) = &self.start_location {
+            edited.start_location = start_location.clone();
+        }
+        if let Some(end_location) = &self.end_location {
+            edited.end_location = end_location.clone();
+        }
+        if let Some(lodging) = &self.lodging {
+            edited.lodging = Some(lodging.clone());
+        }
+        if let Some(transportation) = &self.transportation {
+            edited.transportation = Some(transportation.clone());
+        }
+        if let Some(days) = &self.days {
+            edited.days.days = days.clone();
+        }
+        edited
+    }
+}
+
+/// A search to score against: a preset's name or the search itself
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ScoreScenario {
+    Preset(String),
+    Search(SearchItinerary),
+}
+
+/// Label each scenario, with every preset when none are given
+fn resolve_scenarios(
+    scenarios: Vec<ScoreScenario>,
+    presets: &ScorePresets,
+) -> Result<Vec<(String, SearchItinerary)>, ScorePreviewError> {
+    if scenarios.is_empty() {
+        return Ok(presets.iter().map(|(name, search)| (name.clone(), search.clone())).collect());
+    }
+    scenarios
+        .into_iter()
+        .enumerate()
+        .map(|(index, scenario)| match scenario {
+            ScoreScenario::Preset(name) => match presets.get(&name) {
+                Some(search) => Ok((name, search.clone())),
+                None => Err(ScorePreviewError::UnknownPreset(name)),
+            },
+            ScoreScenario::Search(search) => Ok((format!("scenario {}", index + 1), search)),
+        })
+        .collect()
+}
+
+/// One version's score on the 0-100 scale clients see
+#[derive(Debug, Serialize)]
+pub struct VersionScore {
+    pub match_score: u8,
+    pub total_score: f32,
+    pub breakdown: ScoreBreakdown,
+    pub explanations: Vec<String>,
+}
+
+impl VersionScore {
+    fn new(scored: &ScoredItinerary, weights: &SearchWeights) -> Self {
+        let (match_score, breakdown) = scored.normalized(weights);
+        VersionScore {
+            match_score,
+            total_score: scored.total_score,
+            explanations: breakdown.explanations(),
+            breakdown,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScenarioComparison {
+    pub scenario: String,
+    pub current: VersionScore,
+    pub modified: VersionScore,
+    /// Match score points gained by the edits; negative when they cost points
+    pub change: i16,
+}
+
+/// Score both versions against each scenario. `modified_scorer` is the one that
+/// knows about edited activities.
+async fn compare(
+    current_scorer: &AsyncSearchScorer,
+    current: &FeaturedVacation,
+    modified_scorer: &AsyncSearchScorer,
+    modified: &FeaturedVacation,
+    scenarios: &[(String, SearchItinerary)],
+) -> Vec<ScenarioComparison> {
+    let mut comparisons = Vec::with_capacity(scenarios.len());
+    for (scenario, search) in scenarios {
+        let before = VersionScore::new(
+            &current_scorer.score_itinerary(current, search).await,
+            &current_scorer.weights,
+        );
+        let after = VersionScore::new(
+            &modified_scorer.score_itinerary(modified, search).await,
+            &modified_scorer.weights,
+        );
+        comparisons.push(ScenarioComparison {
+            scenario: scenario.clone(),
+            change: after.match_score as i16 - before.match_score as i16,
+            current: before,
+            modified: after,
+        });
+    }
+    comparisons
+}
+
+#[derive(Debug)]
+pub enum ScorePreviewError {
+    NotFound,
+    UnknownPreset(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ScorePreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScorePreviewError::NotFound => write!(f, "Itinerary not found"),
+            ScorePreviewError::UnknownPreset(name) => write!(f, "Unknown score preset: {}", name),
+            ScorePreviewError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScorePreviewError {}
+
+impl From<mongodb::error::Error> for ScorePreviewError {
+    fn from(e: mongodb::error::Error) -> Self {
+        ScorePreviewError::DatabaseError(e.to_string())
+    }
+}
+
+pub struct ScorePreviewService {
+    client: Arc<Client>,
+    weights: Arc<SearchWeights>,
+}
+
+impl ScorePreviewService {
+    pub fn new(client: Arc<Client>, weights: Arc<SearchWeights>) -> Self {
+        Self { client, weights }
+    }
+
+    fn itineraries(&self) -> Collection<FeaturedVacation> {
+        self.client.database("Itineraries").collection("Featured")
+    }
+
+    /// How the stored itinerary and the edited copy score for each scenario, or
+    /// for every preset when no scenarios are given
+    pub async fn preview(
+        &self,
+        itinerary_id: ObjectId,
+        edits: &ItineraryEdits,
+        scenarios: Vec<ScoreScenario>,
+        presets: &ScorePresets,
+    ) -> Result<Vec<ScenarioComparison>, ScorePreviewError> {
+        let scenarios = resolve_scenarios(scenarios, presets)?;
+        let current = self
+            .itineraries()
+            .find_one(doc! { "_id": itinerary_id })
+            .await?
+            .ok_or(ScorePreviewError::NotFound)?;
+        let modified = edits.apply(&current);
+
+        let current_scorer = AsyncSearchScorer::with_weights(self.client.clone(), self.weights.clone());
+        let modified_scorer = AsyncSearchScorer::with_weights(self.client.clone(), self.weights.clone())
+            .with_activity_overrides(edits.activities.iter().cloned());
+        Ok(compare(&current_scorer, &current, &modified_scorer, &modified, &scenarios).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(title: &str, activity_types: &[&str]) -> Activity {
+        Activity {
+            description: String::new(),
+            activity_types: activity_types.iter().map(|t| t.to_string()).collect(),
+            duration_minutes: 120,
+            ..Activity::test(title)
+        }
+    }
+
+    fn day_items(activities: &[&Activity]) -> Vec<DayItem> {
+        activities
+            .iter()
+            .map(|activity| DayItem::Activity {
+                time: "09:00:00".to_string(),
+                activity_id: activity.id.unwrap(),
+            })
+            .collect()
+    }
+
+    fn scorer(activities: &[&Activity]) -> AsyncSearchScorer {
+        let client = mongodb::Client::with_options(
+            mongodb::options::ClientOptions::builder()
+                .hosts(vec![mongodb::options::ServerAddress::Tcp {
+                    host: "localhost".to_string(),
+                    port: Some(27017),
+                }])
+                .build(),
+        )
+        .unwrap();
+        AsyncSearchScorer::with_weights(Arc::new(client), Arc::new(SearchWeights::default()))
+            .with_activity_overrides(activities.iter().map(|activity| (*activity).clone()))
+    }
+
+    #[actix_rt::test]
+    async fn test_adding_a_hike_raises_the_hiking_score() {
+        let museum = activity("Art Museum", &["culture"]);
+        let hike = activity("Mountain Trail", &["hiking"]);
+        let mut current = FeaturedVacation {
+            trip_name: "City break".to_string(),
+            min_group: 1,
+            max_group: 6,
+            ..Default::default()
+        };
+        current.days.days.insert("1".to_string(), day_items(&[&museum]));
+
+        let edits = ItineraryEdits {
+            days: Some(HashMap::from([("1".to_string(), day_items(&[&museum, &hike]))])),
+            activities: vec![hike.clone()],
+            ..Default::default()
+        };
+        let modified = edits.apply(&current);
+        let scenarios = resolve_scenarios(
+            vec![serde_json::from_value(serde_json::json!({ "adults": 2, "activities": ["hiking"] })).unwrap()],
+            &default_score_presets(),
+        )
+        .unwrap();
+
+        // Every activity is in memory, so neither scorer goes to the database
+        let comparisons = compare(
+            &scorer(&[&museum]),
+            &current,
+            &scorer(&[&museum, &hike]),
+            &modified,
+            &scenarios,
+        )
+        .await;
+        let comparison = &comparisons[0];
+        assert_eq!(comparison.scenario, "scenario 1");
+        assert_eq!(comparison.current.breakdown.activity_score, 0.0);
+        assert_eq!(comparison.modified.breakdown.activity_score, 100.0);
+        assert!(comparison.change > 0);
+        assert!(comparison.modified.explanations.contains(&"'hiking' matches an activity directly".to_string()));
+        assert!(comparison.current.explanations.contains(&"'hiking' isn't on this itinerary".to_string()));
+
+        // The edits only touched the copy
+        assert_eq!(current.days.days["1"].len(), 1);
+        assert_eq!(modified.days.days["1"].len(), 2);
+        assert_eq!(modified.trip_name, current.trip_name);
+    }
+
+    #[test]
+    fn test_scenarios_default_to_every_preset() {
+        let presets = parse_score_presets(r#"{"ski_weekend": {"locations": ["Vail, CO"], "activities": ["skiing"]}}"#)
+            .unwrap();
+        let names: Vec<String> = resolve_scenarios(Vec::new(), &presets)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["adventure_couple", "denver_family", "ski_weekend"]);
+
+        let scenarios: Vec<ScoreScenario> =
+            serde_json::from_value(serde_json::json!(["denver_family", { "adults": 4 }])).unwrap();
+        let resolved = resolve_scenarios(scenarios, &presets).unwrap();
+        assert_eq!(resolved[0].0, "denver_family");
+        assert_eq!(resolved[0].1.children, Some(2));
+        assert_eq!(resolved[1].0, "scenario 2");
+        assert_eq!(resolved[1].1.adults, Some(4));
+
+        let unknown = resolve_scenarios(vec![ScoreScenario::Preset("honeymoon".to_string())], &presets);
+        assert!(matches!(unknown, Err(ScorePreviewError::UnknownPreset(name)) if name == "honeymoon"));
+        assert!(parse_score_presets("[]").is_err());
+    }
+}
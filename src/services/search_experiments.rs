@@ -0,0 +1,571 @@
+//! Search weight experiments, so product can try a different set of scoring
+//! weights on part of the traffic without a redeploy.
+//!
+//! An experiment in `Options.SearchExperiments` overrides some of the
+//! `SearchWeights` for `traffic_percent` of searches between its start and end.
+//! Each search is put in one of 100 buckets by hashing the experiment's name with
+//! the signed-in user's id, or with the `X-Session-Id` an anonymous client sends,
+//! so the same visitor keeps the same weights for as long as the experiment runs.
+//! Searches with neither use the default weights.
+//!
+//! Only one experiment can be active at a time. Activating a second is refused,
+//! and a partial unique index backs that up when two admins race.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::services::search_scoring::SearchWeights;
+use crate::services::webhook_replay::is_duplicate_key;
+
+/// Header an anonymous client sends to keep its experiment assignment between searches
+pub const SESSION_ID_HEADER: &str = "X-Session-Id";
+
+/// Longest session id used for assignment; longer ones are ignored
+const MAX_SESSION_ID_LEN: usize = 128;
+
+/// Weights an experiment changes. Those left out keep their configured value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeightOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location_weight: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity_weight: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_size_weight: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lodging_weight: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transportation_weight: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trip_pace_weight: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum_score: Option<f32>,
+}
+
+impl WeightOverrides {
+    fn values(&self) -> [(&'static str, Option<f32>); 7] {
+        [
+            ("location_weight", self.location_weight),
+            ("activity_weight", self.activity_weight),
+            ("group_size_weight", self.group_size_weight),
+            ("lodging_weight", self.lodging_weight),
+            ("transportation_weight", self.transportation_weight),
+            ("trip_pace_weight", self.trip_pace_weight),
+            ("minimum_score", self.minimum_score),
+        ]
+    }
+
+    /// `base` with these overrides applied
+    pub fn apply(&self, base: &SearchWeights) -> SearchWeights {
+        SearchWeights {
+            location_weight: self.location_weight.unwrap_or(base.location_weight),
+            activity_weight: self.activity_weight.unwrap_or(base.activity_weight),
+            group_size_weight: self.group_size_weight.unwrap_or(base.group_size_weight),
+            lodging_weight: self.lodging_weight.unwrap_or(base.lodging_weight),
+            transportation_weight: self.transportation_weight.unwrap_or(base.transportation_weight),
+            trip_pace_weight: self.trip_pace_weight.unwrap_or(base.trip_pace_weight),
+            minimum_score: self.minimum_score.unwrap_or(base.minimum_score),
+        }
+    }
+
+    fn validate(&self) -> Result<(), SearchExperimentError> {
+        let set: Vec<_> = self.values().into_iter().filter_map(|(name, value)| Some((name, value?))).collect();
+        if set.is_empty() {
+            return Err(SearchExperimentError::Invalid("weights must override at least one weight".to_string()));
+        }
+        match set.iter().find(|(_, value)| !value.is_finite() || *value < 0.0) {
+            Some((name, value)) => Err(SearchExperimentError::Invalid(format!(
+                "{} must be a number of at least 0, not {}",
+                name, value
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchExperiment {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// Reported as `meta.experiment` and in the search submission log
+    pub name: String,
+    pub weights: WeightOverrides,
+    /// Share of searches, 1 to 100, that get the experiment's weights
+    pub traffic_percent: u8,
+    pub starts_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime>,
+    pub active: bool,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl SearchExperiment {
+    /// Active, and between its start and end
+    pub fn is_running(&self, now: DateTime) -> bool {
+        self.active && self.starts_at <= now && self.ends_at.is_none_or(|ends_at| now < ends_at)
+    }
+
+    /// Whether searches by `subject` (a user id or session id) get this experiment
+    pub fn includes(&self, subject: &str) -> bool {
+        bucket(&self.name, subject) < self.traffic_percent
+    }
+}
+
+/// Which of 100 buckets `subject` falls in for `experiment`. Hashing the name in
+/// too means consecutive experiments don't all land on the same visitors.
+pub fn bucket(experiment: &str, subject: &str) -> u8 {
+    let digest = Sha256::digest(format!("{}:{}", experiment, subject).as_bytes());
+    let mut first = [0; 8];
+    first.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(first) % 100) as u8
+}
+
+/// The id searches are assigned by: the signed-in user's, else the anonymous
+/// session's. Blank and overlong session ids are ignored.
+pub fn assignment_subject(user_id: Option<ObjectId>, session_id: Option<&str>) -> Option<String> {
+    if let Some(user_id) = user_id {
+        return Some(user_id.to_hex());
+    }
+    session_id
+        .map(str::trim)
+        .filter(|session_id| !session_id.is_empty() && session_id.len() <= MAX_SESSION_ID_LEN)
+        .map(|session_id| format!("session:{}", session_id))
+}
+
+/// Body of `POST /admin/search-experiments` and `PUT /admin/search-experiments/{id}`
+#[derive(Debug, Deserialize)]
+pub struct ExperimentInput {
+    pub name: String,
+    pub weights: WeightOverrides,
+    pub traffic_percent: u8,
+    /// Defaults to now
+    #[serde(default)]
+    pub starts_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub ends_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ExperimentInput {
+    /// The name trimmed, and the dates
+    fn validate(&self, now: DateTime) -> Result<(String, DateTime, Option<DateTime>), SearchExperimentError> {
+        let name = self.name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(SearchExperimentError::Invalid(
+                "name must be lowercase letters, digits and underscores".to_string(),
+            ));
+        }
+        if !(1..=100).contains(&self.traffic_percent) {
+            return Err(SearchExperimentError::Invalid("traffic_percent must be 1 to 100".to_string()));
+        }
+        self.weights.validate()?;
+
+        let to_bson = |at: chrono::DateTime<chrono::Utc>| DateTime::from_millis(at.timestamp_millis());
+        let starts_at = self.starts_at.map_or(now, to_bson);
+        let ends_at = self.ends_at.map(to_bson);
+        if ends_at.is_some_and(|ends_at| ends_at <= starts_at) {
+            return Err(SearchExperimentError::Invalid("ends_at must be after starts_at".to_string()));
+        }
+        Ok((name.to_string(), starts_at, ends_at))
+    }
+}
+
+#[derive(Debug)]
+pub enum SearchExperimentError {
+    Invalid(String),
+    NameTaken(String),
+    NotFound,
+    /// Another experiment, named here, is already active
+    AnotherActive(String),
+    /// The experiment's end date has passed
+    Ended,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for SearchExperimentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SearchExperimentError::Invalid(msg) => write!(f, "{}", msg),
+            SearchExperimentError::NameTaken(name) => write!(f, "An experiment named {} already exists", name),
+            SearchExperimentError::NotFound => write!(f, "Search experiment not found"),
+            SearchExperimentError::AnotherActive(name) => {
+                write!(f, "Experiment {} is already active; deactivate it first", name)
+            }
+            SearchExperimentError::Ended => write!(f, "The experiment has already ended"),
+            SearchExperimentError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SearchExperimentError {}
+
+impl From<mongodb::error::Error> for SearchExperimentError {
+    fn from(err: mongodb::error::Error) -> Self {
+        SearchExperimentError::DatabaseError(err.to_string())
+    }
+}
+
+/// A change to an experiment, kept in the admin audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchExperimentAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub experiment_id: ObjectId,
+    pub name: String,
+    pub created_at: DateTime,
+}
+
+pub struct SearchExperimentService {
+    client: Arc<Client>,
+}
+
+impl SearchExperimentService {
+    pub fn new(client: Arc<Client>) -> Self {
+        SearchExperimentService { client }
+    }
+
+    fn experiments(&self) -> Collection<SearchExperiment> {
+        self.client.database("Options").collection("SearchExperiments")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let unique_name = IndexModel::builder()
+            .keys(doc! { "name": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        // At most one active experiment
+        let one_active = IndexModel::builder()
+            .keys(doc! { "active": 1 })
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { "active": true })
+                    .build(),
+            )
+            .build();
+        self.experiments().create_indexes([unique_name, one_active]).await?;
+        Ok(())
+    }
+
+    /// Every experiment, newest first
+    pub async fn list(&self) -> Result<Vec<SearchExperiment>, mongodb::error::Error> {
+        self.experiments()
+            .find(doc! {})
+            .sort(doc! { "created_at": -1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// The experiment a search by `subject` gets, if one is running and the
+    /// subject's bucket is in its traffic. Lookup failures fall back to no
+    /// experiment, so a search never fails over one.
+    pub async fn assign(&self, subject: Option<&str>, now: DateTime) -> Option<SearchExperiment> {
+        let subject = subject?;
+        match self.experiments().find_one(doc! { "active": true }).await {
+            Ok(experiment) => experiment.filter(|experiment| experiment.is_running(now) && experiment.includes(subject)),
+            Err(e) => {
+                eprintln!("Failed to look up the active search experiment: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Add an experiment. It starts inactive.
+    pub async fn create(&self, admin_id: ObjectId, input: ExperimentInput) -> Result<SearchExperiment, SearchExperimentError> {
+        let now = DateTime::now();
+        let (name, starts_at, ends_at) = input.validate(now)?;
+        let mut experiment = SearchExperiment {
+            id: None,
+            name,
+            weights: input.weights,
+            traffic_percent: input.traffic_percent,
+            starts_at,
+            ends_at,
+            active: false,
+            created_at: now,
+            updated_at: now,
+        };
+        match self.experiments().insert_one(&experiment).await {
+            Ok(result) => experiment.id = result.inserted_id.as_object_id(),
+            Err(e) if is_duplicate_key(&e) => return Err(SearchExperimentError::NameTaken(experiment.name)),
+            Err(e) => return Err(e.into()),
+        }
+        self.audit("search_experiment_created", admin_id, &experiment).await;
+        Ok(experiment)
+    }
+
+    /// Replace an experiment's name, weights, traffic and dates. Whether it's
+    /// active doesn't change.
+    pub async fn update(
+        &self,
+        admin_id: ObjectId,
+        id: ObjectId,
+        input: ExperimentInput,
+    ) -> Result<SearchExperiment, SearchExperimentError> {
+        let now = DateTime::now();
+        let (name, starts_at, ends_at) = input.validate(now)?;
+        let weights = mongodb::bson::to_bson(&input.weights)
+            .map_err(|e| SearchExperimentError::DatabaseError(e.to_string()))?;
+        let update = doc! {
+            "$set": {
+                "name": &name,
+                "weights": weights,
+                "traffic_percent": input.traffic_percent as i32,
+                "starts_at": starts_at,
+                "ends_at": ends_at,
+                "updated_at": now,
+            }
+        };
+        let updated = match self
+            .experiments()
+            .find_one_and_update(doc! { "_id": id }, update)
+            .return_document(mongodb::options::ReturnDocument::After)
+            .await
+        {
+            Ok(updated) => updated.ok_or(SearchExperimentError::NotFound)?,
+            Err(e) if is_duplicate_key(&e) => return Err(SearchExperimentError::NameTaken(name)),
+            Err(e) => return Err(e.into()),
+        };
+        self.audit("search_experiment_updated", admin_id, &updated).await;
+        Ok(updated)
+    }
+
+    /// Start giving searches the experiment's weights. Refused while another
+    /// experiment is active, and once the experiment has ended.
+    pub async fn activate(&self, admin_id: ObjectId, id: ObjectId) -> Result<SearchExperiment, SearchExperimentError> {
+        let experiment = self
+            .experiments()
+            .find_one(doc! { "_id": id })
+            .await?
+            .ok_or(SearchExperimentError::NotFound)?;
+        if experiment.active {
+            return Ok(experiment);
+        }
+        let now = DateTime::now();
+        if experiment.ends_at.is_some_and(|ends_at| ends_at <= now) {
+            return Err(SearchExperimentError::Ended);
+        }
+        if let Some(other) = self.experiments().find_one(doc! { "active": true }).await? {
+            return Err(SearchExperimentError::AnotherActive(other.name));
+        }
+
+        let activated = match self
+            .experiments()
+            .find_one_and_update(
+                doc! { "_id": id },
+                doc! { "$set": { "active": true, "updated_at": now } },
+            )
+            .return_document(mongodb::options::ReturnDocument::After)
+            .await
+        {
+            Ok(activated) => activated.ok_or(SearchExperimentError::NotFound)?,
+            // Another one was activated since the check above
+            Err(e) if is_duplicate_key(&e) => {
+                let other = self.experiments().find_one(doc! { "active": true }).await?;
+                return Err(SearchExperimentError::AnotherActive(
+                    other.map(|other| other.name).unwrap_or_default(),
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        println!("🧪 Search experiment {} activated by admin {}", activated.name, admin_id);
+        self.audit("search_experiment_activated", admin_id, &activated).await;
+        Ok(activated)
+    }
+
+    /// Stop the experiment. Searches go back to the default weights at once.
+    pub async fn deactivate(&self, admin_id: ObjectId, id: ObjectId) -> Result<SearchExperiment, SearchExperimentError> {
+        let deactivated = self
+            .experiments()
+            .find_one_and_update(
+                doc! { "_id": id },
+                doc! { "$set": { "active": false, "updated_at": DateTime::now() } },
+            )
+            .return_document(mongodb::options::ReturnDocument::After)
+            .await?
+            .ok_or(SearchExperimentError::NotFound)?;
+        println!("🧪 Search experiment {} deactivated by admin {}", deactivated.name, admin_id);
+        self.audit("search_experiment_deactivated", admin_id, &deactivated).await;
+        Ok(deactivated)
+    }
+
+    async fn audit(&self, action: &str, admin_id: ObjectId, experiment: &SearchExperiment) {
+        let Some(experiment_id) = experiment.id else {
+            return;
+        };
+        let audit = SearchExperimentAudit {
+            id: None,
+            action: action.to_string(),
+            admin_id,
+            experiment_id,
+            name: experiment.name.clone(),
+            created_at: DateTime::now(),
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<SearchExperimentAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for search experiment {}: {}", experiment.name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::itinerary::base::FeaturedVacation;
+    use crate::models::search::SearchItinerary;
+    use crate::services::search_scoring::SearchScorer;
+    use serde_json::json;
+
+    fn experiment(traffic_percent: u8) -> SearchExperiment {
+        SearchExperiment {
+            id: Some(ObjectId::new()),
+            name: "heavier_activities".to_string(),
+            weights: WeightOverrides {
+                location_weight: Some(10.0),
+                activity_weight: Some(60.0),
+                ..Default::default()
+            },
+            traffic_percent,
+            starts_at: DateTime::from_millis(0),
+            ends_at: None,
+            active: true,
+            created_at: DateTime::from_millis(0),
+            updated_at: DateTime::from_millis(0),
+        }
+    }
+
+    #[test]
+    fn test_assignment_is_stable_for_the_same_user() {
+        let half = experiment(50);
+        let user_id = ObjectId::new();
+        let subject = assignment_subject(Some(user_id), Some("ignored")).unwrap();
+        assert_eq!(subject, user_id.to_hex());
+        let first = half.includes(&subject);
+        assert!((0..20).all(|_| half.includes(&subject) == first));
+        assert_eq!(bucket(&half.name, &subject), bucket("heavier_activities", &user_id.to_hex()));
+
+        // Roughly the traffic share across many visitors, none of them at 0 or 100
+        let subjects: Vec<String> = (0..2000).map(|_| ObjectId::new().to_hex()).collect();
+        let included = subjects.iter().filter(|subject| half.includes(subject)).count();
+        assert!((800..1200).contains(&included), "{} of 2000 included", included);
+        assert!(subjects.iter().all(|subject| experiment(100).includes(subject)));
+        assert!(!subjects.iter().any(|subject| experiment(0).includes(subject)));
+
+        assert_eq!(assignment_subject(None, Some(" abc ")), Some("session:abc".to_string()));
+        assert_eq!(assignment_subject(None, Some("  ")), None);
+        assert_eq!(assignment_subject(None, Some(&"x".repeat(129))), None);
+        assert_eq!(assignment_subject(None, None), None);
+    }
+
+    #[test]
+    fn test_experiments_only_run_between_their_dates() {
+        let mut running = experiment(100);
+        running.starts_at = DateTime::from_millis(1_000);
+        running.ends_at = Some(DateTime::from_millis(2_000));
+        assert!(!running.is_running(DateTime::from_millis(999)));
+        assert!(running.is_running(DateTime::from_millis(1_000)));
+        assert!(!running.is_running(DateTime::from_millis(2_000)));
+        running.active = false;
+        assert!(!running.is_running(DateTime::from_millis(1_500)));
+    }
+
+    #[test]
+    fn test_weight_override_changes_the_ranking() {
+        use crate::models::itinerary::base::{DayItem, Days, Location};
+
+        let itinerary = |trip_name: &str, (city, state): (&str, &str), activities: usize| {
+            let location: Location =
+                serde_json::from_value(json!({ "city": city, "state": state, "coordinates": [0.0, 0.0] })).unwrap();
+            let day = (0..activities)
+                .map(|_| DayItem::Activity {
+                    time: "09:00:00".to_string(),
+                    activity_id: ObjectId::new(),
+                })
+                .collect();
+            FeaturedVacation {
+                id: Some(ObjectId::new()),
+                trip_name: trip_name.to_string(),
+                min_group: 1,
+                max_group: 8,
+                start_location: location.clone(),
+                end_location: location,
+                days: Days {
+                    days: std::collections::HashMap::from([("1".to_string(), day)]),
+                },
+                ..Default::default()
+            }
+        };
+        let search: SearchItinerary = serde_json::from_value(json!({
+            "locations": ["Denver, CO"],
+            "activities": ["rafting"],
+            "adults": 2
+        }))
+        .unwrap();
+        // The city searched for with nothing to do, or the activity asked for elsewhere
+        let itineraries = vec![
+            itinerary("Denver Downtime", ("Denver", "CO"), 0),
+            itinerary("Green River Rafting", ("Moab", "UT"), 1),
+        ];
+        let ranked = |weights: SearchWeights| -> Vec<String> {
+            SearchScorer::with_weights(Arc::new(weights))
+                .score_and_rank_itineraries(itineraries.clone(), &search)
+                .into_iter()
+                .map(|scored| scored.itinerary.trip_name)
+                .collect()
+        };
+
+        assert_eq!(ranked(SearchWeights::default()), ["Denver Downtime", "Green River Rafting"]);
+        let heavier = experiment(100).weights.apply(&SearchWeights::default());
+        assert_eq!(heavier.activity_weight, 60.0);
+        assert_eq!(heavier.group_size_weight, SearchWeights::default().group_size_weight);
+        assert_eq!(ranked(heavier), ["Green River Rafting", "Denver Downtime"]);
+    }
+
+    #[test]
+    fn test_experiment_input_is_validated() {
+        let input = |value: serde_json::Value| serde_json::from_value::<ExperimentInput>(value);
+        let now = DateTime::now();
+        let valid = input(json!({
+            "name": "heavier_activities",
+            "weights": { "activity_weight": 45.0 },
+            "traffic_percent": 20
+        }))
+        .unwrap();
+        assert_eq!(valid.validate(now).unwrap(), ("heavier_activities".to_string(), now, None));
+
+        for invalid in [
+            json!({ "name": "Heavier Activities", "weights": { "activity_weight": 45.0 }, "traffic_percent": 20 }),
+            json!({ "name": "a", "weights": {}, "traffic_percent": 20 }),
+            json!({ "name": "a", "weights": { "lodging_weight": -1.0 }, "traffic_percent": 20 }),
+            json!({ "name": "a", "weights": { "activity_weight": 45.0 }, "traffic_percent": 0 }),
+            json!({
+                "name": "a", "weights": { "activity_weight": 45.0 }, "traffic_percent": 20,
+                "starts_at": "2026-11-01T00:00:00Z", "ends_at": "2026-10-01T00:00:00Z"
+            }),
+        ] {
+            assert!(matches!(input(invalid).unwrap().validate(now), Err(SearchExperimentError::Invalid(_))));
+        }
+        // A misspelt weight is refused rather than silently ignored
+        assert!(input(json!({ "name": "a", "weights": { "activty_weight": 45.0 }, "traffic_percent": 20 })).is_err());
+    }
+}
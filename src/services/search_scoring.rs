@@ -1,10 +1,14 @@
 use crate::models::{activity::Activity, itinerary::base::FeaturedVacation, search::SearchItinerary};
+use crate::services::activity_synonyms::ActivitySynonyms;
+use crate::services::location_terms::{self, LocationTerm};
 use futures::TryStreamExt;
 use mongodb::{bson::oid::ObjectId, Client};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Loaded once into `AppConfig` at startup and shared by every scorer
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SearchWeights {
     /// Weight for location matching (start/end cities)
     pub location_weight: f32,
@@ -22,8 +26,27 @@ pub struct SearchWeights {
     pub minimum_score: f32,
 }
 
+#[cfg(test)]
+thread_local! {
+    /// `SearchWeights` built on this thread, so tests can check scoring doesn't build its own
+    static WEIGHTS_BUILT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn count_weights_built() {
+    #[cfg(test)]
+    WEIGHTS_BUILT.with(|built| built.set(built.get() + 1));
+}
+
+impl Clone for SearchWeights {
+    fn clone(&self) -> Self {
+        count_weights_built();
+        Self { ..*self }
+    }
+}
+
 impl Default for SearchWeights {
     fn default() -> Self {
+        count_weights_built();
         Self {
             location_weight: 35.0,
             activity_weight: 30.0,
@@ -37,48 +60,89 @@ impl Default for SearchWeights {
 }
 
 impl SearchWeights {
-    /// Create weights from environment variables or use defaults
-    pub fn from_env() -> Self {
-        let defaults = Self::default();
+    /// Highest total score an itinerary can reach
+    pub fn max_score(&self) -> f32 {
+        self.location_weight
+            + self.activity_weight
+            + self.group_size_weight
+            + self.lodging_weight
+            + self.transportation_weight
+            + self.trip_pace_weight
+    }
 
-        Self {
-            location_weight: std::env::var("SEARCH_LOCATION_WEIGHT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.location_weight),
-            activity_weight: std::env::var("SEARCH_ACTIVITY_WEIGHT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.activity_weight),
-            group_size_weight: std::env::var("SEARCH_GROUP_SIZE_WEIGHT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.group_size_weight),
-            lodging_weight: std::env::var("SEARCH_LODGING_WEIGHT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.lodging_weight),
-            transportation_weight: std::env::var("SEARCH_TRANSPORT_WEIGHT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.transportation_weight),
-            trip_pace_weight: std::env::var("SEARCH_TRIP_PACE_WEIGHT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.trip_pace_weight),
-            minimum_score: std::env::var("SEARCH_MIN_SCORE")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(defaults.minimum_score),
+    /// Highest total score an itinerary can reach for `search`. Lodging,
+    /// transportation and trip pace only count when the search asks for them,
+    /// since they score nothing otherwise.
+    pub fn max_score_for(&self, search: &SearchItinerary) -> f32 {
+        let mut max_score = self.max_score();
+        if !wants_lodging(search) {
+            max_score -= self.lodging_weight;
+        }
+        if !wants_transportation(search) {
+            max_score -= self.transportation_weight;
+        }
+        if search.trip_pace.is_none() {
+            max_score -= self.trip_pace_weight;
         }
+        max_score
     }
 }
 
+fn wants_lodging(search: &SearchItinerary) -> bool {
+    search.lodging.as_ref().is_some_and(|lodging| !lodging.is_empty())
+}
+
+fn wants_transportation(search: &SearchItinerary) -> bool {
+    search
+        .transportation
+        .as_deref()
+        .is_some_and(|transportation| !transportation.trim().is_empty())
+}
+
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct ScoredItinerary {
     pub itinerary: FeaturedVacation,
     pub total_score: f32,
     pub score_breakdown: ScoreBreakdown,
+    /// Highest total the search could have scored (`SearchWeights::max_score_for`)
+    #[serde(skip)]
+    pub max_score: f32,
+}
+
+impl ScoredItinerary {
+    /// Total score as a percentage of what the search could have scored
+    pub fn match_percentage(&self) -> f32 {
+        if self.max_score > 0.0 {
+            ((self.total_score / self.max_score) * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Match score and breakdown on the 0-100 scale shown to clients, each
+    /// component relative to its own weight
+    pub fn normalized(&self, weights: &SearchWeights) -> (u8, ScoreBreakdown) {
+        fn percent(score: f32, weight: f32) -> f32 {
+            if weight > 0.0 {
+                ((score / weight) * 100.0).clamp(0.0, 100.0)
+            } else {
+                0.0
+            }
+        }
+
+        let match_score = self.match_percentage() as u8;
+
+        let mut breakdown = self.score_breakdown.clone();
+        breakdown.location_score = percent(breakdown.location_score, weights.location_weight);
+        breakdown.activity_score = percent(breakdown.activity_score, weights.activity_weight);
+        breakdown.group_size_score = percent(breakdown.group_size_score, weights.group_size_weight);
+        breakdown.lodging_score = percent(breakdown.lodging_score, weights.lodging_weight);
+        breakdown.transportation_score =
+            percent(breakdown.transportation_score, weights.transportation_weight);
+        breakdown.trip_pace_score = percent(breakdown.trip_pace_score, weights.trip_pace_weight);
+
+        (match_score, breakdown)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -89,415 +153,605 @@ pub struct ScoreBreakdown {
     pub lodging_score: f32,
     pub transportation_score: f32,
     pub trip_pace_score: f32,
+    /// One entry per requested activity term. Absent on breakdowns stored before it existed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub activity_matches: Vec<ActivityMatch>,
+    /// exact_city, partial, state (a search for the whole state) or state_only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location_match_type: Option<String>,
+    /// within_range, near or far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_size_fit: Option<String>,
 }
 
-#[derive(Default)]
-pub struct SearchScorer {
-    pub weights: SearchWeights,
+impl ScoreBreakdown {
+    /// Why each dimension scored what it did, in words for admins
+    pub fn explanations(&self) -> Vec<String> {
+        let mut explanations = Vec::new();
+        if let Some(match_type) = &self.location_match_type {
+            explanations.push(
+                match match_type.as_str() {
+                    "exact_city" => "Starts or ends in the searched city",
+                    "partial" => "Starts or ends in a city whose name partly matches the search",
+                    "state" => "Starts or ends in the searched state",
+                    "state_only" => "In the searched city's state, but not that city",
+                    other => other,
+                }
+                .to_string(),
+            );
+        }
+        for activity_match in &self.activity_matches {
+            explanations.push(match activity_match.matched_via.as_deref() {
+                Some(MATCHED_DIRECT) => format!("'{}' matches an activity directly", activity_match.requested),
+                Some(_) => format!("'{}' matches a related activity", activity_match.requested),
+                None => format!("'{}' isn't on this itinerary", activity_match.requested),
+            });
+        }
+        if let Some(fit) = &self.group_size_fit {
+            explanations.push(
+                match fit.as_str() {
+                    "within_range" => "The party fits the group size",
+                    "near" => "The party is just outside the group size",
+                    "far" => "The party is well outside the group size",
+                    other => other,
+                }
+                .to_string(),
+            );
+        }
+        explanations
+    }
 }
 
-pub struct AsyncSearchScorer {
-    pub weights: SearchWeights,
-    pub client: Arc<Client>,
+/// How a single requested activity term matched an itinerary
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ActivityMatch {
+    pub requested: String,
+    pub matched: bool,
+    /// "direct" when the term itself was found, "synonym" when only a synonym was
+    pub matched_via: Option<String>,
+    /// Activities that matched the term (only known when activities were looked up)
+    pub matched_activity_ids: Vec<ObjectId>,
 }
 
-impl SearchScorer {
-    pub fn new() -> Self {
-        let weights = SearchWeights::from_env();
-        println!("SearchScorer initialized with weights: {:?}", weights);
-        Self { weights }
-    }
+/// Location match for a state-level search term. The traveler named no city, so
+/// any itinerary in the state is close to what they asked for.
+const STATE_SEARCH_MATCH: f32 = 0.8;
+
+/// Lowercase state code when `state` names a US state, else `state` lowercased,
+/// so "Colorado" and "CO" compare equal
+fn state_key(state: &str) -> String {
+    location_terms::resolve_state(state)
+        .map(|state| state.code)
+        .unwrap_or(state)
+        .trim()
+        .to_lowercase()
+}
 
-    pub fn with_weights(weights: SearchWeights) -> Self {
-        Self { weights }
+const MATCHED_DIRECT: &str = "direct";
+const MATCHED_SYNONYM: &str = "synonym";
+
+/// Score every dimension but activities, which each scorer matches its own way
+fn scored_itinerary(
+    weights: &SearchWeights,
+    itinerary: &FeaturedVacation,
+    search: &SearchItinerary,
+    (activity_score, activity_matches): (f32, Vec<ActivityMatch>),
+) -> ScoredItinerary {
+    let (location_score, location_match_type) = score_location(weights, itinerary, search);
+    let (group_size_score, group_size_fit) = score_group_size(weights, itinerary, search);
+    let lodging_score = score_lodging(weights, itinerary, search);
+    let transportation_score = score_transportation(weights, itinerary, search);
+    let trip_pace_score = score_trip_pace(weights, itinerary, search);
+
+    let total_score = location_score
+        + activity_score
+        + group_size_score
+        + lodging_score
+        + transportation_score
+        + trip_pace_score;
+
+    ScoredItinerary {
+        itinerary: itinerary.clone(),
+        total_score,
+        score_breakdown: ScoreBreakdown {
+            location_score,
+            activity_score,
+            group_size_score,
+            lodging_score,
+            transportation_score,
+            trip_pace_score,
+            activity_matches,
+            location_match_type,
+            group_size_fit,
+        },
+        max_score: weights.max_score_for(search),
     }
+}
 
-    /// Score an itinerary against search criteria
-    pub fn score_itinerary(
-        &self,
-        itinerary: &FeaturedVacation,
-        search: &SearchItinerary,
-    ) -> ScoredItinerary {
-        let location_score = self.score_location(itinerary, search);
-        let activity_score = self.score_activities(itinerary, search);
-        let group_size_score = self.score_group_size(itinerary, search);
-        let lodging_score = self.score_lodging(itinerary, search);
-        let transportation_score = self.score_transportation(itinerary, search);
-        let trip_pace_score = self.score_trip_pace(itinerary, search);
-
-        let total_score = location_score
-            + activity_score
-            + group_size_score
-            + lodging_score
-            + transportation_score
-            + trip_pace_score;
-
-        ScoredItinerary {
-            itinerary: itinerary.clone(),
-            total_score,
-            score_breakdown: ScoreBreakdown {
-                location_score,
-                activity_score,
-                group_size_score,
-                lodging_score,
-                transportation_score,
-                trip_pace_score,
-            },
+/// Score location matching, along with the kind of match that produced the score
+fn score_location(
+    weights: &SearchWeights,
+    itinerary: &FeaturedVacation,
+    search: &SearchItinerary,
+) -> (f32, Option<String>) {
+    if let Some(locations) = &search.locations {
+        if locations.is_empty() {
+            return (0.0, None);
         }
-    }
 
-    /// Score location matching
-    fn score_location(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        if let Some(locations) = &search.locations {
-            if locations.is_empty() {
-                return 0.0;
+        let mut best_score: f32 = 0.0;
+        let mut best_match_type = None;
+
+        // Check start and end location
+        let start_city = itinerary.start_location.city().to_lowercase();
+        let start_state = state_key(itinerary.start_location.state());
+        let end_city = itinerary.end_location.city().to_lowercase();
+        let end_state = state_key(itinerary.end_location.state());
+
+        for term in location_terms::parse_terms(locations) {
+            let (search_city, search_state) = match term {
+                LocationTerm::City { city, state } => {
+                    (city.to_lowercase(), state.as_deref().map(state_key).unwrap_or_default())
+                }
+                LocationTerm::State(state) => {
+                    let code = state.code.to_lowercase();
+                    if (code == start_state || code == end_state) && STATE_SEARCH_MATCH > best_score {
+                        best_score = STATE_SEARCH_MATCH;
+                        best_match_type = Some("state");
+                    }
+                    continue;
+                }
+            };
+
+            // Calculate match scores
+            let start_match_score = calculate_location_match_score(
+                &search_city,
+                &search_state,
+                &start_city,
+                &start_state,
+            );
+            let end_match_score = calculate_location_match_score(
+                &search_city,
+                &search_state,
+                &end_city,
+                &end_state,
+            );
+
+            // Take the better of start or end location match
+            let (location_match_score, match_type) = if end_match_score.0 > start_match_score.0 {
+                end_match_score
+            } else {
+                start_match_score
+            };
+            if location_match_score > best_score {
+                best_score = location_match_score;
+                best_match_type = match_type;
             }
+        }
+
+        (
+            best_score * weights.location_weight,
+            best_match_type.map(str::to_string),
+        )
+    } else {
+        (0.0, None)
+    }
+}
+
+fn calculate_location_match_score(
+    search_city: &str,
+    search_state: &str,
+    itinerary_city: &str,
+    itinerary_state: &str,
+) -> (f32, Option<&'static str>) {
+    // Exact city and state match
+    if search_city == itinerary_city && search_state == itinerary_state {
+        return (1.0, Some("exact_city"));
+    }
+
+    // Exact city match, different state
+    if search_city == itinerary_city {
+        return (0.7, Some("exact_city"));
+    }
+
+    // State match only
+    if search_state == itinerary_state && !search_state.is_empty() {
+        return (0.3, Some("state_only"));
+    }
 
-            let mut best_score: f32 = 0.0;
-
-            for search_location in locations {
-                // Parse search location (assuming "City, State" format)
-                let search_parts: Vec<&str> =
-                    search_location.split(',').map(|s| s.trim()).collect();
-                let search_city = search_parts.get(0).unwrap_or(&"").to_lowercase();
-                let search_state = search_parts.get(1).unwrap_or(&"").to_lowercase();
-
-                // Check start location
-                let start_city = itinerary.start_location.city().to_lowercase();
-                let start_state = itinerary.start_location.state().to_lowercase();
-
-                // Check end location
-                let end_city = itinerary.end_location.city().to_lowercase();
-                let end_state = itinerary.end_location.state().to_lowercase();
-
-                // Calculate match scores
-                let start_match_score = self.calculate_location_match_score(
-                    &search_city,
-                    &search_state,
-                    &start_city,
-                    &start_state,
-                );
-                let end_match_score = self.calculate_location_match_score(
-                    &search_city,
-                    &search_state,
-                    &end_city,
-                    &end_state,
-                );
-
-                // Take the better of start or end location match
-                let location_match_score = start_match_score.max(end_match_score);
-                best_score = best_score.max(location_match_score);
+    // Partial city name match (contains)
+    if itinerary_city.contains(search_city) || search_city.contains(itinerary_city) {
+        return (0.5, Some("partial"));
+    }
+
+    (0.0, None)
+}
+
+/// Match each requested term against the itinerary's own name and description
+fn match_itinerary_text(
+    itinerary: &FeaturedVacation,
+    search_activities: &[String],
+) -> Vec<ActivityMatch> {
+    let texts = [
+        itinerary.trip_name.to_lowercase(),
+        itinerary.description.to_lowercase(),
+    ];
+
+    search_activities
+        .iter()
+        .map(|search_activity| {
+            let matched_via = match_term(&search_activity.to_lowercase(), &texts);
+            ActivityMatch {
+                requested: search_activity.clone(),
+                matched: matched_via.is_some(),
+                matched_via: matched_via.map(str::to_string),
+                matched_activity_ids: Vec::new(),
             }
+        })
+        .collect()
+}
 
-            best_score * self.weights.location_weight
-        } else {
-            0.0
-        }
+/// How `search_term` matches any of `texts`, preferring a direct match over a synonym
+fn match_term(search_term: &str, texts: &[String]) -> Option<&'static str> {
+    if texts.iter().any(|text| text.contains(search_term)) {
+        Some(MATCHED_DIRECT)
+    } else if texts
+        .iter()
+        .any(|text| matches_activity_synonyms(search_term, text))
+    {
+        Some(MATCHED_SYNONYM)
+    } else {
+        None
     }
+}
 
-    fn calculate_location_match_score(
-        &self,
-        search_city: &str,
-        search_state: &str,
-        itinerary_city: &str,
-        itinerary_state: &str,
-    ) -> f32 {
-        // Exact city and state match
-        if search_city == itinerary_city && search_state == itinerary_state {
-            return 1.0;
-        }
+/// Check for activity synonyms and common variations
+fn matches_activity_synonyms(search_term: &str, text: &str) -> bool {
+    ActivitySynonyms::shared()
+        .synonyms_for(search_term)
+        .iter()
+        .any(|synonym| text.contains(synonym))
+}
 
-        // Exact city match, different state
-        if search_city == itinerary_city {
-            return 0.7;
+/// Score group size compatibility, along with how well the party fits
+fn score_group_size(
+    weights: &SearchWeights,
+    itinerary: &FeaturedVacation,
+    search: &SearchItinerary,
+) -> (f32, Option<String>) {
+    if let Some(adults) = search.adults {
+        let total_people =
+            adults + search.children.unwrap_or_default() + search.infants.unwrap_or_default();
+
+        // Perfect fit
+        if total_people >= itinerary.min_group && total_people <= itinerary.max_group {
+            return (weights.group_size_weight, Some("within_range".to_string()));
         }
 
-        // State match only
-        if search_state == itinerary_state && !search_state.is_empty() {
-            return 0.3;
+        // Close to range
+        if total_people + 1 == itinerary.min_group || total_people == itinerary.max_group + 1 {
+            return (weights.group_size_weight * 0.7, Some("near".to_string()));
         }
 
-        // Partial city name match (contains)
-        if itinerary_city.contains(search_city) || search_city.contains(itinerary_city) {
-            return 0.5;
+        // Moderately close
+        if total_people + 2 >= itinerary.min_group && total_people <= itinerary.max_group + 2 {
+            return (weights.group_size_weight * 0.4, Some("near".to_string()));
         }
 
-        0.0
+        (0.0, Some("far".to_string()))
+    } else {
+        (0.0, None)
     }
+}
 
-    /// Score activity matching with detailed activity lookup
-    fn score_activities(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        if let Some(search_activities) = &search.activities {
-            if search_activities.is_empty() {
-                return 0.0;
-            }
+/// Score lodging/accommodation matching
+fn score_lodging(weights: &SearchWeights, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
+    if let Some(search_lodging) = &search.lodging {
+        if search_lodging.is_empty() {
+            return 0.0;
+        }
 
-            // Extract activity IDs from itinerary
-            let mut activity_ids = Vec::new();
-            for day_items in itinerary.days.days.values() {
-                for item in day_items {
-                    if let crate::models::itinerary::base::DayItem::Activity { activity_id, .. } = item {
-                        activity_ids.push(*activity_id);
-                    }
+        // Check if itinerary has accommodation items
+        let mut has_accommodations = false;
+        for day_items in itinerary.days.days.values() {
+            for item in day_items {
+                if matches!(
+                    item,
+                    crate::models::itinerary::base::DayItem::Accommodation { .. }
+                ) {
+                    has_accommodations = true;
+                    break;
                 }
             }
-
-            if activity_ids.is_empty() {
-                return 0.0;
+            if has_accommodations {
+                break;
             }
+        }
 
-            // Since we can't easily make async calls here, use activity type matching
-            // from the itinerary description and any available metadata
-            let mut matched_activities = 0;
-            let total_search_activities = search_activities.len();
+        if has_accommodations {
+            // For now, give partial points for having any accommodations
+            // In a full implementation, you'd match specific lodging types
+            weights.lodging_weight * 0.6
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    }
+}
 
-            for search_activity in search_activities {
-                let search_term = search_activity.to_lowercase();
-                
-                // Check if any part of the itinerary mentions this activity type
-                let mut found_match = false;
-                
-                // Check trip name
-                if itinerary.trip_name.to_lowercase().contains(&search_term) {
-                    found_match = true;
-                }
-                
-                // Check description
-                if itinerary.description.to_lowercase().contains(&search_term) {
-                    found_match = true;
-                }
-                
-                // Check for common activity synonyms and variations
-                if self.matches_activity_synonyms(&search_term, &itinerary.trip_name.to_lowercase()) ||
-                   self.matches_activity_synonyms(&search_term, &itinerary.description.to_lowercase()) {
-                    found_match = true;
+/// Score transportation matching
+fn score_transportation(weights: &SearchWeights, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
+    if !wants_transportation(search) {
+        return 0.0;
+    }
+    if let Some(search_transport) = &search.transportation {
+        // Check if itinerary has transportation items
+        for day_items in itinerary.days.days.values() {
+            for item in day_items {
+                if let crate::models::itinerary::base::DayItem::Transportation {
+                    name, ..
+                } = item
+                {
+                    if name
+                        .to_lowercase()
+                        .contains(&search_transport.to_lowercase())
+                    {
+                        return weights.transportation_weight;
+                    }
                 }
+            }
+        }
 
-                if found_match {
-                    matched_activities += 1;
-                    println!("Found match for activity '{}' in itinerary '{}'", search_activity, itinerary.trip_name);
+        // Partial match for having any transportation
+        for day_items in itinerary.days.days.values() {
+            for item in day_items {
+                if matches!(
+                    item,
+                    crate::models::itinerary::base::DayItem::Transportation { .. }
+                ) {
+                    return weights.transportation_weight * 0.3;
                 }
             }
+        }
 
-            // Calculate match percentage
-            let match_percentage = matched_activities as f32 / total_search_activities as f32;
-            
-            // Give full weight for perfect matches, scaled down for partial matches
-            let activity_score = match_percentage * self.weights.activity_weight;
-            
-            println!("Activity scoring: {}/{} activities matched = {:.1}% = {:.1} points", 
-                matched_activities, total_search_activities, match_percentage * 100.0, activity_score);
+        0.0
+    } else {
+        0.0
+    }
+}
+
+/// Score trip pace matching
+fn score_trip_pace(weights: &SearchWeights, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
+    if let Some(search_pace) = &search.trip_pace {
+        // Count activities per day in the itinerary
+        let mut total_activities = 0;
+        let mut total_activity_hours = 0.0;
+        let num_days = itinerary.days.days.len() as f32;
+        
+        for day_items in itinerary.days.days.values() {
+            let mut day_activities = 0;
+            let mut day_hours = 0.0;
             
-            activity_score
-        } else {
-            // No activity preference specified, give partial credit for having activities
-            let mut activity_count = 0;
-            for day_items in itinerary.days.days.values() {
-                for item in day_items {
-                    if matches!(item, crate::models::itinerary::base::DayItem::Activity { .. }) {
-                        activity_count += 1;
-                    }
+            for item in day_items {
+                if let crate::models::itinerary::base::DayItem::Activity { .. } = item {
+                    day_activities += 1;
+                    // Assume 2 hours per activity if we don't have duration info
+                    day_hours += 2.0;
                 }
             }
             
-            if activity_count > 0 {
-                self.weights.activity_weight * 0.5 // 50% for having any activities when no preference
-            } else {
-                0.0
-            }
+            total_activities += day_activities;
+            total_activity_hours += day_hours;
         }
-    }
-
-    /// Check for activity synonyms and common variations
-    fn matches_activity_synonyms(&self, search_term: &str, text: &str) -> bool {
-        let synonyms = match search_term {
-            "atving" | "atv" | "atvs" => vec!["quad", "four wheeler", "off road", "off-road", "4x4", "all terrain vehicle", "dirt bike", "trail riding"],
-            "hotsprings" | "hot springs" | "hot spring" => vec!["thermal", "spa", "mineral springs", "geothermal", "springs", "natural springs", "thermal baths"],
-            "goldminetours" | "gold mine tours" | "gold mine" | "goldmine" => vec!["mining", "mine tour", "mining tour", "historical mine", "gold rush", "underground tour", "mine exploration", "mining history"],
-            "hiking" | "hike" | "hikes" => vec!["trail", "trek", "walking", "nature walk", "mountain", "wilderness"],
-            "skiing" | "ski" => vec!["slope", "mountain resort", "powder", "alpine"],
-            "rafting" | "raft" => vec!["river", "whitewater", "rapids", "float"],
-            "climbing" | "climb" => vec!["rock climbing", "bouldering", "mountaineering"],
-            "fishing" | "fish" => vec!["angling", "fly fishing", "catch"],
-            "biking" | "bike" | "cycling" => vec!["bicycle", "mountain bike", "trail ride"],
-            "kayaking" | "kayak" => vec!["paddle", "paddling", "water sports"],
-            "camping" | "camp" => vec!["campground", "outdoor", "tent", "rv"],
-            "wildlife" => vec!["animals", "safari", "nature viewing", "bird watching"],
-            _ => vec![],
+        
+        let avg_activities_per_day = if num_days > 0.0 { total_activities as f32 / num_days } else { 0.0 };
+        let avg_hours_per_day = if num_days > 0.0 { total_activity_hours / num_days } else { 0.0 };
+        
+        // Score based on how well the itinerary matches the desired pace
+        let expected_activities = search_pace.typical_activities_per_day() as f32;
+        let expected_hours = search_pace.max_activity_hours_per_day();
+        
+        // Calculate activity count match (50% of pace score)
+        let activity_diff = (avg_activities_per_day - expected_activities).abs();
+        let activity_match = if activity_diff <= 0.5 {
+            1.0
+        } else if activity_diff <= 1.0 {
+            0.8
+        } else if activity_diff <= 2.0 {
+            0.5
+        } else {
+            0.2
+        };
+        
+        // Calculate hours match (50% of pace score)
+        let hours_diff = (avg_hours_per_day - expected_hours).abs();
+        let hours_match = if hours_diff <= 1.0 {
+            1.0
+        } else if hours_diff <= 2.0 {
+            0.8
+        } else if hours_diff <= 3.0 {
+            0.5
+        } else {
+            0.2
         };
+        
+        // Combined score
+        let pace_match = (activity_match + hours_match) / 2.0;
+        
+        pace_match * weights.trip_pace_weight
+    } else {
+        // No pace preference: the dimension is left out of `max_score_for`
+        0.0
+    }
+}
 
-        for synonym in synonyms {
-            if text.contains(synonym) {
-                return true;
+/// Ids of the activities scheduled on the itinerary
+fn scheduled_activity_ids(itinerary: &FeaturedVacation) -> Vec<ObjectId> {
+    let mut activity_ids = Vec::new();
+    for day_items in itinerary.days.days.values() {
+        for item in day_items {
+            if let crate::models::itinerary::base::DayItem::Activity { activity_id, .. } = item {
+                activity_ids.push(*activity_id);
             }
         }
-        
-        false
     }
+    activity_ids
+}
 
-    /// Score group size compatibility
-    fn score_group_size(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        if let Some(adults) = search.adults {
-            let total_people =
-                adults + search.children.unwrap_or_default() + search.infants.unwrap_or_default();
-
-            // Perfect fit
-            if total_people >= itinerary.min_group && total_people <= itinerary.max_group {
-                return self.weights.group_size_weight;
+/// Match each requested term against the looked-up activities, keeping every
+/// activity that matched rather than stopping at the first
+fn match_activities(activities: &[Activity], search_activities: &[String]) -> Vec<ActivityMatch> {
+    let activity_texts: Vec<(Option<ObjectId>, Vec<String>)> = activities
+        .iter()
+        .map(|activity| {
+            let mut texts: Vec<String> = activity
+                .activity_types
+                .iter()
+                .chain(activity.tags.iter())
+                .map(|text| text.to_lowercase())
+                .collect();
+            texts.push(activity.title.to_lowercase());
+            texts.push(activity.description.to_lowercase());
+            (activity.id, texts)
+        })
+        .collect();
+
+    search_activities
+        .iter()
+        .map(|search_activity| {
+            let search_term = search_activity.to_lowercase();
+            let mut matched_via = None;
+            let mut matched_activity_ids = Vec::new();
+
+            for (activity_id, texts) in &activity_texts {
+                if let Some(via) = match_term(&search_term, texts) {
+                    if matched_via != Some(MATCHED_DIRECT) {
+                        matched_via = Some(via);
+                    }
+                    if let Some(id) = activity_id {
+                        matched_activity_ids.push(*id);
+                    }
+                }
             }
 
-            // Close to range
-            if total_people == itinerary.min_group - 1 || total_people == itinerary.max_group + 1 {
-                return self.weights.group_size_weight * 0.7;
+            ActivityMatch {
+                requested: search_activity.clone(),
+                matched: matched_via.is_some(),
+                matched_via: matched_via.map(str::to_string),
+                matched_activity_ids,
             }
+        })
+        .collect()
+}
 
-            // Moderately close
-            if total_people >= itinerary.min_group - 2 && total_people <= itinerary.max_group + 2 {
-                return self.weights.group_size_weight * 0.4;
-            }
+/// Sort by score descending, dropping results under the minimum
+fn rank(mut scored: Vec<ScoredItinerary>, weights: &SearchWeights) -> Vec<ScoredItinerary> {
+    scored.retain(|scored| scored.total_score >= weights.minimum_score);
+    scored.sort_by(|a, b| {
+        b.total_score
+            .partial_cmp(&a.total_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    scored
+}
 
-            0.0
-        } else {
-            0.0
+/// No activity preference specified, give partial credit for having activities
+fn activity_presence_score(weights: &SearchWeights, itinerary: &FeaturedVacation) -> f32 {
+    let mut activity_count = 0;
+    for day_items in itinerary.days.days.values() {
+        for item in day_items {
+            if matches!(item, crate::models::itinerary::base::DayItem::Activity { .. }) {
+                activity_count += 1;
+            }
         }
     }
 
-    /// Score lodging/accommodation matching
-    fn score_lodging(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        if let Some(search_lodging) = &search.lodging {
-            if search_lodging.is_empty() {
-                return 0.0;
-            }
+    if activity_count > 0 {
+        weights.activity_weight * 0.5 // 50% for having any activities when no preference
+    } else {
+        0.0
+    }
+}
 
-            // Check if itinerary has accommodation items
-            let mut has_accommodations = false;
-            for day_items in itinerary.days.days.values() {
-                for item in day_items {
-                    if matches!(
-                        item,
-                        crate::models::itinerary::base::DayItem::Accommodation { .. }
-                    ) {
-                        has_accommodations = true;
-                        break;
-                    }
-                }
-                if has_accommodations {
-                    break;
-                }
-            }
+#[derive(Default)]
+pub struct SearchScorer {
+    pub weights: Arc<SearchWeights>,
+}
 
-            if has_accommodations {
-                // For now, give partial points for having any accommodations
-                // In a full implementation, you'd match specific lodging types
-                self.weights.lodging_weight * 0.6
-            } else {
-                0.0
-            }
-        } else {
-            0.0
-        }
+pub struct AsyncSearchScorer {
+    pub weights: Arc<SearchWeights>,
+    pub client: Arc<Client>,
+    /// Activities scored as given instead of as stored, so unsaved edits can be previewed
+    activity_overrides: HashMap<ObjectId, Activity>,
+}
+
+impl SearchScorer {
+    /// Scorer with the default weights. Configured weights come from `AppConfig`.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Score transportation matching
-    fn score_transportation(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        if let Some(search_transport) = &search.transportation {
-            // Check if itinerary has transportation items
-            for day_items in itinerary.days.days.values() {
-                for item in day_items {
-                    if let crate::models::itinerary::base::DayItem::Transportation {
-                        name, ..
-                    } = item
-                    {
-                        if name
-                            .to_lowercase()
-                            .contains(&search_transport.to_lowercase())
-                        {
-                            return self.weights.transportation_weight;
-                        }
-                    }
-                }
+    pub fn with_weights(weights: Arc<SearchWeights>) -> Self {
+        Self { weights }
+    }
+
+    /// Score an itinerary against search criteria
+    pub fn score_itinerary(
+        &self,
+        itinerary: &FeaturedVacation,
+        search: &SearchItinerary,
+    ) -> ScoredItinerary {
+        scored_itinerary(&self.weights, itinerary, search, self.score_activities(itinerary, search))
+    }
+
+    /// Score activity matching with detailed activity lookup
+    fn score_activities(
+        &self,
+        itinerary: &FeaturedVacation,
+        search: &SearchItinerary,
+    ) -> (f32, Vec<ActivityMatch>) {
+        if let Some(search_activities) = &search.activities {
+            if search_activities.is_empty() {
+                return (0.0, Vec::new());
             }
 
-            // Partial match for having any transportation
+            // Extract activity IDs from itinerary
+            let mut activity_ids = Vec::new();
             for day_items in itinerary.days.days.values() {
                 for item in day_items {
-                    if matches!(
-                        item,
-                        crate::models::itinerary::base::DayItem::Transportation { .. }
-                    ) {
-                        return self.weights.transportation_weight * 0.3;
+                    if let crate::models::itinerary::base::DayItem::Activity { activity_id, .. } = item {
+                        activity_ids.push(*activity_id);
                     }
                 }
             }
 
-            0.0
-        } else {
-            0.0
-        }
-    }
-    
-    /// Score trip pace matching
-    fn score_trip_pace(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        if let Some(search_pace) = &search.trip_pace {
-            // Count activities per day in the itinerary
-            let mut total_activities = 0;
-            let mut total_activity_hours = 0.0;
-            let num_days = itinerary.days.days.len() as f32;
-            
-            for day_items in itinerary.days.days.values() {
-                let mut day_activities = 0;
-                let mut day_hours = 0.0;
-                
-                for item in day_items {
-                    if let crate::models::itinerary::base::DayItem::Activity { .. } = item {
-                        day_activities += 1;
-                        // Assume 2 hours per activity if we don't have duration info
-                        day_hours += 2.0;
-                    }
-                }
-                
-                total_activities += day_activities;
-                total_activity_hours += day_hours;
+            if activity_ids.is_empty() {
+                return (0.0, Vec::new());
             }
+
+            // Since we can't easily make async calls here, use activity type matching
+            // from the itinerary description and any available metadata
+            let matches = match_itinerary_text(itinerary, search_activities);
+            let matched_activities = matches.iter().filter(|m| m.matched).count();
+            let total_search_activities = search_activities.len();
+
+            for activity_match in matches.iter().filter(|m| m.matched) {
+                println!("Found match for activity '{}' in itinerary '{}'", activity_match.requested, itinerary.trip_name);
+            }
+
+            // Calculate match percentage
+            let match_percentage = matched_activities as f32 / total_search_activities as f32;
             
-            let avg_activities_per_day = if num_days > 0.0 { total_activities as f32 / num_days } else { 0.0 };
-            let avg_hours_per_day = if num_days > 0.0 { total_activity_hours / num_days } else { 0.0 };
-            
-            // Score based on how well the itinerary matches the desired pace
-            let expected_activities = search_pace.typical_activities_per_day() as f32;
-            let expected_hours = search_pace.max_activity_hours_per_day();
-            
-            // Calculate activity count match (50% of pace score)
-            let activity_diff = (avg_activities_per_day - expected_activities).abs();
-            let activity_match = if activity_diff <= 0.5 {
-                1.0
-            } else if activity_diff <= 1.0 {
-                0.8
-            } else if activity_diff <= 2.0 {
-                0.5
-            } else {
-                0.2
-            };
-            
-            // Calculate hours match (50% of pace score)
-            let hours_diff = (avg_hours_per_day - expected_hours).abs();
-            let hours_match = if hours_diff <= 1.0 {
-                1.0
-            } else if hours_diff <= 2.0 {
-                0.8
-            } else if hours_diff <= 3.0 {
-                0.5
-            } else {
-                0.2
-            };
+            // Give full weight for perfect matches, scaled down for partial matches
+            let activity_score = match_percentage * self.weights.activity_weight;
             
-            // Combined score
-            let pace_match = (activity_match + hours_match) / 2.0;
+            println!("Activity scoring: {}/{} activities matched = {:.1}% = {:.1} points", 
+                matched_activities, total_search_activities, match_percentage * 100.0, activity_score);
             
-            pace_match * self.weights.trip_pace_weight
+            (activity_score, matches)
         } else {
-            // No pace preference, give partial credit
-            self.weights.trip_pace_weight * 0.5
+            (activity_presence_score(&self.weights, itinerary), Vec::new())
         }
     }
 
@@ -507,32 +761,75 @@ impl SearchScorer {
         itineraries: Vec<FeaturedVacation>,
         search: &SearchItinerary,
     ) -> Vec<ScoredItinerary> {
-        let mut scored: Vec<ScoredItinerary> = itineraries
+        let scored = itineraries
             .iter()
             .map(|itinerary| self.score_itinerary(itinerary, search))
-            .filter(|scored| scored.total_score >= self.weights.minimum_score)
             .collect();
+        rank(scored, &self.weights)
+    }
 
-        // Sort by score descending
-        scored.sort_by(|a, b| {
-            b.total_score
-                .partial_cmp(&a.total_score)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+    /// Score against activities that were already looked up, matching them the way
+    /// `AsyncSearchScorer` matches stored ones. Activities that aren't on the
+    /// itinerary are ignored, so one list can serve many itineraries.
+    pub fn score_with_activities(
+        &self,
+        itinerary: &FeaturedVacation,
+        search: &SearchItinerary,
+        activities: &[Activity],
+    ) -> ScoredItinerary {
+        let activity_score = match &search.activities {
+            Some(search_activities) if !search_activities.is_empty() => {
+                let scheduled: HashSet<ObjectId> = scheduled_activity_ids(itinerary).into_iter().collect();
+                if scheduled.is_empty() {
+                    (0.0, Vec::new())
+                } else {
+                    let resolved: Vec<Activity> = activities
+                        .iter()
+                        .filter(|activity| activity.id.is_some_and(|id| scheduled.contains(&id)))
+                        .cloned()
+                        .collect();
+                    let matches = match_activities(&resolved, search_activities);
+                    let matched = matches.iter().filter(|m| m.matched).count();
+                    let score = matched as f32 / search_activities.len() as f32 * self.weights.activity_weight;
+                    (score, matches)
+                }
+            }
+            Some(_) => (0.0, Vec::new()),
+            None => (activity_presence_score(&self.weights, itinerary), Vec::new()),
+        };
+        scored_itinerary(&self.weights, itinerary, search, activity_score)
+    }
 
-        scored
+    /// `score_and_rank_itineraries` with activities already looked up
+    pub fn rank_with_activities(
+        &self,
+        itineraries: &[FeaturedVacation],
+        search: &SearchItinerary,
+        activities: &[Activity],
+    ) -> Vec<ScoredItinerary> {
+        let scored = itineraries
+            .iter()
+            .map(|itinerary| self.score_with_activities(itinerary, search, activities))
+            .collect();
+        rank(scored, &self.weights)
     }
 }
 
 impl AsyncSearchScorer {
-    pub fn new(client: Arc<Client>) -> Self {
-        let weights = SearchWeights::from_env();
-        println!("AsyncSearchScorer initialized with weights: {:?}", weights);
-        Self { weights, client }
+    pub fn with_weights(client: Arc<Client>, weights: Arc<SearchWeights>) -> Self {
+        Self {
+            weights,
+            client,
+            activity_overrides: HashMap::new(),
+        }
     }
 
-    pub fn with_weights(client: Arc<Client>, weights: SearchWeights) -> Self {
-        Self { weights, client }
+    /// Score these activities instead of looking them up, including ones that aren't
+    /// saved yet. Activities without an ID are ignored.
+    pub fn with_activity_overrides(mut self, activities: impl IntoIterator<Item = Activity>) -> Self {
+        self.activity_overrides
+            .extend(activities.into_iter().filter_map(|activity| Some((activity.id?, activity))));
+        self
     }
 
     /// Score an itinerary against search criteria with full activity lookup
@@ -541,53 +838,25 @@ impl AsyncSearchScorer {
         itinerary: &FeaturedVacation,
         search: &SearchItinerary,
     ) -> ScoredItinerary {
-        let location_score = self.score_location(itinerary, search);
-        let activity_score = self.score_activities_async(itinerary, search).await;
-        let group_size_score = self.score_group_size(itinerary, search);
-        let lodging_score = self.score_lodging(itinerary, search);
-        let transportation_score = self.score_transportation(itinerary, search);
-        let trip_pace_score = self.score_trip_pace(itinerary, search);
-
-        let total_score = location_score
-            + activity_score
-            + group_size_score
-            + lodging_score
-            + transportation_score
-            + trip_pace_score;
-
-        ScoredItinerary {
-            itinerary: itinerary.clone(),
-            total_score,
-            score_breakdown: ScoreBreakdown {
-                location_score,
-                activity_score,
-                group_size_score,
-                lodging_score,
-                transportation_score,
-                trip_pace_score,
-            },
-        }
+        let activities = self.score_activities_async(itinerary, search).await;
+        scored_itinerary(&self.weights, itinerary, search, activities)
     }
 
     /// Score activity matching with full database lookup
-    async fn score_activities_async(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
+    async fn score_activities_async(
+        &self,
+        itinerary: &FeaturedVacation,
+        search: &SearchItinerary,
+    ) -> (f32, Vec<ActivityMatch>) {
         if let Some(search_activities) = &search.activities {
             if search_activities.is_empty() {
-                return 0.0;
+                return (0.0, Vec::new());
             }
 
             // Extract activity IDs from itinerary
-            let mut activity_ids = Vec::new();
-            for day_items in itinerary.days.days.values() {
-                for item in day_items {
-                    if let crate::models::itinerary::base::DayItem::Activity { activity_id, .. } = item {
-                        activity_ids.push(*activity_id);
-                    }
-                }
-            }
-
+            let activity_ids = scheduled_activity_ids(itinerary);
             if activity_ids.is_empty() {
-                return 0.0;
+                return (0.0, Vec::new());
             }
 
             // Fetch activities from database
@@ -600,56 +869,13 @@ impl AsyncSearchScorer {
                 }
             };
 
-            let mut matched_activities = 0;
+            let matches = match_activities(&activities, search_activities);
+            let matched_activities = matches.iter().filter(|m| m.matched).count();
             let total_search_activities = search_activities.len();
 
-            for search_activity in search_activities {
-                let search_term = search_activity.to_lowercase();
-                
-                let mut found_match = false;
-                
-                // Check against actual activity data
-                for activity in &activities {
-                    // Check activity types
-                    for activity_type in &activity.activity_types {
-                        if activity_type.to_lowercase().contains(&search_term) ||
-                           self.matches_activity_synonyms(&search_term, &activity_type.to_lowercase()) {
-                            found_match = true;
-                            break;
-                        }
-                    }
-                    
-                    // Check tags
-                    if !found_match {
-                        for tag in &activity.tags {
-                            if tag.to_lowercase().contains(&search_term) ||
-                               self.matches_activity_synonyms(&search_term, &tag.to_lowercase()) {
-                                found_match = true;
-                                break;
-                            }
-                        }
-                    }
-                    
-                    // Check title and description
-                    if !found_match {
-                        if activity.title.to_lowercase().contains(&search_term) ||
-                           activity.description.to_lowercase().contains(&search_term) ||
-                           self.matches_activity_synonyms(&search_term, &activity.title.to_lowercase()) ||
-                           self.matches_activity_synonyms(&search_term, &activity.description.to_lowercase()) {
-                            found_match = true;
-                        }
-                    }
-                    
-                    if found_match {
-                        break;
-                    }
-                }
-
-                if found_match {
-                    matched_activities += 1;
-                    println!("Found match for activity '{}' in itinerary '{}' (database lookup)", 
-                        search_activity, itinerary.trip_name);
-                }
+            for activity_match in matches.iter().filter(|m| m.matched) {
+                println!("Found match for activity '{}' in itinerary '{}' (database lookup)", 
+                    activity_match.requested, itinerary.trip_name);
             }
 
             // Calculate match percentage
@@ -661,28 +887,28 @@ impl AsyncSearchScorer {
             println!("Activity scoring (database): {}/{} activities matched = {:.1}% = {:.1} points", 
                 matched_activities, total_search_activities, match_percentage * 100.0, activity_score);
             
-            activity_score
+            (activity_score, matches)
         } else {
-            // No activity preference specified, give partial credit for having activities
-            let mut activity_count = 0;
-            for day_items in itinerary.days.days.values() {
-                for item in day_items {
-                    if matches!(item, crate::models::itinerary::base::DayItem::Activity { .. }) {
-                        activity_count += 1;
-                    }
-                }
-            }
-            
-            if activity_count > 0 {
-                self.weights.activity_weight * 0.5 // 50% for having any activities when no preference
-            } else {
-                0.0
-            }
+            (activity_presence_score(&self.weights, itinerary), Vec::new())
         }
     }
 
-    /// Fetch activities from database by IDs
+    /// Fetch activities from database by IDs, taking overridden ones from memory
     async fn fetch_activities(&self, activity_ids: Vec<ObjectId>) -> Result<Vec<Activity>, mongodb::error::Error> {
+        let mut seen = HashSet::new();
+        let mut overridden = Vec::new();
+        let mut stored_ids = Vec::new();
+        for id in activity_ids.into_iter().filter(|id| seen.insert(*id)) {
+            match self.activity_overrides.get(&id) {
+                Some(activity) => overridden.push(activity.clone()),
+                None => stored_ids.push(id),
+            }
+        }
+        if stored_ids.is_empty() {
+            return Ok(overridden);
+        }
+        let activity_ids = stored_ids;
+
         let collection: mongodb::Collection<Activity> = self
             .client
             .database("Options")
@@ -693,54 +919,33 @@ impl AsyncSearchScorer {
         };
 
         let cursor = collection.find(filter).await?;
-        let activities: Vec<Activity> = cursor.try_collect().await?;
+        let mut activities: Vec<Activity> = cursor.try_collect().await?;
         
         println!("Fetched {} activities from database for scoring", activities.len());
+        activities.extend(overridden);
         Ok(activities)
     }
 
     /// Fallback to basic text matching if database lookup fails
-    fn score_activities_fallback(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
+    fn score_activities_fallback(
+        &self,
+        itinerary: &FeaturedVacation,
+        search: &SearchItinerary,
+    ) -> (f32, Vec<ActivityMatch>) {
         if let Some(search_activities) = &search.activities {
             if search_activities.is_empty() {
-                return 0.0;
+                return (0.0, Vec::new());
             }
 
-            let mut matched_activities = 0;
+            let matches = match_itinerary_text(itinerary, search_activities);
+            let matched_activities = matches.iter().filter(|m| m.matched).count();
             let total_search_activities = search_activities.len();
 
-            for search_activity in search_activities {
-                let search_term = search_activity.to_lowercase();
-                
-                // Check if any part of the itinerary mentions this activity type
-                let mut found_match = false;
-                
-                // Check trip name
-                if itinerary.trip_name.to_lowercase().contains(&search_term) {
-                    found_match = true;
-                }
-                
-                // Check description
-                if itinerary.description.to_lowercase().contains(&search_term) {
-                    found_match = true;
-                }
-                
-                // Check for common activity synonyms and variations
-                if self.matches_activity_synonyms(&search_term, &itinerary.trip_name.to_lowercase()) ||
-                   self.matches_activity_synonyms(&search_term, &itinerary.description.to_lowercase()) {
-                    found_match = true;
-                }
-
-                if found_match {
-                    matched_activities += 1;
-                }
-            }
-
             // Calculate match percentage
             let match_percentage = matched_activities as f32 / total_search_activities as f32;
-            match_percentage * self.weights.activity_weight
+            (match_percentage * self.weights.activity_weight, matches)
         } else {
-            0.0
+            (0.0, Vec::new())
         }
     }
 
@@ -753,50 +958,180 @@ impl AsyncSearchScorer {
         let mut scored = Vec::new();
         
         for itinerary in itineraries {
-            let scored_itinerary = self.score_itinerary(&itinerary, search).await;
-            if scored_itinerary.total_score >= self.weights.minimum_score {
-                scored.push(scored_itinerary);
-            }
+            scored.push(self.score_itinerary(&itinerary, search).await);
         }
 
-        // Sort by score descending
-        scored.sort_by(|a, b| {
-            b.total_score
-                .partial_cmp(&a.total_score)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        rank(scored, &self.weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        scored
+    fn test_activity(title: &str, activity_types: Vec<&str>) -> Activity {
+        Activity {
+            description: "".to_string(),
+            activity_types: activity_types.into_iter().map(String::from).collect(),
+            ..Activity::test(title)
+        }
     }
 
-    // Delegate methods from SearchScorer for compatibility
-    fn score_location(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        let scorer = SearchScorer { weights: self.weights.clone() };
-        scorer.score_location(itinerary, search)
+    #[test]
+    fn test_two_of_three_activity_matches() {
+        let hike = test_activity("Mountain Trail", vec!["hiking"]);
+        let raft = test_activity("Whitewater Adventure", vec!["water"]);
+        let requested = vec!["hiking".to_string(), "rafting".to_string(), "skiing".to_string()];
+
+        let matches = match_activities(&[hike.clone(), raft.clone()], &requested);
+
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].requested, "hiking");
+        assert_eq!(matches[0].matched_via.as_deref(), Some("direct"));
+        assert_eq!(matches[0].matched_activity_ids, vec![hike.id.unwrap()]);
+        assert_eq!(matches[1].matched_via.as_deref(), Some("synonym"));
+        assert_eq!(matches[1].matched_activity_ids, vec![raft.id.unwrap()]);
+        assert!(!matches[2].matched);
+        assert!(matches[2].matched_via.is_none());
+        assert!(matches[2].matched_activity_ids.is_empty());
     }
 
-    fn score_group_size(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        let scorer = SearchScorer { weights: self.weights.clone() };
-        scorer.score_group_size(itinerary, search)
+    fn search(adults: u32) -> SearchItinerary {
+        SearchItinerary {
+            id: None,
+            user_id: None,
+            locations: None,
+            arrival_datetime: None,
+            departure_datetime: None,
+            adults: Some(adults),
+            children: None,
+            infants: None,
+            activities: None,
+            lodging: None,
+            transportation: None,
+            trip_pace: None,
+            response_version: None,
+        }
     }
 
-    fn score_lodging(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        let scorer = SearchScorer { weights: self.weights.clone() };
-        scorer.score_lodging(itinerary, search)
+    #[test]
+    fn test_unspecified_dimensions_are_left_out_of_the_percentage() {
+        use crate::models::itinerary::base::{DayItem, Days};
+        use crate::models::search::TripPace;
+
+        // Two activities on a single day is exactly a relaxed pace
+        let activity = |_| DayItem::Activity {
+            time: "09:00:00".to_string(),
+            activity_id: ObjectId::new(),
+        };
+        let itinerary = FeaturedVacation {
+            min_group: 1,
+            max_group: 6,
+            days: Days {
+                days: std::collections::HashMap::from([("1".to_string(), (0..2).map(activity).collect())]),
+            },
+            ..Default::default()
+        };
+        let scorer = SearchScorer::new();
+        let percentage = |search: &SearchItinerary| scorer.score_itinerary(&itinerary, search).match_percentage();
+
+        // Group size (15) and half the activity weight (15) out of location, activities and group size
+        let plain = search(2);
+        assert_eq!(percentage(&plain), 30.0 / 80.0 * 100.0);
+        assert_eq!(scorer.score_itinerary(&itinerary, &plain).score_breakdown.trip_pace_score, 0.0);
+
+        let relaxed = SearchItinerary { trip_pace: Some(TripPace::Relaxed), ..plain.clone() };
+        assert_eq!(percentage(&relaxed), 42.0 / 92.0 * 100.0);
+        let adventure = SearchItinerary { trip_pace: Some(TripPace::Adventure), ..plain.clone() };
+        assert!(percentage(&adventure) < percentage(&plain));
+
+        // No accommodation and no transportation on the trip
+        let lodging = SearchItinerary { lodging: Some(vec!["cabin".to_string()]), ..plain.clone() };
+        assert_eq!(percentage(&lodging), 30.0 / 85.0 * 100.0);
+        let transportation = SearchItinerary { transportation: Some("train".to_string()), ..plain.clone() };
+        assert_eq!(percentage(&transportation), 30.0 / 83.0 * 100.0);
+
+        // Empty preferences are no preference
+        let empty = SearchItinerary {
+            lodging: Some(Vec::new()),
+            transportation: Some("  ".to_string()),
+            ..plain.clone()
+        };
+        assert_eq!(percentage(&empty), percentage(&plain));
+        assert_eq!(SearchWeights::default().max_score_for(&empty), 80.0);
     }
 
-    fn score_transportation(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        let scorer = SearchScorer { weights: self.weights.clone() };
-        scorer.score_transportation(itinerary, search)
+    #[actix_rt::test]
+    async fn test_scoring_builds_no_weights_of_its_own() {
+        let client = mongodb::Client::with_options(mongodb::options::ClientOptions::default()).unwrap();
+        let weights = Arc::new(SearchWeights::default());
+        let built = || WEIGHTS_BUILT.with(|built| built.get());
+        let before = built();
+
+        let itinerary = FeaturedVacation {
+            min_group: 1,
+            max_group: 6,
+            ..Default::default()
+        };
+        let scorer = AsyncSearchScorer::with_weights(Arc::new(client), weights.clone());
+        let ranked = scorer
+            .score_and_rank_itineraries(vec![itinerary.clone(), itinerary.clone()], &search(2))
+            .await;
+        assert_eq!(ranked.len(), 2);
+        match_activities(&[test_activity("Mountain Trail", vec!["hiking"])], &["rafting".to_string()]);
+        scorer.score_activities_fallback(&itinerary, &search(2));
+        SearchScorer::with_weights(weights.clone()).score_and_rank_itineraries(vec![itinerary], &search(2));
+
+        assert_eq!(built(), before);
     }
 
-    fn matches_activity_synonyms(&self, search_term: &str, text: &str) -> bool {
-        let scorer = SearchScorer { weights: self.weights.clone() };
-        scorer.matches_activity_synonyms(search_term, text)
+    #[test]
+    fn test_state_search_is_a_strong_location_match() {
+        let boulder: crate::models::itinerary::base::Location = serde_json::from_value(serde_json::json!({
+            "city": "Boulder",
+            "state": "CO",
+            "coordinates": [-105.2705, 40.0150]
+        }))
+        .unwrap();
+        let itinerary = FeaturedVacation {
+            start_location: boulder.clone(),
+            end_location: boulder,
+            ..Default::default()
+        };
+        let scorer = SearchScorer::new();
+        let location = |locations: &[&str]| {
+            let search = SearchItinerary {
+                locations: Some(locations.iter().map(|l| l.to_string()).collect()),
+                ..search(2)
+            };
+            let breakdown = scorer.score_itinerary(&itinerary, &search).score_breakdown;
+            (breakdown.location_score, breakdown.location_match_type)
+        };
+
+        assert_eq!(location(&["Colorado"]), (0.8 * 35.0, Some("state".to_string())));
+        assert_eq!(location(&["co"]), (0.8 * 35.0, Some("state".to_string())));
+        assert_eq!(location(&["Boulder, Colorado"]), (35.0, Some("exact_city".to_string())));
+        // Naming another city in the state is still only a weak match
+        assert_eq!(location(&["Denver, CO"]), (0.3 * 35.0, Some("state_only".to_string())));
+        assert_eq!(location(&["Denver, CO", "Colorado"]), (0.8 * 35.0, Some("state".to_string())));
+        assert_eq!(location(&["Utah"]), (0.0, None));
     }
-    
-    fn score_trip_pace(&self, itinerary: &FeaturedVacation, search: &SearchItinerary) -> f32 {
-        let scorer = SearchScorer { weights: self.weights.clone() };
-        scorer.score_trip_pace(itinerary, search)
+
+    #[test]
+    fn test_legacy_breakdown_round_trips() {
+        let legacy = serde_json::json!({
+            "location_score": 35.0,
+            "activity_score": 15.0,
+            "group_size_score": 15.0,
+            "lodging_score": 0.0,
+            "transportation_score": 0.0,
+            "trip_pace_score": 6.0
+        });
+
+        let breakdown: ScoreBreakdown = serde_json::from_value(legacy.clone()).unwrap();
+        assert!(breakdown.activity_matches.is_empty());
+        assert!(breakdown.location_match_type.is_none());
+        assert!(breakdown.group_size_fit.is_none());
+        assert_eq!(serde_json::to_value(&breakdown).unwrap(), legacy);
     }
 }
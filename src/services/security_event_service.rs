@@ -0,0 +1,366 @@
+use actix_web::HttpRequest;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::models::security_event::{SecurityEvent, SecurityEventType};
+use crate::services::account_service::{EmailService, EmailSettings};
+use crate::services::write_behind::{WriteBehind, WriteSink};
+
+/// Events older than this are expired by the TTL index and no longer count
+/// towards recognising a device
+pub const RETENTION_DAYS: u64 = 90;
+
+/// Where a request came from, as far as device recognition is concerned
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientFingerprint {
+    pub ip: Option<String>,
+    pub user_agent_hash: Option<String>,
+}
+
+impl ClientFingerprint {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        // Behind the load balancer the socket peer is the proxy, so prefer the
+        // forwarded address. Ports change per connection and are dropped.
+        let ip = req.connection_info().realip_remote_addr().map(|addr| {
+            addr.parse::<SocketAddr>()
+                .map(|socket| socket.ip().to_string())
+                .unwrap_or_else(|_| addr.to_string())
+        });
+        let user_agent_hash = req
+            .headers()
+            .get("user-agent")
+            .and_then(|value| value.to_str().ok())
+            .map(hash_user_agent);
+
+        ClientFingerprint { ip, user_agent_hash }
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of a user agent string, hex encoded
+pub fn hash_user_agent(user_agent: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in user_agent.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Whether `event` comes from an IP/user agent pair the user hasn't successfully
+/// authenticated from in `prior`. Failed signins don't make a device known, and a
+/// user with no history yet has nothing to compare against.
+pub fn is_new_device(prior: &[SecurityEvent], event: &SecurityEvent) -> bool {
+    let mut known = prior
+        .iter()
+        .filter(|prior| {
+            matches!(
+                prior.event_type,
+                SecurityEventType::SigninSuccess | SecurityEventType::TokenRefreshedFromNewDevice
+            )
+        })
+        .peekable();
+
+    if known.peek().is_none() {
+        return false;
+    }
+
+    !known.any(|prior| prior.ip == event.ip && prior.user_agent_hash == event.user_agent_hash)
+}
+
+/// Write-behind queue for security events. Handlers push and return immediately;
+/// a background task stores events and runs new-device detection.
+#[derive(Clone)]
+pub struct SecurityEventQueue {
+    events: WriteBehind<SecurityEvent>,
+}
+
+impl SecurityEventQueue {
+    /// Start the background worker that drains the queue into MongoDB. New-device
+    /// notices are emailed with `email`.
+    pub fn start(client: Arc<Client>, email: &EmailSettings) -> Self {
+        let indexes = SecurityEventService::new(client.clone());
+        tokio::spawn(async move {
+            if let Err(e) = indexes.ensure_indexes().await {
+                eprintln!("⚠️  Failed to create security event indexes: {}", e);
+            }
+        });
+        Self::start_with(SecurityEventService::new(client).with_email(email))
+    }
+
+    pub fn start_with<S: WriteSink<SecurityEvent> + Send + Sync + 'static>(sink: S) -> Self {
+        SecurityEventQueue {
+            events: WriteBehind::start(sink),
+        }
+    }
+
+    pub fn record(
+        &self,
+        user_id: ObjectId,
+        event_type: SecurityEventType,
+        fingerprint: &ClientFingerprint,
+    ) {
+        let event = SecurityEvent {
+            id: None,
+            user_id,
+            event_type,
+            timestamp: DateTime::now(),
+            ip: fingerprint.ip.clone(),
+            user_agent_hash: fingerprint.user_agent_hash.clone(),
+        };
+        self.events.push("security event", event);
+    }
+}
+
+pub struct SecurityEventPage {
+    pub events: Vec<SecurityEvent>,
+    pub total: u64,
+}
+
+pub struct SecurityEventService {
+    client: Arc<Client>,
+    email: EmailSettings,
+}
+
+impl SecurityEventService {
+    pub fn new(client: Arc<Client>) -> Self {
+        SecurityEventService {
+            client,
+            email: EmailSettings::default(),
+        }
+    }
+
+    /// Where new-device notices are emailed from. Without it none are sent.
+    pub fn with_email(mut self, settings: &EmailSettings) -> Self {
+        self.email = settings.clone();
+        self
+    }
+
+    fn collection(&self) -> Collection<SecurityEvent> {
+        self.client.database("Account").collection("SecurityEvents")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let ttl = IndexModel::builder()
+            .keys(doc! { "timestamp": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(RETENTION_DAYS * 24 * 60 * 60))
+                    .build(),
+            )
+            .build();
+        let by_user = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "timestamp": -1 })
+            .build();
+        self.collection().create_indexes([ttl, by_user]).await?;
+        Ok(())
+    }
+
+    /// Newest first
+    pub async fn list(
+        &self,
+        user_id: ObjectId,
+        page: u64,
+        limit: i64,
+    ) -> Result<SecurityEventPage, mongodb::error::Error> {
+        let filter = doc! { "user_id": user_id };
+        let total = self.collection().count_documents(filter.clone()).await?;
+        let events = self
+            .collection()
+            .find(filter)
+            .sort(doc! { "timestamp": -1 })
+            .skip(page.saturating_sub(1) * limit as u64)
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(SecurityEventPage { events, total })
+    }
+
+    /// Store one queued event. Successful signins and token use are compared with
+    /// the user's recent history first; token use is only kept when the device is new.
+    pub async fn process(&self, event: SecurityEvent) -> Result<(), mongodb::error::Error> {
+        let checks_device = matches!(
+            event.event_type,
+            SecurityEventType::SigninSuccess | SecurityEventType::TokenRefreshedFromNewDevice
+        );
+        let new_device = checks_device && is_new_device(&self.recent_history(event.user_id).await?, &event);
+
+        if event.event_type == SecurityEventType::TokenRefreshedFromNewDevice && !new_device {
+            return Ok(());
+        }
+
+        self.collection().insert_one(&event).await?;
+
+        if new_device && event.event_type == SecurityEventType::SigninSuccess {
+            self.notify_new_device(&event).await;
+        }
+        Ok(())
+    }
+
+    async fn recent_history(&self, user_id: ObjectId) -> Result<Vec<SecurityEvent>, mongodb::error::Error> {
+        let since = DateTime::from_millis(
+            DateTime::now().timestamp_millis() - (RETENTION_DAYS * 24 * 60 * 60 * 1000) as i64,
+        );
+        self.collection()
+            .find(doc! { "user_id": user_id, "timestamp": { "$gte": since } })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    async fn notify_new_device(&self, event: &SecurityEvent) {
+        let users: Collection<Document> = self.client.database("Account").collection("Users");
+        let email = match users.find_one(doc! { "_id": event.user_id }).await {
+            Ok(Some(user)) => match user.get_str("email") {
+                Ok(email) => email.to_string(),
+                Err(_) => return,
+            },
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Failed to load user for new device notification: {}", e);
+                return;
+            }
+        };
+
+        // Security notices are always on, even when `account_activities` emails are turned off
+        match EmailService::new(self.client.clone(), &self.email) {
+            Ok(email_service) => {
+                if let Err(e) = email_service
+                    .send_new_device_signin_email(&email, event.ip.as_deref(), event.timestamp)
+                    .await
+                {
+                    eprintln!("Failed to send new device signin email: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Email service unavailable for new device notification: {}", e),
+        }
+    }
+}
+
+impl WriteSink<SecurityEvent> for SecurityEventService {
+    fn write(&self, event: &SecurityEvent) -> impl Future<Output = Result<(), String>> + Send {
+        let event = event.clone();
+        async move { self.process(event).await.map_err(|e| e.to_string()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::sync::Mutex;
+
+    fn event(event_type: SecurityEventType, ip: &str, user_agent: &str) -> SecurityEvent {
+        SecurityEvent {
+            id: None,
+            user_id: ObjectId::new(),
+            event_type,
+            timestamp: DateTime::now(),
+            ip: Some(ip.to_string()),
+            user_agent_hash: Some(hash_user_agent(user_agent)),
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_uses_forwarded_ip_and_hashed_user_agent() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:51234".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .insert_header(("user-agent", "Mozilla/5.0 (Macintosh)"))
+            .to_http_request();
+        let fingerprint = ClientFingerprint::from_request(&req);
+        assert_eq!(fingerprint.ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(
+            fingerprint.user_agent_hash,
+            Some(hash_user_agent("Mozilla/5.0 (Macintosh)"))
+        );
+        assert_ne!(fingerprint.user_agent_hash.as_deref(), Some("Mozilla/5.0 (Macintosh)"));
+
+        let direct = TestRequest::default()
+            .peer_addr("198.51.100.4:443".parse().unwrap())
+            .to_http_request();
+        assert_eq!(ClientFingerprint::from_request(&direct).ip.as_deref(), Some("198.51.100.4"));
+    }
+
+    /// Keeps what it's sent, in order
+    #[derive(Clone, Default)]
+    struct CollectingSink(Arc<Mutex<Vec<SecurityEvent>>>);
+
+    impl WriteSink<SecurityEvent> for CollectingSink {
+        fn write(&self, event: &SecurityEvent) -> impl Future<Output = Result<(), String>> + Send {
+            self.0.lock().unwrap().push(event.clone());
+            std::future::ready(Ok(()))
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_queue_delivers_recorded_events_in_order() {
+        let sink = CollectingSink::default();
+        let queue = SecurityEventQueue::start_with(sink.clone());
+        let user_id = ObjectId::new();
+        let fingerprint = ClientFingerprint {
+            ip: Some("203.0.113.7".to_string()),
+            user_agent_hash: Some(hash_user_agent("curl/8.0")),
+        };
+
+        let types = [
+            SecurityEventType::SigninSuccess,
+            SecurityEventType::SigninFailed,
+            SecurityEventType::PasswordChanged,
+            SecurityEventType::EmailChanged,
+            SecurityEventType::PaymentMethodAdded,
+            SecurityEventType::TokenRefreshedFromNewDevice,
+            SecurityEventType::OauthLinked,
+            SecurityEventType::OauthLinkFailed,
+        ];
+        for event_type in types {
+            queue.record(user_id, event_type, &fingerprint);
+        }
+
+        for _ in 0..100 {
+            if sink.0.lock().unwrap().len() >= types.len() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.iter().map(|event| event.event_type).collect::<Vec<_>>(), types);
+        assert!(events.iter().all(|event| event.user_id == user_id && event.ip == fingerprint.ip));
+    }
+
+    #[test]
+    fn test_new_device_notifies_once_then_not_for_repeats() {
+        let mut history = vec![event(SecurityEventType::SigninSuccess, "198.51.100.4", "Safari")];
+
+        let mut notifications = 0;
+        for _ in 0..3 {
+            let signin = event(SecurityEventType::SigninSuccess, "203.0.113.7", "Firefox");
+            if is_new_device(&history, &signin) {
+                notifications += 1;
+            }
+            history.push(signin);
+        }
+        assert_eq!(notifications, 1);
+    }
+
+    #[test]
+    fn test_first_signin_and_failed_attempts_do_not_count() {
+        let first = event(SecurityEventType::SigninSuccess, "203.0.113.7", "Firefox");
+        assert!(!is_new_device(&[], &first));
+
+        // An attacker's failed attempts must not make their device look familiar
+        let history = vec![
+            event(SecurityEventType::SigninSuccess, "198.51.100.4", "Safari"),
+            event(SecurityEventType::SigninFailed, "203.0.113.7", "Firefox"),
+        ];
+        assert!(is_new_device(&history, &first));
+    }
+}
@@ -0,0 +1,477 @@
+//! Dependency checks for `--self-check` and `/health`
+//!
+//! `actota-api --self-check` runs every check, prints a report and exits instead
+//! of starting the server: non-zero if any check failed, so a deploy can stop a
+//! revision whose environment is broken before it takes traffic.
+//! `--self-check=json` prints the report as JSON instead.
+//!
+//! A check fails when the server can't work without it (bad config, MongoDB
+//! unreachable, Stripe refusing the key). It warns when a feature would be
+//! degraded or the service couldn't be reached to tell, and is skipped when the
+//! dependency isn't configured at all. `/health` shares the MongoDB, Stripe and
+//! Cloud Storage checks.
+
+use mongodb::{
+    bson::{doc, Document},
+    options::ClientOptions,
+    Client,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::services::credential_check::{BucketProbe, CredentialError, GcsProbe};
+use crate::services::storage::BucketKind;
+use crate::services::vertex_search_service::{VertexSearchService, VertexSearchSettings};
+
+/// Longest a check waits on an outside service
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Collections the API can't serve requests without, read once each
+pub const CRITICAL_COLLECTIONS: [(&str, &str); 5] = [
+    ("Account", "Users"),
+    ("Account", "Bookings"),
+    ("Itineraries", "Featured"),
+    ("Options", "Activity"),
+    ("Options", "Lodging"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// Works, or couldn't be told apart from working, with something degraded
+    Warn,
+    /// The server shouldn't run like this
+    Fail,
+    /// Not configured, so there was nothing to check
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub details: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus, details: impl Into<String>) -> Self {
+        CheckResult {
+            name,
+            status,
+            details: details.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfCheckReport {
+    /// `ok`, or `fail` when any check failed
+    pub status: CheckStatus,
+    pub version: &'static str,
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfCheckReport {
+    pub fn new(checks: Vec<CheckResult>) -> Self {
+        let failed = checks.iter().any(|check| check.status == CheckStatus::Fail);
+        SelfCheckReport {
+            status: if failed { CheckStatus::Fail } else { CheckStatus::Ok },
+            version: env!("CARGO_PKG_VERSION"),
+            checks,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.status != CheckStatus::Fail
+    }
+
+    /// The report for a terminal, one line per check
+    pub fn render_text(&self) -> String {
+        let mut text = format!("Self-check, actota-api {}\n", self.version);
+        for check in &self.checks {
+            let icon = match check.status {
+                CheckStatus::Ok => "✅",
+                CheckStatus::Warn => "⚠️ ",
+                CheckStatus::Fail => "❌",
+                CheckStatus::Skipped => "➖",
+            };
+            text.push_str(&format!("   {} {:<14} {}\n", icon, check.name, check.details));
+        }
+        let count = |status| self.checks.iter().filter(|check| check.status == status).count();
+        text.push_str(&format!(
+            "{}: {} failed, {} warnings\n",
+            if self.passed() { "PASSED" } else { "FAILED" },
+            count(CheckStatus::Fail),
+            count(CheckStatus::Warn)
+        ));
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfCheckOutput {
+    Text,
+    Json,
+}
+
+/// Whether the command line asks for a self-check: `--self-check` or
+/// `--self-check=json`. Any other value is an error.
+pub fn output_mode(args: impl IntoIterator<Item = String>) -> Result<Option<SelfCheckOutput>, String> {
+    for arg in args {
+        match arg.as_str() {
+            "--self-check" | "--self-check=text" => return Ok(Some(SelfCheckOutput::Text)),
+            "--self-check=json" => return Ok(Some(SelfCheckOutput::Json)),
+            other if other.starts_with("--self-check=") => {
+                return Err(format!("Unknown self-check output '{}'; use text or json", &other[13..]))
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Why an outside service didn't answer a probe the way it should
+#[derive(Debug)]
+pub enum ProbeError {
+    /// The service answered and refused the credentials or request
+    Rejected(String),
+    /// No usable answer, so whether it would work is unknown
+    Unreachable(String),
+}
+
+impl std::fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProbeError::Rejected(e) => write!(f, "rejected: {}", e),
+            ProbeError::Unreachable(e) => write!(f, "unreachable: {}", e),
+        }
+    }
+}
+
+/// The calls the checks make to outside services. `LiveProbes` in production;
+/// tests use fixed answers.
+pub trait Probes: BucketProbe {
+    /// An authenticated Stripe call that changes nothing
+    fn stripe_key(&self, secret_key: &str) -> impl Future<Output = Result<(), ProbeError>>;
+    /// One Vertex AI Search query
+    fn vertex_search(&self, settings: &VertexSearchSettings) -> impl Future<Output = Result<(), ProbeError>>;
+}
+
+pub struct LiveProbes;
+
+impl BucketProbe for LiveProbes {
+    async fn list_one(&self, bucket: &str) -> Result<(), CredentialError> {
+        GcsProbe.list_one(bucket).await
+    }
+}
+
+impl Probes for LiveProbes {
+    async fn stripe_key(&self, secret_key: &str) -> Result<(), ProbeError> {
+        let client = stripe::Client::new(secret_key.to_string());
+        match tokio::time::timeout(PROBE_TIMEOUT, stripe::Balance::retrieve(&client, None)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(stripe::StripeError::Stripe(e))) if matches!(e.http_status, 401 | 403) => {
+                Err(ProbeError::Rejected(e.message.unwrap_or_else(|| e.http_status.to_string())))
+            }
+            Ok(Err(e)) => Err(ProbeError::Unreachable(e.to_string())),
+            Err(_) => Err(ProbeError::Unreachable(format!("no answer after {}s", PROBE_TIMEOUT.as_secs()))),
+        }
+    }
+
+    async fn vertex_search(&self, settings: &VertexSearchSettings) -> Result<(), ProbeError> {
+        let service = VertexSearchService::new(settings);
+        match tokio::time::timeout(PROBE_TIMEOUT, service.search_activities(&[], "self check")).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(ProbeError::Unreachable(e.to_string())),
+            Err(_) => Err(ProbeError::Unreachable(format!("no answer after {}s", PROBE_TIMEOUT.as_secs()))),
+        }
+    }
+}
+
+/// The key's prefix and last four characters, enough to tell keys apart in a log
+fn mask(secret: &str) -> String {
+    if secret.len() > 12 && secret.is_ascii() {
+        format!("{}***{}", &secret[..8], &secret[secret.len() - 4..])
+    } else {
+        "***".to_string()
+    }
+}
+
+/// Every variable `AppConfig` reads, parsed the way the server would
+pub fn check_config(lookup: impl Fn(&str) -> Option<String>) -> CheckResult {
+    match AppConfig::from_lookup(lookup) {
+        Ok(_) => CheckResult::new("config", CheckStatus::Ok, "All required variables set and valid"),
+        Err(e) => CheckResult::new("config", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// A ping, then one read from each of `CRITICAL_COLLECTIONS`
+pub async fn check_mongodb(client: &Client) -> CheckResult {
+    if let Err(e) = client.database("Account").run_command(doc! { "ping": 1 }).await {
+        return CheckResult::new("mongodb", CheckStatus::Fail, format!("Failed to connect: {}", e));
+    }
+    for (database, collection) in CRITICAL_COLLECTIONS {
+        if let Err(e) = client
+            .database(database)
+            .collection::<Document>(collection)
+            .find_one(doc! {})
+            .projection(doc! { "_id": 1 })
+            .await
+        {
+            return CheckResult::new(
+                "mongodb",
+                CheckStatus::Fail,
+                format!("Can't read {}.{}: {}", database, collection, e),
+            );
+        }
+    }
+    CheckResult::new(
+        "mongodb",
+        CheckStatus::Ok,
+        format!("Connected; read {} critical collections", CRITICAL_COLLECTIONS.len()),
+    )
+}
+
+/// A client for `uri` that gives up quickly, for checking rather than serving
+async fn mongo_client(uri: &str) -> Result<Client, mongodb::error::Error> {
+    let mut options = ClientOptions::parse(uri).await?;
+    options.server_selection_timeout = Some(Duration::from_secs(10));
+    options.connect_timeout = Some(Duration::from_secs(10));
+    Client::with_options(options)
+}
+
+/// That the key is set and shaped like a Stripe secret or restricted key
+pub fn check_stripe_key(key: Option<&str>) -> CheckResult {
+    let Some(key) = key.map(str::trim).filter(|key| !key.is_empty()) else {
+        return CheckResult::new("stripe", CheckStatus::Fail, "STRIPE_SECRET_KEY not configured");
+    };
+    if !["sk_live_", "sk_test_", "rk_live_", "rk_test_"].iter().any(|prefix| key.starts_with(prefix)) {
+        return CheckResult::new(
+            "stripe",
+            CheckStatus::Fail,
+            "STRIPE_SECRET_KEY isn't a Stripe secret key (sk_live_, sk_test_, rk_live_ or rk_test_)",
+        );
+    }
+    CheckResult::new("stripe", CheckStatus::Ok, format!("Stripe API key configured ({})", mask(key)))
+}
+
+/// The key's shape, then whether Stripe accepts it
+pub async fn check_stripe(probes: &impl Probes, key: Option<&str>) -> CheckResult {
+    let result = check_stripe_key(key);
+    if result.status != CheckStatus::Ok {
+        return result;
+    }
+    match probes.stripe_key(key.unwrap_or_default().trim()).await {
+        Ok(()) => CheckResult::new("stripe", CheckStatus::Ok, format!("{}; accepted by Stripe", result.details)),
+        Err(e @ ProbeError::Rejected(_)) => CheckResult::new("stripe", CheckStatus::Fail, format!("Stripe key {}", e)),
+        Err(e) => CheckResult::new("stripe", CheckStatus::Warn, format!("Stripe {}", e)),
+    }
+}
+
+/// Lists one object from every configured bucket
+pub async fn check_storage(probe: &impl BucketProbe, lookup: impl Fn(&str) -> Option<String>) -> CheckResult {
+    let buckets: Vec<String> = BucketKind::ALL
+        .iter()
+        .filter_map(|kind| lookup(kind.env_var()).filter(|bucket| !bucket.trim().is_empty()))
+        .collect();
+    if buckets.is_empty() {
+        return CheckResult::new("cloud_storage", CheckStatus::Skipped, "No buckets configured");
+    }
+
+    let mut unreachable = None;
+    for bucket in &buckets {
+        match probe.list_one(bucket).await {
+            Ok(()) => {}
+            Err(e @ CredentialError::Unreachable(_)) => unreachable = Some(e),
+            Err(e) => return CheckResult::new("cloud_storage", CheckStatus::Fail, e.to_string()),
+        }
+    }
+    match unreachable {
+        Some(e) => CheckResult::new("cloud_storage", CheckStatus::Warn, e.to_string()),
+        None => CheckResult::new(
+            "cloud_storage",
+            CheckStatus::Ok,
+            format!("Buckets accessible: {}", buckets.join(", ")),
+        ),
+    }
+}
+
+/// SendGrid keys are `SG.<id>.<secret>`. Without one emails aren't sent, which
+/// only warns.
+pub fn check_sendgrid(key: Option<&str>) -> CheckResult {
+    let Some(key) = key.map(str::trim).filter(|key| !key.is_empty()) else {
+        return CheckResult::new("sendgrid", CheckStatus::Warn, "SENDGRID_API_KEY not configured; emails won't be sent");
+    };
+    let parts: Vec<&str> = key.split('.').collect();
+    if parts.len() == 3 && parts[0] == "SG" && parts[1..].iter().all(|part| !part.is_empty()) {
+        CheckResult::new("sendgrid", CheckStatus::Ok, format!("SendGrid API key configured ({})", mask(key)))
+    } else {
+        CheckResult::new("sendgrid", CheckStatus::Fail, "SENDGRID_API_KEY isn't a SendGrid key (SG.<id>.<secret>)")
+    }
+}
+
+/// Search falls back to MongoDB when Vertex is down, so only warns
+pub async fn check_vertex(probes: &impl Probes, lookup: impl Fn(&str) -> Option<String>) -> CheckResult {
+    let Some(settings) = VertexSearchSettings::from_lookup(|name| lookup(name).filter(|value| !value.trim().is_empty()))
+    else {
+        return CheckResult::new("vertex_search", CheckStatus::Skipped, "Vertex AI Search not configured");
+    };
+    match probes.vertex_search(&settings).await {
+        Ok(()) => CheckResult::new("vertex_search", CheckStatus::Ok, "Vertex AI Search answered a query"),
+        Err(e) => CheckResult::new("vertex_search", CheckStatus::Warn, format!("Vertex AI Search {}", e)),
+    }
+}
+
+/// There are no schema migrations to be behind on; indexes are created at
+/// startup. Reported so the report covers it explicitly.
+pub fn check_migrations() -> CheckResult {
+    CheckResult::new(
+        "migrations",
+        CheckStatus::Skipped,
+        "No schema migrations; indexes are created at startup",
+    )
+}
+
+/// Every check, against the variables in `lookup`
+pub async fn run(lookup: impl Fn(&str) -> Option<String>, probes: &impl Probes) -> SelfCheckReport {
+    let get = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+    let mut checks = vec![check_config(&lookup)];
+
+    checks.push(match get("MONGODB_URI") {
+        None => CheckResult::new("mongodb", CheckStatus::Fail, "MONGODB_URI not configured"),
+        Some(uri) => match mongo_client(&uri).await {
+            Ok(client) => check_mongodb(&client).await,
+            Err(e) => CheckResult::new("mongodb", CheckStatus::Fail, format!("Invalid MONGODB_URI: {}", e)),
+        },
+    });
+    checks.push(check_stripe(probes, get("STRIPE_SECRET_KEY").as_deref()).await);
+    checks.push(check_storage(probes, &get).await);
+    checks.push(check_sendgrid(get("SENDGRID_API_KEY").as_deref()));
+    checks.push(check_vertex(probes, &get).await);
+    checks.push(check_migrations());
+
+    SelfCheckReport::new(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct FixedProbes;
+
+    impl BucketProbe for FixedProbes {
+        async fn list_one(&self, bucket: &str) -> Result<(), CredentialError> {
+            Err(CredentialError::Forbidden {
+                bucket: bucket.to_string(),
+                detail: "storage.objects.list denied".to_string(),
+            })
+        }
+    }
+
+    impl Probes for FixedProbes {
+        async fn stripe_key(&self, _secret_key: &str) -> Result<(), ProbeError> {
+            Err(ProbeError::Rejected("Invalid API Key provided".to_string()))
+        }
+
+        async fn vertex_search(&self, _settings: &VertexSearchSettings) -> Result<(), ProbeError> {
+            Err(ProbeError::Unreachable("timed out".to_string()))
+        }
+    }
+
+    fn status(report: &SelfCheckReport, name: &str) -> CheckStatus {
+        report.checks.iter().find(|check| check.name == name).unwrap().status
+    }
+
+    #[actix_rt::test]
+    async fn test_broken_config_fails() {
+        let vars: HashMap<&str, &str> = HashMap::from([
+            ("JWT_SECRET", "secret"),
+            ("STRIPE_SECRET_KEY", "pk_test_publishable"),
+            ("ITINERARY_BUCKET", "actota-itineraries"),
+            ("SENDGRID_API_KEY", "not-a-key"),
+            ("GOOGLE_CLOUD_PROJECT_ID", "actota"),
+            ("VERTEX_SEARCH_DATA_STORE_ID", "activities"),
+            ("PORT", "eighty"),
+        ]);
+        let report = run(|name| vars.get(name).map(|value| value.to_string()), &FixedProbes).await;
+
+        assert!(!report.passed());
+        assert_eq!(report.status, CheckStatus::Fail);
+        let config = &report.checks[0];
+        assert_eq!(config.status, CheckStatus::Fail);
+        assert!(config.details.contains("MONGODB_URI"));
+        assert!(config.details.contains("PORT"));
+        assert_eq!(status(&report, "mongodb"), CheckStatus::Fail);
+        assert_eq!(status(&report, "stripe"), CheckStatus::Fail);
+        assert_eq!(status(&report, "cloud_storage"), CheckStatus::Fail);
+        assert_eq!(status(&report, "sendgrid"), CheckStatus::Fail);
+        assert_eq!(status(&report, "vertex_search"), CheckStatus::Warn);
+        assert_eq!(status(&report, "migrations"), CheckStatus::Skipped);
+        assert!(report.render_text().contains("FAILED: 5 failed, 1 warnings"));
+    }
+
+    #[actix_rt::test]
+    async fn test_rejected_stripe_key_fails_but_unreachable_warns() {
+        struct Offline;
+        impl BucketProbe for Offline {
+            async fn list_one(&self, _bucket: &str) -> Result<(), CredentialError> {
+                Err(CredentialError::Unreachable("dns".to_string()))
+            }
+        }
+        impl Probes for Offline {
+            async fn stripe_key(&self, _secret_key: &str) -> Result<(), ProbeError> {
+                Err(ProbeError::Unreachable("dns".to_string()))
+            }
+            async fn vertex_search(&self, _settings: &VertexSearchSettings) -> Result<(), ProbeError> {
+                Ok(())
+            }
+        }
+
+        let key = Some("sk_test_51Habcdefghijkl");
+        assert_eq!(check_stripe(&FixedProbes, key).await.status, CheckStatus::Fail);
+        assert_eq!(check_stripe(&Offline, key).await.status, CheckStatus::Warn);
+        let storage = check_storage(&Offline, |name| (name == "ITINERARY_BUCKET").then(|| "b".to_string())).await;
+        assert_eq!(storage.status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn test_key_formats() {
+        assert_eq!(check_sendgrid(Some("SG.abc.def")).status, CheckStatus::Ok);
+        assert_eq!(check_sendgrid(Some("SG..def")).status, CheckStatus::Fail);
+        assert_eq!(check_sendgrid(None).status, CheckStatus::Warn);
+        let stripe = check_stripe_key(Some("sk_live_51Habcdefghijkl"));
+        assert_eq!(stripe.status, CheckStatus::Ok);
+        assert!(!stripe.details.contains("51Habcdefgh"));
+        assert_eq!(check_stripe_key(Some("sk_test")).status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_json_report_schema_is_stable() {
+        let report = SelfCheckReport::new(vec![
+            CheckResult::new("config", CheckStatus::Ok, "fine"),
+            CheckResult::new("sendgrid", CheckStatus::Warn, "unset"),
+        ]);
+        let json = serde_json::to_value(&report).unwrap();
+        let mut keys: Vec<&str> = json.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["checks", "status", "version"]);
+        assert_eq!(json["status"], "ok");
+        assert_eq!(
+            json["checks"][1],
+            serde_json::json!({ "name": "sendgrid", "status": "warn", "details": "unset" })
+        );
+    }
+
+    #[test]
+    fn test_output_mode_from_args() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(output_mode(args(&[])), Ok(None));
+        assert_eq!(output_mode(args(&["--self-check"])), Ok(Some(SelfCheckOutput::Text)));
+        assert_eq!(output_mode(args(&["--self-check=json"])), Ok(Some(SelfCheckOutput::Json)));
+        assert!(output_mode(args(&["--self-check=xml"])).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Free-text special requests travelers attach to a booking ("vegetarian,
+//! afraid of heights, celebrating an anniversary")
+//!
+//! The text is shown to operators and copied into emails, so it is cleaned of
+//! control characters and must not contain payment details.
+
+use mongodb::bson::DateTime;
+
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+
+pub const MAX_SPECIAL_REQUESTS_CHARS: usize = 2_000;
+
+#[derive(Debug, PartialEq)]
+pub enum SpecialRequestsError {
+    TooLong,
+    /// Contains what looks like a full card number
+    PaymentDetails,
+}
+
+impl std::fmt::Display for SpecialRequestsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SpecialRequestsError::TooLong => write!(
+                f,
+                "Special requests can be at most {} characters",
+                MAX_SPECIAL_REQUESTS_CHARS
+            ),
+            SpecialRequestsError::PaymentDetails => write!(
+                f,
+                "Special requests look like they contain a card number. Please don't include payment details."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpecialRequestsError {}
+
+/// Clean and check special requests. Control characters other than newlines and
+/// tabs are dropped; blank input clears the field.
+pub fn sanitize_special_requests(input: &str) -> Result<Option<String>, SpecialRequestsError> {
+    let cleaned: String = input
+        .replace("\r\n", "\n")
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.is_empty() {
+        return Ok(None);
+    }
+    if cleaned.chars().count() > MAX_SPECIAL_REQUESTS_CHARS {
+        return Err(SpecialRequestsError::TooLong);
+    }
+    if contains_card_number(cleaned) {
+        return Err(SpecialRequestsError::PaymentDetails);
+    }
+    Ok(Some(cleaned.to_string()))
+}
+
+/// Whether `text` has 13-19 digits, written together or in groups split by single
+/// spaces or dashes (as cards usually are), that pass the Luhn check
+fn contains_card_number(text: &str) -> bool {
+    let mut groups: Vec<Vec<u32>> = vec![Vec::new()];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let current = groups.last_mut().unwrap();
+        if let Some(digit) = c.to_digit(10) {
+            current.push(digit);
+            continue;
+        }
+        // A single separator between digits starts the next group of the same run
+        let separator = (c == ' ' || c == '-')
+            && !current.is_empty()
+            && chars.peek().is_some_and(|next| next.is_ascii_digit());
+        if separator {
+            groups.push(Vec::new());
+        } else {
+            if has_card_number(&groups) {
+                return true;
+            }
+            groups = vec![Vec::new()];
+        }
+    }
+    has_card_number(&groups)
+}
+
+/// Any consecutive groups of a run that together make a Luhn-valid 13-19 digit
+/// number. Spans follow group boundaries so a phone number written next to a
+/// card still leaves the card detectable, without testing arbitrary substrings.
+fn has_card_number(groups: &[Vec<u32>]) -> bool {
+    (0..groups.len()).any(|start| {
+        let mut digits: Vec<u32> = Vec::new();
+        groups[start..].iter().any(|group| {
+            digits.extend(group);
+            (13..=19).contains(&digits.len()) && luhn_valid(&digits)
+        })
+    })
+}
+
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| {
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Special requests can be changed until the trip starts, and not on cancelled bookings
+pub fn special_requests_editable(booking: &BookingDetails, now: DateTime) -> bool {
+    now < booking.arrival_datetime
+        && !matches!(
+            booking.status,
+            PaymentStatus::Cancelled | PaymentStatus::Refunded
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn booking(arrival: DateTime, special_requests: Option<&str>) -> BookingDetails {
+        BookingDetails {
+            special_requests: special_requests.map(str::to_string),
+            ..BookingDetails::test(PaymentStatus::Confirmed, arrival, arrival)
+        }
+    }
+
+    #[test]
+    fn test_length_cap_and_cleanup() {
+        let at_limit = "a".repeat(MAX_SPECIAL_REQUESTS_CHARS);
+        assert_eq!(sanitize_special_requests(&at_limit), Ok(Some(at_limit.clone())));
+        assert_eq!(
+            sanitize_special_requests(&format!("{}a", at_limit)),
+            Err(SpecialRequestsError::TooLong)
+        );
+        // Multi-byte characters count once
+        assert!(sanitize_special_requests(&"é".repeat(MAX_SPECIAL_REQUESTS_CHARS)).is_ok());
+
+        assert_eq!(
+            sanitize_special_requests("  Vegetarian\r\nAfraid of heights\u{0}\u{7} "),
+            Ok(Some("Vegetarian\nAfraid of heights".to_string()))
+        );
+        assert_eq!(sanitize_special_requests(" \n "), Ok(None));
+    }
+
+    #[test]
+    fn test_card_numbers_are_rejected() {
+        for text in [
+            "Charge 4242424242424242 for the upgrade",
+            "card: 4242 4242 4242 4242, exp 12/29",
+            "Amex 3782-822463-10005",
+            "call me at 3035550142 4242424242424242",
+        ] {
+            assert_eq!(
+                sanitize_special_requests(text),
+                Err(SpecialRequestsError::PaymentDetails),
+                "{}",
+                text
+            );
+        }
+
+        // Phone numbers, dates and long digit runs that fail Luhn are fine
+        for text in [
+            "Celebrating our anniversary on 2026-06-14, call +1 303 555 0142",
+            "Confirmation 4242424242424241",
+            "Party of 2, arriving 10:30",
+        ] {
+            assert!(sanitize_special_requests(text).is_ok(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_edits_lock_at_arrival() {
+        let now = DateTime::from_millis(1_760_000_000_000);
+        let tomorrow = DateTime::from_millis(now.timestamp_millis() + 86_400_000);
+        let yesterday = DateTime::from_millis(now.timestamp_millis() - 86_400_000);
+
+        assert!(special_requests_editable(&booking(tomorrow, None), now));
+        assert!(!special_requests_editable(&booking(yesterday, None), now));
+        assert!(!special_requests_editable(&booking(now, None), now));
+
+        let mut cancelled = booking(tomorrow, None);
+        cancelled.status = PaymentStatus::Cancelled;
+        assert!(!special_requests_editable(&cancelled, now));
+    }
+
+    #[test]
+    fn test_special_requests_persist_through_storage() {
+        let stored = booking(DateTime::now(), Some("Vegetarian\nAfraid of heights"));
+        let document = mongodb::bson::to_document(&stored).unwrap();
+        let loaded: BookingDetails = mongodb::bson::from_document(document).unwrap();
+        assert_eq!(loaded.special_requests.as_deref(), Some("Vegetarian\nAfraid of heights"));
+
+        // Bookings stored before the field existed still load
+        let legacy = mongodb::bson::to_document(&booking(DateTime::now(), None)).unwrap();
+        assert!(!legacy.contains_key("special_requests"));
+        let loaded: BookingDetails = mongodb::bson::from_document(legacy).unwrap();
+        assert_eq!(loaded.special_requests, None);
+    }
+}
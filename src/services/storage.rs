@@ -0,0 +1,806 @@
+//! Cloud Storage buckets by purpose, and the facade handlers reach them through.
+//!
+//! Each kind of object has its own bucket with its own URL, size limit and
+//! visibility: itinerary images can sit in a public, CDN-backed bucket while profile
+//! pictures stay in a private one and are handed out as signed URLs. Buckets are
+//! read from the environment once into `AppConfig`:
+//!
+//! | Bucket             | Name variable          | Default              |
+//! |--------------------|------------------------|----------------------|
+//! | `itinerary_images` | `ITINERARY_BUCKET`     | `actota-itineraries` |
+//! | `profile_pictures` | `PROFILE_PIC_BUCKET`   | none                 |
+//! | `documents`        | `DOCUMENTS_BUCKET`     | none                 |
+//!
+//! Each name variable can be suffixed with `_VISIBILITY` (`public` or `signed`),
+//! `_URL` (defaults to `CLOUD_STORAGE_URL`), `_MAX_BYTES` and `_SIGNED_URL_TTL_SECS`
+//! (how long signed URLs stay valid, an hour by default and at most seven days).
+//! `STORAGE_REQUIRED_BUCKETS` lists the buckets the server won't start without.
+//!
+//! Signed URLs are cached per object and handed out again until less than a
+//! quarter of their lifetime is left, so listing a bucket's images doesn't sign
+//! every one of them on every request.
+
+use google_cloud_storage::client::{Client, ClientConfig};
+use google_cloud_storage::http::objects::{
+    get::GetObjectRequest,
+    list::ListObjectsRequest,
+    upload::{Media, UploadObjectRequest, UploadType},
+};
+use google_cloud_storage::sign::SignedURLOptions;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+pub const DEFAULT_STORAGE_URL: &str = "https://storage.googleapis.com";
+/// How long a signed URL handed out for a private bucket stays valid, unless
+/// the bucket sets its own
+pub const SIGNED_URL_TTL: Duration = Duration::from_secs(60 * 60);
+/// The longest Cloud Storage will sign a URL for
+pub const MAX_SIGNED_URL_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Signed URLs shared by every `Storage` built with `Storage::new`, which
+/// handlers do per request
+static SIGNED_URLS: LazyLock<Arc<SignedUrlCache>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BucketKind {
+    ItineraryImages,
+    ProfilePictures,
+    Documents,
+}
+
+impl BucketKind {
+    pub const ALL: [BucketKind; 3] = [
+        BucketKind::ItineraryImages,
+        BucketKind::ProfilePictures,
+        BucketKind::Documents,
+    ];
+
+    /// Name in `STORAGE_REQUIRED_BUCKETS` and in errors
+    pub fn name(self) -> &'static str {
+        match self {
+            BucketKind::ItineraryImages => "itinerary_images",
+            BucketKind::ProfilePictures => "profile_pictures",
+            BucketKind::Documents => "documents",
+        }
+    }
+
+    /// The variable naming the bucket
+    pub fn env_var(self) -> &'static str {
+        self.settings()[0]
+    }
+
+    /// The bucket, visibility, URL, size limit and signed URL lifetime variables
+    fn settings(self) -> [&'static str; 5] {
+        match self {
+            BucketKind::ItineraryImages => [
+                "ITINERARY_BUCKET",
+                "ITINERARY_BUCKET_VISIBILITY",
+                "ITINERARY_BUCKET_URL",
+                "ITINERARY_BUCKET_MAX_BYTES",
+                "ITINERARY_BUCKET_SIGNED_URL_TTL_SECS",
+            ],
+            BucketKind::ProfilePictures => [
+                "PROFILE_PIC_BUCKET",
+                "PROFILE_PIC_BUCKET_VISIBILITY",
+                "PROFILE_PIC_BUCKET_URL",
+                "PROFILE_PIC_BUCKET_MAX_BYTES",
+                "PROFILE_PIC_BUCKET_SIGNED_URL_TTL_SECS",
+            ],
+            BucketKind::Documents => [
+                "DOCUMENTS_BUCKET",
+                "DOCUMENTS_BUCKET_VISIBILITY",
+                "DOCUMENTS_BUCKET_URL",
+                "DOCUMENTS_BUCKET_MAX_BYTES",
+                "DOCUMENTS_BUCKET_SIGNED_URL_TTL_SECS",
+            ],
+        }
+    }
+
+    fn default_bucket(self) -> Option<&'static str> {
+        match self {
+            BucketKind::ItineraryImages => Some("actota-itineraries"),
+            _ => None,
+        }
+    }
+
+    fn default_max_bytes(self) -> u64 {
+        match self {
+            BucketKind::ItineraryImages => 10 * 1024 * 1024,
+            BucketKind::ProfilePictures => 5 * 1024 * 1024,
+            BucketKind::Documents => 20 * 1024 * 1024,
+        }
+    }
+}
+
+impl FromStr for BucketKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        BucketKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == value.trim())
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Objects are readable at their plain URL
+    Public,
+    /// Objects are only handed out as time-limited signed URLs
+    Signed,
+}
+
+impl FromStr for Visibility {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "public" => Ok(Visibility::Public),
+            "signed" => Ok(Visibility::Signed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketConfig {
+    pub bucket: String,
+    pub visibility: Visibility,
+    /// Storage host objects are addressed under, `{base_url}/{bucket}/{object}`
+    pub base_url: String,
+    pub max_object_bytes: u64,
+    /// How long signed URLs for this bucket stay valid
+    pub signed_url_ttl: Duration,
+}
+
+impl BucketConfig {
+    /// Where the object lives, whether or not it can be read there
+    pub fn object_url(&self, object: &str) -> String {
+        format!("{}/{}/{}", self.base_url.trim_end_matches('/'), self.bucket, object)
+    }
+
+    /// The object a stored reference points at: a bare object name, or a URL
+    /// (signed or not) under this bucket. URLs anywhere else aren't ours to check.
+    pub fn object_name(&self, reference: &str) -> Option<String> {
+        let reference = reference.trim();
+        let without_query = reference.split('?').next().unwrap_or_default();
+        let name = match without_query.strip_prefix(&self.object_url("")) {
+            Some(name) => name,
+            None if reference.contains("://") => return None,
+            None => without_query.trim_start_matches('/'),
+        };
+        (!name.is_empty()).then(|| name.to_string())
+    }
+}
+
+/// Every configured bucket. Buckets that aren't configured are left out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageConfig {
+    buckets: HashMap<BucketKind, BucketConfig>,
+}
+
+impl StorageConfig {
+    /// Read the buckets, recording unparseable values as `(variable, value)` in
+    /// `invalid` and required buckets that aren't configured in `missing`
+    pub fn from_lookup(
+        get: impl Fn(&str) -> Option<String>,
+        missing: &mut Vec<&'static str>,
+        invalid: &mut Vec<(&'static str, String)>,
+    ) -> Self {
+        let default_url = get("CLOUD_STORAGE_URL").unwrap_or_else(|| DEFAULT_STORAGE_URL.to_string());
+        let mut buckets = HashMap::new();
+        for kind in BucketKind::ALL {
+            let [name_var, visibility_var, url_var, max_bytes_var, ttl_var] = kind.settings();
+            let Some(bucket) = get(name_var)
+                .filter(|bucket| !bucket.trim().is_empty())
+                .or_else(|| kind.default_bucket().map(str::to_string))
+            else {
+                continue;
+            };
+            let visibility = match get(visibility_var) {
+                Some(value) => value.parse().unwrap_or_else(|_| {
+                    invalid.push((visibility_var, value));
+                    Visibility::Public
+                }),
+                None => Visibility::Public,
+            };
+            let max_object_bytes = match get(max_bytes_var) {
+                Some(value) => value.trim().parse().unwrap_or_else(|_| {
+                    invalid.push((max_bytes_var, value));
+                    kind.default_max_bytes()
+                }),
+                None => kind.default_max_bytes(),
+            };
+            let signed_url_ttl = match get(ttl_var) {
+                Some(value) => match value.trim().parse().map(Duration::from_secs) {
+                    Ok(ttl) if !ttl.is_zero() && ttl <= MAX_SIGNED_URL_TTL => ttl,
+                    _ => {
+                        invalid.push((ttl_var, value));
+                        SIGNED_URL_TTL
+                    }
+                },
+                None => SIGNED_URL_TTL,
+            };
+            buckets.insert(
+                kind,
+                BucketConfig {
+                    bucket: bucket.trim().to_string(),
+                    visibility,
+                    base_url: get(url_var).unwrap_or_else(|| default_url.clone()),
+                    max_object_bytes,
+                    signed_url_ttl,
+                },
+            );
+        }
+
+        let required = get("STORAGE_REQUIRED_BUCKETS").unwrap_or_else(|| BucketKind::ItineraryImages.name().to_string());
+        for name in required.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name.parse::<BucketKind>() {
+                Ok(kind) if !buckets.contains_key(&kind) => missing.push(kind.env_var()),
+                Ok(_) => {}
+                Err(_) => invalid.push(("STORAGE_REQUIRED_BUCKETS", name.to_string())),
+            }
+        }
+        StorageConfig { buckets }
+    }
+
+    pub fn bucket(&self, kind: BucketKind) -> Option<&BucketConfig> {
+        self.buckets.get(&kind)
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// The bucket for this kind of object isn't configured
+    NotConfigured(BucketKind),
+    TooLarge { size: u64, limit: u64 },
+    Gcs(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StorageError::NotConfigured(kind) => {
+                write!(f, "The {} bucket isn't configured ({})", kind.name(), kind.env_var())
+            }
+            StorageError::TooLarge { size, limit } => {
+                write!(f, "File is {} bytes, over the {} byte limit", size, limit)
+            }
+            StorageError::Gcs(e) => write!(f, "Cloud Storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// The storage calls the facade needs. Google Cloud Storage in production; tests
+/// keep objects in memory.
+pub trait ObjectStore {
+    fn upload(
+        &self,
+        bucket: &str,
+        object: &str,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> impl Future<Output = Result<(), StorageError>>;
+
+    /// Names of the objects under `prefix`
+    fn list(&self, bucket: &str, prefix: &str) -> impl Future<Output = Result<Vec<String>, StorageError>>;
+
+    /// Whether the object is in the bucket, without downloading it
+    fn exists(&self, bucket: &str, object: &str) -> impl Future<Output = Result<bool, StorageError>>;
+
+    fn signed_url(
+        &self,
+        bucket: &str,
+        object: &str,
+        expires: Duration,
+    ) -> impl Future<Output = Result<String, StorageError>>;
+}
+
+/// Google Cloud Storage with the Application Default Credentials. The client is
+/// created on first use, so a store that's never used needs no credentials.
+#[derive(Default)]
+pub struct GcsStore {
+    client: OnceCell<Client>,
+}
+
+impl GcsStore {
+    async fn client(&self) -> Result<&Client, StorageError> {
+        self.client
+            .get_or_try_init(|| async {
+                let config = ClientConfig::default()
+                    .with_auth()
+                    .await
+                    .map_err(|e| StorageError::Gcs(format!("Failed to set up credentials: {}", e)))?;
+                Ok(Client::new(config))
+            })
+            .await
+    }
+}
+
+impl ObjectStore for GcsStore {
+    async fn upload(&self, bucket: &str, object: &str, content_type: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        let mut media = Media::new(object.to_string());
+        media.content_type = content_type.to_string().into();
+        let request = UploadObjectRequest {
+            bucket: bucket.to_string(),
+            ..Default::default()
+        };
+        self.client()
+            .await?
+            .upload_object(&request, data, &UploadType::Simple(media))
+            .await
+            .map_err(|e| StorageError::Gcs(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let request = ListObjectsRequest {
+            bucket: bucket.to_string(),
+            prefix: Some(prefix.to_string()),
+            ..Default::default()
+        };
+        let response = self
+            .client()
+            .await?
+            .list_objects(&request)
+            .await
+            .map_err(|e| StorageError::Gcs(e.to_string()))?;
+        Ok(response.items.unwrap_or_default().into_iter().map(|item| item.name).collect())
+    }
+
+    async fn exists(&self, bucket: &str, object: &str) -> Result<bool, StorageError> {
+        let request = GetObjectRequest {
+            bucket: bucket.to_string(),
+            object: object.to_string(),
+            ..Default::default()
+        };
+        match self.client().await?.get_object(&request).await {
+            Ok(_) => Ok(true),
+            Err(google_cloud_storage::http::Error::Response(e)) if e.code == 404 => Ok(false),
+            Err(e) => Err(StorageError::Gcs(e.to_string())),
+        }
+    }
+
+    async fn signed_url(&self, bucket: &str, object: &str, expires: Duration) -> Result<String, StorageError> {
+        let options = SignedURLOptions {
+            expires,
+            ..Default::default()
+        };
+        self.client()
+            .await?
+            .signed_url(bucket, object, None, None, options)
+            .await
+            .map_err(|e| StorageError::Gcs(e.to_string()))
+    }
+}
+
+/// An uploaded object: the URL to store and the URL to show now. They differ for
+/// signed buckets, whose readable URL expires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredObject {
+    pub object_url: String,
+    pub url: String,
+}
+
+/// An object found by listing a prefix
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListedObject {
+    pub name: String,
+    pub object_url: String,
+    pub url: String,
+}
+
+fn is_image(name: &str) -> bool {
+    let name = name.to_lowercase();
+    [".jpg", ".jpeg", ".png"].iter().any(|extension| name.ends_with(extension))
+}
+
+/// Signed URLs by `(bucket, object)`, with when each expires
+#[derive(Default)]
+pub struct SignedUrlCache {
+    urls: Mutex<HashMap<(String, String), (String, Instant)>>,
+}
+
+impl SignedUrlCache {
+    /// The cached URL, unless less than a quarter of `ttl` is left on it
+    fn get(&self, bucket: &str, object: &str, ttl: Duration, now: Instant) -> Option<String> {
+        let urls = self.urls.lock().unwrap();
+        let (url, expires_at) = urls.get(&(bucket.to_string(), object.to_string()))?;
+        (*expires_at >= now + ttl / 4).then(|| url.clone())
+    }
+
+    /// Remember a URL signed at `now`, dropping the ones that have expired
+    fn insert(&self, bucket: &str, object: &str, url: String, ttl: Duration, now: Instant) {
+        let mut urls = self.urls.lock().unwrap();
+        urls.retain(|_, (_, expires_at)| *expires_at > now);
+        urls.insert((bucket.to_string(), object.to_string()), (url, now + ttl));
+    }
+}
+
+pub struct Storage<S = GcsStore> {
+    config: Arc<StorageConfig>,
+    store: S,
+    signed_urls: Arc<SignedUrlCache>,
+}
+
+impl Storage {
+    pub fn new(config: Arc<StorageConfig>) -> Self {
+        Storage {
+            config,
+            store: GcsStore::default(),
+            signed_urls: SIGNED_URLS.clone(),
+        }
+    }
+}
+
+impl<S: ObjectStore> Storage<S> {
+    /// A storage on `store` with signed URLs cached for it alone
+    pub fn with_store(config: Arc<StorageConfig>, store: S) -> Self {
+        Storage {
+            config,
+            store,
+            signed_urls: Arc::default(),
+        }
+    }
+
+    pub fn bucket(&self, kind: BucketKind) -> Result<&BucketConfig, StorageError> {
+        self.config.bucket(kind).ok_or(StorageError::NotConfigured(kind))
+    }
+
+    /// A readable URL for an object: the plain URL for public buckets, a signed
+    /// one for the others, reused until it's close to expiring
+    pub async fn url(&self, kind: BucketKind, object: &str) -> Result<String, StorageError> {
+        let bucket = self.bucket(kind)?;
+        if bucket.visibility == Visibility::Public {
+            return Ok(bucket.object_url(object));
+        }
+        let ttl = bucket.signed_url_ttl;
+        if let Some(url) = self.signed_urls.get(&bucket.bucket, object, ttl, Instant::now()) {
+            return Ok(url);
+        }
+        // Timed from before signing, so the cached expiry is never later than the real one
+        let signed_at = Instant::now();
+        let url = self.store.signed_url(&bucket.bucket, object, ttl).await?;
+        self.signed_urls.insert(&bucket.bucket, object, url.clone(), ttl, signed_at);
+        Ok(url)
+    }
+
+    /// Upload an object, refusing files over the bucket's size limit
+    pub async fn upload(
+        &self,
+        kind: BucketKind,
+        object: &str,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> Result<StoredObject, StorageError> {
+        let bucket = self.bucket(kind)?;
+        let size = data.len() as u64;
+        if size > bucket.max_object_bytes {
+            return Err(StorageError::TooLarge {
+                size,
+                limit: bucket.max_object_bytes,
+            });
+        }
+        self.store.upload(&bucket.bucket, object, content_type, data).await?;
+        Ok(StoredObject {
+            object_url: bucket.object_url(object),
+            url: self.url(kind, object).await?,
+        })
+    }
+
+    /// Readable URLs of the images under `prefix`
+    pub async fn image_urls(&self, kind: BucketKind, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let bucket = self.bucket(kind)?;
+        let mut urls = Vec::new();
+        for name in self.store.list(&bucket.bucket, prefix).await? {
+            if is_image(&name) {
+                urls.push(self.url(kind, &name).await?);
+            }
+        }
+        Ok(urls)
+    }
+
+    /// Every object under `prefix`, images or not, with where it lives and a
+    /// readable URL
+    pub async fn objects(&self, kind: BucketKind, prefix: &str) -> Result<Vec<ListedObject>, StorageError> {
+        let bucket = self.bucket(kind)?;
+        let mut objects = Vec::new();
+        for name in self.store.list(&bucket.bucket, prefix).await? {
+            objects.push(ListedObject {
+                object_url: bucket.object_url(&name),
+                url: self.url(kind, &name).await?,
+                name,
+            });
+        }
+        Ok(objects)
+    }
+
+    /// The objects `references` point at that aren't in the bucket, in order and
+    /// without repeats. References outside the bucket aren't checked.
+    pub async fn missing_objects(&self, kind: BucketKind, references: &[String]) -> Result<Vec<String>, StorageError> {
+        let bucket = self.bucket(kind)?;
+        let mut missing: Vec<String> = Vec::new();
+        for name in references.iter().filter_map(|reference| bucket.object_name(reference)) {
+            if !missing.contains(&name) && !self.store.exists(&bucket.bucket, &name).await? {
+                missing.push(name);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// A user's profile picture goes in their own folder of the profile bucket
+    pub async fn upload_profile_picture(
+        &self,
+        user_id: &str,
+        extension: &str,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> Result<StoredObject, StorageError> {
+        let object = format!("{}/profile-pic.{}", user_id, extension);
+        self.upload(BucketKind::ProfilePictures, &object, content_type, data).await
+    }
+}
+
+/// A store for tests elsewhere in the crate to build a `Storage` on
+#[cfg(test)]
+pub(crate) mod memory {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Keeps uploads in memory as `(bucket, object, content type, size)`
+    #[derive(Default)]
+    pub struct MemoryStore {
+        pub objects: Mutex<Vec<(String, String, String, usize)>>,
+        /// How many URLs have been signed
+        pub signatures: AtomicUsize,
+    }
+
+    impl ObjectStore for MemoryStore {
+        async fn upload(&self, bucket: &str, object: &str, content_type: &str, data: Vec<u8>) -> Result<(), StorageError> {
+            self.objects.lock().unwrap().push((
+                bucket.to_string(),
+                object.to_string(),
+                content_type.to_string(),
+                data.len(),
+            ));
+            Ok(())
+        }
+
+        async fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<String>, StorageError> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(stored_in, object, _, _)| stored_in == bucket && object.starts_with(prefix))
+                .map(|(_, object, _, _)| object.clone())
+                .collect())
+        }
+
+        async fn exists(&self, bucket: &str, object: &str) -> Result<bool, StorageError> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(stored_in, stored, _, _)| stored_in == bucket && stored == object))
+        }
+
+        async fn signed_url(&self, bucket: &str, object: &str, expires: Duration) -> Result<String, StorageError> {
+            self.signatures.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("https://signed.example.com/{}/{}?expires={}", bucket, object, expires.as_secs()))
+        }
+    }
+
+    /// The default buckets, kept in memory
+    pub fn storage() -> Storage<MemoryStore> {
+        let config = StorageConfig::from_lookup(|_| None, &mut Vec::new(), &mut Vec::new());
+        Storage::with_store(Arc::new(config), MemoryStore::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::memory::MemoryStore;
+    use super::*;
+
+    fn load(vars: &[(&str, &str)]) -> Result<StorageConfig, (Vec<&'static str>, Vec<(&'static str, String)>)> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let (mut missing, mut invalid) = (Vec::new(), Vec::new());
+        let config = StorageConfig::from_lookup(|name| vars.get(name).cloned(), &mut missing, &mut invalid);
+        if missing.is_empty() && invalid.is_empty() {
+            Ok(config)
+        } else {
+            Err((missing, invalid))
+        }
+    }
+
+    fn storage(vars: &[(&str, &str)]) -> Storage<MemoryStore> {
+        Storage::with_store(Arc::new(load(vars).unwrap()), MemoryStore::default())
+    }
+
+    #[test]
+    fn test_buckets_from_the_environment() {
+        let config = load(&[
+            ("PROFILE_PIC_BUCKET", "actota-profile-pictures"),
+            ("PROFILE_PIC_BUCKET_VISIBILITY", "signed"),
+            ("PROFILE_PIC_BUCKET_MAX_BYTES", "1048576"),
+            ("ITINERARY_BUCKET_URL", "https://cdn.actota.com"),
+        ])
+        .unwrap();
+        let itinerary = config.bucket(BucketKind::ItineraryImages).unwrap();
+        assert_eq!(itinerary.bucket, "actota-itineraries");
+        assert_eq!(itinerary.visibility, Visibility::Public);
+        assert_eq!(itinerary.object_url("abc/cover.jpg"), "https://cdn.actota.com/actota-itineraries/abc/cover.jpg");
+        let profile = config.bucket(BucketKind::ProfilePictures).unwrap();
+        assert_eq!(profile.visibility, Visibility::Signed);
+        assert_eq!(profile.max_object_bytes, 1_048_576);
+        assert_eq!(profile.base_url, DEFAULT_STORAGE_URL);
+        assert!(config.bucket(BucketKind::Documents).is_none());
+
+        let (missing, invalid) = load(&[
+            ("STORAGE_REQUIRED_BUCKETS", "itinerary_images, profile_pictures, photos"),
+            ("ITINERARY_BUCKET_VISIBILITY", "private"),
+        ])
+        .unwrap_err();
+        assert_eq!(missing, ["PROFILE_PIC_BUCKET"]);
+        assert_eq!(
+            invalid,
+            [
+                ("ITINERARY_BUCKET_VISIBILITY", "private".to_string()),
+                ("STORAGE_REQUIRED_BUCKETS", "photos".to_string()),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_each_bucket_enforces_its_own_size_limit() {
+        let storage = storage(&[
+            ("PROFILE_PIC_BUCKET", "actota-profile-pictures"),
+            ("PROFILE_PIC_BUCKET_MAX_BYTES", "1000"),
+            ("ITINERARY_BUCKET_MAX_BYTES", "5000"),
+        ]);
+
+        let too_large = storage
+            .upload(BucketKind::ProfilePictures, "u1/profile-pic.png", "image/png", vec![0; 2000])
+            .await;
+        assert!(matches!(too_large, Err(StorageError::TooLarge { size: 2000, limit: 1000 })));
+        storage
+            .upload(BucketKind::ItineraryImages, "i1/cover.png", "image/png", vec![0; 2000])
+            .await
+            .unwrap();
+        assert!(storage
+            .upload(BucketKind::Documents, "d1/waiver.pdf", "application/pdf", vec![0; 10])
+            .await
+            .is_err());
+        assert_eq!(storage.store.objects.lock().unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_profile_pictures_go_to_the_profile_bucket() {
+        let storage = storage(&[
+            ("PROFILE_PIC_BUCKET", "actota-profile-pictures"),
+            ("PROFILE_PIC_BUCKET_VISIBILITY", "signed"),
+        ]);
+
+        let stored = storage
+            .upload_profile_picture("u1", "png", "image/png", vec![0; 10])
+            .await
+            .unwrap();
+        assert_eq!(
+            *storage.store.objects.lock().unwrap(),
+            [(
+                "actota-profile-pictures".to_string(),
+                "u1/profile-pic.png".to_string(),
+                "image/png".to_string(),
+                10
+            )]
+        );
+        assert_eq!(stored.object_url, "https://storage.googleapis.com/actota-profile-pictures/u1/profile-pic.png");
+        assert_eq!(stored.url, "https://signed.example.com/actota-profile-pictures/u1/profile-pic.png?expires=3600");
+
+        // Listing signs each image too, and skips what isn't one
+        storage
+            .upload(BucketKind::ProfilePictures, "u1/notes.txt", "text/plain", vec![0; 1])
+            .await
+            .unwrap();
+        let urls = storage.image_urls(BucketKind::ProfilePictures, "u1").await.unwrap();
+        assert_eq!(urls, [stored.url]);
+    }
+
+    #[actix_rt::test]
+    async fn test_signed_urls_are_reused_until_near_expiry() {
+        let storage = storage(&[
+            ("ITINERARY_BUCKET_VISIBILITY", "signed"),
+            ("ITINERARY_BUCKET_SIGNED_URL_TTL_SECS", "900"),
+        ]);
+        for name in ["i1/cover.jpg", "i1/beach.png"] {
+            storage
+                .upload(BucketKind::ItineraryImages, name, "image/jpeg", vec![0; 10])
+                .await
+                .unwrap();
+        }
+        let signatures = || storage.store.signatures.load(std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(signatures(), 2);
+
+        // Both were signed on upload, so listing them again signs nothing
+        let urls = storage.image_urls(BucketKind::ItineraryImages, "i1").await.unwrap();
+        assert_eq!(
+            urls,
+            [
+                "https://signed.example.com/actota-itineraries/i1/cover.jpg?expires=900",
+                "https://signed.example.com/actota-itineraries/i1/beach.png?expires=900",
+            ]
+        );
+        storage.image_urls(BucketKind::ItineraryImages, "i1").await.unwrap();
+        assert_eq!(signatures(), 2);
+
+        let ttl = Duration::from_secs(900);
+        let cache = SignedUrlCache::default();
+        let signed_at = Instant::now();
+        cache.insert("b", "o", "url".to_string(), ttl, signed_at);
+        assert_eq!(cache.get("b", "o", ttl, signed_at + Duration::from_secs(600)), Some("url".to_string()));
+        // With under a quarter of its lifetime left it's signed again
+        assert_eq!(cache.get("b", "o", ttl, signed_at + Duration::from_secs(700)), None);
+        assert_eq!(cache.get("b", "other", ttl, signed_at), None);
+
+        // Public buckets hand out plain URLs and never sign
+        let public = super::memory::storage();
+        let url = public.url(BucketKind::ItineraryImages, "i1/cover.jpg").await.unwrap();
+        assert_eq!(url, "https://storage.googleapis.com/actota-itineraries/i1/cover.jpg");
+        assert_eq!(public.store.signatures.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_signed_url_lifetime_is_bounded() {
+        let config = load(&[
+            ("PROFILE_PIC_BUCKET", "actota-profile-pictures"),
+            ("PROFILE_PIC_BUCKET_SIGNED_URL_TTL_SECS", "900"),
+        ])
+        .unwrap();
+        assert_eq!(config.bucket(BucketKind::ProfilePictures).unwrap().signed_url_ttl, Duration::from_secs(900));
+        assert_eq!(config.bucket(BucketKind::ItineraryImages).unwrap().signed_url_ttl, SIGNED_URL_TTL);
+
+        for value in ["0", "604801", "an hour"] {
+            let (_, invalid) = load(&[("ITINERARY_BUCKET_SIGNED_URL_TTL_SECS", value)]).unwrap_err();
+            assert_eq!(invalid, [("ITINERARY_BUCKET_SIGNED_URL_TTL_SECS", value.to_string())]);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_missing_objects_are_found_by_name_or_url() {
+        let storage = storage(&[]);
+        storage
+            .upload(BucketKind::ItineraryImages, "i1/cover.jpg", "image/jpeg", vec![0; 10])
+            .await
+            .unwrap();
+        let bucket = storage.bucket(BucketKind::ItineraryImages).unwrap();
+        assert_eq!(
+            bucket.object_name("https://storage.googleapis.com/actota-itineraries/i1/cover.jpg?X-Goog-Expires=3600"),
+            Some("i1/cover.jpg".to_string())
+        );
+        assert_eq!(bucket.object_name("/i1/cover.jpg"), Some("i1/cover.jpg".to_string()));
+        assert_eq!(bucket.object_name("https://cdn.fareharbor.com/images/tour.jpg"), None);
+
+        let references = [
+            "https://storage.googleapis.com/actota-itineraries/i1/cover.jpg",
+            "i1/gone.jpg",
+            "https://storage.googleapis.com/actota-itineraries/i1/gone.jpg",
+            "https://cdn.fareharbor.com/images/tour.jpg",
+        ]
+        .map(str::to_string);
+        let missing = storage
+            .missing_objects(BucketKind::ItineraryImages, &references)
+            .await
+            .unwrap();
+        assert_eq!(missing, ["i1/gone.jpg"]);
+    }
+}
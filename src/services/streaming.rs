@@ -0,0 +1,238 @@
+//! Streamed CSV and NDJSON bodies for admin exports
+//!
+//! Rows are written to the response as the cursor yields them, a chunk at a
+//! time, so an export holds at most one chunk in memory however large the
+//! collection. The body has no length, so actix sends it chunked. A cursor error
+//! partway through ends the body with a trailer line (see `ERROR_TRAILER`) so a
+//! cut-short export can't be mistaken for a complete one.
+
+use actix_web::{body::BodyStream, web::Bytes, HttpResponse};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::pin::Pin;
+
+/// A chunk is sent once the buffered rows reach this size
+const FLUSH_BYTES: usize = 64 * 1024;
+
+/// Starts the last line of an export that failed partway through
+pub const ERROR_TRAILER: &str = "#export-error";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// A row of an export. NDJSON lines are the row's JSON; CSV uses the columns below.
+pub trait ExportRow: Serialize {
+    fn csv_header() -> &'static [&'static str];
+    fn csv_fields(&self) -> Vec<String>;
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|field| csv_field(field.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+fn row_line<T: ExportRow>(row: &T, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Csv => csv_line(&row.csv_fields()),
+        ExportFormat::Ndjson => {
+            let mut line = serde_json::to_string(row).unwrap_or_else(|e| {
+                serde_json::json!({ "serialization_error": e.to_string() }).to_string()
+            });
+            line.push('\n');
+            line
+        }
+    }
+}
+
+fn error_line(error: &str, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Csv => format!("{} {}\n", ERROR_TRAILER, error.replace(['\n', '\r'], " ")),
+        ExportFormat::Ndjson => format!("{}\n", serde_json::json!({ ERROR_TRAILER: error })),
+    }
+}
+
+struct ExportState<T, E> {
+    rows: Pin<Box<dyn Stream<Item = Result<T, E>>>>,
+    buffer: String,
+    format: ExportFormat,
+    finished: bool,
+}
+
+/// Encode `rows` as chunks of `format` lines, ending with a trailer on the first error
+pub fn export_chunks<T, E, S>(
+    rows: S,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>>
+where
+    T: ExportRow + 'static,
+    E: Display + 'static,
+    S: Stream<Item = Result<T, E>> + 'static,
+{
+    let buffer = match format {
+        ExportFormat::Csv => csv_line(T::csv_header()),
+        ExportFormat::Ndjson => String::new(),
+    };
+    let state = ExportState {
+        rows: Box::pin(rows),
+        buffer,
+        format,
+        finished: false,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        if state.finished {
+            return None;
+        }
+        loop {
+            match state.rows.next().await {
+                Some(Ok(row)) => {
+                    state.buffer.push_str(&row_line(&row, state.format));
+                    if state.buffer.len() >= FLUSH_BYTES {
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
+                    eprintln!("Export stopped partway: {}", e);
+                    state.buffer.push_str(&error_line(&e.to_string(), state.format));
+                    state.finished = true;
+                    break;
+                }
+                None => {
+                    state.finished = true;
+                    if state.buffer.is_empty() {
+                        return None;
+                    }
+                    break;
+                }
+            }
+        }
+        let chunk = Bytes::from(std::mem::take(&mut state.buffer));
+        Some((Ok(chunk), state))
+    })
+}
+
+/// A chunked download of `rows`, saved by browsers as `filename` plus the format's extension
+pub fn export_response<T, E, S>(rows: S, format: ExportFormat, filename: &str) -> HttpResponse
+where
+    T: ExportRow + 'static,
+    E: Display + 'static,
+    S: Stream<Item = Result<T, E>> + 'static,
+{
+    let extension = match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Ndjson => "ndjson",
+    };
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.{}\"", filename, extension),
+        ))
+        .body(BodyStream::new(export_chunks(rows, format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::{BodySize, MessageBody};
+
+    #[derive(Serialize)]
+    struct Row {
+        id: usize,
+        note: String,
+    }
+
+    impl ExportRow for Row {
+        fn csv_header() -> &'static [&'static str] {
+            &["id", "note"]
+        }
+
+        fn csv_fields(&self) -> Vec<String> {
+            vec![self.id.to_string(), self.note.clone()]
+        }
+    }
+
+    /// Rows made as they're pulled, like a cursor, never held all at once
+    fn rows(count: usize) -> impl Stream<Item = Result<Row, String>> {
+        futures::stream::iter(0..count).map(|id| {
+            Ok(Row {
+                id,
+                note: format!("booking {}, \"party\" of {}", id, id % 6),
+            })
+        })
+    }
+
+    async fn collect(stream: impl Stream<Item = Result<Bytes, actix_web::Error>>) -> Vec<Bytes> {
+        stream.map(|chunk| chunk.unwrap()).collect().await
+    }
+
+    #[actix_rt::test]
+    async fn test_large_export_is_sent_in_bounded_chunks() {
+        let chunks = collect(export_chunks(rows(10_000), ExportFormat::Csv)).await;
+
+        assert!(chunks.len() > 1, "rows should be flushed as they come");
+        let longest_line = 64;
+        assert!(chunks.iter().all(|chunk| chunk.len() < FLUSH_BYTES + longest_line));
+
+        let body: String = chunks.iter().map(|chunk| String::from_utf8_lossy(chunk)).collect();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 10_001);
+        assert_eq!(lines[0], "id,note");
+        assert_eq!(lines[1], "0,\"booking 0, \"\"party\"\" of 0\"");
+        assert!(!body.contains(ERROR_TRAILER));
+    }
+
+    #[actix_rt::test]
+    async fn test_cursor_error_ends_with_trailer() {
+        let failing = rows(3).chain(futures::stream::iter(vec![Err("cursor killed".to_string())]));
+        let body: String = collect(export_chunks(failing, ExportFormat::Ndjson))
+            .await
+            .iter()
+            .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+            .collect();
+        let lines: Vec<&str> = body.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        let trailer: serde_json::Value = serde_json::from_str(lines[3]).unwrap();
+        assert_eq!(trailer[ERROR_TRAILER], "cursor killed");
+    }
+
+    #[test]
+    fn test_response_has_no_content_length() {
+        let response = export_response(rows(10), ExportFormat::Ndjson, "bookings");
+        assert_eq!(response.body().size(), BodySize::Stream);
+        assert!(response.headers().get("content-length").is_none());
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+    }
+}
@@ -9,12 +9,16 @@ use super::models::customer::CustomerData;
 
 pub struct StripeProvider {
     pub client: stripe::Client,
+    /// Also sent directly on the payment method calls stripe-rust doesn't cover
+    api_key: String,
 }
 
 impl StripeProvider {
     pub fn new(api_key: impl Into<String>) -> Self {
+        let api_key = api_key.into();
         Self {
-            client: stripe::Client::new(api_key.into()),
+            client: stripe::Client::new(api_key.clone()),
+            api_key,
         }
     }
 }
@@ -56,10 +60,6 @@ impl PaymentOperations for StripeProvider {
         &self,
         customer_id: String,
     ) -> Result<Vec<PaymentMethod>, PaymentError> {
-        let api_key = std::env::var("STRIPE_SECRET_KEY").unwrap();
-
-        println!("api_key: {}", api_key);
-
         let client = reqwest::Client::new();
         let url = format!(
             "https://api.stripe.com/v1/customers/{}/payment_methods",
@@ -70,7 +70,7 @@ impl PaymentOperations for StripeProvider {
 
         let res = match client
             .get(&url)
-            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Authorization", format!("Bearer {}", self.api_key))
             .send()
             .await
         {
@@ -95,8 +95,6 @@ impl PaymentOperations for StripeProvider {
         customer_id: String,
         payment_id: String,
     ) -> Result<HttpResponse, PaymentError> {
-        let api_key = std::env::var("STRIPE_SECRET_KEY").unwrap();
-
         let client = reqwest::Client::new();
         let url = format!(
             "https://api.stripe.com/v1/payment_methods/{}/attach",
@@ -108,7 +106,7 @@ impl PaymentOperations for StripeProvider {
 
         let res = match client
             .post(&url) // Changed to POST from GET
-            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Authorization", format!("Bearer {}", self.api_key))
             .form(&params) // Added form parameters
             .send()
             .await
@@ -129,8 +127,6 @@ impl PaymentOperations for StripeProvider {
         customer_id: String,
         payment_id: String,
     ) -> Result<HttpResponse, PaymentError> {
-        let api_key = std::env::var("STRIPE_SECRET_KEY").unwrap();
-
         let client = reqwest::Client::new();
         let url = format!(
             "https://api.stripe.com/v1/payment_methods/{}/detach",
@@ -147,7 +143,7 @@ impl PaymentOperations for StripeProvider {
         // The payment method already knows which customer it's attached to
         let res = match client
             .post(&url) // Stripe uses POST not DELETE for the detach operation
-            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Authorization", format!("Bearer {}", self.api_key))
             .send()
             .await
         {
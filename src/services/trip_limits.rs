@@ -0,0 +1,185 @@
+//! Business limits on trips, checked wherever a trip's length or party is chosen:
+//! search, generation, booking and checkout. Configured with `MAX_TRIP_DAYS`,
+//! `MAX_PARTY_SIZE`, `MAX_ACTIVITIES_FETCH`, `MAX_VERTEX_ACTIVITIES` and
+//! `MAX_VERTEX_ACTIVITIES_PER_TYPE`.
+
+use mongodb::bson::DateTime;
+
+use crate::models::api_error::ApiError;
+use crate::models::itinerary::base::FeaturedVacation;
+use crate::models::search::SearchItinerary;
+use crate::services::itinerary_generation_service::ItineraryGenerator;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TripLimits {
+    /// Longest trip, in nights, that can be searched for, generated or booked
+    pub max_trip_days: u32,
+    /// Most travelers in one party, infants included
+    pub max_party_size: u32,
+    /// Most activities generation reads from MongoDB for one itinerary. Longer
+    /// trips need more to avoid running out of unique activities.
+    pub max_activities_fetch: u32,
+    /// Most activities one search keeps from Vertex AI across its activity types
+    pub max_vertex_activities: u32,
+    /// Most activities kept from Vertex AI for any one activity type, so a broad
+    /// type can't crowd out the rest
+    pub max_vertex_activities_per_type: u32,
+}
+
+impl Default for TripLimits {
+    fn default() -> Self {
+        TripLimits {
+            max_trip_days: 14,
+            max_party_size: 16,
+            max_activities_fetch: 50,
+            max_vertex_activities: 60,
+            max_vertex_activities_per_type: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitExceeded {
+    TripDays { requested: i64, limit: u32 },
+    PartySize { requested: u32, limit: u32 },
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LimitExceeded::TripDays { requested, limit } => write!(
+                f,
+                "Trips can be at most {} days long; this one is {} days",
+                limit, requested
+            ),
+            LimitExceeded::PartySize { requested, limit } => write!(
+                f,
+                "Parties can have at most {} travelers; this one has {}",
+                limit, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl LimitExceeded {
+    pub fn limit(&self) -> u32 {
+        match self {
+            LimitExceeded::TripDays { limit, .. } | LimitExceeded::PartySize { limit, .. } => *limit,
+        }
+    }
+
+    /// The request field to point the traveler at
+    pub fn field(&self) -> &'static str {
+        match self {
+            LimitExceeded::TripDays { .. } => "departure_datetime",
+            LimitExceeded::PartySize { .. } => "adults",
+        }
+    }
+
+    pub fn to_api_error(&self) -> ApiError {
+        ApiError {
+            error: self.to_string(),
+            field: Some(self.field().to_string()),
+            expected: None,
+            limit: Some(self.limit()),
+        }
+    }
+}
+
+impl TripLimits {
+    pub fn check_trip_days(&self, days: i64) -> Result<(), LimitExceeded> {
+        if days > self.max_trip_days as i64 {
+            return Err(LimitExceeded::TripDays {
+                requested: days,
+                limit: self.max_trip_days,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_party_size(&self, travelers: u32) -> Result<(), LimitExceeded> {
+        if travelers > self.max_party_size {
+            return Err(LimitExceeded::PartySize {
+                requested: travelers,
+                limit: self.max_party_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// A booking's dates, counted in whole days as generation counts them
+    pub fn check_booking_dates(&self, arrival: DateTime, departure: DateTime) -> Result<(), LimitExceeded> {
+        self.check_trip_days((departure.timestamp_millis() - arrival.timestamp_millis()) / MILLIS_PER_DAY)
+    }
+
+    /// The party recorded on an itinerary being booked or paid for
+    pub fn check_itinerary(&self, itinerary: &FeaturedVacation) -> Result<(), LimitExceeded> {
+        itinerary
+            .party_size()
+            .map_or(Ok(()), |travelers| self.check_party_size(travelers))
+    }
+
+    /// A search's party and dates. Dates that don't parse are left to generation
+    /// to report, as before.
+    pub fn check_search(&self, search: &SearchItinerary) -> Result<(), LimitExceeded> {
+        let travelers =
+            search.adults.unwrap_or(0) + search.children.unwrap_or(0) + search.infants.unwrap_or(0);
+        self.check_party_size(travelers)?;
+
+        let parse = |datetime: &Option<String>| {
+            datetime
+                .as_deref()
+                .and_then(|datetime| ItineraryGenerator::parse_datetime(datetime).ok())
+        };
+        if let (Some(arrival), Some(departure)) =
+            (parse(&search.arrival_datetime), parse(&search.departure_datetime))
+        {
+            self.check_trip_days((departure - arrival).num_days())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(arrival: &str, departure: &str, adults: u32) -> SearchItinerary {
+        serde_json::from_value(serde_json::json!({
+            "locations": ["Denver, CO"],
+            "arrival_datetime": arrival,
+            "departure_datetime": departure,
+            "adults": adults,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_trip_length_and_party_limits() {
+        let limits = TripLimits::default();
+        assert_eq!(limits.check_search(&search("2025-07-01", "2025-07-15", 16)), Ok(()));
+
+        let err = limits.check_search(&search("2025-07-01", "2025-07-21", 2)).unwrap_err();
+        assert_eq!(err, LimitExceeded::TripDays { requested: 20, limit: 14 });
+        let body = err.to_api_error();
+        assert_eq!(body.limit, Some(14));
+        assert_eq!(body.field.as_deref(), Some("departure_datetime"));
+        assert!(body.error.contains("14"));
+
+        let err = limits.check_search(&search("2025-07-01", "2025-07-03", 17)).unwrap_err();
+        assert_eq!(err, LimitExceeded::PartySize { requested: 17, limit: 16 });
+
+        // Unparseable dates aren't a limit problem
+        assert_eq!(limits.check_search(&search("someday", "later", 2)), Ok(()));
+
+        let arrival = DateTime::from_millis(0);
+        let departure = DateTime::from_millis(15 * MILLIS_PER_DAY);
+        assert!(limits.check_booking_dates(arrival, departure).is_err());
+        let relaxed = TripLimits { max_trip_days: 30, ..limits };
+        assert_eq!(relaxed.check_booking_dates(arrival, departure), Ok(()));
+    }
+}
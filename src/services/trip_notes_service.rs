@@ -0,0 +1,343 @@
+//! Travelers' own notes on a booking: a free-text blob (packing lists and the
+//! like) and a note per day of the trip
+//!
+//! Notes are private to the traveler. They live in `Account.TripNotes`, apart
+//! from the booking, so nothing that shows bookings to admins or operators
+//! carries them, and admins impersonating the traveler can't open them. They
+//! are part of the traveler's own data export and go with their account.
+//!
+//! Every save bumps `version`. A save names the version it was editing and is
+//! refused with the server copy if it's no longer current, so two devices
+//! can't silently overwrite each other.
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::{IndexOptions, ReturnDocument},
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::db::mongo::primary_collection;
+use crate::models::bookings::BookingDetails;
+use crate::services::webhook_replay::is_duplicate_key;
+
+/// Most bytes of text, across the blob and every day, one booking's notes hold
+pub const MAX_TRIP_NOTES_BYTES: usize = 20 * 1024;
+
+/// A note for one day of the trip
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayNote {
+    /// 1 for the first day of the trip
+    pub day: u32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TripNotes {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub booking_id: ObjectId,
+    pub user_id: ObjectId,
+    #[serde(default)]
+    pub text: String,
+    /// By day, ascending
+    #[serde(default)]
+    pub days: Vec<DayNote>,
+    /// 0 until the notes are first saved
+    pub version: u64,
+    pub updated_at: Option<DateTime>,
+}
+
+impl TripNotes {
+    /// What a booking has before anything is saved
+    pub fn empty(booking_id: ObjectId, user_id: ObjectId) -> Self {
+        TripNotes {
+            id: None,
+            booking_id,
+            user_id,
+            text: String::new(),
+            days: Vec::new(),
+            version: 0,
+            updated_at: None,
+        }
+    }
+
+    /// The notes as the traveler sees them, with days keyed by day number
+    pub fn to_json(&self) -> serde_json::Value {
+        let days: BTreeMap<u32, &str> = self.days.iter().map(|note| (note.day, note.text.as_str())).collect();
+        serde_json::json!({
+            "booking_id": self.booking_id.to_hex(),
+            "text": self.text,
+            "days": days,
+            "version": self.version,
+            "updated_at": self.updated_at.and_then(|at| at.try_to_rfc3339_string().ok()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum TripNotesError {
+    BookingNotFound,
+    TooLarge,
+    /// Null bytes aren't stripped like other control characters; they usually
+    /// mean binary was pasted or sent by mistake
+    NullByte,
+    InvalidDay(u32),
+    /// `version` wasn't the latest; carries what's saved now
+    VersionConflict(Box<TripNotes>),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for TripNotesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TripNotesError::BookingNotFound => write!(f, "Booking not found"),
+            TripNotesError::TooLarge => write!(
+                f,
+                "Trip notes can be at most {}KB",
+                MAX_TRIP_NOTES_BYTES / 1024
+            ),
+            TripNotesError::NullByte => write!(f, "Trip notes can't contain null bytes"),
+            TripNotesError::InvalidDay(day) => write!(f, "Day {} isn't part of the trip", day),
+            TripNotesError::VersionConflict(_) => {
+                write!(f, "These notes were changed somewhere else; reload them and try again")
+            }
+            TripNotesError::DatabaseError(err) => write!(f, "Database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TripNotesError {}
+
+impl From<mongodb::error::Error> for TripNotesError {
+    fn from(err: mongodb::error::Error) -> Self {
+        TripNotesError::DatabaseError(err.to_string())
+    }
+}
+
+/// Clean one piece of note text: Windows line endings become `\n`, and control
+/// characters other than newlines and tabs are dropped. Null bytes are refused.
+pub fn sanitize_note(input: &str) -> Result<String, TripNotesError> {
+    if input.contains('\0') {
+        return Err(TripNotesError::NullByte);
+    }
+    Ok(input
+        .replace("\r\n", "\n")
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect())
+}
+
+/// Days the trip covers, counting the arrival and departure days
+pub fn trip_days(booking: &BookingDetails) -> u32 {
+    let date = |at: DateTime| chrono::DateTime::from_timestamp_millis(at.timestamp_millis()).map(|at| at.date_naive());
+    match (date(booking.arrival_datetime), date(booking.departure_datetime)) {
+        (Some(arrival), Some(departure)) => ((departure - arrival).num_days().max(0) + 1) as u32,
+        _ => 1,
+    }
+}
+
+/// Clean and check a save. Blank day notes are dropped; day numbers have to fall
+/// within the trip's `days`.
+pub fn sanitize_notes(
+    text: &str,
+    days: &BTreeMap<u32, String>,
+    trip_days: u32,
+) -> Result<(String, Vec<DayNote>), TripNotesError> {
+    let text = sanitize_note(text)?;
+    let mut size = text.len();
+    let mut notes = Vec::with_capacity(days.len());
+    for (day, note) in days {
+        if *day == 0 || *day > trip_days {
+            return Err(TripNotesError::InvalidDay(*day));
+        }
+        let note = sanitize_note(note)?;
+        if note.trim().is_empty() {
+            continue;
+        }
+        size += note.len();
+        notes.push(DayNote { day: *day, text: note });
+    }
+    if size > MAX_TRIP_NOTES_BYTES {
+        return Err(TripNotesError::TooLarge);
+    }
+    Ok((text, notes))
+}
+
+pub struct TripNotesService {
+    client: Arc<Client>,
+}
+
+impl TripNotesService {
+    pub fn new(client: Arc<Client>) -> Self {
+        TripNotesService { client }
+    }
+
+    fn collection(&self) -> Collection<TripNotes> {
+        primary_collection(&self.client, "Account", "TripNotes")
+    }
+
+    /// One set of notes per booking
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let per_booking = IndexModel::builder()
+            .keys(doc! { "booking_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let per_user = IndexModel::builder().keys(doc! { "user_id": 1 }).build();
+        self.collection().create_indexes([per_booking, per_user]).await?;
+        Ok(())
+    }
+
+    async fn booking(&self, user_id: ObjectId, booking_id: ObjectId) -> Result<BookingDetails, TripNotesError> {
+        primary_collection::<BookingDetails>(&self.client, "Account", "Bookings")
+            .find_one(doc! { "_id": booking_id, "user_id": user_id })
+            .await?
+            .ok_or(TripNotesError::BookingNotFound)
+    }
+
+    async fn current(&self, user_id: ObjectId, booking_id: ObjectId) -> Result<TripNotes, mongodb::error::Error> {
+        Ok(self
+            .collection()
+            .find_one(doc! { "booking_id": booking_id, "user_id": user_id })
+            .await?
+            .unwrap_or_else(|| TripNotes::empty(booking_id, user_id)))
+    }
+
+    /// The notes on one of `user_id`'s bookings, empty if none were saved
+    pub async fn get(&self, user_id: ObjectId, booking_id: ObjectId) -> Result<TripNotes, TripNotesError> {
+        self.booking(user_id, booking_id).await?;
+        Ok(self.current(user_id, booking_id).await?)
+    }
+
+    /// Replace the notes on one of `user_id`'s bookings, if `version` is still
+    /// the latest
+    pub async fn save(
+        &self,
+        user_id: ObjectId,
+        booking_id: ObjectId,
+        version: u64,
+        text: &str,
+        days: &BTreeMap<u32, String>,
+        now: DateTime,
+    ) -> Result<TripNotes, TripNotesError> {
+        let booking = self.booking(user_id, booking_id).await?;
+        let (text, days) = sanitize_notes(text, days, trip_days(&booking))?;
+
+        if version == 0 {
+            let notes = TripNotes {
+                id: None,
+                booking_id,
+                user_id,
+                text,
+                days,
+                version: 1,
+                updated_at: Some(now),
+            };
+            return match self.collection().insert_one(&notes).await {
+                Ok(inserted) => Ok(TripNotes {
+                    id: inserted.inserted_id.as_object_id(),
+                    ..notes
+                }),
+                Err(e) if is_duplicate_key(&e) => Err(TripNotesError::VersionConflict(Box::new(
+                    self.current(user_id, booking_id).await?,
+                ))),
+                Err(e) => Err(e.into()),
+            };
+        }
+
+        let saved = self
+            .collection()
+            .find_one_and_update(
+                doc! { "booking_id": booking_id, "user_id": user_id, "version": version as i64 },
+                doc! {
+                    "$set": {
+                        "text": &text,
+                        "days": mongodb::bson::to_bson(&days).map_err(mongodb::error::Error::from)?,
+                        "updated_at": now,
+                    },
+                    "$inc": { "version": 1 },
+                },
+            )
+            .return_document(ReturnDocument::After)
+            .await?;
+        match saved {
+            Some(notes) => Ok(notes),
+            None => Err(TripNotesError::VersionConflict(Box::new(
+                self.current(user_id, booking_id).await?,
+            ))),
+        }
+    }
+
+    /// Every booking's notes for `user_id`, for their data export
+    pub async fn for_user(&self, user_id: ObjectId) -> Result<Vec<TripNotes>, mongodb::error::Error> {
+        self.collection()
+            .find(doc! { "user_id": user_id })
+            .sort(doc! { "booking_id": 1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// Remove everything `user_id` wrote, when their account is deleted
+    pub async fn delete_for_user(&self, user_id: ObjectId) -> Result<u64, mongodb::error::Error> {
+        Ok(self.collection().delete_many(doc! { "user_id": user_id }).await?.deleted_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn days(entries: &[(u32, &str)]) -> BTreeMap<u32, String> {
+        entries.iter().map(|(day, text)| (*day, text.to_string())).collect()
+    }
+
+    #[test]
+    fn test_control_characters_are_dropped_and_null_bytes_refused() {
+        assert_eq!(
+            sanitize_note("Pack:\r\n- boots\u{7}\n\t- rain jacket").unwrap(),
+            "Pack:\n- boots\n\t- rain jacket"
+        );
+        assert!(matches!(sanitize_note("boots\0"), Err(TripNotesError::NullByte)));
+        assert!(matches!(
+            sanitize_notes("", &days(&[(1, "ok\0")]), 3),
+            Err(TripNotesError::NullByte)
+        ));
+    }
+
+    #[test]
+    fn test_day_notes_must_fall_within_the_trip() {
+        let (_, notes) = sanitize_notes("", &days(&[(3, "Fly home"), (1, "Check in"), (2, "  ")]), 3).unwrap();
+        assert_eq!(
+            notes,
+            vec![
+                DayNote { day: 1, text: "Check in".to_string() },
+                DayNote { day: 3, text: "Fly home".to_string() },
+            ]
+        );
+        assert!(matches!(sanitize_notes("", &days(&[(4, "x")]), 3), Err(TripNotesError::InvalidDay(4))));
+        assert!(matches!(sanitize_notes("", &days(&[(0, "x")]), 3), Err(TripNotesError::InvalidDay(0))));
+    }
+
+    #[test]
+    fn test_size_cap_counts_every_note() {
+        let half = "a".repeat(MAX_TRIP_NOTES_BYTES / 2);
+        assert!(sanitize_notes(&half, &days(&[(1, &half)]), 1).is_ok());
+        assert!(matches!(
+            sanitize_notes(&half, &days(&[(1, &format!("{}b", half))]), 1),
+            Err(TripNotesError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_days_are_keyed_by_number() {
+        let notes = TripNotes {
+            days: vec![DayNote { day: 2, text: "Hike".to_string() }],
+            ..TripNotes::empty(ObjectId::new(), ObjectId::new())
+        };
+        assert_eq!(notes.to_json()["days"], serde_json::json!({ "2": "Hike" }));
+    }
+}
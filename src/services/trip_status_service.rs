@@ -0,0 +1,338 @@
+//! Moves confirmed bookings along with their trip: to `in_progress` once the
+//! arrival date passes and to `completed` once the departure date does.
+//!
+//! Each booking is moved with a conditional update on the status it was read in,
+//! so overlapping runs (or a webhook cancelling it meanwhile) can't move it twice.
+//! Only the run that moved it records the transition in
+//! `Account.BookingStatusTransitions` and, for a finished trip, calls the
+//! [`PostTripHook`], so rerunning the job over the same bookings does nothing.
+//!
+//! A trip that has started can't be refunded, and neither can one arriving within
+//! the configured cutoff; see [`check_refundable`].
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::mongo::primary_collection;
+use crate::models::bookings::{BookingDetails, PaymentStatus};
+use crate::services::review_request_service::ReviewRequestService;
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+/// Bookings read per page when looking for trips to move
+const BATCH_SIZE: i64 = 100;
+
+/// Why a booking can't be refunded
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefundBlocked {
+    /// The trip has started or already ended
+    TripStarted,
+    /// The trip starts within this many hours
+    InsideCutoff(u64),
+}
+
+impl std::fmt::Display for RefundBlocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefundBlocked::TripStarted => {
+                write!(f, "Bookings can't be cancelled or refunded once the trip has started")
+            }
+            RefundBlocked::InsideCutoff(hours) => write!(
+                f,
+                "Bookings can't be cancelled or refunded within {} hours of arrival",
+                hours
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RefundBlocked {}
+
+/// Whether `booking` may still be cancelled for a refund at `now`. Trips that have
+/// started, by status or by date, never can; others must arrive at least
+/// `cutoff_hours` from now.
+pub fn check_refundable(
+    booking: &BookingDetails,
+    now: DateTime,
+    cutoff_hours: u64,
+) -> Result<(), RefundBlocked> {
+    if matches!(booking.status, PaymentStatus::InProgress | PaymentStatus::Completed)
+        || booking.arrival_datetime <= now
+    {
+        return Err(RefundBlocked::TripStarted);
+    }
+    if booking.arrival_datetime.timestamp_millis() - now.timestamp_millis()
+        < cutoff_hours as i64 * HOUR_MILLIS
+    {
+        return Err(RefundBlocked::InsideCutoff(cutoff_hours));
+    }
+    Ok(())
+}
+
+/// Where `booking` should be at `now`, if it should move. A trip that ended
+/// before the job saw it started goes straight to `completed`.
+pub fn next_status(booking: &BookingDetails, now: DateTime) -> Option<PaymentStatus> {
+    if !matches!(booking.status, PaymentStatus::Confirmed | PaymentStatus::InProgress) {
+        return None;
+    }
+    if booking.departure_datetime <= now {
+        return Some(PaymentStatus::Completed);
+    }
+    if booking.status == PaymentStatus::Confirmed && booking.arrival_datetime <= now {
+        return Some(PaymentStatus::InProgress);
+    }
+    None
+}
+
+/// One status change made by the job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TripStatusTransition {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub booking_id: ObjectId,
+    pub user_id: ObjectId,
+    pub from: PaymentStatus,
+    pub to: PaymentStatus,
+    pub at: DateTime,
+}
+
+/// Bookings moved by one run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TripStatusSummary {
+    pub started: u64,
+    pub completed: u64,
+}
+
+/// Called once for each booking the job moves to `completed`, after the move is
+/// recorded. Failures are the hook's to log; they don't undo the move.
+pub trait PostTripHook {
+    fn trip_completed(&self, booking: &BookingDetails, now: DateTime) -> impl Future<Output = ()> + Send;
+}
+
+/// No hooks
+impl PostTripHook for () {
+    async fn trip_completed(&self, _booking: &BookingDetails, _now: DateTime) {}
+}
+
+/// Asks for a review as soon as a trip completes if the review delay has already
+/// passed: when no delay is configured, or the job catches up on a trip that
+/// ended a while ago. Otherwise the review request job asks once the delay is up.
+pub struct ReviewRequestHook {
+    pub service: ReviewRequestService,
+    pub delay_days: u64,
+}
+
+impl PostTripHook for ReviewRequestHook {
+    async fn trip_completed(&self, booking: &BookingDetails, now: DateTime) {
+        let due = booking.departure_datetime.timestamp_millis() + self.delay_days as i64 * 24 * HOUR_MILLIS;
+        if due > now.timestamp_millis() {
+            return;
+        }
+        let email_service = self.service.email_service().ok();
+        if let Err(e) = self.service.send_after_trip(booking, &email_service, now).await {
+            println!("Review request for booking {:?} left to the review job: {}", booking.id, e);
+        }
+    }
+}
+
+pub struct TripStatusService {
+    client: Arc<Client>,
+}
+
+impl TripStatusService {
+    pub fn new(client: Arc<Client>) -> Self {
+        TripStatusService { client }
+    }
+
+    fn bookings(&self) -> Collection<BookingDetails> {
+        primary_collection(&self.client, "Account", "Bookings")
+    }
+
+    fn transitions(&self) -> Collection<TripStatusTransition> {
+        self.client.database("Account").collection("BookingStatusTransitions")
+    }
+
+    /// A booking makes each transition at most once
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let per_booking = IndexModel::builder()
+            .keys(doc! { "booking_id": 1, "to": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.transitions().create_index(per_booking).await?;
+        Ok(())
+    }
+
+    /// Transitions recorded for `booking_id`, oldest first
+    pub async fn history(&self, booking_id: ObjectId) -> Result<Vec<TripStatusTransition>, mongodb::error::Error> {
+        self.transitions()
+            .find(doc! { "booking_id": booking_id })
+            .sort(doc! { "at": 1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// Advance every booking whose trip has started or ended by `now`, a page at
+    /// a time, calling `hook` for each trip this run completed
+    pub async fn advance(
+        &self,
+        now: DateTime,
+        hook: &impl PostTripHook,
+    ) -> Result<TripStatusSummary, mongodb::error::Error> {
+        let mut summary = TripStatusSummary::default();
+        let mut last_id: Option<ObjectId> = None;
+        loop {
+            let mut filter = doc! {
+                "status": { "$in": ["confirmed", "in_progress"] },
+                "arrival_datetime": { "$lte": now },
+            };
+            if let Some(last_id) = last_id {
+                filter.insert("_id", doc! { "$gt": last_id });
+            }
+            let page: Vec<BookingDetails> = self
+                .bookings()
+                .find(filter)
+                .sort(doc! { "_id": 1 })
+                .limit(BATCH_SIZE)
+                .await?
+                .try_collect()
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            last_id = last.id;
+
+            for booking in &page {
+                let (Some(booking_id), Some(to)) = (booking.id, next_status(booking, now)) else {
+                    continue;
+                };
+                let moved = self
+                    .bookings()
+                    .update_one(
+                        doc! { "_id": booking_id, "status": mongodb::bson::to_bson(&booking.status)? },
+                        doc! { "$set": { "status": mongodb::bson::to_bson(&to)?, "updated_at": now } },
+                    )
+                    .await?;
+                if moved.modified_count == 0 {
+                    continue;
+                }
+
+                let transition = TripStatusTransition {
+                    id: None,
+                    booking_id,
+                    user_id: booking.user_id,
+                    from: booking.status.clone(),
+                    to: to.clone(),
+                    at: now,
+                };
+                if let Err(e) = self.transitions().insert_one(&transition).await {
+                    eprintln!("Failed to record trip status transition for booking {}: {}", booking_id, e);
+                }
+                if to == PaymentStatus::Completed {
+                    summary.completed += 1;
+                    let completed = BookingDetails {
+                        status: to,
+                        updated_at: Some(now),
+                        ..booking.clone()
+                    };
+                    hook.trip_completed(&completed, now).await;
+                } else {
+                    summary.started += 1;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Advance trip statuses every `interval`, starting one interval from now
+    pub fn start(self, interval: Duration, hook: impl PostTripHook + Send + Sync + 'static) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                match self.advance(DateTime::now(), &hook).await {
+                    Ok(summary) if summary != TripStatusSummary::default() => {
+                        println!("🧳 Trip statuses advanced: {:?}", summary)
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Failed to advance trip statuses: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime {
+        DateTime::from_millis(1_750_000_000_000)
+    }
+
+    fn booking(status: PaymentStatus, arrival_in_hours: i64, nights: i64) -> BookingDetails {
+        let arrival = now().timestamp_millis() + arrival_in_hours * HOUR_MILLIS;
+        BookingDetails::test(
+            status,
+            DateTime::from_millis(arrival),
+            DateTime::from_millis(arrival + nights * 24 * HOUR_MILLIS),
+        )
+    }
+
+    #[test]
+    fn test_next_status_follows_the_trip_dates() {
+        assert_eq!(next_status(&booking(PaymentStatus::Confirmed, 24, 3), now()), None);
+        assert_eq!(
+            next_status(&booking(PaymentStatus::Confirmed, -24, 3), now()),
+            Some(PaymentStatus::InProgress)
+        );
+        assert_eq!(next_status(&booking(PaymentStatus::InProgress, -24, 3), now()), None);
+        assert_eq!(
+            next_status(&booking(PaymentStatus::InProgress, -240, 3), now()),
+            Some(PaymentStatus::Completed)
+        );
+        // Missed while it was underway
+        assert_eq!(
+            next_status(&booking(PaymentStatus::Confirmed, -240, 3), now()),
+            Some(PaymentStatus::Completed)
+        );
+    }
+
+    #[test]
+    fn test_only_confirmed_trips_move() {
+        for status in [
+            PaymentStatus::Pending,
+            PaymentStatus::Cancelled,
+            PaymentStatus::Refunded,
+            PaymentStatus::Completed,
+        ] {
+            assert_eq!(next_status(&booking(status, -240, 3), now()), None);
+        }
+    }
+
+    #[test]
+    fn test_refunds_stop_at_the_cutoff_and_once_trips_start() {
+        assert!(check_refundable(&booking(PaymentStatus::Confirmed, 48, 3), now(), 24).is_ok());
+        assert!(check_refundable(&booking(PaymentStatus::Confirmed, 1, 3), now(), 0).is_ok());
+        assert_eq!(
+            check_refundable(&booking(PaymentStatus::Confirmed, 12, 3), now(), 24),
+            Err(RefundBlocked::InsideCutoff(24))
+        );
+        assert_eq!(
+            check_refundable(&booking(PaymentStatus::Confirmed, -1, 3), now(), 0),
+            Err(RefundBlocked::TripStarted)
+        );
+        // The status counts even if the dates were moved afterwards
+        assert_eq!(
+            check_refundable(&booking(PaymentStatus::Completed, 48, 3), now(), 0),
+            Err(RefundBlocked::TripStarted)
+        );
+    }
+}
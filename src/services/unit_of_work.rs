@@ -0,0 +1,177 @@
+//! Groups the writes of one operation so they land together or not at all.
+//!
+//! With `MONGODB_TRANSACTIONS` on, writes made through a [`UnitOfWork`] share a
+//! session and commit as one multi-document transaction. A transaction that fails
+//! with a transient error is run again from the start, and a commit whose outcome
+//! is unknown is retried, as MongoDB recommends. Transactions need a replica set;
+//! on a standalone server leave the flag off and the same writes go out one after
+//! another.
+
+use mongodb::{
+    bson::Document,
+    error::{Error, TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT},
+    options::WriteConcern,
+    results::{InsertOneResult, UpdateResult},
+    Client, ClientSession, Collection,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Times a transaction is started, or its commit sent, before the error is returned
+const MAX_TRANSACTION_ATTEMPTS: u32 = 5;
+
+/// The writes of one operation. Repositories take one in the variants of their
+/// write methods that can be part of a larger operation.
+pub struct UnitOfWork {
+    session: Option<ClientSession>,
+}
+
+impl UnitOfWork {
+    /// Each write stands on its own, as if there were no unit of work
+    pub fn sequential() -> Self {
+        UnitOfWork { session: None }
+    }
+
+    /// Whether the writes commit together. Sequential writes that already went out
+    /// stay written when a later one fails.
+    pub fn is_transactional(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub async fn insert_one<T: Serialize + Send + Sync>(
+        &mut self,
+        collection: &Collection<T>,
+        document: &T,
+    ) -> Result<InsertOneResult, Error> {
+        let action = collection.insert_one(document);
+        match self.session.as_mut() {
+            Some(session) => action.session(session).await,
+            None => action.await,
+        }
+    }
+
+    pub async fn update_one<T: Send + Sync>(
+        &mut self,
+        collection: &Collection<T>,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateResult, Error> {
+        let action = collection.update_one(filter, update);
+        match self.session.as_mut() {
+            Some(session) => action.session(session).await,
+            None => action.await,
+        }
+    }
+
+    pub async fn update_many<T: Send + Sync>(
+        &mut self,
+        collection: &Collection<T>,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateResult, Error> {
+        let action = collection.update_many(filter, update);
+        match self.session.as_mut() {
+            Some(session) => action.session(session).await,
+            None => action.await,
+        }
+    }
+
+    /// `update_many` with array filters, for updates that use `$[identifier]`
+    pub async fn update_many_filtered<T: Send + Sync>(
+        &mut self,
+        collection: &Collection<T>,
+        filter: Document,
+        update: Document,
+        array_filters: Vec<Document>,
+    ) -> Result<UpdateResult, Error> {
+        let action = collection.update_many(filter, update).array_filters(array_filters);
+        match self.session.as_mut() {
+            Some(session) => action.session(session).await,
+            None => action.await,
+        }
+    }
+
+    /// `update_one`, inserting the document if nothing matches
+    pub async fn upsert_one<T: Send + Sync>(
+        &mut self,
+        collection: &Collection<T>,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateResult, Error> {
+        let action = collection.update_one(filter, update).upsert(true);
+        match self.session.as_mut() {
+            Some(session) => action.session(session).await,
+            None => action.await,
+        }
+    }
+
+    /// Update the first match, returning it as it was before the update
+    pub async fn find_one_and_update<T: DeserializeOwned + Send + Sync>(
+        &mut self,
+        collection: &Collection<T>,
+        filter: Document,
+        update: Document,
+    ) -> Result<Option<T>, Error> {
+        let action = collection.find_one_and_update(filter, update);
+        match self.session.as_mut() {
+            Some(session) => action.session(session).await,
+            None => action.await,
+        }
+    }
+}
+
+/// Run `work` as one unit of work. With `transactional` set it runs inside a
+/// transaction, committed with majority write concern; otherwise it runs once with
+/// sequential writes. On an error nothing `work` wrote in the transaction remains.
+pub async fn run<T>(
+    client: &Client,
+    transactional: bool,
+    mut work: impl AsyncFnMut(&mut UnitOfWork) -> Result<T, Error>,
+) -> Result<T, Error> {
+    if !transactional {
+        return work(&mut UnitOfWork::sequential()).await;
+    }
+
+    let mut uow = UnitOfWork {
+        session: Some(client.start_session().await?),
+    };
+    let mut attempt = 1;
+    loop {
+        let session = uow.session.as_mut().expect("transactional unit of work has a session");
+        session
+            .start_transaction()
+            .write_concern(WriteConcern::majority())
+            .await?;
+
+        let result = match work(&mut uow).await {
+            Ok(value) => commit(uow.session.as_mut().unwrap()).await.map(|()| value),
+            Err(e) => {
+                if let Err(abort_err) = uow.session.as_mut().unwrap().abort_transaction().await {
+                    eprintln!("Failed to abort transaction: {}", abort_err);
+                }
+                Err(e)
+            }
+        };
+
+        match result {
+            Err(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR) && attempt < MAX_TRANSACTION_ATTEMPTS => {
+                println!("🔁 Retrying transaction after transient error (attempt {}): {}", attempt, e);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Commit, retrying while the server can't say whether the commit went through
+async fn commit(session: &mut ClientSession) -> Result<(), Error> {
+    let mut attempt = 1;
+    loop {
+        match session.commit_transaction().await {
+            Err(e) if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) && attempt < MAX_TRANSACTION_ATTEMPTS => {
+                println!("🔁 Retrying commit with unknown result (attempt {}): {}", attempt, e);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
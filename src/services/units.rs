@@ -0,0 +1,261 @@
+//! Distances and temperatures in the viewer's units.
+//!
+//! Stored data keeps one canonical unit: metres for distance and degrees Celsius
+//! for temperature. Text that is stored, like generated descriptions, embeds a
+//! placeholder (`{{distance:80467}}`, `{{temperature:29.5}}`) instead of a
+//! formatted value, and responses render it with `render` for each request.
+//!
+//! The units a response uses come from `?units=`, then the viewer's
+//! `preferred_units`, then the `Accept-Language` region, and are imperial
+//! otherwise.
+
+use serde::{Deserialize, Serialize};
+
+pub const METERS_PER_MILE: f64 = 1609.344;
+
+/// Regions that measure road distance in miles and weather in °F
+const IMPERIAL_REGIONS: &[&str] = &["US", "LR", "MM"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    #[default]
+    Imperial,
+    Metric,
+}
+
+impl UnitSystem {
+    /// `explicit` (a query or body parameter) wins over the profile's preference,
+    /// which wins over what `Accept-Language` suggests
+    pub fn resolve(
+        explicit: Option<UnitSystem>,
+        preferred: Option<UnitSystem>,
+        accept_language: Option<&str>,
+    ) -> UnitSystem {
+        explicit
+            .or(preferred)
+            .or_else(|| accept_language.and_then(Self::from_accept_language))
+            .unwrap_or_default()
+    }
+
+    /// Guess from the most preferred language tag. A region decides it; without
+    /// one, English says nothing (it's spoken on both sides) and any other
+    /// language suggests metric.
+    pub fn from_accept_language(header: &str) -> Option<UnitSystem> {
+        let mut best: Option<(f32, &str)> = None;
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let tag = parts.next().unwrap_or_default().trim();
+            if tag.is_empty() || tag == "*" {
+                continue;
+            }
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+                .unwrap_or(0.0);
+            if quality > 0.0 && best.is_none_or(|(best_quality, _)| quality > best_quality) {
+                best = Some((quality, tag));
+            }
+        }
+
+        let mut subtags = best?.1.split(['-', '_']);
+        let language = subtags.next()?.to_ascii_lowercase();
+        // The region is the first two-letter subtag after the language (`zh-Hant-TW`)
+        match subtags.find(|subtag| subtag.len() == 2) {
+            Some(region) if IMPERIAL_REGIONS.contains(&region.to_ascii_uppercase().as_str()) => {
+                Some(UnitSystem::Imperial)
+            }
+            Some(_) => Some(UnitSystem::Metric),
+            None if language == "en" => None,
+            None => Some(UnitSystem::Metric),
+        }
+    }
+}
+
+pub fn meters_to_miles(meters: f64) -> f64 {
+    meters / METERS_PER_MILE
+}
+
+pub fn miles_to_meters(miles: f64) -> f64 {
+    miles * METERS_PER_MILE
+}
+
+pub fn celsius_to_fahrenheit(celsius: f64) -> f64 {
+    celsius * 9.0 / 5.0 + 32.0
+}
+
+/// A distance for display.
+///
+/// Metric: metres to the nearest 10 under 1 km, kilometres to one decimal under
+/// 100 km, whole kilometres beyond. Imperial: whole miles under 10 (never less
+/// than 1), the nearest 5 miles beyond.
+pub fn format_distance(meters: f64, units: UnitSystem) -> String {
+    let meters = meters.max(0.0);
+    match units {
+        UnitSystem::Metric if meters < 1000.0 => {
+            // 995 m and up would round to "1000 m"
+            let rounded = (meters / 10.0).round() * 10.0;
+            if rounded >= 1000.0 {
+                "1.0 km".to_string()
+            } else {
+                format!("{} m", rounded as u64)
+            }
+        }
+        UnitSystem::Metric => {
+            let km = meters / 1000.0;
+            if (km * 10.0).round() / 10.0 < 100.0 {
+                format!("{:.1} km", km)
+            } else {
+                format!("{} km", km.round() as u64)
+            }
+        }
+        UnitSystem::Imperial => {
+            let miles = meters_to_miles(meters);
+            let whole = if miles.round() < 10.0 {
+                miles.round().max(1.0)
+            } else {
+                (miles / 5.0).round() * 5.0
+            };
+            if whole == 1.0 {
+                "1 mile".to_string()
+            } else {
+                format!("{} miles", whole as u64)
+            }
+        }
+    }
+}
+
+/// A temperature for display, in whole degrees
+pub fn format_temperature(celsius: f64, units: UnitSystem) -> String {
+    match units {
+        UnitSystem::Metric => format!("{}°C", celsius.round() as i64),
+        UnitSystem::Imperial => format!("{}°F", celsius_to_fahrenheit(celsius).round() as i64),
+    }
+}
+
+/// The placeholder stored text uses for a distance
+pub fn distance_placeholder(meters: f64) -> String {
+    format!("{{{{distance:{}}}}}", meters.max(0.0).round() as u64)
+}
+
+/// The placeholder stored text uses for a temperature
+pub fn temperature_placeholder(celsius: f64) -> String {
+    format!("{{{{temperature:{:.1}}}}}", celsius)
+}
+
+/// Replace the distance and temperature placeholders in `text` with values in
+/// `units`. Anything that isn't a well-formed placeholder is left as it is.
+pub fn render(text: &str, units: UnitSystem) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let formatted = after.find("}}").and_then(|end| {
+            let (kind, value) = after[..end].split_once(':')?;
+            let value: f64 = value.trim().parse().ok()?;
+            let formatted = match kind.trim() {
+                "distance" => format_distance(value, units),
+                "temperature" => format_temperature(value, units),
+                _ => return None,
+            };
+            Some((formatted, end + 2))
+        });
+        match formatted {
+            Some((formatted, consumed)) => {
+                rendered.push_str(&formatted);
+                rest = &after[consumed..];
+            }
+            None => {
+                rendered.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kilometres_round_to_one_decimal() {
+        assert_eq!(format_distance(0.0, UnitSystem::Metric), "0 m");
+        assert_eq!(format_distance(847.0, UnitSystem::Metric), "850 m");
+        assert_eq!(format_distance(996.0, UnitSystem::Metric), "1.0 km");
+        assert_eq!(format_distance(12_345.0, UnitSystem::Metric), "12.3 km");
+        assert_eq!(format_distance(72_420.0, UnitSystem::Metric), "72.4 km");
+        assert_eq!(format_distance(99_960.0, UnitSystem::Metric), "100 km");
+        assert_eq!(format_distance(256_700.0, UnitSystem::Metric), "257 km");
+    }
+
+    #[test]
+    fn test_miles_are_whole_under_ten_and_fives_beyond() {
+        assert_eq!(format_distance(0.0, UnitSystem::Imperial), "1 mile");
+        assert_eq!(format_distance(miles_to_meters(1.4), UnitSystem::Imperial), "1 mile");
+        assert_eq!(format_distance(miles_to_meters(2.5), UnitSystem::Imperial), "3 miles");
+        assert_eq!(format_distance(miles_to_meters(9.4), UnitSystem::Imperial), "9 miles");
+        assert_eq!(format_distance(miles_to_meters(9.6), UnitSystem::Imperial), "10 miles");
+        assert_eq!(format_distance(miles_to_meters(12.4), UnitSystem::Imperial), "10 miles");
+        assert_eq!(format_distance(miles_to_meters(44.0), UnitSystem::Imperial), "45 miles");
+        assert!((meters_to_miles(miles_to_meters(45.0)) - 45.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_temperatures_are_whole_degrees() {
+        assert_eq!(format_temperature(29.4, UnitSystem::Metric), "29°C");
+        assert_eq!(format_temperature(29.4, UnitSystem::Imperial), "85°F");
+        assert_eq!(format_temperature(-40.0, UnitSystem::Imperial), "-40°F");
+        assert_eq!(format_temperature(-0.4, UnitSystem::Metric), "0°C");
+    }
+
+    #[test]
+    fn test_same_text_renders_in_both_systems() {
+        let stored = format!(
+            "The drive covers about {} and afternoons reach {}.",
+            distance_placeholder(miles_to_meters(45.0)),
+            temperature_placeholder(29.4)
+        );
+        assert_eq!(stored, "The drive covers about {{distance:72420}} and afternoons reach {{temperature:29.4}}.");
+
+        assert_eq!(
+            render(&stored, UnitSystem::Imperial),
+            "The drive covers about 45 miles and afternoons reach 85°F."
+        );
+        assert_eq!(render(&stored, UnitSystem::Metric), "The drive covers about 72.4 km and afternoons reach 29°C.");
+    }
+
+    #[test]
+    fn test_text_that_isnt_a_placeholder_is_kept() {
+        for text in ["{{}}", "{{distance:far}}", "{{altitude:3000}}", "open {{distance:10", "a {b} c", ""] {
+            assert_eq!(render(text, UnitSystem::Metric), text);
+        }
+        assert_eq!(render("{{{{distance:1000}}", UnitSystem::Metric), "{{1.0 km");
+    }
+
+    #[test]
+    fn test_accept_language_region_decides() {
+        let guess = UnitSystem::from_accept_language;
+        assert_eq!(guess("en-US,en;q=0.9"), Some(UnitSystem::Imperial));
+        assert_eq!(guess("en-GB,en;q=0.9"), Some(UnitSystem::Metric));
+        assert_eq!(guess("de"), Some(UnitSystem::Metric));
+        assert_eq!(guess("en"), None);
+        assert_eq!(guess("fr-CA;q=0.5, en-us;q=0.8"), Some(UnitSystem::Imperial));
+        assert_eq!(guess("zh-Hant-TW"), Some(UnitSystem::Metric));
+        assert_eq!(guess("*"), None);
+        assert_eq!(guess(""), None);
+    }
+
+    #[test]
+    fn test_explicit_units_win_over_the_profile() {
+        let metric = Some(UnitSystem::Metric);
+        let imperial = Some(UnitSystem::Imperial);
+        assert_eq!(UnitSystem::resolve(imperial, metric, Some("de-DE")), UnitSystem::Imperial);
+        assert_eq!(UnitSystem::resolve(None, metric, Some("en-US")), UnitSystem::Metric);
+        assert_eq!(UnitSystem::resolve(None, None, Some("en-AU")), UnitSystem::Metric);
+        assert_eq!(UnitSystem::resolve(None, None, Some("en")), UnitSystem::Imperial);
+        assert_eq!(UnitSystem::resolve(None, None, None), UnitSystem::Imperial);
+    }
+}
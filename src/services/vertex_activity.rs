@@ -0,0 +1,395 @@
+//! Turns Vertex AI Search documents into activities. The index is synced from
+//! `Options.Activity` but its documents are often sparse, so missing fields are
+//! filled from one set of defaults (`VERTEX_DEFAULT_PRICE`,
+//! `VERTEX_DEFAULT_DURATION_MINUTES`, `VERTEX_DEFAULT_CAPACITY_MIN` and
+//! `VERTEX_DEFAULT_CAPACITY_MAX`) and every filled field is recorded on the
+//! activity. Itineraries scheduling an activity whose price or capacity was
+//! filled in can't be paid for until a cost recompute re-prices them from
+//! MongoDB (see `Activity::needs_pricing_review`).
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::models::activity::{Activity, Address, Capacity, TimeSlot};
+
+/// What a Vertex document gets for each field it doesn't have
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityDefaults {
+    pub price_per_person: f32,
+    pub duration_minutes: u16,
+    pub capacity_minimum: u16,
+    pub capacity_maximum: u16,
+}
+
+impl Default for ActivityDefaults {
+    fn default() -> Self {
+        ActivityDefaults {
+            price_per_person: 50.0,
+            duration_minutes: 120,
+            capacity_minimum: 1,
+            capacity_maximum: 20,
+        }
+    }
+}
+
+/// A field filled from `ActivityDefaults` or the title instead of the document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultedField {
+    Price,
+    Duration,
+    Capacity,
+    Description,
+}
+
+impl DefaultedField {
+    /// Whether a guess at this field could charge travelers the wrong amount or
+    /// sell seats that don't exist
+    pub fn blocks_booking(self) -> bool {
+        matches!(self, DefaultedField::Price | DefaultedField::Capacity)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VertexActivityError {
+    NotAnObject,
+    MissingTitle,
+}
+
+impl std::fmt::Display for VertexActivityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VertexActivityError::NotAnObject => write!(f, "Vertex document data is not an object"),
+            VertexActivityError::MissingTitle => write!(f, "Vertex document has no title"),
+        }
+    }
+}
+
+impl std::error::Error for VertexActivityError {}
+
+/// Convert a Vertex document's `struct_data` into an activity.
+///
+/// Accepts both shapes the index has held: `price_per_person` or `price`,
+/// `duration_minutes` or `duration`, a `capacity` object or
+/// `min_capacity`/`max_capacity`, an address object, comma-separated string or
+/// flat `full_address`/`city`/`state`/`zip` fields, and time slots as
+/// `{start, end}` objects or `"start-end"` strings. The id is the document's
+/// `id` or `_id` when it's an ObjectId; other ids are left unset.
+pub fn activity_from_vertex(
+    data: &Value,
+    defaults: &ActivityDefaults,
+) -> Result<Activity, VertexActivityError> {
+    let data = data.as_object().ok_or(VertexActivityError::NotAnObject)?;
+    let title = text(data, "title")
+        .filter(|title| !title.trim().is_empty())
+        .ok_or(VertexActivityError::MissingTitle)?;
+    let mut defaulted = Vec::new();
+
+    let description = match text(data, "description").filter(|description| !description.is_empty()) {
+        Some(description) => description,
+        None => {
+            defaulted.push(DefaultedField::Description);
+            title.clone()
+        }
+    };
+
+    let price_per_person = match number(data, &["price_per_person", "price"]).filter(|price| *price >= 0.0) {
+        Some(price) => price as f32,
+        None => {
+            defaulted.push(DefaultedField::Price);
+            defaults.price_per_person
+        }
+    };
+
+    let duration_minutes = match number(data, &["duration_minutes", "duration"]).filter(|minutes| *minutes > 0.0) {
+        Some(minutes) => minutes.ceil().min(u16::MAX as f64) as u16,
+        None => {
+            defaulted.push(DefaultedField::Duration);
+            defaults.duration_minutes
+        }
+    };
+
+    let nested = data.get("capacity").and_then(Value::as_object);
+    let bound = |nested_key: &str, flat_key: &str| {
+        nested
+            .and_then(|capacity| number(capacity, &[nested_key]))
+            .or_else(|| number(data, &[flat_key]))
+            .filter(|value| *value > 0.0)
+            .map(|value| value.ceil().min(u16::MAX as f64) as u16)
+    };
+    let capacity = match (bound("minimum", "min_capacity"), bound("maximum", "max_capacity")) {
+        (Some(minimum), Some(maximum)) if minimum <= maximum => Capacity { minimum, maximum },
+        (minimum, maximum) => {
+            defaulted.push(DefaultedField::Capacity);
+            let maximum = maximum.unwrap_or(defaults.capacity_maximum);
+            Capacity {
+                minimum: minimum.unwrap_or(defaults.capacity_minimum).min(maximum),
+                maximum,
+            }
+        }
+    };
+
+    let id = ["id", "_id"].iter().find_map(|key| match data.get(*key)? {
+        Value::String(id) => ObjectId::parse_str(id).ok(),
+        Value::Object(id) => id.get("$oid").and_then(Value::as_str).and_then(|id| ObjectId::parse_str(id).ok()),
+        _ => None,
+    });
+
+    Ok(Activity {
+        id,
+        company: text(data, "company").unwrap_or_default(),
+        company_id: text(data, "company_id").unwrap_or_default(),
+        booking_link: text(data, "booking_link").unwrap_or_default(),
+        online_booking_status: text(data, "online_booking_status").unwrap_or_else(|| "available".to_string()),
+        guide: text(data, "guide"),
+        title,
+        description,
+        activity_types: strings(data, "activity_types"),
+        tags: strings(data, "tags"),
+        price_per_person,
+        duration_minutes,
+        daily_time_slots: time_slots(data),
+        address: address(data),
+        whats_included: strings(data, "whats_included"),
+        weight_limit_lbs: number(data, &["weight_limit_lbs", "weight_limit"]).map(|value| value.ceil() as u16),
+        age_requirement: number(data, &["age_requirement"]).map(|value| value.ceil() as u8),
+        height_requiremnt: number(data, &["height_requiremnt", "height_requirement"]).map(|value| value.ceil() as u8),
+        blackout_date_ranges: None,
+        operating_days: data
+            .get("operating_days")
+            .and_then(|days| serde_json::from_value(days.clone()).ok()),
+        closed_dates: data
+            .get("closed_dates")
+            .and_then(|dates| serde_json::from_value(dates.clone()).ok())
+            .unwrap_or_default(),
+        closed_on_holidays: data.get("closed_on_holidays").and_then(Value::as_bool).unwrap_or(false),
+        capacity,
+        location: None,
+        images: strings(data, "images"),
+        defaulted_fields: defaulted,
+        created_at: None,
+        updated_at: None,
+    })
+}
+
+fn text(data: &Map<String, Value>, key: &str) -> Option<String> {
+    data.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+/// The first of `keys` holding a number, or a string that parses as one
+fn number(data: &Map<String, Value>, keys: &[&str]) -> Option<f64> {
+    keys.iter().find_map(|key| match data.get(*key)? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(number) => number.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+fn strings(data: &Map<String, Value>, key: &str) -> Vec<String> {
+    data.get(key)
+        .and_then(Value::as_array)
+        .map(|values| values.iter().filter_map(Value::as_str).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn time_slots(data: &Map<String, Value>) -> Vec<TimeSlot> {
+    let Some(slots) = data.get("daily_time_slots").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    slots
+        .iter()
+        .filter_map(|slot| match slot {
+            Value::String(slot) => slot.split_once('-').map(|(start, end)| TimeSlot {
+                start: start.trim().to_string(),
+                end: end.trim().to_string(),
+            }),
+            Value::Object(slot) => Some(TimeSlot {
+                start: text(slot, "start")?,
+                end: text(slot, "end")?,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn address(data: &Map<String, Value>) -> Address {
+    let field = |address: &Map<String, Value>, key: &str| text(address, key).unwrap_or_default();
+    match data.get("address") {
+        Some(Value::Object(address)) => Address {
+            street: field(address, "street"),
+            unit: field(address, "unit"),
+            city: field(address, "city"),
+            state: field(address, "state"),
+            zip: field(address, "zip"),
+            country: text(address, "country").unwrap_or_else(|| "USA".to_string()),
+        },
+        Some(Value::String(address)) => {
+            let parts: Vec<&str> = address.split(',').map(str::trim).collect();
+            let part = |index: usize| parts.get(index).copied().unwrap_or_default().to_string();
+            Address {
+                street: part(0),
+                unit: String::new(),
+                city: part(1),
+                state: part(2),
+                zip: part(3),
+                country: "USA".to_string(),
+            }
+        }
+        _ => Address {
+            street: field(data, "full_address"),
+            unit: String::new(),
+            city: field(data, "city"),
+            state: field(data, "state"),
+            zip: field(data, "zip"),
+            country: text(data, "country").unwrap_or_else(|| "USA".to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_complete_document_needs_no_defaults() {
+        let id = ObjectId::new();
+        let activity = activity_from_vertex(
+            &json!({
+                "id": id.to_hex(),
+                "title": "Royal Gorge Rafting",
+                "description": "Class III-IV rapids",
+                "company": "Echo Canyon",
+                "price_per_person": 95,
+                "duration_minutes": 180,
+                "capacity": { "minimum": 2, "maximum": 12 },
+                "address": { "street": "45000 US-50", "city": "Cañon City", "state": "CO", "zip": "81212" },
+                "daily_time_slots": ["09:00-12:00", { "start": "13:00", "end": "16:00" }],
+                "activity_types": ["rafting"]
+            }),
+            &ActivityDefaults::default(),
+        )
+        .unwrap();
+
+        assert_eq!(activity.id, Some(id));
+        assert_eq!(activity.price_per_person, 95.0);
+        assert_eq!(activity.duration_minutes, 180);
+        assert_eq!((activity.capacity.minimum, activity.capacity.maximum), (2, 12));
+        assert_eq!(activity.address.city, "Cañon City");
+        assert_eq!(activity.address.country, "USA");
+        assert_eq!(activity.daily_time_slots.len(), 2);
+        assert_eq!(activity.daily_time_slots[1].start, "13:00");
+        assert!(activity.defaulted_fields.is_empty());
+        assert!(!activity.needs_pricing_review());
+    }
+
+    #[test]
+    fn test_sparse_document_is_filled_from_defaults_and_flagged() {
+        let defaults = ActivityDefaults {
+            price_per_person: 75.0,
+            duration_minutes: 90,
+            capacity_minimum: 2,
+            capacity_maximum: 8,
+        };
+        let activity = activity_from_vertex(&json!({ "id": "vertex-doc-17", "title": "Hot Springs Soak" }), &defaults)
+            .unwrap();
+
+        assert_eq!(activity.id, None);
+        assert_eq!(activity.description, "Hot Springs Soak");
+        assert_eq!(activity.price_per_person, 75.0);
+        assert_eq!(activity.duration_minutes, 90);
+        assert_eq!((activity.capacity.minimum, activity.capacity.maximum), (2, 8));
+        // No city is made up for a document without one
+        assert_eq!(activity.address.city, "");
+        assert!(activity.daily_time_slots.is_empty());
+        assert_eq!(
+            activity.defaulted_fields,
+            vec![
+                DefaultedField::Description,
+                DefaultedField::Price,
+                DefaultedField::Duration,
+                DefaultedField::Capacity
+            ]
+        );
+        assert!(activity.needs_pricing_review());
+    }
+
+    #[test]
+    fn test_legacy_field_names_and_flat_address() {
+        let activity = activity_from_vertex(
+            &json!({
+                "title": "Jeep Tour",
+                "description": "Mountain passes",
+                "price": "120.50",
+                "duration": 240,
+                "min_capacity": 1,
+                "max_capacity": 6,
+                "full_address": "1 Main St",
+                "city": "Ouray",
+                "state": "CO"
+            }),
+            &ActivityDefaults::default(),
+        )
+        .unwrap();
+
+        assert_eq!(activity.price_per_person, 120.5);
+        assert_eq!(activity.duration_minutes, 240);
+        assert_eq!((activity.capacity.minimum, activity.capacity.maximum), (1, 6));
+        assert_eq!(activity.address.street, "1 Main St");
+        assert_eq!(activity.address.city, "Ouray");
+        assert!(activity.defaulted_fields.is_empty());
+    }
+
+    #[test]
+    fn test_comma_separated_address() {
+        let activity = activity_from_vertex(
+            &json!({ "title": "Zip Line", "address": "12 Canyon Rd, Moab, UT, 84532", "price_per_person": 80 }),
+            &ActivityDefaults::default(),
+        )
+        .unwrap();
+
+        assert_eq!(activity.address.street, "12 Canyon Rd");
+        assert_eq!(activity.address.city, "Moab");
+        assert_eq!(activity.address.state, "UT");
+        assert_eq!(activity.address.zip, "84532");
+    }
+
+    #[test]
+    fn test_only_price_and_capacity_block_booking() {
+        let base = json!({
+            "title": "Guided Hike",
+            "price_per_person": 40,
+            "capacity": { "minimum": 1, "maximum": 10 }
+        });
+        let activity = activity_from_vertex(&base, &ActivityDefaults::default()).unwrap();
+        assert_eq!(activity.defaulted_fields, vec![DefaultedField::Description, DefaultedField::Duration]);
+        assert!(!activity.needs_pricing_review());
+
+        // A negative price is as good as none
+        let mut free = base.clone();
+        free["price_per_person"] = json!(-5);
+        assert!(activity_from_vertex(&free, &ActivityDefaults::default()).unwrap().needs_pricing_review());
+
+        // Only one bound, or bounds the wrong way round
+        let mut half = base.clone();
+        half["capacity"] = json!({ "minimum": 4 });
+        let activity = activity_from_vertex(&half, &ActivityDefaults::default()).unwrap();
+        assert_eq!((activity.capacity.minimum, activity.capacity.maximum), (4, 20));
+        assert!(activity.needs_pricing_review());
+        half["capacity"] = json!({ "minimum": 30, "maximum": 6 });
+        let activity = activity_from_vertex(&half, &ActivityDefaults::default()).unwrap();
+        assert_eq!((activity.capacity.minimum, activity.capacity.maximum), (6, 6));
+        assert!(activity.needs_pricing_review());
+    }
+
+    #[test]
+    fn test_document_without_title_is_rejected() {
+        let defaults = ActivityDefaults::default();
+        assert_eq!(
+            activity_from_vertex(&json!({ "price_per_person": 10 }), &defaults).unwrap_err(),
+            VertexActivityError::MissingTitle
+        );
+        assert_eq!(activity_from_vertex(&json!("rafting"), &defaults).unwrap_err(), VertexActivityError::NotAnObject);
+    }
+}
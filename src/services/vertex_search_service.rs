@@ -1,6 +1,5 @@
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::env;
 use std::error::Error;
 use std::fmt;
 
@@ -80,36 +79,42 @@ impl From<reqwest::Error> for VertexSearchError {
     }
 }
 
+/// Which Vertex AI Search data store to query. Only present when
+/// `GOOGLE_CLOUD_PROJECT_ID` and `VERTEX_SEARCH_DATA_STORE_ID` are both set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexSearchSettings {
+    pub project_id: String,
+    pub location: String,
+    pub data_store_id: String,
+    pub serving_config: String,
+    /// Used instead of asking the gcloud CLI for a token
+    pub access_token: Option<String>,
+}
+
+impl VertexSearchSettings {
+    pub fn from_lookup(get: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        Some(VertexSearchSettings {
+            project_id: get("GOOGLE_CLOUD_PROJECT_ID")?,
+            location: get("VERTEX_SEARCH_LOCATION").unwrap_or_else(|| "global".to_string()),
+            data_store_id: get("VERTEX_SEARCH_DATA_STORE_ID")?,
+            serving_config: get("VERTEX_SEARCH_SERVING_CONFIG").unwrap_or_else(|| "default_config".to_string()),
+            access_token: get("GOOGLE_CLOUD_ACCESS_TOKEN"),
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct VertexSearchService {
     client: Client,
-    project_id: String,
-    location: String,
-    data_store_id: String,
-    serving_config: String,
+    settings: VertexSearchSettings,
 }
 
 impl VertexSearchService {
-    pub fn new() -> Result<Self, VertexSearchError> {
-        let project_id = env::var("GOOGLE_CLOUD_PROJECT_ID")
-            .map_err(|_| VertexSearchError::EnvironmentError("GOOGLE_CLOUD_PROJECT_ID not set".to_string()))?;
-        
-        let location = env::var("VERTEX_SEARCH_LOCATION")
-            .unwrap_or_else(|_| "global".to_string());
-        
-        let data_store_id = env::var("VERTEX_SEARCH_DATA_STORE_ID")
-            .map_err(|_| VertexSearchError::EnvironmentError("VERTEX_SEARCH_DATA_STORE_ID not set".to_string()))?;
-        
-        let serving_config = env::var("VERTEX_SEARCH_SERVING_CONFIG")
-            .unwrap_or_else(|_| "default_config".to_string());
-
-        Ok(Self {
+    pub fn new(settings: &VertexSearchSettings) -> Self {
+        Self {
             client: Client::new(),
-            project_id,
-            location,
-            data_store_id,
-            serving_config,
-        })
+            settings: settings.clone(),
+        }
     }
 
     pub async fn search_activities(&self, activity_types: &[String], query: &str) -> Result<VertexSearchResponse, VertexSearchError> {
@@ -136,7 +141,7 @@ impl VertexSearchService {
         
         let url = format!(
             "https://discoveryengine.googleapis.com/v1/projects/{}/locations/{}/dataStores/{}/servingConfigs/{}:search",
-            self.project_id, self.location, self.data_store_id, self.serving_config
+            self.settings.project_id, self.settings.location, self.settings.data_store_id, self.settings.serving_config
         );
 
         let response = self.client
@@ -191,33 +196,28 @@ impl VertexSearchService {
 
 
     async fn get_access_token(&self) -> Result<String, VertexSearchError> {
-        // For now, return a placeholder token or use environment variable
+        // For now, use the configured token or the gcloud CLI
         // In production, you would implement proper OAuth2 flow or service account authentication
-        let token = env::var("GOOGLE_CLOUD_ACCESS_TOKEN")
-            .or_else(|_| {
-                // Try getting token from gcloud CLI
-                std::process::Command::new("gcloud")
-                    .args(&["auth", "print-access-token"])
-                    .output()
-                    .map_err(|e| format!("Failed to get gcloud token: {}", e))
-                    .and_then(|output| {
-                        if output.status.success() {
-                            String::from_utf8(output.stdout)
-                                .map_err(|e| format!("Invalid UTF-8 in token: {}", e))
-                                .map(|s| s.trim().to_string())
-                        } else {
-                            Err(format!("gcloud command failed: {}", String::from_utf8_lossy(&output.stderr)))
-                        }
-                    })
+        if let Some(token) = &self.settings.access_token {
+            return Ok(token.clone());
+        }
+
+        // Try getting token from gcloud CLI
+        let token = std::process::Command::new("gcloud")
+            .args(&["auth", "print-access-token"])
+            .output()
+            .map_err(|e| format!("Failed to get gcloud token: {}", e))
+            .and_then(|output| {
+                if output.status.success() {
+                    String::from_utf8(output.stdout)
+                        .map_err(|e| format!("Invalid UTF-8 in token: {}", e))
+                        .map(|s| s.trim().to_string())
+                } else {
+                    Err(format!("gcloud command failed: {}", String::from_utf8_lossy(&output.stderr)))
+                }
             })
             .map_err(|e| VertexSearchError::AuthError(format!("Failed to get access token: {}", e)))?;
 
         Ok(token)
     }
 }
-
-impl Default for VertexSearchService {
-    fn default() -> Self {
-        Self::new().expect("Failed to create VertexSearchService")
-    }
-}
\ No newline at end of file
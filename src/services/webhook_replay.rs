@@ -0,0 +1,236 @@
+//! Replay protection for Stripe webhooks
+//!
+//! The signature check already rejects deliveries signed more than five minutes
+//! ago. On top of that, each event id is claimed in `Account.ProcessedWebhooks`
+//! before it's handled, so a retry or a replayed payload is answered without
+//! running twice, and events created longer ago than Stripe keeps retrying are
+//! refused outright. A claim expires when the event would be refused as stale
+//! anyway, so the collection only holds events that could still arrive.
+//!
+//! Claims keep the verified payload, so an admin can run an event through the
+//! webhook handling again (`POST /admin/stripe/events/{id}/reprocess`) until its
+//! claim expires.
+
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    error::{ErrorKind, WriteError, WriteFailure},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+const HOUR_SECS: i64 = 60 * 60;
+
+/// Stripe retries failed deliveries for up to three days
+pub const DEFAULT_MAX_EVENT_AGE_HOURS: u64 = 72;
+
+/// Whether an event created at `created` (Unix seconds) is too old to act on
+pub fn is_stale(created: i64, now: i64, max_age_hours: u64) -> bool {
+    now - created > max_age_hours as i64 * HOUR_SECS
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessedWebhook {
+    #[serde(rename = "_id")]
+    pub event_id: String,
+    pub event_type: String,
+    pub event_created: DateTime,
+    pub received_at: DateTime,
+    /// When the event turns stale and the claim is no longer needed (TTL)
+    pub expires_at: DateTime,
+    /// The event as Stripe sent it. Absent on claims recorded before it was kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+/// What reprocessing a stored event did
+#[derive(Debug, Serialize)]
+pub struct ReprocessOutcome {
+    pub event_id: String,
+    pub event_type: String,
+    /// Status the webhook handling answered with
+    pub status: u16,
+    /// Its response body
+    pub response: serde_json::Value,
+    pub force_notifications: bool,
+}
+
+impl ReprocessOutcome {
+    pub fn succeeded(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReprocessError {
+    /// No claim for the event, or it expired
+    NotFound,
+    /// Claimed before payloads were kept
+    NoPayload,
+    InvalidPayload(String),
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for ReprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReprocessError::NotFound => write!(f, "Event not found"),
+            ReprocessError::NoPayload => write!(f, "The event was received before payloads were kept"),
+            ReprocessError::InvalidPayload(e) => write!(f, "Stored event can't be read: {}", e),
+            ReprocessError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReprocessError {}
+
+impl From<mongodb::error::Error> for ReprocessError {
+    fn from(e: mongodb::error::Error) -> Self {
+        ReprocessError::DatabaseError(e.to_string())
+    }
+}
+
+/// Written to the admin audit log for each reprocessed event
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookReprocessAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// Always "stripe_event_reprocessed"
+    pub action: String,
+    pub admin_id: ObjectId,
+    pub event_id: String,
+    pub event_type: Option<String>,
+    pub force_notifications: bool,
+    /// Status the handling answered with; absent when the event couldn't be run
+    pub status: Option<u16>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Claim {
+    /// First delivery; handle it
+    New,
+    /// Seen before; answer 200 so Stripe stops retrying
+    AlreadyProcessed,
+}
+
+pub(crate) fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        ErrorKind::Write(WriteFailure::WriteError(WriteError { code: 11000, .. }))
+    )
+}
+
+pub struct ProcessedWebhookService {
+    client: Arc<Client>,
+}
+
+impl ProcessedWebhookService {
+    pub fn new(client: Arc<Client>) -> Self {
+        ProcessedWebhookService { client }
+    }
+
+    fn collection(&self) -> Collection<ProcessedWebhook> {
+        self.client.database("Account").collection("ProcessedWebhooks")
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<(), mongodb::error::Error> {
+        let ttl = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        self.collection().create_index(ttl).await?;
+        Ok(())
+    }
+
+    /// Record the event as being handled, keeping its `payload`. The `_id` is the
+    /// event id, so two deliveries racing each other can't both get `Claim::New`.
+    pub async fn claim(
+        &self,
+        event_id: &str,
+        event_type: &str,
+        created: i64,
+        max_age_hours: u64,
+        payload: &str,
+    ) -> Result<Claim, mongodb::error::Error> {
+        let record = ProcessedWebhook {
+            event_id: event_id.to_string(),
+            event_type: event_type.to_string(),
+            event_created: DateTime::from_millis(created * 1000),
+            received_at: DateTime::now(),
+            expires_at: DateTime::from_millis((created + max_age_hours as i64 * HOUR_SECS) * 1000),
+            payload: Some(payload.to_string()),
+        };
+        match self.collection().insert_one(&record).await {
+            Ok(_) => Ok(Claim::New),
+            Err(e) if is_duplicate_key(&e) => Ok(Claim::AlreadyProcessed),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Drop a claim after handling failed, so Stripe's retry is processed
+    pub async fn release(&self, event_id: &str) -> Result<(), mongodb::error::Error> {
+        self.collection()
+            .delete_one(doc! { "_id": event_id })
+            .await?;
+        Ok(())
+    }
+
+    /// The stored payload of a claimed event
+    pub async fn stored_event(&self, event_id: &str) -> Result<ProcessedWebhook, ReprocessError> {
+        let record = self
+            .collection()
+            .find_one(doc! { "_id": event_id })
+            .await?
+            .ok_or(ReprocessError::NotFound)?;
+        if record.payload.is_none() {
+            return Err(ReprocessError::NoPayload);
+        }
+        Ok(record)
+    }
+
+    pub async fn audit_reprocess(
+        &self,
+        admin_id: ObjectId,
+        event_id: &str,
+        force_notifications: bool,
+        outcome: Option<&ReprocessOutcome>,
+    ) {
+        let audit = WebhookReprocessAudit {
+            id: None,
+            action: "stripe_event_reprocessed".to_string(),
+            admin_id,
+            event_id: event_id.to_string(),
+            event_type: outcome.map(|outcome| outcome.event_type.clone()),
+            force_notifications,
+            status: outcome.map(|outcome| outcome.status),
+            created_at: DateTime::now(),
+        };
+        if let Err(e) = self
+            .client
+            .database("Account")
+            .collection::<WebhookReprocessAudit>("AdminAuditLog")
+            .insert_one(&audit)
+            .await
+        {
+            eprintln!("Failed to write audit log for reprocessing event {}: {}", event_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_older_than_the_retry_window_are_stale() {
+        let now = 1_750_000_000;
+        assert!(!is_stale(now, now, 72));
+        assert!(!is_stale(now - 72 * HOUR_SECS, now, 72));
+        assert!(is_stale(now - 72 * HOUR_SECS - 1, now, 72));
+        // Clock skew putting the event slightly in the future isn't stale
+        assert!(!is_stale(now + 30, now, 72));
+    }
+}
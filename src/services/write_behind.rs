@@ -0,0 +1,245 @@
+//! Write-behind queues for best-effort writes on request paths.
+//!
+//! Search and itinerary reads log submissions and similar bookkeeping. None of it
+//! may fail the request, so handlers enqueue the write and move on; a background
+//! worker stores it and keeps track of failures. When the database is read-only
+//! (a primary stepping down during maintenance) searches keep working, writes are
+//! dropped, and `/health` reports that writes are failing. Security events go
+//! through the same bounded worker (`WriteBehind`) with their own sink.
+
+use mongodb::{
+    bson::{DateTime, Document},
+    Client,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Writes held while the worker is behind. More than this and new writes are
+/// dropped rather than queued without limit during an outage.
+const QUEUE_CAPACITY: usize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct BestEffortWrite {
+    pub database: &'static str,
+    pub collection: &'static str,
+    pub document: Document,
+}
+
+/// Where queued items end up. MongoDB in production; tests inject failures.
+pub trait WriteSink<T = BestEffortWrite> {
+    fn write(&self, item: &T) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+impl WriteSink for Arc<Client> {
+    fn write(&self, write: &BestEffortWrite) -> impl Future<Output = Result<(), String>> + Send {
+        let collection = self
+            .database(write.database)
+            .collection::<Document>(write.collection);
+        let document = write.document.clone();
+        async move {
+            collection
+                .insert_one(document)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// How queued writes have been going
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WriteHealth {
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Dropped because the queue was full or closed
+    pub dropped: u64,
+    pub last_success_at: Option<DateTime>,
+    pub last_failure_at: Option<DateTime>,
+    pub last_error: Option<String>,
+}
+
+impl WriteHealth {
+    /// The most recent write failed
+    pub fn writes_failing(&self) -> bool {
+        match (self.last_failure_at, self.last_success_at) {
+            (Some(failure), Some(success)) => failure > success,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    fn record(&mut self, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                self.succeeded += 1;
+                self.last_success_at = Some(DateTime::now());
+            }
+            Err(e) => {
+                self.failed += 1;
+                self.last_failure_at = Some(DateTime::now());
+                self.last_error = Some(e);
+            }
+        }
+    }
+}
+
+/// A bounded queue drained in order by one background task. Pushing never blocks:
+/// once `QUEUE_CAPACITY` items are waiting, new ones are dropped and counted.
+pub struct WriteBehind<T> {
+    sender: mpsc::Sender<(&'static str, T)>,
+    health: Arc<Mutex<WriteHealth>>,
+}
+
+impl<T> Clone for WriteBehind<T> {
+    fn clone(&self) -> Self {
+        WriteBehind {
+            sender: self.sender.clone(),
+            health: self.health.clone(),
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static> WriteBehind<T> {
+    pub fn start<S: WriteSink<T> + Send + Sync + 'static>(sink: S) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<(&'static str, T)>(QUEUE_CAPACITY);
+        let health = Arc::new(Mutex::new(WriteHealth::default()));
+        let worker_health = health.clone();
+        tokio::spawn(async move {
+            while let Some((label, item)) = receiver.recv().await {
+                let result = sink.write(&item).await;
+                if let Err(e) = &result {
+                    eprintln!("Failed to store {} (best effort, dropped): {}", label, e);
+                }
+                if let Ok(mut health) = worker_health.lock() {
+                    health.record(result);
+                }
+            }
+        });
+        WriteBehind { sender, health }
+    }
+
+    /// Queue `item`, described as `label` in logs. Never blocks and never fails the caller.
+    pub fn push(&self, label: &'static str, item: T) {
+        if let Err(e) = self.sender.try_send((label, item)) {
+            eprintln!("Write-behind queue unavailable, dropping {}: {}", label, e);
+            if let Ok(mut health) = self.health.lock() {
+                health.dropped += 1;
+            }
+        }
+    }
+
+    pub fn health(&self) -> WriteHealth {
+        self.health
+            .lock()
+            .map(|health| health.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone)]
+pub struct WriteBehindQueue {
+    writes: WriteBehind<BestEffortWrite>,
+}
+
+impl WriteBehindQueue {
+    /// Start the background worker that drains the queue into MongoDB
+    pub fn start(client: Arc<Client>) -> Self {
+        Self::start_with(client)
+    }
+
+    pub fn start_with<S: WriteSink + Send + Sync + 'static>(sink: S) -> Self {
+        WriteBehindQueue {
+            writes: WriteBehind::start(sink),
+        }
+    }
+
+    /// Queue an insert of `record`. Never blocks and never fails the caller.
+    pub fn insert<T: Serialize>(
+        &self,
+        label: &'static str,
+        database: &'static str,
+        collection: &'static str,
+        record: &T,
+    ) {
+        let document = match mongodb::bson::to_document(record) {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("Failed to serialize {}: {}", label, e);
+                return;
+            }
+        };
+        self.writes.push(
+            label,
+            BestEffortWrite {
+                database,
+                collection,
+                document,
+            },
+        );
+    }
+
+    pub fn health(&self) -> WriteHealth {
+        self.writes.health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+    use std::time::Duration;
+
+    /// A database that accepts reads but refuses every write
+    struct ReadOnlySink;
+
+    impl WriteSink for ReadOnlySink {
+        fn write(&self, _write: &BestEffortWrite) -> impl Future<Output = Result<(), String>> + Send {
+            std::future::ready(Err("NotWritablePrimary".to_string()))
+        }
+    }
+
+    struct AcceptingSink;
+
+    impl WriteSink for AcceptingSink {
+        fn write(&self, _write: &BestEffortWrite) -> impl Future<Output = Result<(), String>> + Send {
+            std::future::ready(Ok(()))
+        }
+    }
+
+    async fn settle(queue: &WriteBehindQueue, writes: u64) -> WriteHealth {
+        for _ in 0..100 {
+            let health = queue.health();
+            if health.succeeded + health.failed >= writes {
+                return health;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        queue.health()
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_writes_are_recorded_not_raised() {
+        let queue = WriteBehindQueue::start_with(ReadOnlySink);
+        queue.insert("search submission", "Travelers", "Submission", &doc! { "location_start": "Denver" });
+        queue.insert("search submission", "Travelers", "Submission", &doc! { "location_start": "Boulder" });
+
+        let health = settle(&queue, 2).await;
+        assert_eq!(health.failed, 2);
+        assert_eq!(health.succeeded, 0);
+        assert!(health.writes_failing());
+        assert_eq!(health.last_error.as_deref(), Some("NotWritablePrimary"));
+    }
+
+    #[actix_rt::test]
+    async fn test_healthy_writes() {
+        let queue = WriteBehindQueue::start_with(AcceptingSink);
+        assert!(!queue.health().writes_failing());
+        queue.insert("search submission", "Travelers", "Submission", &doc! {});
+
+        let health = settle(&queue, 1).await;
+        assert_eq!(health.succeeded, 1);
+        assert!(!health.writes_failing());
+    }
+}
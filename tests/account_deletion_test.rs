@@ -0,0 +1,110 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates and deletes its own users.
+
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::Collection;
+use serde_json::json;
+use serial_test::serial;
+use std::sync::Mutex;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::routes::account::account_info::delete_account_with;
+use actota_api::services::payment_teardown::{
+    CustomerDisposition, CustomerVault, PaymentTeardownService, PendingTeardown, TeardownOutcome,
+};
+
+/// A Stripe customer with two saved cards. Detaching fails while `down` is set.
+#[derive(Default)]
+struct MockStripeCustomer {
+    down: Mutex<bool>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl CustomerVault for MockStripeCustomer {
+    async fn payment_methods(&self, _customer_id: &str) -> Result<Vec<String>, String> {
+        Ok(vec!["pm_card_1".to_string(), "pm_card_2".to_string()])
+    }
+
+    async fn detach(&self, payment_method_id: &str) -> Result<(), String> {
+        if *self.down.lock().unwrap() {
+            return Err("Stripe API unavailable".to_string());
+        }
+        self.calls.lock().unwrap().push(format!("detach {}", payment_method_id));
+        Ok(())
+    }
+
+    async fn clear_default(&self, customer_id: &str, _mark: bool) -> Result<(), String> {
+        self.calls.lock().unwrap().push(format!("clear {}", customer_id));
+        Ok(())
+    }
+
+    async fn delete_customer(&self, customer_id: &str) -> Result<(), String> {
+        self.calls.lock().unwrap().push(format!("delete {}", customer_id));
+        Ok(())
+    }
+}
+
+async fn insert_user(users: &Collection<User>, customer_id: &str) -> ObjectId {
+    let user: User = serde_json::from_value(json!({
+        "email": format!("delete-{}@example.com", ObjectId::new().to_hex()),
+        "password": "hashed",
+        "customer_id": customer_id,
+    }))
+    .unwrap();
+    users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_deleting_an_account_tears_down_its_stripe_customer() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let queue: Collection<PendingTeardown> = client.database("Account").collection("PaymentTeardowns");
+
+    let stripe = MockStripeCustomer::default();
+    let user_id = insert_user(&users, "cus_delete_ok").await;
+    let outcome = delete_account_with(client.clone(), &stripe, user_id, CustomerDisposition::Delete)
+        .await
+        .unwrap();
+    assert_eq!(outcome, Some(TeardownOutcome::Completed { payment_methods_detached: 2 }));
+    assert_eq!(
+        *stripe.calls.lock().unwrap(),
+        ["detach pm_card_1", "detach pm_card_2", "clear cus_delete_ok", "delete cus_delete_ok"]
+    );
+    assert!(users.find_one(doc! { "_id": user_id }).await.unwrap().is_none());
+
+    // Stripe being down doesn't stop the deletion; the teardown waits in the queue
+    *stripe.down.lock().unwrap() = true;
+    stripe.calls.lock().unwrap().clear();
+    let user_id = insert_user(&users, "cus_delete_later").await;
+    let outcome = delete_account_with(client.clone(), &stripe, user_id, CustomerDisposition::Delete)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, Some(TeardownOutcome::Queued { .. })));
+    assert!(users.find_one(doc! { "_id": user_id }).await.unwrap().is_none());
+    let pending = queue.find_one(doc! { "user_id": user_id }).await.unwrap().unwrap();
+    assert_eq!(pending.customer_id, "cus_delete_later");
+    assert_eq!(pending.attempts, 1);
+
+    // Once Stripe is back, the next retry run finishes it
+    *stripe.down.lock().unwrap() = false;
+    let later = DateTime::from_millis(DateTime::now().timestamp_millis() + 60 * 60 * 1000);
+    let summary = PaymentTeardownService::new(client.clone())
+        .retry_due(&stripe, later)
+        .await
+        .unwrap();
+    assert!(summary.completed >= 1);
+    assert!(queue.find_one(doc! { "user_id": user_id }).await.unwrap().is_none());
+    assert!(stripe.calls.lock().unwrap().contains(&"delete cus_delete_later".to_string()));
+
+    // Nobody to delete
+    let outcome = delete_account_with(client.clone(), &stripe, ObjectId::new(), CustomerDisposition::Delete)
+        .await
+        .unwrap();
+    assert_eq!(outcome, None);
+}
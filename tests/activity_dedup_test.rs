@@ -0,0 +1,202 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own activities, itinerary and
+//! review request, and removes them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::routes::account::auth::generate_token;
+
+fn activity(id: ObjectId, company: &str, title: &str, description: &str) -> Document {
+    doc! {
+        "_id": id,
+        "company": company,
+        "company_id": "dedup-test",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": title,
+        "description": description,
+        "activity_types": ["rafting"],
+        "tags": [],
+        "price_per_person": 95.0,
+        "duration_minutes": 180,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Cañon City", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 12 },
+        "created_at": DateTime::now(),
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_duplicates_are_found_and_merged() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    // A company name no other test data uses
+    let company = format!("Dedup Test Outfitters {}", ObjectId::new().to_hex());
+    let activities = client.database("Options").collection::<Document>("Activity");
+    let (primary, duplicate, distinct) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+    activities
+        .insert_many([
+            activity(primary, &company, "Royal Gorge Rafting", ""),
+            activity(duplicate, &company, "Royal Gorge White Water Rafting Trip", "Class III-IV rapids"),
+            activity(distinct, &company, "Sunset Horseback Ride", "Two hours on the ridge"),
+        ])
+        .await
+        .unwrap();
+
+    let itinerary = FeaturedVacation {
+        trip_name: "Dedup test trip".to_string(),
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![
+                    DayItem::Activity { time: "09:00:00".to_string(), activity_id: duplicate },
+                    DayItem::Activity { time: "14:00:00".to_string(), activity_id: distinct },
+                ],
+            )]),
+        },
+        defaulted_activity_ids: vec![duplicate],
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id().unwrap();
+    let review_requests = client.database("Account").collection::<Document>("ReviewRequests");
+    let review_request_id = review_requests
+        .insert_one(doc! {
+            "booking_id": ObjectId::new(),
+            "user_id": ObjectId::new(),
+            "activities": [{ "id": duplicate, "title": "Royal Gorge White Water Rafting Trip" }],
+            "sent_at": DateTime::now(),
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "ops@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+
+    // The near-duplicate pair clusters; the horseback ride doesn't
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/admin/activities/duplicates")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let ours: Vec<&Value> = body["clusters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|cluster| cluster["company"] == company.as_str())
+        .collect();
+    assert_eq!(ours.len(), 1);
+    let mut ids: Vec<&str> = ours[0]["activities"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|activity| activity["id"].as_str().unwrap())
+        .collect();
+    ids.sort();
+    let mut expected = vec![primary.to_hex(), duplicate.to_hex()];
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert!(ours[0]["pairs"][0]["score"].as_f64().unwrap() >= 75.0);
+
+    let merge = |dry_run: bool| {
+        test::TestRequest::post()
+            .uri("/admin/activities/merge")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({
+                "primary_id": primary.to_hex(),
+                "duplicate_ids": [duplicate.to_hex()],
+                "dry_run": dry_run
+            }))
+            .to_request()
+    };
+    let day_ids = |itinerary: &FeaturedVacation| itinerary.days.activity_ids();
+
+    // A dry run reports the changes and makes none
+    let resp = test::call_service(&app, merge(true)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["dry_run"], true);
+    assert_eq!(body["data"]["itinerary_ids"], json!([{ "$oid": itinerary_id.to_hex() }]));
+    assert_eq!(body["data"]["review_requests"], 1);
+    assert_eq!(body["data"]["references_updated"], 3);
+    assert_eq!(body["data"]["fields_merged"], json!(["description"]));
+    let stored = itineraries.find_one(doc! { "_id": itinerary_id }).await.unwrap().unwrap();
+    assert_eq!(day_ids(&stored), vec![duplicate, distinct]);
+    let stored_duplicate = activities.find_one(doc! { "_id": duplicate }).await.unwrap().unwrap();
+    assert!(!stored_duplicate.contains_key("merged_into"));
+    let stored_primary = activities.find_one(doc! { "_id": primary }).await.unwrap().unwrap();
+    assert_eq!(stored_primary.get_str("description").unwrap(), "");
+    let audit_log = client.database("Account").collection::<Document>("AdminAuditLog");
+    assert_eq!(audit_log.count_documents(doc! { "admin_id": admin_id }).await.unwrap(), 0);
+
+    // The merge rewrites the itinerary's day item and flagged activity, and the
+    // review request
+    let resp = test::call_service(&app, merge(false)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["references_updated"], 3);
+    let stored = itineraries.find_one(doc! { "_id": itinerary_id }).await.unwrap().unwrap();
+    assert_eq!(day_ids(&stored), vec![primary, distinct]);
+    assert_eq!(stored.defaulted_activity_ids, vec![primary]);
+    let request = review_requests.find_one(doc! { "_id": review_request_id }).await.unwrap().unwrap();
+    let activity_ids: Vec<ObjectId> = request
+        .get_array("activities")
+        .unwrap()
+        .iter()
+        .map(|activity| activity.as_document().unwrap().get_object_id("id").unwrap())
+        .collect();
+    assert_eq!(activity_ids, vec![primary]);
+    let stored_primary = activities.find_one(doc! { "_id": primary }).await.unwrap().unwrap();
+    assert_eq!(stored_primary.get_str("description").unwrap(), "Class III-IV rapids");
+    let stored_duplicate = activities.find_one(doc! { "_id": duplicate }).await.unwrap().unwrap();
+    assert_eq!(stored_duplicate.get_object_id("merged_into").unwrap(), primary);
+    assert!(stored_duplicate.get_datetime("deleted_at").is_ok());
+    assert_eq!(audit_log.count_documents(doc! { "admin_id": admin_id }).await.unwrap(), 1);
+
+    // A merged duplicate can't be merged again
+    let resp = test::call_service(&app, merge(false)).await;
+    assert_eq!(resp.status(), 409);
+
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    review_requests.delete_one(doc! { "_id": review_request_id }).await.unwrap();
+    activities.delete_many(doc! { "_id": { "$in": [primary, duplicate, distinct] } }).await.unwrap();
+    audit_log.delete_many(doc! { "admin_id": admin_id }).await.unwrap();
+}
@@ -0,0 +1,184 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own itinerary, users and bookings.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::Collection;
+use serial_test::serial;
+use std::future::Future;
+use std::sync::Mutex;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::models::bookings::{BookingDetails, Party, PaymentStatus};
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::models::money::Money;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::admin_booking_service::{
+    AdminBookingError, AdminBookingRequest, AdminBookingService, Customer, InvitationSender,
+};
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::booking_confirmation::CapturedPayment;
+
+fn config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
+
+/// Remembers who was invited instead of emailing them
+#[derive(Default)]
+struct RecordedInvitations(Mutex<Vec<String>>);
+
+impl InvitationSender for RecordedInvitations {
+    fn send_invitation(&self, user: &User, _trip_name: &str) -> impl Future<Output = Result<(), String>> + Send {
+        self.0.lock().unwrap().push(user.email.clone());
+        std::future::ready(Ok(()))
+    }
+}
+
+fn request(customer: Customer, itinerary_id: ObjectId, paid_cents: i64, override_amount_check: bool) -> AdminBookingRequest {
+    let arrival = DateTime::now().timestamp_millis() + 30 * 24 * 60 * 60 * 1000;
+    AdminBookingRequest {
+        customer,
+        itinerary_id,
+        arrival_datetime: DateTime::from_millis(arrival),
+        departure_datetime: DateTime::from_millis(arrival + 3 * 24 * 60 * 60 * 1000),
+        party: Party { adults: 2, children: 0, infants: 0 },
+        payment: Some(CapturedPayment {
+            payment_intent_id: format!("pi_test_{}", ObjectId::new()),
+            amount: paid_cents,
+            currency: "usd".to_string(),
+        }),
+        override_amount_check,
+        internal_note: Some("Booked by phone".to_string()),
+        special_requests: Some("Ground-floor room, one guest uses a wheelchair".to_string()),
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_phone_booking_for_a_new_customer() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries
+        .insert_one(FeaturedVacation {
+            trip_name: "Phone booking test trip".to_string(),
+            person_cost: Some(Money::from_dollars(450.0)),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let service = AdminBookingService::new(client.clone());
+    service.ensure_indexes().await.unwrap();
+    let cache = AvailabilityCache::default();
+    let invitations = RecordedInvitations::default();
+    let admin_id = ObjectId::new();
+    let email = format!("phone-{}@example.com", ObjectId::new());
+    let customer = Customer::Email {
+        email: email.clone(),
+        first_name: Some("Pat".to_string()),
+        last_name: None,
+    };
+
+    // Two adults at $450 is $900; $500 needs the override
+    let err = service
+        .create(admin_id, request(customer.clone(), itinerary_id, 50_000, false), &cache, &invitations)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AdminBookingError::AmountMismatch(check) if check.expected == Some(Money::from_cents(90_000))));
+    assert!(invitations.0.lock().unwrap().is_empty(), "no account is made for a rejected booking");
+
+    let outcome = service
+        .create(admin_id, request(customer.clone(), itinerary_id, 50_000, true), &cache, &invitations)
+        .await
+        .unwrap();
+    assert!(outcome.created_account);
+    assert!(outcome.invitation_sent);
+    assert_eq!(*invitations.0.lock().unwrap(), vec![email.clone()]);
+    assert!(outcome.booking.created_by_admin);
+    assert_eq!(outcome.booking.status, PaymentStatus::Confirmed);
+    assert_eq!(
+        outcome.booking.special_requests.as_deref(),
+        Some("Ground-floor room, one guest uses a wheelchair")
+    );
+
+    let booking_id = outcome.booking.id.unwrap();
+    let audit = client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("AdminAuditLog")
+        .find_one(doc! { "booking_id": booking_id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(audit.get_bool("override_amount_check"), Ok(true));
+    assert_eq!(audit.get_str("internal_note"), Ok("Booked by phone"));
+    assert_eq!(audit.get_str("captured_currency"), Ok("usd"));
+
+    // A second booking for the same email reuses the account without inviting again
+    let again = service
+        .create(admin_id, request(customer.clone(), itinerary_id, 90_000, false), &cache, &invitations)
+        .await
+        .unwrap();
+    assert!(!again.created_account);
+    assert_eq!(again.booking.user_id, outcome.booking.user_id);
+    assert_eq!(invitations.0.lock().unwrap().len(), 1);
+
+    // A payment already behind a booking can't be booked again
+    let mut reused = request(customer, itinerary_id, 90_000, false);
+    reused.payment.as_mut().unwrap().payment_intent_id = again.booking.transaction_id.clone().unwrap();
+    let err = service.create(admin_id, reused, &cache, &invitations).await.unwrap_err();
+    assert!(matches!(err, AdminBookingError::PaymentAlreadyUsed));
+
+    // Both show up in the customer's own bookings
+    let user_id = outcome.booking.user_id;
+    let config = config(&mongo_uri);
+    let secret = config.jwt_secret.clone();
+    let token = generate_token(&secret, &email, user_id, None).unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(client.clone())),
+    )
+    .await;
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&format!("/account/{}/bookings", user_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    let bookings: Vec<BookingDetails> = test::read_body_json(response).await;
+    let ids: Vec<ObjectId> = bookings.iter().filter_map(|booking| booking.id).collect();
+    assert!(ids.contains(&booking_id));
+    assert!(ids.contains(&again.booking.id.unwrap()));
+    assert!(bookings.iter().all(|booking| booking.created_by_admin));
+
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    bookings.delete_many(doc! { "user_id": user_id }).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<User>("Users")
+        .delete_one(doc! { "_id": user_id })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,145 @@
+//! Needs MongoDB at `MONGODB_URI`. Stores its own personal access tokens and
+//! removes them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::api_token::TokenScope;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::api_token_service::{new_api_token, ApiTokenRateLimiter, ApiTokenService};
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::write_behind::WriteBehindQueue;
+
+#[actix_rt::test]
+#[serial]
+async fn test_personal_access_tokens_on_search_and_bookings() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MIN_SEARCH_RESULTS" => Some("0".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default()))
+            .app_data(web::Data::new(WriteBehindQueue::start(client.clone())))
+            .app_data(web::Data::new(ApiTokenRateLimiter::new(3))),
+    )
+    .await;
+
+    let user_id = ObjectId::new();
+    let service = ApiTokenService::new(client.clone());
+    service.ensure_indexes().await.unwrap();
+    let (token, api_token) = new_api_token(
+        user_id,
+        "script",
+        vec![TokenScope::SearchRead, TokenScope::BookingsRead],
+        None,
+        DateTime::now(),
+    );
+    service.insert(&api_token).await.unwrap();
+
+    let search = |token: &str| {
+        test::TestRequest::post()
+            .uri("/itineraries/search")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({
+                "locations": ["Denver, CO"],
+                "activities": ["Hiking"],
+                "arrival_datetime": "2031-07-10T00:00:00",
+                "departure_datetime": "2031-07-12T00:00:00",
+            }))
+            .to_request()
+    };
+
+    // Search works with the token
+    let response = test::call_service(&app, search(&token)).await;
+    assert!(response.status().is_success(), "search failed: {}", response.status());
+
+    // Creating a booking is a write no scope covers
+    let response = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri(&format!("/account/{}/bookings/itinerary/{}", user_id, ObjectId::new()))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), 403);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], "insufficient_scope");
+
+    // A token without the itineraries scope can't browse them either
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&format!("/itineraries/{}", ObjectId::new()))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), 403);
+
+    // Requests are limited per token, and the calls above used all three
+    let response = test::call_service(&app, search(&token)).await;
+    assert_eq!(response.status(), 429);
+
+    // Once revoked by its owner the token stops working straight away
+    let (revoked, revoked_token) =
+        new_api_token(user_id, "old script", vec![TokenScope::SearchRead], None, DateTime::now());
+    service.insert(&revoked_token).await.unwrap();
+    let session = generate_token("test_secret", "pat@example.com", user_id, None).unwrap();
+    let response = test::call_service(
+        &app,
+        test::TestRequest::delete()
+            .uri(&format!("/account/{}/api-tokens/{}", user_id, revoked_token.id))
+            .insert_header(("Authorization", format!("Bearer {}", session)))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    let response = test::call_service(&app, search(&revoked)).await;
+    assert_eq!(response.status(), 401);
+
+    // Searching without any token is still open to everyone
+    let response = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/itineraries/search")
+            .set_json(json!({
+                "locations": ["Denver, CO"],
+                "activities": ["Hiking"],
+                "arrival_datetime": "2031-07-10T00:00:00",
+                "departure_datetime": "2031-07-12T00:00:00",
+            }))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success(), "search failed: {}", response.status());
+
+    client
+        .database("Account")
+        .collection::<Document>("ApiTokens")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
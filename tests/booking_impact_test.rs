@@ -0,0 +1,159 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own itinerary and bookings.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::bookings::{BookingDetails, PaymentStatus};
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::booking_impact_service::BookingChangeNotice;
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+async fn booking(
+    bookings: &Collection<BookingDetails>,
+    itinerary_id: ObjectId,
+    status: PaymentStatus,
+    arrives_in_days: i64,
+) -> ObjectId {
+    let arrival = DateTime::from_millis(DateTime::now().timestamp_millis() + arrives_in_days * DAY_MILLIS);
+    bookings
+        .insert_one(BookingDetails {
+            id: None,
+            user_id: ObjectId::new(),
+            itinerary_id,
+            customer_id: None,
+            transaction_id: None,
+            arrival_datetime: arrival,
+            departure_datetime: DateTime::from_millis(arrival.timestamp_millis() + 2 * DAY_MILLIS),
+            status,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: None,
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_removing_a_booked_activity_needs_acknowledgement_and_notifies_travelers() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let (rafting, hiking) = (ObjectId::new(), ObjectId::new());
+    let day = |day: u32, items: Value| json!({ "day": day, "items": items });
+    let rafting_item = json!({ "type": "activity", "time": "09:00", "activity_id": { "$oid": rafting.to_hex() } });
+    let hiking_item = json!({ "type": "activity", "time": "10:00", "activity_id": { "$oid": hiking.to_hex() } });
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let mut itinerary = FeaturedVacation {
+        trip_name: "Booking impact test".to_string(),
+        ..Default::default()
+    };
+    itinerary.days.days.insert("1".to_string(), vec![serde_json::from_value(rafting_item.clone()).unwrap()]);
+    itinerary.days.days.insert("2".to_string(), vec![serde_json::from_value(hiking_item.clone()).unwrap()]);
+    let itinerary_id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    let upcoming = booking(&bookings, itinerary_id, PaymentStatus::Confirmed, 10).await;
+    booking(&bookings, itinerary_id, PaymentStatus::Confirmed, -10).await;
+    booking(&bookings, itinerary_id, PaymentStatus::Cancelled, 10).await;
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let token = generate_token("test_secret", "admin@example.com", ObjectId::new(), Some(&UserRole::Admin)).unwrap();
+    let update = |body: Value| {
+        test::TestRequest::put()
+            .uri(&format!("/admin/itineraries/{}/days", itinerary_id.to_hex()))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request()
+    };
+    let notices: Collection<BookingChangeNotice> = client.database("Account").collection("BookingChangeNotices");
+
+    // Adding a transfer doesn't touch what anyone booked
+    let shuttle = json!({
+        "type": "transportation",
+        "time": "08:00",
+        "name": "Shuttle",
+        "location": { "name": "Hotel", "coordinates": [0.0, 0.0] },
+    });
+    let resp = test::call_service(
+        &app,
+        update(json!({ "days": [day(1, json!([shuttle, rafting_item])), day(2, json!([hiking_item]))] })),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["booking_impact"]["kind"], "cosmetic");
+    assert_eq!(notices.count_documents(doc! { "itinerary_id": itinerary_id }).await.unwrap(), 0);
+
+    // Dropping rafting needs acknowledging, and nothing is saved until it is
+    let removal = json!({ "days": [day(1, json!([hiking_item]))] });
+    let resp = test::call_service(&app, update(removal.clone())).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["booking_impact"]["kind"], "removed_activity");
+    assert_eq!(body["booking_impact"]["affected_booking_ids"], json!([{ "$oid": upcoming.to_hex() }]));
+    let stored = itineraries.find_one(doc! { "_id": itinerary_id }).await.unwrap().unwrap();
+    assert_eq!(stored.days.days.len(), 2);
+
+    let mut acknowledged = removal;
+    acknowledged["acknowledge_booking_impact"] = json!(true);
+    let resp = test::call_service(&app, update(acknowledged)).await;
+    assert_eq!(resp.status(), 200);
+
+    let notified: Vec<ObjectId> = notices
+        .distinct("booking_id", doc! { "itinerary_id": itinerary_id })
+        .await
+        .unwrap()
+        .into_iter()
+        .filter_map(|id| id.as_object_id())
+        .collect();
+    assert_eq!(notified, vec![upcoming]);
+
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    bookings.delete_many(doc! { "itinerary_id": itinerary_id }).await.unwrap();
+    notices.delete_many(doc! { "itinerary_id": itinerary_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("AdminAuditLog")
+        .delete_many(doc! { "itinerary_id": itinerary_id })
+        .await
+        .unwrap();
+}
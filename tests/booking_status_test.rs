@@ -0,0 +1,121 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own bookings and removes them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::bookings::PaymentStatus;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::booking_status_service::BookingStatusAudit;
+
+#[actix_rt::test]
+#[serial]
+async fn test_bulk_status_update_applies_legal_transitions_and_audits_each() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(AvailabilityCache::default()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    let bookings = client.database("Account").collection::<Document>("Bookings");
+    let user_id = ObjectId::new();
+    let booking = |status: &str| {
+        let bookings = bookings.clone();
+        let status = status.to_string();
+        async move {
+            bookings
+                .insert_one(doc! {
+                    "user_id": user_id,
+                    "itinerary_id": ObjectId::new(),
+                    "arrival_datetime": DateTime::from_millis(1_700_000_000_000),
+                    "departure_datetime": DateTime::from_millis(1_700_200_000_000),
+                    "status": status,
+                })
+                .await
+                .unwrap()
+                .inserted_id
+                .as_object_id()
+                .unwrap()
+        }
+    };
+    let in_progress = booking("in_progress").await;
+    let refunded = booking("refunded").await;
+    let missing = ObjectId::new();
+
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "ops@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let request = |token: &str| {
+        test::TestRequest::post()
+            .uri("/admin/bookings/status")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({
+                "booking_ids": [in_progress.to_hex(), refunded.to_hex(), missing.to_hex()],
+                "status": "completed",
+                "note": "Trips ended during the outage"
+            }))
+            .to_request()
+    };
+
+    // Travelers can't use it
+    let traveler = generate_token("test_secret", "traveler@example.com", user_id, None).unwrap();
+    let resp = test::call_service(&app, request(&traveler)).await;
+    assert_eq!(resp.status(), 403);
+
+    let resp = test::call_service(&app, request(&token)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!((body["updated"].as_u64(), body["rejected"].as_u64(), body["not_found"].as_u64()), (Some(1), Some(1), Some(1)));
+    assert_eq!(body["results"][0]["outcome"], "updated");
+    assert_eq!(body["results"][1]["outcome"], "rejected");
+    assert_eq!(body["results"][1]["reason"], "Can't move a booking from refunded to completed");
+    assert_eq!(body["results"][2]["outcome"], "not_found");
+
+    let status = |id: ObjectId| {
+        let bookings = bookings.clone();
+        async move {
+            bookings
+                .find_one(doc! { "_id": id })
+                .await
+                .unwrap()
+                .unwrap()
+                .get_str("status")
+                .unwrap()
+                .to_string()
+        }
+    };
+    assert_eq!(status(in_progress).await, "completed");
+    assert_eq!(status(refunded).await, "refunded");
+
+    let audit_log = client.database("Account").collection::<BookingStatusAudit>("AdminAuditLog");
+    let audits = audit_log.count_documents(doc! { "admin_id": admin_id }).await.unwrap();
+    assert_eq!(audits, 1);
+    let audit = audit_log.find_one(doc! { "admin_id": admin_id }).await.unwrap().unwrap();
+    assert_eq!(audit.booking_id, in_progress);
+    assert_eq!((audit.from, audit.to), (PaymentStatus::InProgress, PaymentStatus::Completed));
+    assert_eq!(audit.note.as_deref(), Some("Trips ended during the outage"));
+
+    bookings.delete_many(doc! { "user_id": user_id }).await.unwrap();
+    audit_log.delete_many(doc! { "admin_id": admin_id }).await.unwrap();
+}
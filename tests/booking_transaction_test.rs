@@ -0,0 +1,228 @@
+//! Needs MongoDB running as a replica set at `MONGODB_REPLICA_SET_URI` (falling
+//! back to `MONGODB_URI`), since standalone servers can't run transactions.
+//! Creates its own itineraries and bookings.
+
+mod common;
+
+use chrono::NaiveDate;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::{Client, Collection};
+use serial_test::serial;
+use std::collections::HashMap;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::bookings::{BookingDetails, PaymentStatus};
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::booking_confirmation::{
+    BookingConfirmationService, CapturedPayment, ConfirmationOutcome,
+};
+
+struct Seeded {
+    booking: BookingDetails,
+    itinerary_id: ObjectId,
+    /// Its inventory record is written first and is fine
+    first_activity: ObjectId,
+    /// Its inventory record can't be incremented, so the confirmation fails here
+    broken_activity: ObjectId,
+    date: NaiveDate,
+}
+
+/// A pending booking for a one-day trip with two activities, the second of which
+/// has a corrupt inventory record
+async fn seed(client: &Client) -> Seeded {
+    let (first_activity, broken_activity) = (ObjectId::new(), ObjectId::new());
+    let activity = |time: &str, activity_id| DayItem::Activity {
+        time: time.to_string(),
+        activity_id,
+    };
+    let itinerary = FeaturedVacation {
+        trip_name: "Transaction test trip".to_string(),
+        length_days: 1,
+        adults: Some(2),
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![activity("09:00:00", first_activity), activity("14:00:00", broken_activity)],
+            )]),
+        },
+        ..Default::default()
+    };
+    let itinerary_id = client
+        .database("Itineraries")
+        .collection::<FeaturedVacation>("Featured")
+        .insert_one(&itinerary)
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let date = NaiveDate::from_ymd_opt(2031, 6, 1).unwrap();
+    let arrival = DateTime::parse_rfc3339_str("2031-06-01T00:00:00Z").unwrap();
+    client
+        .database("Options")
+        .collection::<Document>("ActivityBookings")
+        .insert_one(doc! { "activity_id": broken_activity, "date": date.to_string(), "booked": "corrupt" })
+        .await
+        .unwrap();
+
+    let mut booking = BookingDetails {
+        id: None,
+        user_id: ObjectId::new(),
+        itinerary_id,
+        customer_id: None,
+        transaction_id: None,
+        arrival_datetime: arrival,
+        departure_datetime: arrival,
+        status: PaymentStatus::Pending,
+        bookings: None,
+        gift_card_redemption_id: None,
+        gift_card_amount: None,
+        special_requests: None,
+        party: None,
+        created_by_admin: false,
+        reservation_id: None,
+        modifications: Vec::new(),
+        review_request_sent_at: None,
+        created_at: None,
+        updated_at: None,
+    };
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    booking.id = bookings.insert_one(&booking).await.unwrap().inserted_id.as_object_id();
+
+    Seeded {
+        booking,
+        itinerary_id,
+        first_activity,
+        broken_activity,
+        date,
+    }
+}
+
+fn payment() -> CapturedPayment {
+    CapturedPayment {
+        payment_intent_id: format!("pi_test_{}", ObjectId::new()),
+        amount: 40_000,
+        currency: "usd".to_string(),
+    }
+}
+
+async fn booked(client: &Client, activity_id: ObjectId, date: NaiveDate) -> Option<Document> {
+    client
+        .database("Options")
+        .collection::<Document>("ActivityBookings")
+        .find_one(doc! { "activity_id": activity_id, "date": date.to_string() })
+        .await
+        .unwrap()
+}
+
+async fn reconciliation_queued(client: &Client, booking_id: ObjectId) -> bool {
+    client
+        .database("Account")
+        .collection::<Document>("PaymentReconciliation")
+        .find_one(doc! { "booking_id": booking_id })
+        .await
+        .unwrap()
+        .is_some()
+}
+
+async fn clean_up(client: &Client, seeded: &Seeded) {
+    let booking_id = seeded.booking.id.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("Bookings")
+        .delete_one(doc! { "_id": booking_id })
+        .await
+        .unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("PaymentReconciliation")
+        .delete_many(doc! { "booking_id": booking_id })
+        .await
+        .unwrap();
+    client
+        .database("Options")
+        .collection::<Document>("ActivityBookings")
+        .delete_many(doc! { "activity_id": { "$in": [seeded.first_activity, seeded.broken_activity] } })
+        .await
+        .unwrap();
+    client
+        .database("Itineraries")
+        .collection::<Document>("Featured")
+        .delete_one(doc! { "_id": seeded.itinerary_id })
+        .await
+        .unwrap();
+}
+
+async fn client() -> Option<std::sync::Arc<Client>> {
+    let mongo_uri = std::env::var("MONGODB_REPLICA_SET_URI")
+        .ok()
+        .or_else(common::mongodb_uri)?;
+    Some(create_mongo_client(&mongo_uri).await)
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_failed_confirmation_rolls_back_every_write() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let seeded = seed(&client).await;
+    let booking_id = seeded.booking.id.unwrap();
+
+    let result = BookingConfirmationService::new(client.clone())
+        .with_transactions(true)
+        .confirm(&AvailabilityCache::default(), &seeded.booking, &payment())
+        .await;
+    assert!(result.is_err());
+
+    // Neither the status change nor the first activity's seats were kept
+    let stored = client
+        .database("Account")
+        .collection::<BookingDetails>("Bookings")
+        .find_one(doc! { "_id": booking_id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, PaymentStatus::Pending);
+    assert_eq!(stored.transaction_id, None);
+    assert!(booked(&client, seeded.first_activity, seeded.date).await.is_none());
+    // The captured payment waits to be settled by hand
+    assert!(reconciliation_queued(&client, booking_id).await);
+
+    clean_up(&client, &seeded).await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_without_transactions_confirmation_writes_in_sequence() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let seeded = seed(&client).await;
+    let booking_id = seeded.booking.id.unwrap();
+
+    let outcome = BookingConfirmationService::new(client.clone())
+        .confirm(&AvailabilityCache::default(), &seeded.booking, &payment())
+        .await
+        .unwrap();
+    assert!(matches!(outcome, ConfirmationOutcome::Confirmed(_)));
+
+    // The booking and the seats written before the failure stay; the failure is only logged
+    let stored = client
+        .database("Account")
+        .collection::<BookingDetails>("Bookings")
+        .find_one(doc! { "_id": booking_id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, PaymentStatus::Confirmed);
+    let first = booked(&client, seeded.first_activity, seeded.date).await.unwrap();
+    assert_eq!(first.get_i64("booked").or(first.get_i32("booked").map(i64::from)), Ok(2));
+    let broken = booked(&client, seeded.broken_activity, seeded.date).await.unwrap();
+    assert_eq!(broken.get_str("booked"), Ok("corrupt"));
+    assert!(!reconciliation_queued(&client, booking_id).await);
+
+    clean_up(&client, &seeded).await;
+}
@@ -1,34 +1,104 @@
-use actix_web::{web, App, HttpResponse, middleware::Logger, HttpRequest, Responder};
+//! Shared setup for the integration tests. The route suites run against the real
+//! routes from `build_app`. Checks that answer before any query (missing or bad
+//! tokens, request validation) run everywhere; tests that need MongoDB at
+//! `MONGODB_URI` return early when it isn't set.
+#![allow(dead_code)]
+
 use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::test::TestRequest;
+use actix_web::{web, App};
+use mongodb::bson::oid::ObjectId;
+use mongodb::options::{ClientOptions, ServerAddress};
 use std::sync::Arc;
-use tokio::time::{sleep, Duration};
+use std::time::Duration;
 
+use actota_api::build_app;
+use actota_api::config::AppConfig;
 use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::routes::payment::StripeConfig;
+use actota_api::services::api_token_service::ApiTokenRateLimiter;
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::location_autocomplete::LocationAutocomplete;
+use actota_api::services::security_event_service::SecurityEventQueue;
+use actota_api::services::write_behind::WriteBehindQueue;
 
 pub struct TestApp {
     pub client: Arc<mongodb::Client>,
+    pub config: AppConfig,
+}
+
+/// Where MongoDB is, or `None` when `MONGODB_URI` isn't set and the calling test
+/// should skip
+pub fn mongodb_uri() -> Option<String> {
+    let uri = std::env::var("MONGODB_URI").ok();
+    if uri.is_none() {
+        eprintln!("MONGODB_URI is not set; skipping a test that needs MongoDB");
+    }
+    uri
+}
+
+fn test_config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "GOOGLE_CLIENT_ID" | "FACEBOOK_CLIENT_ID" => Some("test_client_id".to_string()),
+        "GOOGLE_CLIENT_SECRET" | "FACEBOOK_CLIENT_SECRET" => Some("test_client_secret".to_string()),
+        "GOOGLE_REDIRECT_URI" => Some("http://localhost:8080/auth/google/callback".to_string()),
+        "FACEBOOK_REDIRECT_URI" => Some("http://localhost:8080/auth/facebook/callback".to_string()),
+        _ => None,
+    })
+    .unwrap()
 }
 
 impl TestApp {
-    pub async fn new() -> Self {
-        let mongo_uri = std::env::var("MONGODB_URI")
-            .unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
+    /// Against the database at `MONGODB_URI`, or `None` when it isn't set
+    pub async fn with_mongodb() -> Option<Self> {
+        let mongo_uri = mongodb_uri()?;
         let client = create_mongo_client(&mongo_uri).await;
+        Some(Self {
+            client,
+            config: test_config(&mongo_uri),
+        })
+    }
 
-        Self { client }
+    /// With a client that never connects, for requests answered before any query.
+    /// A request that does reach the database fails fast instead of waiting.
+    pub fn offline() -> Self {
+        let options = ClientOptions::builder()
+            .hosts(vec![ServerAddress::Tcp {
+                host: "localhost".to_string(),
+                port: Some(1),
+            }])
+            .server_selection_timeout(Duration::from_millis(200))
+            .build();
+        Self {
+            client: Arc::new(mongodb::Client::with_options(options).unwrap()),
+            config: test_config("mongodb://localhost:1"),
+        }
     }
 
+    /// The app `main` serves, with the same shared state but no background refreshes.
+    /// Errors come back as the responses the server would send, rather than
+    /// failing `test::call_service`.
     pub fn create_app(&self) -> App<
-        impl actix_web::dev::ServiceFactory<
-            actix_web::dev::ServiceRequest,
+        impl ServiceFactory<
+            ServiceRequest,
             Config = (),
-            Response = actix_web::dev::ServiceResponse,
+            Response = ServiceResponse<impl MessageBody>,
             Error = actix_web::Error,
             InitError = (),
         >,
     > {
-        App::new()
-            .app_data(web::Data::new(self.client.clone()))
+        let stripe_client = Arc::new(stripe::Client::new(self.config.stripe_secret_key.clone()));
+        build_app()
             .wrap(
                 Cors::default()
                     .allow_any_origin()
@@ -36,128 +106,43 @@ impl TestApp {
                     .allow_any_header()
                     .max_age(3600),
             )
-            .wrap(Logger::default())
-            .route("/", web::get().to(|| async { "ACTOTA API is running" }))
-            .route("/health", web::get().to(health_check))
-            .route("/locations", web::get().to(get_locations))
-            .route("/activities", web::get().to(get_activities))
-            .route("/lodging", web::get().to(get_lodging))
-            .route("/itineraries", web::get().to(get_itineraries))
-            .route("/itineraries/featured", web::get().to(get_featured_itineraries))
-            .route("/itineraries/search", web::post().to(search_itineraries))
-            .route("/itineraries/search-or-generate", web::post().to(search_or_generate))
-            .route("/itineraries/{id}", web::get().to(get_itinerary_by_id))
-            .service(
-                web::scope("/auth")
-                    .route("/signin", web::post().to(signin))
-                    .route("/signup", web::post().to(signup))
-                    .route("/google", web::get().to(google_oauth))
-                    .route("/facebook", web::get().to(facebook_oauth))
-                    .route("/session", web::get().to(unauthorized_handler))
-            )
-            .service(
-                web::scope("/account/{id}")
-                    .route("", web::get().to(unauthorized_handler))
-                    .route("", web::put().to(unauthorized_handler))
-                    .route("/favorites", web::get().to(unauthorized_handler))
-                    .route("/favorites/{itinerary_id}", web::post().to(unauthorized_handler))
-                    .route("/favorites/{itinerary_id}", web::delete().to(unauthorized_handler))
-                    .route("/bookings", web::get().to(unauthorized_handler))
-                    .route("/bookings/{booking_id}", web::get().to(unauthorized_handler))
-                    .route("/bookings/itinerary/{itinerary_id}", web::post().to(unauthorized_handler))
-                    .route("/bookings/itinerary/{itinerary_id}", web::delete().to(unauthorized_handler))
-                    .route("/payment-methods", web::get().to(unauthorized_handler))
-                    .route("/transactions", web::get().to(unauthorized_handler))
-                    .route("/email-verifications", web::post().to(unauthorized_handler))
-                    .route("/email-verifications", web::get().to(unauthorized_handler))
-                    .route("/email-verifications/{verification_id}", web::put().to(unauthorized_handler))
-            )
-            .service(
-                web::scope("/payment")
-                    .route("/payment-intent", web::post().to(unauthorized_handler))
-                    .route("/capture-payment", web::post().to(unauthorized_handler))
-            )
-            .service(
-                web::scope("/admin")
-                    .route("/users", web::get().to(unauthorized_handler))
-                    .route("/users/{id}/role", web::put().to(unauthorized_handler))
-                    .route("/itineraries/featured/add", web::post().to(unauthorized_handler))
-                    .route("/itineraries/{id}/images", web::put().to(unauthorized_handler))
-            )
-            .route("/itineraries/find", web::post().to(unauthorized_handler))
-            .route("/email-verifications", web::post().to(create_email_verification))
-            .route("/email-verifications/{id}", web::put().to(verify_email))
-            .route("/stripe/webhook", web::post().to(stripe_webhook))
+            .app_data(web::Data::new(stripe_client))
+            .app_data(web::Data::new(self.client.clone()))
+            .app_data(web::Data::new(self.config.clone()))
+            .app_data(web::Data::new(StripeConfig {
+                webhook_secret: self.config.stripe_webhook_secret.clone(),
+                max_event_age_hours: self.config.stripe_webhook_max_age_hours,
+            }))
+            .app_data(web::Data::new(AvailabilityCache::default()))
+            .app_data(web::Data::new(SecurityEventQueue::start(
+                self.client.clone(),
+                &self.config.email,
+            )))
+            .app_data(web::Data::new(WriteBehindQueue::start(self.client.clone())))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default()))
+            .app_data(web::Data::new(LocationAutocomplete::default()))
+            .app_data(web::Data::new(ApiTokenRateLimiter::new(
+                self.config.api_token_rate_limit_per_minute,
+            )))
+            // Routing needs the only handle on the request, so an error is answered
+            // against a stand-in; tests only look at the response
+            .wrap_fn(|req, srv| {
+                let response = srv.call(req);
+                async move {
+                    Ok(match response.await {
+                        Ok(response) => response.map_into_boxed_body(),
+                        Err(error) => ServiceResponse::from_err(error, TestRequest::default().to_http_request()),
+                    })
+                }
+            })
     }
 }
 
-// Mock handler functions for testing
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({"status": "OK"}))
-}
-
-async fn get_locations() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!([]))
-}
-
-async fn get_activities() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!([]))
-}
-
-async fn get_lodging() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!([]))
-}
-
-async fn get_itineraries() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!([]))
-}
-
-async fn get_featured_itineraries() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!([]))
-}
-
-async fn search_itineraries() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!([]))
-}
-
-async fn search_or_generate() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
-}
-
-async fn get_itinerary_by_id() -> impl Responder {
-    HttpResponse::NotFound().json(serde_json::json!({"error": "Itinerary not found"}))
-}
-
-async fn signin() -> impl Responder {
-    HttpResponse::Unauthorized().json(serde_json::json!({"error": "Invalid credentials"}))
-}
-
-async fn signup() -> impl Responder {
-    HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid input"}))
-}
-
-async fn google_oauth() -> impl Responder {
-    HttpResponse::Found().header("Location", "https://accounts.google.com/oauth").finish()
-}
-
-async fn facebook_oauth() -> impl Responder {
-    HttpResponse::Found().header("Location", "https://www.facebook.com/oauth").finish()
-}
-
-async fn create_email_verification() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({"status": "created"}))
-}
-
-async fn verify_email() -> impl Responder {
-    HttpResponse::NotFound().json(serde_json::json!({"error": "Verification not found"}))
-}
-
-async fn stripe_webhook() -> impl Responder {
-    HttpResponse::BadRequest().json(serde_json::json!({"error": "Invalid webhook"}))
-}
-
-async fn unauthorized_handler() -> impl Responder {
-    HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}))
+/// An `Authorization` header value for a fresh user signed with the test secret
+pub fn bearer_token(role: Option<&UserRole>) -> String {
+    let token = generate_token("test_secret", &get_test_email(), ObjectId::new(), role).unwrap();
+    format!("Bearer {}", token)
 }
 
 pub fn get_test_user_id() -> String {
@@ -189,13 +174,3 @@ pub async fn cleanup_test_data(client: &mongodb::Client) {
         ).await;
     }
 }
-
-pub async fn wait_for_server_ready(port: u16) {
-    for _ in 0..30 {
-        if let Ok(_) = reqwest::get(&format!("http://localhost:{}/health", port)).await {
-            return;
-        }
-        sleep(Duration::from_millis(100)).await;
-    }
-    panic!("Server failed to start within timeout");
-}
\ No newline at end of file
@@ -0,0 +1,147 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own itinerary and admin.
+
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::Collection;
+use serde_json::json;
+use serial_test::serial;
+use std::future::Future;
+use std::sync::Mutex;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::models::content_flag::{ContentFlag, ContentType, FlagReason, FlagResolution, FlagStatus, ReportInput};
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::services::content_flag_service::{
+    ContentFlagError, ContentFlagService, ModerationNotifier, ReportLimits,
+};
+
+/// Remembers which admins were alerted instead of emailing them
+#[derive(Default)]
+struct RecordedAlerts(Mutex<Vec<String>>);
+
+impl ModerationNotifier for RecordedAlerts {
+    fn send_alert(
+        &self,
+        admin_email: &str,
+        _content_title: &str,
+        _content_id: ObjectId,
+        _open_flags: u64,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        self.0.lock().unwrap().push(admin_email.to_string());
+        std::future::ready(Ok(()))
+    }
+}
+
+fn spam() -> ReportInput {
+    ReportInput {
+        reason: FlagReason::Spam,
+        details: Some("  Links to a reseller  ".to_string()),
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_reports_alert_admins_and_take_down_hides_itinerary() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries
+        .insert_one(FeaturedVacation {
+            trip_name: "Reported test trip".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let admin_email = format!("moderator-{}@example.com", ObjectId::new());
+    let admin: User =
+        serde_json::from_value(json!({ "email": admin_email, "password": "hashed", "role": "admin" })).unwrap();
+    let admin_id = users
+        .insert_one(&admin)
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let service = ContentFlagService::new(client.clone());
+    let limits = ReportLimits {
+        per_user_per_hour: 10,
+        notify_threshold: 2,
+    };
+    let alerts = RecordedAlerts::default();
+    let first_reporter = ObjectId::new();
+    let second_reporter = ObjectId::new();
+
+    let outcome = service
+        .report(first_reporter, ContentType::Itinerary, itinerary_id, spam(), &limits, &alerts)
+        .await
+        .unwrap();
+    assert_eq!(outcome.open_flags, 1);
+    assert_eq!(outcome.admins_notified, 0);
+
+    let again = service
+        .report(first_reporter, ContentType::Itinerary, itinerary_id, spam(), &limits, &alerts)
+        .await;
+    assert!(matches!(again, Err(ContentFlagError::AlreadyReported)));
+
+    let outcome = service
+        .report(second_reporter, ContentType::Itinerary, itinerary_id, spam(), &limits, &alerts)
+        .await
+        .unwrap();
+    assert_eq!(outcome.open_flags, 2);
+    assert_eq!(outcome.admins_notified, 1);
+    assert!(alerts.0.lock().unwrap().contains(&admin_email));
+
+    let queue = service.open_queue().await.unwrap();
+    let item = queue
+        .iter()
+        .find(|item| item.content_id == itinerary_id.to_hex())
+        .unwrap();
+    assert_eq!(item.open_flags, 2);
+    assert_eq!(item.title.as_deref(), Some("Reported test trip"));
+    assert_eq!(item.details, vec!["Links to a reseller", "Links to a reseller"]);
+
+    let resolved = service
+        .resolve(admin_id, ContentType::Itinerary, itinerary_id, FlagResolution::TakeDown, None)
+        .await
+        .unwrap();
+    assert_eq!(resolved.status, FlagStatus::TakenDown);
+    assert_eq!(resolved.flags_resolved, 2);
+
+    let hidden = itineraries
+        .find_one(doc! { "_id": itinerary_id, "taken_down_at": null })
+        .await
+        .unwrap();
+    assert!(hidden.is_none());
+
+    // Taken-down content can't be reported or resolved again
+    let late = service
+        .report(ObjectId::new(), ContentType::Itinerary, itinerary_id, spam(), &limits, &alerts)
+        .await;
+    assert!(matches!(late, Err(ContentFlagError::ContentNotFound)));
+    let twice = service
+        .resolve(admin_id, ContentType::Itinerary, itinerary_id, FlagResolution::Dismiss, None)
+        .await;
+    assert!(matches!(twice, Err(ContentFlagError::NothingToResolve)));
+
+    let flags: Collection<ContentFlag> = client.database("Account").collection("ContentFlags");
+    flags.delete_many(doc! { "content_id": itinerary_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("AdminAuditLog")
+        .delete_many(doc! { "content_id": itinerary_id })
+        .await
+        .unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    users.delete_one(doc! { "_id": admin_id }).await.unwrap();
+}
@@ -0,0 +1,158 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own activity and itinerary.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::activity::Activity;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::integrity_service::IntegrityService;
+
+#[actix_rt::test]
+#[serial]
+async fn test_itinerary_with_deleted_activity_is_listed_and_reported() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let activity: Activity = serde_json::from_value(json!({
+        "company": "Rocky Mountain Adventures",
+        "company_id": "rma",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": "Dangling reference test rafting",
+        "description": "",
+        "activity_types": [],
+        "tags": [],
+        "price_per_person": 100.0,
+        "duration_minutes": 120,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 10 },
+    }))
+    .unwrap();
+    let activities: Collection<Activity> = client.database("Options").collection("Activity");
+    let activity_id = activities
+        .insert_one(activity)
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+    let deleted_id = ObjectId::new();
+
+    let mut itinerary = FeaturedVacation {
+        trip_name: "Dangling reference test trip".to_string(),
+        length_days: 1,
+        created_at: Some(DateTime::now()),
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![
+                    DayItem::Activity {
+                        time: "09:00:00".to_string(),
+                        activity_id,
+                    },
+                    DayItem::Activity {
+                        time: "14:00:00".to_string(),
+                        activity_id: deleted_id,
+                    },
+                ],
+            )]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    itinerary.id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id();
+    let itinerary_id = itinerary.id.unwrap();
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default())),
+    )
+    .await;
+
+    // Still served, without the missing activity, and the warning shows in verbose mode
+    let req = test::TestRequest::get()
+        .uri(&format!("/itineraries/{}?verbose=true", itinerary_id.to_hex()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["days"][0]["items"].as_array().unwrap().len(), 1);
+    assert_eq!(body["population_warning_count"], 1);
+    assert_eq!(body["population_warnings"][0]["day"], "1");
+    assert_eq!(body["population_warnings"][0]["item_type"], "activity");
+    assert_eq!(body["population_warnings"][0]["missing_id"]["$oid"], deleted_id.to_hex());
+
+    let req = test::TestRequest::get().uri("/itineraries?verbose=true").to_request();
+    let listing: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let listed = listing
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["_id"]["$oid"] == itinerary_id.to_hex())
+        .expect("itinerary with a missing activity is still listed");
+    assert_eq!(listed["population_warning_count"], 1);
+
+    // Without verbose nothing about it is sent
+    let req = test::TestRequest::get()
+        .uri(&format!("/itineraries/{}", itinerary_id.to_hex()))
+        .to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(body.get("population_warnings").is_none());
+
+    // The integrity check finds it, and admins can read the report
+    let report = IntegrityService::new(client.clone()).run().await.unwrap();
+    let dangling = report
+        .itineraries
+        .iter()
+        .find(|dangling| dangling.itinerary_id == itinerary_id)
+        .unwrap();
+    assert_eq!(dangling.warnings.len(), 1);
+    assert_eq!(dangling.warnings[0].missing_id, deleted_id);
+
+    let token = generate_token("test_secret", "admin@example.com", ObjectId::new(), Some(&UserRole::Admin)).unwrap();
+    let req = test::TestRequest::get()
+        .uri("/admin/integrity/dangling-references")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["_id"]["$oid"], report.id.unwrap().to_hex());
+
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    activities.delete_one(doc! { "_id": activity_id }).await.unwrap();
+    client
+        .database("Options")
+        .collection::<Document>("IntegrityReports")
+        .delete_one(doc! { "_id": report.id.unwrap() })
+        .await
+        .unwrap();
+}
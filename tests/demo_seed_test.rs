@@ -0,0 +1,62 @@
+//! Run with `cargo test --features demo-tools --test demo_seed_test`
+#![cfg(feature = "demo-tools")]
+
+mod common;
+
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use serial_test::serial;
+
+use actota_api::config::DemoAccounts;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::activity::Activity;
+use actota_api::models::itinerary::base::{DayItem, FeaturedVacation};
+use actota_api::services::demo_seed_service::{seed_demo_data, SeedCounts};
+use actota_api::services::storage::StorageConfig;
+
+#[actix_rt::test]
+#[serial]
+async fn test_seed_is_idempotent_and_consistent() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let accounts = DemoAccounts::default();
+    let storage = StorageConfig::default();
+    seed_demo_data(client.clone(), &accounts, &storage).await.expect("First seed failed");
+    let second = seed_demo_data(client.clone(), &accounts, &storage).await.expect("Second seed failed");
+
+    // The second run only replaces what the first created
+    assert_eq!(second.activities, SeedCounts { created: 0, updated: 10 });
+    assert_eq!(second.itineraries, SeedCounts { created: 0, updated: 5 });
+    assert_eq!(second.users, SeedCounts { created: 0, updated: 2 });
+    assert_eq!(second.bookings.created, 0);
+    assert_eq!(second.favorites.created, 0);
+
+    let itineraries: Vec<FeaturedVacation> = client
+        .database("Itineraries")
+        .collection::<FeaturedVacation>("Featured")
+        .find(doc! { "tag": "demo" })
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(itineraries.len(), 5);
+
+    let activities = client.database("Options").collection::<Activity>("Activity");
+    for itinerary in itineraries {
+        for item in itinerary.days.days.values().flatten() {
+            if let DayItem::Activity { activity_id, .. } = item {
+                let activity = activities.find_one(doc! { "_id": activity_id }).await.unwrap();
+                assert!(
+                    activity.is_some(),
+                    "{} references missing activity {}",
+                    itinerary.trip_name,
+                    activity_id
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Needs MongoDB at `MONGODB_URI`. Seeds a season rule for a made-up town and
+//! removes it afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::write_behind::WriteBehindQueue;
+
+#[actix_rt::test]
+#[serial]
+async fn test_off_season_ski_search_gets_a_422() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let constraints = client
+        .database("Itineraries")
+        .collection::<Document>("DestinationConstraints");
+    constraints
+        .insert_one(doc! {
+            "city": "Powder Flats",
+            "state": "CO",
+            "activities": ["Skiing"],
+            "season_months": [12, 1, 2, 3, 4],
+            "min_nights": 3,
+        })
+        .await
+        .unwrap();
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MIN_SEARCH_RESULTS" => Some("0".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default()))
+            .app_data(web::Data::new(WriteBehindQueue::start(client.clone()))),
+    )
+    .await;
+
+    let search = |activity: &str, arrival: &str, departure: &str| {
+        test::TestRequest::post()
+            .uri("/itineraries/search")
+            .set_json(json!({
+                "locations": ["Powder Flats, CO"],
+                "activities": [activity],
+                "arrival_datetime": arrival,
+                "departure_datetime": departure,
+            }))
+            .to_request()
+    };
+
+    let response = test::call_service(&app, search("Skiing", "2031-07-10T00:00:00", "2031-07-15T00:00:00")).await;
+    assert_eq!(response.status(), 422);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(
+        body["error"],
+        "Trips to Powder Flats, CO are only offered December through April; pick dates in season"
+    );
+    assert_eq!(body["field"], "arrival_datetime");
+
+    let response = test::call_service(&app, search("Skiing", "2031-01-10T00:00:00", "2031-01-11T00:00:00")).await;
+    assert_eq!(response.status(), 422);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["limit"], 3);
+
+    // In season and long enough, or not a ski trip at all
+    let response = test::call_service(&app, search("Skiing", "2031-01-10T00:00:00", "2031-01-14T00:00:00")).await;
+    assert!(response.status().is_success(), "search failed: {}", response.status());
+    let response = test::call_service(&app, search("Hiking", "2031-07-10T00:00:00", "2031-07-15T00:00:00")).await;
+    assert!(response.status().is_success(), "search failed: {}", response.status());
+
+    constraints
+        .delete_many(doc! { "city": "Powder Flats" })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,151 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates and deletes its own user and email
+//! events.
+
+mod common;
+
+use actix_web::{test, web};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::{User, UserRole};
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::account_service::{EmailError, EmailService};
+use actota_api::services::email_suppression_service::{SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+/// DER header of a P-256 `SubjectPublicKeyInfo`, as SendGrid hands out the key
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86,
+    0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+fn sign(pair: &EcdsaKeyPair, timestamp: &str, payload: &[u8]) -> String {
+    let signed = [timestamp.as_bytes(), payload].concat();
+    BASE64.encode(pair.sign(&SystemRandom::new(), &signed).unwrap().as_ref())
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_bounce_suppresses_email_until_cleared() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // Stands in for the key pair SendGrid signs with
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+    let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+    let public_key = BASE64.encode([&P256_SPKI_PREFIX[..], pair.public_key().as_ref()].concat());
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "SENDGRID_WEBHOOK_PUBLIC_KEY" => Some(public_key.clone()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    let email = format!("bounce-{}@example.com", ObjectId::new().to_hex());
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let user: User = serde_json::from_value(json!({ "email": email, "password": "hashed" })).unwrap();
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let events_request = |payload: &[u8], signature: String| {
+        test::TestRequest::post()
+            .uri("/webhooks/sendgrid/events")
+            .insert_header((SIGNATURE_HEADER, signature))
+            .insert_header((TIMESTAMP_HEADER, "1700000000"))
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(payload.to_vec())
+            .to_request()
+    };
+    let bounce = serde_json::to_vec(&json!([{
+        "email": email,
+        "event": "bounce",
+        "type": "bounce",
+        "reason": "550 5.1.1 mailbox does not exist",
+        "timestamp": 1_700_000_000,
+        "category": "security",
+        "sg_event_id": format!("evt-{}", ObjectId::new().to_hex()),
+    }]))
+    .unwrap();
+
+    // A payload changed after signing is turned away and nothing is recorded
+    let signature = sign(&pair, "1700000000", b"[]");
+    let resp = test::call_service(&app, events_request(&bounce, signature)).await;
+    assert_eq!(resp.status(), 401);
+    let email_events = client.database("Account").collection::<Document>("EmailEvents");
+    assert_eq!(email_events.count_documents(doc! { "email": &email }).await.unwrap(), 0);
+
+    // The signed bounce is recorded and suppresses the address
+    let signature = sign(&pair, "1700000000", &bounce);
+    let resp = test::call_service(&app, events_request(&bounce, signature)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["recorded"], 1);
+    assert_eq!(body["suppressed"], json!([email]));
+    let stored = email_events.find_one(doc! { "email": &email }).await.unwrap().unwrap();
+    assert_eq!(stored.get_str("category").unwrap(), "security");
+
+    let (email_service, outbox) = EmailService::with_outbox(client.clone());
+    let result = email_service.send_new_device_signin_email(&email, None, DateTime::now()).await;
+    assert!(matches!(result, Err(EmailError::Suppressed(_))));
+    assert_eq!(outbox.sent_to(&email), 0);
+
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "ops@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/admin/email/suppressions")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let listed = body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|suppression| suppression["email"] == email.as_str())
+        .unwrap();
+    assert_eq!(listed["last_event"]["event"], "bounce");
+
+    // Clearing the suppression lets email through again
+    let clear = || {
+        test::TestRequest::delete()
+            .uri(&format!("/admin/email/suppressions/{}", email))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let resp = test::call_service(&app, clear()).await;
+    assert_eq!(resp.status(), 200);
+    email_service.send_new_device_signin_email(&email, None, DateTime::now()).await.unwrap();
+    assert_eq!(outbox.sent_to(&email), 1);
+    let audit_log = client.database("Account").collection::<Document>("AdminAuditLog");
+    assert_eq!(audit_log.count_documents(doc! { "admin_id": admin_id }).await.unwrap(), 1);
+
+    let resp = test::call_service(&app, clear()).await;
+    assert_eq!(resp.status(), 404);
+
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+    email_events.delete_many(doc! { "email": &email }).await.unwrap();
+    audit_log.delete_many(doc! { "admin_id": admin_id }).await.unwrap();
+}
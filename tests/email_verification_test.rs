@@ -0,0 +1,359 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own user and verification codes.
+
+mod common;
+
+use actix_web::{test, web};
+use futures::future::join_all;
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::{Client, Collection};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::future::Future;
+use std::sync::Mutex;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::account_service::{
+    EmailError, EmailService, EmailVerification, MAX_VERIFICATION_ATTEMPTS,
+};
+use actota_api::services::email_verification_service::{EmailVerificationService, VerificationSender};
+use actota_api::services::security_event_service::SecurityEventQueue;
+
+/// Remembers where codes went instead of emailing them
+#[derive(Default)]
+struct RecordedCodes(Mutex<Vec<String>>);
+
+impl VerificationSender for RecordedCodes {
+    fn send_code(
+        &self,
+        _client: &Client,
+        _user_id: ObjectId,
+        email: &str,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        self.0.lock().unwrap().push(email.to_string());
+        std::future::ready(Ok(()))
+    }
+}
+
+fn config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
+
+async fn insert_code(client: &Client, user_id: ObjectId, email: &str, code: &str) -> ObjectId {
+    let now = DateTime::now();
+    client
+        .database("actota")
+        .collection::<EmailVerification>("email_verifications")
+        .insert_one(EmailVerification {
+            id: None,
+            email: email.to_string(),
+            user_id: Some(user_id),
+            verification_code: code.to_string(),
+            expires_at: DateTime::from_millis(now.timestamp_millis() + 15 * 60 * 1000),
+            verified: false,
+            created_at: now,
+            attempts: 0,
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_verification_follows_the_accounts_email() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = config(&mongo_uri);
+
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let email = format!("verify-{}@example.com", ObjectId::new());
+    let user: User = serde_json::from_value(json!({ "email": email, "password": "hashed" })).unwrap();
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let token = generate_token(&config.jwt_secret, &email, user_id, None).unwrap();
+    let auth = ("Authorization", format!("Bearer {}", token));
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(SecurityEventQueue::start(client.clone(), &Default::default())))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let state_uri = format!("/account/{}/email-verifications", user_id);
+
+    let code = "K7Q2XZ";
+    let verification_id = insert_code(&client, user_id, &email, code).await;
+
+    // Pending codes are listed, never their value
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get().uri(&state_uri).insert_header(auth.clone()).to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    let body = test::read_body(response).await;
+    let text = String::from_utf8_lossy(&body);
+    assert!(!text.contains(code) && !text.contains("verification_code"), "code leaked: {}", text);
+    let state: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(state["email_verified"], json!(false));
+    assert_eq!(state["pending"]["id"], json!(verification_id.to_hex()));
+
+    // Verifying marks the account
+    let response = test::call_service(
+        &app,
+        test::TestRequest::put()
+            .uri(&format!("{}/{}", state_uri, verification_id))
+            .insert_header(auth.clone())
+            .set_json(json!({ "code": code }))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    let stored = users.find_one(doc! { "_id": user_id }).await.unwrap().unwrap();
+    assert!(stored.email_verified);
+    assert!(stored.email_verified_at.is_some());
+
+    // A code still outstanding for the old address stops counting once the email changes
+    let stale_id = insert_code(&client, user_id, &email, "OLD123").await;
+    let new_email = format!("moved-{}@example.com", ObjectId::new());
+    let response = test::call_service(
+        &app,
+        test::TestRequest::put()
+            .uri(&format!("/account/{}", user_id))
+            .insert_header(auth.clone())
+            .set_json(json!({ "email": new_email }))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    let stored = users.find_one(doc! { "_id": user_id }).await.unwrap().unwrap();
+    assert_eq!(stored.email, new_email);
+    assert!(!stored.email_verified);
+    assert!(stored.email_verified_at.is_none());
+
+    let response = test::call_service(
+        &app,
+        test::TestRequest::put()
+            .uri(&format!("{}/{}", state_uri, stale_id))
+            .insert_header(auth.clone())
+            .set_json(json!({ "code": "OLD123" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), 409);
+    let stored = users.find_one(doc! { "_id": user_id }).await.unwrap().unwrap();
+    assert!(!stored.email_verified);
+
+    // The new address gets a code
+    let codes = RecordedCodes::default();
+    let service = EmailVerificationService::new(client.clone());
+    assert!(service.email_changed(&stored, &codes).await);
+    assert_eq!(*codes.0.lock().unwrap(), vec![new_email.clone()]);
+
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get().uri(&state_uri).insert_header(auth).to_request(),
+    )
+    .await;
+    let body = test::read_body(response).await;
+    assert!(!String::from_utf8_lossy(&body).contains("OLD123"));
+    let state: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(state["email"], json!(new_email));
+    assert_eq!(state["email_verified"], json!(false));
+
+    client
+        .database("actota")
+        .collection::<EmailVerification>("email_verifications")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_signup_status_tracks_attempts_until_they_run_out() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config(&mongo_uri))),
+    )
+    .await;
+
+    let email = format!("signup-{}@example.com", ObjectId::new());
+    let now = DateTime::now();
+    let verifications: Collection<EmailVerification> =
+        client.database("actota").collection("email_verifications");
+    let verification_id = verifications
+        .insert_one(EmailVerification {
+            id: None,
+            email: email.clone(),
+            user_id: None,
+            verification_code: "R4T8PL".to_string(),
+            expires_at: DateTime::from_millis(now.timestamp_millis() + 15 * 60 * 1000),
+            verified: false,
+            created_at: now,
+            attempts: 0,
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+    let status = |id: ObjectId| {
+        test::TestRequest::get()
+            .uri(&format!("/email-verifications/{}/status", id))
+            .to_request()
+    };
+    let guess = |code: &str| {
+        test::TestRequest::put()
+            .uri(&format!("/email-verifications/{}", verification_id))
+            .set_json(json!({ "code": code }))
+            .to_request()
+    };
+
+    let body: Value = test::call_and_read_body_json(&app, status(verification_id)).await;
+    assert_eq!(body["verified"], json!(false));
+    assert_eq!(body["expired"], json!(false));
+    assert_eq!(body["attempts_remaining"], json!(MAX_VERIFICATION_ATTEMPTS));
+    assert!(body["seconds_until_expiry"].as_i64().unwrap() > 14 * 60);
+    assert!(body.get("verification_code").is_none());
+
+    for _ in 0..MAX_VERIFICATION_ATTEMPTS {
+        let response = test::call_service(&app, guess("WRONG1")).await;
+        assert_eq!(response.status(), 400);
+    }
+    let body: Value = test::call_and_read_body_json(&app, status(verification_id)).await;
+    assert_eq!(body["attempts_remaining"], json!(0));
+
+    // Out of attempts, even the right code needs a fresh one
+    let response = test::call_service(&app, guess("R4T8PL")).await;
+    assert_eq!(response.status(), 429);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], json!("too_many_attempts"));
+    assert!(body["retry_after_seconds"].as_i64().is_some());
+
+    let response = test::call_service(&app, status(ObjectId::new())).await;
+    assert_eq!(response.status(), 404);
+
+    verifications.delete_many(doc! { "email": &email }).await.unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_account_code_locks_after_the_configured_attempts() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "EMAIL_VERIFICATION_MAX_ATTEMPTS" => Some("3".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let email = format!("lockout-{}@example.com", ObjectId::new());
+    let user: User = serde_json::from_value(json!({ "email": email, "password": "hashed" })).unwrap();
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+    let verification_id = insert_code(&client, user_id, &email, "048213").await;
+    let token = generate_token("test_secret", &email, user_id, None).unwrap();
+    let guess = |code: &str| {
+        test::TestRequest::put()
+            .uri(&format!("/account/{}/email-verifications/{}", user_id, verification_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "code": code }))
+            .to_request()
+    };
+
+    for wrong in ["000000", "048212", "48213"] {
+        let response = test::call_service(&app, guess(wrong)).await;
+        assert_eq!(response.status(), 400);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["error"], json!("invalid_code"));
+    }
+
+    // The code is dead after three misses, right or not
+    let response = test::call_service(&app, guess("048213")).await;
+    assert_eq!(response.status(), 429);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], json!("too_many_attempts"));
+    let stored = users.find_one(doc! { "_id": user_id }).await.unwrap().unwrap();
+    assert!(!stored.email_verified);
+
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+    client
+        .database("actota")
+        .collection::<EmailVerification>("email_verifications")
+        .delete_many(doc! { "email": &email })
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_concurrent_guesses_cannot_get_past_the_lockout() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let email = format!("burst-{}@example.com", ObjectId::new());
+    let verification_id = insert_code(&client, ObjectId::new(), &email, "731904").await;
+
+    // Far more guesses than attempts, all in flight at once
+    let outcomes = join_all((0..20).map(|_| {
+        EmailService::verify_email_code(verification_id, "000000", MAX_VERIFICATION_ATTEMPTS, &client)
+    }))
+    .await;
+    let invalid = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, Err(EmailError::InvalidCode)))
+        .count();
+    let locked = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, Err(EmailError::TooManyAttempts { .. })))
+        .count();
+    assert_eq!((invalid, locked), (MAX_VERIFICATION_ATTEMPTS as usize, 20 - MAX_VERIFICATION_ATTEMPTS as usize));
+
+    // The right code comes too late
+    assert!(matches!(
+        EmailService::verify_email_code(verification_id, "731904", MAX_VERIFICATION_ATTEMPTS, &client).await,
+        Err(EmailError::TooManyAttempts { .. })
+    ));
+    let verifications: Collection<EmailVerification> =
+        client.database("actota").collection("email_verifications");
+    let stored = verifications.find_one(doc! { "_id": verification_id }).await.unwrap().unwrap();
+    assert_eq!(stored.attempts, MAX_VERIFICATION_ATTEMPTS);
+    assert!(!stored.verified);
+
+    verifications.delete_many(doc! { "email": &email }).await.unwrap();
+}
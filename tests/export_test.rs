@@ -0,0 +1,60 @@
+//! Needs MongoDB at `MONGODB_URI`. Seeds bookings dated in 2001 so no real
+//! booking falls in the exported range.
+
+mod common;
+
+use chrono::NaiveDate;
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serial_test::serial;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::services::export_service::{created_between, ExportService};
+use actota_api::services::streaming::{export_chunks, ExportFormat, ERROR_TRAILER};
+
+const ROWS: usize = 10_000;
+
+#[actix_rt::test]
+#[serial]
+async fn test_ten_thousand_bookings_stream_as_ndjson() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let day = NaiveDate::from_ymd_opt(2001, 2, 3).unwrap();
+    let created_at = DateTime::from_millis(day.and_hms_opt(12, 0, 0).unwrap().and_utc().timestamp_millis());
+    let bookings: Collection<Document> = client.database("Account").collection("Bookings");
+    bookings.delete_many(created_between(day, day)).await.unwrap();
+    let seeded: Vec<Document> = (0..ROWS)
+        .map(|i| {
+            doc! {
+                "user_id": ObjectId::new(),
+                "itinerary_id": ObjectId::new(),
+                "status": "confirmed",
+                "arrival_datetime": created_at,
+                "departure_datetime": created_at,
+                "gift_card_amount": i as i64,
+                "created_at": created_at,
+            }
+        })
+        .collect();
+    bookings.insert_many(seeded).await.unwrap();
+
+    let rows = ExportService::new(client.clone()).bookings(day, day).await.unwrap();
+    let mut chunks = Box::pin(export_chunks(rows, ExportFormat::Ndjson));
+    let (mut count, mut chunk_count, mut body_tail) = (0, 0, String::new());
+    while let Some(chunk) = chunks.next().await {
+        let chunk = String::from_utf8(chunk.unwrap().to_vec()).unwrap();
+        chunk_count += 1;
+        count += chunk.lines().count();
+        body_tail = chunk;
+    }
+
+    assert_eq!(count, ROWS);
+    assert!(chunk_count > 1);
+    assert!(!body_tail.contains(ERROR_TRAILER));
+
+    bookings.delete_many(created_between(day, day)).await.unwrap();
+}
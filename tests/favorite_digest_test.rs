@@ -0,0 +1,186 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own users and favorites.
+
+mod common;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::{Client, Collection};
+use serde_json::json;
+use serial_test::serial;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::{Favorite, User};
+use actota_api::models::money::Money;
+use actota_api::services::favorite_digest_service::{
+    DigestItem, DigestSender, FavoriteChange, FavoriteDigestService, FavoriteNotification,
+};
+
+/// Records digests instead of emailing them
+#[derive(Default)]
+struct RecordedDigests {
+    sent: Mutex<Vec<(String, Vec<DigestItem>)>>,
+}
+
+impl RecordedDigests {
+    fn to(&self, email: &str) -> Vec<Vec<DigestItem>> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(to, _)| to == email)
+            .map(|(_, items)| items.clone())
+            .collect()
+    }
+}
+
+impl DigestSender for RecordedDigests {
+    fn send_digest(
+        &self,
+        user_email: &str,
+        _first_name: Option<&str>,
+        items: &[DigestItem],
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        self.sent.lock().unwrap().push((user_email.to_string(), items.to_vec()));
+        std::future::ready(Ok(()))
+    }
+}
+
+async fn client() -> Option<Arc<Client>> {
+    let mongo_uri = common::mongodb_uri()?;
+    Some(create_mongo_client(&mongo_uri).await)
+}
+
+/// A user who favorited `itineraries`, with marketing email on or off
+async fn favoriting_user(client: &Client, marketing: bool, itineraries: &[ObjectId]) -> (ObjectId, String) {
+    let email = format!("digest-{}@example.com", ObjectId::new().to_hex());
+    let user: User = serde_json::from_value(json!({
+        "email": email,
+        "password": "hashed",
+        "first_name": "Dana",
+        "notification_preferences": { "email": { "marketing": marketing } },
+    }))
+    .unwrap();
+    let user_id = client
+        .database("Account")
+        .collection::<User>("Users")
+        .insert_one(&user)
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let favorites: Collection<Favorite> = client.database("Account").collection("Favorites");
+    for itinerary_id in itineraries {
+        favorites
+            .insert_one(Favorite {
+                id: None,
+                user_id,
+                itinerary_id: *itinerary_id,
+                last_notified_price: None,
+                created_at: None,
+                updated_at: None,
+            })
+            .await
+            .unwrap();
+    }
+    (user_id, email)
+}
+
+async fn cleanup(client: &Client, user_ids: &[ObjectId]) {
+    let account = client.database("Account");
+    let ids = doc! { "$in": user_ids };
+    account.collection::<User>("Users").delete_many(doc! { "_id": ids.clone() }).await.unwrap();
+    for collection in ["Favorites", "FavoriteNotifications", "FavoriteDigests"] {
+        account
+            .collection::<mongodb::bson::Document>(collection)
+            .delete_many(doc! { "user_id": ids.clone() })
+            .await
+            .unwrap();
+    }
+}
+
+fn drop_to(from: f64, to: f64) -> Vec<FavoriteChange> {
+    vec![FavoriteChange::PriceDrop {
+        from: Money::from_dollars(from),
+        to: Money::from_dollars(to),
+    }]
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_price_drop_queues_only_users_with_marketing_on() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let itinerary_id = ObjectId::new();
+    let (subscribed, _) = favoriting_user(&client, true, &[itinerary_id]).await;
+    let (unsubscribed, _) = favoriting_user(&client, false, &[itinerary_id]).await;
+
+    let service = FavoriteDigestService::new(client.clone());
+    let queued = service
+        .enqueue(itinerary_id, "Canyon Loop", drop_to(900.0, 800.0))
+        .await
+        .unwrap();
+    assert_eq!(queued, 1);
+
+    let notifications: Vec<FavoriteNotification> = client
+        .database("Account")
+        .collection::<FavoriteNotification>("FavoriteNotifications")
+        .find(doc! { "itinerary_id": itinerary_id })
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].user_id, subscribed);
+
+    cleanup(&client, &[subscribed, unsubscribed]).await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_digest_groups_favorites_and_is_sent_once_a_week() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let canyon = ObjectId::new();
+    let coast = ObjectId::new();
+    let (user_id, email) = favoriting_user(&client, true, &[canyon, coast]).await;
+
+    let service = FavoriteDigestService::new(client.clone());
+    service.enqueue(canyon, "Canyon Loop", drop_to(900.0, 800.0)).await.unwrap();
+    service.enqueue(coast, "Coast Drive", vec![FavoriteChange::ImagesUpdated]).await.unwrap();
+    service.enqueue(canyon, "Canyon Loop", drop_to(800.0, 750.0)).await.unwrap();
+
+    let sender = RecordedDigests::default();
+    service.send_digests(&sender).await.unwrap();
+
+    let digests = sender.to(&email);
+    assert_eq!(digests.len(), 1, "every change goes out in one email");
+    assert_eq!(digests[0].len(), 2);
+    let canyon_item = digests[0].iter().find(|item| item.itinerary_id == canyon).unwrap();
+    assert_eq!(
+        canyon_item.price,
+        Some((Money::from_dollars(900.0), Money::from_dollars(750.0)))
+    );
+
+    // A change after this week's digest waits for the next one
+    service.enqueue(coast, "Coast Drive", drop_to(600.0, 550.0)).await.unwrap();
+    let summary = service.send_digests(&sender).await.unwrap();
+    assert!(summary.deferred >= 1);
+    assert_eq!(sender.to(&email).len(), 1);
+
+    let pending = client
+        .database("Account")
+        .collection::<FavoriteNotification>("FavoriteNotifications")
+        .count_documents(doc! { "user_id": user_id, "digest_id": null })
+        .await
+        .unwrap();
+    assert_eq!(pending, 1);
+
+    cleanup(&client, &[user_id]).await;
+}
@@ -0,0 +1,139 @@
+//! Needs MongoDB at `MONGODB_URI`. Rewrites `Options.FeatureFlags` and removes it
+//! afterwards, so don't point it at a shared environment.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+
+#[actix_rt::test]
+#[serial]
+async fn test_admin_toggle_reaches_other_instances_on_refresh() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let stored: Collection<Document> = client.database("Options").collection("FeatureFlags");
+    stored.delete_many(doc! {}).await.unwrap();
+
+    // Without a document every flag keeps its default
+    let flags = Arc::new(Flags::default());
+    flags.refresh(&client).await.unwrap();
+    assert!(flags.search_debug());
+    assert!(flags.itinerary_generation());
+    assert!(flags.persist_generated_itineraries());
+
+    // An itinerary with a missing activity, so verbose mode has something to show
+    let mut itinerary = FeaturedVacation {
+        trip_name: "Feature flag test trip".to_string(),
+        length_days: 1,
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![DayItem::Activity {
+                    time: "09:00:00".to_string(),
+                    activity_id: ObjectId::new(),
+                }],
+            )]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    itinerary.id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id();
+    let verbose_uri = format!("/itineraries/{}?verbose=true", itinerary.id.unwrap().to_hex());
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::from(flags.clone())),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri(&verbose_uri).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["population_warning_count"], 1);
+
+    // Turn search debugging off as an admin
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "admin@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let bearer = ("Authorization", format!("Bearer {}", token));
+    let req = test::TestRequest::put()
+        .uri("/admin/feature-flags")
+        .insert_header(bearer.clone())
+        .set_json(json!({ "search_debug": false }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let search_debug = body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|flag| flag["name"] == "search_debug")
+        .unwrap();
+    assert_eq!(search_debug["enabled"], false);
+    assert_eq!(search_debug["overridden"], true);
+
+    // This instance stops honoring verbose at once
+    let req = test::TestRequest::get().uri(&verbose_uri).to_request();
+    let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(body.get("population_warning_count").is_none());
+
+    // Another instance sees it once it refreshes
+    let other_instance = Flags::default();
+    assert!(other_instance.search_debug());
+    other_instance.refresh(&client).await.unwrap();
+    assert!(!other_instance.search_debug());
+    assert!(other_instance.itinerary_generation());
+
+    // Unknown flags are refused by the endpoint and ignored in the document
+    let req = test::TestRequest::put()
+        .uri("/admin/feature-flags")
+        .insert_header(bearer.clone())
+        .set_json(json!({ "personalization_boost": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    stored
+        .update_one(doc! { "_id": "current" }, doc! { "$set": { "flags.personalization_boost": true } })
+        .await
+        .unwrap();
+    other_instance.refresh(&client).await.unwrap();
+    assert!(!other_instance.search_debug());
+
+    // The change was audited
+    let audit = client.database("Account").collection::<Document>("AdminAuditLog");
+    let entry = audit
+        .find_one(doc! { "admin_id": admin_id, "action": "feature_flags_updated" })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.get_document("changes").unwrap().get_bool("search_debug"), Ok(false));
+
+    audit.delete_many(doc! { "admin_id": admin_id }).await.unwrap();
+    stored.delete_many(doc! {}).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary.id.unwrap() }).await.unwrap();
+}
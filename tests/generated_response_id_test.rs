@@ -0,0 +1,126 @@
+//! Needs MongoDB at `MONGODB_URI`. Adds its own activity and removes it, along
+//! with the itineraries generated from it, afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::activity::Activity;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+
+fn activity(activity_type: &str) -> Activity {
+    serde_json::from_value(json!({
+        "company": "Rocky Mountain Adventures",
+        "company_id": "rma",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": "Response id test rafting",
+        "description": "",
+        "activity_types": [activity_type],
+        "tags": [],
+        "price_per_person": 50.0,
+        "duration_minutes": 120,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 10 },
+    }))
+    .unwrap()
+}
+
+/// The `_id` of every itinerary in a v1 search response
+fn response_ids(body: &Value) -> Vec<String> {
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["_id"]["$oid"].as_str().expect("ephemeral itinerary in response").to_string())
+        .collect()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_repeated_search_returns_the_persisted_generation() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // Cities and an activity type no other itinerary uses, so the first search
+    // of each city has nothing to find and generates
+    let tag = ObjectId::new().to_hex();
+    let activity_type = format!("response-id-test-{}", tag);
+    let cities = [format!("Zyq{}", &tag[16..]), format!("Zyqx{}", &tag[16..])];
+    let activities: Collection<Activity> = client.database("Options").collection("Activity");
+    let activity_id = activities
+        .insert_one(activity(&activity_type))
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MIN_SEARCH_RESULTS" => Some("1".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default())),
+    )
+    .await;
+    let search = |city: &str| {
+        test::TestRequest::post()
+            .uri("/itineraries/search-or-generate")
+            .set_json(json!({
+                "locations": [format!("{}, CO", city)],
+                "activities": [activity_type],
+                "arrival_datetime": "2031-07-10T00:00:00",
+                "departure_datetime": "2031-07-12T00:00:00",
+                "adults": 2,
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, search(&cities[0])).await;
+    assert_eq!(resp.status(), 200);
+    let generated = response_ids(&test::read_body_json(resp).await);
+    assert_eq!(generated.len(), 1);
+
+    // The same search finds the itinerary the first one saved, under the same id
+    let resp = test::call_service(&app, search(&cities[0])).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(response_ids(&test::read_body_json(resp).await), generated);
+
+    // A different search generates, and saves, an itinerary of its own
+    let resp = test::call_service(&app, search(&cities[1])).await;
+    assert_eq!(resp.status(), 200);
+    let other = response_ids(&test::read_body_json(resp).await);
+    assert_eq!(other.len(), 1);
+    assert_ne!(other, generated);
+
+    client
+        .database("Itineraries")
+        .collection::<Document>("Featured")
+        .delete_many(doc! {
+            "start_location.city": { "$regex": format!("^zyqx?{}$", &tag[16..]), "$options": "i" },
+        })
+        .await
+        .unwrap();
+    activities.delete_one(doc! { "_id": activity_id }).await.unwrap();
+}
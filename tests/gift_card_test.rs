@@ -0,0 +1,132 @@
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use serial_test::serial;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::gift_card::GiftCard;
+use actota_api::services::gift_card_service::GiftCardService;
+
+#[actix_rt::test]
+#[serial]
+async fn test_concurrent_redemptions_never_overspend() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let collection = client
+        .database("Account")
+        .collection::<GiftCard>("GiftCards");
+
+    let code = format!("TEST-RACE-{}", ObjectId::new().to_hex().to_uppercase());
+    let now = DateTime::now();
+    collection
+        .insert_one(&GiftCard {
+            id: None,
+            code: code.clone(),
+            initial_balance: 10_000,
+            remaining_balance: 10_000,
+            currency: "usd".to_string(),
+            expires_at: None,
+            active: true,
+            created_at: Some(now),
+            updated_at: Some(now),
+        })
+        .await
+        .expect("Failed to insert test gift card");
+
+    // Ten concurrent $30 redemptions against a $100 balance
+    let service = GiftCardService::new(client.clone());
+    let user_id = ObjectId::new();
+    let tasks: Vec<_> = (0..10)
+        .map(|_| {
+            let service = service.clone();
+            let code = code.clone();
+            tokio::spawn(async move { service.redeem(&code, 3_000, user_id, None).await })
+        })
+        .collect();
+
+    let results = futures::future::join_all(tasks).await;
+    let successes = results
+        .into_iter()
+        .filter(|result| matches!(result, Ok(Ok(_))))
+        .count();
+
+    let gift_card = collection
+        .find_one(doc! { "code": &code })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(successes, 3);
+    assert_eq!(gift_card.remaining_balance, 1_000);
+    assert!(gift_card.remaining_balance >= 0);
+
+    let _ = collection.delete_one(doc! { "code": &code }).await;
+    let _ = client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("GiftCardRedemptions")
+        .delete_many(doc! { "code": &code })
+        .await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_repeated_restore_for_a_booking_credits_once() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let collection = client
+        .database("Account")
+        .collection::<GiftCard>("GiftCards");
+
+    let code = format!("TEST-RESTORE-{}", ObjectId::new().to_hex().to_uppercase());
+    let now = DateTime::now();
+    collection
+        .insert_one(&GiftCard {
+            id: None,
+            code: code.clone(),
+            initial_balance: 10_000,
+            remaining_balance: 10_000,
+            currency: "usd".to_string(),
+            expires_at: None,
+            active: true,
+            created_at: Some(now),
+            updated_at: Some(now),
+        })
+        .await
+        .expect("Failed to insert test gift card");
+
+    let service = GiftCardService::new(client.clone());
+    let redemption = service
+        .redeem(&code, 4_000, ObjectId::new(), Some(ObjectId::new()))
+        .await
+        .unwrap();
+    let redemption_id = redemption.id.unwrap();
+
+    // A cancellation retried while the first one is still running, then once more
+    let (first, second) = futures::future::join(
+        service.restore(redemption_id, 4_000),
+        service.restore(redemption_id, 4_000),
+    )
+    .await;
+    let third = service.restore(redemption_id, 4_000).await;
+    let restored = first.unwrap() + second.unwrap() + third.unwrap();
+
+    let gift_card = collection
+        .find_one(doc! { "code": &code })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(restored, 4_000);
+    assert_eq!(gift_card.remaining_balance, 10_000);
+
+    let _ = collection.delete_one(doc! { "code": &code }).await;
+    let _ = client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("GiftCardRedemptions")
+        .delete_many(doc! { "code": &code })
+        .await;
+}
@@ -0,0 +1,113 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own user and session.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::services::impersonation_service::{ImpersonationError, ImpersonationService};
+
+fn config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_impersonation_token_is_limited_and_ends_at_once() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = config(&mongo_uri);
+    let secret = config.jwt_secret.clone();
+
+    let user: User = serde_json::from_value(json!({
+        "email": format!("impersonated-{}@example.com", ObjectId::new().to_hex()),
+        "password": "hashed",
+    }))
+    .unwrap();
+    let users = client.database("Account").collection::<User>("Users");
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+    let admin_id = ObjectId::new();
+
+    let service = ImpersonationService::new(client.clone());
+    let started = service
+        .start(&secret, admin_id, user_id, Some("Ticket 4411".to_string()), 30)
+        .await
+        .unwrap();
+    assert!(matches!(
+        service.start(&secret, user_id, user_id, None, 30).await,
+        Err(ImpersonationError::CannotImpersonateSelf)
+    ));
+
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(client.clone())),
+    )
+    .await;
+    let bearer = ("Authorization", format!("Bearer {}", started.token));
+
+    let req = test::TestRequest::post()
+        .uri("/payment/payment-intent")
+        .insert_header(bearer.clone())
+        .set_json(json!({}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "not_allowed_while_impersonating");
+
+    let req = test::TestRequest::post()
+        .uri("/auth/impersonation/end")
+        .insert_header(bearer.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // The token is still within its expiry but its session is over
+    let req = test::TestRequest::post()
+        .uri("/auth/impersonation/end")
+        .insert_header(bearer)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let audit = client.database("Account").collection::<Document>("AdminAuditLog");
+    let session_id = started.session.id;
+    let actions: Vec<String> = {
+        use futures::TryStreamExt;
+        audit
+            .find(doc! { "session_id": session_id })
+            .sort(doc! { "created_at": 1 })
+            .await
+            .unwrap()
+            .try_collect::<Vec<Document>>()
+            .await
+            .unwrap()
+            .iter()
+            .map(|entry| entry.get_str("action").unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(actions, vec!["impersonation_started", "impersonation_ended"]);
+
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+    audit.delete_many(doc! { "session_id": session_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("ImpersonationSessions")
+        .delete_one(doc! { "_id": session_id })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,263 @@
+//! Needs MongoDB 8.0+ at `MONGODB_URI` for `bulk_write`. Creates its own
+//! itineraries, tagged with a fresh tag, and removes them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::{Client, Collection};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::sync::Arc;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::itinerary_bulk_service::{BulkOperation, ItineraryBulkAudit};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+async fn client() -> Option<(Arc<Client>, AppConfig)> {
+    let mongo_uri = common::mongodb_uri()?;
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    Some((client, config))
+}
+
+async fn itinerary(itineraries: &Collection<FeaturedVacation>, tag: &str) -> ObjectId {
+    itineraries
+        .insert_one(FeaturedVacation {
+            trip_name: "Bulk test trip".to_string(),
+            tag: Some(tag.to_string()),
+            created_at: Some(DateTime::now()),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap()
+}
+
+async fn cleanup(client: &Client, tag: &str, admin_id: ObjectId) {
+    client
+        .database("Itineraries")
+        .collection::<Document>("Featured")
+        .delete_many(doc! { "tag": tag })
+        .await
+        .unwrap();
+    let account = client.database("Account");
+    account.collection::<Document>("AdminAuditLog").delete_many(doc! { "admin_id": admin_id }).await.unwrap();
+    account
+        .collection::<Document>("BulkItineraryDryRuns")
+        .delete_many(doc! { "admin_id": admin_id })
+        .await
+        .unwrap();
+}
+
+fn outcomes(body: &Value) -> Vec<(String, String)> {
+    body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            (
+                result["id"].as_str().unwrap().to_string(),
+                result["outcome"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_archive_batch_reports_each_outcome_and_is_audited_once() {
+    let Some((client, config)) = client().await else {
+        return;
+    };
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "admin@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let tag = format!("bulk-{}", ObjectId::new().to_hex());
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let free = itinerary(&itineraries, &tag).await;
+    let booked = itinerary(&itineraries, &tag).await;
+    let missing = ObjectId::new();
+
+    let bookings = client.database("Account").collection::<Document>("Bookings");
+    let arrival = DateTime::from_millis(DateTime::now().timestamp_millis() + 10 * DAY_MILLIS);
+    let booking_id = bookings
+        .insert_one(doc! {
+            "user_id": ObjectId::new(),
+            "itinerary_id": booked,
+            "status": "confirmed",
+            "arrival_datetime": arrival,
+            "departure_datetime": arrival,
+        })
+        .await
+        .unwrap()
+        .inserted_id;
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/admin/itineraries/bulk")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({
+                "ids": [free.to_hex(), booked.to_hex(), missing.to_hex()],
+                "operation": "archive"
+            }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        outcomes(&body),
+        vec![
+            (free.to_hex(), "applied".to_string()),
+            (booked.to_hex(), "skipped".to_string()),
+            (missing.to_hex(), "not_found".to_string()),
+        ]
+    );
+    assert_eq!(body["results"][1]["reason"], "Has confirmed bookings that haven't started");
+    assert_eq!((body["applied"].as_u64(), body["skipped"].as_u64(), body["not_found"].as_u64()), (Some(1), Some(1), Some(1)));
+
+    let stored = |id: ObjectId| {
+        let itineraries = itineraries.clone();
+        async move { itineraries.find_one(doc! { "_id": id }).await.unwrap().unwrap() }
+    };
+    assert!(stored(free).await.archived_at.is_some());
+    assert!(stored(booked).await.archived_at.is_none());
+
+    let audits: Vec<ItineraryBulkAudit> = {
+        use futures::TryStreamExt;
+        client
+            .database("Account")
+            .collection::<ItineraryBulkAudit>("AdminAuditLog")
+            .find(doc! { "admin_id": admin_id, "action": "itineraries_bulk_updated" })
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap()
+    };
+    assert_eq!(audits.len(), 1);
+    assert_eq!(audits[0].operation, BulkOperation::Archive);
+    assert_eq!(audits[0].itinerary_ids, vec![free, booked, missing]);
+    assert_eq!(audits[0].applied_ids, vec![free]);
+    assert!(audits[0].filter.is_none());
+
+    bookings.delete_one(doc! { "_id": booking_id }).await.unwrap();
+    cleanup(&client, &tag, admin_id).await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_filter_needs_its_dry_run_token_to_execute() {
+    let Some((client, config)) = client().await else {
+        return;
+    };
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "admin@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let tag = format!("bulk-{}", ObjectId::new().to_hex());
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let first = itinerary(&itineraries, &tag).await;
+    let second = itinerary(&itineraries, &tag).await;
+    let bulk = |body: Value| {
+        test::TestRequest::post()
+            .uri("/admin/itineraries/bulk")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request()
+    };
+    let request = json!({
+        "filter": { "tag": tag },
+        "operation": "add_tags",
+        "params": { "tags": ["ski"] }
+    });
+
+    // Executing a filter straight away is refused and changes nothing
+    let resp = test::call_service(&app, bulk(request.clone())).await;
+    assert_eq!(resp.status(), 400);
+    assert!(itineraries
+        .find_one(doc! { "_id": first })
+        .await
+        .unwrap()
+        .unwrap()
+        .tags
+        .is_empty());
+
+    let mut dry_run = request.clone();
+    dry_run["dry_run"] = json!(true);
+    let resp = test::call_service(&app, bulk(dry_run)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["dry_run"], true);
+    assert_eq!(body["applied"], 2);
+    let dry_run_token = body["dry_run_token"].as_str().unwrap().to_string();
+
+    // Added after the dry run, so it isn't part of what was previewed
+    let later = itinerary(&itineraries, &tag).await;
+
+    // The token only executes the same request
+    let mut other_operation = request.clone();
+    other_operation["operation"] = json!("remove_tags");
+    other_operation["dry_run_token"] = json!(dry_run_token);
+    let resp = test::call_service(&app, bulk(other_operation)).await;
+    assert_eq!(resp.status(), 409);
+
+    let mut execute = request.clone();
+    execute["dry_run_token"] = json!(dry_run_token);
+    let resp = test::call_service(&app, bulk(execute.clone())).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        outcomes(&body),
+        vec![(first.to_hex(), "applied".to_string()), (second.to_hex(), "applied".to_string())]
+    );
+    for (id, tags) in [(first, vec!["ski"]), (second, vec!["ski"]), (later, vec![])] {
+        let stored = itineraries.find_one(doc! { "_id": id }).await.unwrap().unwrap();
+        assert_eq!(stored.tags, tags);
+    }
+
+    // And only once
+    let resp = test::call_service(&app, bulk(execute)).await;
+    assert_eq!(resp.status(), 404);
+
+    let audit = client
+        .database("Account")
+        .collection::<ItineraryBulkAudit>("AdminAuditLog")
+        .find_one(doc! { "admin_id": admin_id, "action": "itineraries_bulk_updated" })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(audit.filter.and_then(|filter| filter.tag), Some(tag.clone()));
+    assert_eq!(audit.params.tags, vec!["ski"]);
+    assert_eq!(audit.applied_ids, vec![first, second]);
+
+    cleanup(&client, &tag, admin_id).await;
+}
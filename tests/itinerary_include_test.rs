@@ -0,0 +1,136 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own activity, lodging and itinerary.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+
+#[actix_rt::test]
+#[serial]
+async fn test_included_sections_match_the_full_response() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let activities = client.database("Options").collection::<Document>("Activity");
+    let activity_id = ObjectId::new();
+    activities
+        .insert_one(doc! {
+            "_id": activity_id,
+            "company": "Rocky Mountain Adventures",
+            "company_id": "rma",
+            "booking_link": "",
+            "online_booking_status": "available",
+            "title": "Include test rafting",
+            "description": "",
+            "activity_types": [],
+            "tags": [],
+            "price_per_person": 100.0,
+            "duration_minutes": 120,
+            "daily_time_slots": [],
+            "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+            "whats_included": [],
+            "capacity": { "minimum": 1, "maximum": 10 },
+        })
+        .await
+        .unwrap();
+    let lodging = client.database("Options").collection::<Document>("Lodging");
+    let lodging_id = ObjectId::new();
+    lodging
+        .insert_one(doc! { "_id": lodging_id, "name": "Include test lodge", "price_per_night": 150.0 })
+        .await
+        .unwrap();
+
+    let itinerary = FeaturedVacation {
+        trip_name: "Include test trip".to_string(),
+        length_days: 1,
+        images: Some(vec!["https://example.com/hero.jpg".to_string()]),
+        created_at: Some(DateTime::now()),
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![
+                    DayItem::Activity { time: "09:00:00".to_string(), activity_id },
+                    DayItem::Accommodation { time: "18:00:00".to_string(), accommodation_id: lodging_id },
+                ],
+            )]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default())),
+    )
+    .await;
+    let get = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/itineraries/{}{}", itinerary_id.to_hex(), query))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, get("")).await;
+    assert_eq!(resp.status(), 200);
+    let full: Value = test::read_body_json(resp).await;
+    assert_eq!(full["lodging"][0]["name"], "Include test lodge");
+    assert_eq!(full["stats"], json!({ "days": 1, "activities": 1, "accommodations": 1, "transportation": 0 }));
+
+    let core = ["_id", "trip_name", "min_group", "max_group", "length_days", "length_hours", "description"];
+    for (include, fields) in [
+        ("images", vec!["images"]),
+        ("days,activities", vec!["days", "activities"]),
+        ("lodging", vec!["lodging"]),
+        ("pricing", vec!["person_cost", "activity_cost", "lodging_cost", "service_fee"]),
+        ("map,stats", vec!["start_location", "end_location", "stats"]),
+    ] {
+        let resp = test::call_service(&app, get(&format!("?include={}", include))).await;
+        assert_eq!(resp.status(), 200, "include={}", include);
+        let partial: Value = test::read_body_json(resp).await;
+        for field in core.iter().chain(fields.iter()) {
+            assert_eq!(partial[field], full[field], "include={} field {}", include, field);
+        }
+        for section in ["images", "days", "activities", "lodging", "person_cost", "start_location", "stats"] {
+            if !fields.contains(&section) {
+                assert!(partial.get(section).is_none(), "include={} sent {}", include, section);
+            }
+        }
+    }
+
+    let resp = test::call_service(&app, get("?include=days,reviews")).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Unknown sections: reviews");
+    assert_eq!(
+        body["valid_sections"],
+        json!(["days", "activities", "images", "lodging", "pricing", "map", "stats"])
+    );
+
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    activities.delete_one(doc! { "_id": activity_id }).await.unwrap();
+    lodging.delete_one(doc! { "_id": lodging_id }).await.unwrap();
+}
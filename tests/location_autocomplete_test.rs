@@ -0,0 +1,48 @@
+//! Needs MongoDB at `MONGODB_URI`. Adds and removes its own itinerary.
+
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::Collection;
+use serial_test::serial;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::services::location_autocomplete::LocationAutocomplete;
+
+#[actix_rt::test]
+#[serial]
+async fn test_refresh_picks_up_a_new_itinerary_city() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // A made-up city no other data will share
+    let city = format!("Zyqtown {}", ObjectId::new().to_hex());
+    let autocomplete = LocationAutocomplete::default();
+    autocomplete.refresh(&client).await.unwrap();
+    assert!(autocomplete.search(&city, 8).is_empty());
+
+    let itineraries: Collection<Document> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries
+        .insert_one(doc! {
+            "trip_name": "Autocomplete test trip",
+            "start_location": { "city": &city, "state": "MT", "coordinates": [-110.5, 45.2] },
+            "end_location": { "city": &city, "state": "MT", "coordinates": [-110.5, 45.2] },
+        })
+        .await
+        .unwrap()
+        .inserted_id;
+
+    // Searches keep using the old index until the next refresh
+    assert!(autocomplete.search("zyqtown", 8).iter().all(|s| s.city != city));
+    autocomplete.refresh(&client).await.unwrap();
+
+    let suggestions = autocomplete.search("ZYQTOWN", 25);
+    let found = suggestions.iter().find(|s| s.city == city).expect("new city is suggested");
+    assert_eq!(found.state, "MT");
+    assert_eq!(found.itinerary_count, 1);
+    assert_eq!(found.coordinates, Some((-110.5, 45.2)));
+
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+}
@@ -0,0 +1,189 @@
+//! Needs MongoDB at `MONGODB_URI`.
+
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Client;
+use serial_test::serial;
+use std::sync::Arc;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::services::oauth_link_service::{
+    oauth_signin, LinkProof, OAuthLinkError, OAuthLinkService, OAuthSignin, MAX_LINK_ATTEMPTS,
+};
+
+async fn client() -> Option<Arc<Client>> {
+    let mongo_uri = common::mongodb_uri()?;
+    Some(create_mongo_client(&mongo_uri).await)
+}
+
+/// A password account with a booking, as someone who signed up with email would have
+async fn password_account(client: &Client, email: &str, password: &str) -> (ObjectId, ObjectId) {
+    let user_id = ObjectId::new();
+    client
+        .database("Account")
+        .collection::<Document>("Users")
+        .insert_one(doc! {
+            "_id": user_id,
+            "email": email,
+            "password": bcrypt::hash(password, 4).unwrap(),
+            "role": "user",
+            "failed_signins": 0,
+        })
+        .await
+        .unwrap();
+    let booking_id = ObjectId::new();
+    client
+        .database("Account")
+        .collection::<Document>("Bookings")
+        .insert_one(doc! { "_id": booking_id, "user_id": user_id, "status": "confirmed" })
+        .await
+        .unwrap();
+    (user_id, booking_id)
+}
+
+async fn cleanup(client: &Client, user_id: ObjectId) {
+    let account = client.database("Account");
+    account.collection::<Document>("Users").delete_one(doc! { "_id": user_id }).await.unwrap();
+    account.collection::<Document>("Bookings").delete_many(doc! { "user_id": user_id }).await.unwrap();
+    account
+        .collection::<Document>("PendingOAuthLinks")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
+
+fn unique_email() -> String {
+    format!("Traveler.{}@Example.com", ObjectId::new().to_hex())
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_callback_detects_the_existing_password_account() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let email = unique_email();
+    let (user_id, _) = password_account(&client, &email, "correct horse").await;
+    let service = OAuthLinkService::new(client.clone());
+
+    // The provider reports the address in another case
+    let existing = service.find_user_by_email(&email.to_uppercase()).await.unwrap();
+    assert_eq!(existing.as_ref().and_then(|user| user.id), Some(user_id));
+    assert_eq!(
+        oauth_signin(existing.as_ref(), "google", "g-123"),
+        OAuthSignin::RequireLink
+    );
+
+    cleanup(&client, user_id).await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_correct_password_links_the_account_and_keeps_its_bookings() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let email = unique_email();
+    let (user_id, booking_id) = password_account(&client, &email, "correct horse").await;
+    let service = OAuthLinkService::new(client.clone());
+
+    let user = service.find_user_by_email(&email).await.unwrap().unwrap();
+    let token = service.start(&user, "google", "g-123", DateTime::now()).await.unwrap();
+    let link = service.pending(&token, DateTime::now()).await.unwrap();
+    let signed_in = service
+        .complete(&link, &LinkProof::Password("correct horse".to_string()), 5)
+        .await
+        .unwrap();
+
+    assert_eq!(signed_in.id, Some(user_id));
+    let linked: User = client
+        .database("Account")
+        .collection::<User>("Users")
+        .find_one(doc! { "_id": user_id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(linked.is_linked("google", "g-123"));
+    assert_eq!(oauth_signin(Some(&linked), "google", "g-123"), OAuthSignin::SignIn { link: false });
+
+    // Still the same account, so its bookings are still its own
+    let booking = client
+        .database("Account")
+        .collection::<Document>("Bookings")
+        .find_one(doc! { "_id": booking_id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(booking.get_object_id("user_id").unwrap(), user_id);
+
+    // The token can't be used again
+    assert_eq!(
+        service.pending(&token, DateTime::now()).await.unwrap_err(),
+        OAuthLinkError::LinkNotFound
+    );
+
+    cleanup(&client, user_id).await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_wrong_passwords_are_limited() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let email = unique_email();
+    let (user_id, _) = password_account(&client, &email, "correct horse").await;
+    let service = OAuthLinkService::new(client.clone());
+
+    let user = service.find_user_by_email(&email).await.unwrap().unwrap();
+    let token = service.start(&user, "facebook", "f-123", DateTime::now()).await.unwrap();
+    let wrong = LinkProof::Password("battery staple".to_string());
+
+    for attempt in 1..=MAX_LINK_ATTEMPTS {
+        let link = service.pending(&token, DateTime::now()).await.unwrap();
+        assert_eq!(
+            service.complete(&link, &wrong, 5).await.unwrap_err(),
+            OAuthLinkError::InvalidProof { attempts_remaining: MAX_LINK_ATTEMPTS - attempt }
+        );
+    }
+
+    // Out of attempts, even the right password is refused
+    assert_eq!(
+        service.pending(&token, DateTime::now()).await.unwrap_err(),
+        OAuthLinkError::TooManyAttempts
+    );
+    let linked: User = client
+        .database("Account")
+        .collection::<User>("Users")
+        .find_one(doc! { "_id": user_id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(linked.linked_accounts.is_empty());
+
+    cleanup(&client, user_id).await;
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_expired_link_is_refused() {
+    let Some(client) = client().await else {
+        return;
+    };
+    let email = unique_email();
+    let (user_id, _) = password_account(&client, &email, "correct horse").await;
+    let service = OAuthLinkService::new(client.clone());
+
+    let user = service.find_user_by_email(&email).await.unwrap().unwrap();
+    let token = service.start(&user, "google", "g-123", DateTime::now()).await.unwrap();
+    let later = DateTime::from_millis(DateTime::now().timestamp_millis() + 11 * 60 * 1000);
+
+    assert_eq!(
+        service.pending(&token, later).await.unwrap_err(),
+        OAuthLinkError::LinkNotFound
+    );
+
+    cleanup(&client, user_id).await;
+}
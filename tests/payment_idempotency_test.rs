@@ -0,0 +1,136 @@
+//! Needs MongoDB at `MONGODB_URI`, except for the malformed-key check. Stripe is
+//! replaced by a mock counting the intents it creates.
+
+mod common;
+
+use mongodb::bson::DateTime;
+use serial_test::serial;
+use std::sync::Mutex;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::services::payment_idempotency::{
+    IdempotencyError, IntentCreator, PaymentIdempotencyService, KEY_TTL_HOURS,
+};
+
+/// Hands out a new intent for every call, recording the idempotency key sent
+#[derive(Default)]
+struct MockStripe {
+    calls: Mutex<Vec<Option<String>>>,
+}
+
+impl MockStripe {
+    fn calls(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+}
+
+impl IntentCreator for MockStripe {
+    async fn create_intent(
+        &self,
+        params: stripe::CreatePaymentIntent<'_>,
+        idempotency_key: Option<&str>,
+    ) -> Result<stripe::PaymentIntent, String> {
+        let mut calls = self.calls.lock().unwrap();
+        calls.push(idempotency_key.map(str::to_string));
+        let id = format!("pi_mock{}_{}", calls.len(), chrono::Utc::now().timestamp_millis());
+        Ok(stripe::PaymentIntent {
+            id: id.parse().unwrap(),
+            amount: params.amount,
+            client_secret: Some(format!("{}_secret_mock", id)),
+            ..Default::default()
+        })
+    }
+}
+
+async fn service() -> Option<PaymentIdempotencyService> {
+    let mongo_uri = common::mongodb_uri()?;
+    Some(PaymentIdempotencyService::new(create_mongo_client(&mongo_uri).await))
+}
+
+fn params<'a>(amount: i64) -> stripe::CreatePaymentIntent<'a> {
+    stripe::CreatePaymentIntent::new(amount, stripe::Currency::USD)
+}
+
+fn unique_key() -> String {
+    format!("test-key-{}", uuid::Uuid::new_v4())
+}
+
+/// Create an intent the way the handler does: replay first, create on a miss
+async fn submit(
+    service: &PaymentIdempotencyService,
+    stripe: &MockStripe,
+    user_id: &str,
+    key: &str,
+    now: DateTime,
+) -> Result<serde_json::Value, IdempotencyError> {
+    if let Some(intent) = service.replay(user_id, key, 125_000, now).await? {
+        return Ok(intent);
+    }
+    service
+        .create(stripe, user_id, Some(key), 125_000, params(125_000), now)
+        .await
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_duplicate_submit_returns_the_same_intent() {
+    let Some(service) = service().await else {
+        return;
+    };
+    let stripe = MockStripe::default();
+    let key = unique_key();
+    let now = DateTime::now();
+
+    let first = submit(&service, &stripe, "user_a", &key, now).await.unwrap();
+    let second = submit(&service, &stripe, "user_a", &key, now).await.unwrap();
+
+    assert_eq!(first["id"], second["id"]);
+    assert_eq!(second["client_secret"], first["client_secret"]);
+    assert_eq!(stripe.calls(), 1);
+    // Stripe sees the key scoped to the user
+    assert_eq!(stripe.calls.lock().unwrap()[0], Some(format!("user_a:{}", key)));
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_key_replayed_by_another_user_is_rejected() {
+    let Some(service) = service().await else {
+        return;
+    };
+    let stripe = MockStripe::default();
+    let key = unique_key();
+    let now = DateTime::now();
+
+    submit(&service, &stripe, "user_a", &key, now).await.unwrap();
+    let replayed = submit(&service, &stripe, "user_b", &key, now).await;
+
+    assert_eq!(replayed, Err(IdempotencyError::KeyInUse));
+    assert_eq!(stripe.calls(), 1);
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_expired_key_creates_a_fresh_intent() {
+    let Some(service) = service().await else {
+        return;
+    };
+    let stripe = MockStripe::default();
+    let key = unique_key();
+    let now = DateTime::now();
+    let later = DateTime::from_millis(now.timestamp_millis() + (KEY_TTL_HOURS * 60 + 1) * 60 * 1000);
+
+    let first = submit(&service, &stripe, "user_a", &key, now).await.unwrap();
+    let second = submit(&service, &stripe, "user_a", &key, later).await.unwrap();
+
+    assert_ne!(first["id"], second["id"]);
+    assert_eq!(stripe.calls(), 2);
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_malformed_key_is_rejected_before_stripe() {
+    // Refused before the lookup, so no database is needed
+    let service = PaymentIdempotencyService::new(common::TestApp::offline().client);
+    let replayed = service.replay("user_a", "not a key", 125_000, DateTime::now()).await;
+    assert_eq!(replayed, Err(IdempotencyError::InvalidKey));
+}
@@ -0,0 +1,152 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own activity and itineraries.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::{Client, Collection};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::activity::Activity;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::models::money::Money;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::pricing_service::{PersonPrice, PricingService};
+
+fn config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
+
+fn activity(price: f32) -> Activity {
+    serde_json::from_value(json!({
+        "company": "Rocky Mountain Adventures",
+        "company_id": "rma",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": "Person cost test rafting",
+        "description": "",
+        "activity_types": [],
+        "tags": [],
+        "price_per_person": price,
+        "duration_minutes": 120,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 10 },
+    }))
+    .unwrap()
+}
+
+/// An itinerary with no stored price that schedules `activity_ids` on day one
+async fn unpriced_itinerary(client: &Client, activity_ids: &[ObjectId]) -> FeaturedVacation {
+    let items = activity_ids
+        .iter()
+        .map(|id| DayItem::Activity {
+            time: "09:00:00".to_string(),
+            activity_id: *id,
+        })
+        .collect();
+    let mut itinerary = FeaturedVacation {
+        trip_name: "Person cost test trip".to_string(),
+        days: Days {
+            days: HashMap::from([("1".to_string(), items)]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    itinerary.id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id();
+    itinerary
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_missing_price_is_estimated_or_refused_at_checkout() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let activities: Collection<Activity> = client.database("Options").collection("Activity");
+    let activity_id = activities
+        .insert_one(activity(120.0))
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let estimable = unpriced_itinerary(&client, &[activity_id, activity_id]).await;
+    let unestimable = unpriced_itinerary(&client, &[activity_id, ObjectId::new()]).await;
+
+    let price = PricingService::person_price(&client, &estimable).await.unwrap();
+    assert_eq!(price, PersonPrice::Estimated(Money::from_dollars(240.0)));
+    let price = PricingService::person_price(&client, &unestimable).await.unwrap();
+    assert_eq!(price, PersonPrice::Unavailable);
+
+    // Checkout stops before Stripe is called
+    let config = config(&mongo_uri);
+    let secret = config.jwt_secret.clone();
+    let user_id = ObjectId::new();
+    let token = generate_token(&secret, "traveler@example.com", user_id, None).unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(Arc::new(stripe::Client::new("sk_test_unused")))),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/payment/payment-intent")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({
+            "user_id": user_id.to_hex(),
+            "amount": 0,
+            "customer_id": "cus_test",
+            "payment_method_id": "pm_test",
+            "description": "Person cost test trip",
+            "itinerary_id": unestimable.id.unwrap().to_hex(),
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "price_unavailable");
+
+    // An estimated price is what's charged; claiming less is refused
+    let req = test::TestRequest::post()
+        .uri("/payment/payment-intent")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({
+            "user_id": user_id.to_hex(),
+            "amount": 100,
+            "customer_id": "cus_test",
+            "payment_method_id": "pm_test",
+            "description": "Person cost test trip",
+            "itinerary_id": estimable.id.unwrap().to_hex(),
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "amount_mismatch");
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    itineraries
+        .delete_many(doc! { "_id": { "$in": [estimable.id.unwrap(), unestimable.id.unwrap()] } })
+        .await
+        .unwrap();
+    activities.delete_one(doc! { "_id": activity_id }).await.unwrap();
+}
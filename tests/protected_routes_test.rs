@@ -1,21 +1,15 @@
 mod common;
 
-use actix_web::{test, http::header};
+use actix_web::test;
 use serde_json::json;
 use serial_test::serial;
 
-use common::{TestApp, get_test_user_id, get_test_email, cleanup_test_data};
-
-async fn create_test_jwt_token() -> String {
-    // In a real implementation, this would create a valid JWT token
-    // For testing purposes, we'll use a mock token
-    "Bearer test_jwt_token".to_string()
-}
+use common::{TestApp, get_test_user_id, cleanup_test_data};
 
 #[actix_rt::test]
 #[serial]
 async fn test_get_session_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
 
     let req = test::TestRequest::get()
@@ -29,7 +23,7 @@ async fn test_get_session_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_account_info_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -45,14 +39,14 @@ async fn test_get_account_info_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_update_account_info_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
 
     let req = test::TestRequest::put()
         .uri(&format!("/account/{}", user_id))
-        .set_json(&json!({
+        .set_json(json!({
             "first_name": "Updated",
             "last_name": "Name"
         }))
@@ -65,7 +59,7 @@ async fn test_update_account_info_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_upload_profile_picture_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -81,7 +75,7 @@ async fn test_upload_profile_picture_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_favorites_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -97,7 +91,7 @@ async fn test_get_favorites_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_add_favorite_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -114,7 +108,7 @@ async fn test_add_favorite_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_remove_favorite_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -131,7 +125,7 @@ async fn test_remove_favorite_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_all_bookings_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -147,7 +141,7 @@ async fn test_get_all_bookings_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_booking_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -164,7 +158,7 @@ async fn test_get_booking_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_add_booking_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -172,7 +166,7 @@ async fn test_add_booking_without_auth() {
 
     let req = test::TestRequest::post()
         .uri(&format!("/account/{}/bookings/itinerary/{}", user_id, itinerary_id))
-        .set_json(&json!({
+        .set_json(json!({
             "booking_date": "2024-12-25",
             "travelers": 2
         }))
@@ -185,7 +179,7 @@ async fn test_add_booking_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_remove_booking_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -202,7 +196,7 @@ async fn test_remove_booking_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_cancel_booking_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -219,7 +213,7 @@ async fn test_cancel_booking_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_payment_methods_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -235,14 +229,14 @@ async fn test_get_payment_methods_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_create_payment_method_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
 
     let req = test::TestRequest::post()
         .uri(&format!("/account/{}/payment-methods", user_id))
-        .set_json(&json!({
+        .set_json(json!({
             "payment_method_id": "pm_test_123"
         }))
         .to_request();
@@ -254,7 +248,7 @@ async fn test_create_payment_method_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_delete_payment_method_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -271,7 +265,7 @@ async fn test_delete_payment_method_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_transactions_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -287,14 +281,14 @@ async fn test_get_transactions_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_create_user_email_verification_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
 
     let req = test::TestRequest::post()
         .uri(&format!("/account/{}/email-verifications", user_id))
-        .set_json(&json!({
+        .set_json(json!({
             "email": "test@example.com"
         }))
         .to_request();
@@ -306,7 +300,7 @@ async fn test_create_user_email_verification_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_user_email_verifications_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -322,7 +316,7 @@ async fn test_get_user_email_verifications_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_verify_user_email_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -330,7 +324,7 @@ async fn test_verify_user_email_without_auth() {
 
     let req = test::TestRequest::put()
         .uri(&format!("/account/{}/email-verifications/{}", user_id, verification_id))
-        .set_json(&json!({
+        .set_json(json!({
             "code": "123456"
         }))
         .to_request();
@@ -342,12 +336,12 @@ async fn test_verify_user_email_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_find_dream_vacation_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
 
     let req = test::TestRequest::post()
         .uri("/itineraries/find")
-        .set_json(&json!({
+        .set_json(json!({
             "location": "Paris",
             "budget": 3000,
             "duration": 7,
@@ -362,7 +356,7 @@ async fn test_find_dream_vacation_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_get_or_create_customer_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
@@ -378,14 +372,14 @@ async fn test_get_or_create_customer_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_update_customer_id_without_auth() {
-    let test_app = TestApp::new().await;
+    let test_app = TestApp::offline();
     let app = test::init_service(test_app.create_app()).await;
     
     let user_id = get_test_user_id();
 
     let req = test::TestRequest::post()
         .uri(&format!("/account/{}/update-customer-id", user_id))
-        .set_json(&json!({
+        .set_json(json!({
             "customer_id": "cus_test_123"
         }))
         .to_request();
@@ -398,6 +392,8 @@ async fn test_update_customer_id_without_auth() {
 #[actix_rt::test]
 #[serial]
 async fn test_cleanup() {
-    let test_app = TestApp::new().await;
+    let Some(test_app) = TestApp::with_mongodb().await else {
+        return;
+    };
     cleanup_test_data(&test_app.client).await;
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+//! Needs MongoDB at `MONGODB_URI`. Adds and removes its own itineraries.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::generation_trace::{GenerationMetadata, GenerationTrace};
+
+#[actix_rt::test]
+#[serial]
+async fn test_admins_see_where_generated_itineraries_came_from() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let generated = FeaturedVacation {
+        trip_name: "Provenance test - generated".to_string(),
+        tag: Some("generated".to_string()),
+        generation_metadata: Some(GenerationMetadata {
+            search: serde_json::from_value(json!({ "locations": ["Moab, UT"], "adults": 2 })).unwrap(),
+            variation_index: Some(1),
+            seed: 42,
+            generated_at: DateTime::now(),
+            selection: GenerationTrace::new(true),
+        }),
+        ..Default::default()
+    };
+    let curated = FeaturedVacation {
+        trip_name: "Provenance test - curated".to_string(),
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let generated_id = itineraries.insert_one(&generated).await.unwrap().inserted_id.as_object_id().unwrap();
+    let curated_id = itineraries.insert_one(&curated).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let provenance = |id: ObjectId, role: Option<UserRole>| {
+        let token = generate_token("test_secret", "someone@example.com", ObjectId::new(), role.as_ref()).unwrap();
+        test::TestRequest::get()
+            .uri(&format!("/admin/itineraries/{}/provenance", id.to_hex()))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, provenance(generated_id, Some(UserRole::Admin))).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let metadata = &body["data"]["generation_metadata"];
+    assert_eq!(metadata["search"]["locations"][0], "Moab, UT");
+    assert_eq!(metadata["variation_index"], 1);
+    assert_eq!(metadata["seed"], 42);
+
+    let resp = test::call_service(&app, provenance(curated_id, Some(UserRole::Admin))).await;
+    assert_eq!(resp.status(), 404);
+
+    let resp = test::call_service(&app, provenance(generated_id, None)).await;
+    assert_eq!(resp.status(), 403);
+
+    itineraries
+        .delete_many(doc! { "_id": { "$in": [generated_id, curated_id] } })
+        .await
+        .unwrap();
+}
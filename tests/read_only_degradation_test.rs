@@ -0,0 +1,73 @@
+//! Needs MongoDB at `MONGODB_URI` for the search's reads. Writes go through a sink
+//! that refuses them, standing in for a primary that has stepped down.
+
+mod common;
+
+use actix_web::{test, web};
+use serde_json::json;
+use std::future::Future;
+use std::time::Duration;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::write_behind::{BestEffortWrite, WriteBehindQueue, WriteSink};
+
+struct NotWritablePrimary;
+
+impl WriteSink for NotWritablePrimary {
+    fn write(&self, _write: &BestEffortWrite) -> impl Future<Output = Result<(), String>> + Send {
+        std::future::ready(Err("NotWritablePrimary: not primary".to_string()))
+    }
+}
+
+#[actix_rt::test]
+async fn test_search_succeeds_while_writes_fail() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // Skip generation so the search only reads
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MIN_SEARCH_RESULTS" => Some("0".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let writes = WriteBehindQueue::start_with(NotWritablePrimary);
+
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default()))
+            .app_data(web::Data::new(writes.clone())),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/itineraries/search")
+        .set_json(json!({ "locations": ["Denver"], "adults": 2 }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert!(response.status().is_success(), "search failed: {}", response.status());
+
+    // The submission log was attempted, failed, and reported
+    let mut health = writes.health();
+    for _ in 0..100 {
+        if health.failed > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        health = writes.health();
+    }
+    assert_eq!(health.failed, 1);
+    assert!(health.writes_failing());
+}
@@ -0,0 +1,107 @@
+//! Needs MongoDB at `MONGODB_URI`. Records searches for a fresh user id and
+//! removes them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::write_behind::WriteBehindQueue;
+
+#[actix_rt::test]
+#[serial]
+async fn test_repeated_searches_are_listed_once_and_can_be_rerun() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // Skip generation so the search only reads
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MIN_SEARCH_RESULTS" => Some("0".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default()))
+            .app_data(web::Data::new(WriteBehindQueue::start(client.clone()))),
+    )
+    .await;
+
+    let user_id = ObjectId::new();
+    let token = generate_token("test_secret", "searcher@example.com", user_id, None).unwrap();
+    let auth = ("Authorization", format!("Bearer {}", token));
+
+    // The same search twice, the second with its activities in another order
+    for activities in [["Hiking", "Rafting"], ["rafting", "Hiking"]] {
+        let request = test::TestRequest::post()
+            .uri("/itineraries/search")
+            .insert_header(auth.clone())
+            .set_json(json!({
+                "locations": ["Denver"],
+                "activities": activities,
+                "adults": 2,
+                "arrival_datetime": "2031-07-12T00:00:00",
+                "departure_datetime": "2031-07-15T00:00:00",
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(response.status().is_success(), "search failed: {}", response.status());
+    }
+
+    let request = test::TestRequest::get()
+        .uri(&format!("/account/{}/recent-searches?limit=5", user_id))
+        .insert_header(auth.clone())
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, request).await;
+    let searches = body["searches"].as_array().unwrap();
+    assert_eq!(searches.len(), 1);
+    assert_eq!(searches[0]["summary"], "Denver · 2 adults · rafting, Hiking · Jul 12–15");
+    assert_eq!(searches[0]["search_count"], 2);
+    let fingerprint = searches[0]["fingerprint"].as_str().unwrap().to_string();
+
+    let request = test::TestRequest::post()
+        .uri(&format!("/account/{}/recent-searches/{}/rerun", user_id, fingerprint))
+        .insert_header(auth.clone())
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert!(response.status().is_success(), "rerun failed: {}", response.status());
+
+    let request = test::TestRequest::post()
+        .uri(&format!("/account/{}/recent-searches/0000000000000000/rerun", user_id))
+        .insert_header(auth.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+    // Another traveler can't see or rerun them
+    let other = ObjectId::new();
+    let other_token = generate_token("test_secret", "other@example.com", other, None).unwrap();
+    let request = test::TestRequest::get()
+        .uri(&format!("/account/{}/recent-searches", user_id))
+        .insert_header(("Authorization", format!("Bearer {}", other_token)))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+    client
+        .database("Account")
+        .collection::<Document>("RecentSearches")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
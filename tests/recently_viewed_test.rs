@@ -0,0 +1,131 @@
+//! Needs MongoDB at `MONGODB_URI`. Views are recorded for a fresh user id and
+//! removed afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde_json::Value;
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::recently_viewed_service::{RecentlyViewedService, MAX_STORED_VIEWS};
+
+fn at(millis: i64) -> DateTime {
+    DateTime::from_millis(1_800_000_000_000 + millis)
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_views_are_kept_once_newest_first_and_capped() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let service = RecentlyViewedService::new(client.clone());
+    let user_id = ObjectId::new();
+
+    let itineraries: Vec<ObjectId> = (0..MAX_STORED_VIEWS + 5).map(|_| ObjectId::new()).collect();
+    for (i, itinerary_id) in itineraries.iter().enumerate() {
+        service.record(user_id, *itinerary_id, at(i as i64)).await.unwrap();
+    }
+    // Viewing the oldest kept one again moves it to the top instead of adding it twice
+    let revisited = itineraries[10];
+    service.record(user_id, revisited, at(1_000)).await.unwrap();
+
+    let stored = client
+        .database("Account")
+        .collection::<Document>("RecentlyViewed")
+        .count_documents(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+    assert_eq!(stored, MAX_STORED_VIEWS);
+
+    let recent = service.recent(user_id, 3).await.unwrap();
+    let ids: Vec<ObjectId> = recent.iter().map(|view| view.itinerary_id).collect();
+    let newest = itineraries.len() - 1;
+    assert_eq!(ids, vec![revisited, itineraries[newest], itineraries[newest - 1]]);
+    assert_eq!(recent[0].view_count, 2);
+
+    client
+        .database("Account")
+        .collection::<Document>("RecentlyViewed")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_endpoint_lists_summaries_and_skips_missing_itineraries() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    let itinerary_id = ObjectId::new();
+    let featured = client.database("Itineraries").collection::<Document>("Featured");
+    featured
+        .insert_one(doc! {
+            "_id": itinerary_id,
+            "trip_name": "Recently Viewed Test Trip",
+            "min_age": 0,
+            "min_group": 1,
+            "max_group": 8,
+            "length_days": 3,
+            "length_hours": 72,
+            "start_location": { "city": "Denver", "state": "CO", "coordinates": [-104.99, 39.74] },
+            "end_location": { "city": "Denver", "state": "CO", "coordinates": [-104.99, 39.74] },
+            "description": "",
+            "days": {},
+        })
+        .await
+        .unwrap();
+
+    let user_id = ObjectId::new();
+    let service = RecentlyViewedService::new(client.clone());
+    service.record(user_id, itinerary_id, at(0)).await.unwrap();
+    // Deleted since it was viewed
+    service.record(user_id, ObjectId::new(), at(1)).await.unwrap();
+
+    let token = generate_token("test_secret", "viewer@example.com", user_id, None).unwrap();
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&format!("/account/{}/recently-viewed", user_id.to_hex()))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = test::read_body_json(response).await;
+    let listed = body["itineraries"].as_array().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["itinerary"]["trip_name"], "Recently Viewed Test Trip");
+    assert_eq!(listed[0]["view_count"], 1);
+
+    featured.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("RecentlyViewed")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,181 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own itinerary and booking.
+
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::Collection;
+use serial_test::serial;
+use std::future::Future;
+use std::sync::Mutex;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::bookings::{BookingDetails, Party, PaymentStatus, RescheduleInput};
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::models::money::Money;
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::booking_reschedule::{
+    BookingRescheduleService, PaymentAdjuster, RescheduleError, ReschedulePolicy,
+};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// A card that paid `received` and records refunds instead of calling Stripe
+struct FakeCard {
+    received: i64,
+    refunds: Mutex<Vec<i64>>,
+}
+
+impl PaymentAdjuster for FakeCard {
+    fn amount_received(&self, _payment_intent_id: &str) -> impl Future<Output = Result<i64, String>> + Send {
+        std::future::ready(Ok(self.received))
+    }
+
+    fn capture(&self, _payment_intent_id: &str, _amount: i64) -> impl Future<Output = Result<(), String>> + Send {
+        std::future::ready(Ok(()))
+    }
+
+    fn refund(&self, _payment_intent_id: &str, amount: i64) -> impl Future<Output = Result<String, String>> + Send {
+        self.refunds.lock().unwrap().push(amount);
+        std::future::ready(Ok(format!("re_test_{}", amount)))
+    }
+}
+
+fn dates(days_ahead: i64) -> (DateTime, DateTime) {
+    let arrival = DateTime::now().timestamp_millis() / DAY_MILLIS * DAY_MILLIS + days_ahead * DAY_MILLIS;
+    (DateTime::from_millis(arrival), DateTime::from_millis(arrival + DAY_MILLIS))
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_reschedule_refunds_a_cheaper_price_and_keeps_history() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries
+        .insert_one(FeaturedVacation {
+            trip_name: "Reschedule test trip".to_string(),
+            length_days: 2,
+            person_cost: Some(Money::from_dollars(400.0)),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let user_id = ObjectId::new();
+    let (arrival, departure) = dates(30);
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    let booking_id = bookings
+        .insert_one(BookingDetails {
+            id: None,
+            user_id,
+            itinerary_id,
+            customer_id: None,
+            transaction_id: Some(format!("pi_test_{}", ObjectId::new())),
+            arrival_datetime: arrival,
+            departure_datetime: departure,
+            status: PaymentStatus::Confirmed,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: Some(Party { adults: 2, children: 0, infants: 0 }),
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: Some(DateTime::now()),
+            updated_at: Some(DateTime::now()),
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let service = BookingRescheduleService::new(client.clone());
+    let policy = ReschedulePolicy { cutoff_hours: 72, limited_threshold: 4 };
+    let cache = AvailabilityCache::default();
+    // Booked at $450 a head; the trip is $400 now
+    let card = FakeCard { received: 90_000, refunds: Mutex::new(Vec::new()) };
+
+    let (new_arrival, new_departure) = dates(40);
+    let wrong_length = service
+        .reschedule(
+            user_id,
+            booking_id,
+            RescheduleInput {
+                arrival_datetime: new_arrival,
+                departure_datetime: DateTime::from_millis(new_departure.timestamp_millis() + DAY_MILLIS),
+                payment_intent_id: None,
+            },
+            &policy,
+            &cache,
+            &card,
+        )
+        .await;
+    assert!(matches!(wrong_length, Err(RescheduleError::InvalidDates(_))));
+
+    let outcome = service
+        .reschedule(
+            user_id,
+            booking_id,
+            RescheduleInput {
+                arrival_datetime: new_arrival,
+                departure_datetime: new_departure,
+                payment_intent_id: None,
+            },
+            &policy,
+            &cache,
+            &card,
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcome.price_difference, -10_000);
+    assert_eq!(*card.refunds.lock().unwrap(), vec![10_000]);
+
+    let stored = bookings.find_one(doc! { "_id": booking_id }).await.unwrap().unwrap();
+    assert_eq!(stored.arrival_datetime, new_arrival);
+    assert_eq!(stored.modifications.len(), 1);
+    assert_eq!(stored.modifications[0].previous_arrival_datetime, arrival);
+    assert_eq!(stored.modifications[0].refund_id.as_deref(), Some("re_test_10000"));
+
+    // Moving back costs nothing more: the refund is counted against what was paid
+    let again = service
+        .reschedule(
+            user_id,
+            booking_id,
+            RescheduleInput { arrival_datetime: arrival, departure_datetime: departure, payment_intent_id: None },
+            &policy,
+            &cache,
+            &card,
+        )
+        .await
+        .unwrap();
+    assert_eq!(again.price_difference, 0);
+    assert_eq!(again.booking.modifications.len(), 2);
+
+    bookings
+        .update_one(doc! { "_id": booking_id }, doc! { "$set": { "status": "cancelled" } })
+        .await
+        .unwrap();
+    let cancelled = service
+        .reschedule(
+            user_id,
+            booking_id,
+            RescheduleInput { arrival_datetime: new_arrival, departure_datetime: new_departure, payment_intent_id: None },
+            &policy,
+            &cache,
+            &card,
+        )
+        .await;
+    assert!(matches!(cancelled, Err(RescheduleError::NotReschedulable(PaymentStatus::Cancelled))));
+
+    bookings.delete_one(doc! { "_id": booking_id }).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+}
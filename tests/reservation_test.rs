@@ -0,0 +1,231 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own activity, itinerary and reservations.
+
+mod common;
+
+use actix_web::{test, web};
+use chrono::{Duration, Utc};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::{Client, Collection};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::activity::Activity;
+use actota_api::models::bookings::{BookingDetails, PaymentStatus};
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::models::money::Money;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::availability_service::{ActivityBookingCount, AvailabilityCache};
+use actota_api::services::booking_confirmation::BookingConfirmationService;
+use actota_api::services::reservation_service::{ReservationError, ReservationService, ReservationStatus};
+
+fn config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
+
+/// A four-seat activity and a one-day trip for a party of two that runs it
+async fn four_seat_trip(client: &Client) -> (ObjectId, FeaturedVacation) {
+    let activity: Activity = serde_json::from_value(json!({
+        "company": "Rocky Mountain Adventures",
+        "company_id": "rma",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": "Reservation test rafting",
+        "description": "",
+        "activity_types": [],
+        "tags": [],
+        "price_per_person": 100.0,
+        "duration_minutes": 120,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Salida", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 4 },
+    }))
+    .unwrap();
+    let activity_id = client
+        .database("Options")
+        .collection::<Activity>("Activity")
+        .insert_one(activity)
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let mut itinerary = FeaturedVacation {
+        trip_name: "Reservation test trip".to_string(),
+        length_days: 1,
+        adults: Some(2),
+        person_cost: Some(Money::from_dollars(100.0)),
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![DayItem::Activity {
+                    time: "09:00:00".to_string(),
+                    activity_id,
+                }],
+            )]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    itinerary.id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id();
+    (activity_id, itinerary)
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_reservation_holds_extends_expires_and_converts() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let service = ReservationService::new(client.clone());
+    service.ensure_indexes().await.unwrap();
+    let cache = AvailabilityCache::default();
+
+    let (activity_id, itinerary) = four_seat_trip(&client).await;
+    let itinerary_id = itinerary.id.unwrap();
+    let start = (Utc::now() + Duration::days(30)).date_naive();
+    let arrival = DateTime::from_millis(start.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+    let first = ObjectId::new();
+    let second = ObjectId::new();
+
+    // Three of four seats held: the second traveler can't take two
+    let held = service.reserve(&cache, first, itinerary_id, start, 3, 15).await.unwrap();
+    assert!(matches!(
+        service.reserve(&cache, second, itinerary_id, start, 2, 15).await,
+        Err(ReservationError::SoldOut)
+    ));
+    assert!(!service.has_room_for(&itinerary, start, 2).await.unwrap());
+
+    // Nor book without a reservation
+    let config = config(&mongo_uri);
+    let secret = config.jwt_secret.clone();
+    let token = generate_token(&secret, "second@example.com", second, None).unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(Arc::new(stripe::Client::new("sk_test_unused"))))
+            .app_data(web::Data::new(AvailabilityCache::default())),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/account/{}/bookings/itinerary/{}/with-payment",
+            second.to_hex(),
+            itinerary_id.to_hex()
+        ))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({
+            "arrival_datetime": start.to_string(),
+            "departure_datetime": start.succ_opt().unwrap().to_string(),
+            "customer_id": "cus_test",
+            "payment_intent_id": "pi_test_unused",
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "sold_out");
+
+    // Reserving again extends the same reservation instead of holding twice
+    let extended = service.reserve(&cache, first, itinerary_id, start, 3, 30).await.unwrap();
+    assert_eq!(extended.id, held.id);
+    assert!(extended.expires_at > held.expires_at);
+    let inventory: Collection<ActivityBookingCount> = client.database("Options").collection("ActivityBookings");
+    let record = doc! { "activity_id": activity_id, "date": start.to_string() };
+    let count = inventory.find_one(record.clone()).await.unwrap().unwrap();
+    assert_eq!(count.holds.len(), 1);
+    assert_eq!(count.holds[0].expires_at, extended.expires_at);
+
+    // Once the hold lapses its seats are free again
+    let lapsed = DateTime::from_millis(DateTime::now().timestamp_millis() - 1000);
+    inventory
+        .update_one(record.clone(), doc! { "$set": { "holds.0.expires_at": lapsed } })
+        .await
+        .unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("Reservations")
+        .update_one(doc! { "_id": held.id }, doc! { "$set": { "expires_at": lapsed } })
+        .await
+        .unwrap();
+    let second_hold = service.reserve(&cache, second, itinerary_id, start, 2, 15).await.unwrap();
+
+    // Confirming the second traveler's booking turns the hold into booked seats
+    let booking = BookingDetails {
+        id: Some(ObjectId::new()),
+        user_id: second,
+        itinerary_id,
+        customer_id: None,
+        transaction_id: None,
+        arrival_datetime: arrival,
+        departure_datetime: arrival,
+        status: PaymentStatus::Confirmed,
+        bookings: None,
+        gift_card_redemption_id: None,
+        gift_card_amount: None,
+        special_requests: None,
+        party: None,
+        created_by_admin: false,
+        reservation_id: Some(second_hold.id),
+        modifications: Vec::new(),
+        review_request_sent_at: None,
+        created_at: None,
+        updated_at: None,
+    };
+    BookingConfirmationService::new(client.clone())
+        .reserve_inventory(&cache, &booking)
+        .await;
+    let count = inventory.find_one(record.clone()).await.unwrap().unwrap();
+    assert_eq!(count.booked, 2);
+    assert!(count.holds.iter().all(|hold| hold.reservation_id != second_hold.id));
+    assert!(matches!(
+        service.find_for_checkout(second_hold.id, second, itinerary_id, Some(start)).await,
+        Err(ReservationError::Expired)
+    ));
+    let converted = client
+        .database("Account")
+        .collection::<Document>("Reservations")
+        .find_one(doc! { "_id": second_hold.id })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        converted.get_str("status").unwrap(),
+        mongodb::bson::to_bson(&ReservationStatus::Converted).unwrap().as_str().unwrap()
+    );
+
+    client
+        .database("Account")
+        .collection::<Document>("Reservations")
+        .delete_many(doc! { "itinerary_id": itinerary_id })
+        .await
+        .unwrap();
+    inventory.delete_many(doc! { "activity_id": activity_id }).await.unwrap();
+    client
+        .database("Itineraries")
+        .collection::<Document>("Featured")
+        .delete_one(doc! { "_id": itinerary_id })
+        .await
+        .unwrap();
+    client
+        .database("Options")
+        .collection::<Document>("Activity")
+        .delete_one(doc! { "_id": activity_id })
+        .await
+        .unwrap();
+}
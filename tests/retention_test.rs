@@ -0,0 +1,96 @@
+//! Needs a disposable MongoDB at `MONGODB_URI`: a real run purges every expired
+//! record there, not just the ones this test creates.
+
+mod common;
+
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serial_test::serial;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::services::retention_service::{RetentionPolicy, RetentionService, RetentionTrigger};
+
+/// Two years ago, past every default retention window
+fn long_ago() -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() - 2 * 365 * 24 * 60 * 60 * 1000)
+}
+
+async fn exists(collection: &Collection<Document>, id: ObjectId) -> bool {
+    collection.find_one(doc! { "_id": id }).await.unwrap().is_some()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_retention_keeps_linked_records_and_honours_dry_run() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let submissions: Collection<Document> = client.database("Travelers").collection("Submission");
+    let itineraries: Collection<Document> = client.database("Itineraries").collection("Featured");
+    let bookings: Collection<Document> = client.database("Account").collection("Bookings");
+
+    let anonymous_old = ObjectId::new();
+    let user_old = ObjectId::new();
+    let anonymous_recent = ObjectId::new();
+    submissions
+        .insert_many([
+            doc! { "_id": anonymous_old, "user_id": null, "created_at": long_ago() },
+            doc! { "_id": user_old, "user_id": ObjectId::new(), "created_at": long_ago() },
+            doc! { "_id": anonymous_recent, "user_id": null, "created_at": DateTime::now() },
+        ])
+        .await
+        .unwrap();
+
+    let unbooked = ObjectId::new();
+    let booked = ObjectId::new();
+    let curated = ObjectId::new();
+    itineraries
+        .insert_many([
+            doc! { "_id": unbooked, "tag": "generated", "created_at": long_ago() },
+            doc! { "_id": booked, "tag": "generated", "created_at": long_ago() },
+            doc! { "_id": curated, "tag": "featured", "created_at": long_ago() },
+        ])
+        .await
+        .unwrap();
+    let booking = ObjectId::new();
+    bookings
+        .insert_one(doc! { "_id": booking, "itinerary_id": booked })
+        .await
+        .unwrap();
+
+    let service = RetentionService::new(client.clone(), RetentionPolicy::default());
+
+    // A dry run reports without deleting
+    let dry = service.run(RetentionTrigger::Manual, true).await.unwrap();
+    assert!(dry.dry_run && dry.error.is_none());
+    assert!(dry.counts.search_submissions >= 1);
+    assert!(dry.counts.generated_itineraries >= 1);
+    assert!(dry.counts.generated_itineraries_kept >= 1);
+    assert!(exists(&submissions, anonymous_old).await);
+    assert!(exists(&itineraries, unbooked).await);
+
+    let run = service.run(RetentionTrigger::Manual, false).await.unwrap();
+    assert!(run.error.is_none());
+    assert!(!exists(&submissions, anonymous_old).await);
+    assert!(exists(&submissions, user_old).await);
+    assert!(exists(&submissions, anonymous_recent).await);
+    assert!(!exists(&itineraries, unbooked).await);
+    assert!(exists(&itineraries, booked).await);
+    assert!(exists(&itineraries, curated).await);
+
+    // Both runs were recorded
+    let runs = service.list_runs(2).await.unwrap();
+    assert_eq!(runs.iter().map(|r| r.dry_run).collect::<Vec<_>>(), vec![false, true]);
+
+    submissions
+        .delete_many(doc! { "_id": { "$in": [user_old, anonymous_recent] } })
+        .await
+        .unwrap();
+    itineraries
+        .delete_many(doc! { "_id": { "$in": [booked, curated] } })
+        .await
+        .unwrap();
+    bookings.delete_one(doc! { "_id": booking }).await.unwrap();
+}
@@ -0,0 +1,310 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own users and bookings.
+
+mod common;
+
+use actix_web::{test, web};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::{Client, Collection};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use actota_api::build_app;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::User;
+use actota_api::models::bookings::{BookingDetails, PaymentStatus};
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::services::review_request_service::{
+    ReviewActivity, ReviewRequestEmail, ReviewRequestSender, ReviewRequestService, REVIEW_LINK_DAYS,
+};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Records review requests instead of emailing them
+#[derive(Default)]
+struct RecordedRequests {
+    sent: Mutex<Vec<ReviewRequestEmail>>,
+}
+
+impl RecordedRequests {
+    fn emails_for(&self, booking_id: ObjectId) -> Vec<ReviewRequestEmail> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|email| email.booking_id == booking_id)
+            .cloned()
+            .collect()
+    }
+
+    fn for_booking(&self, booking_id: ObjectId) -> Vec<String> {
+        self.emails_for(booking_id)
+            .into_iter()
+            .map(|email| email.unsubscribe_token)
+            .collect()
+    }
+}
+
+impl ReviewRequestSender for RecordedRequests {
+    fn send_review_request(&self, email: &ReviewRequestEmail) -> impl Future<Output = Result<(), String>> + Send {
+        self.sent.lock().unwrap().push(email.clone());
+        std::future::ready(Ok(()))
+    }
+}
+
+async fn traveler(client: &Client, review_requests: bool) -> ObjectId {
+    let user: User = serde_json::from_value(json!({
+        "email": format!("review-{}@example.com", ObjectId::new().to_hex()),
+        "password": "hashed",
+        "notification_preferences": { "email": { "review_requests": review_requests } },
+    }))
+    .unwrap();
+    client
+        .database("Account")
+        .collection::<User>("Users")
+        .insert_one(&user)
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap()
+}
+
+async fn booking(
+    bookings: &Collection<BookingDetails>,
+    user_id: ObjectId,
+    status: PaymentStatus,
+    departed_days_ago: i64,
+    review_request_sent_at: Option<DateTime>,
+) -> ObjectId {
+    booking_for(bookings, user_id, ObjectId::new(), status, departed_days_ago, review_request_sent_at).await
+}
+
+async fn booking_for(
+    bookings: &Collection<BookingDetails>,
+    user_id: ObjectId,
+    itinerary_id: ObjectId,
+    status: PaymentStatus,
+    departed_days_ago: i64,
+    review_request_sent_at: Option<DateTime>,
+) -> ObjectId {
+    let departure = DateTime::from_millis(DateTime::now().timestamp_millis() - departed_days_ago * DAY_MILLIS);
+    bookings
+        .insert_one(BookingDetails {
+            id: None,
+            user_id,
+            itinerary_id,
+            customer_id: None,
+            transaction_id: None,
+            arrival_datetime: DateTime::from_millis(departure.timestamp_millis() - 3 * DAY_MILLIS),
+            departure_datetime: departure,
+            status,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: None,
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at,
+            created_at: None,
+            updated_at: None,
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_finished_trips_are_asked_about_once() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    let users: Collection<User> = client.database("Account").collection("Users");
+
+    let traveler_id = traveler(&client, true).await;
+    let opted_out_id = traveler(&client, false).await;
+    let due = booking(&bookings, traveler_id, PaymentStatus::Confirmed, 3, None).await;
+    let too_recent = booking(&bookings, traveler_id, PaymentStatus::Confirmed, 1, None).await;
+    let upcoming = booking(&bookings, traveler_id, PaymentStatus::Confirmed, -5, None).await;
+    let cancelled = booking(&bookings, traveler_id, PaymentStatus::Cancelled, 3, None).await;
+    let already_asked = booking(&bookings, traveler_id, PaymentStatus::Confirmed, 3, Some(DateTime::now())).await;
+    let opted_out = booking(&bookings, opted_out_id, PaymentStatus::Confirmed, 3, None).await;
+
+    let service = ReviewRequestService::new(client.clone());
+    let sender = RecordedRequests::default();
+    service.send_due(&sender, 2, DateTime::now()).await.unwrap();
+
+    assert_eq!(sender.for_booking(due).len(), 1);
+    for not_due in [too_recent, upcoming, cancelled, already_asked, opted_out] {
+        assert!(sender.for_booking(not_due).is_empty());
+    }
+    let stamped = bookings.find_one(doc! { "_id": due }).await.unwrap().unwrap();
+    assert!(stamped.review_request_sent_at.is_some());
+    let unstamped = bookings.find_one(doc! { "_id": opted_out }).await.unwrap().unwrap();
+    assert!(unstamped.review_request_sent_at.is_none());
+
+    // The stamp keeps the next run from asking again
+    service.send_due(&sender, 2, DateTime::now()).await.unwrap();
+    assert_eq!(sender.for_booking(due).len(), 1);
+
+    // The link in the email turns review requests off
+    let token = sender.for_booking(due).remove(0);
+    assert!(service.unsubscribe(&token).await.unwrap());
+    assert!(!service.unsubscribe("not-a-token").await.unwrap());
+    let traveler = users.find_one(doc! { "_id": traveler_id }).await.unwrap().unwrap();
+    let preferences = traveler.effective_notification_preferences();
+    assert!(!preferences.email.review_requests);
+    assert!(preferences.email.booking_updates);
+
+    let booking_ids = [due, too_recent, upcoming, cancelled, already_asked, opted_out];
+    bookings.delete_many(doc! { "_id": { "$in": booking_ids.to_vec() } }).await.unwrap();
+    users
+        .delete_many(doc! { "_id": { "$in": [traveler_id, opted_out_id] } })
+        .await
+        .unwrap();
+    client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("ReviewRequests")
+        .delete_many(doc! { "booking_id": due })
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_each_activity_is_linked_and_the_link_opens_the_request() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+
+    let activities = client.database("Options").collection::<Document>("Activity");
+    let (rafting, hike, deleted) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+    activities
+        .insert_many([
+            doc! { "_id": rafting, "title": "Review test rafting" },
+            doc! { "_id": hike, "title": "Review test hike" },
+        ])
+        .await
+        .unwrap();
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let activity = |activity_id| DayItem::Activity { time: "09:00:00".to_string(), activity_id };
+    let itinerary_id = itineraries
+        .insert_one(FeaturedVacation {
+            trip_name: "Review test trip".to_string(),
+            days: Days {
+                days: HashMap::from([
+                    ("2".to_string(), vec![activity(hike), activity(rafting)]),
+                    ("1".to_string(), vec![activity(rafting), activity(deleted)]),
+                ]),
+            },
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let traveler_id = traveler(&client, true).await;
+    let booking_id = booking_for(&bookings, traveler_id, itinerary_id, PaymentStatus::Completed, 3, None).await;
+
+    let service = ReviewRequestService::new(client.clone());
+    let sender = RecordedRequests::default();
+    service.send_due(&sender, 2, DateTime::now()).await.unwrap();
+
+    let emails = sender.emails_for(booking_id);
+    assert_eq!(emails.len(), 1);
+    let email = &emails[0];
+    assert_eq!(email.trip_name, "Review test trip");
+    // In trip order, each once, less the one deleted since
+    assert_eq!(
+        email.activities,
+        vec![
+            ReviewActivity { id: rafting, title: "Review test rafting".to_string() },
+            ReviewActivity { id: hike, title: "Review test hike".to_string() },
+        ]
+    );
+    assert_ne!(email.review_token, email.unsubscribe_token);
+
+    // The link opens the request without signing in
+    let app = test::init_service(build_app().app_data(web::Data::new(client.clone()))).await;
+    let open = |token: &str| test::TestRequest::get().uri(&format!("/review-requests/{}", token)).to_request();
+    let resp = test::call_service(&app, open(&email.review_token)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["booking_id"], booking_id.to_hex());
+    assert_eq!(body["activities"][0]["id"], rafting.to_hex());
+    assert_eq!(body["activities"][1]["title"], "Review test hike");
+
+    // Neither the unsubscribe token nor an old link does
+    let resp = test::call_service(&app, open(&email.unsubscribe_token)).await;
+    assert_eq!(resp.status(), 404);
+    let later = DateTime::from_millis(DateTime::now().timestamp_millis() + (REVIEW_LINK_DAYS + 1) * DAY_MILLIS);
+    assert!(service.find_by_review_token(&email.review_token, later).await.unwrap().is_none());
+
+    // The link also submits reviews of the activities it asked about, once each
+    let review = |token: &str, body: Value| {
+        test::TestRequest::post()
+            .uri(&format!("/review-requests/{}/reviews", token))
+            .set_json(body)
+            .to_request()
+    };
+    let resp = test::call_service(&app, review(&email.review_token, json!({ "activity_id": rafting.to_hex(), "rating": 5 }))).await;
+    assert_eq!(resp.status(), 201);
+    let resp = test::call_service(
+        &app,
+        review(&email.review_token, json!({ "activity_id": rafting.to_hex(), "rating": 4, "comment": " Cold water " })),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+    let reviews = client.database("Account").collection::<Document>("ActivityReviews");
+    let stored: Vec<Document> = reviews
+        .find(doc! { "booking_id": booking_id })
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].get_i32("rating").unwrap(), 4);
+    assert_eq!(stored[0].get_str("comment").unwrap(), "Cold water");
+    assert_eq!(stored[0].get_object_id("user_id").unwrap(), traveler_id);
+
+    for (token, activity_id, rating, status) in [
+        (email.review_token.as_str(), deleted, 5, 400),
+        (email.review_token.as_str(), hike, 0, 400),
+        (email.unsubscribe_token.as_str(), hike, 5, 404),
+    ] {
+        let resp = test::call_service(&app, review(token, json!({ "activity_id": activity_id.to_hex(), "rating": rating }))).await;
+        assert_eq!(resp.status(), status);
+    }
+    reviews.delete_many(doc! { "booking_id": booking_id }).await.unwrap();
+
+    bookings.delete_one(doc! { "_id": booking_id }).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    activities.delete_many(doc! { "_id": { "$in": [rafting, hike] } }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<User>("Users")
+        .delete_one(doc! { "_id": traveler_id })
+        .await
+        .unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("ReviewRequests")
+        .delete_many(doc! { "booking_id": booking_id })
+        .await
+        .unwrap();
+}
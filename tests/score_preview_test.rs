@@ -0,0 +1,134 @@
+//! Needs MongoDB at `MONGODB_URI`. Adds and removes its own activity and itinerary.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::collections::HashMap;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::activity::Activity;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::routes::account::auth::generate_token;
+
+fn activity(title: &str, activity_types: &[&str]) -> Value {
+    json!({
+        "company": "Score Preview Co",
+        "company_id": "score-preview",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": title,
+        "description": "",
+        "activity_types": activity_types,
+        "tags": [],
+        "price_per_person": 40.0,
+        "duration_minutes": 120,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Denver", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 10 },
+    })
+}
+
+fn item(activity_id: ObjectId) -> DayItem {
+    DayItem::Activity {
+        time: "09:00:00".to_string(),
+        activity_id,
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_score_preview_scores_edits_without_saving_them() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let activities: Collection<Activity> = client.database("Options").collection("Activity");
+    let museum_id = activities
+        .insert_one(serde_json::from_value::<Activity>(activity("Score preview museum", &["culture"])).unwrap())
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+    let itinerary = FeaturedVacation {
+        trip_name: "Score preview test trip".to_string(),
+        min_group: 1,
+        max_group: 6,
+        days: Days {
+            days: HashMap::from([("1".to_string(), vec![item(museum_id)])]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let itinerary_id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let token = generate_token("test_secret", "admin@example.com", ObjectId::new(), Some(&UserRole::Admin)).unwrap();
+    let preview = |body: Value| {
+        test::TestRequest::post()
+            .uri(&format!("/admin/itineraries/{}/score-preview", itinerary_id.to_hex()))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request()
+    };
+
+    // A hike that only exists in the request
+    let hike_id = ObjectId::new();
+    let mut hike = activity("Score preview hike", &["hiking"]);
+    hike["_id"] = json!({ "$oid": hike_id.to_hex() });
+    let resp = test::call_service(
+        &app,
+        preview(json!({
+            "overrides": {
+                "days": [{ "day": 1, "items": [
+                    { "type": "activity", "time": "09:00:00", "activity_id": { "$oid": museum_id.to_hex() } },
+                    { "type": "activity", "time": "13:00:00", "activity_id": { "$oid": hike_id.to_hex() } },
+                ] }],
+                "activities": [hike],
+            },
+            "scenarios": [{ "adults": 2, "activities": ["hiking"] }, "denver_family"],
+        })),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    let hiking = &body["data"][0];
+    assert_eq!(hiking["scenario"], "scenario 1");
+    assert_eq!(hiking["current"]["breakdown"]["activity_score"], 0.0);
+    assert_eq!(hiking["modified"]["breakdown"]["activity_score"], 100.0);
+    assert!(hiking["change"].as_i64().unwrap() > 0);
+    assert_eq!(body["data"][1]["scenario"], "denver_family");
+
+    // Nothing was written
+    let stored = itineraries.find_one(doc! { "_id": itinerary_id }).await.unwrap().unwrap();
+    assert_eq!(stored.days.days["1"].len(), 1);
+    assert!(activities.find_one(doc! { "_id": hike_id }).await.unwrap().is_none());
+
+    let resp = test::call_service(&app, preview(json!({ "scenarios": ["honeymoon"] }))).await;
+    assert_eq!(resp.status(), 400);
+
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    activities.delete_one(doc! { "_id": museum_id }).await.unwrap();
+}
@@ -0,0 +1,132 @@
+//! Needs MongoDB at `MONGODB_URI` with no search experiment active. Creates and
+//! deletes its own experiments and search submissions.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::time::Duration;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::write_behind::WriteBehindQueue;
+
+#[actix_rt::test]
+#[serial]
+async fn test_assigned_experiment_is_reported_and_logged() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // Skip generation so the search only reads
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MIN_SEARCH_RESULTS" => Some("0".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default()))
+            .app_data(web::Data::new(WriteBehindQueue::start(client.clone()))),
+    )
+    .await;
+
+    let admin_id = ObjectId::new();
+    let admin_token = generate_token("test_secret", "ops@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let admin = ("Authorization", format!("Bearer {}", admin_token));
+    let suffix = ObjectId::new().to_hex();
+    let mut experiment_ids = Vec::new();
+    for name in ["heavier_activities", "closer_locations"] {
+        let request = test::TestRequest::post()
+            .uri("/admin/search-experiments")
+            .insert_header(admin.clone())
+            .set_json(json!({
+                "name": format!("{}_{}", name, suffix),
+                "weights": { "activity_weight": 45.0 },
+                "traffic_percent": 100
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 201);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["data"]["active"], false);
+        experiment_ids.push(body["data"]["_id"]["$oid"].as_str().unwrap().to_string());
+    }
+    let heavier = format!("heavier_activities_{}", suffix);
+
+    // Only one experiment runs at a time
+    let activate = |id: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/admin/search-experiments/{}/activate", id))
+            .insert_header(admin.clone())
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, activate(&experiment_ids[0])).await.status(), 200);
+    let response = test::call_service(&app, activate(&experiment_ids[1])).await;
+    assert_eq!(response.status(), 409);
+    let body: Value = test::read_body_json(response).await;
+    assert!(body["message"].as_str().unwrap().contains(&heavier));
+
+    let user_id = ObjectId::new();
+    let token = generate_token("test_secret", "searcher@example.com", user_id, None).unwrap();
+    let search = || {
+        test::TestRequest::post()
+            .uri("/v2/itineraries/search")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "locations": ["Denver"], "activities": ["Rafting"], "adults": 2 }))
+            .to_request()
+    };
+    let body: Value = test::call_and_read_body_json(&app, search()).await;
+    assert_eq!(body["meta"]["experiment"], heavier.as_str());
+
+    // The submission is written behind the request
+    let submissions = client.database("Travelers").collection::<Document>("Submission");
+    let mut logged = None;
+    for _ in 0..50 {
+        logged = submissions.find_one(doc! { "user_id": user_id }).await.unwrap();
+        if logged.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(logged.unwrap().get_str("experiment").unwrap(), heavier);
+
+    // Deactivated, searches go back to the default weights
+    let request = test::TestRequest::post()
+        .uri(&format!("/admin/search-experiments/{}/deactivate", experiment_ids[0]))
+        .insert_header(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let body: Value = test::call_and_read_body_json(&app, search()).await;
+    assert_eq!(body["meta"]["experiment"], Value::Null);
+
+    let ids: Vec<ObjectId> = experiment_ids.iter().map(|id| ObjectId::parse_str(id).unwrap()).collect();
+    client
+        .database("Options")
+        .collection::<Document>("SearchExperiments")
+        .delete_many(doc! { "_id": { "$in": ids } })
+        .await
+        .unwrap();
+    submissions.delete_many(doc! { "user_id": user_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("AdminAuditLog")
+        .delete_many(doc! { "admin_id": admin_id })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,253 @@
+//! Search relevance against golden rankings. No database needed.
+//!
+//! The fixtures under `tests/fixtures/relevance` hold itineraries, the activities
+//! they schedule and canonical searches. `golden.json` records the top three
+//! itineraries each search is expected to rank. Changing the weights or the
+//! synonym table shows up here as a list of position changes; when the change is
+//! intended, regenerate the file with
+//!
+//! ```text
+//! UPDATE_GOLDENS=1 cargo test --test search_relevance_test
+//! ```
+//!
+//! and commit it with the change.
+//!
+//! The goldens are for the default weights only. Search experiments
+//! (`/admin/search-experiments`) override weights per request and are never
+//! applied here; their own tests check how they move rankings.
+
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use actota_api::models::activity::Activity;
+use actota_api::models::itinerary::base::FeaturedVacation;
+use actota_api::models::search::SearchItinerary;
+use actota_api::services::search_scoring::SearchScorer;
+
+const TOP: usize = 3;
+
+#[derive(Deserialize)]
+struct ActivityFixture {
+    key: String,
+    title: String,
+    activity_types: Vec<String>,
+    tags: Vec<String>,
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct ItineraryFixture {
+    key: String,
+    trip_name: String,
+    description: String,
+    /// City and state
+    start: (String, String),
+    end: Option<(String, String)>,
+    /// Minimum and maximum group size
+    group: (u32, u32),
+    /// Activity keys scheduled on each day
+    days: Vec<Vec<String>>,
+    /// Whether each day ends at an accommodation
+    #[serde(default)]
+    stays: bool,
+    /// Name of a transportation item on the first day
+    transport: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Query {
+    name: String,
+    search: Value,
+}
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/relevance")
+}
+
+fn read<T: serde::de::DeserializeOwned>(file: &str) -> T {
+    let path = fixtures().join(file);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("parsing {}: {}", path.display(), e))
+}
+
+fn location((city, state): &(String, String)) -> Value {
+    json!({ "city": city, "state": state, "coordinates": [0.0, 0.0] })
+}
+
+/// Activities get ids from their position, so the same fixtures always score the same
+fn load_activities() -> (Vec<Activity>, HashMap<String, ObjectId>) {
+    let fixtures: Vec<ActivityFixture> = read("activities.json");
+    let mut ids = HashMap::new();
+    let activities = fixtures
+        .into_iter()
+        .enumerate()
+        .map(|(index, fixture)| {
+            let id = ObjectId::parse_str(format!("{:024x}", index + 1)).unwrap();
+            ids.insert(fixture.key, id);
+            serde_json::from_value(json!({
+                "_id": id,
+                "company": "Relevance Fixtures",
+                "company_id": "relevance",
+                "booking_link": "",
+                "online_booking_status": "available",
+                "title": fixture.title,
+                "description": fixture.description,
+                "activity_types": fixture.activity_types,
+                "tags": fixture.tags,
+                "price_per_person": 50.0,
+                "duration_minutes": 120,
+                "daily_time_slots": [],
+                "address": { "street": "", "unit": "", "city": "", "state": "", "zip": "", "country": "USA" },
+                "whats_included": [],
+                "capacity": { "minimum": 1, "maximum": 20 },
+            }))
+            .unwrap()
+        })
+        .collect();
+    (activities, ids)
+}
+
+/// Itineraries with the trip name set to the fixture key, which is what rankings report
+fn load_itineraries(activity_ids: &HashMap<String, ObjectId>) -> Vec<FeaturedVacation> {
+    let fixtures: Vec<ItineraryFixture> = read("itineraries.json");
+    fixtures
+        .into_iter()
+        .map(|fixture| {
+            let mut days = serde_json::Map::new();
+            for (index, keys) in fixture.days.iter().enumerate() {
+                let mut items = Vec::new();
+                if index == 0 {
+                    if let Some(name) = &fixture.transport {
+                        items.push(json!({ "type": "transportation", "time": "08:00:00", "name": name,
+                            "location": { "name": "", "coordinates": [0.0, 0.0] } }));
+                    }
+                }
+                for key in keys {
+                    let id = activity_ids
+                        .get(key)
+                        .unwrap_or_else(|| panic!("itinerary '{}' schedules unknown activity '{}'", fixture.key, key));
+                    items.push(json!({ "type": "activity", "time": "10:00:00", "activity_id": id }));
+                }
+                if fixture.stays {
+                    items.push(json!({ "type": "accommodation", "time": "20:00:00", "accommodation_id": ObjectId::new() }));
+                }
+                days.insert((index + 1).to_string(), Value::Array(items));
+            }
+            let end = fixture.end.as_ref().unwrap_or(&fixture.start);
+            serde_json::from_value(json!({
+                "trip_name": fixture.key,
+                "min_group": fixture.group.0,
+                "max_group": fixture.group.1,
+                "length_days": fixture.days.len(),
+                "length_hours": 0,
+                "start_location": location(&fixture.start),
+                "end_location": location(end),
+                "description": format!("{}. {}", fixture.trip_name, fixture.description),
+                "days": days,
+                "images": null,
+            }))
+            .unwrap()
+        })
+        .collect()
+}
+
+/// Every search's ranking, best first, by fixture key
+fn rankings() -> BTreeMap<String, Vec<String>> {
+    let (activities, ids) = load_activities();
+    let itineraries = load_itineraries(&ids);
+    let queries: Vec<Query> = read("queries.json");
+    // Default weights, never an experiment's
+    let scorer = SearchScorer::new();
+
+    queries
+        .into_iter()
+        .map(|query| {
+            let search: SearchItinerary = serde_json::from_value(query.search)
+                .unwrap_or_else(|e| panic!("query '{}' isn't a search: {}", query.name, e));
+            let ranked = scorer
+                .rank_with_activities(&itineraries, &search, &activities)
+                .into_iter()
+                .map(|scored| scored.itinerary.trip_name)
+                .collect();
+            (query.name, ranked)
+        })
+        .collect()
+}
+
+/// How the expected top results moved, one line each
+fn position_changes(expected: &[String], ranked: &[String]) -> Vec<String> {
+    let place = |key: &String| ranked.iter().position(|ranked| ranked == key);
+    let mut changes = Vec::new();
+    for (expected_at, key) in expected.iter().enumerate() {
+        match place(key) {
+            Some(at) if at == expected_at => {}
+            Some(at) => changes.push(format!("    {}: #{} -> #{}", key, expected_at + 1, at + 1)),
+            None => changes.push(format!("    {}: #{} -> below the minimum score", key, expected_at + 1)),
+        }
+    }
+    for (at, key) in ranked.iter().take(TOP).enumerate() {
+        if !expected.contains(key) {
+            changes.push(format!("    {}: new at #{}", key, at + 1));
+        }
+    }
+    changes
+}
+
+#[test]
+fn test_rankings_match_the_golden_file() {
+    let rankings = rankings();
+    let produced: BTreeMap<&String, Vec<String>> = rankings
+        .iter()
+        .map(|(name, ranked)| (name, ranked.iter().take(TOP).cloned().collect()))
+        .collect();
+    let golden_path = fixtures().join("golden.json");
+
+    if std::env::var("UPDATE_GOLDENS").is_ok_and(|value| value == "1") {
+        let text = serde_json::to_string_pretty(&produced).unwrap() + "\n";
+        std::fs::write(&golden_path, text).unwrap();
+        println!("Wrote {}", golden_path.display());
+        return;
+    }
+
+    let golden: BTreeMap<String, Vec<String>> = read("golden.json");
+    let mut report = Vec::new();
+    for (name, expected) in &golden {
+        match rankings.get(name) {
+            Some(ranked) if ranked.iter().take(TOP).eq(expected.iter()) => {}
+            Some(ranked) => {
+                report.push(format!("  '{}':", name));
+                report.extend(position_changes(expected, ranked));
+            }
+            None => report.push(format!("  '{}': in golden.json but not in queries.json", name)),
+        }
+    }
+    for name in rankings.keys().filter(|name| !golden.contains_key(*name)) {
+        report.push(format!("  '{}': no golden ranking yet", name));
+    }
+
+    assert!(
+        report.is_empty(),
+        "Search rankings changed:\n{}\nIf this is intended, run with UPDATE_GOLDENS=1 and commit tests/fixtures/relevance/golden.json.",
+        report.join("\n")
+    );
+}
+
+#[test]
+fn test_position_changes_are_readable() {
+    let keys = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        position_changes(&keys(&["a", "b", "c"]), &keys(&["a", "c", "d", "b"])),
+        [
+            "    b: #2 -> #4",
+            "    c: #3 -> #2",
+            "    d: new at #3",
+        ]
+    );
+    assert_eq!(
+        position_changes(&keys(&["a", "b"]), &keys(&["a"])),
+        ["    b: #2 -> below the minimum score"]
+    );
+}
@@ -0,0 +1,142 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own itinerary, bookings and seat
+//! counts and removes them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use chrono::{Duration, Utc};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serial_test::serial;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::UserRole;
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::availability_service::{
+    ActivityBookingCount, AvailabilityCache, AvailabilityService,
+};
+
+#[actix_rt::test]
+#[serial]
+async fn test_cancelled_and_refunded_bookings_give_their_seats_back() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let cache = web::Data::new(AvailabilityCache::default());
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(Arc::new(stripe::Client::new("sk_test"))))
+            .app_data(cache.clone())
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    // A one-day trip for a party of two, confirmed twice for the same start
+    let activity_id = ObjectId::new();
+    let mut itinerary = FeaturedVacation {
+        trip_name: "Seat release test trip".to_string(),
+        length_days: 1,
+        adults: Some(2),
+        days: Days {
+            days: HashMap::from([(
+                "1".to_string(),
+                vec![DayItem::Activity {
+                    time: "09:00:00".to_string(),
+                    activity_id,
+                }],
+            )]),
+        },
+        ..Default::default()
+    };
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    itinerary.id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id();
+    let itinerary_id = itinerary.id.unwrap();
+
+    let start = (Utc::now() + Duration::days(30)).date_naive();
+    let arrival = DateTime::from_millis(start.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+    let departure = DateTime::from_millis(arrival.timestamp_millis() + 24 * 60 * 60 * 1000);
+    let availability = AvailabilityService::new(client.clone());
+    let bookings = client.database("Account").collection::<Document>("Bookings");
+    let user_id = ObjectId::new();
+    let mut booking_ids = Vec::new();
+    for _ in 0..2 {
+        availability.record_confirmed_booking(&cache, &itinerary, start).await.unwrap();
+        let booking_id = bookings
+            .insert_one(doc! {
+                "user_id": user_id,
+                "itinerary_id": itinerary_id,
+                "arrival_datetime": arrival,
+                "departure_datetime": departure,
+                "status": "confirmed",
+            })
+            .await
+            .unwrap()
+            .inserted_id
+            .as_object_id()
+            .unwrap();
+        booking_ids.push(booking_id);
+    }
+
+    let inventory: Collection<ActivityBookingCount> = client.database("Options").collection("ActivityBookings");
+    let record = doc! { "activity_id": activity_id, "date": start.to_string() };
+    let booked = || {
+        let inventory = inventory.clone();
+        let record = record.clone();
+        async move { inventory.find_one(record).await.unwrap().unwrap().booked }
+    };
+    assert_eq!(booked().await, 4);
+
+    // The traveler cancels one; nothing was paid, so it is simply cancelled
+    let token = generate_token("test_secret", "traveler@example.com", user_id, None).unwrap();
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri(&format!("/account/{}/bookings/{}/cancel", user_id, booking_ids[0]))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(booked().await, 2);
+
+    // Ops mark the other refunded
+    let admin_id = ObjectId::new();
+    let admin = generate_token("test_secret", "ops@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/admin/bookings/status")
+            .insert_header(("Authorization", format!("Bearer {}", admin)))
+            .set_json(json!({ "booking_ids": [booking_ids[1].to_hex()], "status": "refunded" }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(booked().await, 0);
+
+    bookings.delete_many(doc! { "user_id": user_id }).await.unwrap();
+    inventory.delete_many(doc! { "activity_id": activity_id }).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("AdminAuditLog")
+        .delete_many(doc! { "admin_id": admin_id })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,158 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates and deletes its own user. Events go to
+//! an in-memory sink instead of the SecurityEvents collection.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde_json::json;
+use serial_test::serial;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::security_event::{SecurityEvent, SecurityEventType};
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::oauth_link_service::OAuthLinkService;
+use actota_api::services::security_event_service::SecurityEventQueue;
+use actota_api::services::write_behind::WriteSink;
+
+/// Keeps what it's sent, in order
+#[derive(Clone, Default)]
+struct CollectingSink(Arc<Mutex<Vec<SecurityEvent>>>);
+
+impl WriteSink<SecurityEvent> for CollectingSink {
+    fn write(&self, event: &SecurityEvent) -> impl Future<Output = Result<(), String>> + Send {
+        self.0.lock().unwrap().push(event.clone());
+        std::future::ready(Ok(()))
+    }
+}
+
+impl CollectingSink {
+    /// Event types recorded so far, once the queue has delivered `count` of them
+    async fn types(&self, count: usize) -> Vec<SecurityEventType> {
+        for _ in 0..200 {
+            if self.0.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        self.0.lock().unwrap().iter().map(|event| event.event_type).collect()
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_handlers_record_their_security_events() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let token_secret = config.jwt_secret.clone();
+
+    let user_id = ObjectId::new();
+    let email = format!("security-{}@example.com", user_id.to_hex());
+    let users = client.database("Account").collection::<Document>("Users");
+    users
+        .insert_one(doc! {
+            "_id": user_id,
+            "email": &email,
+            // Low cost, so the test doesn't spend its time hashing
+            "password": bcrypt::hash("correct horse", 4).unwrap(),
+            "role": "user",
+            "failed_signins": 0,
+        })
+        .await
+        .unwrap();
+
+    let sink = CollectingSink::default();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(SecurityEventQueue::start_with(sink.clone()))),
+    )
+    .await;
+    let signin = |password: &str| {
+        test::TestRequest::post()
+            .uri("/auth/signin")
+            .insert_header(("User-Agent", "security-event-test"))
+            .set_json(json!({ "email": &email, "password": password }))
+            .to_request()
+    };
+
+    let response = test::call_service(&app, signin("wrong horse")).await;
+    assert_eq!(response.status(), 401);
+    assert_eq!(sink.types(1).await, [SecurityEventType::SigninFailed]);
+
+    let response = test::call_service(&app, signin("correct horse")).await;
+    assert!(response.status().is_success());
+    assert_eq!(sink.types(2).await[1..], [SecurityEventType::SigninSuccess]);
+
+    let token = generate_token(&token_secret, &email, user_id, None).unwrap();
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/auth/session")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    assert_eq!(sink.types(3).await[2..], [SecurityEventType::TokenRefreshedFromNewDevice]);
+
+    let new_email = format!("security-new-{}@example.com", user_id.to_hex());
+    let response = test::call_service(
+        &app,
+        test::TestRequest::put()
+            .uri(&format!("/account/{}", user_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "email": &new_email, "password": "battery staple" }))
+            .to_request(),
+    )
+    .await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        sink.types(5).await[3..],
+        [SecurityEventType::EmailChanged, SecurityEventType::PasswordChanged]
+    );
+
+    let service = OAuthLinkService::new(client.clone());
+    let user = service.find_user_by_email(&new_email).await.unwrap().unwrap();
+    let link_token = service.start(&user, "google", "g-security", DateTime::now()).await.unwrap();
+    let link = |password: &str| {
+        test::TestRequest::post()
+            .uri("/auth/link-oauth")
+            .set_json(json!({ "link_token": &link_token, "password": password }))
+            .to_request()
+    };
+
+    let response = test::call_service(&app, link("correct horse")).await;
+    assert_eq!(response.status(), 401);
+    assert_eq!(sink.types(6).await[5..], [SecurityEventType::OauthLinkFailed]);
+
+    let response = test::call_service(&app, link("battery staple")).await;
+    assert!(response.status().is_success());
+    assert_eq!(sink.types(7).await[6..], [SecurityEventType::OauthLinked]);
+
+    assert!(sink.0.lock().unwrap().iter().all(|event| event.user_id == user_id));
+
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("PendingOAuthLinks")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
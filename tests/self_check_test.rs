@@ -0,0 +1,59 @@
+//! Needs MongoDB at `MONGODB_URI`. Only reads.
+
+mod common;
+
+use actota_api::services::credential_check::{BucketProbe, CredentialError};
+use actota_api::services::self_check::{self, CheckStatus, ProbeError, Probes};
+use actota_api::services::vertex_search_service::VertexSearchSettings;
+use serial_test::serial;
+
+/// Stripe, Cloud Storage and Vertex as they answer when everything works
+struct Healthy;
+
+impl BucketProbe for Healthy {
+    async fn list_one(&self, _bucket: &str) -> Result<(), CredentialError> {
+        Ok(())
+    }
+}
+
+impl Probes for Healthy {
+    async fn stripe_key(&self, _secret_key: &str) -> Result<(), ProbeError> {
+        Ok(())
+    }
+
+    async fn vertex_search(&self, _settings: &VertexSearchSettings) -> Result<(), ProbeError> {
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_self_check_passes_in_the_test_environment() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let report = self_check::run(
+        |name| match name {
+            "MONGODB_URI" => Some(mongo_uri.clone()),
+            "JWT_SECRET" => Some("test_secret".to_string()),
+            "STRIPE_SECRET_KEY" => Some("sk_test_51SelfCheckKey".to_string()),
+            "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+            "ITINERARY_BUCKET" => Some("actota-test-itineraries".to_string()),
+            "SENDGRID_API_KEY" => Some("SG.test.key".to_string()),
+            _ => None,
+        },
+        &Healthy,
+    )
+    .await;
+
+    assert!(report.passed(), "{}", report.render_text());
+    let status = |name: &str| report.checks.iter().find(|check| check.name == name).unwrap().status;
+    for name in ["config", "mongodb", "stripe", "cloud_storage", "sendgrid"] {
+        assert_eq!(status(name), CheckStatus::Ok, "{}", name);
+    }
+    assert_eq!(status("vertex_search"), CheckStatus::Skipped);
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["checks"].as_array().unwrap().len(), 7);
+}
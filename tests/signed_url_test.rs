@@ -0,0 +1,36 @@
+//! Run with `cargo test --features gcs-signing-tests --test signed_url_test`.
+//! Needs Application Default Credentials that can sign, `SIGNED_URL_TEST_BUCKET`
+//! (a private bucket) and `SIGNED_URL_TEST_OBJECT` (an image in it).
+#![cfg(feature = "gcs-signing-tests")]
+
+use std::sync::Arc;
+
+use actota_api::services::storage::{BucketKind, Storage, StorageConfig};
+
+#[actix_rt::test]
+async fn test_private_bucket_images_are_readable_through_signed_urls() {
+    let bucket = std::env::var("SIGNED_URL_TEST_BUCKET").expect("SIGNED_URL_TEST_BUCKET");
+    let object = std::env::var("SIGNED_URL_TEST_OBJECT").expect("SIGNED_URL_TEST_OBJECT");
+    let config = StorageConfig::from_lookup(
+        |name| match name {
+            "ITINERARY_BUCKET" => Some(bucket.clone()),
+            "ITINERARY_BUCKET_VISIBILITY" => Some("signed".to_string()),
+            "ITINERARY_BUCKET_SIGNED_URL_TTL_SECS" => Some("300".to_string()),
+            _ => None,
+        },
+        &mut Vec::new(),
+        &mut Vec::new(),
+    );
+    let storage = Storage::new(Arc::new(config));
+
+    let plain = storage.bucket(BucketKind::ItineraryImages).unwrap().object_url(&object);
+    assert_ne!(reqwest::get(&plain).await.unwrap().status(), 200, "the bucket should be private");
+
+    let url = storage.url(BucketKind::ItineraryImages, &object).await.unwrap();
+    assert!(url.contains("X-Goog-Signature="));
+    assert!(url.contains("X-Goog-Expires=300"));
+    assert_eq!(reqwest::get(&url).await.unwrap().status(), 200);
+
+    // Asking again hands out the same URL rather than signing a new one
+    assert_eq!(storage.url(BucketKind::ItineraryImages, &object).await.unwrap(), url);
+}
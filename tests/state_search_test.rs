@@ -0,0 +1,80 @@
+//! Needs MongoDB at `MONGODB_URI`. Adds and removes its own itineraries.
+
+mod common;
+
+use mongodb::bson::{doc, Bson};
+use mongodb::Collection;
+use serial_test::serial;
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::itinerary::base::{FeaturedVacation, Location};
+use actota_api::models::search::SearchItinerary;
+use actota_api::services::generation_budget::GenerationBudget;
+use actota_api::services::itinerary_search_service::search_itineraries;
+use actota_api::services::trip_limits::TripLimits;
+use actota_api::services::vertex_activity::ActivityDefaults;
+
+fn itinerary(trip_name: &str, city: &str, state: &str) -> FeaturedVacation {
+    let location: Location = serde_json::from_value(serde_json::json!({
+        "city": city,
+        "state": state,
+        "coordinates": [-106.0, 39.0],
+    }))
+    .unwrap();
+    FeaturedVacation {
+        trip_name: trip_name.to_string(),
+        start_location: location.clone(),
+        end_location: location,
+        ..Default::default()
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_state_only_search_returns_in_state_itineraries() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    // States are stored both ways, and neither city is one a search would name
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let inserted = itineraries
+        .insert_many([
+            itinerary("State search test - code", "Zyqville", "CO"),
+            itinerary("State search test - name", "Zyqburg", "Colorado"),
+            itinerary("State search test - elsewhere", "Zyqton", "UT"),
+        ])
+        .await
+        .unwrap();
+    let ids: Vec<Bson> = inserted.inserted_ids.values().cloned().collect();
+
+    let search: SearchItinerary = serde_json::from_value(serde_json::json!({
+        "locations": ["Colorado"],
+    }))
+    .unwrap();
+    let results = search_itineraries(
+        client.clone(),
+        search,
+        &GenerationBudget::unlimited(),
+        &TripLimits::default(),
+        &ActivityDefaults::default(),
+        None,
+    )
+    .await
+    .unwrap();
+    let found: Vec<&str> = results
+        .iter()
+        .map(|itinerary| itinerary.trip_name.as_str())
+        .filter(|name| name.starts_with("State search test"))
+        .collect();
+    assert!(found.contains(&"State search test - code"));
+    assert!(found.contains(&"State search test - name"));
+    assert!(!found.contains(&"State search test - elsewhere"));
+    assert!(results.iter().all(|itinerary| {
+        ["CO", "Colorado"].contains(&itinerary.start_location.state())
+            || ["CO", "Colorado"].contains(&itinerary.end_location.state())
+    }));
+
+    itineraries.delete_many(doc! { "_id": { "$in": ids } }).await.unwrap();
+}
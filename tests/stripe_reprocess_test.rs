@@ -0,0 +1,334 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own user, itinerary, booking and event.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::sync::Mutex;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::{User, UserRole};
+use actota_api::models::bookings::{BookingDetails, PaymentStatus};
+use actota_api::models::itinerary::base::{DayItem, Days, FeaturedVacation};
+use actota_api::models::money::Money;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::routes::payment::reprocess_event;
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::booking_confirmation::{CapturedPayment, ConfirmationSender};
+use actota_api::services::webhook_replay::ProcessedWebhookService;
+
+/// Records confirmation emails instead of sending them
+#[derive(Default)]
+struct RecordedConfirmations {
+    sent: Mutex<Vec<(String, String)>>,
+}
+
+impl ConfirmationSender for RecordedConfirmations {
+    async fn send_confirmation(
+        &self,
+        user_email: &str,
+        _user_name: &str,
+        _booking: &BookingDetails,
+        _trip_name: &str,
+        payment: &CapturedPayment,
+    ) -> Result<(), String> {
+        self.sent
+            .lock()
+            .unwrap()
+            .push((user_email.to_string(), payment.payment_intent_id.clone()));
+        Ok(())
+    }
+}
+
+fn succeeded_event(event_id: &str, intent_id: &str, created: i64, amount: i64) -> String {
+    json!({
+        "id": event_id,
+        "object": "event",
+        "api_version": "2023-10-16",
+        "created": created,
+        "data": {
+            "object": {
+                "id": intent_id,
+                "object": "payment_intent",
+                "amount": amount,
+                "amount_capturable": 0,
+                "amount_received": amount,
+                "capture_method": "manual",
+                "confirmation_method": "automatic",
+                "created": created,
+                "currency": "usd",
+                "livemode": false,
+                "metadata": {},
+                "payment_method_types": ["card"],
+                "status": "succeeded"
+            }
+        },
+        "livemode": false,
+        "pending_webhooks": 1,
+        "request": null,
+        "type": "payment_intent.succeeded"
+    })
+    .to_string()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_reprocessing_a_confirmed_booking_only_resends_when_forced() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+
+    let email = format!("reprocess-{}@example.com", ObjectId::new().to_hex());
+    let user: User = serde_json::from_value(json!({ "email": email, "password": "hashed" })).unwrap();
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+    let itinerary = FeaturedVacation {
+        trip_name: "Reprocess test trip".to_string(),
+        ..Default::default()
+    };
+    let itinerary_id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let now = chrono::Utc::now().timestamp();
+    let intent_id = format!("pi_reprocess_{}", now);
+    let confirmed_at = DateTime::from_millis(1_750_000_000_000);
+    let booking_id = bookings
+        .insert_one(BookingDetails {
+            id: None,
+            user_id,
+            itinerary_id,
+            customer_id: None,
+            transaction_id: Some(intent_id.clone()),
+            arrival_datetime: DateTime::from_millis(1_790_000_000_000),
+            departure_datetime: DateTime::from_millis(1_790_300_000_000),
+            status: PaymentStatus::Confirmed,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: None,
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: None,
+            updated_at: Some(confirmed_at),
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let event_id = format!("evt_reprocess_{}", now);
+    let processed = ProcessedWebhookService::new(client.clone());
+    processed
+        .claim(
+            &event_id,
+            "payment_intent.succeeded",
+            now,
+            72,
+            &succeeded_event(&event_id, &intent_id, now, 125_000),
+        )
+        .await
+        .unwrap();
+
+    // The booking is already confirmed, so nothing happens
+    let cache = AvailabilityCache::default();
+    let sender = RecordedConfirmations::default();
+    let outcome = reprocess_event(client.clone(), &cache, false, &event_id, false, &sender, None)
+        .await
+        .unwrap();
+    assert!(outcome.succeeded());
+    assert_eq!(outcome.event_type, "payment_intent.succeeded");
+    assert_eq!(outcome.response["already_processed"], true);
+    assert_eq!(outcome.response["notifications_resent"], false);
+    assert!(sender.sent.lock().unwrap().is_empty());
+    let stored = bookings.find_one(doc! { "_id": booking_id }).await.unwrap().unwrap();
+    assert_eq!(stored.status, PaymentStatus::Confirmed);
+    assert_eq!(stored.updated_at, Some(confirmed_at));
+
+    // Forcing notifications sends the confirmation again, and only that
+    let outcome = reprocess_event(client.clone(), &cache, false, &event_id, true, &sender, None)
+        .await
+        .unwrap();
+    assert_eq!(outcome.response["notifications_resent"], true);
+    assert_eq!(*sender.sent.lock().unwrap(), vec![(email.clone(), intent_id.clone())]);
+    let stored = bookings.find_one(doc! { "_id": booking_id }).await.unwrap().unwrap();
+    assert_eq!(stored.updated_at, Some(confirmed_at));
+
+    // Through the admin route, which audits each attempt
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(AvailabilityCache::default())),
+    )
+    .await;
+    let admin_id = ObjectId::new();
+    let token = generate_token("test_secret", "admin@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let reprocess = |event_id: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/admin/stripe/events/{}/reprocess", event_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({}))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, reprocess(&event_id)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["response"]["already_processed"], true);
+
+    let resp = test::call_service(&app, reprocess("evt_never_received")).await;
+    assert_eq!(resp.status(), 404);
+
+    let audit = client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("AdminAuditLog");
+    assert_eq!(
+        audit
+            .count_documents(doc! { "action": "stripe_event_reprocessed", "admin_id": admin_id })
+            .await
+            .unwrap(),
+        2
+    );
+
+    processed.release(&event_id).await.unwrap();
+    audit.delete_many(doc! { "admin_id": admin_id }).await.unwrap();
+    bookings.delete_one(doc! { "_id": booking_id }).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+}
+
+fn pending_booking(user_id: ObjectId, itinerary_id: ObjectId, intent_id: &str) -> BookingDetails {
+    BookingDetails {
+        id: None,
+        user_id,
+        itinerary_id,
+        customer_id: None,
+        transaction_id: Some(intent_id.to_string()),
+        arrival_datetime: DateTime::from_millis(1_790_000_000_000),
+        departure_datetime: DateTime::from_millis(1_790_300_000_000),
+        status: PaymentStatus::Pending,
+        bookings: None,
+        gift_card_redemption_id: None,
+        gift_card_amount: None,
+        special_requests: None,
+        party: None,
+        created_by_admin: false,
+        reservation_id: None,
+        modifications: Vec::new(),
+        review_request_sent_at: None,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_double_delivery_confirms_once_and_wrong_amounts_wait_for_reconciliation() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let users: Collection<User> = client.database("Account").collection("Users");
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+    let itineraries: Collection<FeaturedVacation> = client.database("Itineraries").collection("Featured");
+    let inventory: Collection<Document> = client.database("Options").collection("ActivityBookings");
+    let reconciliation: Collection<Document> = client.database("Account").collection("PaymentReconciliation");
+
+    let email = format!("double-delivery-{}@example.com", ObjectId::new().to_hex());
+    let user: User = serde_json::from_value(json!({ "email": email, "password": "hashed" })).unwrap();
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+    // Two travelers at $625 each
+    let activity_id = ObjectId::new();
+    let itinerary = FeaturedVacation {
+        trip_name: "Double delivery test trip".to_string(),
+        person_cost: Some(Money::from_cents(62_500)),
+        adults: Some(2),
+        days: Days {
+            days: [(
+                "1".to_string(),
+                vec![DayItem::Activity { time: "09:00:00".to_string(), activity_id }],
+            )]
+            .into(),
+        },
+        ..Default::default()
+    };
+    let itinerary_id = itineraries.insert_one(&itinerary).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    let now = chrono::Utc::now().timestamp();
+    let processed = ProcessedWebhookService::new(client.clone());
+    let cache = AvailabilityCache::default();
+    let sender = RecordedConfirmations::default();
+    let mut cleanup = Vec::new();
+    for (suffix, amount) in [("paid", 125_000), ("underpaid", 100)] {
+        let intent_id = format!("pi_{}_{}", suffix, now);
+        let booking_id = bookings
+            .insert_one(pending_booking(user_id, itinerary_id, &intent_id))
+            .await
+            .unwrap()
+            .inserted_id
+            .as_object_id()
+            .unwrap();
+        let event_id = format!("evt_{}_{}", suffix, now);
+        processed
+            .claim(&event_id, "payment_intent.succeeded", now, 72, &succeeded_event(&event_id, &intent_id, now, amount))
+            .await
+            .unwrap();
+
+        // Stripe delivers the same event twice
+        for _ in 0..2 {
+            let outcome = reprocess_event(client.clone(), &cache, false, &event_id, false, &sender, None)
+                .await
+                .unwrap();
+            assert!(outcome.succeeded());
+        }
+        cleanup.push((event_id, booking_id, intent_id));
+    }
+
+    // The paid booking is confirmed once: one email, its seats taken once
+    let (_, paid, paid_intent) = &cleanup[0];
+    assert_eq!(*sender.sent.lock().unwrap(), vec![(email.clone(), paid_intent.clone())]);
+    let stored = bookings.find_one(doc! { "_id": paid }).await.unwrap().unwrap();
+    assert_eq!(stored.status, PaymentStatus::Confirmed);
+    let seats = inventory.find_one(doc! { "activity_id": activity_id }).await.unwrap().unwrap();
+    assert_eq!(seats.get_i64("booked").or_else(|_| seats.get_i32("booked").map(i64::from)).unwrap(), 2);
+
+    // The underpaid one stays pending, queued for reconciliation once
+    let (_, underpaid, underpaid_intent) = &cleanup[1];
+    let stored = bookings.find_one(doc! { "_id": underpaid }).await.unwrap().unwrap();
+    assert_eq!(stored.status, PaymentStatus::Pending);
+    assert_eq!(
+        reconciliation
+            .count_documents(doc! { "payment_intent_id": underpaid_intent })
+            .await
+            .unwrap(),
+        1
+    );
+
+    for (event_id, booking_id, intent_id) in &cleanup {
+        processed.release(event_id).await.unwrap();
+        bookings.delete_one(doc! { "_id": booking_id }).await.unwrap();
+        reconciliation.delete_many(doc! { "payment_intent_id": intent_id }).await.unwrap();
+    }
+    inventory.delete_many(doc! { "activity_id": activity_id }).await.unwrap();
+    itineraries.delete_one(doc! { "_id": itinerary_id }).await.unwrap();
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+}
@@ -0,0 +1,124 @@
+//! Needs MongoDB at `MONGODB_URI`. Adds and removes its own activities.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, Bson};
+use mongodb::Collection;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::activity::Activity;
+use actota_api::models::search::SearchItinerary;
+use actota_api::services::feature_flags::Flags;
+use actota_api::services::fx_service::FxRates;
+use actota_api::services::itinerary_generation_service::ItineraryGenerator;
+use actota_api::services::trip_limits::TripLimits;
+
+const ACTIVITY_TYPE: &str = "trip-limits-test";
+
+fn activity(n: usize) -> Activity {
+    serde_json::from_value(json!({
+        "company": "Rocky Mountain Adventures",
+        "company_id": "rma",
+        "booking_link": "",
+        "online_booking_status": "available",
+        "title": format!("Trip limits test activity {}", n),
+        "description": "",
+        "activity_types": [ACTIVITY_TYPE],
+        "tags": [],
+        "price_per_person": 50.0,
+        "duration_minutes": 120,
+        "daily_time_slots": [],
+        "address": { "street": "", "unit": "", "city": "Denver", "state": "CO", "zip": "", "country": "USA" },
+        "whats_included": [],
+        "capacity": { "minimum": 1, "maximum": 10 },
+    }))
+    .unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_fetch_limit_fills_more_days_of_a_week_long_trip() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let activities: Collection<Activity> = client.database("Options").collection("Activity");
+    let inserted = activities.insert_many((0..30).map(activity)).await.unwrap();
+    let ids: Vec<Bson> = inserted.inserted_ids.values().cloned().collect();
+
+    let search: SearchItinerary = serde_json::from_value(json!({
+        "locations": ["Denver, CO"],
+        "arrival_datetime": "2025-07-01",
+        "departure_datetime": "2025-07-08",
+        "adults": 2,
+        "activities": [ACTIVITY_TYPE],
+    }))
+    .unwrap();
+    let populated_days = |max_activities_fetch: u32| {
+        let generator = ItineraryGenerator::new(client.clone()).with_limits(TripLimits {
+            max_activities_fetch,
+            ..TripLimits::default()
+        });
+        let search = search.clone();
+        async move {
+            let itinerary = generator.generate_itinerary(&search).await.unwrap();
+            itinerary.days.days.values().filter(|items| !items.is_empty()).count()
+        }
+    };
+
+    let with_old_limit = populated_days(10).await;
+    let with_raised_limit = populated_days(30).await;
+    assert!(with_old_limit < 7);
+    assert!(with_raised_limit > with_old_limit);
+
+    activities.delete_many(doc! { "_id": { "$in": ids } }).await.unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_search_over_the_trip_limit_is_refused_with_the_limit() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "MAX_TRIP_DAYS" => Some("10".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(FxRates::default()))
+            .app_data(web::Data::new(Flags::default())),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/itineraries/search-or-generate")
+        .set_json(json!({
+            "locations": ["Denver, CO"],
+            "arrival_datetime": "2025-07-01",
+            "departure_datetime": "2025-07-21",
+            "adults": 2,
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["limit"], 10);
+    assert_eq!(body["field"], "departure_datetime");
+    assert!(body["error"].as_str().unwrap().contains("10 days"));
+}
@@ -0,0 +1,196 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own user and booking and removes
+//! them afterwards.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::account::{User, UserRole};
+use actota_api::routes::account::account_info::delete_account_with;
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::impersonation_service::ImpersonationService;
+use actota_api::services::payment_teardown::CustomerDisposition;
+use actota_api::services::trip_notes_service::MAX_TRIP_NOTES_BYTES;
+
+fn config(mongo_uri: &str) -> AppConfig {
+    AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.to_string()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        _ => None,
+    })
+    .unwrap()
+}
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+#[actix_rt::test]
+#[serial]
+async fn test_notes_round_trip_conflict_and_stay_private() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let config = config(&mongo_uri);
+    let secret = config.jwt_secret.clone();
+
+    let email = format!("notes-{}@example.com", ObjectId::new().to_hex());
+    let user: User = serde_json::from_value(json!({ "email": email, "password": "hashed" })).unwrap();
+    let users = client.database("Account").collection::<User>("Users");
+    let user_id = users.insert_one(&user).await.unwrap().inserted_id.as_object_id().unwrap();
+
+    // A three-day trip that has already ended
+    let bookings = client.database("Account").collection::<Document>("Bookings");
+    let arrival = DateTime::now().timestamp_millis() - 10 * DAY_MILLIS;
+    let booking_id = bookings
+        .insert_one(doc! {
+            "user_id": user_id,
+            "itinerary_id": ObjectId::new(),
+            "arrival_datetime": DateTime::from_millis(arrival),
+            "departure_datetime": DateTime::from_millis(arrival + 2 * DAY_MILLIS),
+            "status": "completed",
+            "created_at": DateTime::now(),
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap();
+
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(client.clone())),
+    )
+    .await;
+    let token = generate_token(&secret, &email, user_id, None).unwrap();
+    let notes_uri = format!("/account/{}/bookings/{}/notes", user_id, booking_id);
+    let put = |token: &str, body: Value| {
+        test::TestRequest::put()
+            .uri(&notes_uri)
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request()
+    };
+    let get = |token: &str| {
+        test::TestRequest::get()
+            .uri(&notes_uri)
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, get(&token)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!((body["text"].clone(), body["version"].clone()), (json!(""), json!(0)));
+
+    // Saved after the trip, with a stray bell character
+    let resp = test::call_service(
+        &app,
+        put(&token, json!({
+            "text": "Packing:\r\n- boots\u{7}",
+            "days": { "1": "Check in by 4pm", "3": "Fly home" },
+            "version": 0
+        })),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let resp = test::call_service(&app, get(&token)).await;
+    let saved: Value = test::read_body_json(resp).await;
+    assert_eq!(saved["text"], "Packing:\n- boots");
+    assert_eq!(saved["days"], json!({ "1": "Check in by 4pm", "3": "Fly home" }));
+    assert_eq!(saved["version"], 1);
+    assert!(saved["updated_at"].is_string());
+
+    // A second device still editing version 0 gets the saved copy back
+    let resp = test::call_service(&app, put(&token, json!({ "text": "Other device", "version": 0 }))).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["notes"], saved);
+
+    let resp = test::call_service(
+        &app,
+        put(&token, json!({ "text": "a".repeat(MAX_TRIP_NOTES_BYTES + 1), "version": 1 })),
+    )
+    .await;
+    assert_eq!(resp.status(), 413);
+    let resp = test::call_service(&app, put(&token, json!({ "text": "nul\u{0}", "version": 1 }))).await;
+    assert_eq!(resp.status(), 400);
+    let resp = test::call_service(&app, put(&token, json!({ "days": { "4": "Extra day" }, "version": 1 }))).await;
+    assert_eq!(resp.status(), 400);
+
+    // The traveler's export carries them
+    let export = |token: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/account/{}/export", user_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+    let resp = test::call_service(&app, export(&token)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["trip_notes"], json!([saved]));
+    assert_eq!(body["bookings"][0]["_id"]["$oid"], booking_id.to_hex());
+    assert!(body["account"].get("password").is_none());
+
+    // Admins don't see them: not impersonating the traveler, nor in the booking export
+    let admin_id = ObjectId::new();
+    let started = ImpersonationService::new(client.clone())
+        .start(&secret, admin_id, user_id, Some("Ticket 5120".to_string()), 30)
+        .await
+        .unwrap();
+    let resp = test::call_service(&app, get(&started.token)).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, put(&started.token, json!({ "text": "", "version": 1 }))).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, export(&started.token)).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body.get("trip_notes").is_none());
+
+    let admin = generate_token(&secret, "ops@example.com", admin_id, Some(&UserRole::Admin)).unwrap();
+    let today = chrono::Utc::now().date_naive();
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri(&format!("/admin/export/bookings?format=ndjson&from={}&to={}", today, today))
+            .insert_header(("Authorization", format!("Bearer {}", admin)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let rows = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(rows.contains(&booking_id.to_hex()));
+    assert!(!rows.contains("Check in by 4pm"));
+
+    // Deleting the account takes the notes with it
+    let notes = client.database("Account").collection::<Document>("TripNotes");
+    // No Stripe customer, so nothing is sent to Stripe
+    let deleted = delete_account_with(
+        client.clone(),
+        &stripe::Client::new("sk_test"),
+        user_id,
+        CustomerDisposition::Delete,
+    )
+    .await
+    .unwrap();
+    assert!(deleted.is_some());
+    assert_eq!(notes.count_documents(doc! { "user_id": user_id }).await.unwrap(), 0);
+
+    bookings.delete_one(doc! { "_id": booking_id }).await.unwrap();
+    users.delete_one(doc! { "_id": user_id }).await.unwrap();
+    notes.delete_many(doc! { "user_id": user_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<Document>("AdminAuditLog")
+        .delete_many(doc! { "admin_id": admin_id })
+        .await
+        .unwrap();
+}
@@ -0,0 +1,207 @@
+//! Needs MongoDB at `MONGODB_URI`. Creates its own bookings.
+
+mod common;
+
+use actix_web::{test, web};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::Collection;
+use serde_json::Value;
+use serial_test::serial;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use actota_api::build_app;
+use actota_api::config::AppConfig;
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::models::bookings::{BookingDetails, PaymentStatus};
+use actota_api::routes::account::auth::generate_token;
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::trip_status_service::{PostTripHook, TripStatusService};
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+
+async fn booking(
+    bookings: &Collection<BookingDetails>,
+    user_id: ObjectId,
+    status: PaymentStatus,
+    arrival_in_hours: i64,
+) -> ObjectId {
+    let arrival = DateTime::now().timestamp_millis() + arrival_in_hours * HOUR_MILLIS;
+    bookings
+        .insert_one(BookingDetails {
+            id: None,
+            user_id,
+            itinerary_id: ObjectId::new(),
+            customer_id: None,
+            transaction_id: None,
+            arrival_datetime: DateTime::from_millis(arrival),
+            departure_datetime: DateTime::from_millis(arrival + 72 * HOUR_MILLIS),
+            status,
+            bookings: None,
+            gift_card_redemption_id: None,
+            gift_card_amount: None,
+            special_requests: None,
+            party: None,
+            created_by_admin: false,
+            reservation_id: None,
+            modifications: Vec::new(),
+            review_request_sent_at: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await
+        .unwrap()
+        .inserted_id
+        .as_object_id()
+        .unwrap()
+}
+
+async fn status_of(bookings: &Collection<BookingDetails>, booking_id: ObjectId) -> PaymentStatus {
+    bookings.find_one(doc! { "_id": booking_id }).await.unwrap().unwrap().status
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_trips_that_started_or_are_inside_the_cutoff_are_not_refunded() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+
+    let user_id = ObjectId::new();
+    let started = booking(&bookings, user_id, PaymentStatus::Confirmed, -24).await;
+    let completed = booking(&bookings, user_id, PaymentStatus::Completed, -240).await;
+    let tomorrow = booking(&bookings, user_id, PaymentStatus::Confirmed, 24).await;
+    let next_month = booking(&bookings, user_id, PaymentStatus::Confirmed, 24 * 30).await;
+
+    let config = AppConfig::from_lookup(|name| match name {
+        "MONGODB_URI" => Some(mongo_uri.clone()),
+        "JWT_SECRET" => Some("test_secret".to_string()),
+        "STRIPE_SECRET_KEY" => Some("sk_test".to_string()),
+        "STRIPE_WEBHOOK_SECRET" => Some("whsec_test".to_string()),
+        "REFUND_CUTOFF_HOURS" => Some("48".to_string()),
+        _ => None,
+    })
+    .unwrap();
+    let app = test::init_service(
+        build_app()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(Arc::new(stripe::Client::new("sk_test"))))
+            .app_data(web::Data::new(AvailabilityCache::default()))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+    let token = generate_token("test_secret", "traveler@example.com", user_id, None).unwrap();
+    let cancel = |booking_id: ObjectId| {
+        test::TestRequest::post()
+            .uri(&format!("/account/{}/bookings/{}/cancel", user_id, booking_id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    for booking_id in [started, completed] {
+        let resp = test::call_service(&app, cancel(booking_id)).await;
+        assert_eq!(resp.status(), 409);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["error"].as_str().unwrap().contains("trip has started"));
+    }
+
+    let resp = test::call_service(&app, cancel(tomorrow)).await;
+    assert_eq!(resp.status(), 409);
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["error"].as_str().unwrap().contains("48 hours"));
+    assert_eq!(status_of(&bookings, tomorrow).await, PaymentStatus::Confirmed);
+
+    // Nothing was paid, so outside the cutoff the booking is simply cancelled
+    let resp = test::call_service(&app, cancel(next_month)).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(status_of(&bookings, next_month).await, PaymentStatus::Cancelled);
+
+    bookings.delete_many(doc! { "user_id": user_id }).await.unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_bookings_follow_their_trip_dates() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+
+    let user_id = ObjectId::new();
+    let upcoming = booking(&bookings, user_id, PaymentStatus::Confirmed, 24).await;
+    let underway = booking(&bookings, user_id, PaymentStatus::Confirmed, -24).await;
+    let finished = booking(&bookings, user_id, PaymentStatus::Confirmed, -240).await;
+    let was_underway = booking(&bookings, user_id, PaymentStatus::InProgress, -240).await;
+    let cancelled = booking(&bookings, user_id, PaymentStatus::Cancelled, -240).await;
+
+    let service = TripStatusService::new(client.clone());
+    let summary = service.advance(DateTime::now(), &()).await.unwrap();
+    assert!(summary.started >= 1);
+    assert!(summary.completed >= 2);
+
+    assert_eq!(status_of(&bookings, upcoming).await, PaymentStatus::Confirmed);
+    assert_eq!(status_of(&bookings, underway).await, PaymentStatus::InProgress);
+    assert_eq!(status_of(&bookings, finished).await, PaymentStatus::Completed);
+    assert_eq!(status_of(&bookings, was_underway).await, PaymentStatus::Completed);
+    assert_eq!(status_of(&bookings, cancelled).await, PaymentStatus::Cancelled);
+
+    bookings.delete_many(doc! { "user_id": user_id }).await.unwrap();
+}
+
+/// Remembers which bookings it was called for
+#[derive(Default)]
+struct RecordingHook {
+    completed: Mutex<Vec<ObjectId>>,
+}
+
+impl PostTripHook for RecordingHook {
+    fn trip_completed(&self, booking: &BookingDetails, _now: DateTime) -> impl Future<Output = ()> + Send {
+        assert_eq!(booking.status, PaymentStatus::Completed);
+        self.completed.lock().unwrap().push(booking.id.unwrap());
+        async {}
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_transitions_are_recorded_and_hooks_fire_once() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let bookings: Collection<BookingDetails> = client.database("Account").collection("Bookings");
+
+    let user_id = ObjectId::new();
+    let underway = booking(&bookings, user_id, PaymentStatus::Confirmed, -24).await;
+    let finished = booking(&bookings, user_id, PaymentStatus::Confirmed, -240).await;
+
+    let service = TripStatusService::new(client.clone());
+    service.ensure_indexes().await.unwrap();
+    let hook = RecordingHook::default();
+    service.advance(DateTime::now(), &hook).await.unwrap();
+    // A second run finds nothing left to do for these bookings
+    service.advance(DateTime::now(), &hook).await.unwrap();
+
+    let completed: Vec<ObjectId> = hook.completed.lock().unwrap().clone();
+    assert_eq!(completed.iter().filter(|id| **id == finished).count(), 1);
+    assert!(!completed.contains(&underway));
+
+    let history = service.history(underway).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!((&history[0].from, &history[0].to), (&PaymentStatus::Confirmed, &PaymentStatus::InProgress));
+    let history = service.history(finished).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!((&history[0].from, &history[0].to), (&PaymentStatus::Confirmed, &PaymentStatus::Completed));
+    assert_eq!(history[0].user_id, user_id);
+
+    bookings.delete_many(doc! { "user_id": user_id }).await.unwrap();
+    client
+        .database("Account")
+        .collection::<mongodb::bson::Document>("BookingStatusTransitions")
+        .delete_many(doc! { "user_id": user_id })
+        .await
+        .unwrap();
+}
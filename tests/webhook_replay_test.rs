@@ -0,0 +1,152 @@
+//! Needs MongoDB at `MONGODB_URI`. Posts signed events to the real webhook handler.
+
+mod common;
+
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+use serial_test::serial;
+use sha2::{Digest, Sha256};
+
+use actota_api::db::mongo::create_mongo_client;
+use actota_api::routes::payment::{handle_stripe_webhook, StripeConfig};
+use actota_api::services::availability_service::AvailabilityCache;
+use actota_api::services::webhook_replay::{Claim, ProcessedWebhookService};
+
+const SECRET: &str = "whsec_replay_test";
+
+/// HMAC-SHA256, as Stripe signs webhook payloads
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; 64];
+    block[..key.len()].copy_from_slice(key);
+    let pad = |byte: u8| block.iter().map(|k| k ^ byte).collect::<Vec<u8>>();
+
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().to_vec()
+}
+
+fn signature_header(payload: &str, signed_at: i64) -> String {
+    let mac = hmac_sha256(SECRET.as_bytes(), format!("{}.{}", signed_at, payload).as_bytes());
+    let hex: String = mac.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("t={},v1={}", signed_at, hex)
+}
+
+/// An event the handler acknowledges without touching bookings
+fn balance_event(event_id: &str, created: i64) -> String {
+    json!({
+        "id": event_id,
+        "object": "event",
+        "api_version": "2023-10-16",
+        "created": created,
+        "data": {
+            "object": { "object": "balance", "available": [], "livemode": false, "pending": [] }
+        },
+        "livemode": false,
+        "pending_webhooks": 1,
+        "request": null,
+        "type": "balance.available"
+    })
+    .to_string()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_duplicate_delivery_is_acknowledged_once() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+    let processed = ProcessedWebhookService::new(client.clone());
+    processed.ensure_indexes().await.unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(StripeConfig {
+                webhook_secret: SECRET.to_string(),
+                max_event_age_hours: 72,
+            }))
+            .app_data(web::Data::new(AvailabilityCache::default()))
+            .route("/stripe/webhook", web::post().to(handle_stripe_webhook)),
+    )
+    .await;
+
+    let now = chrono::Utc::now().timestamp();
+    let event_id = format!("evt_replay_{}", now);
+    let payload = balance_event(&event_id, now);
+
+    let mut bodies = Vec::new();
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/stripe/webhook")
+            .insert_header(("stripe-signature", signature_header(&payload, now)))
+            .set_payload(payload.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        bodies.push(test::read_body_json::<Value, _>(resp).await);
+    }
+    assert_eq!(bodies[0]["duplicate"], Value::Null);
+    assert_eq!(bodies[1]["duplicate"], true);
+
+    // Released claims are processed again on the next delivery
+    processed.release(&event_id).await.unwrap();
+    let claim = processed
+        .claim(&event_id, "balance.available", now, 72, &payload)
+        .await
+        .unwrap();
+    assert_eq!(claim, Claim::New);
+    processed.release(&event_id).await.unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_freshly_signed_stale_event_is_rejected() {
+    let Some(mongo_uri) = common::mongodb_uri() else {
+        return;
+    };
+    let client = create_mongo_client(&mongo_uri).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(client.clone()))
+            .app_data(web::Data::new(StripeConfig {
+                webhook_secret: SECRET.to_string(),
+                max_event_age_hours: 72,
+            }))
+            .app_data(web::Data::new(AvailabilityCache::default()))
+            .route("/stripe/webhook", web::post().to(handle_stripe_webhook)),
+    )
+    .await;
+
+    let now = chrono::Utc::now().timestamp();
+    let four_days_ago = now - 4 * 24 * 60 * 60;
+    let event_id = format!("evt_stale_{}", now);
+    let payload = balance_event(&event_id, four_days_ago);
+
+    // Signed just now, so only the event's own age gives it away
+    let req = test::TestRequest::post()
+        .uri("/stripe/webhook")
+        .insert_header(("stripe-signature", signature_header(&payload, now)))
+        .set_payload(payload.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // An old signature is refused by the signature check itself
+    let req = test::TestRequest::post()
+        .uri("/stripe/webhook")
+        .insert_header(("stripe-signature", signature_header(&payload, now - 600)))
+        .set_payload(payload.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // Nothing was claimed for the refused event
+    let processed = ProcessedWebhookService::new(client);
+    let claim = processed
+        .claim(&event_id, "balance.available", now, 72, &payload)
+        .await
+        .unwrap();
+    assert_eq!(claim, Claim::New);
+    processed.release(&event_id).await.unwrap();
+}